@@ -828,6 +828,7 @@ pub fn proto_term_cell(nano_ergs: u64, tokens: Vec<Token>, address_bytes: Vec<u8
         target: ChainId::from(0),
         address: SerializedValue::from(address_bytes),
         inputs: None,
+        constraints: None,
     };
     let mut assets = HashMap::new();
     let asset_map: HashMap<AssetId, CustomAsset> = tokens
@@ -18,7 +18,7 @@ use spectrum_chain_connector::{
 };
 use spectrum_crypto::digest::Blake2bDigest256;
 use spectrum_ergo_connector::script::ExtraErgoData;
-use spectrum_ergo_connector::AncillaryVaultInfo;
+use spectrum_ergo_connector::{erg_denomination, AncillaryVaultInfo};
 use spectrum_ledger::{
     cell::{AssetId, BoxDestination, CustomAsset, NativeCoin, Owner, PolicyId, SValue, TermCell},
     ChainId,
@@ -211,6 +211,13 @@ impl<'a> Component for Home<'a> {
                             ConnectorMsgOut::GenesisVaultUtxo(s) => {
                                 //self.vault_utxo_details = Some(s);
                             }
+                            ConnectorMsgOut::VaultMigration(_) => {}
+                            ConnectorMsgOut::AccountingReport(_) => {}
+                            ConnectorMsgOut::CommitteeRotationRejected(_) => {}
+                            ConnectorMsgOut::NodeHealthAlert(_) => {}
+                            ConnectorMsgOut::RenotarizationRequired(_) => {}
+                            ConnectorMsgOut::VaultBalanceMismatch(_) => {}
+                            ConnectorMsgOut::MisbehaviourEvidence(_) => {}
                         }
                     }
                     None
@@ -453,54 +460,34 @@ impl<'a> Home<'a> {
             })
             .collect();
 
-        // Check if there's a pending TX
+        // Add rows for pending TXs
         if let Some(status) = &self.connector_status {
-            match status {
-                ConnectorStatus::Synced {
-                    current_progress_point,
-                    pending_tx_status: Some(tx_status),
-                }
-                | ConnectorStatus::Syncing {
-                    current_progress_point,
-                    pending_tx_status: Some(tx_status),
-                    ..
-                } => {
-                    let height_cell =
-                        Cell::from(u64::from(current_progress_point.point).to_string()).style(Style::reset());
-                    let tx_id_cell = Cell::from("...".to_string()).style(Style::reset());
-                    match tx_status {
-                        PendingTxStatus::Withdrawal(e) => {
-                            let PendingWithdrawalStatus { identifier, .. } = e;
-                            let ValueSummary { ergs, .. } =
-                                summarise_term_cells(&identifier.value_to_withdraw);
-                            let status_cell = Cell::from("PENDING").style(Style::reset().fg(DARK_ORANGE));
-                            let tx_type = Cell::from("WITHDRAWAL").style(Style::reset());
-
-                            tx_rows.push(Row::new(vec![
-                                tx_type,
-                                tx_id_cell,
-                                ergs,
-                                height_cell,
-                                status_cell,
-                            ]));
-                        }
-                        PendingTxStatus::Deposit(d) => {
-                            let PendingDepositStatus { identifier, .. } = d;
-                            let ValueSummary { ergs, .. } = summarise_inbound_value(identifier);
-                            let status_cell = Cell::from("PENDING").style(Style::reset().fg(DARK_ORANGE));
-                            let tx_type = Cell::from("DEPOSIT").style(Style::reset());
-
-                            tx_rows.push(Row::new(vec![
-                                tx_type,
-                                tx_id_cell,
-                                ergs,
-                                height_cell,
-                                status_cell,
-                            ]));
-                        }
+            let height_cell = Cell::from(u64::from(status.get_current_progress_point().point).to_string())
+                .style(Style::reset());
+            for tx_status in status.get_pending_txs() {
+                let tx_id_cell = Cell::from("...".to_string()).style(Style::reset());
+                let (tx_type, ergs) = match tx_status {
+                    PendingTxStatus::Withdrawal(PendingWithdrawalStatus { identifier, .. }) => {
+                        let ValueSummary { ergs, .. } = summarise_term_cells(&identifier.value_to_withdraw);
+                        (Cell::from("WITHDRAWAL").style(Style::reset()), ergs)
                     }
-                }
-                _ => (),
+                    PendingTxStatus::Deposit(PendingDepositStatus { identifier, .. }) => {
+                        let ValueSummary { ergs, .. } = summarise_inbound_value(identifier);
+                        (Cell::from("DEPOSIT").style(Style::reset()), ergs)
+                    }
+                    PendingTxStatus::CommitteeRotation(_) => (
+                        Cell::from("COMMITTEE ROTATION").style(Style::reset()),
+                        Cell::from("-").style(Style::reset()),
+                    ),
+                };
+                let status_cell = Cell::from("PENDING").style(Style::reset().fg(DARK_ORANGE));
+                tx_rows.push(Row::new(vec![
+                    tx_type,
+                    tx_id_cell,
+                    ergs,
+                    height_cell.clone(),
+                    status_cell,
+                ]));
             }
         }
 
@@ -567,11 +554,8 @@ impl<'a> Home<'a> {
                 };
                 Row::new(vec![
                     Cell::from(owner_str.to_string()).style(Style::reset()),
-                    Cell::from(format!(
-                        "{:?}",
-                        (u64::from(inbound_value.value.native) as f64 / 1000000000.0)
-                    ))
-                    .style(Style::reset()),
+                    Cell::from(erg_denomination().format_units(inbound_value.value.native.into()))
+                        .style(Style::reset()),
                     status_cell,
                 ])
             })
@@ -736,6 +720,12 @@ fn render_status_line(vault_manager_status: &Option<ConnectorStatus<ExtraErgoDat
             spans.push(Span::styled("Unknown", Style::reset()));
         }
     };
+    if vault_manager_status
+        .as_ref()
+        .is_some_and(|status| status.get_node_health().degraded)
+    {
+        spans.push(Span::styled(", node degraded", Style::reset().fg(DARK_ORANGE)));
+    }
     Line::from(spans)
 }
 
@@ -749,8 +739,8 @@ fn render_vault_utxo_details(value: Option<&VaultBalance<AncillaryVaultInfo>>) -
 
     let content = if let Some(value) = value {
         format!(
-            "  Value: {} ergs",
-            (u64::from(value.value.native) as f64 / 1000000000.0)
+            "  Value: {}",
+            erg_denomination().format(value.value.native.into())
         )
     } else {
         String::from("  UNKNOWN")
@@ -767,6 +757,7 @@ pub fn proto_term_cell(nano_ergs: u64, tokens: Vec<Token>, address: SerializedVa
         target: ChainId::from(0),
         address,
         inputs: None,
+        constraints: None,
     };
     let mut assets = HashMap::new();
     let asset_map: HashMap<AssetId, CustomAsset> = tokens
@@ -810,12 +801,12 @@ impl ActiveBlock {
 }
 
 fn summarise_inbound_value(values: &Vec<InboundValue<BoxId>>) -> ValueSummary {
-    let mut ergs = 0.0;
+    let mut nano_ergs = 0;
     let mut total_num_tokens = 0;
     let mut token_qty = 0;
     let policy_id = PolicyId::from(Blake2bDigest256::zero());
     for inbound_value in values {
-        ergs += u64::from(inbound_value.value.native) as f64 / 1000000000.0;
+        nano_ergs += u64::from(inbound_value.value.native);
         if let Some(tokens) = inbound_value.value.assets.get(&policy_id) {
             total_num_tokens += tokens.len();
             token_qty += tokens.values().fold(0, |acc, t| acc + u64::from(*t));
@@ -823,7 +814,7 @@ fn summarise_inbound_value(values: &Vec<InboundValue<BoxId>>) -> ValueSummary {
     }
     let total_num_tokens = Cell::from(total_num_tokens.to_string()).style(Style::reset());
     let total_qty_tokens = Cell::from(token_qty.to_string()).style(Style::reset());
-    let ergs = Cell::from(format!("{:.5}", ergs)).style(Style::reset());
+    let ergs = Cell::from(erg_denomination().format_units(nano_ergs)).style(Style::reset());
 
     ValueSummary {
         ergs,
@@ -833,13 +824,13 @@ fn summarise_inbound_value(values: &Vec<InboundValue<BoxId>>) -> ValueSummary {
 }
 
 fn summarise_term_cells(values: &Vec<TermCell>) -> ValueSummary {
-    let mut ergs = 0.0;
+    let mut nano_ergs = 0;
     let mut total_num_tokens = 0;
     let mut token_qty = 0;
     let policy_id = PolicyId::from(Blake2bDigest256::zero());
 
     for cell in values {
-        ergs += u64::from(cell.value.native) as f64 / 1000000000.0;
+        nano_ergs += u64::from(cell.value.native);
         if let Some(tokens) = cell.value.assets.get(&policy_id) {
             total_num_tokens += tokens.len();
             token_qty += tokens.values().fold(0, |acc, t| acc + u64::from(*t));
@@ -847,7 +838,7 @@ fn summarise_term_cells(values: &Vec<TermCell>) -> ValueSummary {
     }
     let total_num_tokens = Cell::from(total_num_tokens.to_string()).style(Style::reset());
     let total_qty_tokens = Cell::from(token_qty.to_string()).style(Style::reset());
-    let ergs = Cell::from(format!("{:.5}", ergs)).style(Style::reset());
+    let ergs = Cell::from(erg_denomination().format_units(nano_ergs)).style(Style::reset());
 
     ValueSummary {
         ergs,
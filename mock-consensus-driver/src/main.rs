@@ -9,12 +9,14 @@ use crate::app::App;
 use ergo_lib::ergotree_ir::chain::address::Address;
 use ergo_lib::ergotree_ir::chain::address::{AddressEncoder, NetworkPrefix};
 use k256::SecretKey;
-use log::{error, info};
+use log::{error, info, warn};
 use serde::Deserialize;
 use spectrum_chain_connector::{
-    ChainTxEvent, ConnectorMsgOut, ConnectorRequest, ConnectorResponse, ConnectorStatus, Kilobytes,
-    NotarizedReport, NotarizedReportConstraints, PendingDepositStatus, PendingTxIdentifier, PendingTxStatus,
-    PendingWithdrawalStatus, ProtoTermCell, SpectrumTx, SpectrumTxType, TxStatus,
+    ipc::{encode_request, IpcHandshake, IpcRequest, IpcResponse, SEQUENCED_RESPONSES_IPC_PROTOCOL_VERSION},
+    ChainTxEvent, Confirmation, ConnectorMsgOut, ConnectorRequest, ConnectorResponse, ConnectorStatus,
+    Kilobytes, NotarizedReport, NotarizedReportConstraints, PendingCommitteeRotationStatus,
+    PendingDepositStatus, PendingTxIdentifier, PendingTxStatus, PendingWithdrawalStatus, ProtoTermCell,
+    SpectrumTx, SpectrumTxType, TxStatus,
 };
 use spectrum_crypto::digest::blake2b256_hash;
 use spectrum_ergo_connector::{
@@ -84,6 +86,25 @@ async fn main() {
     app.run(response_rx, frontend_command_tx).await.unwrap();
 }
 
+/// Sends requests to the connector in their IPC wire encoding.
+struct ConnectorRequestSender(Sender<IpcRequest>);
+
+impl ConnectorRequestSender {
+    async fn send(&self, req: ConnectorRequest<ExtraErgoData, BoxId>) -> std::io::Result<()> {
+        let bytes =
+            encode_request(&req).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        self.0.send(IpcRequest::Request(bytes)).await
+    }
+
+    async fn resume(&self, last_acked_seq: Option<u64>) -> std::io::Result<()> {
+        self.0.send(IpcRequest::Resume { last_acked_seq }).await
+    }
+
+    async fn ack(&self, seq: u64) -> std::io::Result<()> {
+        self.0.send(IpcRequest::Ack(seq)).await
+    }
+}
+
 struct MockConsensusDriver {
     connector_status: Option<ConnectorStatus<ExtraErgoData, BoxId>>,
     pending_tx_status: Option<PendingTxStatus<ExtraErgoData, BoxId>>,
@@ -96,6 +117,10 @@ struct MockConsensusDriver {
     tick_delay_in_seconds: u64,
     proposed_withdrawal_term_cells: Option<Vec<ProtoTermCell>>,
     notarized_report_to_send: Option<NotarizedReport<ExtraErgoData>>,
+    /// Sequence number of the last response processed by the driver.
+    last_acked_seq: Option<u64>,
+    /// Set once the Connector reports that the vault balance doesn't reconcile.
+    exports_halted: bool,
 }
 
 impl MockConsensusDriver {
@@ -118,6 +143,8 @@ impl MockConsensusDriver {
             tick_delay_in_seconds,
             proposed_withdrawal_term_cells: None,
             notarized_report_to_send: None,
+            last_acked_seq: None,
+            exports_halted: false,
         }
     }
 
@@ -125,13 +152,29 @@ impl MockConsensusDriver {
         // Keep trying to connect to the unix socket.
         let (unix_sock_tx, unix_sock_rx) = loop {
             if let Ok(receiver) = Receiver::<(
-                Sender<ConnectorRequest<ExtraErgoData, BoxId>>,
-                Receiver<ConnectorResponse<ExtraErgoData, ErgoNotarizationBounds, BoxId, AncillaryVaultInfo>>,
+                Sender<IpcRequest>,
+                Receiver<IpcResponse<ExtraErgoData, ErgoNotarizationBounds, BoxId, AncillaryVaultInfo>>,
             )>::connect(self.unix_socket_path.clone())
             .await
             {
-                if let Ok(received) = receiver.recv().await {
-                    break received;
+                if let Ok((tx, rx)) = receiver.recv().await {
+                    if tx.send(IpcRequest::Hello(IpcHandshake::local())).await.is_ok() {
+                        match rx.recv().await {
+                            Ok(IpcResponse::Welcome(version)) => {
+                                info!(target: "driver", "Negotiated IPC protocol version {}", version);
+                                let tx = ConnectorRequestSender(tx);
+                                if version >= SEQUENCED_RESPONSES_IPC_PROTOCOL_VERSION {
+                                    // Pick up responses produced while the driver was offline.
+                                    tx.resume(self.last_acked_seq).await.unwrap();
+                                }
+                                break (tx, rx);
+                            }
+                            Ok(IpcResponse::Rejected(e)) => {
+                                error!(target: "driver", "Connector refused handshake: {}", e);
+                            }
+                            _ => {}
+                        }
+                    }
                 }
             }
             sleep(tokio::time::Duration::from_secs(self.tick_delay_in_seconds)).await;
@@ -183,6 +226,7 @@ impl MockConsensusDriver {
                                         },
                                         max_tx_size: Kilobytes(5.0),
                                         estimated_number_of_byzantine_nodes: 0,
+                                        fee_policy: None,
                                     };
 
                                     unix_sock_tx
@@ -197,7 +241,12 @@ impl MockConsensusDriver {
                             }
                         } else {
                             // Check for pending NotarizedReport
-                            if let Some(notarized_report) = self.notarized_report_to_send.take() {
+                            let notarized_report = if self.exports_halted {
+                                None
+                            } else {
+                                self.notarized_report_to_send.take()
+                            };
+                            if let Some(notarized_report) = notarized_report {
                                 info!(target: "driver", "Sending request for withdrawal TX");
                                 unix_sock_tx
                                     .send(ConnectorRequest::ValidateAndProcessWithdrawals(Box::new(
@@ -232,7 +281,7 @@ impl MockConsensusDriver {
                         identifier: data,
                         status,
                     }) => match status {
-                        TxStatus::Confirmed => {
+                        TxStatus::Confirmed(Confirmation { settled: true, .. }) => {
                             info!(target: "driver", "ACK CONFIRMED WITHDRAWAL TX");
                             unix_sock_tx
                                 .send(ConnectorRequest::AcknowledgeConfirmedTx(
@@ -258,7 +307,7 @@ impl MockConsensusDriver {
                                 .await
                                 .unwrap();
                         }
-                        TxStatus::WaitingForConfirmation => {
+                        TxStatus::WaitingForConfirmation | TxStatus::Confirmed(_) => {
                             unix_sock_tx
                                 .send(ConnectorRequest::SyncFrom(
                                     self.connector_status
@@ -273,7 +322,7 @@ impl MockConsensusDriver {
                         identifier: data,
                         status,
                     }) => match status {
-                        TxStatus::WaitingForConfirmation => {
+                        TxStatus::WaitingForConfirmation | TxStatus::Confirmed(_) => {
                             unix_sock_tx
                                 .send(ConnectorRequest::SyncFrom(
                                     self.connector_status
@@ -283,7 +332,7 @@ impl MockConsensusDriver {
                                 .await
                                 .unwrap();
                         }
-                        TxStatus::Confirmed => {
+                        TxStatus::Confirmed(Confirmation { settled: true, .. }) => {
                             info!(target: "driver", "ACK CONFIRMED DEPOSIT TX");
                             unix_sock_tx
                                 .send(ConnectorRequest::AcknowledgeConfirmedTx(
@@ -310,11 +359,65 @@ impl MockConsensusDriver {
                                 .unwrap();
                         }
                     },
+                    PendingTxStatus::CommitteeRotation(PendingCommitteeRotationStatus {
+                        identifier: handover,
+                        status,
+                    }) => match status {
+                        TxStatus::Confirmed(Confirmation { settled: true, .. }) => {
+                            info!(target: "driver", "ACK CONFIRMED COMMITTEE ROTATION");
+                            unix_sock_tx
+                                .send(ConnectorRequest::AcknowledgeConfirmedTx(
+                                    PendingTxIdentifier::CommitteeRotation(Box::new(handover.clone())),
+                                    self.connector_status
+                                        .clone()
+                                        .map(|status| status.get_current_progress_point())
+                                        .unwrap(),
+                                ))
+                                .await
+                                .unwrap();
+                        }
+                        TxStatus::Aborted => {
+                            info!(target: "driver", "ACK ABORTED COMMITTEE ROTATION");
+                            unix_sock_tx
+                                .send(ConnectorRequest::AcknowledgeAbortedTx(
+                                    PendingTxIdentifier::CommitteeRotation(Box::new(handover.clone())),
+                                    self.connector_status
+                                        .clone()
+                                        .map(|status| status.get_current_progress_point())
+                                        .unwrap(),
+                                ))
+                                .await
+                                .unwrap();
+                        }
+                        TxStatus::WaitingForConfirmation | TxStatus::Confirmed(_) => {
+                            unix_sock_tx
+                                .send(ConnectorRequest::SyncFrom(
+                                    self.connector_status
+                                        .clone()
+                                        .map(|status| status.get_current_progress_point()),
+                                ))
+                                .await
+                                .unwrap();
+                        }
+                    },
                 },
             }
 
             // Get response from vault manager.
-            let resp = unix_sock_rx.recv().await.unwrap();
+            let (seq, resp) = match unix_sock_rx.recv().await.unwrap() {
+                IpcResponse::Response(resp) => (None, resp),
+                IpcResponse::Sequenced { seq, response } => {
+                    if self.last_acked_seq.is_some_and(|acked| seq <= acked) {
+                        continue;
+                    }
+                    (Some(seq), response)
+                }
+                IpcResponse::Rejected(e) => {
+                    error!(target: "driver", "Request rejected by connector: {}", e);
+                    continue;
+                }
+                IpcResponse::Welcome(_) => continue,
+            };
             self.frontend_tx.send(resp.clone()).await.unwrap();
             let ConnectorResponse { status, messages } = resp;
 
@@ -390,6 +493,7 @@ impl MockConsensusDriver {
                             value_to_withdraw,
                             authenticated_digest: inputs.resulting_digest,
                             additional_chain_data: extra_ergo_data,
+                            inclusion_proof: None,
                         };
 
                         self.notarized_report_to_send = Some(notarized_report);
@@ -398,8 +502,58 @@ impl MockConsensusDriver {
                     ConnectorMsgOut::GenesisVaultUtxo(value) => {
                         error!(target: "driver", "GOT GENESIS VAULT UTXO: {:?}", value);
                     }
+
+                    ConnectorMsgOut::VaultMigration(status) => {
+                        info!(target: "driver", "vault migration: {:?}", status);
+                    }
+
+                    ConnectorMsgOut::AccountingReport(report) => {
+                        info!(target: "driver", "accounting report {:?}:\n{}", report.query, report.content);
+                    }
+
+                    ConnectorMsgOut::CommitteeRotationRejected(reason) => {
+                        error!(target: "driver", "committee rotation rejected: {}", reason);
+                    }
+
+                    ConnectorMsgOut::NodeHealthAlert(alert) => {
+                        warn!(target: "driver", "node health alert: {:?}", alert);
+                    }
+
+                    ConnectorMsgOut::RenotarizationRequired(export) => {
+                        warn!(
+                            target: "driver",
+                            "export of {} term cells certified in epoch {:?} has to be notarized again in epoch {:?}",
+                            export.value_to_withdraw.len(),
+                            export.certified_in_epoch,
+                            export.current_epoch
+                        );
+                    }
+
+                    ConnectorMsgOut::VaultBalanceMismatch(discrepancy) => {
+                        error!(
+                            target: "driver",
+                            "vault balance mismatch, halting exports. on-chain: {:?}, expected: {:?}",
+                            discrepancy.on_chain,
+                            discrepancy.expected
+                        );
+                        self.exports_halted = true;
+                    }
+
+                    ConnectorMsgOut::MisbehaviourEvidence(signed) => {
+                        warn!(
+                            target: "driver",
+                            "evidence of misbehaviour of {:?} reported by {:?}",
+                            signed.evidence.offender,
+                            signed.reporter
+                        );
+                    }
                 }
             }
+
+            if let Some(seq) = seq {
+                unix_sock_tx.ack(seq).await.unwrap();
+                self.last_acked_seq = Some(seq);
+            }
         }
     }
 }
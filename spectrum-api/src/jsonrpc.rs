@@ -0,0 +1,139 @@
+//! JSON-RPC 2.0 envelope, see <https://www.jsonrpc.org/specification>.
+//! Batches and notifications aren't supported, every request is answered.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub const VERSION: &str = "2.0";
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Request {
+    pub jsonrpc: String,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+    #[serde(default)]
+    pub id: Value,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    pub jsonrpc: String,
+    #[serde(flatten)]
+    pub payload: Payload,
+    pub id: Value,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Payload {
+    Result(Value),
+    Error(RpcError),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, thiserror::Error)]
+#[error("{message} ({code})")]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn invalid_params<E: ToString>(err: E) -> Self {
+        Self {
+            code: INVALID_PARAMS,
+            message: format!("Invalid params: {}", err.to_string()),
+        }
+    }
+
+    pub fn method_not_found(method: &str) -> Self {
+        Self {
+            code: METHOD_NOT_FOUND,
+            message: format!("Method {} not found", method),
+        }
+    }
+
+    pub fn internal<E: ToString>(err: E) -> Self {
+        Self {
+            code: INTERNAL_ERROR,
+            message: err.to_string(),
+        }
+    }
+}
+
+impl Response {
+    pub fn new(id: Value, res: Result<Value, RpcError>) -> Self {
+        Self {
+            jsonrpc: VERSION.to_string(),
+            payload: match res {
+                Ok(value) => Payload::Result(value),
+                Err(err) => Payload::Error(err),
+            },
+            id,
+        }
+    }
+}
+
+/// Parse a request, or make the response reporting why it can't be parsed.
+pub fn parse(body: &[u8]) -> Result<Request, Response> {
+    let value = serde_json::from_slice::<Value>(body).map_err(|err| {
+        let err = RpcError {
+            code: PARSE_ERROR,
+            message: err.to_string(),
+        };
+        Response::new(Value::Null, Err(err))
+    })?;
+    let id = value.get("id").cloned().unwrap_or(Value::Null);
+    match serde_json::from_value::<Request>(value) {
+        Ok(req) if req.jsonrpc == VERSION => Ok(req),
+        Ok(req) => Err(Response::new(
+            id,
+            Err(RpcError {
+                code: INVALID_REQUEST,
+                message: format!("Unsupported version {}", req.jsonrpc),
+            }),
+        )),
+        Err(err) => Err(Response::new(
+            id,
+            Err(RpcError {
+                code: INVALID_REQUEST,
+                message: err.to_string(),
+            }),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::jsonrpc::{parse, Payload, Response, INVALID_REQUEST, PARSE_ERROR};
+
+    #[test]
+    fn malformed_requests_answered_with_errors() {
+        let code = |resp: Response| match resp.payload {
+            Payload::Error(err) => err.code,
+            Payload::Result(_) => panic!("Error expected"),
+        };
+        assert_eq!(code(parse(b"{").unwrap_err()), PARSE_ERROR);
+        let wrong_version = parse(br#"{"jsonrpc":"1.0","method":"get_sync_state","id":7}"#).unwrap_err();
+        assert_eq!(wrong_version.id, json!(7));
+        assert_eq!(code(wrong_version), INVALID_REQUEST);
+        assert_eq!(
+            code(parse(br#"{"jsonrpc":"2.0","id":7}"#).unwrap_err()),
+            INVALID_REQUEST
+        );
+        let req = parse(br#"{"jsonrpc":"2.0","method":"get_sync_state","id":"a"}"#).unwrap();
+        assert_eq!(req.method, "get_sync_state");
+        assert_eq!(
+            serde_json::to_value(Response::new(req.id, Ok(json!(1)))).unwrap(),
+            json!({"jsonrpc": "2.0", "result": 1, "id": "a"})
+        );
+    }
+}
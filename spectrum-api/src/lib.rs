@@ -0,0 +1,4 @@
+//! JSON-RPC API of the node letting wallets and explorers query the chain and the ledger state.
+
+pub mod jsonrpc;
+pub mod node_api;
@@ -0,0 +1,409 @@
+//! Chain queries served over JSON-RPC: blocks and headers by id or slot, unspent cells by owner,
+//! status of transactions and the sync state of the node.
+//!
+//! Params are passed by name. Ids are base16-encoded, blocks and headers are returned in the
+//! serialized form they are stored in.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::routing::post;
+use axum::{Json, Router};
+use log::{error, info};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use spectrum_crypto::digest::Blake2bDigest256;
+use spectrum_ledger::block::{BlockHeader, BlockId, BlockSectionType, Modifier};
+use spectrum_ledger::cell::{AnyCell, CellMeta, Owner};
+use spectrum_ledger::transaction::TxId;
+use spectrum_ledger::{ModifierId, SlotNo};
+use spectrum_view::chain::HeaderLike;
+use spectrum_view::history::{LedgerHistoryReadAsync, LedgerHistoryReadSync};
+use spectrum_view::mempool::MempoolReadAsync;
+use spectrum_view::state::StateIndexes;
+
+use crate::jsonrpc::{self, Request, Response, RpcError};
+
+pub const GET_HEADER: &str = "get_header";
+pub const GET_BLOCK: &str = "get_block";
+pub const GET_CELLS: &str = "get_cells";
+pub const GET_TX_STATUS: &str = "get_tx_status";
+pub const GET_SYNC_STATE: &str = "get_sync_state";
+
+/// Block is looked up either by its id or by the slot it occupies in the best chain,
+/// i.e. `{"id": "<base16>"}` or `{"slot": <n>}`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockLocator {
+    Id(String),
+    Slot(SlotNo),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GetCellsParams {
+    pub owner: Owner,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GetTxStatusParams {
+    pub tx_id: String,
+}
+
+/// Serialized sections of a block, base16-encoded.
+/// Body is missing until it is downloaded.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RawBlock {
+    pub header: String,
+    pub body: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxStatus {
+    /// Waiting in the mempool.
+    Pending,
+    /// Applied to the ledger state.
+    Applied,
+    Unknown,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockPoint {
+    pub id: String,
+    pub slot: SlotNo,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncState {
+    /// Best block known to the node.
+    pub tip: BlockPoint,
+    /// Latest block finalized by the committee.
+    pub last_finalized: Option<BlockPoint>,
+}
+
+/// Node API built on top of the ledger history, the ledger state and the mempool.
+pub struct NodeApi<THistory, TState, TMempool> {
+    history: Arc<THistory>,
+    state: Arc<TState>,
+    mempool: Arc<TMempool>,
+}
+
+impl<THistory, TState, TMempool> NodeApi<THistory, TState, TMempool>
+where
+    THistory: LedgerHistoryReadAsync<BlockHeader> + LedgerHistoryReadSync + 'static,
+    TState: StateIndexes + Send + Sync + 'static,
+    TMempool: MempoolReadAsync + 'static,
+{
+    pub fn new(history: Arc<THistory>, state: Arc<TState>, mempool: Arc<TMempool>) -> Self {
+        Self {
+            history,
+            state,
+            mempool,
+        }
+    }
+
+    pub async fn handle(&self, req: Request) -> Response {
+        Response::new(req.id, self.call(&req.method, req.params).await)
+    }
+
+    async fn call(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            GET_HEADER => {
+                let header = match self.resolve(params_of(params)?)? {
+                    Some(id) => self.get_raw(BlockSectionType::Header, id).await,
+                    None => None,
+                };
+                to_value(header)
+            }
+            GET_BLOCK => {
+                let block = match self.resolve(params_of(params)?)? {
+                    Some(id) => match self.get_raw(BlockSectionType::Header, id).await {
+                        Some(header) => Some(RawBlock {
+                            header,
+                            body: self.get_raw(BlockSectionType::Body, id).await,
+                        }),
+                        None => None,
+                    },
+                    None => None,
+                };
+                to_value(block)
+            }
+            GET_CELLS => {
+                let GetCellsParams { owner } = params_of(params)?;
+                to_value::<Vec<CellMeta<AnyCell>>>(self.state.get_cells_of(owner))
+            }
+            GET_TX_STATUS => {
+                let GetTxStatusParams { tx_id } = params_of(params)?;
+                let tx_id = TxId::from(parse_digest(&tx_id)?);
+                let status = if self.state.is_applied(tx_id) {
+                    TxStatus::Applied
+                } else if self.mempool.contains(&ModifierId::from(tx_id)).await {
+                    TxStatus::Pending
+                } else {
+                    TxStatus::Unknown
+                };
+                to_value(status)
+            }
+            GET_SYNC_STATE => {
+                let tip = self.history.get_tip().await;
+                let last_finalized = self.history.get_last_finalized().await;
+                to_value(SyncState {
+                    tip: BlockPoint {
+                        id: BlockId::from(Blake2bDigest256::from(tip.id)).to_string(),
+                        slot: tip.modifier.slot_num(),
+                    },
+                    last_finalized: last_finalized.map(|cp| BlockPoint {
+                        id: cp.id.to_string(),
+                        slot: cp.slot,
+                    }),
+                })
+            }
+            _ => Err(RpcError::method_not_found(method)),
+        }
+    }
+
+    fn resolve(&self, locator: BlockLocator) -> Result<Option<BlockId>, RpcError> {
+        match locator {
+            BlockLocator::Id(id) => Ok(Some(BlockId::from(parse_digest(&id)?))),
+            BlockLocator::Slot(slot) => Ok(self
+                .history
+                .get_header_at(slot)
+                .map(|hdr| BlockId::from(Blake2bDigest256::from(hdr.id())))),
+        }
+    }
+
+    async fn get_raw(&self, sec_type: BlockSectionType, id: BlockId) -> Option<String> {
+        self.history
+            .multi_get_raw(sec_type, vec![ModifierId::from(id)])
+            .await
+            .pop()
+            .map(|raw| base16::encode_lower(&raw.0))
+    }
+
+    /// Serve the API at the given address until `shutdown` resolves.
+    pub async fn serve<F>(self, addr: SocketAddr, shutdown: F)
+    where
+        F: std::future::Future<Output = ()>,
+    {
+        match axum::Server::try_bind(&addr) {
+            Ok(server) => {
+                info!("[API] Listening on {}", addr);
+                let router = Router::new()
+                    .route("/", post(rpc::<THistory, TState, TMempool>))
+                    .with_state(Arc::new(self));
+                let res = server
+                    .serve(router.into_make_service())
+                    .with_graceful_shutdown(shutdown)
+                    .await;
+                if let Err(err) = res {
+                    error!("[API] Terminated: {}", err);
+                }
+            }
+            Err(err) => error!("[API] Cannot bind {}: {}", addr, err),
+        }
+    }
+}
+
+async fn rpc<THistory, TState, TMempool>(
+    State(api): State<Arc<NodeApi<THistory, TState, TMempool>>>,
+    body: Bytes,
+) -> Json<Response>
+where
+    THistory: LedgerHistoryReadAsync<BlockHeader> + LedgerHistoryReadSync + 'static,
+    TState: StateIndexes + Send + Sync + 'static,
+    TMempool: MempoolReadAsync + 'static,
+{
+    match jsonrpc::parse(&body) {
+        Ok(req) => Json(api.handle(req).await),
+        Err(resp) => Json(resp),
+    }
+}
+
+fn params_of<P: DeserializeOwned>(params: Value) -> Result<P, RpcError> {
+    serde_json::from_value(params).map_err(RpcError::invalid_params)
+}
+
+fn to_value<R: Serialize>(res: R) -> Result<Value, RpcError> {
+    serde_json::to_value(res).map_err(RpcError::internal)
+}
+
+fn parse_digest(s: &str) -> Result<Blake2bDigest256, RpcError> {
+    Blake2bDigest256::from_base16(s).map_err(RpcError::invalid_params)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use nonempty::NonEmpty;
+    use serde_json::{json, Value};
+
+    use spectrum_crypto::digest::Blake2bDigest256;
+    use spectrum_ledger::block::{BlockHeader, BlockId, BlockSectionType};
+    use spectrum_ledger::cell::{AnyCell, CellMeta, Owner};
+    use spectrum_ledger::transaction::TxId;
+    use spectrum_ledger::{ModifierId, ModifierRecord, ModifierType, SerializedModifier, SlotNo};
+    use spectrum_view::finality::Checkpoint;
+    use spectrum_view::history::{LedgerHistoryReadAsync, LedgerHistoryReadSync};
+    use spectrum_view::mempool::MempoolReadAsync;
+    use spectrum_view::state::StateIndexes;
+
+    use crate::jsonrpc::{Payload, Request, RpcError, INVALID_PARAMS, METHOD_NOT_FOUND, VERSION};
+    use crate::node_api::{NodeApi, RawBlock, TxStatus, GET_BLOCK, GET_HEADER, GET_TX_STATUS};
+
+    /// History of raw block sections with no headers to look up by slot.
+    struct RawHistory(HashMap<(BlockSectionType, ModifierId), SerializedModifier>);
+
+    impl LedgerHistoryReadSync for RawHistory {
+        fn get_header(&self, _: &BlockId) -> Option<BlockHeader> {
+            None
+        }
+        fn get_header_at(&self, _: SlotNo) -> Option<BlockHeader> {
+            None
+        }
+        fn get_header_by_body_root(&self, _: &Blake2bDigest256) -> Option<BlockHeader> {
+            None
+        }
+    }
+
+    #[async_trait]
+    impl LedgerHistoryReadAsync<BlockHeader> for RawHistory {
+        async fn member(&self, _: &BlockId) -> bool {
+            unimplemented!()
+        }
+        async fn contains(&self, _: &ModifierId) -> bool {
+            unimplemented!()
+        }
+        async fn get_tip(&self) -> ModifierRecord<BlockHeader> {
+            unimplemented!()
+        }
+        async fn get_last_finalized(&self) -> Option<Checkpoint> {
+            unimplemented!()
+        }
+        async fn get_tail(&self, _: usize) -> NonEmpty<ModifierRecord<BlockHeader>> {
+            unimplemented!()
+        }
+        async fn follow(&self, _: BlockId, _: usize) -> Vec<BlockId> {
+            unimplemented!()
+        }
+        async fn multi_get_raw(
+            &self,
+            sec_type: BlockSectionType,
+            ids: Vec<ModifierId>,
+        ) -> Vec<SerializedModifier> {
+            ids.into_iter()
+                .filter_map(|id| self.0.get(&(sec_type, id)).cloned())
+                .collect()
+        }
+    }
+
+    struct AppliedTxs(HashSet<TxId>);
+
+    impl StateIndexes for AppliedTxs {
+        fn get_cells_of(&self, _: Owner) -> Vec<CellMeta<AnyCell>> {
+            vec![]
+        }
+        fn is_applied(&self, tx_id: TxId) -> bool {
+            self.0.contains(&tx_id)
+        }
+    }
+
+    struct PendingTxs(HashSet<ModifierId>);
+
+    #[async_trait]
+    impl MempoolReadAsync for PendingTxs {
+        async fn contains(&self, id: &ModifierId) -> bool {
+            self.0.contains(id)
+        }
+        async fn multi_get_raw(&self, _: ModifierType, _: Vec<ModifierId>) -> Vec<SerializedModifier> {
+            vec![]
+        }
+    }
+
+    type Api = NodeApi<RawHistory, AppliedTxs, PendingTxs>;
+
+    async fn call(api: &Api, method: &str, params: Value) -> Result<Value, RpcError> {
+        let resp = api
+            .handle(Request {
+                jsonrpc: VERSION.to_string(),
+                method: method.to_string(),
+                params,
+                id: json!(1),
+            })
+            .await;
+        assert_eq!(resp.id, json!(1));
+        match resp.payload {
+            Payload::Result(value) => Ok(value),
+            Payload::Error(err) => Err(err),
+        }
+    }
+
+    #[tokio::test]
+    async fn blocks_looked_up_by_id() {
+        let block_id = BlockId::from(Blake2bDigest256::random());
+        let api = NodeApi::new(
+            Arc::new(RawHistory(HashMap::from([(
+                (BlockSectionType::Header, ModifierId::from(block_id)),
+                SerializedModifier(vec![0xab, 0xcd]),
+            )]))),
+            Arc::new(AppliedTxs(HashSet::new())),
+            Arc::new(PendingTxs(HashSet::new())),
+        );
+        let id = json!({ "id": block_id.to_string() });
+        assert_eq!(call(&api, GET_HEADER, id.clone()).await, Ok(json!("abcd")));
+        assert_eq!(
+            call(&api, GET_BLOCK, id).await,
+            Ok(serde_json::to_value(RawBlock {
+                header: "abcd".to_string(),
+                body: None
+            })
+            .unwrap())
+        );
+        assert_eq!(
+            call(&api, GET_BLOCK, json!({ "slot": 10 })).await,
+            Ok(Value::Null)
+        );
+        assert_eq!(
+            call(&api, GET_HEADER, json!({ "id": "xyz" }))
+                .await
+                .unwrap_err()
+                .code,
+            INVALID_PARAMS
+        );
+        assert_eq!(
+            call(&api, "get_everything", Value::Null).await.unwrap_err().code,
+            METHOD_NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn tx_status_resolved_from_state_and_mempool() {
+        let (applied, pending, unknown) = (
+            TxId::from(Blake2bDigest256::random()),
+            TxId::from(Blake2bDigest256::random()),
+            TxId::from(Blake2bDigest256::random()),
+        );
+        let api = NodeApi::new(
+            Arc::new(RawHistory(HashMap::new())),
+            Arc::new(AppliedTxs(HashSet::from([applied]))),
+            Arc::new(PendingTxs(HashSet::from([ModifierId::from(pending)]))),
+        );
+        for (tx_id, status) in [
+            (applied, TxStatus::Applied),
+            (pending, TxStatus::Pending),
+            (unknown, TxStatus::Unknown),
+        ] {
+            let params = json!({ "tx_id": Blake2bDigest256::from(tx_id).to_string() });
+            assert_eq!(
+                call(&api, GET_TX_STATUS, params).await,
+                Ok(serde_json::to_value(status).unwrap())
+            );
+        }
+    }
+}
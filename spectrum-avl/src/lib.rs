@@ -0,0 +1,173 @@
+//! Authenticated AVL+ trees notarized reports commit to.
+//!
+//! Terminal cells of a report are inserted into an empty tree under keys `1..=n`, optionally
+//! followed by a chain-specific trailer under `n + 1`. The committee signs the resulting digest,
+//! while the proof of insertions lets the target chain (or anyone off-chain) recompute it.
+
+use bytes::Bytes;
+use scorex_crypto_avltree::authenticated_tree_ops::AuthenticatedTreeOps;
+use scorex_crypto_avltree::batch_avl_prover::BatchAVLProver;
+use scorex_crypto_avltree::batch_avl_verifier::BatchAVLVerifier;
+use scorex_crypto_avltree::batch_node::{AVLTree, Node, NodeHeader};
+use scorex_crypto_avltree::operation::{Digest32, KeyValue, Operation};
+use serde::{Deserialize, Serialize};
+
+/// Keys are big-endian `i64` indices of entries.
+pub const KEY_LENGTH: usize = 8;
+pub const VALUE_LENGTH: usize = 32;
+
+pub type Value = [u8; VALUE_LENGTH];
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum AvlError {
+    #[error("Failed to insert entry under key {0:?}")]
+    InsertionFailed(Bytes),
+    #[error("Proof is malformed or doesn't match the entries")]
+    InvalidProof,
+    #[error("Insertions lead to a digest other than the claimed one")]
+    DigestMismatch,
+}
+
+/// Proof of insertion of a batch of entries into an empty report tree.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct InsertionProof {
+    pub initial_digest: Vec<u8>,
+    pub proof: Vec<u8>,
+    pub resulting_digest: Vec<u8>,
+}
+
+/// Entries of the report tree in the order of insertion: hashes of terminal cells followed by
+/// the `trailer`, if any.
+pub fn report_entries(cell_hashes: &[Value], trailer: Option<Value>) -> Vec<KeyValue> {
+    cell_hashes
+        .iter()
+        .chain(trailer.as_ref())
+        .enumerate()
+        .map(|(i, value)| KeyValue {
+            key: Bytes::copy_from_slice(&(i as i64 + 1).to_be_bytes()),
+            value: Bytes::copy_from_slice(value),
+        })
+        .collect()
+}
+
+/// Report tree under construction.
+pub struct ReportTree {
+    prover: BatchAVLProver,
+    initial_digest: Vec<u8>,
+}
+
+impl ReportTree {
+    pub fn new() -> Self {
+        let prover = BatchAVLProver::new(empty_tree(), true);
+        let initial_digest = prover.digest().unwrap().to_vec();
+        Self {
+            prover,
+            initial_digest,
+        }
+    }
+
+    /// Current digest of the tree.
+    pub fn digest(&self) -> Vec<u8> {
+        self.prover.digest().unwrap().to_vec()
+    }
+
+    pub fn insert(&mut self, entry: KeyValue) -> Result<(), AvlError> {
+        let key = entry.key.clone();
+        self.prover
+            .perform_one_operation(&Operation::Insert(entry))
+            .map(|_| ())
+            .map_err(|_| AvlError::InsertionFailed(key))
+    }
+
+    /// Proof of all insertions made so far.
+    pub fn prove(mut self) -> InsertionProof {
+        let proof = self.prover.generate_proof().to_vec();
+        InsertionProof {
+            initial_digest: self.initial_digest,
+            proof,
+            resulting_digest: self.prover.digest().unwrap().to_vec(),
+        }
+    }
+}
+
+impl Default for ReportTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Insert `entries` into an empty report tree.
+pub fn prove_insertions(entries: Vec<KeyValue>) -> Result<InsertionProof, AvlError> {
+    let mut tree = ReportTree::new();
+    for entry in entries {
+        tree.insert(entry)?;
+    }
+    Ok(tree.prove())
+}
+
+/// Check that insertion of `entries` into an empty report tree leads to the claimed digest.
+pub fn verify_insertions(proof: &InsertionProof, entries: Vec<KeyValue>) -> Result<(), AvlError> {
+    let mut verifier = BatchAVLVerifier::new(
+        &Bytes::copy_from_slice(&proof.initial_digest),
+        &Bytes::copy_from_slice(&proof.proof),
+        empty_tree(),
+        Some(entries.len()),
+        Some(0),
+    )
+    .map_err(|_| AvlError::InvalidProof)?;
+    for entry in entries {
+        verifier
+            .perform_one_operation(&Operation::Insert(entry))
+            .map_err(|_| AvlError::InvalidProof)?;
+    }
+    match verifier.digest() {
+        Some(digest) if digest.as_ref() == proof.resulting_digest.as_slice() => Ok(()),
+        Some(_) => Err(AvlError::DigestMismatch),
+        None => Err(AvlError::InvalidProof),
+    }
+}
+
+/// Empty report tree. Nodes are never resolved as the tree is built from scratch.
+pub fn empty_tree() -> AVLTree {
+    AVLTree::new(label_only_resolver, KEY_LENGTH, Some(VALUE_LENGTH))
+}
+
+fn label_only_resolver(digest: &Digest32) -> Node {
+    Node::LabelOnly(NodeHeader::new(Some(*digest), None))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{prove_insertions, report_entries, verify_insertions, AvlError, ReportTree};
+
+    #[test]
+    fn insertions_verified_off_chain() {
+        let entries = report_entries(&[[1u8; 32], [2u8; 32], [3u8; 32]], Some([0u8; 32]));
+        let proof = prove_insertions(entries.clone()).unwrap();
+        assert_eq!(proof.initial_digest, ReportTree::new().digest());
+        assert_eq!(verify_insertions(&proof, entries.clone()), Ok(()));
+
+        let mut forged_digest = proof.clone();
+        forged_digest.resulting_digest[0] ^= 1;
+        assert_eq!(
+            verify_insertions(&forged_digest, entries.clone()),
+            Err(AvlError::DigestMismatch)
+        );
+        let other_entries = report_entries(&[[1u8; 32], [2u8; 32], [4u8; 32]], Some([0u8; 32]));
+        assert!(verify_insertions(&proof, other_entries).is_err());
+    }
+
+    #[test]
+    fn keys_assigned_in_order_of_insertion() {
+        let entries = report_entries(&[[7u8; 32]], Some([9u8; 32]));
+        assert_eq!(entries[0].key.as_ref(), 1i64.to_be_bytes());
+        assert_eq!(entries[1].key.as_ref(), 2i64.to_be_bytes());
+        assert_eq!(entries[1].value.as_ref(), [9u8; 32]);
+        let mut tree = ReportTree::new();
+        tree.insert(entries[0].clone()).unwrap();
+        assert!(matches!(
+            tree.insert(entries[0].clone()),
+            Err(AvlError::InsertionFailed(_))
+        ));
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::VecDeque;
+
+use spectrum_chain_connector::certificate::{verify_report_certificate, CommitteeSnapshot};
+use spectrum_chain_connector::ipc::RequestError;
+use spectrum_chain_connector::{
+    ConnectorRequest, ConnectorResponse, ConnectorStatus, NotarizedReport, PendingTxIdentifier,
+    PendingTxStatus, PendingWithdrawalStatus, TxStatus,
+};
+use spectrum_ledger::cell::ProgressPoint;
+use spectrum_ledger::interop::Point;
+use spectrum_ledger::CARDANO_CHAIN_ID;
+
+use crate::datum::ToPlutusData;
+use crate::script::{CardanoNotarizedReport, CardanoUtxoRef, ExtraCardanoData};
+
+pub type CardanoConnectorRequest = ConnectorRequest<ExtraCardanoData, CardanoUtxoRef>;
+
+/// Notarization bounds aren't proposed by the Cardano connector yet, hence `()`.
+pub type CardanoConnectorResponse = ConnectorResponse<ExtraCardanoData, (), CardanoUtxoRef, CardanoUtxoRef>;
+
+/// Withdrawal validated by the connector and waiting to be submitted on-chain.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WithdrawalToSubmit {
+    pub vault_utxos: Vec<CardanoUtxoRef>,
+    /// CBOR-encoded Plutus data of the notarized report, used as the redeemer of the vault.
+    pub redeemer: Vec<u8>,
+}
+
+/// Handles requests of the consensus-driver for the Cardano chain.
+pub struct CardanoConnector {
+    progress_point: ProgressPoint,
+    /// Committee which must have notarized withdrawals.
+    committee: CommitteeSnapshot,
+    pending_withdrawals: Vec<NotarizedReport<ExtraCardanoData>>,
+    withdrawals_to_submit: VecDeque<WithdrawalToSubmit>,
+}
+
+impl CardanoConnector {
+    pub fn new(starting_slot: u64, committee: CommitteeSnapshot) -> Self {
+        Self {
+            progress_point: ProgressPoint {
+                chain_id: CARDANO_CHAIN_ID,
+                point: Point::from(starting_slot),
+            },
+            committee,
+            pending_withdrawals: vec![],
+            withdrawals_to_submit: VecDeque::new(),
+        }
+    }
+
+    /// Advance the progress point as blocks are applied by the data bridge.
+    pub fn on_block_applied(&mut self, slot: u64) {
+        self.progress_point.point = Point::from(slot);
+    }
+
+    /// Check that the report is notarized by the committee and spends vault UTxOs
+    /// which aren't already claimed by another pending withdrawal.
+    fn validate_withdrawal(&self, report: &NotarizedReport<ExtraCardanoData>) -> Result<(), RequestError> {
+        verify_report_certificate(report, &self.committee)
+            .map_err(|e| RequestError::Invalid(format!("Withdrawal certificate: {}", e)))?;
+        let vault_utxos = &report.additional_chain_data.vault_utxos;
+        if vault_utxos.is_empty() {
+            return Err(RequestError::Invalid("No vault UTxOs to withdraw from".into()));
+        }
+        for (ix, utxo) in vault_utxos.iter().enumerate() {
+            if vault_utxos[..ix].contains(utxo) {
+                return Err(RequestError::Invalid(format!(
+                    "Vault UTxO {:?} is spent twice",
+                    utxo
+                )));
+            }
+            let claimed = self
+                .pending_withdrawals
+                .iter()
+                .any(|r| r.additional_chain_data.vault_utxos.contains(utxo));
+            if claimed {
+                return Err(RequestError::Invalid(format!(
+                    "Vault UTxO {:?} is already spent by a pending withdrawal",
+                    utxo
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// The oldest validated withdrawal which isn't submitted yet.
+    pub fn next_withdrawal_to_submit(&mut self) -> Option<WithdrawalToSubmit> {
+        self.withdrawals_to_submit.pop_front()
+    }
+
+    pub fn get_connector_status(&self) -> ConnectorStatus<ExtraCardanoData, CardanoUtxoRef> {
+        ConnectorStatus::Synced {
+            current_progress_point: self.progress_point.clone(),
+            pending_txs: self
+                .pending_withdrawals
+                .iter()
+                .map(|report| {
+                    PendingTxStatus::Withdrawal(PendingWithdrawalStatus {
+                        identifier: report.clone(),
+                        status: TxStatus::WaitingForConfirmation,
+                    })
+                })
+                .collect(),
+            // Node health isn't monitored yet.
+            node_health: NodeHealth::default(),
+        }
+    }
+
+    pub fn handle_request(
+        &mut self,
+        request: CardanoConnectorRequest,
+    ) -> Result<CardanoConnectorResponse, RequestError> {
+        match request {
+            ConnectorRequest::SyncFrom(point) => {
+                if let Some(point) = point {
+                    if point.chain_id != CARDANO_CHAIN_ID {
+                        return Err(RequestError::Invalid(format!(
+                            "Progress point of chain {:?}",
+                            point.chain_id
+                        )));
+                    }
+                    self.progress_point = point;
+                }
+            }
+            ConnectorRequest::ValidateAndProcessWithdrawals(report) => {
+                self.validate_withdrawal(&report)?;
+                let vault_utxos = report.additional_chain_data.vault_utxos.clone();
+                let cardano_report = CardanoNotarizedReport::try_from(*report.clone())
+                    .map_err(|e| RequestError::Invalid(format!("{:?}", e)))?;
+                self.withdrawals_to_submit.push_back(WithdrawalToSubmit {
+                    vault_utxos,
+                    redeemer: cardano_report.to_plutus_data().to_cbor(),
+                });
+                self.pending_withdrawals.push(*report);
+            }
+            ConnectorRequest::AcknowledgeConfirmedTx(PendingTxIdentifier::Withdrawal(report), _)
+            | ConnectorRequest::AcknowledgeAbortedTx(PendingTxIdentifier::Withdrawal(report), _) => {
+                self.pending_withdrawals.retain(|r| *r != *report);
+            }
+            ConnectorRequest::Disconnect => {}
+            other => {
+                return Err(RequestError::Invalid(format!(
+                    "{:?} isn't supported by the Cardano connector yet",
+                    other
+                )))
+            }
+        }
+        Ok(ConnectorResponse {
+            status: self.get_connector_status(),
+            messages: vec![],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::SecretKey;
+    use rand::rngs::OsRng;
+    use spectrum_chain_connector::certificate::CommitteeSnapshot;
+    use spectrum_chain_connector::ipc::RequestError;
+    use spectrum_chain_connector::{ConnectorRequest, NotarizedReport, PendingTxIdentifier};
+    use spectrum_crypto::digest::{blake2b256_hash, Blake2b256};
+    use spectrum_crypto::pubkey::PublicKey;
+    use spectrum_handel::Threshold;
+    use spectrum_ledger::cell::ProgressPoint;
+    use spectrum_ledger::interop::{Point, ReportCertificate};
+    use spectrum_ledger::{CARDANO_CHAIN_ID, ERGO_CHAIN_ID};
+    use spectrum_sigma::crypto::{
+        aggregate_commitment, aggregate_pk, aggregate_response, challenge, individual_input, response,
+        schnorr_commitment_pair,
+    };
+    use spectrum_sigma::sigma_aggregation::AggregateCertificate;
+
+    use crate::cardano_connector::CardanoConnector;
+    use crate::script::{CardanoUtxoRef, ExtraCardanoData};
+
+    const THRESHOLD: Threshold = Threshold { num: 2, denom: 3 };
+
+    fn committee(sks: &[SecretKey]) -> CommitteeSnapshot {
+        CommitteeSnapshot {
+            members: sks.iter().map(|sk| PublicKey::from(sk.clone())).collect(),
+            threshold: THRESHOLD,
+        }
+    }
+
+    /// Report notarized by all members of the committee.
+    fn notarized_report(
+        sks: &[SecretKey],
+        vault_utxos: Vec<CardanoUtxoRef>,
+    ) -> NotarizedReport<ExtraCardanoData> {
+        let authenticated_digest = vec![1u8; 33];
+        let md = blake2b256_hash(&authenticated_digest);
+        let members = committee(sks).members;
+        let inputs = members
+            .iter()
+            .map(|pk| individual_input::<Blake2b256>(members.clone(), *pk))
+            .collect::<Vec<_>>();
+        let commitments = sks.iter().map(|_| schnorr_commitment_pair()).collect::<Vec<_>>();
+        let aggr_commitment = aggregate_commitment(commitments.iter().map(|(_, c)| c.clone()).collect());
+        let c = challenge(aggregate_pk(members, inputs.clone()), aggr_commitment.clone(), md);
+        let responses = commitments
+            .into_iter()
+            .enumerate()
+            .map(|(i, (secret, _))| response(secret, sks[i].clone(), c, inputs[i]))
+            .collect();
+        NotarizedReport {
+            certificate: ReportCertificate::SchnorrK256(AggregateCertificate {
+                message_digest: md,
+                aggregate_commitment: aggr_commitment,
+                aggregate_response: aggregate_response(responses),
+                exclusion_set: vec![],
+            }),
+            value_to_withdraw: vec![],
+            authenticated_digest,
+            additional_chain_data: ExtraCardanoData {
+                proof: vec![],
+                max_tx_fee: 1_000_000,
+                threshold: THRESHOLD,
+                vault_utxos,
+            },
+            inclusion_proof: None,
+        }
+    }
+
+    fn vault_utxo(index: u64) -> CardanoUtxoRef {
+        CardanoUtxoRef {
+            tx_hash: [0; 32],
+            index,
+        }
+    }
+
+    fn make_committee() -> Vec<SecretKey> {
+        (0..3).map(|_| SecretKey::random(&mut OsRng)).collect()
+    }
+
+    #[test]
+    fn withdrawal_is_pending_until_acknowledged() {
+        let sks = make_committee();
+        let mut connector = CardanoConnector::new(0, committee(&sks));
+        let report = notarized_report(&sks, vec![vault_utxo(0)]);
+        let resp = connector
+            .handle_request(ConnectorRequest::ValidateAndProcessWithdrawals(Box::new(
+                report.clone(),
+            )))
+            .unwrap();
+        assert_eq!(resp.status.get_pending_txs().len(), 1);
+        let withdrawal = connector.next_withdrawal_to_submit().unwrap();
+        assert_eq!(withdrawal.vault_utxos, report.additional_chain_data.vault_utxos);
+        assert!(!withdrawal.redeemer.is_empty());
+
+        let point = connector.get_connector_status().get_current_progress_point();
+        let resp = connector
+            .handle_request(ConnectorRequest::AcknowledgeConfirmedTx(
+                PendingTxIdentifier::Withdrawal(Box::new(report)),
+                point,
+            ))
+            .unwrap();
+        assert!(resp.status.get_pending_txs().is_empty());
+    }
+
+    #[test]
+    fn reject_withdrawal_not_notarized_by_committee() {
+        let sks = make_committee();
+        let mut connector = CardanoConnector::new(0, committee(&make_committee()));
+        let report = notarized_report(&sks, vec![vault_utxo(0)]);
+        assert!(matches!(
+            connector.handle_request(ConnectorRequest::ValidateAndProcessWithdrawals(Box::new(report))),
+            Err(RequestError::Invalid(_))
+        ));
+        // Certificate issued over another digest.
+        let mut connector = CardanoConnector::new(0, committee(&sks));
+        let mut report = notarized_report(&sks, vec![vault_utxo(0)]);
+        report.authenticated_digest = vec![2u8; 33];
+        assert!(matches!(
+            connector.handle_request(ConnectorRequest::ValidateAndProcessWithdrawals(Box::new(report))),
+            Err(RequestError::Invalid(_))
+        ));
+        assert!(connector.next_withdrawal_to_submit().is_none());
+        assert!(connector.get_connector_status().get_pending_txs().is_empty());
+    }
+
+    #[test]
+    fn reject_withdrawal_with_invalid_vault_utxos() {
+        let sks = make_committee();
+        let mut connector = CardanoConnector::new(0, committee(&sks));
+        for vault_utxos in [vec![], vec![vault_utxo(0), vault_utxo(0)]] {
+            let report = notarized_report(&sks, vault_utxos);
+            assert!(matches!(
+                connector.handle_request(ConnectorRequest::ValidateAndProcessWithdrawals(Box::new(report))),
+                Err(RequestError::Invalid(_))
+            ));
+        }
+        // UTxO already spent by a pending withdrawal.
+        let report = notarized_report(&sks, vec![vault_utxo(0)]);
+        connector
+            .handle_request(ConnectorRequest::ValidateAndProcessWithdrawals(Box::new(report)))
+            .unwrap();
+        let report = notarized_report(&sks, vec![vault_utxo(1), vault_utxo(0)]);
+        assert!(matches!(
+            connector.handle_request(ConnectorRequest::ValidateAndProcessWithdrawals(Box::new(report))),
+            Err(RequestError::Invalid(_))
+        ));
+        assert_eq!(connector.get_connector_status().get_pending_txs().len(), 1);
+    }
+
+    #[test]
+    fn reject_foreign_progress_point() {
+        let mut connector = CardanoConnector::new(0, committee(&make_committee()));
+        let point = ProgressPoint {
+            chain_id: ERGO_CHAIN_ID,
+            point: Point::from(10),
+        };
+        assert!(matches!(
+            connector.handle_request(ConnectorRequest::SyncFrom(Some(point))),
+            Err(RequestError::Invalid(_))
+        ));
+        assert_eq!(
+            connector
+                .get_connector_status()
+                .get_current_progress_point()
+                .chain_id,
+            CARDANO_CHAIN_ID
+        );
+    }
+}
@@ -0,0 +1,161 @@
+//! Plutus data and its CBOR encoding as expected by the Cardano ledger.
+
+use minicbor::data::{Int, Tag};
+use minicbor::encode::{Error, Write};
+use minicbor::Encoder;
+
+/// Byte strings longer than this are encoded as indefinite sequences of chunks.
+const MAX_BYTES_CHUNK: usize = 64;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PlutusData {
+    /// Constructor `ix` of a sum type applied to `fields`.
+    Constr(u64, Vec<PlutusData>),
+    Map(Vec<(PlutusData, PlutusData)>),
+    List(Vec<PlutusData>),
+    Int(i128),
+    Bytes(Vec<u8>),
+}
+
+/// Conversion of a value into the datum of a Cardano output or a redeemer.
+pub trait ToPlutusData {
+    fn to_plutus_data(&self) -> PlutusData;
+}
+
+impl PlutusData {
+    pub fn maybe(value: Option<PlutusData>) -> Self {
+        match value {
+            Some(v) => PlutusData::Constr(0, vec![v]),
+            None => PlutusData::Constr(1, vec![]),
+        }
+    }
+
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new(Vec::new());
+        self.encode(&mut encoder).expect("Writing to Vec never fails");
+        encoder.into_writer()
+    }
+
+    pub fn encode<W: Write>(&self, e: &mut Encoder<W>) -> Result<(), Error<W::Error>> {
+        match self {
+            PlutusData::Constr(ix, fields) => {
+                // Compact tags are assigned to the first 128 constructors, see CIP-0005.
+                match ix {
+                    0..=6 => {
+                        e.tag(Tag::Unassigned(121 + ix))?;
+                        encode_list(e, fields)?;
+                    }
+                    7..=127 => {
+                        e.tag(Tag::Unassigned(1280 + ix - 7))?;
+                        encode_list(e, fields)?;
+                    }
+                    _ => {
+                        e.tag(Tag::Unassigned(102))?.array(2)?.u64(*ix)?;
+                        encode_list(e, fields)?;
+                    }
+                }
+            }
+            PlutusData::Map(entries) => {
+                e.map(entries.len() as u64)?;
+                for (k, v) in entries {
+                    k.encode(e)?;
+                    v.encode(e)?;
+                }
+            }
+            PlutusData::List(items) => encode_list(e, items)?,
+            PlutusData::Int(i) => match Int::try_from(*i) {
+                Ok(i) => {
+                    e.int(i)?;
+                }
+                Err(_) => {
+                    // Outside of the CBOR integer range, encoded as a bignum.
+                    let (tag, magnitude) = if *i >= 0 {
+                        (Tag::PosBignum, *i as u128)
+                    } else {
+                        (Tag::NegBignum, (-1 - *i) as u128)
+                    };
+                    let bytes = magnitude.to_be_bytes();
+                    let leading_zeros = bytes.iter().take_while(|b| **b == 0).count();
+                    e.tag(tag)?.bytes(&bytes[leading_zeros..])?;
+                }
+            },
+            PlutusData::Bytes(bytes) => {
+                if bytes.len() <= MAX_BYTES_CHUNK {
+                    e.bytes(bytes)?;
+                } else {
+                    e.begin_bytes()?;
+                    for chunk in bytes.chunks(MAX_BYTES_CHUNK) {
+                        e.bytes(chunk)?;
+                    }
+                    e.end()?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Non-empty lists are encoded as indefinite arrays, the same way the Cardano ledger does it.
+fn encode_list<W: Write>(e: &mut Encoder<W>, items: &[PlutusData]) -> Result<(), Error<W::Error>> {
+    if items.is_empty() {
+        e.array(0)?;
+    } else {
+        e.begin_array()?;
+        for item in items {
+            item.encode(e)?;
+        }
+        e.end()?;
+    }
+    Ok(())
+}
+
+impl From<u64> for PlutusData {
+    fn from(value: u64) -> Self {
+        PlutusData::Int(value as i128)
+    }
+}
+
+impl From<Vec<u8>> for PlutusData {
+    fn from(value: Vec<u8>) -> Self {
+        PlutusData::Bytes(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::datum::PlutusData;
+
+    #[test]
+    fn constr_encoding() {
+        let datum = PlutusData::Constr(0, vec![PlutusData::Int(1), PlutusData::Bytes(vec![0xab])]);
+        assert_eq!(datum.to_cbor(), hex::decode("d8799f0141abff").unwrap());
+        assert_eq!(
+            PlutusData::Constr(1, vec![]).to_cbor(),
+            hex::decode("d87a80").unwrap()
+        );
+        assert_eq!(
+            PlutusData::Constr(7, vec![]).to_cbor(),
+            hex::decode("d9050080").unwrap()
+        );
+        assert_eq!(
+            PlutusData::Constr(128, vec![]).to_cbor(),
+            hex::decode("d86682188080").unwrap()
+        );
+    }
+
+    #[test]
+    fn long_bytes_are_chunked() {
+        let datum = PlutusData::Bytes(vec![0; 65]);
+        let cbor = datum.to_cbor();
+        // Indefinite byte string: 64-byte chunk, 1-byte chunk, break.
+        assert_eq!(cbor[0], 0x5f);
+        assert_eq!(&cbor[1..3], &[0x58, 0x40]);
+        assert_eq!(&cbor[67..], &[0x41, 0x00, 0xff]);
+    }
+
+    #[test]
+    fn big_int_encoding() {
+        let datum = PlutusData::Int(u64::MAX as i128 + 1);
+        assert_eq!(datum.to_cbor(), hex::decode("c249010000000000000000").unwrap());
+    }
+}
@@ -7,12 +7,21 @@ use pallas_network::{
     },
 };
 use pallas_traverse::{MultiEraBlock, MultiEraHeader};
-use spectrum_chain_connector::{DataBridge, DataBridgeComponents, TxEvent};
+use spectrum_chain_connector::{
+    bridge::{self, BridgeReceiver, BridgeSender},
+    DataBridge, DataBridgeComponents, TxEvent,
+};
 
+pub mod cardano_connector;
+pub mod datum;
 mod rocksdb;
+pub mod script;
+
+/// Number of the most recent events the bridge keeps to replay them to the consumer on resync.
+const REPLAY_CAPACITY: usize = 4096;
 
 pub struct CardanoDataBridge {
-    pub receiver: tokio::sync::mpsc::Receiver<TxEvent<Vec<u8>>>,
+    pub receiver: BridgeReceiver<Vec<u8>>,
     tx_start: tokio::sync::oneshot::Sender<()>,
 }
 
@@ -36,7 +45,7 @@ pub struct CardanoDataBridgeConfig {
 
 impl CardanoDataBridge {
     pub fn new(config: CardanoDataBridgeConfig) -> Self {
-        let (tx, receiver) = tokio::sync::mpsc::channel(16);
+        let (tx, receiver) = bridge::channel(16, REPLAY_CAPACITY);
         let (tx_start, rx_start) = tokio::sync::oneshot::channel();
 
         tokio::spawn(run_bridge(tx, rx_start, config));
@@ -46,7 +55,7 @@ impl CardanoDataBridge {
 }
 
 async fn run_bridge(
-    tx: tokio::sync::mpsc::Sender<TxEvent<Vec<u8>>>,
+    mut tx: BridgeSender<Vec<u8>>,
     rx_start: tokio::sync::oneshot::Receiver<()>,
     config: CardanoDataBridgeConfig,
 ) {
@@ -114,8 +123,9 @@ async fn run_bridge(
                     transactions: transactions.clone(),
                 };
                 chain_cache.append_block(block).await;
+                let slot = spectrum_ledger::interop::Point::from(multi_era_block.slot());
                 for transaction in transactions {
-                    tx.send(TxEvent::AppliedTx(transaction)).await.unwrap();
+                    tx.send(slot, TxEvent::AppliedTx(transaction)).await.unwrap();
                 }
             }
             NextResponse::RollBackward(point, _) => {
@@ -127,8 +137,9 @@ async fn run_bridge(
                             break;
                         } else {
                             let block = chain_cache.take_best_block().await.unwrap();
+                            let slot = spectrum_ledger::interop::Point::from(block.slot);
                             for transaction in block.transactions {
-                                tx.send(TxEvent::UnappliedTx(transaction)).await.unwrap();
+                                tx.send(slot, TxEvent::UnappliedTx(transaction)).await.unwrap();
                             }
                         }
                     }
@@ -152,7 +163,7 @@ mod tests {
     use pallas_primitives::babbage::MintedBlock;
     use pallas_traverse::{MultiEraBlock, MultiEraHeader};
     use rand::RngCore;
-    use spectrum_chain_connector::{DataBridge, DataBridgeComponents, TxEvent};
+    use spectrum_chain_connector::{bridge::BridgeEvent, DataBridge, DataBridgeComponents, TxEvent};
 
     use crate::rocksdb::{deserialize_tx, RocksConfig};
     use crate::{CardanoDataBridge, CardanoDataBridgeConfig};
@@ -183,14 +194,21 @@ mod tests {
         for _ in 0..10 {
             let tx = receiver.recv().await.unwrap();
             match tx {
-                TxEvent::AppliedTx(bytes) => {
+                BridgeEvent::Tx {
+                    event: TxEvent::AppliedTx(bytes),
+                    ..
+                } => {
                     let transaction = deserialize_tx(&bytes);
                     println!("AppliedTx: {:?}", transaction.hash());
                 }
-                TxEvent::UnappliedTx(bytes) => {
+                BridgeEvent::Tx {
+                    event: TxEvent::UnappliedTx(bytes),
+                    ..
+                } => {
                     let transaction = deserialize_tx(&bytes);
                     println!("UnappliedTx: {:?}", transaction.hash());
                 }
+                other => panic!("Unexpected bridge event {:?}", other),
             }
         }
     }
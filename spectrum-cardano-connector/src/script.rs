@@ -0,0 +1,421 @@
+use std::collections::{BTreeMap, HashMap};
+
+use derive_more::From;
+use pallas_addresses::Address;
+use serde::{Deserialize, Serialize};
+use spectrum_chain_connector::{NotarizedReport, ProtoTermCell};
+use spectrum_crypto::digest::Blake2bDigest256;
+use spectrum_crypto::merkle::{MerkleProof, Side};
+use spectrum_handel::Threshold;
+use spectrum_ledger::cell::{
+    AssetId, BoxDestination, CustomAsset, NativeCoin, PolicyId, SValue, TermCell, TermConstraints,
+};
+use spectrum_ledger::interop::ReportCertificate;
+use spectrum_ledger::CARDANO_CHAIN_ID;
+use spectrum_move::SerializedValue;
+use spectrum_sigma::sigma_aggregation::AggregateCertificate;
+
+use crate::datum::{PlutusData, ToPlutusData};
+
+/// Length of a Cardano minting policy ID (Blake2b-224 hash of the policy script).
+pub const POLICY_ID_LEN: usize = 28;
+
+pub type CardanoPolicyId = [u8; POLICY_ID_LEN];
+
+/// Spectrum asset IDs are 32 bytes long, so are the names of the assets bridged to Cardano.
+pub type CardanoAssetName = [u8; 32];
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+/// Reference to an output on Cardano.
+pub struct CardanoUtxoRef {
+    pub tx_hash: [u8; 32],
+    pub index: u64,
+}
+
+/// Output to create on Cardano for a term cell.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CardanoTermCell {
+    pub lovelace: u64,
+    /// Raw bytes of the Shelley address of the recipient.
+    pub address: Vec<u8>,
+    pub assets: BTreeMap<CardanoPolicyId, BTreeMap<CardanoAssetName, u64>>,
+    pub timelock: Option<CardanoTimelock>,
+}
+
+/// Value of the term cell is refundable to `refund_address` if it is left unclaimed until `slot`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CardanoTimelock {
+    pub refund_address: Vec<u8>,
+    pub slot: u64,
+}
+
+#[derive(Debug, From)]
+pub enum CardanoTermCellError {
+    Address(pallas_addresses::Error),
+    /// Only Shelley addresses can receive value from the vault.
+    NotShelleyAddress,
+    /// Cardano policy IDs are 28 bytes long, remaining bytes of the policy ID must be zero.
+    PolicyIdOutOfRange,
+    WrongChainId,
+}
+
+fn shelley_address(address: &SerializedValue) -> Result<Vec<u8>, CardanoTermCellError> {
+    let bytes: Vec<u8> = address.clone().into();
+    match Address::from_bytes(&bytes)? {
+        Address::Shelley(_) => Ok(bytes),
+        _ => Err(CardanoTermCellError::NotShelleyAddress),
+    }
+}
+
+fn cardano_policy_id(policy_id: PolicyId) -> Result<CardanoPolicyId, CardanoTermCellError> {
+    let raw = *Blake2bDigest256::from(policy_id).raw();
+    let (id, rest) = raw.split_at(POLICY_ID_LEN);
+    if rest.iter().any(|b| *b != 0) {
+        return Err(CardanoTermCellError::PolicyIdOutOfRange);
+    }
+    Ok(CardanoPolicyId::try_from(id).unwrap())
+}
+
+fn spectrum_policy_id(policy_id: &CardanoPolicyId) -> PolicyId {
+    let mut raw = policy_id.to_vec();
+    raw.resize(32, 0);
+    PolicyId::from(Blake2bDigest256::try_from(raw).unwrap())
+}
+
+impl CardanoTermCell {
+    fn from_parts(value: SValue, dst: &BoxDestination) -> Result<Self, CardanoTermCellError> {
+        if dst.target != CARDANO_CHAIN_ID {
+            return Err(CardanoTermCellError::WrongChainId);
+        }
+        let address = shelley_address(&dst.address)?;
+        let timelock = if let Some(TermConstraints {
+            timelock_height,
+            refund_owner,
+        }) = &dst.constraints
+        {
+            Some(CardanoTimelock {
+                refund_address: shelley_address(refund_owner)?,
+                slot: *timelock_height,
+            })
+        } else {
+            None
+        };
+        let mut assets = BTreeMap::new();
+        for (policy_id, tokens) in value.assets {
+            if tokens.is_empty() {
+                continue;
+            }
+            let policy_assets: &mut BTreeMap<_, _> = assets.entry(cardano_policy_id(policy_id)?).or_default();
+            for (asset_id, amount) in tokens {
+                policy_assets.insert(*Blake2bDigest256::from(asset_id).raw(), u64::from(amount));
+            }
+        }
+        Ok(CardanoTermCell {
+            lovelace: u64::from(value.native),
+            address,
+            assets,
+            timelock,
+        })
+    }
+
+    pub fn value(&self) -> SValue {
+        let assets = self
+            .assets
+            .iter()
+            .map(|(policy_id, tokens)| {
+                let tokens: HashMap<AssetId, CustomAsset> = tokens
+                    .iter()
+                    .map(|(name, amount)| {
+                        let asset_id = AssetId::from(Blake2bDigest256::try_from(name.to_vec()).unwrap());
+                        (asset_id, CustomAsset::from(*amount))
+                    })
+                    .collect();
+                (spectrum_policy_id(policy_id), tokens)
+            })
+            .collect();
+        SValue {
+            native: NativeCoin::from(self.lovelace),
+            assets,
+        }
+    }
+
+    pub fn destination(&self) -> BoxDestination {
+        BoxDestination {
+            target: CARDANO_CHAIN_ID,
+            address: SerializedValue::from(self.address.clone()),
+            inputs: None,
+            constraints: self.timelock.as_ref().map(|t| TermConstraints {
+                timelock_height: t.slot,
+                refund_owner: SerializedValue::from(t.refund_address.clone()),
+            }),
+        }
+    }
+}
+
+impl TryFrom<TermCell> for CardanoTermCell {
+    type Error = CardanoTermCellError;
+
+    fn try_from(value: TermCell) -> Result<Self, Self::Error> {
+        CardanoTermCell::from_parts(value.value, &value.dst)
+    }
+}
+
+impl TryFrom<ProtoTermCell> for CardanoTermCell {
+    type Error = CardanoTermCellError;
+
+    fn try_from(value: ProtoTermCell) -> Result<Self, Self::Error> {
+        CardanoTermCell::from_parts(value.value, &value.dst)
+    }
+}
+
+impl From<CardanoTermCell> for ProtoTermCell {
+    fn from(value: CardanoTermCell) -> Self {
+        Self {
+            value: value.value(),
+            dst: value.destination(),
+        }
+    }
+}
+
+impl ToPlutusData for CardanoTermCell {
+    fn to_plutus_data(&self) -> PlutusData {
+        // TermCell { lovelace, address, assets, timelock: Maybe (refund_address, slot) }
+        let assets = self
+            .assets
+            .iter()
+            .map(|(policy_id, tokens)| {
+                let tokens = tokens
+                    .iter()
+                    .map(|(name, amount)| (PlutusData::from(name.to_vec()), PlutusData::from(*amount)))
+                    .collect();
+                (PlutusData::from(policy_id.to_vec()), PlutusData::Map(tokens))
+            })
+            .collect();
+        let timelock = self.timelock.as_ref().map(|t| {
+            PlutusData::Constr(
+                0,
+                vec![
+                    PlutusData::from(t.refund_address.clone()),
+                    PlutusData::from(t.slot),
+                ],
+            )
+        });
+        PlutusData::Constr(
+            0,
+            vec![
+                PlutusData::from(self.lovelace),
+                PlutusData::from(self.address.clone()),
+                PlutusData::Map(assets),
+                PlutusData::maybe(timelock),
+            ],
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+/// Cardano-specific data of a notarized report.
+pub struct ExtraCardanoData {
+    /// Proof of the transition of the vault's AVL tree.
+    pub proof: Vec<u8>,
+    pub max_tx_fee: u64,
+    pub threshold: Threshold,
+    /// Vault UTxOs to withdraw the value from.
+    pub vault_utxos: Vec<CardanoUtxoRef>,
+}
+
+/// Notarized report in the shape the vault validator expects it in the redeemer.
+pub struct CardanoNotarizedReport {
+    pub certificate: ReportCertificate,
+    pub term_cells: Vec<CardanoTermCell>,
+    pub authenticated_digest: Vec<u8>,
+    pub proof: Vec<u8>,
+    pub max_tx_fee: u64,
+    pub threshold: Threshold,
+    /// Proof of inclusion of the report into the batch notarized by the certificate.
+    pub inclusion_proof: Option<MerkleProof>,
+}
+
+impl TryFrom<NotarizedReport<ExtraCardanoData>> for CardanoNotarizedReport {
+    type Error = CardanoTermCellError;
+
+    fn try_from(value: NotarizedReport<ExtraCardanoData>) -> Result<Self, Self::Error> {
+        let term_cells = value
+            .value_to_withdraw
+            .into_iter()
+            .map(CardanoTermCell::try_from)
+            .collect::<Result<_, _>>()?;
+        let ExtraCardanoData {
+            proof,
+            max_tx_fee,
+            threshold,
+            ..
+        } = value.additional_chain_data;
+        Ok(Self {
+            certificate: value.certificate,
+            term_cells,
+            authenticated_digest: value.authenticated_digest,
+            proof,
+            max_tx_fee,
+            threshold,
+            inclusion_proof: value.inclusion_proof,
+        })
+    }
+}
+
+impl ToPlutusData for ReportCertificate {
+    fn to_plutus_data(&self) -> PlutusData {
+        // Certificate { message_digest, aggregate_commitment, aggregate_response,
+        //               exclusion_set: [(ix, Maybe (commitment, signature))] }
+        let ReportCertificate::SchnorrK256(AggregateCertificate {
+            message_digest,
+            aggregate_commitment,
+            aggregate_response,
+            exclusion_set,
+        }) = self;
+        let exclusion_set = exclusion_set
+            .iter()
+            .map(|(ix, pair)| {
+                let pair = pair.as_ref().map(|(commitment, signature)| {
+                    let signature_bytes = k256::schnorr::Signature::from(signature.clone()).to_bytes();
+                    PlutusData::Constr(
+                        0,
+                        vec![
+                            PlutusData::from(commitment.as_bytes()),
+                            PlutusData::from(signature_bytes.to_vec()),
+                        ],
+                    )
+                });
+                PlutusData::Constr(0, vec![PlutusData::from(*ix as u64), PlutusData::maybe(pair)])
+            })
+            .collect();
+        PlutusData::Constr(
+            0,
+            vec![
+                PlutusData::from(message_digest.as_ref().to_vec()),
+                PlutusData::from(aggregate_commitment.clone().to_bytes()),
+                PlutusData::from(aggregate_response.to_bytes().to_vec()),
+                PlutusData::List(exclusion_set),
+            ],
+        )
+    }
+}
+
+impl ToPlutusData for MerkleProof {
+    fn to_plutus_data(&self) -> PlutusData {
+        // [(sibling, Left | Right)], nodes closest to the leaf first
+        PlutusData::List(
+            self.path
+                .iter()
+                .map(|node| {
+                    let side = match node.side {
+                        Side::Left => PlutusData::Constr(0, vec![]),
+                        Side::Right => PlutusData::Constr(1, vec![]),
+                    };
+                    PlutusData::Constr(0, vec![PlutusData::from(node.sibling.as_ref().to_vec()), side])
+                })
+                .collect(),
+        )
+    }
+}
+
+impl ToPlutusData for CardanoNotarizedReport {
+    fn to_plutus_data(&self) -> PlutusData {
+        // Report { certificate, term_cells, authenticated_digest, proof, max_tx_fee, (num, denom),
+        //          Maybe inclusion_proof }
+        PlutusData::Constr(
+            0,
+            vec![
+                self.certificate.to_plutus_data(),
+                PlutusData::List(self.term_cells.iter().map(|c| c.to_plutus_data()).collect()),
+                PlutusData::from(self.authenticated_digest.clone()),
+                PlutusData::from(self.proof.clone()),
+                PlutusData::from(self.max_tx_fee),
+                PlutusData::Constr(
+                    0,
+                    vec![
+                        PlutusData::from(self.threshold.num as u64),
+                        PlutusData::from(self.threshold.denom as u64),
+                    ],
+                ),
+                PlutusData::maybe(self.inclusion_proof.as_ref().map(|p| p.to_plutus_data())),
+            ],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use spectrum_crypto::digest::Blake2bDigest256;
+    use spectrum_ledger::cell::{
+        AssetId, BoxDestination, CustomAsset, NativeCoin, PolicyId, SValue, TermCell, TermConstraints,
+    };
+    use spectrum_ledger::transaction::TxId;
+    use spectrum_ledger::{CARDANO_CHAIN_ID, ERGO_CHAIN_ID};
+    use spectrum_move::SerializedValue;
+
+    use crate::datum::{PlutusData, ToPlutusData};
+    use crate::script::{CardanoTermCell, CardanoTermCellError};
+
+    /// Enterprise address (header `0x61`: mainnet, key hash payment credential).
+    fn enterprise_address(key_hash_byte: u8) -> SerializedValue {
+        let mut bytes = vec![0x61];
+        bytes.extend([key_hash_byte; 28]);
+        SerializedValue::from(bytes)
+    }
+
+    fn term_cell(target: spectrum_ledger::ChainId) -> TermCell {
+        let mut policy = vec![7; 28];
+        policy.extend([0; 4]);
+        let policy_id = PolicyId::from(Blake2bDigest256::try_from(policy).unwrap());
+        let asset_id = AssetId::from(Blake2bDigest256::random());
+        TermCell {
+            value: SValue {
+                native: NativeCoin::from(2_000_000),
+                assets: HashMap::from([(policy_id, HashMap::from([(asset_id, CustomAsset::from(100))]))]),
+            },
+            tx_id: TxId::from(Blake2bDigest256::random()),
+            index: 0,
+            dst: BoxDestination {
+                target,
+                address: enterprise_address(1),
+                inputs: None,
+                constraints: Some(TermConstraints {
+                    timelock_height: 120_000_000,
+                    refund_owner: enterprise_address(2),
+                }),
+            },
+        }
+    }
+
+    #[test]
+    fn term_cell_survives_conversion() {
+        let cell = term_cell(CARDANO_CHAIN_ID);
+        let cardano_cell = CardanoTermCell::try_from(cell.clone()).unwrap();
+        assert_eq!(cardano_cell.value(), cell.value);
+        assert_eq!(cardano_cell.destination(), cell.dst);
+    }
+
+    #[test]
+    fn wrong_chain_is_rejected() {
+        assert!(matches!(
+            CardanoTermCell::try_from(term_cell(ERGO_CHAIN_ID)),
+            Err(CardanoTermCellError::WrongChainId)
+        ));
+    }
+
+    #[test]
+    fn term_cell_datum_shape() {
+        let cardano_cell = CardanoTermCell::try_from(term_cell(CARDANO_CHAIN_ID)).unwrap();
+        match cardano_cell.to_plutus_data() {
+            PlutusData::Constr(0, fields) => {
+                assert_eq!(fields[0], PlutusData::Int(2_000_000));
+                assert_eq!(fields[1], PlutusData::Bytes(enterprise_address(1).into()));
+                assert!(matches!(&fields[2], PlutusData::Map(assets) if assets.len() == 1));
+                assert!(matches!(&fields[3], PlutusData::Constr(0, timelock) if timelock.len() == 1));
+            }
+            other => panic!("Unexpected datum {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,322 @@
+//! Sequenced stream of TX events between a [`crate::DataBridge`] and its consumer.
+//!
+//! Every event is tagged with a sequence number and the progress point it relates to, so that
+//! the consumer can detect missed events (e.g. after an overflow of the channel) and ask the
+//! bridge to replay the stream from the first missed event instead of silently diverging.
+
+use std::collections::VecDeque;
+
+use spectrum_ledger::interop::Point;
+use tokio::sync::mpsc;
+
+use crate::TxEvent;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SequencedTxEvent<T> {
+    /// Sequence number of the event, incremented by one with every event emitted by the bridge.
+    pub seq: u64,
+    /// Progress point the event relates to.
+    pub point: Point,
+    pub event: TxEvent<T>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum BridgeMsg<T> {
+    Event(SequencedTxEvent<T>),
+    /// Events starting from the given sequence number are about to be replayed.
+    Replay {
+        from_seq: u64,
+    },
+    ResyncUnavailable {
+        requested_seq: u64,
+        oldest_seq: u64,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StreamGap {
+    /// Events with sequence numbers in `[expected_seq, received_seq)` were lost.
+    MissedEvents { expected_seq: u64, received_seq: u64 },
+    /// Progress point of the event contradicts the preceding events, e.g. an applied TX is
+    /// reported at a point below the previously applied one without a rollback in between.
+    PointDiscontinuity {
+        last_point: Point,
+        received_point: Point,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BridgeEvent<T> {
+    Tx {
+        point: Point,
+        event: TxEvent<T>,
+    },
+    /// Continuity of the stream is broken. All subsequent events are dropped until the stream
+    /// is resync'ed, see [`ResyncHandle::resync`].
+    GapDetected {
+        resume_from_seq: u64,
+        gap: StreamGap,
+    },
+    /// The bridge no longer remembers events starting from the requested one.
+    ResyncFailed {
+        requested_seq: u64,
+        oldest_seq: u64,
+    },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Consumer of the data bridge is gone")]
+pub struct BridgeClosed;
+
+/// Create a sequenced channel. `replay_capacity` is the number of the most recent events the
+/// bridge remembers in order to serve resync requests.
+pub fn channel<T: Clone>(buffer: usize, replay_capacity: usize) -> (BridgeSender<T>, BridgeReceiver<T>) {
+    let (tx, rx) = mpsc::channel(buffer);
+    let (resync_tx, resync_rx) = mpsc::channel(1);
+    (
+        BridgeSender {
+            tx,
+            resync_rx,
+            next_seq: 0,
+            replay: VecDeque::with_capacity(replay_capacity),
+            replay_capacity,
+        },
+        BridgeReceiver {
+            rx,
+            resync: ResyncHandle(resync_tx),
+            continuity: StreamContinuity::default(),
+        },
+    )
+}
+
+pub struct BridgeSender<T> {
+    tx: mpsc::Sender<BridgeMsg<T>>,
+    resync_rx: mpsc::Receiver<u64>,
+    next_seq: u64,
+    replay: VecDeque<SequencedTxEvent<T>>,
+    replay_capacity: usize,
+}
+
+impl<T: Clone> BridgeSender<T> {
+    /// Emit the event. The event is dropped if the channel is full, the consumer detects the
+    /// gap on the next delivered event.
+    pub async fn send(&mut self, point: Point, event: TxEvent<T>) -> Result<(), BridgeClosed> {
+        self.serve_resync_requests().await?;
+        let event = SequencedTxEvent {
+            seq: self.next_seq,
+            point,
+            event,
+        };
+        self.next_seq += 1;
+        if self.replay.len() >= self.replay_capacity {
+            self.replay.pop_front();
+        }
+        if self.replay_capacity > 0 {
+            self.replay.push_back(event.clone());
+        }
+        match self.tx.try_send(BridgeMsg::Event(event)) {
+            Ok(()) | Err(mpsc::error::TrySendError::Full(_)) => Ok(()),
+            Err(mpsc::error::TrySendError::Closed(_)) => Err(BridgeClosed),
+        }
+    }
+
+    async fn serve_resync_requests(&mut self) -> Result<(), BridgeClosed> {
+        while let Ok(from_seq) = self.resync_rx.try_recv() {
+            let oldest_seq = self.replay.front().map(|e| e.seq).unwrap_or(self.next_seq);
+            let msg = if from_seq >= oldest_seq && from_seq <= self.next_seq {
+                BridgeMsg::Replay { from_seq }
+            } else {
+                BridgeMsg::ResyncUnavailable {
+                    requested_seq: from_seq,
+                    oldest_seq,
+                }
+            };
+            let replay = matches!(msg, BridgeMsg::Replay { .. });
+            self.tx.send(msg).await.map_err(|_| BridgeClosed)?;
+            if replay {
+                for event in self.replay.iter().filter(|e| e.seq >= from_seq) {
+                    self.tx
+                        .send(BridgeMsg::Event(event.clone()))
+                        .await
+                        .map_err(|_| BridgeClosed)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Asks the bridge to replay the stream.
+#[derive(Clone, Debug)]
+pub struct ResyncHandle(mpsc::Sender<u64>);
+
+impl ResyncHandle {
+    /// Replay events starting from the given sequence number. The request is served once the
+    /// bridge emits its next event.
+    pub async fn resync(&self, from_seq: u64) {
+        let _ = self.0.send(from_seq).await;
+    }
+}
+
+pub struct BridgeReceiver<T> {
+    rx: mpsc::Receiver<BridgeMsg<T>>,
+    resync: ResyncHandle,
+    continuity: StreamContinuity,
+}
+
+impl<T> BridgeReceiver<T> {
+    /// Next event of the stream. `None` if the bridge is gone.
+    pub async fn recv(&mut self) -> Option<BridgeEvent<T>> {
+        loop {
+            let msg = self.rx.recv().await?;
+            if let Some(event) = self.continuity.accept(msg) {
+                return Some(event);
+            }
+        }
+    }
+
+    pub fn resync_handle(&self) -> ResyncHandle {
+        self.resync.clone()
+    }
+}
+
+/// Checks continuity of the sequenced stream.
+#[derive(Debug, Default)]
+struct StreamContinuity {
+    next_seq: u64,
+    last_applied: Option<Point>,
+    /// Whether a rollback happened since the last applied TX.
+    rolled_back: bool,
+    /// Whether continuity is broken and the stream awaits resync.
+    broken: bool,
+}
+
+impl StreamContinuity {
+    fn accept<T>(&mut self, msg: BridgeMsg<T>) -> Option<BridgeEvent<T>> {
+        match msg {
+            BridgeMsg::Replay { from_seq } => {
+                self.next_seq = from_seq;
+                self.last_applied = None;
+                self.rolled_back = false;
+                self.broken = false;
+                None
+            }
+            BridgeMsg::ResyncUnavailable {
+                requested_seq,
+                oldest_seq,
+            } => Some(BridgeEvent::ResyncFailed {
+                requested_seq,
+                oldest_seq,
+            }),
+            BridgeMsg::Event(_) if self.broken => None,
+            // Stale event which was already delivered.
+            BridgeMsg::Event(SequencedTxEvent { seq, .. }) if seq < self.next_seq => None,
+            BridgeMsg::Event(SequencedTxEvent { seq, .. }) if seq > self.next_seq => {
+                self.broken = true;
+                Some(BridgeEvent::GapDetected {
+                    resume_from_seq: self.next_seq,
+                    gap: StreamGap::MissedEvents {
+                        expected_seq: self.next_seq,
+                        received_seq: seq,
+                    },
+                })
+            }
+            BridgeMsg::Event(SequencedTxEvent { point, event, .. }) => {
+                if let Some(last_point) = self.last_applied {
+                    let continuous = match event {
+                        TxEvent::AppliedTx(_) => self.rolled_back || point >= last_point,
+                        TxEvent::UnappliedTx(_) => point <= last_point,
+                    };
+                    if !continuous {
+                        self.broken = true;
+                        return Some(BridgeEvent::GapDetected {
+                            resume_from_seq: self.next_seq,
+                            gap: StreamGap::PointDiscontinuity {
+                                last_point,
+                                received_point: point,
+                            },
+                        });
+                    }
+                }
+                match event {
+                    TxEvent::AppliedTx(_) => {
+                        self.last_applied = Some(point);
+                        self.rolled_back = false;
+                    }
+                    TxEvent::UnappliedTx(_) => self.rolled_back = true,
+                }
+                self.next_seq += 1;
+                Some(BridgeEvent::Tx { point, event })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use spectrum_ledger::interop::Point;
+
+    use crate::bridge::{channel, BridgeEvent, StreamGap};
+    use crate::TxEvent;
+
+    #[tokio::test]
+    async fn lost_events_are_replayed_after_resync() {
+        let (mut sender, mut receiver) = channel::<u32>(2, 16);
+        for i in 0..4 {
+            sender
+                .send(Point::from(i), TxEvent::AppliedTx(i as u32))
+                .await
+                .unwrap();
+        }
+        // Events 2 and 3 didn't fit into the channel.
+        assert!(matches!(receiver.recv().await, Some(BridgeEvent::Tx { .. })));
+        assert!(matches!(receiver.recv().await, Some(BridgeEvent::Tx { .. })));
+        sender.send(Point::from(4), TxEvent::AppliedTx(4)).await.unwrap();
+        assert_eq!(
+            receiver.recv().await,
+            Some(BridgeEvent::GapDetected {
+                resume_from_seq: 2,
+                gap: StreamGap::MissedEvents {
+                    expected_seq: 2,
+                    received_seq: 4
+                },
+            })
+        );
+        receiver.resync_handle().resync(2).await;
+        // Replay doesn't fit into the channel either, so the bridge must run concurrently.
+        tokio::spawn(async move { sender.send(Point::from(5), TxEvent::AppliedTx(5)).await });
+        let mut replayed = vec![];
+        while replayed.len() < 3 {
+            match receiver.recv().await {
+                Some(BridgeEvent::Tx {
+                    event: TxEvent::AppliedTx(i),
+                    ..
+                }) => replayed.push(i),
+                other => panic!("Unexpected event {:?}", other),
+            }
+        }
+        assert_eq!(replayed, vec![2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn applied_tx_below_tip_is_a_discontinuity() {
+        let (mut sender, mut receiver) = channel::<u32>(16, 16);
+        sender.send(Point::from(10), TxEvent::AppliedTx(0)).await.unwrap();
+        sender
+            .send(Point::from(9), TxEvent::UnappliedTx(0))
+            .await
+            .unwrap();
+        sender.send(Point::from(9), TxEvent::AppliedTx(1)).await.unwrap();
+        sender.send(Point::from(5), TxEvent::AppliedTx(2)).await.unwrap();
+        for _ in 0..3 {
+            assert!(matches!(receiver.recv().await, Some(BridgeEvent::Tx { .. })));
+        }
+        assert!(matches!(
+            receiver.recv().await,
+            Some(BridgeEvent::GapDetected {
+                resume_from_seq: 3,
+                gap: StreamGap::PointDiscontinuity { .. },
+            })
+        ));
+    }
+}
@@ -0,0 +1,175 @@
+//! Verification of committee certificates over notarized reports, shared by all connectors.
+
+use serde::{Deserialize, Serialize};
+use spectrum_crypto::digest::Blake2bDigest256;
+use spectrum_crypto::pubkey::PublicKey;
+use spectrum_crypto::VerifiableAgainst;
+use spectrum_handel::Threshold;
+use spectrum_ledger::interop::ReportCertificate;
+use spectrum_sigma::crypto::verify;
+
+use crate::NotarizedReport;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ReportCertificateError {
+    #[error("Certificate isn't issued over the report or the batch it's included into")]
+    DigestMismatch,
+    #[error("Committee is empty")]
+    EmptyCommittee,
+    #[error("Invalid certificate")]
+    InvalidCertificate,
+}
+
+/// Committee a certificate is verified against.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitteeSnapshot {
+    /// Keys of members in the order they were indexed in during aggregation,
+    /// indices of the exclusion set of a certificate refer to it.
+    pub members: Vec<PublicKey>,
+    /// Share of members which must have signed.
+    pub threshold: Threshold,
+}
+
+/// Check that the certificate is issued by the committee over the given digest.
+pub fn verify_certificate(
+    certificate: &ReportCertificate,
+    digest: Blake2bDigest256,
+    committee: &CommitteeSnapshot,
+) -> Result<(), ReportCertificateError> {
+    match certificate {
+        ReportCertificate::SchnorrK256(certificate) => {
+            if certificate.message_digest != digest {
+                return Err(ReportCertificateError::DigestMismatch);
+            }
+            if committee.members.is_empty() {
+                return Err(ReportCertificateError::EmptyCommittee);
+            }
+            if !verify(
+                certificate.aggregate_commitment.clone(),
+                certificate.aggregate_response,
+                certificate.exclusion_set.clone(),
+                committee.members.clone(),
+                certificate.message_digest,
+                committee.threshold,
+            ) {
+                return Err(ReportCertificateError::InvalidCertificate);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check that the report is notarized by the given committee, either alone or as a part of a batch.
+pub fn verify_report_certificate<T>(
+    report: &NotarizedReport<T>,
+    committee: &CommitteeSnapshot,
+) -> Result<(), ReportCertificateError> {
+    verify_certificate(&report.certificate, report.signed_digest(), committee)
+}
+
+impl<T> VerifiableAgainst<CommitteeSnapshot> for NotarizedReport<T> {
+    fn verify(&self, committee: &CommitteeSnapshot) -> bool {
+        verify_report_certificate(self, committee).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::SecretKey;
+    use rand::rngs::OsRng;
+
+    use spectrum_crypto::digest::{blake2b256_hash, Blake2b256, Blake2bDigest256};
+    use spectrum_crypto::pubkey::PublicKey;
+    use spectrum_crypto::VerifiableAgainst;
+    use spectrum_handel::Threshold;
+    use spectrum_ledger::interop::ReportCertificate;
+    use spectrum_sigma::crypto::{
+        aggregate_commitment, aggregate_pk, aggregate_response, challenge, individual_input, response,
+        schnorr_commitment_pair,
+    };
+    use spectrum_sigma::sigma_aggregation::AggregateCertificate;
+
+    use crate::certificate::{verify_report_certificate, CommitteeSnapshot, ReportCertificateError};
+    use crate::NotarizedReport;
+
+    /// Certificate of the committee over `md`, the last member didn't take part in aggregation.
+    fn certificate(sks: &[SecretKey], md: Blake2bDigest256) -> ReportCertificate {
+        let committee = sks
+            .iter()
+            .map(|sk| PublicKey::from(sk.clone()))
+            .collect::<Vec<_>>();
+        let inputs = committee
+            .iter()
+            .map(|pk| individual_input::<Blake2b256>(committee.clone(), *pk))
+            .collect::<Vec<_>>();
+        let signers = sks.len() - 1;
+        let commitments = (0..signers)
+            .map(|_| schnorr_commitment_pair())
+            .collect::<Vec<_>>();
+        let aggr_commitment = aggregate_commitment(commitments.iter().map(|(_, c)| c.clone()).collect());
+        let c = challenge(
+            aggregate_pk(committee.clone(), inputs.clone()),
+            aggr_commitment.clone(),
+            md,
+        );
+        let responses = commitments
+            .into_iter()
+            .enumerate()
+            .map(|(i, (secret, _))| response(secret, sks[i].clone(), c, inputs[i]))
+            .collect();
+        ReportCertificate::SchnorrK256(AggregateCertificate {
+            message_digest: md,
+            aggregate_commitment: aggr_commitment,
+            aggregate_response: aggregate_response(responses),
+            exclusion_set: vec![(signers, None)],
+        })
+    }
+
+    fn report(sks: &[SecretKey]) -> NotarizedReport<()> {
+        let authenticated_digest = vec![1u8; 33];
+        NotarizedReport {
+            certificate: certificate(sks, blake2b256_hash(&authenticated_digest)),
+            value_to_withdraw: vec![],
+            authenticated_digest,
+            additional_chain_data: (),
+            inclusion_proof: None,
+        }
+    }
+
+    fn snapshot(sks: &[SecretKey], threshold: Threshold) -> CommitteeSnapshot {
+        CommitteeSnapshot {
+            members: sks.iter().map(|sk| PublicKey::from(sk.clone())).collect(),
+            threshold,
+        }
+    }
+
+    #[test]
+    fn certificate_of_committee_accepted() {
+        let sks = (0..4).map(|_| SecretKey::random(&mut OsRng)).collect::<Vec<_>>();
+        let report = report(&sks);
+        assert!(report.verify(&snapshot(&sks, Threshold { num: 3, denom: 4 })));
+        // Not enough members signed.
+        assert_eq!(
+            verify_report_certificate(&report, &snapshot(&sks, Threshold { num: 1, denom: 1 })),
+            Err(ReportCertificateError::InvalidCertificate)
+        );
+        // Another committee.
+        let others = (0..4).map(|_| SecretKey::random(&mut OsRng)).collect::<Vec<_>>();
+        assert!(!report.verify(&snapshot(&others, Threshold { num: 3, denom: 4 })));
+        assert_eq!(
+            verify_report_certificate(&report, &snapshot(&[], Threshold { num: 3, denom: 4 })),
+            Err(ReportCertificateError::EmptyCommittee)
+        );
+    }
+
+    #[test]
+    fn certificate_over_another_digest_rejected() {
+        let sks = (0..4).map(|_| SecretKey::random(&mut OsRng)).collect::<Vec<_>>();
+        let mut report = report(&sks);
+        report.authenticated_digest = vec![2u8; 33];
+        assert_eq!(
+            verify_report_certificate(&report, &snapshot(&sks, Threshold { num: 3, denom: 4 })),
+            Err(ReportCertificateError::DigestMismatch)
+        );
+    }
+}
@@ -0,0 +1,61 @@
+//! Fees charged on exports of value. Fees are deducted from the native coin of exported cells
+//! and paid out to a collector in a dedicated cell, which is notarized along with the rest of
+//! the report.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use spectrum_ledger::cell::{BoxDestination, NativeCoin, SValue};
+
+use crate::ProtoTermCell;
+
+/// Fees of exports to a particular chain.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
+pub struct FeePolicy {
+    /// Charged for every exported cell.
+    pub flat: NativeCoin,
+    /// Charged for every byte of an exported cell.
+    pub per_byte: NativeCoin,
+    /// Where collected fees are paid out to.
+    pub collector: BoxDestination,
+}
+
+impl FeePolicy {
+    /// Fee for exporting the given cell. The cell is measured in its canonical encoding, so that
+    /// all members of the committee arrive at the same fee.
+    pub fn fee_of(&self, cell: &ProtoTermCell) -> u64 {
+        let size = bincode::serialized_size(cell).unwrap();
+        u64::from(self.flat).saturating_add(u64::from(self.per_byte).saturating_mul(size))
+    }
+
+    /// Whether the cell holds enough native coin to pay for its export.
+    pub fn can_pay(&self, cell: &ProtoTermCell) -> bool {
+        self.fee_of(cell) <= u64::from(cell.value.native)
+    }
+
+    /// Deduct fees from `cells` and append the cell collecting them. Cells which can't pay for
+    /// their export are expected to be filtered out beforehand, see [`FeePolicy::can_pay`].
+    pub fn charge(&self, cells: &[ProtoTermCell]) -> Vec<ProtoTermCell> {
+        let mut total_fee = 0_u64;
+        let mut charged: Vec<_> = cells
+            .iter()
+            .map(|cell| {
+                let fee = self.fee_of(cell).min(u64::from(cell.value.native));
+                total_fee = total_fee.saturating_add(fee);
+                let mut cell = cell.clone();
+                cell.value.native = NativeCoin::from(u64::from(cell.value.native) - fee);
+                cell
+            })
+            .collect();
+        if total_fee > 0 {
+            charged.push(ProtoTermCell {
+                value: SValue {
+                    native: NativeCoin::from(total_fee),
+                    assets: HashMap::new(),
+                },
+                dst: self.collector.clone(),
+            });
+        }
+        charged
+    }
+}
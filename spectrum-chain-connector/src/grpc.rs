@@ -0,0 +1,333 @@
+//! gRPC control API of the vault manager, letting operators inspect the status of the Connector and
+//! drive it out-of-band, alongside the consensus-driver.
+//!
+//! Chain-specific and composite payloads are carried as bincode-encoded values of the types of this
+//! crate, see `proto/vault_manager.proto`.
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+
+use bincode::Options;
+use futures::{Stream, StreamExt};
+use log::warn;
+use serde::{de::DeserializeOwned, Serialize};
+use spectrum_ledger::cell::ProgressPoint;
+use spectrum_ledger::interop::Point;
+use spectrum_ledger::ChainId;
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::BroadcastStream;
+use tonic::{Request, Response, Status};
+
+use crate::ipc::{codec, validate_request};
+use crate::{ConnectorRequest, ConnectorResponse, ConnectorStatus, Kilobytes, NotarizedReportConstraints};
+
+pub mod proto {
+    tonic::include_proto!("spectrum.vault_manager");
+}
+
+use proto::vault_control_server::{VaultControl, VaultControlServer};
+
+/// gRPC service forwarding requests of operators to the Connector and streaming its responses back.
+pub struct VaultControlService<S, T, U, V> {
+    request_to_connector_tx: mpsc::Sender<ConnectorRequest<S, U>>,
+    connector_responses: broadcast::Sender<ConnectorResponse<S, T, U, V>>,
+}
+
+impl<S, T, U, V> VaultControlService<S, T, U, V>
+where
+    S: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+    T: Serialize + Clone + Send + Sync + 'static,
+    U: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+    V: Serialize + Clone + Send + Sync + 'static,
+{
+    /// Responses of the Connector are expected to be published to `connector_responses`,
+    /// see [`fan_out_responses`].
+    pub fn new(
+        request_to_connector_tx: mpsc::Sender<ConnectorRequest<S, U>>,
+        connector_responses: broadcast::Sender<ConnectorResponse<S, T, U, V>>,
+    ) -> Self {
+        Self {
+            request_to_connector_tx,
+            connector_responses,
+        }
+    }
+
+    /// Serve the API at the given address until the server fails.
+    pub async fn serve(self, addr: SocketAddr) -> Result<(), tonic::transport::Error> {
+        tonic::transport::Server::builder()
+            .add_service(VaultControlServer::new(self))
+            .serve(addr)
+            .await
+    }
+
+    async fn forward(&self, req: ConnectorRequest<S, U>) -> Result<Response<proto::Ack>, Status> {
+        validate_request(&req).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        self.request_to_connector_tx
+            .send(req)
+            .await
+            .map_err(|_| Status::unavailable("Connector is shut down"))?;
+        Ok(Response::new(proto::Ack {}))
+    }
+}
+
+/// Forward responses of the Connector to the consensus-driver and to subscribers of the gRPC API.
+/// Subscribers lagging behind miss responses, the driver never does.
+pub async fn fan_out_responses<S, T, U, V>(
+    mut connector_response_rx: mpsc::Receiver<ConnectorResponse<S, T, U, V>>,
+    driver_tx: mpsc::Sender<ConnectorResponse<S, T, U, V>>,
+    subscribers: broadcast::Sender<ConnectorResponse<S, T, U, V>>,
+) where
+    ConnectorResponse<S, T, U, V>: Clone,
+{
+    while let Some(resp) = connector_response_rx.recv().await {
+        // No subscribers is fine.
+        let _ = subscribers.send(resp.clone());
+        if driver_tx.send(resp).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl<S, T, U, V> VaultControl for VaultControlService<S, T, U, V>
+where
+    S: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+    T: Serialize + Clone + Send + Sync + 'static,
+    U: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+    V: Serialize + Clone + Send + Sync + 'static,
+{
+    async fn sync_from(
+        &self,
+        request: Request<proto::SyncFromRequest>,
+    ) -> Result<Response<proto::Ack>, Status> {
+        let progress_point = request
+            .into_inner()
+            .progress_point
+            .map(ProgressPoint::try_from)
+            .transpose()?;
+        self.forward(ConnectorRequest::SyncFrom(progress_point)).await
+    }
+
+    async fn request_txs_to_notarize(
+        &self,
+        request: Request<proto::RequestTxsToNotarizeRequest>,
+    ) -> Result<Response<proto::Ack>, Status> {
+        let req = request.into_inner();
+        let constraints = NotarizedReportConstraints {
+            term_cells: req
+                .term_cells
+                .iter()
+                .map(|cell| decode(cell))
+                .collect::<Result<_, _>>()
+                .map_err(malformed)?,
+            last_progress_point: req
+                .last_progress_point
+                .ok_or_else(|| Status::invalid_argument("Missing last progress point"))?
+                .try_into()?,
+            max_tx_size: Kilobytes(req.max_tx_size_kb),
+            estimated_number_of_byzantine_nodes: req.estimated_number_of_byzantine_nodes,
+            fee_policy: if req.fee_policy.is_empty() {
+                None
+            } else {
+                Some(decode(&req.fee_policy).map_err(malformed)?)
+            },
+        };
+        self.forward(ConnectorRequest::RequestTxsToNotarize(constraints))
+            .await
+    }
+
+    async fn export_value(
+        &self,
+        request: Request<proto::ExportValueRequest>,
+    ) -> Result<Response<proto::Ack>, Status> {
+        let report = decode(&request.into_inner().notarized_report).map_err(malformed)?;
+        self.forward(ConnectorRequest::ValidateAndProcessWithdrawals(Box::new(report)))
+            .await
+    }
+
+    async fn process_deposits(
+        &self,
+        _: Request<proto::ProcessDepositsRequest>,
+    ) -> Result<Response<proto::Ack>, Status> {
+        self.forward(ConnectorRequest::ProcessDeposits).await
+    }
+
+    type StreamStatusStream = Pin<Box<dyn Stream<Item = Result<proto::VaultStatus, Status>> + Send>>;
+
+    async fn stream_status(
+        &self,
+        _: Request<proto::StreamStatusRequest>,
+    ) -> Result<Response<Self::StreamStatusStream>, Status> {
+        let statuses = BroadcastStream::new(self.connector_responses.subscribe()).filter_map(|resp| async {
+            match resp {
+                Ok(resp) => Some(proto::VaultStatus::try_from(&resp)),
+                Err(err) => {
+                    warn!("Status subscriber lags behind: {}", err);
+                    None
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(statuses)))
+    }
+}
+
+impl TryFrom<proto::ProgressPoint> for ProgressPoint {
+    type Error = Status;
+
+    fn try_from(pp: proto::ProgressPoint) -> Result<Self, Self::Error> {
+        let chain_id =
+            u16::try_from(pp.chain_id).map_err(|_| Status::invalid_argument("Chain ID out of range"))?;
+        Ok(ProgressPoint {
+            chain_id: ChainId::from(chain_id),
+            point: Point::from(pp.point),
+        })
+    }
+}
+
+impl From<ProgressPoint> for proto::ProgressPoint {
+    fn from(pp: ProgressPoint) -> Self {
+        proto::ProgressPoint {
+            chain_id: u16::from(pp.chain_id) as u32,
+            point: u64::from(pp.point),
+        }
+    }
+}
+
+impl<S, T, U, V> TryFrom<&ConnectorResponse<S, T, U, V>> for proto::VaultStatus
+where
+    S: Serialize + Clone,
+    T: Serialize,
+    U: Serialize + Clone,
+    V: Serialize,
+{
+    type Error = Status;
+
+    fn try_from(resp: &ConnectorResponse<S, T, U, V>) -> Result<Self, Self::Error> {
+        let num_points_remaining = match &resp.status {
+            ConnectorStatus::Synced { .. } => 0,
+            ConnectorStatus::Syncing {
+                num_points_remaining, ..
+            } => *num_points_remaining,
+        };
+        Ok(proto::VaultStatus {
+            synced: matches!(resp.status, ConnectorStatus::Synced { .. }),
+            current_progress_point: Some(resp.status.get_current_progress_point().into()),
+            num_points_remaining,
+            pending_txs: resp
+                .status
+                .get_pending_txs()
+                .iter()
+                .map(encode)
+                .collect::<Result<_, _>>()
+                .map_err(unencodable)?,
+            node_degraded: resp.status.get_node_health().degraded,
+            messages: resp
+                .messages
+                .iter()
+                .map(encode)
+                .collect::<Result<_, _>>()
+                .map_err(unencodable)?,
+        })
+    }
+}
+
+fn decode<A: DeserializeOwned>(bytes: &[u8]) -> Result<A, bincode::Error> {
+    codec().deserialize(bytes)
+}
+
+fn encode<A: Serialize>(value: &A) -> Result<Vec<u8>, bincode::Error> {
+    codec().serialize(value)
+}
+
+fn malformed(err: bincode::Error) -> Status {
+    Status::invalid_argument(format!("Malformed payload: {}", err))
+}
+
+fn unencodable(err: bincode::Error) -> Status {
+    Status::internal(format!("Failed to encode payload: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use spectrum_ledger::cell::ProgressPoint;
+    use spectrum_ledger::interop::Point;
+    use spectrum_ledger::ChainId;
+    use tokio::sync::{broadcast, mpsc};
+    use tonic::{Code, Request};
+
+    use crate::grpc::proto::vault_control_server::VaultControl;
+    use crate::grpc::{proto, VaultControlService};
+    use crate::health::NodeHealth;
+    use crate::{ConnectorMsgOut, ConnectorRequest, ConnectorResponse, ConnectorStatus};
+
+    type Service = VaultControlService<Vec<u8>, u64, u64, u64>;
+
+    fn progress_point() -> ProgressPoint {
+        ProgressPoint {
+            chain_id: ChainId::from(0),
+            point: Point::from(100),
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_forwarded_to_connector() {
+        let (req_tx, mut req_rx) = mpsc::channel(10);
+        let (resp_tx, _) = broadcast::channel(10);
+        let service: Service = VaultControlService::new(req_tx, resp_tx);
+        service
+            .sync_from(Request::new(proto::SyncFromRequest {
+                progress_point: Some(progress_point().into()),
+            }))
+            .await
+            .unwrap();
+        assert!(matches!(
+            req_rx.recv().await,
+            Some(ConnectorRequest::SyncFrom(Some(pp))) if pp == progress_point()
+        ));
+        let malformed = service
+            .export_value(Request::new(proto::ExportValueRequest {
+                notarized_report: vec![1, 2, 3],
+            }))
+            .await;
+        assert_eq!(malformed.unwrap_err().code(), Code::InvalidArgument);
+        let out_of_range = service
+            .sync_from(Request::new(proto::SyncFromRequest {
+                progress_point: Some(proto::ProgressPoint {
+                    chain_id: u32::MAX,
+                    point: 0,
+                }),
+            }))
+            .await;
+        assert_eq!(out_of_range.unwrap_err().code(), Code::InvalidArgument);
+        assert!(req_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn statuses_streamed_to_subscribers() {
+        let (req_tx, _req_rx) = mpsc::channel(10);
+        let (resp_tx, _) = broadcast::channel(10);
+        let service: Service = VaultControlService::new(req_tx, resp_tx.clone());
+        let mut statuses = service
+            .stream_status(Request::new(proto::StreamStatusRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+        resp_tx
+            .send(ConnectorResponse {
+                status: ConnectorStatus::Syncing {
+                    current_progress_point: progress_point(),
+                    num_points_remaining: 5,
+                    pending_txs: vec![],
+                    node_health: NodeHealth::default(),
+                },
+                messages: vec![ConnectorMsgOut::ProposedTxsToNotarize(7)],
+            })
+            .unwrap();
+        let status = statuses.next().await.unwrap().unwrap();
+        assert!(!status.synced);
+        assert_eq!(status.current_progress_point, Some(progress_point().into()));
+        assert_eq!(status.num_points_remaining, 5);
+        assert_eq!(status.messages.len(), 1);
+    }
+}
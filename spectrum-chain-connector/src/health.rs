@@ -0,0 +1,289 @@
+//! Health of the chain node backing a Connector.
+//!
+//! Connectors observe every call to the node API along with the timestamp of the chain tip the
+//! node reports. The node is considered degraded once calls get slow or fail too often, or once
+//! its tip falls behind the wall clock, in which case the consensus-driver is alerted.
+
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct NodeHealthConfig {
+    /// How often the node is probed.
+    pub probe_interval_secs: u64,
+    /// Number of the most recent calls error rate and latency are measured over.
+    pub window: usize,
+    /// Max number of failed calls within the window.
+    pub max_errors: usize,
+    /// Max average latency of calls within the window.
+    pub max_avg_latency_millis: u64,
+    /// Max age of the chain tip, should be a generous multiple of the block interval.
+    pub max_tip_lag_secs: u64,
+}
+
+impl Default for NodeHealthConfig {
+    fn default() -> Self {
+        Self {
+            probe_interval_secs: 30,
+            window: 20,
+            max_errors: 5,
+            max_avg_latency_millis: 5_000,
+            max_tip_lag_secs: 30 * 60,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Degradation {
+    /// Too many calls within the window failed.
+    ErrorRate { errors: u32, calls: u32 },
+    /// Calls within the window are too slow on average.
+    Latency { avg_latency_millis: u64 },
+    /// Chain tip of the node is too old.
+    TipLag { lag_secs: u64 },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default)]
+/// Health of the node as reported in [`crate::ConnectorStatus`].
+pub struct NodeHealth {
+    /// Set if the node can't be relied upon, e.g. statuses of TXs may be stale.
+    pub degraded: bool,
+    pub reasons: Vec<Degradation>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+/// Change of health of the node, see [`crate::ConnectorMsgOut::NodeHealthAlert`].
+pub enum HealthAlert {
+    Degraded(Vec<Degradation>),
+    Recovered,
+}
+
+/// Labels of a particular time series of a metric.
+pub type Labels = Vec<(&'static str, String)>;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HealthMetric {
+    NodeCalls,
+    NodeCallErrors,
+    NodeCallLatency,
+    ChainTipLag,
+    NodeDegraded,
+}
+
+impl HealthMetric {
+    /// Name of the metric following Prometheus conventions.
+    pub fn name(&self) -> &'static str {
+        match self {
+            HealthMetric::NodeCalls => "spectrum_connector_node_calls_total",
+            HealthMetric::NodeCallErrors => "spectrum_connector_node_call_errors_total",
+            HealthMetric::NodeCallLatency => "spectrum_connector_node_call_latency_millis",
+            HealthMetric::ChainTipLag => "spectrum_connector_chain_tip_lag_seconds",
+            HealthMetric::NodeDegraded => "spectrum_connector_node_degraded",
+        }
+    }
+
+    pub fn help(&self) -> &'static str {
+        match self {
+            HealthMetric::NodeCalls => "Calls to the node API",
+            HealthMetric::NodeCallErrors => "Failed calls to the node API",
+            HealthMetric::NodeCallLatency => "Latency of the most recent call to the node API",
+            HealthMetric::ChainTipLag => "Age of the chain tip reported by the node",
+            HealthMetric::NodeDegraded => "Whether the node is considered degraded",
+        }
+    }
+
+    pub fn kind(&self) -> MetricKind {
+        match self {
+            HealthMetric::NodeCalls | HealthMetric::NodeCallErrors => MetricKind::Counter,
+            _ => MetricKind::Gauge,
+        }
+    }
+}
+
+/// Destination of metrics. Implement to wire metrics into a registry of choice.
+pub trait MetricsSink: Send + Sync {
+    fn inc_counter(&self, metric: HealthMetric, labels: Labels, value: u64);
+    fn set_gauge(&self, metric: HealthMetric, labels: Labels, value: i64);
+}
+
+struct Call {
+    latency: Duration,
+    ok: bool,
+}
+
+pub struct NodeHealthMonitor {
+    conf: NodeHealthConfig,
+    calls: VecDeque<Call>,
+    /// Timestamp of the most recent chain tip reported by the node.
+    tip_time: Option<SystemTime>,
+    /// Whether the node was degraded as of the last check.
+    degraded: bool,
+    metrics: Option<Box<dyn MetricsSink>>,
+}
+
+impl NodeHealthMonitor {
+    pub fn new(conf: NodeHealthConfig) -> Self {
+        Self {
+            conf,
+            calls: VecDeque::with_capacity(conf.window),
+            tip_time: None,
+            degraded: false,
+            metrics: None,
+        }
+    }
+
+    pub fn with_metrics(mut self, metrics: Box<dyn MetricsSink>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn probe_interval(&self) -> Duration {
+        Duration::from_secs(self.conf.probe_interval_secs)
+    }
+
+    /// Record a call of the node API `endpoint`.
+    pub fn record_call(&mut self, endpoint: &'static str, latency: Duration, ok: bool) {
+        if self.calls.len() == self.conf.window {
+            self.calls.pop_front();
+        }
+        self.calls.push_back(Call { latency, ok });
+        if let Some(metrics) = &self.metrics {
+            let labels = vec![("endpoint", endpoint.to_string())];
+            metrics.inc_counter(HealthMetric::NodeCalls, labels.clone(), 1);
+            if !ok {
+                metrics.inc_counter(HealthMetric::NodeCallErrors, labels.clone(), 1);
+            }
+            metrics.set_gauge(HealthMetric::NodeCallLatency, labels, latency.as_millis() as i64);
+        }
+    }
+
+    /// Record the timestamp of the chain tip reported by the node.
+    pub fn record_tip(&mut self, tip_time: SystemTime) {
+        self.tip_time = Some(tip_time);
+    }
+
+    pub fn health(&self, now: SystemTime) -> NodeHealth {
+        let mut reasons = vec![];
+        let errors = self.calls.iter().filter(|call| !call.ok).count();
+        if errors > self.conf.max_errors {
+            reasons.push(Degradation::ErrorRate {
+                errors: errors as u32,
+                calls: self.calls.len() as u32,
+            });
+        }
+        if !self.calls.is_empty() {
+            let total_latency = self.calls.iter().map(|call| call.latency).sum::<Duration>();
+            let avg_latency_millis = (total_latency / self.calls.len() as u32).as_millis() as u64;
+            if avg_latency_millis > self.conf.max_avg_latency_millis {
+                reasons.push(Degradation::Latency { avg_latency_millis });
+            }
+        }
+        if let Some(lag_secs) = self.tip_lag(now) {
+            if lag_secs > self.conf.max_tip_lag_secs {
+                reasons.push(Degradation::TipLag { lag_secs });
+            }
+        }
+        NodeHealth {
+            degraded: !reasons.is_empty(),
+            reasons,
+        }
+    }
+
+    /// Evaluate health of the node. Returns an alert if the node got degraded or recovered since
+    /// the previous check.
+    pub fn check(&mut self, now: SystemTime) -> Option<HealthAlert> {
+        let health = self.health(now);
+        if let Some(metrics) = &self.metrics {
+            metrics.set_gauge(HealthMetric::NodeDegraded, vec![], health.degraded as i64);
+            if let Some(lag_secs) = self.tip_lag(now) {
+                metrics.set_gauge(HealthMetric::ChainTipLag, vec![], lag_secs as i64);
+            }
+        }
+        let alert = match (self.degraded, health.degraded) {
+            (false, true) => {
+                warn!(target: "health", "Node is degraded: {:?}", health.reasons);
+                Some(HealthAlert::Degraded(health.reasons))
+            }
+            (true, false) => {
+                info!(target: "health", "Node recovered");
+                Some(HealthAlert::Recovered)
+            }
+            _ => None,
+        };
+        self.degraded = health.degraded;
+        alert
+    }
+
+    fn tip_lag(&self, now: SystemTime) -> Option<u64> {
+        self.tip_time
+            .map(|tip_time| now.duration_since(tip_time).unwrap_or(Duration::ZERO).as_secs())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use crate::health::{Degradation, HealthAlert, NodeHealthConfig, NodeHealthMonitor};
+
+    fn monitor() -> NodeHealthMonitor {
+        NodeHealthMonitor::new(NodeHealthConfig {
+            probe_interval_secs: 1,
+            window: 4,
+            max_errors: 1,
+            max_avg_latency_millis: 100,
+            max_tip_lag_secs: 60,
+        })
+    }
+
+    #[test]
+    fn alert_on_transitions_only() {
+        let mut monitor = monitor();
+        let now = SystemTime::now();
+        monitor.record_tip(now);
+        for _ in 0..2 {
+            monitor.record_call("height", Duration::from_millis(10), false);
+        }
+        assert_eq!(
+            monitor.check(now),
+            Some(HealthAlert::Degraded(vec![Degradation::ErrorRate {
+                errors: 2,
+                calls: 2
+            }]))
+        );
+        assert_eq!(monitor.check(now), None);
+        // Failed calls drop out of the window.
+        for _ in 0..3 {
+            monitor.record_call("height", Duration::from_millis(10), true);
+        }
+        assert_eq!(monitor.check(now), Some(HealthAlert::Recovered));
+    }
+
+    #[test]
+    fn stale_tip_degrades_node() {
+        let mut monitor = monitor();
+        let now = SystemTime::now();
+        monitor.record_tip(now - Duration::from_secs(120));
+        monitor.record_call("height", Duration::from_millis(500), true);
+        let health = monitor.health(now);
+        assert!(health.degraded);
+        assert_eq!(
+            health.reasons,
+            vec![
+                Degradation::Latency {
+                    avg_latency_millis: 500
+                },
+                Degradation::TipLag { lag_secs: 120 }
+            ]
+        );
+    }
+}
@@ -0,0 +1,387 @@
+//! Wire format of the IPC channel between consensus-driver and a Connector.
+//!
+//! Requests travel as opaque bytes so that the Connector can reject malformed or version-skewed
+//! payloads explicitly instead of dropping the connection (or panicking) on a decoding failure.
+
+use bincode::Options;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    AccountingQuery, AccountingQueryKind, ConnectorRequest, ConnectorResponse, NotarizedReportConstraints,
+    PendingTxIdentifier,
+};
+
+/// Version of the IPC protocol spoken by this build.
+pub const IPC_PROTOCOL_VERSION: u16 = 6;
+/// Oldest version of the IPC protocol this build can still talk to.
+pub const MIN_COMPATIBLE_IPC_PROTOCOL_VERSION: u16 = 4;
+/// First version of the IPC protocol in which responses are sequenced and can be replayed,
+/// see [`IpcRequest::Resume`].
+pub const SEQUENCED_RESPONSES_IPC_PROTOCOL_VERSION: u16 = 6;
+/// Upper bound on the size of a single encoded request.
+pub const MAX_REQUEST_SIZE: u64 = 4 * 1024 * 1024;
+
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, Eq)]
+/// Announcement of a supported range of IPC protocol versions.
+pub struct IpcHandshake {
+    pub version: u16,
+    pub min_compatible_version: u16,
+}
+
+impl IpcHandshake {
+    pub fn local() -> Self {
+        Self {
+            version: IPC_PROTOCOL_VERSION,
+            min_compatible_version: MIN_COMPATIBLE_IPC_PROTOCOL_VERSION,
+        }
+    }
+
+    /// Pick the version both sides understand, if any.
+    pub fn negotiate(&self, remote: &IpcHandshake) -> Result<u16, RequestError> {
+        let version = self.version.min(remote.version);
+        if version < self.min_compatible_version || version < remote.min_compatible_version {
+            Err(RequestError::UnsupportedVersion {
+                remote: remote.version,
+                min_supported: self.min_compatible_version,
+                max_supported: self.version,
+            })
+        } else {
+            Ok(version)
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+/// Inbound message to Connector over IPC.
+pub enum IpcRequest {
+    /// Must be the first message of every session.
+    Hello(IpcHandshake),
+    /// Encoded `ConnectorRequest`, see [`encode_request`].
+    Request(Vec<u8>),
+    /// Replay responses following the last one acknowledged by the driver, all responses kept
+    /// by the Connector are replayed if `None`. Sent right after the handshake on reconnect.
+    Resume { last_acked_seq: Option<u64> },
+    /// Responses with sequence numbers up to and including the given one are processed by the
+    /// driver, so the Connector no longer needs to keep them.
+    Ack(u64),
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+/// Outbound message from Connector over IPC.
+pub enum IpcResponse<S, T, U, V> {
+    /// Reply to `IpcRequest::Hello` carrying the negotiated version.
+    Welcome(u16),
+    Response(ConnectorResponse<S, T, U, V>),
+    /// The request wasn't processed.
+    Rejected(RequestError),
+    /// Response tagged with its sequence number. Sent instead of `Response` once
+    /// [`SEQUENCED_RESPONSES_IPC_PROTOCOL_VERSION`] is negotiated.
+    Sequenced {
+        seq: u64,
+        response: ConnectorResponse<S, T, U, V>,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum RequestError {
+    #[error(
+        "Unsupported IPC protocol version {remote}, supported range is [{min_supported}, {max_supported}]"
+    )]
+    UnsupportedVersion {
+        remote: u16,
+        min_supported: u16,
+        max_supported: u16,
+    },
+    #[error("Handshake is required before any request")]
+    HandshakeRequired,
+    #[error("Malformed request: {0}")]
+    Malformed(String),
+    #[error("Invalid request: {0}")]
+    Invalid(String),
+    /// Responses following the requested one were dropped due to retention limits,
+    /// the driver has to `SyncFrom` its last known progress point instead.
+    #[error("Cannot replay responses after #{requested_seq}, the oldest retained one is #{oldest_seq}")]
+    ReplayUnavailable { requested_seq: u64, oldest_seq: u64 },
+}
+
+pub(crate) fn codec() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_limit(MAX_REQUEST_SIZE)
+        .reject_trailing_bytes()
+}
+
+pub fn encode_request<T: Serialize, U: Serialize>(
+    req: &ConnectorRequest<T, U>,
+) -> Result<Vec<u8>, RequestError> {
+    codec()
+        .serialize(req)
+        .map_err(|e| RequestError::Malformed(e.to_string()))
+}
+
+/// Decode and validate a request. Never panics on arbitrary input.
+pub fn decode_request<T: DeserializeOwned, U: DeserializeOwned>(
+    bytes: &[u8],
+) -> Result<ConnectorRequest<T, U>, RequestError> {
+    let req = codec()
+        .deserialize(bytes)
+        .map_err(|e| RequestError::Malformed(e.to_string()))?;
+    validate_request(&req)?;
+    Ok(req)
+}
+
+/// Semantic checks which can't be expressed by the schema itself.
+pub fn validate_request<T, U>(req: &ConnectorRequest<T, U>) -> Result<(), RequestError> {
+    match req {
+        ConnectorRequest::RequestTxsToNotarize(NotarizedReportConstraints { max_tx_size, .. }) => {
+            if !max_tx_size.0.is_finite() || max_tx_size.0 <= 0.0 {
+                return Err(RequestError::Invalid(format!(
+                    "max_tx_size must be positive, got {}",
+                    max_tx_size.0
+                )));
+            }
+        }
+        ConnectorRequest::ValidateAndProcessWithdrawals(report) => {
+            if report.value_to_withdraw.is_empty() {
+                return Err(RequestError::Invalid("Nothing to withdraw".into()));
+            }
+        }
+        ConnectorRequest::AcknowledgeConfirmedTx(PendingTxIdentifier::Deposit(deposits), _)
+        | ConnectorRequest::AcknowledgeAbortedTx(PendingTxIdentifier::Deposit(deposits), _) => {
+            if deposits.is_empty() {
+                return Err(RequestError::Invalid(
+                    "Empty set of deposits to acknowledge".into(),
+                ));
+            }
+        }
+        ConnectorRequest::ProposeVaultMigration(migration) => {
+            if migration.new_contract.is_empty() {
+                return Err(RequestError::Invalid("Empty vault contract".into()));
+            }
+        }
+        ConnectorRequest::ApproveVaultMigration(approval) => {
+            if approval.signature.is_empty() {
+                return Err(RequestError::Invalid("Empty operator signature".into()));
+            }
+        }
+        ConnectorRequest::RotateCommittee(handover) => {
+            if !handover.aggregate_key_matches() {
+                return Err(RequestError::Invalid(
+                    "Aggregate key doesn't match the incoming committee".into(),
+                ));
+            }
+            if !handover.reshared_key_matches() {
+                return Err(RequestError::Invalid(
+                    "Reshared key doesn't match the incoming committee".into(),
+                ));
+            }
+        }
+        ConnectorRequest::QueryAccounting(AccountingQuery { kind, .. }) => match kind {
+            AccountingQueryKind::BalanceHistory { from, to }
+            | AccountingQueryKind::FlowsPerEpoch { from, to, .. }
+                if from > to =>
+            {
+                return Err(RequestError::Invalid(format!(
+                    "Empty range of points [{:?}, {:?}]",
+                    from, to
+                )));
+            }
+            AccountingQueryKind::FlowsPerEpoch { epoch_length: 0, .. } => {
+                return Err(RequestError::Invalid("Epoch length must be positive".into()));
+            }
+            AccountingQueryKind::LargestPendingWithdrawals { limit: 0 } => {
+                return Err(RequestError::Invalid("Limit must be positive".into()));
+            }
+            _ => {}
+        },
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::elliptic_curve::rand_core::OsRng;
+    use k256::{ProjectivePoint, Scalar, SecretKey};
+    use rand::{Rng, RngCore};
+    use spectrum_crypto::digest::Blake2bDigest256;
+    use spectrum_crypto::pubkey::PublicKey;
+    use spectrum_ledger::cell::ProgressPoint;
+    use spectrum_ledger::interop::{Point, ReportCertificate};
+    use spectrum_ledger::{ChainId, EpochNo};
+    use spectrum_sigma::sigma_aggregation::AggregateCertificate;
+
+    use crate::ipc::{decode_request, encode_request, IpcHandshake, RequestError};
+    use crate::{
+        AccountingQuery, AccountingQueryKind, CommitteeHandover, ConnectorRequest, ExportFormat, Kilobytes,
+        NotarizedReportConstraints, OperatorApproval,
+    };
+
+    type Req = ConnectorRequest<Vec<u8>, u64>;
+
+    fn progress_point() -> ProgressPoint {
+        ProgressPoint {
+            chain_id: ChainId::from(0),
+            point: Point::from(100),
+        }
+    }
+
+    fn constraints(max_tx_size: f32) -> Req {
+        ConnectorRequest::RequestTxsToNotarize(NotarizedReportConstraints {
+            term_cells: vec![],
+            last_progress_point: progress_point(),
+            max_tx_size: Kilobytes(max_tx_size),
+            estimated_number_of_byzantine_nodes: 0,
+            fee_policy: None,
+        })
+    }
+
+    #[test]
+    fn roundtrip() {
+        let bytes = encode_request(&Req::SyncFrom(Some(progress_point()))).unwrap();
+        assert!(matches!(
+            decode_request::<Vec<u8>, u64>(&bytes),
+            Ok(ConnectorRequest::SyncFrom(Some(_)))
+        ));
+    }
+
+    #[test]
+    fn reject_trailing_bytes() {
+        let mut bytes = encode_request(&Req::ProcessDeposits).unwrap();
+        bytes.push(0);
+        assert!(matches!(
+            decode_request::<Vec<u8>, u64>(&bytes),
+            Err(RequestError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn reject_bad_constraints() {
+        for size in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            let bytes = encode_request(&constraints(size)).unwrap();
+            assert!(matches!(
+                decode_request::<Vec<u8>, u64>(&bytes),
+                Err(RequestError::Invalid(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn reject_unsigned_approval() {
+        let bytes = encode_request(&Req::ApproveVaultMigration(OperatorApproval {
+            migration_digest: vec![0; 32],
+            operator_ix: 0,
+            signature: vec![],
+        }))
+        .unwrap();
+        assert!(matches!(
+            decode_request::<Vec<u8>, u64>(&bytes),
+            Err(RequestError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn reject_handover_with_foreign_aggregate_key() {
+        let new_committee: Vec<_> = (0..3)
+            .map(|_| PublicKey::from(SecretKey::random(&mut OsRng)))
+            .collect();
+        let bytes = encode_request(&Req::RotateCommittee(Box::new(CommitteeHandover {
+            new_aggregate_key: PublicKey::from(SecretKey::random(&mut OsRng)),
+            new_committee,
+            epoch: EpochNo::from(1),
+            reshared_key: None,
+            certificate: ReportCertificate::SchnorrK256(AggregateCertificate {
+                message_digest: Blake2bDigest256::random(),
+                aggregate_commitment: ProjectivePoint::GENERATOR.into(),
+                aggregate_response: Scalar::ONE,
+                exclusion_set: vec![],
+            }),
+        })))
+        .unwrap();
+        assert!(matches!(
+            decode_request::<Vec<u8>, u64>(&bytes),
+            Err(RequestError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn reject_degenerate_accounting_queries() {
+        let kinds = [
+            AccountingQueryKind::BalanceHistory {
+                from: Point::from(10),
+                to: Point::from(9),
+            },
+            AccountingQueryKind::FlowsPerEpoch {
+                from: Point::from(0),
+                to: Point::from(10),
+                epoch_length: 0,
+            },
+            AccountingQueryKind::LargestPendingWithdrawals { limit: 0 },
+        ];
+        for kind in kinds {
+            let bytes = encode_request(&Req::QueryAccounting(AccountingQuery {
+                kind,
+                format: ExportFormat::Csv,
+            }))
+            .unwrap();
+            assert!(matches!(
+                decode_request::<Vec<u8>, u64>(&bytes),
+                Err(RequestError::Invalid(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn negotiate_versions() {
+        let local = IpcHandshake {
+            version: 3,
+            min_compatible_version: 2,
+        };
+        let older = IpcHandshake {
+            version: 2,
+            min_compatible_version: 1,
+        };
+        let ancient = IpcHandshake {
+            version: 1,
+            min_compatible_version: 1,
+        };
+        let newer = IpcHandshake {
+            version: 5,
+            min_compatible_version: 4,
+        };
+        assert_eq!(local.negotiate(&older), Ok(2));
+        assert!(local.negotiate(&ancient).is_err());
+        assert!(local.negotiate(&newer).is_err());
+    }
+
+    #[test]
+    fn fuzz_random_bytes() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..10_000 {
+            let mut bytes = vec![0u8; rng.gen_range(0..256)];
+            rng.fill_bytes(&mut bytes);
+            let _ = decode_request::<Vec<u8>, u64>(&bytes);
+        }
+    }
+
+    #[test]
+    fn fuzz_mutated_requests() {
+        let mut rng = rand::thread_rng();
+        let samples = vec![
+            encode_request(&constraints(5.0)).unwrap(),
+            encode_request(&Req::SyncFrom(Some(progress_point()))).unwrap(),
+            encode_request(&Req::Disconnect).unwrap(),
+        ];
+        for _ in 0..10_000 {
+            let mut bytes = samples[rng.gen_range(0..samples.len())].clone();
+            for _ in 0..rng.gen_range(1..4) {
+                let ix = rng.gen_range(0..bytes.len());
+                bytes[ix] = rng.gen();
+            }
+            if rng.gen_bool(0.2) {
+                bytes.truncate(rng.gen_range(0..bytes.len()));
+            }
+            let _ = decode_request::<Vec<u8>, u64>(&bytes);
+        }
+    }
+}
@@ -1,11 +1,34 @@
+pub mod bridge;
+pub mod certificate;
+pub mod fee;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod health;
+pub mod ipc;
+pub mod progress;
+pub mod report_builder;
+pub mod resharing;
+pub mod server;
+
+use bridge::BridgeReceiver;
+use fee::FeePolicy;
+use health::{HealthAlert, NodeHealth};
+use resharing::ResharedKey;
 use serde::{Deserialize, Serialize};
+use spectrum_crypto::digest::Blake2b256;
+use spectrum_crypto::digest::{blake2b256_hash, Blake2bDigest256};
+use spectrum_crypto::merkle::{leaf_hash, MerkleProof};
+use spectrum_crypto::pubkey::PublicKey;
 use spectrum_ledger::cell::{ActiveCell, Serial};
 use spectrum_ledger::{
     cell::{BoxDestination, Owner, ProgressPoint, SValue, TermCell},
-    interop::ReportCertificate,
+    interop::{Point, ReportCertificate},
+    EpochNo,
 };
+use spectrum_sigma::crypto::{aggregate_pk, individual_input};
+use spectrum_sigma::evidence::SignedEvidence;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum TxEvent<T> {
     AppliedTx(T),
     UnappliedTx(T),
@@ -18,7 +41,7 @@ pub trait DataBridge {
 
 pub struct DataBridgeComponents<T> {
     /// Each consumer of the data bridge is given a receiver to stream transaction data.
-    pub receiver: tokio::sync::mpsc::Receiver<TxEvent<T>>,
+    pub receiver: BridgeReceiver<T>,
     /// Call `send(())` on this `Sender` to indicate that the bridge should start transmitting
     /// transaction data. Note that the receivers should have already been distributed to
     /// consumers.
@@ -38,6 +61,41 @@ pub enum ConnectorMsgOut<T, U, V> {
     TxEvent(ChainTxEvent<U, V>),
     ProposedTxsToNotarize(T),
     GenesisVaultUtxo(SValue),
+    VaultMigration(VaultMigrationStatus),
+    AccountingReport(AccountingReport),
+    /// Request to rotate the committee was rejected.
+    CommitteeRotationRejected(String),
+    /// Health of the chain node backing the Connector changed.
+    NodeHealthAlert(HealthAlert),
+    /// Export can't settle anymore since the vault was handed over to another committee.
+    RenotarizationRequired(StaleExport),
+    /// Balance of the vault on-chain doesn't match the value moved through it on the Spectrum side.
+    /// Exports must be halted until the discrepancy is resolved.
+    VaultBalanceMismatch(VaultBalanceDiscrepancy),
+    /// Evidence of misbehaviour of a committee member, to construct a slashing transaction from.
+    MisbehaviourEvidence(SignedEvidence<Blake2b256>),
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
+/// Result of reconciliation of the vault UTxO set against the ledger.
+pub struct VaultBalanceDiscrepancy {
+    /// Point of the chain at which the vault was reconciled.
+    pub progress_point: ProgressPoint,
+    /// Total value of the vault UTxOs.
+    pub on_chain: SValue,
+    /// Value the vault is expected to hold given deposits and withdrawals reported to Spectrum.
+    pub expected: SValue,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
+/// Export certified by a committee which no longer guards the vault. Its term cells have to be
+/// notarized again by the committee currently in charge.
+pub struct StaleExport {
+    /// Epoch of the committee which certified the export.
+    pub certified_in_epoch: EpochNo,
+    /// Epoch of the committee guarding the vault now.
+    pub current_epoch: EpochNo,
+    pub value_to_withdraw: Vec<TermCell>,
 }
 
 #[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
@@ -114,10 +172,147 @@ pub enum ConnectorRequest<T, U> {
     AcknowledgeConfirmedTx(PendingTxIdentifier<T, U>, ProgressPoint),
     /// Acknowledge that TX was aborted.
     AcknowledgeAbortedTx(PendingTxIdentifier<T, U>, ProgressPoint),
-    /// Indicate to the Connector to start rotating committee (WIP)
-    RotateCommittee,
+    /// Hand the vault over to the committee of the next epoch. Status of the rotation is then
+    /// tracked as [`PendingTxStatus::CommitteeRotation`].
+    RotateCommittee(Box<CommitteeHandover>),
     /// Indicate to Connector that consensus-driver is disconnecting.
     Disconnect,
+    /// Propose to migrate funds of the vault to an upgraded vault contract. The migration TX is
+    /// only submitted once enough operators of the Connector approve the migration.
+    ProposeVaultMigration(Box<VaultMigration>),
+    /// Approval of the pending vault migration by one of the operators of the Connector.
+    ApproveVaultMigration(OperatorApproval),
+    /// Query accounting of the SN Vault. The result is exported in the requested format.
+    QueryAccounting(AccountingQuery),
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
+pub struct AccountingQuery {
+    pub kind: AccountingQueryKind,
+    pub format: ExportFormat,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
+pub enum AccountingQueryKind {
+    /// Balance of the vault after every movement of value within the given range of points.
+    BalanceHistory { from: Point, to: Point },
+    /// Inflow and outflow of the vault aggregated per epoch of `epoch_length` points. Epochs
+    /// are counted from `from`, epochs without any movement of value are omitted.
+    FlowsPerEpoch {
+        from: Point,
+        to: Point,
+        epoch_length: u64,
+    },
+    /// Pending withdrawals, largest first.
+    LargestPendingWithdrawals { limit: usize },
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Copy, Clone)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
+/// Result of an [`AccountingQuery`].
+pub struct AccountingReport {
+    pub query: AccountingQuery,
+    /// Records exported in the requested format.
+    pub content: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
+/// Migration of the SN Vault to an upgraded contract.
+pub struct VaultMigration {
+    /// Chain-specific serialization of the new vault contract.
+    pub new_contract: Vec<u8>,
+    /// Committee certificate over the migration digest.
+    pub certificate: ReportCertificate,
+}
+
+/// Domain separation tag of the handover digest.
+const HANDOVER_TAG: &[u8] = b"spectrum/committee-handover";
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
+/// Report of the outgoing committee handing the vault over to the committee of the next epoch.
+pub struct CommitteeHandover {
+    /// Members of the incoming committee in the order their keys are aggregated.
+    pub new_committee: Vec<PublicKey>,
+    /// Aggregate key of the incoming committee.
+    pub new_aggregate_key: PublicKey,
+    /// Epoch the incoming committee is in charge of.
+    pub epoch: EpochNo,
+    /// Threshold key of the outgoing committee reshared to the incoming one, if any.
+    /// Certified along with the rest of the handover, so that control over the key changes hands
+    /// at the same epoch boundary as the vault.
+    pub reshared_key: Option<ResharedKey>,
+    /// Certificate of the outgoing committee over [`CommitteeHandover::digest`].
+    pub certificate: ReportCertificate,
+}
+
+impl CommitteeHandover {
+    /// Digest the outgoing committee certifies.
+    pub fn digest(&self) -> Blake2bDigest256 {
+        let mut bytes = HANDOVER_TAG.to_vec();
+        bytes.extend_from_slice(&u64::from(self.epoch).to_be_bytes());
+        bytes.extend(bincode::serialize(&self.new_committee).unwrap());
+        bytes.extend(bincode::serialize(&self.new_aggregate_key).unwrap());
+        // Digests of handovers without resharing are left as they were.
+        if let Some(reshared_key) = &self.reshared_key {
+            bytes.extend(bincode::serialize(reshared_key).unwrap());
+        }
+        blake2b256_hash(&bytes)
+    }
+
+    /// Whether shares of the reshared key, if any, are held by the incoming committee.
+    pub fn reshared_key_matches(&self) -> bool {
+        match &self.reshared_key {
+            Some(key) => key.is_consistent(self.new_committee.len()),
+            None => true,
+        }
+    }
+
+    /// Whether the aggregate key is the one of the incoming committee.
+    pub fn aggregate_key_matches(&self) -> bool {
+        if self.new_committee.is_empty() {
+            return false;
+        }
+        let individual_inputs = self
+            .new_committee
+            .iter()
+            .map(|pk| individual_input::<Blake2b256>(self.new_committee.clone(), *pk))
+            .collect();
+        aggregate_pk(self.new_committee.clone(), individual_inputs) == self.new_aggregate_key
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
+pub struct OperatorApproval {
+    /// Digest of the migration being approved.
+    pub migration_digest: Vec<u8>,
+    /// Index of the operator in the configuration of the Connector.
+    pub operator_ix: u16,
+    /// Schnorr signature of the operator over the migration digest.
+    pub signature: Vec<u8>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
+pub enum VaultMigrationStatus {
+    /// Migration is waiting for approval of the operators.
+    AwaitingApproval {
+        migration_digest: Vec<u8>,
+        approvals: usize,
+        required: usize,
+    },
+    /// Migration TX is submitted, but not yet confirmed.
+    Submitted { migration_digest: Vec<u8> },
+    /// Migration TX is confirmed at the given progress point.
+    Settled {
+        migration_digest: Vec<u8>,
+        progress_point: ProgressPoint,
+    },
+    /// Request related to vault migration was rejected.
+    Rejected(String),
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -130,6 +325,9 @@ pub struct NotarizedReportConstraints {
     pub max_tx_size: Kilobytes,
     /// An estimate of number of byzantine nodes in the current committee.
     pub estimated_number_of_byzantine_nodes: u32,
+    /// Fees to deduct from exported value. Nothing is charged if absent.
+    #[serde(default)]
+    pub fee_policy: Option<FeePolicy>,
 }
 
 #[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
@@ -145,8 +343,11 @@ pub enum ConnectorStatus<T, U> {
         /// The current progress point that the Connector is up to. It represents the
         /// tip of the chain at the time the struct is created.
         current_progress_point: ProgressPoint,
-        /// Contains information on a pending TX (withdrawal or deposit), if it currently exists.
-        pending_tx_status: Option<PendingTxStatus<T, U>>,
+        /// Statuses of all pending TXs (withdrawals, deposits and committee rotations) in order
+        /// of submission.
+        pending_txs: Vec<PendingTxStatus<T, U>>,
+        /// Health of the chain node backing the Connector.
+        node_health: NodeHealth,
     },
 
     /// Indicates that the Connector has yet to complete sync'ing with its associated chain.
@@ -155,8 +356,11 @@ pub enum ConnectorStatus<T, U> {
         current_progress_point: ProgressPoint,
         /// The number of progress points remaining for the Connector to process to be in sync.
         num_points_remaining: u32,
-        /// Contains information on a pending TX (withdrawal or deposit), if it currently exists.
-        pending_tx_status: Option<PendingTxStatus<T, U>>,
+        /// Statuses of all pending TXs (withdrawals, deposits and committee rotations) in order
+        /// of submission.
+        pending_txs: Vec<PendingTxStatus<T, U>>,
+        /// Health of the chain node backing the Connector.
+        node_health: NodeHealth,
     },
 }
 
@@ -165,14 +369,25 @@ where
     T: Clone,
     U: Clone,
 {
+    /// All pending TXs, oldest first.
+    pub fn get_pending_txs(&self) -> &[PendingTxStatus<T, U>] {
+        match self {
+            ConnectorStatus::Synced { pending_txs, .. } | ConnectorStatus::Syncing { pending_txs, .. } => {
+                pending_txs
+            }
+        }
+    }
+
+    /// The oldest pending TX. TXs may be chained, so they are to be acknowledged in this order.
     pub fn get_pending_tx_status(&self) -> Option<PendingTxStatus<T, U>> {
+        self.get_pending_txs().first().cloned()
+    }
+
+    pub fn get_node_health(&self) -> &NodeHealth {
         match self {
-            ConnectorStatus::Synced {
-                pending_tx_status, ..
+            ConnectorStatus::Synced { node_health, .. } | ConnectorStatus::Syncing { node_health, .. } => {
+                node_health
             }
-            | ConnectorStatus::Syncing {
-                pending_tx_status, ..
-            } => pending_tx_status.clone(),
         }
     }
 
@@ -193,10 +408,41 @@ where
 #[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
 pub enum TxStatus {
     WaitingForConfirmation,
-    Confirmed,
+    /// TX is included into the chain. The depth is updated with every status as the chain advances.
+    Confirmed(Confirmation),
     Aborted,
 }
 
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Confirmation {
+    /// Number of blocks applied on top of the one containing the TX.
+    pub depth: u32,
+    /// Whether the TX is buried deep enough to be acknowledged, see [`AcknowledgementThreshold`].
+    pub settled: bool,
+}
+
+/// Depth at which a confirmed TX is considered settled on a particular chain.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Copy, Clone)]
+pub struct AcknowledgementThreshold {
+    pub min_depth: u32,
+}
+
+impl AcknowledgementThreshold {
+    pub fn confirmation(&self, depth: u32) -> Confirmation {
+        Confirmation {
+            depth,
+            settled: depth >= self.min_depth,
+        }
+    }
+}
+
+impl Default for AcknowledgementThreshold {
+    /// Settle TXs as soon as they are included into the chain.
+    fn default() -> Self {
+        Self { min_depth: 0 }
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
 pub struct PendingWithdrawalStatus<T> {
     pub identifier: NotarizedReport<T>,
@@ -209,6 +455,12 @@ pub struct PendingDepositStatus<T> {
     pub status: TxStatus,
 }
 
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
+pub struct PendingCommitteeRotationStatus {
+    pub identifier: CommitteeHandover,
+    pub status: TxStatus,
+}
+
 #[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
 /// Represents the status of a pending SN TX.
 ///
@@ -219,15 +471,17 @@ pub struct PendingDepositStatus<T> {
 pub enum PendingTxStatus<T, U> {
     Withdrawal(PendingWithdrawalStatus<T>),
     Deposit(PendingDepositStatus<U>),
+    CommitteeRotation(PendingCommitteeRotationStatus),
 }
 
 #[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
 pub enum PendingTxIdentifier<T, U> {
     Withdrawal(Box<NotarizedReport<T>>),
     Deposit(Vec<InboundValue<U>>),
+    CommitteeRotation(Box<CommitteeHandover>),
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, PartialOrd)]
 pub struct Kilobytes(pub f32);
 
 #[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
@@ -285,4 +539,18 @@ pub struct NotarizedReport<T> {
     pub value_to_withdraw: Vec<TermCell>,
     pub authenticated_digest: Vec<u8>,
     pub additional_chain_data: T,
+    /// Proof of inclusion of the report into the batch notarized by the certificate,
+    /// see [`report_builder::batch_reports`]. `None` if the report was notarized alone.
+    #[serde(default)]
+    pub inclusion_proof: Option<MerkleProof>,
+}
+
+impl<T> NotarizedReport<T> {
+    /// Digest the certificate of the report must be issued over.
+    pub fn signed_digest(&self) -> Blake2bDigest256 {
+        match &self.inclusion_proof {
+            Some(proof) => proof.root(leaf_hash(&self.authenticated_digest)),
+            None => blake2b256_hash(&self.authenticated_digest),
+        }
+    }
 }
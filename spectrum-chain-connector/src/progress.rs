@@ -0,0 +1,309 @@
+//! Tracking of chain progress on top of the TX event stream of a [`crate::DataBridge`].
+//!
+//! [`TxEvent`] alone doesn't tell how deep an applied TX is buried or where a reorg ends, so
+//! [`ProgressTracker`] remembers the progress point of every applied TX, reports rollbacks
+//! explicitly and streams TXs once they reach a given confirmation depth.
+
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::Stream;
+use log::warn;
+use spectrum_ledger::cell::ProgressPoint;
+use spectrum_ledger::interop::Point;
+use spectrum_ledger::ChainId;
+use tokio::sync::mpsc;
+
+use crate::TxEvent;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProgressEvent<T> {
+    Tx {
+        point: ProgressPoint,
+        event: TxEvent<T>,
+    },
+    /// The chain was rolled back to the given point, TXs above it are about to be unapplied.
+    /// Emitted once per reorg, before the first unapplied TX.
+    RollbackTo(ProgressPoint),
+}
+
+/// TXs applied at the same progress point.
+#[derive(Debug)]
+struct Block<T> {
+    point: Point,
+    txs: Vec<T>,
+}
+
+struct ConfirmedSender<T> {
+    depth: usize,
+    tx: mpsc::UnboundedSender<(ProgressPoint, T)>,
+    /// Point of the most recent block emitted to the subscriber.
+    emitted_up_to: Option<Point>,
+}
+
+pub struct ProgressTracker<T> {
+    chain_id: ChainId,
+    /// Number of the most recent blocks remembered in order to resolve rollback targets.
+    max_rollback_depth: usize,
+    /// Recently applied blocks, ordered by point.
+    blocks: VecDeque<Block<T>>,
+    /// Point of the most recent block evicted from `blocks`.
+    forgotten_tip: Option<Point>,
+    subscribers: Vec<ConfirmedSender<T>>,
+}
+
+impl<T: Clone> ProgressTracker<T> {
+    pub fn new(chain_id: ChainId, max_rollback_depth: usize) -> Self {
+        Self {
+            chain_id,
+            max_rollback_depth,
+            blocks: VecDeque::new(),
+            forgotten_tip: None,
+            subscribers: Vec::new(),
+        }
+    }
+
+    /// Most recent applied progress point.
+    pub fn tip(&self) -> Option<ProgressPoint> {
+        self.blocks
+            .back()
+            .map(|b| b.point)
+            .or(self.forgotten_tip)
+            .map(|point| self.progress_point(point))
+    }
+
+    /// Stream of applied TXs which have at least `depth` blocks on top of them.
+    /// Depth is measured in distinct progress points, `0` streams TXs as soon as they're applied.
+    pub fn confirmed(&mut self, depth: usize) -> ConfirmedTxs<T> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        // Blocks which are already gone from memory are never emitted to the new subscriber.
+        let emitted_up_to = self.forgotten_tip;
+        self.subscribers.push(ConfirmedSender {
+            depth,
+            tx,
+            emitted_up_to,
+        });
+        self.emit_confirmed();
+        ConfirmedTxs(rx)
+    }
+
+    /// Record a TX event observed at the given point.
+    pub fn track(&mut self, point: Point, event: TxEvent<T>) -> Vec<ProgressEvent<T>> {
+        let mut events = vec![];
+        match &event {
+            TxEvent::AppliedTx(tx) => {
+                // An applied TX below the tip implies that the blocks above it were dropped.
+                if let Some(target) = self.rollback(point, false) {
+                    events.push(ProgressEvent::RollbackTo(target));
+                }
+                match self.blocks.back_mut() {
+                    Some(block) if block.point == point => block.txs.push(tx.clone()),
+                    _ => self.blocks.push_back(Block {
+                        point,
+                        txs: vec![tx.clone()],
+                    }),
+                }
+            }
+            TxEvent::UnappliedTx(_) => {
+                if let Some(target) = self.rollback(point, true) {
+                    events.push(ProgressEvent::RollbackTo(target));
+                }
+            }
+        }
+        events.push(ProgressEvent::Tx {
+            point: self.progress_point(point),
+            event,
+        });
+        self.emit_confirmed();
+        self.evict();
+        events
+    }
+
+    /// Drop blocks above `point` (including the one at `point` if `inclusive`).
+    /// Returns the point the chain was rolled back to if any block was dropped.
+    fn rollback(&mut self, point: Point, inclusive: bool) -> Option<ProgressPoint> {
+        let is_dropped = |b: &Block<T>| b.point > point || (inclusive && b.point == point);
+        if !self.blocks.back().is_some_and(is_dropped) {
+            return None;
+        }
+        while self.blocks.back().is_some_and(is_dropped) {
+            self.blocks.pop_back();
+        }
+        // The tracker doesn't remember anything below, so the chain is rolled back to its origin.
+        let target = self
+            .blocks
+            .back()
+            .map(|b| b.point)
+            .or(self.forgotten_tip)
+            .unwrap_or(Point::from(0));
+        for sub in self.subscribers.iter_mut() {
+            if sub.emitted_up_to.is_some_and(|p| p > target) {
+                warn!(
+                    "Rollback to {:?} reverts TXs already confirmed at depth {}",
+                    target, sub.depth
+                );
+                sub.emitted_up_to = Some(target);
+            }
+        }
+        Some(self.progress_point(target))
+    }
+
+    fn emit_confirmed(&mut self) {
+        let chain_id = self.chain_id;
+        let num_blocks = self.blocks.len();
+        self.subscribers.retain(|sub| !sub.tx.is_closed());
+        for sub in self.subscribers.iter_mut() {
+            for (ix, block) in self.blocks.iter().enumerate() {
+                let depth = num_blocks - 1 - ix;
+                if depth < sub.depth {
+                    break;
+                }
+                if sub.emitted_up_to.is_some_and(|p| block.point <= p) {
+                    continue;
+                }
+                for tx in block.txs.iter() {
+                    let point = ProgressPoint {
+                        chain_id,
+                        point: block.point,
+                    };
+                    let _ = sub.tx.send((point, tx.clone()));
+                }
+                sub.emitted_up_to = Some(block.point);
+            }
+        }
+    }
+
+    /// Forget blocks which are too deep to be rolled back and are already emitted to every
+    /// subscriber.
+    fn evict(&mut self) {
+        while self.blocks.len() > self.max_rollback_depth {
+            let Some(front) = self.blocks.front().map(|b| b.point) else {
+                break;
+            };
+            let emitted = self
+                .subscribers
+                .iter()
+                .all(|sub| sub.emitted_up_to.is_some_and(|p| front <= p));
+            if !emitted {
+                break;
+            }
+            self.blocks.pop_front();
+            self.forgotten_tip = Some(front);
+        }
+    }
+
+    fn progress_point(&self, point: Point) -> ProgressPoint {
+        ProgressPoint {
+            chain_id: self.chain_id,
+            point,
+        }
+    }
+}
+
+/// Applied TXs that reached the requested confirmation depth, see [`ProgressTracker::confirmed`].
+pub struct ConfirmedTxs<T>(mpsc::UnboundedReceiver<(ProgressPoint, T)>);
+
+impl<T> Stream for ConfirmedTxs<T> {
+    type Item = (ProgressPoint, T);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.poll_recv(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use spectrum_ledger::cell::ProgressPoint;
+    use spectrum_ledger::interop::Point;
+    use spectrum_ledger::ERGO_CHAIN_ID;
+
+    use crate::progress::{ProgressEvent, ProgressTracker};
+    use crate::TxEvent;
+
+    fn pp(point: u64) -> ProgressPoint {
+        ProgressPoint {
+            chain_id: ERGO_CHAIN_ID,
+            point: Point::from(point),
+        }
+    }
+
+    #[tokio::test]
+    async fn txs_are_confirmed_at_depth() {
+        let mut tracker = ProgressTracker::<u32>::new(ERGO_CHAIN_ID, 16);
+        let mut confirmed = tracker.confirmed(2);
+        tracker.track(Point::from(1), TxEvent::AppliedTx(0));
+        tracker.track(Point::from(1), TxEvent::AppliedTx(1));
+        tracker.track(Point::from(2), TxEvent::AppliedTx(2));
+        tracker.track(Point::from(5), TxEvent::AppliedTx(3));
+        assert_eq!(confirmed.next().await, Some((pp(1), 0)));
+        assert_eq!(confirmed.next().await, Some((pp(1), 1)));
+        tracker.track(Point::from(6), TxEvent::AppliedTx(4));
+        assert_eq!(confirmed.next().await, Some((pp(2), 2)));
+        drop(tracker);
+        assert_eq!(confirmed.next().await, None);
+    }
+
+    #[test]
+    fn rollback_is_reported_once_per_reorg() {
+        let mut tracker = ProgressTracker::<u32>::new(ERGO_CHAIN_ID, 16);
+        tracker.track(Point::from(1), TxEvent::AppliedTx(0));
+        tracker.track(Point::from(2), TxEvent::AppliedTx(1));
+        tracker.track(Point::from(3), TxEvent::AppliedTx(2));
+        assert_eq!(
+            tracker.track(Point::from(3), TxEvent::UnappliedTx(2)),
+            vec![
+                ProgressEvent::RollbackTo(pp(2)),
+                ProgressEvent::Tx {
+                    point: pp(3),
+                    event: TxEvent::UnappliedTx(2)
+                }
+            ]
+        );
+        assert_eq!(
+            tracker.track(Point::from(3), TxEvent::UnappliedTx(3)),
+            vec![ProgressEvent::Tx {
+                point: pp(3),
+                event: TxEvent::UnappliedTx(3)
+            }]
+        );
+        assert_eq!(
+            tracker.track(Point::from(2), TxEvent::UnappliedTx(1)),
+            vec![
+                ProgressEvent::RollbackTo(pp(1)),
+                ProgressEvent::Tx {
+                    point: pp(2),
+                    event: TxEvent::UnappliedTx(1)
+                }
+            ]
+        );
+        tracker.track(Point::from(2), TxEvent::AppliedTx(4));
+        assert_eq!(tracker.tip(), Some(pp(2)));
+    }
+
+    #[tokio::test]
+    async fn rolled_back_txs_are_not_confirmed() {
+        let mut tracker = ProgressTracker::<u32>::new(ERGO_CHAIN_ID, 16);
+        let mut confirmed = tracker.confirmed(1);
+        tracker.track(Point::from(1), TxEvent::AppliedTx(0));
+        tracker.track(Point::from(2), TxEvent::AppliedTx(1));
+        tracker.track(Point::from(2), TxEvent::UnappliedTx(1));
+        tracker.track(Point::from(2), TxEvent::AppliedTx(2));
+        tracker.track(Point::from(3), TxEvent::AppliedTx(3));
+        assert_eq!(confirmed.next().await, Some((pp(1), 0)));
+        assert_eq!(confirmed.next().await, Some((pp(2), 2)));
+    }
+
+    #[test]
+    fn rollback_below_remembered_blocks_targets_forgotten_tip() {
+        let mut tracker = ProgressTracker::<u32>::new(ERGO_CHAIN_ID, 2);
+        for i in 1..=4 {
+            tracker.track(Point::from(i), TxEvent::AppliedTx(i as u32));
+        }
+        assert_eq!(
+            tracker.track(Point::from(3), TxEvent::UnappliedTx(3))[0],
+            ProgressEvent::RollbackTo(pp(2))
+        );
+    }
+}
@@ -0,0 +1,290 @@
+//! Packing of pending withdrawals into notarized reports which fit the size limit of a TX.
+//!
+//! The size of a withdrawal TX is chain-specific, so connectors supply their own
+//! [`TxSizeEstimator`] (e.g. `estimate_tx_size_in_kb` of the Ergo connector).
+//!
+//! When withdrawal volume is high several reports are notarized in a single aggregation round,
+//! see [`batch_reports`].
+//!
+//! If the constraints carry a [`FeePolicy`], fees are deducted from the selected cells and
+//! the cell collecting them is appended to the report, so that it's covered by the report digest.
+
+use spectrum_crypto::digest::{blake2b256_hash, Blake2bDigest256};
+use spectrum_crypto::merkle::{leaf_hash, MerkleProof, MerkleTree};
+
+use crate::fee::FeePolicy;
+use crate::{Kilobytes, NotarizedReportConstraints, ProtoTermCell};
+
+/// Estimates the size of a TX withdrawing the given terminal cells.
+pub trait TxSizeEstimator {
+    fn estimate(&self, term_cells: &[ProtoTermCell], estimated_number_of_byzantine_nodes: u32) -> Kilobytes;
+}
+
+impl<F> TxSizeEstimator for F
+where
+    F: Fn(&[ProtoTermCell], u32) -> Kilobytes,
+{
+    fn estimate(&self, term_cells: &[ProtoTermCell], estimated_number_of_byzantine_nodes: u32) -> Kilobytes {
+        self(term_cells, estimated_number_of_byzantine_nodes)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Packed<T> {
+    /// Items which fit into the TX, in their original order.
+    pub selected: Vec<T>,
+    /// Items which didn't fit and are left for subsequent reports, in their original order.
+    pub deferred: Vec<T>,
+    /// Estimated size of the TX made of `selected` items.
+    pub estimated_size: Kilobytes,
+}
+
+/// Select terminal cells for the next notarized report so that the size of the resulted TX
+/// doesn't exceed `constraints.max_tx_size`. Selected cells are charged according to
+/// `constraints.fee_policy`, cells which can't pay for their export are deferred.
+pub fn pack_term_cells<E: TxSizeEstimator>(
+    constraints: NotarizedReportConstraints,
+    estimator: &E,
+) -> Packed<ProtoTermCell> {
+    let NotarizedReportConstraints {
+        term_cells,
+        max_tx_size,
+        estimated_number_of_byzantine_nodes,
+        fee_policy,
+        ..
+    } = constraints;
+    let estimate = |cells: &[ProtoTermCell]| estimator.estimate(cells, estimated_number_of_byzantine_nodes);
+    match fee_policy {
+        None => pack(term_cells, max_tx_size, estimate),
+        Some(policy) => pack_charged(term_cells, max_tx_size, &policy, estimate),
+    }
+}
+
+fn pack_charged<F>(
+    term_cells: Vec<ProtoTermCell>,
+    max_tx_size: Kilobytes,
+    policy: &FeePolicy,
+    estimate: F,
+) -> Packed<ProtoTermCell>
+where
+    F: Fn(&[ProtoTermCell]) -> Kilobytes,
+{
+    let (affordable, unaffordable): (Vec<_>, Vec<_>) =
+        term_cells.into_iter().partition(|cell| policy.can_pay(cell));
+    let Packed {
+        selected,
+        mut deferred,
+        estimated_size,
+    } = pack(affordable, max_tx_size, |cells| estimate(&policy.charge(cells)));
+    deferred.extend(unaffordable);
+    Packed {
+        selected: policy.charge(&selected),
+        deferred,
+        estimated_size,
+    }
+}
+
+/// Greedily select a maximal subset of `items` whose estimated size fits `max_size`.
+///
+/// Items are tried in the given order, so older withdrawals take precedence. An item which
+/// doesn't fit is deferred, while subsequent (possibly smaller) items are still tried.
+/// The resulted subset is maximal provided that the estimate doesn't decrease as items are added.
+pub fn pack<T, F>(items: Vec<T>, max_size: Kilobytes, estimate: F) -> Packed<T>
+where
+    F: Fn(&[T]) -> Kilobytes,
+{
+    let mut selected = Vec::with_capacity(items.len());
+    let mut deferred = Vec::new();
+    let mut estimated_size = estimate(&selected);
+    for item in items {
+        selected.push(item);
+        let size = estimate(&selected);
+        if size <= max_size {
+            estimated_size = size;
+        } else if let Some(item) = selected.pop() {
+            deferred.push(item);
+        }
+    }
+    Packed {
+        selected,
+        deferred,
+        estimated_size,
+    }
+}
+
+/// Digest to notarize reports with the given authenticated digests in one aggregation round,
+/// along with proofs of inclusion to attach to each of the reports, in the same order.
+/// A single report is notarized alone, without a proof. Returns `None` if there are no reports.
+pub fn batch_reports(
+    authenticated_digests: &[Vec<u8>],
+) -> Option<(Blake2bDigest256, Vec<Option<MerkleProof>>)> {
+    match authenticated_digests {
+        [] => None,
+        [single] => Some((blake2b256_hash(single), vec![None])),
+        _ => {
+            let tree = MerkleTree::new(authenticated_digests.iter().map(|d| leaf_hash(d)).collect());
+            let proofs = (0..authenticated_digests.len())
+                .map(|ix| tree.proof(ix))
+                .collect();
+            tree.root().map(|root| (root, proofs))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::SecretKey;
+    use rand::rngs::OsRng;
+
+    use spectrum_crypto::digest::Blake2bDigest256;
+    use spectrum_crypto::pubkey::PublicKey;
+    use spectrum_handel::Threshold;
+    use spectrum_ledger::interop::ReportCertificate;
+    use spectrum_sigma::sigma_aggregation::AggregateCertificate;
+    use spectrum_sigma::AggregateCommitment;
+
+    use std::collections::HashMap;
+
+    use spectrum_ledger::cell::{BoxDestination, NativeCoin, ProgressPoint, SValue};
+    use spectrum_ledger::interop::Point;
+    use spectrum_ledger::ChainId;
+
+    use crate::certificate::{verify_report_certificate, CommitteeSnapshot, ReportCertificateError};
+    use crate::fee::FeePolicy;
+    use crate::report_builder::{batch_reports, pack, pack_term_cells, Packed};
+    use crate::{Kilobytes, NotarizedReport, NotarizedReportConstraints, ProtoTermCell};
+
+    /// Size of a TX is a fixed overhead plus the size of every item.
+    fn estimate(items: &[u32]) -> Kilobytes {
+        Kilobytes(0.5 + items.iter().map(|i| *i as f32).sum::<f32>())
+    }
+
+    #[test]
+    fn everything_fits() {
+        assert_eq!(
+            pack(vec![1, 2], Kilobytes(5.0), estimate),
+            Packed {
+                selected: vec![1, 2],
+                deferred: vec![],
+                estimated_size: Kilobytes(3.5),
+            }
+        );
+    }
+
+    #[test]
+    fn smaller_items_fill_the_space_left() {
+        let Packed {
+            selected, deferred, ..
+        } = pack(vec![2, 3, 1, 4, 1], Kilobytes(5.0), estimate);
+        assert_eq!(selected, vec![2, 1, 1]);
+        assert_eq!(deferred, vec![3, 4]);
+    }
+
+    #[test]
+    fn nothing_fits_when_overhead_exceeds_limit() {
+        let Packed {
+            selected, deferred, ..
+        } = pack(vec![1, 2], Kilobytes(0.4), estimate);
+        assert!(selected.is_empty());
+        assert_eq!(deferred, vec![1, 2]);
+    }
+
+    fn term_cell(native: u64, address: u8) -> ProtoTermCell {
+        ProtoTermCell {
+            value: SValue {
+                native: NativeCoin::from(native),
+                assets: HashMap::new(),
+            },
+            dst: BoxDestination {
+                target: ChainId::from(0),
+                address: vec![address].into(),
+                inputs: None,
+                constraints: None,
+            },
+        }
+    }
+
+    #[test]
+    fn fees_deducted_and_paid_to_collector() {
+        let policy = FeePolicy {
+            flat: NativeCoin::from(100),
+            per_byte: NativeCoin::from(1),
+            collector: term_cell(0, 0xff).dst,
+        };
+        let (rich, poor, large) = (term_cell(1000, 1), term_cell(101, 2), term_cell(5000, 3));
+        let fee = policy.fee_of(&rich);
+        let constraints = NotarizedReportConstraints {
+            term_cells: vec![rich, poor.clone(), large.clone()],
+            last_progress_point: ProgressPoint {
+                chain_id: ChainId::from(0),
+                point: Point::from(0),
+            },
+            max_tx_size: Kilobytes(2.0),
+            estimated_number_of_byzantine_nodes: 0,
+            fee_policy: Some(policy),
+        };
+        // Every cell takes 1kb, the fee cell included.
+        let estimator = |cells: &[ProtoTermCell], _: u32| Kilobytes(cells.len() as f32);
+        let Packed {
+            selected, deferred, ..
+        } = pack_term_cells(constraints, &estimator);
+        assert_eq!(selected, vec![term_cell(1000 - fee, 1), term_cell(fee, 0xff)]);
+        assert_eq!(deferred, vec![large, poor]);
+    }
+
+    fn report(authenticated_digest: Vec<u8>, message_digest: Blake2bDigest256) -> NotarizedReport<()> {
+        NotarizedReport {
+            certificate: ReportCertificate::SchnorrK256(AggregateCertificate {
+                message_digest,
+                aggregate_commitment: AggregateCommitment::from(PublicKey::from(SecretKey::random(
+                    &mut OsRng,
+                ))),
+                aggregate_response: k256::Scalar::ZERO,
+                exclusion_set: vec![],
+            }),
+            value_to_withdraw: vec![],
+            authenticated_digest,
+            additional_chain_data: (),
+            inclusion_proof: None,
+        }
+    }
+
+    #[test]
+    fn batched_reports_commit_to_signed_digest() {
+        let digests = vec![vec![1u8; 33], vec![2u8; 33], vec![3u8; 33]];
+        let (root, proofs) = batch_reports(&digests).unwrap();
+        for (digest, proof) in digests.into_iter().zip(proofs) {
+            let mut report = report(digest, root);
+            assert_ne!(report.signed_digest(), root);
+            report.inclusion_proof = proof;
+            assert_eq!(report.signed_digest(), root);
+        }
+    }
+
+    #[test]
+    fn single_report_is_signed_alone() {
+        let digest = vec![7u8; 33];
+        let (signed, proofs) = batch_reports(&[digest.clone()]).unwrap();
+        assert_eq!(proofs, vec![None]);
+        assert_eq!(report(digest, signed).signed_digest(), signed);
+        assert_eq!(batch_reports(&[]), None);
+    }
+
+    #[test]
+    fn certificate_over_other_batch_is_rejected() {
+        let (other_root, _) = batch_reports(&[vec![4u8; 33], vec![5u8; 33]]).unwrap();
+        let (_, proofs) = batch_reports(&[vec![1u8; 33], vec![2u8; 33]]).unwrap();
+        let mut report = report(vec![1u8; 33], other_root);
+        report.inclusion_proof = proofs[0].clone();
+        assert_eq!(
+            verify_report_certificate(
+                &report,
+                &CommitteeSnapshot {
+                    members: vec![],
+                    threshold: Threshold { num: 1, denom: 1 }
+                }
+            ),
+            Err(ReportCertificateError::DigestMismatch)
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use k256::{ProjectivePoint, Scalar};
+use serde::{Deserialize, Serialize};
+
+use spectrum_crypto::pubkey::PublicKey;
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
+/// Committee key reshared to the incoming committee, so that any `threshold` of its members
+/// can use it. The key itself stays the same across handovers.
+pub struct ResharedKey {
+    pub group_public_key: PublicKey,
+    pub threshold: u32,
+    /// Public counterparts of secret shares of members of the incoming committee, in committee order.
+    pub verification_shares: Vec<PublicKey>,
+}
+
+impl ResharedKey {
+    /// Whether the shares are held by a committee of the given size and lie on a polynomial of
+    /// degree `threshold - 1` whose secret term is the group key.
+    pub fn is_consistent(&self, committee_size: usize) -> bool {
+        let threshold = self.threshold as usize;
+        if threshold == 0 || threshold > committee_size || self.verification_shares.len() != committee_size {
+            return false;
+        }
+        let shares: Vec<_> = self
+            .verification_shares
+            .iter()
+            .map(|pk| k256::PublicKey::from(*pk).to_projective())
+            .collect();
+        let (base, rest) = shares.split_at(threshold);
+        interpolate(base, Scalar::ZERO) == k256::PublicKey::from(self.group_public_key).to_projective()
+            && rest
+                .iter()
+                .enumerate()
+                .all(|(i, share)| interpolate(base, participant_id(threshold + i)) == *share)
+    }
+}
+
+/// Shares are evaluations of the polynomial at `index + 1`.
+fn participant_id(index: usize) -> Scalar {
+    Scalar::from(index as u64 + 1)
+}
+
+/// Value at `x` of the polynomial with the given values at `1, 2, ..., n`, in the exponent.
+fn interpolate(values: &[ProjectivePoint], x: Scalar) -> ProjectivePoint {
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let xi = participant_id(i);
+            let (num, den) =
+                (0..values.len())
+                    .filter(|j| *j != i)
+                    .fold((Scalar::ONE, Scalar::ONE), |(num, den), j| {
+                        let xj = participant_id(j);
+                        (num * (x - xj), den * (xi - xj))
+                    });
+            *value * (num * den.invert().unwrap())
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::elliptic_curve::Field;
+    use k256::{ProjectivePoint, Scalar};
+    use rand::rngs::OsRng;
+
+    use spectrum_crypto::pubkey::PublicKey;
+
+    use crate::resharing::{participant_id, ResharedKey};
+
+    fn to_pk(point: ProjectivePoint) -> PublicKey {
+        PublicKey::from(k256::PublicKey::try_from(point).unwrap())
+    }
+
+    #[test]
+    fn shares_consistent_with_group_key() {
+        let coefficients: Vec<_> = (0..3).map(|_| Scalar::random(&mut OsRng)).collect();
+        let eval = |x: Scalar| coefficients.iter().rev().fold(Scalar::ZERO, |acc, a| acc * x + a);
+        let key = ResharedKey {
+            group_public_key: to_pk(ProjectivePoint::GENERATOR * coefficients[0]),
+            threshold: 3,
+            verification_shares: (0..5)
+                .map(|ix| to_pk(ProjectivePoint::GENERATOR * eval(participant_id(ix))))
+                .collect(),
+        };
+        assert!(key.is_consistent(5));
+        assert!(!key.is_consistent(6));
+        let mut lower_threshold = key.clone();
+        lower_threshold.threshold = 2;
+        assert!(!lower_threshold.is_consistent(5));
+        let mut forged_share = key.clone();
+        forged_share.verification_shares[4] = to_pk(ProjectivePoint::GENERATOR * Scalar::random(&mut OsRng));
+        assert!(!forged_share.is_consistent(5));
+        let mut foreign_key = key;
+        foreign_key.group_public_key = to_pk(ProjectivePoint::GENERATOR * Scalar::random(&mut OsRng));
+        assert!(!foreign_key.is_consistent(5));
+    }
+}
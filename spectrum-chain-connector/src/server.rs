@@ -0,0 +1,522 @@
+//! Framed socket transport between consensus-drivers and a Connector.
+//!
+//! Every frame is length-delimited and carries a single bincode-encoded message. A session starts
+//! with authentication of the driver by a pre-shared token, followed by the usual IPC handshake
+//! (see [`IpcRequest::Hello`]). Only one driver is served at a time, responses of the Connector
+//! emitted while no driver is connected are dropped.
+
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use bincode::Options;
+use futures::{SinkExt, StreamExt};
+use log::{info, warn};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+use tokio::sync::mpsc;
+use tokio::time::{sleep_until, timeout, Instant};
+use tokio_util::bytes::Bytes;
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+use crate::ipc::{decode_request, encode_request, IpcHandshake, IpcRequest, IpcResponse, RequestError};
+use crate::{ConnectorMsgOut, ConnectorRequest, ConnectorResponse};
+
+/// Upper bound on the size of a single frame.
+pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub listen_addr: ListenAddr,
+    /// Token drivers have to present before anything else. Note that it's sent in the clear, so
+    /// TCP listeners are to be bound to loopback or tunneled.
+    pub auth_token: Vec<u8>,
+    /// Time given to a freshly connected driver to authenticate.
+    pub auth_timeout: Duration,
+    /// Max number of `RequestTxsToNotarize` awaiting the proposal of the Connector. Once reached,
+    /// the server stops reading from the driver until one of them is served or expires.
+    pub max_pending_notarization_requests: usize,
+    /// Requests to notarize which didn't result in a proposal within this time are considered
+    /// dropped by the Connector and no longer hold the driver back.
+    pub notarization_request_timeout: Duration,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+/// The first message of a driver on every connection.
+pub struct AuthRequest {
+    pub token: Vec<u8>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AuthResponse {
+    Accepted,
+    Denied,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ServerError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Malformed frame: {0}")]
+    Malformed(String),
+    #[error("Driver failed to authenticate")]
+    AuthFailed,
+    #[error("Driver didn't authenticate in time")]
+    AuthTimeout,
+    #[error("Connection closed by the remote side")]
+    ConnectionClosed,
+    #[error("Connector is shut down")]
+    ConnectorClosed,
+}
+
+/// Socket server forwarding requests of consensus-drivers to the Connector and responses back.
+pub struct VaultServer {
+    conf: ServerConfig,
+    listener: Listener,
+}
+
+enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl VaultServer {
+    pub async fn bind(conf: ServerConfig) -> io::Result<Self> {
+        if conf.max_pending_notarization_requests < 1 {
+            // The driver would never be served.
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "max_pending_notarization_requests must be at least 1",
+            ));
+        }
+        let listener = match &conf.listen_addr {
+            ListenAddr::Tcp(addr) => Listener::Tcp(TcpListener::bind(addr).await?),
+            ListenAddr::Unix(path) => {
+                // Socket file may be left over by a previous run.
+                if path.exists() {
+                    std::fs::remove_file(path)?;
+                }
+                Listener::Unix(UnixListener::bind(path)?)
+            }
+        };
+        Ok(Self { conf, listener })
+    }
+
+    /// Actual address of the listener, e.g. when bound to an ephemeral TCP port.
+    pub fn local_addr(&self) -> io::Result<ListenAddr> {
+        match &self.listener {
+            Listener::Tcp(l) => l.local_addr().map(ListenAddr::Tcp),
+            Listener::Unix(_) => Ok(self.conf.listen_addr.clone()),
+        }
+    }
+
+    /// Serve drivers one after another until the Connector goes away.
+    pub async fn run<S, T, U, V>(
+        self,
+        mut connector_response_rx: mpsc::Receiver<ConnectorResponse<S, T, U, V>>,
+        request_to_connector_tx: mpsc::Sender<ConnectorRequest<S, U>>,
+    ) -> Result<(), ServerError>
+    where
+        S: DeserializeOwned + Serialize,
+        T: Serialize,
+        U: DeserializeOwned + Serialize,
+        V: Serialize,
+    {
+        loop {
+            let res = tokio::select! {
+                conn = self.listener.accept() => match conn? {
+                    Connection::Tcp(stream, addr) => {
+                        info!("Consensus-driver connected from {}", addr);
+                        self.serve(stream, &mut connector_response_rx, &request_to_connector_tx).await
+                    }
+                    Connection::Unix(stream) => {
+                        info!("Consensus-driver connected");
+                        self.serve(stream, &mut connector_response_rx, &request_to_connector_tx).await
+                    }
+                },
+                resp = connector_response_rx.recv() => match resp {
+                    Some(_) => {
+                        warn!("No consensus-driver connected, dropping response of the Connector");
+                        continue;
+                    }
+                    None => return Err(ServerError::ConnectorClosed),
+                },
+            };
+            match res {
+                Ok(()) | Err(ServerError::ConnectionClosed) => info!("Consensus-driver disconnected"),
+                Err(ServerError::ConnectorClosed) => return Err(ServerError::ConnectorClosed),
+                Err(e) => warn!("Session with consensus-driver aborted: {}", e),
+            }
+        }
+    }
+
+    async fn serve<Io, S, T, U, V>(
+        &self,
+        io: Io,
+        connector_response_rx: &mut mpsc::Receiver<ConnectorResponse<S, T, U, V>>,
+        request_to_connector_tx: &mpsc::Sender<ConnectorRequest<S, U>>,
+    ) -> Result<(), ServerError>
+    where
+        Io: AsyncRead + AsyncWrite + Unpin,
+        S: DeserializeOwned + Serialize,
+        T: Serialize,
+        U: DeserializeOwned + Serialize,
+        V: Serialize,
+    {
+        let mut framed = Framed::new(io, frame_codec());
+        self.authenticate(&mut framed).await?;
+
+        let mut negotiated_version = None;
+        // Deadlines of notarization requests the Connector hasn't responded to yet, oldest first.
+        let mut pending_notarizations: VecDeque<Instant> = VecDeque::new();
+        loop {
+            let now = Instant::now();
+            while pending_notarizations.front().map_or(false, |d| *d <= now) {
+                pending_notarizations.pop_front();
+            }
+            let saturated = pending_notarizations.len() >= self.conf.max_pending_notarization_requests;
+            let next_expiry = pending_notarizations.front().copied();
+
+            let reply = tokio::select! {
+                resp = connector_response_rx.recv() => match resp {
+                    Some(resp) => {
+                        if resp
+                            .messages
+                            .iter()
+                            .any(|m| matches!(m, ConnectorMsgOut::ProposedTxsToNotarize(_)))
+                        {
+                            pending_notarizations.pop_front();
+                        }
+                        IpcResponse::Response(resp)
+                    }
+                    None => return Err(ServerError::ConnectorClosed),
+                },
+                _ = sleep_until(next_expiry.unwrap_or(now)), if saturated && next_expiry.is_some() => continue,
+                frame = framed.next(), if !saturated => {
+                    let frame = match frame {
+                        Some(frame) => frame?,
+                        None => return Err(ServerError::ConnectionClosed),
+                    };
+                    match (decode_frame::<IpcRequest>(&frame), negotiated_version) {
+                        (Err(e), _) => IpcResponse::Rejected(e),
+                        (Ok(IpcRequest::Hello(remote)), _) => match IpcHandshake::local().negotiate(&remote) {
+                            Ok(version) => {
+                                info!("Negotiated IPC protocol version {}", version);
+                                negotiated_version = Some(version);
+                                IpcResponse::Welcome(version)
+                            }
+                            Err(e) => {
+                                warn!("Incompatible consensus-driver: {}", e);
+                                negotiated_version = None;
+                                IpcResponse::Rejected(e)
+                            }
+                        },
+                        (Ok(IpcRequest::Request(_) | IpcRequest::Resume { .. } | IpcRequest::Ack(_)), None) => {
+                            IpcResponse::Rejected(RequestError::HandshakeRequired)
+                        }
+                        // Responses aren't retained by this transport, so there is nothing to replay.
+                        (Ok(IpcRequest::Resume { last_acked_seq: None } | IpcRequest::Ack(_)), Some(_)) => continue,
+                        (Ok(IpcRequest::Resume { last_acked_seq: Some(_) }), Some(_)) => IpcResponse::Rejected(
+                            RequestError::Invalid("Responses are not retained by this transport".into()),
+                        ),
+                        (Ok(IpcRequest::Request(bytes)), Some(_)) => match decode_request::<S, U>(&bytes) {
+                            Ok(ConnectorRequest::Disconnect) => return Ok(()),
+                            Ok(req) => {
+                                if matches!(req, ConnectorRequest::RequestTxsToNotarize(_)) {
+                                    pending_notarizations.push_back(now + self.conf.notarization_request_timeout);
+                                }
+                                request_to_connector_tx
+                                    .send(req)
+                                    .await
+                                    .map_err(|_| ServerError::ConnectorClosed)?;
+                                continue;
+                            }
+                            Err(e) => {
+                                warn!("Rejected request from consensus-driver: {}", e);
+                                IpcResponse::Rejected(e)
+                            }
+                        },
+                    }
+                },
+            };
+            send_frame(&mut framed, &reply).await?;
+        }
+    }
+
+    async fn authenticate<Io>(&self, framed: &mut Framed<Io, LengthDelimitedCodec>) -> Result<(), ServerError>
+    where
+        Io: AsyncRead + AsyncWrite + Unpin,
+    {
+        let frame = match timeout(self.conf.auth_timeout, framed.next()).await {
+            Ok(Some(frame)) => frame?,
+            Ok(None) => return Err(ServerError::ConnectionClosed),
+            Err(_) => return Err(ServerError::AuthTimeout),
+        };
+        let authenticated = decode_frame::<AuthRequest>(&frame)
+            .map(|req| tokens_match(&req.token, &self.conf.auth_token))
+            .unwrap_or(false);
+        if authenticated {
+            send_frame(framed, &AuthResponse::Accepted).await
+        } else {
+            send_frame(framed, &AuthResponse::Denied).await?;
+            Err(ServerError::AuthFailed)
+        }
+    }
+}
+
+enum Connection {
+    Tcp(TcpStream, SocketAddr),
+    Unix(UnixStream),
+}
+
+impl Listener {
+    async fn accept(&self) -> io::Result<Connection> {
+        match self {
+            Listener::Tcp(l) => l.accept().await.map(|(s, addr)| Connection::Tcp(s, addr)),
+            Listener::Unix(l) => l.accept().await.map(|(s, _)| Connection::Unix(s)),
+        }
+    }
+}
+
+trait Io: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Io for T {}
+
+/// Driver side of the socket transport.
+pub struct VaultClient {
+    framed: Framed<Box<dyn Io>, LengthDelimitedCodec>,
+}
+
+impl VaultClient {
+    /// Connect to the server and authenticate with the given token.
+    pub async fn connect(addr: &ListenAddr, auth_token: Vec<u8>) -> Result<Self, ServerError> {
+        let io: Box<dyn Io> = match addr {
+            ListenAddr::Tcp(addr) => Box::new(TcpStream::connect(addr).await?),
+            ListenAddr::Unix(path) => Box::new(UnixStream::connect(path).await?),
+        };
+        let mut framed = Framed::new(io, frame_codec());
+        send_frame(&mut framed, &AuthRequest { token: auth_token }).await?;
+        match recv_frame::<_, AuthResponse>(&mut framed).await? {
+            AuthResponse::Accepted => Ok(Self { framed }),
+            AuthResponse::Denied => Err(ServerError::AuthFailed),
+        }
+    }
+
+    /// Perform the IPC handshake, returns the negotiated protocol version.
+    pub async fn hello<S, T, U, V>(&mut self) -> Result<Result<u16, RequestError>, ServerError>
+    where
+        S: DeserializeOwned,
+        T: DeserializeOwned,
+        U: DeserializeOwned,
+        V: DeserializeOwned,
+    {
+        send_frame(&mut self.framed, &IpcRequest::Hello(IpcHandshake::local())).await?;
+        match self.recv::<S, T, U, V>().await? {
+            IpcResponse::Welcome(version) => Ok(Ok(version)),
+            IpcResponse::Rejected(e) => Ok(Err(e)),
+            IpcResponse::Response(_) | IpcResponse::Sequenced { .. } => {
+                Err(ServerError::Malformed("Unexpected response to Hello".into()))
+            }
+        }
+    }
+
+    pub async fn send<T: Serialize, U: Serialize>(
+        &mut self,
+        req: &ConnectorRequest<T, U>,
+    ) -> Result<(), ServerError> {
+        let bytes = encode_request(req).map_err(|e| ServerError::Malformed(e.to_string()))?;
+        send_frame(&mut self.framed, &IpcRequest::Request(bytes)).await
+    }
+
+    pub async fn recv<S, T, U, V>(&mut self) -> Result<IpcResponse<S, T, U, V>, ServerError>
+    where
+        S: DeserializeOwned,
+        T: DeserializeOwned,
+        U: DeserializeOwned,
+        V: DeserializeOwned,
+    {
+        recv_frame(&mut self.framed).await
+    }
+}
+
+fn frame_codec() -> LengthDelimitedCodec {
+    LengthDelimitedCodec::builder()
+        .max_frame_length(MAX_FRAME_SIZE)
+        .new_codec()
+}
+
+fn message_codec() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .reject_trailing_bytes()
+}
+
+fn decode_frame<M: DeserializeOwned>(bytes: &[u8]) -> Result<M, RequestError> {
+    message_codec()
+        .deserialize(bytes)
+        .map_err(|e| RequestError::Malformed(e.to_string()))
+}
+
+async fn send_frame<Io, M>(framed: &mut Framed<Io, LengthDelimitedCodec>, msg: &M) -> Result<(), ServerError>
+where
+    Io: AsyncRead + AsyncWrite + Unpin,
+    M: Serialize,
+{
+    let bytes = message_codec()
+        .serialize(msg)
+        .map_err(|e| ServerError::Malformed(e.to_string()))?;
+    framed.send(Bytes::from(bytes)).await?;
+    Ok(())
+}
+
+async fn recv_frame<Io, M>(framed: &mut Framed<Io, LengthDelimitedCodec>) -> Result<M, ServerError>
+where
+    Io: AsyncRead + AsyncWrite + Unpin,
+    M: DeserializeOwned,
+{
+    match framed.next().await {
+        Some(frame) => decode_frame(&frame?).map_err(|e| ServerError::Malformed(e.to_string())),
+        None => Err(ServerError::ConnectionClosed),
+    }
+}
+
+/// Compare tokens in constant time.
+fn tokens_match(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    use spectrum_ledger::cell::ProgressPoint;
+    use spectrum_ledger::interop::Point;
+    use spectrum_ledger::ChainId;
+    use tokio::sync::mpsc;
+    use tokio::time::timeout;
+
+    use crate::health::NodeHealth;
+    use crate::ipc::IpcResponse;
+    use crate::server::{ListenAddr, ServerConfig, ServerError, VaultClient, VaultServer};
+    use crate::{
+        ConnectorMsgOut, ConnectorRequest, ConnectorResponse, ConnectorStatus, Kilobytes,
+        NotarizedReportConstraints,
+    };
+
+    type Req = ConnectorRequest<Vec<u8>, u64>;
+    type Resp = ConnectorResponse<Vec<u8>, u64, u64, u64>;
+
+    const TOKEN: &[u8] = b"secret";
+
+    fn progress_point() -> ProgressPoint {
+        ProgressPoint {
+            chain_id: ChainId::from(0),
+            point: Point::from(100),
+        }
+    }
+
+    fn constraints() -> Req {
+        ConnectorRequest::RequestTxsToNotarize(NotarizedReportConstraints {
+            term_cells: vec![],
+            last_progress_point: progress_point(),
+            max_tx_size: Kilobytes(5.0),
+            estimated_number_of_byzantine_nodes: 0,
+            fee_policy: None,
+        })
+    }
+
+    fn proposal() -> Resp {
+        ConnectorResponse {
+            status: ConnectorStatus::Synced {
+                current_progress_point: progress_point(),
+                pending_txs: vec![],
+                node_health: NodeHealth::default(),
+            },
+            messages: vec![ConnectorMsgOut::ProposedTxsToNotarize(0)],
+        }
+    }
+
+    fn server_config(max_pending: usize) -> ServerConfig {
+        ServerConfig {
+            listen_addr: ListenAddr::Tcp(SocketAddr::from(([127, 0, 0, 1], 0))),
+            auth_token: TOKEN.to_vec(),
+            auth_timeout: Duration::from_secs(1),
+            max_pending_notarization_requests: max_pending,
+            notarization_request_timeout: Duration::from_secs(60),
+        }
+    }
+
+    async fn spawn_server(max_pending: usize) -> (ListenAddr, mpsc::Sender<Resp>, mpsc::Receiver<Req>) {
+        let server = VaultServer::bind(server_config(max_pending)).await.unwrap();
+        let addr = server.local_addr().unwrap();
+        let (resp_tx, resp_rx) = mpsc::channel(10);
+        let (req_tx, req_rx) = mpsc::channel(10);
+        tokio::spawn(server.run(resp_rx, req_tx));
+        (addr, resp_tx, req_rx)
+    }
+
+    #[tokio::test]
+    async fn reject_zero_pending_notarization_requests() {
+        let err = VaultServer::bind(server_config(0)).await.err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[tokio::test]
+    async fn reject_wrong_token() {
+        let (addr, _resp_tx, _req_rx) = spawn_server(1).await;
+        assert!(matches!(
+            VaultClient::connect(&addr, b"wrong".to_vec()).await,
+            Err(ServerError::AuthFailed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn forward_requests_and_responses() {
+        let (addr, resp_tx, mut req_rx) = spawn_server(1).await;
+        let mut client = VaultClient::connect(&addr, TOKEN.to_vec()).await.unwrap();
+        assert!(client.hello::<Vec<u8>, u64, u64, u64>().await.unwrap().is_ok());
+
+        client.send(&Req::SyncFrom(Some(progress_point()))).await.unwrap();
+        assert!(matches!(
+            req_rx.recv().await,
+            Some(ConnectorRequest::SyncFrom(Some(_)))
+        ));
+
+        resp_tx.send(proposal()).await.unwrap();
+        assert!(matches!(
+            client.recv::<Vec<u8>, u64, u64, u64>().await,
+            Ok(IpcResponse::Response(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn hold_back_notarization_requests_until_served() {
+        let (addr, resp_tx, mut req_rx) = spawn_server(1).await;
+        let mut client = VaultClient::connect(&addr, TOKEN.to_vec()).await.unwrap();
+        assert!(client.hello::<Vec<u8>, u64, u64, u64>().await.unwrap().is_ok());
+
+        client.send(&constraints()).await.unwrap();
+        client.send(&constraints()).await.unwrap();
+        assert!(matches!(
+            req_rx.recv().await,
+            Some(ConnectorRequest::RequestTxsToNotarize(_))
+        ));
+        assert!(timeout(Duration::from_millis(100), req_rx.recv()).await.is_err());
+
+        resp_tx.send(proposal()).await.unwrap();
+        assert!(matches!(
+            req_rx.recv().await,
+            Some(ConnectorRequest::RequestTxsToNotarize(_))
+        ));
+    }
+}
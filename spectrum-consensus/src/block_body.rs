@@ -0,0 +1,101 @@
+use std::collections::HashSet;
+
+use spectrum_ledger::block::BlockBody;
+use spectrum_ledger::transaction::Transaction;
+use spectrum_ledger::SystemDigest;
+use spectrum_validation::rules::ConsensusRuleSet;
+use spectrum_validation::validation::{AsInvalidModifier, Validation, ValidationState};
+use spectrum_view::history::LedgerHistoryReadSync;
+use spectrum_view::state::eval::{ProgrammableTxEvaluator, TxEvaluator};
+use spectrum_view::state::linking::{LedgerTxLinker, TxLinker};
+use spectrum_view::state::Cells;
+
+use crate::rules::*;
+
+pub fn validate_block_body<H, S, RS>(
+    body: BlockBody,
+    history: &H,
+    state: &S,
+    rules: &RS,
+) -> Validation<BlockBody, (), ()>
+where
+    H: LedgerHistoryReadSync,
+    S: Cells,
+    RS: ConsensusRuleSet,
+{
+    Validation::new(body).and_then(|body, _| {
+        let body_root = body.digest();
+        ValidationState::unwrap(
+            BODY_HEADER_LINK,
+            rules,
+            || history.get_header_by_body_root(&body_root),
+            || body.as_invalid(format!("Header committing to body {} not found", body_root)),
+        )
+        .discard()
+        .flat_tap(|_| {
+            ValidationState::assert_static(
+                BODY_WITNESSES,
+                rules,
+                || body.witnesses.len() == body.txs.len(),
+                || {
+                    body.as_invalid(format!(
+                        "Body holds {} transactions, but {} witnesses",
+                        body.txs.len(),
+                        body.witnesses.len()
+                    ))
+                },
+            )
+        })
+        .flat_tap(|_| {
+            let mut consumed = HashSet::new();
+            let double_spent = body
+                .txs
+                .iter()
+                .flat_map(|tx| tx.inputs.clone())
+                .map(|(ptr, _)| ptr.cell_id())
+                .find(|cell| !consumed.insert(*cell));
+            ValidationState::assert_static(
+                BODY_DOUBLE_SPEND,
+                rules,
+                || double_spent.is_none(),
+                || body.as_invalid(format!("Cell {:?} is consumed twice", double_spent)),
+            )
+        })
+        .and_then(|_| validate_transactions(body, state, rules))
+    })
+}
+
+/// Transactions are linked against the state preceding the block,
+/// so they can't spend outputs of each other.
+fn validate_transactions<S, RS>(body: &BlockBody, state: &S, rules: &RS) -> ValidationState<(), ()>
+where
+    S: Cells,
+    RS: ConsensusRuleSet,
+{
+    let linker = LedgerTxLinker { pool: state };
+    let evaluator = ProgrammableTxEvaluator { pool: state };
+    for (ix, (tx, witness)) in body.txs.iter().zip(&body.witnesses).enumerate() {
+        let tx = Transaction {
+            body: tx.clone(),
+            witness: witness.clone(),
+        };
+        let linked_tx = match linker.link_transaction(tx) {
+            Ok(linked_tx) => linked_tx,
+            Err(err) => {
+                return ValidationState::fail(
+                    TX_LINKED,
+                    rules,
+                    body.as_invalid(format!("Transaction #{} cannot be linked: {:?}", ix, err)),
+                )
+            }
+        };
+        if let Err(err) = evaluator.evaluate_transaction(linked_tx) {
+            return ValidationState::fail(
+                TX_EVALUATED,
+                rules,
+                body.as_invalid(format!("Transaction #{} is invalid: {:?}", ix, err)),
+            );
+        }
+    }
+    ValidationState::ok()
+}
@@ -7,26 +7,29 @@ use spectrum_view::state::{ConsensusIndexes, StakeDistribution, ValidatorCredent
 use spectrum_vrf::lottery::{lottery_threshold, proof_to_random_number};
 
 use crate::constants::EPOCH_MEMBERSHIP_SALT;
+use crate::leader::LeaderEligibility;
 use crate::protocol_params::ProtocolParams;
 use crate::rules::*;
 
-pub fn validate_block_header<H, S, RS, PP>(
+pub fn validate_block_header<H, S, RS, PP, LE>(
     hdr: BlockHeader,
     history: &H,
     state: &S,
     rules: &RS,
     protocol: &PP,
+    leader: &LE,
 ) -> Validation<BlockHeader, (), ()>
 where
     H: LedgerHistoryReadSync,
     S: ConsensusIndexes + StakeDistribution + ValidatorCredentials,
     RS: ConsensusRuleSet,
     PP: ProtocolParams,
+    LE: LeaderEligibility,
 {
     Validation::new(hdr).and_then(|hdr, _| {
         let prev_id = hdr.body.prev_id;
         if let Some(parent_hdr) = history.get_header(&prev_id) {
-            validate_child_block_header(hdr, parent_hdr, history, state, rules, protocol)
+            validate_child_block_header(hdr, parent_hdr, history, state, rules, protocol, leader)
         } else {
             ValidationState::fail(
                 HEADER_PARENT_LINK,
@@ -37,19 +40,21 @@ where
     })
 }
 
-fn validate_child_block_header<H, S, RS, PP>(
+fn validate_child_block_header<H, S, RS, PP, LE>(
     hdr: &BlockHeader,
     parent_hdr: BlockHeader,
     history: &H,
     state: &S,
     rules: &RS,
     protocol: &PP,
+    leader: &LE,
 ) -> ValidationState<(), ()>
 where
     H: LedgerHistoryReadSync,
     S: ConsensusIndexes + StakeDistribution + ValidatorCredentials,
     RS: ConsensusRuleSet,
     PP: ProtocolParams,
+    LE: LeaderEligibility,
 {
     ValidationState::new(hdr)
         .assert(
@@ -99,6 +104,14 @@ where
                     ))
                 },
             )
+            .flat_tap(|epoch_rand_proof| {
+                ValidationState::assert_static(
+                    HEADER_VRF,
+                    rules,
+                    || leader.verify_vrf(&hdr.body, epoch_rand_proof),
+                    || hdr.as_invalid(format!("Invalid VRF proof")),
+                )
+            })
             .and_then(|epoch_rand_proof| {
                 let vrf_range = protocol.base_vrf_range();
                 let consensus_selection_frac = protocol.consensus_selection_frac();
@@ -121,6 +134,14 @@ where
                     || epoch_seed < epoch_threshold,
                     || hdr.as_invalid(format!("Author not a member")),
                 )
+                .flat_tap(|_| {
+                    ValidationState::assert_static(
+                        HEADER_VALIDATOR_LEADER,
+                        rules,
+                        || leader.is_slot_leader(&hdr.body, spo_stake, total_stake),
+                        || hdr.as_invalid(format!("Author not a leader of slot {}", hdr.body.slot_num)),
+                    )
+                })
             })
         })
         .discard()
@@ -0,0 +1,12 @@
+use spectrum_ledger::block::HeaderBody;
+use spectrum_ledger::cell::NativeCoin;
+use spectrum_ledger::VRFProof;
+
+/// Checks of the right of a validator to produce a block in a particular slot.
+pub trait LeaderEligibility {
+    /// Verify the VRF proof of the header against the key of its author and the seed of the epoch.
+    fn verify_vrf(&self, hdr: &HeaderBody, epoch_rand_proof: &VRFProof) -> bool;
+    /// Check that the VRF output of the header wins the lottery of its slot
+    /// given the stake managed by the author.
+    fn is_slot_leader(&self, hdr: &HeaderBody, stake: NativeCoin, total_stake: NativeCoin) -> bool;
+}
@@ -1,4 +1,6 @@
+pub mod block_body;
 pub mod block_header;
 mod constants;
+pub mod leader;
 pub mod protocol_params;
 pub mod rules;
@@ -4,11 +4,22 @@ use spectrum_validation::rules::{RuleId, TermRuleId};
 pub const HEADER_PARENT_LINK: TermRuleId = RuleId::from_u16(0);
 pub const HEADER_NON_DESC_SLOT: TermRuleId = RuleId::from_u16(1);
 pub const HEADER_PARENT_SLOT_DELTA: TermRuleId = RuleId::from_u16(2);
-pub const HEADER_VALIDATOR_CREDS: TermRuleId = RuleId::from_u16(2);
-pub const HEADER_VALIDATOR_MEMBER: TermRuleId = RuleId::from_u16(2);
-pub const HEADER_VALIDATOR_LEADER: TermRuleId = RuleId::from_u16(2);
-pub const HEADER_EPOCH_SEED: TermRuleId = RuleId::from_u16(3);
+pub const HEADER_VALIDATOR_CREDS: TermRuleId = RuleId::from_u16(3);
+pub const HEADER_VALIDATOR_MEMBER: TermRuleId = RuleId::from_u16(4);
+/// Author of the header won the lottery of its slot.
+pub const HEADER_VALIDATOR_LEADER: TermRuleId = RuleId::from_u16(5);
+pub const HEADER_EPOCH_SEED: TermRuleId = RuleId::from_u16(6);
 /// SPO's credentials are verified.
-pub const HEADER_SPO_VERIFIED: TermRuleId = RuleId::from_u16(1);
+pub const HEADER_SPO_VERIFIED: TermRuleId = RuleId::from_u16(7);
 /// Header's VRF is valid against SPO key.
-pub const HEADER_VRF: TermRuleId = RuleId::from_u16(2);
+pub const HEADER_VRF: TermRuleId = RuleId::from_u16(8);
+/// Body is committed to by a known header.
+pub const BODY_HEADER_LINK: TermRuleId = RuleId::from_u16(9);
+/// Every transaction in the body is accompanied by a witness.
+pub const BODY_WITNESSES: TermRuleId = RuleId::from_u16(10);
+/// No cell is consumed twice within the body.
+pub const BODY_DOUBLE_SPEND: TermRuleId = RuleId::from_u16(11);
+/// Inputs, scripts and data referenced by the transaction are resolved.
+pub const TX_LINKED: TermRuleId = RuleId::from_u16(12);
+/// Signatures and script invocations of the transaction are valid.
+pub const TX_EVALUATED: TermRuleId = RuleId::from_u16(13);
@@ -0,0 +1,232 @@
+//! BLS signatures over BLS12-381 as used by the Ethereum consensus layer: public keys in G1,
+//! signatures in G2, proofs of possession guarding aggregates of keys against rogue-key attacks.
+
+use std::hash::{Hash, Hasher};
+
+use blst::min_pk;
+use blst::BLST_ERROR;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::VerifiableAgainst;
+
+/// Domain separation tag of signatures.
+pub const DST: &[u8] = b"BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+/// Domain separation tag of proofs of possession.
+pub const POP_DST: &[u8] = b"BLS_POP_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+
+#[derive(Debug, thiserror::Error)]
+pub enum BlsError {
+    #[error("Invalid secret key")]
+    InvalidSecretKey,
+    #[error("Invalid public key")]
+    InvalidPublicKey,
+    #[error("Invalid signature")]
+    InvalidSignature,
+    #[error("Nothing to aggregate")]
+    EmptyAggregate,
+}
+
+#[derive(Clone)]
+pub struct SecretKey(min_pk::SecretKey);
+
+impl SecretKey {
+    pub fn random() -> Self {
+        let mut ikm = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut ikm);
+        Self::from_ikm(&ikm).unwrap()
+    }
+
+    /// Derive the key from at least 32 bytes of input key material.
+    pub fn from_ikm(ikm: &[u8]) -> Result<Self, BlsError> {
+        min_pk::SecretKey::key_gen(ikm, &[])
+            .map(Self)
+            .map_err(|_| BlsError::InvalidSecretKey)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BlsError> {
+        min_pk::SecretKey::from_bytes(bytes)
+            .map(Self)
+            .map_err(|_| BlsError::InvalidSecretKey)
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey(self.0.sk_to_pk())
+    }
+
+    pub fn sign(&self, msg: &[u8]) -> Signature {
+        Signature(self.0.sign(msg, DST, &[]))
+    }
+
+    /// Proof of possession of the key, required before the public key is aggregated with others.
+    pub fn prove_possession(&self) -> Signature {
+        Signature(self.0.sign(&self.public_key().to_bytes(), POP_DST, &[]))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<u8>", into = "Vec<u8>")]
+pub struct PublicKey(min_pk::PublicKey);
+
+impl PublicKey {
+    /// Compressed G1 point.
+    pub fn to_bytes(&self) -> [u8; 48] {
+        self.0.compress()
+    }
+
+    /// Keys must be verified with [`PublicKey::verify_possession`] before they are aggregated.
+    pub fn aggregate(pks: &[PublicKey]) -> Result<PublicKey, BlsError> {
+        let pks = pks.iter().map(|pk| &pk.0).collect::<Vec<_>>();
+        min_pk::AggregatePublicKey::aggregate(&pks, false)
+            .map(|aggr| PublicKey(aggr.to_public_key()))
+            .map_err(|_| BlsError::EmptyAggregate)
+    }
+
+    pub fn verify_possession(&self, proof: &Signature) -> bool {
+        proof
+            .0
+            .verify(true, &self.to_bytes(), POP_DST, &[], &self.0, false)
+            == BLST_ERROR::BLST_SUCCESS
+    }
+}
+
+impl Hash for PublicKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write(&self.to_bytes())
+    }
+}
+
+impl TryFrom<Vec<u8>> for PublicKey {
+    type Error = BlsError;
+    fn try_from(bytes: Vec<u8>) -> Result<Self, Self::Error> {
+        // Infinity and points outside of the subgroup are rejected.
+        min_pk::PublicKey::key_validate(&bytes)
+            .map(Self)
+            .map_err(|_| BlsError::InvalidPublicKey)
+    }
+}
+
+impl From<PublicKey> for Vec<u8> {
+    fn from(pk: PublicKey) -> Self {
+        pk.to_bytes().to_vec()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<u8>", into = "Vec<u8>")]
+pub struct Signature(min_pk::Signature);
+
+impl Signature {
+    /// Compressed G2 point.
+    pub fn to_bytes(&self) -> [u8; 96] {
+        self.0.compress()
+    }
+
+    pub fn aggregate(sigs: &[Signature]) -> Result<Signature, BlsError> {
+        let sigs = sigs.iter().map(|sig| &sig.0).collect::<Vec<_>>();
+        min_pk::AggregateSignature::aggregate(&sigs, false)
+            .map(|aggr| Signature(aggr.to_signature()))
+            .map_err(|_| BlsError::EmptyAggregate)
+    }
+
+    pub fn verify(&self, msg: &[u8], pk: &PublicKey) -> bool {
+        self.0.verify(true, msg, DST, &[], &pk.0, true) == BLST_ERROR::BLST_SUCCESS
+    }
+
+    /// Verify aggregate signature of the same message by all of `pks`.
+    /// Possession of each key must be verified beforehand.
+    pub fn fast_aggregate_verify(&self, msg: &[u8], pks: &[PublicKey]) -> bool {
+        let pks = pks.iter().map(|pk| &pk.0).collect::<Vec<_>>();
+        self.0.fast_aggregate_verify(true, msg, DST, &pks) == BLST_ERROR::BLST_SUCCESS
+    }
+
+    /// Verify aggregate signature of distinct messages, `msgs[i]` signed by `pks[i]`.
+    pub fn aggregate_verify(&self, msgs: &[&[u8]], pks: &[PublicKey]) -> bool {
+        let pks = pks.iter().map(|pk| &pk.0).collect::<Vec<_>>();
+        self.0.aggregate_verify(true, msgs, DST, &pks, true) == BLST_ERROR::BLST_SUCCESS
+    }
+}
+
+impl TryFrom<Vec<u8>> for Signature {
+    type Error = BlsError;
+    fn try_from(bytes: Vec<u8>) -> Result<Self, Self::Error> {
+        min_pk::Signature::sig_validate(&bytes, false)
+            .map(Self)
+            .map_err(|_| BlsError::InvalidSignature)
+    }
+}
+
+impl From<Signature> for Vec<u8> {
+    fn from(sig: Signature) -> Self {
+        sig.to_bytes().to_vec()
+    }
+}
+
+/// Message signed by a group of keys.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AggregateCertificate {
+    pub message: Vec<u8>,
+    pub signature: Signature,
+}
+
+/// Verified against public keys of the signers, whose possession was proven.
+impl VerifiableAgainst<Vec<PublicKey>> for AggregateCertificate {
+    fn verify(&self, signers: &Vec<PublicKey>) -> bool {
+        self.signature.fast_aggregate_verify(&self.message, signers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bls::{AggregateCertificate, PublicKey, SecretKey, Signature};
+    use crate::VerifiableAgainst;
+
+    #[test]
+    fn sign_verify() {
+        let sk = SecretKey::random();
+        let pk = sk.public_key();
+        let sig = sk.sign(b"message");
+        assert!(sig.verify(b"message", &pk));
+        assert!(!sig.verify(b"another message", &pk));
+        assert!(!sig.verify(b"message", &SecretKey::random().public_key()));
+        let sk_restored = SecretKey::from_bytes(&sk.to_bytes()).unwrap();
+        assert_eq!(sk_restored.public_key(), pk);
+        let encoded = bincode::serialize(&(pk, sig)).unwrap();
+        assert_eq!(
+            bincode::deserialize::<(PublicKey, Signature)>(&encoded).unwrap(),
+            (pk, sig)
+        );
+    }
+
+    #[test]
+    fn aggregate_certificate() {
+        let msg = b"report".to_vec();
+        let sks = (0..4).map(|_| SecretKey::random()).collect::<Vec<_>>();
+        let pks = sks.iter().map(SecretKey::public_key).collect::<Vec<_>>();
+        for sk in &sks {
+            assert!(sk.public_key().verify_possession(&sk.prove_possession()));
+            // Signature of the message doesn't prove possession.
+            assert!(!sk
+                .public_key()
+                .verify_possession(&sk.sign(&sk.public_key().to_bytes())));
+        }
+        let sigs = sks.iter().map(|sk| sk.sign(&msg)).collect::<Vec<_>>();
+        let cert = AggregateCertificate {
+            message: msg.clone(),
+            signature: Signature::aggregate(&sigs).unwrap(),
+        };
+        assert!(cert.verify(&pks));
+        assert!(cert.signature.verify(&msg, &PublicKey::aggregate(&pks).unwrap()));
+        assert!(!cert.verify(&pks[1..].to_vec()));
+
+        let msgs = [b"a".as_slice(), b"b".as_slice()];
+        let sig = Signature::aggregate(&[sks[0].sign(msgs[0]), sks[1].sign(msgs[1])]).unwrap();
+        assert!(sig.aggregate_verify(&msgs, &pks[..2]));
+        assert!(!sig.aggregate_verify(&[msgs[1], msgs[0]], &pks[..2]));
+        assert!(Signature::aggregate(&[]).is_err());
+    }
+}
@@ -0,0 +1,460 @@
+//! Keys of the node kept encrypted at rest.
+//!
+//! Every key is stored in its own file `<name>.json` within the keystore directory, encrypted with
+//! XChaCha20-Poly1305 under a key derived from the passphrase with scrypt. Rotated keys are retired
+//! to `<name>.retired-<n>.json` rather than deleted, so that old signatures can still be attributed.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use elliptic_curve::rand_core::{OsRng, RngCore};
+use elliptic_curve::sec1::ToEncodedPoint;
+use k256::SecretKey;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::pubkey::PublicKey;
+use crate::signer::InMemorySigner;
+
+/// Version of the format of key files.
+pub const KEY_FILE_VERSION: u8 = 1;
+const SALT_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+const RETIRED_INFIX: &str = ".retired-";
+const KEY_FILE_EXT: &str = "json";
+/// Extension of a key generated during rotation which isn't active yet.
+const NEXT_KEY_FILE_EXT: &str = "next";
+
+#[derive(Debug, thiserror::Error)]
+pub enum KeystoreError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Malformed key file {0}: {1}")]
+    Malformed(String, String),
+    #[error("Wrong passphrase or corrupted key file {0}")]
+    Decryption(String),
+    #[error("Unsupported version {1} of key file {0}")]
+    UnsupportedVersion(String, u8),
+    #[error("No key named {0}")]
+    NotFound(String),
+    #[error("Key {0} already exists")]
+    AlreadyExists(String),
+    #[error("Invalid key name {0:?}, only alphanumerics, '-' and '_' are allowed")]
+    InvalidName(String),
+    #[error("Invalid KDF params")]
+    InvalidKdfParams,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
+pub enum KeyPurpose {
+    /// Identity of the node in the P2P network.
+    NodeIdentity,
+    /// Key of a committee member.
+    Committee,
+}
+
+impl KeyPurpose {
+    fn tag(&self) -> u8 {
+        match self {
+            KeyPurpose::NodeIdentity => 0,
+            KeyPurpose::Committee => 1,
+        }
+    }
+}
+
+/// Parameters of scrypt.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
+pub struct KdfParams {
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            log_n: 15,
+            r: 8,
+            p: 1,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct KeyFile {
+    version: u8,
+    purpose: KeyPurpose,
+    public_key: PublicKey,
+    kdf: KdfParams,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct KeyInfo {
+    pub name: String,
+    pub purpose: KeyPurpose,
+    pub public_key: PublicKey,
+    /// Key was replaced during rotation.
+    pub retired: bool,
+}
+
+pub struct Keystore {
+    dir: PathBuf,
+    passphrase: Zeroizing<Vec<u8>>,
+    kdf: KdfParams,
+}
+
+impl Keystore {
+    /// Open the keystore in the given directory, which is created if missing.
+    pub fn open<P: AsRef<Path>>(dir: P, passphrase: &str) -> Result<Self, KeystoreError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+        }
+        Ok(Self {
+            dir,
+            passphrase: Zeroizing::new(passphrase.as_bytes().to_vec()),
+            kdf: KdfParams::default(),
+        })
+    }
+
+    /// KDF params used for keys written from now on.
+    pub fn with_kdf_params(self, kdf: KdfParams) -> Self {
+        Self { kdf, ..self }
+    }
+
+    pub fn generate(&self, name: &str, purpose: KeyPurpose) -> Result<PublicKey, KeystoreError> {
+        self.import(name, purpose, &SecretKey::random(&mut OsRng))
+    }
+
+    pub fn import(
+        &self,
+        name: &str,
+        purpose: KeyPurpose,
+        sk: &SecretKey,
+    ) -> Result<PublicKey, KeystoreError> {
+        let path = self.active_path(name)?;
+        if path.exists() {
+            return Err(KeystoreError::AlreadyExists(name.to_string()));
+        }
+        self.write(&path, purpose, sk)
+    }
+
+    /// Decrypted active key with the given name.
+    pub fn export(&self, name: &str) -> Result<SecretKey, KeystoreError> {
+        let path = self.active_path(name)?;
+        if !path.exists() {
+            return Err(KeystoreError::NotFound(name.to_string()));
+        }
+        self.read(&path).map(|(_, sk)| sk)
+    }
+
+    /// Active key with the given name, a fresh one is generated if there is none yet.
+    pub fn get_or_generate(&self, name: &str, purpose: KeyPurpose) -> Result<SecretKey, KeystoreError> {
+        match self.export(name) {
+            Err(KeystoreError::NotFound(_)) => {
+                self.generate(name, purpose)?;
+                self.export(name)
+            }
+            res => res,
+        }
+    }
+
+    /// Replace the active key with a fresh one. The old key is retired.
+    /// The active key is left intact if the new one can't be generated.
+    pub fn rotate(&self, name: &str) -> Result<PublicKey, KeystoreError> {
+        let path = self.active_path(name)?;
+        if !path.exists() {
+            return Err(KeystoreError::NotFound(name.to_string()));
+        }
+        // Make sure the key is readable with our passphrase before retiring it.
+        let (file, _) = self.read(&path)?;
+        let num_retired = self
+            .list()?
+            .iter()
+            .filter(|k| k.name == name && k.retired)
+            .count();
+        let retired_path = self.dir.join(format!(
+            "{}{}{}.{}",
+            name, RETIRED_INFIX, num_retired, KEY_FILE_EXT
+        ));
+        let next_path = path.with_extension(NEXT_KEY_FILE_EXT);
+        let public_key = self.write(&next_path, file.purpose, &SecretKey::random(&mut OsRng))?;
+        fs::rename(&path, &retired_path)?;
+        if let Err(err) = fs::rename(&next_path, &path) {
+            fs::rename(&retired_path, &path)?;
+            return Err(err.into());
+        }
+        Ok(public_key)
+    }
+
+    /// All keys in the keystore, retired ones included. Keys aren't decrypted.
+    pub fn list(&self) -> Result<Vec<KeyInfo>, KeystoreError> {
+        let mut keys = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(KEY_FILE_EXT) {
+                continue;
+            }
+            let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+            let (name, retired) = match stem.split_once(RETIRED_INFIX) {
+                Some((name, _)) => (name.to_string(), true),
+                None => (stem.to_string(), false),
+            };
+            let file = read_key_file(&path)?;
+            keys.push(KeyInfo {
+                name,
+                purpose: file.purpose,
+                public_key: file.public_key,
+                retired,
+            });
+        }
+        keys.sort_by(|a, b| (&a.name, a.retired).cmp(&(&b.name, b.retired)));
+        Ok(keys)
+    }
+
+    /// Re-encrypt all keys under the new passphrase.
+    pub fn change_passphrase(&mut self, new_passphrase: &str) -> Result<(), KeystoreError> {
+        let mut keys = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) == Some(KEY_FILE_EXT) {
+                let (file, sk) = self.read(&path)?;
+                keys.push((path, file.purpose, sk));
+            }
+        }
+        self.passphrase = Zeroizing::new(new_passphrase.as_bytes().to_vec());
+        for (path, purpose, sk) in keys {
+            self.write(&path, purpose, &sk)?;
+        }
+        Ok(())
+    }
+
+    /// Signer for sigma aggregation holding the active key with the given name.
+    pub fn signer(&self, name: &str) -> Result<InMemorySigner, KeystoreError> {
+        self.export(name).map(InMemorySigner::new)
+    }
+
+    fn active_path(&self, name: &str) -> Result<PathBuf, KeystoreError> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(KeystoreError::InvalidName(name.to_string()));
+        }
+        Ok(self.dir.join(format!("{}.{}", name, KEY_FILE_EXT)))
+    }
+
+    fn write(&self, path: &Path, purpose: KeyPurpose, sk: &SecretKey) -> Result<PublicKey, KeystoreError> {
+        let public_key = PublicKey::from(sk.clone());
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        let cipher = self.cipher(&self.kdf, &salt)?;
+        let plaintext = Zeroizing::new(sk.to_bytes().to_vec());
+        let ciphertext = cipher
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: &plaintext,
+                    aad: &associated_data(purpose, &public_key),
+                },
+            )
+            .expect("Encryption never fails");
+        let file = KeyFile {
+            version: KEY_FILE_VERSION,
+            purpose,
+            public_key,
+            kdf: self.kdf,
+            salt: base16::encode_lower(&salt),
+            nonce: base16::encode_lower(&nonce),
+            ciphertext: base16::encode_lower(&ciphertext),
+        };
+        // Written to a temporary file first, so that the key is never left half-written.
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut opts = fs::OpenOptions::new();
+            opts.write(true).create(true).truncate(true);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::OpenOptionsExt;
+                opts.mode(0o600);
+            }
+            let mut tmp = opts.open(&tmp_path)?;
+            tmp.write_all(&serde_json::to_vec_pretty(&file).unwrap())?;
+            tmp.sync_all()?;
+        }
+        fs::rename(tmp_path, path)?;
+        Ok(public_key)
+    }
+
+    fn read(&self, path: &Path) -> Result<(KeyFile, SecretKey), KeystoreError> {
+        let file = read_key_file(path)?;
+        let malformed = |err: &str| KeystoreError::Malformed(path.display().to_string(), err.to_string());
+        let salt = base16::decode(&file.salt).map_err(|_| malformed("invalid salt"))?;
+        let nonce = base16::decode(&file.nonce).map_err(|_| malformed("invalid nonce"))?;
+        let ciphertext = base16::decode(&file.ciphertext).map_err(|_| malformed("invalid ciphertext"))?;
+        if nonce.len() != NONCE_LEN {
+            return Err(malformed("invalid nonce"));
+        }
+        let plaintext = Zeroizing::new(
+            self.cipher(&file.kdf, &salt)?
+                .decrypt(
+                    XNonce::from_slice(&nonce),
+                    Payload {
+                        msg: &ciphertext,
+                        aad: &associated_data(file.purpose, &file.public_key),
+                    },
+                )
+                .map_err(|_| KeystoreError::Decryption(path.display().to_string()))?,
+        );
+        let sk = SecretKey::from_slice(&plaintext).map_err(|_| malformed("invalid secret key"))?;
+        if PublicKey::from(sk.clone()) != file.public_key {
+            return Err(malformed("public key doesn't match the secret one"));
+        }
+        Ok((file, sk))
+    }
+
+    fn cipher(&self, kdf: &KdfParams, salt: &[u8]) -> Result<XChaCha20Poly1305, KeystoreError> {
+        let params =
+            scrypt::Params::new(kdf.log_n, kdf.r, kdf.p, 32).map_err(|_| KeystoreError::InvalidKdfParams)?;
+        let mut key = Zeroizing::new([0u8; 32]);
+        scrypt::scrypt(&self.passphrase, salt, &params, key.as_mut()).expect("Key length is valid");
+        Ok(XChaCha20Poly1305::new(key.as_ref().into()))
+    }
+}
+
+/// Identity of the node in the P2P network derived from the given key.
+pub fn libp2p_keypair(sk: &SecretKey) -> libp2p_identity::Keypair {
+    let sk = libp2p_identity::secp256k1::SecretKey::try_from_bytes(sk.to_bytes().as_mut_slice())
+        .expect("Valid secp256k1 key");
+    libp2p_identity::Keypair::from(libp2p_identity::secp256k1::Keypair::from(sk))
+}
+
+fn read_key_file(path: &Path) -> Result<KeyFile, KeystoreError> {
+    let file: KeyFile = serde_json::from_slice(&fs::read(path)?)
+        .map_err(|err| KeystoreError::Malformed(path.display().to_string(), err.to_string()))?;
+    if file.version != KEY_FILE_VERSION {
+        return Err(KeystoreError::UnsupportedVersion(
+            path.display().to_string(),
+            file.version,
+        ));
+    }
+    Ok(file)
+}
+
+/// Metadata of the key is authenticated along with the key itself.
+fn associated_data(purpose: KeyPurpose, public_key: &PublicKey) -> Vec<u8> {
+    let mut aad = vec![purpose.tag()];
+    aad.extend_from_slice(
+        <&k256::PublicKey>::from(public_key)
+            .to_encoded_point(true)
+            .as_bytes(),
+    );
+    aad
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use elliptic_curve::rand_core::OsRng;
+    use k256::SecretKey;
+
+    use crate::keystore::{KdfParams, KeyPurpose, Keystore, KeystoreError};
+    use crate::pubkey::PublicKey;
+
+    /// Cheap KDF, so that tests run fast.
+    const TEST_KDF: KdfParams = KdfParams { log_n: 4, r: 8, p: 1 };
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("spectrum-keystore-{}", rand::random::<u64>()))
+    }
+
+    #[test]
+    fn keys_stored_encrypted_and_restored() {
+        let dir = temp_dir();
+        let ks = Keystore::open(&dir, "passphrase")
+            .unwrap()
+            .with_kdf_params(TEST_KDF);
+        let sk = SecretKey::random(&mut OsRng);
+        let pk = ks.import("committee", KeyPurpose::Committee, &sk).unwrap();
+        assert_eq!(pk, PublicKey::from(sk.clone()));
+        assert!(matches!(
+            ks.import("committee", KeyPurpose::Committee, &sk),
+            Err(KeystoreError::AlreadyExists(_))
+        ));
+        let raw = std::fs::read_to_string(dir.join("committee.json")).unwrap();
+        assert!(!raw.contains(&base16::encode_lower(&sk.to_bytes())));
+
+        let reopened = Keystore::open(&dir, "passphrase").unwrap();
+        assert_eq!(reopened.export("committee").unwrap(), sk);
+        let wrong = Keystore::open(&dir, "wrong").unwrap();
+        assert!(matches!(
+            wrong.export("committee"),
+            Err(KeystoreError::Decryption(_))
+        ));
+        assert!(matches!(reopened.export("node"), Err(KeystoreError::NotFound(_))));
+        assert!(matches!(
+            reopened.export("../committee"),
+            Err(KeystoreError::InvalidName(_))
+        ));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rotated_keys_retired() {
+        let dir = temp_dir();
+        let mut ks = Keystore::open(&dir, "passphrase")
+            .unwrap()
+            .with_kdf_params(TEST_KDF);
+        let identity = ks.get_or_generate("node", KeyPurpose::NodeIdentity).unwrap();
+        assert_eq!(
+            ks.get_or_generate("node", KeyPurpose::NodeIdentity).unwrap(),
+            identity
+        );
+        let pk0 = ks.generate("committee", KeyPurpose::Committee).unwrap();
+        let pk1 = ks.rotate("committee").unwrap();
+        let pk2 = ks.rotate("committee").unwrap();
+        assert_ne!(pk0, pk1);
+        assert_eq!(PublicKey::from(ks.export("committee").unwrap()), pk2);
+        let committee_keys = ks
+            .list()
+            .unwrap()
+            .into_iter()
+            .filter(|k| k.name == "committee")
+            .map(|k| (k.public_key, k.retired))
+            .collect::<Vec<_>>();
+        assert_eq!(committee_keys.len(), 3);
+        assert!(committee_keys.contains(&(pk0, true)));
+        assert!(committee_keys.contains(&(pk1, true)));
+        assert!(committee_keys.contains(&(pk2, false)));
+
+        // Failed rotation keeps the active key.
+        let broken = Keystore::open(&dir, "passphrase")
+            .unwrap()
+            .with_kdf_params(KdfParams { log_n: 4, r: 0, p: 1 });
+        assert!(matches!(
+            broken.rotate("committee"),
+            Err(KeystoreError::InvalidKdfParams)
+        ));
+        assert_eq!(PublicKey::from(ks.export("committee").unwrap()), pk2);
+        assert_eq!(ks.list().unwrap().len(), 4);
+
+        ks.change_passphrase("new passphrase").unwrap();
+        let reopened = Keystore::open(&dir, "new passphrase").unwrap();
+        assert_eq!(PublicKey::from(reopened.export("committee").unwrap()), pk2);
+        assert_eq!(reopened.export("node").unwrap(), identity);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
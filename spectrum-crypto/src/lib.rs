@@ -1,8 +1,12 @@
 use async_trait::async_trait;
 
+pub mod bls;
 pub mod digest;
+pub mod keystore;
+pub mod merkle;
 pub mod pubkey;
 pub mod signature;
+pub mod signer;
 
 /// Some statement which can be verified against public data `P`.
 pub trait VerifiableAgainst<P> {
@@ -0,0 +1,154 @@
+//! Binary Merkle tree over Blake2b256 digests.
+//!
+//! Leaves and inner nodes are hashed with distinct prefixes, so that an inner node can't be
+//! passed off as a leaf. A node left without a sibling is carried over to the next level as is.
+
+use serde::{Deserialize, Serialize};
+
+use crate::digest::{blake2b256_hash, Blake2bDigest256};
+
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+/// Hash of the leaf holding the given data.
+pub fn leaf_hash(data: &[u8]) -> Blake2bDigest256 {
+    let mut bytes = Vec::with_capacity(data.len() + 1);
+    bytes.push(LEAF_PREFIX);
+    bytes.extend_from_slice(data);
+    blake2b256_hash(&bytes)
+}
+
+fn node_hash(left: &Blake2bDigest256, right: &Blake2bDigest256) -> Blake2bDigest256 {
+    let mut bytes = Vec::with_capacity(65);
+    bytes.push(NODE_PREFIX);
+    bytes.extend_from_slice(left.as_ref());
+    bytes.extend_from_slice(right.as_ref());
+    blake2b256_hash(&bytes)
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ProofNode {
+    pub sibling: Blake2bDigest256,
+    /// Side the sibling is on.
+    pub side: Side,
+}
+
+/// Path from a leaf to the root, nodes closest to the leaf first.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MerkleProof {
+    pub path: Vec<ProofNode>,
+}
+
+impl MerkleProof {
+    /// Root of the tree the given leaf hash is included into according to the proof.
+    pub fn root(&self, leaf: Blake2bDigest256) -> Blake2bDigest256 {
+        self.path.iter().fold(leaf, |acc, node| match node.side {
+            Side::Left => node_hash(&node.sibling, &acc),
+            Side::Right => node_hash(&acc, &node.sibling),
+        })
+    }
+
+    pub fn verify(&self, leaf: Blake2bDigest256, root: Blake2bDigest256) -> bool {
+        self.root(leaf) == root
+    }
+}
+
+pub struct MerkleTree {
+    /// Levels of the tree from leaves up to the root.
+    levels: Vec<Vec<Blake2bDigest256>>,
+}
+
+impl MerkleTree {
+    /// Build the tree over the given leaf hashes, see [`leaf_hash`].
+    pub fn new(leaves: Vec<Blake2bDigest256>) -> Self {
+        let mut levels = vec![leaves];
+        while let Some(level) = levels.last().filter(|level| level.len() > 1) {
+            let next = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => node_hash(left, right),
+                    [single] => *single,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(next);
+        }
+        Self { levels }
+    }
+
+    /// `None` if the tree is empty.
+    pub fn root(&self) -> Option<Blake2bDigest256> {
+        self.levels.last().and_then(|level| level.first()).copied()
+    }
+
+    /// Proof of inclusion of the leaf at the given index.
+    pub fn proof(&self, index: usize) -> Option<MerkleProof> {
+        if index >= self.levels[0].len() {
+            return None;
+        }
+        let mut path = Vec::new();
+        let mut ix = index;
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = if ix % 2 == 0 {
+                level.get(ix + 1).map(|sibling| ProofNode {
+                    sibling: *sibling,
+                    side: Side::Right,
+                })
+            } else {
+                Some(ProofNode {
+                    sibling: level[ix - 1],
+                    side: Side::Left,
+                })
+            };
+            path.extend(sibling);
+            ix /= 2;
+        }
+        Some(MerkleProof { path })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::digest::blake2b256_hash;
+    use crate::merkle::{leaf_hash, MerkleTree};
+
+    #[test]
+    fn every_leaf_is_proven_against_root() {
+        for size in 1..=9 {
+            let leaves = (0..size).map(|i: u8| leaf_hash(&[i])).collect::<Vec<_>>();
+            let tree = MerkleTree::new(leaves.clone());
+            let root = tree.root().unwrap();
+            for (ix, leaf) in leaves.into_iter().enumerate() {
+                let proof = tree.proof(ix).unwrap();
+                assert!(proof.verify(leaf, root));
+                assert!(!proof.verify(leaf_hash(b"other"), root));
+            }
+            assert_eq!(tree.proof(size as usize), None);
+        }
+    }
+
+    #[test]
+    fn single_leaf_is_root() {
+        let leaf = leaf_hash(b"report");
+        let tree = MerkleTree::new(vec![leaf]);
+        assert_eq!(tree.root(), Some(leaf));
+        assert!(tree.proof(0).unwrap().path.is_empty());
+        assert_eq!(MerkleTree::new(vec![]).root(), None);
+    }
+
+    #[test]
+    fn inner_node_is_not_a_leaf() {
+        let leaves = vec![leaf_hash(b"a"), leaf_hash(b"b")];
+        let tree = MerkleTree::new(leaves.clone());
+        let mut inner = leaves[0].as_ref().to_vec();
+        inner.extend_from_slice(leaves[1].as_ref());
+        assert_ne!(tree.root(), Some(leaf_hash(&inner)));
+        assert_ne!(tree.root(), Some(blake2b256_hash(&inner)));
+    }
+}
@@ -0,0 +1,700 @@
+//! Custody of long-term keys of committee members.
+//!
+//! Keys are held either in-process ([`InMemorySigner`]) or by a separate process fronting an HSM,
+//! possibly on an isolated machine, reached over a unix or TCP socket ([`RemoteSigner`], [`serve`]).
+//! Secrets of Schnorr commitments never leave the signer, as together with the response they reveal
+//! the key. Calls to a signer may block, so async code goes through [`AsyncSigner`].
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use futures::channel::{mpsc, oneshot};
+use k256::elliptic_curve::rand_core::{OsRng, RngCore};
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use k256::elliptic_curve::PrimeField;
+use k256::schnorr::signature::{Signer as _, Verifier};
+use k256::schnorr::{Signature, SigningKey, VerifyingKey};
+use k256::{Scalar, SecretKey};
+use rand_chacha::rand_core::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::pubkey::PublicKey;
+
+/// Requests and responses are capped, so that a peer can't make us allocate arbitrarily.
+const MAX_FRAME_SIZE: usize = 1 << 16;
+
+/// Commitments which are not answered within this time are forgotten.
+const DEFAULT_COMMITMENT_TTL: Duration = Duration::from_secs(600);
+
+#[derive(Debug, thiserror::Error)]
+pub enum SignerError {
+    #[error("Unknown or already answered commitment")]
+    UnknownCommitment,
+    #[error("Signer is unreachable: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Malformed message")]
+    Malformed,
+    #[error("Remote signer failed: {0}")]
+    Remote(String),
+    #[error("Peer failed to authenticate")]
+    Unauthenticated,
+    #[error("Latency budget of {0:?} exceeded")]
+    Timeout(Duration),
+    #[error("Signer is stopped")]
+    Stopped,
+}
+
+/// Holder of a long-term secp256k1 key `x`.
+pub trait Signer: Send + Sync {
+    fn public_key(&self) -> PublicKey;
+
+    /// Fresh Schnorr commitment `Y = g^y` along with a signature of `msg` under `y`,
+    /// proving knowledge of `y`.
+    fn schnorr_commitment(&self, msg: &[u8]) -> Result<(VerifyingKey, Signature), SignerError>;
+
+    /// Response `z = y + e * x` to the challenge `e` for the commitment `Y = g^y` issued before.
+    /// Every commitment is answered at most once.
+    fn schnorr_response(&self, commitment: &VerifyingKey, e: Scalar) -> Result<Scalar, SignerError>;
+
+    /// BIP-340 signature of `msg` under `x`, e.g. attesting evidence of misbehaviour of other members.
+    fn sign(&self, msg: &[u8]) -> Result<Signature, SignerError>;
+}
+
+/// Key verifying [`Signer::sign`] signatures of the holder of `pk`.
+pub fn verifying_key(pk: PublicKey) -> VerifyingKey {
+    // BIP-340 keys are x-only, so the parity of `pk` is dropped.
+    VerifyingKey::from_bytes(&k256::PublicKey::from(pk).to_encoded_point(true).as_bytes()[1..]).unwrap()
+}
+
+/// Key held in memory of the current process.
+pub struct InMemorySigner {
+    sk: SecretKey,
+    /// Secrets of issued commitments which are not answered yet, along with the time of issue.
+    commitments: Mutex<HashMap<Vec<u8>, (SigningKey, Instant)>>,
+    commitment_ttl: Duration,
+    /// Source of commitment secrets, `OsRng` if not set.
+    rng: Option<Mutex<ChaCha20Rng>>,
+}
+
+impl InMemorySigner {
+    pub fn new(sk: SecretKey) -> Self {
+        Self {
+            sk,
+            commitments: Mutex::new(HashMap::new()),
+            commitment_ttl: DEFAULT_COMMITMENT_TTL,
+            rng: None,
+        }
+    }
+
+    /// Signer drawing commitment secrets from a PRNG with the given seed, so that
+    /// recorded rounds can be reproduced. For simulations only: answering two challenges
+    /// for commitments drawn with the same seed reveals the key.
+    pub fn seeded(sk: SecretKey, seed: [u8; 32]) -> Self {
+        Self {
+            rng: Some(Mutex::new(ChaCha20Rng::from_seed(seed))),
+            ..Self::new(sk)
+        }
+    }
+
+    /// Forget commitments which aren't answered within `ttl`, e.g. of rounds abandoned by the host.
+    pub fn with_commitment_ttl(mut self, ttl: Duration) -> Self {
+        self.commitment_ttl = ttl;
+        self
+    }
+}
+
+impl Signer for InMemorySigner {
+    fn public_key(&self) -> PublicKey {
+        PublicKey::from(self.sk.clone())
+    }
+
+    fn schnorr_commitment(&self, msg: &[u8]) -> Result<(VerifyingKey, Signature), SignerError> {
+        let secret = match &self.rng {
+            Some(rng) => SigningKey::random(&mut *rng.lock().unwrap()),
+            None => SigningKey::random(&mut OsRng),
+        };
+        let commitment = *secret.verifying_key();
+        let proof = secret.sign(msg);
+        let mut commitments = self.commitments.lock().unwrap();
+        let ttl = self.commitment_ttl;
+        commitments.retain(|_, (_, issued_at)| issued_at.elapsed() < ttl);
+        commitments.insert(commitment.to_bytes().to_vec(), (secret, Instant::now()));
+        Ok((commitment, proof))
+    }
+
+    fn schnorr_response(&self, commitment: &VerifyingKey, e: Scalar) -> Result<Scalar, SignerError> {
+        let secret = self
+            .commitments
+            .lock()
+            .unwrap()
+            .remove(&commitment.to_bytes()[..])
+            .filter(|(_, issued_at)| issued_at.elapsed() < self.commitment_ttl)
+            .map(|(secret, _)| secret)
+            .ok_or(SignerError::UnknownCommitment)?;
+        let y: Scalar = *secret.as_nonzero_scalar().as_ref();
+        let x: Scalar = *self.sk.to_nonzero_scalar().as_ref();
+        Ok(y + e * x)
+    }
+
+    fn sign(&self, msg: &[u8]) -> Result<Signature, SignerError> {
+        Ok(SigningKey::from(self.sk.to_nonzero_scalar()).sign(msg))
+    }
+}
+
+/// Future resolved on the thread of an [`AsyncSigner`].
+pub type SignerFuture<T> = Pin<Box<dyn Future<Output = Result<T, SignerError>> + Send>>;
+
+type SignerCall = Box<dyn FnOnce(&dyn Signer) + Send>;
+
+/// Front of a [`Signer`] for async code. Calls are run one by one on a dedicated thread,
+/// so that a slow key custodian, e.g. a [`RemoteSigner`], never blocks the caller.
+#[derive(Clone)]
+pub struct AsyncSigner {
+    public_key: PublicKey,
+    calls: SignerCalls,
+}
+
+#[derive(Clone)]
+enum SignerCalls {
+    Thread(mpsc::UnboundedSender<SignerCall>),
+    Inline(Arc<dyn Signer>),
+}
+
+impl AsyncSigner {
+    /// Move the signer to a new thread, which stops once all handles are dropped.
+    pub fn spawn<S: Signer + 'static>(signer: S) -> Self {
+        let public_key = signer.public_key();
+        let (calls, inbox) = mpsc::unbounded::<SignerCall>();
+        thread::Builder::new()
+            .name("signer".to_string())
+            .spawn(move || {
+                for call in futures::executor::block_on_stream(inbox) {
+                    call(&signer);
+                }
+            })
+            .expect("Failed to spawn the signer thread");
+        Self {
+            public_key,
+            calls: SignerCalls::Thread(calls),
+        }
+    }
+
+    /// Signer called on the caller's thread, so that calls complete by the time they are polled.
+    /// For simulations only, where rounds must be reproducible.
+    pub fn inline<S: Signer + 'static>(signer: S) -> Self {
+        Self {
+            public_key: signer.public_key(),
+            calls: SignerCalls::Inline(Arc::new(signer)),
+        }
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        self.public_key
+    }
+
+    /// Run `f` with the signer on its thread.
+    pub fn call<T, F>(&self, f: F) -> SignerFuture<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&dyn Signer) -> Result<T, SignerError> + Send + 'static,
+    {
+        let calls = match &self.calls {
+            SignerCalls::Thread(calls) => calls,
+            SignerCalls::Inline(signer) => return Box::pin(futures::future::ready(f(&**signer))),
+        };
+        let (snd, recv) = oneshot::channel();
+        let call: SignerCall = Box::new(move |signer| {
+            let _ = snd.send(f(signer));
+        });
+        let _ = calls.unbounded_send(call);
+        // The call is dropped along with the sender if the thread is gone.
+        Box::pin(async move { recv.await.unwrap_or(Err(SignerError::Stopped)) })
+    }
+
+    /// See [`Signer::schnorr_commitment`].
+    pub fn schnorr_commitment(&self, msg: Vec<u8>) -> SignerFuture<(VerifyingKey, Signature)> {
+        self.call(move |signer| signer.schnorr_commitment(&msg))
+    }
+
+    /// See [`Signer::schnorr_response`].
+    pub fn schnorr_response(&self, commitment: VerifyingKey, e: Scalar) -> SignerFuture<Scalar> {
+        self.call(move |signer| signer.schnorr_response(&commitment, e))
+    }
+}
+
+/// Address a remote signer listens on.
+#[derive(Clone, Debug)]
+pub enum SignerAddr {
+    /// Signer on the same machine.
+    Unix(PathBuf),
+    /// Signer on an isolated machine.
+    Tcp(SocketAddr),
+}
+
+/// Listening socket of a remote signer, see [`serve`].
+pub enum SignerListener {
+    Unix(UnixListener),
+    Tcp(TcpListener),
+}
+
+#[derive(Clone, Debug)]
+pub struct RemoteSignerConfig {
+    pub addr: SignerAddr,
+    /// `X_i`. Public key of the member the remote signer holds the key of.
+    pub signer_pk: PublicKey,
+    /// Key this node authenticates itself to the remote signer with.
+    pub auth_sk: SecretKey,
+    /// Max time a single call to the remote signer may take, connection setup included.
+    pub latency_budget: Duration,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ClientHello {
+    client_pk: PublicKey,
+    nonce: [u8; 32],
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ServerHello {
+    nonce: [u8; 32],
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+enum SignerRequest {
+    PublicKey,
+    SchnorrCommitment { msg: Vec<u8> },
+    SchnorrResponse { commitment: Vec<u8>, challenge: Vec<u8> },
+    Sign { msg: Vec<u8> },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+enum SignerResponse {
+    PublicKey(PublicKey),
+    SchnorrCommitment { commitment: Vec<u8>, proof: Vec<u8> },
+    SchnorrResponse(Vec<u8>),
+    Failed(String),
+    Signature(Vec<u8>),
+}
+
+/// Message bound to the session it's sent in.
+#[derive(Serialize, Deserialize, Debug)]
+struct Signed<T> {
+    body: T,
+    sig: Vec<u8>,
+}
+
+/// Signer in another process reached over a socket. Every request is made in a fresh session:
+/// the client signs the request with its key, the signer signs the response with the member key.
+pub struct RemoteSigner {
+    conf: RemoteSignerConfig,
+}
+
+impl RemoteSigner {
+    /// Make sure the signer is reachable and holds the expected key.
+    pub fn connect(conf: RemoteSignerConfig) -> Result<Self, SignerError> {
+        let signer = Self { conf };
+        match signer.request(SignerRequest::PublicKey)? {
+            SignerResponse::PublicKey(pk) if pk == signer.conf.signer_pk => Ok(signer),
+            SignerResponse::PublicKey(_) => Err(SignerError::Unauthenticated),
+            resp => Err(unexpected(resp)),
+        }
+    }
+
+    fn request(&self, req: SignerRequest) -> Result<SignerResponse, SignerError> {
+        let budget = self.conf.latency_budget;
+        let started_at = Instant::now();
+        let resp = match &self.conf.addr {
+            SignerAddr::Unix(path) => {
+                let mut stream = UnixStream::connect(path)?;
+                stream.set_read_timeout(Some(budget))?;
+                stream.set_write_timeout(Some(budget))?;
+                self.exchange(&mut stream, req)
+            }
+            SignerAddr::Tcp(addr) => {
+                let mut stream = TcpStream::connect_timeout(addr, budget)?;
+                stream.set_nodelay(true)?;
+                stream.set_read_timeout(Some(budget))?;
+                stream.set_write_timeout(Some(budget))?;
+                self.exchange(&mut stream, req)
+            }
+        }?;
+        if started_at.elapsed() > budget {
+            return Err(SignerError::Timeout(budget));
+        }
+        Ok(resp)
+    }
+
+    fn exchange<T: Read + Write>(
+        &self,
+        stream: &mut T,
+        req: SignerRequest,
+    ) -> Result<SignerResponse, SignerError> {
+        let nonce_c = random_nonce();
+        let hello = ClientHello {
+            client_pk: PublicKey::from(self.conf.auth_sk.clone()),
+            nonce: nonce_c,
+        };
+        write_frame(stream, &hello)?;
+        let ServerHello { nonce: nonce_s } = read_frame(stream)?;
+        let session = session_id(&nonce_c, &nonce_s);
+        let auth_key = SigningKey::from(self.conf.auth_sk.to_nonzero_scalar());
+        let sig = auth_key.sign(&transcript(&session, &req));
+        write_frame(
+            stream,
+            &Signed {
+                body: req,
+                sig: sig.to_bytes().to_vec(),
+            },
+        )?;
+        let resp: Signed<SignerResponse> = read_frame(stream)?;
+        verify(
+            &verifying_key(self.conf.signer_pk),
+            &transcript(&session, &resp.body),
+            &resp.sig,
+        )?;
+        Ok(resp.body)
+    }
+}
+
+impl Signer for RemoteSigner {
+    fn public_key(&self) -> PublicKey {
+        self.conf.signer_pk
+    }
+
+    fn schnorr_commitment(&self, msg: &[u8]) -> Result<(VerifyingKey, Signature), SignerError> {
+        let req = SignerRequest::SchnorrCommitment { msg: msg.to_vec() };
+        match self.request(req)? {
+            SignerResponse::SchnorrCommitment { commitment, proof } => {
+                let commitment = VerifyingKey::from_bytes(&commitment).map_err(|_| SignerError::Malformed)?;
+                let proof = Signature::try_from(proof.as_slice()).map_err(|_| SignerError::Malformed)?;
+                // Make sure the commitment is bound to the requested message.
+                commitment
+                    .verify(msg, &proof)
+                    .map_err(|_| SignerError::Unauthenticated)?;
+                Ok((commitment, proof))
+            }
+            resp => Err(unexpected(resp)),
+        }
+    }
+
+    fn schnorr_response(&self, commitment: &VerifyingKey, e: Scalar) -> Result<Scalar, SignerError> {
+        let req = SignerRequest::SchnorrResponse {
+            commitment: commitment.to_bytes().to_vec(),
+            challenge: e.to_bytes().to_vec(),
+        };
+        match self.request(req)? {
+            SignerResponse::SchnorrResponse(z) => decode_scalar(&z),
+            resp => Err(unexpected(resp)),
+        }
+    }
+
+    fn sign(&self, msg: &[u8]) -> Result<Signature, SignerError> {
+        let req = SignerRequest::Sign { msg: msg.to_vec() };
+        match self.request(req)? {
+            SignerResponse::Signature(sig) => {
+                Signature::try_from(sig.as_slice()).map_err(|_| SignerError::Malformed)
+            }
+            resp => Err(unexpected(resp)),
+        }
+    }
+}
+
+/// Serve requests of [`RemoteSigner`]s authenticated with one of `authorized_clients` keys
+/// with the given signer until the listener fails. Clients are served one at a time,
+/// `idle_timeout` bounds every read and write.
+pub fn serve<S: Signer>(
+    listener: SignerListener,
+    signer: &S,
+    authorized_clients: &[PublicKey],
+    idle_timeout: Duration,
+) -> std::io::Result<()> {
+    // Failures of individual sessions are the client's problem.
+    match listener {
+        SignerListener::Unix(listener) => {
+            for stream in listener.incoming() {
+                let mut stream = stream?;
+                stream.set_read_timeout(Some(idle_timeout))?;
+                stream.set_write_timeout(Some(idle_timeout))?;
+                let _ = serve_session(&mut stream, signer, authorized_clients);
+            }
+        }
+        SignerListener::Tcp(listener) => {
+            for stream in listener.incoming() {
+                let mut stream = stream?;
+                stream.set_read_timeout(Some(idle_timeout))?;
+                stream.set_write_timeout(Some(idle_timeout))?;
+                let _ = serve_session(&mut stream, signer, authorized_clients);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn serve_session<S: Signer, T: Read + Write>(
+    stream: &mut T,
+    signer: &S,
+    authorized_clients: &[PublicKey],
+) -> Result<(), SignerError> {
+    let hello: ClientHello = read_frame(stream)?;
+    if !authorized_clients.contains(&hello.client_pk) {
+        return Err(SignerError::Unauthenticated);
+    }
+    let nonce_s = random_nonce();
+    write_frame(stream, &ServerHello { nonce: nonce_s })?;
+    let session = session_id(&hello.nonce, &nonce_s);
+    let req: Signed<SignerRequest> = read_frame(stream)?;
+    verify(
+        &verifying_key(hello.client_pk),
+        &transcript(&session, &req.body),
+        &req.sig,
+    )?;
+    let resp = handle(signer, req.body).unwrap_or_else(|err| SignerResponse::Failed(err.to_string()));
+    let sig = signer.sign(&transcript(&session, &resp))?;
+    write_frame(
+        stream,
+        &Signed {
+            body: resp,
+            sig: sig.to_bytes().to_vec(),
+        },
+    )
+}
+
+fn handle<S: Signer>(signer: &S, req: SignerRequest) -> Result<SignerResponse, SignerError> {
+    Ok(match req {
+        SignerRequest::PublicKey => SignerResponse::PublicKey(signer.public_key()),
+        SignerRequest::SchnorrCommitment { msg } => {
+            let (commitment, proof) = signer.schnorr_commitment(&msg)?;
+            SignerResponse::SchnorrCommitment {
+                commitment: commitment.to_bytes().to_vec(),
+                proof: proof.to_bytes().to_vec(),
+            }
+        }
+        SignerRequest::SchnorrResponse {
+            commitment,
+            challenge,
+        } => {
+            let commitment = VerifyingKey::from_bytes(&commitment).map_err(|_| SignerError::Malformed)?;
+            let z = signer.schnorr_response(&commitment, decode_scalar(&challenge)?)?;
+            SignerResponse::SchnorrResponse(z.to_bytes().to_vec())
+        }
+        SignerRequest::Sign { msg } => SignerResponse::Signature(signer.sign(&msg)?.to_bytes().to_vec()),
+    })
+}
+
+fn unexpected(resp: SignerResponse) -> SignerError {
+    match resp {
+        SignerResponse::Failed(err) => SignerError::Remote(err),
+        _ => SignerError::Malformed,
+    }
+}
+
+fn decode_scalar(bytes: &[u8]) -> Result<Scalar, SignerError> {
+    let repr = <[u8; 32]>::try_from(bytes).map_err(|_| SignerError::Malformed)?;
+    Option::from(Scalar::from_repr(repr.into())).ok_or(SignerError::Malformed)
+}
+
+fn random_nonce() -> [u8; 32] {
+    let mut nonce = [0u8; 32];
+    OsRng.fill_bytes(&mut nonce);
+    nonce
+}
+
+fn session_id(nonce_c: &[u8; 32], nonce_s: &[u8; 32]) -> Vec<u8> {
+    let mut bf = b"spectrum/signer/v1".to_vec();
+    bf.extend_from_slice(nonce_c);
+    bf.extend_from_slice(nonce_s);
+    bf
+}
+
+/// Bytes a message of the given session is signed over.
+fn transcript<T: Serialize>(session: &[u8], body: &T) -> Vec<u8> {
+    let mut bf = session.to_vec();
+    bf.extend(bincode::serialize(body).unwrap());
+    bf
+}
+
+fn verify(vk: &VerifyingKey, msg: &[u8], sig: &[u8]) -> Result<(), SignerError> {
+    Signature::try_from(sig)
+        .ok()
+        .and_then(|sig| vk.verify(msg, &sig).ok())
+        .ok_or(SignerError::Unauthenticated)
+}
+
+/// Frames are prefixed with their length as big-endian `u32`.
+fn write_frame<T: Serialize>(stream: &mut impl Write, msg: &T) -> Result<(), SignerError> {
+    let bytes = bincode::serialize(msg).unwrap();
+    stream.write_all(&(bytes.len() as u32).to_be_bytes())?;
+    stream.write_all(&bytes)?;
+    stream.flush()?;
+    Ok(())
+}
+
+fn read_frame<T: serde::de::DeserializeOwned>(stream: &mut impl Read) -> Result<T, SignerError> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(SignerError::Malformed);
+    }
+    let mut bytes = vec![0u8; len];
+    stream.read_exact(&mut bytes)?;
+    bincode::deserialize(&bytes).map_err(|_| SignerError::Malformed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+    use std::time::Duration;
+
+    use k256::elliptic_curve::rand_core::OsRng;
+    use k256::schnorr::signature::Verifier;
+    use k256::{ProjectivePoint, Scalar, SecretKey};
+
+    use crate::pubkey::PublicKey;
+    use crate::signer::{
+        serve, verifying_key, AsyncSigner, InMemorySigner, RemoteSigner, RemoteSignerConfig, Signer,
+        SignerAddr, SignerError, SignerListener,
+    };
+
+    fn check_signer<S: Signer>(signer: &S) {
+        let msg = b"message";
+        let (commitment, proof) = signer.schnorr_commitment(msg).unwrap();
+        assert!(commitment.verify(msg, &proof).is_ok());
+        let e = Scalar::from(42u64);
+        let z = signer.schnorr_response(&commitment, e).unwrap();
+        let x = k256::PublicKey::from(signer.public_key()).to_projective();
+        let y = ProjectivePoint::from(*commitment.as_affine());
+        assert_eq!(ProjectivePoint::GENERATOR * z, y + x * e);
+        assert!(signer.schnorr_response(&commitment, e).is_err());
+        let sig = signer.sign(msg).unwrap();
+        assert!(verifying_key(signer.public_key()).verify(msg, &sig).is_ok());
+    }
+
+    /// Serve a fresh key to the holder of `auth_sk` over TCP.
+    fn spawn_tcp_signer(sk: SecretKey, auth_sk: &SecretKey) -> SignerAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let authorized = vec![PublicKey::from(auth_sk.clone())];
+        let local = InMemorySigner::new(sk);
+        thread::spawn(move || {
+            serve(
+                SignerListener::Tcp(listener),
+                &local,
+                &authorized,
+                Duration::from_secs(5),
+            )
+        });
+        SignerAddr::Tcp(addr)
+    }
+
+    #[test]
+    fn in_memory_signer_answers_commitments_once() {
+        check_signer(&InMemorySigner::new(SecretKey::random(&mut OsRng)));
+    }
+
+    #[test]
+    fn in_memory_signer_forgets_expired_commitments() {
+        let signer = InMemorySigner::new(SecretKey::random(&mut OsRng)).with_commitment_ttl(Duration::ZERO);
+        let (first, _) = signer.schnorr_commitment(b"round").unwrap();
+        signer.schnorr_commitment(b"another round").unwrap();
+        assert_eq!(signer.commitments.lock().unwrap().len(), 1);
+        assert!(matches!(
+            signer.schnorr_response(&first, Scalar::ONE),
+            Err(SignerError::UnknownCommitment)
+        ));
+    }
+
+    #[test]
+    fn remote_signer_over_unix_socket() {
+        let dir = std::env::temp_dir().join(format!("spectrum-signer-{}", rand::random::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket_path = dir.join("signer.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let sk = SecretKey::random(&mut OsRng);
+        let auth_sk = SecretKey::random(&mut OsRng);
+        let authorized = vec![PublicKey::from(auth_sk.clone())];
+        let local = InMemorySigner::new(sk.clone());
+        thread::spawn(move || {
+            serve(
+                SignerListener::Unix(listener),
+                &local,
+                &authorized,
+                Duration::from_secs(5),
+            )
+        });
+        let remote = RemoteSigner::connect(RemoteSignerConfig {
+            addr: SignerAddr::Unix(socket_path),
+            signer_pk: PublicKey::from(sk),
+            auth_sk,
+            latency_budget: Duration::from_secs(5),
+        })
+        .unwrap();
+        check_signer(&remote);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn remote_signer_over_tcp() {
+        let sk = SecretKey::random(&mut OsRng);
+        let auth_sk = SecretKey::random(&mut OsRng);
+        let remote = RemoteSigner::connect(RemoteSignerConfig {
+            addr: spawn_tcp_signer(sk.clone(), &auth_sk),
+            signer_pk: PublicKey::from(sk),
+            auth_sk,
+            latency_budget: Duration::from_secs(5),
+        })
+        .unwrap();
+        check_signer(&remote);
+    }
+
+    #[test]
+    fn remote_signer_rejects_unauthorized_client() {
+        let sk = SecretKey::random(&mut OsRng);
+        let res = RemoteSigner::connect(RemoteSignerConfig {
+            addr: spawn_tcp_signer(sk.clone(), &SecretKey::random(&mut OsRng)),
+            signer_pk: PublicKey::from(sk),
+            auth_sk: SecretKey::random(&mut OsRng),
+            latency_budget: Duration::from_secs(1),
+        });
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn remote_signer_must_prove_key_ownership() {
+        let auth_sk = SecretKey::random(&mut OsRng);
+        // Signer holds a key different from the one the client expects.
+        let res = RemoteSigner::connect(RemoteSignerConfig {
+            addr: spawn_tcp_signer(SecretKey::random(&mut OsRng), &auth_sk),
+            signer_pk: PublicKey::from(SecretKey::random(&mut OsRng)),
+            auth_sk,
+            latency_budget: Duration::from_secs(1),
+        });
+        assert!(matches!(res, Err(SignerError::Unauthenticated)));
+    }
+
+    #[test]
+    fn async_signer_runs_calls_on_its_thread() {
+        let sk = SecretKey::random(&mut OsRng);
+        let signer = AsyncSigner::spawn(InMemorySigner::new(sk.clone()));
+        assert_eq!(signer.public_key(), PublicKey::from(sk));
+        let (commitment, _) =
+            futures::executor::block_on(signer.schnorr_commitment(b"round".to_vec())).unwrap();
+        let e = Scalar::from(7u64);
+        assert!(futures::executor::block_on(signer.schnorr_response(commitment, e)).is_ok());
+        assert!(matches!(
+            futures::executor::block_on(signer.schnorr_response(commitment, e)),
+            Err(SignerError::UnknownCommitment)
+        ));
+    }
+}
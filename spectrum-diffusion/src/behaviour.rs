@@ -1,4 +1,4 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
@@ -6,24 +6,34 @@ use std::time::{Duration, Instant};
 
 use async_std::channel::{Receiver, Sender};
 use futures::channel::oneshot;
-use futures::{stream, Stream, StreamExt};
+use futures::{future, stream, Stream, StreamExt};
 use libp2p_identity::PeerId;
+use tracing::warn;
 
-use spectrum_ledger::block::{BlockBody, BlockHeader};
-use spectrum_ledger::transaction::Transaction;
+use spectrum_ledger::block::{BlockBody, BlockHeader, BlockId};
+use spectrum_ledger::transaction::{Transaction, TxPackage};
 use spectrum_ledger::{Modifier, ModifierId, ModifierType, SerializedModifier};
+use spectrum_network::cancellation::CancellationToken;
+use spectrum_network::memory_budget::{MemoryQuota, Shrink};
+use spectrum_network::peer_manager::data::ReputationChange;
 use spectrum_network::protocol_handler::pool::{FromTask, TaskPool};
 use spectrum_network::protocol_handler::{
     NetworkAction, ProtocolBehaviour, ProtocolBehaviourOut, ProtocolSpec,
 };
+use spectrum_network::types::ProtocolVer;
 use spectrum_view::chain::HeaderLike;
 use spectrum_view::history::LedgerHistoryReadAsync;
-use spectrum_view::node_view::NodeViewWriteAsync;
+use spectrum_view::mempool::MempoolReadAsync;
+use spectrum_view::node_view::{ModifierSource, NodeViewWriteAsync};
+use spectrum_view::snapshot::{
+    SignedSnapshot, SnapshotAssembler, SnapshotChunk, SnapshotSource, SnapshotVerifier,
+};
 
 use crate::message::{
-    DiffusionHandshake, DiffusionMessage, DiffusionMessageV1, DiffusionSpec, HandshakeV1, Modifiers,
-    SyncStatus,
+    ChunkRef, Continuation, DiffusionHandshake, DiffusionMessage, DiffusionMessageV1, DiffusionMessageV2,
+    DiffusionSpec, HandshakeV1, Modifiers, SyncStatus,
 };
+use crate::scheduler::{Delivery, DownloadScheduler};
 use crate::service::{RemoteChainCmp, RemoteSync, SyncState};
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
@@ -52,6 +62,28 @@ impl ModifierTracker for HashMap<ModifierId, ModifierStatus> {
     }
 }
 
+/// Approximate size of an entry of the modifier tracker.
+const TRACKER_ENTRY_SIZE: usize = std::mem::size_of::<(ModifierId, ModifierStatus)>();
+
+/// Evicts received modifiers from the tracker under memory pressure.
+/// Modifiers in progress are never evicted.
+struct ReceivedModifiers<'a>(&'a mut HashMap<ModifierId, ModifierStatus>);
+
+impl<'a> Shrink for ReceivedModifiers<'a> {
+    fn shrink(&mut self, bytes: usize) -> usize {
+        let mut freed = 0;
+        self.0.retain(|_, status| {
+            if freed < bytes && matches!(status, ModifierStatus::Received) {
+                freed += TRACKER_ENTRY_SIZE;
+                false
+            } else {
+                true
+            }
+        });
+        freed
+    }
+}
+
 #[derive(Debug)]
 enum DiffusionBehaviourIn {
     UpdatePeer {
@@ -66,6 +98,50 @@ enum DiffusionBehaviourIn {
         modifier: ModifierId,
         status_future: oneshot::Sender<ModifierStatus>,
     },
+    RequestTimeout {
+        peer_id: PeerId,
+        mod_type: ModifierType,
+        round: u64,
+    },
+    /// Peer is ahead of the checkpoint the node is bootstrapping from.
+    SnapshotWanted {
+        peer_id: PeerId,
+    },
+    SnapshotTimeout {
+        round: u64,
+    },
+    /// Bodies to download.
+    BodiesWanted {
+        bodies: Vec<ModifierId>,
+    },
+    DownloadTimeout,
+    /// Modifiers accepted locally to be announced to peers.
+    Announce {
+        mod_type: ModifierType,
+        modifiers: Vec<ModifierId>,
+        source: ModifierSource,
+    },
+}
+
+/// Handle to announce modifiers accepted by the node to its peers.
+#[derive(Clone)]
+pub struct DiffusionMailbox {
+    inner: Sender<FromTask<DiffusionBehaviourIn, DiffusionBehaviourOut>>,
+}
+
+impl DiffusionMailbox {
+    /// Announce the modifiers to all peers except the one they were received from.
+    /// Announcements are dropped if the behaviour lags behind.
+    pub fn announce(&self, mod_type: ModifierType, modifiers: Vec<ModifierId>, source: ModifierSource) {
+        let announcement = DiffusionBehaviourIn::Announce {
+            mod_type,
+            modifiers,
+            source,
+        };
+        if self.inner.try_send(FromTask::ToBehaviour(announcement)).is_err() {
+            warn!("[Diffusion] Announcement dropped");
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -120,40 +196,153 @@ type DiffusionBehaviourOut = ProtocolBehaviourOut<DiffusionHandshake, DiffusionM
 pub struct DiffusionConfig {
     max_inv_size: usize,
     task_timeout: Duration,
+    /// Max total size of modifiers sent in a single response.
+    /// Should be lower than the max message size of the protocol.
+    max_modifiers_response_bytes: usize,
+    /// How long to wait for the next portion of requested modifiers before giving up on the peer.
+    /// Must not exceed `task_timeout`.
+    modifiers_request_timeout: Duration,
+    /// Max number of block bodies requested from a single peer at a time.
+    max_body_requests_per_peer: usize,
+}
+
+/// Bootstrap from the snapshot of the ledger state at a checkpoint certified by the committee
+/// instead of syncing the chain from genesis.
+#[derive(Clone)]
+pub struct CheckpointSyncConfig {
+    /// Block to bootstrap from.
+    pub checkpoint: BlockId,
+    pub verifier: SnapshotVerifier,
+}
+
+enum Bootstrap {
+    /// Snapshot isn't requested yet or the last attempt failed.
+    Idle,
+    AwaitingManifest {
+        peer_id: PeerId,
+    },
+    /// Chunks are downloaded from the peer one by one.
+    Downloading {
+        peer_id: PeerId,
+        snapshot: Box<SignedSnapshot>,
+        assembler: SnapshotAssembler,
+    },
+    /// Snapshot is handed over to the node view.
+    Done,
+}
+
+struct CheckpointSync {
+    conf: CheckpointSyncConfig,
+    state: Bootstrap,
+    /// Incremented with each request, so that stale timeouts can be told apart.
+    round: u64,
+}
+
+/// Modifiers requested from a peer which are not delivered yet.
+struct PendingRequest {
+    remaining: HashSet<ModifierId>,
+    /// Incremented with each follow-up request, so that stale timeouts can be told apart.
+    round: u64,
 }
 
-pub struct DiffusionBehaviour<'a, THeader, THistory, TLedgerView> {
+pub struct DiffusionBehaviour<'a, THeader, THistory, TMempool, TLedgerView> {
     conf: DiffusionConfig,
     from_tasks: Receiver<FromTask<DiffusionBehaviourIn, DiffusionBehaviourOut>>,
+    to_self: Sender<FromTask<DiffusionBehaviourIn, DiffusionBehaviourOut>>,
     outbox: VecDeque<DiffusionBehaviourOut>,
     tasks: TaskPool<'a, DiffusionBehaviourIn, DiffusionBehaviourOut, ()>,
     peers: HashMap<PeerId, SyncState>,
+    /// Versions of the protocol negotiated with peers.
+    peer_versions: HashMap<PeerId, ProtocolVer>,
     delivery: HashMap<ModifierId, ModifierStatus>,
-    remote_sync: RemoteSync<THeader, THistory>,
+    requests: HashMap<(PeerId, ModifierType), PendingRequest>,
+    /// Block bodies are downloaded from multiple peers in parallel.
+    downloads: DownloadScheduler,
+    remote_sync: RemoteSync<THeader, THistory, TMempool>,
     history: Arc<THistory>,
     ledger_view: TLedgerView,
+    /// Quota for the modifier tracker.
+    memory_quota: Option<MemoryQuota>,
+    checkpoint_sync: Option<CheckpointSync>,
+    /// Snapshots served to bootstrapping peers.
+    snapshots: Option<Arc<dyn SnapshotSource>>,
 }
 
 const FROM_TASK_BUFFER_SIZE: usize = 1000;
 
-impl<'a, THeader, THistory, TLedgerView> DiffusionBehaviour<'a, THeader, THistory, TLedgerView>
+impl<'a, THeader, THistory, TMempool, TLedgerView>
+    DiffusionBehaviour<'a, THeader, THistory, TMempool, TLedgerView>
 where
     THeader: HeaderLike + 'a,
     THistory: LedgerHistoryReadAsync<THeader> + 'a,
+    TMempool: MempoolReadAsync + 'a,
     TLedgerView: NodeViewWriteAsync + 'a,
 {
-    pub fn new(conf: DiffusionConfig, history: Arc<THistory>, ledger_view: TLedgerView) -> Self {
+    /// Transactions and packages requested by peers are served from the given mempool.
+    pub fn new(
+        conf: DiffusionConfig,
+        history: Arc<THistory>,
+        mempool: Arc<TMempool>,
+        ledger_view: TLedgerView,
+    ) -> Self {
         let (snd, recv) = async_std::channel::bounded(FROM_TASK_BUFFER_SIZE);
         Self {
             conf,
             from_tasks: recv,
+            to_self: snd.clone(),
             outbox: VecDeque::new(),
             tasks: TaskPool::new(String::from("Diffusion"), conf.task_timeout, snd),
             peers: HashMap::new(),
+            peer_versions: HashMap::new(),
             delivery: HashMap::new(),
-            remote_sync: RemoteSync::new(Arc::clone(&history)),
+            requests: HashMap::new(),
+            downloads: DownloadScheduler::new(
+                conf.max_body_requests_per_peer,
+                conf.modifiers_request_timeout,
+            ),
+            remote_sync: RemoteSync::new(Arc::clone(&history), mempool),
             history,
             ledger_view,
+            memory_quota: None,
+            checkpoint_sync: None,
+            snapshots: None,
+        }
+    }
+
+    /// Bootstrap from the snapshot at the given checkpoint unless the node has reached it already.
+    pub fn with_checkpoint_sync(mut self, conf: CheckpointSyncConfig) -> Self {
+        self.checkpoint_sync = Some(CheckpointSync {
+            conf,
+            state: Bootstrap::Idle,
+            round: 0,
+        });
+        self
+    }
+
+    /// Serve snapshots from the given source to bootstrapping peers.
+    pub fn with_snapshot_source(mut self, source: Arc<dyn SnapshotSource>) -> Self {
+        self.snapshots = Some(source);
+        self
+    }
+
+    /// Account memory occupied by the modifier tracker within the given quota.
+    /// Received modifiers are evicted from the tracker once the quota is exceeded.
+    pub fn with_memory_quota(mut self, quota: MemoryQuota) -> Self {
+        self.memory_quota = Some(quota);
+        self
+    }
+
+    /// Handle to announce locally accepted modifiers, e.g. transaction packages.
+    pub fn mailbox(&self) -> DiffusionMailbox {
+        DiffusionMailbox {
+            inner: self.to_self.clone(),
+        }
+    }
+
+    fn update_memory_usage(&mut self) {
+        if let Some(quota) = &self.memory_quota {
+            quota.update(self.delivery.len() * TRACKER_ENTRY_SIZE);
+            quota.apply_pressure(&mut ReceivedModifiers(&mut self.delivery));
         }
     }
 
@@ -161,12 +350,14 @@ where
         match event {
             DiffusionBehaviourIn::UpdatePeer { peer_id, peer_state } => {
                 self.peers.insert(peer_id, peer_state);
+                self.schedule_downloads();
             }
             DiffusionBehaviourIn::UpdateModifier {
                 modifier_id: modifier,
                 status,
             } => {
                 self.delivery.set_status(modifier, status);
+                self.update_memory_usage();
             }
 
             DiffusionBehaviourIn::GetModifierStatus {
@@ -175,13 +366,340 @@ where
             } => {
                 status_future.send(self.delivery.status(&modifier)).unwrap();
             }
+            DiffusionBehaviourIn::RequestTimeout {
+                peer_id,
+                mod_type,
+                round,
+            } => {
+                if matches!(self.requests.get(&(peer_id, mod_type)), Some(req) if req.round == round) {
+                    self.release_request(peer_id, mod_type);
+                }
+            }
+            DiffusionBehaviourIn::SnapshotWanted { peer_id } => {
+                if let Some(sync) = &mut self.checkpoint_sync {
+                    if let Bootstrap::Idle = sync.state {
+                        sync.state = Bootstrap::AwaitingManifest { peer_id };
+                        let message = DiffusionMessage::request_snapshot_v1(sync.conf.checkpoint);
+                        self.request_snapshot_part(peer_id, message);
+                    }
+                }
+            }
+            DiffusionBehaviourIn::SnapshotTimeout { round } => {
+                if let Some(sync) = &mut self.checkpoint_sync {
+                    if sync.round == round && !matches!(sync.state, Bootstrap::Done) {
+                        // Try another peer.
+                        sync.state = Bootstrap::Idle;
+                    }
+                }
+            }
+            DiffusionBehaviourIn::BodiesWanted { bodies } => {
+                self.downloads.enqueue(bodies);
+                self.schedule_downloads();
+            }
+            DiffusionBehaviourIn::DownloadTimeout => {
+                for peer_id in self.downloads.on_timeout(Instant::now()) {
+                    self.outbox
+                        .push_back(DiffusionBehaviourOut::NetworkAction(NetworkAction::ReportPeer(
+                            peer_id,
+                            ReputationChange::NoResponse,
+                        )));
+                }
+                self.schedule_downloads();
+            }
+            DiffusionBehaviourIn::Announce {
+                mod_type,
+                modifiers,
+                source,
+            } => {
+                for pid in self.peers.keys() {
+                    if source != ModifierSource::Remote(*pid) {
+                        self.outbox.push_back(DiffusionBehaviourOut::Send {
+                            peer_id: *pid,
+                            message: DiffusionMessage::inv_v1(mod_type, modifiers.clone()),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    /// Request wanted bodies from peers which are not behind the local node.
+    fn schedule_downloads(&mut self) {
+        let peers = self
+            .peers
+            .iter()
+            .filter(|(_, st)| matches!(st.cmp, RemoteChainCmp::Equal | RemoteChainCmp::Longer(_)))
+            .map(|(pid, _)| *pid)
+            .collect::<Vec<_>>();
+        let batches = self.downloads.schedule(Instant::now(), &peers);
+        if batches.is_empty() {
+            return;
+        }
+        for (peer_id, bodies) in batches {
+            self.on_request_sent(peer_id, ModifierType::BlockBody, &bodies);
+            self.outbox.push_back(DiffusionBehaviourOut::Send {
+                peer_id,
+                message: DiffusionMessage::request_modifiers_v1(ModifierType::BlockBody, bodies),
+            });
+        }
+        let timeout = self.conf.modifiers_request_timeout;
+        self.tasks.spawn(|to_behaviour, cancellation| async move {
+            async_std::task::sleep(timeout).await;
+            if cancellation.is_cancelled() {
+                return;
+            }
+            to_behaviour
+                .send(FromTask::ToBehaviour(DiffusionBehaviourIn::DownloadTimeout))
+                .await
+                .unwrap();
+        })
+    }
+
+    /// Request the manifest or a chunk of the snapshot from the peer.
+    /// The peer is given up on unless it responds in time.
+    fn request_snapshot_part(&mut self, peer_id: PeerId, message: DiffusionMessage) {
+        let Some(sync) = &mut self.checkpoint_sync else {
+            return;
+        };
+        sync.round += 1;
+        let round = sync.round;
+        let timeout = self.conf.modifiers_request_timeout;
+        self.outbox
+            .push_back(DiffusionBehaviourOut::Send { peer_id, message });
+        self.tasks.spawn(|to_behaviour, cancellation| async move {
+            async_std::task::sleep(timeout).await;
+            if cancellation.is_cancelled() {
+                return;
+            }
+            to_behaviour
+                .send(FromTask::ToBehaviour(DiffusionBehaviourIn::SnapshotTimeout {
+                    round,
+                }))
+                .await
+                .unwrap();
+        })
+    }
+
+    fn on_snapshot_request(&mut self, peer_id: PeerId, block_id: BlockId) {
+        let Some(source) = self.snapshots.clone() else {
+            self.outbox.push_back(DiffusionBehaviourOut::Send {
+                peer_id,
+                message: DiffusionMessage::snapshot_v1(block_id, None),
+            });
+            return;
+        };
+        self.tasks.spawn(|to_behaviour, _| async move {
+            let snapshot = source.get_snapshot(block_id).await;
+            to_behaviour
+                .send(FromTask::ToHandler(DiffusionBehaviourOut::Send {
+                    peer_id,
+                    message: DiffusionMessage::snapshot_v1(block_id, snapshot),
+                }))
+                .await
+                .unwrap();
+        })
+    }
+
+    /// Missing chunks are not responded to.
+    fn on_snapshot_chunk_request(&mut self, peer_id: PeerId, ChunkRef { block_id, index }: ChunkRef) {
+        let Some(source) = self.snapshots.clone() else {
+            return;
+        };
+        self.tasks.spawn(|to_behaviour, _| async move {
+            if let Some(chunk) = source.get_chunk(block_id, index).await {
+                to_behaviour
+                    .send(FromTask::ToHandler(DiffusionBehaviourOut::Send {
+                        peer_id,
+                        message: DiffusionMessage::snapshot_chunk_v1(block_id, index, chunk),
+                    }))
+                    .await
+                    .unwrap();
+            }
+        })
+    }
+
+    fn on_snapshot(&mut self, peer_id: PeerId, block_id: BlockId, snapshot: Option<Box<SignedSnapshot>>) {
+        let Some(sync) = &mut self.checkpoint_sync else {
+            return;
+        };
+        if !matches!(sync.state, Bootstrap::AwaitingManifest { peer_id: from } if from == peer_id)
+            || block_id != sync.conf.checkpoint
+        {
+            return;
+        }
+        let Some(snapshot) = snapshot else {
+            sync.state = Bootstrap::Idle;
+            return;
+        };
+        if snapshot.manifest.checkpoint.id != block_id || sync.conf.verifier.verify(&snapshot).is_err() {
+            sync.state = Bootstrap::Idle;
+            self.outbox
+                .push_back(DiffusionBehaviourOut::NetworkAction(NetworkAction::BanPeer(
+                    peer_id,
+                )));
+            return;
+        }
+        let assembler = SnapshotAssembler::new(snapshot.manifest.clone());
+        sync.state = Bootstrap::Downloading {
+            peer_id,
+            snapshot,
+            assembler,
+        };
+        self.on_snapshot_progress(peer_id);
+    }
+
+    fn on_snapshot_chunk(
+        &mut self,
+        peer_id: PeerId,
+        ChunkRef { block_id, index }: ChunkRef,
+        chunk: SnapshotChunk,
+    ) {
+        let Some(sync) = &mut self.checkpoint_sync else {
+            return;
+        };
+        let Bootstrap::Downloading {
+            peer_id: from,
+            assembler,
+            ..
+        } = &mut sync.state
+        else {
+            return;
+        };
+        if *from != peer_id || block_id != sync.conf.checkpoint {
+            return;
+        }
+        if assembler.add_chunk(index, chunk).is_err() {
+            sync.state = Bootstrap::Idle;
+            self.outbox
+                .push_back(DiffusionBehaviourOut::NetworkAction(NetworkAction::BanPeer(
+                    peer_id,
+                )));
+            return;
+        }
+        self.on_snapshot_progress(peer_id);
+    }
+
+    /// Request the next missing chunk or hand the snapshot over to the node view once complete.
+    fn on_snapshot_progress(&mut self, peer_id: PeerId) {
+        let Some(sync) = &mut self.checkpoint_sync else {
+            return;
+        };
+        let Bootstrap::Downloading { assembler, .. } = &sync.state else {
+            return;
+        };
+        if let Some(index) = assembler.next_missing() {
+            let message = DiffusionMessage::request_snapshot_chunk_v1(sync.conf.checkpoint, index);
+            self.request_snapshot_part(peer_id, message);
+            return;
+        }
+        let Bootstrap::Downloading {
+            snapshot, assembler, ..
+        } = std::mem::replace(&mut sync.state, Bootstrap::Done)
+        else {
+            unreachable!()
+        };
+        let state = assembler.finish().unwrap();
+        let mut ledger_view = self.ledger_view.clone();
+        // Header sync from the checkpoint on resumes with the next exchange of sync statuses.
+        self.tasks.spawn(|_, _| async move {
+            ledger_view
+                .install_snapshot(*snapshot, state, ModifierSource::Remote(peer_id))
+                .await;
+        })
+    }
+
+    /// Track modifiers requested from the peer.
+    fn on_request_sent(&mut self, peer_id: PeerId, mod_type: ModifierType, modifiers: &[ModifierId]) {
+        let now = Instant::now();
+        for mid in modifiers {
+            self.delivery.set_status(*mid, ModifierStatus::Requested(now));
+        }
+        self.update_memory_usage();
+        let req = self
+            .requests
+            .entry((peer_id, mod_type))
+            .or_insert(PendingRequest {
+                remaining: HashSet::new(),
+                round: 0,
+            });
+        req.remaining.extend(modifiers);
+        req.round += 1;
+        let round = req.round;
+        let timeout = self.conf.modifiers_request_timeout;
+        self.tasks.spawn(|to_behaviour, cancellation| async move {
+            async_std::task::sleep(timeout).await;
+            if cancellation.is_cancelled() {
+                return;
+            }
+            to_behaviour
+                .send(FromTask::ToBehaviour(DiffusionBehaviourIn::RequestTimeout {
+                    peer_id,
+                    mod_type,
+                    round,
+                }))
+                .await
+                .unwrap();
+        })
+    }
+
+    /// Handle the next portion of requested modifiers.
+    /// Remaining modifiers are requested again until the peer has nothing more to send,
+    /// the ones it didn't deliver by then can be requested elsewhere.
+    fn on_response(
+        &mut self,
+        peer_id: PeerId,
+        mod_type: ModifierType,
+        num_delivered: usize,
+        continuation: Option<Continuation>,
+    ) {
+        let Some(req) = self.requests.get_mut(&(peer_id, mod_type)) else {
+            return;
+        };
+        match continuation {
+            None => self.release_request(peer_id, mod_type),
+            Some(Continuation(rest)) => {
+                let rest = rest
+                    .into_iter()
+                    .filter(|mid| req.remaining.contains(mid))
+                    .collect::<HashSet<_>>();
+                if num_delivered == 0 || rest.len() >= req.remaining.len() || rest.is_empty() {
+                    // Peer doesn't make progress or has nothing more to send.
+                    self.release_request(peer_id, mod_type);
+                } else {
+                    req.remaining = HashSet::new();
+                    let rest = rest.into_iter().collect::<Vec<_>>();
+                    self.on_request_sent(peer_id, mod_type, &rest);
+                    self.outbox.push_back(DiffusionBehaviourOut::Send {
+                        peer_id,
+                        message: DiffusionMessage::request_modifiers_v1(mod_type, rest),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Give up on the modifiers requested from the peer, so that they can be requested elsewhere.
+    /// Delivered modifiers are marked received once decoded.
+    fn release_request(&mut self, peer_id: PeerId, mod_type: ModifierType) {
+        if let Some(req) = self.requests.remove(&(peer_id, mod_type)) {
+            for mid in req.remaining {
+                if let ModifierStatus::Requested(_) = self.delivery.status(&mid) {
+                    self.delivery.set_status(mid, ModifierStatus::Wanted);
+                }
+            }
         }
     }
 
     fn on_sync(&mut self, peer_id: PeerId, peer_status: SyncStatus, initial: bool) {
         let service = self.remote_sync.clone();
         let conf = self.conf;
-        self.tasks.spawn(|to_behaviour| async move {
+        let history = self.history.clone();
+        let bootstrap_from = self
+            .checkpoint_sync
+            .as_ref()
+            .filter(|sync| !matches!(sync.state, Bootstrap::Done))
+            .map(|sync| sync.conf.checkpoint);
+        self.tasks.spawn(|to_behaviour, _| async move {
             let peer_state = service.remote_state(peer_status).await;
             to_behaviour.update_peer(peer_id, peer_state.clone()).await;
             if initial {
@@ -195,6 +713,18 @@ where
                     .await
                     .unwrap();
             }
+            if let Some(checkpoint) = bootstrap_from {
+                if matches!(peer_state.cmp, RemoteChainCmp::Longer(_)) && !history.member(&checkpoint).await {
+                    // The chain is synced from the checkpoint on once the snapshot is installed.
+                    to_behaviour
+                        .send(FromTask::ToBehaviour(DiffusionBehaviourIn::SnapshotWanted {
+                            peer_id,
+                        }))
+                        .await
+                        .unwrap();
+                    return;
+                }
+            }
             match peer_state.cmp {
                 RemoteChainCmp::Equal | RemoteChainCmp::Nonsense => {}
                 RemoteChainCmp::Longer(None) | RemoteChainCmp::Fork(None) => {
@@ -238,20 +768,62 @@ where
         })
     }
 
+    /// Responses are bounded in size for peers speaking V2, which request the remainder by continuation.
     fn on_modifiers_request(&mut self, peer_id: PeerId, mod_type: ModifierType, modifiers: Vec<ModifierId>) {
         let service = self.remote_sync.clone();
-        self.tasks.spawn(|to_behaviour| async move {
-            let raw_modifiers = service.get_modifiers(mod_type, modifiers).await;
+        let max_bytes = self.conf.max_modifiers_response_bytes;
+        let bounded = self.peer_version(&peer_id) == DiffusionSpec::v2();
+        self.tasks.spawn(|to_behaviour, _| async move {
+            let message = if bounded {
+                let (raw_modifiers, continuation) = service
+                    .get_modifiers_bounded(mod_type, modifiers, max_bytes)
+                    .await;
+                DiffusionMessage::modifiers_v2(mod_type, raw_modifiers, continuation)
+            } else {
+                DiffusionMessage::modifiers_v1(mod_type, service.get_modifiers(mod_type, modifiers).await)
+            };
             to_behaviour
                 .send(FromTask::ToHandler(ProtocolBehaviourOut::Send {
                     peer_id,
-                    message: DiffusionMessage::modifiers_v1(mod_type, raw_modifiers),
+                    message,
                 }))
                 .await
                 .unwrap();
         })
     }
 
+    fn peer_version(&self, peer_id: &PeerId) -> ProtocolVer {
+        self.peer_versions
+            .get(peer_id)
+            .copied()
+            .unwrap_or_else(DiffusionSpec::v1)
+    }
+
+    /// Messages are built in V1 unless they need V2, and sent in the version negotiated with the peer.
+    fn in_peer_version(&self, out: DiffusionBehaviourOut) -> DiffusionBehaviourOut {
+        match out {
+            ProtocolBehaviourOut::Send { peer_id, message } => ProtocolBehaviourOut::Send {
+                peer_id,
+                message: message.into_version(self.peer_version(&peer_id)),
+            },
+            out => out,
+        }
+    }
+
+    fn on_modifiers_response(
+        &mut self,
+        peer_id: PeerId,
+        Modifiers { mod_type, modifiers }: Modifiers<SerializedModifier>,
+        continuation: Option<Continuation>,
+    ) {
+        self.on_response(peer_id, mod_type, modifiers.len(), continuation);
+        if mod_type == ModifierType::BlockBody {
+            self.on_bodies(peer_id, modifiers)
+        } else {
+            self.on_modifiers(peer_id, mod_type, modifiers)
+        }
+    }
+
     fn on_modifiers(
         &mut self,
         peer_id: PeerId,
@@ -259,7 +831,8 @@ where
         raw_modifiers: Vec<SerializedModifier>,
     ) {
         let ledger_view = self.ledger_view.clone();
-        self.tasks.spawn(|to_behaviour| async move {
+        let history = self.history.clone();
+        self.tasks.spawn(|to_behaviour, cancellation| async move {
             let mut modifiers = vec![];
             for m in raw_modifiers {
                 if let Ok(md) = decode_modifier(mod_type, &m) {
@@ -277,15 +850,76 @@ where
                     break;
                 }
             }
-            stream::iter(modifiers)
-                .then(|md| {
-                    let mut ledger = ledger_view.clone();
-                    async move { ledger.apply_modifier(md).await }
+            let bodies = modifiers
+                .iter()
+                .filter_map(|md| match md {
+                    Modifier::BlockHeader(hdr) => Some(ModifierId::from(hdr.body.block_body_root)),
+                    _ => None,
                 })
-                .collect::<Vec<_>>()
-                .await;
+                .collect::<Vec<_>>();
+            let bodies = select_wanted(&history, &to_behaviour, bodies).await;
+            if !bodies.is_empty() {
+                to_behaviour
+                    .send(FromTask::ToBehaviour(DiffusionBehaviourIn::BodiesWanted {
+                        bodies,
+                    }))
+                    .await
+                    .unwrap();
+            }
+            apply_modifiers(ledger_view, modifiers, peer_id, cancellation).await;
         })
     }
+
+    /// Accept bodies delivered in response to requests of the download scheduler.
+    /// Peer is punished for malformed bodies and ones it wasn't asked for.
+    fn on_bodies(&mut self, peer_id: PeerId, raw_bodies: Vec<SerializedModifier>) {
+        let mut accepted = vec![];
+        for raw in raw_bodies {
+            let delivery = decode_modifier(ModifierType::BlockBody, &raw).map(|md| {
+                let mid = md.id();
+                (self.downloads.on_delivered(peer_id, mid), mid, md)
+            });
+            match delivery {
+                Ok((Delivery::Accepted, mid, md)) => {
+                    self.delivery.set_status(mid, ModifierStatus::Received);
+                    accepted.push(md);
+                }
+                Ok((Delivery::Redundant, _, _)) => {}
+                Ok((Delivery::Unsolicited, _, _)) | Err(_) => {
+                    self.downloads.release_peer(peer_id);
+                    self.outbox
+                        .push_back(DiffusionBehaviourOut::NetworkAction(NetworkAction::ReportPeer(
+                            peer_id,
+                            ReputationChange::InvalidModifier,
+                        )));
+                    break;
+                }
+            }
+        }
+        self.update_memory_usage();
+        let ledger_view = self.ledger_view.clone();
+        self.tasks.spawn(|_, cancellation| async move {
+            apply_modifiers(ledger_view, accepted, peer_id, cancellation).await;
+        });
+        self.schedule_downloads();
+    }
+}
+
+/// Apply modifiers one by one, stopping in between them once cancelled.
+async fn apply_modifiers<TLedgerView: NodeViewWriteAsync>(
+    ledger_view: TLedgerView,
+    modifiers: Vec<Modifier>,
+    peer_id: PeerId,
+    cancellation: CancellationToken,
+) {
+    stream::iter(modifiers)
+        .take_while(|_| future::ready(!cancellation.is_cancelled()))
+        .then(|md| {
+            let mut ledger = ledger_view.clone();
+            async move { ledger.apply_modifier(md, ModifierSource::Remote(peer_id)).await }
+        })
+        .collect::<Vec<_>>()
+        .await;
 }
 
 /// Select desired modifiers from the given list of proposed modifiers.
@@ -322,59 +956,112 @@ fn decode_modifier(
         ModifierType::Transaction => {
             ciborium::de::from_reader::<Transaction, _>(&bf[..]).map(|h| Modifier::from(h))
         }
+        ModifierType::TxPackage => {
+            ciborium::de::from_reader::<TxPackage, _>(&bf[..]).map(|h| Modifier::from(h))
+        }
     };
     res.map_err(|_| ())
 }
 
-impl<'a, THeader, THistory, TLedgerView> ProtocolBehaviour
-    for DiffusionBehaviour<'a, THeader, THistory, TLedgerView>
+impl<'a, THeader, THistory, TMempool, TLedgerView> ProtocolBehaviour
+    for DiffusionBehaviour<'a, THeader, THistory, TMempool, TLedgerView>
 where
     THeader: HeaderLike + 'a,
     THistory: LedgerHistoryReadAsync<THeader> + 'a,
+    TMempool: MempoolReadAsync + 'a,
     TLedgerView: NodeViewWriteAsync + 'a,
 {
     type TProto = DiffusionSpec;
 
-    fn inject_message(
-        &mut self,
-        peer_id: PeerId,
-        DiffusionMessage::DiffusionMessageV1(msg): DiffusionMessage,
-    ) {
+    fn inject_cancelled(&mut self) {
+        self.tasks.cancel();
+        self.requests.clear();
+        self.downloads = DownloadScheduler::new(
+            self.conf.max_body_requests_per_peer,
+            self.conf.modifiers_request_timeout,
+        );
+        self.outbox.clear();
+    }
+
+    fn inject_message(&mut self, peer_id: PeerId, msg: DiffusionMessage) {
+        let msg = match msg {
+            DiffusionMessage::DiffusionMessageV1(msg) => msg,
+            DiffusionMessage::DiffusionMessageV2(DiffusionMessageV2::Modifiers(modifiers, continuation)) => {
+                return self.on_modifiers_response(peer_id, modifiers, continuation);
+            }
+            DiffusionMessage::DiffusionMessageV2(msg) => DiffusionMessageV1::from(msg),
+        };
         match msg {
             DiffusionMessageV1::Inv(Modifiers { mod_type, modifiers }) => {
                 let history = self.history.clone();
-                self.tasks.spawn(|to_behaviour| async move {
+                self.tasks.spawn(|to_behaviour, _| async move {
                     let wanted = select_wanted(&history, &to_behaviour, modifiers).await;
-                    if !wanted.is_empty() {
-                        to_behaviour
-                            .send(FromTask::ToHandler(DiffusionBehaviourOut::Send {
-                                peer_id,
-                                message: DiffusionMessage::request_modifiers_v1(mod_type, wanted),
-                            }))
-                            .await
-                            .unwrap();
+                    if wanted.is_empty() {
+                        return;
                     }
+                    let out = if mod_type == ModifierType::BlockBody {
+                        // Bodies are downloaded by the scheduler, not necessarily from the announcer.
+                        FromTask::ToBehaviour(DiffusionBehaviourIn::BodiesWanted { bodies: wanted })
+                    } else {
+                        FromTask::ToHandler(DiffusionBehaviourOut::Send {
+                            peer_id,
+                            message: DiffusionMessage::request_modifiers_v1(mod_type, wanted),
+                        })
+                    };
+                    to_behaviour.send(out).await.unwrap();
                 })
             }
             DiffusionMessageV1::RequestModifiers(Modifiers { mod_type, modifiers }) => {
                 self.on_modifiers_request(peer_id, mod_type, modifiers)
             }
-            DiffusionMessageV1::Modifiers(Modifiers { mod_type, modifiers }) => {
-                self.on_modifiers(peer_id, mod_type, modifiers)
-            }
+            DiffusionMessageV1::Modifiers(modifiers) => self.on_modifiers_response(peer_id, modifiers, None),
             DiffusionMessageV1::SyncStatus(status) => self.on_sync(peer_id, status, false),
+            DiffusionMessageV1::RequestSnapshot(block_id) => self.on_snapshot_request(peer_id, block_id),
+            DiffusionMessageV1::Snapshot(block_id, snapshot) => self.on_snapshot(peer_id, block_id, snapshot),
+            DiffusionMessageV1::RequestSnapshotChunk(chunk_ref) => {
+                self.on_snapshot_chunk_request(peer_id, chunk_ref)
+            }
+            DiffusionMessageV1::SnapshotChunk(chunk_ref, chunk) => {
+                self.on_snapshot_chunk(peer_id, chunk_ref, chunk)
+            }
         }
     }
 
-    fn inject_protocol_requested(&mut self, peer_id: PeerId, handshake: Option<DiffusionHandshake>) {
-        if let Some(DiffusionHandshake::HandshakeV1(HandshakeV1(status))) = handshake {
+    fn inject_protocol_requested(
+        &mut self,
+        peer_id: PeerId,
+        protocol_ver: ProtocolVer,
+        handshake: Option<DiffusionHandshake>,
+    ) {
+        self.peer_versions.insert(peer_id, protocol_ver);
+        if let Some(
+            DiffusionHandshake::HandshakeV1(HandshakeV1(status))
+            | DiffusionHandshake::HandshakeV2(HandshakeV1(status)),
+        ) = handshake
+        {
             self.on_sync(peer_id, status, true)
         }
     }
 
+    fn inject_protocol_enabled(
+        &mut self,
+        peer_id: PeerId,
+        protocol_ver: ProtocolVer,
+        _handshake: Option<DiffusionHandshake>,
+    ) {
+        self.peer_versions.insert(peer_id, protocol_ver);
+    }
+
+    fn inject_protocol_disabled(&mut self, peer_id: PeerId) {
+        self.peers.remove(&peer_id);
+        self.peer_versions.remove(&peer_id);
+        self.downloads.release_peer(peer_id);
+        self.schedule_downloads();
+    }
+
     fn inject_protocol_requested_locally(&mut self, peer_id: PeerId) {
         let service = self.remote_sync.clone();
-        self.tasks.spawn(|to_behaviour| async move {
+        self.tasks.spawn(|to_behaviour, _| async move {
             to_behaviour
                 .send(FromTask::ToHandler(DiffusionBehaviourOut::NetworkAction(
                     NetworkAction::EnablePeer {
@@ -402,6 +1089,16 @@ where
                 Poll::Ready(Some(out)) => match out {
                     FromTask::ToBehaviour(input) => self.on_event(input),
                     FromTask::ToHandler(out) => {
+                        if let DiffusionBehaviourOut::Send {
+                            peer_id,
+                            message:
+                                DiffusionMessage::DiffusionMessageV1(DiffusionMessageV1::RequestModifiers(
+                                    Modifiers { mod_type, modifiers },
+                                )),
+                        } = &out
+                        {
+                            self.on_request_sent(*peer_id, *mod_type, modifiers);
+                        }
                         self.outbox.push_back(out);
                         break;
                     }
@@ -410,7 +1107,7 @@ where
             }
         }
         if let Some(out) = self.outbox.pop_front() {
-            return Poll::Ready(Some(out));
+            return Poll::Ready(Some(self.in_peer_version(out)));
         }
         Poll::Pending
     }
@@ -421,19 +1118,28 @@ mod tests {
     use std::sync::Arc;
     use std::time::Duration;
 
+    use async_std::sync::RwLock;
     use async_std::{future, task};
     use futures::channel::mpsc;
     use futures::StreamExt;
     use libp2p_identity::PeerId;
 
     use spectrum_ledger::block::BlockId;
-    use spectrum_ledger::{ModifierId, ModifierType, SlotNo};
+    use spectrum_ledger::{ModifierId, ModifierType, SerializedModifier, SlotNo};
+    use spectrum_network::protocol_handler::versioning::Versioned;
     use spectrum_network::protocol_handler::{BehaviourStream, ProtocolBehaviour, ProtocolBehaviourOut};
-    use spectrum_view::node_view::NodeViewMailbox;
+    use spectrum_view::mempool::Mempool;
+    use spectrum_view::node_view::{ModifierSource, NodeViewMailbox};
 
-    use crate::behaviour::{DiffusionBehaviour, DiffusionConfig};
-    use crate::message::{DiffusionHandshake, DiffusionMessage, HandshakeV1, SyncStatus};
-    use crate::service::tests::{EphemeralHistory, Header};
+    use crate::behaviour::{
+        DiffusionBehaviour, DiffusionBehaviourIn, DiffusionConfig, ModifierStatus, ModifierTracker,
+    };
+    use crate::message::{
+        Continuation, DiffusionHandshake, DiffusionMessage, DiffusionMessageV1, DiffusionSpec, HandshakeV1,
+        Modifiers, SyncStatus,
+    };
+    use crate::service::tests::{empty_mempool, EphemeralHistory, Header};
+    use crate::service::{RemoteChainCmp, SyncState};
 
     #[async_std::test]
     async fn process_inv() {
@@ -485,7 +1191,7 @@ mod tests {
         let remote_pid = PeerId::random();
         let remote_hs = DiffusionHandshake::HandshakeV1(HandshakeV1(remote_ss));
 
-        beh.inject_protocol_requested(remote_pid, Some(remote_hs));
+        beh.inject_protocol_requested(remote_pid, remote_hs.version(), Some(remote_hs));
 
         let handle = task::spawn(async move {
             let mut stream = BehaviourStream::new(beh);
@@ -512,20 +1218,150 @@ mod tests {
         assert_eq!(msg, expected_msg);
     }
 
+    #[async_std::test]
+    async fn request_remaining_modifiers() {
+        let mut beh = make_behaviour(make_chain(16));
+        let requested = (0..4)
+            .map(|_| ModifierId::from(BlockId::random()))
+            .collect::<Vec<_>>();
+        let remote_pid = PeerId::random();
+        beh.on_request_sent(remote_pid, ModifierType::BlockHeader, &requested);
+        let partial_response = DiffusionMessage::modifiers_v2(
+            ModifierType::BlockHeader,
+            vec![SerializedModifier(vec![])],
+            Some(Continuation(requested[1..].to_vec())),
+        );
+        beh.inject_message(remote_pid, partial_response);
+        let handle = task::spawn(async move {
+            let mut stream = BehaviourStream::new(beh);
+            loop {
+                match stream.select_next_some().await {
+                    ProtocolBehaviourOut::Send { peer_id, message } => {
+                        return (peer_id, message);
+                    }
+                    ProtocolBehaviourOut::NetworkAction(_) => {}
+                }
+            }
+        });
+        let (peer, msg) = future::timeout(Duration::from_secs(5), handle).await.unwrap();
+        assert_eq!(peer, remote_pid);
+        let DiffusionMessage::DiffusionMessageV1(DiffusionMessageV1::RequestModifiers(Modifiers {
+            mod_type,
+            mut modifiers,
+        })) = msg
+        else {
+            panic!("Expected a request for the remaining modifiers");
+        };
+        assert_eq!(mod_type, ModifierType::BlockHeader);
+        let mut expected = requested[1..].to_vec();
+        modifiers.sort();
+        expected.sort();
+        assert_eq!(modifiers, expected);
+    }
+
+    #[async_std::test]
+    async fn undelivered_modifiers_released_on_final_response() {
+        let mut beh = make_behaviour(make_chain(16));
+        let requested = (0..4)
+            .map(|_| ModifierId::from(BlockId::random()))
+            .collect::<Vec<_>>();
+        let remote_pid = PeerId::random();
+        beh.on_request_sent(remote_pid, ModifierType::BlockHeader, &requested);
+        beh.inject_message(
+            remote_pid,
+            DiffusionMessage::modifiers_v1(ModifierType::BlockHeader, vec![]),
+        );
+        assert!(beh.requests.is_empty());
+        for mid in &requested {
+            assert_eq!(beh.delivery.status(mid), ModifierStatus::Wanted);
+        }
+    }
+
+    #[async_std::test]
+    async fn modifiers_sent_in_negotiated_version() {
+        let mut beh = make_behaviour(make_chain(16));
+        let remote_pid = PeerId::random();
+        beh.inject_protocol_enabled(remote_pid, DiffusionSpec::v2(), None);
+        let announced = ModifierId::random();
+        beh.outbox.push_back(ProtocolBehaviourOut::Send {
+            peer_id: remote_pid,
+            message: DiffusionMessage::inv_v1(ModifierType::Transaction, vec![announced]),
+        });
+        let handle = task::spawn(async move {
+            let mut stream = BehaviourStream::new(beh);
+            loop {
+                if let ProtocolBehaviourOut::Send { peer_id, message } = stream.select_next_some().await {
+                    return (peer_id, message);
+                }
+            }
+        });
+        let (peer_id, message) = future::timeout(Duration::from_secs(5), handle).await.unwrap();
+        assert_eq!(peer_id, remote_pid);
+        assert_eq!(message.version(), DiffusionSpec::v2());
+    }
+
+    #[async_std::test]
+    async fn announce_package_to_other_peers() {
+        let mut beh = make_behaviour(make_chain(16));
+        let origin = PeerId::random();
+        let other = PeerId::random();
+        for peer_id in [origin, other] {
+            beh.on_event(DiffusionBehaviourIn::UpdatePeer {
+                peer_id,
+                peer_state: SyncState {
+                    height: SlotNo::from(15),
+                    cmp: RemoteChainCmp::Shorter(BlockId::random()),
+                },
+            });
+        }
+        let pkg_id = ModifierId::random();
+        beh.mailbox().announce(
+            ModifierType::TxPackage,
+            vec![pkg_id],
+            ModifierSource::Remote(origin),
+        );
+        let handle = task::spawn(async move {
+            let mut stream = BehaviourStream::new(beh);
+            let mut sent = vec![];
+            while sent.len() < 2 {
+                if let Ok(ProtocolBehaviourOut::Send { peer_id, message }) =
+                    future::timeout(Duration::from_millis(500), stream.select_next_some()).await
+                {
+                    sent.push((peer_id, message));
+                } else {
+                    break;
+                }
+            }
+            sent
+        });
+        let sent = handle.await;
+        assert_eq!(
+            sent,
+            vec![(
+                other,
+                DiffusionMessage::inv_v1(ModifierType::TxPackage, vec![pkg_id])
+            )]
+        );
+    }
+
     fn make_behaviour(
         chain: Vec<Header>,
-    ) -> DiffusionBehaviour<'static, Header, EphemeralHistory, NodeViewMailbox> {
+    ) -> DiffusionBehaviour<'static, Header, EphemeralHistory, RwLock<Mempool>, NodeViewMailbox> {
         let history = Arc::new(EphemeralHistory {
             db: chain.into_iter().map(|hdr| (hdr.id, hdr)).collect(),
+            finalized: None,
         });
 
         let conf = DiffusionConfig {
             max_inv_size: 9182,
             task_timeout: Duration::from_secs(5),
+            max_modifiers_response_bytes: 1 << 20,
+            modifiers_request_timeout: Duration::from_secs(5),
+            max_body_requests_per_peer: 16,
         };
         let (snd, recv) = mpsc::channel(100);
         let lv = NodeViewMailbox::new(snd);
-        DiffusionBehaviour::new(conf, history, lv)
+        DiffusionBehaviour::new(conf, history, empty_mempool(), lv)
     }
 
     fn make_chain(n: usize) -> Vec<Header> {
@@ -0,0 +1,77 @@
+//! Decode protocol messages recorded in the network event journal into human-readable form.
+//! The node must be run with recording of messages enabled in the journal configuration.
+//!
+//! Usage: netinspect <PATH> [--peer <PEER_ID>] [--protocol <PROTOCOL_ID>]
+//!                          [--since <UNIX_MILLIS>] [--until <UNIX_MILLIS>] [--follow]
+//!
+//! With `--follow` the journal of a running node is watched for new messages.
+//!
+//! Lives here rather than in `spectrum-network` as this crate sees message types of all protocols.
+
+use std::error::Error;
+use std::time::Duration;
+
+use spectrum_diffusion::message::DiffusionSpec;
+use spectrum_network::journal::{read_journal_from, EventSource, JournalFilter, JournalRecord};
+use spectrum_network::protocol::DIFFUSION_PROTOCOL_ID;
+use spectrum_network::protocol_handler::inspect::MessageInspector;
+use spectrum_network::types::{ProtocolId, ProtocolVer};
+
+const FOLLOW_INTERVAL: Duration = Duration::from_secs(1);
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = std::env::args().skip(1);
+    let path = args.next().ok_or("Path to the journal is required")?;
+    let mut filter = JournalFilter {
+        source: Some(EventSource::InboundMessage),
+        ..JournalFilter::default()
+    };
+    let mut follow = false;
+    while let Some(flag) = args.next() {
+        if flag == "--follow" {
+            follow = true;
+            continue;
+        }
+        let value = args.next().ok_or(format!("Value for {} is missing", flag))?;
+        match flag.as_str() {
+            "--peer" => filter.peer_id = Some(value),
+            "--protocol" => filter.protocol = Some(ProtocolId::from(value.parse::<u8>()?)),
+            "--since" => filter.since = Some(value.parse()?),
+            "--until" => filter.until = Some(value.parse()?),
+            _ => return Err(format!("Unknown flag {}", flag).into()),
+        }
+    }
+    let inspector = MessageInspector::new()
+        .with_builtin_protocols()
+        .with_protocol::<DiffusionSpec>(DIFFUSION_PROTOCOL_ID, "diffusion");
+    let mut next_seq = 0;
+    loop {
+        let (records, written) = read_journal_from(&path, &filter, next_seq)?;
+        for rec in records {
+            print_message(&inspector, rec);
+        }
+        next_seq = written;
+        if !follow {
+            return Ok(());
+        }
+        std::thread::sleep(FOLLOW_INTERVAL);
+    }
+}
+
+fn print_message(inspector: &MessageInspector, rec: JournalRecord) {
+    let peer = rec.peer_id.as_deref().unwrap_or("-");
+    match rec.frame {
+        Some(frame) if !frame.truncated => {
+            let inspection = inspector.inspect(frame.protocol, ProtocolVer(frame.version), &frame.bytes);
+            println!(
+                "{} {} /{}/{} {}",
+                rec.timestamp,
+                peer,
+                u8::from(frame.protocol),
+                frame.version,
+                inspection
+            );
+        }
+        _ => println!("{} {} {} [content not recorded]", rec.timestamp, peer, rec.event),
+    }
+}
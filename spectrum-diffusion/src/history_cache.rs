@@ -0,0 +1,423 @@
+//! Read-through cache of block sections served to peers.
+//!
+//! Peers syncing from the node tend to request the same recent headers and bodies at about the
+//! same time. [`CachedHistory`] keeps recently served sections in an LRU cache bounded in bytes
+//! and coalesces concurrent lookups of the same sections into a single read of the store.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use futures::future::{join_all, BoxFuture, Shared};
+use futures::FutureExt;
+use nonempty::NonEmpty;
+
+use spectrum_ledger::block::{BlockId, BlockSectionType};
+use spectrum_ledger::{ModifierId, ModifierRecord, SerializedModifier};
+use spectrum_network::memory_budget::{MemoryQuota, Shrink};
+use spectrum_network::metrics::{Labels, Metric, MetricsSink};
+use spectrum_view::chain::HeaderLike;
+use spectrum_view::finality::Checkpoint;
+use spectrum_view::history::LedgerHistoryReadAsync;
+
+type Key = (BlockSectionType, ModifierId);
+
+/// Sections fetched from the store by a single read, `None` if the section wasn't found.
+type Fetch = Shared<BoxFuture<'static, Arc<HashMap<ModifierId, Option<SerializedModifier>>>>>;
+
+/// Approximate overhead of a cache entry on top of the serialized section.
+const ENTRY_OVERHEAD: usize = std::mem::size_of::<(Key, SerializedModifier, u64)>() * 2;
+
+fn entry_size(modifier: &SerializedModifier) -> usize {
+    modifier.0.len() + ENTRY_OVERHEAD
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct HistoryCacheConfig {
+    /// Max bytes occupied by cached sections.
+    pub max_bytes: usize,
+}
+
+/// Sections ordered from the least recently used one.
+#[derive(Default)]
+struct LruCache {
+    entries: HashMap<Key, (SerializedModifier, u64)>,
+    order: BTreeMap<u64, Key>,
+    tick: u64,
+    used: usize,
+}
+
+impl LruCache {
+    fn get(&mut self, key: &Key) -> Option<SerializedModifier> {
+        let (modifier, last_used) = self.entries.get_mut(key)?;
+        self.order.remove(last_used);
+        self.tick += 1;
+        *last_used = self.tick;
+        self.order.insert(self.tick, *key);
+        Some(modifier.clone())
+    }
+
+    fn insert(&mut self, key: Key, modifier: SerializedModifier) {
+        self.remove(&key);
+        self.tick += 1;
+        self.used += entry_size(&modifier);
+        self.order.insert(self.tick, key);
+        self.entries.insert(key, (modifier, self.tick));
+    }
+
+    fn remove(&mut self, key: &Key) -> usize {
+        if let Some((modifier, last_used)) = self.entries.remove(key) {
+            self.order.remove(&last_used);
+            let size = entry_size(&modifier);
+            self.used -= size;
+            size
+        } else {
+            0
+        }
+    }
+
+    /// Evict least recently used sections until at most `max_bytes` are occupied.
+    fn evict_to(&mut self, max_bytes: usize) -> usize {
+        let mut freed = 0;
+        while self.used > max_bytes {
+            let Some((_, key)) = self.order.pop_first() else {
+                break;
+            };
+            freed += self.remove(&key);
+        }
+        freed
+    }
+}
+
+impl Shrink for LruCache {
+    fn shrink(&mut self, bytes: usize) -> usize {
+        self.evict_to(self.used.saturating_sub(bytes))
+    }
+}
+
+struct CacheState {
+    conf: HistoryCacheConfig,
+    cache: LruCache,
+    in_flight: HashMap<Key, Fetch>,
+    memory_quota: Option<MemoryQuota>,
+}
+
+impl CacheState {
+    fn insert(&mut self, key: Key, modifier: SerializedModifier) {
+        if entry_size(&modifier) > self.conf.max_bytes {
+            return;
+        }
+        self.cache.insert(key, modifier);
+        self.cache.evict_to(self.conf.max_bytes);
+        if let Some(quota) = &self.memory_quota {
+            quota.update(self.cache.used);
+            quota.apply_pressure(&mut self.cache);
+        }
+    }
+}
+
+/// [`LedgerHistoryReadAsync`] caching sections returned by [`multi_get_raw`](LedgerHistoryReadAsync::multi_get_raw).
+/// The rest of the queries are passed through to the underlying history as is.
+pub struct CachedHistory<THistory> {
+    history: Arc<THistory>,
+    state: Arc<Mutex<CacheState>>,
+    metrics: Option<Arc<dyn MetricsSink>>,
+}
+
+impl<THistory> CachedHistory<THistory> {
+    pub fn new(conf: HistoryCacheConfig, history: Arc<THistory>) -> Self {
+        Self {
+            history,
+            state: Arc::new(Mutex::new(CacheState {
+                conf,
+                cache: LruCache::default(),
+                in_flight: HashMap::new(),
+                memory_quota: None,
+            })),
+            metrics: None,
+        }
+    }
+
+    /// Report hits and misses of the cache to the given sink.
+    pub fn with_metrics(mut self, metrics: Arc<dyn MetricsSink>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Account memory occupied by the cache within the given quota.
+    /// Least recently used sections are evicted once the quota is exceeded.
+    pub fn with_memory_quota(self, quota: MemoryQuota) -> Self {
+        self.state.lock().unwrap().memory_quota = Some(quota);
+        self
+    }
+
+    /// Bytes occupied by cached sections.
+    pub fn used(&self) -> usize {
+        self.state.lock().unwrap().cache.used
+    }
+
+    fn report(&self, metric: Metric, sec_type: BlockSectionType, value: usize) {
+        if let Some(metrics) = &self.metrics {
+            if value > 0 {
+                metrics.inc_counter(metric, section_label(sec_type), value as u64);
+            }
+        }
+    }
+}
+
+fn section_label(sec_type: BlockSectionType) -> Labels {
+    let section = match sec_type {
+        BlockSectionType::Header => "header",
+        BlockSectionType::Body => "body",
+    };
+    vec![("section", section.to_string())]
+}
+
+impl<THistory> CachedHistory<THistory> {
+    /// Read the given sections from the store and cache them once done.
+    fn fetch<H>(&self, sec_type: BlockSectionType, ids: Vec<ModifierId>) -> Fetch
+    where
+        H: HeaderLike,
+        THistory: LedgerHistoryReadAsync<H> + 'static,
+    {
+        let history = Arc::clone(&self.history);
+        let state = Arc::clone(&self.state);
+        async move {
+            let found = history.multi_get_raw(sec_type, ids.clone()).await;
+            // Missing sections are skipped by the store, so sections can be matched with
+            // their ids only when all of them are found. Otherwise look them up one by one.
+            let fetched = if found.len() == ids.len() {
+                ids.into_iter().zip(found.into_iter().map(Some)).collect()
+            } else if let [id] = ids[..] {
+                vec![(id, None)]
+            } else {
+                let mut fetched = Vec::with_capacity(ids.len());
+                for id in ids {
+                    let modifier = history.multi_get_raw(sec_type, vec![id]).await.pop();
+                    fetched.push((id, modifier));
+                }
+                fetched
+            };
+            let mut state = state.lock().unwrap();
+            // Sections are cached in the order they were requested in.
+            for (id, modifier) in &fetched {
+                state.in_flight.remove(&(sec_type, *id));
+                if let Some(modifier) = modifier {
+                    state.insert((sec_type, *id), modifier.clone());
+                }
+            }
+            Arc::new(fetched.into_iter().collect::<HashMap<_, _>>())
+        }
+        .boxed()
+        .shared()
+    }
+}
+
+#[async_trait]
+impl<H, THistory> LedgerHistoryReadAsync<H> for CachedHistory<THistory>
+where
+    H: HeaderLike + 'static,
+    THistory: LedgerHistoryReadAsync<H> + 'static,
+{
+    async fn member(&self, id: &BlockId) -> bool {
+        self.history.member(id).await
+    }
+
+    async fn contains(&self, id: &ModifierId) -> bool {
+        self.history.contains(id).await
+    }
+
+    async fn get_tip(&self) -> ModifierRecord<H> {
+        self.history.get_tip().await
+    }
+
+    async fn get_last_finalized(&self) -> Option<Checkpoint> {
+        self.history.get_last_finalized().await
+    }
+
+    async fn get_tail(&self, n: usize) -> NonEmpty<ModifierRecord<H>> {
+        self.history.get_tail(n).await
+    }
+
+    async fn follow(&self, pre_start: BlockId, cap: usize) -> Vec<BlockId> {
+        self.history.follow(pre_start, cap).await
+    }
+
+    async fn multi_get_raw(
+        &self,
+        sec_type: BlockSectionType,
+        ids: Vec<ModifierId>,
+    ) -> Vec<SerializedModifier> {
+        let mut cached = HashMap::new();
+        let mut pending = Vec::new();
+        let mut misses = Vec::new();
+        let mut coalesced = 0;
+        {
+            let mut state = self.state.lock().unwrap();
+            for id in &ids {
+                let key = (sec_type, *id);
+                if cached.contains_key(id) {
+                    continue;
+                }
+                if let Some(modifier) = state.cache.get(&key) {
+                    cached.insert(*id, modifier);
+                } else if let Some(fetch) = state.in_flight.get(&key) {
+                    if !pending.iter().any(|f: &Fetch| f.ptr_eq(fetch)) {
+                        pending.push(fetch.clone());
+                    }
+                    coalesced += 1;
+                } else if !misses.contains(id) {
+                    misses.push(*id);
+                }
+            }
+            if !misses.is_empty() {
+                let fetch = self.fetch::<H>(sec_type, misses.clone());
+                for id in &misses {
+                    state.in_flight.insert((sec_type, *id), fetch.clone());
+                }
+                pending.push(fetch);
+            }
+        }
+        self.report(Metric::HistoryCacheHits, sec_type, cached.len());
+        self.report(Metric::HistoryCacheMisses, sec_type, misses.len());
+        self.report(Metric::HistoryCacheCoalesced, sec_type, coalesced);
+        for fetched in join_all(pending).await {
+            for (id, modifier) in fetched.iter() {
+                if let Some(modifier) = modifier {
+                    cached.entry(*id).or_insert_with(|| modifier.clone());
+                }
+            }
+        }
+        ids.iter().filter_map(|id| cached.get(id).cloned()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use nonempty::NonEmpty;
+
+    use spectrum_ledger::block::{BlockId, BlockSectionType};
+    use spectrum_ledger::{ModifierId, ModifierRecord, SerializedModifier, SlotNo};
+    use spectrum_network::memory_budget::MemoryBudget;
+    use spectrum_view::finality::Checkpoint;
+    use spectrum_view::history::LedgerHistoryReadAsync;
+
+    use crate::history_cache::{entry_size, CachedHistory, HistoryCacheConfig};
+    use crate::service::tests::{EphemeralHistory, Header, RAW_HEADER_SIZE};
+
+    /// History counting reads of block sections.
+    struct CountingHistory {
+        inner: EphemeralHistory,
+        reads: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl LedgerHistoryReadAsync<Header> for CountingHistory {
+        async fn member(&self, id: &BlockId) -> bool {
+            self.inner.member(id).await
+        }
+
+        async fn contains(&self, id: &ModifierId) -> bool {
+            self.inner.contains(id).await
+        }
+
+        async fn get_tip(&self) -> ModifierRecord<Header> {
+            self.inner.get_tip().await
+        }
+
+        async fn get_last_finalized(&self) -> Option<Checkpoint> {
+            self.inner.get_last_finalized().await
+        }
+
+        async fn get_tail(&self, n: usize) -> NonEmpty<ModifierRecord<Header>> {
+            self.inner.get_tail(n).await
+        }
+
+        async fn follow(&self, pre_start: BlockId, cap: usize) -> Vec<BlockId> {
+            self.inner.follow(pre_start, cap).await
+        }
+
+        async fn multi_get_raw(
+            &self,
+            sec_type: BlockSectionType,
+            ids: Vec<ModifierId>,
+        ) -> Vec<SerializedModifier> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            async_std::task::yield_now().await;
+            self.inner.multi_get_raw(sec_type, ids).await
+        }
+    }
+
+    fn history(blocks: usize) -> (Arc<CountingHistory>, Vec<ModifierId>) {
+        let headers = (0..blocks)
+            .map(|i| Header {
+                id: BlockId::random(),
+                slot: SlotNo::from(i as u64),
+            })
+            .collect::<Vec<_>>();
+        let ids = headers.iter().map(|hd| ModifierId::from(hd.id)).collect();
+        let history = CountingHistory {
+            inner: EphemeralHistory {
+                db: headers
+                    .into_iter()
+                    .map(|hd| (hd.id, hd))
+                    .collect::<HashMap<_, _>>(),
+                finalized: None,
+            },
+            reads: AtomicUsize::new(0),
+        };
+        (Arc::new(history), ids)
+    }
+
+    #[async_std::test]
+    async fn concurrent_requests_are_coalesced() {
+        let (history, ids) = history(4);
+        let cached = CachedHistory::new(HistoryCacheConfig { max_bytes: 1 << 20 }, Arc::clone(&history));
+        let (a, b) = futures::join!(
+            cached.multi_get_raw(BlockSectionType::Header, ids.clone()),
+            cached.multi_get_raw(BlockSectionType::Header, ids.clone())
+        );
+        assert_eq!(a.len(), 4);
+        assert_eq!(a, b);
+        assert_eq!(history.reads.load(Ordering::SeqCst), 1);
+        // Served from the cache now.
+        let c = cached.multi_get_raw(BlockSectionType::Header, ids).await;
+        assert_eq!(c, a);
+        assert_eq!(history.reads.load(Ordering::SeqCst), 1);
+    }
+
+    #[async_std::test]
+    async fn missing_sections_are_not_cached() {
+        let (history, mut ids) = history(2);
+        let cached = CachedHistory::new(HistoryCacheConfig { max_bytes: 1 << 20 }, Arc::clone(&history));
+        ids.push(ModifierId::random());
+        let found = cached.multi_get_raw(BlockSectionType::Header, ids.clone()).await;
+        assert_eq!(found.len(), 2);
+        let reads = history.reads.load(Ordering::SeqCst);
+        cached.multi_get_raw(BlockSectionType::Header, ids).await;
+        // Only the missing section is read again.
+        assert_eq!(history.reads.load(Ordering::SeqCst), reads + 1);
+    }
+
+    #[async_std::test]
+    async fn cache_stays_within_bounds() {
+        let (history, ids) = history(8);
+        let entry = entry_size(&SerializedModifier(vec![0; RAW_HEADER_SIZE]));
+        let budget = MemoryBudget::new(entry * 8);
+        let cached = CachedHistory::new(HistoryCacheConfig { max_bytes: entry * 4 }, Arc::clone(&history))
+            .with_memory_quota(budget.register("history_cache", entry * 2));
+        cached.multi_get_raw(BlockSectionType::Header, ids.clone()).await;
+        assert!(cached.used() <= entry * 2);
+        assert_eq!(budget.used(), cached.used());
+        // The most recently used sections survive.
+        let reads = history.reads.load(Ordering::SeqCst);
+        cached
+            .multi_get_raw(BlockSectionType::Header, ids[ids.len() - 1..].to_vec())
+            .await;
+        assert_eq!(history.reads.load(Ordering::SeqCst), reads);
+    }
+}
@@ -1,3 +1,5 @@
 pub mod behaviour;
+pub mod history_cache;
 pub mod message;
+mod scheduler;
 mod service;
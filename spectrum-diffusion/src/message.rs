@@ -2,14 +2,18 @@ use serde::{Deserialize, Serialize};
 
 use spectrum_ledger::block::BlockId;
 use spectrum_ledger::{ModifierId, ModifierType, SerializedModifier, SlotNo};
+use spectrum_network::peer_conn_handler::message_sink::MessagePriority;
 use spectrum_network::protocol_handler::versioning::Versioned;
 use spectrum_network::protocol_handler::ProtocolSpec;
 use spectrum_network::types::ProtocolVer;
+use spectrum_view::snapshot::{SignedSnapshot, SnapshotChunk};
 
 /// Sync handshake provides initial node status.
 #[derive(Serialize, Deserialize, Debug)]
 pub enum DiffusionHandshake {
     HandshakeV1(HandshakeV1),
+    /// Sync status is the same in V2.
+    HandshakeV2(HandshakeV1),
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -19,6 +23,7 @@ impl Versioned for DiffusionHandshake {
     fn version(&self) -> ProtocolVer {
         match self {
             DiffusionHandshake::HandshakeV1(_) => DiffusionSpec::v1(),
+            DiffusionHandshake::HandshakeV2(_) => DiffusionSpec::v2(),
         }
     }
 }
@@ -26,6 +31,7 @@ impl Versioned for DiffusionHandshake {
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub enum DiffusionMessage {
     DiffusionMessageV1(DiffusionMessageV1),
+    DiffusionMessageV2(DiffusionMessageV2),
 }
 
 impl DiffusionMessage {
@@ -44,9 +50,56 @@ impl DiffusionMessage {
         DiffusionMessage::DiffusionMessageV1(DiffusionMessageV1::Modifiers(Modifiers { mod_type, modifiers }))
     }
 
+    pub fn modifiers_v2(
+        mod_type: ModifierType,
+        modifiers: Vec<SerializedModifier>,
+        continuation: Option<Continuation>,
+    ) -> DiffusionMessage {
+        DiffusionMessage::DiffusionMessageV2(DiffusionMessageV2::Modifiers(
+            Modifiers { mod_type, modifiers },
+            continuation,
+        ))
+    }
+
     pub fn sync_status_v1(status: SyncStatus) -> DiffusionMessage {
         DiffusionMessage::DiffusionMessageV1(DiffusionMessageV1::SyncStatus(status))
     }
+
+    pub fn request_snapshot_v1(block_id: BlockId) -> DiffusionMessage {
+        DiffusionMessage::DiffusionMessageV1(DiffusionMessageV1::RequestSnapshot(block_id))
+    }
+
+    pub fn snapshot_v1(block_id: BlockId, snapshot: Option<SignedSnapshot>) -> DiffusionMessage {
+        DiffusionMessage::DiffusionMessageV1(DiffusionMessageV1::Snapshot(block_id, snapshot.map(Box::new)))
+    }
+
+    pub fn request_snapshot_chunk_v1(block_id: BlockId, index: u32) -> DiffusionMessage {
+        DiffusionMessage::DiffusionMessageV1(DiffusionMessageV1::RequestSnapshotChunk(ChunkRef {
+            block_id,
+            index,
+        }))
+    }
+
+    pub fn snapshot_chunk_v1(block_id: BlockId, index: u32, chunk: SnapshotChunk) -> DiffusionMessage {
+        DiffusionMessage::DiffusionMessageV1(DiffusionMessageV1::SnapshotChunk(
+            ChunkRef { block_id, index },
+            chunk,
+        ))
+    }
+
+    /// The same message in the given version of the protocol.
+    /// Continuations of modifiers responses are lost in V1.
+    pub fn into_version(self, ver: ProtocolVer) -> DiffusionMessage {
+        match self {
+            DiffusionMessage::DiffusionMessageV1(msg) if ver == DiffusionSpec::v2() => {
+                DiffusionMessage::DiffusionMessageV2(msg.into())
+            }
+            DiffusionMessage::DiffusionMessageV2(msg) if ver == DiffusionSpec::v1() => {
+                DiffusionMessage::DiffusionMessageV1(msg.into())
+            }
+            msg => msg,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -55,6 +108,11 @@ pub struct Modifiers<T> {
     pub modifiers: Vec<T>,
 }
 
+/// Requested modifiers which didn't fit into the response.
+/// The requester is expected to ask for them in a follow-up request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Continuation(pub Vec<ModifierId>);
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct SyncStatus {
     /// Slot number of best available block.
@@ -63,18 +121,88 @@ pub struct SyncStatus {
     pub last_blocks: Vec<BlockId>,
 }
 
+/// Chunk of the snapshot of the ledger state at the given block.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ChunkRef {
+    pub block_id: BlockId,
+    pub index: u32,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub enum DiffusionMessageV1 {
     Inv(Modifiers<ModifierId>),
     RequestModifiers(Modifiers<ModifierId>),
     Modifiers(Modifiers<SerializedModifier>),
     SyncStatus(SyncStatus),
+    /// Request the certified snapshot of the ledger state at the given block.
+    RequestSnapshot(BlockId),
+    /// Manifest of the requested snapshot, `None` if the peer can't serve it.
+    Snapshot(BlockId, Option<Box<SignedSnapshot>>),
+    RequestSnapshotChunk(ChunkRef),
+    SnapshotChunk(ChunkRef, SnapshotChunk),
+}
+
+/// Same as [`DiffusionMessageV1`], except that responses with modifiers are bounded in size
+/// and list the requested modifiers which didn't fit.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum DiffusionMessageV2 {
+    Inv(Modifiers<ModifierId>),
+    RequestModifiers(Modifiers<ModifierId>),
+    Modifiers(Modifiers<SerializedModifier>, Option<Continuation>),
+    SyncStatus(SyncStatus),
+    RequestSnapshot(BlockId),
+    Snapshot(BlockId, Option<Box<SignedSnapshot>>),
+    RequestSnapshotChunk(ChunkRef),
+    SnapshotChunk(ChunkRef, SnapshotChunk),
+}
+
+impl From<DiffusionMessageV1> for DiffusionMessageV2 {
+    fn from(msg: DiffusionMessageV1) -> Self {
+        match msg {
+            DiffusionMessageV1::Inv(mods) => DiffusionMessageV2::Inv(mods),
+            DiffusionMessageV1::RequestModifiers(mods) => DiffusionMessageV2::RequestModifiers(mods),
+            DiffusionMessageV1::Modifiers(mods) => DiffusionMessageV2::Modifiers(mods, None),
+            DiffusionMessageV1::SyncStatus(status) => DiffusionMessageV2::SyncStatus(status),
+            DiffusionMessageV1::RequestSnapshot(block_id) => DiffusionMessageV2::RequestSnapshot(block_id),
+            DiffusionMessageV1::Snapshot(block_id, snapshot) => {
+                DiffusionMessageV2::Snapshot(block_id, snapshot)
+            }
+            DiffusionMessageV1::RequestSnapshotChunk(chunk_ref) => {
+                DiffusionMessageV2::RequestSnapshotChunk(chunk_ref)
+            }
+            DiffusionMessageV1::SnapshotChunk(chunk_ref, chunk) => {
+                DiffusionMessageV2::SnapshotChunk(chunk_ref, chunk)
+            }
+        }
+    }
+}
+
+impl From<DiffusionMessageV2> for DiffusionMessageV1 {
+    fn from(msg: DiffusionMessageV2) -> Self {
+        match msg {
+            DiffusionMessageV2::Inv(mods) => DiffusionMessageV1::Inv(mods),
+            DiffusionMessageV2::RequestModifiers(mods) => DiffusionMessageV1::RequestModifiers(mods),
+            DiffusionMessageV2::Modifiers(mods, _) => DiffusionMessageV1::Modifiers(mods),
+            DiffusionMessageV2::SyncStatus(status) => DiffusionMessageV1::SyncStatus(status),
+            DiffusionMessageV2::RequestSnapshot(block_id) => DiffusionMessageV1::RequestSnapshot(block_id),
+            DiffusionMessageV2::Snapshot(block_id, snapshot) => {
+                DiffusionMessageV1::Snapshot(block_id, snapshot)
+            }
+            DiffusionMessageV2::RequestSnapshotChunk(chunk_ref) => {
+                DiffusionMessageV1::RequestSnapshotChunk(chunk_ref)
+            }
+            DiffusionMessageV2::SnapshotChunk(chunk_ref, chunk) => {
+                DiffusionMessageV1::SnapshotChunk(chunk_ref, chunk)
+            }
+        }
+    }
 }
 
 impl Versioned for DiffusionMessage {
     fn version(&self) -> ProtocolVer {
         match self {
             DiffusionMessage::DiffusionMessageV1(_) => DiffusionSpec::v1(),
+            DiffusionMessage::DiffusionMessageV2(_) => DiffusionSpec::v2(),
         }
     }
 }
@@ -85,9 +213,129 @@ impl DiffusionSpec {
     pub fn v1() -> ProtocolVer {
         ProtocolVer::from(1)
     }
+
+    pub fn v2() -> ProtocolVer {
+        ProtocolVer::from(2)
+    }
 }
 
 impl ProtocolSpec for DiffusionSpec {
     type THandshake = DiffusionHandshake;
     type TMessage = DiffusionMessage;
+
+    /// Inventories, requests and status updates shouldn't wait for modifiers and snapshots in transfer.
+    fn message_priority(message: &DiffusionMessage) -> MessagePriority {
+        match message {
+            DiffusionMessage::DiffusionMessageV1(
+                DiffusionMessageV1::Modifiers(..)
+                | DiffusionMessageV1::Snapshot(..)
+                | DiffusionMessageV1::SnapshotChunk(..),
+            )
+            | DiffusionMessage::DiffusionMessageV2(
+                DiffusionMessageV2::Modifiers(..)
+                | DiffusionMessageV2::Snapshot(..)
+                | DiffusionMessageV2::SnapshotChunk(..),
+            ) => MessagePriority::Bulk,
+            DiffusionMessage::DiffusionMessageV1(_) | DiffusionMessage::DiffusionMessageV2(_) => {
+                MessagePriority::Control
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use spectrum_ledger::block::BlockId;
+    use spectrum_ledger::{ModifierId, ModifierType, SerializedModifier, SlotNo};
+    use spectrum_network::protocol::DIFFUSION_PROTOCOL_ID;
+    use spectrum_network::protocol_handler::conformance::{load_corpus, Transcript};
+    use spectrum_network::protocol_handler::versioning::Versioned;
+    use spectrum_view::snapshot::SnapshotChunk;
+
+    use crate::message::{Continuation, DiffusionMessage, DiffusionSpec, SyncStatus};
+
+    const CORPUS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/conformance_corpus");
+
+    /// Diffusion traffic as encoded by the current build.
+    fn record_transcript() -> Transcript {
+        let mut transcript = Transcript::new(env!("CARGO_PKG_VERSION"));
+        let ids = vec![ModifierId::random(), ModifierId::random()];
+        let checkpoint = BlockId::random();
+        for msg in [
+            DiffusionMessage::inv_v1(ModifierType::BlockHeader, ids.clone()),
+            DiffusionMessage::request_modifiers_v1(ModifierType::BlockBody, ids.clone()),
+            DiffusionMessage::modifiers_v1(
+                ModifierType::Transaction,
+                vec![SerializedModifier(vec![1, 2, 3])],
+            ),
+            DiffusionMessage::modifiers_v2(
+                ModifierType::Transaction,
+                vec![SerializedModifier(vec![1, 2, 3])],
+                Some(Continuation(ids)),
+            ),
+            DiffusionMessage::modifiers_v2(ModifierType::TxPackage, vec![], None),
+            DiffusionMessage::sync_status_v1(SyncStatus {
+                height: SlotNo::from(100),
+                last_blocks: vec![BlockId::random(), BlockId::ORIGIN],
+            }),
+            DiffusionMessage::request_snapshot_v1(checkpoint),
+            DiffusionMessage::snapshot_v1(checkpoint, None),
+            DiffusionMessage::request_snapshot_chunk_v1(checkpoint, 1),
+            DiffusionMessage::snapshot_chunk_v1(checkpoint, 1, SnapshotChunk(vec![4, 5, 6])),
+        ] {
+            transcript.record(DIFFUSION_PROTOCOL_ID, msg.version(), &msg);
+        }
+        transcript
+    }
+
+    #[test]
+    fn current_build_conforms_to_itself() {
+        let transcript = record_transcript();
+        assert_eq!(
+            transcript.check::<DiffusionMessage>(DIFFUSION_PROTOCOL_ID),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn modifiers_converted_between_versions() {
+        let modifiers = vec![SerializedModifier(vec![1, 2, 3])];
+        let v1 = DiffusionMessage::modifiers_v1(ModifierType::BlockHeader, modifiers.clone());
+        let v2 = DiffusionMessage::modifiers_v2(
+            ModifierType::BlockHeader,
+            modifiers.clone(),
+            Some(Continuation(vec![ModifierId::random()])),
+        );
+        assert_eq!(v2.into_version(DiffusionSpec::v1()), v1);
+        assert_eq!(v1.clone().into_version(DiffusionSpec::v1()), v1);
+        assert_eq!(
+            v1.into_version(DiffusionSpec::v2()),
+            DiffusionMessage::modifiers_v2(ModifierType::BlockHeader, modifiers, None)
+        );
+    }
+
+    /// Traffic recorded with previous releases must be handled by the current build identically.
+    #[test]
+    fn replay_corpus() {
+        for (path, transcript) in load_corpus(CORPUS_DIR).unwrap() {
+            assert_eq!(
+                transcript.check::<DiffusionMessage>(DIFFUSION_PROTOCOL_ID),
+                vec![],
+                "Protocol change against release {} ({:?})",
+                transcript.release,
+                path
+            );
+        }
+    }
+
+    /// Pin the wire format of the current release. Run before cutting a release.
+    #[test]
+    #[ignore]
+    fn record_release_transcript() {
+        let transcript = record_transcript();
+        std::fs::create_dir_all(CORPUS_DIR).unwrap();
+        transcript
+            .save(format!("{}/{}.cbor", CORPUS_DIR, transcript.release))
+            .unwrap();
+    }
 }
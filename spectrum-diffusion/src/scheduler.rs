@@ -0,0 +1,253 @@
+//! Scheduling of block body downloads.
+//!
+//! Missing bodies are requested from all suitable peers in parallel, each peer being given at most
+//! a fixed number of bodies at a time. Bodies not delivered in time are requested from other peers.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use libp2p_identity::PeerId;
+
+use spectrum_ledger::ModifierId;
+
+struct InFlight {
+    peer_id: PeerId,
+    deadline: Instant,
+    /// Order in which sections were requested.
+    seq: u64,
+}
+
+/// Outcome of the delivery of a section by a peer.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub(super) enum Delivery {
+    /// Section is wanted.
+    Accepted,
+    /// Section was requested from the peer, but got delivered by another one in the meantime.
+    Redundant,
+    /// Section was never requested from the peer.
+    Unsolicited,
+}
+
+pub(super) struct DownloadScheduler {
+    max_in_flight_per_peer: usize,
+    timeout: Duration,
+    /// Sections waiting for a peer, in the order they are wanted.
+    queue: VecDeque<ModifierId>,
+    queued: HashSet<ModifierId>,
+    in_flight: HashMap<ModifierId, InFlight>,
+    /// Sections requested from each peer and not delivered by it yet,
+    /// including ones requested elsewhere after the peer timed out.
+    assigned: HashMap<PeerId, HashSet<ModifierId>>,
+    /// Peers which failed to deliver the section. They are asked for it again only as a last resort.
+    failed: HashMap<ModifierId, HashSet<PeerId>>,
+    next_seq: u64,
+}
+
+impl DownloadScheduler {
+    pub fn new(max_in_flight_per_peer: usize, timeout: Duration) -> Self {
+        Self {
+            max_in_flight_per_peer,
+            timeout,
+            queue: VecDeque::new(),
+            queued: HashSet::new(),
+            in_flight: HashMap::new(),
+            assigned: HashMap::new(),
+            failed: HashMap::new(),
+            next_seq: 0,
+        }
+    }
+
+    /// Sections already queued or in flight are ignored.
+    pub fn enqueue<I: IntoIterator<Item = ModifierId>>(&mut self, sections: I) {
+        for mid in sections {
+            if !self.in_flight.contains_key(&mid) && self.queued.insert(mid) {
+                self.queue.push_back(mid);
+            }
+        }
+    }
+
+    /// Assign queued sections to the given peers, least loaded peers first.
+    /// Returns sections to request from each of the peers.
+    pub fn schedule(&mut self, now: Instant, peers: &[PeerId]) -> Vec<(PeerId, Vec<ModifierId>)> {
+        let mut spare = peers
+            .iter()
+            .map(|pid| (*pid, self.max_in_flight_per_peer))
+            .collect::<HashMap<_, _>>();
+        for req in self.in_flight.values() {
+            if let Some(n) = spare.get_mut(&req.peer_id) {
+                *n = n.saturating_sub(1);
+            }
+        }
+        let mut batches = HashMap::<PeerId, Vec<ModifierId>>::new();
+        while let Some(mid) = self.queue.pop_front() {
+            let failed = self.failed.get(&mid);
+            let best = spare
+                .iter()
+                .filter(|(_, n)| **n > 0)
+                .max_by_key(|(pid, n)| (!failed.is_some_and(|f| f.contains(pid)), **n, **pid))
+                .map(|(pid, _)| *pid);
+            let Some(peer_id) = best else {
+                // All peers are busy.
+                self.queue.push_front(mid);
+                break;
+            };
+            *spare.get_mut(&peer_id).unwrap() -= 1;
+            self.queued.remove(&mid);
+            self.in_flight.insert(
+                mid,
+                InFlight {
+                    peer_id,
+                    deadline: now + self.timeout,
+                    seq: self.next_seq,
+                },
+            );
+            self.next_seq += 1;
+            self.assigned.entry(peer_id).or_default().insert(mid);
+            batches.entry(peer_id).or_default().push(mid);
+        }
+        batches.into_iter().collect()
+    }
+
+    pub fn on_delivered(&mut self, peer_id: PeerId, mid: ModifierId) -> Delivery {
+        let requested = self
+            .assigned
+            .get_mut(&peer_id)
+            .is_some_and(|sections| sections.remove(&mid));
+        if !requested {
+            return Delivery::Unsolicited;
+        }
+        let in_flight = self.in_flight.remove(&mid).is_some();
+        let queued = self.queued.remove(&mid);
+        if queued {
+            self.queue.retain(|m| *m != mid);
+        }
+        self.failed.remove(&mid);
+        if in_flight || queued {
+            Delivery::Accepted
+        } else {
+            Delivery::Redundant
+        }
+    }
+
+    /// Requeue sections not delivered in time. Returns peers which failed to deliver them.
+    pub fn on_timeout(&mut self, now: Instant) -> Vec<PeerId> {
+        let expired = self.take_in_flight(|req| req.deadline <= now);
+        let peers = expired.iter().map(|(_, req)| req.peer_id).collect::<HashSet<_>>();
+        self.requeue(expired);
+        peers.into_iter().collect()
+    }
+
+    /// Requeue all sections requested from the peer, e.g. once it's gone or misbehaves.
+    /// Sections it delivers afterwards are treated as unsolicited.
+    pub fn release_peer(&mut self, peer_id: PeerId) {
+        let released = self.take_in_flight(|req| req.peer_id == peer_id);
+        self.requeue(released);
+        self.assigned.remove(&peer_id);
+    }
+
+    fn take_in_flight<F: Fn(&InFlight) -> bool>(&mut self, pred: F) -> Vec<(ModifierId, InFlight)> {
+        let taken = self
+            .in_flight
+            .iter()
+            .filter(|(_, req)| pred(req))
+            .map(|(mid, _)| *mid)
+            .collect::<Vec<_>>();
+        taken
+            .into_iter()
+            .map(|mid| (mid, self.in_flight.remove(&mid).unwrap()))
+            .collect()
+    }
+
+    /// Requeued sections go first in the order they were requested,
+    /// as they are wanted longer than the rest.
+    fn requeue(&mut self, mut sections: Vec<(ModifierId, InFlight)>) {
+        sections.sort_by_key(|(_, req)| req.seq);
+        for (mid, req) in sections.into_iter().rev() {
+            self.failed.entry(mid).or_default().insert(req.peer_id);
+            if self.queued.insert(mid) {
+                self.queue.push_front(mid);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use libp2p_identity::PeerId;
+
+    use spectrum_ledger::block::BlockId;
+    use spectrum_ledger::ModifierId;
+
+    use crate::scheduler::{Delivery, DownloadScheduler};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn sections(n: usize) -> Vec<ModifierId> {
+        (0..n).map(|_| ModifierId::from(BlockId::random())).collect()
+    }
+
+    #[test]
+    fn sections_spread_across_peers_within_limits() {
+        let mut scheduler = DownloadScheduler::new(2, TIMEOUT);
+        let peers = vec![PeerId::random(), PeerId::random()];
+        let wanted = sections(5);
+        scheduler.enqueue(wanted.clone());
+        let now = Instant::now();
+        let batches = scheduler.schedule(now, &peers);
+        assert_eq!(batches.len(), 2);
+        assert!(batches.iter().all(|(_, batch)| batch.len() == 2));
+        // Both peers are busy.
+        assert!(scheduler.schedule(now, &peers).is_empty());
+        let (peer, batch) = &batches[0];
+        assert_eq!(scheduler.on_delivered(*peer, batch[0]), Delivery::Accepted);
+        assert_eq!(scheduler.schedule(now, &peers), vec![(*peer, vec![wanted[4]])]);
+    }
+
+    #[test]
+    fn timed_out_sections_reassigned() {
+        let mut scheduler = DownloadScheduler::new(2, TIMEOUT);
+        let slow_peer = PeerId::random();
+        let other_peer = PeerId::random();
+        let wanted = sections(2);
+        scheduler.enqueue(wanted.clone());
+        let now = Instant::now();
+        assert_eq!(
+            scheduler.schedule(now, &[slow_peer]),
+            vec![(slow_peer, wanted.clone())]
+        );
+        assert!(scheduler.on_timeout(now).is_empty());
+        assert_eq!(scheduler.on_timeout(now + TIMEOUT), vec![slow_peer]);
+        // The peer which failed to deliver is asked last.
+        let batches = scheduler.schedule(now + TIMEOUT, &[slow_peer, other_peer]);
+        assert_eq!(batches, vec![(other_peer, wanted.clone())]);
+        // Late delivery by the slow peer is still fine.
+        assert_eq!(scheduler.on_delivered(slow_peer, wanted[0]), Delivery::Accepted);
+        assert_eq!(scheduler.on_delivered(other_peer, wanted[0]), Delivery::Redundant);
+        assert_eq!(
+            scheduler.on_delivered(other_peer, sections(1)[0]),
+            Delivery::Unsolicited
+        );
+    }
+
+    #[test]
+    fn released_peer_sections_requeued() {
+        let mut scheduler = DownloadScheduler::new(1, TIMEOUT);
+        let bad_peer = PeerId::random();
+        let other_peer = PeerId::random();
+        let wanted = sections(1);
+        scheduler.enqueue(wanted.clone());
+        let now = Instant::now();
+        assert_eq!(
+            scheduler.schedule(now, &[bad_peer]),
+            vec![(bad_peer, wanted.clone())]
+        );
+        scheduler.release_peer(bad_peer);
+        assert_eq!(scheduler.on_delivered(bad_peer, wanted[0]), Delivery::Unsolicited);
+        assert_eq!(
+            scheduler.schedule(now, &[bad_peer, other_peer]),
+            vec![(other_peer, wanted.clone())]
+        );
+    }
+}
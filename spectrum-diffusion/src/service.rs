@@ -1,3 +1,4 @@
+use std::iter;
 use std::marker::PhantomData;
 use std::sync::Arc;
 
@@ -6,8 +7,9 @@ use spectrum_ledger::{ModifierId, ModifierType, SerializedModifier, SlotNo};
 use spectrum_network::types::ProtocolVer;
 use spectrum_view::chain::HeaderLike;
 use spectrum_view::history::LedgerHistoryReadAsync;
+use spectrum_view::mempool::MempoolReadAsync;
 
-use crate::message::{DiffusionHandshake, DiffusionSpec, HandshakeV1, SyncStatus};
+use crate::message::{Continuation, DiffusionHandshake, DiffusionSpec, HandshakeV1, SyncStatus};
 
 /// Peer chain in comparison to the local one.
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -33,28 +35,32 @@ pub(super) struct SyncState {
     pub cmp: RemoteChainCmp,
 }
 
-pub(super) struct RemoteSync<THeader, THistory> {
+pub(super) struct RemoteSync<THeader, THistory, TMempool> {
     history: Arc<THistory>,
+    mempool: Arc<TMempool>,
     pd: PhantomData<THeader>,
 }
 
-impl<THistory, THeader> Clone for RemoteSync<THistory, THeader> {
+impl<THeader, THistory, TMempool> Clone for RemoteSync<THeader, THistory, TMempool> {
     fn clone(&self) -> Self {
         Self {
             history: self.history.clone(),
+            mempool: self.mempool.clone(),
             pd: PhantomData::default(),
         }
     }
 }
 
-impl<THeader, THistory> RemoteSync<THeader, THistory>
+impl<THeader, THistory, TMempool> RemoteSync<THeader, THistory, TMempool>
 where
     THeader: HeaderLike,
     THistory: LedgerHistoryReadAsync<THeader>,
+    TMempool: MempoolReadAsync,
 {
-    pub fn new(history: Arc<THistory>) -> Self {
+    pub fn new(history: Arc<THistory>, mempool: Arc<TMempool>) -> Self {
         Self {
             history,
+            mempool,
             pd: PhantomData::default(),
         }
     }
@@ -71,12 +77,17 @@ where
     }
 
     pub async fn make_poly_handshake(&self) -> Vec<(ProtocolVer, Option<DiffusionHandshake>)> {
-        vec![(
-            DiffusionSpec::v1(),
-            Some(DiffusionHandshake::HandshakeV1(HandshakeV1(
-                self.local_status().await,
-            ))),
-        )]
+        let status = self.local_status().await;
+        vec![
+            (
+                DiffusionSpec::v2(),
+                Some(DiffusionHandshake::HandshakeV2(HandshakeV1(status.clone()))),
+            ),
+            (
+                DiffusionSpec::v1(),
+                Some(DiffusionHandshake::HandshakeV1(HandshakeV1(status))),
+            ),
+        ]
     }
 
     pub async fn remote_state(&self, peer_status: SyncStatus) -> SyncState {
@@ -106,12 +117,37 @@ where
                     .multi_get_raw(BlockSectionType::Body, modifiers)
                     .await
             }
-            ModifierType::Transaction => {
-                todo!("Go to mempool")
+            ModifierType::Transaction | ModifierType::TxPackage => {
+                self.mempool.multi_get_raw(mod_type, modifiers).await
             }
         }
     }
 
+    /// Select requested modifiers until their total size reaches `max_bytes`.
+    /// Ids of the modifiers which didn't fit are returned as a continuation.
+    /// At least one modifier is always included so that the requester makes progress.
+    pub async fn get_modifiers_bounded(
+        &self,
+        mod_type: ModifierType,
+        modifiers: Vec<ModifierId>,
+        max_bytes: usize,
+    ) -> (Vec<SerializedModifier>, Option<Continuation>) {
+        let mut selected = vec![];
+        let mut size = 0;
+        let mut ids = modifiers.into_iter();
+        while let Some(mid) = ids.next() {
+            if let Some(md) = self.get_modifiers(mod_type, vec![mid]).await.pop() {
+                if !selected.is_empty() && size + md.0.len() > max_bytes {
+                    let rest = iter::once(mid).chain(ids).collect();
+                    return (selected, Some(Continuation(rest)));
+                }
+                size += md.0.len();
+                selected.push(md);
+            }
+        }
+        (selected, None)
+    }
+
     /// Compare remote chain with the local one.
     async fn compare_remote(&self, peer_status: SyncStatus) -> RemoteChainCmp {
         let local_tip = self.history.get_tip().await;
@@ -158,8 +194,14 @@ where
                         if let Some(common_point) = self.common_point(&peer_tail).await {
                             if common_point == peer_tip {
                                 RemoteChainCmp::Shorter(common_point)
-                            } else {
+                            } else if self
+                                .rollback_permitted(common_point, local_tip.modifier.slot_num())
+                                .await
+                            {
                                 RemoteChainCmp::Fork(Some(common_point))
+                            } else {
+                                // Switching to remote chain would revert finalized blocks.
+                                RemoteChainCmp::Nonsense
                             }
                         } else {
                             RemoteChainCmp::Fork(None)
@@ -171,6 +213,22 @@ where
         }
     }
 
+    /// Check whether local chain can be rolled back to the given block
+    /// without reverting the last finalized one.
+    async fn rollback_permitted(&self, to: BlockId, local_tip_slot: SlotNo) -> bool {
+        match self.history.get_last_finalized().await {
+            Some(finalized) if finalized.id != to => {
+                // There is at most one block per slot between finalized block and the tip.
+                let depth = <u64>::from(local_tip_slot).saturating_sub(<u64>::from(finalized.slot));
+                self.history
+                    .follow(finalized.id, depth as usize)
+                    .await
+                    .contains(&to)
+            }
+            _ => true,
+        }
+    }
+
     /// Find the point where remote chain intersects local one.
     async fn common_point(&self, remote_tail: &Vec<BlockId>) -> Option<BlockId> {
         for blk in remote_tail {
@@ -189,18 +247,29 @@ pub(crate) mod tests {
     use std::collections::HashMap;
     use std::sync::Arc;
 
+    use async_std::sync::RwLock;
     use nonempty::NonEmpty;
 
     use spectrum_ledger::block::{BlockId, BlockSectionType};
-    use spectrum_ledger::{ModifierId, ModifierRecord, SerializedModifier, SlotNo};
+    use spectrum_ledger::{ModifierId, ModifierRecord, ModifierType, SerializedModifier, SlotNo};
     use spectrum_view::chain::HeaderLike;
+    use spectrum_view::finality::Checkpoint;
     use spectrum_view::history::LedgerHistoryReadAsync;
+    use spectrum_view::mempool::Mempool;
 
-    use crate::message::SyncStatus;
+    use crate::message::{Continuation, SyncStatus};
     use crate::service::{RemoteChainCmp, RemoteSync};
 
+    /// Size of a header returned by [EphemeralHistory::multi_get_raw].
+    pub(crate) const RAW_HEADER_SIZE: usize = 64;
+
+    pub(crate) fn empty_mempool() -> Arc<RwLock<Mempool>> {
+        Arc::new(RwLock::new(Mempool::new()))
+    }
+
     pub(crate) struct EphemeralHistory {
         pub(crate) db: HashMap<BlockId, Header>,
+        pub(crate) finalized: Option<Checkpoint>,
     }
 
     #[derive(Debug, Clone)]
@@ -251,6 +320,10 @@ pub(crate) mod tests {
             ModifierRecord::from(header)
         }
 
+        async fn get_last_finalized(&self) -> Option<Checkpoint> {
+            self.finalized
+        }
+
         async fn get_tail(&self, n: usize) -> NonEmpty<ModifierRecord<Header>> {
             let mut headers = self.db.values().collect::<Vec<_>>();
             headers.sort_by_key(|hd| hd.slot);
@@ -275,7 +348,10 @@ pub(crate) mod tests {
             sec_type: BlockSectionType,
             ids: Vec<ModifierId>,
         ) -> Vec<SerializedModifier> {
-            todo!()
+            ids.into_iter()
+                .filter(|id| self.db.contains_key(&<ModifierId as Into<BlockId>>::into(*id)))
+                .map(|_| SerializedModifier(vec![0; RAW_HEADER_SIZE]))
+                .collect()
         }
     }
 
@@ -299,8 +375,9 @@ pub(crate) mod tests {
         };
         let history = EphemeralHistory {
             db: local_chain.into_iter().map(|hdr| (hdr.id, hdr)).collect(),
+            finalized: None,
         };
-        let service = RemoteSync::new(Arc::new(history));
+        let service = RemoteSync::new(Arc::new(history), empty_mempool());
         assert_eq!(service.compare_remote(remote_ss).await, RemoteChainCmp::Equal);
     }
 
@@ -323,8 +400,9 @@ pub(crate) mod tests {
         };
         let history = EphemeralHistory {
             db: local_chain.into_iter().map(|hdr| (hdr.id, hdr)).collect(),
+            finalized: None,
         };
-        let service = RemoteSync::new(Arc::new(history));
+        let service = RemoteSync::new(Arc::new(history), empty_mempool());
         assert_eq!(
             service.compare_remote(remote_ss).await,
             RemoteChainCmp::Shorter(remote_chain[0])
@@ -351,8 +429,9 @@ pub(crate) mod tests {
         };
         let history = EphemeralHistory {
             db: local_chain.into_iter().map(|hdr| (hdr.id, hdr)).collect(),
+            finalized: None,
         };
-        let service = RemoteSync::new(Arc::new(history));
+        let service = RemoteSync::new(Arc::new(history), empty_mempool());
         assert_eq!(service.compare_remote(remote_ss).await, RemoteChainCmp::Nonsense);
     }
 
@@ -372,8 +451,9 @@ pub(crate) mod tests {
         };
         let history = EphemeralHistory {
             db: local_chain.into_iter().map(|hdr| (hdr.id, hdr)).collect(),
+            finalized: None,
         };
-        let service = RemoteSync::new(Arc::new(history));
+        let service = RemoteSync::new(Arc::new(history), empty_mempool());
         assert_eq!(
             service.compare_remote(remote_ss).await,
             RemoteChainCmp::Fork(None)
@@ -415,14 +495,48 @@ pub(crate) mod tests {
         };
         let history = EphemeralHistory {
             db: local_chain.into_iter().map(|hdr| (hdr.id, hdr)).collect(),
+            finalized: None,
         };
-        let service = RemoteSync::new(Arc::new(history));
+        let service = RemoteSync::new(Arc::new(history), empty_mempool());
         assert_eq!(
             service.compare_remote(remote_ss).await,
             RemoteChainCmp::Fork(Some(pre_fork_hdr))
         );
     }
 
+    #[async_std::test]
+    async fn fork_beyond_finalized_block() {
+        let remote_chain = (0..32)
+            .map(|i| Header {
+                id: BlockId::random(),
+                slot: SlotNo::from(i as u64),
+            })
+            .collect::<Vec<_>>();
+        let mut local_chain = remote_chain.clone()[..25].to_vec();
+        let fork_hdrs = (25..27)
+            .map(|i| Header {
+                id: BlockId::random(),
+                slot: SlotNo::from(i as u64),
+            })
+            .collect::<Vec<_>>();
+        local_chain.extend(fork_hdrs.clone());
+        let mut remote_chain_rev = remote_chain.clone();
+        remote_chain_rev.reverse();
+        let remote_ss = SyncStatus {
+            height: SlotNo::from(31),
+            last_blocks: remote_chain_rev.into_iter().map(|blk| blk.id).collect::<Vec<_>>(),
+        };
+        let history = EphemeralHistory {
+            db: local_chain.into_iter().map(|hdr| (hdr.id, hdr)).collect(),
+            finalized: Some(Checkpoint {
+                id: fork_hdrs[0].id,
+                slot: fork_hdrs[0].slot,
+            }),
+        };
+        let service = RemoteSync::new(Arc::new(history), empty_mempool());
+        assert_eq!(service.compare_remote(remote_ss).await, RemoteChainCmp::Nonsense);
+    }
+
     #[async_std::test]
     async fn significantly_longer_chain() {
         let local_chain = (0..32)
@@ -439,8 +553,9 @@ pub(crate) mod tests {
         };
         let history = EphemeralHistory {
             db: local_chain.into_iter().map(|hdr| (hdr.id, hdr)).collect(),
+            finalized: None,
         };
-        let service = RemoteSync::new(Arc::new(history));
+        let service = RemoteSync::new(Arc::new(history), empty_mempool());
         assert_eq!(
             service.compare_remote(remote_ss).await,
             RemoteChainCmp::Longer(None)
@@ -476,8 +591,9 @@ pub(crate) mod tests {
         };
         let history = EphemeralHistory {
             db: local_chain.into_iter().map(|hdr| (hdr.id, hdr)).collect(),
+            finalized: None,
         };
-        let service = RemoteSync::new(Arc::new(history));
+        let service = RemoteSync::new(Arc::new(history), empty_mempool());
         assert_eq!(
             service.compare_remote(remote_ss).await,
             RemoteChainCmp::Longer(Some(
@@ -485,4 +601,39 @@ pub(crate) mod tests {
             ))
         );
     }
+
+    #[async_std::test]
+    async fn modifiers_response_is_bounded() {
+        let local_chain = (0..4)
+            .map(|i| Header {
+                id: BlockId::random(),
+                slot: SlotNo::from(i as u64),
+            })
+            .collect::<Vec<_>>();
+        let ids = local_chain
+            .iter()
+            .map(|hdr| ModifierId::from(hdr.id))
+            .collect::<Vec<_>>();
+        let history = EphemeralHistory {
+            db: local_chain.into_iter().map(|hdr| (hdr.id, hdr)).collect(),
+            finalized: None,
+        };
+        let service = RemoteSync::new(Arc::new(history), empty_mempool());
+        let (modifiers, continuation) = service
+            .get_modifiers_bounded(ModifierType::BlockHeader, ids.clone(), 2 * RAW_HEADER_SIZE + 1)
+            .await;
+        assert_eq!(modifiers.len(), 2);
+        assert_eq!(continuation, Some(Continuation(ids[2..].to_vec())));
+        // A modifier exceeding the budget on its own is still delivered.
+        let (modifiers, continuation) = service
+            .get_modifiers_bounded(ModifierType::BlockHeader, ids.clone(), 1)
+            .await;
+        assert_eq!(modifiers.len(), 1);
+        assert_eq!(continuation, Some(Continuation(ids[1..].to_vec())));
+        let (modifiers, continuation) = service
+            .get_modifiers_bounded(ModifierType::BlockHeader, ids, usize::MAX)
+            .await;
+        assert_eq!(modifiers.len(), 4);
+        assert_eq!(continuation, None);
+    }
 }
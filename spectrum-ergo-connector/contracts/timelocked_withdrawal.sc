@@ -0,0 +1,18 @@
+{ // ===== Contract Information ===== //
+  // Name: Timelocked withdrawal
+  // Description: Guards a value withdrawn from a Spectrum-Network Vault UTxO which has to be
+  //              refunded if it is left unclaimed by the recipient.
+
+  // Spending paths
+  // 1. Recipient can claim the value at any time;
+  // 2. Refund owner can reclaim the value once the timelock expires.
+  //
+  // Note that the contract is constructed by the connector directly (see `timelock.rs`), the
+  // parameters below are embedded as constants.
+
+  val recipientPk = PK("$recipient")
+  val refundPk = PK("$refundOwner")
+  val timelockHeight = $timelockHeight
+
+  recipientPk || (sigmaProp(HEIGHT >= timelockHeight) && refundPk)
+}
@@ -0,0 +1,369 @@
+//! Accounting of the SN Vault reconstructed from the history of [`ErgoTxEvent`]s.
+
+use std::collections::BTreeMap;
+
+use ergo_lib::{
+    chain::transaction::TxId,
+    ergo_chain_types::Digest32,
+    ergotree_ir::chain::token::{Token, TokenId},
+};
+use serde::Serialize;
+use spectrum_chain_connector::{ExportFormat, PendingWithdrawalStatus};
+
+use crate::{
+    script::{ErgoCell, ErgoTermCell, ExtraErgoData},
+    tx_event::{ErgoTxEvent, ErgoTxType, SpectrumErgoTx},
+    vault_utxo::VaultUtxo,
+};
+
+/// Multi-asset amount of value on Ergo.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct ErgoAmount {
+    pub nano_ergs: u64,
+    /// Amounts of tokens keyed by base16-encoded token ID.
+    pub tokens: BTreeMap<String, u64>,
+}
+
+impl ErgoAmount {
+    fn add(&mut self, nano_ergs: u64, tokens: &[Token]) {
+        self.nano_ergs = self.nano_ergs.saturating_add(nano_ergs);
+        for Token { token_id, amount } in tokens {
+            let amt = self.tokens.entry(encode_token_id(*token_id)).or_insert(0);
+            *amt = amt.saturating_add(*amount.as_u64());
+        }
+    }
+
+    fn add_cell(&mut self, cell: &ErgoCell) {
+        self.add(*cell.ergs.as_u64(), &cell.tokens);
+    }
+
+    /// Tokens in the form `<token_id>:<amount>` separated by `;`.
+    fn tokens_csv(&self) -> String {
+        self.tokens
+            .iter()
+            .map(|(token_id, amount)| format!("{}:{}", token_id, amount))
+            .collect::<Vec<_>>()
+            .join(";")
+    }
+}
+
+impl From<&VaultUtxo> for ErgoAmount {
+    fn from(vault_utxo: &VaultUtxo) -> Self {
+        let mut amount = ErgoAmount::default();
+        amount.add(*vault_utxo.value.as_u64(), &vault_utxo.tokens);
+        amount
+    }
+}
+
+fn encode_token_id(token_id: TokenId) -> String {
+    base16::encode_lower(&Digest32::from(token_id).0)
+}
+
+fn encode_tx_id(tx_id: &TxId) -> String {
+    base16::encode_lower(&tx_id.0 .0)
+}
+
+/// Record which can be exported as a row of a CSV table.
+pub trait CsvRecord {
+    const HEADER: &'static str;
+    fn to_csv_row(&self) -> String;
+}
+
+/// Balance of the vault right after the given TX.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct BalanceRecord {
+    pub height: u32,
+    pub tx_id: String,
+    pub balance: ErgoAmount,
+}
+
+impl CsvRecord for BalanceRecord {
+    const HEADER: &'static str = "height,tx_id,nano_ergs,tokens";
+    fn to_csv_row(&self) -> String {
+        format!(
+            "{},{},{},{}",
+            self.height,
+            self.tx_id,
+            self.balance.nano_ergs,
+            self.balance.tokens_csv()
+        )
+    }
+}
+
+/// Movements of value in and out of the vault within an epoch `[epoch_start, epoch_end]`.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FlowRecord {
+    pub epoch_start: u32,
+    pub epoch_end: u32,
+    pub num_deposits: u32,
+    pub num_withdrawals: u32,
+    pub inflow: ErgoAmount,
+    pub outflow: ErgoAmount,
+}
+
+impl CsvRecord for FlowRecord {
+    const HEADER: &'static str =
+        "epoch_start,epoch_end,num_deposits,num_withdrawals,inflow_nano_ergs,inflow_tokens,outflow_nano_ergs,outflow_tokens";
+    fn to_csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{}",
+            self.epoch_start,
+            self.epoch_end,
+            self.num_deposits,
+            self.num_withdrawals,
+            self.inflow.nano_ergs,
+            self.inflow.tokens_csv(),
+            self.outflow.nano_ergs,
+            self.outflow.tokens_csv()
+        )
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PendingWithdrawalRecord {
+    /// Base16-encoded digest of the notarized report.
+    pub report_digest: String,
+    pub num_recipients: usize,
+    pub value: ErgoAmount,
+    pub status: String,
+}
+
+impl CsvRecord for PendingWithdrawalRecord {
+    const HEADER: &'static str = "report_digest,num_recipients,nano_ergs,tokens,status";
+    fn to_csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{}",
+            self.report_digest,
+            self.num_recipients,
+            self.value.nano_ergs,
+            self.value.tokens_csv(),
+            self.status
+        )
+    }
+}
+
+pub fn export<R: Serialize + CsvRecord>(records: &[R], format: ExportFormat) -> String {
+    match format {
+        ExportFormat::Json => serde_json::to_string(records).unwrap(),
+        ExportFormat::Csv => {
+            let mut res = String::from(R::HEADER);
+            for record in records {
+                res.push('\n');
+                res.push_str(&record.to_csv_row());
+            }
+            res
+        }
+    }
+}
+
+/// TXs that remain applied after rollbacks are taken into account, in order of height.
+pub(crate) fn applied_txs(events: Vec<ErgoTxEvent>) -> Vec<SpectrumErgoTx> {
+    let mut res: Vec<SpectrumErgoTx> = vec![];
+    for event in events {
+        match event {
+            ErgoTxEvent::Applied(tx) => res.push(tx),
+            ErgoTxEvent::Unapplied(tx) => {
+                if let Some(ix) = res.iter().rposition(|t| t.tx_id == tx.tx_id) {
+                    res.remove(ix);
+                }
+            }
+        }
+    }
+    res
+}
+
+/// Balance of the vault after every deposit and withdrawal.
+pub fn balance_history(events: Vec<ErgoTxEvent>) -> Vec<BalanceRecord> {
+    applied_txs(events)
+        .into_iter()
+        .filter_map(|tx| match &tx.tx_type {
+            ErgoTxType::Deposit {
+                vault_info: (vault_utxo, _),
+                ..
+            }
+            | ErgoTxType::Withdrawal {
+                vault_info: (vault_utxo, _),
+                ..
+            } => Some(BalanceRecord {
+                height: tx.progress_point,
+                tx_id: encode_tx_id(&tx.tx_id),
+                balance: ErgoAmount::from(vault_utxo),
+            }),
+            ErgoTxType::NewUnprocessedDeposit(_) | ErgoTxType::RefundedDeposit(_) => None,
+        })
+        .collect()
+}
+
+/// Inflow and outflow of the vault aggregated per epoch of `epoch_length` points counted from
+/// `from`. Epochs without any deposit or withdrawal are omitted.
+pub fn flows_per_epoch(events: Vec<ErgoTxEvent>, from: u32, epoch_length: u32) -> Vec<FlowRecord> {
+    let mut epochs: BTreeMap<u32, FlowRecord> = BTreeMap::new();
+    for tx in applied_txs(events) {
+        if tx.progress_point < from {
+            continue;
+        }
+        let epoch_start = from + (tx.progress_point - from) / epoch_length * epoch_length;
+        match &tx.tx_type {
+            ErgoTxType::Deposit { imported_value, .. } => {
+                let record = epoch_record(&mut epochs, epoch_start, epoch_length);
+                record.num_deposits += 1;
+                for cell in imported_value {
+                    record.inflow.add_cell(&cell.0);
+                }
+            }
+            ErgoTxType::Withdrawal { withdrawn_value, .. } => {
+                let record = epoch_record(&mut epochs, epoch_start, epoch_length);
+                record.num_withdrawals += 1;
+                for ErgoTermCell(cell) in withdrawn_value {
+                    record.outflow.add_cell(cell);
+                }
+            }
+            ErgoTxType::NewUnprocessedDeposit(_) | ErgoTxType::RefundedDeposit(_) => {}
+        }
+    }
+    epochs.into_values().collect()
+}
+
+fn epoch_record(
+    epochs: &mut BTreeMap<u32, FlowRecord>,
+    epoch_start: u32,
+    epoch_length: u32,
+) -> &mut FlowRecord {
+    epochs.entry(epoch_start).or_insert_with(|| FlowRecord {
+        epoch_start,
+        epoch_end: epoch_start.saturating_add(epoch_length - 1),
+        num_deposits: 0,
+        num_withdrawals: 0,
+        inflow: ErgoAmount::default(),
+        outflow: ErgoAmount::default(),
+    })
+}
+
+/// Pending withdrawals ordered by withdrawn amount of ERG, largest first.
+pub fn largest_pending_withdrawals(
+    withdrawals: Vec<PendingWithdrawalStatus<ExtraErgoData>>,
+    limit: usize,
+) -> Vec<PendingWithdrawalRecord> {
+    let mut records: Vec<_> = withdrawals
+        .into_iter()
+        .map(|PendingWithdrawalStatus { identifier, status }| {
+            let mut value = ErgoAmount::default();
+            for term_cell in identifier.value_to_withdraw.iter().cloned() {
+                if let Ok(ErgoTermCell(cell)) = ErgoTermCell::try_from(term_cell) {
+                    value.add_cell(&cell);
+                }
+            }
+            PendingWithdrawalRecord {
+                report_digest: base16::encode_lower(&identifier.authenticated_digest),
+                num_recipients: identifier.value_to_withdraw.len(),
+                value,
+                status: format!("{:?}", status),
+            }
+        })
+        .collect();
+    records.sort_by(|a, b| b.value.nano_ergs.cmp(&a.value.nano_ergs));
+    records.truncate(limit);
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use ergo_lib::{
+        chain::transaction::TxId,
+        ergotree_ir::chain::{
+            address::{AddressEncoder, NetworkPrefix},
+            ergo_box::box_value::BoxValue,
+        },
+    };
+    use sigma_test_util::force_any_val;
+    use spectrum_chain_connector::ExportFormat;
+
+    use crate::{
+        accounting::{balance_history, export, flows_per_epoch},
+        script::{ErgoCell, ErgoInboundCell, ErgoTermCell},
+        tx_event::{ErgoTxEvent, ErgoTxType, SpectrumErgoTx},
+        vault_utxo::VaultUtxo,
+        AncillaryVaultInfo,
+    };
+
+    fn cell(nano_ergs: u64) -> ErgoCell {
+        let encoder = AddressEncoder::new(NetworkPrefix::Mainnet);
+        ErgoCell {
+            ergs: BoxValue::try_from(nano_ergs).unwrap(),
+            address: encoder
+                .parse_address_from_str("9hVmDmyrLoNAupFVoobZRCfbwDWnAvCmjT1KCS4yGy3XziaCyMg")
+                .unwrap(),
+            tokens: vec![],
+        }
+    }
+
+    fn vault_info(nano_ergs: u64) -> (VaultUtxo, AncillaryVaultInfo) {
+        (
+            VaultUtxo {
+                value: BoxValue::try_from(nano_ergs).unwrap(),
+                tokens: vec![],
+            },
+            AncillaryVaultInfo {
+                box_id: force_any_val(),
+                height: 0,
+                tx_id: force_any_val(),
+            },
+        )
+    }
+
+    fn deposit(height: u32, amount: u64, vault_balance: u64) -> SpectrumErgoTx {
+        SpectrumErgoTx {
+            progress_point: height,
+            tx_id: force_any_val::<TxId>(),
+            tx_type: ErgoTxType::Deposit {
+                imported_value: vec![ErgoInboundCell(cell(amount), force_any_val())],
+                vault_info: vault_info(vault_balance),
+            },
+        }
+    }
+
+    fn withdrawal(height: u32, amount: u64, vault_balance: u64) -> SpectrumErgoTx {
+        SpectrumErgoTx {
+            progress_point: height,
+            tx_id: force_any_val::<TxId>(),
+            tx_type: ErgoTxType::Withdrawal {
+                withdrawn_value: vec![ErgoTermCell(cell(amount))],
+                vault_info: vault_info(vault_balance),
+            },
+        }
+    }
+
+    #[test]
+    fn rolled_back_txs_are_not_accounted() {
+        let d0 = deposit(10, 5_000_000, 10_000_000);
+        let w0 = withdrawal(15, 2_000_000, 8_000_000);
+        let d1 = deposit(25, 3_000_000, 11_000_000);
+        let events = vec![
+            ErgoTxEvent::Applied(d0),
+            ErgoTxEvent::Applied(w0.clone()),
+            ErgoTxEvent::Unapplied(w0),
+            ErgoTxEvent::Applied(d1),
+        ];
+
+        let balances = balance_history(events.clone());
+        assert_eq!(
+            balances
+                .iter()
+                .map(|r| (r.height, r.balance.nano_ergs))
+                .collect::<Vec<_>>(),
+            vec![(10, 10_000_000), (25, 11_000_000)]
+        );
+
+        let flows = flows_per_epoch(events, 0, 20);
+        assert_eq!(flows.len(), 2);
+        assert_eq!((flows[0].epoch_start, flows[0].epoch_end), (0, 19));
+        assert_eq!(flows[0].inflow.nano_ergs, 5_000_000);
+        assert_eq!(flows[0].num_withdrawals, 0);
+        assert_eq!(flows[1].epoch_start, 20);
+        assert_eq!(flows[1].inflow.nano_ergs, 3_000_000);
+
+        let csv = export(&flows, ExportFormat::Csv);
+        assert_eq!(csv.lines().count(), 3);
+        assert!(csv.lines().nth(1).unwrap().starts_with("0,19,1,0,5000000,"));
+    }
+}
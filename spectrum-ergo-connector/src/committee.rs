@@ -20,6 +20,61 @@ pub struct CommitteeData {
 }
 
 impl CommitteeData {
+    /// Parse committee boxes in the order they are passed as data inputs to the vault contract.
+    /// Keys found in the boxes must match `public_keys`.
+    pub fn try_from_boxes(
+        guarding_script: ErgoTree,
+        public_keys: &[EcPoint],
+        boxes: TxIoVec<ErgoBox>,
+    ) -> Option<Self> {
+        let mut slice_ix = 0_usize;
+
+        let first_box = AsBox(
+            boxes.first().clone(),
+            FirstCommitteeBox::try_from_box(boxes.first().clone(), (guarding_script.clone(), public_keys))?,
+        );
+        slice_ix += first_box.1.public_keys.len();
+
+        let mut subsequent_data_inputs = vec![];
+        for (index, ergo_box) in boxes.iter().enumerate().skip(1) {
+            let subsequent = SubsequentCommitteeBox::try_from_box(
+                ergo_box.clone(),
+                (
+                    ergo_box.value,
+                    guarding_script.clone(),
+                    index as u32,
+                    &public_keys[slice_ix..],
+                ),
+            )?;
+            slice_ix += subsequent.public_keys.len();
+            subsequent_data_inputs.push(AsBox(ergo_box.clone(), subsequent));
+        }
+
+        let subsequent_boxes = TxIoVec::try_from(subsequent_data_inputs).ok();
+        Some(CommitteeData {
+            first_box,
+            subsequent_boxes,
+        })
+    }
+
+    /// Committee boxes in the order they are passed as data inputs to the vault contract.
+    pub fn boxes(&self) -> Vec<ErgoBox> {
+        let mut boxes = vec![self.first_box.0.clone()];
+        if let Some(subsequent) = &self.subsequent_boxes {
+            boxes.extend(subsequent.iter().map(|AsBox(bx, _)| bx.clone()));
+        }
+        boxes
+    }
+
+    /// Keys of all members of the committee.
+    pub fn public_keys(&self) -> Vec<EcPoint> {
+        let mut keys = self.first_box.1.public_keys.clone();
+        if let Some(subsequent) = &self.subsequent_boxes {
+            keys.extend(subsequent.iter().flat_map(|AsBox(_, bx)| bx.public_keys.clone()));
+        }
+        keys
+    }
+
     pub fn committee_size(&self) -> u32 {
         let res = 1 + self
             .subsequent_boxes
@@ -143,6 +198,7 @@ impl IntoBoxCandidate for SubsequentCommitteeBox {
 }
 
 /// Stores parameters associated with the vault.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct VaultParameters {
     /// The number of UTXOs that exist to store committee information.
     pub num_committee_boxes: i32,
@@ -10,11 +10,18 @@ use ergo_chain_sync::{
 use ergo_lib::chain::transaction::Transaction;
 use futures::StreamExt;
 use isahc::{prelude::Configurable, HttpClient};
-use spectrum_chain_connector::{DataBridge, DataBridgeComponents, TxEvent};
+use spectrum_chain_connector::{
+    bridge::{self, BridgeReceiver, BridgeSender},
+    DataBridge, DataBridgeComponents, TxEvent,
+};
+use spectrum_ledger::interop::Point;
 use spectrum_offchain::event_source::{data::LedgerTxEvent, event_source_ledger};
 
+/// Number of the most recent events the bridge keeps to replay them to the consumer on resync.
+const REPLAY_CAPACITY: usize = 4096;
+
 pub struct ErgoDataBridge {
-    pub receiver: tokio::sync::mpsc::Receiver<TxEvent<(ergo_lib::chain::transaction::Transaction, u32)>>,
+    pub receiver: BridgeReceiver<(ergo_lib::chain::transaction::Transaction, u32)>,
     tx_start: tokio::sync::oneshot::Sender<()>,
 }
 
@@ -27,7 +34,7 @@ pub struct ErgoDataBridgeConfig {
 
 impl ErgoDataBridge {
     pub fn new(config: ErgoDataBridgeConfig) -> Self {
-        let (tx, receiver) = tokio::sync::mpsc::channel(16);
+        let (tx, receiver) = bridge::channel(16, REPLAY_CAPACITY);
         let (tx_start, rx_start) = tokio::sync::oneshot::channel();
 
         tokio::spawn(run_bridge(tx, rx_start, config));
@@ -48,7 +55,7 @@ impl DataBridge for ErgoDataBridge {
 }
 
 async fn run_bridge(
-    tx: tokio::sync::mpsc::Sender<TxEvent<(ergo_lib::chain::transaction::Transaction, u32)>>,
+    mut tx: BridgeSender<(ergo_lib::chain::transaction::Transaction, u32)>,
     rx_start: tokio::sync::oneshot::Receiver<()>,
     config: ErgoDataBridgeConfig,
 ) {
@@ -82,14 +89,14 @@ async fn run_bridge(
 
     let mut tx_stream = Box::pin(event_source_ledger(chain_sync_stream(chain_sync)));
     while let Some(event) = tx_stream.next().await {
-        let event = match event {
-            LedgerTxEvent::AppliedTx { tx, height, .. } => TxEvent::AppliedTx((tx, height)),
+        let (height, event) = match event {
+            LedgerTxEvent::AppliedTx { tx, height, .. } => (height, TxEvent::AppliedTx((tx, height))),
             LedgerTxEvent::UnappliedTx(tx) => {
                 let height = greatest_height(&tx);
-                TxEvent::UnappliedTx((tx, height))
+                (height, TxEvent::UnappliedTx((tx, height)))
             }
         };
-        tx.send(event).await.unwrap();
+        tx.send(Point::from(height as u64), event).await.unwrap();
     }
 }
 
@@ -107,7 +114,7 @@ fn greatest_height(tx: &Transaction) -> u32 {
 #[cfg(test)]
 mod tests {
     use ergo_chain_sync::client::types::Url;
-    use spectrum_chain_connector::{DataBridge, DataBridgeComponents, TxEvent};
+    use spectrum_chain_connector::{bridge::BridgeEvent, DataBridge, DataBridgeComponents, TxEvent};
 
     use super::{ErgoDataBridge, ErgoDataBridgeConfig};
 
@@ -129,14 +136,21 @@ mod tests {
         for _ in 0..10 {
             let tx = receiver.recv().await.unwrap();
             match tx {
-                TxEvent::AppliedTx((tx, _)) => {
+                BridgeEvent::Tx {
+                    event: TxEvent::AppliedTx((tx, _)),
+                    ..
+                } => {
                     let height = tx.outputs.first().creation_height;
                     println!("AppliedTx: {:?}, height: {}", tx.id(), height);
                 }
-                TxEvent::UnappliedTx((tx, _)) => {
+                BridgeEvent::Tx {
+                    event: TxEvent::UnappliedTx((tx, _)),
+                    ..
+                } => {
                     let height = tx.outputs.first().creation_height;
                     println!("UnappliedTx: {:?}, height: {}", tx.id(), height);
                 }
+                other => panic!("Unexpected bridge event {:?}", other),
             }
         }
     }
@@ -1,9 +1,16 @@
-use ergo_lib::ergotree_ir::chain::ergo_box::BoxId;
+use ergo_lib::chain::transaction::{Transaction, TxId};
+use ergo_lib::ergo_chain_types::Digest32;
+use ergo_lib::ergotree_ir::chain::address::Address;
+use ergo_lib::ergotree_ir::chain::ergo_box::{BoxId, ErgoBox, NonMandatoryRegisterId};
+use ergo_lib::ergotree_ir::chain::token::TokenId;
+use ergo_lib::ergotree_ir::mir::constant::Literal;
+use ergo_lib::ergotree_ir::mir::value::{CollKind, NativeColl};
+use ergo_lib::ergotree_ir::sigma_protocol::sigma_boolean::ProveDlog;
 use serde::{Deserialize, Serialize};
-use spectrum_chain_connector::InboundValue;
+use spectrum_chain_connector::{InboundValue, TxEvent};
 use spectrum_offchain_lm::data::AsBox;
 
-use crate::script::ErgoInboundCell;
+use crate::script::{owner_of, ErgoCell, ErgoInboundCell, DEPOSIT_CONTRACT};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub struct ProcessedDeposit(pub AsBox<ErgoInboundCell>);
@@ -16,3 +23,223 @@ impl From<UnprocessedDeposit> for InboundValue<BoxId> {
         InboundValue::from(value.0 .1)
     }
 }
+
+/// Extract a deposit into the vault from the given box. Boxes of the deposit contract whose
+/// owner can't be represented on Spectrum are ignored.
+pub fn extract_deposit(bx: &ErgoBox, vault_utxo_token_id: TokenId) -> Option<UnprocessedDeposit> {
+    if bx.ergo_tree != *DEPOSIT_CONTRACT {
+        return None;
+    }
+    let r4 = bx.get_register(NonMandatoryRegisterId::R4.into()).ok()??;
+    let Literal::Coll(CollKind::NativeColl(NativeColl::CollByte(bytes))) = r4.v else {
+        return None;
+    };
+    let bytes_u8: Vec<u8> = bytes.into_iter().map(|b| b as u8).collect();
+    if TokenId::from(Digest32::try_from(bytes_u8).ok()?) != vault_utxo_token_id {
+        return None;
+    }
+    let r5 = bx.get_register(NonMandatoryRegisterId::R5.into()).ok()??;
+    let address = Address::P2Pk(ProveDlog::try_from(r5.v).ok()?);
+    owner_of(&address)?;
+    let tokens = bx.tokens.clone().map(|toks| toks.to_vec()).unwrap_or_default();
+    let cell = ErgoInboundCell(
+        ErgoCell {
+            ergs: bx.value,
+            address,
+            tokens,
+        },
+        bx.box_id(),
+    );
+    Some(UnprocessedDeposit(AsBox(bx.clone(), cell)))
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum DepositEvent {
+    /// Deposit is buried under enough blocks to be imported.
+    Matured {
+        tx_id: TxId,
+        deposit: UnprocessedDeposit,
+    },
+    /// TX which created an already matured deposit was rolled back.
+    Reverted {
+        tx_id: TxId,
+        deposit: UnprocessedDeposit,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct PendingDeposit {
+    height: u32,
+    tx_id: TxId,
+    deposit: UnprocessedDeposit,
+}
+
+/// Watches TXs coming from the data bridge for deposits into the vault and reports them only once
+/// they are `confirmation_depth` blocks deep, so that shallow forks don't make it to Spectrum.
+/// Immature deposits are kept in memory only and are picked up again once the chain is re-synced.
+#[derive(Debug, Clone)]
+pub struct DepositScanner {
+    vault_utxo_token_id: TokenId,
+    confirmation_depth: u32,
+    /// Height of the best applied block.
+    tip: u32,
+    /// Immature deposits, in order of appearance.
+    pending: Vec<PendingDeposit>,
+}
+
+impl DepositScanner {
+    pub fn new(vault_utxo_token_id: TokenId, confirmation_depth: u32) -> Self {
+        Self {
+            vault_utxo_token_id,
+            confirmation_depth,
+            tip: 0,
+            pending: vec![],
+        }
+    }
+
+    pub fn scan(&mut self, event: &TxEvent<(Transaction, u32)>) -> Vec<DepositEvent> {
+        match event {
+            TxEvent::AppliedTx((tx, height)) => {
+                let spent: Vec<_> = tx.inputs.iter().map(|i| i.box_id).collect();
+                self.apply(tx.id(), *height, &spent, self.deposits_of(tx))
+            }
+            TxEvent::UnappliedTx((tx, height)) => self.unapply(tx.id(), *height, self.deposits_of(tx)),
+        }
+    }
+
+    fn deposits_of(&self, tx: &Transaction) -> Vec<UnprocessedDeposit> {
+        tx.outputs
+            .iter()
+            .filter_map(|bx| extract_deposit(bx, self.vault_utxo_token_id))
+            .collect()
+    }
+
+    fn apply(
+        &mut self,
+        tx_id: TxId,
+        height: u32,
+        spent: &[BoxId],
+        deposits: Vec<UnprocessedDeposit>,
+    ) -> Vec<DepositEvent> {
+        self.tip = self.tip.max(height);
+        // Deposits refunded before maturity are never reported.
+        self.pending.retain(|p| !spent.contains(&p.deposit.0 .1 .1));
+        self.pending
+            .extend(deposits.into_iter().map(|deposit| PendingDeposit {
+                height,
+                tx_id,
+                deposit,
+            }));
+        let (matured, pending) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition::<Vec<_>, _>(|p| p.height.saturating_add(self.confirmation_depth) <= self.tip);
+        self.pending = pending;
+        matured
+            .into_iter()
+            .map(|p| DepositEvent::Matured {
+                tx_id: p.tx_id,
+                deposit: p.deposit,
+            })
+            .collect()
+    }
+
+    fn unapply(&mut self, tx_id: TxId, height: u32, deposits: Vec<UnprocessedDeposit>) -> Vec<DepositEvent> {
+        self.tip = self.tip.min(height.saturating_sub(1));
+        if self.pending.iter().any(|p| p.tx_id == tx_id) {
+            self.pending.retain(|p| p.tx_id != tx_id);
+            vec![]
+        } else {
+            deposits
+                .into_iter()
+                .map(|deposit| DepositEvent::Reverted { tx_id, deposit })
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ergo_lib::chain::transaction::TxId;
+    use ergo_lib::ergo_chain_types::ec_point::generator;
+    use ergo_lib::ergo_chain_types::EcPoint;
+    use ergo_lib::ergotree_ir::chain::address::Address;
+    use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+    use ergo_lib::ergotree_ir::chain::token::TokenId;
+    use ergo_lib::ergotree_ir::sigma_protocol::sigma_boolean::ProveDlog;
+    use k256::ProjectivePoint;
+    use sigma_test_util::force_any_val;
+    use spectrum_offchain_lm::data::AsBox;
+
+    use crate::deposit::{DepositEvent, DepositScanner, UnprocessedDeposit};
+    use crate::script::{owner_of, ErgoCell, ErgoInboundCell};
+
+    fn deposit() -> UnprocessedDeposit {
+        let bx = force_any_val::<ErgoBox>();
+        let cell = ErgoCell {
+            ergs: bx.value,
+            address: Address::P2Pk(ProveDlog::new(generator())),
+            tokens: vec![],
+        };
+        let box_id = bx.box_id();
+        UnprocessedDeposit(AsBox(bx, ErgoInboundCell(cell, box_id)))
+    }
+
+    fn matured(events: Vec<DepositEvent>) -> Vec<UnprocessedDeposit> {
+        events
+            .into_iter()
+            .filter_map(|e| match e {
+                DepositEvent::Matured { deposit, .. } => Some(deposit),
+                DepositEvent::Reverted { .. } => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn only_valid_keys_own_deposits() {
+        assert!(owner_of(&Address::P2Pk(ProveDlog::new(generator()))).is_some());
+        let identity = EcPoint::from(ProjectivePoint::IDENTITY);
+        assert!(owner_of(&Address::P2Pk(ProveDlog::new(identity))).is_none());
+    }
+
+    #[test]
+    fn deposits_reported_at_confirmation_depth() {
+        let mut scanner = DepositScanner::new(force_any_val::<TokenId>(), 2);
+        let (early, late) = (deposit(), deposit());
+        assert!(scanner
+            .apply(force_any_val::<TxId>(), 10, &[], vec![early.clone()])
+            .is_empty());
+        assert!(scanner
+            .apply(force_any_val::<TxId>(), 11, &[], vec![late.clone()])
+            .is_empty());
+        assert_eq!(
+            matured(scanner.apply(force_any_val::<TxId>(), 12, &[], vec![])),
+            vec![early]
+        );
+        // Refunded before maturity.
+        let refund = scanner.apply(force_any_val::<TxId>(), 12, &[late.0 .1 .1], vec![]);
+        assert!(refund.is_empty());
+        assert!(scanner.apply(force_any_val::<TxId>(), 13, &[], vec![]).is_empty());
+    }
+
+    #[test]
+    fn rollbacks_of_immature_and_matured_deposits() {
+        let mut scanner = DepositScanner::new(force_any_val::<TokenId>(), 1);
+        let (shallow, deep) = (deposit(), deposit());
+        let (shallow_tx, deep_tx) = (force_any_val::<TxId>(), force_any_val::<TxId>());
+        assert!(scanner.apply(deep_tx, 10, &[], vec![deep.clone()]).is_empty());
+        assert_eq!(
+            matured(scanner.apply(shallow_tx, 11, &[], vec![shallow.clone()])),
+            vec![deep.clone()]
+        );
+        assert!(scanner.unapply(shallow_tx, 11, vec![shallow]).is_empty());
+        assert!(scanner.apply(force_any_val::<TxId>(), 11, &[], vec![]).is_empty());
+        assert!(scanner.apply(force_any_val::<TxId>(), 12, &[], vec![]).is_empty());
+        assert_eq!(
+            scanner.unapply(deep_tx, 10, vec![deep.clone()]),
+            vec![DepositEvent::Reverted {
+                tx_id: deep_tx,
+                deposit: deep
+            }]
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use std::future::Future;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::{collections::VecDeque, time::Instant};
 
 use chrono::Utc;
@@ -8,37 +10,37 @@ use ergo_lib::{
         ergo_state_context::ErgoStateContext,
         transaction::{unsigned::UnsignedTransaction, DataInput, Transaction, TxIoVec, UnsignedInput},
     },
-    ergo_chain_types::{Digest32, EcPoint},
+    ergo_chain_types::EcPoint,
     ergotree_interpreter::sigma_protocol::prover::ContextExtension,
     ergotree_ir::{
         bigint256::BigInt256,
         chain::{
-            address::Address,
             ergo_box::{
-                box_value::BoxValue, BoxId, BoxTokens, ErgoBox, ErgoBoxCandidate, NonMandatoryRegisterId,
-                NonMandatoryRegisters,
+                box_value::BoxValue, BoxId, BoxTokens, ErgoBox, ErgoBoxCandidate, NonMandatoryRegisters,
             },
             token::TokenId,
         },
         ergo_tree::ErgoTree,
-        mir::{
-            constant::{Constant, Literal},
-            value::{CollKind, NativeColl},
-        },
-        sigma_protocol::sigma_boolean::ProveDlog,
+        mir::constant::Constant,
     },
     wallet::{miner_fee::MINERS_FEE_ADDRESS, tx_context::TransactionContext, Wallet},
 };
 use indexmap::IndexMap;
-use k256::ProjectivePoint;
-use log::info;
+use k256::{ProjectivePoint, Scalar};
+use log::{info, warn};
 use num_bigint::{BigUint, Sign};
+use spectrum_chain_connector::health::{HealthAlert, NodeHealthMonitor};
 use spectrum_chain_connector::{
-    ConnectorStatus, NotarizedReport, NotarizedReportConstraints, PendingTxIdentifier, PendingTxStatus,
-    TxEvent,
+    AccountingQuery, AccountingQueryKind, AccountingReport, AcknowledgementThreshold, CommitteeHandover,
+    ConnectorStatus, NotarizedReport, NotarizedReportConstraints, OperatorApproval,
+    PendingCommitteeRotationStatus, PendingTxIdentifier, PendingTxStatus, StaleExport, TxEvent,
+    VaultBalanceDiscrepancy, VaultMigration, VaultMigrationStatus,
+};
+use spectrum_ledger::{
+    cell::{ProgressPoint, TermCell},
+    interop::{Point, ReportCertificate},
+    ChainId, EpochNo,
 };
-use spectrum_crypto::digest::blake2b256_hash;
-use spectrum_ledger::{cell::ProgressPoint, interop::Point, ChainId};
 use spectrum_offchain::{
     data::unique_entity::{Confirmed, Predicted},
     event_sink::handlers::types::TryFromBoxCtx,
@@ -46,13 +48,22 @@ use spectrum_offchain::{
 };
 use spectrum_offchain_lm::data::AsBox;
 
+use crate::accounting;
+use crate::migration::{
+    build_migration_tx, MigrationError, MigrationOperators, MigrationState, PendingMigration,
+};
+use crate::reconciliation;
+use crate::rotation::{
+    build_handover_tx, build_rotation_txs, committee_keys, guarded_by, verify_handover, PendingRotation,
+    RotationError, RotationState,
+};
 use crate::tx_event::{ErgoTxEvent, ErgoTxType, SpectrumErgoTx};
 use crate::tx_in_progress::{DepositInProgress, TxInProgress, WithdrawalInProgress};
 use crate::vault_utxo::VaultUtxo;
 use crate::AncillaryVaultInfo;
 use crate::{
-    committee::{CommitteeData, FirstCommitteeBox, SubsequentCommitteeBox},
-    deposit::UnprocessedDeposit,
+    committee::CommitteeData,
+    deposit::{DepositEvent, DepositScanner, UnprocessedDeposit},
     rocksdb::{
         deposit::{DepositRepo, DepositRepoRocksDB},
         ergo_tx_event_history::ErgoTxEventHistory,
@@ -61,18 +72,23 @@ use crate::{
         withdrawals::{WithdrawalRepo, WithdrawalRepoRocksDB},
     },
     script::{
-        scalar_to_biguint, serialize_exclusion_set, ErgoCell, ErgoInboundCell, ErgoTermCell, ErgoTermCells,
-        ExtraErgoData, SignatureAggregationWithNotarizationElements, DEPOSIT_CONTRACT, VAULT_CONTRACT,
+        report_digest, scalar_to_biguint, serialize_exclusion_set, ErgoCell, ErgoInboundCell, ErgoTermCell,
+        ErgoTermCells, ExtraErgoData, SignatureAggregationWithNotarizationElements, VAULT_CONTRACT,
     },
 };
 
 const MAX_SYNCED_BLOCK_HEIGHTS: usize = 100;
 const MAX_MOVED_VALUES_PER_RESPONSE: usize = 100;
+const MAX_MIGRATION_MINER_FEE: i64 = 1000000;
+const MAX_ROTATION_MINER_FEE: i64 = 1000000;
+/// Upper bound on the miner fee of any TX spending the vault.
+const MAX_VAULT_TX_MINER_FEE: u64 = 1000000;
 
 pub struct ErgoConnector<MVH, E> {
     vault_box_repo: VaultUtxoRepoRocksDB,
     withdrawal_repo: WithdrawalRepoRocksDB,
     deposit_repo: DepositRepoRocksDB,
+    deposit_scanner: DepositScanner,
     committee_data: CommitteeData,
     synced_block_heights: VecDeque<u32>,
     sync_starting_height: u32,
@@ -81,6 +97,18 @@ pub struct ErgoConnector<MVH, E> {
     dummy_wallet: Wallet,
     vault_utxo_token_id: TokenId,
     genesis_vault_utxo_box_id: Option<VaultUtxo>,
+    migration_operators: MigrationOperators,
+    pending_migration: Option<PendingMigration>,
+    pending_rotation: Option<PendingRotation>,
+    /// Number of blocks the vault stays guarded by the outgoing committee once boxes of the
+    /// incoming one are created.
+    handover_grace_period: u32,
+    /// Exports to be notarized again by the current committee, not yet reported to the driver.
+    stale_exports: Vec<StaleExport>,
+    ack_threshold: AcknowledgementThreshold,
+    node_health: NodeHealthMonitor,
+    /// Number of applied TXs which spent the vault, and thus paid miner fees out of it.
+    num_vault_txs: u64,
 }
 
 impl<M, E> ErgoConnector<M, E>
@@ -99,44 +127,21 @@ where
         sync_starting_height: u32,
         moved_value_history: M,
         tx_retry_scheduler: E,
+        migration_operators: MigrationOperators,
+        handover_grace_period: u32,
+        ack_threshold: AcknowledgementThreshold,
+        node_health: NodeHealthMonitor,
+        deposit_confirmation_depth: u32,
     ) -> Option<Self> {
-        let mut slice_ix = 0_usize;
-
-        let first_box = AsBox(
-            data_inputs.first().clone(),
-            FirstCommitteeBox::try_from_box(
-                data_inputs.first().clone(),
-                (committee_guarding_script.clone(), &committee_public_keys),
-            )?,
-        );
-        slice_ix += first_box.1.public_keys.len();
-
-        let mut subsequent_data_inputs = vec![];
-        for (index, ergo_box) in data_inputs.iter().enumerate().skip(1) {
-            let subsequent = SubsequentCommitteeBox::try_from_box(
-                ergo_box.clone(),
-                (
-                    ergo_box.value,
-                    committee_guarding_script.clone(),
-                    index as u32,
-                    &committee_public_keys[slice_ix..],
-                ),
-            )?;
-            slice_ix += subsequent.public_keys.len();
-            subsequent_data_inputs.push(AsBox(ergo_box.clone(), subsequent));
-        }
-
-        let subsequent_boxes = TxIoVec::try_from(subsequent_data_inputs).ok();
-        let committee_data = CommitteeData {
-            first_box,
-            subsequent_boxes,
-        };
+        let committee_data =
+            CommitteeData::try_from_boxes(committee_guarding_script, &committee_public_keys, data_inputs)?;
         const SEED_PHRASE: &str = "gather gather gather gather gather gather gather gather gather gather gather gather gather gather gather";
         let dummy_wallet = Wallet::from_mnemonic(SEED_PHRASE, "").expect("Invalid seed");
         Some(Self {
             vault_box_repo,
             withdrawal_repo,
             deposit_repo,
+            deposit_scanner: DepositScanner::new(vault_utxo_token_id, deposit_confirmation_depth),
             committee_data,
             synced_block_heights: VecDeque::with_capacity(MAX_SYNCED_BLOCK_HEIGHTS),
             sync_starting_height,
@@ -145,13 +150,53 @@ where
             dummy_wallet,
             vault_utxo_token_id,
             genesis_vault_utxo_box_id: None,
+            migration_operators,
+            pending_migration: None,
+            pending_rotation: None,
+            handover_grace_period,
+            stale_exports: vec![],
+            ack_threshold,
+            node_health,
+            num_vault_txs: 0,
         })
     }
 
     pub async fn handle(&mut self, event: TxEvent<(Transaction, u32)>) {
+        let deposit_events = self.deposit_scanner.scan(&event);
+        let (tx, height) = match &event {
+            TxEvent::AppliedTx((tx, height)) | TxEvent::UnappliedTx((tx, height)) => (tx, *height),
+        };
+        if self
+            .vault_box_repo
+            .get_confirmed(&tx.inputs.first().box_id)
+            .await
+            .is_some()
+        {
+            self.num_vault_txs = match event {
+                TxEvent::AppliedTx(_) => self.num_vault_txs + 1,
+                TxEvent::UnappliedTx(_) => self.num_vault_txs.saturating_sub(1),
+            };
+        }
         match event {
             TxEvent::AppliedTx((tx, height)) => {
+                self.track_migration_applied(&tx, height).await;
+                self.track_rotation_conflicts(&tx);
                 match self.try_extract_vault_tx(&tx).await {
+                    Some(VaultTx::CommitteeRotation { completes_handover }) => {
+                        info!(target: "vault", "COMMITTEE ROTATION TX {:?} FOUND", tx.id());
+                        self.vault_box_repo.spend_box(tx.inputs.first().box_id).await;
+                        let vault_output = tx.outputs.first().clone();
+                        let vault_utxo =
+                            VaultUtxo::try_from_box(vault_output.clone(), self.vault_utxo_token_id).unwrap();
+                        self.vault_box_repo
+                            .put_confirmed(Confirmed(AsBox(vault_output, vault_utxo)))
+                            .await;
+                        if completes_handover {
+                            self.settle_rotation(height).await;
+                        } else {
+                            self.track_committee_boxes_created(height);
+                        }
+                    }
                     Some(VaultTx::Withdrawals { terminal_cells }) => {
                         info!(target: "vault", "VAULT WITHDRAWAL TX {:?} FOUND", tx.id());
                         // Spend input vault box
@@ -173,22 +218,7 @@ where
                         }
 
                         // If this Tx was in the mempool and tracked, we can confirm it now.
-                        match self.tx_retry_scheduler.next_command().await {
-                            Command::ResubmitTx(tx_in_progress) | Command::Wait(_, tx_in_progress) => {
-                                // If the signed-input of the vault UTXO coincides with the input tracked
-                                // by `tx_retry_scheduler`, we can be sure it is our Tx that has been
-                                // confirmed.
-                                if let TxInProgress::Withdrawal(ref tracked_withdrawal) = tx_in_progress {
-                                    if tracked_withdrawal.vault_utxo_signed_input == *tx.inputs.first() {
-                                        info!(target: "vault", "VAULT WITHDRAWAL TX {:?} CONFIRMED", tx.id());
-                                        self.tx_retry_scheduler.notify_confirmed(&tx_in_progress).await;
-                                    }
-                                } else {
-                                    panic!("Expecting withdrawal TX in progress, not deposits!");
-                                }
-                            }
-                            _ => (),
-                        }
+                        self.confirm_tracked_tx(&tx, height).await;
 
                         let vault_info = (
                             vault_utxo,
@@ -230,22 +260,7 @@ where
                         }
 
                         // If this Tx was in the mempool and tracked, we can confirm it now.
-                        match self.tx_retry_scheduler.next_command().await {
-                            Command::ResubmitTx(tx_in_progress) | Command::Wait(_, tx_in_progress) => {
-                                // If the signed-input of the vault UTXO coincides with the input tracked
-                                // by `deposit_tx_retry_scheduler`, we can be sure it is our Tx that has been
-                                // confirmed.
-                                if let TxInProgress::Deposit(ref tracked_deposit) = tx_in_progress {
-                                    if tracked_deposit.vault_utxo_signed_input == *tx.inputs.first() {
-                                        info!(target: "vault", "VAULT DEPOSIT TX {:?} CONFIRMED", tx.id());
-                                        self.tx_retry_scheduler.notify_confirmed(&tx_in_progress).await;
-                                    }
-                                } else {
-                                    panic!("Expecting deposit TX in progress, not withdrawal!");
-                                }
-                            }
-                            _ => (),
-                        }
+                        self.confirm_tracked_tx(&tx, height).await;
 
                         let vault_info = (
                             vault_utxo,
@@ -289,23 +304,9 @@ where
                             }
                         }
 
-                        // Scan for genesis Vault UTxO and created deposit boxes
+                        // Scan for genesis Vault UTxO. Deposit boxes are tracked by the deposit scanner.
                         for output in &tx.outputs {
-                            if let Some(unprocessed_deposit) = self.try_extract_unprocessed_deposit(output) {
-                                info!(
-                                    target: "vault",
-                                    "DEPOSIT FOUND (value: {:?} nErgs), height: {}",
-                                    unprocessed_deposit.0 .1 .0.ergs, height
-                                );
-                                self.deposit_repo.put(unprocessed_deposit.clone()).await;
-                                self.moved_value_history
-                                    .append(ErgoTxEvent::Applied(SpectrumErgoTx {
-                                        progress_point: height,
-                                        tx_id: tx.id(),
-                                        tx_type: ErgoTxType::NewUnprocessedDeposit(unprocessed_deposit.0 .1),
-                                    }))
-                                    .await;
-                            } else if let Some(vault_utxo) =
+                            if let Some(vault_utxo) =
                                 VaultUtxo::try_from_box(output.clone(), self.vault_utxo_token_id)
                             {
                                 info!("GENESIS VAULT UTXO {:?} FOUND", output.box_id());
@@ -328,8 +329,19 @@ where
                 }
             }
             TxEvent::UnappliedTx((tx, height)) => {
+                self.track_migration_unapplied(&tx).await;
                 match self.try_extract_vault_tx(&tx).await {
+                    Some(VaultTx::CommitteeRotation { completes_handover }) => {
+                        self.vault_box_repo.unspend_box(tx.inputs.first().box_id).await;
+                        self.vault_box_repo.remove(tx.outputs.first().box_id()).await;
+                        if completes_handover {
+                            self.unsettle_rotation();
+                        } else {
+                            self.track_committee_boxes_removed();
+                        }
+                    }
                     Some(VaultTx::Withdrawals { terminal_cells }) => {
+                        self.unconfirm_tracked_tx(&tx).await;
                         // Add back previous vault box
                         let prev_vault_box_id = tx.inputs.first().box_id;
                         self.vault_box_repo.unspend_box(prev_vault_box_id).await;
@@ -367,6 +379,7 @@ where
                         self.moved_value_history.append(ergo_moved_value).await;
                     }
                     Some(VaultTx::Deposits { deposits }) => {
+                        self.unconfirm_tracked_tx(&tx).await;
                         // Add back previous vault box
                         let prev_vault_box_id = tx.inputs.first().box_id;
                         self.vault_box_repo.unspend_box(prev_vault_box_id).await;
@@ -403,16 +416,7 @@ where
                         let ergo_moved_value = ErgoTxEvent::Unapplied(tx);
                         self.moved_value_history.append(ergo_moved_value).await;
                     }
-                    None => {
-                        // Check for unprocessed deposits and remove them
-                        for output in &tx.outputs {
-                            if let Some(unprocessed_deposit) = self.try_extract_unprocessed_deposit(output) {
-                                self.deposit_repo
-                                    .remove_unprocessed(unprocessed_deposit.0.box_id())
-                                    .await;
-                            }
-                        }
-                    }
+                    None => {}
                 }
                 if let Some(last_synced_height) = self.synced_block_heights.back() {
                     if *last_synced_height == height {
@@ -421,23 +425,97 @@ where
                 }
             }
         }
+        self.track_deposits(deposit_events, height).await;
+    }
+
+    /// Import deposits which reached the confirmation depth and drop those rolled back afterwards.
+    async fn track_deposits(&mut self, events: Vec<DepositEvent>, height: u32) {
+        for event in events {
+            match event {
+                DepositEvent::Matured { tx_id, deposit } => {
+                    info!(
+                        target: "vault",
+                        "DEPOSIT FOUND (value: {:?} nErgs), height: {}",
+                        deposit.0 .1 .0.ergs, height
+                    );
+                    self.deposit_repo.put(deposit.clone()).await;
+                    self.moved_value_history
+                        .append(ErgoTxEvent::Applied(SpectrumErgoTx {
+                            progress_point: height,
+                            tx_id,
+                            tx_type: ErgoTxType::NewUnprocessedDeposit(deposit.0 .1),
+                        }))
+                        .await;
+                }
+                DepositEvent::Reverted { tx_id, deposit } => {
+                    let box_id = deposit.0 .1 .1;
+                    // Deposits already processed or refunded are rolled back along with their TXs.
+                    if self.deposit_repo.get_unprocessed(box_id).await.is_some() {
+                        self.deposit_repo.remove_unprocessed(box_id).await;
+                        self.moved_value_history
+                            .append(ErgoTxEvent::Unapplied(SpectrumErgoTx {
+                                progress_point: height,
+                                tx_id,
+                                tx_type: ErgoTxType::NewUnprocessedDeposit(deposit.0 .1),
+                            }))
+                            .await;
+                    }
+                }
+            }
+        }
     }
 
     pub fn get_genesis_vault_utxo(&self) -> Option<VaultUtxo> {
         self.genesis_vault_utxo_box_id.clone()
     }
 
-    pub async fn handle_tx_resubmission(&mut self, ergo_node: &ErgoNodeHttpClient) {
-        let withdrawal_command = self.tx_retry_scheduler.next_command().await;
-        if let Command::ResubmitTx(tx) = withdrawal_command {
-            match tx {
-                TxInProgress::Withdrawal(e) => {
-                    info!(target: "vault", "Resubmitting withdrawal tx");
-                    self.withdraw_value(e.report, true, e.vault_utxo, ergo_node).await;
+    /// Mark the pending TX which spends the same vault UTXO as `tx` as confirmed at `height`.
+    async fn confirm_tracked_tx(&mut self, tx: &Transaction, height: u32) {
+        for command in self.tx_retry_scheduler.all_commands().await {
+            if let Command::ResubmitTx(tx_in_progress) | Command::Wait(_, tx_in_progress) = command {
+                // If the signed-input of the vault UTXO coincides with the input tracked
+                // by `tx_retry_scheduler`, we can be sure it is our Tx that has been
+                // confirmed.
+                if *tx_in_progress.vault_utxo_signed_input() == *tx.inputs.first() {
+                    info!(target: "vault", "VAULT TX {:?} CONFIRMED", tx.id());
+                    self.tx_retry_scheduler
+                        .notify_confirmed(&tx_in_progress, height)
+                        .await;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Return the confirmed pending TX which spends the same vault UTXO as rolled back `tx` to
+    /// the in-progress state.
+    async fn unconfirm_tracked_tx(&mut self, tx: &Transaction) {
+        for command in self.tx_retry_scheduler.all_commands().await {
+            if let Command::Confirmed(_, tx_in_progress) = command {
+                if *tx_in_progress.vault_utxo_signed_input() == *tx.inputs.first() {
+                    info!(target: "vault", "VAULT TX {:?} ROLLED BACK", tx.id());
+                    self.tx_retry_scheduler.notify_unconfirmed(&tx_in_progress).await;
+                    return;
                 }
-                TxInProgress::Deposit(d) => {
-                    info!(target: "vault", "Resubmitting deposit tx");
-                    self.process_deposits(true, ergo_node).await;
+            }
+        }
+    }
+
+    /// Resubmit due TXs in order of their original submission, since later TXs may spend outputs
+    /// of earlier ones.
+    pub async fn handle_tx_resubmission(&mut self, ergo_node: &ErgoNodeHttpClient) {
+        self.submit_handover_if_due(ergo_node).await;
+        for command in self.tx_retry_scheduler.all_commands().await {
+            if let Command::ResubmitTx(tx) = command {
+                match tx {
+                    TxInProgress::Withdrawal(e) => {
+                        info!(target: "vault", "Resubmitting withdrawal tx");
+                        self.withdraw_value(e.report, true, e.vault_utxo, ergo_node).await;
+                    }
+                    TxInProgress::Deposit(_) => {
+                        info!(target: "vault", "Resubmitting deposit tx");
+                        self.process_deposits(true, ergo_node).await;
+                    }
                 }
             }
         }
@@ -464,24 +542,134 @@ where
             point: Point::from(current_sync_height as u64),
         };
 
-        let pending_tx_status = Option::<PendingTxStatus<ExtraErgoData, BoxId>>::from(
-            self.tx_retry_scheduler.next_command().await,
-        );
+        let pending_txs = self.pending_tx_statuses(current_sync_height).await;
+        let node_health = self.node_health.health(SystemTime::now());
 
         if current_height > current_sync_height {
             ConnectorStatus::Syncing {
                 current_progress_point,
                 num_points_remaining: current_height - current_sync_height,
-                pending_tx_status,
+                pending_txs,
+                node_health,
             }
         } else {
             ConnectorStatus::Synced {
                 current_progress_point,
-                pending_tx_status,
+                pending_txs,
+                node_health,
             }
         }
     }
 
+    /// Probe the node. Returns an alert if its health changed since the previous probe.
+    pub async fn probe_node(&mut self, ergo_node: &ErgoNodeHttpClient) -> Option<HealthAlert> {
+        let ctx = observe_node_call(
+            &mut self.node_health,
+            "state_context",
+            ergo_node.get_ergo_state_context(),
+        )
+        .await;
+        if let Ok(ctx) = ctx {
+            // Headers are ordered from the most recent one, timestamps are in millis.
+            let tip_time = UNIX_EPOCH + Duration::from_millis(ctx.headers[0].timestamp);
+            self.node_health.record_tip(tip_time);
+        }
+        self.node_health.check(SystemTime::now())
+    }
+
+    async fn pending_tx_statuses(
+        &self,
+        current_sync_height: u32,
+    ) -> Vec<PendingTxStatus<ExtraErgoData, BoxId>> {
+        self.tx_retry_scheduler
+            .all_commands()
+            .await
+            .into_iter()
+            .filter_map(|command| command.pending_tx_status(current_sync_height, self.ack_threshold))
+            .chain(self.pending_rotation.as_ref().map(|rotation| {
+                PendingTxStatus::CommitteeRotation(rotation.status(current_sync_height, self.ack_threshold))
+            }))
+            .collect()
+    }
+
+    /// Reconcile the vault UTxO set against the genesis value of the vault and deposits and
+    /// withdrawals reported to Spectrum. Nothing to reconcile until the genesis vault UTxO is found.
+    pub async fn reconcile_vault(&self) -> Option<VaultBalanceDiscrepancy> {
+        let genesis_vault_utxo = self.genesis_vault_utxo_box_id.as_ref()?;
+        let current_sync_height = self
+            .synced_block_heights
+            .back()
+            .copied()
+            .unwrap_or(self.sync_starting_height);
+        let vault_utxos: Vec<_> = self
+            .vault_box_repo
+            .get_all_confirmed()
+            .await
+            .into_iter()
+            .map(|Confirmed(AsBox(_, vault_utxo))| vault_utxo)
+            .collect();
+        let events = self
+            .moved_value_history
+            .range(self.sync_starting_height, current_sync_height)
+            .await;
+        reconciliation::reconcile(
+            &vault_utxos,
+            genesis_vault_utxo,
+            events,
+            self.num_vault_txs.saturating_mul(MAX_VAULT_TX_MINER_FEE),
+            ProgressPoint {
+                chain_id: ChainId::from(0),
+                point: Point::from(current_sync_height as u64),
+            },
+        )
+    }
+
+    /// Answer accounting query of an operator from the history of moved value and pending TXs.
+    pub async fn query_accounting(&self, query: AccountingQuery) -> AccountingReport {
+        let to_height = |point: Point| u32::try_from(u64::from(point)).unwrap_or(u32::MAX);
+        let content = match query.kind {
+            AccountingQueryKind::BalanceHistory { from, to } => {
+                let events = self
+                    .moved_value_history
+                    .range(to_height(from), to_height(to))
+                    .await;
+                accounting::export(&accounting::balance_history(events), query.format)
+            }
+            AccountingQueryKind::FlowsPerEpoch {
+                from,
+                to,
+                epoch_length,
+            } => {
+                let events = self
+                    .moved_value_history
+                    .range(to_height(from), to_height(to))
+                    .await;
+                let epoch_length = u32::try_from(epoch_length).unwrap_or(u32::MAX);
+                let flows = accounting::flows_per_epoch(events, to_height(from), epoch_length);
+                accounting::export(&flows, query.format)
+            }
+            AccountingQueryKind::LargestPendingWithdrawals { limit } => {
+                let current_sync_height = self
+                    .synced_block_heights
+                    .back()
+                    .copied()
+                    .unwrap_or(self.sync_starting_height);
+                let withdrawals = self
+                    .pending_tx_statuses(current_sync_height)
+                    .await
+                    .into_iter()
+                    .filter_map(|status| match status {
+                        PendingTxStatus::Withdrawal(w) => Some(w),
+                        PendingTxStatus::Deposit(_) | PendingTxStatus::CommitteeRotation(_) => None,
+                    })
+                    .collect();
+                let records = accounting::largest_pending_withdrawals(withdrawals, limit);
+                accounting::export(&records, query.format)
+            }
+        };
+        AccountingReport { query, content }
+    }
+
     pub async fn sync_consensus_driver(&self, from_height: Option<u32>) -> Vec<ErgoTxEvent> {
         let mut res = vec![];
         let mut height = from_height.map(|h| h + 1).unwrap_or(self.sync_starting_height);
@@ -502,6 +690,14 @@ where
             info!(target: "vault", "CHAIN TIP NOT REACHED");
             return false;
         }
+        if self.migration_submitted() {
+            info!(target: "vault", "VAULT MIGRATION IN PROGRESS");
+            return false;
+        }
+        if self.rotation_submitted(current_height) {
+            info!(target: "vault", "COMMITTEE ROTATION IN PROGRESS");
+            return false;
+        }
 
         let max_miner_fee = 1000000_i64;
         let max_miner_fee_constant = Constant::from(max_miner_fee);
@@ -576,10 +772,7 @@ where
             creation_height: current_height,
         };
         let outputs = TxIoVec::from_vec(vec![vault_output_box, miner_output]).unwrap();
-        let mut data_boxes = vec![self.committee_data.first_box.0.clone()];
-        if let Some(subsequent) = &self.committee_data.subsequent_boxes {
-            data_boxes.extend(subsequent.iter().map(|AsBox(bx, _)| bx.clone()));
-        }
+        let data_boxes = self.committee_data.boxes();
         let data_inputs: Vec<_> = data_boxes
             .iter()
             .map(|d| DataInput { box_id: d.box_id() })
@@ -607,7 +800,16 @@ where
             timestamp: Utc::now().timestamp(),
         });
 
-        if let Err(e) = ergo_node.submit_tx(signed_tx).await {
+        if !is_resubmission {
+            if let Err(e) = self.tx_retry_scheduler.check(&deposit).await {
+                info!(target: "vault", "Deposit TX can't be submitted now: {:?}", e);
+                return false;
+            }
+        }
+
+        if let Err(e) =
+            observe_node_call(&mut self.node_health, "submit_tx", ergo_node.submit_tx(signed_tx)).await
+        {
             println!("ERGO NODE ERROR: {:?}", e);
             if is_resubmission {
                 self.tx_retry_scheduler.notify_failed(&deposit).await;
@@ -629,7 +831,13 @@ where
             }
 
             if !is_resubmission {
-                self.tx_retry_scheduler.add(deposit).await;
+                // Admission was checked prior to submission, but the window may have filled since.
+                if let Err(e) = self.tx_retry_scheduler.add(deposit).await {
+                    warn!(
+                        target: "vault",
+                        "Deposit TX {:?} isn't tracked for resubmission: {:?}", tx_id, e
+                    );
+                }
             }
 
             true
@@ -648,13 +856,39 @@ where
             info!(target: "vault", "CHAIN TIP NOT REACHED");
             return false;
         }
+        if self.migration_submitted() {
+            info!(target: "vault", "VAULT MIGRATION IN PROGRESS");
+            return false;
+        }
+        if self.rotation_submitted(current_height) {
+            info!(target: "vault", "COMMITTEE ROTATION IN PROGRESS");
+            return false;
+        }
+        // The vault contract verifies the certificate against the digest of the report itself.
+        if report.inclusion_proof.is_some() {
+            info!(target: "vault", "BATCHED REPORTS AREN'T SUPPORTED BY THE VAULT CONTRACT");
+            return false;
+        }
+        // The vault contract accepts certificates of the committee the vault refers to only.
+        if !guarded_by(&vault_utxo, &self.committee_data) {
+            info!(target: "vault", "VAULT UTXO ISN'T GUARDED BY THE CURRENT COMMITTEE");
+            let previous_committee = self
+                .pending_rotation
+                .as_ref()
+                .and_then(|rotation| rotation.previous_committee.as_ref());
+            if let Some(previous_committee) = previous_committee {
+                if guarded_by(&vault_utxo, previous_committee) {
+                    let certified_in_epoch = previous_committee.first_box.1.vault_parameters.current_epoch;
+                    let stale_export = self.stale_export(certified_in_epoch, report.value_to_withdraw);
+                    self.stale_exports.push(stale_export);
+                }
+            }
+            return false;
+        }
 
         let inputs = SignatureAggregationWithNotarizationElements::from(report.clone());
         let ergo_state_context = ergo_node.get_ergo_state_context().await.unwrap();
-        let mut data_boxes = vec![self.committee_data.first_box.0.clone()];
-        if let Some(subsequent) = &self.committee_data.subsequent_boxes {
-            data_boxes.extend(subsequent.iter().map(|AsBox(bx, _)| bx.clone()));
-        }
+        let data_boxes = self.committee_data.boxes();
         let signed_tx = verify_vault_contract_ergoscript_with_sigma_rust(
             inputs,
             self.committee_data.committee_size(),
@@ -681,8 +915,18 @@ where
             vault_utxo_signed_input: signed_tx.inputs.first().clone(),
             vault_utxo,
             timestamp: Utc::now().timestamp(),
+            committee_epoch: self.current_epoch(),
         });
-        if let Err(e) = ergo_node.submit_tx(signed_tx).await {
+        if !is_resubmission {
+            if let Err(e) = self.tx_retry_scheduler.check(&withdrawal).await {
+                info!(target: "vault", "Withdrawal TX can't be submitted now: {:?}", e);
+                return false;
+            }
+        }
+
+        if let Err(e) =
+            observe_node_call(&mut self.node_health, "submit_tx", ergo_node.submit_tx(signed_tx)).await
+        {
             println!("ERGO NODE ERROR: {:?}", e);
             if is_resubmission {
                 self.tx_retry_scheduler.notify_failed(&withdrawal).await;
@@ -704,22 +948,387 @@ where
             }
 
             if !is_resubmission {
-                self.tx_retry_scheduler.add(withdrawal).await;
+                // Admission was checked prior to submission, but the window may have filled since.
+                if let Err(e) = self.tx_retry_scheduler.add(withdrawal).await {
+                    warn!(
+                        target: "vault",
+                        "Withdrawal TX {:?} isn't tracked for resubmission: {:?}", tx_id, e
+                    );
+                }
             }
 
             true
         }
     }
 
+    /// Start migration of the vault to an upgraded contract. Nothing is submitted until the
+    /// migration is approved by enough operators, see [`Self::approve_vault_migration`].
+    pub async fn propose_vault_migration(
+        &mut self,
+        migration: VaultMigration,
+    ) -> Result<VaultMigrationStatus, MigrationError> {
+        if self.migration_operators.keys.is_empty() {
+            return Err(MigrationError::NoOperators);
+        }
+        if let Some(pending) = &self.pending_migration {
+            if !matches!(pending.state, MigrationState::Settled { .. }) {
+                return Err(MigrationError::MigrationInProgress);
+            }
+        }
+        // For now we assume only 1 vault UTxO
+        let Confirmed(AsBox(vault_utxo, _)) = self
+            .vault_box_repo
+            .get_all_confirmed()
+            .await
+            .first()
+            .cloned()
+            .ok_or(MigrationError::NoVaultUtxo)?;
+        let pending = PendingMigration::new(vault_utxo, migration, &self.committee_data.public_keys())?;
+        let status = pending.status(&self.migration_operators);
+        info!(target: "vault", "VAULT MIGRATION PROPOSED: {:?}", status);
+        self.pending_migration = Some(pending);
+        Ok(status)
+    }
+
+    /// Register approval of the pending migration. The migration TX is built and submitted as
+    /// soon as the approval threshold is reached.
+    pub async fn approve_vault_migration(
+        &mut self,
+        approval: OperatorApproval,
+        ergo_node: &ErgoNodeHttpClient,
+    ) -> Result<VaultMigrationStatus, MigrationError> {
+        let data_boxes = self.committee_data.boxes();
+        let committee_size = self.committee_data.committee_size();
+        let migration = self
+            .pending_migration
+            .as_mut()
+            .ok_or(MigrationError::NoPendingMigration)?;
+        if migration.approve(&self.migration_operators, &approval)? {
+            let current_height = ergo_node.get_height().await;
+            let ergo_state_context = ergo_node.get_ergo_state_context().await.unwrap();
+            let signed_tx = build_migration_tx(
+                migration,
+                committee_size,
+                self.vault_utxo_token_id,
+                data_boxes,
+                &ergo_state_context,
+                &self.dummy_wallet,
+                MAX_MIGRATION_MINER_FEE,
+                current_height,
+            )?;
+            let tx_id = signed_tx.id();
+            if let Err(e) =
+                observe_node_call(&mut self.node_health, "submit_tx", ergo_node.submit_tx(signed_tx)).await
+            {
+                // Approvals are kept, so the submission is retried on the next approval.
+                return Err(MigrationError::TxRejected(format!("{:?}", e)));
+            }
+            info!(target: "vault", "VAULT MIGRATION TX {:?} SUBMITTED", tx_id);
+            migration.state = MigrationState::Submitted { tx_id };
+        }
+        Ok(migration.status(&self.migration_operators))
+    }
+
+    pub fn get_vault_migration_status(&self) -> Option<VaultMigrationStatus> {
+        self.pending_migration
+            .as_ref()
+            .map(|m| m.status(&self.migration_operators))
+    }
+
+    async fn track_migration_applied(&mut self, tx: &Transaction, height: u32) {
+        let Some(migration) = &mut self.pending_migration else {
+            return;
+        };
+        if !migration.spends_vault_utxo(tx) {
+            return;
+        }
+        match &migration.state {
+            MigrationState::Submitted { tx_id } if *tx_id == tx.id() => {
+                info!(target: "vault", "VAULT MIGRATION TX {:?} SETTLED", tx.id());
+                migration.state = MigrationState::Settled {
+                    tx_id: tx.id(),
+                    height,
+                };
+                self.vault_box_repo.spend_box(tx.inputs.first().box_id).await;
+            }
+            MigrationState::AwaitingApproval { .. } => {
+                // The certificate is bound to the spent vault box, so the migration is void now.
+                info!(target: "vault", "VAULT MIGRATION DISCARDED, VAULT UTXO SPENT BY {:?}", tx.id());
+                self.pending_migration = None;
+            }
+            _ => {}
+        }
+    }
+
+    async fn track_migration_unapplied(&mut self, tx: &Transaction) {
+        if let Some(migration) = &mut self.pending_migration {
+            if matches!(&migration.state, MigrationState::Settled { tx_id, .. } if *tx_id == tx.id()) {
+                migration.state = MigrationState::Submitted { tx_id: tx.id() };
+                self.vault_box_repo.unspend_box(tx.inputs.first().box_id).await;
+            }
+        }
+    }
+
+    fn migration_submitted(&self) -> bool {
+        self.pending_migration
+            .as_ref()
+            .map(|m| m.is_submitted())
+            .unwrap_or(false)
+    }
+
+    /// Hand the vault over to the incoming committee. Deposits and withdrawals are suspended
+    /// while the rotation TXs are pending. If the grace period is set, only boxes of the incoming
+    /// committee are created for now, see [`Self::submit_handover_if_due`].
+    pub async fn rotate_committee(
+        &mut self,
+        handover: CommitteeHandover,
+        ergo_node: &ErgoNodeHttpClient,
+    ) -> Result<PendingCommitteeRotationStatus, RotationError> {
+        if self.pending_rotation.is_some() {
+            return Err(RotationError::RotationInProgress);
+        }
+        let current_height = ergo_node.get_height().await;
+        if self.migration_submitted() || !self.pending_tx_statuses(current_height).await.is_empty() {
+            return Err(RotationError::PendingTxs);
+        }
+        let certificate = verify_handover(&handover, self.current_epoch())?;
+        // For now we assume only 1 vault UTxO
+        let Confirmed(AsBox(vault_utxo, _)) = self
+            .vault_box_repo
+            .get_all_confirmed()
+            .await
+            .first()
+            .cloned()
+            .ok_or(RotationError::NoVaultUtxo)?;
+        let ergo_state_context = ergo_node.get_ergo_state_context().await.unwrap();
+        let (committee_tx, handover_tx) = build_rotation_txs(
+            &handover,
+            &certificate,
+            &self.committee_data,
+            vault_utxo,
+            self.vault_utxo_token_id,
+            &ergo_state_context,
+            &self.dummy_wallet,
+            MAX_ROTATION_MINER_FEE,
+            current_height,
+        )?;
+        // The handover TX spends an output of the committee TX, so they are submitted in this order.
+        let txs = if self.handover_grace_period == 0 {
+            vec![committee_tx.clone(), handover_tx.clone()]
+        } else {
+            vec![committee_tx.clone()]
+        };
+        for tx in txs {
+            let tx_id = tx.id();
+            if let Err(e) =
+                observe_node_call(&mut self.node_health, "submit_tx", ergo_node.submit_tx(tx)).await
+            {
+                return Err(RotationError::TxRejected(format!("{:?}", e)));
+            }
+            info!(target: "vault", "COMMITTEE ROTATION TX {:?} SUBMITTED", tx_id);
+        }
+        let rotation = PendingRotation {
+            handover,
+            committee_tx,
+            handover_tx,
+            state: RotationState::Submitted,
+            previous_committee: None,
+            committee_boxes_height: None,
+        };
+        let status = rotation.status(current_height, self.ack_threshold);
+        self.pending_rotation = Some(rotation);
+        Ok(status)
+    }
+
+    fn track_committee_boxes_created(&mut self, height: u32) {
+        if let Some(rotation) = &mut self.pending_rotation {
+            rotation.committee_boxes_height = Some(height);
+            if self.handover_grace_period == 0 {
+                rotation.state = RotationState::HandingOver;
+            } else {
+                info!(
+                    target: "vault",
+                    "INCOMING COMMITTEE BOXES CREATED, HANDOVER AT HEIGHT {}",
+                    height + self.handover_grace_period
+                );
+                rotation.state = RotationState::GracePeriod;
+            }
+        }
+    }
+
+    fn track_committee_boxes_removed(&mut self) {
+        if let Some(rotation) = &mut self.pending_rotation {
+            rotation.committee_boxes_height = None;
+            rotation.state = RotationState::Submitted;
+        }
+    }
+
+    /// Submit the handover TX once the grace period is over. The TX is rebuilt if the vault was
+    /// spent by exports of the outgoing committee in the meantime.
+    async fn submit_handover_if_due(&mut self, ergo_node: &ErgoNodeHttpClient) {
+        let current_height = ergo_node.get_height().await;
+        let grace_period = self.handover_grace_period;
+        if !matches!(&self.pending_rotation, Some(r) if r.is_handover_due(current_height, grace_period)) {
+            return;
+        }
+        let Some(Confirmed(AsBox(vault_utxo, _))) =
+            self.vault_box_repo.get_all_confirmed().await.first().cloned()
+        else {
+            return;
+        };
+        let rotation = self.pending_rotation.as_mut().unwrap();
+        if vault_utxo.box_id() != rotation.handover_tx.inputs.first().box_id {
+            let ReportCertificate::SchnorrK256(certificate) = rotation.handover.certificate.clone();
+            let ergo_state_context = ergo_node.get_ergo_state_context().await.unwrap();
+            match build_handover_tx(
+                &certificate,
+                &self.committee_data,
+                &rotation.new_committee_boxes(),
+                vault_utxo,
+                self.vault_utxo_token_id,
+                &ergo_state_context,
+                &self.dummy_wallet,
+                MAX_ROTATION_MINER_FEE,
+                current_height,
+            ) {
+                Ok(tx) => rotation.handover_tx = tx,
+                Err(e) => {
+                    warn!(target: "vault", "Cannot rebuild committee handover TX: {:?}", e);
+                    return;
+                }
+            }
+        }
+        let tx = rotation.handover_tx.clone();
+        let tx_id = tx.id();
+        if let Err(e) = observe_node_call(&mut self.node_health, "submit_tx", ergo_node.submit_tx(tx)).await {
+            warn!(target: "vault", "Committee handover TX {:?} rejected: {:?}", tx_id, e);
+            return;
+        }
+        info!(target: "vault", "COMMITTEE HANDOVER TX {:?} SUBMITTED", tx_id);
+        rotation.state = RotationState::HandingOver;
+    }
+
+    /// The vault is guarded by the incoming committee from now on. Pending exports certified by
+    /// the outgoing committee can't settle anymore, so they are aborted and reported to the driver.
+    async fn settle_rotation(&mut self, height: u32) {
+        let Some(rotation) = &mut self.pending_rotation else {
+            return;
+        };
+        let new_committee = CommitteeData::try_from_boxes(
+            self.committee_data.first_box.1.guarding_script.clone(),
+            &committee_keys(&rotation.handover),
+            TxIoVec::from_vec(rotation.new_committee_boxes()).unwrap(),
+        )
+        .expect("Committee boxes are built by the connector");
+        info!(target: "vault", "COMMITTEE ROTATION SETTLED AT HEIGHT {}", height);
+        rotation.state = RotationState::Settled { height };
+        rotation.previous_committee = Some(std::mem::replace(&mut self.committee_data, new_committee));
+
+        let current_epoch = self.current_epoch();
+        for command in self.tx_retry_scheduler.all_commands().await {
+            let (Command::ResubmitTx(tx) | Command::Wait(_, tx)) = command else {
+                continue;
+            };
+            if let TxInProgress::Withdrawal(withdrawal) = &tx {
+                if withdrawal.committee_epoch < current_epoch {
+                    info!(target: "vault", "EXPORT CERTIFIED IN EPOCH {} IS STALE", withdrawal.committee_epoch);
+                    self.tx_retry_scheduler.notify_superseded(&tx).await;
+                    let stale_export = self.stale_export(
+                        withdrawal.committee_epoch,
+                        withdrawal.report.value_to_withdraw.clone(),
+                    );
+                    self.stale_exports.push(stale_export);
+                }
+            }
+        }
+    }
+
+    fn unsettle_rotation(&mut self) {
+        if let Some(rotation) = &mut self.pending_rotation {
+            if let Some(previous_committee) = rotation.previous_committee.take() {
+                info!(target: "vault", "COMMITTEE ROTATION ROLLED BACK");
+                self.committee_data = previous_committee;
+                rotation.state = RotationState::HandingOver;
+            }
+        }
+    }
+
+    fn track_rotation_conflicts(&mut self, tx: &Transaction) {
+        if let Some(rotation) = &mut self.pending_rotation {
+            match rotation.state {
+                RotationState::Submitted if rotation.conflicts_with(tx) => {
+                    info!(target: "vault", "COMMITTEE ROTATION ABORTED, VAULT UTXO SPENT BY {:?}", tx.id());
+                    rotation.state = RotationState::Aborted;
+                }
+                // An export of the outgoing committee got ahead of the handover, which is to be rebuilt.
+                RotationState::HandingOver if rotation.supersedes_handover(tx) => {
+                    info!(target: "vault", "COMMITTEE HANDOVER SUPERSEDED BY {:?}", tx.id());
+                    rotation.state = RotationState::GracePeriod;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether deposits and withdrawals have to wait for the rotation, i.e. rotation TXs are
+    /// pending or the handover is due.
+    fn rotation_submitted(&self, current_height: u32) -> bool {
+        self.pending_rotation
+            .as_ref()
+            .map(|r| r.is_submitted() || r.is_handover_due(current_height, self.handover_grace_period))
+            .unwrap_or(false)
+    }
+
+    fn current_epoch(&self) -> i32 {
+        self.committee_data.first_box.1.vault_parameters.current_epoch
+    }
+
+    fn stale_export(&self, certified_in_epoch: i32, value_to_withdraw: Vec<TermCell>) -> StaleExport {
+        StaleExport {
+            certified_in_epoch: EpochNo::from(certified_in_epoch as u64),
+            current_epoch: EpochNo::from(self.current_epoch() as u64),
+            value_to_withdraw,
+        }
+    }
+
+    /// Exports to be notarized again, see [`StaleExport`].
+    pub fn take_stale_exports(&mut self) -> Vec<StaleExport> {
+        std::mem::take(&mut self.stale_exports)
+    }
+
     pub async fn acknowledge_confirmed_tx(&mut self, data: &PendingTxIdentifier<ExtraErgoData, BoxId>) {
+        if let PendingTxIdentifier::CommitteeRotation(handover) = data {
+            if matches!(&self.pending_rotation, Some(r) if r.handover == **handover
+                && matches!(r.state, RotationState::Settled { .. }))
+            {
+                self.pending_rotation = None;
+            }
+            return;
+        }
         self.tx_retry_scheduler.clear_confirmed(data).await;
     }
 
     pub async fn acknowledge_aborted_tx(&mut self, data: &PendingTxIdentifier<ExtraErgoData, BoxId>) {
+        if let PendingTxIdentifier::CommitteeRotation(handover) = data {
+            if matches!(&self.pending_rotation, Some(r) if r.handover == **handover
+                && r.state == RotationState::Aborted)
+            {
+                self.pending_rotation = None;
+            }
+            return;
+        }
         self.tx_retry_scheduler.clear_aborted(data).await;
     }
 
     async fn try_extract_vault_tx(&self, tx: &Transaction) -> Option<VaultTx> {
+        // Rotation TXs move value out of the vault too, so they have to be told apart from withdrawals.
+        if let Some(rotation) = &self.pending_rotation {
+            if rotation.is_rotation_tx(tx) {
+                return Some(VaultTx::CommitteeRotation {
+                    completes_handover: tx.id() == rotation.handover_tx.id(),
+                });
+            }
+        }
         if let Some(vault_utxo) =
             VaultUtxo::try_from_box(tx.outputs.first().clone(), self.vault_utxo_token_id)
         {
@@ -751,41 +1360,6 @@ where
         }
         None
     }
-
-    fn try_extract_unprocessed_deposit(&self, bx: &ErgoBox) -> Option<UnprocessedDeposit> {
-        if bx.ergo_tree == *DEPOSIT_CONTRACT {
-            let valid_vault_token = if let Ok(Some(r4)) = bx.get_register(NonMandatoryRegisterId::R4.into()) {
-                if let Literal::Coll(CollKind::NativeColl(NativeColl::CollByte(bytes))) = r4.v {
-                    let bytes_u8: Vec<u8> = bytes.into_iter().map(|b| b as u8).collect();
-                    let t = TokenId::from(Digest32::try_from(bytes_u8).unwrap());
-                    t == self.vault_utxo_token_id
-                } else {
-                    false
-                }
-            } else {
-                false
-            };
-
-            if valid_vault_token {
-                if let Ok(Some(r5)) = bx.get_register(NonMandatoryRegisterId::R5.into()) {
-                    if let Ok(prove_dlog) = ProveDlog::try_from(r5.v) {
-                        let address = Address::P2Pk(prove_dlog);
-                        let tokens = bx.tokens.clone().map(|toks| toks.to_vec()).unwrap_or_default();
-                        let cell = ErgoInboundCell(
-                            ErgoCell {
-                                ergs: bx.value,
-                                address,
-                                tokens,
-                            },
-                            bx.box_id(),
-                        );
-                        return Some(UnprocessedDeposit(AsBox(bx.clone(), cell)));
-                    }
-                }
-            }
-        }
-        None
-    }
 }
 
 pub fn verify_vault_contract_ergoscript_with_sigma_rust(
@@ -813,34 +1387,11 @@ pub fn verify_vault_contract_ergoscript_with_sigma_rust(
     let serialized_aggregate_commitment =
         Constant::from(EcPoint::from(ProjectivePoint::from(aggregate_commitment)));
 
-    let s_biguint = scalar_to_biguint(aggregate_response);
-    let biguint_bytes = s_biguint.to_bytes_be();
-    if biguint_bytes.len() < 32 {
-        println!("# bytes: {}", biguint_bytes.len());
-    }
-    let split = biguint_bytes.len() - 16;
-    let upper = BigUint::from_bytes_be(&biguint_bytes[..split]);
-    let upper_256 = BigInt256::try_from(upper).unwrap();
-    assert_eq!(upper_256.sign(), Sign::Plus);
-    let lower = BigUint::from_bytes_be(&biguint_bytes[split..]);
-    let lower_256 = BigInt256::try_from(lower).unwrap();
-    assert_eq!(lower_256.sign(), Sign::Plus);
-
-    let mut aggregate_response_bytes = upper_256.to_signed_bytes_be();
-    // VERY IMPORTANT: Need this variable because we could add an extra byte to the encoding
-    // for signed-representation.
-    let first_len = aggregate_response_bytes.len() as i32;
-    aggregate_response_bytes.extend(lower_256.to_signed_bytes_be());
-
     let change_for_miner = BoxValue::try_from(max_miner_fee).unwrap();
 
-    let md = blake2b256_hash(&resulting_digest);
+    let md = report_digest(&resulting_digest);
     let exclusion_set_data = serialize_exclusion_set(exclusion_set, md.as_ref());
-    let aggregate_response: Constant = (
-        Constant::from(aggregate_response_bytes),
-        Constant::from(first_len),
-    )
-        .into();
+    let aggregate_response = aggregate_response_constant(aggregate_response);
     let threshold = ((committee_size as usize) * threshold.num / threshold.denom) as i32;
     let proof = Constant::from(proof);
     let avl_const = Constant::from(starting_avl_tree);
@@ -930,6 +1481,34 @@ pub fn verify_vault_contract_ergoscript_with_sigma_rust(
     res.unwrap()
 }
 
+/// Aggregate response doesn't fit into a signed 256bit integer of ErgoScript, so it's passed
+/// to the contract split into two signed integers along with the length of the first one.
+pub fn aggregate_response_constant(aggregate_response: Scalar) -> Constant {
+    let s_biguint = scalar_to_biguint(aggregate_response);
+    let biguint_bytes = s_biguint.to_bytes_be();
+    if biguint_bytes.len() < 32 {
+        println!("# bytes: {}", biguint_bytes.len());
+    }
+    let split = biguint_bytes.len() - 16;
+    let upper = BigUint::from_bytes_be(&biguint_bytes[..split]);
+    let upper_256 = BigInt256::try_from(upper).unwrap();
+    assert_eq!(upper_256.sign(), Sign::Plus);
+    let lower = BigUint::from_bytes_be(&biguint_bytes[split..]);
+    let lower_256 = BigInt256::try_from(lower).unwrap();
+    assert_eq!(lower_256.sign(), Sign::Plus);
+
+    let mut aggregate_response_bytes = upper_256.to_signed_bytes_be();
+    // VERY IMPORTANT: Need this variable because we could add an extra byte to the encoding
+    // for signed-representation.
+    let first_len = aggregate_response_bytes.len() as i32;
+    aggregate_response_bytes.extend(lower_256.to_signed_bytes_be());
+    (
+        Constant::from(aggregate_response_bytes),
+        Constant::from(first_len),
+    )
+        .into()
+}
+
 pub enum VaultTx {
     Withdrawals {
         terminal_cells: Vec<(ErgoTermCell, ErgoBox)>,
@@ -938,4 +1517,24 @@ pub enum VaultTx {
     Deposits {
         deposits: Vec<(ErgoInboundCell, BoxId)>,
     },
+
+    /// One of the TXs handing the vault over to the incoming committee.
+    CommitteeRotation {
+        completes_handover: bool,
+    },
+}
+
+/// Run the call of the node API, recording its latency and outcome.
+async fn observe_node_call<T, Err, F>(
+    node_health: &mut NodeHealthMonitor,
+    endpoint: &'static str,
+    call: F,
+) -> Result<T, Err>
+where
+    F: Future<Output = Result<T, Err>>,
+{
+    let started_at = Instant::now();
+    let res = call.await;
+    node_health.record_call(endpoint, started_at.elapsed(), res.is_ok());
+    res
 }
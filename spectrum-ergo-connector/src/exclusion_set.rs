@@ -0,0 +1,216 @@
+//! Layout of the exclusion set of an aggregate signature within a vault TX.
+//!
+//! With large committees the exclusion set outgrows what a single box can carry. Elements are
+//! encoded one at a time and packed into chunks of bounded size, which are then passed either as
+//! context extension vars of the vault input or in registers of data-input boxes, whichever
+//! keeps the TX within size limits.
+
+use std::collections::HashMap;
+
+use ergo_lib::ergotree_ir::chain::ergo_box::{NonMandatoryRegisterId, NonMandatoryRegisters};
+use ergo_lib::ergotree_ir::mir::constant::{Constant, Literal};
+use ergo_lib::ergotree_ir::mir::value::CollKind;
+use ergo_lib::ergotree_ir::serialization::SigmaSerializable;
+use ergo_lib::ergotree_ir::types::stype::SType;
+
+use crate::script::schnorr_signature_verification_ergoscript_type;
+
+/// Max size of a serialized box.
+pub const MAX_BOX_SIZE: usize = 4096;
+/// Max size of a TX accepted to the mempool by default.
+pub const MAX_TX_SIZE: usize = 98304;
+/// Max size of a single chunk, leaves room for the rest of a data-input box carrying it.
+pub const MAX_CHUNK_SIZE: usize = MAX_BOX_SIZE - 512;
+/// Context extension var holding the first (or the only) chunk.
+pub const EXCLUSION_SET_VAR: u8 = 0;
+/// Context extension vars holding subsequent chunks, `10, 11, ...`.
+pub const EXCLUSION_SET_CHUNK_VARS_START: u8 = 10;
+
+#[derive(Debug, Clone)]
+pub enum ExclusionSetLayout {
+    /// The whole set in var [`EXCLUSION_SET_VAR`].
+    Single(Constant),
+    /// The first chunk in var [`EXCLUSION_SET_VAR`], the rest in vars starting from
+    /// [`EXCLUSION_SET_CHUNK_VARS_START`].
+    ContextExtension(Vec<Constant>),
+    /// One chunk in R4 of every data-input box. Boxes must be created ahead of the vault TX.
+    DataInputs(Vec<NonMandatoryRegisters>),
+}
+
+impl ExclusionSetLayout {
+    /// Context extension vars to set on the vault input.
+    pub fn context_extension_vars(&self) -> Vec<(u8, Constant)> {
+        match self {
+            ExclusionSetLayout::Single(chunk) => vec![(EXCLUSION_SET_VAR, chunk.clone())],
+            ExclusionSetLayout::ContextExtension(chunks) => chunks
+                .iter()
+                .enumerate()
+                .map(|(i, chunk)| {
+                    let var = if i == 0 {
+                        EXCLUSION_SET_VAR
+                    } else {
+                        EXCLUSION_SET_CHUNK_VARS_START + (i - 1) as u8
+                    };
+                    (var, chunk.clone())
+                })
+                .collect(),
+            ExclusionSetLayout::DataInputs(_) => vec![],
+        }
+    }
+}
+
+/// Packs encoded elements of the exclusion set into chunks as they arrive, see
+/// [`crate::script::exclusion_set_elements`].
+pub struct ExclusionSetBuilder {
+    max_chunk_size: usize,
+    elem_tpe: SType,
+    chunks: Vec<Constant>,
+    items: Vec<Literal>,
+    chunk_size: usize,
+    total_size: usize,
+}
+
+impl ExclusionSetBuilder {
+    pub fn new() -> Self {
+        Self::with_max_chunk_size(MAX_CHUNK_SIZE)
+    }
+
+    pub fn with_max_chunk_size(max_chunk_size: usize) -> Self {
+        Self {
+            max_chunk_size,
+            elem_tpe: schnorr_signature_verification_ergoscript_type(),
+            chunks: vec![],
+            items: vec![],
+            chunk_size: 0,
+            total_size: 0,
+        }
+    }
+
+    pub fn push(&mut self, elem: Constant) {
+        let elem_size = elem.sigma_serialize_bytes().map(|bs| bs.len()).unwrap_or(0);
+        if !self.items.is_empty() && self.chunk_size + elem_size > self.max_chunk_size {
+            self.seal_chunk();
+        }
+        self.items.push(elem.v);
+        self.chunk_size += elem_size;
+        self.total_size += elem_size;
+    }
+
+    /// Estimated size of the whole set once serialized.
+    pub fn serialized_size(&self) -> usize {
+        self.total_size
+    }
+
+    /// Pick the layout given the estimated size of the rest of the vault TX.
+    pub fn finish(mut self, tx_size_without_exclusion_set: usize) -> ExclusionSetLayout {
+        if !self.items.is_empty() || self.chunks.is_empty() {
+            self.seal_chunk();
+        }
+        let max_num_chunks = (u8::MAX - EXCLUSION_SET_CHUNK_VARS_START) as usize + 1;
+        if self.chunks.len() == 1 {
+            ExclusionSetLayout::Single(self.chunks.remove(0))
+        } else if tx_size_without_exclusion_set + self.total_size <= MAX_TX_SIZE
+            && self.chunks.len() <= max_num_chunks
+        {
+            ExclusionSetLayout::ContextExtension(self.chunks)
+        } else {
+            ExclusionSetLayout::DataInputs(
+                self.chunks
+                    .into_iter()
+                    .map(|chunk| {
+                        let mut registers = HashMap::new();
+                        registers.insert(NonMandatoryRegisterId::R4, chunk);
+                        NonMandatoryRegisters::new(registers).unwrap()
+                    })
+                    .collect(),
+            )
+        }
+    }
+
+    fn seal_chunk(&mut self) {
+        let items = std::mem::take(&mut self.items);
+        self.chunks.push(Constant {
+            tpe: SType::SColl(Box::new(self.elem_tpe.clone())),
+            v: Literal::Coll(CollKind::WrappedColl {
+                elem_tpe: self.elem_tpe.clone(),
+                items,
+            }),
+        });
+        self.chunk_size = 0;
+    }
+}
+
+impl Default for ExclusionSetBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ergo_lib::ergotree_ir::mir::constant::{Constant, Literal};
+    use ergo_lib::ergotree_ir::mir::value::CollKind;
+    use spectrum_crypto::digest::Blake2bDigest256;
+    use spectrum_sigma::crypto::{exclusion_proof, schnorr_commitment_pair};
+    use spectrum_sigma::{Commitment, Signature};
+
+    use crate::exclusion_set::{ExclusionSetBuilder, ExclusionSetLayout, MAX_TX_SIZE};
+    use crate::script::{exclusion_set_elements, serialize_exclusion_set};
+
+    fn exclusion_set(n: usize, md: Blake2bDigest256) -> Vec<(usize, Option<(Commitment, Signature)>)> {
+        (0..n)
+            .map(|ix| {
+                let (commitment_sk, commitment) = schnorr_commitment_pair();
+                (ix, Some((commitment, exclusion_proof(commitment_sk, md))))
+            })
+            .collect()
+    }
+
+    fn build(exclusion_set: Vec<(usize, Option<(Commitment, Signature)>)>, md: &[u8]) -> ExclusionSetBuilder {
+        let mut builder = ExclusionSetBuilder::new();
+        for elem in exclusion_set_elements(exclusion_set, md) {
+            builder.push(elem);
+        }
+        builder
+    }
+
+    fn num_items(chunk: &Constant) -> usize {
+        match &chunk.v {
+            Literal::Coll(CollKind::WrappedColl { items, .. }) => items.len(),
+            _ => panic!("Chunk must be a collection"),
+        }
+    }
+
+    #[test]
+    fn small_set_passed_as_single_constant() {
+        let md = Blake2bDigest256::random();
+        let set = exclusion_set(3, md);
+        let layout = build(set.clone(), md.as_ref()).finish(1024);
+        assert!(matches!(
+            layout,
+            ExclusionSetLayout::Single(chunk) if chunk == serialize_exclusion_set(set, md.as_ref())
+        ));
+    }
+
+    #[test]
+    fn large_set_split_into_chunks() {
+        let md = Blake2bDigest256::random();
+        let set = exclusion_set(60, md);
+        let builder = build(set.clone(), md.as_ref());
+        let layout = builder.finish(1024);
+        let ExclusionSetLayout::ContextExtension(chunks) = &layout else {
+            panic!("Expected chunks in context extension");
+        };
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks.iter().map(num_items).sum::<usize>(), 60);
+        let vars: Vec<_> = layout
+            .context_extension_vars()
+            .into_iter()
+            .map(|(var, _)| var)
+            .collect();
+        assert_eq!(vars[..2], [0, 10]);
+
+        let layout = build(set, md.as_ref()).finish(MAX_TX_SIZE);
+        assert!(matches!(layout, ExclusionSetLayout::DataInputs(boxes) if boxes.len() == chunks.len()));
+    }
+}
@@ -1,11 +1,19 @@
 use ergo_lib::{chain::transaction::TxId, ergotree_ir::chain::ergo_box::BoxId};
 use serde::{Deserialize, Serialize};
+use spectrum_ledger::denomination::Denomination;
 
+pub mod accounting;
 pub mod committee;
 pub mod deposit;
 pub mod ergo_connector;
+pub mod exclusion_set;
+pub mod migration;
+pub mod reconciliation;
 pub mod rocksdb;
+pub mod rotation;
 pub mod script;
+pub mod test_vectors;
+pub mod timelock;
 pub mod tx_event;
 pub mod tx_in_progress;
 pub mod vault_utxo;
@@ -17,3 +25,8 @@ pub struct AncillaryVaultInfo {
     pub box_id: BoxId,
     pub height: u32,
 }
+
+/// Denomination of the native coin of Ergo (1 ERG = 10^9 nanoERG).
+pub fn erg_denomination() -> Denomination {
+    Denomination::new("ERG", 9).unwrap()
+}
@@ -18,13 +18,19 @@ use ergo_lib::{
 };
 use futures::StreamExt;
 use isahc::{config::Configurable, HttpClient};
-use log::info;
+use log::{error, info, warn};
 use rocksdb::{vault_boxes::VaultUtxoRepoRocksDB, withdrawals::WithdrawalRepoRocksDB};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_with::serde_as;
 use spectrum_chain_connector::{
-    ChainTxEvent, ConnectorMsgOut, ConnectorRequest, ConnectorResponse, DataBridge, DataBridgeComponents,
-    TxEvent,
+    bridge::BridgeEvent,
+    health::{NodeHealthConfig, NodeHealthMonitor},
+    ipc::{
+        decode_request, IpcHandshake, IpcRequest, IpcResponse, RequestError,
+        SEQUENCED_RESPONSES_IPC_PROTOCOL_VERSION,
+    },
+    AcknowledgementThreshold, ChainTxEvent, ConnectorMsgOut, ConnectorRequest, ConnectorResponse,
+    ConnectorStatus, DataBridge, DataBridgeComponents, VaultMigrationStatus,
 };
 use spectrum_deploy_lm_pool::Explorer;
 use spectrum_ergo_connector::AncillaryVaultInfo;
@@ -34,19 +40,27 @@ use tokio_stream::wrappers::ReceiverStream;
 use tokio_unix_ipc::{symmetric_channel, Bootstrapper};
 
 use crate::{
+    migration::MigrationOperators,
     rocksdb::{
-        deposit::DepositRepoRocksDB, ergo_tx_event_history::ErgoTxEventHistoryRocksDB,
-        tx_retry_scheduler::TxRetrySchedulerRocksDB, vault_boxes::ErgoNotarizationBounds,
+        deposit::DepositRepoRocksDB,
+        ergo_tx_event_history::ErgoTxEventHistoryRocksDB,
+        outbox::{Outbox, OutboxRetention, OutboxRocksDB, ReplayUnavailable},
+        tx_retry_scheduler::TxRetrySchedulerRocksDB,
+        vault_boxes::ErgoNotarizationBounds,
     },
     script::ExtraErgoData,
 };
 
+mod accounting;
 mod committee;
 mod data_bridge;
 mod deposit;
 mod ergo_connector;
+mod migration;
 mod rocksdb;
+mod rotation;
 mod script;
+mod timelock;
 mod tx_event;
 mod tx_in_progress;
 mod vault_utxo;
@@ -57,6 +71,10 @@ async fn main() {
     let raw_config = std::fs::read_to_string(args.config_path).expect("Cannot load configuration file");
     let config_proto: AppConfigProto = serde_yaml::from_str(&raw_config).expect("Invalid configuration file");
     let config = AppConfig::from(config_proto);
+    config
+        .migration_operators
+        .validate()
+        .expect("Invalid configuration file");
 
     if let Some(log4rs_path) = args.log4rs_path {
         log4rs::init_file(log4rs_path, Default::default()).unwrap();
@@ -75,9 +93,10 @@ async fn main() {
 
     let ergo_bridge = ErgoDataBridge::new(ergo_bridge_config);
     let DataBridgeComponents {
-        receiver: data_bridge_receiver,
+        receiver: mut data_bridge_receiver,
         start_signal,
     } = ergo_bridge.get_components();
+    let data_bridge_resync = data_bridge_receiver.resync_handle();
 
     let client = HttpClient::builder()
         .timeout(std::time::Duration::from_secs(
@@ -104,6 +123,7 @@ async fn main() {
     let deposit_repo = DepositRepoRocksDB::new(&config.deposits_store_db_path);
 
     let unix_socket_path = config.unix_socket_path.clone();
+    let outbox = OutboxRocksDB::new(&config.outbox_db_path, config.outbox_retention);
 
     let (connector_response_tx, connector_response_rx) = tokio::sync::mpsc::channel::<
         ConnectorResponse<ExtraErgoData, ErgoNotarizationBounds, BoxId, AncillaryVaultInfo>,
@@ -117,6 +137,7 @@ async fn main() {
         connector_response_rx,
         request_to_connector_tx,
         unix_socket_path,
+        outbox,
     ));
 
     let mut ergo_connector = ErgoConnector::new(
@@ -133,19 +154,33 @@ async fn main() {
             &config.tx_retry_db_path,
             config.tx_retry_config.retry_delay_duration.num_seconds(),
             config.tx_retry_config.max_retries,
+            config.tx_retry_config.max_pending_txs,
         )
         .await,
+        config.migration_operators,
+        config.handover_grace_period,
+        config.acknowledgement_threshold,
+        NodeHealthMonitor::new(config.node_health),
+        config.deposit_confirmation_depth,
     )
     .unwrap();
 
     enum StreamValueFrom {
-        Chain(TxEvent<(Transaction, u32)>),
+        Chain(BridgeEvent<(Transaction, u32)>),
         Driver(Option<ConnectorRequest<ExtraErgoData, BoxId>>),
         ResubmitTx,
+        ProbeNode,
+        ReconcileVault,
     }
 
     type CombinedStream = std::pin::Pin<Box<dyn futures::stream::Stream<Item = StreamValueFrom> + Send>>;
 
+    let chain_stream = stream! {
+        while let Some(event) = data_bridge_receiver.recv().await {
+            yield event;
+        }
+    };
+
     // Convert the tokio_unix_ipc Receiver into a stream.
     let consensus_driver_stream = stream! {
         loop {
@@ -161,12 +196,30 @@ async fn main() {
         }
     };
 
+    let probe_interval = std::time::Duration::from_secs(config.node_health.probe_interval_secs);
+    let probe_node_stream = stream! {
+        loop {
+            tokio::time::sleep(probe_interval).await;
+            yield ();
+        }
+    };
+
+    let reconciliation_interval = std::time::Duration::from_secs(config.reconciliation_interval_secs);
+    let reconcile_vault_stream = stream! {
+        loop {
+            tokio::time::sleep(reconciliation_interval).await;
+            yield ();
+        }
+    };
+
     let streams: Vec<CombinedStream> = vec![
-        ReceiverStream::new(data_bridge_receiver)
-            .map(StreamValueFrom::Chain)
-            .boxed(),
+        chain_stream.map(StreamValueFrom::Chain).boxed(),
         consensus_driver_stream.map(StreamValueFrom::Driver).boxed(),
         resubmit_tx_stream.map(|_| StreamValueFrom::ResubmitTx).boxed(),
+        probe_node_stream.map(|_| StreamValueFrom::ProbeNode).boxed(),
+        reconcile_vault_stream
+            .map(|_| StreamValueFrom::ReconcileVault)
+            .boxed(),
     ];
     let mut combined_stream = futures::stream::select_all(streams);
 
@@ -174,8 +227,36 @@ async fn main() {
 
     while let Some(m) = combined_stream.next().await {
         match m {
-            StreamValueFrom::Chain(tx_event) => {
-                ergo_connector.handle(tx_event).await;
+            StreamValueFrom::Chain(BridgeEvent::Tx { event, .. }) => {
+                ergo_connector.handle(event).await;
+                let stale_exports = ergo_connector.take_stale_exports();
+                if !stale_exports.is_empty() {
+                    let current_height = node.get_height().await;
+                    let status = ergo_connector.get_connector_status(current_height).await;
+                    let messages = stale_exports
+                        .into_iter()
+                        .map(ConnectorMsgOut::RenotarizationRequired)
+                        .collect();
+                    connector_response_tx
+                        .send(ConnectorResponse { status, messages })
+                        .await
+                        .unwrap();
+                }
+            }
+            StreamValueFrom::Chain(BridgeEvent::GapDetected { resume_from_seq, gap }) => {
+                warn!(target: "vault", "Gap in the chain event stream: {:?}, resyncing", gap);
+                data_bridge_resync.resync(resume_from_seq).await;
+            }
+            StreamValueFrom::Chain(BridgeEvent::ResyncFailed {
+                requested_seq,
+                oldest_seq,
+            }) => {
+                error!(
+                    target: "vault",
+                    "Cannot resync chain event stream from {}, oldest available event is {}",
+                    requested_seq, oldest_seq
+                );
+                panic!("Chain event stream is irrecoverably broken");
             }
             StreamValueFrom::Driver(msg_in) => {
                 if let Some(request) = msg_in {
@@ -192,7 +273,11 @@ async fn main() {
 
                             let status = ergo_connector.get_connector_status(current_height).await;
 
-                            let messages = vec![];
+                            let messages = ergo_connector
+                                .take_stale_exports()
+                                .into_iter()
+                                .map(ConnectorMsgOut::RenotarizationRequired)
+                                .collect();
                             connector_response_tx
                                 .send(ConnectorResponse { status, messages })
                                 .await
@@ -293,7 +378,62 @@ async fn main() {
                             unreachable!("");
                         }
 
-                        ConnectorRequest::RotateCommittee => todo!(),
+                        ConnectorRequest::RotateCommittee(handover) => {
+                            let messages = match ergo_connector.rotate_committee(*handover, &node).await {
+                                Ok(_) => vec![],
+                                Err(e) => {
+                                    vec![ConnectorMsgOut::CommitteeRotationRejected(format!("{:?}", e))]
+                                }
+                            };
+                            let current_height = node.get_height().await;
+                            let status = ergo_connector.get_connector_status(current_height).await;
+                            info!(target: "vault", "respond to RotateCommittee. status: {:?}, messages: {:?}", status, messages);
+                            connector_response_tx
+                                .send(ConnectorResponse { status, messages })
+                                .await
+                                .unwrap();
+                        }
+
+                        ConnectorRequest::ProposeVaultMigration(migration) => {
+                            let migration_status = ergo_connector
+                                .propose_vault_migration(*migration)
+                                .await
+                                .unwrap_or_else(|e| VaultMigrationStatus::Rejected(format!("{:?}", e)));
+                            let current_height = node.get_height().await;
+                            let status = ergo_connector.get_connector_status(current_height).await;
+                            let messages = vec![ConnectorMsgOut::VaultMigration(migration_status)];
+                            info!(target: "vault", "respond to ProposeVaultMigration. status: {:?}, messages: {:?}", status, messages);
+                            connector_response_tx
+                                .send(ConnectorResponse { status, messages })
+                                .await
+                                .unwrap();
+                        }
+
+                        ConnectorRequest::QueryAccounting(query) => {
+                            let report = ergo_connector.query_accounting(query).await;
+                            let current_height = node.get_height().await;
+                            let status = ergo_connector.get_connector_status(current_height).await;
+                            let messages = vec![ConnectorMsgOut::AccountingReport(report)];
+                            connector_response_tx
+                                .send(ConnectorResponse { status, messages })
+                                .await
+                                .unwrap();
+                        }
+
+                        ConnectorRequest::ApproveVaultMigration(approval) => {
+                            let migration_status = ergo_connector
+                                .approve_vault_migration(approval, &node)
+                                .await
+                                .unwrap_or_else(|e| VaultMigrationStatus::Rejected(format!("{:?}", e)));
+                            let current_height = node.get_height().await;
+                            let status = ergo_connector.get_connector_status(current_height).await;
+                            let messages = vec![ConnectorMsgOut::VaultMigration(migration_status)];
+                            info!(target: "vault", "respond to ApproveVaultMigration. status: {:?}, messages: {:?}", status, messages);
+                            connector_response_tx
+                                .send(ConnectorResponse { status, messages })
+                                .await
+                                .unwrap();
+                        }
                     }
                 }
             }
@@ -301,19 +441,49 @@ async fn main() {
             StreamValueFrom::ResubmitTx => {
                 ergo_connector.handle_tx_resubmission(&node).await;
             }
+
+            StreamValueFrom::ProbeNode => {
+                if let Some(alert) = ergo_connector.probe_node(&node).await {
+                    let current_height = node.get_height().await;
+                    let status = ergo_connector.get_connector_status(current_height).await;
+                    let messages = vec![ConnectorMsgOut::NodeHealthAlert(alert)];
+                    connector_response_tx
+                        .send(ConnectorResponse { status, messages })
+                        .await
+                        .unwrap();
+                }
+            }
+
+            StreamValueFrom::ReconcileVault => {
+                let current_height = node.get_height().await;
+                let status = ergo_connector.get_connector_status(current_height).await;
+                // The vault UTxO set is only comparable with the history once we're caught up with the chain.
+                if matches!(status, ConnectorStatus::Synced { .. }) {
+                    if let Some(discrepancy) = ergo_connector.reconcile_vault().await {
+                        error!(target: "vault", "Vault balance doesn't reconcile: {:?}", discrepancy);
+                        let messages = vec![ConnectorMsgOut::VaultBalanceMismatch(discrepancy)];
+                        connector_response_tx
+                            .send(ConnectorResponse { status, messages })
+                            .await
+                            .unwrap();
+                    }
+                }
+            }
         }
     }
 }
 
-async fn manage_unix_socket_communications_task<S, T, U, V>(
+async fn manage_unix_socket_communications_task<S, T, U, V, O>(
     connector_response_rx: tokio::sync::mpsc::Receiver<ConnectorResponse<S, T, U, V>>,
     request_to_connector_tx: tokio::sync::mpsc::Sender<ConnectorRequest<S, U>>,
     unix_socket_path: String,
+    mut outbox: O,
 ) where
-    S: std::fmt::Debug + Send + Serialize + DeserializeOwned + 'static,
-    T: Send + Serialize + DeserializeOwned + 'static,
-    U: std::fmt::Debug + Send + Serialize + DeserializeOwned + 'static,
-    V: Send + Serialize + DeserializeOwned + 'static,
+    S: std::fmt::Debug + Send + Sync + Serialize + DeserializeOwned + 'static,
+    T: Send + Sync + Serialize + DeserializeOwned + 'static,
+    U: std::fmt::Debug + Send + Sync + Serialize + DeserializeOwned + 'static,
+    V: Send + Sync + Serialize + DeserializeOwned + 'static,
+    O: Outbox<ConnectorResponse<S, T, U, V>> + Send + 'static,
 {
     let (driver_req_tx, mut driver_req_rx) = tokio::sync::mpsc::channel::<ConnectorRequest<S, U>>(10);
 
@@ -326,12 +496,21 @@ async fn manage_unix_socket_communications_task<S, T, U, V>(
     });
 
     let (response_sender_tx, response_sender_rx) =
-        tokio::sync::mpsc::channel::<tokio_unix_ipc::Sender<ConnectorResponse<S, T, U, V>>>(10);
+        tokio::sync::mpsc::channel::<tokio_unix_ipc::Sender<IpcResponse<S, T, U, V>>>(10);
+    let (ipc_reply_tx, ipc_reply_rx) = tokio::sync::mpsc::channel::<IpcResponse<S, T, U, V>>(10);
+    let (outbox_cmd_tx, outbox_cmd_rx) = tokio::sync::mpsc::channel::<OutboxCommand>(10);
+
+    enum OutboxCommand {
+        Resume(Option<u64>),
+        Ack(u64),
+    }
 
     // Merged stream
     enum MergedStream<S, T, U, V> {
-        NewUnixSender(tokio_unix_ipc::Sender<ConnectorResponse<S, T, U, V>>),
+        NewUnixSender(tokio_unix_ipc::Sender<IpcResponse<S, T, U, V>>),
         VaultManagerResponse(Box<ConnectorResponse<S, T, U, V>>),
+        IpcReply(Box<IpcResponse<S, T, U, V>>),
+        Outbox(OutboxCommand),
     }
 
     type CombinedStream<S, T, U, V> =
@@ -344,18 +523,82 @@ async fn manage_unix_socket_communications_task<S, T, U, V>(
         ReceiverStream::new(connector_response_rx)
             .map(|r| MergedStream::VaultManagerResponse(Box::new(r)))
             .boxed(),
+        ReceiverStream::new(ipc_reply_rx)
+            .map(|r| MergedStream::IpcReply(Box::new(r)))
+            .boxed(),
+        ReceiverStream::new(outbox_cmd_rx)
+            .map(MergedStream::Outbox)
+            .boxed(),
     ];
     let mut combined_stream = futures::stream::select_all(streams);
 
     // The response-forwarder task. It forwards responses from the Connector to a connected driver.
+    // Every response is kept in the outbox until the driver acknowledges it, so that responses
+    // produced while the driver is offline are replayed once it reconnects.
     tokio::spawn(async move {
         let mut current_tx = None;
+        let mut sequenced = false;
         while let Some(m) = combined_stream.next().await {
-            match m {
-                MergedStream::NewUnixSender(new_tx) => current_tx = Some(new_tx),
+            let replies = match m {
+                MergedStream::NewUnixSender(new_tx) => {
+                    current_tx = Some(new_tx);
+                    sequenced = false;
+                    continue;
+                }
                 MergedStream::VaultManagerResponse(response) => {
-                    if let Some(ref tx) = current_tx {
-                        tx.send(*response).await.unwrap();
+                    let seq = outbox.push(&response).await;
+                    if sequenced {
+                        vec![IpcResponse::Sequenced {
+                            seq,
+                            response: *response,
+                        }]
+                    } else {
+                        vec![IpcResponse::Response(*response)]
+                    }
+                }
+                MergedStream::IpcReply(reply) => {
+                    if let IpcResponse::Welcome(version) = *reply {
+                        sequenced = version >= SEQUENCED_RESPONSES_IPC_PROTOCOL_VERSION;
+                    }
+                    vec![*reply]
+                }
+                MergedStream::Outbox(OutboxCommand::Ack(seq)) => {
+                    outbox.acknowledge(seq).await;
+                    continue;
+                }
+                MergedStream::Outbox(OutboxCommand::Resume(last_acked_seq)) => {
+                    if let Some(seq) = last_acked_seq {
+                        outbox.acknowledge(seq).await;
+                    }
+                    match outbox.replay(last_acked_seq).await {
+                        Ok(responses) => {
+                            info!("Replaying {} responses to consensus-driver", responses.len());
+                            responses
+                                .into_iter()
+                                .map(|(seq, response)| IpcResponse::Sequenced { seq, response })
+                                .collect()
+                        }
+                        Err(ReplayUnavailable {
+                            requested_seq,
+                            oldest_seq,
+                        }) => {
+                            warn!(
+                                "Cannot replay responses after #{}, the oldest retained one is #{}",
+                                requested_seq, oldest_seq
+                            );
+                            vec![IpcResponse::Rejected(RequestError::ReplayUnavailable {
+                                requested_seq,
+                                oldest_seq,
+                            })]
+                        }
+                    }
+                }
+            };
+            if let Some(ref tx) = current_tx {
+                for reply in replies {
+                    if let Err(e) = tx.send(reply).await {
+                        warn!("Failed to send response to consensus-driver: {:?}", e);
+                        break;
                     }
                 }
             }
@@ -363,24 +606,61 @@ async fn manage_unix_socket_communications_task<S, T, U, V>(
     });
 
     loop {
-        let (req_tx, req_rx) = symmetric_channel::<ConnectorRequest<S, U>>().unwrap();
-        let (resp_tx, resp_rx) = symmetric_channel::<ConnectorResponse<S, T, U, V>>().unwrap();
+        let (req_tx, req_rx) = symmetric_channel::<IpcRequest>().unwrap();
+        let (resp_tx, resp_rx) = symmetric_channel::<IpcResponse<S, T, U, V>>().unwrap();
         let bootstrapper = Bootstrapper::bind(unix_socket_path.clone()).unwrap();
         bootstrapper.send((req_tx, resp_rx)).await.unwrap();
 
         response_sender_tx.send(resp_tx).await.unwrap();
         let driver_req_tx = driver_req_tx.clone();
+        let mut negotiated_version = None;
 
-        while let Ok(req) = req_rx.recv().await {
-            match req {
-                ConnectorRequest::Disconnect => {
+        loop {
+            let req = match req_rx.recv().await {
+                Ok(req) => req,
+                Err(e) => {
+                    warn!("Failed to receive message from consensus-driver: {:?}", e);
                     break;
                 }
-                e => {
-                    // Pass off to task above, which forwards to the Connector
-                    let _ = driver_req_tx.send(e).await;
+            };
+            let reply = match (req, negotiated_version) {
+                (IpcRequest::Hello(remote), _) => match IpcHandshake::local().negotiate(&remote) {
+                    Ok(version) => {
+                        info!("Negotiated IPC protocol version {}", version);
+                        negotiated_version = Some(version);
+                        IpcResponse::Welcome(version)
+                    }
+                    Err(e) => {
+                        warn!("Incompatible consensus-driver: {}", e);
+                        negotiated_version = None;
+                        IpcResponse::Rejected(e)
+                    }
+                },
+                (IpcRequest::Request(_) | IpcRequest::Resume { .. } | IpcRequest::Ack(_), None) => {
+                    IpcResponse::Rejected(RequestError::HandshakeRequired)
                 }
-            }
+                (IpcRequest::Resume { last_acked_seq }, Some(_)) => {
+                    let _ = outbox_cmd_tx.send(OutboxCommand::Resume(last_acked_seq)).await;
+                    continue;
+                }
+                (IpcRequest::Ack(seq), Some(_)) => {
+                    let _ = outbox_cmd_tx.send(OutboxCommand::Ack(seq)).await;
+                    continue;
+                }
+                (IpcRequest::Request(bytes), Some(_)) => match decode_request::<S, U>(&bytes) {
+                    Ok(ConnectorRequest::Disconnect) => break,
+                    Ok(req) => {
+                        // Pass off to task above, which forwards to the Connector
+                        let _ = driver_req_tx.send(req).await;
+                        continue;
+                    }
+                    Err(e) => {
+                        warn!("Rejected request from consensus-driver: {}", e);
+                        IpcResponse::Rejected(e)
+                    }
+                },
+            };
+            let _ = ipc_reply_tx.send(reply).await;
         }
     }
 }
@@ -397,12 +677,20 @@ struct AppConfig {
     vault_boxes_store_db_path: String,
     moved_value_history_db_path: String,
     chain_cache_db_path: String,
+    outbox_db_path: String,
+    outbox_retention: OutboxRetention,
     unix_socket_path: String,
     committee_public_keys: Vec<EcPoint>,
     committee_box_ids: Vec<BoxId>,
     /// Base58 encoding of guarding script of committee boxes
     committee_guarding_script: ErgoTree,
     vault_utxo_token_id: TokenId,
+    migration_operators: MigrationOperators,
+    handover_grace_period: u32,
+    acknowledgement_threshold: AcknowledgementThreshold,
+    node_health: NodeHealthConfig,
+    deposit_confirmation_depth: u32,
+    reconciliation_interval_secs: u64,
 }
 
 #[derive(Deserialize)]
@@ -418,12 +706,38 @@ struct AppConfigProto {
     deposits_store_db_path: String,
     moved_value_history_db_path: String,
     chain_cache_db_path: String,
+    /// Responses not yet acknowledged by consensus-driver are kept here.
+    outbox_db_path: String,
+    #[serde(default)]
+    outbox_retention: OutboxRetention,
     unix_socket_path: String,
     committee_public_keys: Vec<String>,
     committee_box_ids: Vec<BoxId>,
     /// Base58 encoding of guarding script of committee boxes
     committee_guarding_script: String,
     vault_utxo_token_id: TokenId,
+    /// Base16 encoded (x-only) public keys of operators who approve vault migrations.
+    #[serde(default)]
+    migration_operators: Vec<String>,
+    /// Number of operators required to approve a vault migration.
+    #[serde(default)]
+    migration_approval_threshold: usize,
+    /// Number of blocks the vault stays guarded by the outgoing committee once boxes of the
+    /// incoming one are created, so that exports certified by it can still settle.
+    #[serde(default)]
+    handover_grace_period: u32,
+    /// Depth at which confirmed TXs are reported as settled to consensus-driver.
+    #[serde(default)]
+    acknowledgement_threshold: AcknowledgementThreshold,
+    /// Thresholds beyond which the node is reported as degraded.
+    #[serde(default)]
+    node_health: NodeHealthConfig,
+    /// Number of blocks a deposit must be buried under before it's imported into Spectrum.
+    #[serde(default)]
+    deposit_confirmation_depth: u32,
+    /// Interval between reconciliations of the vault UTxO set against the ledger.
+    #[serde(default = "default_reconciliation_interval_secs")]
+    reconciliation_interval_secs: u64,
 }
 
 impl From<AppConfigProto> for AppConfig {
@@ -443,6 +757,17 @@ impl From<AppConfigProto> for AppConfig {
                 EcPoint::from(pk.to_projective())
             })
             .collect();
+        let migration_operators = MigrationOperators {
+            keys: value
+                .migration_operators
+                .into_iter()
+                .map(|pk_str| {
+                    let bytes = base16::decode(&pk_str).unwrap();
+                    k256::schnorr::VerifyingKey::from_bytes(&bytes).unwrap()
+                })
+                .collect(),
+            threshold: value.migration_approval_threshold,
+        };
         Self {
             node_addr: value.node_addr,
             http_client_timeout_duration_secs: value.http_client_timeout_duration_secs,
@@ -455,11 +780,19 @@ impl From<AppConfigProto> for AppConfig {
             vault_boxes_store_db_path: value.vault_boxes_store_db_path,
             moved_value_history_db_path: value.moved_value_history_db_path,
             chain_cache_db_path: value.chain_cache_db_path,
+            outbox_db_path: value.outbox_db_path,
+            outbox_retention: value.outbox_retention,
             unix_socket_path: value.unix_socket_path,
             committee_public_keys,
             committee_box_ids: value.committee_box_ids,
             committee_guarding_script,
             vault_utxo_token_id: value.vault_utxo_token_id,
+            migration_operators,
+            handover_grace_period: value.handover_grace_period,
+            acknowledgement_threshold: value.acknowledgement_threshold,
+            node_health: value.node_health,
+            deposit_confirmation_depth: value.deposit_confirmation_depth,
+            reconciliation_interval_secs: value.reconciliation_interval_secs,
         }
     }
 }
@@ -470,6 +803,17 @@ pub struct TxRetryConfig {
     #[serde_as(as = "serde_with::DurationSeconds<i64>")]
     pub retry_delay_duration: Duration,
     pub max_retries: u32,
+    /// Max number of non-conflicting TXs that can be pending at the same time.
+    #[serde(default = "default_max_pending_txs")]
+    pub max_pending_txs: usize,
+}
+
+fn default_max_pending_txs() -> usize {
+    1
+}
+
+fn default_reconciliation_interval_secs() -> u64 {
+    600
 }
 
 #[derive(Parser)]
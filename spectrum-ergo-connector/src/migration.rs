@@ -0,0 +1,467 @@
+use std::collections::BTreeSet;
+
+use ergo_lib::{
+    chain::{
+        ergo_state_context::ErgoStateContext,
+        transaction::{unsigned::UnsignedTransaction, DataInput, Transaction, TxId, TxIoVec, UnsignedInput},
+    },
+    ergo_chain_types::EcPoint,
+    ergotree_interpreter::sigma_protocol::prover::ContextExtension,
+    ergotree_ir::{
+        chain::{
+            ergo_box::{box_value::BoxValue, BoxId, ErgoBox, ErgoBoxCandidate, NonMandatoryRegisters},
+            token::TokenId,
+        },
+        ergo_tree::ErgoTree,
+        mir::constant::Constant,
+        serialization::SigmaSerializable,
+    },
+    wallet::{miner_fee::MINERS_FEE_ADDRESS, tx_context::TransactionContext, Wallet},
+};
+use indexmap::IndexMap;
+use k256::schnorr::{signature::Verifier, Signature, VerifyingKey};
+use k256::ProjectivePoint;
+use spectrum_chain_connector::{OperatorApproval, VaultMigration, VaultMigrationStatus};
+use spectrum_crypto::digest::{blake2b256_hash, Blake2b256, Blake2bDigest256};
+use spectrum_crypto::pubkey::PublicKey;
+use spectrum_handel::Threshold;
+use spectrum_ledger::{cell::ProgressPoint, interop::Point, interop::ReportCertificate, ChainId};
+use spectrum_sigma::crypto::verify;
+use spectrum_sigma::sigma_aggregation::AggregateCertificate;
+
+use crate::ergo_connector::aggregate_response_constant;
+use crate::script::serialize_exclusion_set;
+
+/// Domain separation tag of the migration digest.
+const MIGRATION_TAG: &[u8] = b"spectrum/vault-migration";
+
+/// Share of the committee which has to certify a migration.
+/// Upgrade of the vault contract is more sensitive than a regular withdrawal,
+/// so we require a supermajority here regardless of the notarization threshold.
+pub const MIGRATION_THRESHOLD: Threshold = Threshold { num: 2, denom: 3 };
+
+/// Digest certified by the committee and signed by the operators to authorize the
+/// migration of funds from the vault box `vault_box_id` to a box guarded by `new_contract`.
+pub fn migration_digest(vault_box_id: BoxId, new_contract: &ErgoTree) -> Blake2bDigest256 {
+    let mut bytes = MIGRATION_TAG.to_vec();
+    bytes.extend(vault_box_id.sigma_serialize_bytes().unwrap());
+    bytes.extend(new_contract.sigma_serialize_bytes().unwrap());
+    blake2b256_hash(&bytes)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MigrationError {
+    /// There is no migration to approve.
+    NoPendingMigration,
+    /// Another migration is already in progress.
+    MigrationInProgress,
+    /// No operators are configured, so migration can never be approved.
+    NoOperators,
+    NoVaultUtxo,
+    MalformedContract,
+    /// Committee certificate doesn't certify the migration digest.
+    CertificateMismatch,
+    /// Aggregate signature of the committee doesn't verify.
+    InvalidCertificate,
+    /// Approval refers to a different migration.
+    DigestMismatch,
+    UnknownOperator(u16),
+    InvalidSignature(u16),
+    /// Vault contract rejected the migration TX.
+    TxRejected(String),
+}
+
+/// Operators of the connector who have to approve a migration before it is submitted.
+#[derive(Clone, Debug)]
+pub struct MigrationOperators {
+    pub keys: Vec<VerifyingKey>,
+    /// Number of distinct operators required to approve a migration.
+    pub threshold: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MigrationState {
+    AwaitingApproval { approved_by: BTreeSet<u16> },
+    Submitted { tx_id: TxId },
+    Settled { tx_id: TxId, height: u32 },
+}
+
+/// Migration of the vault to the new contract along with its progress.
+#[derive(Clone, Debug)]
+pub struct PendingMigration {
+    pub vault_utxo: ErgoBox,
+    pub new_contract: ErgoTree,
+    pub certificate: AggregateCertificate<Blake2b256>,
+    pub digest: Blake2bDigest256,
+    pub state: MigrationState,
+}
+
+impl MigrationOperators {
+    /// Check that a migration can ever be approved, and that a single operator can't approve it
+    /// on behalf of all of them.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.keys.is_empty() && !(1..=self.keys.len()).contains(&self.threshold) {
+            return Err(format!(
+                "migration_approval_threshold must be within [1, {}], got {}",
+                self.keys.len(),
+                self.threshold
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl PendingMigration {
+    /// The certificate is verified against the given committee.
+    pub fn new(
+        vault_utxo: ErgoBox,
+        migration: VaultMigration,
+        committee: &[EcPoint],
+    ) -> Result<Self, MigrationError> {
+        let new_contract = ErgoTree::sigma_parse_bytes(&migration.new_contract)
+            .map_err(|_| MigrationError::MalformedContract)?;
+        let digest = migration_digest(vault_utxo.box_id(), &new_contract);
+        let ReportCertificate::SchnorrK256(certificate) = migration.certificate;
+        if certificate.message_digest != digest {
+            return Err(MigrationError::CertificateMismatch);
+        }
+        let committee = committee
+            .iter()
+            .map(|pk| {
+                k256::PublicKey::from_affine(ProjectivePoint::from(pk.clone()).to_affine())
+                    .map(PublicKey::from)
+                    .map_err(|_| MigrationError::InvalidCertificate)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if !verify(
+            certificate.aggregate_commitment.clone(),
+            certificate.aggregate_response,
+            certificate.exclusion_set.clone(),
+            committee,
+            certificate.message_digest,
+            MIGRATION_THRESHOLD,
+        ) {
+            return Err(MigrationError::InvalidCertificate);
+        }
+        Ok(Self {
+            vault_utxo,
+            new_contract,
+            certificate,
+            digest,
+            state: MigrationState::AwaitingApproval {
+                approved_by: BTreeSet::new(),
+            },
+        })
+    }
+
+    /// Register approval of an operator. Returns `true` once the migration is approved by
+    /// enough operators.
+    pub fn approve(
+        &mut self,
+        operators: &MigrationOperators,
+        approval: &OperatorApproval,
+    ) -> Result<bool, MigrationError> {
+        let MigrationState::AwaitingApproval { approved_by } = &mut self.state else {
+            return Err(MigrationError::MigrationInProgress);
+        };
+        if approval.migration_digest != self.digest.as_ref() {
+            return Err(MigrationError::DigestMismatch);
+        }
+        let key = operators
+            .keys
+            .get(approval.operator_ix as usize)
+            .ok_or(MigrationError::UnknownOperator(approval.operator_ix))?;
+        let valid = Signature::try_from(&*approval.signature)
+            .map(|sig| key.verify(self.digest.as_ref(), &sig).is_ok())
+            .unwrap_or(false);
+        if !valid {
+            return Err(MigrationError::InvalidSignature(approval.operator_ix));
+        }
+        approved_by.insert(approval.operator_ix);
+        Ok(approved_by.len() >= operators.threshold)
+    }
+
+    pub fn is_submitted(&self) -> bool {
+        matches!(self.state, MigrationState::Submitted { .. })
+    }
+
+    pub fn spends_vault_utxo(&self, tx: &Transaction) -> bool {
+        tx.inputs.first().box_id == self.vault_utxo.box_id()
+    }
+
+    pub fn status(&self, operators: &MigrationOperators) -> VaultMigrationStatus {
+        let migration_digest = self.digest.as_ref().to_vec();
+        match &self.state {
+            MigrationState::AwaitingApproval { approved_by } => VaultMigrationStatus::AwaitingApproval {
+                migration_digest,
+                approvals: approved_by.len(),
+                required: operators.threshold,
+            },
+            MigrationState::Submitted { .. } => VaultMigrationStatus::Submitted { migration_digest },
+            MigrationState::Settled { height, .. } => VaultMigrationStatus::Settled {
+                migration_digest,
+                progress_point: ProgressPoint {
+                    chain_id: ChainId::from(0),
+                    point: Point::from(*height as u64),
+                },
+            },
+        }
+    }
+}
+
+/// Context extension passing the committee certificate to the vault contract, the same way as
+/// a notarized report is passed for withdrawals.
+pub fn certificate_context_extension(
+    certificate: &AggregateCertificate<Blake2b256>,
+    committee_size: u32,
+    threshold: Threshold,
+    vault_utxo_token_id: TokenId,
+    change_for_miner: BoxValue,
+) -> ContextExtension {
+    let AggregateCertificate {
+        message_digest,
+        aggregate_commitment,
+        aggregate_response,
+        exclusion_set,
+    } = certificate.clone();
+    let threshold = ((committee_size as usize) * threshold.num / threshold.denom) as i32;
+
+    let mut values = IndexMap::new();
+    values.insert(0, serialize_exclusion_set(exclusion_set, message_digest.as_ref()));
+    values.insert(
+        1,
+        Constant::from(EcPoint::from(ProjectivePoint::from(aggregate_commitment))),
+    );
+    values.insert(5, aggregate_response_constant(aggregate_response));
+    values.insert(6, Constant::from(message_digest.as_ref().to_vec()));
+    values.insert(9, threshold.into());
+    values.insert(8, change_for_miner.as_i64().into());
+    values.insert(4, vault_utxo_token_id.into());
+    ContextExtension { values }
+}
+
+/// Build the TX moving all funds of the vault box to a box guarded by the new contract.
+/// The committee certificate over the migration digest is passed to the old contract
+/// in the context extension, the same way as a notarized report is passed for withdrawals.
+pub fn build_migration_tx(
+    migration: &PendingMigration,
+    committee_size: u32,
+    vault_utxo_token_id: TokenId,
+    data_boxes: Vec<ErgoBox>,
+    ergo_state_context: &ErgoStateContext,
+    wallet: &Wallet,
+    max_miner_fee: i64,
+    current_height: u32,
+) -> Result<Transaction, MigrationError> {
+    let change_for_miner = BoxValue::try_from(max_miner_fee).unwrap();
+    let context_extension = certificate_context_extension(
+        &migration.certificate,
+        committee_size,
+        MIGRATION_THRESHOLD,
+        vault_utxo_token_id,
+        change_for_miner,
+    );
+
+    let vault_utxo = migration.vault_utxo.clone();
+    let migrated_vault_box = ErgoBoxCandidate {
+        value: BoxValue::try_from(vault_utxo.value.as_i64() - change_for_miner.as_i64())
+            .map_err(|e| MigrationError::TxRejected(e.to_string()))?,
+        ergo_tree: migration.new_contract.clone(),
+        tokens: vault_utxo.tokens.clone(),
+        additional_registers: vault_utxo.additional_registers.clone(),
+        creation_height: current_height,
+    };
+    let miner_output = ErgoBoxCandidate {
+        value: change_for_miner,
+        ergo_tree: MINERS_FEE_ADDRESS.script().unwrap(),
+        tokens: None,
+        additional_registers: NonMandatoryRegisters::empty(),
+        creation_height: current_height,
+    };
+    let outputs = TxIoVec::from_vec(vec![migrated_vault_box, miner_output]).unwrap();
+    let unsigned_input = UnsignedInput::new(vault_utxo.box_id(), context_extension);
+    let data_inputs: Vec<_> = data_boxes
+        .iter()
+        .map(|d| DataInput { box_id: d.box_id() })
+        .collect();
+    let data_inputs = Some(TxIoVec::from_vec(data_inputs).unwrap());
+    let unsigned_tx = UnsignedTransaction::new(
+        TxIoVec::from_vec(vec![unsigned_input]).unwrap(),
+        data_inputs,
+        outputs,
+    )
+    .unwrap();
+    let tx_context = TransactionContext::new(unsigned_tx, vec![vault_utxo], data_boxes).unwrap();
+    wallet
+        .sign_transaction(tx_context, ergo_state_context, None)
+        .map_err(|e| MigrationError::TxRejected(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use ergo_lib::ergo_chain_types::EcPoint;
+    use ergo_lib::ergotree_ir::chain::ergo_box::ErgoBox;
+    use ergo_lib::ergotree_ir::serialization::SigmaSerializable;
+    use k256::elliptic_curve::rand_core::OsRng;
+    use k256::schnorr::signature::Signer;
+    use k256::schnorr::SigningKey;
+    use k256::{ProjectivePoint, Scalar, SecretKey};
+    use sigma_test_util::force_any_val;
+    use spectrum_chain_connector::{OperatorApproval, VaultMigration};
+    use spectrum_crypto::digest::{Blake2b256, Blake2bDigest256};
+    use spectrum_crypto::pubkey::PublicKey;
+    use spectrum_ledger::interop::ReportCertificate;
+    use spectrum_sigma::crypto::{
+        aggregate_commitment, aggregate_pk, aggregate_response, challenge, individual_input, response,
+        schnorr_commitment_pair,
+    };
+    use spectrum_sigma::sigma_aggregation::AggregateCertificate;
+
+    use crate::migration::{
+        migration_digest, MigrationError, MigrationOperators, MigrationState, PendingMigration,
+    };
+    use crate::script::VAULT_CONTRACT;
+
+    fn pending_migration() -> PendingMigration {
+        let vault_utxo = force_any_val::<ErgoBox>();
+        let digest = migration_digest(vault_utxo.box_id(), &VAULT_CONTRACT);
+        PendingMigration {
+            vault_utxo,
+            new_contract: VAULT_CONTRACT.clone(),
+            certificate: AggregateCertificate {
+                message_digest: digest,
+                aggregate_commitment: ProjectivePoint::GENERATOR.into(),
+                aggregate_response: Scalar::ONE,
+                exclusion_set: vec![],
+            },
+            digest,
+            state: MigrationState::AwaitingApproval {
+                approved_by: BTreeSet::new(),
+            },
+        }
+    }
+
+    fn approval(sk: &SigningKey, digest: Blake2bDigest256, operator_ix: u16) -> OperatorApproval {
+        OperatorApproval {
+            migration_digest: digest.as_ref().to_vec(),
+            operator_ix,
+            signature: sk.sign(digest.as_ref()).to_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn migration_is_approved_by_threshold_of_operators() {
+        let sks: Vec<_> = (0..3).map(|_| SigningKey::random(&mut OsRng)).collect();
+        let operators = MigrationOperators {
+            keys: sks.iter().map(|sk| *sk.verifying_key()).collect(),
+            threshold: 2,
+        };
+        let mut migration = pending_migration();
+        let digest = migration.digest;
+        assert_eq!(
+            migration.approve(&operators, &approval(&sks[0], digest, 0)),
+            Ok(false)
+        );
+        // Repeated approval doesn't count twice.
+        assert_eq!(
+            migration.approve(&operators, &approval(&sks[0], digest, 0)),
+            Ok(false)
+        );
+        assert_eq!(
+            migration.approve(&operators, &approval(&sks[0], digest, 1)),
+            Err(MigrationError::InvalidSignature(1))
+        );
+        assert_eq!(
+            migration.approve(&operators, &approval(&sks[2], digest, 3)),
+            Err(MigrationError::UnknownOperator(3))
+        );
+        assert_eq!(
+            migration.approve(&operators, &approval(&sks[2], Blake2bDigest256::random(), 2)),
+            Err(MigrationError::DigestMismatch)
+        );
+        assert_eq!(
+            migration.approve(&operators, &approval(&sks[2], digest, 2)),
+            Ok(true)
+        );
+    }
+
+    fn certify(sks: &[SecretKey], md: Blake2bDigest256) -> AggregateCertificate<Blake2b256> {
+        let committee = sks
+            .iter()
+            .map(|sk| PublicKey::from(sk.clone()))
+            .collect::<Vec<_>>();
+        let ais = committee
+            .iter()
+            .map(|pk| individual_input::<Blake2b256>(committee.clone(), pk.clone()))
+            .collect::<Vec<_>>();
+        let aggr_pk = aggregate_pk(committee.clone(), ais.clone());
+        let pairs = sks.iter().map(|_| schnorr_commitment_pair()).collect::<Vec<_>>();
+        let aggr_commitment = aggregate_commitment(pairs.iter().map(|(_, yi)| yi.clone()).collect());
+        let c = challenge(aggr_pk, aggr_commitment.clone(), md);
+        let responses = sks
+            .iter()
+            .zip(pairs)
+            .zip(ais)
+            .map(|((sk, (yi, _)), ai)| response(yi, sk.clone(), c, ai))
+            .collect();
+        AggregateCertificate {
+            message_digest: md,
+            aggregate_commitment: aggr_commitment,
+            aggregate_response: aggregate_response(responses),
+            exclusion_set: vec![],
+        }
+    }
+
+    #[test]
+    fn migration_certificate_is_verified() {
+        let sks: Vec<_> = (0..4).map(|_| SecretKey::random(&mut OsRng)).collect();
+        let committee: Vec<_> = sks
+            .iter()
+            .map(|sk| EcPoint::from(sk.public_key().to_projective()))
+            .collect();
+        let vault_utxo = force_any_val::<ErgoBox>();
+        let digest = migration_digest(vault_utxo.box_id(), &VAULT_CONTRACT);
+        let migration = |certificate| VaultMigration {
+            new_contract: VAULT_CONTRACT.sigma_serialize_bytes().unwrap(),
+            certificate: ReportCertificate::SchnorrK256(certificate),
+        };
+        let outsiders: Vec<_> = (0..4).map(|_| SecretKey::random(&mut OsRng)).collect();
+        assert_eq!(
+            PendingMigration::new(
+                vault_utxo.clone(),
+                migration(certify(&outsiders, digest)),
+                &committee
+            )
+            .err(),
+            Some(MigrationError::InvalidCertificate)
+        );
+        assert!(PendingMigration::new(vault_utxo, migration(certify(&sks, digest)), &committee).is_ok());
+    }
+
+    #[test]
+    fn approval_threshold_is_validated() {
+        let keys: Vec<_> = (0..3)
+            .map(|_| *SigningKey::random(&mut OsRng).verifying_key())
+            .collect();
+        let operators = |threshold| MigrationOperators {
+            keys: keys.clone(),
+            threshold,
+        };
+        assert!(operators(0).validate().is_err());
+        assert!(operators(4).validate().is_err());
+        assert!(operators(1).validate().is_ok());
+        assert!(operators(3).validate().is_ok());
+    }
+
+    #[test]
+    fn digest_binds_vault_box() {
+        let a = force_any_val::<ErgoBox>();
+        let b = force_any_val::<ErgoBox>();
+        assert_ne!(
+            migration_digest(a.box_id(), &VAULT_CONTRACT),
+            migration_digest(b.box_id(), &VAULT_CONTRACT)
+        );
+    }
+}
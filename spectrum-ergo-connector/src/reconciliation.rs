@@ -0,0 +1,236 @@
+//! Reconciliation of the vault UTxO set against the value moved through the vault on the
+//! Spectrum side, reconstructed from the history of [`ErgoTxEvent`]s.
+
+use std::collections::HashMap;
+
+use ergo_lib::ergotree_ir::chain::token::{Token, TokenId};
+use spectrum_chain_connector::VaultBalanceDiscrepancy;
+use spectrum_crypto::digest::Blake2bDigest256;
+use spectrum_ledger::cell::{AssetId, CustomAsset, NativeCoin, PolicyId, ProgressPoint, SValue};
+
+use crate::{
+    accounting::applied_txs,
+    script::{ErgoCell, ErgoTermCell},
+    tx_event::{ErgoTxEvent, ErgoTxType},
+    vault_utxo::VaultUtxo,
+};
+
+/// Signed multi-asset balance, so that withdrawals can be accounted before deposits.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Balance {
+    nano_ergs: i128,
+    tokens: HashMap<TokenId, i128>,
+}
+
+impl Balance {
+    fn add(&mut self, nano_ergs: u64, tokens: &[Token]) {
+        self.nano_ergs += nano_ergs as i128;
+        for Token { token_id, amount } in tokens {
+            *self.tokens.entry(*token_id).or_insert(0) += *amount.as_u64() as i128;
+        }
+    }
+
+    fn sub_cell(&mut self, cell: &ErgoCell) {
+        self.nano_ergs -= *cell.ergs.as_u64() as i128;
+        for Token { token_id, amount } in &cell.tokens {
+            *self.tokens.entry(*token_id).or_insert(0) -= *amount.as_u64() as i128;
+        }
+    }
+
+    fn nonzero_tokens(&self) -> HashMap<TokenId, i128> {
+        self.tokens
+            .iter()
+            .filter(|(_, amount)| **amount != 0)
+            .map(|(token_id, amount)| (*token_id, *amount))
+            .collect()
+    }
+
+    /// Negative amounts are shown as zero.
+    fn to_svalue(&self) -> SValue {
+        let clamp = |amount: i128| u64::try_from(amount.max(0)).unwrap_or(u64::MAX);
+        let assets = self
+            .nonzero_tokens()
+            .into_iter()
+            .map(|(token_id, amount)| {
+                let asset_id = AssetId::from(Blake2bDigest256::try_from(<Vec<u8>>::from(token_id)).unwrap());
+                (asset_id, CustomAsset::from(clamp(amount)))
+            })
+            .collect();
+        SValue {
+            native: NativeCoin::from(clamp(self.nano_ergs)),
+            assets: HashMap::from([(PolicyId::from(Blake2bDigest256::zero()), assets)]),
+        }
+    }
+}
+
+/// Compare total value of `vault_utxos` against the genesis value of the vault plus deposits
+/// minus withdrawals recorded in `events`. Tokens must match exactly. Miner fees of TXs spending
+/// the vault are paid out of its ERG, so the vault may hold up to `fee_allowance` nanoERG less
+/// than expected, but never more.
+pub fn reconcile(
+    vault_utxos: &[VaultUtxo],
+    genesis_vault_utxo: &VaultUtxo,
+    events: Vec<ErgoTxEvent>,
+    fee_allowance: u64,
+    progress_point: ProgressPoint,
+) -> Option<VaultBalanceDiscrepancy> {
+    let mut on_chain = Balance::default();
+    for utxo in vault_utxos {
+        on_chain.add(*utxo.value.as_u64(), &utxo.tokens);
+    }
+    let mut expected = Balance::default();
+    expected.add(*genesis_vault_utxo.value.as_u64(), &genesis_vault_utxo.tokens);
+    for tx in applied_txs(events) {
+        match tx.tx_type {
+            ErgoTxType::Deposit { imported_value, .. } => {
+                for cell in imported_value {
+                    expected.add(*cell.0.ergs.as_u64(), &cell.0.tokens);
+                }
+            }
+            ErgoTxType::Withdrawal { withdrawn_value, .. } => {
+                for ErgoTermCell(cell) in withdrawn_value {
+                    expected.sub_cell(&cell);
+                }
+            }
+            ErgoTxType::NewUnprocessedDeposit(_) | ErgoTxType::RefundedDeposit(_) => {}
+        }
+    }
+    let shortfall = expected.nano_ergs - on_chain.nano_ergs;
+    let reconciled = (0..=fee_allowance as i128).contains(&shortfall)
+        && on_chain.nonzero_tokens() == expected.nonzero_tokens();
+    (!reconciled).then(|| VaultBalanceDiscrepancy {
+        progress_point,
+        on_chain: on_chain.to_svalue(),
+        expected: expected.to_svalue(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ergo_lib::{
+        chain::transaction::TxId,
+        ergotree_ir::chain::{
+            address::{AddressEncoder, NetworkPrefix},
+            ergo_box::box_value::BoxValue,
+            token::{Token, TokenAmount, TokenId},
+        },
+    };
+    use sigma_test_util::force_any_val;
+    use spectrum_ledger::{cell::ProgressPoint, interop::Point, ERGO_CHAIN_ID};
+
+    use crate::{
+        reconciliation::reconcile,
+        script::{ErgoCell, ErgoInboundCell, ErgoTermCell},
+        tx_event::{ErgoTxEvent, ErgoTxType, SpectrumErgoTx},
+        vault_utxo::VaultUtxo,
+        AncillaryVaultInfo,
+    };
+
+    fn cell(nano_ergs: u64, tokens: Vec<Token>) -> ErgoCell {
+        let encoder = AddressEncoder::new(NetworkPrefix::Mainnet);
+        ErgoCell {
+            ergs: BoxValue::try_from(nano_ergs).unwrap(),
+            address: encoder
+                .parse_address_from_str("9hVmDmyrLoNAupFVoobZRCfbwDWnAvCmjT1KCS4yGy3XziaCyMg")
+                .unwrap(),
+            tokens,
+        }
+    }
+
+    fn vault_utxo(nano_ergs: u64, tokens: Vec<Token>) -> VaultUtxo {
+        VaultUtxo {
+            value: BoxValue::try_from(nano_ergs).unwrap(),
+            tokens,
+        }
+    }
+
+    fn vault_info() -> (VaultUtxo, AncillaryVaultInfo) {
+        (
+            vault_utxo(1_000_000, vec![]),
+            AncillaryVaultInfo {
+                box_id: force_any_val(),
+                height: 0,
+                tx_id: force_any_val(),
+            },
+        )
+    }
+
+    fn events(token: Token) -> Vec<ErgoTxEvent> {
+        let tx = |tx_type| SpectrumErgoTx {
+            progress_point: 10,
+            tx_id: force_any_val::<TxId>(),
+            tx_type,
+        };
+        let rolled_back = tx(ErgoTxType::Withdrawal {
+            withdrawn_value: vec![ErgoTermCell(cell(9_000_000, vec![]))],
+            vault_info: vault_info(),
+        });
+        vec![
+            ErgoTxEvent::Applied(tx(ErgoTxType::Deposit {
+                imported_value: vec![ErgoInboundCell(cell(5_000_000, vec![token]), force_any_val())],
+                vault_info: vault_info(),
+            })),
+            ErgoTxEvent::Applied(rolled_back.clone()),
+            ErgoTxEvent::Unapplied(rolled_back),
+            ErgoTxEvent::Applied(tx(ErgoTxType::Withdrawal {
+                withdrawn_value: vec![ErgoTermCell(cell(2_000_000, vec![]))],
+                vault_info: vault_info(),
+            })),
+        ]
+    }
+
+    #[test]
+    fn vault_reconciled_within_fee_allowance() {
+        let token = Token {
+            token_id: force_any_val::<TokenId>(),
+            amount: TokenAmount::try_from(100_u64).unwrap(),
+        };
+        let genesis = vault_utxo(10_000_000, vec![]);
+        let pp = ProgressPoint {
+            chain_id: ERGO_CHAIN_ID,
+            point: Point::from(10),
+        };
+        // 10 + 5 - 2 ERG expected, 0.002 ERG paid in fees.
+        let vault = vault_utxo(12_998_000, vec![token.clone()]);
+        assert_eq!(
+            reconcile(
+                &[vault.clone()],
+                &genesis,
+                events(token.clone()),
+                2_000,
+                pp.clone()
+            ),
+            None
+        );
+        assert!(reconcile(
+            &[vault.clone()],
+            &genesis,
+            events(token.clone()),
+            1_000,
+            pp.clone()
+        )
+        .is_some());
+        let surplus = vault_utxo(13_000_001, vec![token.clone()]);
+        assert!(reconcile(&[surplus], &genesis, events(token.clone()), 2_000, pp.clone()).is_some());
+        let missing_token = vault_utxo(12_998_000, vec![]);
+        let discrepancy = reconcile(&[missing_token], &genesis, events(token), 2_000, pp).unwrap();
+        assert_eq!(
+            discrepancy
+                .on_chain
+                .assets
+                .values()
+                .map(|a| a.len())
+                .sum::<usize>(),
+            0
+        );
+        assert_eq!(
+            discrepancy
+                .expected
+                .assets
+                .values()
+                .map(|a| a.len())
+                .sum::<usize>(),
+            1
+        );
+    }
+}
@@ -1,5 +1,6 @@
 pub mod deposit;
 pub mod ergo_tx_event_history;
+pub mod outbox;
 pub mod tx_retry_scheduler;
 pub mod vault_boxes;
 pub mod withdrawals;
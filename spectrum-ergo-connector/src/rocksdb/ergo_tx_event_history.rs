@@ -14,6 +14,8 @@ pub trait ErgoTxEventHistory {
     async fn append(&mut self, moved_value: ErgoTxEvent);
     /// Returns `ErgoTxEvent` that is closest and >= `height`.
     async fn get(&self, height: u32) -> Option<(ErgoTxEvent, u32)>;
+    /// Returns all `ErgoTxEvent`s with heights within `[from, to]`, in order of height.
+    async fn range(&self, from: u32, to: u32) -> Vec<ErgoTxEvent>;
 }
 
 pub struct ErgoTxEventHistoryRocksDB {
@@ -78,6 +80,26 @@ impl ErgoTxEventHistory for ErgoTxEventHistoryRocksDB {
         })
         .await
     }
+
+    async fn range(&self, from: u32, to: u32) -> Vec<ErgoTxEvent> {
+        let db = Arc::clone(&self.db);
+        spawn_blocking(move || {
+            let key = from.to_be_bytes();
+            let mut res = vec![];
+            for (key_bytes, value_bytes) in db
+                .iterator(IteratorMode::From(&key, Direction::Forward))
+                .flatten()
+            {
+                let bb: [u8; 4] = key_bytes.as_ref().try_into().unwrap();
+                if u32::from_be_bytes(bb) > to {
+                    break;
+                }
+                res.push(rmp_serde::from_slice(&value_bytes).unwrap());
+            }
+            res
+        })
+        .await
+    }
 }
 
 #[derive(Default)]
@@ -111,6 +133,14 @@ impl ErgoTxEventHistory for InMemoryMovedValueHistory {
         }
         None
     }
+
+    async fn range(&self, from: u32, to: u32) -> Vec<ErgoTxEvent> {
+        self.history
+            .iter()
+            .filter(|mv| (from..=to).contains(&mv.get_height()))
+            .cloned()
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -190,6 +220,13 @@ mod tests {
 
         // Test greater height
         assert_eq!(history.get(height + 1).await, Some((mv_1.clone(), height + 10)));
+
+        assert_eq!(
+            history.range(height, height + 10).await,
+            vec![mv_0.clone(), mv_1.clone()]
+        );
+        assert_eq!(history.range(height + 1, height + 20).await, vec![mv_1.clone()]);
+        assert_eq!(history.range(height + 11, height + 20).await, vec![]);
     }
 
     fn gen_moved_value(height: u32) -> ErgoTxEvent {
@@ -0,0 +1,313 @@
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use async_std::task::spawn_blocking;
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Responses to consensus-driver which weren't acknowledged by it yet. Every response is assigned
+/// a sequence number, so that a reconnected driver can resume from the last one it processed
+/// instead of losing responses emitted while it was offline.
+#[async_trait]
+pub trait Outbox<M> {
+    /// Persist the message, returns its sequence number.
+    async fn push(&mut self, msg: &M) -> u64;
+    /// All retained messages following `last_acked_seq` (or all of them if `None`), oldest first.
+    async fn replay(&self, last_acked_seq: Option<u64>) -> Result<Vec<(u64, M)>, ReplayUnavailable>;
+    /// Messages up to and including `seq` are processed by consensus-driver, drop them.
+    async fn acknowledge(&mut self, seq: u64);
+}
+
+/// Messages following the requested one were dropped due to retention limits.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ReplayUnavailable {
+    pub requested_seq: u64,
+    pub oldest_seq: u64,
+}
+
+/// Limits on unacknowledged messages, the oldest ones are dropped once any of them is exceeded.
+#[serde_with::serde_as]
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct OutboxRetention {
+    pub max_messages: usize,
+    #[serde_as(as = "serde_with::DurationSeconds<i64>")]
+    pub max_age: Duration,
+}
+
+impl Default for OutboxRetention {
+    fn default() -> Self {
+        Self {
+            max_messages: 10_000,
+            max_age: Duration::days(1),
+        }
+    }
+}
+
+impl OutboxRetention {
+    fn is_exceeded(&self, num_messages: usize, timestamp: i64, now: i64) -> bool {
+        num_messages > self.max_messages || timestamp < now - self.max_age.num_seconds()
+    }
+}
+
+fn check_replay(last_acked_seq: Option<u64>, oldest_seq: u64) -> Result<(), ReplayUnavailable> {
+    match last_acked_seq {
+        Some(requested_seq) if requested_seq + 1 < oldest_seq => Err(ReplayUnavailable {
+            requested_seq,
+            oldest_seq,
+        }),
+        _ => Ok(()),
+    }
+}
+
+pub struct OutboxRocksDB<M> {
+    db: Arc<rocksdb::OptimisticTransactionDB>,
+    retention: OutboxRetention,
+    next_seq: u64,
+    pd: PhantomData<M>,
+}
+
+impl<M> OutboxRocksDB<M> {
+    pub fn new(db_path: &str, retention: OutboxRetention) -> Self {
+        let db = rocksdb::OptimisticTransactionDB::open_default(db_path).unwrap();
+        let next_seq = db
+            .get(NEXT_SEQ_KEY)
+            .unwrap()
+            .map(|bytes| u64::from_be_bytes(bytes.try_into().unwrap()))
+            .unwrap_or(0);
+        Self {
+            db: Arc::new(db),
+            retention,
+            next_seq,
+            pd: PhantomData,
+        }
+    }
+}
+
+fn key(seq: u64) -> Vec<u8> {
+    let mut key = MESSAGE_KEY.as_bytes().to_vec();
+    key.extend_from_slice(&seq.to_be_bytes());
+    key
+}
+
+/// Retained messages starting from `from_seq` along with their sequence numbers, oldest first.
+/// Value of each message is prefixed by the timestamp it was pushed at.
+fn messages(db: &rocksdb::OptimisticTransactionDB, from_seq: u64) -> Vec<(u64, Vec<u8>)> {
+    db.iterator(rocksdb::IteratorMode::From(
+        &key(from_seq),
+        rocksdb::Direction::Forward,
+    ))
+    .map(|kv| kv.unwrap())
+    .take_while(|(k, _)| k.starts_with(MESSAGE_KEY.as_bytes()))
+    .map(|(k, v)| {
+        let seq = u64::from_be_bytes(k[MESSAGE_KEY.len()..].try_into().unwrap());
+        (seq, v.to_vec())
+    })
+    .collect()
+}
+
+fn timestamp(value: &[u8]) -> i64 {
+    i64::from_be_bytes(value[..8].try_into().unwrap())
+}
+
+#[async_trait]
+impl<M> Outbox<M> for OutboxRocksDB<M>
+where
+    M: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    async fn push(&mut self, msg: &M) -> u64 {
+        let db = Arc::clone(&self.db);
+        let retention = self.retention;
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let msg_bytes = rmp_serde::to_vec_named(msg).unwrap();
+        spawn_blocking(move || {
+            let now = Utc::now().timestamp();
+            let mut value = now.to_be_bytes().to_vec();
+            value.extend(msg_bytes);
+            let tx = db.transaction();
+            tx.put(key(seq), value).unwrap();
+            tx.put(NEXT_SEQ_KEY, (seq + 1).to_be_bytes()).unwrap();
+            let retained = messages(&db, 0);
+            let mut num_messages = retained.len() + 1;
+            for (old_seq, old_value) in retained {
+                if !retention.is_exceeded(num_messages, timestamp(&old_value), now) {
+                    break;
+                }
+                tx.delete(key(old_seq)).unwrap();
+                num_messages -= 1;
+            }
+            tx.commit().unwrap();
+        })
+        .await;
+        seq
+    }
+
+    async fn replay(&self, last_acked_seq: Option<u64>) -> Result<Vec<(u64, M)>, ReplayUnavailable> {
+        let db = Arc::clone(&self.db);
+        let next_seq = self.next_seq;
+        spawn_blocking(move || {
+            let oldest_seq = messages(&db, 0).first().map_or(next_seq, |(seq, _)| *seq);
+            check_replay(last_acked_seq, oldest_seq)?;
+            Ok(messages(&db, last_acked_seq.map_or(0, |seq| seq + 1))
+                .into_iter()
+                .map(|(seq, value)| (seq, rmp_serde::from_slice(&value[8..]).unwrap()))
+                .collect())
+        })
+        .await
+    }
+
+    async fn acknowledge(&mut self, seq: u64) {
+        let db = Arc::clone(&self.db);
+        spawn_blocking(move || {
+            let tx = db.transaction();
+            for (acked_seq, _) in messages(&db, 0).into_iter().take_while(|(s, _)| *s <= seq) {
+                tx.delete(key(acked_seq)).unwrap();
+            }
+            tx.commit().unwrap();
+        })
+        .await
+    }
+}
+
+pub struct InMemoryOutbox<M> {
+    /// Messages along with their sequence numbers and timestamps, oldest first.
+    messages: VecDeque<(u64, i64, M)>,
+    retention: OutboxRetention,
+    next_seq: u64,
+}
+
+impl<M> InMemoryOutbox<M> {
+    pub fn new(retention: OutboxRetention) -> Self {
+        Self {
+            messages: VecDeque::new(),
+            retention,
+            next_seq: 0,
+        }
+    }
+}
+
+#[async_trait]
+impl<M: Clone + Send + Sync> Outbox<M> for InMemoryOutbox<M> {
+    async fn push(&mut self, msg: &M) -> u64 {
+        let now = Utc::now().timestamp();
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.messages.push_back((seq, now, msg.clone()));
+        while let Some((_, ts, _)) = self.messages.front() {
+            if !self.retention.is_exceeded(self.messages.len(), *ts, now) {
+                break;
+            }
+            self.messages.pop_front();
+        }
+        seq
+    }
+
+    async fn replay(&self, last_acked_seq: Option<u64>) -> Result<Vec<(u64, M)>, ReplayUnavailable> {
+        let oldest_seq = self.messages.front().map_or(self.next_seq, |(seq, _, _)| *seq);
+        check_replay(last_acked_seq, oldest_seq)?;
+        Ok(self
+            .messages
+            .iter()
+            .filter(|(seq, _, _)| last_acked_seq.map_or(true, |acked| *seq > acked))
+            .map(|(seq, _, msg)| (*seq, msg.clone()))
+            .collect())
+    }
+
+    async fn acknowledge(&mut self, seq: u64) {
+        while self.messages.front().map_or(false, |(s, _, _)| *s <= seq) {
+            self.messages.pop_front();
+        }
+    }
+}
+
+const MESSAGE_KEY: &str = "m:";
+const NEXT_SEQ_KEY: &str = "next_seq";
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use rand::RngCore;
+
+    use crate::rocksdb::outbox::{InMemoryOutbox, Outbox, OutboxRetention, OutboxRocksDB, ReplayUnavailable};
+
+    fn retention(max_messages: usize) -> OutboxRetention {
+        OutboxRetention {
+            max_messages,
+            max_age: Duration::days(1),
+        }
+    }
+
+    fn rocks_db_outbox(max_messages: usize) -> OutboxRocksDB<String> {
+        let rnd = rand::thread_rng().next_u32();
+        OutboxRocksDB::new(&format!("./tmp/{}", rnd), retention(max_messages))
+    }
+
+    #[tokio::test]
+    async fn test_rocksdb_replay_after_ack() {
+        replay_after_ack(rocks_db_outbox(10)).await;
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_replay_after_ack() {
+        replay_after_ack(InMemoryOutbox::new(retention(10))).await;
+    }
+
+    async fn replay_after_ack<O: Outbox<String>>(mut outbox: O) {
+        for i in 0..5 {
+            assert_eq!(outbox.push(&format!("msg{}", i)).await, i);
+        }
+        outbox.acknowledge(1).await;
+        assert_eq!(
+            outbox.replay(Some(2)).await,
+            Ok(vec![(3, "msg3".to_string()), (4, "msg4".to_string())])
+        );
+        assert_eq!(outbox.replay(None).await.unwrap().len(), 3);
+        outbox.acknowledge(4).await;
+        assert_eq!(outbox.replay(Some(4)).await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn test_rocksdb_retention_limit() {
+        retention_limit(rocks_db_outbox(2)).await;
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_retention_limit() {
+        retention_limit(InMemoryOutbox::new(retention(2))).await;
+    }
+
+    async fn retention_limit<O: Outbox<String>>(mut outbox: O) {
+        for i in 0..5 {
+            outbox.push(&format!("msg{}", i)).await;
+        }
+        assert_eq!(
+            outbox.replay(Some(1)).await,
+            Err(ReplayUnavailable {
+                requested_seq: 1,
+                oldest_seq: 3
+            })
+        );
+        assert_eq!(
+            outbox.replay(Some(2)).await,
+            Ok(vec![(3, "msg3".to_string()), (4, "msg4".to_string())])
+        );
+    }
+
+    #[tokio::test]
+    async fn sequence_survives_restart() {
+        let rnd = rand::thread_rng().next_u32();
+        let path = format!("./tmp/{}", rnd);
+        {
+            let mut outbox = OutboxRocksDB::<String>::new(&path, retention(10));
+            outbox.push(&"msg0".to_string()).await;
+            outbox.push(&"msg1".to_string()).await;
+            outbox.acknowledge(1).await;
+        }
+        let mut outbox = OutboxRocksDB::<String>::new(&path, retention(10));
+        assert_eq!(outbox.push(&"msg2".to_string()).await, 2);
+        assert_eq!(outbox.replay(Some(1)).await, Ok(vec![(2, "msg2".to_string())]));
+    }
+}
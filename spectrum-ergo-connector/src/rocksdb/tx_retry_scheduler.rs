@@ -8,48 +8,165 @@ use ergo_lib::ergotree_ir::chain::ergo_box::BoxId;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use spectrum_chain_connector::{
-    InboundValue, PendingDepositStatus, PendingTxStatus, PendingWithdrawalStatus, TxStatus,
+    AcknowledgementThreshold, Confirmation, InboundValue, PendingDepositStatus, PendingTxStatus,
+    PendingWithdrawalStatus, TxStatus,
 };
 
 use crate::script::ExtraErgoData;
-use crate::tx_in_progress::{IdentifyBy, Timestamped, TxInProgress, WithdrawalInProgress};
+use crate::tx_in_progress::{Conflicts, IdentifyBy, Timestamped, TxInProgress};
 
 /// Handle resubmission of Spectrum Network TXs.
+///
+/// Up to a configured number of non-conflicting TXs can be pending at the same time. Pending TXs
+/// are kept in order of submission, which is also the order they are resubmitted in (a TX may
+/// spend an output of a preceding one).
 #[async_trait(?Send)]
 pub trait TxRetryScheduler<T, U>
 where
     T: IdentifyBy<U>,
 {
-    /// To be called when connector has submitted a TX to mempool.
-    async fn add(&mut self, data: T);
-    /// Obtain next command from the scheduler
+    /// To be called when connector has submitted a TX to mempool. Adding a TX which is pending
+    /// already is a no-op.
+    async fn add(&mut self, data: T) -> Result<(), Rejected>;
+    /// Check whether the TX can be added without submitting it.
+    async fn check(&self, data: &T) -> Result<(), Rejected>;
+    /// Obtain next command from the scheduler (the one for the oldest pending TX).
     async fn next_command(&self) -> Command<T>;
-    async fn notify_confirmed(&mut self, data: &T);
+    /// Obtain commands for all pending TXs, oldest first.
+    async fn all_commands(&self) -> Vec<Command<T>>;
+    /// To be called when the TX is included into the block at the given height.
+    async fn notify_confirmed(&mut self, data: &T, height: u32);
+    /// To be called when the block containing the confirmed TX is rolled back.
+    async fn notify_unconfirmed(&mut self, data: &T);
     async fn notify_failed(&mut self, data: &T);
+    /// To be called when the TX can't become valid anymore, e.g. its input is spent by another TX.
+    /// The TX is aborted right away.
+    async fn notify_superseded(&mut self, data: &T);
     async fn clear_confirmed(&mut self, element: &U);
     async fn clear_aborted(&mut self, element: &U);
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Rejected {
+    /// Max number of pending TXs is reached.
+    WindowFull,
+    /// The TX conflicts with some pending TX which isn't aborted, see [`Conflicts`].
+    Conflict,
+}
+
 pub struct TxRetrySchedulerRocksDB {
     db: Arc<rocksdb::OptimisticTransactionDB>,
     retry_delay_duration: i64,
     max_retries: u32,
+    max_pending_txs: usize,
 }
 
 impl TxRetrySchedulerRocksDB {
-    pub async fn new(db_path: &str, retry_delay_duration: i64, max_retries: u32) -> Self {
-        let res = Self {
+    pub async fn new(
+        db_path: &str,
+        retry_delay_duration: i64,
+        max_retries: u32,
+        max_pending_txs: usize,
+    ) -> Self {
+        Self {
             db: Arc::new(rocksdb::OptimisticTransactionDB::open_default(db_path).unwrap()),
             retry_delay_duration,
             max_retries,
-        };
-        let db = Arc::clone(&res.db);
-        spawn_blocking(move || {
-            db.put(COUNT_KEY.as_bytes(), 0_u32.to_be_bytes()).unwrap();
+            max_pending_txs,
+        }
+    }
+}
+
+fn key(prefix: &str, seq: u64) -> Vec<u8> {
+    let mut key = prefix.as_bytes().to_vec();
+    key.extend_from_slice(&seq.to_be_bytes());
+    key
+}
+
+/// All pending TXs along with their sequence numbers, oldest first.
+fn pending<T: DeserializeOwned>(db: &rocksdb::OptimisticTransactionDB) -> Vec<(u64, T)> {
+    db.prefix_iterator(TX_KEY.as_bytes())
+        .map(|kv| kv.unwrap())
+        .take_while(|(k, _)| k.starts_with(TX_KEY.as_bytes()))
+        .map(|(k, v)| {
+            let seq = u64::from_be_bytes(k[TX_KEY.len()..].try_into().unwrap());
+            (seq, rmp_serde::from_slice(&v).unwrap())
         })
-        .await;
+        .collect()
+}
+
+fn find<T, F>(db: &rocksdb::OptimisticTransactionDB, pred: F) -> Option<(u64, T)>
+where
+    T: DeserializeOwned,
+    F: Fn(&T) -> bool,
+{
+    pending(db).into_iter().find(|(_, tx)| pred(tx))
+}
+
+fn status(db: &rocksdb::OptimisticTransactionDB, seq: u64) -> Status {
+    let status_bytes = db.get(key(STATUS_KEY, seq)).unwrap().unwrap();
+    rmp_serde::from_slice(&status_bytes).unwrap()
+}
+
+fn command<T>(db: &rocksdb::OptimisticTransactionDB, seq: u64, tx: T) -> Command<T> {
+    match status(db, seq) {
+        Status::InProgress => {
+            let ts_now = Utc::now().timestamp();
+            let timestamp_bytes = db.get(key(RETRY_TIMESTAMP_KEY, seq)).unwrap().unwrap();
+            let next_timestamp = i64::from_be_bytes(timestamp_bytes.try_into().unwrap());
+            if ts_now >= next_timestamp {
+                Command::ResubmitTx(tx)
+            } else {
+                Command::Wait(Duration::from_secs((next_timestamp - ts_now) as u64), tx)
+            }
+        }
+        Status::Confirmed => {
+            let height = db
+                .get(key(CONFIRMED_HEIGHT_KEY, seq))
+                .unwrap()
+                .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()));
+            Command::Confirmed(height, tx)
+        }
+        Status::Aborted => Command::Abort(tx),
+    }
+}
 
-        res
+fn check_admissible<T: Conflicts + DeserializeOwned>(
+    db: &rocksdb::OptimisticTransactionDB,
+    data: &T,
+    max_pending_txs: usize,
+) -> Result<(), Rejected> {
+    let pending = pending::<T>(db);
+    // Aborted TXs are never resubmitted, so they may be replaced by conflicting ones.
+    if pending
+        .iter()
+        .any(|(seq, tx)| status(db, *seq) != Status::Aborted && tx.conflicts_with(data))
+    {
+        Err(Rejected::Conflict)
+    } else if pending.len() >= max_pending_txs {
+        Err(Rejected::WindowFull)
+    } else {
+        Ok(())
+    }
+}
+
+fn clear<T, U>(db: &rocksdb::OptimisticTransactionDB, element: &U, expected_status: Status)
+where
+    T: IdentifyBy<U> + DeserializeOwned,
+{
+    if let Some((seq, _)) = find::<T, _>(db, |tx| tx.is_identified_by(element)) {
+        assert_eq!(status(db, seq), expected_status);
+        let tx = db.transaction();
+        for prefix in [
+            TX_KEY,
+            COUNT_KEY,
+            RETRY_TIMESTAMP_KEY,
+            STATUS_KEY,
+            CONFIRMED_HEIGHT_KEY,
+        ] {
+            tx.delete(key(prefix, seq)).unwrap();
+        }
+        tx.commit().unwrap()
     }
 }
 
@@ -58,6 +175,7 @@ impl<T, U> TxRetryScheduler<T, U> for TxRetrySchedulerRocksDB
 where
     T: IdentifyBy<U>
         + Timestamped
+        + Conflicts
         + Clone
         + Debug
         + Eq
@@ -68,70 +186,107 @@ where
         + 'static,
     U: Clone + Debug + Send + Sync + 'static,
 {
-    async fn add(&mut self, data: T) {
+    async fn add(&mut self, data: T) -> Result<(), Rejected> {
         let db = Arc::clone(&self.db);
         let retry_delay_duration = self.retry_delay_duration;
+        let max_pending_txs = self.max_pending_txs;
         spawn_blocking(move || {
+            if find::<T, _>(&db, |tx| *tx == data).is_some() {
+                return Ok(());
+            }
+            check_admissible(&db, &data, max_pending_txs)?;
+            let seq = db
+                .get(NEXT_SEQ_KEY.as_bytes())
+                .unwrap()
+                .map(|bytes| u64::from_be_bytes(bytes.try_into().unwrap()))
+                .unwrap_or(0);
             let value_bytes = rmp_serde::to_vec_named(&data).unwrap();
             let tx = db.transaction();
-            tx.put(TX_KEY.as_bytes(), value_bytes).unwrap();
-            tx.put(COUNT_KEY.as_bytes(), 0_u32.to_be_bytes()).unwrap();
+            tx.put(NEXT_SEQ_KEY.as_bytes(), (seq + 1).to_be_bytes()).unwrap();
+            tx.put(key(TX_KEY, seq), value_bytes).unwrap();
+            tx.put(key(COUNT_KEY, seq), 0_u32.to_be_bytes()).unwrap();
             tx.put(
-                STATUS_KEY.as_bytes(),
+                key(STATUS_KEY, seq),
                 rmp_serde::to_vec_named(&Status::InProgress).unwrap(),
             )
             .unwrap();
             tx.put(
-                RETRY_TIMESTAMP_KEY.as_bytes(),
+                key(RETRY_TIMESTAMP_KEY, seq),
                 (data.get_timestamp() + retry_delay_duration).to_be_bytes(),
             )
             .unwrap();
-            tx.commit().unwrap()
+            tx.commit().unwrap();
+            Ok(())
         })
         .await
     }
 
+    async fn check(&self, data: &T) -> Result<(), Rejected> {
+        let db = Arc::clone(&self.db);
+        let cloned = data.clone();
+        let max_pending_txs = self.max_pending_txs;
+        spawn_blocking(move || check_admissible(&db, &cloned, max_pending_txs)).await
+    }
+
     async fn next_command(&self) -> Command<T> {
         let db = Arc::clone(&self.db);
-        spawn_blocking(move || match db.get(TX_KEY.as_bytes()).unwrap() {
-            Some(value_bytes) => {
-                let status_bytes = db.get(STATUS_KEY.as_bytes()).unwrap().unwrap();
-                let status: Status = rmp_serde::from_slice(&status_bytes).unwrap();
-                let tx: T = rmp_serde::from_slice(&value_bytes).unwrap();
-                match status {
-                    Status::InProgress => {
-                        let ts_now = Utc::now().timestamp();
-                        let timestamp_bytes = db.get(RETRY_TIMESTAMP_KEY.as_bytes()).unwrap().unwrap();
-                        let next_timestamp = i64::from_be_bytes(timestamp_bytes.try_into().unwrap());
-                        if ts_now >= next_timestamp {
-                            Command::ResubmitTx(tx)
-                        } else {
-                            Command::Wait(Duration::from_secs((next_timestamp - ts_now) as u64), tx)
-                        }
-                    }
-                    Status::Confirmed => Command::Confirmed(tx),
-                    Status::Aborted => Command::Abort(tx),
-                }
-            }
+        spawn_blocking(move || match pending::<T>(&db).into_iter().next() {
+            Some((seq, tx)) => command(&db, seq, tx),
             None => Command::Idle,
         })
         .await
     }
 
-    async fn notify_confirmed(&mut self, data: &T) {
+    async fn all_commands(&self) -> Vec<Command<T>> {
+        let db = Arc::clone(&self.db);
+        spawn_blocking(move || {
+            pending::<T>(&db)
+                .into_iter()
+                .map(|(seq, tx)| command(&db, seq, tx))
+                .collect()
+        })
+        .await
+    }
+
+    async fn notify_confirmed(&mut self, data: &T, height: u32) {
         let db = Arc::clone(&self.db);
         let cloned = data.clone();
         spawn_blocking(move || {
-            let value_bytes = db.get(TX_KEY.as_bytes()).unwrap().unwrap();
-            let value: T = rmp_serde::from_slice(&value_bytes).unwrap();
-            assert_eq!(value, cloned);
+            let (seq, _) = find::<T, _>(&db, |tx| *tx == cloned).unwrap();
             let tx = db.transaction();
             tx.put(
-                STATUS_KEY.as_bytes(),
+                key(STATUS_KEY, seq),
                 rmp_serde::to_vec_named(&Status::Confirmed).unwrap(),
             )
             .unwrap();
-            tx.put(COUNT_KEY.as_bytes(), 0_u32.to_be_bytes()).unwrap();
+            tx.put(key(COUNT_KEY, seq), 0_u32.to_be_bytes()).unwrap();
+            tx.put(key(CONFIRMED_HEIGHT_KEY, seq), height.to_be_bytes())
+                .unwrap();
+            tx.commit().unwrap()
+        })
+        .await
+    }
+
+    async fn notify_unconfirmed(&mut self, data: &T) {
+        let db = Arc::clone(&self.db);
+        let cloned = data.clone();
+        let retry_delay_duration = self.retry_delay_duration;
+        spawn_blocking(move || {
+            let (seq, _) = find::<T, _>(&db, |tx| *tx == cloned).unwrap();
+            // The TX is likely to be included into another block, so give it some time before
+            // resubmitting.
+            let tx = db.transaction();
+            tx.put(
+                key(STATUS_KEY, seq),
+                rmp_serde::to_vec_named(&Status::InProgress).unwrap(),
+            )
+            .unwrap();
+            tx.put(
+                key(RETRY_TIMESTAMP_KEY, seq),
+                (Utc::now().timestamp() + retry_delay_duration).to_be_bytes(),
+            )
+            .unwrap();
+            tx.delete(key(CONFIRMED_HEIGHT_KEY, seq)).unwrap();
             tx.commit().unwrap()
         })
         .await
@@ -142,21 +297,19 @@ where
         let cloned = data.clone();
         let max_retries = self.max_retries;
         spawn_blocking(move || {
-            let value_bytes = db.get(TX_KEY.as_bytes()).unwrap().unwrap();
-            let value: T = rmp_serde::from_slice(&value_bytes).unwrap();
-            assert_eq!(value, cloned);
-            let count_bytes = db.get(COUNT_KEY.as_bytes()).unwrap().unwrap();
+            let (seq, _) = find::<T, _>(&db, |tx| *tx == cloned).unwrap();
+            let count_bytes = db.get(key(COUNT_KEY, seq)).unwrap().unwrap();
             let count = u32::from_be_bytes(count_bytes.try_into().unwrap());
 
             // We need to overwrite the mapped value with the newer one because we need the latest
             // timestamp.
             let updated_bytes = rmp_serde::to_vec_named(&cloned).unwrap();
             let tx = db.transaction();
-            tx.put(TX_KEY.as_bytes(), updated_bytes).unwrap();
-            tx.put(COUNT_KEY.as_bytes(), (count + 1).to_be_bytes()).unwrap();
+            tx.put(key(TX_KEY, seq), updated_bytes).unwrap();
+            tx.put(key(COUNT_KEY, seq), (count + 1).to_be_bytes()).unwrap();
             if count + 1 == max_retries {
                 tx.put(
-                    STATUS_KEY.as_bytes(),
+                    key(STATUS_KEY, seq),
                     rmp_serde::to_vec_named(&Status::Aborted).unwrap(),
                 )
                 .unwrap();
@@ -166,36 +319,30 @@ where
         .await
     }
 
-    async fn clear_confirmed(&mut self, element: &U) {
+    async fn notify_superseded(&mut self, data: &T) {
         let db = Arc::clone(&self.db);
-        let cloned = element.clone();
+        let cloned = data.clone();
         spawn_blocking(move || {
-            if let Some(value_bytes) = db.get(TX_KEY.as_bytes()).unwrap() {
-                let value: T = rmp_serde::from_slice(&value_bytes).unwrap();
-                assert!(value.is_identified_by(&cloned));
-                let status_bytes = db.get(STATUS_KEY.as_bytes()).unwrap().unwrap();
-                let status: Status = rmp_serde::from_slice(&status_bytes).unwrap();
-                assert_eq!(status, Status::Confirmed);
-                db.delete(TX_KEY.as_bytes()).unwrap();
-            }
+            let (seq, _) = find::<T, _>(&db, |tx| *tx == cloned).unwrap();
+            db.put(
+                key(STATUS_KEY, seq),
+                rmp_serde::to_vec_named(&Status::Aborted).unwrap(),
+            )
+            .unwrap();
         })
         .await
     }
 
+    async fn clear_confirmed(&mut self, element: &U) {
+        let db = Arc::clone(&self.db);
+        let cloned = element.clone();
+        spawn_blocking(move || clear::<T, U>(&db, &cloned, Status::Confirmed)).await
+    }
+
     async fn clear_aborted(&mut self, element: &U) {
         let db = Arc::clone(&self.db);
         let cloned = element.clone();
-        spawn_blocking(move || {
-            if let Some(value_bytes) = db.get(TX_KEY.as_bytes()).unwrap() {
-                let value: T = rmp_serde::from_slice(&value_bytes).unwrap();
-                assert!(value.is_identified_by(&cloned));
-                let status_bytes = db.get(STATUS_KEY.as_bytes()).unwrap().unwrap();
-                let status: Status = rmp_serde::from_slice(&status_bytes).unwrap();
-                assert_eq!(status, Status::Aborted);
-                db.delete(TX_KEY.as_bytes()).unwrap();
-            }
-        })
-        .await
+        spawn_blocking(move || clear::<T, U>(&db, &cloned, Status::Aborted)).await
     }
 }
 
@@ -203,6 +350,8 @@ const TX_KEY: &str = "e:";
 const COUNT_KEY: &str = "c:";
 const RETRY_TIMESTAMP_KEY: &str = "r:";
 const STATUS_KEY: &str = "s:";
+const NEXT_SEQ_KEY: &str = "n:";
+const CONFIRMED_HEIGHT_KEY: &str = "h:";
 
 #[derive(PartialEq, Eq, Debug)]
 pub enum Command<T> {
@@ -212,61 +361,51 @@ pub enum Command<T> {
     Abort(T),
     /// Wait for the specified duration to retry Tx
     Wait(Duration, T),
-    /// Current TX has been confirmed
-    Confirmed(T),
+    /// Current TX has been confirmed at the given height. The height is unknown for TXs
+    /// confirmed before heights were recorded.
+    Confirmed(Option<u32>, T),
     /// There's currently no TX in progress
     Idle,
 }
 
-impl From<Command<WithdrawalInProgress>> for Option<PendingWithdrawalStatus<ExtraErgoData>> {
-    fn from(value: Command<WithdrawalInProgress>) -> Self {
-        match value {
-            Command::ResubmitTx(e) | Command::Wait(_, e) => Some(PendingWithdrawalStatus {
-                identifier: e.report,
-                status: TxStatus::WaitingForConfirmation,
-            }),
-            Command::Abort(e) => Some(PendingWithdrawalStatus {
-                identifier: e.report,
-                status: TxStatus::Aborted,
-            }),
-
-            Command::Confirmed(e) => Some(PendingWithdrawalStatus {
-                identifier: e.report,
-                status: TxStatus::Confirmed,
-            }),
-            Command::Idle => None,
-        }
-    }
-}
-
-impl From<Command<TxInProgress>> for Option<PendingTxStatus<ExtraErgoData, BoxId>> {
-    fn from(value: Command<TxInProgress>) -> Self {
-        let status = match value {
-            Command::ResubmitTx(_) | Command::Wait(_, _) => Some(TxStatus::WaitingForConfirmation),
-            Command::Abort(_) => Some(TxStatus::Aborted),
-            Command::Confirmed(_) => Some(TxStatus::Confirmed),
-            Command::Idle => None,
+impl Command<TxInProgress> {
+    /// Status of the pending TX as reported to consensus-driver. Depth of confirmed TXs is
+    /// measured relative to `tip_height`.
+    pub fn pending_tx_status(
+        self,
+        tip_height: u32,
+        threshold: AcknowledgementThreshold,
+    ) -> Option<PendingTxStatus<ExtraErgoData, BoxId>> {
+        let (status, tx) = match self {
+            Command::ResubmitTx(tx) | Command::Wait(_, tx) => (TxStatus::WaitingForConfirmation, tx),
+            Command::Abort(tx) => (TxStatus::Aborted, tx),
+            Command::Confirmed(Some(height), tx) => (
+                TxStatus::Confirmed(threshold.confirmation(tip_height.saturating_sub(height))),
+                tx,
+            ),
+            // Back then inclusion alone settled a TX.
+            Command::Confirmed(None, tx) => (
+                TxStatus::Confirmed(Confirmation {
+                    depth: 0,
+                    settled: true,
+                }),
+                tx,
+            ),
+            Command::Idle => return None,
         };
-        match value {
-            Command::ResubmitTx(t) | Command::Wait(_, t) | Command::Abort(t) | Command::Confirmed(t) => {
-                match t {
-                    TxInProgress::Withdrawal(e) => {
-                        Some(PendingTxStatus::Withdrawal(PendingWithdrawalStatus {
-                            identifier: e.report,
-                            status: status.unwrap(),
-                        }))
-                    }
-                    TxInProgress::Deposit(d) => Some(PendingTxStatus::Deposit(PendingDepositStatus {
-                        identifier: d
-                            .unprocessed_deposits
-                            .into_iter()
-                            .map(InboundValue::from)
-                            .collect(),
-                        status: status.unwrap(),
-                    })),
-                }
-            }
-            Command::Idle => None,
+        match tx {
+            TxInProgress::Withdrawal(e) => Some(PendingTxStatus::Withdrawal(PendingWithdrawalStatus {
+                identifier: e.report,
+                status,
+            })),
+            TxInProgress::Deposit(d) => Some(PendingTxStatus::Deposit(PendingDepositStatus {
+                identifier: d
+                    .unprocessed_deposits
+                    .into_iter()
+                    .map(InboundValue::from)
+                    .collect(),
+                status,
+            })),
         }
     }
 }
@@ -280,30 +419,33 @@ enum Status {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
 
     use chrono::Utc;
-    use ergo_lib::{
-        chain::transaction::Input,
-        ergo_chain_types::Digest,
-        ergotree_ir::mir::avl_tree_data::{AvlTreeData, AvlTreeFlags},
-    };
+    use ergo_lib::chain::transaction::Input;
     use rand::{rngs::OsRng, RngCore};
-    use scorex_crypto_avltree::{
-        authenticated_tree_ops::AuthenticatedTreeOps, batch_avl_prover::BatchAVLProver, batch_node::AVLTree,
-    };
     use sigma_test_util::force_any_val;
-    use spectrum_chain_connector::NotarizedReport;
+    use spectrum_avl::ReportTree;
+    use spectrum_chain_connector::{
+        AcknowledgementThreshold, Confirmation, NotarizedReport, PendingTxIdentifier, PendingTxStatus,
+        PendingWithdrawalStatus, TxStatus,
+    };
     use spectrum_crypto::{digest::Blake2bDigest256, pubkey::PublicKey};
     use spectrum_handel::Threshold;
+    use spectrum_ledger::cell::{BoxDestination, NativeCoin, SValue, TermCell};
     use spectrum_ledger::interop::ReportCertificate;
+    use spectrum_ledger::transaction::TxId;
+    use spectrum_ledger::ChainId;
+    use spectrum_move::SerializedValue;
     use spectrum_sigma::{sigma_aggregation::AggregateCertificate, AggregateCommitment};
 
     use crate::{
-        rocksdb::tx_retry_scheduler::{Command, TxRetryScheduler},
-        script::{dummy_resolver, ExtraErgoData},
+        rocksdb::tx_retry_scheduler::{Command, Rejected, TxRetryScheduler},
+        script::{starting_avl_tree, ExtraErgoData},
+        tx_in_progress::WithdrawalInProgress,
     };
 
-    use super::{TxInProgress, TxRetrySchedulerRocksDB, WithdrawalInProgress};
+    use super::{TxInProgress, TxRetrySchedulerRocksDB};
 
     #[tokio::test]
     async fn test_confirmed_withdrawal() {
@@ -311,12 +453,68 @@ mod tests {
         let tx = make_dummy_withdrawal();
         let idle: Command<TxInProgress> = Command::Idle;
         assert_eq!(idle, client.next_command().await);
-        client.add(tx).await;
+        client.add(tx).await.unwrap();
         let Command::Wait(_, exp): Command<TxInProgress> = client.next_command().await else {
             panic!("Expected Command::Wait");
         };
-        client.notify_confirmed(&exp).await;
-        assert_eq!(Command::Confirmed(exp.clone()), client.next_command().await);
+        client.notify_confirmed(&exp, 100).await;
+        assert_eq!(
+            Command::Confirmed(Some(100), exp.clone()),
+            client.next_command().await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_confirmed_at_unknown_height() {
+        let mut client = rocks_db_client(10).await;
+        let tx = make_dummy_withdrawal();
+        client.add(tx.clone()).await.unwrap();
+        client.notify_confirmed(&tx, 100).await;
+        // As recorded before confirmation heights were tracked.
+        client
+            .db
+            .delete(super::key(super::CONFIRMED_HEIGHT_KEY, 0))
+            .unwrap();
+        let command = client.next_command().await;
+        assert_eq!(command, Command::Confirmed(None, tx));
+        let status = command.pending_tx_status(102, AcknowledgementThreshold { min_depth: 3 });
+        let Some(PendingTxStatus::Withdrawal(PendingWithdrawalStatus { status, .. })) = status else {
+            panic!("Expected pending withdrawal");
+        };
+        assert_eq!(
+            status,
+            TxStatus::Confirmed(Confirmation {
+                depth: 0,
+                settled: true
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rolled_back_withdrawal() {
+        let mut client = rocks_db_client(10).await;
+        let tx = make_dummy_withdrawal();
+        client.add(tx.clone()).await.unwrap();
+        client.notify_confirmed(&tx, 100).await;
+        let status = client
+            .next_command()
+            .await
+            .pending_tx_status(102, AcknowledgementThreshold { min_depth: 3 });
+        let Some(PendingTxStatus::Withdrawal(PendingWithdrawalStatus { status, .. })) = status else {
+            panic!("Expected pending withdrawal");
+        };
+        assert_eq!(
+            status,
+            TxStatus::Confirmed(Confirmation {
+                depth: 2,
+                settled: false
+            })
+        );
+        client.notify_unconfirmed(&tx).await;
+        let Command::Wait(_, exp): Command<TxInProgress> = client.next_command().await else {
+            panic!("Expected Command::Wait");
+        };
+        assert_eq!(exp, tx);
     }
 
     #[tokio::test]
@@ -325,7 +523,7 @@ mod tests {
         let tx = make_dummy_withdrawal();
         let idle: Command<TxInProgress> = Command::Idle;
         assert_eq!(idle, client.next_command().await);
-        client.add(tx.clone()).await;
+        client.add(tx.clone()).await.unwrap();
         client.notify_failed(&tx).await;
         let Command::Wait(_, exp): Command<TxInProgress> = client.next_command().await else {
             panic!("Expected Command::Wait");
@@ -338,11 +536,22 @@ mod tests {
         assert_eq!(Command::Abort(exp.clone()), client.next_command().await);
     }
 
+    #[tokio::test]
+    async fn test_superseded_withdrawal() {
+        let mut client = rocks_db_client(10).await;
+        let tx = make_dummy_withdrawal();
+        client.add(tx.clone()).await.unwrap();
+        client.notify_superseded(&tx).await;
+        assert_eq!(Command::Abort(tx.clone()), client.next_command().await);
+        // Superseded TX doesn't block admission of the one replacing it.
+        assert_eq!(client.check(&tx).await, Ok(()));
+    }
+
     #[tokio::test]
     async fn test_delays() {
         let mut client = rocks_db_client(1).await;
         let tx = make_dummy_withdrawal();
-        client.add(tx.clone()).await;
+        client.add(tx.clone()).await.unwrap();
         let Command::Wait(d, _): Command<TxInProgress> = client.next_command().await else {
             panic!("Expected Command::Wait");
         };
@@ -354,22 +563,117 @@ mod tests {
         assert_eq!(exp, tx);
     }
 
-    fn make_dummy_withdrawal() -> TxInProgress {
-        let empty_tree = AVLTree::new(dummy_resolver, 8, Some(32));
-        let mut prover = BatchAVLProver::new(empty_tree.clone(), true);
-        let initial_digest = prover.digest().unwrap().to_vec();
-
-        let starting_avl_tree = AvlTreeData {
-            digest: Digest::<33>::try_from(initial_digest).unwrap(),
-            tree_flags: AvlTreeFlags::new(true, false, false),
-            key_length: 8,
-            value_length_opt: Some(Box::new(32)),
+    #[tokio::test]
+    async fn test_pending_window() {
+        let mut client = rocks_db_client(10).await;
+        let tx_0 = make_dummy_withdrawal();
+        let tx_1 = make_dummy_withdrawal();
+        client.add(tx_0.clone()).await.unwrap();
+        client.add(tx_1.clone()).await.unwrap();
+        assert_eq!(
+            client.add(make_dummy_withdrawal()).await,
+            Err(Rejected::WindowFull)
+        );
+
+        // Commands are reported in order of submission.
+        let commands: Vec<Command<TxInProgress>> = client.all_commands().await;
+        let txs: Vec<_> = commands
+            .into_iter()
+            .map(|c| match c {
+                Command::Wait(_, tx) => tx,
+                _ => panic!("Expected Command::Wait"),
+            })
+            .collect();
+        assert_eq!(txs, vec![tx_0.clone(), tx_1.clone()]);
+
+        // Confirmation of the second TX doesn't affect the first one.
+        client.notify_confirmed(&tx_1, 100).await;
+        let Command::Wait(_, exp): Command<TxInProgress> = client.next_command().await else {
+            panic!("Expected Command::Wait");
         };
-        let proof = prover.generate_proof().to_vec();
+        assert_eq!(exp, tx_0);
+        let TxInProgress::Withdrawal(ref w) = tx_1 else {
+            unreachable!()
+        };
+        let id = PendingTxIdentifier::Withdrawal(Box::new(w.report.clone()));
+        TxRetryScheduler::<TxInProgress, _>::clear_confirmed(&mut client, &id).await;
+        let commands: Vec<Command<TxInProgress>> = client.all_commands().await;
+        assert_eq!(commands.len(), 1);
+        client.add(make_dummy_withdrawal()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_conflicting_txs() {
+        let mut client = rocks_db_client(10).await;
+        let tx = make_dummy_withdrawal();
+        client.add(tx.clone()).await.unwrap();
+        let TxInProgress::Withdrawal(mut conflicting) = make_dummy_withdrawal() else {
+            unreachable!()
+        };
+        let TxInProgress::Withdrawal(ref w) = tx else {
+            unreachable!()
+        };
+        conflicting.vault_utxo = w.vault_utxo.clone();
+        let conflicting = TxInProgress::Withdrawal(conflicting);
+        assert_eq!(client.check(&conflicting).await, Err(Rejected::Conflict));
+        assert_eq!(client.add(conflicting).await, Err(Rejected::Conflict));
+    }
+
+    #[tokio::test]
+    async fn test_add_is_idempotent() {
+        let mut client = rocks_db_client(10).await;
+        let tx = make_dummy_withdrawal();
+        client.add(tx.clone()).await.unwrap();
+        client.add(tx.clone()).await.unwrap();
+        let commands: Vec<Command<TxInProgress>> = client.all_commands().await;
+        assert_eq!(commands.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_term_cells_exported_once() {
+        let mut client = rocks_db_client(10).await;
+        let cells = vec![dummy_term_cell(), dummy_term_cell()];
+        let tx = make_dummy_withdrawal_of(cells.clone());
+        client.add(tx.clone()).await.unwrap();
+        // Report notarized in another round overlapping with the pending one.
+        let overlapping = make_dummy_withdrawal_of(vec![cells[1].clone(), dummy_term_cell()]);
+        assert_eq!(client.check(&overlapping).await, Err(Rejected::Conflict));
+        client.check(&make_dummy_withdrawal()).await.unwrap();
+        // Cells of the aborted report may be exported again.
+        for _ in 0..3 {
+            client.notify_failed(&tx).await;
+        }
+        assert_eq!(Command::Abort(tx), client.next_command().await);
+        client.add(overlapping).await.unwrap();
+    }
+
+    fn dummy_term_cell() -> TermCell {
+        TermCell {
+            value: SValue {
+                native: NativeCoin::from(1_000_000),
+                assets: HashMap::new(),
+            },
+            tx_id: TxId::from(Blake2bDigest256::random()),
+            index: 0,
+            dst: BoxDestination {
+                target: ChainId::from(0),
+                address: SerializedValue::from(vec![0; 32]),
+                inputs: None,
+                constraints: None,
+            },
+        }
+    }
+
+    fn make_dummy_withdrawal() -> TxInProgress {
+        make_dummy_withdrawal_of(vec![])
+    }
+
+    fn make_dummy_withdrawal_of(value_to_withdraw: Vec<TermCell>) -> TxInProgress {
+        let insertion_proof = ReportTree::new().prove();
 
         let additional_chain_data = ExtraErgoData {
-            starting_avl_tree,
-            proof,
+            starting_avl_tree: starting_avl_tree(&insertion_proof),
+            proof: insertion_proof.proof,
             max_miner_fee: 1000000,
             threshold: Threshold { num: 4, denom: 4 },
             vault_utxos: vec![],
@@ -388,9 +692,10 @@ mod tests {
 
         let report = NotarizedReport {
             certificate: ReportCertificate::SchnorrK256(aggr_certificate),
-            value_to_withdraw: vec![],
+            value_to_withdraw,
             authenticated_digest: vec![],
             additional_chain_data,
+            inclusion_proof: None,
         };
 
         TxInProgress::Withdrawal(WithdrawalInProgress {
@@ -398,11 +703,12 @@ mod tests {
             vault_utxo_signed_input: force_any_val::<Input>(),
             vault_utxo: force_any_val(),
             timestamp: Utc::now().timestamp(),
+            committee_epoch: 0,
         })
     }
 
     async fn rocks_db_client(retry_delay_duration: i64) -> TxRetrySchedulerRocksDB {
         let rnd = rand::thread_rng().next_u32();
-        TxRetrySchedulerRocksDB::new(&format!("./tmp/{}", rnd), retry_delay_duration, 3).await
+        TxRetrySchedulerRocksDB::new(&format!("./tmp/{}", rnd), retry_delay_duration, 3, 2).await
     }
 }
@@ -575,6 +575,7 @@ pub mod tests {
             },
             max_tx_size: Kilobytes(5.0),
             estimated_number_of_byzantine_nodes: 10,
+            fee_policy: None,
         };
         let term_cells = constraints.term_cells.clone();
         let ErgoNotarizationBoundsWithBoxes {
@@ -607,6 +608,7 @@ pub mod tests {
             },
             max_tx_size: Kilobytes(4.06),
             estimated_number_of_byzantine_nodes: 20,
+            fee_policy: None,
         };
         let term_cells = constraints.term_cells.clone();
         let ErgoNotarizationBoundsWithBoxes {
@@ -656,6 +658,7 @@ pub mod tests {
             },
             max_tx_size: Kilobytes(max_tx_size), // So even the first vault UTXO will be over limit
             estimated_number_of_byzantine_nodes: estimated_number_of_byzantine_nodes as u32,
+            fee_policy: None,
         };
         let term_cells = constraints.term_cells.clone();
         let ErgoNotarizationBoundsWithBoxes {
@@ -722,6 +725,7 @@ pub mod tests {
             },
             max_tx_size: Kilobytes(max_tx_size), // So even the first vault UTXO will be over limit
             estimated_number_of_byzantine_nodes: estimated_number_of_byzantine_nodes as u32,
+            fee_policy: None,
         };
         let term_cells = constraints.term_cells.clone();
         let ErgoNotarizationBoundsWithBoxes {
@@ -768,6 +772,7 @@ pub mod tests {
             target: ChainId::from(0),
             address: SerializedValue::from(address_bytes),
             inputs: None,
+            constraints: None,
         };
         let mut assets = HashMap::new();
         let asset_map: HashMap<AssetId, CustomAsset> = tokens
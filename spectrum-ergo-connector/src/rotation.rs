@@ -0,0 +1,570 @@
+use std::collections::HashMap;
+
+use ergo_lib::{
+    chain::{
+        ergo_state_context::ErgoStateContext,
+        transaction::{unsigned::UnsignedTransaction, DataInput, Transaction, TxIoVec, UnsignedInput},
+    },
+    ergo_chain_types::EcPoint,
+    ergotree_ir::{
+        chain::{
+            ergo_box::{
+                box_value::BoxValue, BoxId, ErgoBox, ErgoBoxCandidate, NonMandatoryRegisterId,
+                NonMandatoryRegisters,
+            },
+            token::TokenId,
+        },
+        ergo_tree::ErgoTree,
+        mir::{
+            constant::{Constant, Literal},
+            value::CollKind,
+        },
+        serialization::SigmaSerializable,
+        types::stype::SType,
+    },
+    wallet::{miner_fee::MINERS_FEE_ADDRESS, tx_context::TransactionContext, Wallet},
+};
+use spectrum_chain_connector::{
+    AcknowledgementThreshold, CommitteeHandover, PendingCommitteeRotationStatus, TxStatus,
+};
+use spectrum_crypto::digest::Blake2b256;
+use spectrum_crypto::pubkey::PublicKey;
+use spectrum_handel::Threshold;
+use spectrum_ledger::interop::ReportCertificate;
+use spectrum_offchain::event_sink::handlers::types::IntoBoxCandidate;
+use spectrum_sigma::sigma_aggregation::AggregateCertificate;
+
+use crate::committee::{CommitteeData, FirstCommitteeBox, SubsequentCommitteeBox, VaultParameters};
+use crate::migration::{certificate_context_extension, MIGRATION_THRESHOLD};
+use crate::script::committee_hash;
+
+/// Share of the outgoing committee which has to certify the handover. Handing the vault over
+/// is as sensitive as upgrading its contract.
+pub const ROTATION_THRESHOLD: Threshold = MIGRATION_THRESHOLD;
+
+/// The first committee box holds the keys along with other data necessary to verify signatures.
+pub const NUM_COMMITTEE_KEYS_IN_FIRST_BOX: usize = 115;
+/// Max number of keys fitting into any subsequent committee box.
+pub const MAX_NUM_COMMITTEE_KEYS_PER_BOX: usize = 118;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RotationError {
+    /// Another rotation is already in progress.
+    RotationInProgress,
+    /// TXs spending the vault are pending, the rotation would conflict with them.
+    PendingTxs,
+    NoVaultUtxo,
+    EmptyCommittee,
+    /// Handover isn't addressed to the committee of the next epoch.
+    EpochMismatch {
+        expected: i32,
+        got: u64,
+    },
+    AggregateKeyMismatch,
+    /// Shares of the reshared key aren't consistent with the key or the incoming committee.
+    ResharedKeyMismatch,
+    /// Committee certificate doesn't certify the handover digest.
+    CertificateMismatch,
+    TxRejected(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RotationState {
+    Submitted,
+    /// Boxes of the incoming committee are created, but the vault is still guarded by the
+    /// outgoing committee, so exports certified by it can settle until the grace period is over.
+    GracePeriod,
+    /// The handover TX is submitted once the grace period is over.
+    HandingOver,
+    /// Vault is guarded by the incoming committee since the given height.
+    Settled {
+        height: u32,
+    },
+    /// The vault UTXO was spent by some other TX, so the rotation can't be applied anymore.
+    Aborted,
+}
+
+/// Handover of the vault to the incoming committee along with its progress.
+///
+/// The vault refers to committee boxes by their ids, which aren't known until the boxes are
+/// created, so the rotation takes two chained TXs: the first one creates boxes of the incoming
+/// committee, the second one points the vault to them. The handover TX may be held back for a
+/// grace period, in which case it's rebuilt if the vault is spent in the meantime.
+pub struct PendingRotation {
+    pub handover: CommitteeHandover,
+    pub committee_tx: Transaction,
+    pub handover_tx: Transaction,
+    pub state: RotationState,
+    /// Committee replaced by the incoming one, kept until the rotation is acknowledged in case
+    /// the handover TX is rolled back.
+    pub previous_committee: Option<CommitteeData>,
+    /// Height the boxes of the incoming committee were created at.
+    pub committee_boxes_height: Option<u32>,
+}
+
+impl PendingRotation {
+    /// Whether rotation TXs spending the vault are pending.
+    pub fn is_submitted(&self) -> bool {
+        matches!(self.state, RotationState::Submitted | RotationState::HandingOver)
+    }
+
+    /// Whether the handover TX is due, i.e. `grace_period` blocks passed since the boxes of the
+    /// incoming committee were created.
+    pub fn is_handover_due(&self, current_height: u32, grace_period: u32) -> bool {
+        self.state == RotationState::GracePeriod
+            && self
+                .committee_boxes_height
+                .is_some_and(|height| current_height >= height + grace_period)
+    }
+
+    pub fn is_rotation_tx(&self, tx: &Transaction) -> bool {
+        tx.id() == self.committee_tx.id() || tx.id() == self.handover_tx.id()
+    }
+
+    /// Whether the TX spends the vault UTXO the rotation was going to spend.
+    pub fn conflicts_with(&self, tx: &Transaction) -> bool {
+        !self.is_rotation_tx(tx) && tx.inputs.first().box_id == self.committee_tx.inputs.first().box_id
+    }
+
+    /// Whether the TX spends the vault UTXO the handover TX was going to spend, e.g. an export
+    /// of the outgoing committee settled in the meantime.
+    pub fn supersedes_handover(&self, tx: &Transaction) -> bool {
+        !self.is_rotation_tx(tx) && tx.inputs.first().box_id == self.handover_tx.inputs.first().box_id
+    }
+
+    /// Boxes of the incoming committee in the order they are passed as data inputs.
+    pub fn new_committee_boxes(&self) -> Vec<ErgoBox> {
+        let num_outputs = self.committee_tx.outputs.len();
+        self.committee_tx
+            .outputs
+            .iter()
+            .skip(1)
+            .take(num_outputs - 2)
+            .cloned()
+            .collect()
+    }
+
+    pub fn status(
+        &self,
+        current_height: u32,
+        ack_threshold: AcknowledgementThreshold,
+    ) -> PendingCommitteeRotationStatus {
+        let status = match self.state {
+            RotationState::Submitted | RotationState::GracePeriod | RotationState::HandingOver => {
+                TxStatus::WaitingForConfirmation
+            }
+            RotationState::Settled { height } => {
+                TxStatus::Confirmed(ack_threshold.confirmation(current_height.saturating_sub(height)))
+            }
+            RotationState::Aborted => TxStatus::Aborted,
+        };
+        PendingCommitteeRotationStatus {
+            identifier: self.handover.clone(),
+            status,
+        }
+    }
+}
+
+/// Check that the handover is certified by the outgoing committee and addressed to the
+/// committee of the epoch following `current_epoch`.
+pub fn verify_handover(
+    handover: &CommitteeHandover,
+    current_epoch: i32,
+) -> Result<AggregateCertificate<Blake2b256>, RotationError> {
+    if handover.new_committee.is_empty() {
+        return Err(RotationError::EmptyCommittee);
+    }
+    if u64::from(handover.epoch) != current_epoch as u64 + 1 {
+        return Err(RotationError::EpochMismatch {
+            expected: current_epoch + 1,
+            got: u64::from(handover.epoch),
+        });
+    }
+    if !handover.aggregate_key_matches() {
+        return Err(RotationError::AggregateKeyMismatch);
+    }
+    if !handover.reshared_key_matches() {
+        return Err(RotationError::ResharedKeyMismatch);
+    }
+    let ReportCertificate::SchnorrK256(certificate) = handover.certificate.clone();
+    if certificate.message_digest != handover.digest() {
+        return Err(RotationError::CertificateMismatch);
+    }
+    Ok(certificate)
+}
+
+fn to_ec_point(pk: &PublicKey) -> EcPoint {
+    EcPoint::from(k256::PublicKey::from(*pk).to_projective())
+}
+
+/// Keys of the incoming committee in the form they are stored in committee boxes.
+pub fn committee_keys(handover: &CommitteeHandover) -> Vec<EcPoint> {
+    handover.new_committee.iter().map(to_ec_point).collect()
+}
+
+/// Committee boxes holding keys of the incoming committee. Parameters of the vault are carried
+/// over from the outgoing committee, except for the epoch and the number of boxes.
+pub fn committee_boxes(
+    handover: &CommitteeHandover,
+    vault_parameters: VaultParameters,
+    guarding_script: ErgoTree,
+    box_value: BoxValue,
+    current_height: u32,
+) -> Vec<ErgoBoxCandidate> {
+    let keys = committee_keys(handover);
+    let (first_keys, rest) = keys.split_at(NUM_COMMITTEE_KEYS_IN_FIRST_BOX.min(keys.len()));
+    let chunks = rest.chunks(MAX_NUM_COMMITTEE_KEYS_PER_BOX);
+    let first_box = FirstCommitteeBox {
+        public_keys: first_keys.to_vec(),
+        vault_parameters: VaultParameters {
+            num_committee_boxes: 1 + chunks.len() as i32,
+            current_epoch: u64::from(handover.epoch) as i32,
+            ..vault_parameters
+        },
+        committee_hash: committee_hash(&handover.new_committee),
+        guarding_script: guarding_script.clone(),
+        box_value,
+    };
+    let mut boxes = vec![first_box.into_candidate(current_height)];
+    boxes.extend(chunks.enumerate().map(|(ix, chunk)| {
+        SubsequentCommitteeBox {
+            public_keys: chunk.to_vec(),
+            index: ix as u32 + 1,
+            guarding_script: guarding_script.clone(),
+            box_value,
+        }
+        .into_candidate(current_height)
+    }));
+    boxes
+}
+
+/// Whether the vault is guarded by the committee, i.e. refers to its boxes.
+pub fn guarded_by(vault_utxo: &ErgoBox, committee: &CommitteeData) -> bool {
+    let box_ids: Vec<_> = committee.boxes().iter().map(|bx| bx.box_id()).collect();
+    matches!(
+        vault_utxo.get_register(NonMandatoryRegisterId::R4.into()),
+        Ok(Some(r4)) if r4 == committee_box_ids_constant(&box_ids)
+    )
+}
+
+/// Value of R4 of the vault box: ids of the committee boxes.
+fn committee_box_ids_constant(box_ids: &[BoxId]) -> Constant {
+    let items = box_ids
+        .iter()
+        .map(|id| Literal::from(id.sigma_serialize_bytes().unwrap()))
+        .collect();
+    Constant {
+        tpe: SType::SColl(Box::new(SType::SColl(Box::new(SType::SByte)))),
+        v: Literal::Coll(CollKind::WrappedColl {
+            elem_tpe: SType::SColl(Box::new(SType::SByte)),
+            items,
+        }),
+    }
+}
+
+/// Build and sign the TX spending `vault_utxo` with the certificate of the outgoing committee.
+fn sign_vault_tx(
+    certificate: &AggregateCertificate<Blake2b256>,
+    committee: &CommitteeData,
+    vault_utxo: ErgoBox,
+    vault_utxo_token_id: TokenId,
+    outputs: Vec<ErgoBoxCandidate>,
+    change_for_miner: BoxValue,
+    ergo_state_context: &ErgoStateContext,
+    wallet: &Wallet,
+) -> Result<Transaction, RotationError> {
+    let context_extension = certificate_context_extension(
+        certificate,
+        committee.committee_size(),
+        ROTATION_THRESHOLD,
+        vault_utxo_token_id,
+        change_for_miner,
+    );
+    let unsigned_input = UnsignedInput::new(vault_utxo.box_id(), context_extension);
+    let data_boxes = committee.boxes();
+    let data_inputs: Vec<_> = data_boxes
+        .iter()
+        .map(|d| DataInput { box_id: d.box_id() })
+        .collect();
+    let unsigned_tx = UnsignedTransaction::new(
+        TxIoVec::from_vec(vec![unsigned_input]).unwrap(),
+        Some(TxIoVec::from_vec(data_inputs).unwrap()),
+        TxIoVec::from_vec(outputs).unwrap(),
+    )
+    .unwrap();
+    let tx_context = TransactionContext::new(unsigned_tx, vec![vault_utxo], data_boxes).unwrap();
+    wallet
+        .sign_transaction(tx_context, ergo_state_context, None)
+        .map_err(|e| RotationError::TxRejected(e.to_string()))
+}
+
+/// Build the pair of TXs handing the vault over to the incoming committee, see [`PendingRotation`].
+/// Both TXs are certified by the outgoing committee and funded by the vault.
+pub fn build_rotation_txs(
+    handover: &CommitteeHandover,
+    certificate: &AggregateCertificate<Blake2b256>,
+    committee: &CommitteeData,
+    vault_utxo: ErgoBox,
+    vault_utxo_token_id: TokenId,
+    ergo_state_context: &ErgoStateContext,
+    wallet: &Wallet,
+    max_miner_fee: i64,
+    current_height: u32,
+) -> Result<(Transaction, Transaction), RotationError> {
+    let change_for_miner = BoxValue::try_from(max_miner_fee).unwrap();
+    let miner_output = ErgoBoxCandidate {
+        value: change_for_miner,
+        ergo_tree: MINERS_FEE_ADDRESS.script().unwrap(),
+        tokens: None,
+        additional_registers: NonMandatoryRegisters::empty(),
+        creation_height: current_height,
+    };
+    let first_box = &committee.first_box.1;
+    let new_committee_boxes = committee_boxes(
+        handover,
+        first_box.vault_parameters,
+        first_box.guarding_script.clone(),
+        first_box.box_value,
+        current_height,
+    );
+    let committee_boxes_value: i64 = new_committee_boxes.iter().map(|bx| bx.value.as_i64()).sum();
+    let funded_vault_box = ErgoBoxCandidate {
+        value: BoxValue::try_from(vault_utxo.value.as_i64() - committee_boxes_value - max_miner_fee)
+            .map_err(|e| RotationError::TxRejected(e.to_string()))?,
+        ergo_tree: vault_utxo.ergo_tree.clone(),
+        tokens: vault_utxo.tokens.clone(),
+        additional_registers: vault_utxo.additional_registers.clone(),
+        creation_height: current_height,
+    };
+    let mut outputs = vec![funded_vault_box];
+    outputs.extend(new_committee_boxes);
+    outputs.push(miner_output);
+    let committee_tx = sign_vault_tx(
+        certificate,
+        committee,
+        vault_utxo,
+        vault_utxo_token_id,
+        outputs,
+        change_for_miner,
+        ergo_state_context,
+        wallet,
+    )?;
+
+    let num_outputs = committee_tx.outputs.len();
+    let new_committee_boxes: Vec<_> = committee_tx
+        .outputs
+        .iter()
+        .skip(1)
+        .take(num_outputs - 2)
+        .cloned()
+        .collect();
+    let handover_tx = build_handover_tx(
+        certificate,
+        committee,
+        &new_committee_boxes,
+        committee_tx.outputs.first().clone(),
+        vault_utxo_token_id,
+        ergo_state_context,
+        wallet,
+        max_miner_fee,
+        current_height,
+    )?;
+    Ok((committee_tx, handover_tx))
+}
+
+/// Build the TX pointing the vault to the boxes of the incoming committee.
+pub fn build_handover_tx(
+    certificate: &AggregateCertificate<Blake2b256>,
+    committee: &CommitteeData,
+    new_committee_boxes: &[ErgoBox],
+    vault_utxo: ErgoBox,
+    vault_utxo_token_id: TokenId,
+    ergo_state_context: &ErgoStateContext,
+    wallet: &Wallet,
+    max_miner_fee: i64,
+    current_height: u32,
+) -> Result<Transaction, RotationError> {
+    let change_for_miner = BoxValue::try_from(max_miner_fee).unwrap();
+    let miner_output = ErgoBoxCandidate {
+        value: change_for_miner,
+        ergo_tree: MINERS_FEE_ADDRESS.script().unwrap(),
+        tokens: None,
+        additional_registers: NonMandatoryRegisters::empty(),
+        creation_height: current_height,
+    };
+    let new_committee_box_ids: Vec<_> = new_committee_boxes.iter().map(|bx| bx.box_id()).collect();
+    let registers = NonMandatoryRegisters::new(HashMap::from([(
+        NonMandatoryRegisterId::R4,
+        committee_box_ids_constant(&new_committee_box_ids),
+    )]))
+    .unwrap();
+    let handed_over_vault_box = ErgoBoxCandidate {
+        value: BoxValue::try_from(vault_utxo.value.as_i64() - max_miner_fee)
+            .map_err(|e| RotationError::TxRejected(e.to_string()))?,
+        ergo_tree: vault_utxo.ergo_tree.clone(),
+        tokens: vault_utxo.tokens.clone(),
+        additional_registers: registers,
+        creation_height: current_height,
+    };
+    sign_vault_tx(
+        certificate,
+        committee,
+        vault_utxo,
+        vault_utxo_token_id,
+        vec![handed_over_vault_box, miner_output],
+        change_for_miner,
+        ergo_state_context,
+        wallet,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use ergo_lib::chain::transaction::{TxId, TxIoVec};
+    use ergo_lib::ergotree_ir::chain::ergo_box::box_value::BoxValue;
+    use ergo_lib::ergotree_ir::chain::ergo_box::{
+        ErgoBox, ErgoBoxCandidate, NonMandatoryRegisterId, NonMandatoryRegisters,
+    };
+    use k256::elliptic_curve::rand_core::OsRng;
+    use k256::{ProjectivePoint, Scalar, SecretKey};
+    use spectrum_chain_connector::resharing::ResharedKey;
+    use spectrum_chain_connector::CommitteeHandover;
+    use spectrum_crypto::digest::{Blake2b256, Blake2bDigest256};
+    use spectrum_crypto::pubkey::PublicKey;
+    use spectrum_ledger::interop::ReportCertificate;
+    use spectrum_ledger::EpochNo;
+    use spectrum_sigma::crypto::{aggregate_pk, individual_input};
+    use spectrum_sigma::sigma_aggregation::AggregateCertificate;
+
+    use crate::committee::{CommitteeData, VaultParameters};
+    use crate::rotation::{
+        committee_box_ids_constant, committee_boxes, committee_keys, guarded_by, verify_handover,
+        RotationError,
+    };
+    use crate::script::VAULT_CONTRACT;
+
+    fn certificate(message_digest: Blake2bDigest256) -> ReportCertificate {
+        ReportCertificate::SchnorrK256(AggregateCertificate {
+            message_digest,
+            aggregate_commitment: ProjectivePoint::GENERATOR.into(),
+            aggregate_response: Scalar::ONE,
+            exclusion_set: vec![],
+        })
+    }
+
+    fn handover(committee_size: usize, epoch: u64) -> CommitteeHandover {
+        let new_committee: Vec<_> = (0..committee_size)
+            .map(|_| PublicKey::from(SecretKey::random(&mut OsRng)))
+            .collect();
+        let individual_inputs = new_committee
+            .iter()
+            .map(|pk| individual_input::<Blake2b256>(new_committee.clone(), *pk))
+            .collect();
+        let mut handover = CommitteeHandover {
+            new_aggregate_key: aggregate_pk(new_committee.clone(), individual_inputs),
+            new_committee,
+            epoch: EpochNo::from(epoch),
+            reshared_key: None,
+            certificate: certificate(Blake2bDigest256::random()),
+        };
+        handover.certificate = certificate(handover.digest());
+        handover
+    }
+
+    const VAULT_PARAMETERS: VaultParameters = VaultParameters {
+        num_committee_boxes: 1,
+        current_epoch: 2,
+        epoch_length: 100,
+        vault_starting_height: 1000,
+    };
+
+    fn committee(handover: &CommitteeHandover) -> CommitteeData {
+        let boxes = committee_boxes(
+            handover,
+            VAULT_PARAMETERS,
+            VAULT_CONTRACT.clone(),
+            BoxValue::try_from(1000000_u64).unwrap(),
+            1300,
+        )
+        .iter()
+        .enumerate()
+        .map(|(ix, candidate)| ErgoBox::from_box_candidate(candidate, TxId::zero(), ix as u16).unwrap())
+        .collect();
+        CommitteeData::try_from_boxes(
+            VAULT_CONTRACT.clone(),
+            &committee_keys(handover),
+            TxIoVec::from_vec(boxes).unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn handover_addressed_to_next_committee() {
+        let valid = handover(4, 3);
+        assert!(verify_handover(&valid, 2).is_ok());
+        assert_eq!(
+            verify_handover(&valid, 3).err(),
+            Some(RotationError::EpochMismatch { expected: 4, got: 3 })
+        );
+        let mut forged_key = valid.clone();
+        forged_key.new_aggregate_key = handover(4, 3).new_aggregate_key;
+        assert_eq!(
+            verify_handover(&forged_key, 2).err(),
+            Some(RotationError::AggregateKeyMismatch)
+        );
+        let mut inconsistent_shares = valid.clone();
+        inconsistent_shares.reshared_key = Some(ResharedKey {
+            group_public_key: valid.new_aggregate_key,
+            threshold: 3,
+            verification_shares: valid.new_committee.clone(),
+        });
+        inconsistent_shares.certificate = certificate(inconsistent_shares.digest());
+        assert_eq!(
+            verify_handover(&inconsistent_shares, 2).err(),
+            Some(RotationError::ResharedKeyMismatch)
+        );
+        let mut uncertified = valid;
+        uncertified.certificate = certificate(Blake2bDigest256::random());
+        assert_eq!(
+            verify_handover(&uncertified, 2).err(),
+            Some(RotationError::CertificateMismatch)
+        );
+    }
+
+    #[test]
+    fn vault_guarded_by_committee_it_refers_to() {
+        let outgoing = committee(&handover(4, 2));
+        let incoming = committee(&handover(4, 3));
+        let box_ids: Vec<_> = outgoing.boxes().iter().map(|bx| bx.box_id()).collect();
+        let vault = ErgoBoxCandidate {
+            value: BoxValue::try_from(1000000_u64).unwrap(),
+            ergo_tree: VAULT_CONTRACT.clone(),
+            tokens: None,
+            additional_registers: NonMandatoryRegisters::new(HashMap::from([(
+                NonMandatoryRegisterId::R4,
+                committee_box_ids_constant(&box_ids),
+            )]))
+            .unwrap(),
+            creation_height: 1300,
+        };
+        let vault = ErgoBox::from_box_candidate(&vault, TxId::zero(), 0).unwrap();
+        assert!(guarded_by(&vault, &outgoing));
+        assert!(!guarded_by(&vault, &incoming));
+    }
+
+    #[test]
+    fn committee_split_across_boxes() {
+        let boxes = committee_boxes(
+            &handover(250, 3),
+            VAULT_PARAMETERS,
+            VAULT_CONTRACT.clone(),
+            BoxValue::try_from(1000000_u64).unwrap(),
+            1300,
+        );
+        // 115 keys in the first box, 118 in the second one and the remaining 17 in the last one.
+        assert_eq!(boxes.len(), 3);
+        assert!(boxes.iter().all(|bx| bx.ergo_tree == *VAULT_CONTRACT));
+    }
+}
@@ -1,7 +1,6 @@
-use std::{collections::HashMap, hash::Hash, iter::repeat};
+use std::{collections::HashMap, hash::Hash};
 
 use blake2::Blake2b;
-use bytes::Bytes;
 use derive_more::From;
 use elliptic_curve::{
     consts::U32,
@@ -25,7 +24,7 @@ use ergo_lib::{
             constant::{Constant, Literal},
             value::CollKind,
         },
-        serialization::{SigmaParsingError, SigmaSerializable},
+        serialization::{SigmaParsingError, SigmaSerializable, SigmaSerializationError},
         sigma_protocol::sigma_boolean::ProveDlog,
         types::{
             stuple::{STuple, TupleItems},
@@ -37,23 +36,21 @@ use k256::{FieldElement, NonZeroScalar, ProjectivePoint, Scalar, SecretKey, U256
 use lazy_static::lazy_static;
 use num_bigint::{BigUint, Sign, ToBigUint};
 use rand::{rngs::OsRng, Rng};
-use scorex_crypto_avltree::{
-    authenticated_tree_ops::AuthenticatedTreeOps,
-    batch_avl_prover::BatchAVLProver,
-    batch_node::{AVLTree, Node, NodeHeader},
-    operation::{KeyValue, Operation},
-};
+use scorex_crypto_avltree::operation::KeyValue;
 use serde::{Deserialize, Serialize};
 use sha2::Digest as OtherDigest;
 use sha2::Sha256;
-use spectrum_chain_connector::{InboundValue, NotarizedReport, ProtoTermCell};
+use spectrum_avl::{prove_insertions, report_entries, InsertionProof, KEY_LENGTH, VALUE_LENGTH};
+use spectrum_chain_connector::{InboundValue, Kilobytes, NotarizedReport, ProtoTermCell};
 use spectrum_crypto::{
     digest::{blake2b256_hash, Blake2bDigest256},
     pubkey::PublicKey,
 };
 use spectrum_handel::Threshold;
 use spectrum_ledger::{
-    cell::{AssetId, BoxDestination, CustomAsset, NativeCoin, Owner, PolicyId, SValue, TermCell},
+    cell::{
+        AssetId, BoxDestination, CustomAsset, NativeCoin, Owner, PolicyId, SValue, TermCell, TermConstraints,
+    },
     interop::ReportCertificate,
     transaction::TxId,
     ChainId, ERGO_CHAIN_ID,
@@ -68,6 +65,10 @@ use spectrum_sigma::{
     AggregateCommitment, Commitment, Signature,
 };
 
+use crate::timelock::TimelockedWithdrawal;
+
+pub use spectrum_sigma::crypto::committee_hash;
+
 const VAULT_CONTRACT_SCRIPT_BYTES: &str = "CNboD6YCg7rn6nX2cYWkCoiHMLu5NU73DCwnxzKcoJHam4AYuvXxfYY4xDa6eUujvXTe4NPkeHj1kXV4s6JrXArDobFPkXXgoegmqcRh6MeyJh3zxBDcjWehiqkHBdRBtoK6o8kxMMDKHyqQfanrYmxNLjQecpAHvkhPQrX5Khy8NuXXciYtb8e3DGM4siX4L8STZTt96anfA6EKiYCKMCo6uWzKuMJVvrrLyAEoxh9RVznnjuwt4p6tNqMW1t8BqBzAZ3Jtjx6fyDu2gegRQseoVUk5TPZBhEVWJsan8aLDoWMieSkv37SMQfhT1tAX7tTC1jAVvtNpJLCCgxy31c4qq9GeqFr8Y1ej6VP6ZAWouBfU24KzrZAPLgTYnDpQBc4dmWmYztSxi5WTBf9uBoKrRDz3pFJgk9o6cydjcR7hww8Dv1mTkhq3QMh7hC8tMwznGAbhSCTP8qAMzVcHnm9WTxfrZnzRdFh4DY7EA42ahZ8AvGfjf6gVdAzTBd1wijdoCNDn26H1QvQjHuMJxujPVNiVZUMpiR6SubU6heXLgCy7e1AYs4rzPFHKoZV7oqy1KgfVAKgx1bwBdn3fQu86cKi7XZbHadYKmtsbrgiF7cvV2YY3nswr8dBiStPNsyviJUxTGXezdv4phbTq86vrH92Utv62LCw3wePnYZD1sq5shbZVWS77uuryfZo9rz88VpxGvW1gUDKftRNTJjRDnKDN88H1dhttb9wD4iptMc6pusL597WcADQxguhRVch87sNuBqgyWXAajub5XprShNgVHwD4qpje9xnEhVpKb3XS8tpcBsNzrx92tuvuRevLwDpVkWQrcN1arooBaqnsDsnsbfk33i7hhgXNkx7GWZk76uLqbZnihJ9r23vxtqwdtAAnEno8VmYKjPNc9Gn6WiTXraq9ZCfe1VPapq5JKu2wC2KDnT4AeUDA2FPb5ULWTP2dpiF8YBms1T7DM1yRnFLthDJgjThLHy2x8deLoFPz7p9Hx1hZqY7FkAwFhGVJDJSjNrqsMJiBbiJUPSYTYVYpZHBkeKqX75Vfj966LLxQ9XwQYE1VWtXyRx7Y9ifAxgxfAThABTc6RCbieibeb9P2Fiaxbeb6Nyqj3zBSiSHBLyxcH49zA7DQzRoCgGqzch1sCUALdjmG54bkGiS6hwwcY2Dz9HQoZdEuWixoDc7RnLJhQxQXucjt1giKHpZjU3FsQzCyaq6doiBYuKgXSHvjcFKe5Xs4fyDsapX5E9gmStBCsKE74vmBf2pRCMpJ1X39EPY1wYmMpc73RZYfBYzBfeydKq2BwzmdmxE6ZkaVdPiEzsSEDKL4vMRo1WKF17rjSSe79CPkT2vURTL5KYijqyFGnxKFnUbc5n3qE25unDvqWQgwWSyC34iss2RPdwdsRkZLP1Vn6syk6k4P2jYP9hm9x6PLx1rDKtJWwRrRDJNfkFSxapdPGukMXU6CSkwkre8Qf1xPsRviDFDKZKvaTKoU7smpRs9K9RjYKbdiGgfAs4HC2tAPCSJ2TCHp5uRFdjeXYtWdQDyG1UVmh3VKKtEWLdLAPJkQA3nbV2axVGrFXqsrpN377FrXpbqfJCNUima48JTPmBS8gH9TPejGAm5DFxChhVu8mwwEeyPhoBDsQSUPmHX29p2jtvzPiAEhDa1TVWWz4HBwaznvtQPvViuW7wT6yxZAgyunHqg6CETEZxXkedwU4UowhZrowEdA3ieWzpmmVLb36DyFmyFvGtd8vspK1p7DTwvrZPm27vNxHDd8GULqU24XT2YnqLJMAmrXpauvAznpTxBvk5k9VXAxpPj3RdgA7bTBzup9vmYtsotWWuoCwm5CjU9ctGJXHYRTf4k8Tot7rYz8yBFYEGDpHVVbt7pmtRhfdCiDzQuUtJyEnGR6aDsz7wuxv8AP3MK83sLveKcKZSB6ncSG3GyANRQA43rdnGmLGLJCCUayqzUARajthyoh5h2bbZXHLirtGpx4kyuVxHgsDCPmL6yorcQe3qBcjEAsm4DBmL8bzT5Wj1fVRWiHaTVq7u9JCaAqmx2A4twqd16a15nfC1fWH4h8HcEdfaJMdNzBbSvNckcbHzhcFN3fgjh1ucVqmfkhPgD9BpiMKXjidAsWXjNMLT1QUeXJKMxv243PBGWLqj6RPhTaYTuyzRnaC1W9ovZphsruidusdcKXf4s8pE2hnLUE35EJ3nv9gYb9J7uzgRCf4mfsSLxB4RWiPqfmk5uXvBr4gFadkJ5fvpBRAoM8CMTK6L7yDyk8uSvT5PWsFeqcv6Lo7wxu9CN4oNQNbghZyBzVUyhtbcyLfyvof4hc7xL3b1Ls3fgCDjT5qU66u9TQBd9Efm";
 const DEPOSIT_CONTRACT_SCRIPT_BYTES: &str = "26GyorB6GrM6DMrMS6CTLUoqD4Xo3xBafX17D96pEk4u8b5PwbBQUS5J51xnB2s2QsiUxxYKnvzkf58Y84idV5XiY69oU9Gi3GYfKrRajkZJWHxuaYySu4PDGeUEr8S9efxcEKNTiupbMhzny8vk8ZNMjx4KxSQD1uRNbX72HjD6yMKULcK8pW724Fat9Uy4ZbkpAxgLmemZYgrSqAPp524raJMbSA7Cg3NMTiVejbXsh4js7epuwE959Hcco76kxxJeyutPkPDETcELXt5CfJhiAxkp69RsWozhr5UUhHsu5r2vtG2rsY2VEd4U2qDrPEUKfzpZsUv8Zd45eeirbARiqiRDErTPd9DubPuMV1X5jt5gKRPhRPoER3xfutVnzxCxgMto2WmFy7mLPQz6rgWCuQswLytp2tyMn6En3n38jA9f1yixYPGAnHkqPgwgAQRGWFGJhAY9fh9bHLBGZ7vQYWy8WhLU89tJzgKnfP2PxEVNeXS1yDL5RZbt7emign8Fyc5gG5STqWNEChLxCaiqRm95jY2uCF1aQuzzhVHPACc1gEdfeLyENfvfqkbSmW41jHQZoYqJEPEb4HiJwnL4rnu9ibMFTGSCHPsfsV2PwPekHQbAHC9yaCm8bnDZqQKBDg8ZQetFdkqyPqrzgvq7KTbBxqfzEEYdFXrURDryFwch6DWPw81cDWGS9b3vRzNKrvgiKwTUBW1NQjBgP69L7BijnAkW88Pnu7MCn9s8FrxWR8dY4DuUyCPd1LeG5qKkV1Gj5sLBGFV5RhCAnDY2iPvxG3sNuxYPBYVykHPeoJQ6bK3Ys6ygbzWRXuz16vpBovWiA6sJqgmpejyt1hkMeQzSCnaHaWYsqtELFpCPFdtZjwPeuCLzXuRWgm2MiT31DNWEfD1feoAqFg3H4iJVR6djH8vaXJJdjBLf6wgd3W4czBUMf9kJJN4VhPC6f86oSvyrGVQaREecDYYVAPdMk8fEE8AKFeggbHzfW9rqDm8is6Z2DZwrRAZgSq2r3cxcoveBfQydws4gwxY3TSuzbuBENCqvBV8LnqusuRgsuAZNoRkTzxrz3F74MQQ3msHsSktoRxjHCKYQA2zAfzMCaSyht";
 lazy_static! {
@@ -121,15 +122,7 @@ impl From<&ErgoCell> for SValue {
 impl From<&ProtoTermCell> for ErgoCell {
     fn from(value: &ProtoTermCell) -> Self {
         let ergs = BoxValue::try_from(u64::from(value.value.native)).unwrap();
-
-        let projective_point = EcPoint::from(
-            k256::PublicKey::from_sec1_bytes(&<Vec<u8>>::from(value.dst.address.clone()))
-                .unwrap()
-                .to_projective(),
-        );
-
-        let prove_dlog = ProveDlog::new(projective_point);
-        let address = Address::P2Pk(prove_dlog);
+        let address = destination_address(&value.dst).unwrap();
 
         let policy_id = PolicyId::from(Blake2bDigest256::zero());
         let tokens = if let Some(tokens) = value.value.assets.get(&policy_id) {
@@ -153,23 +146,24 @@ impl From<&ProtoTermCell> for ErgoCell {
     }
 }
 
+/// Owner of value locked by the given address on Spectrum. Only P2PK addresses with a valid
+/// public key are supported.
+pub fn owner_of(address: &Address) -> Option<Owner> {
+    match address {
+        Address::P2Pk(pdl) => {
+            let affine_point = ProjectivePoint::from(pdl.h.as_ref().clone()).to_affine();
+            k256::PublicKey::from_affine(affine_point)
+                .ok()
+                .map(Owner::ProveDlog)
+        }
+        Address::P2S(_) | Address::P2SH(_) => None,
+    }
+}
+
 impl From<ErgoInboundCell> for InboundValue<BoxId> {
     fn from(ErgoInboundCell(value, box_id): ErgoInboundCell) -> Self {
         let s_value = SValue::from(&value);
-        let owner = match value.address {
-            Address::P2Pk(pdl) => {
-                let affine_point = ProjectivePoint::from(pdl.h.as_ref().clone()).to_affine();
-                let pk = k256::PublicKey::from_affine(affine_point).unwrap();
-                Owner::ProveDlog(pk)
-            }
-
-            Address::P2S(_) => {
-                unimplemented!()
-            }
-            Address::P2SH(_) => {
-                unimplemented!()
-            }
-        };
+        let owner = owner_of(&value.address).expect("Inbound value is owned by a valid public key");
 
         Self {
             value: s_value,
@@ -384,10 +378,74 @@ impl ErgoTermCell {
 pub enum ErgoTermCellError {
     BoxValue(BoxValueError),
     SigmaParsing(SigmaParsingError),
+    SigmaSerialization(SigmaSerializationError),
     DigestN(DigestNError),
     TokenAmount(TokenAmountError),
     EllipticCurve(elliptic_curve::Error),
     WrongChainId,
+    /// Timelock height doesn't fit into Ergo `HEIGHT`.
+    TimelockOutOfRange,
+}
+
+/// Address of the output to create on Ergo for a term cell going to `dst`.
+fn destination_address(dst: &BoxDestination) -> Result<Address, ErgoTermCellError> {
+    let recipient = sec1_prove_dlog(&dst.address)?;
+    if let Some(TermConstraints {
+        timelock_height,
+        refund_owner,
+    }) = &dst.constraints
+    {
+        let withdrawal = TimelockedWithdrawal {
+            recipient,
+            refund_owner: sec1_prove_dlog(refund_owner)?,
+            timelock_height: i32::try_from(*timelock_height)
+                .map_err(|_| ErgoTermCellError::TimelockOutOfRange)?,
+        };
+        Ok(Address::P2S(withdrawal.ergo_tree().sigma_serialize_bytes()?))
+    } else {
+        Ok(Address::P2Pk(recipient))
+    }
+}
+
+/// Inverse of [`destination_address`].
+fn box_destination(address: &Address) -> BoxDestination {
+    let (recipient, constraints) = match address {
+        Address::P2Pk(prove_dlog) => (prove_dlog.clone(), None),
+        Address::P2S(_) => {
+            let withdrawal = address
+                .script()
+                .ok()
+                .and_then(|tree| TimelockedWithdrawal::try_from_ergo_tree(&tree))
+                .expect("ONLY P2PK and timelocked withdrawal addresses supported atm");
+            let constraints = TermConstraints {
+                timelock_height: withdrawal.timelock_height as u64,
+                refund_owner: prove_dlog_sec1(&withdrawal.refund_owner),
+            };
+            (withdrawal.recipient, Some(constraints))
+        }
+        Address::P2SH(_) => panic!("ONLY P2PK and timelocked withdrawal addresses supported atm"),
+    };
+    BoxDestination {
+        target: ChainId::from(0),
+        address: prove_dlog_sec1(&recipient),
+        inputs: None,
+        constraints,
+    }
+}
+
+fn sec1_prove_dlog(address: &SerializedValue) -> Result<ProveDlog, ErgoTermCellError> {
+    let address_bytes: Vec<u8> = address.clone().into();
+    let pk = k256::PublicKey::from_sec1_bytes(&address_bytes)?;
+    Ok(ProveDlog::new(EcPoint::from(pk.to_projective())))
+}
+
+fn prove_dlog_sec1(prove_dlog: &ProveDlog) -> SerializedValue {
+    let address_bytes =
+        k256::PublicKey::from_affine(ProjectivePoint::from(prove_dlog.h.as_ref().clone()).to_affine())
+            .unwrap()
+            .to_sec1_bytes()
+            .to_vec();
+    SerializedValue::from(address_bytes)
 }
 
 impl TryFrom<TermCell> for ErgoTermCell {
@@ -396,10 +454,7 @@ impl TryFrom<TermCell> for ErgoTermCell {
     fn try_from(value: TermCell) -> Result<Self, Self::Error> {
         if value.dst.target == ERGO_CHAIN_ID {
             let ergs = BoxValue::try_from(u64::from(value.value.native))?;
-            let address_bytes: Vec<u8> = value.dst.address.into();
-            let pk = k256::PublicKey::from_sec1_bytes(&address_bytes)?;
-            let prove_dlog = ProveDlog::new(EcPoint::from(pk.to_projective()));
-            let address = Address::P2Pk(prove_dlog);
+            let address = destination_address(&value.dst)?;
             let mut token_details = vec![];
             for (_, assets) in value.value.assets {
                 for (id, a) in assets {
@@ -433,18 +488,7 @@ impl TryFrom<TermCell> for ErgoTermCell {
 impl From<ErgoTermCell> for TermCell {
     fn from(value: ErgoTermCell) -> Self {
         let s_value = SValue::from(&value.0);
-        let Address::P2Pk(prove_dlog) = value.0.address else {
-            panic!("ONLY P2PK addresses supported atm");
-        };
-        let address_bytes = k256::PublicKey::from_affine(ProjectivePoint::from(*prove_dlog.h).to_affine())
-            .unwrap()
-            .to_sec1_bytes()
-            .to_vec();
-        let dst = BoxDestination {
-            target: ChainId::from(0),
-            address: SerializedValue::from(address_bytes),
-            inputs: None,
-        };
+        let dst = box_destination(&value.0.address);
 
         Self {
             value: s_value,
@@ -458,18 +502,7 @@ impl From<ErgoTermCell> for TermCell {
 impl From<ErgoTermCell> for ProtoTermCell {
     fn from(value: ErgoTermCell) -> Self {
         let s_value = SValue::from(&value.0);
-        let Address::P2Pk(prove_dlog) = value.0.address else {
-            panic!("ONLY P2PK addresses supported atm");
-        };
-        let address_bytes = k256::PublicKey::from_affine(ProjectivePoint::from(*prove_dlog.h).to_affine())
-            .unwrap()
-            .to_sec1_bytes()
-            .to_vec();
-        let dst = BoxDestination {
-            target: ChainId::from(0),
-            address: SerializedValue::from(address_bytes),
-            inputs: None,
-        };
+        let dst = box_destination(&value.0.address);
         Self { value: s_value, dst }
     }
 }
@@ -479,10 +512,7 @@ impl TryFrom<ProtoTermCell> for ErgoTermCell {
 
     fn try_from(value: ProtoTermCell) -> Result<Self, Self::Error> {
         let ergs = BoxValue::try_from(u64::from(value.value.native))?;
-        let address_bytes: Vec<u8> = value.dst.address.into();
-        let pk = k256::PublicKey::from_sec1_bytes(&address_bytes)?;
-        let prove_dlog = ProveDlog::new(EcPoint::from(pk.to_projective()));
-        let address = Address::P2Pk(prove_dlog);
+        let address = destination_address(&value.dst)?;
         let mut token_details = vec![];
         for (_, assets) in value.value.assets {
             for (id, a) in assets {
@@ -582,109 +612,25 @@ fn tagged_hash(tag: &[u8]) -> Sha256 {
     digest
 }
 
+/// Encode elements of the exclusion set one by one, skipping members with no exclusion proof.
+pub fn exclusion_set_elements(
+    exclusion_set: Vec<(usize, Option<(Commitment, Signature)>)>,
+    md: &[u8],
+) -> impl Iterator<Item = Constant> + '_ {
+    exclusion_set.into_iter().filter_map(move |(ix, pair)| {
+        pair.map(|(Commitment(verifying_key), signature)| {
+            exclusion_set_element(ix, verifying_key, signature, md)
+        })
+    })
+}
+
 pub fn serialize_exclusion_set(
     exclusion_set: Vec<(usize, Option<(Commitment, Signature)>)>,
     md: &[u8],
 ) -> Constant {
     let mut elem_tpe = None;
     let mut items = vec![];
-    let filtered_exclusion_set = exclusion_set.into_iter().filter_map(|(ix, pair)| {
-        if let Some((Commitment(verifying_key), sig)) = pair {
-            Some((ix, verifying_key, sig))
-        } else {
-            None
-        }
-    });
-    for (ix, verifying_key, signature) in filtered_exclusion_set {
-        let signature_bytes = k256::schnorr::Signature::from(signature).to_bytes();
-
-        // The components (r,s) of the taproot `Signature` struct are not public, but we can
-        // extract it through its byte representation.
-        let (r_bytes, s_bytes) = signature_bytes.split_at(32);
-        let r: FieldElement = Option::from(FieldElement::from_bytes(r_bytes.into())).unwrap();
-
-        const CHALLENGE_TAG: &[u8] = b"BIP0340/challenge";
-        //  int(sha256(sha256(CHALLENGE_TAG) || sha256(CHALLENGE_TAG) || bytes(r) || bytes(P) || m)) mod n
-        let e = <Scalar as Reduce<U256>>::reduce_bytes(
-            &tagged_hash(CHALLENGE_TAG)
-                .chain_update(r.to_bytes())
-                .chain_update(verifying_key.to_bytes())
-                .chain_update(md)
-                .finalize(),
-        );
-        let s = NonZeroScalar::try_from(s_bytes).unwrap();
-
-        // R
-        let r_point = ProjectivePoint::lincomb(
-            &ProjectivePoint::GENERATOR,
-            &s,
-            &ProjectivePoint::from(verifying_key.as_affine()),
-            &-e,
-        );
-
-        // The taproot signature satisfies:
-        //     g ^ s == R * P^e
-        // Note: `k256` uses additive notation for elliptic-curves, so we can compute the right
-        // hand side with:
-        //   r_point + ProjectivePoint::from(verifying_key.as_affine()) * e;
-        //
-        // Note in the above equation that the values `s` and `e` have a 256bit UNSIGNED integer
-        // representation. This is a problem for Ergoscript since the largest integer values it
-        // allows for is 256bit signed. We can work around the problem by splitting the value
-        // into 2 signed ints.
-        //
-        // Let `B` denote the big-endian unsigned byte representation of `s`. Let `U` and `L`
-        // denote the first 16 and last 16 bytes of `B`, respectively. Then `U` and `L` are
-        // themselves unsigned integers. Moreover,
-        //    B == U*p + L, where p == 340282366920938463463374607431768211456
-        //
-        // We want to use this decomposition on the ergo side, but we need to convert `U` and `L`
-        // into signed integers, `U_S` and `L_S`. We need to be careful as `U_S` and/or `L_S` could
-        // each require 17 bytes if the most-significant-bit of `U`/`L` is 1 (and so we need to
-        // prepend a zero byte to accomodate the sign-bit).
-        //
-        // So we can transport `s` across the boundary with the bytes of [U_S | L_S], and decoding
-        // `U_S` and `L_S` within Ergoscript.
-        let s_biguint = scalar_to_biguint(*s.as_ref());
-        let biguint_bytes = s_biguint.to_bytes_be();
-        let split = biguint_bytes.len() - 16;
-        //println!("# bytes: {}", s_biguint.to_bytes_be().len());
-        let upper = BigUint::from_bytes_be(&biguint_bytes[..split]);
-        let upper_256 = BigInt256::try_from(upper).unwrap();
-        assert_eq!(upper_256.sign(), Sign::Plus);
-        let lower = BigUint::from_bytes_be(&s_biguint.to_bytes_be()[split..]);
-        let lower_256 = BigInt256::try_from(lower).unwrap();
-        assert_eq!(lower_256.sign(), Sign::Plus);
-
-        let mut s_bytes = upper_256.to_signed_bytes_be();
-        // Need this variable because we could add an extra byte to the encoding for signed-representation.
-        let first_len = s_bytes.len() as i32;
-        s_bytes.extend(lower_256.to_signed_bytes_be());
-
-        //println!("first_len: {}, S_BYTES_LEN: {}", first_len, s_bytes.len());
-        //let p = BigInt256::from_str_radix("340282366920938463463374607431768211456", 10).unwrap();
-
-        //println!(
-        //    "PP_base64: {}",
-        //    base64::engine::general_purpose::STANDARD_NO_PAD.encode(p.to_signed_bytes_be())
-        //);
-
-        // P from BIP-0340
-        let pubkey_point = EcPoint::from(ProjectivePoint::from(verifying_key.as_affine()));
-        // The x-coordinate of P
-        let pubkey_x_coords = verifying_key.to_bytes().to_vec();
-
-        let pubkey_tuple: Constant = (Constant::from(pubkey_point), Constant::from(pubkey_x_coords)).into();
-        let with_ix: Constant = (Constant::from(ix as i32), pubkey_tuple).into();
-        let s_tuple: Constant = (Constant::from(s_bytes), Constant::from(first_len)).into();
-        let r_tuple: Constant = (
-            Constant::from(EcPoint::from(r_point)),
-            Constant::from(r.to_bytes().to_vec()),
-        )
-            .into();
-        let s_r_tuple: Constant = (s_tuple, r_tuple).into();
-        let elem: Constant = (with_ix, s_r_tuple).into();
-
+    for elem in exclusion_set_elements(exclusion_set, md) {
         items.push(elem.v);
 
         if elem_tpe.is_none() {
@@ -708,6 +654,102 @@ pub fn serialize_exclusion_set(
     }
 }
 
+fn exclusion_set_element(
+    ix: usize,
+    verifying_key: k256::schnorr::VerifyingKey,
+    signature: Signature,
+    md: &[u8],
+) -> Constant {
+    let signature_bytes = k256::schnorr::Signature::from(signature).to_bytes();
+
+    // The components (r,s) of the taproot `Signature` struct are not public, but we can
+    // extract it through its byte representation.
+    let (r_bytes, s_bytes) = signature_bytes.split_at(32);
+    let r: FieldElement = Option::from(FieldElement::from_bytes(r_bytes.into())).unwrap();
+
+    const CHALLENGE_TAG: &[u8] = b"BIP0340/challenge";
+    //  int(sha256(sha256(CHALLENGE_TAG) || sha256(CHALLENGE_TAG) || bytes(r) || bytes(P) || m)) mod n
+    let e = <Scalar as Reduce<U256>>::reduce_bytes(
+        &tagged_hash(CHALLENGE_TAG)
+            .chain_update(r.to_bytes())
+            .chain_update(verifying_key.to_bytes())
+            .chain_update(md)
+            .finalize(),
+    );
+    let s = NonZeroScalar::try_from(s_bytes).unwrap();
+
+    // R
+    let r_point = ProjectivePoint::lincomb(
+        &ProjectivePoint::GENERATOR,
+        &s,
+        &ProjectivePoint::from(verifying_key.as_affine()),
+        &-e,
+    );
+
+    // The taproot signature satisfies:
+    //     g ^ s == R * P^e
+    // Note: `k256` uses additive notation for elliptic-curves, so we can compute the right
+    // hand side with:
+    //   r_point + ProjectivePoint::from(verifying_key.as_affine()) * e;
+    //
+    // Note in the above equation that the values `s` and `e` have a 256bit UNSIGNED integer
+    // representation. This is a problem for Ergoscript since the largest integer values it
+    // allows for is 256bit signed. We can work around the problem by splitting the value
+    // into 2 signed ints.
+    //
+    // Let `B` denote the big-endian unsigned byte representation of `s`. Let `U` and `L`
+    // denote the first 16 and last 16 bytes of `B`, respectively. Then `U` and `L` are
+    // themselves unsigned integers. Moreover,
+    //    B == U*p + L, where p == 340282366920938463463374607431768211456
+    //
+    // We want to use this decomposition on the ergo side, but we need to convert `U` and `L`
+    // into signed integers, `U_S` and `L_S`. We need to be careful as `U_S` and/or `L_S` could
+    // each require 17 bytes if the most-significant-bit of `U`/`L` is 1 (and so we need to
+    // prepend a zero byte to accomodate the sign-bit).
+    //
+    // So we can transport `s` across the boundary with the bytes of [U_S | L_S], and decoding
+    // `U_S` and `L_S` within Ergoscript.
+    let s_biguint = scalar_to_biguint(*s.as_ref());
+    let biguint_bytes = s_biguint.to_bytes_be();
+    let split = biguint_bytes.len() - 16;
+    //println!("# bytes: {}", s_biguint.to_bytes_be().len());
+    let upper = BigUint::from_bytes_be(&biguint_bytes[..split]);
+    let upper_256 = BigInt256::try_from(upper).unwrap();
+    assert_eq!(upper_256.sign(), Sign::Plus);
+    let lower = BigUint::from_bytes_be(&s_biguint.to_bytes_be()[split..]);
+    let lower_256 = BigInt256::try_from(lower).unwrap();
+    assert_eq!(lower_256.sign(), Sign::Plus);
+
+    let mut s_bytes = upper_256.to_signed_bytes_be();
+    // Need this variable because we could add an extra byte to the encoding for signed-representation.
+    let first_len = s_bytes.len() as i32;
+    s_bytes.extend(lower_256.to_signed_bytes_be());
+
+    //println!("first_len: {}, S_BYTES_LEN: {}", first_len, s_bytes.len());
+    //let p = BigInt256::from_str_radix("340282366920938463463374607431768211456", 10).unwrap();
+
+    //println!(
+    //    "PP_base64: {}",
+    //    base64::engine::general_purpose::STANDARD_NO_PAD.encode(p.to_signed_bytes_be())
+    //);
+
+    // P from BIP-0340
+    let pubkey_point = EcPoint::from(ProjectivePoint::from(verifying_key.as_affine()));
+    // The x-coordinate of P
+    let pubkey_x_coords = verifying_key.to_bytes().to_vec();
+
+    let pubkey_tuple: Constant = (Constant::from(pubkey_point), Constant::from(pubkey_x_coords)).into();
+    let with_ix: Constant = (Constant::from(ix as i32), pubkey_tuple).into();
+    let s_tuple: Constant = (Constant::from(s_bytes), Constant::from(first_len)).into();
+    let r_tuple: Constant = (
+        Constant::from(EcPoint::from(r_point)),
+        Constant::from(r.to_bytes().to_vec()),
+    )
+        .into();
+    let s_r_tuple: Constant = (s_tuple, r_tuple).into();
+    (with_ix, s_r_tuple).into()
+}
+
 pub fn scalar_to_biguint(scalar: Scalar) -> BigUint {
     scalar
         .to_bytes()
@@ -717,11 +759,7 @@ pub fn scalar_to_biguint(scalar: Scalar) -> BigUint {
         .sum()
 }
 
-pub fn dummy_resolver(digest: &scorex_crypto_avltree::operation::Digest32) -> Node {
-    Node::LabelOnly(NodeHeader::new(Some(*digest), None))
-}
-
-fn schnorr_signature_verification_ergoscript_type() -> SType {
+pub(crate) fn schnorr_signature_verification_ergoscript_type() -> SType {
     //   ( ( Int, (GroupElement, Coll[Byte]) ),
     //     ( (Coll[Byte], Int), (GroupElement, Coll[Byte]) )
     //   )
@@ -751,6 +789,38 @@ fn schnorr_signature_verification_ergoscript_type() -> SType {
     })
 }
 
+/// Hash of the terminal cell the AVL tree of a notarized report commits to.
+pub fn term_cell_hash(cell: &ErgoTermCell) -> Blake2bDigest256 {
+    blake2b256_hash(&cell.to_bytes())
+}
+
+/// Entries of the AVL tree of a notarized report in the order of insertion: hashes of terminal
+/// cells under keys `1..=n` followed by the max miner fee (padded to 32 bytes) under `n + 1`.
+pub fn report_tree_entries(terminal_cells: &[ErgoTermCell], max_miner_fee: i64) -> Vec<KeyValue> {
+    let cell_hashes: Vec<_> = terminal_cells
+        .iter()
+        .map(|cell| <[u8; VALUE_LENGTH]>::try_from(term_cell_hash(cell).as_ref()).unwrap())
+        .collect();
+    let mut max_miner_fee_padded = [0u8; VALUE_LENGTH];
+    max_miner_fee_padded[..8].copy_from_slice(&max_miner_fee.to_be_bytes());
+    report_entries(&cell_hashes, Some(max_miner_fee_padded))
+}
+
+/// Ergo representation of the empty AVL tree a report with the given proof is built upon.
+pub fn starting_avl_tree(proof: &InsertionProof) -> AvlTreeData {
+    AvlTreeData {
+        digest: Digest::<33>::try_from(proof.initial_digest.clone()).unwrap(),
+        tree_flags: AvlTreeFlags::new(true, false, false),
+        key_length: KEY_LENGTH as u32,
+        value_length_opt: Some(Box::new(VALUE_LENGTH as u32)),
+    }
+}
+
+/// Message the committee signs to notarize the report with the given resulting AVL digest.
+pub fn report_digest(resulting_digest: &[u8]) -> Blake2bDigest256 {
+    blake2b256_hash(resulting_digest)
+}
+
 pub fn estimate_tx_size_in_kb(
     num_withdrawals: usize,
     num_byzantine_nodes: usize,
@@ -761,6 +831,25 @@ pub fn estimate_tx_size_in_kb(
         + (num_token_occurrences as f32) * 0.039
 }
 
+/// Size estimate of a withdrawal TX, suitable for [`spectrum_chain_connector::report_builder`].
+pub fn estimate_withdrawal_tx_size(term_cells: &[ProtoTermCell], num_byzantine_nodes: u32) -> Kilobytes {
+    let num_token_occurrences = term_cells
+        .iter()
+        .map(|cell| {
+            cell.value
+                .assets
+                .values()
+                .map(|assets| assets.len())
+                .sum::<usize>()
+        })
+        .sum();
+    Kilobytes(estimate_tx_size_in_kb(
+        term_cells.len(),
+        num_byzantine_nodes as usize,
+        num_token_occurrences,
+    ))
+}
+
 pub fn simulate_signature_aggregation_notarized_proofs(
     participant_secret_keys: Vec<SecretKey>,
     terminal_cells: Vec<ErgoTermCell>,
@@ -808,42 +897,15 @@ pub fn simulate_signature_aggregation_notarized_proofs(
             .collect(),
     );
 
-    let empty_tree = AVLTree::new(dummy_resolver, KEY_LENGTH, Some(VALUE_LENGTH));
-    let mut prover = BatchAVLProver::new(empty_tree.clone(), true);
-    let initial_digest = prover.digest().unwrap().to_vec();
-
-    for (i, cell) in terminal_cells.iter().enumerate() {
-        let value = Bytes::copy_from_slice(blake2b256_hash(&cell.to_bytes()).as_ref());
-        let key_bytes = ((i + 1) as i64).to_be_bytes();
-        let key = Bytes::copy_from_slice(&key_bytes);
-        let kv = KeyValue { key, value };
-        let insert = Operation::Insert(kv.clone());
-        prover.perform_one_operation(&insert).unwrap();
-    }
-
-    // Perform insertion for max_miner_fee
-    {
-        let key_bytes = ((terminal_cells.len() + 1) as i64).to_be_bytes();
-        let key = Bytes::copy_from_slice(&key_bytes);
-        let mut value_bytes = max_miner_fee.to_be_bytes().to_vec();
-        // Need to pad to 32 bytes
-        value_bytes.extend(repeat(0).take(24));
-        let value = Bytes::copy_from_slice(&value_bytes);
-        let kv = KeyValue { key, value };
-        let insert = Operation::Insert(kv.clone());
-        prover.perform_one_operation(&insert).unwrap();
-    }
-
-    let proof = prover.generate_proof().to_vec();
-    let resulting_digest = prover.digest().unwrap().to_vec();
-    let avl_tree_data = AvlTreeData {
-        digest: Digest::<33>::try_from(initial_digest).unwrap(),
-        tree_flags: AvlTreeFlags::new(true, false, false),
-        key_length: KEY_LENGTH as u32,
-        value_length_opt: Some(Box::new(VALUE_LENGTH as u32)),
-    };
+    let insertion_proof = prove_insertions(report_tree_entries(&terminal_cells, max_miner_fee)).unwrap();
+    let avl_tree_data = starting_avl_tree(&insertion_proof);
+    let InsertionProof {
+        proof,
+        resulting_digest,
+        ..
+    } = insertion_proof;
 
-    let md = blake2b256_hash(&resulting_digest);
+    let md = report_digest(&resulting_digest);
 
     let challenge = challenge(aggregate_x, aggregate_commitment.clone(), md);
     let (byz_keys, active_keys): (Vec<_>, Vec<_>) = individual_keys
@@ -902,15 +964,13 @@ pub fn simulate_signature_aggregation_notarized_proofs(
     }
 }
 
-const KEY_LENGTH: usize = 8;
-const VALUE_LENGTH: usize = 32;
 const MIN_KEY: [u8; KEY_LENGTH] = [0u8; KEY_LENGTH];
 const MAX_KEY: [u8; KEY_LENGTH] = [0xFFu8; KEY_LENGTH];
 #[cfg(test)]
 pub mod tests {
     use bytes::Bytes;
     use elliptic_curve::group::GroupEncoding;
-    use ergo_lib::ergo_chain_types::{ec_point::generator, Digest, EcPoint};
+    use ergo_lib::ergo_chain_types::{ec_point::generator, EcPoint};
     use ergo_lib::ergotree_interpreter::sigma_protocol::prover::ContextExtension;
     use ergo_lib::ergotree_ir::chain::address::Address;
     use ergo_lib::ergotree_ir::chain::ergo_box::BoxTokens;
@@ -926,10 +986,7 @@ pub mod tests {
             },
         },
         ergo_tree::ErgoTree,
-        mir::{
-            avl_tree_data::{AvlTreeData, AvlTreeFlags},
-            constant::{Constant, Literal},
-        },
+        mir::constant::{Constant, Literal},
         types::stype::SType,
     };
     use ergo_lib::ergotree_ir::{
@@ -956,12 +1013,11 @@ pub mod tests {
     use rand::rngs::OsRng;
     use rand::seq::SliceRandom;
     use rand::Rng;
-    use scorex_crypto_avltree::{
-        authenticated_tree_ops::*, batch_avl_prover::BatchAVLProver, batch_node::*, operation::*,
-    };
+    use scorex_crypto_avltree::operation::*;
     use serde::Deserialize;
     use serde::Serialize;
     use sigma_test_util::force_any_val;
+    use spectrum_avl::{prove_insertions, verify_insertions, KEY_LENGTH, VALUE_LENGTH};
     use spectrum_crypto::{digest::blake2b256_hash, pubkey::PublicKey};
     use spectrum_handel::Threshold;
     use spectrum_offchain_lm::prover::SeedPhrase;
@@ -970,13 +1026,13 @@ pub mod tests {
     use std::time::Instant;
 
     use crate::script::{
-        estimate_tx_size_in_kb, scalar_to_biguint, serialize_exclusion_set, ErgoCell, ErgoTermCell,
-        ErgoTermCells, DEPOSIT_CONTRACT, VAULT_CONTRACT, VAULT_CONTRACT_SCRIPT_BYTES,
+        committee_hash, estimate_tx_size_in_kb, scalar_to_biguint, serialize_exclusion_set, ErgoCell,
+        ErgoTermCell, ErgoTermCells, DEPOSIT_CONTRACT, VAULT_CONTRACT, VAULT_CONTRACT_SCRIPT_BYTES,
     };
 
     use super::{
-        dummy_resolver, simulate_signature_aggregation_notarized_proofs,
-        SignatureAggregationWithNotarizationElements, KEY_LENGTH, MAX_KEY, MIN_KEY, VALUE_LENGTH,
+        simulate_signature_aggregation_notarized_proofs, starting_avl_tree,
+        SignatureAggregationWithNotarizationElements, MAX_KEY, MIN_KEY,
     };
 
     fn random_key() -> ADKey {
@@ -1553,16 +1609,7 @@ pub mod tests {
             max_miner_fee,
         } = inputs;
         let threshold = (num_participants * threshold.num / threshold.denom) as i32;
-        let c_bytes = committee.iter().fold(Vec::<u8>::new(), |mut b, p| {
-            b.extend_from_slice(
-                k256::PublicKey::from(p.clone())
-                    .to_projective()
-                    .to_bytes()
-                    .as_slice(),
-            );
-            b
-        });
-        let committee_bytes = blake2b256_hash(&c_bytes).as_ref().to_vec();
+        let committee_bytes = committee_hash(&committee).as_ref().to_vec();
         let committee_lit = Literal::from(
             committee
                 .into_iter()
@@ -1689,14 +1736,9 @@ pub mod tests {
 
     #[test]
     fn test_avl_tree_verification() {
-        let empty_tree = AVLTree::new(dummy_resolver, KEY_LENGTH, Some(VALUE_LENGTH));
-        let mut prover = BatchAVLProver::new(empty_tree.clone(), true);
-        let initial_digest = prover.digest().unwrap().to_vec();
         let pairs: Vec<_> = (0..3).map(|_| random_kv()).collect();
-        for kv in &pairs {
-            let m = Operation::Insert(kv.clone());
-            prover.perform_one_operation(&m).unwrap();
-        }
+        let insertion_proof = prove_insertions(pairs.clone()).unwrap();
+        assert!(verify_insertions(&insertion_proof, pairs.clone()).is_ok());
         let operations_vec: Vec<_> = pairs
             .into_iter()
             .map(|kv| {
@@ -1722,14 +1764,9 @@ pub mod tests {
             v: operations_lit,
         };
 
-        let proof = Constant::from(prover.generate_proof().to_vec());
-        let resulting_digest = prover.digest().unwrap().to_vec();
-        let avl_tree_data = AvlTreeData {
-            digest: Digest::<33>::try_from(initial_digest).unwrap(),
-            tree_flags: AvlTreeFlags::new(true, false, false),
-            key_length: KEY_LENGTH as u32,
-            value_length_opt: Some(Box::new(VALUE_LENGTH as u32)),
-        };
+        let avl_tree_data = starting_avl_tree(&insertion_proof);
+        let proof = Constant::from(insertion_proof.proof);
+        let resulting_digest = insertion_proof.resulting_digest;
         let avl_const = Constant::from(avl_tree_data);
 
         // Script: https://wallet.plutomonkey.com/p2s/?source=eyAvLyA9PT09PSBDb250cmFjdCBJbmZvcm1hdGlvbiA9PT09PSAvLwogIC8vIE5hbWU6IFZlcmlmeSBBVkwgdHJlZSB0ZXN0CiAgLy8KICAvLyBDb250ZXh0RXh0ZW5zaW9uIGNvbnN0YW50czoKICAvLyAwOiBBdmxUcmVlIC0gaW5pdGlhbCBzdGF0ZSBvZiB0aGUgQVZMIHRyZWUKICAvLyAxOiBDb2xsW0NvbGxbKEludCwgQ29sbFtCeXRlXSldXSAtIGluc2VydCBvcGVyYXRpb25zIGZvciBBVkwgdHJlZQogIC8vIDI6IENvbGxbQnl0ZV0gLSBBVkwgdHJlZSBwcm9vZgogIC8vIDM6IENvbGxbQnl0ZV0gLSBFeHBlY3RlZCBkaWdlc3QgYWZ0ZXIgaW5zZXJ0IG9wZXJhdGlvbnMgaGF2ZSBiZWVuIHBlcmZvcm1lZAogCgogIHZhbCB0cmVlICAgICAgICA9IGdldFZhcltBdmxUcmVlXSgwKS5nZXQKICB2YWwgb3BlcmF0aW9ucyAgPSBnZXRWYXJbQ29sbFsoQ29sbFtCeXRlXSwgQ29sbFtCeXRlXSldXSgxKS5nZXQKICB2YWwgcHJvb2YgICAgICAgPSBnZXRWYXJbQ29sbFtCeXRlXV0oMikuZ2V0CiAgdmFsIGRpZ2VzdCAgICAgID0gZ2V0VmFyW0NvbGxbQnl0ZV1dKDMpLmdldAoKICB2YWwgZW5kVHJlZSA9IHRyZWUuaW5zZXJ0KG9wZXJhdGlvbnMsIHByb29mKS5nZXQKICAKICBzaWdtYVByb3AgKGVuZFRyZWUuZGlnZXN0ID09IGRpZ2VzdCkKfQ==
@@ -1890,16 +1927,7 @@ pub mod tests {
             terminal_cells,
             max_miner_fee,
         } = inputs;
-        let c_bytes = committee.iter().fold(Vec::<u8>::new(), |mut b, p| {
-            b.extend_from_slice(
-                k256::PublicKey::from(p.clone())
-                    .to_projective()
-                    .to_bytes()
-                    .as_slice(),
-            );
-            b
-        });
-        let committee_bytes = blake2b256_hash(&c_bytes).as_ref().to_vec();
+        let committee_bytes = committee_hash(&committee).as_ref().to_vec();
 
         let serialized_aggregate_commitment =
             Constant::from(EcPoint::from(ProjectivePoint::from(aggregate_commitment)));
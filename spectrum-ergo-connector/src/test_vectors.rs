@@ -0,0 +1,282 @@
+//! Canonical test vectors of state hashing.
+//!
+//! Implementations other than this one (the on-chain vault contract, light clients, etc.) must
+//! reproduce digests of terminal cells, notarized reports and committees bit by bit. Vectors
+//! generated from fixed inputs are kept in `tests/test_vectors/state_hashing.json`, all binary
+//! data is hex-encoded. Any change of the hashing scheme shows up as a failure of the drift test,
+//! after an intended change the fixture is regenerated with `UPDATE_TEST_VECTORS=1 cargo test`.
+
+use std::path::Path;
+
+use elliptic_curve::group::GroupEncoding;
+use ergo_lib::ergo_chain_types::{Digest32, EcPoint};
+use ergo_lib::ergotree_ir::chain::address::Address;
+use ergo_lib::ergotree_ir::chain::ergo_box::box_value::BoxValue;
+use ergo_lib::ergotree_ir::chain::token::{Token, TokenAmount, TokenId};
+use ergo_lib::ergotree_ir::serialization::SigmaSerializable;
+use ergo_lib::ergotree_ir::sigma_protocol::sigma_boolean::ProveDlog;
+use k256::SecretKey;
+use serde::{Deserialize, Serialize};
+use spectrum_avl::{ReportTree, KEY_LENGTH, VALUE_LENGTH};
+use spectrum_crypto::pubkey::PublicKey;
+
+use crate::script::{
+    committee_hash, report_digest, report_tree_entries, term_cell_hash, ErgoCell, ErgoTermCell,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TestVectors {
+    pub cell_hashes: Vec<CellHashVector>,
+    pub avl_insertions: Vec<AvlInsertionVector>,
+    pub report_digests: Vec<ReportDigestVector>,
+    pub committee_hashes: Vec<CommitteeHashVector>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TokenVector {
+    pub token_id: String,
+    pub amount: u64,
+}
+
+/// Terminal cell, its canonical serialization and hash.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CellHashVector {
+    pub description: String,
+    /// Value in nanoERG.
+    pub value: u64,
+    pub ergo_tree: String,
+    pub tokens: Vec<TokenVector>,
+    pub bytes: String,
+    pub hash: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AvlInsertionStep {
+    pub key: String,
+    pub value: String,
+    /// Digest of the tree after the insertion.
+    pub digest: String,
+}
+
+/// Sequence of insertions into an empty AVL+ tree.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AvlInsertionVector {
+    pub description: String,
+    pub key_length: usize,
+    pub value_length: usize,
+    pub initial_digest: String,
+    pub steps: Vec<AvlInsertionStep>,
+    /// Batch proof of all insertions.
+    pub proof: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ReportDigestVector {
+    pub description: String,
+    /// Hashes of terminal cells in the order they're inserted into the tree.
+    pub cell_hashes: Vec<String>,
+    pub max_miner_fee: i64,
+    pub resulting_avl_digest: String,
+    /// Message signed by the committee.
+    pub report_digest: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CommitteeHashVector {
+    pub description: String,
+    /// Compressed SEC1 encodings of members' public keys.
+    pub public_keys: Vec<String>,
+    pub hash: String,
+}
+
+impl TestVectors {
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        if let Some(dir) = path.as_ref().parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json + "\n")
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+}
+
+/// Generate test vectors from fixed inputs.
+pub fn generate() -> TestVectors {
+    let cells = [
+        ("P2PK, no tokens", term_cell(1, 1_000_000_000, &[])),
+        ("P2PK, single token", term_cell(2, 67_500_000, &[(0xaa, 1)])),
+        (
+            "P2PK, multiple tokens",
+            term_cell(3, 1_000_000, &[(0xbb, 1_000_000_000_000), (0xcc, 42)]),
+        ),
+    ];
+    let cell_hashes = cells
+        .iter()
+        .map(|(description, cell)| cell_hash_vector(description, cell))
+        .collect();
+    let reports = [
+        ("No terminal cells", vec![], 1_000_000),
+        ("Single terminal cell", vec![cells[0].1.clone()], 1_000_000),
+        (
+            "All terminal cells",
+            cells.iter().map(|(_, c)| c.clone()).collect(),
+            2_500_000,
+        ),
+    ];
+    let mut avl_insertions = vec![];
+    let mut report_digests = vec![];
+    for (description, term_cells, max_miner_fee) in reports {
+        let (avl, report) = report_vectors(description, &term_cells, max_miner_fee);
+        avl_insertions.push(avl);
+        report_digests.push(report);
+    }
+    let committee_hashes = [1, 3, 16]
+        .into_iter()
+        .map(|size| committee_hash_vector(&format!("{} members", size), &committee(size)))
+        .collect();
+    TestVectors {
+        cell_hashes,
+        avl_insertions,
+        report_digests,
+        committee_hashes,
+    }
+}
+
+fn secret_key(seed: u8) -> SecretKey {
+    SecretKey::from_slice(&[seed; 32]).unwrap()
+}
+
+fn committee(size: u8) -> Vec<PublicKey> {
+    (1..=size).map(|i| PublicKey::from(secret_key(i))).collect()
+}
+
+fn term_cell(owner_seed: u8, nano_ergs: u64, tokens: &[(u8, u64)]) -> ErgoTermCell {
+    let owner = ProveDlog::new(EcPoint::from(secret_key(owner_seed).public_key().to_projective()));
+    ErgoTermCell(ErgoCell {
+        ergs: BoxValue::try_from(nano_ergs).unwrap(),
+        address: Address::P2Pk(owner),
+        tokens: tokens
+            .iter()
+            .map(|(id, amount)| Token {
+                token_id: TokenId::from(Digest32::from([*id; 32])),
+                amount: TokenAmount::try_from(*amount).unwrap(),
+            })
+            .collect(),
+    })
+}
+
+fn cell_hash_vector(description: &str, cell: &ErgoTermCell) -> CellHashVector {
+    let ErgoTermCell(ErgoCell {
+        ergs,
+        address,
+        tokens,
+    }) = cell;
+    CellHashVector {
+        description: description.to_string(),
+        value: *ergs.as_u64(),
+        ergo_tree: hex(&address.script().unwrap().sigma_serialize_bytes().unwrap()),
+        tokens: tokens
+            .iter()
+            .map(|Token { token_id, amount }| TokenVector {
+                token_id: hex(&Digest32::from(*token_id).0),
+                amount: *amount.as_u64(),
+            })
+            .collect(),
+        bytes: hex(&cell.to_bytes()),
+        hash: hex(term_cell_hash(cell).as_ref()),
+    }
+}
+
+fn report_vectors(
+    description: &str,
+    term_cells: &[ErgoTermCell],
+    max_miner_fee: i64,
+) -> (AvlInsertionVector, ReportDigestVector) {
+    let mut tree = ReportTree::new();
+    let initial_digest = hex(&tree.digest());
+    let mut steps = vec![];
+    for kv in report_tree_entries(term_cells, max_miner_fee) {
+        let (key, value) = (hex(&kv.key), hex(&kv.value));
+        tree.insert(kv).unwrap();
+        steps.push(AvlInsertionStep {
+            key,
+            value,
+            digest: hex(&tree.digest()),
+        });
+    }
+    let insertion_proof = tree.prove();
+    let resulting_digest = insertion_proof.resulting_digest;
+    let avl = AvlInsertionVector {
+        description: description.to_string(),
+        key_length: KEY_LENGTH,
+        value_length: VALUE_LENGTH,
+        initial_digest,
+        steps,
+        proof: hex(&insertion_proof.proof),
+    };
+    let report = ReportDigestVector {
+        description: description.to_string(),
+        cell_hashes: term_cells
+            .iter()
+            .map(|c| hex(term_cell_hash(c).as_ref()))
+            .collect(),
+        max_miner_fee,
+        resulting_avl_digest: hex(&resulting_digest),
+        report_digest: hex(report_digest(&resulting_digest).as_ref()),
+    };
+    (avl, report)
+}
+
+fn committee_hash_vector(description: &str, committee: &[PublicKey]) -> CommitteeHashVector {
+    CommitteeHashVector {
+        description: description.to_string(),
+        public_keys: committee
+            .iter()
+            .map(|pk| {
+                hex(k256::PublicKey::from(pk.clone())
+                    .to_projective()
+                    .to_bytes()
+                    .as_slice())
+            })
+            .collect(),
+        hash: hex(committee_hash(committee).as_ref()),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    base16::encode_lower(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_vectors::{generate, TestVectors};
+
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/test_vectors/state_hashing.json"
+    );
+
+    #[test]
+    fn test_vectors_are_up_to_date() {
+        let actual = generate();
+        if std::env::var_os("UPDATE_TEST_VECTORS").is_some() {
+            actual.save(FIXTURE).unwrap();
+            return;
+        }
+        let recorded = TestVectors::load(FIXTURE)
+            .expect("Test vectors are missing, generate them with `UPDATE_TEST_VECTORS=1 cargo test`");
+        assert_eq!(
+            recorded, actual,
+            "State hashing diverged from the recorded test vectors"
+        );
+    }
+
+    #[test]
+    fn generation_is_deterministic() {
+        assert_eq!(generate(), generate());
+    }
+}
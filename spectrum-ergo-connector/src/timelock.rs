@@ -0,0 +1,157 @@
+use ergo_lib::ergotree_ir::{
+    ergo_tree::{ErgoTree, ErgoTreeHeader},
+    mir::{
+        bin_op::{BinOp, BinOpKind, RelationOp},
+        bool_to_sigma::BoolToSigmaProp,
+        constant::{Constant, TryExtractInto},
+        expr::Expr,
+        global_vars::GlobalVars,
+        sigma_and::SigmaAnd,
+        sigma_or::SigmaOr,
+    },
+    sigma_protocol::sigma_boolean::{ProveDlog, SigmaBoolean, SigmaProofOfKnowledgeTree, SigmaProp},
+    types::stype::SType,
+};
+
+/// Output of a withdrawal which is refundable if left unclaimed, see
+/// `contracts/timelocked_withdrawal.sc`:
+///   `recipientPk || (sigmaProp(HEIGHT >= timelockHeight) && refundPk)`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimelockedWithdrawal {
+    pub recipient: ProveDlog,
+    pub refund_owner: ProveDlog,
+    /// Height starting from which the refund owner can spend the output.
+    pub timelock_height: i32,
+}
+
+impl TimelockedWithdrawal {
+    pub fn ergo_tree(&self) -> ErgoTree {
+        let recipient = Expr::Const(Constant::from(SigmaProp::from(self.recipient.clone())));
+        let refund_owner = Expr::Const(Constant::from(SigmaProp::from(self.refund_owner.clone())));
+        let timelock_expired = Expr::BinOp(
+            BinOp {
+                kind: BinOpKind::Relation(RelationOp::Ge),
+                left: Box::new(Expr::GlobalVars(GlobalVars::Height)),
+                right: Box::new(Expr::Const(Constant::from(self.timelock_height))),
+            }
+            .into(),
+        );
+        let refund = Expr::SigmaAnd(
+            SigmaAnd::new(vec![
+                Expr::BoolToSigmaProp(BoolToSigmaProp {
+                    input: Box::new(timelock_expired),
+                }),
+                refund_owner,
+            ])
+            .unwrap(),
+        );
+        let prop = Expr::SigmaOr(SigmaOr::new(vec![recipient, refund]).unwrap());
+        ErgoTree::new(ErgoTreeHeader::v0(true), &prop).unwrap()
+    }
+
+    /// Recover parameters of the contract from the given tree.
+    /// Returns `None` if the tree is not an instance of the timelocked withdrawal contract.
+    pub fn try_from_ergo_tree(tree: &ErgoTree) -> Option<Self> {
+        let mut keys = vec![];
+        let mut timelock_height = None;
+        for ix in 0..tree.constants_len().ok()? {
+            let constant = tree.get_constant(ix).ok()??;
+            match constant.tpe {
+                SType::SSigmaProp => {
+                    keys.push(as_prove_dlog(constant.try_extract_into::<SigmaProp>().ok()?)?)
+                }
+                SType::SInt => timelock_height = Some(constant.try_extract_into::<i32>().ok()?),
+                _ => return None,
+            }
+        }
+        let [recipient, refund_owner] = <[ProveDlog; 2]>::try_from(keys).ok()?;
+        let candidate = Self {
+            recipient,
+            refund_owner,
+            timelock_height: timelock_height?,
+        };
+        // Make sure the tree has exactly the shape of the contract.
+        (candidate.ergo_tree() == *tree).then_some(candidate)
+    }
+}
+
+fn as_prove_dlog(prop: SigmaProp) -> Option<ProveDlog> {
+    match prop.value() {
+        SigmaBoolean::ProofOfKnowledge(SigmaProofOfKnowledgeTree::ProveDlog(pd)) => Some(pd.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use ergo_lib::ergo_chain_types::EcPoint;
+    use ergo_lib::ergotree_ir::chain::address::Address;
+    use ergo_lib::ergotree_ir::sigma_protocol::sigma_boolean::ProveDlog;
+    use k256::SecretKey;
+    use rand::rngs::OsRng;
+    use spectrum_crypto::digest::Blake2bDigest256;
+    use spectrum_ledger::cell::{BoxDestination, NativeCoin, SValue, TermCell, TermConstraints};
+    use spectrum_ledger::transaction::TxId;
+    use spectrum_ledger::ERGO_CHAIN_ID;
+    use spectrum_move::SerializedValue;
+
+    use crate::script::ErgoTermCell;
+    use crate::timelock::TimelockedWithdrawal;
+
+    fn random_prove_dlog() -> ProveDlog {
+        ProveDlog::new(EcPoint::from(
+            SecretKey::random(&mut OsRng).public_key().to_projective(),
+        ))
+    }
+
+    #[test]
+    fn recover_contract_params() {
+        let withdrawal = TimelockedWithdrawal {
+            recipient: random_prove_dlog(),
+            refund_owner: random_prove_dlog(),
+            timelock_height: 1_200_000,
+        };
+        let tree = withdrawal.ergo_tree();
+        assert_eq!(TimelockedWithdrawal::try_from_ergo_tree(&tree), Some(withdrawal));
+    }
+
+    #[test]
+    fn p2pk_is_not_timelocked() {
+        let tree = Address::P2Pk(random_prove_dlog()).script().unwrap();
+        assert_eq!(TimelockedWithdrawal::try_from_ergo_tree(&tree), None);
+    }
+
+    #[test]
+    fn term_cell_constraints_survive_conversion() {
+        let sec1 = || {
+            SerializedValue::from(
+                SecretKey::random(&mut OsRng)
+                    .public_key()
+                    .to_sec1_bytes()
+                    .to_vec(),
+            )
+        };
+        let cell = TermCell {
+            value: SValue {
+                native: NativeCoin::from(1_000_000_000),
+                assets: HashMap::new(),
+            },
+            tx_id: TxId::from(Blake2bDigest256::random()),
+            index: 0,
+            dst: BoxDestination {
+                target: ERGO_CHAIN_ID,
+                address: sec1(),
+                inputs: None,
+                constraints: Some(TermConstraints {
+                    timelock_height: 1_200_000,
+                    refund_owner: sec1(),
+                }),
+            },
+        };
+        let ergo_cell = ErgoTermCell::try_from(cell.clone()).unwrap();
+        assert!(matches!(ergo_cell.0.address, Address::P2S(_)));
+        assert_eq!(TermCell::from(ergo_cell).dst, cell.dst);
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use derivative::Derivative;
 use ergo_lib::{
     chain::transaction::Input,
@@ -5,6 +7,8 @@ use ergo_lib::{
 };
 use serde::{Deserialize, Serialize};
 use spectrum_chain_connector::{InboundValue, NotarizedReport, PendingTxIdentifier};
+use spectrum_ledger::cell::TermCell;
+use spectrum_offchain_lm::data::AsBox;
 
 use crate::{deposit::UnprocessedDeposit, script::ExtraErgoData};
 
@@ -16,6 +20,12 @@ pub trait Timestamped {
     fn get_timestamp(&self) -> i64;
 }
 
+/// Two TXs conflict if they can't both be included in the chain, i.e. they spend a common input,
+/// or if they must not be, i.e. they export a common term cell.
+pub trait Conflicts {
+    fn conflicts_with(&self, other: &Self) -> bool;
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
 pub enum TxInProgress {
     Withdrawal(WithdrawalInProgress),
@@ -30,6 +40,11 @@ pub struct WithdrawalInProgress {
     pub vault_utxo: ErgoBox,
     #[derivative(PartialEq = "ignore")]
     pub timestamp: i64,
+    /// Epoch of the committee which certified the withdrawal, i.e. the one guarding the vault
+    /// when the TX was built.
+    #[serde(default)]
+    #[derivative(PartialEq = "ignore")]
+    pub committee_epoch: i32,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, Derivative)]
@@ -70,3 +85,53 @@ impl Timestamped for TxInProgress {
         }
     }
 }
+
+impl TxInProgress {
+    fn vault_utxo(&self) -> &ErgoBox {
+        match self {
+            TxInProgress::Withdrawal(w) => &w.vault_utxo,
+            TxInProgress::Deposit(d) => &d.vault_utxo,
+        }
+    }
+
+    pub fn vault_utxo_signed_input(&self) -> &Input {
+        match self {
+            TxInProgress::Withdrawal(w) => &w.vault_utxo_signed_input,
+            TxInProgress::Deposit(d) => &d.vault_utxo_signed_input,
+        }
+    }
+}
+
+impl Conflicts for TxInProgress {
+    fn conflicts_with(&self, other: &Self) -> bool {
+        if self.vault_utxo().box_id() == other.vault_utxo().box_id() {
+            return true;
+        }
+        match (self, other) {
+            (TxInProgress::Deposit(a), TxInProgress::Deposit(b)) => {
+                a.unprocessed_deposits
+                    .iter()
+                    .any(|UnprocessedDeposit(AsBox(x, _))| {
+                        b.unprocessed_deposits
+                            .iter()
+                            .any(|UnprocessedDeposit(AsBox(y, _))| x.box_id() == y.box_id())
+                    })
+            }
+            (TxInProgress::Withdrawal(a), TxInProgress::Withdrawal(b)) => {
+                // Overlapping reports may be notarized in different rounds, e.g. after a restart of
+                // consensus-driver. Exporting both of them would pay the common cells out twice.
+                let cells = a
+                    .report
+                    .value_to_withdraw
+                    .iter()
+                    .map(TermCell::id)
+                    .collect::<HashSet<_>>();
+                b.report
+                    .value_to_withdraw
+                    .iter()
+                    .any(|cell| cells.contains(&cell.id()))
+            }
+            _ => false,
+        }
+    }
+}
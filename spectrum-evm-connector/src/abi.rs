@@ -0,0 +1,153 @@
+//! Minimal ABI encoding sufficient to talk to ERC-20 tokens and the vault contract.
+
+use tiny_keccak::{Hasher, Keccak};
+
+use crate::types::{EvmAddress, EvmHash};
+
+pub fn keccak256(bytes: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak::v256();
+    let mut out = [0u8; 32];
+    hasher.update(bytes);
+    hasher.finalize(&mut out);
+    out
+}
+
+/// Selector of the function with the given canonical signature, e.g. `transfer(address,uint256)`.
+pub fn selector(signature: &str) -> [u8; 4] {
+    let hash = keccak256(signature.as_bytes());
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+/// Topic of the event with the given canonical signature.
+pub fn event_topic(signature: &str) -> EvmHash {
+    EvmHash(keccak256(signature.as_bytes()))
+}
+
+pub const ERC20_TRANSFER_EVENT: &str = "Transfer(address,address,uint256)";
+pub const ERC20_TRANSFER_FN: &str = "transfer(address,uint256)";
+
+pub fn uint_word(n: u128) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[16..].copy_from_slice(&n.to_be_bytes());
+    word
+}
+
+/// Decode a `uint256` word. Values which don't fit into `u128` are rejected.
+pub fn decode_uint(word: &[u8]) -> Option<u128> {
+    if word.len() != 32 || word[..16].iter().any(|b| *b != 0) {
+        return None;
+    }
+    Some(u128::from_be_bytes(word[16..].try_into().unwrap()))
+}
+
+/// ABI value, only the types used by the connector are supported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Uint(u128),
+    Bool(bool),
+    Address(EvmAddress),
+    FixedBytes32([u8; 32]),
+    Bytes(Vec<u8>),
+    /// Tuple of static values.
+    StaticTuple(Vec<Token>),
+    /// Dynamic array of static values.
+    Array(Vec<Token>),
+}
+
+impl Token {
+    fn is_dynamic(&self) -> bool {
+        matches!(self, Token::Bytes(_) | Token::Array(_))
+    }
+
+    fn encode_static(&self, out: &mut Vec<u8>) {
+        match self {
+            Token::Uint(n) => out.extend_from_slice(&uint_word(*n)),
+            Token::Bool(b) => out.extend_from_slice(&uint_word(*b as u128)),
+            Token::Address(addr) => out.extend_from_slice(&addr.to_word()),
+            Token::FixedBytes32(bytes) => out.extend_from_slice(bytes),
+            Token::StaticTuple(items) => items.iter().for_each(|item| item.encode_static(out)),
+            Token::Bytes(_) | Token::Array(_) => unreachable!("Dynamic token"),
+        }
+    }
+
+    fn encode_tail(&self, out: &mut Vec<u8>) {
+        match self {
+            Token::Bytes(bytes) => {
+                out.extend_from_slice(&uint_word(bytes.len() as u128));
+                out.extend_from_slice(bytes);
+                let padding = (32 - bytes.len() % 32) % 32;
+                out.extend(std::iter::repeat(0u8).take(padding));
+            }
+            Token::Array(items) => {
+                out.extend_from_slice(&uint_word(items.len() as u128));
+                items.iter().for_each(|item| item.encode_static(out));
+            }
+            _ => unreachable!("Static token"),
+        }
+    }
+}
+
+/// Encode arguments of a function call.
+pub fn encode(tokens: &[Token]) -> Vec<u8> {
+    let head_size = 32
+        * tokens
+            .iter()
+            .map(|t| match t {
+                Token::StaticTuple(items) => items.len(),
+                _ => 1,
+            })
+            .sum::<usize>();
+    let mut head = Vec::with_capacity(head_size);
+    let mut tail = Vec::new();
+    for token in tokens {
+        if token.is_dynamic() {
+            head.extend_from_slice(&uint_word((head_size + tail.len()) as u128));
+            token.encode_tail(&mut tail);
+        } else {
+            token.encode_static(&mut head);
+        }
+    }
+    head.extend(tail);
+    head
+}
+
+pub fn encode_call(signature: &str, tokens: &[Token]) -> Vec<u8> {
+    let mut data = selector(signature).to_vec();
+    data.extend(encode(tokens));
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::abi::{encode, event_topic, selector, Token, ERC20_TRANSFER_EVENT, ERC20_TRANSFER_FN};
+    use crate::types::{EvmAddress, EvmHash};
+
+    #[test]
+    fn well_known_selectors() {
+        assert_eq!(hex::encode(selector(ERC20_TRANSFER_FN)), "a9059cbb");
+        assert_eq!(
+            event_topic(ERC20_TRANSFER_EVENT),
+            EvmHash::from_str("0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef").unwrap()
+        );
+    }
+
+    #[test]
+    fn dynamic_args_are_encoded_after_head() {
+        let addr = EvmAddress([1u8; 20]);
+        let encoded = encode(&[
+            Token::Uint(7),
+            Token::Bytes(vec![0xab; 33]),
+            Token::Array(vec![Token::StaticTuple(vec![
+                Token::Address(addr),
+                Token::Uint(1),
+            ])]),
+        ]);
+        // head: 3 words, bytes: length + 2 words, array: length + 2 words.
+        assert_eq!(encoded.len(), 32 * 9);
+        assert_eq!(encoded[63], 96);
+        assert_eq!(encoded[95], 96 + 96);
+        assert_eq!(encoded[96 + 31], 33);
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use log::{error, info};
+use spectrum_chain_connector::bridge::{self, BridgeReceiver, BridgeSender};
+use spectrum_chain_connector::{DataBridge, DataBridgeComponents, TxEvent};
+use spectrum_ledger::interop::Point;
+
+use crate::abi::{self, ERC20_TRANSFER_EVENT};
+use crate::deposit::EvmVaultTx;
+use crate::rpc::{EvmBlock, EvmRpcClient, RpcError};
+use crate::types::EvmHash;
+use crate::vault::VaultConfig;
+
+/// Number of the most recent events the bridge keeps to replay them to the consumer on resync.
+const REPLAY_CAPACITY: usize = 4096;
+
+pub struct EvmDataBridgeConfig {
+    pub rpc_url: String,
+    pub vault: VaultConfig,
+    /// Block to start sync'ing from.
+    pub starting_block: u64,
+    /// Number of the most recent blocks remembered to handle reorgs.
+    pub max_rollback_depth: usize,
+    /// How often the node is polled for new blocks.
+    pub poll_interval: Duration,
+}
+
+/// Streams TXs relevant to the vault from an Ethereum-compatible node.
+pub struct EvmDataBridge {
+    pub receiver: BridgeReceiver<EvmVaultTx>,
+    tx_start: tokio::sync::oneshot::Sender<()>,
+}
+
+impl DataBridge for EvmDataBridge {
+    type TxType = EvmVaultTx;
+
+    fn get_components(self) -> DataBridgeComponents<Self::TxType> {
+        DataBridgeComponents {
+            receiver: self.receiver,
+            start_signal: self.tx_start,
+        }
+    }
+}
+
+impl EvmDataBridge {
+    pub fn new(config: EvmDataBridgeConfig) -> Self {
+        let (tx, receiver) = bridge::channel(16, REPLAY_CAPACITY);
+        let (tx_start, rx_start) = tokio::sync::oneshot::channel();
+
+        tokio::spawn(run_bridge(tx, rx_start, config));
+
+        EvmDataBridge { receiver, tx_start }
+    }
+}
+
+/// Block applied by the bridge.
+struct AppliedBlock {
+    number: u64,
+    hash: EvmHash,
+    txs: Vec<EvmVaultTx>,
+}
+
+/// TXs of the given block relevant to the vault: calls of the vault and ERC-20 transfers to it.
+async fn vault_txs(
+    client: &EvmRpcClient,
+    config: &VaultConfig,
+    block: EvmBlock,
+) -> Result<Vec<EvmVaultTx>, RpcError> {
+    let token_addrs = config.tokens.iter().map(|t| t.address).collect::<Vec<_>>();
+    let token_transfers = if token_addrs.is_empty() {
+        vec![]
+    } else {
+        client
+            .logs(
+                block.hash,
+                &token_addrs,
+                &[
+                    Some(abi::event_topic(ERC20_TRANSFER_EVENT)),
+                    None,
+                    Some(EvmHash(config.vault.to_word())),
+                ],
+            )
+            .await?
+    };
+    let mut txs = vec![];
+    for tx in block.transactions {
+        let relevant =
+            tx.to == Some(config.vault) || token_transfers.iter().any(|log| log.transaction_hash == tx.hash);
+        if relevant {
+            let receipt = client
+                .receipt(tx.hash)
+                .await?
+                .ok_or_else(|| RpcError::Malformed(format!("No receipt of {}", tx.hash)))?;
+            txs.push(EvmVaultTx { tx, receipt });
+        }
+    }
+    Ok(txs)
+}
+
+async fn run_bridge(
+    mut tx: BridgeSender<EvmVaultTx>,
+    rx_start: tokio::sync::oneshot::Receiver<()>,
+    config: EvmDataBridgeConfig,
+) {
+    // Wait for signal to start
+    rx_start.await.unwrap();
+
+    let EvmDataBridgeConfig {
+        rpc_url,
+        vault,
+        starting_block,
+        max_rollback_depth,
+        poll_interval,
+    } = config;
+    let client = EvmRpcClient::new(rpc_url);
+    let mut applied: VecDeque<AppliedBlock> = VecDeque::new();
+    let mut next_block = starting_block;
+
+    loop {
+        let tip = match client.block_number().await {
+            Ok(tip) => tip,
+            Err(err) => {
+                error!("Failed to get tip of the chain: {}", err);
+                tokio::time::sleep(poll_interval).await;
+                continue;
+            }
+        };
+        if next_block > tip {
+            tokio::time::sleep(poll_interval).await;
+            continue;
+        }
+        let block = match client.block_by_number(next_block).await {
+            Ok(Some(block)) => block,
+            Ok(None) => {
+                tokio::time::sleep(poll_interval).await;
+                continue;
+            }
+            Err(err) => {
+                error!("Failed to get block {}: {}", next_block, err);
+                tokio::time::sleep(poll_interval).await;
+                continue;
+            }
+        };
+        if let Some(best) = applied.back() {
+            if best.hash != block.parent_hash {
+                // Reorg, unapply the best block and re-check its predecessor.
+                let best = applied.pop_back().unwrap();
+                info!("Rolling back block {} ({})", best.number, best.hash);
+                let point = Point::from(best.number);
+                for vtx in best.txs.into_iter().rev() {
+                    tx.send(point, TxEvent::UnappliedTx(vtx)).await.unwrap();
+                }
+                next_block = best.number;
+                if applied.is_empty() {
+                    error!(
+                        "Rollback is deeper than {} blocks, continuing from block {}",
+                        max_rollback_depth, next_block
+                    );
+                }
+                continue;
+            }
+        }
+        let number = next_block;
+        let hash = block.hash;
+        let txs = match vault_txs(&client, &vault, block).await {
+            Ok(txs) => txs,
+            Err(err) => {
+                error!("Failed to get TXs of block {}: {}", number, err);
+                tokio::time::sleep(poll_interval).await;
+                continue;
+            }
+        };
+        let point = Point::from(number);
+        for vtx in &txs {
+            tx.send(point, TxEvent::AppliedTx(vtx.clone())).await.unwrap();
+        }
+        applied.push_back(AppliedBlock { number, hash, txs });
+        if applied.len() > max_rollback_depth {
+            applied.pop_front();
+        }
+        next_block += 1;
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use spectrum_chain_connector::InboundValue;
+use spectrum_ledger::cell::{CustomAsset, NativeCoin, Owner, SValue};
+
+use crate::abi::{self, ERC20_TRANSFER_EVENT, ERC20_TRANSFER_FN};
+use crate::rpc::{EvmReceipt, EvmTx};
+use crate::types::{EvmAddress, EvmHash};
+use crate::vault::{asset_of, scale_down, VaultConfig};
+
+/// Size of a SEC1-encoded compressed public key of the Spectrum owner of a deposit.
+const OWNER_KEY_SIZE: usize = 33;
+
+/// Identifies a deposit on-chain.
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct EvmDepositRef {
+    pub tx_hash: EvmHash,
+    /// Index of the `Transfer` log of an ERC-20 deposit, `None` for a deposit of the native coin.
+    pub log_index: Option<u64>,
+}
+
+/// TX relevant to the vault along with its receipt.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct EvmVaultTx {
+    pub tx: EvmTx,
+    pub receipt: EvmReceipt,
+}
+
+/// Value moved into the vault by a TX.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Deposits {
+    /// Deposits credited to their owners on Spectrum.
+    pub inbound: Vec<InboundValue<EvmDepositRef>>,
+    /// Remainders of deposited amounts below the unit of Spectrum values which can't be credited,
+    /// per token, the zero address stands for the native coin.
+    pub dust: Vec<(EvmAddress, u128)>,
+}
+
+fn owner_of(key: &[u8]) -> Option<Owner> {
+    k256::PublicKey::from_sec1_bytes(key).ok().map(Owner::ProveDlog)
+}
+
+/// Deposits made by the given TX.
+///
+/// The native coin is deposited by a plain transfer to the vault with the compressed public key
+/// of the Spectrum owner as the TX input. ERC-20 tokens are deposited by a direct call of
+/// `transfer(vault, amount)` of the token with the public key of the owner appended to the
+/// arguments.
+///
+/// Amounts are rounded down to the unit of Spectrum values, the remainders are reported as dust.
+pub fn deposits(conf: &VaultConfig, vtx: &EvmVaultTx) -> Deposits {
+    let EvmVaultTx { tx, receipt } = vtx;
+    let mut deposits = Deposits::default();
+    if !receipt.succeeded() {
+        return deposits;
+    }
+    if tx.to == Some(conf.vault) && tx.value.0 > 0 {
+        match (owner_of(&tx.input.0), scale_down(tx.value.0, conf.native_scale)) {
+            (Some(owner), Some((amount, dust))) => {
+                if dust > 0 {
+                    deposits.dust.push((EvmAddress([0u8; 20]), dust));
+                }
+                if amount > 0 {
+                    deposits.inbound.push(InboundValue {
+                        value: SValue {
+                            native: NativeCoin::from(amount),
+                            assets: HashMap::new(),
+                        },
+                        owner,
+                        on_chain_identifier: EvmDepositRef {
+                            tx_hash: tx.hash,
+                            log_index: None,
+                        },
+                    })
+                }
+            }
+            _ => warn!("Unrecognized native transfer {} to the vault", tx.hash),
+        }
+    }
+    let transfer_topic = abi::event_topic(ERC20_TRANSFER_EVENT);
+    let transfer_call_size = 4 + 32 * 2;
+    for log in &receipt.logs {
+        let Some(token) = conf.token(&log.address) else {
+            continue;
+        };
+        if log.removed
+            || log.topics.len() != 3
+            || log.topics[0] != transfer_topic
+            || EvmAddress::from_word(&log.topics[2].0) != Some(conf.vault)
+        {
+            continue;
+        }
+        let owner = if tx.to == Some(token.address)
+            && tx.input.0.len() == transfer_call_size + OWNER_KEY_SIZE
+            && tx.input.0[..4] == abi::selector(ERC20_TRANSFER_FN)
+        {
+            owner_of(&tx.input.0[transfer_call_size..])
+        } else {
+            None
+        };
+        let amount = abi::decode_uint(&log.data.0).and_then(|amount| scale_down(amount, token.scale));
+        match (owner, amount) {
+            (Some(owner), Some((amount, dust))) => {
+                if dust > 0 {
+                    deposits.dust.push((token.address, dust));
+                }
+                if amount == 0 {
+                    continue;
+                }
+                let (policy, asset) = asset_of(&token.address);
+                deposits.inbound.push(InboundValue {
+                    value: SValue {
+                        native: NativeCoin::from(0),
+                        assets: HashMap::from([(
+                            policy,
+                            HashMap::from([(asset, CustomAsset::from(amount))]),
+                        )]),
+                    },
+                    owner,
+                    on_chain_identifier: EvmDepositRef {
+                        tx_hash: tx.hash,
+                        log_index: u64::try_from(log.log_index.0).ok(),
+                    },
+                })
+            }
+            _ => warn!(
+                "Unrecognized transfer of {} to the vault in {}",
+                token.address, tx.hash
+            ),
+        }
+    }
+    deposits
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::elliptic_curve::sec1::ToEncodedPoint;
+    use k256::SecretKey;
+    use spectrum_ledger::cell::{NativeCoin, Owner};
+    use spectrum_ledger::ChainId;
+
+    use crate::abi::{self, Token, ERC20_TRANSFER_EVENT, ERC20_TRANSFER_FN};
+    use crate::deposit::{deposits, EvmVaultTx};
+    use crate::rpc::{EvmLog, EvmReceipt, EvmTx};
+    use crate::types::{EvmAddress, EvmHash, HexBytes, Quantity};
+    use crate::vault::{asset_of, TokenConfig, VaultConfig};
+
+    fn conf() -> VaultConfig {
+        VaultConfig {
+            chain_id: ChainId::from(2),
+            vault: EvmAddress([1u8; 20]),
+            native_scale: 9,
+            tokens: vec![TokenConfig {
+                address: EvmAddress([2u8; 20]),
+                scale: 0,
+            }],
+        }
+    }
+
+    fn vault_tx(to: EvmAddress, value: u128, input: Vec<u8>, logs: Vec<EvmLog>) -> EvmVaultTx {
+        EvmVaultTx {
+            tx: EvmTx {
+                hash: EvmHash([0u8; 32]),
+                from: EvmAddress([9u8; 20]),
+                to: Some(to),
+                value: Quantity(value),
+                input: HexBytes(input),
+            },
+            receipt: EvmReceipt {
+                transaction_hash: EvmHash([0u8; 32]),
+                block_number: Quantity(1),
+                status: Quantity(1),
+                logs,
+            },
+        }
+    }
+
+    #[test]
+    fn native_and_token_deposits_are_recognized() {
+        let conf = conf();
+        let pk = SecretKey::random(&mut rand::thread_rng()).public_key();
+        let owner_key = pk.to_encoded_point(true).as_bytes().to_vec();
+
+        let native = deposits(
+            &conf,
+            &vault_tx(conf.vault, 3_000_000_000, owner_key.clone(), vec![]),
+        )
+        .inbound;
+        assert_eq!(native.len(), 1);
+        assert_eq!(native[0].value.native, NativeCoin::from(3));
+        assert_eq!(native[0].owner, Owner::ProveDlog(pk));
+
+        let token = conf.tokens[0].address;
+        let mut input = abi::encode_call(ERC20_TRANSFER_FN, &[Token::Address(conf.vault), Token::Uint(42)]);
+        input.extend(owner_key);
+        let log = EvmLog {
+            address: token,
+            topics: vec![
+                abi::event_topic(ERC20_TRANSFER_EVENT),
+                EvmHash(EvmAddress([9u8; 20]).to_word()),
+                EvmHash(conf.vault.to_word()),
+            ],
+            data: HexBytes(abi::uint_word(42).to_vec()),
+            transaction_hash: EvmHash([0u8; 32]),
+            log_index: Quantity(0),
+            removed: false,
+        };
+        let erc20 = deposits(&conf, &vault_tx(token, 0, input, vec![log])).inbound;
+        assert_eq!(erc20.len(), 1);
+        let (policy, asset) = asset_of(&token);
+        assert_eq!(u64::from(erc20[0].value.assets[&policy][&asset]), 42);
+    }
+
+    #[test]
+    fn remainders_of_deposits_are_reported_as_dust() {
+        let conf = conf();
+        let pk = SecretKey::random(&mut rand::thread_rng()).public_key();
+        let owner_key = pk.to_encoded_point(true).as_bytes().to_vec();
+
+        let floored = deposits(
+            &conf,
+            &vault_tx(conf.vault, 3_000_000_007, owner_key.clone(), vec![]),
+        );
+        assert_eq!(floored.inbound.len(), 1);
+        assert_eq!(floored.inbound[0].value.native, NativeCoin::from(3));
+        assert_eq!(floored.dust, vec![(EvmAddress([0u8; 20]), 7)]);
+
+        // Nothing to credit.
+        let dust_only = deposits(&conf, &vault_tx(conf.vault, 7, owner_key, vec![]));
+        assert!(dust_only.inbound.is_empty());
+        assert_eq!(dust_only.dust, vec![(EvmAddress([0u8; 20]), 7)]);
+    }
+}
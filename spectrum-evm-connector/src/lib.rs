@@ -0,0 +1,230 @@
+use std::collections::{HashMap, VecDeque};
+
+use spectrum_chain_connector::health::NodeHealth;
+use spectrum_chain_connector::ipc::RequestError;
+use spectrum_chain_connector::{
+    ChainTxEvent, ConnectorMsgOut, ConnectorRequest, ConnectorResponse, ConnectorStatus, NotarizedReport,
+    PendingTxIdentifier, PendingTxStatus, PendingWithdrawalStatus, SpectrumTx, SpectrumTxType, TxEvent,
+    TxStatus, VaultBalance,
+};
+use spectrum_ledger::cell::{ProgressPoint, SValue};
+use spectrum_ledger::interop::Point;
+
+use crate::deposit::{deposits, Deposits, EvmDepositRef, EvmVaultTx};
+use crate::rpc::EvmCall;
+use crate::store::{VaultState, VaultStateStore};
+use crate::types::EvmAddress;
+use crate::vault::{
+    add_value, empty_value, settled_withdrawal, sub_value, withdrawal_call, ExtraEvmData, VaultConfig,
+};
+
+pub mod abi;
+pub mod data_bridge;
+pub mod deposit;
+pub mod rpc;
+pub mod store;
+pub mod types;
+pub mod vault;
+
+pub type EvmConnectorRequest = ConnectorRequest<ExtraEvmData, EvmDepositRef>;
+
+/// Notarization bounds aren't proposed by the EVM connector yet, hence `()`.
+pub type EvmConnectorResponse = ConnectorResponse<ExtraEvmData, (), EvmDepositRef, ()>;
+
+pub type EvmConnectorMsgOut = ConnectorMsgOut<(), EvmDepositRef, ()>;
+
+/// Handles requests of the consensus-driver for an EVM chain.
+pub struct EvmConnector<S> {
+    conf: VaultConfig,
+    /// Account unlocked on the node which submits TXs to the vault.
+    operator: EvmAddress,
+    progress_point: ProgressPoint,
+    vault_balance: SValue,
+    /// Remainders of deposits which aren't credited to anyone, per token.
+    vault_dust: HashMap<EvmAddress, u128>,
+    store: S,
+    pending_withdrawals: Vec<NotarizedReport<ExtraEvmData>>,
+    calls_to_submit: VecDeque<EvmCall>,
+}
+
+impl<S: VaultStateStore> EvmConnector<S> {
+    /// Accounting of the vault is restored from the store if it was persisted before,
+    /// otherwise it starts from `starting_block` with an empty vault.
+    pub fn new(conf: VaultConfig, operator: EvmAddress, starting_block: u64, store: S) -> Self {
+        let state = store
+            .load()
+            .filter(|state| state.progress_point.chain_id == conf.chain_id)
+            .unwrap_or_else(|| VaultState {
+                progress_point: ProgressPoint {
+                    chain_id: conf.chain_id,
+                    point: Point::from(starting_block),
+                },
+                balance: empty_value(),
+                dust: HashMap::new(),
+            });
+        Self {
+            progress_point: state.progress_point,
+            conf,
+            operator,
+            vault_balance: state.balance,
+            vault_dust: state.dust,
+            store,
+            pending_withdrawals: vec![],
+            calls_to_submit: VecDeque::new(),
+        }
+    }
+
+    /// Remainders of deposits left in the vault which aren't credited to anyone.
+    pub fn dust(&self) -> &HashMap<EvmAddress, u128> {
+        &self.vault_dust
+    }
+
+    fn persist(&mut self) {
+        self.store.save(&VaultState {
+            progress_point: self.progress_point.clone(),
+            balance: self.vault_balance.clone(),
+            dust: self.vault_dust.clone(),
+        });
+    }
+
+    /// The oldest validated withdrawal which isn't submitted yet.
+    pub fn next_call_to_submit(&mut self) -> Option<EvmCall> {
+        self.calls_to_submit.pop_front()
+    }
+
+    /// Translate TX streamed by the data bridge into movements of value of the vault.
+    pub fn on_tx_event(&mut self, point: Point, event: TxEvent<EvmVaultTx>) -> Vec<EvmConnectorMsgOut> {
+        let (vtx, applied) = match event {
+            TxEvent::AppliedTx(vtx) => (vtx, true),
+            TxEvent::UnappliedTx(vtx) => (vtx, false),
+        };
+        self.progress_point.point = point;
+        let progress_point = self.progress_point.clone();
+        let mut txs = vec![];
+        let Deposits {
+            inbound: imported_value,
+            dust,
+        } = deposits(&self.conf, &vtx);
+        for (token, amount) in dust {
+            let entry = self.vault_dust.entry(token).or_default();
+            *entry = if applied {
+                entry.saturating_add(amount)
+            } else {
+                entry.saturating_sub(amount)
+            };
+        }
+        self.vault_dust.retain(|_, amount| *amount > 0);
+        if !imported_value.is_empty() {
+            for deposit in &imported_value {
+                if applied {
+                    add_value(&mut self.vault_balance, &deposit.value);
+                } else {
+                    sub_value(&mut self.vault_balance, &deposit.value);
+                }
+            }
+            txs.push(SpectrumTxType::Deposit {
+                imported_value,
+                vault_balance: self.balance(),
+            });
+        }
+        let withdrawn = settled_withdrawal(&self.conf, &vtx).and_then(|digest| {
+            self.pending_withdrawals
+                .iter()
+                .find(|report| report.authenticated_digest == digest)
+        });
+        if let Some(report) = withdrawn {
+            let withdrawn_value = report.value_to_withdraw.clone();
+            for cell in &withdrawn_value {
+                if applied {
+                    sub_value(&mut self.vault_balance, &cell.value);
+                } else {
+                    add_value(&mut self.vault_balance, &cell.value);
+                }
+            }
+            txs.push(SpectrumTxType::Withdrawal {
+                withdrawn_value,
+                vault_balance: self.balance(),
+            });
+        }
+        self.persist();
+        txs.into_iter()
+            .map(|tx_type| {
+                let tx = SpectrumTx {
+                    progress_point: progress_point.clone(),
+                    tx_type,
+                };
+                ConnectorMsgOut::TxEvent(if applied {
+                    ChainTxEvent::Applied(tx)
+                } else {
+                    ChainTxEvent::Unapplied(tx)
+                })
+            })
+            .collect()
+    }
+
+    fn balance(&self) -> VaultBalance<()> {
+        VaultBalance {
+            value: self.vault_balance.clone(),
+            on_chain_characteristics: (),
+        }
+    }
+
+    pub fn get_connector_status(&self) -> ConnectorStatus<ExtraEvmData, EvmDepositRef> {
+        ConnectorStatus::Synced {
+            current_progress_point: self.progress_point.clone(),
+            pending_txs: self
+                .pending_withdrawals
+                .iter()
+                .map(|report| {
+                    PendingTxStatus::Withdrawal(PendingWithdrawalStatus {
+                        identifier: report.clone(),
+                        status: TxStatus::WaitingForConfirmation,
+                    })
+                })
+                .collect(),
+            // Node health isn't monitored yet.
+            node_health: NodeHealth::default(),
+        }
+    }
+
+    pub fn handle_request(
+        &mut self,
+        request: EvmConnectorRequest,
+    ) -> Result<EvmConnectorResponse, RequestError> {
+        match request {
+            ConnectorRequest::SyncFrom(point) => {
+                if let Some(point) = point {
+                    if point.chain_id != self.conf.chain_id {
+                        return Err(RequestError::Invalid(format!(
+                            "Progress point of chain {:?}",
+                            point.chain_id
+                        )));
+                    }
+                    self.progress_point = point;
+                }
+            }
+            ConnectorRequest::ValidateAndProcessWithdrawals(report) => {
+                let call = withdrawal_call(&self.conf, self.operator, &report)
+                    .map_err(|e| RequestError::Invalid(e.to_string()))?;
+                self.calls_to_submit.push_back(call);
+                self.pending_withdrawals.push(*report);
+            }
+            ConnectorRequest::AcknowledgeConfirmedTx(PendingTxIdentifier::Withdrawal(report), _)
+            | ConnectorRequest::AcknowledgeAbortedTx(PendingTxIdentifier::Withdrawal(report), _) => {
+                self.pending_withdrawals.retain(|r| *r != *report);
+            }
+            // Deposits go straight to the vault, nothing to process.
+            ConnectorRequest::ProcessDeposits | ConnectorRequest::Disconnect => {}
+            other => {
+                return Err(RequestError::Invalid(format!(
+                    "{:?} isn't supported by the EVM connector yet",
+                    other
+                )))
+            }
+        }
+        Ok(ConnectorResponse {
+            status: self.get_connector_status(),
+            messages: vec![],
+        })
+    }
+}
@@ -0,0 +1,173 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::types::{encode_quantity, EvmAddress, EvmHash, HexBytes, Quantity};
+
+#[derive(Debug, thiserror::Error)]
+pub enum RpcError {
+    #[error("Transport error: {0}")]
+    Transport(#[from] reqwest::Error),
+    #[error("JSON-RPC error {code}: {message}")]
+    Rpc { code: i64, message: String },
+    #[error("Malformed response: {0}")]
+    Malformed(String),
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct EvmBlock {
+    pub number: Quantity,
+    pub hash: EvmHash,
+    pub parent_hash: EvmHash,
+    pub transactions: Vec<EvmTx>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct EvmTx {
+    pub hash: EvmHash,
+    pub from: EvmAddress,
+    /// `None` for contract creation.
+    pub to: Option<EvmAddress>,
+    pub value: Quantity,
+    pub input: HexBytes,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct EvmLog {
+    pub address: EvmAddress,
+    pub topics: Vec<EvmHash>,
+    pub data: HexBytes,
+    pub transaction_hash: EvmHash,
+    pub log_index: Quantity,
+    #[serde(default)]
+    pub removed: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct EvmReceipt {
+    pub transaction_hash: EvmHash,
+    pub block_number: Quantity,
+    /// `1` if the TX succeeded, `0` if reverted.
+    pub status: Quantity,
+    pub logs: Vec<EvmLog>,
+}
+
+impl EvmReceipt {
+    pub fn succeeded(&self) -> bool {
+        self.status == Quantity(1)
+    }
+}
+
+/// Call to a contract submitted on behalf of an account unlocked on the node.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct EvmCall {
+    pub from: EvmAddress,
+    pub to: EvmAddress,
+    pub data: HexBytes,
+}
+
+#[derive(Deserialize)]
+struct RpcResponse<T> {
+    result: Option<T>,
+    error: Option<RpcErrorObject>,
+}
+
+#[derive(Deserialize)]
+struct RpcErrorObject {
+    code: i64,
+    message: String,
+}
+
+/// Client of the Ethereum JSON-RPC API.
+pub struct EvmRpcClient {
+    url: String,
+    http: reqwest::Client,
+    next_id: AtomicU64,
+}
+
+impl EvmRpcClient {
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            http: reqwest::Client::new(),
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    async fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<Option<T>, RpcError> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": self.next_id.fetch_add(1, Ordering::Relaxed),
+            "method": method,
+            "params": params,
+        });
+        let response: RpcResponse<T> = self
+            .http
+            .post(&self.url)
+            .json(&request)
+            .send()
+            .await?
+            .json()
+            .await?;
+        match response.error {
+            Some(RpcErrorObject { code, message }) => Err(RpcError::Rpc { code, message }),
+            None => Ok(response.result),
+        }
+    }
+
+    pub async fn block_number(&self) -> Result<u64, RpcError> {
+        let n: Quantity = self
+            .call("eth_blockNumber", json!([]))
+            .await?
+            .ok_or_else(|| RpcError::Malformed("eth_blockNumber: empty result".to_string()))?;
+        u64::try_from(n.0).map_err(|_| RpcError::Malformed(format!("Block number {}", n.0)))
+    }
+
+    /// Block with full TX objects.
+    pub async fn block_by_number(&self, number: u64) -> Result<Option<EvmBlock>, RpcError> {
+        self.call(
+            "eth_getBlockByNumber",
+            json!([encode_quantity(number as u128), true]),
+        )
+        .await
+    }
+
+    pub async fn receipt(&self, tx_hash: EvmHash) -> Result<Option<EvmReceipt>, RpcError> {
+        self.call("eth_getTransactionReceipt", json!([tx_hash])).await
+    }
+
+    /// Logs of the given block emitted by one of `addresses` and matching the given topics.
+    /// `None` matches any topic at the given position.
+    pub async fn logs(
+        &self,
+        block_hash: EvmHash,
+        addresses: &[EvmAddress],
+        topics: &[Option<EvmHash>],
+    ) -> Result<Vec<EvmLog>, RpcError> {
+        let filter = json!({
+            "blockHash": block_hash,
+            "address": addresses,
+            "topics": topics,
+        });
+        Ok(self
+            .call("eth_getLogs", json!([filter]))
+            .await?
+            .unwrap_or_default())
+    }
+
+    pub async fn send_transaction(&self, call: &EvmCall) -> Result<EvmHash, RpcError> {
+        self.call("eth_sendTransaction", json!([call]))
+            .await?
+            .ok_or_else(|| RpcError::Malformed("eth_sendTransaction: empty result".to_string()))
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use spectrum_ledger::cell::{ProgressPoint, SValue};
+
+use crate::types::EvmAddress;
+
+static VAULT_STATE_KEY: &str = "VAULT_STATE";
+
+/// Accounting of the vault which must survive restarts of the connector.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct VaultState {
+    /// Point the balance is accounted up to.
+    pub progress_point: ProgressPoint,
+    pub balance: SValue,
+    /// Remainders of deposits below the unit of Spectrum values, per token,
+    /// the zero address stands for the native coin.
+    pub dust: HashMap<EvmAddress, u128>,
+}
+
+pub trait VaultStateStore {
+    fn load(&self) -> Option<VaultState>;
+    fn save(&mut self, state: &VaultState);
+}
+
+pub struct VaultStateRocksDB {
+    db: rocksdb::DB,
+}
+
+impl VaultStateRocksDB {
+    pub fn new(db_path: &str) -> Self {
+        Self {
+            db: rocksdb::DB::open_default(db_path).unwrap(),
+        }
+    }
+}
+
+impl VaultStateStore for VaultStateRocksDB {
+    fn load(&self) -> Option<VaultState> {
+        self.db
+            .get(VAULT_STATE_KEY)
+            .unwrap()
+            .map(|bytes| bincode::deserialize(&bytes).unwrap())
+    }
+
+    fn save(&mut self, state: &VaultState) {
+        self.db
+            .put(VAULT_STATE_KEY, bincode::serialize(state).unwrap())
+            .unwrap();
+    }
+}
+
+#[derive(Default)]
+pub struct InMemoryVaultState(Option<VaultState>);
+
+impl VaultStateStore for InMemoryVaultState {
+    fn load(&self) -> Option<VaultState> {
+        self.0.clone()
+    }
+
+    fn save(&mut self, state: &VaultState) {
+        self.0 = Some(state.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rand::RngCore;
+    use spectrum_ledger::cell::{NativeCoin, ProgressPoint, SValue};
+    use spectrum_ledger::interop::Point;
+    use spectrum_ledger::ChainId;
+
+    use crate::store::{VaultState, VaultStateRocksDB, VaultStateStore};
+    use crate::types::EvmAddress;
+
+    #[test]
+    fn state_survives_reopening() {
+        let db_path = format!("./tmp/{}", rand::thread_rng().next_u32());
+        let state = VaultState {
+            progress_point: ProgressPoint {
+                chain_id: ChainId::from(2),
+                point: Point::from(10),
+            },
+            balance: SValue {
+                native: NativeCoin::from(3),
+                assets: HashMap::new(),
+            },
+            dust: HashMap::from([(EvmAddress([0u8; 20]), 7)]),
+        };
+        {
+            let mut store = VaultStateRocksDB::new(&db_path);
+            assert_eq!(store.load(), None);
+            store.save(&state);
+        }
+        assert_eq!(VaultStateRocksDB::new(&db_path).load(), Some(state));
+    }
+}
@@ -0,0 +1,158 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum HexError {
+    #[error("Missing 0x prefix: {0}")]
+    MissingPrefix(String),
+    #[error("Invalid hex: {0}")]
+    Invalid(String),
+    #[error("Expected {expected} bytes, got {actual}")]
+    InvalidLength { expected: usize, actual: usize },
+    #[error("Quantity out of range: {0}")]
+    OutOfRange(String),
+}
+
+/// Decode `0x`-prefixed hex string.
+pub fn decode_hex(s: &str) -> Result<Vec<u8>, HexError> {
+    let digits = s
+        .strip_prefix("0x")
+        .ok_or_else(|| HexError::MissingPrefix(s.to_string()))?;
+    hex::decode(digits).map_err(|_| HexError::Invalid(s.to_string()))
+}
+
+pub fn encode_hex(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+/// Decode a JSON-RPC quantity, e.g. `0x1a`.
+pub fn decode_quantity(s: &str) -> Result<u128, HexError> {
+    let digits = s
+        .strip_prefix("0x")
+        .ok_or_else(|| HexError::MissingPrefix(s.to_string()))?;
+    u128::from_str_radix(digits, 16).map_err(|_| HexError::OutOfRange(s.to_string()))
+}
+
+pub fn encode_quantity(n: u128) -> String {
+    format!("0x{:x}", n)
+}
+
+macro_rules! fixed_bytes {
+    ($name:ident, $len:expr) => {
+        #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $name(pub [u8; $len]);
+
+        impl TryFrom<&[u8]> for $name {
+            type Error = HexError;
+            fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+                <[u8; $len]>::try_from(bytes)
+                    .map($name)
+                    .map_err(|_| HexError::InvalidLength {
+                        expected: $len,
+                        actual: bytes.len(),
+                    })
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = HexError;
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                Self::try_from(decode_hex(s)?.as_slice())
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+                f.write_str(&encode_hex(&self.0))
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(&self.to_string())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let s = String::deserialize(deserializer)?;
+                $name::from_str(&s).map_err(serde::de::Error::custom)
+            }
+        }
+    };
+}
+
+fixed_bytes!(EvmAddress, 20);
+fixed_bytes!(EvmHash, 32);
+
+impl EvmAddress {
+    /// Address left-padded to a 32-byte word, as found in indexed topics of logs.
+    pub fn to_word(&self) -> [u8; 32] {
+        let mut word = [0u8; 32];
+        word[12..].copy_from_slice(&self.0);
+        word
+    }
+
+    pub fn from_word(word: &[u8; 32]) -> Option<Self> {
+        if word[..12].iter().all(|b| *b == 0) {
+            EvmAddress::try_from(&word[12..]).ok()
+        } else {
+            None
+        }
+    }
+}
+
+/// JSON-RPC quantity.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Quantity(pub u128);
+
+impl Serialize for Quantity {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&encode_quantity(self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for Quantity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        decode_quantity(&s)
+            .map(Quantity)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Arbitrary `0x`-prefixed binary data.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct HexBytes(pub Vec<u8>);
+
+impl Serialize for HexBytes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&encode_hex(&self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for HexBytes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        decode_hex(&s).map(HexBytes).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::types::{decode_quantity, EvmAddress};
+
+    #[test]
+    fn address_roundtrip() {
+        let s = "0x00000000219ab540356cbb839cbe05303d7705fa";
+        let addr = EvmAddress::from_str(s).unwrap();
+        assert_eq!(addr.to_string(), s);
+        assert_eq!(EvmAddress::from_word(&addr.to_word()), Some(addr));
+        assert!(EvmAddress::from_str("0x00").is_err());
+        assert_eq!(decode_quantity("0x1a"), Ok(26));
+    }
+}
@@ -0,0 +1,377 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use spectrum_chain_connector::NotarizedReport;
+use spectrum_crypto::digest::Blake2bDigest256;
+use spectrum_crypto::merkle::{MerkleProof, Side};
+use spectrum_ledger::cell::{AssetId, CustomAsset, NativeCoin, PolicyId, SValue, TermCell};
+use spectrum_ledger::interop::ReportCertificate;
+use spectrum_ledger::ChainId;
+
+use crate::abi::{self, Token};
+use crate::deposit::EvmVaultTx;
+use crate::rpc::EvmCall;
+use crate::types::{EvmAddress, HexBytes};
+
+/// Canonical signature of the withdrawal function of the vault contract. Each transfer is
+/// `(token, recipient, amount)`, the zero token address stands for the native coin.
+/// Reports notarized in a batch come with siblings on the path to the root of the batch and
+/// a bitmask of their sides, bit `i` is set if the `i`-th sibling is on the left.
+/// Both are empty if the report was notarized alone.
+///
+/// The certificate is passed as `(message_digest, commitment_parity, commitment_x, response)`
+/// followed by the exclusion set of `(index, has_commitment, commitment_parity, commitment_x,
+/// signature_r, signature_s)`. Points are compressed, the parity is the SEC1 prefix (`2` or `3`).
+pub const VAULT_WITHDRAW_FN: &str = "withdraw(bytes32,uint64,(address,address,uint256)[],\
+     (bytes32,uint8,bytes32,bytes32),(uint256,bool,uint8,bytes32,bytes32,bytes32)[],bytes32[],uint256)";
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct TokenConfig {
+    pub address: EvmAddress,
+    /// Amounts of the token are divided by `10^scale` when converted into Spectrum values.
+    pub scale: u32,
+}
+
+/// Vault contract deployed on a particular EVM chain.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct VaultConfig {
+    /// Id of the chain within Spectrum. Allows to serve multiple EVM chains with the same connector.
+    pub chain_id: ChainId,
+    pub vault: EvmAddress,
+    /// Amounts of the native coin are divided by `10^native_scale` when converted into
+    /// Spectrum values, e.g. `9` to account ETH in gwei.
+    pub native_scale: u32,
+    /// ERC-20 tokens accepted by the vault.
+    pub tokens: Vec<TokenConfig>,
+}
+
+impl VaultConfig {
+    pub fn token(&self, address: &EvmAddress) -> Option<&TokenConfig> {
+        self.tokens.iter().find(|t| t.address == *address)
+    }
+}
+
+/// Chain-specific data of a notarized report.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ExtraEvmData {
+    /// Nonce of the withdrawal, the vault contract rejects reused nonces.
+    pub nonce: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum EvmReportError {
+    #[error("Term cell is destined to chain {0:?}")]
+    ForeignDestination(ChainId),
+    #[error("Invalid recipient address")]
+    InvalidRecipient,
+    #[error("Term constraints aren't supported by the vault")]
+    UnsupportedConstraints,
+    #[error("Unknown asset {0:?}")]
+    UnknownAsset(AssetId),
+    #[error("Amount overflow")]
+    AmountOverflow,
+    #[error("Authenticated digest must be 32 bytes")]
+    InvalidDigest,
+    #[error("Inclusion proof is too deep")]
+    InclusionProofTooDeep,
+}
+
+/// ERC-20 tokens are represented by assets of the zero policy with id of the token address
+/// left-padded to 32 bytes.
+pub fn asset_of(token: &EvmAddress) -> (PolicyId, AssetId) {
+    (
+        PolicyId::from(Blake2bDigest256::zero()),
+        AssetId::from(Blake2bDigest256::try_from(token.to_word().to_vec()).unwrap()),
+    )
+}
+
+/// Inverse of [`asset_of`].
+pub fn token_of(policy: &PolicyId, asset: &AssetId) -> Option<EvmAddress> {
+    if *policy != PolicyId::from(Blake2bDigest256::zero()) {
+        return None;
+    }
+    EvmAddress::from_word(Blake2bDigest256::from(*asset).raw())
+}
+
+/// Convert on-chain amount into Spectrum amount, rounding down. The remainder below `10^scale`
+/// is returned as dust, so that it can be accounted by the connector.
+pub fn scale_down(amount: u128, scale: u32) -> Option<(u64, u128)> {
+    let unit = 10u128.checked_pow(scale)?;
+    u64::try_from(amount / unit)
+        .ok()
+        .map(|scaled| (scaled, amount % unit))
+}
+
+pub fn scale_up(amount: u64, scale: u32) -> Option<u128> {
+    10u128.checked_pow(scale)?.checked_mul(amount as u128)
+}
+
+pub fn empty_value() -> SValue {
+    SValue {
+        native: NativeCoin::from(0),
+        assets: HashMap::new(),
+    }
+}
+
+pub fn add_value(balance: &mut SValue, value: &SValue) {
+    balance.native = NativeCoin::from(u64::from(balance.native).saturating_add(u64::from(value.native)));
+    for (policy, assets) in &value.assets {
+        let balance_assets = balance.assets.entry(*policy).or_default();
+        for (asset, amount) in assets {
+            let entry = balance_assets.entry(*asset).or_insert(CustomAsset::from(0));
+            *entry = CustomAsset::from(u64::from(*entry).saturating_add(u64::from(*amount)));
+        }
+    }
+}
+
+pub fn sub_value(balance: &mut SValue, value: &SValue) {
+    balance.native = NativeCoin::from(u64::from(balance.native).saturating_sub(u64::from(value.native)));
+    for (policy, assets) in &value.assets {
+        if let Some(balance_assets) = balance.assets.get_mut(policy) {
+            for (asset, amount) in assets {
+                if let Some(entry) = balance_assets.get_mut(asset) {
+                    *entry = CustomAsset::from(u64::from(*entry).saturating_sub(u64::from(*amount)));
+                }
+            }
+            balance_assets.retain(|_, amount| u64::from(*amount) > 0);
+        }
+    }
+    balance.assets.retain(|_, assets| !assets.is_empty());
+}
+
+fn transfers(conf: &VaultConfig, cell: &TermCell) -> Result<Vec<Token>, EvmReportError> {
+    if cell.dst.target != conf.chain_id {
+        return Err(EvmReportError::ForeignDestination(cell.dst.target));
+    }
+    if cell.dst.constraints.is_some() {
+        return Err(EvmReportError::UnsupportedConstraints);
+    }
+    let recipient_bytes: Vec<u8> = cell.dst.address.clone().into();
+    let recipient =
+        EvmAddress::try_from(recipient_bytes.as_slice()).map_err(|_| EvmReportError::InvalidRecipient)?;
+    let transfer = |token: EvmAddress, amount: u128| {
+        Token::StaticTuple(vec![
+            Token::Address(token),
+            Token::Address(recipient),
+            Token::Uint(amount),
+        ])
+    };
+    let mut transfers = vec![];
+    let native = u64::from(cell.value.native);
+    if native > 0 {
+        let amount = scale_up(native, conf.native_scale).ok_or(EvmReportError::AmountOverflow)?;
+        transfers.push(transfer(EvmAddress([0u8; 20]), amount));
+    }
+    for (policy, assets) in &cell.value.assets {
+        for (asset, amount) in assets {
+            let token = token_of(policy, asset)
+                .and_then(|addr| conf.token(&addr))
+                .ok_or(EvmReportError::UnknownAsset(*asset))?;
+            let amount = scale_up(u64::from(*amount), token.scale).ok_or(EvmReportError::AmountOverflow)?;
+            transfers.push(transfer(token.address, amount));
+        }
+    }
+    Ok(transfers)
+}
+
+/// Siblings on the path to the root of the batch along with the bitmask of their sides.
+fn inclusion_proof(proof: Option<&MerkleProof>) -> Result<(Token, Token), EvmReportError> {
+    let path = proof.map_or(&[][..], |proof| proof.path.as_slice());
+    if path.len() > u128::BITS as usize {
+        return Err(EvmReportError::InclusionProofTooDeep);
+    }
+    let mut sides = 0u128;
+    let mut siblings = vec![];
+    for (i, node) in path.iter().enumerate() {
+        if node.side == Side::Left {
+            sides |= 1 << i;
+        }
+        siblings.push(Token::FixedBytes32(*node.sibling.raw()));
+    }
+    Ok((Token::Array(siblings), Token::Uint(sides)))
+}
+
+/// Compressed SEC1 point split into the parity prefix and `x`.
+fn point(sec1: &[u8]) -> [Token; 2] {
+    [
+        Token::Uint(sec1[0] as u128),
+        Token::FixedBytes32(sec1[1..].try_into().unwrap()),
+    ]
+}
+
+/// Fields of the certificate and its exclusion set in the layout expected by the vault contract.
+fn certificate(certificate: &ReportCertificate) -> (Token, Token) {
+    let ReportCertificate::SchnorrK256(cert) = certificate;
+    let mut fields = vec![Token::FixedBytes32(*cert.message_digest.raw())];
+    fields.extend(point(&cert.aggregate_commitment.clone().to_bytes()));
+    fields.push(Token::FixedBytes32(
+        cert.aggregate_response.to_bytes().as_slice().try_into().unwrap(),
+    ));
+    let exclusion_set = cert
+        .exclusion_set
+        .iter()
+        .map(|(ix, pair)| {
+            let mut fields = vec![Token::Uint(*ix as u128), Token::Bool(pair.is_some())];
+            match pair {
+                Some((commitment, signature)) => {
+                    let signature = Vec::<u8>::from(signature.clone());
+                    fields.extend(point(&commitment.as_bytes()));
+                    fields.push(Token::FixedBytes32(signature[..32].try_into().unwrap()));
+                    fields.push(Token::FixedBytes32(signature[32..].try_into().unwrap()));
+                }
+                None => {
+                    fields.push(Token::Uint(0));
+                    fields.extend(std::iter::repeat(Token::FixedBytes32([0u8; 32])).take(3));
+                }
+            }
+            Token::StaticTuple(fields)
+        })
+        .collect();
+    (Token::StaticTuple(fields), Token::Array(exclusion_set))
+}
+
+/// Call of the vault contract exporting value to the recipients of the given report.
+pub fn withdrawal_call(
+    conf: &VaultConfig,
+    operator: EvmAddress,
+    report: &NotarizedReport<ExtraEvmData>,
+) -> Result<EvmCall, EvmReportError> {
+    let digest = <[u8; 32]>::try_from(report.authenticated_digest.as_slice())
+        .map_err(|_| EvmReportError::InvalidDigest)?;
+    let mut all_transfers = vec![];
+    for cell in &report.value_to_withdraw {
+        all_transfers.extend(transfers(conf, cell)?);
+    }
+    // The vault contract is expected to verify the certificate against the digest.
+    let (certificate, exclusion_set) = certificate(&report.certificate);
+    let (siblings, sides) = inclusion_proof(report.inclusion_proof.as_ref())?;
+    let data = abi::encode_call(
+        VAULT_WITHDRAW_FN,
+        &[
+            Token::FixedBytes32(digest),
+            Token::Uint(report.additional_chain_data.nonce as u128),
+            Token::Array(all_transfers),
+            certificate,
+            exclusion_set,
+            siblings,
+            sides,
+        ],
+    );
+    Ok(EvmCall {
+        from: operator,
+        to: conf.vault,
+        data: HexBytes(data),
+    })
+}
+
+/// Digest of the report settled by the given TX if it is a successful withdrawal from the vault.
+pub fn settled_withdrawal(conf: &VaultConfig, vtx: &EvmVaultTx) -> Option<[u8; 32]> {
+    let input = &vtx.tx.input.0;
+    if vtx.tx.to != Some(conf.vault)
+        || !vtx.receipt.succeeded()
+        || input.len() < 36
+        || input[..4] != abi::selector(VAULT_WITHDRAW_FN)
+    {
+        return None;
+    }
+    input[4..36].try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::SecretKey;
+    use rand::rngs::OsRng;
+    use spectrum_crypto::digest::{Blake2b256, Blake2bDigest256};
+    use spectrum_crypto::merkle::{leaf_hash, MerkleTree};
+    use spectrum_crypto::pubkey::PublicKey;
+    use spectrum_ledger::interop::ReportCertificate;
+    use spectrum_sigma::crypto::{exclusion_proof, schnorr_commitment_pair};
+    use spectrum_sigma::sigma_aggregation::AggregateCertificate;
+    use spectrum_sigma::AggregateCommitment;
+
+    use crate::abi::Token;
+    use crate::types::EvmAddress;
+    use crate::vault::{asset_of, certificate, inclusion_proof, scale_down, scale_up, token_of};
+
+    #[test]
+    fn token_asset_roundtrip() {
+        let token = EvmAddress([7u8; 20]);
+        let (policy, asset) = asset_of(&token);
+        assert_eq!(token_of(&policy, &asset), Some(token));
+    }
+
+    #[test]
+    fn amounts_are_floored_into_dust() {
+        assert_eq!(scale_down(5_000_000_000, 9), Some((5, 0)));
+        assert_eq!(scale_down(5_000_000_001, 9), Some((5, 1)));
+        assert_eq!(scale_down(999, 9), Some((0, 999)));
+        assert_eq!(scale_down(u128::MAX, 0), None);
+        assert_eq!(scale_up(5, 9), Some(5_000_000_000));
+    }
+
+    #[test]
+    fn inclusion_proof_sides_are_packed_into_bitmask() {
+        let leaves = vec![leaf_hash(b"a"), leaf_hash(b"b"), leaf_hash(b"c")];
+        let tree = MerkleTree::new(leaves.clone());
+        // The last leaf has no sibling at the bottom level, so it's carried over.
+        let proof = tree.proof(2).unwrap();
+        let (siblings, sides) = inclusion_proof(Some(&proof)).unwrap();
+        assert_eq!(
+            siblings,
+            Token::Array(vec![Token::FixedBytes32(*proof.path[0].sibling.raw())])
+        );
+        assert_eq!(sides, Token::Uint(0b1));
+        assert_eq!(
+            inclusion_proof(None).unwrap(),
+            (Token::Array(vec![]), Token::Uint(0))
+        );
+    }
+
+    #[test]
+    fn certificate_is_abi_encoded_field_by_field() {
+        let md = Blake2bDigest256::random();
+        let (secret, commitment) = schnorr_commitment_pair();
+        let sig = exclusion_proof::<Blake2b256>(secret, md);
+        let cert = ReportCertificate::SchnorrK256(AggregateCertificate {
+            message_digest: md,
+            aggregate_commitment: AggregateCommitment::from(PublicKey::from(SecretKey::random(&mut OsRng))),
+            aggregate_response: k256::Scalar::ONE,
+            exclusion_set: vec![(0, None), (2, Some((commitment.clone(), sig.clone())))],
+        });
+        let (head, exclusion_set) = certificate(&cert);
+        let Token::StaticTuple(fields) = head else {
+            panic!("Certificate must be a static tuple");
+        };
+        assert_eq!(fields.len(), 4);
+        assert_eq!(fields[0], Token::FixedBytes32(*md.raw()));
+        assert!(matches!(fields[1], Token::Uint(2) | Token::Uint(3)));
+        let mut response = [0u8; 32];
+        response[31] = 1;
+        assert_eq!(fields[3], Token::FixedBytes32(response));
+        let Token::Array(entries) = exclusion_set else {
+            panic!("Exclusion set must be an array");
+        };
+        assert_eq!(
+            entries[0],
+            Token::StaticTuple(vec![
+                Token::Uint(0),
+                Token::Bool(false),
+                Token::Uint(0),
+                Token::FixedBytes32([0u8; 32]),
+                Token::FixedBytes32([0u8; 32]),
+                Token::FixedBytes32([0u8; 32]),
+            ])
+        );
+        let Token::StaticTuple(excluded) = &entries[1] else {
+            panic!("Exclusion entry must be a static tuple");
+        };
+        assert_eq!(excluded[..2], [Token::Uint(2), Token::Bool(true)]);
+        assert_eq!(
+            excluded[3],
+            Token::FixedBytes32(commitment.as_bytes()[1..].try_into().unwrap())
+        );
+        let sig = Vec::<u8>::from(sig);
+        assert_eq!(excluded[4], Token::FixedBytes32(sig[..32].try_into().unwrap()));
+        assert_eq!(excluded[5], Token::FixedBytes32(sig[32..].try_into().unwrap()));
+    }
+}
@@ -1,4 +1,5 @@
 use spectrum_crypto::digest::{blake2b256_hash, Blake2bDigest256, Digest};
+use spectrum_crypto::merkle::{leaf_hash, MerkleTree};
 use spectrum_crypto::pubkey::PublicKey;
 
 use crate::interop::{ReportBody, ReportCertificate};
@@ -89,9 +90,34 @@ pub struct BlockBody {
     pub witnesses: Vec<Witness>,
 }
 
+fn encoded_leaf<T: serde::Serialize>(item: &T) -> Blake2bDigest256 {
+    let mut encoded = Vec::new();
+    ciborium::ser::into_writer(item, &mut encoded).unwrap();
+    leaf_hash(&encoded)
+}
+
 impl SystemDigest for BlockBody {
+    /// Root hash of the Merkle Tree over reports, certificates, transactions and witnesses
+    /// of the block, in this order. Empty body hashes to zero.
     fn digest(&self) -> Blake2bDigest256 {
-        todo!("Use root hash of the Merkle Tree here")
+        let leaves = self
+            .reports
+            .iter()
+            .map(encoded_leaf)
+            .chain(self.certificates.iter().map(encoded_leaf))
+            .chain(self.txs.iter().map(encoded_leaf))
+            .chain(self.witnesses.iter().map(encoded_leaf))
+            .collect();
+        MerkleTree::new(leaves).root().unwrap_or(Digest::zero())
+    }
+}
+
+impl Modifier for BlockBody {
+    fn id(&self) -> ModifierId {
+        self.digest().into()
+    }
+    fn tpe() -> ModifierType {
+        ModifierType::BlockBody
     }
 }
 
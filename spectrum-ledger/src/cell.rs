@@ -47,6 +47,16 @@ pub enum CellPtr {
     Ref(CellRef),
 }
 
+impl CellPtr {
+    /// Stable identifier of the cell regardless of its version.
+    pub fn cell_id(&self) -> CellId {
+        match self {
+            CellPtr::Id(id) => *id,
+            CellPtr::Ref(CellRef(id, _)) => *id,
+        }
+    }
+}
+
 #[derive(
     Eq,
     PartialEq,
@@ -74,7 +84,9 @@ pub struct PolicyId(Blake2bDigest256);
 )]
 pub struct AssetId(Blake2bDigest256);
 
-#[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(
+    Eq, PartialEq, Ord, PartialOrd, Copy, Clone, From, Into, Hash, Debug, serde::Serialize, serde::Deserialize,
+)]
 pub struct AssetRef(PolicyId, AssetId);
 
 #[derive(Eq, PartialEq, Clone, Debug, serde::Serialize, serde::Deserialize)]
@@ -93,11 +105,15 @@ impl From<SerializedModule> for ScriptHash {
 }
 
 /// Where the script source can be found.
-#[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Hash, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(
+    Eq, PartialEq, Ord, PartialOrd, Copy, Clone, From, Into, Hash, Debug, serde::Serialize, serde::Deserialize,
+)]
 pub struct ScriptRef(CellRef);
 
 /// Where the datum source can be found.
-#[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Hash, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(
+    Eq, PartialEq, Ord, PartialOrd, Copy, Clone, From, Into, Hash, Debug, serde::Serialize, serde::Deserialize,
+)]
 pub struct DatumRef(CellRef);
 
 #[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Debug, serde::Serialize, serde::Deserialize)]
@@ -113,11 +129,24 @@ pub struct DatumHash(Blake2bDigest256);
 #[derive(Eq, PartialEq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct BridgeInputs(Witness);
 
+/// Spending conditions of the output created for a term cell on the destination chain.
+#[derive(Eq, PartialEq, Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct TermConstraints {
+    /// Height of the destination chain starting from which the value of the cell,
+    /// if still unclaimed, can be reclaimed by `refund_owner`.
+    pub timelock_height: u64,
+    /// Address on the destination chain the value is refunded to.
+    pub refund_owner: SerializedValue,
+}
+
 #[derive(Eq, PartialEq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct BoxDestination {
     pub target: ChainId,
     pub address: SerializedValue,
     pub inputs: Option<BridgeInputs>,
+    /// Additional conditions to lock the output with. The output is claimable by `address`
+    /// with no further restrictions if absent.
+    pub constraints: Option<TermConstraints>,
 }
 
 /// Progress point on external chain.
@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+use crate::cell::{AssetRef, CustomAsset, NativeCoin};
+
+/// How an amount of some asset is presented to humans.
+#[derive(Eq, PartialEq, Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "RawDenomination")]
+pub struct Denomination {
+    ticker: String,
+    /// Number of decimal places between the base unit and the display unit,
+    /// e.g. 9 for ERG (1 ERG = 10^9 nanoERG).
+    decimals: u8,
+}
+
+/// Denomination as it's deserialized, before validation.
+#[derive(serde::Deserialize)]
+struct RawDenomination {
+    ticker: String,
+    decimals: u8,
+}
+
+impl TryFrom<RawDenomination> for Denomination {
+    type Error = DenominationError;
+    fn try_from(raw: RawDenomination) -> Result<Self, Self::Error> {
+        Self::new(raw.ticker, raw.decimals)
+    }
+}
+
+#[derive(Eq, PartialEq, Clone, Debug, thiserror::Error)]
+pub enum DenominationError {
+    #[error("Denomination has {0} decimals, at most {1} are allowed")]
+    TooManyDecimals(u8, u8),
+}
+
+#[derive(Eq, PartialEq, Clone, Debug, thiserror::Error)]
+pub enum AmountError {
+    #[error("Empty amount")]
+    Empty,
+    #[error("Invalid character in amount: {0:?}")]
+    InvalidChar(char),
+    #[error("Amount has {0} decimal places, at most {1} are allowed")]
+    TooPrecise(usize, u8),
+    #[error("Amount doesn't fit into 64 bits")]
+    Overflow,
+    #[error("Expected ticker {expected}, got {got}")]
+    TickerMismatch { expected: String, got: String },
+}
+
+impl Denomination {
+    /// Max number of decimals an amount representable by `u64` can have.
+    pub const MAX_DECIMALS: u8 = 19;
+
+    pub fn new(ticker: impl Into<String>, decimals: u8) -> Result<Self, DenominationError> {
+        if decimals > Self::MAX_DECIMALS {
+            return Err(DenominationError::TooManyDecimals(decimals, Self::MAX_DECIMALS));
+        }
+        Ok(Self {
+            ticker: ticker.into(),
+            decimals,
+        })
+    }
+
+    /// Amounts in base units, e.g. of assets of unknown denomination.
+    fn base_units() -> Self {
+        Self {
+            ticker: String::new(),
+            decimals: 0,
+        }
+    }
+
+    pub fn ticker(&self) -> &str {
+        &self.ticker
+    }
+
+    pub fn decimals(&self) -> u8 {
+        self.decimals
+    }
+
+    /// Render amount of base units in display units, without the ticker.
+    /// Trailing zeros of the fractional part are omitted.
+    pub fn format_units(&self, amount: u64) -> String {
+        if self.decimals == 0 {
+            return amount.to_string();
+        }
+        let scale = 10u64.pow(self.decimals as u32);
+        let (int, frac) = (amount / scale, amount % scale);
+        if frac == 0 {
+            return int.to_string();
+        }
+        let frac = format!("{:0width$}", frac, width = self.decimals as usize);
+        format!("{}.{}", int, frac.trim_end_matches('0'))
+    }
+
+    /// Render amount of base units in display units followed by the ticker.
+    pub fn format(&self, amount: u64) -> String {
+        format!("{} {}", self.format_units(amount), self.ticker)
+    }
+
+    /// Parse amount given in display units (optionally followed by the ticker) into base units.
+    /// Parsing is exact: amounts which can't be represented in base units are rejected
+    /// rather than rounded.
+    pub fn parse(&self, input: &str) -> Result<u64, AmountError> {
+        let mut parts = input.split_whitespace();
+        let number = parts.next().ok_or(AmountError::Empty)?;
+        if let Some(ticker) = parts.next() {
+            if !ticker.eq_ignore_ascii_case(&self.ticker) || parts.next().is_some() {
+                return Err(AmountError::TickerMismatch {
+                    expected: self.ticker.clone(),
+                    got: input.trim()[number.len()..].trim().to_string(),
+                });
+            }
+        }
+        let (int, frac) = match number.split_once('.') {
+            Some((int, frac)) => (int, frac),
+            None => (number, ""),
+        };
+        if int.is_empty() && frac.is_empty() {
+            return Err(AmountError::Empty);
+        }
+        if let Some(c) = int.chars().chain(frac.chars()).find(|c| !c.is_ascii_digit()) {
+            return Err(AmountError::InvalidChar(c));
+        }
+        let frac = frac.trim_end_matches('0');
+        if frac.len() > self.decimals as usize {
+            return Err(AmountError::TooPrecise(frac.len(), self.decimals));
+        }
+        let int = int.trim_start_matches('0');
+        // Base units are the digits of the integer part followed by the right-padded fractional part.
+        let digits = format!("{}{:0<width$}", int, frac, width = self.decimals as usize);
+        if digits.is_empty() {
+            return Ok(0);
+        }
+        digits.parse::<u64>().map_err(|_| AmountError::Overflow)
+    }
+}
+
+impl Display for Denomination {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.ticker)
+    }
+}
+
+/// Registry of denominations of the native coin and known custom assets.
+#[derive(Clone, Debug)]
+pub struct AssetRegistry {
+    native: Denomination,
+    assets: HashMap<AssetRef, Denomination>,
+}
+
+impl AssetRegistry {
+    pub fn new(native: Denomination) -> Self {
+        Self {
+            native,
+            assets: HashMap::new(),
+        }
+    }
+
+    pub fn register(&mut self, asset: AssetRef, denom: Denomination) -> Option<Denomination> {
+        self.assets.insert(asset, denom)
+    }
+
+    pub fn native(&self) -> &Denomination {
+        &self.native
+    }
+
+    pub fn get(&self, asset: &AssetRef) -> Option<&Denomination> {
+        self.assets.get(asset)
+    }
+
+    pub fn format_native(&self, amount: NativeCoin) -> String {
+        self.native.format(amount.into())
+    }
+
+    /// Unknown assets are rendered in base units.
+    pub fn format_asset(&self, asset: &AssetRef, amount: CustomAsset) -> String {
+        match self.assets.get(asset) {
+            Some(denom) => denom.format(amount.into()),
+            None => u64::from(amount).to_string(),
+        }
+    }
+
+    pub fn parse_native(&self, input: &str) -> Result<NativeCoin, AmountError> {
+        self.native.parse(input).map(NativeCoin::from)
+    }
+
+    pub fn parse_asset(&self, asset: &AssetRef, input: &str) -> Result<CustomAsset, AmountError> {
+        match self.assets.get(asset) {
+            Some(denom) => denom.parse(input),
+            None => Denomination::base_units().parse(input),
+        }
+        .map(CustomAsset::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::denomination::{AmountError, Denomination, DenominationError};
+
+    fn erg() -> Denomination {
+        Denomination::new("ERG", 9).unwrap()
+    }
+
+    #[test]
+    fn format() {
+        let erg = erg();
+        assert_eq!(erg.format(0), "0 ERG");
+        assert_eq!(erg.format(1), "0.000000001 ERG");
+        assert_eq!(erg.format(1_500_000_000), "1.5 ERG");
+        assert_eq!(erg.format_units(u64::MAX), "18446744073.709551615");
+        assert_eq!(Denomination::new("T", 0).unwrap().format_units(42), "42");
+        let max = Denomination::new("T", Denomination::MAX_DECIMALS).unwrap();
+        assert_eq!(max.format_units(u64::MAX), "1.8446744073709551615");
+    }
+
+    #[test]
+    fn parse() {
+        let erg = erg();
+        assert_eq!(erg.parse("1.5"), Ok(1_500_000_000));
+        assert_eq!(erg.parse("1.5 erg"), Ok(1_500_000_000));
+        assert_eq!(erg.parse(".5"), Ok(500_000_000));
+        assert_eq!(erg.parse("2."), Ok(2_000_000_000));
+        assert_eq!(erg.parse("0.000000001"), Ok(1));
+        assert_eq!(erg.parse("1.1000000000"), Ok(1_100_000_000));
+        assert_eq!(erg.parse("18446744073.709551615"), Ok(u64::MAX));
+        assert_eq!(erg.parse("0"), Ok(0));
+    }
+
+    #[test]
+    fn parse_rejects() {
+        let erg = erg();
+        assert_eq!(erg.parse(""), Err(AmountError::Empty));
+        assert_eq!(erg.parse("."), Err(AmountError::Empty));
+        assert_eq!(erg.parse("-1"), Err(AmountError::InvalidChar('-')));
+        assert_eq!(erg.parse("1e9"), Err(AmountError::InvalidChar('e')));
+        assert_eq!(erg.parse("0.0000000001"), Err(AmountError::TooPrecise(10, 9)));
+        assert_eq!(erg.parse("18446744073.709551616"), Err(AmountError::Overflow));
+        assert!(matches!(
+            erg.parse("1 ADA"),
+            Err(AmountError::TickerMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn too_many_decimals_rejected() {
+        assert_eq!(
+            Denomination::new("T", 20),
+            Err(DenominationError::TooManyDecimals(20, Denomination::MAX_DECIMALS))
+        );
+        let encoded = bincode::serialize(&erg()).unwrap();
+        assert_eq!(bincode::deserialize::<Denomination>(&encoded).unwrap(), erg());
+        // Same layout as `Denomination`, so that invalid decimals can be encoded.
+        let encoded = bincode::serialize(&(String::from("T"), 20u8)).unwrap();
+        assert!(bincode::deserialize::<Denomination>(&encoded).is_err());
+    }
+
+    #[test]
+    fn roundtrip() {
+        let erg = erg();
+        for amount in [0, 1, 10, 999_999_999, 1_000_000_000, 123_456_789_012, u64::MAX] {
+            assert_eq!(erg.parse(&erg.format(amount)), Ok(amount));
+        }
+    }
+}
@@ -12,12 +12,14 @@ use spectrum_crypto::pubkey::PublicKey;
 use spectrum_vrf::ECVRFProof;
 
 use crate::block::{BlockBody, BlockHeader, BlockId};
-use crate::transaction::{Transaction, TxId};
+use crate::transaction::{Transaction, TxId, TxPackage};
 
 pub mod block;
 pub mod cell;
 pub mod consensus;
+pub mod denomination;
 pub mod interop;
+pub mod token_registry;
 pub mod transaction;
 
 #[derive(
@@ -109,6 +111,7 @@ pub struct EpochNo(u64);
 pub struct ChainId(u16);
 
 pub const ERGO_CHAIN_ID: ChainId = ChainId(0);
+pub const CARDANO_CHAIN_ID: ChainId = ChainId(1);
 
 #[derive(
     Copy,
@@ -162,6 +165,7 @@ pub enum Modifier {
     BlockHeader(BlockHeader),
     BlockBody(BlockBody),
     Transaction(Transaction),
+    TxPackage(TxPackage),
 }
 
 impl Modifier {
@@ -172,15 +176,17 @@ impl Modifier {
             Modifier::BlockHeader(bh) => ModifierId::from(bh.body.digest()),
             Modifier::BlockBody(bb) => ModifierId::from(bb.digest()),
             Modifier::Transaction(tx) => ModifierId::from(tx.id()),
+            Modifier::TxPackage(pkg) => ModifierId::from(pkg.digest()),
         }
     }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, serde::Serialize, serde::Deserialize)]
 pub enum ModifierType {
     BlockHeader,
     BlockBody,
     Transaction,
+    TxPackage,
 }
 
 /// Provides digest used across the system for authentication.
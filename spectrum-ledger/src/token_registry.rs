@@ -0,0 +1,388 @@
+//! Mapping between tokens native to connected chains and assets of Spectrum, maintained by
+//! the governance of the network.
+
+use std::collections::{HashMap, HashSet};
+
+use async_trait::async_trait;
+use k256::ecdsa::signature::Verifier;
+use k256::ecdsa::{Signature, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+use spectrum_crypto::digest::{blake2b256_hash, Blake2bDigest256};
+use spectrum_crypto::pubkey::PublicKey;
+
+use crate::cell::AssetRef;
+use crate::ChainId;
+
+/// Identifier of a token on its native chain, e.g. a token ID on Ergo.
+#[derive(
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    Clone,
+    Hash,
+    Debug,
+    derive_more::From,
+    derive_more::Into,
+    Serialize,
+    Deserialize,
+)]
+pub struct NativeTokenId(Vec<u8>);
+
+#[derive(Eq, PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct TokenMapping {
+    pub chain_id: ChainId,
+    pub native_token_id: NativeTokenId,
+    pub asset: AssetRef,
+}
+
+#[derive(Eq, PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub enum RegistryAction {
+    Register(TokenMapping),
+    Deregister {
+        chain_id: ChainId,
+        native_token_id: NativeTokenId,
+    },
+}
+
+#[derive(Eq, PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct RegistryUpdate {
+    /// Updates are applied strictly in order, so that approvals can't be replayed.
+    pub seq_no: u64,
+    pub action: RegistryAction,
+}
+
+impl RegistryUpdate {
+    /// Digest signed by members of the governance approving the update.
+    pub fn digest(&self) -> Blake2bDigest256 {
+        blake2b256_hash(&bincode::serialize(self).unwrap())
+    }
+}
+
+/// ECDSA signature of a [`RegistryUpdate`] digest by a member of the governance.
+#[derive(Eq, PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct GovernanceApproval {
+    pub member_ix: u16,
+    pub signature: Vec<u8>,
+}
+
+/// Keys of members of the governance, any `threshold` of which can update the registry.
+#[derive(Eq, PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct Governance {
+    pub keys: Vec<PublicKey>,
+    pub threshold: usize,
+}
+
+#[derive(Eq, PartialEq, Clone, Debug, thiserror::Error)]
+pub enum RegistryError {
+    #[error("Expected update #{expected}, got #{got}")]
+    OutOfOrder { expected: u64, got: u64 },
+    #[error("Unknown governance member {0}")]
+    UnknownMember(u16),
+    #[error("Invalid signature of governance member {0}")]
+    InvalidSignature(u16),
+    #[error("Update approved by {got} members, while {required} are required")]
+    NotEnoughApprovals { got: usize, required: usize },
+    #[error("Token is already mapped to {0:?}")]
+    TokenAlreadyMapped(AssetRef),
+    #[error("Asset is already mapped from token {0:?} of the same chain")]
+    AssetAlreadyMapped(NativeTokenId),
+    #[error("Token {0:?} isn't mapped")]
+    UnknownToken(NativeTokenId),
+}
+
+/// Persistence of the registry as a log of applied updates.
+#[async_trait]
+pub trait TokenRegistryStore {
+    /// Applied updates, in order.
+    async fn updates(&self) -> Vec<RegistryUpdate>;
+    async fn append(&mut self, update: RegistryUpdate);
+}
+
+/// Registry used by connectors to convert native tokens into Spectrum assets on deposits and back
+/// on withdrawals. Within a chain the mapping is one-to-one, while the same asset can be mapped
+/// from tokens of different chains.
+#[derive(Clone, Debug)]
+pub struct TokenRegistry {
+    governance: Governance,
+    next_seq_no: u64,
+    assets: HashMap<(ChainId, NativeTokenId), AssetRef>,
+    native_tokens: HashMap<(ChainId, AssetRef), NativeTokenId>,
+}
+
+impl TokenRegistry {
+    pub fn new(governance: Governance) -> Self {
+        Self {
+            governance,
+            next_seq_no: 0,
+            assets: HashMap::new(),
+            native_tokens: HashMap::new(),
+        }
+    }
+
+    /// Replay updates persisted in the `store`. Approvals of updates aren't checked again.
+    pub async fn restore<S: TokenRegistryStore + Sync>(
+        governance: Governance,
+        store: &S,
+    ) -> Result<Self, RegistryError> {
+        let mut registry = Self::new(governance);
+        for update in store.updates().await {
+            registry.check_seq_no(&update)?;
+            registry.apply(update.action)?;
+        }
+        Ok(registry)
+    }
+
+    pub fn to_asset(&self, chain_id: ChainId, native_token_id: &NativeTokenId) -> Option<AssetRef> {
+        self.assets.get(&(chain_id, native_token_id.clone())).copied()
+    }
+
+    pub fn to_native(&self, chain_id: ChainId, asset: AssetRef) -> Option<&NativeTokenId> {
+        self.native_tokens.get(&(chain_id, asset))
+    }
+
+    /// Sequence number the next update must have.
+    pub fn next_seq_no(&self) -> u64 {
+        self.next_seq_no
+    }
+
+    /// Apply the update approved by the governance and persist it in the `store`.
+    pub async fn update<S: TokenRegistryStore + Send>(
+        &mut self,
+        update: RegistryUpdate,
+        approvals: &[GovernanceApproval],
+        store: &mut S,
+    ) -> Result<(), RegistryError> {
+        self.check_seq_no(&update)?;
+        self.verify_approvals(&update, approvals)?;
+        self.apply(update.action.clone())?;
+        store.append(update).await;
+        Ok(())
+    }
+
+    fn check_seq_no(&self, update: &RegistryUpdate) -> Result<(), RegistryError> {
+        if update.seq_no != self.next_seq_no {
+            return Err(RegistryError::OutOfOrder {
+                expected: self.next_seq_no,
+                got: update.seq_no,
+            });
+        }
+        Ok(())
+    }
+
+    fn verify_approvals(
+        &self,
+        update: &RegistryUpdate,
+        approvals: &[GovernanceApproval],
+    ) -> Result<(), RegistryError> {
+        let digest = update.digest();
+        let mut approved_by = HashSet::new();
+        for GovernanceApproval { member_ix, signature } in approvals {
+            let key = self
+                .governance
+                .keys
+                .get(*member_ix as usize)
+                .ok_or(RegistryError::UnknownMember(*member_ix))?;
+            Signature::from_slice(signature)
+                .ok()
+                .filter(|sig| {
+                    VerifyingKey::from(k256::PublicKey::from(*key))
+                        .verify(digest.as_ref(), sig)
+                        .is_ok()
+                })
+                .ok_or(RegistryError::InvalidSignature(*member_ix))?;
+            approved_by.insert(*member_ix);
+        }
+        if approved_by.len() < self.governance.threshold {
+            return Err(RegistryError::NotEnoughApprovals {
+                got: approved_by.len(),
+                required: self.governance.threshold,
+            });
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, action: RegistryAction) -> Result<(), RegistryError> {
+        match action {
+            RegistryAction::Register(TokenMapping {
+                chain_id,
+                native_token_id,
+                asset,
+            }) => {
+                if let Some(mapped_asset) = self.to_asset(chain_id, &native_token_id) {
+                    return Err(RegistryError::TokenAlreadyMapped(mapped_asset));
+                }
+                if let Some(mapped_token) = self.to_native(chain_id, asset) {
+                    return Err(RegistryError::AssetAlreadyMapped(mapped_token.clone()));
+                }
+                self.native_tokens
+                    .insert((chain_id, asset), native_token_id.clone());
+                self.assets.insert((chain_id, native_token_id), asset);
+            }
+            RegistryAction::Deregister {
+                chain_id,
+                native_token_id,
+            } => {
+                let asset = self
+                    .assets
+                    .remove(&(chain_id, native_token_id.clone()))
+                    .ok_or(RegistryError::UnknownToken(native_token_id))?;
+                self.native_tokens.remove(&(chain_id, asset));
+            }
+        }
+        self.next_seq_no += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use futures::executor::block_on;
+    use k256::ecdsa::signature::Signer;
+    use k256::ecdsa::{Signature, SigningKey};
+    use k256::elliptic_curve::rand_core::OsRng;
+    use k256::SecretKey;
+
+    use spectrum_crypto::digest::Blake2bDigest256;
+    use spectrum_crypto::pubkey::PublicKey;
+
+    use crate::cell::{AssetId, AssetRef, PolicyId};
+    use crate::token_registry::{
+        Governance, GovernanceApproval, NativeTokenId, RegistryAction, RegistryError, RegistryUpdate,
+        TokenMapping, TokenRegistry, TokenRegistryStore,
+    };
+    use crate::{CARDANO_CHAIN_ID, ERGO_CHAIN_ID};
+
+    #[derive(Default)]
+    struct InMemoryStore(Vec<RegistryUpdate>);
+
+    #[async_trait]
+    impl TokenRegistryStore for InMemoryStore {
+        async fn updates(&self) -> Vec<RegistryUpdate> {
+            self.0.clone()
+        }
+
+        async fn append(&mut self, update: RegistryUpdate) {
+            self.0.push(update);
+        }
+    }
+
+    fn asset() -> AssetRef {
+        AssetRef::from((
+            PolicyId::from(Blake2bDigest256::random()),
+            AssetId::from(Blake2bDigest256::random()),
+        ))
+    }
+
+    fn register(seq_no: u64, chain_id: crate::ChainId, token: &[u8], asset: AssetRef) -> RegistryUpdate {
+        RegistryUpdate {
+            seq_no,
+            action: RegistryAction::Register(TokenMapping {
+                chain_id,
+                native_token_id: NativeTokenId::from(token.to_vec()),
+                asset,
+            }),
+        }
+    }
+
+    fn approve(sks: &[SecretKey], update: &RegistryUpdate) -> Vec<GovernanceApproval> {
+        sks.iter()
+            .enumerate()
+            .map(|(ix, sk)| {
+                let signature: Signature = SigningKey::from(sk).sign(update.digest().as_ref());
+                GovernanceApproval {
+                    member_ix: ix as u16,
+                    signature: signature.to_bytes().to_vec(),
+                }
+            })
+            .collect()
+    }
+
+    fn governance(sks: &[SecretKey], threshold: usize) -> Governance {
+        Governance {
+            keys: sks.iter().map(|sk| PublicKey::from(sk.public_key())).collect(),
+            threshold,
+        }
+    }
+
+    #[test]
+    fn tokens_mapped_one_to_one_within_chain() {
+        let sks: Vec<_> = (0..3).map(|_| SecretKey::random(&mut OsRng)).collect();
+        let mut store = InMemoryStore::default();
+        let mut registry = TokenRegistry::new(governance(&sks, 2));
+        let (usd, gold) = (asset(), asset());
+        let updates = [
+            register(0, ERGO_CHAIN_ID, b"sigusd", usd),
+            register(1, CARDANO_CHAIN_ID, b"djed", usd),
+            register(2, ERGO_CHAIN_ID, b"sigusd", gold),
+            register(2, ERGO_CHAIN_ID, b"sigusd2", usd),
+        ];
+        for update in &updates[..2] {
+            block_on(registry.update(update.clone(), &approve(&sks[..2], update), &mut store)).unwrap();
+        }
+        assert_eq!(
+            block_on(registry.update(updates[2].clone(), &approve(&sks, &updates[2]), &mut store)),
+            Err(RegistryError::TokenAlreadyMapped(usd))
+        );
+        assert_eq!(
+            block_on(registry.update(updates[3].clone(), &approve(&sks, &updates[3]), &mut store)),
+            Err(RegistryError::AssetAlreadyMapped(NativeTokenId::from(
+                b"sigusd".to_vec()
+            )))
+        );
+        let sigusd = NativeTokenId::from(b"sigusd".to_vec());
+        assert_eq!(registry.to_asset(ERGO_CHAIN_ID, &sigusd), Some(usd));
+        assert_eq!(
+            registry.to_native(CARDANO_CHAIN_ID, usd),
+            Some(&NativeTokenId::from(b"djed".to_vec()))
+        );
+        assert_eq!(registry.to_asset(CARDANO_CHAIN_ID, &sigusd), None);
+
+        let restored = block_on(TokenRegistry::restore(governance(&sks, 2), &store)).unwrap();
+        assert_eq!(restored.next_seq_no(), 2);
+        assert_eq!(restored.to_asset(ERGO_CHAIN_ID, &sigusd), Some(usd));
+    }
+
+    #[test]
+    fn updates_controlled_by_governance() {
+        let sks: Vec<_> = (0..3).map(|_| SecretKey::random(&mut OsRng)).collect();
+        let mut store = InMemoryStore::default();
+        let mut registry = TokenRegistry::new(governance(&sks, 2));
+        let update = register(0, ERGO_CHAIN_ID, b"token", asset());
+        // Repeated approval doesn't count twice.
+        let mut approvals = approve(&sks[..1], &update);
+        approvals.extend(approvals.clone());
+        assert_eq!(
+            block_on(registry.update(update.clone(), &approvals, &mut store)),
+            Err(RegistryError::NotEnoughApprovals { got: 1, required: 2 })
+        );
+        let mut forged = approve(&sks, &update);
+        forged[1].member_ix = 2;
+        assert_eq!(
+            block_on(registry.update(update.clone(), &forged, &mut store)),
+            Err(RegistryError::InvalidSignature(2))
+        );
+        let approvals = approve(&sks, &update);
+        block_on(registry.update(update.clone(), &approvals, &mut store)).unwrap();
+        // Approvals can't be replayed.
+        assert_eq!(
+            block_on(registry.update(update, &approvals, &mut store)),
+            Err(RegistryError::OutOfOrder { expected: 1, got: 0 })
+        );
+        let deregister = RegistryUpdate {
+            seq_no: 1,
+            action: RegistryAction::Deregister {
+                chain_id: ERGO_CHAIN_ID,
+                native_token_id: NativeTokenId::from(b"token".to_vec()),
+            },
+        };
+        block_on(registry.update(deregister.clone(), &approve(&sks, &deregister), &mut store)).unwrap();
+        assert_eq!(
+            registry.to_asset(ERGO_CHAIN_ID, &NativeTokenId::from(b"token".to_vec())),
+            None
+        );
+        assert_eq!(store.0.len(), 2);
+    }
+}
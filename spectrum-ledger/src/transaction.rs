@@ -113,6 +113,33 @@ impl SystemDigest for Transaction {
     }
 }
 
+/// Chain of dependent transactions which are to be accepted (or rejected) atomically.
+/// Transactions are ordered topologically, i.e. parents always precede their children.
+#[derive(Clone, Eq, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+pub struct TxPackage(pub Vec<Transaction>);
+
+impl TxPackage {
+    /// IDs of the transactions in the package, parents first.
+    pub fn tx_ids(&self) -> Vec<TxId> {
+        self.0.iter().map(|tx| tx.id()).collect()
+    }
+}
+
+impl From<Transaction> for TxPackage {
+    fn from(tx: Transaction) -> Self {
+        Self(vec![tx])
+    }
+}
+
+impl SystemDigest for TxPackage {
+    /// Package is identified by the ordered list of IDs of its members.
+    fn digest(&self) -> Blake2bDigest256 {
+        let mut encoded = Vec::new();
+        ciborium::ser::into_writer(&self.tx_ids(), &mut encoded).unwrap();
+        blake2b256_hash(&*encoded)
+    }
+}
+
 /// Unverified transaction whose inputs are resolved.
 /// `Transaction` -> `LinkedTransaction`
 #[derive(Clone, Eq, PartialEq, Debug)]
@@ -193,3 +220,15 @@ pub struct LinkedScriptInv {
 #[repr(transparent)]
 #[derive(Clone, Debug)]
 pub struct ValidTx<T>(T);
+
+impl<T> ValidTx<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> AsRef<T> for ValidTx<T> {
+    fn as_ref(&self) -> &T {
+        &self.0
+    }
+}
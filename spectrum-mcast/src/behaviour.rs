@@ -219,8 +219,12 @@ where
     fn inject_message(&mut self, peer_id: PeerId, content: S) {
         if self.overlay.parent_nodes.contains(&peer_id) {
             let pd = Arc::clone(&self.public_data);
-            self.tasks.spawn(|to_behaviour| async move {
-                if let Ok(ver) = content.verify(&pd).await {
+            self.tasks.spawn(|to_behaviour, cancellation| async move {
+                let verified = content.verify(&pd).await;
+                if cancellation.is_cancelled() {
+                    return;
+                }
+                if let Ok(ver) = verified {
                     to_behaviour
                         .send(FromTask::ToBehaviour(ApplyStatement(ver)))
                         .await
@@ -2,12 +2,17 @@ use std::collections::HashMap;
 
 use void::Void;
 
+use move_binary_format::CompiledModule;
 use move_core_types::account_address::AccountAddress;
-use move_core_types::identifier::Identifier;
-use move_core_types::language_storage::{ModuleId, StructTag};
+use move_core_types::identifier::{IdentStr, Identifier};
+use move_core_types::language_storage::{ModuleId, StructTag, TypeTag};
 use move_core_types::resolver::{ModuleResolver, ResourceResolver};
+use move_core_types::value::MoveTypeLayout;
+use move_core_types::vm_status::StatusCode;
+use move_vm_runtime::move_vm::MoveVM;
 
-use crate::{SerializedModule, SerializedValue};
+use crate::gas::{ScriptGasMeter, SCRIPT_COST_TABLE};
+use crate::{GasUnits, SerializedModule, SerializedValue};
 
 pub struct ExecutionScope {
     pub modules: HashMap<Identifier, SerializedModule>,
@@ -29,3 +34,181 @@ impl ResourceResolver for ExecutionScope {
         Ok(self.resources.get(typ).cloned().map(<Vec<u8>>::from))
     }
 }
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug, thiserror::Error)]
+pub enum ExecutionError {
+    #[error("Module cannot be deserialized")]
+    MalformedModule,
+    #[error("Only entry functions can be invoked")]
+    NotEntryFunction,
+    #[error("Function returned a value of type other than vector<u8>")]
+    UnexpectedReturnType,
+    #[error("Execution aborted with code {0}")]
+    Aborted(u64),
+    #[error("Out of gas")]
+    OutOfGas,
+    #[error("VM failed with {0:?}")]
+    Vm(StatusCode),
+}
+
+/// Executes script invocations of a single transaction within a shared gas budget.
+pub struct ScriptExecutor {
+    gas_limit: GasUnits,
+    gas: ScriptGasMeter,
+}
+
+impl ScriptExecutor {
+    pub fn new(gas_limit: GasUnits) -> Self {
+        Self {
+            gas_limit,
+            gas: ScriptGasMeter::new(SCRIPT_COST_TABLE, gas_limit),
+        }
+    }
+
+    /// Gas consumed by all invocations so far.
+    pub fn gas_consumed(&self) -> GasUnits {
+        GasUnits(self.gas_limit.0.saturating_sub(self.gas.gas_left().0))
+    }
+
+    /// Call the given entry function of the module. Every value returned by the function must be
+    /// of type `vector<u8>`, the bytes are returned as is.
+    /// Arguments are expected to be BCS-encoded.
+    pub fn invoke(
+        &mut self,
+        module: &SerializedModule,
+        function: &IdentStr,
+        targs: Vec<TypeTag>,
+        args: Vec<Vec<u8>>,
+    ) -> Result<Vec<Vec<u8>>, ExecutionError> {
+        let module_id = CompiledModule::deserialize(&module.0)
+            .map_err(|_| ExecutionError::MalformedModule)?
+            .self_id();
+        let scope = ExecutionScope {
+            modules: HashMap::from([(module_id.name().to_owned(), module.clone())]),
+            resources: HashMap::new(),
+        };
+        // Scripts can't touch the storage, so a VM is never reused across invocations.
+        let vm = MoveVM::new(vec![]).map_err(|err| ExecutionError::Vm(err.major_status()))?;
+        let mut session = vm.new_session(&scope);
+        let returned = session
+            .execute_entry_function(&module_id, function, targs, args, &mut self.gas)
+            .map_err(|err| match err.major_status() {
+                StatusCode::OUT_OF_GAS => ExecutionError::OutOfGas,
+                StatusCode::EXECUTE_ENTRY_FUNCTION_CALLED_ON_NON_ENTRY_FUNCTION => {
+                    ExecutionError::NotEntryFunction
+                }
+                StatusCode::ABORTED => ExecutionError::Aborted(err.sub_status().unwrap_or_default()),
+                status => ExecutionError::Vm(status),
+            })?;
+        returned
+            .return_values
+            .into_iter()
+            .map(|(bytes, layout)| match layout {
+                MoveTypeLayout::Vector(elem) if *elem == MoveTypeLayout::U8 => {
+                    bcs::from_bytes::<Vec<u8>>(&bytes).map_err(|_| ExecutionError::UnexpectedReturnType)
+                }
+                _ => Err(ExecutionError::UnexpectedReturnType),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use move_core_types::identifier::Identifier;
+    use move_ir_compiler::Compiler;
+
+    use crate::execution::{ExecutionError, ScriptExecutor};
+    use crate::{GasUnits, SerializedModule};
+
+    const SCRIPT: &str = r#"
+        module 0x1.Script {
+            public entry produce(inputs: vector<vector<u8>>): vector<u8> {
+            label l0:
+                return h"0102";
+            }
+            public entry fail(inputs: vector<vector<u8>>) {
+            label l0:
+                abort(42);
+            }
+            public entry spin(inputs: vector<vector<u8>>) {
+            label l0:
+                jump l0;
+            }
+            public entry number(inputs: vector<vector<u8>>): u64 {
+            label l0:
+                return 7;
+            }
+            helper(): vector<u8> {
+            label l0:
+                return h"01";
+            }
+        }
+    "#;
+
+    fn script() -> SerializedModule {
+        SerializedModule::from(Compiler::new(vec![]).into_module_blob(SCRIPT).unwrap())
+    }
+
+    fn invoke(executor: &mut ScriptExecutor, function: &str) -> Result<Vec<Vec<u8>>, ExecutionError> {
+        let no_inputs = bcs::to_bytes(&Vec::<Vec<u8>>::new()).unwrap();
+        executor.invoke(
+            &script(),
+            &Identifier::new(function).unwrap(),
+            vec![],
+            vec![no_inputs],
+        )
+    }
+
+    #[test]
+    fn entry_function_returns_bytes() {
+        let mut executor = ScriptExecutor::new(GasUnits::from(1_000));
+        assert_eq!(invoke(&mut executor, "produce"), Ok(vec![vec![1, 2]]));
+        let consumed = executor.gas_consumed();
+        assert_ne!(consumed, GasUnits::ZERO);
+        // Gas is shared by all invocations.
+        invoke(&mut executor, "produce").unwrap();
+        assert_eq!(executor.gas_consumed(), consumed + consumed);
+    }
+
+    #[test]
+    fn only_entry_functions_are_invoked() {
+        let mut executor = ScriptExecutor::new(GasUnits::from(1_000));
+        assert_eq!(
+            invoke(&mut executor, "helper"),
+            Err(ExecutionError::NotEntryFunction)
+        );
+    }
+
+    #[test]
+    fn abort_code_is_reported() {
+        let mut executor = ScriptExecutor::new(GasUnits::from(1_000));
+        assert_eq!(invoke(&mut executor, "fail"), Err(ExecutionError::Aborted(42)));
+    }
+
+    #[test]
+    fn execution_stops_once_gas_is_exhausted() {
+        let gas_limit = GasUnits::from(1_000);
+        let mut executor = ScriptExecutor::new(gas_limit);
+        assert_eq!(invoke(&mut executor, "spin"), Err(ExecutionError::OutOfGas));
+        assert_eq!(executor.gas_consumed(), gas_limit);
+    }
+
+    #[test]
+    fn malformed_modules_and_outputs_are_rejected() {
+        let mut executor = ScriptExecutor::new(GasUnits::from(1_000));
+        assert_eq!(
+            invoke(&mut executor, "number"),
+            Err(ExecutionError::UnexpectedReturnType)
+        );
+        assert_eq!(
+            executor.invoke(
+                &SerializedModule::from(vec![1, 2, 3]),
+                &Identifier::new("produce").unwrap(),
+                vec![],
+                vec![],
+            ),
+            Err(ExecutionError::MalformedModule)
+        );
+    }
+}
@@ -0,0 +1,305 @@
+use move_binary_format::errors::{PartialVMError, PartialVMResult};
+use move_core_types::gas_algebra::{AbstractMemorySize, InternalGas, NumArgs, NumBytes};
+use move_core_types::language_storage::ModuleId;
+use move_core_types::vm_status::StatusCode;
+use move_vm_types::gas::{GasMeter, SimpleInstruction};
+use move_vm_types::views::{TypeView, ValueView};
+
+use crate::GasUnits;
+
+/// Costs of VM operations in gas units.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct CostTable {
+    /// Instructions over primitive values: control flow, loads of literals, casts, borrows
+    /// of locals and fields, cheap arithmetic and comparisons.
+    pub simple_instruction: u64,
+    /// `mul`, `div` and `mod`.
+    pub heavy_arithmetic: u64,
+    /// Base cost of a function call.
+    pub call: u64,
+    /// Cost of a call per argument and type argument.
+    pub call_per_arg: u64,
+    /// Cost per abstract memory unit of values which are copied, moved, compared, (un)packed,
+    /// read or written by reference and of loaded constants.
+    pub per_memory_unit: u64,
+    /// Base cost of vector operations.
+    pub vector_op: u64,
+    /// Cost of vector operations per element.
+    pub vector_per_elem: u64,
+    /// Access to global storage, scripts can't publish resources, so it always fails.
+    pub global_access: u64,
+}
+
+/// Costs all script invocations are metered with.
+pub const SCRIPT_COST_TABLE: CostTable = CostTable {
+    simple_instruction: 1,
+    heavy_arithmetic: 3,
+    call: 10,
+    call_per_arg: 1,
+    per_memory_unit: 1,
+    vector_op: 2,
+    vector_per_elem: 1,
+    global_access: 50,
+};
+
+/// Meters VM operations against the [`CostTable`] within a fixed budget.
+pub struct ScriptGasMeter {
+    table: CostTable,
+    gas_left: u64,
+}
+
+impl ScriptGasMeter {
+    pub fn new(table: CostTable, budget: GasUnits) -> Self {
+        Self {
+            table,
+            gas_left: budget.0,
+        }
+    }
+
+    pub fn gas_left(&self) -> GasUnits {
+        GasUnits(self.gas_left)
+    }
+
+    fn charge(&mut self, amount: u64) -> PartialVMResult<()> {
+        match self.gas_left.checked_sub(amount) {
+            Some(gas_left) => {
+                self.gas_left = gas_left;
+                Ok(())
+            }
+            None => {
+                self.gas_left = 0;
+                Err(PartialVMError::new(StatusCode::OUT_OF_GAS))
+            }
+        }
+    }
+
+    fn charge_with_size(&mut self, base: u64, size: AbstractMemorySize) -> PartialVMResult<()> {
+        self.charge(base.saturating_add(self.table.per_memory_unit.saturating_mul(u64::from(size))))
+    }
+
+    fn charge_call_with_args(&mut self, num_args: usize) -> PartialVMResult<()> {
+        self.charge(
+            self.table
+                .call
+                .saturating_add(self.table.call_per_arg.saturating_mul(num_args as u64)),
+        )
+    }
+
+    fn charge_vector_op(&mut self, num_elems: u64) -> PartialVMResult<()> {
+        self.charge(
+            self.table
+                .vector_op
+                .saturating_add(self.table.vector_per_elem.saturating_mul(num_elems)),
+        )
+    }
+}
+
+fn size_of(vals: impl Iterator<Item = impl ValueView>) -> AbstractMemorySize {
+    vals.fold(AbstractMemorySize::zero(), |acc, val| {
+        acc + val.legacy_abstract_memory_size()
+    })
+}
+
+impl GasMeter for ScriptGasMeter {
+    fn balance_internal(&self) -> InternalGas {
+        InternalGas::new(self.gas_left)
+    }
+
+    fn charge_simple_instr(&mut self, instr: SimpleInstruction) -> PartialVMResult<()> {
+        match instr {
+            SimpleInstruction::Mul | SimpleInstruction::Div | SimpleInstruction::Mod => {
+                self.charge(self.table.heavy_arithmetic)
+            }
+            _ => self.charge(self.table.simple_instruction),
+        }
+    }
+
+    fn charge_pop(&mut self, _popped_val: impl ValueView) -> PartialVMResult<()> {
+        self.charge(self.table.simple_instruction)
+    }
+
+    fn charge_call(
+        &mut self,
+        _module_id: &ModuleId,
+        _func_name: &str,
+        args: impl ExactSizeIterator<Item = impl ValueView>,
+        _num_locals: NumArgs,
+    ) -> PartialVMResult<()> {
+        self.charge_call_with_args(args.len())
+    }
+
+    fn charge_call_generic(
+        &mut self,
+        _module_id: &ModuleId,
+        _func_name: &str,
+        ty_args: impl ExactSizeIterator<Item = impl TypeView>,
+        args: impl ExactSizeIterator<Item = impl ValueView>,
+        _num_locals: NumArgs,
+    ) -> PartialVMResult<()> {
+        self.charge_call_with_args(ty_args.len() + args.len())
+    }
+
+    fn charge_ld_const(&mut self, size: NumBytes) -> PartialVMResult<()> {
+        self.charge_with_size(self.table.simple_instruction, u64::from(size).into())
+    }
+
+    fn charge_ld_const_after_deserialization(&mut self, _val: impl ValueView) -> PartialVMResult<()> {
+        Ok(())
+    }
+
+    fn charge_copy_loc(&mut self, val: impl ValueView) -> PartialVMResult<()> {
+        self.charge_with_size(self.table.simple_instruction, val.legacy_abstract_memory_size())
+    }
+
+    fn charge_move_loc(&mut self, _val: impl ValueView) -> PartialVMResult<()> {
+        self.charge(self.table.simple_instruction)
+    }
+
+    fn charge_store_loc(&mut self, _val: impl ValueView) -> PartialVMResult<()> {
+        self.charge(self.table.simple_instruction)
+    }
+
+    fn charge_pack(
+        &mut self,
+        _is_generic: bool,
+        args: impl ExactSizeIterator<Item = impl ValueView>,
+    ) -> PartialVMResult<()> {
+        self.charge_with_size(self.table.simple_instruction, size_of(args))
+    }
+
+    fn charge_unpack(
+        &mut self,
+        _is_generic: bool,
+        args: impl ExactSizeIterator<Item = impl ValueView>,
+    ) -> PartialVMResult<()> {
+        self.charge_with_size(self.table.simple_instruction, size_of(args))
+    }
+
+    fn charge_read_ref(&mut self, val: impl ValueView) -> PartialVMResult<()> {
+        self.charge_with_size(self.table.simple_instruction, val.legacy_abstract_memory_size())
+    }
+
+    fn charge_write_ref(&mut self, new_val: impl ValueView, _old_val: impl ValueView) -> PartialVMResult<()> {
+        self.charge_with_size(
+            self.table.simple_instruction,
+            new_val.legacy_abstract_memory_size(),
+        )
+    }
+
+    fn charge_eq(&mut self, lhs: impl ValueView, rhs: impl ValueView) -> PartialVMResult<()> {
+        self.charge_with_size(
+            self.table.simple_instruction,
+            lhs.legacy_abstract_memory_size() + rhs.legacy_abstract_memory_size(),
+        )
+    }
+
+    fn charge_neq(&mut self, lhs: impl ValueView, rhs: impl ValueView) -> PartialVMResult<()> {
+        self.charge_with_size(
+            self.table.simple_instruction,
+            lhs.legacy_abstract_memory_size() + rhs.legacy_abstract_memory_size(),
+        )
+    }
+
+    fn charge_borrow_global(
+        &mut self,
+        _is_mut: bool,
+        _is_generic: bool,
+        _ty: impl TypeView,
+        _is_success: bool,
+    ) -> PartialVMResult<()> {
+        self.charge(self.table.global_access)
+    }
+
+    fn charge_exists(&mut self, _is_generic: bool, _ty: impl TypeView, _exists: bool) -> PartialVMResult<()> {
+        self.charge(self.table.global_access)
+    }
+
+    fn charge_move_from(
+        &mut self,
+        _is_generic: bool,
+        _ty: impl TypeView,
+        _val: Option<impl ValueView>,
+    ) -> PartialVMResult<()> {
+        self.charge(self.table.global_access)
+    }
+
+    fn charge_move_to(
+        &mut self,
+        _is_generic: bool,
+        _ty: impl TypeView,
+        _val: impl ValueView,
+        _is_success: bool,
+    ) -> PartialVMResult<()> {
+        self.charge(self.table.global_access)
+    }
+
+    fn charge_vec_pack<'a>(
+        &mut self,
+        _ty: impl TypeView + 'a,
+        args: impl ExactSizeIterator<Item = impl ValueView>,
+    ) -> PartialVMResult<()> {
+        self.charge_vector_op(args.len() as u64)
+    }
+
+    fn charge_vec_len(&mut self, _ty: impl TypeView) -> PartialVMResult<()> {
+        self.charge_vector_op(0)
+    }
+
+    fn charge_vec_borrow(
+        &mut self,
+        _is_mut: bool,
+        _ty: impl TypeView,
+        _is_success: bool,
+    ) -> PartialVMResult<()> {
+        self.charge_vector_op(0)
+    }
+
+    fn charge_vec_push_back(&mut self, _ty: impl TypeView, _val: impl ValueView) -> PartialVMResult<()> {
+        self.charge_vector_op(1)
+    }
+
+    fn charge_vec_pop_back(
+        &mut self,
+        _ty: impl TypeView,
+        _val: Option<impl ValueView>,
+    ) -> PartialVMResult<()> {
+        self.charge_vector_op(1)
+    }
+
+    fn charge_vec_unpack(
+        &mut self,
+        _ty: impl TypeView,
+        expect_num_elements: NumArgs,
+        _elems: impl ExactSizeIterator<Item = impl ValueView>,
+    ) -> PartialVMResult<()> {
+        self.charge_vector_op(u64::from(expect_num_elements))
+    }
+
+    fn charge_vec_swap(&mut self, _ty: impl TypeView) -> PartialVMResult<()> {
+        self.charge_vector_op(0)
+    }
+
+    fn charge_load_resource(&mut self, _loaded: Option<(NumBytes, impl ValueView)>) -> PartialVMResult<()> {
+        Ok(())
+    }
+
+    fn charge_native_function(
+        &mut self,
+        amount: InternalGas,
+        _ret_vals: Option<impl ExactSizeIterator<Item = impl ValueView>>,
+    ) -> PartialVMResult<()> {
+        self.charge(u64::from(amount))
+    }
+
+    fn charge_native_function_before_execution(
+        &mut self,
+        _ty_args: impl ExactSizeIterator<Item = impl TypeView>,
+        _args: impl ExactSizeIterator<Item = impl ValueView>,
+    ) -> PartialVMResult<()> {
+        Ok(())
+    }
+
+    fn charge_drop_frame(&mut self, _locals: impl Iterator<Item = impl ValueView>) -> PartialVMResult<()> {
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 pub mod execution;
+pub mod gas;
 
 #[derive(
     Eq, PartialEq, Clone, Debug, derive_more::From, derive_more::Into, serde::Serialize, serde::Deserialize,
@@ -17,6 +18,8 @@ pub struct SerializedValue(Vec<u8>);
     Clone,
     derive_more::Add,
     derive_more::Sub,
+    derive_more::From,
+    derive_more::Into,
     Debug,
     serde::Serialize,
     serde::Deserialize,
@@ -0,0 +1,260 @@
+//! Permissioned mode for private deployments.
+//!
+//! Only members of the [`Allowlist`] may connect to the node and open protocols with it,
+//! connections with everyone else are refused or torn down. Members are either listed explicitly
+//! by peer id or committee key, or come from the committee announced by the ledger. Explicit
+//! members are replaced at runtime by [`SignedAllowlistUpdate`]s issued by the authority of
+//! the network, so that a compromised peer can't extend the list on its own.
+
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+use k256::ecdsa::signature::{Signer, Verifier};
+use k256::SecretKey;
+use libp2p::PeerId;
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use spectrum_crypto::digest::blake2b256_hash;
+use spectrum_crypto::pubkey::PublicKey;
+
+use crate::inbound_policy::{InboundEvent, InboundPolicy, Rejection};
+
+/// Explicit members of the network.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AllowlistUpdate {
+    /// Updates are applied only in increasing order of versions.
+    pub version: u64,
+    #[serde(default)]
+    pub peers: Vec<PeerId>,
+    #[serde(default)]
+    pub committee_keys: Vec<PublicKey>,
+}
+
+impl AllowlistUpdate {
+    fn members(&self) -> HashSet<PeerId> {
+        self.peers
+            .iter()
+            .copied()
+            .chain(self.committee_keys.iter().map(PeerId::from))
+            .collect()
+    }
+
+    fn signed_bytes(&self) -> Vec<u8> {
+        let mut bf = vec![];
+        ciborium::ser::into_writer(self, &mut bf).unwrap();
+        let mut msg = b"spectrum/allowlist/v1".to_vec();
+        msg.extend_from_slice(blake2b256_hash(&bf).as_ref());
+        msg
+    }
+
+    pub fn sign(self, sk: &SecretKey) -> SignedAllowlistUpdate {
+        let sig: k256::ecdsa::Signature = k256::ecdsa::SigningKey::from(sk).sign(&self.signed_bytes());
+        SignedAllowlistUpdate {
+            update: self,
+            signature: sig.to_bytes().to_vec(),
+        }
+    }
+}
+
+/// Update of the allowlist signed by the authority of the network.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedAllowlistUpdate {
+    pub update: AllowlistUpdate,
+    #[serde(with = "serde_bytes")]
+    pub signature: Vec<u8>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AllowlistError {
+    #[error("No authority is configured to accept signed updates from")]
+    NoAuthority,
+    #[error("Signature of the update is invalid")]
+    InvalidSignature,
+    #[error("Update of version {version} is not newer than the current one {current}")]
+    StaleVersion { version: u64, current: u64 },
+}
+
+#[derive(Debug)]
+struct AllowlistState {
+    /// Version of the last applied signed update.
+    version: u64,
+    /// Members listed explicitly.
+    members: HashSet<PeerId>,
+    /// Members of the current committee as announced by the ledger.
+    committee: HashSet<PeerId>,
+    /// Bumped on every change, lets the network controller notice evicted members.
+    revision: u64,
+}
+
+/// Members of a permissioned network shared by all components of the node.
+#[derive(Clone, Debug)]
+pub struct Allowlist {
+    state: Arc<RwLock<AllowlistState>>,
+    /// Key signed updates are verified against. Only the ledger can change the allowlist if `None`.
+    authority: Option<PublicKey>,
+}
+
+impl Allowlist {
+    pub fn new(members: HashSet<PeerId>) -> Self {
+        Self {
+            state: Arc::new(RwLock::new(AllowlistState {
+                version: 0,
+                members,
+                committee: HashSet::new(),
+                revision: 0,
+            })),
+            authority: None,
+        }
+    }
+
+    /// Accept signed updates issued by the given authority.
+    pub fn with_authority(mut self, authority: PublicKey) -> Self {
+        self.authority = Some(authority);
+        self
+    }
+
+    pub fn is_allowed(&self, peer_id: &PeerId) -> bool {
+        let state = self.state.read().unwrap();
+        state.members.contains(peer_id) || state.committee.contains(peer_id)
+    }
+
+    /// Changes each time the set of members changes.
+    pub fn revision(&self) -> u64 {
+        self.state.read().unwrap().revision
+    }
+
+    /// Replace explicit members with the ones from the signed update.
+    pub fn apply_signed(&self, signed: &SignedAllowlistUpdate) -> Result<(), AllowlistError> {
+        let authority = self.authority.ok_or(AllowlistError::NoAuthority)?;
+        let vk = k256::ecdsa::VerifyingKey::from(k256::PublicKey::from(authority));
+        k256::ecdsa::Signature::from_slice(&signed.signature)
+            .ok()
+            .and_then(|sig| vk.verify(&signed.update.signed_bytes(), &sig).ok())
+            .ok_or(AllowlistError::InvalidSignature)?;
+        let mut state = self.state.write().unwrap();
+        if signed.update.version <= state.version {
+            return Err(AllowlistError::StaleVersion {
+                version: signed.update.version,
+                current: state.version,
+            });
+        }
+        info!("[Allowlist] Applying update of version {}", signed.update.version);
+        state.version = signed.update.version;
+        state.members = signed.update.members();
+        state.revision += 1;
+        Ok(())
+    }
+
+    /// Committee announced by the ledger replaces the previous one.
+    pub fn on_committee_changed(&self, committee: &[PublicKey]) {
+        let mut state = self.state.write().unwrap();
+        state.committee = committee.iter().map(PeerId::from).collect();
+        state.revision += 1;
+    }
+}
+
+impl InboundPolicy for Allowlist {
+    fn check(&mut self, event: InboundEvent, _now: Instant) -> Result<(), Rejection> {
+        match event {
+            InboundEvent::Connection { peer_id, .. } | InboundEvent::ProtocolOpen { peer_id, .. }
+                if !self.is_allowed(&peer_id) =>
+            {
+                Err(Rejection::NotAllowlisted)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::time::Instant;
+
+    use elliptic_curve::rand_core::OsRng;
+    use k256::SecretKey;
+    use libp2p::{Multiaddr, PeerId};
+
+    use spectrum_crypto::pubkey::PublicKey;
+
+    use crate::allowlist::{Allowlist, AllowlistError, AllowlistUpdate};
+    use crate::inbound_policy::{InboundEvent, InboundPolicy, Rejection};
+    use crate::types::ProtocolId;
+
+    #[test]
+    fn non_members_are_rejected() {
+        let member = PeerId::random();
+        let committee_sk = SecretKey::random(&mut OsRng);
+        let committee_member = PeerId::from(PublicKey::from(committee_sk.clone()));
+        let mut allowlist = Allowlist::new(HashSet::from([member]));
+        let addr: Multiaddr = "/ip4/10.0.0.1/tcp/3000".parse().unwrap();
+        let conn = |peer_id| InboundEvent::Connection {
+            peer_id,
+            remote_addr: &addr,
+        };
+        let now = Instant::now();
+        assert!(allowlist.check(conn(member), now).is_ok());
+        assert_eq!(
+            allowlist.check(conn(committee_member), now),
+            Err(Rejection::NotAllowlisted)
+        );
+        let revision = allowlist.revision();
+        allowlist.on_committee_changed(&[PublicKey::from(committee_sk)]);
+        assert_ne!(allowlist.revision(), revision);
+        assert!(allowlist.check(conn(committee_member), now).is_ok());
+        assert_eq!(
+            allowlist.check(
+                InboundEvent::ProtocolOpen {
+                    peer_id: PeerId::random(),
+                    protocol_id: ProtocolId::from_u8(1),
+                },
+                now
+            ),
+            Err(Rejection::NotAllowlisted)
+        );
+    }
+
+    #[test]
+    fn only_fresh_updates_of_authority_are_applied() {
+        let authority_sk = SecretKey::random(&mut OsRng);
+        let allowlist = Allowlist::new(HashSet::new()).with_authority(PublicKey::from(authority_sk.clone()));
+        let peer_id = PeerId::random();
+        let update = AllowlistUpdate {
+            version: 1,
+            peers: vec![peer_id],
+            committee_keys: vec![],
+        };
+        let forged = update.clone().sign(&SecretKey::random(&mut OsRng));
+        assert_eq!(
+            allowlist.apply_signed(&forged),
+            Err(AllowlistError::InvalidSignature)
+        );
+        assert!(!allowlist.is_allowed(&peer_id));
+        let signed = update.sign(&authority_sk);
+        assert_eq!(allowlist.apply_signed(&signed), Ok(()));
+        assert!(allowlist.is_allowed(&peer_id));
+        assert_eq!(
+            allowlist.apply_signed(&signed),
+            Err(AllowlistError::StaleVersion {
+                version: 1,
+                current: 1
+            })
+        );
+    }
+
+    #[test]
+    fn updates_are_rejected_without_authority() {
+        let update = AllowlistUpdate {
+            version: 1,
+            peers: vec![],
+            committee_keys: vec![],
+        }
+        .sign(&SecretKey::random(&mut OsRng));
+        assert_eq!(
+            Allowlist::new(HashSet::new()).apply_signed(&update),
+            Err(AllowlistError::NoAuthority)
+        );
+    }
+}
@@ -0,0 +1,47 @@
+//! Dump records of the network event journal. See `netinspect` to decode recorded messages.
+//!
+//! Usage: netjournal <PATH> [--peer <PEER_ID>] [--source nc|pm|msg] [--grep <PATTERN>]
+//!                          [--since <UNIX_MILLIS>] [--until <UNIX_MILLIS>]
+
+use std::error::Error;
+
+use spectrum_network::journal::{read_journal, EventSource, JournalFilter};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = std::env::args().skip(1);
+    let path = args.next().ok_or("Path to the journal is required")?;
+    let mut filter = JournalFilter::default();
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or(format!("Value for {} is missing", flag))?;
+        match flag.as_str() {
+            "--peer" => filter.peer_id = Some(value),
+            "--source" => {
+                filter.source = Some(match value.as_str() {
+                    "nc" => EventSource::NetworkController,
+                    "pm" => EventSource::PeerManager,
+                    "msg" => EventSource::InboundMessage,
+                    _ => return Err(format!("Unknown source {}", value).into()),
+                })
+            }
+            "--grep" => filter.contains = Some(value),
+            "--since" => filter.since = Some(value.parse()?),
+            "--until" => filter.until = Some(value.parse()?),
+            _ => return Err(format!("Unknown flag {}", flag).into()),
+        }
+    }
+    for rec in read_journal(path, &filter)? {
+        let source = match rec.source {
+            EventSource::NetworkController => "NC",
+            EventSource::PeerManager => "PM",
+            EventSource::InboundMessage => "IN",
+        };
+        println!(
+            "{} [{}] {} {}",
+            rec.timestamp,
+            source,
+            rec.peer_id.as_deref().unwrap_or("-"),
+            rec.event
+        );
+    }
+    Ok(())
+}
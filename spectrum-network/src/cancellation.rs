@@ -0,0 +1,175 @@
+//! Cooperative cancellation of async work.
+//!
+//! A [`CancellationToken`] is handed over to work which has to stop on shutdown, epoch transition
+//! or reset of a round. The work checks the token at points where it's safe to stop instead of being
+//! dropped at an arbitrary await point. Tokens form a tree: cancelling a token cancels all of its
+//! children, e.g. every task spawned by a behaviour is given a child of the behaviour's token.
+
+use std::fmt::{Debug, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll, Waker};
+
+#[derive(Default)]
+struct Node {
+    cancelled: AtomicBool,
+    state: Mutex<NodeState>,
+}
+
+#[derive(Default)]
+struct NodeState {
+    wakers: Vec<Waker>,
+    children: Vec<Weak<Node>>,
+}
+
+fn cancel_node(node: &Node) {
+    // The flag is set before the state is locked, see [`CancellationToken::child_token`].
+    if node.cancelled.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let NodeState { wakers, children } = std::mem::take(&mut *node.state.lock().unwrap());
+    for waker in wakers {
+        waker.wake();
+    }
+    for child in children.iter().filter_map(Weak::upgrade) {
+        cancel_node(&child);
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct CancellationToken(Arc<Node>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Token which is cancelled along with this one, but can also be cancelled on its own.
+    pub fn child_token(&self) -> Self {
+        let child = Self::new();
+        let mut state = self.0.state.lock().unwrap();
+        if self.is_cancelled() {
+            drop(state);
+            child.cancel();
+        } else {
+            state.children.retain(|c| c.strong_count() > 0);
+            state.children.push(Arc::downgrade(&child.0));
+        }
+        child
+    }
+
+    /// Cancel the token and all of its children.
+    pub fn cancel(&self) {
+        cancel_node(&self.0);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once the token is cancelled.
+    pub fn cancelled(&self) -> WaitForCancellation {
+        WaitForCancellation(self.clone())
+    }
+
+    /// Cancel the token once the returned guard is dropped.
+    pub fn drop_guard(self) -> DropGuard {
+        DropGuard(Some(self))
+    }
+}
+
+impl Debug for CancellationToken {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// Future returned by [`CancellationToken::cancelled`].
+pub struct WaitForCancellation(CancellationToken);
+
+impl Future for WaitForCancellation {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.0.is_cancelled() {
+            return Poll::Ready(());
+        }
+        let mut state = self.0 .0.state.lock().unwrap();
+        // The token might have been cancelled before the lock was taken.
+        if self.0.is_cancelled() {
+            return Poll::Ready(());
+        }
+        if !state.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+            state.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+/// Cancels the token once dropped, unless disarmed.
+#[derive(Debug)]
+pub struct DropGuard(Option<CancellationToken>);
+
+impl DropGuard {
+    /// Give up the guard without cancelling the token.
+    pub fn disarm(mut self) -> CancellationToken {
+        self.0.take().unwrap()
+    }
+}
+
+impl Drop for DropGuard {
+    fn drop(&mut self) {
+        if let Some(token) = self.0.take() {
+            token.cancel();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+
+    use crate::cancellation::CancellationToken;
+
+    #[test]
+    fn cancellation_propagates_to_children_only() {
+        let parent = CancellationToken::new();
+        let child = parent.child_token();
+        let grandchild = child.child_token();
+        let sibling = parent.child_token();
+        child.cancel();
+        assert!(child.is_cancelled() && grandchild.is_cancelled());
+        assert!(!parent.is_cancelled() && !sibling.is_cancelled());
+        parent.cancel();
+        assert!(sibling.is_cancelled());
+        // Children of a cancelled token are born cancelled.
+        assert!(parent.child_token().is_cancelled());
+    }
+
+    #[async_std::test]
+    async fn waiters_are_woken_on_cancellation() {
+        let token = CancellationToken::new();
+        let mut cancelled = token.child_token().cancelled();
+        assert!((&mut cancelled).now_or_never().is_none());
+        let canceller = async_std::task::spawn({
+            let token = token.clone();
+            async move { token.cancel() }
+        });
+        cancelled.await;
+        canceller.await;
+    }
+
+    #[test]
+    fn drop_guard_cancels_unless_disarmed() {
+        let token = CancellationToken::new();
+        drop(token.clone().drop_guard());
+        assert!(token.is_cancelled());
+        let token = CancellationToken::new();
+        let token = token.drop_guard().disarm();
+        assert!(!token.is_cancelled());
+    }
+}
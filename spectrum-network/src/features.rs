@@ -0,0 +1,243 @@
+//! Feature flags guarding staged rollouts of protocol changes.
+//!
+//! New message types or behaviours of protocols are put behind named features. Every feature is
+//! activated by a condition: either right away, or once the chain reaches the given height or
+//! epoch. Conditions come from the config of the node, activations signaled by the ledger
+//! (e.g. agreed on-chain) override configured ones. Protocol handlers consult a shared
+//! [`FeatureFlags`] whenever they decide, so features go live network-wide at the same point
+//! of the chain without restarting nodes.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+
+/// Condition a feature becomes active at.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Activation {
+    Enabled,
+    Disabled,
+    /// Active starting from the block at the given height.
+    AtHeight(u64),
+    /// Active starting from the given epoch.
+    AtEpoch(u64),
+}
+
+impl Activation {
+    pub fn is_met(&self, progress: ChainProgress) -> bool {
+        match self {
+            Activation::Enabled => true,
+            Activation::Disabled => false,
+            Activation::AtHeight(height) => progress.height >= *height,
+            Activation::AtEpoch(epoch) => progress.epoch >= *epoch,
+        }
+    }
+}
+
+/// Tip of the chain as seen by the local node.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct ChainProgress {
+    pub height: u64,
+    pub epoch: u64,
+}
+
+/// Configured activations by feature name. Features not mentioned here stay disabled
+/// unless activated by the ledger.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct FeatureFlagsConf(pub BTreeMap<String, Activation>);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivationSource {
+    Config,
+    Ledger,
+}
+
+/// Current state of a feature, as exposed by the API.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeatureStatus {
+    pub feature: String,
+    pub activation: Activation,
+    pub source: ActivationSource,
+    pub active: bool,
+}
+
+#[derive(Debug)]
+struct FlagsState {
+    progress: ChainProgress,
+    configured: BTreeMap<String, Activation>,
+    signaled: BTreeMap<String, Activation>,
+}
+
+impl FlagsState {
+    fn activation(&self, feature: &str) -> Option<(Activation, ActivationSource)> {
+        self.signaled
+            .get(feature)
+            .map(|act| (*act, ActivationSource::Ledger))
+            .or_else(|| {
+                self.configured
+                    .get(feature)
+                    .map(|act| (*act, ActivationSource::Config))
+            })
+    }
+
+    fn is_enabled(&self, feature: &str) -> bool {
+        self.activation(feature)
+            .is_some_and(|(act, _)| act.is_met(self.progress))
+    }
+
+    fn status(&self) -> Vec<FeatureStatus> {
+        let mut features = self
+            .configured
+            .keys()
+            .chain(self.signaled.keys())
+            .collect::<Vec<_>>();
+        features.sort();
+        features.dedup();
+        features
+            .into_iter()
+            .filter_map(|feature| {
+                self.activation(feature)
+                    .map(|(activation, source)| FeatureStatus {
+                        feature: feature.clone(),
+                        activation,
+                        source,
+                        active: activation.is_met(self.progress),
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Feature flags shared by all components of the node.
+#[derive(Clone, Debug)]
+pub struct FeatureFlags {
+    state: Arc<RwLock<FlagsState>>,
+}
+
+impl FeatureFlags {
+    pub fn new(conf: FeatureFlagsConf) -> Self {
+        Self {
+            state: Arc::new(RwLock::new(FlagsState {
+                progress: ChainProgress::default(),
+                configured: conf.0,
+                signaled: BTreeMap::new(),
+            })),
+        }
+    }
+
+    pub fn is_enabled(&self, feature: &str) -> bool {
+        self.state.read().unwrap().is_enabled(feature)
+    }
+
+    /// Update the tip of the chain, features whose conditions are met become active.
+    pub fn on_chain_progress(&self, progress: ChainProgress) {
+        let mut state = self.state.write().unwrap();
+        let before = state.status();
+        state.progress = progress;
+        for status in state.status() {
+            if status.active && !before.iter().any(|s| s.feature == status.feature && s.active) {
+                info!(
+                    "[Features] Feature {} activated at {:?}",
+                    status.feature, progress
+                );
+            }
+        }
+    }
+
+    /// Activation of the feature signaled by the ledger, overrides the configured one.
+    pub fn signal(&self, feature: &str, activation: Activation) {
+        info!("[Features] Ledger signaled {:?} of {}", activation, feature);
+        self.state
+            .write()
+            .unwrap()
+            .signaled
+            .insert(feature.to_string(), activation);
+    }
+
+    pub fn progress(&self) -> ChainProgress {
+        self.state.read().unwrap().progress
+    }
+
+    /// Status of all known features ordered by name.
+    pub fn status(&self) -> Vec<FeatureStatus> {
+        self.state.read().unwrap().status()
+    }
+}
+
+impl Default for FeatureFlags {
+    fn default() -> Self {
+        Self::new(FeatureFlagsConf::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::features::{
+        Activation, ActivationSource, ChainProgress, FeatureFlags, FeatureFlagsConf, FeatureStatus,
+    };
+
+    fn flags() -> FeatureFlags {
+        FeatureFlags::new(FeatureFlagsConf(BTreeMap::from([
+            ("always".to_string(), Activation::Enabled),
+            ("at_height".to_string(), Activation::AtHeight(100)),
+            ("at_epoch".to_string(), Activation::AtEpoch(2)),
+        ])))
+    }
+
+    #[test]
+    fn features_activate_with_chain_progress() {
+        let flags = flags();
+        assert!(flags.is_enabled("always"));
+        assert!(!flags.is_enabled("at_height"));
+        assert!(!flags.is_enabled("at_epoch"));
+        assert!(!flags.is_enabled("unknown"));
+        flags.on_chain_progress(ChainProgress {
+            height: 100,
+            epoch: 1,
+        });
+        assert!(flags.is_enabled("at_height"));
+        assert!(!flags.is_enabled("at_epoch"));
+        flags.on_chain_progress(ChainProgress {
+            height: 2000,
+            epoch: 2,
+        });
+        assert!(flags.is_enabled("at_epoch"));
+    }
+
+    #[test]
+    fn ledger_signal_overrides_config() {
+        let flags = flags();
+        flags.signal("always", Activation::AtHeight(10));
+        flags.signal("new", Activation::Enabled);
+        assert!(!flags.is_enabled("always"));
+        assert!(flags.is_enabled("new"));
+        assert_eq!(
+            flags.status().into_iter().find(|s| s.feature == "always"),
+            Some(FeatureStatus {
+                feature: "always".to_string(),
+                activation: Activation::AtHeight(10),
+                source: ActivationSource::Ledger,
+                active: false,
+            })
+        );
+        assert_eq!(flags.status().len(), 4);
+    }
+
+    #[test]
+    fn conf_is_read_from_yaml() {
+        let conf: FeatureFlagsConf = serde_yaml::from_str("a: enabled\nb: !at_height 100\n").unwrap();
+        assert_eq!(
+            conf,
+            FeatureFlagsConf(BTreeMap::from([
+                ("a".to_string(), Activation::Enabled),
+                ("b".to_string(), Activation::AtHeight(100)),
+            ]))
+        );
+    }
+}
@@ -0,0 +1,417 @@
+//! Policies guarding the node against abusive peers.
+//!
+//! Every inbound network event (connection, protocol open, message) is passed through an
+//! [`InboundPolicyChain`] before the network controller acts upon it. Policies are checked in
+//! the order they were added, the first one to reject the event short-circuits the chain.
+//! A [`Rejection`] tells why the event was dropped and how the peer should be punished for it.
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId};
+
+use crate::peer_manager::data::ReputationChange;
+use crate::types::ProtocolId;
+
+#[derive(Debug, Copy, Clone)]
+pub enum InboundEvent<'a> {
+    /// Peer connected to us.
+    Connection {
+        peer_id: PeerId,
+        remote_addr: &'a Multiaddr,
+    },
+    /// Peer requested to open a protocol.
+    ProtocolOpen {
+        peer_id: PeerId,
+        protocol_id: ProtocolId,
+    },
+    /// Peer sent a message of the given size.
+    Message {
+        peer_id: PeerId,
+        protocol_id: ProtocolId,
+        size: usize,
+    },
+}
+
+impl<'a> InboundEvent<'a> {
+    pub fn peer_id(&self) -> PeerId {
+        match self {
+            InboundEvent::Connection { peer_id, .. }
+            | InboundEvent::ProtocolOpen { peer_id, .. }
+            | InboundEvent::Message { peer_id, .. } => *peer_id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum Rejection {
+    #[error("Peer is banned")]
+    Banned,
+    #[error("Too many connections from {ip}, limit is {limit}")]
+    TooManyConnectionsFromIp { ip: IpAddr, limit: usize },
+    #[error("Rate limit exceeded")]
+    RateLimited,
+    #[error("Message of {size} bytes exceeds the limit of {limit} bytes")]
+    MessageTooLarge { size: usize, limit: usize },
+    #[error("Peer is not a member of the permissioned network")]
+    NotAllowlisted,
+}
+
+impl Rejection {
+    /// Reputation change the offending peer deserves, if any.
+    pub fn reputation_change(&self) -> Option<ReputationChange> {
+        match self {
+            // Banned peers are not going to be connected anyway.
+            Rejection::Banned => None,
+            // Peers behind the same NAT aren't necessarily malicious.
+            Rejection::TooManyConnectionsFromIp { .. } => None,
+            Rejection::RateLimited => Some(ReputationChange::Spam),
+            Rejection::MessageTooLarge { .. } => Some(ReputationChange::OversizedMessage),
+            // Non-members are refused regardless of their behaviour.
+            Rejection::NotAllowlisted => None,
+        }
+    }
+}
+
+pub trait InboundPolicy: Send {
+    /// Decide whether the event should be processed.
+    fn check(&mut self, event: InboundEvent, now: Instant) -> Result<(), Rejection>;
+
+    /// Inbound connection accepted by all policies is established.
+    fn connection_established(&mut self, _peer_id: PeerId, _remote_addr: &Multiaddr) {}
+
+    /// Inbound connection is closed.
+    fn connection_closed(&mut self, _peer_id: PeerId, _remote_addr: &Multiaddr) {}
+}
+
+/// Policies applied to inbound events one after another.
+#[derive(Default)]
+pub struct InboundPolicyChain {
+    policies: Vec<Box<dyn InboundPolicy>>,
+}
+
+impl InboundPolicyChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append the policy to the end of the chain.
+    pub fn with<P: InboundPolicy + 'static>(mut self, policy: P) -> Self {
+        self.policies.push(Box::new(policy));
+        self
+    }
+
+    /// Assemble the chain of policies enabled in the given config.
+    pub fn from_conf(conf: InboundPolicyConf) -> Self {
+        let mut chain = Self::new();
+        if !conf.banned_peers.is_empty() || !conf.banned_ips.is_empty() {
+            chain = chain.with(BannedPeers::new(conf.banned_peers, conf.banned_ips));
+        }
+        if let Some(limit) = conf.max_connections_per_ip {
+            chain = chain.with(IpLimit::new(limit));
+        }
+        if let Some(conf) = conf.message_size_limit {
+            chain = chain.with(MessageSizeLimit::new(conf));
+        }
+        if let Some(conf) = conf.rate_limit {
+            chain = chain.with(RateLimit::new(conf));
+        }
+        chain
+    }
+
+    pub fn check(&mut self, event: InboundEvent, now: Instant) -> Result<(), Rejection> {
+        self.policies.iter_mut().try_for_each(|p| p.check(event, now))
+    }
+
+    pub fn connection_established(&mut self, peer_id: PeerId, remote_addr: &Multiaddr) {
+        for p in self.policies.iter_mut() {
+            p.connection_established(peer_id, remote_addr);
+        }
+    }
+
+    pub fn connection_closed(&mut self, peer_id: PeerId, remote_addr: &Multiaddr) {
+        for p in self.policies.iter_mut() {
+            p.connection_closed(peer_id, remote_addr);
+        }
+    }
+}
+
+/// Configuration of the standard policies, `None` disables the corresponding policy.
+#[derive(Debug, Clone, Default)]
+pub struct InboundPolicyConf {
+    pub banned_peers: HashSet<PeerId>,
+    pub banned_ips: HashSet<IpAddr>,
+    pub max_connections_per_ip: Option<usize>,
+    pub message_size_limit: Option<MessageSizeLimitConf>,
+    pub rate_limit: Option<RateLimitConf>,
+}
+
+/// Rejects all events from the given peers and connections from the given IPs.
+pub struct BannedPeers {
+    peers: HashSet<PeerId>,
+    ips: HashSet<IpAddr>,
+}
+
+impl BannedPeers {
+    pub fn new(peers: HashSet<PeerId>, ips: HashSet<IpAddr>) -> Self {
+        Self { peers, ips }
+    }
+}
+
+impl InboundPolicy for BannedPeers {
+    fn check(&mut self, event: InboundEvent, _now: Instant) -> Result<(), Rejection> {
+        let banned_ip = match event {
+            InboundEvent::Connection { remote_addr, .. } => {
+                ip_of(remote_addr).is_some_and(|ip| self.ips.contains(&ip))
+            }
+            _ => false,
+        };
+        if banned_ip || self.peers.contains(&event.peer_id()) {
+            return Err(Rejection::Banned);
+        }
+        Ok(())
+    }
+}
+
+/// Limits the number of inbound connections from a single IP.
+pub struct IpLimit {
+    max_connections: usize,
+    connections: HashMap<IpAddr, usize>,
+}
+
+impl IpLimit {
+    pub fn new(max_connections: usize) -> Self {
+        Self {
+            max_connections,
+            connections: HashMap::new(),
+        }
+    }
+}
+
+impl InboundPolicy for IpLimit {
+    fn check(&mut self, event: InboundEvent, _now: Instant) -> Result<(), Rejection> {
+        if let InboundEvent::Connection { remote_addr, .. } = event {
+            if let Some(ip) = ip_of(remote_addr) {
+                if self.connections.get(&ip).copied().unwrap_or(0) >= self.max_connections {
+                    return Err(Rejection::TooManyConnectionsFromIp {
+                        ip,
+                        limit: self.max_connections,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn connection_established(&mut self, _peer_id: PeerId, remote_addr: &Multiaddr) {
+        if let Some(ip) = ip_of(remote_addr) {
+            *self.connections.entry(ip).or_insert(0) += 1;
+        }
+    }
+
+    fn connection_closed(&mut self, _peer_id: PeerId, remote_addr: &Multiaddr) {
+        if let Some(ip) = ip_of(remote_addr) {
+            if let Entry::Occupied(mut entry) = self.connections.entry(ip) {
+                *entry.get_mut() -= 1;
+                if *entry.get() == 0 {
+                    entry.remove();
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MessageSizeLimitConf {
+    /// Limit applied to protocols not listed in `per_protocol`.
+    pub default_max_size: usize,
+    pub per_protocol: HashMap<ProtocolId, usize>,
+}
+
+/// Rejects messages exceeding the size limit of their protocol.
+pub struct MessageSizeLimit {
+    conf: MessageSizeLimitConf,
+}
+
+impl MessageSizeLimit {
+    pub fn new(conf: MessageSizeLimitConf) -> Self {
+        Self { conf }
+    }
+}
+
+impl InboundPolicy for MessageSizeLimit {
+    fn check(&mut self, event: InboundEvent, _now: Instant) -> Result<(), Rejection> {
+        if let InboundEvent::Message {
+            protocol_id, size, ..
+        } = event
+        {
+            let limit = self
+                .conf
+                .per_protocol
+                .get(&protocol_id)
+                .copied()
+                .unwrap_or(self.conf.default_max_size);
+            if size > limit {
+                return Err(Rejection::MessageTooLarge { size, limit });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RateLimitConf {
+    /// Max number of protocol opens and messages a peer can send in a burst.
+    pub burst: u32,
+    /// Time it takes to regain one event of the burst.
+    pub refill_interval: Duration,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Bucket {
+    tokens: u32,
+    refilled_at: Instant,
+}
+
+/// Limits the rate of protocol opens and messages per peer (token bucket).
+pub struct RateLimit {
+    conf: RateLimitConf,
+    buckets: HashMap<PeerId, Bucket>,
+}
+
+impl RateLimit {
+    pub fn new(conf: RateLimitConf) -> Self {
+        Self {
+            conf,
+            buckets: HashMap::new(),
+        }
+    }
+}
+
+impl InboundPolicy for RateLimit {
+    fn check(&mut self, event: InboundEvent, now: Instant) -> Result<(), Rejection> {
+        if let InboundEvent::Connection { .. } = event {
+            return Ok(());
+        }
+        let conf = self.conf;
+        let bucket = self.buckets.entry(event.peer_id()).or_insert(Bucket {
+            tokens: conf.burst,
+            refilled_at: now,
+        });
+        if !conf.refill_interval.is_zero() {
+            let refills = now.saturating_duration_since(bucket.refilled_at).as_nanos()
+                / conf.refill_interval.as_nanos();
+            if refills > 0 {
+                bucket.tokens = conf.burst.min(bucket.tokens.saturating_add(refills as u32));
+                bucket.refilled_at += conf.refill_interval * refills as u32;
+            }
+        } else {
+            bucket.tokens = conf.burst;
+        }
+        if bucket.tokens == 0 {
+            return Err(Rejection::RateLimited);
+        }
+        bucket.tokens -= 1;
+        Ok(())
+    }
+
+    fn connection_closed(&mut self, peer_id: PeerId, _remote_addr: &Multiaddr) {
+        self.buckets.remove(&peer_id);
+    }
+}
+
+fn ip_of(addr: &Multiaddr) -> Option<IpAddr> {
+    addr.iter().find_map(|p| match p {
+        Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+        Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+    use std::time::{Duration, Instant};
+
+    use libp2p::{Multiaddr, PeerId};
+
+    use crate::inbound_policy::{
+        BannedPeers, InboundEvent, InboundPolicyChain, InboundPolicyConf, IpLimit, MessageSizeLimitConf,
+        RateLimit, RateLimitConf, Rejection,
+    };
+    use crate::peer_manager::data::ReputationChange;
+    use crate::types::ProtocolId;
+
+    fn message(peer_id: PeerId, size: usize) -> InboundEvent<'static> {
+        InboundEvent::Message {
+            peer_id,
+            protocol_id: ProtocolId::from_u8(1),
+            size,
+        }
+    }
+
+    #[test]
+    fn first_rejection_short_circuits_the_chain() {
+        let peer_id = PeerId::random();
+        let mut chain = InboundPolicyChain::from_conf(InboundPolicyConf {
+            banned_peers: HashSet::from([peer_id]),
+            message_size_limit: Some(MessageSizeLimitConf {
+                default_max_size: 10,
+                per_protocol: HashMap::new(),
+            }),
+            ..InboundPolicyConf::default()
+        });
+        let now = Instant::now();
+        assert_eq!(chain.check(message(peer_id, 100), now), Err(Rejection::Banned));
+        let rejection = chain.check(message(PeerId::random(), 100), now).unwrap_err();
+        assert_eq!(rejection, Rejection::MessageTooLarge { size: 100, limit: 10 });
+        assert_eq!(
+            rejection.reputation_change(),
+            Some(ReputationChange::OversizedMessage)
+        );
+        assert!(chain.check(message(PeerId::random(), 10), now).is_ok());
+    }
+
+    #[test]
+    fn connections_from_ip_are_limited() {
+        let addr: Multiaddr = "/ip4/10.0.0.1/tcp/3000".parse().unwrap();
+        let mut chain = InboundPolicyChain::new()
+            .with(BannedPeers::new(HashSet::new(), HashSet::new()))
+            .with(IpLimit::new(1));
+        let (p1, p2) = (PeerId::random(), PeerId::random());
+        let conn = |peer_id| InboundEvent::Connection {
+            peer_id,
+            remote_addr: &addr,
+        };
+        let now = Instant::now();
+        assert!(chain.check(conn(p1), now).is_ok());
+        chain.connection_established(p1, &addr);
+        assert!(matches!(
+            chain.check(conn(p2), now),
+            Err(Rejection::TooManyConnectionsFromIp { limit: 1, .. })
+        ));
+        chain.connection_closed(p1, &addr);
+        assert!(chain.check(conn(p2), now).is_ok());
+    }
+
+    #[test]
+    fn rate_limit_refills_over_time() {
+        let peer_id = PeerId::random();
+        let mut policy = InboundPolicyChain::new().with(RateLimit::new(RateLimitConf {
+            burst: 2,
+            refill_interval: Duration::from_secs(1),
+        }));
+        let t0 = Instant::now();
+        assert!(policy.check(message(peer_id, 1), t0).is_ok());
+        assert!(policy.check(message(peer_id, 1), t0).is_ok());
+        assert_eq!(policy.check(message(peer_id, 1), t0), Err(Rejection::RateLimited));
+        // Other peers have their own budget.
+        assert!(policy.check(message(PeerId::random(), 1), t0).is_ok());
+        let t1 = t0 + Duration::from_millis(1500);
+        assert!(policy.check(message(peer_id, 1), t1).is_ok());
+        assert_eq!(policy.check(message(peer_id, 1), t1), Err(Rejection::RateLimited));
+    }
+}
@@ -0,0 +1,619 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::iter;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use libp2p::PeerId;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::network_controller::NetworkControllerOut;
+use crate::peer_manager::PeerManagerOut;
+use crate::types::{ProtocolId, ProtocolTag, RawMessage};
+
+const MAGIC: [u8; 4] = *b"SNJL";
+/// magic ++ slot_size (u32) ++ capacity (u32) ++ total number of written records (u64).
+const HEADER_SIZE: u64 = 20;
+/// Each slot is prefixed with the length of the encoded record.
+const SLOT_LEN_PREFIX: usize = 2;
+/// Largest slot whose record length still fits into the prefix.
+const MAX_SLOT_SIZE: u32 = u16::MAX as u32 + SLOT_LEN_PREFIX as u32;
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EventSource {
+    NetworkController,
+    PeerManager,
+    /// Message received from a peer, see [`JournalConfig::record_messages`].
+    InboundMessage,
+}
+
+/// Raw message as it was received over the wire.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RecordedFrame {
+    pub protocol: ProtocolId,
+    pub version: u8,
+    #[serde(with = "serde_bytes")]
+    pub bytes: Vec<u8>,
+    /// Set if the message didn't fit into a slot, `bytes` are empty then.
+    pub truncated: bool,
+}
+
+/// A single entry of the journal.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct JournalRecord {
+    /// Milliseconds since UNIX epoch.
+    pub timestamp: u64,
+    pub source: EventSource,
+    /// Peer the event relates to.
+    pub peer_id: Option<String>,
+    /// Human readable representation of the event.
+    pub event: String,
+    /// Set for [`EventSource::InboundMessage`].
+    #[serde(default)]
+    pub frame: Option<RecordedFrame>,
+}
+
+/// Criteria to select journal records.
+#[derive(Debug, Clone, Default)]
+pub struct JournalFilter {
+    pub source: Option<EventSource>,
+    pub peer_id: Option<String>,
+    /// Protocol of recorded messages. Records of other events don't match if set.
+    pub protocol: Option<ProtocolId>,
+    /// Substring the event must contain.
+    pub contains: Option<String>,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+}
+
+impl JournalFilter {
+    pub fn matches(&self, rec: &JournalRecord) -> bool {
+        self.source.map_or(true, |s| s == rec.source)
+            && self
+                .peer_id
+                .as_ref()
+                .map_or(true, |pid| rec.peer_id.as_ref() == Some(pid))
+            && self.protocol.map_or(true, |prot| {
+                rec.frame.as_ref().map(|frame| frame.protocol) == Some(prot)
+            })
+            && self
+                .contains
+                .as_ref()
+                .map_or(true, |pat| rec.event.contains(pat.as_str()))
+            && self.since.map_or(true, |ts| rec.timestamp >= ts)
+            && self.until.map_or(true, |ts| rec.timestamp <= ts)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct JournalConfig {
+    /// Max number of records kept. Oldest records are overwritten first.
+    pub capacity: u32,
+    /// Size of a single record on disk. Longer records are truncated.
+    pub slot_size: u32,
+    /// Record messages received from peers. Slots should be large enough to hold typical messages
+    /// of the protocols of interest (up to 64 KiB), larger ones are recorded without their content.
+    pub record_messages: bool,
+}
+
+impl Default for JournalConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 65536,
+            slot_size: 512,
+            record_messages: false,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum JournalError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Not a journal file.")]
+    BadHeader,
+    #[error("Record doesn't fit into a slot.")]
+    RecordTooLarge,
+    #[error("Invalid journal configuration: {0}")]
+    InvalidConfig(&'static str),
+}
+
+/// Bounded on-disk journal of network events organized as a ring file.
+pub struct EventJournal {
+    file: File,
+    conf: JournalConfig,
+    written: u64,
+}
+
+impl EventJournal {
+    /// Open the journal at the given path. The journal is reset if it was created
+    /// with a different configuration.
+    pub fn open<P: AsRef<Path>>(path: P, conf: JournalConfig) -> Result<Self, JournalError> {
+        if conf.capacity == 0 {
+            return Err(JournalError::InvalidConfig("capacity must be positive"));
+        }
+        if conf.slot_size as usize <= SLOT_LEN_PREFIX {
+            return Err(JournalError::InvalidConfig(
+                "slot_size must exceed the length prefix",
+            ));
+        }
+        if conf.slot_size > MAX_SLOT_SIZE {
+            return Err(JournalError::InvalidConfig(
+                "slot_size must not exceed 65537 bytes",
+            ));
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(path)?;
+        let written = match read_header(&mut file) {
+            Ok((slot_size, capacity, written))
+                if slot_size == conf.slot_size && capacity == conf.capacity =>
+            {
+                written
+            }
+            _ => {
+                file.set_len(0)?;
+                write_header(&mut file, conf, 0)?;
+                0
+            }
+        };
+        Ok(Self { file, conf, written })
+    }
+
+    /// Move the journal to a dedicated thread, so that recording events never blocks on disk I/O.
+    /// Up to `buffer_size` records wait to be written, the ones that don't fit are dropped.
+    pub fn spawn_writer(self, buffer_size: usize) -> JournalSink {
+        let record_messages = self.conf.record_messages;
+        let (snd, recv) = mpsc::sync_channel(buffer_size);
+        let writer = thread::Builder::new()
+            .name("event-journal".to_string())
+            .spawn(move || self.run_writer(recv))
+            .expect("Failed to spawn the journal writer");
+        JournalSink {
+            snd,
+            writer,
+            record_messages,
+            dropped: 0,
+        }
+    }
+
+    fn run_writer(mut self, recv: Receiver<JournalRecord>) -> Self {
+        while let Ok(rec) = recv.recv() {
+            let batch = iter::once(rec).chain(recv.try_iter()).collect::<Vec<_>>();
+            if let Err(err) = self.append_batch(batch) {
+                warn!("[Journal] Failed to write records: {}", err);
+            }
+        }
+        self
+    }
+
+    pub fn append(&mut self, rec: JournalRecord) -> Result<(), JournalError> {
+        self.write_slot(rec)?;
+        write_header(&mut self.file, self.conf, self.written)
+    }
+
+    /// Append records updating the header once, records that can't be written are skipped.
+    pub fn append_batch(&mut self, records: Vec<JournalRecord>) -> Result<(), JournalError> {
+        for rec in records {
+            if let Err(err) = self.write_slot(rec) {
+                warn!("[Journal] Failed to write a record: {}", err);
+            }
+        }
+        write_header(&mut self.file, self.conf, self.written)
+    }
+
+    fn write_slot(&mut self, mut rec: JournalRecord) -> Result<(), JournalError> {
+        let max_len = self.conf.slot_size as usize - SLOT_LEN_PREFIX;
+        let mut encoded = encode_record(&rec);
+        // A partial message is of no use, so it's dropped altogether.
+        if encoded.len() > max_len {
+            if let Some(frame) = rec.frame.as_mut() {
+                frame.bytes.clear();
+                frame.truncated = true;
+                encoded = encode_record(&rec);
+            }
+        }
+        // Truncate the event description until the record fits into a slot.
+        while encoded.len() > max_len && !rec.event.is_empty() {
+            let excess = encoded.len() - max_len;
+            let mut cut = rec.event.len().saturating_sub(excess);
+            while !rec.event.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            rec.event.truncate(cut);
+            encoded = encode_record(&rec);
+        }
+        if encoded.len() > max_len {
+            return Err(JournalError::RecordTooLarge);
+        }
+        let mut slot = vec![0u8; self.conf.slot_size as usize];
+        slot[..SLOT_LEN_PREFIX].copy_from_slice(&(encoded.len() as u16).to_le_bytes());
+        slot[SLOT_LEN_PREFIX..SLOT_LEN_PREFIX + encoded.len()].copy_from_slice(&encoded);
+        let ix = self.written % self.conf.capacity as u64;
+        self.file
+            .seek(SeekFrom::Start(HEADER_SIZE + ix * self.conf.slot_size as u64))?;
+        self.file.write_all(&slot)?;
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Number of records available in the journal.
+    pub fn len(&self) -> u64 {
+        self.written.min(self.conf.capacity as u64)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.written == 0
+    }
+}
+
+/// Handle of a journal written by a dedicated thread, see [`EventJournal::spawn_writer`].
+pub struct JournalSink {
+    snd: SyncSender<JournalRecord>,
+    writer: JoinHandle<EventJournal>,
+    record_messages: bool,
+    /// Number of records dropped since the last one buffered successfully.
+    dropped: u64,
+}
+
+impl JournalSink {
+    pub fn record_network_event(&mut self, event: &NetworkControllerOut) {
+        let peer_id = match event {
+            NetworkControllerOut::ConnectedWithInboundPeer(pid)
+            | NetworkControllerOut::ConnectedWithOutboundPeer(pid) => Some(*pid),
+            NetworkControllerOut::Disconnected { peer_id, .. }
+            | NetworkControllerOut::ProtocolPendingApprove { peer_id, .. }
+            | NetworkControllerOut::ProtocolPendingEnable { peer_id, .. }
+            | NetworkControllerOut::ProtocolEnabled { peer_id, .. }
+            | NetworkControllerOut::ProtocolDisabled { peer_id, .. }
+            | NetworkControllerOut::PeerPunished { peer_id, .. }
+            | NetworkControllerOut::PeerBanned { peer_id, .. }
+            | NetworkControllerOut::PeerNotAllowlisted { peer_id }
+            | NetworkControllerOut::ProtocolEnableFailed { peer_id, .. } => Some(*peer_id),
+            NetworkControllerOut::OneShotBroadcastDone { .. } => None,
+        };
+        self.record(
+            EventSource::NetworkController,
+            peer_id,
+            format!("{:?}", event),
+            None,
+        );
+    }
+
+    pub fn record_peer_manager_event(&mut self, event: &PeerManagerOut) {
+        let peer_id = match event {
+            PeerManagerOut::Connect(dest) => dest.peer_id(),
+            PeerManagerOut::Drop(pid)
+            | PeerManagerOut::AcceptIncomingConnection(pid, _)
+            | PeerManagerOut::Reject(pid, _)
+            | PeerManagerOut::StartProtocol(_, pid)
+            | PeerManagerOut::GaveUp(pid) => *pid,
+            PeerManagerOut::NotifyPeerPunished { peer_id, .. } => *peer_id,
+        };
+        self.record(
+            EventSource::PeerManager,
+            Some(peer_id),
+            format!("{:?}", event),
+            None,
+        );
+    }
+
+    /// Record a message received from the peer, if enabled in [`JournalConfig`].
+    pub fn record_inbound_message(
+        &mut self,
+        peer_id: PeerId,
+        protocol_tag: ProtocolTag,
+        content: &RawMessage,
+    ) {
+        if !self.record_messages {
+            return;
+        }
+        let frame = RecordedFrame {
+            protocol: protocol_tag.protocol_id(),
+            version: u8::from(protocol_tag.protocol_ver()),
+            bytes: content.as_ref().to_vec(),
+            truncated: false,
+        };
+        let event = format!("{} ({} bytes)", protocol_tag, content.as_ref().len());
+        self.record(EventSource::InboundMessage, Some(peer_id), event, Some(frame));
+    }
+
+    fn record(
+        &mut self,
+        source: EventSource,
+        peer_id: Option<PeerId>,
+        event: String,
+        frame: Option<RecordedFrame>,
+    ) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let rec = JournalRecord {
+            timestamp,
+            source,
+            peer_id: peer_id.map(|pid| pid.to_string()),
+            event,
+            frame,
+        };
+        match self.snd.try_send(rec) {
+            Ok(()) if self.dropped > 0 => {
+                warn!(
+                    "[Journal] {} records were dropped as the writer couldn't keep up",
+                    self.dropped
+                );
+                self.dropped = 0;
+            }
+            Ok(()) => {}
+            Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) => self.dropped += 1,
+        }
+    }
+
+    /// Wait until buffered records are written and stop the writer.
+    pub fn close(self) -> Option<EventJournal> {
+        drop(self.snd);
+        self.writer.join().ok()
+    }
+}
+
+/// Read all records from the journal at the given path, oldest first.
+pub fn read_journal<P: AsRef<Path>>(
+    path: P,
+    filter: &JournalFilter,
+) -> Result<Vec<JournalRecord>, JournalError> {
+    read_journal_from(path, filter, 0).map(|(records, _)| records)
+}
+
+/// Read records starting from the one with the given sequence number (i.e. the number of records
+/// written before it), oldest first. Records already overwritten are skipped.
+/// Returns the sequence number of the next record to be written, so that a journal of a running
+/// node can be followed by calling this repeatedly.
+pub fn read_journal_from<P: AsRef<Path>>(
+    path: P,
+    filter: &JournalFilter,
+    from_seq: u64,
+) -> Result<(Vec<JournalRecord>, u64), JournalError> {
+    let mut file = File::open(path)?;
+    let (slot_size, capacity, written) = read_header(&mut file)?;
+    let oldest_seq = written.saturating_sub(capacity as u64);
+    let mut records = Vec::new();
+    let mut slot = vec![0u8; slot_size as usize];
+    for seq in from_seq.max(oldest_seq)..written {
+        let ix = seq % capacity as u64;
+        file.seek(SeekFrom::Start(HEADER_SIZE + ix * slot_size as u64))?;
+        file.read_exact(&mut slot)?;
+        let len = u16::from_le_bytes([slot[0], slot[1]]) as usize;
+        match slot
+            .get(SLOT_LEN_PREFIX..SLOT_LEN_PREFIX + len)
+            .and_then(|bytes| ciborium::de::from_reader::<JournalRecord, _>(bytes).ok())
+        {
+            Some(rec) if filter.matches(&rec) => records.push(rec),
+            Some(_) => {}
+            None => warn!("[Journal] Skipping corrupted record #{}", ix),
+        }
+    }
+    Ok((records, written))
+}
+
+fn encode_record(rec: &JournalRecord) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    ciborium::ser::into_writer(rec, &mut encoded).unwrap();
+    encoded
+}
+
+fn read_header(file: &mut File) -> Result<(u32, u32, u64), JournalError> {
+    let mut header = [0u8; HEADER_SIZE as usize];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut header)?;
+    if header[..4] != MAGIC {
+        return Err(JournalError::BadHeader);
+    }
+    let slot_size = u32::from_le_bytes(header[4..8].try_into().unwrap());
+    let capacity = u32::from_le_bytes(header[8..12].try_into().unwrap());
+    let written = u64::from_le_bytes(header[12..20].try_into().unwrap());
+    if capacity == 0 || (slot_size as usize) <= SLOT_LEN_PREFIX {
+        return Err(JournalError::BadHeader);
+    }
+    Ok((slot_size, capacity, written))
+}
+
+fn write_header(file: &mut File, conf: JournalConfig, written: u64) -> Result<(), JournalError> {
+    let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+    header.extend_from_slice(&MAGIC);
+    header.extend_from_slice(&conf.slot_size.to_le_bytes());
+    header.extend_from_slice(&conf.capacity.to_le_bytes());
+    header.extend_from_slice(&written.to_le_bytes());
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&header)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use libp2p::PeerId;
+
+    use crate::journal::{
+        read_journal, read_journal_from, EventJournal, EventSource, JournalConfig, JournalError,
+        JournalFilter, JournalRecord,
+    };
+    use crate::network_controller::NetworkControllerOut;
+    use crate::peer_manager::PeerManagerOut;
+    use crate::types::{ProtocolId, ProtocolTag, ProtocolVer, RawMessage};
+
+    fn tmp_path(name: &str) -> PathBuf {
+        let mut path = std::env::temp_dir();
+        path.push(format!("{}-{}", name, rand::random::<u64>()));
+        path
+    }
+
+    #[test]
+    fn ring_keeps_latest_records() {
+        let path = tmp_path("journal_ring");
+        let conf = JournalConfig {
+            capacity: 4,
+            slot_size: 256,
+            record_messages: false,
+        };
+        let mut sink = EventJournal::open(&path, conf).unwrap().spawn_writer(16);
+        let peers = (0..6).map(|_| PeerId::random()).collect::<Vec<_>>();
+        for pid in &peers {
+            sink.record_network_event(&NetworkControllerOut::ConnectedWithInboundPeer(*pid));
+        }
+        let journal = sink.close().unwrap();
+        assert_eq!(journal.len(), 4);
+        let records = read_journal(&path, &JournalFilter::default()).unwrap();
+        let recorded_peers = records
+            .into_iter()
+            .map(|r| r.peer_id.unwrap())
+            .collect::<Vec<_>>();
+        let expected_peers = peers[2..].iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        assert_eq!(recorded_peers, expected_peers);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn reopen_continues_journal() {
+        let path = tmp_path("journal_reopen");
+        let conf = JournalConfig {
+            capacity: 8,
+            slot_size: 256,
+            record_messages: false,
+        };
+        let pid = PeerId::random();
+        let mut sink = EventJournal::open(&path, conf).unwrap().spawn_writer(16);
+        sink.record_peer_manager_event(&PeerManagerOut::Drop(pid));
+        sink.close().unwrap();
+        let mut sink = EventJournal::open(&path, conf).unwrap().spawn_writer(16);
+        sink.record_network_event(&NetworkControllerOut::ConnectedWithOutboundPeer(pid));
+        sink.close().unwrap();
+        let filter = JournalFilter {
+            source: Some(EventSource::PeerManager),
+            ..JournalFilter::default()
+        };
+        let records = read_journal(&path, &filter).unwrap();
+        assert_eq!(records.len(), 1);
+        assert!(records[0].event.contains("Drop"));
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn long_records_are_truncated() {
+        let path = tmp_path("journal_truncate");
+        let conf = JournalConfig {
+            capacity: 2,
+            slot_size: 160,
+            record_messages: false,
+        };
+        let mut sink = EventJournal::open(&path, conf).unwrap().spawn_writer(16);
+        sink.record_network_event(&NetworkControllerOut::ConnectedWithInboundPeer(PeerId::random()));
+        sink.close().unwrap();
+        let records = read_journal(&path, &JournalFilter::default()).unwrap();
+        assert_eq!(records.len(), 1);
+        assert!(records[0].event.starts_with("Connected"));
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn batch_skips_records_which_do_not_fit() {
+        let path = tmp_path("journal_batch");
+        let conf = JournalConfig {
+            capacity: 4,
+            slot_size: 80,
+            record_messages: false,
+        };
+        let record = |peer_id: Option<String>| JournalRecord {
+            timestamp: 0,
+            source: EventSource::PeerManager,
+            peer_id,
+            event: "GaveUp".to_string(),
+            frame: None,
+        };
+        let mut journal = EventJournal::open(&path, conf).unwrap();
+        journal
+            .append_batch(vec![record(Some(PeerId::random().to_string())), record(None)])
+            .unwrap();
+        assert_eq!(journal.len(), 1);
+        let records = read_journal(&path, &JournalFilter::default()).unwrap();
+        assert_eq!(records, vec![record(None)]);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn invalid_config_rejected() {
+        let path = tmp_path("journal_invalid_config");
+        let valid = JournalConfig {
+            capacity: 2,
+            slot_size: 256,
+            record_messages: false,
+        };
+        for conf in [
+            JournalConfig { capacity: 0, ..valid },
+            JournalConfig {
+                slot_size: 2,
+                ..valid
+            },
+            JournalConfig {
+                slot_size: 0,
+                ..valid
+            },
+            JournalConfig {
+                slot_size: u16::MAX as u32 + 3,
+                ..valid
+            },
+        ] {
+            assert!(matches!(
+                EventJournal::open(&path, conf),
+                Err(JournalError::InvalidConfig(_))
+            ));
+        }
+        // The largest slot whose length fits into the prefix.
+        let conf = JournalConfig {
+            slot_size: u16::MAX as u32 + 2,
+            ..valid
+        };
+        assert!(EventJournal::open(&path, conf).is_ok());
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn inbound_messages_recorded_if_enabled() {
+        let path = tmp_path("journal_messages");
+        let conf = JournalConfig {
+            capacity: 8,
+            slot_size: 256,
+            record_messages: true,
+        };
+        let mut sink = EventJournal::open(&path, conf).unwrap().spawn_writer(16);
+        let pid = PeerId::random();
+        let tag = ProtocolTag::new(ProtocolId::from_u8(2), ProtocolVer(3));
+        sink.record_network_event(&NetworkControllerOut::ConnectedWithInboundPeer(pid));
+        sink.record_inbound_message(pid, tag, &RawMessage::from(vec![1, 2, 3]));
+        sink.record_inbound_message(pid, tag, &RawMessage::from(vec![0; 512]));
+        sink.close().unwrap();
+        let filter = JournalFilter {
+            protocol: Some(ProtocolId::from_u8(2)),
+            ..JournalFilter::default()
+        };
+        let (records, next_seq) = read_journal_from(&path, &filter, 0).unwrap();
+        assert_eq!(next_seq, 3);
+        let frames = records.into_iter().map(|r| r.frame.unwrap()).collect::<Vec<_>>();
+        assert_eq!(frames[0].version, 3);
+        assert_eq!(frames[0].bytes, vec![1, 2, 3]);
+        assert!(frames[1].truncated && frames[1].bytes.is_empty());
+        // Following the journal yields only new records.
+        let mut sink = EventJournal::open(&path, conf).unwrap().spawn_writer(16);
+        sink.record_inbound_message(pid, tag, &RawMessage::from(vec![4]));
+        sink.close().unwrap();
+        let (records, next_seq) = read_journal_from(&path, &filter, next_seq).unwrap();
+        assert_eq!(next_seq, 4);
+        assert_eq!(records.len(), 1);
+        let _ = std::fs::remove_file(path);
+    }
+}
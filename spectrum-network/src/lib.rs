@@ -1,3 +1,11 @@
+pub mod allowlist;
+pub mod cancellation;
+pub mod features;
+pub mod inbound_policy;
+pub mod journal;
+pub mod memory_budget;
+pub mod metrics;
+pub mod network_builder;
 pub mod network_controller;
 pub mod one_shot_upgrade;
 pub mod peer_conn_handler;
@@ -6,4 +14,6 @@ pub mod protocol;
 pub mod protocol_api;
 pub mod protocol_handler;
 pub mod protocol_upgrade;
+pub mod store_recovery;
+pub mod transport;
 pub mod types;
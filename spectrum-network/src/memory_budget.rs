@@ -0,0 +1,350 @@
+//! Accounting of memory consumed by caches and queues of the node.
+//!
+//! Every component holding a potentially large amount of data (queues of pending messages,
+//! dedup caches, etc.) registers a [`MemoryQuota`] within a shared [`MemoryBudget`] and reports
+//! the bytes it occupies. Once either the quota of the component or the global limit is
+//! exceeded, components are asked to shrink, largest consumers first.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use crate::metrics::{Metric, MetricsSink};
+
+/// Name of the component consuming memory.
+pub type ComponentId = &'static str;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum BudgetExceeded {
+    #[error("Quota of {component} exceeded: {used} + {requested} > {quota} bytes")]
+    Quota {
+        component: ComponentId,
+        used: usize,
+        requested: usize,
+        quota: usize,
+    },
+    #[error("Memory budget exceeded: {used} + {requested} > {limit} bytes")]
+    Global {
+        used: usize,
+        requested: usize,
+        limit: usize,
+    },
+}
+
+/// Component able to free memory under pressure.
+pub trait Shrink {
+    /// Try to free at least `bytes`. Returns the number of bytes actually freed.
+    fn shrink(&mut self, bytes: usize) -> usize;
+}
+
+#[derive(Debug, Default)]
+struct ComponentUsage {
+    quota: usize,
+    used: usize,
+    /// Bytes the component was asked to free.
+    shrink_request: usize,
+}
+
+#[derive(Debug)]
+struct BudgetState {
+    limit: usize,
+    components: BTreeMap<ComponentId, ComponentUsage>,
+}
+
+impl BudgetState {
+    fn used(&self) -> usize {
+        self.components.values().map(|c| c.used).sum()
+    }
+
+    /// Ask the largest consumers to free `deficit` bytes in total.
+    fn request_shrink(&mut self, mut deficit: usize) {
+        let mut consumers = self
+            .components
+            .iter_mut()
+            .filter(|(_, c)| c.used > c.shrink_request)
+            .collect::<Vec<_>>();
+        consumers.sort_by_key(|(_, c)| std::cmp::Reverse(c.used - c.shrink_request));
+        for (_, usage) in consumers {
+            if deficit == 0 {
+                break;
+            }
+            let share = deficit.min(usage.used - usage.shrink_request);
+            usage.shrink_request += share;
+            deficit -= share;
+        }
+    }
+}
+
+/// Global memory budget shared by all components of the node.
+#[derive(Clone)]
+pub struct MemoryBudget {
+    state: Arc<Mutex<BudgetState>>,
+    metrics: Option<Arc<dyn MetricsSink>>,
+}
+
+impl MemoryBudget {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(BudgetState {
+                limit,
+                components: BTreeMap::new(),
+            })),
+            metrics: None,
+        }
+    }
+
+    /// Report memory usage of components to the given sink.
+    pub fn with_metrics(mut self, metrics: Arc<dyn MetricsSink>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Register a component allowed to occupy at most `quota` bytes.
+    ///
+    /// Panics if the component is registered already.
+    pub fn register(&self, component: ComponentId, quota: usize) -> MemoryQuota {
+        let mut state = self.state.lock().unwrap();
+        assert!(
+            !state.components.contains_key(component),
+            "Component {} is registered already",
+            component
+        );
+        state.components.insert(
+            component,
+            ComponentUsage {
+                quota,
+                ..ComponentUsage::default()
+            },
+        );
+        MemoryQuota {
+            component,
+            budget: self.clone(),
+        }
+    }
+
+    /// Total bytes occupied by all components.
+    pub fn used(&self) -> usize {
+        self.state.lock().unwrap().used()
+    }
+
+    pub fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit
+    }
+
+    fn report_usage(&self, component: ComponentId, used: usize) {
+        if let Some(metrics) = &self.metrics {
+            metrics.set_gauge(
+                Metric::MemoryUsed,
+                vec![("component", component.to_string())],
+                used as i64,
+            );
+        }
+    }
+
+    fn report(&self, metric: Metric, component: ComponentId, value: u64) {
+        if let Some(metrics) = &self.metrics {
+            metrics.inc_counter(metric, vec![("component", component.to_string())], value);
+        }
+    }
+}
+
+/// Share of the [`MemoryBudget`] owned by a particular component.
+/// Memory accounted to the component is released once the quota is dropped.
+pub struct MemoryQuota {
+    component: ComponentId,
+    budget: MemoryBudget,
+}
+
+impl MemoryQuota {
+    pub fn component(&self) -> ComponentId {
+        self.component
+    }
+
+    /// Account `bytes` more to the component, unless that exceeds the quota of the component
+    /// or the global limit. In the latter case components are asked to shrink.
+    pub fn try_reserve(&self, bytes: usize) -> Result<(), BudgetExceeded> {
+        let res = {
+            let mut state = self.budget.state.lock().unwrap();
+            let total_used = state.used();
+            let limit = state.limit;
+            let usage = state.components.get_mut(self.component).unwrap();
+            if usage.used + bytes > usage.quota {
+                let err = BudgetExceeded::Quota {
+                    component: self.component,
+                    used: usage.used,
+                    requested: bytes,
+                    quota: usage.quota,
+                };
+                usage.shrink_request = usage.shrink_request.max(usage.used + bytes - usage.quota);
+                Err(err)
+            } else if total_used + bytes > limit {
+                state.request_shrink(total_used + bytes - limit);
+                Err(BudgetExceeded::Global {
+                    used: total_used,
+                    requested: bytes,
+                    limit,
+                })
+            } else {
+                usage.used += bytes;
+                Ok(usage.used)
+            }
+        };
+        match res {
+            Ok(used) => {
+                self.budget.report_usage(self.component, used);
+                Ok(())
+            }
+            Err(err) => {
+                self.budget.report(Metric::MemoryRejections, self.component, 1);
+                Err(err)
+            }
+        }
+    }
+
+    /// Return `bytes` previously accounted to the component.
+    pub fn release(&self, bytes: usize) {
+        let used = {
+            let mut state = self.budget.state.lock().unwrap();
+            let usage = state.components.get_mut(self.component).unwrap();
+            usage.used = usage.used.saturating_sub(bytes);
+            usage.shrink_request = usage.shrink_request.saturating_sub(bytes);
+            usage.used
+        };
+        self.budget.report_usage(self.component, used);
+    }
+
+    /// Report the total number of bytes the component occupies at the moment.
+    /// Unlike [`MemoryQuota::try_reserve`] this never fails, the component is asked to shrink
+    /// instead if it goes beyond its quota or the global limit.
+    pub fn update(&self, bytes: usize) {
+        {
+            let mut state = self.budget.state.lock().unwrap();
+            let usage = state.components.get_mut(self.component).unwrap();
+            usage.used = bytes;
+            usage.shrink_request = usage.shrink_request.min(bytes);
+            if bytes > usage.quota {
+                usage.shrink_request = usage.shrink_request.max(bytes - usage.quota);
+            }
+            let total_used = state.used();
+            if total_used > state.limit {
+                let deficit = total_used - state.limit;
+                let requested = state.components.values().map(|c| c.shrink_request).sum::<usize>();
+                if deficit > requested {
+                    state.request_shrink(deficit - requested);
+                }
+            }
+        }
+        self.budget.report_usage(self.component, bytes);
+    }
+
+    pub fn used(&self) -> usize {
+        self.budget.state.lock().unwrap().components[self.component].used
+    }
+
+    /// Bytes the component is asked to free.
+    pub fn shrink_request(&self) -> usize {
+        self.budget.state.lock().unwrap().components[self.component].shrink_request
+    }
+
+    /// Shrink the given target if the component is asked to free memory.
+    /// Returns the number of bytes freed.
+    pub fn apply_pressure<T: Shrink>(&self, target: &mut T) -> usize {
+        let requested = self.shrink_request();
+        if requested == 0 {
+            return 0;
+        }
+        let freed = target.shrink(requested);
+        {
+            let mut state = self.budget.state.lock().unwrap();
+            // Don't ask the component again until it is under pressure once more.
+            state.components.get_mut(self.component).unwrap().shrink_request = 0;
+        }
+        self.release(freed);
+        self.budget.report(Metric::MemoryShrinks, self.component, 1);
+        self.budget
+            .report(Metric::MemoryFreedBytes, self.component, freed as u64);
+        freed
+    }
+}
+
+impl Drop for MemoryQuota {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.budget.state.lock() {
+            state.components.remove(self.component);
+        }
+        self.budget.report_usage(self.component, 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "prometheus")]
+    use std::sync::Arc;
+
+    use crate::memory_budget::{BudgetExceeded, MemoryBudget, Shrink};
+    #[cfg(feature = "prometheus")]
+    use crate::metrics::{Metric, PrometheusMetrics};
+
+    struct Cache(Vec<usize>);
+
+    impl Shrink for Cache {
+        fn shrink(&mut self, bytes: usize) -> usize {
+            let mut freed = 0;
+            while freed < bytes {
+                match self.0.pop() {
+                    Some(entry) => freed += entry,
+                    None => break,
+                }
+            }
+            freed
+        }
+    }
+
+    #[test]
+    fn quota_is_enforced() {
+        let budget = MemoryBudget::new(1000);
+        let quota = budget.register("queue", 100);
+        assert_eq!(quota.try_reserve(60), Ok(()));
+        assert!(matches!(
+            quota.try_reserve(60),
+            Err(BudgetExceeded::Quota {
+                used: 60,
+                quota: 100,
+                ..
+            })
+        ));
+        assert_eq!(quota.shrink_request(), 20);
+        quota.release(30);
+        assert_eq!(quota.try_reserve(60), Ok(()));
+        assert_eq!(budget.used(), 90);
+        drop(quota);
+        assert_eq!(budget.used(), 0);
+    }
+
+    #[cfg(feature = "prometheus")]
+    #[test]
+    fn largest_consumer_shrinks_under_global_pressure() {
+        let metrics = PrometheusMetrics::new();
+        let budget = MemoryBudget::new(100).with_metrics(Arc::new(metrics.clone()));
+        let cache_quota = budget.register("cache", 100);
+        let queue_quota = budget.register("queue", 100);
+        let mut cache = Cache(vec![10; 8]);
+        cache_quota.update(80);
+        assert_eq!(queue_quota.try_reserve(10), Ok(()));
+        assert!(matches!(
+            queue_quota.try_reserve(30),
+            Err(BudgetExceeded::Global { used: 90, .. })
+        ));
+        assert_eq!(queue_quota.shrink_request(), 0);
+        assert_eq!(cache_quota.apply_pressure(&mut cache), 20);
+        assert_eq!(cache.0.len(), 6);
+        assert_eq!(queue_quota.try_reserve(30), Ok(()));
+        assert_eq!(
+            metrics.get(Metric::MemoryUsed, vec![("component", "cache".to_string())]),
+            Some(60)
+        );
+        assert_eq!(
+            metrics.get(Metric::MemoryFreedBytes, vec![("component", "cache".to_string())]),
+            Some(20)
+        );
+    }
+}
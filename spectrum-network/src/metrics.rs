@@ -0,0 +1,322 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+use crate::network_controller::NetworkControllerOut;
+use crate::peer_manager::data::ReputationChange;
+use crate::peer_manager::PeerManagerOut;
+use crate::types::ProtocolId;
+
+/// Labels of a particular time series of a metric.
+pub type Labels = Vec<(&'static str, String)>;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+}
+
+/// Metrics reported by the network controller, the peer manager, the memory budget
+/// and caches of protocols.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Metric {
+    InboundConnections,
+    OutboundConnections,
+    Disconnections,
+    ConnectedPeers,
+    ProtocolsEnabled,
+    ProtocolsDisabled,
+    ProtocolEnableFailures,
+    MessagesReceived,
+    BytesReceived,
+    MessagesSent,
+    BytesSent,
+    SubstreamsEvicted,
+    SubstreamsReopened,
+    PeersPunished,
+    PeersBanned,
+    NonMemberRejections,
+    ReputationChanges,
+    ConnectRequests,
+    PeerDrops,
+    RejectedConnections,
+    PeersGivenUp,
+    MemoryUsed,
+    MemoryRejections,
+    MemoryShrinks,
+    MemoryFreedBytes,
+    HistoryCacheHits,
+    HistoryCacheMisses,
+    HistoryCacheCoalesced,
+}
+
+impl Metric {
+    /// Name of the metric following Prometheus conventions.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::InboundConnections => "spectrum_network_inbound_connections_total",
+            Metric::OutboundConnections => "spectrum_network_outbound_connections_total",
+            Metric::Disconnections => "spectrum_network_disconnections_total",
+            Metric::ConnectedPeers => "spectrum_network_connected_peers",
+            Metric::ProtocolsEnabled => "spectrum_network_protocols_enabled_total",
+            Metric::ProtocolsDisabled => "spectrum_network_protocols_disabled_total",
+            Metric::ProtocolEnableFailures => "spectrum_network_protocol_enable_failures_total",
+            Metric::MessagesReceived => "spectrum_network_messages_received_total",
+            Metric::BytesReceived => "spectrum_network_bytes_received_total",
+            Metric::MessagesSent => "spectrum_network_messages_sent_total",
+            Metric::BytesSent => "spectrum_network_bytes_sent_total",
+            Metric::SubstreamsEvicted => "spectrum_network_substreams_evicted_total",
+            Metric::SubstreamsReopened => "spectrum_network_substreams_reopened_total",
+            Metric::PeersPunished => "spectrum_network_peers_punished_total",
+            Metric::PeersBanned => "spectrum_network_peers_banned_total",
+            Metric::NonMemberRejections => "spectrum_network_non_member_rejections_total",
+            Metric::ReputationChanges => "spectrum_peer_manager_reputation_changes_total",
+            Metric::ConnectRequests => "spectrum_peer_manager_connect_requests_total",
+            Metric::PeerDrops => "spectrum_peer_manager_peer_drops_total",
+            Metric::RejectedConnections => "spectrum_peer_manager_rejected_connections_total",
+            Metric::PeersGivenUp => "spectrum_peer_manager_peers_given_up_total",
+            Metric::MemoryUsed => "spectrum_memory_used_bytes",
+            Metric::MemoryRejections => "spectrum_memory_rejections_total",
+            Metric::MemoryShrinks => "spectrum_memory_shrinks_total",
+            Metric::MemoryFreedBytes => "spectrum_memory_freed_bytes_total",
+            Metric::HistoryCacheHits => "spectrum_history_cache_hits_total",
+            Metric::HistoryCacheMisses => "spectrum_history_cache_misses_total",
+            Metric::HistoryCacheCoalesced => "spectrum_history_cache_coalesced_total",
+        }
+    }
+
+    pub fn help(&self) -> &'static str {
+        match self {
+            Metric::InboundConnections => "Connections initiated by remote peers",
+            Metric::OutboundConnections => "Connections initiated by the node",
+            Metric::Disconnections => "Connections lost",
+            Metric::ConnectedPeers => "Number of currently connected peers",
+            Metric::ProtocolsEnabled => "Protocols enabled with peers",
+            Metric::ProtocolsDisabled => "Protocols disabled with peers",
+            Metric::ProtocolEnableFailures => "Protocols failed to be enabled with peers",
+            Metric::MessagesReceived => "Messages received from peers",
+            Metric::BytesReceived => "Bytes received from peers",
+            Metric::MessagesSent => "Messages sent to peers",
+            Metric::BytesSent => "Bytes sent to peers, including batch framing",
+            Metric::SubstreamsEvicted => "Outbound substreams closed due to inactivity",
+            Metric::SubstreamsReopened => "Outbound substreams re-opened after idle eviction",
+            Metric::PeersPunished => "Peers punished for misbehaviour",
+            Metric::PeersBanned => "Peers banned",
+            Metric::NonMemberRejections => {
+                "Connections refused or torn down due to missing allowlist membership"
+            }
+            Metric::ReputationChanges => "Reputation adjustments of peers",
+            Metric::ConnectRequests => "Connections requested by the peer manager",
+            Metric::PeerDrops => "Peers dropped by the peer manager",
+            Metric::RejectedConnections => "Inbound connections rejected by the peer manager",
+            Metric::PeersGivenUp => "Peers forgotten after exhausting outbound connection attempts",
+            Metric::MemoryUsed => "Bytes occupied by a component",
+            Metric::MemoryRejections => "Allocations rejected due to exceeded memory quotas",
+            Metric::MemoryShrinks => "Times a component was shrunk under memory pressure",
+            Metric::MemoryFreedBytes => "Bytes freed by components under memory pressure",
+            Metric::HistoryCacheHits => "Block sections served to peers from the cache",
+            Metric::HistoryCacheMisses => "Block sections served to peers read from the store",
+            Metric::HistoryCacheCoalesced => {
+                "Block sections served to peers by joining a read of the store already in progress"
+            }
+        }
+    }
+
+    pub fn kind(&self) -> MetricKind {
+        match self {
+            Metric::ConnectedPeers | Metric::MemoryUsed => MetricKind::Gauge,
+            _ => MetricKind::Counter,
+        }
+    }
+}
+
+/// Destination of metrics. Implement to wire metrics into a registry of choice.
+pub trait MetricsSink: Send + Sync {
+    fn inc_counter(&self, metric: Metric, labels: Labels, value: u64);
+    fn set_gauge(&self, metric: Metric, labels: Labels, value: i64);
+}
+
+/// Simple registry rendering metrics in Prometheus text exposition format.
+#[cfg(feature = "prometheus")]
+#[derive(Clone, Default)]
+pub struct PrometheusMetrics {
+    values: Arc<Mutex<BTreeMap<(Metric, Labels), i64>>>,
+}
+
+#[cfg(feature = "prometheus")]
+impl PrometheusMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, metric: Metric, labels: Labels) -> Option<i64> {
+        self.values.lock().unwrap().get(&(metric, labels)).copied()
+    }
+
+    /// Render all metrics in Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let values = self.values.lock().unwrap();
+        let mut out = String::new();
+        let mut last_metric = None;
+        for ((metric, labels), value) in values.iter() {
+            if last_metric != Some(*metric) {
+                let kind = match metric.kind() {
+                    MetricKind::Counter => "counter",
+                    MetricKind::Gauge => "gauge",
+                };
+                let _ = writeln!(out, "# HELP {} {}", metric.name(), metric.help());
+                let _ = writeln!(out, "# TYPE {} {}", metric.name(), kind);
+                last_metric = Some(*metric);
+            }
+            if labels.is_empty() {
+                let _ = writeln!(out, "{} {}", metric.name(), value);
+            } else {
+                let labels = labels
+                    .iter()
+                    .map(|(k, v)| format!("{}=\"{}\"", k, v))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                let _ = writeln!(out, "{}{{{}}} {}", metric.name(), labels, value);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(feature = "prometheus")]
+impl MetricsSink for PrometheusMetrics {
+    fn inc_counter(&self, metric: Metric, labels: Labels, value: u64) {
+        let mut values = self.values.lock().unwrap();
+        let counter = values.entry((metric, labels)).or_insert(0);
+        *counter = counter.saturating_add(value as i64);
+    }
+
+    fn set_gauge(&self, metric: Metric, labels: Labels, value: i64) {
+        self.values.lock().unwrap().insert((metric, labels), value);
+    }
+}
+
+fn protocol_label(protocol_id: ProtocolId) -> Labels {
+    vec![("protocol", u8::from(protocol_id).to_string())]
+}
+
+pub fn record_network_event(sink: &dyn MetricsSink, event: &NetworkControllerOut) {
+    match event {
+        NetworkControllerOut::ConnectedWithInboundPeer(_) => {
+            sink.inc_counter(Metric::InboundConnections, vec![], 1)
+        }
+        NetworkControllerOut::ConnectedWithOutboundPeer(_) => {
+            sink.inc_counter(Metric::OutboundConnections, vec![], 1)
+        }
+        NetworkControllerOut::Disconnected { .. } => sink.inc_counter(Metric::Disconnections, vec![], 1),
+        NetworkControllerOut::ProtocolEnabled { protocol_id, .. } => {
+            sink.inc_counter(Metric::ProtocolsEnabled, protocol_label(*protocol_id), 1)
+        }
+        NetworkControllerOut::ProtocolDisabled { protocol_id, .. } => {
+            sink.inc_counter(Metric::ProtocolsDisabled, protocol_label(*protocol_id), 1)
+        }
+        NetworkControllerOut::ProtocolEnableFailed { protocol_id, .. } => {
+            sink.inc_counter(Metric::ProtocolEnableFailures, protocol_label(*protocol_id), 1)
+        }
+        NetworkControllerOut::PeerPunished { reason, .. } => {
+            sink.inc_counter(Metric::PeersPunished, reason_label(*reason), 1)
+        }
+        NetworkControllerOut::PeerBanned { duration, .. } => {
+            let kind = if duration.is_some() {
+                "temporary"
+            } else {
+                "permanent"
+            };
+            sink.inc_counter(Metric::PeersBanned, vec![("kind", kind.to_string())], 1)
+        }
+        NetworkControllerOut::PeerNotAllowlisted { .. } => {
+            sink.inc_counter(Metric::NonMemberRejections, vec![], 1)
+        }
+        NetworkControllerOut::ProtocolPendingApprove { .. }
+        | NetworkControllerOut::ProtocolPendingEnable { .. }
+        | NetworkControllerOut::OneShotBroadcastDone { .. } => {}
+    }
+}
+
+pub fn record_message(sink: &dyn MetricsSink, protocol_id: ProtocolId, size: usize) {
+    sink.inc_counter(Metric::MessagesReceived, protocol_label(protocol_id), 1);
+    sink.inc_counter(Metric::BytesReceived, protocol_label(protocol_id), size as u64);
+}
+
+pub fn record_sent_message(sink: &dyn MetricsSink, protocol_id: ProtocolId) {
+    sink.inc_counter(Metric::MessagesSent, protocol_label(protocol_id), 1);
+}
+
+/// Record a frame written to a substream. Batching makes a single frame carry many messages.
+pub fn record_sent_frame(sink: &dyn MetricsSink, protocol_id: ProtocolId, size: usize) {
+    sink.inc_counter(Metric::BytesSent, protocol_label(protocol_id), size as u64);
+}
+
+pub fn record_substream_evicted(sink: &dyn MetricsSink, protocol_id: ProtocolId) {
+    sink.inc_counter(Metric::SubstreamsEvicted, protocol_label(protocol_id), 1);
+}
+
+pub fn record_substream_reopened(sink: &dyn MetricsSink, protocol_id: ProtocolId) {
+    sink.inc_counter(Metric::SubstreamsReopened, protocol_label(protocol_id), 1);
+}
+
+pub fn record_peer_manager_event(sink: &dyn MetricsSink, event: &PeerManagerOut) {
+    match event {
+        PeerManagerOut::Connect(_) => sink.inc_counter(Metric::ConnectRequests, vec![], 1),
+        PeerManagerOut::Drop(_) => sink.inc_counter(Metric::PeerDrops, vec![], 1),
+        PeerManagerOut::Reject(..) => sink.inc_counter(Metric::RejectedConnections, vec![], 1),
+        PeerManagerOut::GaveUp(_) => sink.inc_counter(Metric::PeersGivenUp, vec![], 1),
+        PeerManagerOut::AcceptIncomingConnection(..)
+        | PeerManagerOut::StartProtocol(..)
+        | PeerManagerOut::NotifyPeerPunished { .. } => {}
+    }
+}
+
+pub fn record_reputation_change(sink: &dyn MetricsSink, change: ReputationChange) {
+    sink.inc_counter(Metric::ReputationChanges, reason_label(change), 1);
+}
+
+fn reason_label(change: ReputationChange) -> Labels {
+    vec![("reason", format!("{:?}", change))]
+}
+
+#[cfg(all(test, feature = "prometheus"))]
+mod tests {
+    use libp2p::PeerId;
+
+    use crate::metrics::{record_network_event, Metric, MetricsSink, PrometheusMetrics};
+    use crate::network_controller::NetworkControllerOut;
+    use crate::peer_manager::data::ReputationChange;
+    use crate::types::{ProtocolId, ProtocolVer};
+
+    #[test]
+    fn metrics_are_rendered_in_prometheus_format() {
+        let metrics = PrometheusMetrics::new();
+        let peer_id = PeerId::random();
+        record_network_event(&metrics, &NetworkControllerOut::ConnectedWithInboundPeer(peer_id));
+        record_network_event(&metrics, &NetworkControllerOut::ConnectedWithInboundPeer(peer_id));
+        record_network_event(
+            &metrics,
+            &NetworkControllerOut::ProtocolEnabled {
+                peer_id,
+                protocol_id: ProtocolId::from_u8(1),
+                protocol_ver: ProtocolVer::default(),
+            },
+        );
+        record_network_event(
+            &metrics,
+            &NetworkControllerOut::PeerPunished {
+                peer_id,
+                reason: ReputationChange::NoResponse,
+            },
+        );
+        metrics.set_gauge(Metric::ConnectedPeers, vec![], 1);
+        assert_eq!(metrics.get(Metric::InboundConnections, vec![]), Some(2));
+        let rendered = metrics.render();
+        assert!(rendered.contains("# TYPE spectrum_network_inbound_connections_total counter"));
+        assert!(rendered.contains("spectrum_network_inbound_connections_total 2"));
+        assert!(rendered.contains("spectrum_network_protocols_enabled_total{protocol=\"1\"} 1"));
+        assert!(rendered.contains("spectrum_network_peers_punished_total{reason=\"NoResponse\"} 1"));
+        assert!(rendered.contains("# TYPE spectrum_network_connected_peers gauge"));
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use futures::channel::mpsc;
+use futures::stream::{BoxStream, SelectAll};
+use futures::StreamExt;
+use libp2p::{Multiaddr, PeerId};
+
+use crate::allowlist::Allowlist;
+use crate::cancellation::CancellationToken;
+use crate::inbound_policy::InboundPolicyChain;
+use crate::journal::JournalSink;
+use crate::memory_budget::MemoryQuota;
+use crate::metrics::MetricsSink;
+use crate::network_controller::{
+    DedicatedChannelConf, EnableRetryPolicy, MultiConnConf, NetworkController, NetworkControllerIn,
+    NetworkMailbox,
+};
+use crate::peer_conn_handler::PeerConnHandlerConf;
+use crate::peer_manager::peers_state::PeersState;
+use crate::peer_manager::{PeerManager, PeerManagerConfig, PeersMailbox};
+use crate::protocol::ProtocolConfig;
+use crate::protocol_api::ProtocolMailbox;
+use crate::protocol_handler::{ProtocolBehaviour, ProtocolHandler};
+use crate::types::ProtocolId;
+
+const DEFAULT_NC_MSG_BUFFER_SIZE: usize = 10;
+const DEFAULT_PH_MSG_BUFFER_SIZE: usize = 10;
+
+/// Running protocol handler, must be polled to make progress.
+pub type ProtocolHandlerTask = BoxStream<'static, ()>;
+
+type ProtocolInit = Box<
+    dyn FnOnce(
+        PeersMailbox,
+        NetworkMailbox,
+        usize,
+        CancellationToken,
+    ) -> (ProtocolMailbox, ProtocolHandlerTask),
+>;
+
+/// Assembled networking stack.
+pub struct Network<TState> {
+    /// Behaviour to run the swarm with.
+    pub controller: NetworkController<PeersMailbox, PeerManager<TState>, ProtocolMailbox>,
+    /// API to the peer manager.
+    pub peers: PeersMailbox,
+    /// API to the network controller.
+    pub network_api: NetworkMailbox,
+    /// Handlers of all registered protocols, must be polled along with the swarm.
+    pub protocol_handlers: SelectAll<ProtocolHandlerTask>,
+}
+
+/// Fluent API to assemble [`NetworkController`], [`PeerManager`] and protocol handlers
+/// with sane defaults.
+pub struct NetworkBuilder<TState> {
+    local_peer_id: PeerId,
+    peers_state: TState,
+    peer_manager_conf: PeerManagerConfig,
+    conn_handler_conf: PeerConnHandlerConf,
+    enable_retry_policy: EnableRetryPolicy,
+    nc_msg_buffer_size: usize,
+    ph_msg_buffer_size: usize,
+    routing_table: bool,
+    routing_hints: HashMap<PeerId, Multiaddr>,
+    dedicated_channels: Option<DedicatedChannelConf>,
+    max_conns_per_peer: Option<usize>,
+    journal: Option<JournalSink>,
+    metrics: Option<Arc<dyn MetricsSink>>,
+    memory_quota: Option<MemoryQuota>,
+    inbound_policies: Option<InboundPolicyChain>,
+    allowlist: Option<Allowlist>,
+    cancellation: CancellationToken,
+    protocols: Vec<(ProtocolId, ProtocolConfig, ProtocolInit)>,
+}
+
+impl<TState: PeersState> NetworkBuilder<TState> {
+    pub fn new(local_peer_id: PeerId, peers_state: TState) -> Self {
+        Self {
+            local_peer_id,
+            peers_state,
+            peer_manager_conf: PeerManagerConfig::default(),
+            conn_handler_conf: PeerConnHandlerConf::default(),
+            enable_retry_policy: EnableRetryPolicy::default(),
+            nc_msg_buffer_size: DEFAULT_NC_MSG_BUFFER_SIZE,
+            ph_msg_buffer_size: DEFAULT_PH_MSG_BUFFER_SIZE,
+            routing_table: false,
+            routing_hints: HashMap::new(),
+            dedicated_channels: None,
+            max_conns_per_peer: None,
+            journal: None,
+            metrics: None,
+            memory_quota: None,
+            inbound_policies: None,
+            allowlist: None,
+            cancellation: CancellationToken::new(),
+            protocols: Vec::new(),
+        }
+    }
+
+    /// Register a protocol. Its behaviour is created from the peer manager API
+    /// once the stack is built.
+    pub fn with_protocol<TBehaviour, F>(
+        mut self,
+        protocol_id: ProtocolId,
+        conf: ProtocolConfig,
+        behaviour: F,
+    ) -> Self
+    where
+        F: FnOnce(PeersMailbox) -> TBehaviour + 'static,
+        TBehaviour: ProtocolBehaviour + Unpin + Send + 'static,
+    {
+        let init: ProtocolInit = Box::new(move |peers, network_api, msg_buffer_size, cancellation| {
+            let (handler, mailbox) =
+                ProtocolHandler::new(behaviour(peers), network_api, protocol_id, msg_buffer_size);
+            (
+                mailbox,
+                handler.with_cancellation(cancellation).map(|_| ()).boxed(),
+            )
+        });
+        self.protocols.push((protocol_id, conf, init));
+        self
+    }
+
+    pub fn with_peer_manager_conf(mut self, conf: PeerManagerConfig) -> Self {
+        self.peer_manager_conf = conf;
+        self
+    }
+
+    pub fn with_conn_handler_conf(mut self, conf: PeerConnHandlerConf) -> Self {
+        self.conn_handler_conf = conf;
+        self
+    }
+
+    pub fn with_enable_retry_policy(mut self, policy: EnableRetryPolicy) -> Self {
+        self.enable_retry_policy = policy;
+        self
+    }
+
+    /// Capacity of the network controller and protocol handlers inboxes.
+    pub fn with_msg_buffer_sizes(mut self, nc_msg_buffer_size: usize, ph_msg_buffer_size: usize) -> Self {
+        self.nc_msg_buffer_size = nc_msg_buffer_size;
+        self.ph_msg_buffer_size = ph_msg_buffer_size;
+        self
+    }
+
+    /// Maintain Kademlia routing table in the peer manager.
+    pub fn with_routing_table(mut self) -> Self {
+        self.routing_table = true;
+        self
+    }
+
+    /// See [`NetworkController::with_routing_hints`].
+    pub fn with_routing_hints(mut self, routing_hints: HashMap<PeerId, Multiaddr>) -> Self {
+        self.routing_hints = routing_hints;
+        self
+    }
+
+    /// See [`NetworkController::with_dedicated_channels`].
+    pub fn with_dedicated_channels(mut self, conf: DedicatedChannelConf) -> Self {
+        self.dedicated_channels = Some(conf);
+        self
+    }
+
+    /// See [`NetworkController::with_multi_connections`].
+    pub fn with_max_connections_per_peer(mut self, max_conns_per_peer: usize) -> Self {
+        self.max_conns_per_peer = Some(max_conns_per_peer);
+        self
+    }
+
+    /// See [`NetworkController::with_event_journal`].
+    pub fn with_event_journal(mut self, journal: JournalSink) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Report metrics of the network controller and the peer manager to the given sink.
+    pub fn with_metrics(mut self, metrics: Arc<dyn MetricsSink>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// See [`NetworkController::with_memory_quota`].
+    pub fn with_memory_quota(mut self, quota: MemoryQuota) -> Self {
+        self.memory_quota = Some(quota);
+        self
+    }
+
+    /// See [`NetworkController::with_inbound_policies`].
+    pub fn with_inbound_policies(mut self, policies: InboundPolicyChain) -> Self {
+        self.inbound_policies = Some(policies);
+        self
+    }
+
+    /// See [`NetworkController::with_allowlist`].
+    pub fn with_allowlist(mut self, allowlist: Allowlist) -> Self {
+        self.allowlist = Some(allowlist);
+        self
+    }
+
+    /// Terminate protocol handlers once the given token is cancelled,
+    /// see [`ProtocolHandler::with_cancellation`].
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    pub fn build(self) -> Network<TState> {
+        let (peer_manager, peers) = PeerManager::new(self.peers_state, self.peer_manager_conf);
+        let peer_manager =
+            peer_manager.with_protocol_configs(self.protocols.iter().map(|(prot, conf, _)| (prot, conf)));
+        let peer_manager = if self.routing_table {
+            peer_manager.with_routing_table(self.local_peer_id)
+        } else {
+            peer_manager
+        };
+        let peer_manager = match &self.metrics {
+            Some(metrics) => peer_manager.with_metrics(metrics.clone()),
+            None => peer_manager,
+        };
+        let (requests_snd, requests_recv) = mpsc::channel::<NetworkControllerIn>(self.nc_msg_buffer_size);
+        let network_api = NetworkMailbox {
+            mailbox_snd: requests_snd,
+        };
+        let mut supported_protocols = HashMap::new();
+        let mut protocol_handlers = SelectAll::new();
+        for (protocol_id, conf, init) in self.protocols {
+            let (mailbox, handler) = init(
+                peers.clone(),
+                network_api.clone(),
+                self.ph_msg_buffer_size,
+                self.cancellation.child_token(),
+            );
+            supported_protocols.insert(protocol_id, (conf, mailbox));
+            protocol_handlers.push(handler);
+        }
+        let mut controller = NetworkController::new(
+            self.conn_handler_conf,
+            supported_protocols,
+            peers.clone(),
+            peer_manager,
+            requests_recv,
+            self.enable_retry_policy,
+        )
+        .with_local_peer_id(self.local_peer_id)
+        .with_routing_hints(self.routing_hints);
+        if let Some(conf) = self.dedicated_channels {
+            controller = controller.with_dedicated_channels(conf);
+        }
+        if let Some(max_conns_per_peer) = self.max_conns_per_peer {
+            controller = controller.with_multi_connections(MultiConnConf { max_conns_per_peer });
+        }
+        if let Some(journal) = self.journal {
+            controller = controller.with_event_journal(journal);
+        }
+        if let Some(metrics) = self.metrics {
+            controller = controller.with_metrics(metrics);
+        }
+        if let Some(quota) = self.memory_quota {
+            controller = controller.with_memory_quota(quota);
+        }
+        if let Some(policies) = self.inbound_policies {
+            controller = controller.with_inbound_policies(policies);
+        }
+        if let Some(allowlist) = self.allowlist {
+            controller = controller.with_allowlist(allowlist);
+        }
+        Network {
+            controller,
+            peers,
+            network_api,
+            protocol_handlers,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libp2p::PeerId;
+
+    use crate::network_builder::NetworkBuilder;
+    use crate::peer_manager::peers_state::PeerRepo;
+    use crate::peer_manager::NetworkingConfig;
+    use crate::protocol::{
+        ProtocolConfig, ProtocolPriority, StatefulProtocolConfig, StatefulProtocolSpec,
+        DIFFUSION_PROTOCOL_ID, DISCOVERY_PROTOCOL_ID,
+    };
+    use crate::protocol_handler::discovery::message::DiscoverySpec;
+    use crate::protocol_handler::discovery::{DiscoveryBehaviour, NodeStatus};
+
+    fn discovery_conf(priority: ProtocolPriority) -> ProtocolConfig {
+        ProtocolConfig::Stateful(StatefulProtocolConfig {
+            supported_versions: vec![(
+                DiscoverySpec::v1(),
+                StatefulProtocolSpec {
+                    max_message_size: 100,
+                    approve_required: true,
+                    batching: None,
+                    compression: None,
+                },
+            )],
+            preferred_versions: vec![],
+            priority,
+        })
+    }
+
+    #[test]
+    fn protocol_handlers_are_built() {
+        let status = NodeStatus {
+            supported_protocols: vec![DIFFUSION_PROTOCOL_ID],
+            height: 0,
+        };
+        let network = NetworkBuilder::new(
+            PeerId::random(),
+            PeerRepo::new(NetworkingConfig::default(), vec![]),
+        )
+        .with_protocol(
+            DISCOVERY_PROTOCOL_ID,
+            discovery_conf(ProtocolPriority::HIGH),
+            move |peers| DiscoveryBehaviour::new(peers, status),
+        )
+        .build();
+        assert_eq!(network.protocol_handlers.len(), 1);
+    }
+}
@@ -1,12 +1,16 @@
+use std::cmp::Reverse;
 use std::collections::hash_map::Entry;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 use std::time::{Duration, Instant};
 
 use either::{Either, Left, Right};
 use futures::channel::mpsc::{Receiver, Sender};
-use futures::{SinkExt, Stream};
+use futures::future::BoxFuture;
+use futures::stream::FuturesUnordered;
+use futures::{FutureExt, SinkExt, Stream};
 use libp2p::core::Endpoint;
 use libp2p::swarm::behaviour::ConnectionEstablished;
 use libp2p::swarm::dial_opts::{DialOpts, PeerCondition};
@@ -16,18 +20,28 @@ use libp2p::swarm::{
 };
 use libp2p::{Multiaddr, PeerId};
 use log::{info, trace, warn};
+use rand::rngs::OsRng;
+use rand::RngCore;
 
+use crate::allowlist::Allowlist;
+use crate::inbound_policy::{InboundEvent, InboundPolicy, InboundPolicyChain, Rejection};
+use crate::journal::JournalSink;
+use crate::memory_budget::MemoryQuota;
+use crate::metrics::{self, Metric, MetricsSink};
 use crate::one_shot_upgrade::OneShotMessage;
+use crate::peer_conn_handler::batching::OutboundBatch;
 use crate::peer_conn_handler::message_sink::MessageSink;
 use crate::peer_conn_handler::{
     ConnHandlerError, ConnHandlerIn, ConnHandlerOut, OneShotProtocol, OneShotRequest, OneShotRequestId,
     PeerConnHandler, PeerConnHandlerConf, ProtocolState, StatefulProtocol, ThrottleStage,
 };
-use crate::peer_manager::data::{ConnectionLossReason, ReputationChange};
+use crate::peer_manager::data::{ConnectionLossReason, PeerDestination, ReputationChange};
 use crate::peer_manager::{PeerEvents, PeerManagerOut, Peers};
-use crate::protocol::{OneShotProtocolConfig, OneShotProtocolSpec, ProtocolConfig, StatefulProtocolConfig};
+use crate::protocol::{
+    OneShotProtocolConfig, OneShotProtocolSpec, ProtocolConfig, ProtocolPriority, StatefulProtocolConfig,
+};
 use crate::protocol_api::ProtocolEvents;
-use crate::protocol_upgrade::handshake::PolyVerHandshakeSpec;
+use crate::protocol_upgrade::handshake::{split_advertised, Capabilities, PolyVerHandshakeSpec};
 use crate::types::{ProtocolId, ProtocolTag, ProtocolVer, RawMessage};
 
 /// States of an enabled protocol.
@@ -53,13 +67,15 @@ pub enum ConnectedPeer<THandler> {
         /// Note that we can have multiple connections with each peer.
         conn_ids: Vec<ConnectionId>,
         enabled_protocols: HashMap<ProtocolId, (EnabledProtocol, THandler)>,
+        /// Connection reserved for consensus-critical protocols, if any.
+        dedicated: Option<DedicatedConn>,
     },
     /// The peer is connected but not approved by PM yet.
     PendingApprove(ConnectionId),
     /// PM or Protocol requested that we should connect to this peer.
     PendingConnect {
         /// One-shot messages that the handler should try to deliver once connected.
-        tasks: Vec<OneShotMessage>,
+        tasks: Vec<(OneShotRequestId, OneShotMessage)>,
         /// Should the handler terminate as soon as possible when no work left.
         terminate_asap: bool,
     },
@@ -67,6 +83,32 @@ pub enum ConnectedPeer<THandler> {
     PendingDisconnect(ConnectionId),
 }
 
+/// Connection with a peer dedicated to consensus-critical protocols,
+/// so that their messages are not delayed by bulk traffic.
+#[derive(Debug)]
+pub struct DedicatedConn {
+    pub conn_id: ConnectionId,
+    /// Protocols running on this connection.
+    pub protocols: HashSet<ProtocolId>,
+}
+
+/// Policy of dedicating separate connections to consensus-critical protocols.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DedicatedChannelConf {
+    /// Protocols of at least this priority are run on dedicated connections.
+    pub min_priority: ProtocolPriority,
+    /// Max number of peers to maintain dedicated connections with.
+    /// Beyond that, critical protocols share the connection with the rest.
+    pub max_channels: usize,
+}
+
+/// Policy of maintaining multiple connections with the same peer, e.g. for redundancy.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MultiConnConf {
+    /// Max number of shared connections to maintain with a peer.
+    pub max_conns_per_peer: usize,
+}
+
 /// Outbound network events.
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum NetworkControllerOut {
@@ -99,6 +141,94 @@ pub enum NetworkControllerOut {
         peer_id: PeerId,
         reason: ReputationChange,
     },
+    /// Peer is banned for `duration`, or permanently if `None`.
+    PeerBanned {
+        peer_id: PeerId,
+        duration: Option<Duration>,
+    },
+    /// Peer is not a member of the permissioned network.
+    /// Connection with it was refused or torn down.
+    PeerNotAllowlisted { peer_id: PeerId },
+    /// All attempts to enable the protocol with the peer failed.
+    ProtocolEnableFailed {
+        peer_id: PeerId,
+        protocol_id: ProtocolId,
+    },
+    /// Delivery of a one-shot broadcast is finished.
+    OneShotBroadcastDone {
+        id: OneShotBroadcastId,
+        /// Peers the message was delivered to.
+        delivered: Vec<PeerId>,
+        /// Peers the message could not be delivered to even after a retry.
+        failed: Vec<PeerId>,
+    },
+}
+
+/// Identifier of a one-shot broadcast.
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Copy, Clone)]
+pub struct OneShotBroadcastId(u64);
+
+impl OneShotBroadcastId {
+    pub fn random() -> Self {
+        Self(OsRng.next_u64())
+    }
+}
+
+/// Max number of attempts to deliver a one-shot broadcast to a single peer.
+const MAX_BROADCAST_ATTEMPTS: u32 = 2;
+
+/// Delivery state of a one-shot broadcast.
+struct OneShotBroadcast {
+    message: OneShotMessage,
+    /// Peers the message is being delivered to along with the number of attempts made so far.
+    pending: HashMap<PeerId, (Option<Multiaddr>, u32)>,
+    delivered: Vec<PeerId>,
+    failed: Vec<PeerId>,
+}
+
+/// Policy of retrying protocol enablement refused by peer (or timed out).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct EnableRetryPolicy {
+    /// Delay before the first retry.
+    pub initial_backoff: Duration,
+    /// Upper bound of the delay between two consecutive attempts.
+    pub max_backoff: Duration,
+    /// Maximum number of retries before the failure is deemed permanent.
+    pub max_retries: u32,
+}
+
+impl EnableRetryPolicy {
+    /// Backoff before the given retry (starting from 1).
+    /// `None` if the number of retries is exhausted.
+    pub fn backoff(&self, retry: u32) -> Option<Duration> {
+        if retry == 0 || retry > self.max_retries {
+            return None;
+        }
+        let factor = 1u32.checked_shl(retry - 1).unwrap_or(u32::MAX);
+        Some(
+            self.initial_backoff
+                .checked_mul(factor)
+                .map_or(self.max_backoff, |d| d.min(self.max_backoff)),
+        )
+    }
+}
+
+impl Default for EnableRetryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            max_retries: 5,
+        }
+    }
+}
+
+/// Outbound protocol enablement that may be retried.
+struct EnableAttempt {
+    /// Number of retries made so far.
+    retries: u32,
+    /// Handshake to send to the peer upon each attempt.
+    handshake: PolyVerHandshakeSpec,
 }
 
 pub enum NetworkControllerIn {
@@ -124,8 +254,26 @@ pub enum NetworkControllerIn {
         protocol: ProtocolTag,
         message: RawMessage,
     },
-    /// Ban peer permanently.
-    BanPeer(PeerId),
+    /// Send the given message to each of the specified peers without
+    /// establishing persistent two-way communication channels.
+    /// Failed deliveries are retried once, the outcome is reported
+    /// by [`NetworkControllerOut::OneShotBroadcastDone`].
+    SendOneShotBroadcast {
+        id: OneShotBroadcastId,
+        peers: Vec<PeerDestination>,
+        protocol: ProtocolTag,
+        message: RawMessage,
+    },
+    /// Ban peer for `duration`, or permanently if `None`.
+    BanPeer {
+        peer_id: PeerId,
+        duration: Option<Duration>,
+    },
+    /// Adjust reputation of the peer.
+    ReportPeer {
+        peer_id: PeerId,
+        change: ReputationChange,
+    },
 }
 
 /// External API to network controller.
@@ -134,6 +282,7 @@ pub trait NetworkAPI {
     fn enable_protocol(&self, protocol: ProtocolId, peer: PeerId, handshake: PolyVerHandshakeSpec);
 
     /// Updates the set of protocols supported by the specified peer.
+    /// Overrides protocols the peer advertised in handshakes.
     fn update_peer_protocols(&self, peer: PeerId, protocols: Vec<ProtocolId>);
     /// Send the given message to the specified peer without
     /// establishing a persistent two-way communication channel.
@@ -144,8 +293,21 @@ pub trait NetworkAPI {
         protocol: ProtocolTag,
         message: RawMessage,
     );
+    /// Send the given message to each of the specified peers without
+    /// establishing persistent two-way communication channels.
+    /// Returns the ID the delivery report will be tagged with.
+    fn broadcast_one_shot(
+        &self,
+        peers: Vec<PeerDestination>,
+        protocol: ProtocolTag,
+        message: RawMessage,
+    ) -> OneShotBroadcastId;
     /// Ban peer permanently.
     fn ban_peer(&self, peer: PeerId);
+    /// Ban peer for the given duration.
+    fn ban_peer_for(&self, peer: PeerId, duration: Duration);
+    /// Adjust reputation of the peer.
+    fn report_peer(&self, peer: PeerId, change: ReputationChange);
 }
 
 #[derive(Clone)]
@@ -188,9 +350,42 @@ impl NetworkAPI for NetworkMailbox {
                 })
         });
     }
+    fn broadcast_one_shot(
+        &self,
+        peers: Vec<PeerDestination>,
+        protocol: ProtocolTag,
+        message: RawMessage,
+    ) -> OneShotBroadcastId {
+        let id = OneShotBroadcastId::random();
+        let _ = futures::executor::block_on({
+            self.mailbox_snd
+                .clone()
+                .send(NetworkControllerIn::SendOneShotBroadcast {
+                    id,
+                    peers,
+                    protocol,
+                    message,
+                })
+        });
+        id
+    }
     fn ban_peer(&self, peer: PeerId) {
-        let _ =
-            futures::executor::block_on(self.mailbox_snd.clone().send(NetworkControllerIn::BanPeer(peer)));
+        let _ = futures::executor::block_on(self.mailbox_snd.clone().send(NetworkControllerIn::BanPeer {
+            peer_id: peer,
+            duration: None,
+        }));
+    }
+    fn ban_peer_for(&self, peer: PeerId, duration: Duration) {
+        let _ = futures::executor::block_on(self.mailbox_snd.clone().send(NetworkControllerIn::BanPeer {
+            peer_id: peer,
+            duration: Some(duration),
+        }));
+    }
+    fn report_peer(&self, peer: PeerId, change: ReputationChange) {
+        let _ = futures::executor::block_on(self.mailbox_snd.clone().send(NetworkControllerIn::ReportPeer {
+            peer_id: peer,
+            change,
+        }));
     }
 }
 
@@ -202,10 +397,20 @@ pub trait NetworkEvents {
     fn outbound_peer_connected(&mut self, peer_id: PeerId);
     fn peer_disconnected(&mut self, peer_id: PeerId, reason: ConnectionLossReason);
     fn peer_punished(&mut self, peer_id: PeerId, reason: ReputationChange);
+    fn peer_banned(&mut self, peer_id: PeerId, duration: Option<Duration>);
+    /// Connection with the peer was refused or torn down since it isn't in the allowlist.
+    fn peer_not_allowlisted(&mut self, peer_id: PeerId);
     fn protocol_pending_approve(&mut self, peer_id: PeerId, protocol_id: ProtocolId);
     fn protocol_pending_enable(&mut self, peer_id: PeerId, protocol_id: ProtocolId);
     fn protocol_enabled(&mut self, peer_id: PeerId, protocol_id: ProtocolId, protocol_ver: ProtocolVer);
     fn protocol_disabled(&mut self, peer_id: PeerId, protocol_id: ProtocolId);
+    fn protocol_enable_failed(&mut self, peer_id: PeerId, protocol_id: ProtocolId);
+    fn one_shot_broadcast_done(
+        &mut self,
+        id: OneShotBroadcastId,
+        delivered: Vec<PeerId>,
+        failed: Vec<PeerId>,
+    );
 }
 
 impl<TPeers, TPeerManager, THandler> NetworkEvents for NetworkController<TPeers, TPeerManager, THandler> {
@@ -237,6 +442,21 @@ impl<TPeers, TPeerManager, THandler> NetworkEvents for NetworkController<TPeers,
             }));
     }
 
+    fn peer_banned(&mut self, peer_id: PeerId, duration: Option<Duration>) {
+        self.pending_actions
+            .push_back(ToSwarm::GenerateEvent(NetworkControllerOut::PeerBanned {
+                peer_id,
+                duration,
+            }));
+    }
+
+    fn peer_not_allowlisted(&mut self, peer_id: PeerId) {
+        self.pending_actions
+            .push_back(ToSwarm::GenerateEvent(NetworkControllerOut::PeerNotAllowlisted {
+                peer_id,
+            }));
+    }
+
     fn protocol_enabled(&mut self, peer_id: PeerId, protocol_id: ProtocolId, protocol_ver: ProtocolVer) {
         self.pending_actions
             .push_back(ToSwarm::GenerateEvent(NetworkControllerOut::ProtocolEnabled {
@@ -265,12 +485,37 @@ impl<TPeers, TPeerManager, THandler> NetworkEvents for NetworkController<TPeers,
                 protocol_id,
             }));
     }
+
+    fn protocol_enable_failed(&mut self, peer_id: PeerId, protocol_id: ProtocolId) {
+        self.pending_actions.push_back(ToSwarm::GenerateEvent(
+            NetworkControllerOut::ProtocolEnableFailed { peer_id, protocol_id },
+        ));
+    }
+
+    fn one_shot_broadcast_done(
+        &mut self,
+        id: OneShotBroadcastId,
+        delivered: Vec<PeerId>,
+        failed: Vec<PeerId>,
+    ) {
+        self.pending_actions.push_back(ToSwarm::GenerateEvent(
+            NetworkControllerOut::OneShotBroadcastDone {
+                id,
+                delivered,
+                failed,
+            },
+        ));
+    }
 }
 
 pub struct NetworkController<TPeers, TPeerManager, THandler> {
     conn_handler_conf: PeerConnHandlerConf,
     /// All supported protocols and their handlers
     supported_protocols: HashMap<ProtocolId, (ProtocolConfig, THandler)>,
+    /// Supported protocols ordered by descending priority.
+    protocols_by_priority: Vec<ProtocolId>,
+    /// Supported protocols and their versions, advertised to peers in handshakes.
+    capabilities: Capabilities,
     /// PeerManager API
     peers: TPeers,
     /// PeerManager stream itself
@@ -280,6 +525,78 @@ pub struct NetworkController<TPeers, TPeerManager, THandler> {
     pending_one_shot_requests: HashMap<PeerId, OneShotMessage>,
     requests_recv: Receiver<NetworkControllerIn>,
     pending_actions: VecDeque<ToSwarm<NetworkControllerOut, ConnHandlerIn>>,
+    /// Protocol enablements requested by handlers, processed in the order of protocol priority.
+    pending_enable_requests: Vec<(PeerId, ProtocolId, PolyVerHandshakeSpec)>,
+    enable_retry_policy: EnableRetryPolicy,
+    /// Outbound protocol enablements which haven't been confirmed yet.
+    enable_attempts: HashMap<(PeerId, ProtocolId), EnableAttempt>,
+    /// Scheduled retries of protocol enablement.
+    pending_enable_retries: FuturesUnordered<BoxFuture<'static, (PeerId, ProtocolId)>>,
+    /// Optional journal of network events.
+    journal: Option<JournalSink>,
+    /// Optional sink of metrics.
+    metrics: Option<Arc<dyn MetricsSink>>,
+    /// Quota for one-shot messages parked until the recipient is connected.
+    memory_quota: Option<MemoryQuota>,
+    /// Addresses to dial particular peers at, overriding the ones suggested by PM.
+    routing_hints: HashMap<PeerId, Multiaddr>,
+    /// One-shot broadcasts in progress.
+    one_shot_broadcasts: HashMap<OneShotBroadcastId, OneShotBroadcast>,
+    /// Broadcasts and recipients of one-shot messages awaiting delivery.
+    one_shot_deliveries: HashMap<OneShotRequestId, (OneShotBroadcastId, PeerId)>,
+    /// Policy of dedicated connections. `None` if all protocols share the same connection.
+    dedicated_channels: Option<DedicatedChannelConf>,
+    /// Peers dedicated connections are being dialed with.
+    pending_dedicated: HashSet<PeerId>,
+    /// Protocol enablements waiting for dedicated connections to be established.
+    awaiting_dedicated: Vec<(PeerId, ProtocolId, PolyVerHandshakeSpec)>,
+    /// Peers failed to establish dedicated connections with.
+    /// Critical protocols use the shared connection with them.
+    dedicated_fallbacks: HashSet<PeerId>,
+    /// Id of the local peer, used to resolve simultaneous dials.
+    local_peer_id: Option<PeerId>,
+    /// Policy of multiple connections per peer. `None` if duplicate connections are closed.
+    multi_conn: Option<MultiConnConf>,
+    /// Shared connections dialed by the peer with the greater id. Substreams are routed over them first.
+    preferred_conns: HashSet<ConnectionId>,
+    /// Connections which lost simultaneous-dial resolution and are being closed.
+    closing_conns: HashSet<ConnectionId>,
+    /// Connections protocols with peers are opened on.
+    protocol_conns: HashMap<(PeerId, ProtocolId), ConnectionId>,
+    /// Addresses outbound connections with peers were established at.
+    dial_addrs: HashMap<PeerId, Multiaddr>,
+    /// Policies inbound connections, protocol opens and messages are checked against.
+    inbound_policies: Option<InboundPolicyChain>,
+    /// Members of the network if it's permissioned.
+    allowlist: Option<Allowlist>,
+    /// Revision of the allowlist connected peers were last checked against.
+    allowlist_revision: u64,
+}
+
+/// Add the connection to the shared connections with a peer, preferred connections first,
+/// so that new substreams are routed over them.
+/// Returns the least preferred connection to close if there are more than `max_conns` of them.
+/// It's the same connection on both sides in case of simultaneous dials.
+fn add_shared_conn(
+    conn_ids: &mut Vec<ConnectionId>,
+    conn_id: ConnectionId,
+    preferred_conns: &HashSet<ConnectionId>,
+    max_conns: usize,
+) -> Option<ConnectionId> {
+    let ix = if preferred_conns.contains(&conn_id) {
+        conn_ids
+            .iter()
+            .position(|cid| !preferred_conns.contains(cid))
+            .unwrap_or(conn_ids.len())
+    } else {
+        conn_ids.len()
+    };
+    conn_ids.insert(ix, conn_id);
+    if conn_ids.len() > max_conns {
+        conn_ids.pop()
+    } else {
+        None
+    }
 }
 
 impl<TPeers, TPeerManager, THandler> NetworkController<TPeers, TPeerManager, THandler>
@@ -292,23 +609,719 @@ where
         peers: TPeers,
         peer_manager: TPeerManager,
         requests_recv: Receiver<NetworkControllerIn>,
+        enable_retry_policy: EnableRetryPolicy,
     ) -> Self {
+        let mut protocols_by_priority = supported_protocols.keys().copied().collect::<Vec<_>>();
+        protocols_by_priority.sort_by_key(|prot| {
+            let (conf, _) = &supported_protocols[prot];
+            (Reverse(conf.priority()), *prot)
+        });
+        let capabilities = supported_protocols
+            .iter()
+            .map(|(prot, (conf, _))| (*prot, conf.versions()))
+            .collect();
         Self {
             conn_handler_conf,
             supported_protocols,
+            protocols_by_priority,
+            capabilities,
             peers,
             peer_manager,
             enabled_peers: HashMap::new(),
             pending_one_shot_requests: HashMap::new(),
             requests_recv,
             pending_actions: VecDeque::new(),
+            pending_enable_requests: Vec::new(),
+            enable_retry_policy,
+            enable_attempts: HashMap::new(),
+            pending_enable_retries: FuturesUnordered::new(),
+            journal: None,
+            metrics: None,
+            memory_quota: None,
+            routing_hints: HashMap::new(),
+            one_shot_broadcasts: HashMap::new(),
+            one_shot_deliveries: HashMap::new(),
+            dedicated_channels: None,
+            pending_dedicated: HashSet::new(),
+            awaiting_dedicated: Vec::new(),
+            dedicated_fallbacks: HashSet::new(),
+            local_peer_id: None,
+            multi_conn: None,
+            preferred_conns: HashSet::new(),
+            closing_conns: HashSet::new(),
+            protocol_conns: HashMap::new(),
+            dial_addrs: HashMap::new(),
+            inbound_policies: None,
+            allowlist: None,
+            allowlist_revision: 0,
+        }
+    }
+
+    /// Dial peers at the given addresses instead of the ones known otherwise.
+    pub fn with_routing_hints(mut self, routing_hints: HashMap<PeerId, Multiaddr>) -> Self {
+        self.routing_hints = routing_hints;
+        self
+    }
+
+    /// Run consensus-critical protocols on connections separate from the ones used by other protocols.
+    pub fn with_dedicated_channels(mut self, conf: DedicatedChannelConf) -> Self {
+        self.dedicated_channels = Some(conf);
+        self
+    }
+
+    /// Resolve simultaneous dials deterministically: the peer with the smaller id keeps its inbound connection,
+    /// the other one keeps the outbound one. Otherwise the connection established last is closed.
+    pub fn with_local_peer_id(mut self, local_peer_id: PeerId) -> Self {
+        self.local_peer_id = Some(local_peer_id);
+        self
+    }
+
+    /// Keep up to `conf.max_conns_per_peer` shared connections with each peer instead of closing duplicates.
+    pub fn with_multi_connections(mut self, conf: MultiConnConf) -> Self {
+        self.multi_conn = Some(conf);
+        self
+    }
+
+    /// Report metrics of the network controller to the given sink.
+    pub fn with_metrics(mut self, metrics: Arc<dyn MetricsSink>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Account one-shot messages parked until their recipients are connected within the given quota.
+    /// Messages which don't fit into the quota are dropped as undelivered.
+    pub fn with_memory_quota(mut self, quota: MemoryQuota) -> Self {
+        self.memory_quota = Some(quota);
+        self
+    }
+
+    /// Check inbound events against the given policies before processing them.
+    pub fn with_inbound_policies(mut self, policies: InboundPolicyChain) -> Self {
+        self.inbound_policies = Some(policies);
+        self
+    }
+
+    /// Run a permissioned network: connections with peers not in the allowlist are refused,
+    /// and torn down once peers are evicted from it.
+    pub fn with_allowlist(mut self, allowlist: Allowlist) -> Self {
+        self.allowlist_revision = allowlist.revision();
+        self.allowlist = Some(allowlist);
+        self
+    }
+
+    /// Check the inbound event against the policies, punishing the peer if it's rejected.
+    fn check_inbound(&mut self, event: InboundEvent) -> Result<(), Rejection>
+    where
+        TPeers: Peers,
+    {
+        let now = Instant::now();
+        let allowlisted = match &mut self.allowlist {
+            Some(allowlist) => allowlist.check(event, now),
+            None => Ok(()),
+        };
+        let res = allowlisted.and_then(|_| match &mut self.inbound_policies {
+            Some(policies) => policies.check(event, now),
+            None => Ok(()),
+        });
+        res.map_err(|rejection| {
+            let peer_id = event.peer_id();
+            warn!(
+                "[NC] Rejected inbound event from peer {:?}: {}",
+                peer_id, rejection
+            );
+            if let Some(change) = rejection.reputation_change() {
+                self.peers.report_peer(peer_id, change);
+            }
+            if rejection == Rejection::NotAllowlisted {
+                self.peer_not_allowlisted(peer_id);
+            }
+            rejection
+        })
+    }
+
+    /// Tear down connections with peers evicted from the allowlist since the last check.
+    fn evict_non_members(&mut self) {
+        let Some(allowlist) = &self.allowlist else {
+            return;
+        };
+        let revision = allowlist.revision();
+        if revision == self.allowlist_revision {
+            return;
+        }
+        self.allowlist_revision = revision;
+        let evicted = self
+            .enabled_peers
+            .keys()
+            .filter(|peer_id| !allowlist.is_allowed(peer_id))
+            .copied()
+            .collect::<Vec<_>>();
+        for peer_id in evicted {
+            info!("[NC] Peer {:?} is evicted from the allowlist", peer_id);
+            self.close_all_connections(peer_id);
+            self.peer_not_allowlisted(peer_id);
+        }
+    }
+
+    /// Ban the peer in PM and tear down all connections with it right away.
+    fn ban_peer(&mut self, peer_id: PeerId, duration: Option<Duration>)
+    where
+        TPeers: Peers,
+    {
+        info!("[NC] Banning peer {:?} for {:?}", peer_id, duration);
+        self.peers.ban_peer(peer_id, duration);
+        self.close_all_connections(peer_id);
+        self.peer_banned(peer_id, duration);
+    }
+
+    /// Tear down all connections with the peer right away.
+    fn close_all_connections(&mut self, peer_id: PeerId) {
+        match self.enabled_peers.get(&peer_id) {
+            Some(ConnectedPeer::Connected {
+                conn_ids, dedicated, ..
+            }) => {
+                let conn_ids = conn_ids
+                    .iter()
+                    .chain(dedicated.as_ref().map(|d| &d.conn_id))
+                    .copied()
+                    .collect::<Vec<_>>();
+                for conn_id in conn_ids {
+                    self.pending_actions.push_back(ToSwarm::NotifyHandler {
+                        peer_id,
+                        handler: NotifyHandler::One(conn_id),
+                        event: ConnHandlerIn::CloseAllProtocols,
+                    });
+                }
+                self.peer_disconnected(
+                    peer_id,
+                    ConnectionLossReason::Reset(ConnHandlerError::UnacceptablePeer),
+                );
+            }
+            Some(ConnectedPeer::PendingApprove(_) | ConnectedPeer::PendingConnect { .. }) => {
+                self.pending_actions.push_back(ToSwarm::CloseConnection {
+                    peer_id,
+                    connection: CloseConnection::All,
+                });
+            }
+            Some(ConnectedPeer::PendingDisconnect(_)) | None => {}
+        }
+    }
+
+    /// Try to account the given one-shot message parked until the recipient is connected.
+    fn reserve_parked(&self, message: &OneShotMessage) -> bool {
+        match &self.memory_quota {
+            Some(quota) => match quota.try_reserve(message.content.as_ref().len()) {
+                Ok(()) => true,
+                Err(err) => {
+                    warn!("[NC] Dropping one-shot message: {}", err);
+                    false
+                }
+            },
+            None => true,
+        }
+    }
+
+    fn release_parked(&self, tasks: &[(OneShotRequestId, OneShotMessage)]) {
+        if let Some(quota) = &self.memory_quota {
+            quota.release(tasks.iter().map(|(_, msg)| msg.content.as_ref().len()).sum());
+        }
+    }
+
+    /// Record all events emitted by the network controller and the peer manager to the given journal,
+    /// along with inbound messages if the journal is configured to. Records are written off the poll loop,
+    /// see [`EventJournal::spawn_writer`].
+    ///
+    /// [`EventJournal::spawn_writer`]: crate::journal::EventJournal::spawn_writer
+    pub fn with_event_journal(mut self, journal: JournalSink) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Schedule next attempt to enable the given protocol or give up
+    /// if all retries are exhausted.
+    fn retry_enable_later(&mut self, peer_id: PeerId, protocol_id: ProtocolId)
+    where
+        THandler: ProtocolEvents,
+    {
+        if let Some(attempt) = self.enable_attempts.get_mut(&(peer_id, protocol_id)) {
+            attempt.retries += 1;
+            if let Some(backoff) = self.enable_retry_policy.backoff(attempt.retries) {
+                trace!(
+                    "[NC] Retrying to enable protocol {:?} with peer {:?} in {:?}",
+                    protocol_id,
+                    peer_id,
+                    backoff
+                );
+                self.pending_enable_retries.push(
+                    wasm_timer::Delay::new(backoff)
+                        .map(move |_| (peer_id, protocol_id))
+                        .boxed(),
+                );
+            } else {
+                warn!(
+                    "[NC] Failed to enable protocol {:?} with peer {:?} after {} retries",
+                    protocol_id, peer_id, self.enable_retry_policy.max_retries
+                );
+                self.enable_attempts.remove(&(peer_id, protocol_id));
+                if let Some((_, prot_handler)) = self.supported_protocols.get(&protocol_id) {
+                    prot_handler.protocol_enable_failed(peer_id);
+                }
+                self.protocol_enable_failed(peer_id, protocol_id);
+            }
+        }
+    }
+
+    /// Make another attempt to enable the given protocol.
+    fn retry_enable(&mut self, peer_id: PeerId, protocol_id: ProtocolId) {
+        if let Some(attempt) = self.enable_attempts.get(&(peer_id, protocol_id)) {
+            let critical = self.is_critical(&protocol_id);
+            if let Some(ConnectedPeer::Connected {
+                conn_ids,
+                enabled_protocols,
+                dedicated,
+            }) = self.enabled_peers.get_mut(&peer_id)
+            {
+                if let (Entry::Vacant(protocol_entry), Some((_, prot_handler))) = (
+                    enabled_protocols.entry(protocol_id),
+                    self.supported_protocols.get(&protocol_id),
+                ) {
+                    protocol_entry.insert((EnabledProtocol::PendingEnable, prot_handler.clone()));
+                    let conn_id = match dedicated {
+                        Some(dedicated) if critical => {
+                            dedicated.protocols.insert(protocol_id);
+                            dedicated.conn_id
+                        }
+                        _ => *conn_ids.first().unwrap(),
+                    };
+                    self.protocol_conns.insert((peer_id, protocol_id), conn_id);
+                    self.pending_actions.push_back(ToSwarm::NotifyHandler {
+                        peer_id,
+                        handler: NotifyHandler::One(conn_id),
+                        event: ConnHandlerIn::Open {
+                            protocol_id,
+                            handshake: attempt.handshake.clone(),
+                        },
+                    });
+                    self.protocol_pending_enable(peer_id, protocol_id);
+                    return;
+                }
+            }
+            // Peer is gone or protocol was negotiated in the meantime.
+            self.enable_attempts.remove(&(peer_id, protocol_id));
+        }
+    }
+
+    /// Make a single attempt to deliver the given one-shot message to the given peer,
+    /// connecting to the peer if necessary.
+    fn send_one_shot(
+        &mut self,
+        peer: PeerId,
+        addr_hint: Option<Multiaddr>,
+        request_id: OneShotRequestId,
+        message: OneShotMessage,
+    ) {
+        let parked = matches!(
+            self.enabled_peers.get(&peer),
+            None | Some(ConnectedPeer::PendingConnect { .. })
+        );
+        if parked && !self.reserve_parked(&message) {
+            self.on_one_shot_outcome(request_id, false);
+            return;
+        }
+        match self.enabled_peers.entry(peer) {
+            Entry::Occupied(mut enabled_peer) => match enabled_peer.get_mut() {
+                ConnectedPeer::Connected { conn_ids, .. } => {
+                    // if the peer is enabled already we choose existing connection
+                    self.pending_actions.push_back(ToSwarm::NotifyHandler {
+                        peer_id: peer,
+                        handler: NotifyHandler::One(*conn_ids.first().unwrap()),
+                        event: ConnHandlerIn::TryDeliverOnce(request_id, message),
+                    })
+                }
+                ConnectedPeer::PendingApprove(conn_id) => {
+                    // if the peer is enabled already we reuse existing connection
+                    self.pending_actions.push_back(ToSwarm::NotifyHandler {
+                        peer_id: peer,
+                        handler: NotifyHandler::One(*conn_id),
+                        event: ConnHandlerIn::TryDeliverOnce(request_id, message),
+                    })
+                }
+                ConnectedPeer::PendingConnect {
+                    tasks: adjacent_tasks,
+                    ..
+                } => {
+                    // if we are going to connect it anyway then we add an adjacent task
+                    adjacent_tasks.push((request_id, message));
+                    info!(
+                        "[NC] adding to adjacent task {:?}, # adjacent_tasks: {}",
+                        peer,
+                        adjacent_tasks.len()
+                    );
+                }
+                ConnectedPeer::PendingDisconnect(_) => {
+                    info!("[NC] FAILED OS to pending-disconnected-peer {:?}", peer);
+                    self.on_one_shot_outcome(request_id, false);
+                } // todo: wait for disconnect; reconnect?
+            },
+            Entry::Vacant(not_enabled_peer) => {
+                self.pending_actions.push_back(ToSwarm::Dial {
+                    opts: DialOpts::peer_id(peer)
+                        .addresses(
+                            self.routing_hints
+                                .get(&peer)
+                                .cloned()
+                                .or(addr_hint)
+                                .map_or(Vec::new(), |a| vec![a]),
+                        )
+                        .build(),
+                });
+                not_enabled_peer.insert(ConnectedPeer::PendingConnect {
+                    tasks: vec![(request_id, message)],
+                    terminate_asap: true,
+                });
+            }
+        }
+    }
+
+    fn broadcast_one_shot(
+        &mut self,
+        id: OneShotBroadcastId,
+        peers: Vec<PeerDestination>,
+        message: OneShotMessage,
+    ) {
+        let mut pending = HashMap::new();
+        for peer in peers {
+            let (peer_id, addr_hint) = match peer {
+                PeerDestination::PeerId(pid) => (pid, None),
+                PeerDestination::PeerIdWithAddr(pid, addr) => (pid, Some(addr)),
+            };
+            pending.insert(peer_id, (addr_hint, 0));
+        }
+        let recipients = pending.keys().copied().collect::<Vec<_>>();
+        self.one_shot_broadcasts.insert(
+            id,
+            OneShotBroadcast {
+                message,
+                pending,
+                delivered: Vec::new(),
+                failed: Vec::new(),
+            },
+        );
+        for peer_id in recipients {
+            self.attempt_broadcast_delivery(id, peer_id);
+        }
+        self.try_complete_broadcast(id);
+    }
+
+    fn attempt_broadcast_delivery(&mut self, id: OneShotBroadcastId, peer_id: PeerId) {
+        if let Some(broadcast) = self.one_shot_broadcasts.get_mut(&id) {
+            if let Some((addr_hint, attempts)) = broadcast.pending.get_mut(&peer_id) {
+                *attempts += 1;
+                let request_id = OneShotRequestId::random();
+                let addr_hint = addr_hint.clone();
+                let message = broadcast.message.clone();
+                self.one_shot_deliveries.insert(request_id, (id, peer_id));
+                self.send_one_shot(peer_id, addr_hint, request_id, message);
+            }
+        }
+    }
+
+    /// Account the outcome of an attempt to deliver a one-shot message.
+    fn on_one_shot_outcome(&mut self, request_id: OneShotRequestId, delivered: bool) {
+        if let Some((id, peer_id)) = self.one_shot_deliveries.remove(&request_id) {
+            if let Some(broadcast) = self.one_shot_broadcasts.get_mut(&id) {
+                if delivered {
+                    broadcast.pending.remove(&peer_id);
+                    broadcast.delivered.push(peer_id);
+                } else if let Some((_, attempts)) = broadcast.pending.get(&peer_id) {
+                    if *attempts < MAX_BROADCAST_ATTEMPTS {
+                        trace!("[NC] Retrying to deliver broadcast {:?} to {:?}", id, peer_id);
+                        self.attempt_broadcast_delivery(id, peer_id);
+                        return;
+                    }
+                    broadcast.pending.remove(&peer_id);
+                    broadcast.failed.push(peer_id);
+                }
+                self.try_complete_broadcast(id);
+            }
+        }
+    }
+
+    fn try_complete_broadcast(&mut self, id: OneShotBroadcastId) {
+        if let Entry::Occupied(broadcast) = self.one_shot_broadcasts.entry(id) {
+            if broadcast.get().pending.is_empty() {
+                let broadcast = broadcast.remove();
+                self.one_shot_broadcast_done(id, broadcast.delivered, broadcast.failed);
+            }
+        }
+    }
+
+    /// Whether the given protocol should run on a dedicated connection.
+    fn is_critical(&self, protocol_id: &ProtocolId) -> bool {
+        self.dedicated_channels.map_or(false, |conf| {
+            self.protocol_priority(protocol_id) >= conf.min_priority
+        })
+    }
+
+    fn has_dedicated_capacity(&self) -> bool {
+        self.dedicated_channels.map_or(false, |conf| {
+            let num_dedicated = self
+                .enabled_peers
+                .values()
+                .filter(|peer| {
+                    matches!(
+                        peer,
+                        ConnectedPeer::Connected {
+                            dedicated: Some(_),
+                            ..
+                        }
+                    )
+                })
+                .count();
+            num_dedicated + self.pending_dedicated.len() < conf.max_channels
+        })
+    }
+
+    /// Try to establish a dedicated connection with the given peer.
+    /// Returns `false` if the critical protocols should use the shared connection instead.
+    fn dial_dedicated(&mut self, peer_id: PeerId) -> bool {
+        if self.pending_dedicated.contains(&peer_id) {
+            return true;
+        }
+        if self.dedicated_fallbacks.contains(&peer_id) || !self.has_dedicated_capacity() {
+            return false;
+        }
+        // Only the side that dialed the peer knows where to reach it.
+        let Some(addr) = self
+            .routing_hints
+            .get(&peer_id)
+            .or_else(|| self.dial_addrs.get(&peer_id))
+            .cloned()
+        else {
+            return false;
+        };
+        trace!("[NC] Dialing dedicated connection with peer {:?}", peer_id);
+        self.pending_dedicated.insert(peer_id);
+        self.pending_actions.push_back(ToSwarm::Dial {
+            opts: DialOpts::peer_id(peer_id)
+                .condition(PeerCondition::Always)
+                .addresses(vec![addr])
+                .build(),
+        });
+        true
+    }
+
+    /// Process protocol enablements which were waiting for a dedicated connection with the given peer.
+    fn release_awaiting_dedicated(&mut self, peer_id: PeerId) {
+        let (released, awaiting): (Vec<_>, Vec<_>) = std::mem::take(&mut self.awaiting_dedicated)
+            .into_iter()
+            .partition(|(pid, _, _)| *pid == peer_id);
+        self.awaiting_dedicated = awaiting;
+        self.pending_enable_requests.extend(released);
+    }
+
+    /// Gracefully close the connection which lost simultaneous-dial resolution or exceeds the limit of
+    /// connections with the peer. Protocols pending on it are reopened over the surviving connection.
+    fn close_excess_conn(&mut self, peer_id: PeerId, conn_id: ConnectionId)
+    where
+        THandler: ProtocolEvents,
+    {
+        trace!(
+            "[NC] Closing excess connection {:?} with peer {:?}",
+            conn_id,
+            peer_id
+        );
+        self.closing_conns.insert(conn_id);
+        self.pending_actions.push_back(ToSwarm::NotifyHandler {
+            peer_id,
+            handler: NotifyHandler::One(conn_id),
+            event: ConnHandlerIn::CloseAllProtocols,
+        });
+        let moved_protocols = self
+            .protocol_conns
+            .iter()
+            .filter(|((pid, _), cid)| *pid == peer_id && **cid == conn_id)
+            .map(|((_, protocol_id), _)| *protocol_id)
+            .collect::<Vec<_>>();
+        for protocol_id in moved_protocols {
+            self.protocol_conns.remove(&(peer_id, protocol_id));
+            let Some(ConnectedPeer::Connected {
+                enabled_protocols, ..
+            }) = self.enabled_peers.get_mut(&peer_id)
+            else {
+                continue;
+            };
+            if let Some((_, prot_handler)) = enabled_protocols.remove(&protocol_id) {
+                if self.enable_attempts.contains_key(&(peer_id, protocol_id)) {
+                    self.retry_enable(peer_id, protocol_id);
+                } else {
+                    prot_handler.protocol_disabled(peer_id);
+                    self.protocol_disabled(peer_id, protocol_id);
+                }
+            }
+        }
+    }
+
+    /// Dedicated connection with the given peer failed, critical protocols fall back to the shared one.
+    fn dedicated_failed(&mut self, peer_id: PeerId) {
+        self.pending_dedicated.remove(&peer_id);
+        self.dedicated_fallbacks.insert(peer_id);
+        self.release_awaiting_dedicated(peer_id);
+    }
+
+    /// Whether the connection with the given peer was dialed by the side with the greater id.
+    fn is_preferred(&self, peer_id: PeerId, dialed_by_us: bool) -> bool {
+        self.local_peer_id
+            .map_or(false, |local_peer_id| dialed_by_us == (local_peer_id > peer_id))
+    }
+
+    fn protocol_priority(&self, protocol_id: &ProtocolId) -> ProtocolPriority {
+        self.supported_protocols
+            .get(protocol_id)
+            .map(|(conf, _)| conf.priority())
+            .unwrap_or_default()
+    }
+
+    /// Strip capabilities off the handshake received from the peer and let PM know which protocols
+    /// the peer can speak, so that only those are allocated with it.
+    fn accept_capabilities(&mut self, peer_id: PeerId, handshake: Option<RawMessage>) -> Option<RawMessage>
+    where
+        TPeers: Peers,
+    {
+        handshake.map(|hs| {
+            let (capabilities, hs) = split_advertised(hs);
+            if let Some(capabilities) = capabilities {
+                self.peers
+                    .set_peer_protocols(peer_id, self.capabilities.common_protocols(&capabilities));
+            }
+            hs
+        })
+    }
+
+    /// Open substream of the given protocol with the given peer, as requested by a protocol handler.
+    fn open_requested_protocol(
+        &mut self,
+        peer_id: PeerId,
+        protocol_id: ProtocolId,
+        handshake: PolyVerHandshakeSpec,
+    ) where
+        TPeers: PeerEvents,
+    {
+        let critical = self.is_critical(&protocol_id);
+        if critical {
+            // Substreams requested by the peer are approved on the connection they came from.
+            let approving = matches!(
+                self.enabled_peers.get(&peer_id),
+                Some(ConnectedPeer::Connected { enabled_protocols, .. })
+                    if matches!(enabled_protocols.get(&protocol_id), Some((EnabledProtocol::PendingApprove, _)))
+            );
+            let has_dedicated = matches!(
+                self.enabled_peers.get(&peer_id),
+                Some(ConnectedPeer::Connected {
+                    dedicated: Some(_),
+                    ..
+                })
+            );
+            if !approving && !has_dedicated && self.dial_dedicated(peer_id) {
+                self.awaiting_dedicated.push((peer_id, protocol_id, handshake));
+                return;
+            }
+        }
+        if let Some(ConnectedPeer::Connected {
+            conn_ids,
+            enabled_protocols,
+            dedicated,
+        }) = self.enabled_peers.get_mut(&peer_id)
+        {
+            let (_, prot_handler) = self.supported_protocols.get(&protocol_id).unwrap();
+            let shared_conn = *conn_ids.first().unwrap();
+            match enabled_protocols.entry(protocol_id) {
+                Entry::Occupied(protocol_entry) => match protocol_entry.remove_entry().1 {
+                    // Protocol handler approves either outbound or inbound protocol request.
+                    (st @ (EnabledProtocol::PendingEnable | EnabledProtocol::PendingApprove), handler) => {
+                        let conn_id = match dedicated {
+                            Some(dedicated) if dedicated.protocols.contains(&protocol_id) => {
+                                dedicated.conn_id
+                            }
+                            Some(dedicated) if critical && matches!(st, EnabledProtocol::PendingEnable) => {
+                                dedicated.protocols.insert(protocol_id);
+                                dedicated.conn_id
+                            }
+                            // Substreams requested by the peer are approved on the connection they came from.
+                            _ => self
+                                .protocol_conns
+                                .get(&(peer_id, protocol_id))
+                                .copied()
+                                .filter(|conn_id| conn_ids.contains(conn_id))
+                                .unwrap_or(shared_conn),
+                        };
+                        self.protocol_conns.insert((peer_id, protocol_id), conn_id);
+                        enabled_protocols.insert(protocol_id, (EnabledProtocol::PendingEnable, handler));
+                        self.enable_attempts.insert(
+                            (peer_id, protocol_id),
+                            EnableAttempt {
+                                retries: 0,
+                                handshake: handshake.clone(),
+                            },
+                        );
+                        self.pending_actions.push_back(ToSwarm::NotifyHandler {
+                            peer_id,
+                            handler: NotifyHandler::One(conn_id),
+                            event: ConnHandlerIn::Open {
+                                protocol_id,
+                                handshake,
+                            },
+                        });
+                    }
+                    (st @ (EnabledProtocol::Enabled { .. } | EnabledProtocol::PendingDisable), handler) => {
+                        warn!(
+                            "Handler requested to open already enabled protocol {:?} with peer {:?}",
+                            protocol_id, peer_id
+                        );
+                        enabled_protocols.insert(protocol_id, (st, handler));
+                    }
+                },
+                // Also, Protocol Handler can request a substream on its own.
+                Entry::Vacant(protocol_entry) => {
+                    trace!(
+                        "Handler requested to open protocol {:?} with peer {:?}",
+                        protocol_id,
+                        peer_id
+                    );
+                    protocol_entry.insert((EnabledProtocol::PendingEnable, prot_handler.clone()));
+                    let conn_id = match dedicated {
+                        Some(dedicated) if critical => {
+                            dedicated.protocols.insert(protocol_id);
+                            dedicated.conn_id
+                        }
+                        _ => shared_conn,
+                    };
+                    self.protocol_conns.insert((peer_id, protocol_id), conn_id);
+                    self.peers.force_enabled(peer_id, protocol_id); // notify PM
+                    self.enable_attempts.insert(
+                        (peer_id, protocol_id),
+                        EnableAttempt {
+                            retries: 0,
+                            handshake: handshake.clone(),
+                        },
+                    );
+                    self.pending_actions.push_back(ToSwarm::NotifyHandler {
+                        peer_id,
+                        handler: NotifyHandler::One(conn_id),
+                        event: ConnHandlerIn::Open {
+                            protocol_id,
+                            handshake,
+                        },
+                    });
+                    self.protocol_pending_enable(peer_id, protocol_id);
+                }
+            }
         }
     }
 
     fn init_conn_handler(
         &self,
         peer_id: PeerId,
-        one_shot_requests: Vec<OneShotMessage>,
+        one_shot_requests: Vec<(OneShotRequestId, OneShotMessage)>,
         terminate_asap: bool,
     ) -> PeerConnHandler {
         let mut stateful_protocols = HashMap::new();
@@ -316,14 +1329,18 @@ where
         for (protocol_id, (p, _)) in self.supported_protocols.iter() {
             match p {
                 ProtocolConfig::Stateful(stateful) => {
-                    for (ver, spec) in stateful.supported_versions.iter() {
+                    let all_versions_specs = stateful.versions_by_preference();
+                    if let Some((ver, spec)) = all_versions_specs.first().copied() {
                         stateful_protocols.insert(
                             *protocol_id,
                             StatefulProtocol {
-                                ver: *ver,
-                                spec: *spec,
+                                ver,
+                                spec,
                                 state: Some(ProtocolState::Closed),
-                                all_versions_specs: stateful.supported_versions.clone(),
+                                all_versions_specs,
+                                handshake: None,
+                                last_sent_at: Instant::now(),
+                                batch: OutboundBatch::default(),
                             },
                         );
                     }
@@ -356,9 +1373,15 @@ where
             throttle_stage: throttle_recv,
             pending_one_shots: one_shot_requests
                 .into_iter()
-                .map(|msg| (OneShotRequestId::random(), OneShotRequest::Pending(msg)))
+                .map(|(id, msg)| (id, OneShotRequest::Pending(msg)))
                 .collect(),
             terminate_asap,
+            idle_check: self
+                .conn_handler_conf
+                .idle_substream_policy
+                .idle_timeout()
+                .map(wasm_timer::Delay::new),
+            metrics: self.metrics.clone(),
         }
     }
 }
@@ -377,8 +1400,13 @@ where
         _connection_id: ConnectionId,
         peer: PeerId,
         _local_addr: &Multiaddr,
-        _remote_addr: &Multiaddr,
+        remote_addr: &Multiaddr,
     ) -> Result<libp2p::swarm::THandler<Self>, ConnectionDenied> {
+        self.check_inbound(InboundEvent::Connection {
+            peer_id: peer,
+            remote_addr,
+        })
+        .map_err(ConnectionDenied::new)?;
         Ok(self.init_conn_handler(peer, vec![], false))
     }
 
@@ -389,6 +1417,11 @@ where
         _addr: &Multiaddr,
         _role_override: Endpoint,
     ) -> Result<libp2p::swarm::THandler<Self>, ConnectionDenied> {
+        if self.allowlist.as_ref().is_some_and(|a| !a.is_allowed(&peer)) {
+            warn!("[NC] Refusing outbound connection with non-member {:?}", peer);
+            self.peer_not_allowlisted(peer);
+            return Err(ConnectionDenied::new(Rejection::NotAllowlisted));
+        }
         match self.enabled_peers.get(&peer) {
             Some(ConnectedPeer::PendingConnect {
                 tasks,
@@ -404,39 +1437,70 @@ where
             FromSwarm::ConnectionEstablished(ConnectionEstablished {
                 peer_id,
                 connection_id,
+                endpoint,
                 ..
             }) => {
+                if let Some(policies) = self.inbound_policies.as_mut().filter(|_| endpoint.is_listener()) {
+                    policies.connection_established(peer_id, endpoint.get_remote_address());
+                }
+                let accepts_dedicated = self.has_dedicated_capacity();
+                let mut dedicated_established = false;
+                if self.is_preferred(peer_id, endpoint.is_dialer()) {
+                    self.preferred_conns.insert(connection_id);
+                }
+                let max_conns = self.multi_conn.map_or(1, |conf| conf.max_conns_per_peer.max(1));
+                let mut excess_conn = None;
                 match self.enabled_peers.entry(peer_id) {
                     Entry::Occupied(mut peer_entry) => match peer_entry.get_mut() {
                         ConnectedPeer::PendingConnect { tasks, .. } => {
-                            for os_msg in tasks {
+                            if let Some(quota) = &self.memory_quota {
+                                quota.release(tasks.iter().map(|(_, msg)| msg.content.as_ref().len()).sum());
+                            }
+                            for (rid, os_msg) in tasks {
                                 self.pending_actions.push_back(ToSwarm::NotifyHandler {
                                     peer_id,
                                     handler: NotifyHandler::One(connection_id),
-                                    event: ConnHandlerIn::TryDeliverOnce(OneShotMessage {
-                                        protocol: os_msg.protocol,
-                                        content: os_msg.content.clone(),
-                                    }),
+                                    event: ConnHandlerIn::TryDeliverOnce(*rid, os_msg.clone()),
                                 });
                             }
                             self.peers.connection_established(peer_id, connection_id); // confirm connection
+                            self.dial_addrs
+                                .insert(peer_id, endpoint.get_remote_address().clone());
                             peer_entry.insert(ConnectedPeer::Connected {
                                 conn_ids: vec![connection_id],
                                 enabled_protocols: HashMap::new(),
+                                dedicated: None,
                             });
-                            // notify all handlers about new connection.
-                            for (_, ph) in self.supported_protocols.values() {
+                            // notify all handlers about new connection, most important protocols first.
+                            for prot in self.protocols_by_priority.iter() {
+                                let (_, ph) = &self.supported_protocols[prot];
                                 ph.connected(peer_id);
                             }
                             self.outbound_peer_connected(peer_id);
                         }
-                        ConnectedPeer::Connected { conn_ids, .. } => {
+                        ConnectedPeer::Connected {
+                            conn_ids, dedicated, ..
+                        } => {
                             assert!(!conn_ids.contains(&connection_id));
-                            conn_ids.push(connection_id);
-                            self.pending_actions.push_back(ToSwarm::CloseConnection {
-                                peer_id,
-                                connection: CloseConnection::One(connection_id),
-                            })
+                            let dialed_dedicated =
+                                endpoint.is_dialer() && self.pending_dedicated.remove(&peer_id);
+                            if dedicated.is_none()
+                                && (dialed_dedicated || (endpoint.is_listener() && accepts_dedicated))
+                            {
+                                trace!("[NC] Dedicated connection with peer {:?} established", peer_id);
+                                *dedicated = Some(DedicatedConn {
+                                    conn_id: connection_id,
+                                    protocols: HashSet::new(),
+                                });
+                                dedicated_established = true;
+                            } else {
+                                excess_conn = add_shared_conn(
+                                    conn_ids,
+                                    connection_id,
+                                    &self.preferred_conns,
+                                    max_conns,
+                                );
+                            }
                         }
                         ConnectedPeer::PendingDisconnect(..) => {
                             self.pending_actions.push_back(ToSwarm::CloseConnection {
@@ -457,22 +1521,73 @@ where
                         entry.insert(ConnectedPeer::PendingApprove(connection_id));
                     }
                 }
+                if let Some(conn_id) = excess_conn {
+                    self.close_excess_conn(peer_id, conn_id);
+                }
+                if dedicated_established {
+                    self.release_awaiting_dedicated(peer_id);
+                }
             }
 
             FromSwarm::ConnectionClosed(ConnectionClosed {
                 peer_id,
                 connection_id,
+                endpoint,
                 handler,
                 ..
             }) => {
+                if let Some(policies) = self.inbound_policies.as_mut().filter(|_| endpoint.is_listener()) {
+                    policies.connection_closed(peer_id, endpoint.get_remote_address());
+                }
+                self.preferred_conns.remove(&connection_id);
+                self.closing_conns.remove(&connection_id);
+                let mut dedicated_lost = false;
+                let mut lost_protocols = Vec::new();
                 let disconnect_reason = match self.enabled_peers.entry(peer_id) {
                     Entry::Occupied(mut peer_entry) => match peer_entry.get_mut() {
-                        ConnectedPeer::Connected { conn_ids, .. } => {
-                            let ix = conn_ids.iter().position(|c_id| *c_id == connection_id).unwrap();
-                            conn_ids.remove(ix);
-                            if conn_ids.is_empty() {
-                                peer_entry.remove();
+                        ConnectedPeer::Connected {
+                            enabled_protocols,
+                            dedicated,
+                            ..
+                        } if dedicated.as_ref().map_or(false, |d| d.conn_id == connection_id) => {
+                            // Critical protocols fall back to the shared connection.
+                            lost_protocols = dedicated
+                                .take()
+                                .unwrap()
+                                .protocols
+                                .into_iter()
+                                .filter_map(|pid| enabled_protocols.remove(&pid).map(|(_, ph)| (pid, ph)))
+                                .collect::<Vec<_>>();
+                            dedicated_lost = true;
+                            None
+                        }
+                        ConnectedPeer::Connected {
+                            conn_ids,
+                            enabled_protocols,
+                            ..
+                        } if conn_ids.iter().any(|cid| *cid != connection_id) => {
+                            // Protocols running on the lost connection are reopened over the remaining ones.
+                            conn_ids.retain(|cid| *cid != connection_id);
+                            let protocol_conns = &self.protocol_conns;
+                            lost_protocols = enabled_protocols
+                                .keys()
+                                .filter(|pid| protocol_conns.get(&(peer_id, **pid)) == Some(&connection_id))
+                                .copied()
+                                .collect::<Vec<_>>()
+                                .into_iter()
+                                .filter_map(|pid| enabled_protocols.remove(&pid).map(|(_, ph)| (pid, ph)))
+                                .collect();
+                            None
+                        }
+                        // The last shared connection with the peer is lost.
+                        ConnectedPeer::Connected { dedicated, .. } => {
+                            if let Some(dedicated) = dedicated.take() {
+                                self.pending_actions.push_back(ToSwarm::CloseConnection {
+                                    peer_id,
+                                    connection: CloseConnection::One(dedicated.conn_id),
+                                });
                             }
+                            peer_entry.remove();
                             if let Some(err) = handler.get_fault() {
                                 let reason = ConnectionLossReason::Reset(err);
                                 self.peers.connection_lost(peer_id, reason);
@@ -496,11 +1611,44 @@ where
                                 Some(reason)
                             }
                         }
-                        // todo: is it possible in case of simultaneous connection?
+                        ConnectedPeer::PendingApprove(conn_id) if *conn_id == connection_id => {
+                            // Connection was closed before PM approved it.
+                            peer_entry.remove();
+                            self.peers
+                                .connection_lost(peer_id, ConnectionLossReason::ResetByPeer);
+                            None
+                        }
+                        // Other connections with the peer are closed right away in these states.
                         ConnectedPeer::PendingConnect { .. } | ConnectedPeer::PendingApprove(..) => None,
                     },
                     Entry::Vacant(_) => None,
                 };
+                if dedicated_lost {
+                    warn!("[NC] Dedicated connection with peer {:?} is lost", peer_id);
+                    self.dedicated_fallbacks.insert(peer_id);
+                }
+                for (protocol_id, prot_handler) in lost_protocols {
+                    self.protocol_conns.remove(&(peer_id, protocol_id));
+                    if self.enable_attempts.contains_key(&(peer_id, protocol_id)) {
+                        self.retry_enable_later(peer_id, protocol_id);
+                    } else {
+                        prot_handler.protocol_disabled(peer_id);
+                        self.protocol_disabled(peer_id, protocol_id);
+                    }
+                }
+                if !self.enabled_peers.contains_key(&peer_id) {
+                    self.enable_attempts.retain(|(pid, _), _| *pid != peer_id);
+                    self.protocol_conns.retain(|(pid, _), _| *pid != peer_id);
+                    self.awaiting_dedicated.retain(|(pid, _, _)| *pid != peer_id);
+                    self.pending_dedicated.remove(&peer_id);
+                    self.dedicated_fallbacks.remove(&peer_id);
+                    self.dial_addrs.remove(&peer_id);
+                }
+                // One-shot messages the handler didn't manage to deliver.
+                let undelivered = handler.pending_one_shots.keys().copied().collect::<Vec<_>>();
+                for rid in undelivered {
+                    self.on_one_shot_outcome(rid, false);
+                }
                 if let Some(reason) = disconnect_reason {
                     info!("Disconnecting from {:?}, reason: {:?}", peer_id, reason);
                     self.peer_disconnected(peer_id, reason);
@@ -510,7 +1658,20 @@ where
             FromSwarm::DialFailure(DialFailure { peer_id, error, .. }) => {
                 info!("[NC] DIAL FAILURE to {:?}, error: {:?}", peer_id, error);
                 if let Some(peer_id) = peer_id {
+                    if self.pending_dedicated.contains(&peer_id) {
+                        self.dedicated_failed(peer_id);
+                    }
                     self.peers.dial_failure(peer_id);
+                    if let Some(ConnectedPeer::PendingConnect { .. }) = self.enabled_peers.get(&peer_id) {
+                        if let Some(ConnectedPeer::PendingConnect { tasks, .. }) =
+                            self.enabled_peers.remove(&peer_id)
+                        {
+                            self.release_parked(&tasks);
+                            for (rid, _) in tasks {
+                                self.on_one_shot_outcome(rid, false);
+                            }
+                        }
+                    }
                 }
             }
 
@@ -551,6 +1712,23 @@ where
         connection: ConnectionId,
         event: ConnHandlerOut,
     ) {
+        if self.closing_conns.contains(&connection)
+            && matches!(
+                event,
+                ConnHandlerOut::Opened { .. }
+                    | ConnHandlerOut::OpenedByPeer { .. }
+                    | ConnHandlerOut::RefusedToOpen(_)
+                    | ConnHandlerOut::ClosedByPeer(_)
+                    | ConnHandlerOut::Closed(_)
+            )
+        {
+            // Protocols of the closing connection were moved to the surviving one already.
+            trace!(
+                "[NC] Ignoring protocol event of closing connection {:?}",
+                connection
+            );
+            return;
+        }
         match event {
             ConnHandlerOut::Opened {
                 protocol_tag,
@@ -558,6 +1736,7 @@ where
                 handshake,
             } => {
                 trace!("Protocol {} opened with peer {}", protocol_tag, peer_id);
+                let handshake = self.accept_capabilities(peer_id, handshake);
                 if let Some(ConnectedPeer::Connected {
                     enabled_protocols, ..
                 }) = self.enabled_peers.get_mut(&peer_id)
@@ -583,6 +1762,8 @@ where
                                     sink: out_channel,
                                 };
                                 entry.insert((enabled_protocol, handler.clone()));
+                                self.protocol_conns.insert((peer_id, protocol_id), connection);
+                                self.enable_attempts.remove(&(peer_id, protocol_id));
                                 self.protocol_enabled(peer_id, protocol_id, protocol_ver);
                             }
                         }
@@ -596,17 +1777,37 @@ where
                 protocol_tag,
                 handshake,
             } => {
+                let protocol_id = protocol_tag.protocol_id();
+                if self
+                    .check_inbound(InboundEvent::ProtocolOpen { peer_id, protocol_id })
+                    .is_err()
+                {
+                    self.pending_actions.push_back(ToSwarm::NotifyHandler {
+                        peer_id,
+                        handler: NotifyHandler::One(connection),
+                        event: ConnHandlerIn::Close(protocol_id),
+                    });
+                    return;
+                }
+                let handshake = self.accept_capabilities(peer_id, handshake);
                 if let Some(peer) = self.enabled_peers.get_mut(&peer_id) {
                     match peer {
                         ConnectedPeer::Connected {
-                            enabled_protocols, ..
+                            enabled_protocols,
+                            dedicated,
+                            ..
                         } => {
                             trace!("Connection opened by {:?} in Connected state", peer_id);
-                            let protocol_id = protocol_tag.protocol_id();
                             let (_, prot_handler) = self.supported_protocols.get(&protocol_id).unwrap();
                             match enabled_protocols.entry(protocol_id) {
                                 Entry::Vacant(entry) => {
                                     entry.insert((EnabledProtocol::PendingApprove, prot_handler.clone()));
+                                    self.protocol_conns.insert((peer_id, protocol_id), connection);
+                                    if let Some(dedicated) = dedicated {
+                                        if dedicated.conn_id == connection {
+                                            dedicated.protocols.insert(protocol_id);
+                                        }
+                                    }
                                     prot_handler.protocol_requested(
                                         peer_id,
                                         protocol_tag.protocol_ver(),
@@ -641,13 +1842,38 @@ where
                     trace!("Connection opened by {:?}, not in enabled peers", peer_id);
                 }
             }
-            ConnHandlerOut::ClosedByPeer(protocol_id)
-            | ConnHandlerOut::RefusedToOpen(protocol_id)
-            | ConnHandlerOut::Closed(protocol_id) => {
+            ConnHandlerOut::RefusedToOpen(protocol_id) => {
                 if let Some(ConnectedPeer::Connected {
-                    enabled_protocols, ..
+                    enabled_protocols,
+                    dedicated,
+                    ..
                 }) = self.enabled_peers.get_mut(&peer_id)
                 {
+                    if let Some(dedicated) = dedicated {
+                        dedicated.protocols.remove(&protocol_id);
+                    }
+                    if let Entry::Occupied(entry) = enabled_protocols.entry(protocol_id) {
+                        trace!(
+                            "Peer {:?} refused to open the substream for protocol {:?}",
+                            peer_id,
+                            protocol_id
+                        );
+                        entry.remove();
+                        self.protocol_conns.remove(&(peer_id, protocol_id));
+                        self.retry_enable_later(peer_id, protocol_id);
+                    }
+                }
+            }
+            ConnHandlerOut::ClosedByPeer(protocol_id) | ConnHandlerOut::Closed(protocol_id) => {
+                if let Some(ConnectedPeer::Connected {
+                    enabled_protocols,
+                    dedicated,
+                    ..
+                }) = self.enabled_peers.get_mut(&peer_id)
+                {
+                    if let Some(dedicated) = dedicated {
+                        dedicated.protocols.remove(&protocol_id);
+                    }
                     match enabled_protocols.entry(protocol_id) {
                         Entry::Occupied(entry) => {
                             trace!(
@@ -656,32 +1882,94 @@ where
                                 protocol_id
                             );
                             entry.remove();
+                            self.protocol_conns.remove(&(peer_id, protocol_id));
                         }
                         Entry::Vacant(_) => {}
                     }
                 }
             }
             ConnHandlerOut::ClosedAllProtocols => {
-                assert!(self.enabled_peers.remove(&peer_id).is_some());
+                if let Some(ConnectedPeer::Connected { conn_ids, .. }) = self.enabled_peers.get_mut(&peer_id)
+                {
+                    conn_ids.retain(|cid| *cid != connection);
+                    if !conn_ids.is_empty() {
+                        // Other connections with the peer are yet to be closed.
+                        return;
+                    }
+                }
+                let peer = self.enabled_peers.remove(&peer_id);
+                self.protocol_conns.retain(|(pid, _), _| *pid != peer_id);
+                if let Some(ConnectedPeer::Connected {
+                    dedicated: Some(dedicated),
+                    ..
+                }) = peer
+                {
+                    self.pending_actions.push_back(ToSwarm::CloseConnection {
+                        peer_id,
+                        connection: CloseConnection::One(dedicated.conn_id),
+                    });
+                }
+            }
+            ConnHandlerOut::OneShotDelivered(rid) => {
+                self.on_one_shot_outcome(rid, true);
+            }
+            ConnHandlerOut::OneShotFailed(rid) => {
+                self.on_one_shot_outcome(rid, false);
             }
             ConnHandlerOut::OneShotMessage {
                 protocol_tag,
                 content,
             } => {
-                if let Some((_, han)) = self.supported_protocols.get(&protocol_tag.protocol_id()) {
+                if let Some(journal) = &mut self.journal {
+                    journal.record_inbound_message(peer_id, protocol_tag, &content);
+                }
+                if let Some(metrics) = &self.metrics {
+                    metrics::record_message(
+                        metrics.as_ref(),
+                        protocol_tag.protocol_id(),
+                        content.as_ref().len(),
+                    );
+                }
+                let protocol_id = protocol_tag.protocol_id();
+                let msg = InboundEvent::Message {
+                    peer_id,
+                    protocol_id,
+                    size: content.as_ref().len(),
+                };
+                if self.check_inbound(msg).is_err() {
+                    return;
+                }
+                if let Some((_, han)) = self.supported_protocols.get(&protocol_id) {
                     han.incoming_msg(peer_id, protocol_tag.protocol_ver(), content);
                 }
-                // todo: punish peer for spam otherwise?
             }
             ConnHandlerOut::Message {
                 protocol_tag,
                 content,
             } => {
+                if let Some(journal) = &mut self.journal {
+                    journal.record_inbound_message(peer_id, protocol_tag, &content);
+                }
+                if let Some(metrics) = &self.metrics {
+                    metrics::record_message(
+                        metrics.as_ref(),
+                        protocol_tag.protocol_id(),
+                        content.as_ref().len(),
+                    );
+                }
+                let protocol_id = protocol_tag.protocol_id();
+                let msg = InboundEvent::Message {
+                    peer_id,
+                    protocol_id,
+                    size: content.as_ref().len(),
+                };
+                if self.check_inbound(msg).is_err() {
+                    return;
+                }
                 if let Some(ConnectedPeer::Connected {
                     enabled_protocols, ..
                 }) = self.enabled_peers.get_mut(&peer_id)
                 {
-                    let protocol_id = protocol_tag.protocol_id();
                     match enabled_protocols.get(&protocol_id) {
                         Some((_, prot_handler)) => {
                             prot_handler.incoming_msg(peer_id, protocol_tag.protocol_ver(), content);
@@ -698,39 +1986,71 @@ where
         cx: &mut Context<'_>,
         _: &mut impl PollParameters,
     ) -> Poll<ToSwarm<NetworkControllerOut, ConnHandlerIn>> {
+        self.evict_non_members();
         loop {
             // 1. Try to return a pending action.
             if let Some(action) = self.pending_actions.pop_front() {
+                if let (ToSwarm::GenerateEvent(event), Some(journal)) = (&action, &mut self.journal) {
+                    journal.record_network_event(event);
+                }
+                if let (ToSwarm::GenerateEvent(event), Some(metrics)) = (&action, &self.metrics) {
+                    metrics::record_network_event(metrics.as_ref(), event);
+                    let connected_peers = self
+                        .enabled_peers
+                        .values()
+                        .filter(|peer| matches!(peer, ConnectedPeer::Connected { .. }))
+                        .count();
+                    metrics.set_gauge(Metric::ConnectedPeers, vec![], connected_peers as i64);
+                }
                 return Poll::Ready(action);
             };
             // 2. Poll for instructions from PM.
-            match Stream::poll_next(Pin::new(&mut self.peer_manager), cx) {
+            let pm_out = Stream::poll_next(Pin::new(&mut self.peer_manager), cx);
+            if let (Poll::Ready(Some(out)), Some(journal)) = (&pm_out, &mut self.journal) {
+                journal.record_peer_manager_event(out);
+            }
+            match pm_out {
                 Poll::Ready(Some(PeerManagerOut::Connect(pid))) => {
                     match self.enabled_peers.entry(pid.peer_id()) {
-                        Entry::Occupied(_) => {}
+                        Entry::Occupied(mut peer_entry) => {
+                            // Simultaneous dial: the peer has dialed us already, so its connection is used.
+                            if let ConnectedPeer::PendingApprove(cid) = *peer_entry.get() {
+                                trace!(
+                                    "[NC] Using inbound connection {:?} with peer {}",
+                                    cid,
+                                    pid.peer_id()
+                                );
+                                peer_entry.insert(ConnectedPeer::Connected {
+                                    conn_ids: vec![cid],
+                                    enabled_protocols: HashMap::new(),
+                                    dedicated: None,
+                                });
+                                self.peers.connection_established(pid.peer_id(), cid);
+                                for prot in self.protocols_by_priority.iter() {
+                                    let (_, ph) = &self.supported_protocols[prot];
+                                    ph.connected(pid.peer_id());
+                                }
+                                self.outbound_peer_connected(pid.peer_id());
+                            }
+                        }
                         Entry::Vacant(peer_entry) => {
                             peer_entry.insert(ConnectedPeer::PendingConnect {
                                 tasks: Vec::new(),
                                 terminate_asap: false,
                             });
-                            self.pending_actions.push_back(ToSwarm::Dial { opts: pid.into() })
+                            let dest = match self.routing_hints.get(&pid.peer_id()) {
+                                Some(addr) => PeerDestination::PeerIdWithAddr(pid.peer_id(), addr.clone()),
+                                None => pid,
+                            };
+                            self.pending_actions
+                                .push_back(ToSwarm::Dial { opts: dest.into() })
                         }
                     }
                     continue;
                 }
                 Poll::Ready(Some(PeerManagerOut::Drop(peer_id))) => {
-                    if let Some(ConnectedPeer::Connected { conn_ids, .. }) =
-                        self.enabled_peers.get_mut(&peer_id)
-                    {
-                        self.pending_actions.push_back(ToSwarm::NotifyHandler {
-                            peer_id,
-                            handler: NotifyHandler::One(*conn_ids.first().unwrap()),
-                            event: ConnHandlerIn::CloseAllProtocols,
-                        });
-                        self.peer_disconnected(
-                            peer_id,
-                            ConnectionLossReason::Reset(ConnHandlerError::UnacceptablePeer),
-                        );
+                    if let Some(ConnectedPeer::Connected { .. }) = self.enabled_peers.get(&peer_id) {
+                        self.close_all_connections(peer_id);
                     }
                     continue;
                 }
@@ -742,6 +2062,7 @@ where
                                 peer.insert(ConnectedPeer::Connected {
                                     conn_ids: vec![cid],
                                     enabled_protocols: HashMap::new(),
+                                    dedicated: None,
                                 });
                                 self.inbound_peer_connected(pid);
                             }
@@ -803,11 +2124,21 @@ where
                     self.peer_punished(peer_id, reason);
                     continue;
                 }
+                // Already recorded in the journal and metrics, nothing to do with connections.
+                Poll::Ready(Some(PeerManagerOut::GaveUp(_))) => continue,
                 Poll::Pending => {}
                 Poll::Ready(None) => unreachable!("PeerManager should never terminate"),
             }
 
-            // 3. Poll commands from protocol handlers.
+            // 3. Retry to enable protocols refused earlier.
+            if let Poll::Ready(Some((peer_id, protocol_id))) =
+                Stream::poll_next(Pin::new(&mut self.pending_enable_retries), cx)
+            {
+                self.retry_enable(peer_id, protocol_id);
+                continue;
+            }
+
+            // 4. Poll commands from protocol handlers.
             if let Poll::Ready(Some(input)) = Stream::poll_next(Pin::new(&mut self.requests_recv), cx) {
                 match input {
                     NetworkControllerIn::SendOneShotMessage {
@@ -815,136 +2146,300 @@ where
                         addr_hint,
                         protocol,
                         message,
-                    } => match self.enabled_peers.entry(peer) {
-                        Entry::Occupied(mut enabled_peer) => match enabled_peer.get_mut() {
-                            ConnectedPeer::Connected { conn_ids, .. } => {
-                                // if the peer is enabled already we choose existing connection
-                                self.pending_actions.push_back(ToSwarm::NotifyHandler {
-                                    peer_id: peer,
-                                    handler: NotifyHandler::One(*conn_ids.first().unwrap()),
-                                    event: ConnHandlerIn::TryDeliverOnce(OneShotMessage {
-                                        protocol,
-                                        content: message,
-                                    }),
-                                })
-                            }
-                            ConnectedPeer::PendingApprove(conn_id) => {
-                                // if the peer is enabled already we reuse existing connection
-                                self.pending_actions.push_back(ToSwarm::NotifyHandler {
-                                    peer_id: peer,
-                                    handler: NotifyHandler::One(*conn_id),
-                                    event: ConnHandlerIn::TryDeliverOnce(OneShotMessage {
-                                        protocol,
-                                        content: message,
-                                    }),
-                                })
-                            }
-                            ConnectedPeer::PendingConnect {
-                                tasks: adjacent_tasks,
-                                ..
-                            } => {
-                                // if we are going to connect it anyway then we add an adjacent task
-                                adjacent_tasks.push(OneShotMessage {
-                                    protocol,
-                                    content: message,
-                                });
-                                info!(
-                                    "[NC] adding to adjacent task {:?}, # adjacent_tasks: {}",
-                                    peer,
-                                    adjacent_tasks.len()
-                                );
-                            }
-                            ConnectedPeer::PendingDisconnect(_) => {
-                                info!("[NC] FAILED OS to pending-disconnected-peer {:?}", peer);
-                            } // todo: wait for disconnect; reconnect?
+                    } => self.send_one_shot(
+                        peer,
+                        addr_hint,
+                        OneShotRequestId::random(),
+                        OneShotMessage {
+                            protocol,
+                            content: message,
                         },
-                        Entry::Vacant(not_enabled_peer) => {
-                            self.pending_actions.push_back(ToSwarm::Dial {
-                                opts: DialOpts::peer_id(peer)
-                                    .addresses(addr_hint.map_or(Vec::new(), |a| vec![a]))
-                                    .build(),
-                            });
-                            not_enabled_peer.insert(ConnectedPeer::PendingConnect {
-                                tasks: vec![OneShotMessage {
-                                    protocol,
-                                    content: message,
-                                }],
-                                terminate_asap: true,
-                            });
-                        }
-                    },
+                    ),
+                    NetworkControllerIn::SendOneShotBroadcast {
+                        id,
+                        peers,
+                        protocol,
+                        message,
+                    } => self.broadcast_one_shot(
+                        id,
+                        peers,
+                        OneShotMessage {
+                            protocol,
+                            content: message,
+                        },
+                    ),
                     NetworkControllerIn::UpdatePeerProtocols { peer, protocols } => {
                         self.peers.set_peer_protocols(peer, protocols);
                     }
                     NetworkControllerIn::EnableProtocol {
-                        peer: peer_id,
-                        protocol: protocol_id,
+                        peer,
+                        protocol,
                         handshake,
                     } => {
-                        if let Some(ConnectedPeer::Connected {
-                            conn_ids,
-                            enabled_protocols,
-                        }) = self.enabled_peers.get_mut(&peer_id)
-                        {
-                            let (_, prot_handler) = self.supported_protocols.get(&protocol_id).unwrap();
-                            match enabled_protocols.entry(protocol_id) {
-                                Entry::Occupied(protocol_entry) => match protocol_entry.remove_entry().1 {
-                                    // Protocol handler approves either outbound or inbound protocol request.
-                                    (
-                                        EnabledProtocol::PendingEnable | EnabledProtocol::PendingApprove,
-                                        handler,
-                                    ) => {
-                                        enabled_protocols
-                                            .insert(protocol_id, (EnabledProtocol::PendingEnable, handler));
-                                        self.pending_actions.push_back(ToSwarm::NotifyHandler {
-                                            peer_id,
-                                            handler: NotifyHandler::One(*conn_ids.first().unwrap()),
-                                            event: ConnHandlerIn::Open {
-                                                protocol_id,
-                                                handshake,
-                                            },
-                                        });
-                                    }
-                                    (
-                                        st @ (EnabledProtocol::Enabled { .. }
-                                        | EnabledProtocol::PendingDisable),
-                                        handler,
-                                    ) => {
-                                        warn!("Handler requested to open already enabled protocol {:?} with peer {:?}", protocol_id, peer_id);
-                                        enabled_protocols.insert(protocol_id, (st, handler));
-                                    }
-                                },
-                                // Also, Protocol Handler can request a substream on its own.
-                                Entry::Vacant(protocol_entry) => {
-                                    trace!(
-                                        "Handler requested to open protocol {:?} with peer {:?}",
-                                        protocol_id,
-                                        peer_id
-                                    );
-                                    protocol_entry
-                                        .insert((EnabledProtocol::PendingEnable, prot_handler.clone()));
-                                    self.peers.force_enabled(peer_id, protocol_id); // notify PM
-                                    self.pending_actions.push_back(ToSwarm::NotifyHandler {
-                                        peer_id,
-                                        handler: NotifyHandler::One(*conn_ids.first().unwrap()),
-                                        event: ConnHandlerIn::Open {
-                                            protocol_id,
-                                            handshake,
-                                        },
-                                    });
-                                    self.protocol_pending_enable(peer_id, protocol_id);
-                                }
-                            }
-                        }
+                        // Processed in the order of protocol priority once all ready requests are drained.
+                        let handshake = handshake.advertise(&self.capabilities);
+                        self.pending_enable_requests.push((peer, protocol, handshake));
+                    }
+                    NetworkControllerIn::BanPeer { peer_id, duration } => {
+                        self.ban_peer(peer_id, duration);
                     }
-                    NetworkControllerIn::BanPeer(pid) => {
-                        //todo: Ban peer; DEV-941
+                    NetworkControllerIn::ReportPeer { peer_id, change } => {
+                        self.peers.report_peer(peer_id, change);
                     }
                 }
                 continue;
             }
 
+            // 5. Open substreams requested by handlers, most important protocols first.
+            if !self.pending_enable_requests.is_empty() {
+                let mut requests = std::mem::take(&mut self.pending_enable_requests);
+                requests.sort_by_key(|(_, protocol_id, _)| Reverse(self.protocol_priority(protocol_id)));
+                for (peer_id, protocol_id, handshake) in requests {
+                    self.open_requested_protocol(peer_id, protocol_id, handshake);
+                }
+                continue;
+            }
+
             return Poll::Pending;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+    use std::time::Duration;
+
+    use futures::channel::mpsc;
+    use libp2p::swarm::{ConnectionId, ToSwarm};
+    use libp2p::PeerId;
+
+    use crate::network_controller::{
+        add_shared_conn, DedicatedChannelConf, EnableRetryPolicy, NetworkController, NetworkControllerOut,
+        OneShotBroadcastId,
+    };
+    use crate::one_shot_upgrade::OneShotMessage;
+    use crate::peer_conn_handler::{IdleSubstreamPolicy, PeerConnHandlerConf};
+    use crate::peer_manager::data::PeerDestination;
+    use crate::protocol::{
+        OneShotProtocolConfig, OneShotProtocolSpec, ProtocolConfig, ProtocolPriority, DIFFUSION_PROTOCOL_ID,
+        DISCOVERY_PROTOCOL_ID, SIGMA_AGGR_PROTOCOL_ID, SIGMA_AGGR_V2,
+    };
+    use crate::types::{ProtocolTag, RawMessage};
+
+    fn conn_handler_conf() -> PeerConnHandlerConf {
+        PeerConnHandlerConf {
+            control_msg_buffer_size: 1,
+            async_msg_buffer_size: 1,
+            sync_msg_buffer_size: 1,
+            open_timeout: Duration::from_secs(1),
+            initial_keep_alive: Duration::from_secs(1),
+            idle_substream_policy: IdleSubstreamPolicy::KeepOpen,
+        }
+    }
+
+    #[test]
+    fn backoff_grows_exponentially_up_to_max() {
+        let policy = EnableRetryPolicy {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(5),
+            max_retries: 4,
+        };
+        assert_eq!(policy.backoff(0), None);
+        assert_eq!(policy.backoff(1), Some(Duration::from_secs(1)));
+        assert_eq!(policy.backoff(2), Some(Duration::from_secs(2)));
+        assert_eq!(policy.backoff(3), Some(Duration::from_secs(4)));
+        assert_eq!(policy.backoff(4), Some(Duration::from_secs(5)));
+        assert_eq!(policy.backoff(5), None);
+    }
+
+    #[test]
+    fn backoff_does_not_overflow() {
+        let policy = EnableRetryPolicy {
+            initial_backoff: Duration::from_secs(u64::MAX / 2),
+            max_backoff: Duration::from_secs(60),
+            max_retries: 100,
+        };
+        assert_eq!(policy.backoff(64), Some(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn protocols_are_ordered_by_priority() {
+        let conf = |priority| {
+            ProtocolConfig::OneShot(OneShotProtocolConfig {
+                version: SIGMA_AGGR_V2,
+                spec: OneShotProtocolSpec {
+                    max_message_size: 100,
+                },
+                priority,
+            })
+        };
+        let (_, requests_recv) = mpsc::channel(1);
+        let nc = NetworkController::new(
+            conn_handler_conf(),
+            HashMap::from([
+                (DISCOVERY_PROTOCOL_ID, (conf(ProtocolPriority::NORMAL), ())),
+                (DIFFUSION_PROTOCOL_ID, (conf(ProtocolPriority(1)), ())),
+                (SIGMA_AGGR_PROTOCOL_ID, (conf(ProtocolPriority::HIGH), ())),
+            ]),
+            (),
+            (),
+            requests_recv,
+            EnableRetryPolicy::default(),
+        );
+        assert_eq!(
+            nc.protocols_by_priority,
+            vec![
+                SIGMA_AGGR_PROTOCOL_ID,
+                DIFFUSION_PROTOCOL_ID,
+                DISCOVERY_PROTOCOL_ID
+            ]
+        );
+        assert_eq!(
+            nc.protocol_priority(&SIGMA_AGGR_PROTOCOL_ID),
+            ProtocolPriority::HIGH
+        );
+    }
+
+    #[test]
+    fn failed_broadcast_delivery_is_retried_once() {
+        let (_, requests_recv) = mpsc::channel(1);
+        let mut nc = NetworkController::<(), (), ()>::new(
+            conn_handler_conf(),
+            HashMap::new(),
+            (),
+            (),
+            requests_recv,
+            EnableRetryPolicy::default(),
+        );
+        let reachable = PeerId::random();
+        let unreachable = PeerId::random();
+        let id = OneShotBroadcastId::random();
+        nc.broadcast_one_shot(
+            id,
+            vec![
+                PeerDestination::PeerId(reachable),
+                PeerDestination::PeerId(unreachable),
+            ],
+            OneShotMessage {
+                protocol: ProtocolTag::new(DIFFUSION_PROTOCOL_ID, SIGMA_AGGR_V2),
+                content: RawMessage::from(vec![0u8]),
+            },
+        );
+        let request_to = |nc: &NetworkController<(), (), ()>, peer| {
+            *nc.one_shot_deliveries
+                .iter()
+                .find(|(_, (_, pid))| *pid == peer)
+                .unwrap()
+                .0
+        };
+        nc.on_one_shot_outcome(request_to(&nc, reachable), true);
+        nc.on_one_shot_outcome(request_to(&nc, unreachable), false);
+        assert!(nc.one_shot_broadcasts.contains_key(&id));
+        nc.on_one_shot_outcome(request_to(&nc, unreachable), false);
+        assert!(nc.one_shot_broadcasts.is_empty());
+        assert!(nc.one_shot_deliveries.is_empty());
+        assert!(matches!(
+            nc.pending_actions.back(),
+            Some(ToSwarm::GenerateEvent(NetworkControllerOut::OneShotBroadcastDone {
+                id: done_id,
+                delivered,
+                failed,
+            })) if *done_id == id && *delivered == vec![reachable] && *failed == vec![unreachable]
+        ));
+    }
+
+    #[test]
+    fn critical_protocols_fall_back_to_shared_connection() {
+        let conf = |priority| {
+            ProtocolConfig::OneShot(OneShotProtocolConfig {
+                version: SIGMA_AGGR_V2,
+                spec: OneShotProtocolSpec {
+                    max_message_size: 100,
+                },
+                priority,
+            })
+        };
+        let (_, requests_recv) = mpsc::channel(1);
+        let mut nc = NetworkController::<(), (), ()>::new(
+            conn_handler_conf(),
+            HashMap::from([
+                (DIFFUSION_PROTOCOL_ID, (conf(ProtocolPriority::NORMAL), ())),
+                (SIGMA_AGGR_PROTOCOL_ID, (conf(ProtocolPriority::HIGH), ())),
+            ]),
+            (),
+            (),
+            requests_recv,
+            EnableRetryPolicy::default(),
+        );
+        assert!(!nc.is_critical(&SIGMA_AGGR_PROTOCOL_ID));
+        nc = nc.with_dedicated_channels(DedicatedChannelConf {
+            min_priority: ProtocolPriority::HIGH,
+            max_channels: 1,
+        });
+        assert!(nc.is_critical(&SIGMA_AGGR_PROTOCOL_ID));
+        assert!(!nc.is_critical(&DIFFUSION_PROTOCOL_ID));
+        // Address of the peer is unknown.
+        let peer_id = PeerId::random();
+        assert!(!nc.dial_dedicated(peer_id));
+        let addr = "/ip4/127.0.0.1/tcp/8000".parse().unwrap();
+        nc.dial_addrs.insert(peer_id, addr);
+        assert!(nc.dial_dedicated(peer_id));
+        assert!(nc.pending_actions.back().is_some());
+        // No capacity left.
+        let other_peer_id = PeerId::random();
+        nc.dial_addrs
+            .insert(other_peer_id, "/ip4/127.0.0.1/tcp/8001".parse().unwrap());
+        assert!(!nc.dial_dedicated(other_peer_id));
+        // Dial failed.
+        nc.dedicated_failed(peer_id);
+        assert!(!nc.dial_dedicated(peer_id));
+    }
+
+    #[test]
+    fn simultaneous_dials_resolved_by_peer_id() {
+        let (lower, higher) = {
+            let (a, b) = (PeerId::random(), PeerId::random());
+            (a.min(b), a.max(b))
+        };
+        let nc = |local_peer_id| {
+            let (_, requests_recv) = mpsc::channel(1);
+            NetworkController::<(), (), ()>::new(
+                conn_handler_conf(),
+                HashMap::new(),
+                (),
+                (),
+                requests_recv,
+                EnableRetryPolicy::default(),
+            )
+            .with_local_peer_id(local_peer_id)
+        };
+        let (lower_nc, higher_nc) = (nc(lower), nc(higher));
+        // Lower peer keeps its inbound connection, higher one keeps the outbound one.
+        assert!(lower_nc.is_preferred(higher, false));
+        assert!(higher_nc.is_preferred(lower, true));
+        assert!(!lower_nc.is_preferred(higher, true));
+        assert!(!higher_nc.is_preferred(lower, false));
+
+        let (dialed_by_lower, dialed_by_higher) =
+            (ConnectionId::new_unchecked(1), ConnectionId::new_unchecked(2));
+        let preferred = HashSet::from([dialed_by_higher]);
+        // Each side observes its own outbound connection first.
+        let mut lower_conns = vec![dialed_by_lower];
+        let mut higher_conns = vec![dialed_by_higher];
+        assert_eq!(
+            add_shared_conn(&mut lower_conns, dialed_by_higher, &preferred, 1),
+            Some(dialed_by_lower)
+        );
+        assert_eq!(
+            add_shared_conn(&mut higher_conns, dialed_by_lower, &preferred, 1),
+            Some(dialed_by_lower)
+        );
+        assert_eq!(lower_conns, higher_conns);
+
+        // Extra connections are kept up to the limit, preferred ones first.
+        let mut conns = vec![dialed_by_lower];
+        assert_eq!(add_shared_conn(&mut conns, dialed_by_higher, &preferred, 2), None);
+        assert_eq!(conns, vec![dialed_by_higher, dialed_by_lower]);
+    }
+}
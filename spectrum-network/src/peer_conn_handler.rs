@@ -3,11 +3,11 @@ use std::collections::{HashMap, VecDeque};
 use std::fmt::{Debug, Formatter};
 use std::mem;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 use std::time::{Duration, Instant};
 
 use either::{Either, Left, Right};
-use futures::channel::mpsc;
 pub use futures::prelude::*;
 use libp2p::swarm::handler::{
     ConnectionEvent, DialUpgradeError, FullyNegotiatedInbound, FullyNegotiatedOutbound,
@@ -18,8 +18,12 @@ use log::{error, trace};
 use rand::rngs::OsRng;
 use rand::RngCore;
 
+use crate::metrics::{self, MetricsSink};
 use crate::one_shot_upgrade::{OneShotMessage, OneShotUpgradeIn, OneShotUpgradeOut};
-use crate::peer_conn_handler::message_sink::{MessageSink, StreamNotification};
+use crate::peer_conn_handler::batching::{MalformedBatch, OutboundBatch};
+use crate::peer_conn_handler::message_sink::{
+    LaneBufferSizes, MessageSink, OutboundLanes, StreamNotification,
+};
 use crate::protocol::{OneShotProtocolSpec, StatefulProtocolSpec};
 use crate::protocol_upgrade::combinators::AnyUpgradeOf;
 use crate::protocol_upgrade::handshake::PolyVerHandshakeSpec;
@@ -27,6 +31,7 @@ use crate::protocol_upgrade::substream::{ProtocolSubstreamIn, ProtocolSubstreamO
 use crate::protocol_upgrade::{ProtocolUpgradeIn, ProtocolUpgradeOut};
 use crate::types::{ProtocolId, ProtocolTag, ProtocolVer, RawMessage};
 
+pub mod batching;
 pub mod message_sink;
 
 const MIN_TERM_DELAY: Duration = Duration::from_millis(50);
@@ -41,13 +46,42 @@ pub struct StatefulProtocol {
     /// Always `Some`. `None` only during update (state transition).
     pub state: Option<ProtocolState>,
     /// Specs for all supported versions of this protocol
-    /// Note, versions must be listed in descending order.
+    /// Note, versions must be listed in order of preference.
     pub all_versions_specs: Vec<(ProtocolVer, StatefulProtocolSpec)>,
+    /// Handshake the outbound substream was opened with.
+    /// Sent again when the substream is re-opened after idle eviction.
+    pub handshake: Option<RawMessage>,
+    /// When a message was sent to the outbound substream for the last time.
+    pub last_sent_at: Instant,
+    /// Outbound messages held back until they are sent in one frame.
+    /// Only used if batching is enabled in the negotiated version.
+    pub batch: OutboundBatch,
+}
+
+impl StatefulProtocol {
+    /// Messages carried by the given inbound frame.
+    fn unpack(&self, frame: RawMessage) -> Result<Vec<RawMessage>, MalformedBatch> {
+        if self.spec.batching.is_some() {
+            batching::unpack(frame)
+        } else {
+            Ok(vec![frame])
+        }
+    }
+
+    /// Switch to the version agreed on with the peer.
+    fn negotiated(&mut self, ver: ProtocolVer) {
+        if let Some((_, spec)) = self.all_versions_specs.iter().find(|(v, _)| *v == ver) {
+            self.ver = ver;
+            self.spec = *spec;
+        }
+        // Leftovers of the previous substream may be encoded for another version.
+        self.batch = OutboundBatch::default();
+    }
 }
 
 #[derive(Debug)]
 pub struct OneShotProtocol {
-    /// The only version supported by this type of protocol.
+    /// The latest version supported by this type of protocol, earlier versions are accepted too.
     pub ver: ProtocolVer,
     /// Spec for negotiated protocol version
     pub spec: OneShotProtocolSpec,
@@ -75,29 +109,32 @@ pub enum ProtocolState {
     Opened {
         substream_in: ProtocolSubstreamIn<Stream>,
         substream_out: ProtocolSubstreamOut<Stream>,
-        pending_messages_recv: stream::Peekable<
-            stream::Select<
-                stream::Fuse<mpsc::Receiver<StreamNotification>>,
-                stream::Fuse<mpsc::Receiver<StreamNotification>>,
-            >,
-        >,
+        pending_messages_recv: stream::Peekable<OutboundLanes>,
     },
     /// Inbound substream is closed by peer.
     InboundClosedByPeer {
         /// None in the case when the peer closed inbound substream while outbound one
         /// hasn't been negotiated yet.
         substream_out: ProtocolSubstreamOut<Stream>,
-        pending_messages_recv: stream::Peekable<
-            stream::Select<
-                stream::Fuse<mpsc::Receiver<StreamNotification>>,
-                stream::Fuse<mpsc::Receiver<StreamNotification>>,
-            >,
-        >,
+        pending_messages_recv: stream::Peekable<OutboundLanes>,
     },
     /// Outbound substream is closed by peer.
     OutboundClosedByPeer {
         substream_in: ProtocolSubstreamIn<Stream>,
     },
+    /// Outbound substream is closed due to inactivity. It is re-opened once there is
+    /// something to send, meanwhile the protocol remains enabled.
+    Idle {
+        /// None in the case inbound substream is closed by peer.
+        substream_in: Option<ProtocolSubstreamIn<Stream>>,
+        pending_messages_recv: stream::Peekable<OutboundLanes>,
+    },
+    /// Outbound substream is being re-opened after idle eviction.
+    Reopening {
+        /// None in the case inbound substream is closed by peer.
+        substream_in: Option<ProtocolSubstreamIn<Stream>>,
+        pending_messages_recv: stream::Peekable<OutboundLanes>,
+    },
 }
 
 impl Debug for ProtocolState {
@@ -113,16 +150,65 @@ impl Debug for ProtocolState {
             ProtocolState::Opened { .. } => f.write_str("ProtocolState::Opened"),
             ProtocolState::InboundClosedByPeer { .. } => f.write_str("ProtocolState::InboundClosedByPeer"),
             ProtocolState::OutboundClosedByPeer { .. } => f.write_str("ProtocolState::OutboundClosedByPeer"),
+            ProtocolState::Idle { .. } => f.write_str("ProtocolState::Idle"),
+            ProtocolState::Reopening { .. } => f.write_str("ProtocolState::Reopening"),
+        }
+    }
+}
+
+/// What to do with protocol substreams nothing is sent over for a long time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum IdleSubstreamPolicy {
+    /// Keep substreams open for as long as the protocol is enabled.
+    #[default]
+    KeepOpen,
+    /// Close outbound substream once nothing was sent over it for (at least) the given period.
+    /// The connection and the protocol stay enabled, the substream is re-opened on the next send.
+    CloseAfter(Duration),
+}
+
+impl IdleSubstreamPolicy {
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        match self {
+            IdleSubstreamPolicy::KeepOpen => None,
+            IdleSubstreamPolicy::CloseAfter(timeout) => Some(*timeout),
         }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct PeerConnHandlerConf {
+    /// Buffer size of the lane of control messages.
+    pub control_msg_buffer_size: usize,
+    /// Buffer sizes of the lane of bulk messages.
     pub async_msg_buffer_size: usize,
     pub sync_msg_buffer_size: usize,
     pub open_timeout: Duration,
     pub initial_keep_alive: Duration,
+    pub idle_substream_policy: IdleSubstreamPolicy,
+}
+
+impl Default for PeerConnHandlerConf {
+    fn default() -> Self {
+        Self {
+            control_msg_buffer_size: 40,
+            async_msg_buffer_size: 10,
+            sync_msg_buffer_size: 40,
+            open_timeout: Duration::from_secs(60),
+            initial_keep_alive: Duration::from_secs(60),
+            idle_substream_policy: IdleSubstreamPolicy::CloseAfter(Duration::from_secs(300)),
+        }
+    }
+}
+
+impl PeerConnHandlerConf {
+    pub fn lane_buffer_sizes(&self) -> LaneBufferSizes {
+        LaneBufferSizes {
+            control: self.control_msg_buffer_size,
+            bulk_async: self.async_msg_buffer_size,
+            bulk_sync: self.sync_msg_buffer_size,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -149,7 +235,9 @@ pub enum ConnHandlerIn {
     /// Must always be answered by a [`ConnHandlerOut::ClosedAllProtocols`] event.
     CloseAllProtocols,
     /// Instruct the handler to make a single attempt to deliver the given message.
-    TryDeliverOnce(OneShotMessage),
+    ///
+    /// Answered by either [`ConnHandlerOut::OneShotDelivered`] or [`ConnHandlerOut::OneShotFailed`].
+    TryDeliverOnce(OneShotRequestId, OneShotMessage),
 }
 
 #[derive(Debug, Clone)]
@@ -167,6 +255,10 @@ pub enum ConnHandlerOut {
     Closed(ProtocolId),
     /// Ack [`ConnHandlerIn::CloseAllProtocols`]
     ClosedAllProtocols,
+    /// Ack [`ConnHandlerIn::TryDeliverOnce`]. The message was written to the substream.
+    OneShotDelivered(OneShotRequestId),
+    /// Ack [`ConnHandlerIn::TryDeliverOnce`]. Failed to negotiate a substream for the message.
+    OneShotFailed(OneShotRequestId),
 
     // Events:
     /// The remote would like the substreams to be open. Send a [`ConnHandlerIn::Open`] or a
@@ -222,7 +314,7 @@ pub struct PeerConnHandler {
     pub pending_events: VecDeque<
         ConnectionHandlerEvent<
             Either<ProtocolUpgradeOut, OneShotUpgradeOut>,
-            ProtocolTag,
+            OutboundOpenInfo,
             ConnHandlerOut,
             ConnHandlerError,
         >,
@@ -238,6 +330,11 @@ pub struct PeerConnHandler {
     pub pending_one_shots: HashMap<OneShotRequestId, OneShotRequest>,
     /// Should the handler terminate as soon as possible when no work left.
     pub terminate_asap: bool,
+    /// When to look for idle substreams next time.
+    /// `None` if idle substreams are never evicted.
+    pub idle_check: Option<wasm_timer::Delay>,
+    /// Optional sink of metrics.
+    pub metrics: Option<Arc<dyn MetricsSink>>,
 }
 
 impl PeerConnHandler {
@@ -253,17 +350,20 @@ impl ConnectionHandler for PeerConnHandler {
     type InboundProtocol = AnyUpgradeOf<Either<ProtocolUpgradeIn, OneShotUpgradeIn>>;
     type OutboundProtocol = Either<ProtocolUpgradeOut, OneShotUpgradeOut>;
     type InboundOpenInfo = ();
-    type OutboundOpenInfo = ProtocolTag;
+    type OutboundOpenInfo = OutboundOpenInfo;
 
     fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, ()> {
         let stateful_protocols = self
             .stateful_protocols
             .iter()
             .map(|(pid, prot)| Left(ProtocolUpgradeIn::new(*pid, prot.all_versions_specs.clone())));
-        let one_shot_protocols = self.one_shot_protocols.iter().map(|(pid, prot)| {
-            Right(OneShotUpgradeIn {
-                protocol: ProtocolTag::new(*pid, prot.ver),
-                max_message_size: prot.spec.max_message_size,
+        // Messages of earlier versions are accepted as well, so that peers not upgraded yet can reach us.
+        let one_shot_protocols = self.one_shot_protocols.iter().flat_map(|(pid, prot)| {
+            (1..=prot.ver.0).map(move |ver| {
+                Right(OneShotUpgradeIn {
+                    protocol: ProtocolTag::new(*pid, ProtocolVer(ver)),
+                    max_message_size: prot.spec.max_message_size,
+                })
             })
         });
         let protocols = stateful_protocols
@@ -274,9 +374,11 @@ impl ConnectionHandler for PeerConnHandler {
 
     fn on_behaviour_event(&mut self, event: ConnHandlerIn) {
         match event {
-            ConnHandlerIn::TryDeliverOnce(msg) => {
+            ConnHandlerIn::TryDeliverOnce(id, msg) => {
+                // The same request may be passed to the handler upon creation.
                 self.pending_one_shots
-                    .insert(OneShotRequestId::random(), OneShotRequest::Pending(msg));
+                    .entry(id)
+                    .or_insert(OneShotRequest::Pending(msg));
                 trace!(
                     "[PCH] TryDeliverOnce to {:?}: # pending one shots: {}",
                     self.peer_id,
@@ -293,21 +395,23 @@ impl ConnectionHandler for PeerConnHandler {
                     if let Some(state) = state {
                         let state_next = match state {
                             ProtocolState::Closed => {
+                                protocol.handshake = handshake.handshake_for(protocol.ver);
                                 let upgrade = Left(ProtocolUpgradeOut::new(
                                     protocol_id,
                                     protocol
                                         .all_versions_specs
-                                        .clone()
-                                        .into_iter()
-                                        .zip::<Vec<_>>(handshake.into())
-                                        .map(|((ver, spec), (_, hs))| (ver, spec, hs))
+                                        .iter()
+                                        .map(|(ver, spec)| (*ver, *spec, handshake.handshake_for(*ver)))
                                         .collect(),
                                 ));
                                 self.pending_events.push_back(
                                     ConnectionHandlerEvent::OutboundSubstreamRequest {
                                         protocol: SubstreamProtocol::new(
                                             upgrade,
-                                            ProtocolTag::new(protocol_id, protocol.ver),
+                                            OutboundOpenInfo::Stateful(ProtocolTag::new(
+                                                protocol_id,
+                                                protocol.ver,
+                                            )),
                                         )
                                         .with_timeout(self.conf.open_timeout),
                                     },
@@ -316,6 +420,7 @@ impl ConnectionHandler for PeerConnHandler {
                             }
                             ProtocolState::PartiallyOpenedByPeer { mut substream_in } => {
                                 let ver_handshake = handshake.handshake_for(protocol.ver);
+                                protocol.handshake = ver_handshake.clone();
                                 if ver_handshake.is_some() {
                                     // If handshake is defined dialer is waiting for approve, so we send it.
                                     trace!("Sending approve for inbound protocol {:?}", protocol_id);
@@ -330,7 +435,10 @@ impl ConnectionHandler for PeerConnHandler {
                                     ConnectionHandlerEvent::OutboundSubstreamRequest {
                                         protocol: SubstreamProtocol::new(
                                             upgrade,
-                                            ProtocolTag::new(protocol_id, protocol.ver),
+                                            OutboundOpenInfo::Stateful(ProtocolTag::new(
+                                                protocol_id,
+                                                protocol.ver,
+                                            )),
                                         )
                                         .with_timeout(self.conf.open_timeout),
                                     },
@@ -402,6 +510,7 @@ impl ConnectionHandler for PeerConnHandler {
                         trace!("Current protocol state is {:?}", state);
                         let state_next = match state {
                             ProtocolState::Closed => {
+                                protocol.negotiated(negotiated_tag.protocol_ver());
                                 let event =
                                     ConnectionHandlerEvent::NotifyBehaviour(ConnHandlerOut::OpenedByPeer {
                                         protocol_tag: negotiated_tag,
@@ -413,21 +522,59 @@ impl ConnectionHandler for PeerConnHandler {
                                 }
                             }
                             // Should not happen in normal network conditions.
-                            ProtocolState::Opening => ProtocolState::PartiallyOpenedByPeer {
-                                substream_in: upgrade.substream,
-                            },
-                            ProtocolState::PartiallyOpened { substream_out }
-                            | ProtocolState::InboundClosedByPeer { substream_out, .. } => {
+                            ProtocolState::Opening => {
+                                protocol.negotiated(negotiated_tag.protocol_ver());
+                                ProtocolState::PartiallyOpenedByPeer {
+                                    substream_in: upgrade.substream,
+                                }
+                            }
+                            // Peer re-opened its outbound substream, e.g. after idle eviction.
+                            // The sink given to the protocol earlier remains in use.
+                            ProtocolState::InboundClosedByPeer {
+                                substream_out,
+                                pending_messages_recv,
+                            } => {
+                                if protocol.spec.approve_required {
+                                    upgrade.substream.send_approve();
+                                }
+                                ProtocolState::Opened {
+                                    substream_in: upgrade.substream,
+                                    substream_out,
+                                    pending_messages_recv,
+                                }
+                            }
+                            ProtocolState::Idle {
+                                substream_in: None,
+                                pending_messages_recv,
+                            } => {
+                                if protocol.spec.approve_required {
+                                    upgrade.substream.send_approve();
+                                }
+                                ProtocolState::Idle {
+                                    substream_in: Some(upgrade.substream),
+                                    pending_messages_recv,
+                                }
+                            }
+                            ProtocolState::Reopening {
+                                substream_in: None,
+                                pending_messages_recv,
+                            } => {
+                                if protocol.spec.approve_required {
+                                    upgrade.substream.send_approve();
+                                }
+                                ProtocolState::Reopening {
+                                    substream_in: Some(upgrade.substream),
+                                    pending_messages_recv,
+                                }
+                            }
+                            ProtocolState::PartiallyOpened { substream_out } => {
                                 if protocol.spec.approve_required {
                                     // Approve immediately if required.
                                     trace!("Sending approve for outbound protocol {:?}", protocol_id);
                                     upgrade.substream.send_approve();
                                 }
-                                let (async_msg_snd, async_msg_recv) =
-                                    mpsc::channel::<StreamNotification>(self.conf.async_msg_buffer_size);
-                                let (sync_msg_snd, sync_msg_recv) =
-                                    mpsc::channel::<StreamNotification>(self.conf.sync_msg_buffer_size);
-                                let sink = MessageSink::new(self.peer_id, async_msg_snd, sync_msg_snd);
+                                let (sink, lanes) =
+                                    message_sink::channel(self.peer_id, self.conf.lane_buffer_sizes());
                                 self.pending_events
                                     .push_back(ConnectionHandlerEvent::NotifyBehaviour(
                                         ConnHandlerOut::Opened {
@@ -439,11 +586,7 @@ impl ConnectionHandler for PeerConnHandler {
                                 ProtocolState::Opened {
                                     substream_out,
                                     substream_in: upgrade.substream,
-                                    pending_messages_recv: stream::select(
-                                        async_msg_recv.fuse(),
-                                        sync_msg_recv.fuse(),
-                                    )
-                                    .peekable(),
+                                    pending_messages_recv: lanes.peekable(),
                                 }
                             }
                             // If a substream already exists, silently drop the new one.
@@ -456,7 +599,9 @@ impl ConnectionHandler for PeerConnHandler {
                             ProtocolState::PartiallyOpenedByPeer { .. }
                             | ProtocolState::Opened { .. }
                             | ProtocolState::Accepting { .. }
-                            | ProtocolState::OutboundClosedByPeer { .. } => state,
+                            | ProtocolState::OutboundClosedByPeer { .. }
+                            | ProtocolState::Idle { .. }
+                            | ProtocolState::Reopening { .. } => state,
                         };
                         trace!("Next protocol state is {:?}", state_next);
                         protocol.state = Some(state_next);
@@ -470,30 +615,35 @@ impl ConnectionHandler for PeerConnHandler {
             }) => {
                 trace!("[PCH] oneshot {:?} has been fired", rid);
                 self.pending_one_shots.remove(&rid);
+                self.pending_events
+                    .push_back(ConnectionHandlerEvent::NotifyBehaviour(
+                        ConnHandlerOut::OneShotDelivered(rid),
+                    ));
             }
 
             ConnectionEvent::FullyNegotiatedOutbound(FullyNegotiatedOutbound {
                 protocol: future::Either::Left(upgrade),
-                info: negotiated_tag,
+                info: OutboundOpenInfo::Stateful(_),
             }) => {
                 trace!("inject_fully_negotiated_outbound()");
+                let negotiated_tag = upgrade.negotiated_tag;
                 let protocol_id = negotiated_tag.protocol_id();
                 if let Some(protocol) = self.stateful_protocols.get_mut(&protocol_id) {
                     let state = protocol.state.take();
                     trace!("Current protocol state is {:?}", state);
                     if let Some(state) = state {
                         let state_next = match state {
-                            ProtocolState::Opening => ProtocolState::PartiallyOpened {
-                                substream_out: upgrade.substream,
-                            },
+                            ProtocolState::Opening => {
+                                protocol.negotiated(negotiated_tag.protocol_ver());
+                                ProtocolState::PartiallyOpened {
+                                    substream_out: upgrade.substream,
+                                }
+                            }
                             ProtocolState::Accepting {
                                 substream_in: Some(substream_in),
                             } => {
-                                let (async_msg_snd, async_msg_recv) =
-                                    mpsc::channel::<StreamNotification>(self.conf.async_msg_buffer_size);
-                                let (sync_msg_snd, sync_msg_recv) =
-                                    mpsc::channel::<StreamNotification>(self.conf.sync_msg_buffer_size);
-                                let sink = MessageSink::new(self.peer_id, async_msg_snd, sync_msg_snd);
+                                let (sink, lanes) =
+                                    message_sink::channel(self.peer_id, self.conf.lane_buffer_sizes());
                                 self.pending_events
                                     .push_back(ConnectionHandlerEvent::NotifyBehaviour(
                                         ConnHandlerOut::Opened {
@@ -505,11 +655,28 @@ impl ConnectionHandler for PeerConnHandler {
                                 ProtocolState::Opened {
                                     substream_in,
                                     substream_out: upgrade.substream,
-                                    pending_messages_recv: stream::select(
-                                        async_msg_recv.fuse(),
-                                        sync_msg_recv.fuse(),
-                                    )
-                                    .peekable(),
+                                    pending_messages_recv: lanes.peekable(),
+                                }
+                            }
+                            ProtocolState::Reopening {
+                                substream_in,
+                                pending_messages_recv,
+                            } => {
+                                trace!("Outbound substream of {:?} is re-opened", protocol_id);
+                                protocol.last_sent_at = Instant::now();
+                                if let Some(metrics) = &self.metrics {
+                                    metrics::record_substream_reopened(metrics.as_ref(), protocol_id);
+                                }
+                                match substream_in {
+                                    Some(substream_in) => ProtocolState::Opened {
+                                        substream_in,
+                                        substream_out: upgrade.substream,
+                                        pending_messages_recv,
+                                    },
+                                    None => ProtocolState::InboundClosedByPeer {
+                                        substream_out: upgrade.substream,
+                                        pending_messages_recv,
+                                    },
                                 }
                             }
                             // todo: handle this in the case we decide to re-open out substream.
@@ -523,8 +690,30 @@ impl ConnectionHandler for PeerConnHandler {
                 }
             }
 
+            ConnectionEvent::FullyNegotiatedOutbound(FullyNegotiatedOutbound {
+                protocol: future::Either::Left(_),
+                info: OutboundOpenInfo::OneShot(..),
+            }) => {} // Not possible, one-shot upgrades are always on the right.
+
+            ConnectionEvent::DialUpgradeError(DialUpgradeError {
+                info: OutboundOpenInfo::OneShot(protocol_tag, rid),
+                error,
+            }) => {
+                trace!(
+                    "[PCH] Failed to deliver oneshot {:?} of {}, {:?}",
+                    rid,
+                    protocol_tag,
+                    error
+                );
+                self.pending_one_shots.remove(&rid);
+                self.pending_events
+                    .push_back(ConnectionHandlerEvent::NotifyBehaviour(
+                        ConnHandlerOut::OneShotFailed(rid),
+                    ));
+            }
+
             ConnectionEvent::DialUpgradeError(DialUpgradeError {
-                info: protocol_tag,
+                info: OutboundOpenInfo::Stateful(protocol_tag),
                 error,
             }) => {
                 let protocol_id = protocol_tag.protocol_id();
@@ -538,6 +727,14 @@ impl ConnectionHandler for PeerConnHandler {
                                         ConnHandlerOut::RefusedToOpen(protocol_id),
                                     ))
                             }
+                            // The protocol is enabled from the behaviour's point of view.
+                            ProtocolState::Reopening { .. } => {
+                                trace!("Failed to re-open protocol {:?}, {:?}", protocol_id, error);
+                                self.pending_events
+                                    .push_back(ConnectionHandlerEvent::NotifyBehaviour(
+                                        ConnHandlerOut::ClosedByPeer(protocol_id),
+                                    ))
+                            }
                             _ => {}
                         }
                     }
@@ -594,18 +791,109 @@ impl ConnectionHandler for PeerConnHandler {
                 });
                 self.pending_events
                     .push_back(ConnectionHandlerEvent::OutboundSubstreamRequest {
-                        protocol: SubstreamProtocol::new(upgrade, message.protocol)
-                            .with_timeout(self.conf.open_timeout),
+                        protocol: SubstreamProtocol::new(
+                            upgrade,
+                            OutboundOpenInfo::OneShot(message.protocol, *id),
+                        )
+                        .with_timeout(self.conf.open_timeout),
                     });
             }
             *req = OneShotRequest::Confirming;
         }
 
+        // Evict idle outbound substreams.
+        if let (Some(idle_check), Some(idle_timeout)) = (
+            &mut self.idle_check,
+            self.conf.idle_substream_policy.idle_timeout(),
+        ) {
+            if idle_check.poll_unpin(cx).is_ready() {
+                *idle_check = wasm_timer::Delay::new(idle_timeout);
+                for (protocol_id, protocol) in &mut self.stateful_protocols {
+                    if protocol.last_sent_at.elapsed() < idle_timeout || !protocol.batch.is_empty() {
+                        continue;
+                    }
+                    if let Some(
+                        ProtocolState::Opened {
+                            pending_messages_recv,
+                            ..
+                        }
+                        | ProtocolState::InboundClosedByPeer {
+                            pending_messages_recv,
+                            ..
+                        },
+                    ) = &mut protocol.state
+                    {
+                        // Don't evict substreams with messages waiting to be sent.
+                        if let Poll::Ready(Some(_)) = Pin::new(pending_messages_recv).poll_peek(cx) {
+                            continue;
+                        }
+                        trace!("Closing idle outbound substream of {:?}", protocol_id);
+                        if let Some(metrics) = &self.metrics {
+                            metrics::record_substream_evicted(metrics.as_ref(), *protocol_id);
+                        }
+                        protocol.state = match protocol.state.take() {
+                            Some(ProtocolState::Opened {
+                                substream_in,
+                                pending_messages_recv,
+                                ..
+                            }) => Some(ProtocolState::Idle {
+                                substream_in: Some(substream_in),
+                                pending_messages_recv,
+                            }),
+                            Some(ProtocolState::InboundClosedByPeer {
+                                pending_messages_recv,
+                                ..
+                            }) => Some(ProtocolState::Idle {
+                                substream_in: None,
+                                pending_messages_recv,
+                            }),
+                            st => st,
+                        };
+                    }
+                }
+            }
+        }
+
+        // Re-open evicted outbound substreams once there is something to send.
+        for (protocol_id, protocol) in &mut self.stateful_protocols {
+            if let Some(ProtocolState::Idle {
+                pending_messages_recv,
+                ..
+            }) = &mut protocol.state
+            {
+                if let Poll::Ready(Some(_)) = Pin::new(pending_messages_recv).poll_peek(cx) {
+                    trace!("Re-opening outbound substream of {:?}", protocol_id);
+                    let upgrade = Left(ProtocolUpgradeOut::new(
+                        *protocol_id,
+                        vec![(protocol.ver, protocol.spec, protocol.handshake.clone())],
+                    ));
+                    self.pending_events
+                        .push_back(ConnectionHandlerEvent::OutboundSubstreamRequest {
+                            protocol: SubstreamProtocol::new(
+                                upgrade,
+                                OutboundOpenInfo::Stateful(ProtocolTag::new(*protocol_id, protocol.ver)),
+                            )
+                            .with_timeout(self.conf.open_timeout),
+                        });
+                    if let Some(ProtocolState::Idle {
+                        substream_in,
+                        pending_messages_recv,
+                    }) = protocol.state.take()
+                    {
+                        protocol.state = Some(ProtocolState::Reopening {
+                            substream_in,
+                            pending_messages_recv,
+                        });
+                    }
+                }
+            }
+        }
+
         if let Some(out) = self.pending_events.pop_front() {
             Poll::Ready(out)
         } else {
             // For each open substream, try to send messages from `pending_messages_recv`.
-            for protocol in self.stateful_protocols.values_mut() {
+            for (protocol_id, protocol) in self.stateful_protocols.iter_mut() {
                 if let Some(
                     ProtocolState::Opened {
                         substream_out,
@@ -664,10 +952,45 @@ impl ConnectionHandler for PeerConnHandler {
                             | Poll::Ready(None)
                             | Poll::Pending => break,
                         };
+                        if let Some(metrics) = &self.metrics {
+                            metrics::record_sent_message(metrics.as_ref(), *protocol_id);
+                        }
+
+                        let message = match protocol.spec.batching {
+                            Some(batching) => {
+                                match protocol
+                                    .batch
+                                    .push(message, batching, protocol.spec.max_message_size)
+                                {
+                                    Some(batch) => batch,
+                                    None => continue,
+                                }
+                            }
+                            None => message,
+                        };
 
+                        if let Some(metrics) = &self.metrics {
+                            metrics::record_sent_frame(
+                                metrics.as_ref(),
+                                *protocol_id,
+                                message.as_ref().len(),
+                            );
+                        }
                         let _ = substream_out.start_send_unpin(message);
+                        protocol.last_sent_at = Instant::now();
                         // Note that flushing is performed later down this function.
                     }
+
+                    // Send the batch once the oldest message in it waited for long enough.
+                    if protocol.batch.poll_due(cx).is_ready() && substream_out.poll_ready_unpin(cx).is_ready()
+                    {
+                        let batch = protocol.batch.take();
+                        if let Some(metrics) = &self.metrics {
+                            metrics::record_sent_frame(metrics.as_ref(), *protocol_id, batch.as_ref().len());
+                        }
+                        let _ = substream_out.start_send_unpin(batch);
+                        protocol.last_sent_at = Instant::now();
+                    }
                 }
             }
 
@@ -710,13 +1033,25 @@ impl ConnectionHandler for PeerConnHandler {
                         | ProtocolState::OutboundClosedByPeer { substream_in } => {
                             match futures::Stream::poll_next(Pin::new(substream_in), cx) {
                                 Poll::Pending => {}
-                                Poll::Ready(Some(Ok(msg))) => {
-                                    let event = ConnHandlerOut::Message {
-                                        protocol_tag: ProtocolTag::new(*protocol_id, protocol.ver),
-                                        content: msg,
-                                    };
-                                    return Poll::Ready(ConnectionHandlerEvent::NotifyBehaviour(event));
-                                }
+                                Poll::Ready(Some(Ok(frame))) => match protocol.unpack(frame) {
+                                    Ok(messages) => {
+                                        let protocol_tag = ProtocolTag::new(*protocol_id, protocol.ver);
+                                        for content in messages {
+                                            self.pending_events.push_back(
+                                                ConnectionHandlerEvent::NotifyBehaviour(
+                                                    ConnHandlerOut::Message {
+                                                        protocol_tag,
+                                                        content,
+                                                    },
+                                                ),
+                                            );
+                                        }
+                                        if let Some(event) = self.pending_events.pop_front() {
+                                            return Poll::Ready(event);
+                                        }
+                                    }
+                                    Err(err) => error!("[PCH] {} from {:?}", err, protocol_id),
+                                },
                                 Poll::Ready(None) | Poll::Ready(Some(Err(_))) => {
                                     if let Some(ProtocolState::Opened {
                                         substream_out,
@@ -759,11 +1094,49 @@ impl ConnectionHandler for PeerConnHandler {
                                 protocol.state = Some(ProtocolState::Accepting { substream_in: None })
                             }
                         },
+                        ProtocolState::Idle {
+                            substream_in: Some(substream_in),
+                            ..
+                        }
+                        | ProtocolState::Reopening {
+                            substream_in: Some(substream_in),
+                            ..
+                        } => match futures::Stream::poll_next(Pin::new(substream_in), cx) {
+                            Poll::Pending => {}
+                            Poll::Ready(Some(Ok(frame))) => match protocol.unpack(frame) {
+                                Ok(messages) => {
+                                    let protocol_tag = ProtocolTag::new(*protocol_id, protocol.ver);
+                                    for content in messages {
+                                        self.pending_events.push_back(
+                                            ConnectionHandlerEvent::NotifyBehaviour(
+                                                ConnHandlerOut::Message {
+                                                    protocol_tag,
+                                                    content,
+                                                },
+                                            ),
+                                        );
+                                    }
+                                    if let Some(event) = self.pending_events.pop_front() {
+                                        return Poll::Ready(event);
+                                    }
+                                }
+                                Err(err) => error!("[PCH] {} from {:?}", err, protocol_id),
+                            },
+                            Poll::Ready(None) | Poll::Ready(Some(Err(_))) => {
+                                if let ProtocolState::Idle { substream_in, .. }
+                                | ProtocolState::Reopening { substream_in, .. } = state
+                                {
+                                    *substream_in = None;
+                                }
+                            }
+                        },
                         ProtocolState::Closed
                         | ProtocolState::Opening
                         | ProtocolState::PartiallyOpened { .. }
                         | ProtocolState::InboundClosedByPeer { .. }
-                        | ProtocolState::Accepting { .. } => {}
+                        | ProtocolState::Accepting { .. }
+                        | ProtocolState::Idle { .. }
+                        | ProtocolState::Reopening { .. } => {}
                     }
                 }
             }
@@ -773,6 +1146,13 @@ impl ConnectionHandler for PeerConnHandler {
     }
 }
 
+/// Info attached to outbound substream requests.
+#[derive(Debug, Copy, Clone)]
+pub enum OutboundOpenInfo {
+    Stateful(ProtocolTag),
+    OneShot(ProtocolTag, OneShotRequestId),
+}
+
 #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Copy, Clone)]
 pub struct OneShotRequestId(u64);
 
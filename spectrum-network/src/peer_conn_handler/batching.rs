@@ -0,0 +1,150 @@
+//! Batching of messages sent over protocol substreams.
+//!
+//! Once a protocol version with [`BatchingSpec`] is negotiated every frame on the substream is a
+//! batch envelope: a sequence of messages each prefixed with its length encoded as unsigned varint.
+
+use std::task::{Context, Poll};
+
+use futures::FutureExt;
+
+use crate::protocol::BatchingSpec;
+use crate::types::RawMessage;
+
+/// Outbound messages waiting to be sent in one frame.
+#[derive(Debug, Default)]
+pub struct OutboundBatch {
+    envelope: Vec<u8>,
+    /// Fires once the oldest message in the batch waited for long enough.
+    /// `None` if the batch is empty.
+    deadline: Option<wasm_timer::Delay>,
+}
+
+impl OutboundBatch {
+    /// Put the message into the batch.
+    /// Returns the batch which has to be sent right away if the size limit is reached.
+    pub fn push(
+        &mut self,
+        msg: RawMessage,
+        spec: BatchingSpec,
+        max_message_size: usize,
+    ) -> Option<RawMessage> {
+        let limit = spec.max_batch_size.min(max_message_size);
+        let msg = Vec::from(msg);
+        // The message doesn't fit, so the batch is sent without it.
+        let overflow = if !self.is_empty() && self.envelope.len() + entry_len(msg.len()) > limit {
+            Some(self.take())
+        } else {
+            None
+        };
+        if self.is_empty() {
+            self.deadline = Some(wasm_timer::Delay::new(spec.max_delay));
+        }
+        let mut len_buf = unsigned_varint::encode::usize_buffer();
+        self.envelope
+            .extend_from_slice(unsigned_varint::encode::usize(msg.len(), &mut len_buf));
+        self.envelope.extend(msg);
+        overflow.or_else(|| (self.envelope.len() >= limit).then(|| self.take()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.envelope.is_empty()
+    }
+
+    /// Returns `Ready` once the batch has to be sent.
+    pub fn poll_due(&mut self, cx: &mut Context) -> Poll<()> {
+        match &mut self.deadline {
+            Some(deadline) => deadline.poll_unpin(cx).map(|_| ()),
+            None => Poll::Pending,
+        }
+    }
+
+    /// Take all batched messages in one envelope.
+    pub fn take(&mut self) -> RawMessage {
+        self.deadline = None;
+        RawMessage::from(std::mem::take(&mut self.envelope))
+    }
+}
+
+fn entry_len(msg_len: usize) -> usize {
+    unsigned_varint::encode::usize(msg_len, &mut unsigned_varint::encode::usize_buffer()).len() + msg_len
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Malformed batch envelope")]
+pub struct MalformedBatch;
+
+/// Extract messages from the given envelope.
+pub fn unpack(envelope: RawMessage) -> Result<Vec<RawMessage>, MalformedBatch> {
+    let envelope = Vec::from(envelope);
+    let mut rem = envelope.as_slice();
+    let mut messages = Vec::new();
+    while !rem.is_empty() {
+        let (len, tail) = unsigned_varint::decode::usize(rem).map_err(|_| MalformedBatch)?;
+        if tail.len() < len {
+            return Err(MalformedBatch);
+        }
+        messages.push(RawMessage::from(tail[..len].to_vec()));
+        rem = &tail[len..];
+    }
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::peer_conn_handler::batching::{unpack, MalformedBatch, OutboundBatch};
+    use crate::protocol::BatchingSpec;
+    use crate::types::RawMessage;
+
+    const SPEC: BatchingSpec = BatchingSpec {
+        max_batch_size: 10,
+        max_delay: Duration::from_millis(5),
+    };
+
+    fn msg(len: usize) -> RawMessage {
+        RawMessage::from(vec![len as u8; len])
+    }
+
+    fn single(msg: RawMessage) -> RawMessage {
+        let mut batch = OutboundBatch::default();
+        assert_eq!(batch.push(msg, SPEC, usize::MAX), None);
+        batch.take()
+    }
+
+    #[test]
+    fn messages_are_coalesced_up_to_batch_size() {
+        let mut batch = OutboundBatch::default();
+        assert_eq!(batch.push(msg(3), SPEC, 100), None);
+        assert_eq!(batch.push(msg(4), SPEC, 100), None);
+        // 4 + 5 + 3 bytes exceed the limit, so the message is left for the next batch.
+        let sent = batch.push(msg(2), SPEC, 100).unwrap();
+        assert_eq!(unpack(sent), Ok(vec![msg(3), msg(4)]));
+        // The batch is sent as soon as it is full.
+        let sent = batch.push(msg(6), SPEC, 100).unwrap();
+        assert_eq!(unpack(sent), Ok(vec![msg(2), msg(6)]));
+        assert!(batch.is_empty());
+    }
+
+    #[test]
+    fn batch_never_exceeds_max_message_size() {
+        let mut batch = OutboundBatch::default();
+        assert_eq!(batch.push(msg(2), SPEC, 5), None);
+        let sent = batch.push(msg(2), SPEC, 5).unwrap();
+        assert_eq!(unpack(sent), Ok(vec![msg(2)]));
+        assert_eq!(unpack(batch.take()), Ok(vec![msg(2)]));
+    }
+
+    #[test]
+    fn single_message_envelope() {
+        assert_eq!(unpack(single(msg(8))), Ok(vec![msg(8)]));
+        assert_eq!(unpack(single(msg(0))), Ok(vec![msg(0)]));
+    }
+
+    #[test]
+    fn truncated_envelope_is_rejected() {
+        let mut envelope = Vec::from(single(msg(5)));
+        envelope.pop();
+        assert_eq!(unpack(RawMessage::from(envelope)), Err(MalformedBatch));
+    }
+}
@@ -5,7 +5,18 @@ use futures::{
     prelude::*,
 };
 use libp2p::PeerId;
+use std::pin::Pin;
 use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+/// Lane an outbound message is queued in. Messages of the control lane are sent ahead of
+/// bulk ones, so that small protocol-control messages aren't stuck behind large payloads.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum MessagePriority {
+    Control,
+    #[default]
+    Bulk,
+}
 
 /// Sink connected directly to the node background task. Allows sending messages to the peer.
 /// Can be cloned in order to obtain multiple references to the substream of the same peer.
@@ -17,12 +28,14 @@ pub struct MessageSink {
 impl MessageSink {
     pub fn new(
         peer_id: PeerId,
+        control_channel: mpsc::Sender<StreamNotification>,
         async_channel: mpsc::Sender<StreamNotification>,
         sync_channel: mpsc::Sender<StreamNotification>,
     ) -> Self {
         Self {
             inner: Arc::new(MessageSinkIn {
                 peer_id,
+                control_channel: Mutex::new(Some(control_channel)),
                 async_channel: AsyncMutex::new(async_channel),
                 sync_channel: Mutex::new(Some(sync_channel)),
             }),
@@ -30,6 +43,52 @@ impl MessageSink {
     }
 }
 
+/// Sizes of buffers of each lane.
+#[derive(Debug, Copy, Clone)]
+pub struct LaneBufferSizes {
+    pub control: usize,
+    pub bulk_async: usize,
+    pub bulk_sync: usize,
+}
+
+/// Create a sink along with the lanes it feeds.
+pub fn channel(peer_id: PeerId, buffer_sizes: LaneBufferSizes) -> (MessageSink, OutboundLanes) {
+    let (control_snd, control_recv) = mpsc::channel::<StreamNotification>(buffer_sizes.control);
+    let (async_snd, async_recv) = mpsc::channel::<StreamNotification>(buffer_sizes.bulk_async);
+    let (sync_snd, sync_recv) = mpsc::channel::<StreamNotification>(buffer_sizes.bulk_sync);
+    let lanes = OutboundLanes {
+        control: control_recv.fuse(),
+        bulk: stream::select(async_recv.fuse(), sync_recv.fuse()),
+    };
+    (MessageSink::new(peer_id, control_snd, async_snd, sync_snd), lanes)
+}
+
+/// Receiving side of a [`MessageSink`]. Yields messages of the control lane first.
+#[derive(Debug)]
+pub struct OutboundLanes {
+    control: stream::Fuse<mpsc::Receiver<StreamNotification>>,
+    bulk: stream::Select<
+        stream::Fuse<mpsc::Receiver<StreamNotification>>,
+        stream::Fuse<mpsc::Receiver<StreamNotification>>,
+    >,
+}
+
+impl Stream for OutboundLanes {
+    type Item = StreamNotification;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.control.poll_next_unpin(cx) {
+            Poll::Ready(Some(notification)) => Poll::Ready(Some(notification)),
+            // Lanes are exhausted once both of them are.
+            Poll::Ready(None) => self.bulk.poll_next_unpin(cx),
+            Poll::Pending => match self.bulk.poll_next_unpin(cx) {
+                Poll::Ready(None) => Poll::Pending,
+                bulk => bulk,
+            },
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum StreamNotification {
     Message(RawMessage),
@@ -40,6 +99,8 @@ pub enum StreamNotification {
 struct MessageSinkIn {
     /// Target of the sink.
     peer_id: PeerId,
+    /// Sender of control messages. Uses an synchronous mutex.
+    control_channel: Mutex<Option<mpsc::Sender<StreamNotification>>>,
     /// Sender to use in asynchronous contexts. Uses an asynchronous mutex.
     async_channel: AsyncMutex<mpsc::Sender<StreamNotification>>,
     /// Sender to use in synchronous contexts. Uses an synchronous mutex.
@@ -52,12 +113,24 @@ impl MessageSink {
         &self.inner.peer_id
     }
 
-    /// Sends a message to the peer.
+    /// Sends a message to the peer via the bulk lane.
     ///
     /// If the buffer is exhausted, the channel will be closed
     /// via `SyncNotification::ForceClose` directive.
     pub fn send_message(&self, msg: RawMessage) -> Result<(), ()> {
-        let lock = self.inner.sync_channel.lock();
+        self.send_message_with_priority(msg, MessagePriority::Bulk)
+    }
+
+    /// Sends a message to the peer via the lane of the given priority.
+    ///
+    /// If the buffer of the lane is exhausted, the channel will be closed
+    /// via `SyncNotification::ForceClose` directive.
+    pub fn send_message_with_priority(&self, msg: RawMessage, priority: MessagePriority) -> Result<(), ()> {
+        let channel = match priority {
+            MessagePriority::Control => &self.inner.control_channel,
+            MessagePriority::Bulk => &self.inner.sync_channel,
+        };
+        let lock = channel.lock();
         if let Ok(mut permit) = lock {
             if let Some(snd) = permit.as_mut() {
                 if snd.try_send(StreamNotification::Message(msg)).is_err() {
@@ -112,3 +185,36 @@ impl<'a> Ready<'a> {
             .map_err(|_| ())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use libp2p::PeerId;
+
+    use crate::peer_conn_handler::message_sink::{
+        channel, LaneBufferSizes, MessagePriority, StreamNotification,
+    };
+    use crate::types::RawMessage;
+
+    #[async_std::test]
+    async fn control_messages_overtake_bulk_ones() {
+        let (sink, mut lanes) = channel(
+            PeerId::random(),
+            LaneBufferSizes {
+                control: 2,
+                bulk_async: 2,
+                bulk_sync: 2,
+            },
+        );
+        sink.send_message(RawMessage::from(vec![1])).unwrap();
+        sink.send_message(RawMessage::from(vec![2])).unwrap();
+        sink.send_message_with_priority(RawMessage::from(vec![3]), MessagePriority::Control)
+            .unwrap();
+        drop(sink);
+        let mut sent = vec![];
+        while let Some(StreamNotification::Message(msg)) = lanes.next().await {
+            sent.push(Vec::from(msg)[0]);
+        }
+        assert_eq!(sent, vec![3, 1, 2]);
+    }
+}
@@ -1,7 +1,9 @@
-use std::collections::{HashSet, VecDeque};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::future::Future;
 use std::ops::Add;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 use std::time::{Duration, Instant};
 
@@ -13,17 +15,27 @@ use libp2p::PeerId;
 use log::{error, info, trace};
 use wasm_timer::Delay;
 
+use crate::metrics::{self, MetricsSink};
 use crate::peer_conn_handler::ConnHandlerError;
+use crate::peer_manager::ban_list::BanList;
 use crate::peer_manager::data::{
-    ConnectionLossReason, ConnectionState, PeerDestination, PeerInfo, ProtocolAllocationPolicy,
-    ReputationChange,
+    ConnectionLossReason, ConnectionState, DialRetryReason, KnownPeer, PeerDestination, PeerInfo,
+    ProtocolAllocationPolicy, ReputationChange, ReputationDecayPolicy, RetryPolicy,
 };
 use crate::peer_manager::peers_state::{NetworkingState, PeerInState, PeerStateFilter, PeersState};
+use crate::peer_manager::reputation_decay::ReputationDecay;
+use crate::peer_manager::routing_table::{RoutingTable, K_BUCKET_SIZE};
+use crate::protocol::ProtocolConfig;
+use crate::transport::TransportConfig;
 use crate::types::{ProtocolId, Reputation};
 
+pub mod ban_list;
 pub mod data;
 pub mod peer_index;
 pub mod peers_state;
+pub mod persistent_peers_state;
+pub mod reputation_decay;
+pub mod routing_table;
 
 /// Peer Manager output commands.
 #[derive(Debug, PartialEq, Eq)]
@@ -43,6 +55,8 @@ pub enum PeerManagerOut {
         peer_id: PeerId,
         reason: ReputationChange,
     },
+    /// Outbound connection attempts to the peer are exhausted, the peer is forgotten.
+    GaveUp(PeerId),
 }
 
 /// Peer Manager inputs.
@@ -51,6 +65,15 @@ pub enum PeerManagerRequest {
     AddPeers(Vec<PeerDestination>),
     AddReservedPeer(PeerDestination),
     SetReservedPeers(HashSet<PeerId>),
+    /// Disconnect the given peer and forget about it.
+    RemovePeer(PeerId),
+    /// Disconnect the given peer and refuse any connections with it for `duration`,
+    /// or permanently if `None`.
+    BanPeer {
+        peer_id: PeerId,
+        duration: Option<Duration>,
+    },
+    GetAddressBook(Sender<Vec<KnownPeer>>),
     ReportPeer(PeerId, ReputationChange),
     GetPeerReputation(PeerId, Sender<Reputation>),
     GetPeers {
@@ -59,6 +82,12 @@ pub enum PeerManagerRequest {
     },
     /// Update set of protocols that the given peer supports.
     SetProtocols(PeerId, Vec<ProtocolId>),
+    /// Find known peers closest to the `target` in the key space of the routing table.
+    FindClosestPeers {
+        target: PeerId,
+        limit: usize,
+        snd: Sender<Vec<PeerDestination>>,
+    },
 }
 
 /// Events Peer Manager reacts to.
@@ -87,12 +116,20 @@ pub trait Peers {
     fn add_reserved_peer(&mut self, peer_id: PeerDestination);
     /// Update set of reserved peers.
     fn set_reserved_peers(&mut self, peers: HashSet<PeerId>);
+    /// Disconnect the given peer and remove it from the set of known peers.
+    fn remove_peer(&mut self, peer_id: PeerId);
+    /// Ban the given peer for `duration`, or permanently if `None`.
+    fn ban_peer(&mut self, peer_id: PeerId, duration: Option<Duration>);
+    /// Get all peers known to PM.
+    fn get_address_book(&mut self) -> Receiver<Vec<KnownPeer>>;
     /// Report peer behaviour.
     fn report_peer(&mut self, peer_id: PeerId, change: ReputationChange);
     /// Get reputation of the given peer.
     fn get_peer_reputation(&mut self, peer_id: PeerId) -> Receiver<Reputation>;
     /// Update the set of peer protocols.
     fn set_peer_protocols(&mut self, peer_id: PeerId, protocols: Vec<ProtocolId>);
+    /// Find known peers closest to the `target`, nearest first.
+    fn find_closest_peers(&mut self, target: PeerId, limit: usize) -> Receiver<Vec<PeerDestination>>;
 }
 
 /// Async API to PeerManager notifications.
@@ -109,9 +146,13 @@ pub trait PeerManagerRequestsBehavior {
     fn on_get_peers(&mut self, limit: usize, response: Sender<Vec<PeerDestination>>);
     fn on_add_reserved_peer(&mut self, peer_id: PeerDestination);
     fn on_set_reserved_peers(&mut self, peers: HashSet<PeerId>);
+    fn on_remove_peer(&mut self, peer_id: PeerId);
+    fn on_ban_peer(&mut self, peer_id: PeerId, duration: Option<Duration>);
+    fn on_get_address_book(&mut self, response: Sender<Vec<KnownPeer>>);
     fn on_report_peer(&mut self, peer_id: PeerId, change: ReputationChange);
     fn on_get_peer_reputation(&mut self, peer_id: PeerId, response: Sender<Reputation>);
     fn on_set_peer_protocols(&mut self, peer_id: PeerId, protocols: Vec<ProtocolId>);
+    fn on_find_closest_peers(&mut self, target: PeerId, limit: usize, response: Sender<Vec<PeerDestination>>);
 }
 
 pub trait PeerManagerNotificationsBehavior {
@@ -127,6 +168,17 @@ pub struct PeersMailbox {
     mailbox_snd: mpsc::Sender<PeerManagerIn>,
 }
 
+impl PeersMailbox {
+    /// Submit the request to the peer manager, awaiting room in the mailbox instead of blocking
+    /// the thread like [`Peers`] does. Fails if the peer manager is gone.
+    pub async fn request(&self, request: PeerManagerRequest) -> Result<(), mpsc::SendError> {
+        self.mailbox_snd
+            .clone()
+            .send(PeerManagerIn::Request(request))
+            .await
+    }
+}
+
 impl Peers for PeersMailbox {
     fn add_peers(&mut self, peers: Vec<PeerDestination>) {
         let _ = futures::executor::block_on(
@@ -156,6 +208,30 @@ impl Peers for PeersMailbox {
         )));
     }
 
+    fn remove_peer(&mut self, peer_id: PeerId) {
+        let _ = futures::executor::block_on(
+            self.mailbox_snd
+                .clone()
+                .send(PeerManagerIn::Request(PeerManagerRequest::RemovePeer(peer_id))),
+        );
+    }
+
+    fn ban_peer(&mut self, peer_id: PeerId, duration: Option<Duration>) {
+        let _ = futures::executor::block_on(self.mailbox_snd.clone().send(PeerManagerIn::Request(
+            PeerManagerRequest::BanPeer { peer_id, duration },
+        )));
+    }
+
+    fn get_address_book(&mut self) -> Receiver<Vec<KnownPeer>> {
+        let (sender, receiver) = oneshot::channel::<Vec<KnownPeer>>();
+        let _ = futures::executor::block_on(
+            self.mailbox_snd
+                .clone()
+                .send(PeerManagerIn::Request(PeerManagerRequest::GetAddressBook(sender))),
+        );
+        receiver
+    }
+
     fn report_peer(&mut self, peer_id: PeerId, change: ReputationChange) {
         let _ = futures::executor::block_on(self.mailbox_snd.clone().send(PeerManagerIn::Request(
             PeerManagerRequest::ReportPeer(peer_id, change),
@@ -175,6 +251,18 @@ impl Peers for PeersMailbox {
             PeerManagerRequest::SetProtocols(peer_id, protocols),
         )));
     }
+
+    fn find_closest_peers(&mut self, target: PeerId, limit: usize) -> Receiver<Vec<PeerDestination>> {
+        let (sender, receiver) = oneshot::channel::<Vec<PeerDestination>>();
+        let _ = futures::executor::block_on(self.mailbox_snd.clone().send(PeerManagerIn::Request(
+            PeerManagerRequest::FindClosestPeers {
+                target,
+                limit,
+                snd: sender,
+            },
+        )));
+        receiver
+    }
 }
 
 impl PeerEvents for PeersMailbox {
@@ -211,7 +299,7 @@ impl PeerEvents for PeersMailbox {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct NetworkingConfig {
     /// Minimal number of known peers.
     /// If not satisfied, node will have to use bootstrapping peers.
@@ -222,6 +310,40 @@ pub struct NetworkingConfig {
     pub max_inbound: usize,
     /// Maximal number of outbound connections the node can establish.
     pub max_outbound: usize,
+    /// How often the state of known peers is persisted, see [`PersistentPeerRepo`].
+    ///
+    /// [`PersistentPeerRepo`]: crate::peer_manager::persistent_peers_state::PersistentPeerRepo
+    pub peers_snapshot_interval: Duration,
+    /// Where the state of known peers is kept.
+    pub peers_storage: PeerStorage,
+    /// Local address binding and per-peer routing hints of the transport.
+    pub transport: TransportConfig,
+}
+
+/// Storage of known peers, see [`AnyPeerRepo`].
+///
+/// [`AnyPeerRepo`]: crate::peer_manager::persistent_peers_state::AnyPeerRepo
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum PeerStorage {
+    /// Peers are forgotten once the node is stopped.
+    #[default]
+    Memory,
+    /// Peers are persisted in RocksDB and recovered on start.
+    Persistent,
+}
+
+impl Default for NetworkingConfig {
+    fn default() -> Self {
+        Self {
+            min_known_peers: 1,
+            min_outbound: 1,
+            max_inbound: 10,
+            max_outbound: 20,
+            peers_snapshot_interval: Duration::from_secs(60),
+            peers_storage: PeerStorage::Memory,
+            transport: TransportConfig::default(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -231,13 +353,33 @@ pub struct PeerManagerConfig {
     pub min_acceptable_reputation: Reputation,
     /// Represents the minimum reputation a peer must have to accept its incoming connection.
     pub min_reputation: Reputation,
-    pub conn_reset_outbound_backoff: Duration,
+    /// Backoff of outbound connection attempts after dial failures and lost connections.
+    pub dial_retry_policy: RetryPolicy,
+    /// Recovery of reputations of peers over time.
+    pub reputation_decay: ReputationDecayPolicy,
     pub conn_alloc_interval: Duration,
     pub prot_alloc_interval: Duration,
+    /// Protocols are allocated in the order of their priorities,
+    /// see [`PeerManager::with_protocol_configs`].
     pub protocols_allocation: Vec<(ProtocolId, ProtocolAllocationPolicy)>,
     pub peer_manager_msg_buffer_size: usize,
 }
 
+impl Default for PeerManagerConfig {
+    fn default() -> Self {
+        Self {
+            min_acceptable_reputation: Reputation::from(0),
+            min_reputation: Reputation::from(0),
+            dial_retry_policy: RetryPolicy::default(),
+            reputation_decay: ReputationDecayPolicy::default(),
+            conn_alloc_interval: Duration::from_secs(30),
+            prot_alloc_interval: Duration::from_secs(30),
+            protocols_allocation: Vec::new(),
+            peer_manager_msg_buffer_size: 10,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum ConnAllocationMode {
     Active,
@@ -254,12 +396,21 @@ pub struct PeerManager<TState> {
     out_queue: VecDeque<PeerManagerOut>,
     next_conn_alloc: Delay,
     next_prot_alloc: Delay,
+    next_reputation_decay: Delay,
+    last_reputation_decay: Instant,
+    reputation_decay: ReputationDecay,
     boot_in_progress: bool,
+    /// Kademlia routing table, only maintained if enabled with [`PeerManager::with_routing_table`].
+    routing_table: Option<RoutingTable>,
+    banned: BanList,
+    /// Optional sink of metrics.
+    metrics: Option<Arc<dyn MetricsSink>>,
 }
 
 impl<S: PeersState> PeerManager<S> {
     pub fn new(state: S, conf: PeerManagerConfig) -> (Self, PeersMailbox) {
         let (snd, recv) = mpsc::channel::<PeerManagerIn>(conf.peer_manager_msg_buffer_size);
+        let decay_interval = conf.reputation_decay.interval;
         let pm = Self {
             state,
             conf,
@@ -267,12 +418,91 @@ impl<S: PeersState> PeerManager<S> {
             out_queue: VecDeque::new(),
             next_conn_alloc: Delay::new(Duration::new(0, 0)),
             next_prot_alloc: Delay::new(Duration::new(0, 0)),
+            next_reputation_decay: Delay::new(decay_interval),
+            last_reputation_decay: Instant::now(),
+            reputation_decay: ReputationDecay::default(),
             boot_in_progress: false,
+            routing_table: None,
+            banned: BanList::default(),
+            metrics: None,
         };
         let peers = PeersMailbox { mailbox_snd: snd };
         (pm, peers)
     }
 
+    /// Report metrics of the peer manager to the given sink.
+    pub fn with_metrics(mut self, metrics: Arc<dyn MetricsSink>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Allocate protocols in the order of priorities declared in their configs,
+    /// so that protocols with higher priority are started first.
+    pub fn with_protocol_configs<'a>(
+        mut self,
+        protocols: impl IntoIterator<Item = (&'a ProtocolId, &'a ProtocolConfig)>,
+    ) -> Self {
+        let priorities = protocols
+            .into_iter()
+            .map(|(prot, conf)| (*prot, conf.priority()))
+            .collect::<HashMap<_, _>>();
+        self.conf
+            .protocols_allocation
+            .sort_by_key(|(prot, _)| Reverse(priorities.get(prot).copied().unwrap_or_default()));
+        self
+    }
+
+    /// Maintain a routing table of known peers so that closest peers to arbitrary targets
+    /// can be looked up, see [`Peers::find_closest_peers`].
+    pub fn with_routing_table(mut self, local_peer_id: PeerId) -> Self {
+        let mut routing_table = RoutingTable::new(&local_peer_id, K_BUCKET_SIZE);
+        for peer in self.state.get_peers(usize::MAX) {
+            routing_table.insert(peer);
+        }
+        self.routing_table = Some(routing_table);
+        self
+    }
+
+    fn routing_table_insert(&mut self, peer: PeerDestination) {
+        if let Some(routing_table) = &mut self.routing_table {
+            routing_table.insert(peer);
+        }
+    }
+
+    /// Schedule the next outbound connection attempt to the given peer according to
+    /// [PeerManagerConfig::dial_retry_policy]. Non-reserved peers are forgotten once
+    /// attempts are exhausted, reserved ones are retried with the maximum backoff.
+    fn schedule_retry(&mut self, peer_id: PeerId, reason: DialRetryReason) {
+        if let Some(PeerInState::NotConnected(mut ncp)) = self.state.peer(&peer_id) {
+            let attempt = ncp.register_failure(reason);
+            let policy = &self.conf.dial_retry_policy;
+            let backoff = match policy.backoff(attempt, reason) {
+                Some(backoff) => backoff,
+                None if ncp.is_reserved() => policy.schedule(reason).max_backoff,
+                None => {
+                    trace!("Giving up on peer {} after {} attempts", peer_id, attempt - 1);
+                    ncp.forget();
+                    self.routing_table_remove(&peer_id);
+                    self.out_queue.push_back(PeerManagerOut::GaveUp(peer_id));
+                    return;
+                }
+            };
+            trace!(
+                "Backing off peer {} for {:?} after {:?}",
+                peer_id,
+                backoff,
+                reason
+            );
+            ncp.set_backoff_until(Instant::now().add(policy.jittered(backoff)));
+        }
+    }
+
+    fn routing_table_remove(&mut self, peer_id: &PeerId) {
+        if let Some(routing_table) = &mut self.routing_table {
+            routing_table.remove(peer_id);
+        }
+    }
+
     /// Connect to reserved peers we are not connected yet.
     pub fn connect_reserved(&mut self) {
         let peers = self.state.get_reserved_peers(Some(PeerStateFilter::NotConnected));
@@ -284,8 +514,10 @@ impl<S: PeersState> PeerManager<S> {
     /// Connect to the best peer we are not connected yet.
     pub fn connect_best(&mut self) {
         trace!("Going to connect best known peer");
-        if let Some(pid) = self.state.pick_best(Some(|_: &PeerId, pi: &PeerInfo| {
-            matches!(pi.state, ConnectionState::NotConnected)
+        let banned = &self.banned;
+        let now = Instant::now();
+        if let Some(pid) = self.state.pick_best(Some(|pid: &PeerId, pi: &PeerInfo| {
+            matches!(pi.state, ConnectionState::NotConnected) && !banned.is_banned(pid, now)
         })) {
             trace!("Going to connect peer {}", pid);
             self.connect(&pid)
@@ -295,6 +527,10 @@ impl<S: PeersState> PeerManager<S> {
     /// Connect to a known peer.
     fn connect(&mut self, peer_id: &PeerId) {
         trace!("Connect(peer_id={})", peer_id);
+        if self.banned.is_banned(peer_id, Instant::now()) {
+            trace!("Peer {} is banned", peer_id);
+            return;
+        }
         if let Some(PeerInState::NotConnected(ncp)) = self.state.peer(peer_id) {
             if ncp
                 .backoff_until()
@@ -315,6 +551,7 @@ impl<S: PeersState> PeerManager<S> {
             trace!("Peer {} disconnected", peer_id);
             if forget {
                 ncp.forget();
+                self.routing_table_remove(&peer_id);
                 trace!("Peer {} forgotten", peer_id);
             }
             self.out_queue.push_back(PeerManagerOut::Drop(peer_id));
@@ -329,7 +566,8 @@ impl<S: PeersState> PeerManager<S> {
             NetworkingState::NotBootstrapped(boot_peers) => {
                 let mut added = 0;
                 for p in boot_peers.into_iter() {
-                    if self.state.try_add_peer(p, false, true).is_some() {
+                    if self.state.try_add_peer(p.clone(), false, true).is_some() {
+                        self.routing_table_insert(p);
                         added += 1;
                     }
                 }
@@ -357,7 +595,25 @@ impl<S: PeersState> PeerManager<S> {
         }
     }
 
+    /// Move reputations of peers toward neutral as their past reputation changes fade away.
+    fn decay_reputations(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_reputation_decay);
+        self.last_reputation_decay = now;
+        for (peer_id, adjustment) in self.reputation_decay.decay(&self.conf.reputation_decay, elapsed) {
+            match self.state.peer(&peer_id) {
+                Some(peer) => {
+                    let reputation = peer.get_reputation().shifted(adjustment);
+                    trace!("Reputation of peer {} recovered to {:?}", peer_id, reputation);
+                    peer.set_reputation(reputation);
+                }
+                None => self.reputation_decay.forget(&peer_id),
+            }
+        }
+    }
+
     /// Allocate protocol substreams according to configured policies.
+    /// `StartProtocol` is issued in the order of protocol priority.
     fn allocate_protocols(&mut self) {
         for (prot, policy) in self.conf.protocols_allocation.clone().iter() {
             if let Some(enabled_peers) = self.state.get_enabled_peers(prot) {
@@ -386,9 +642,14 @@ impl<S: PeersState> PeerManager<S> {
 
 impl<S: PeersState> PeerManagerRequestsBehavior for PeerManager<S> {
     fn on_add_peers(&mut self, peers: Vec<PeerDestination>) {
+        let now = Instant::now();
         for p in peers {
             let pid = p.peer_id();
-            if self.state.try_add_peer(p, false, false).is_some() {
+            if self.banned.is_banned(&pid, now) {
+                continue;
+            }
+            if self.state.try_add_peer(p.clone(), false, false).is_some() {
+                self.routing_table_insert(p);
                 info!("New peer {:?} added", pid);
             }
         }
@@ -402,7 +663,9 @@ impl<S: PeersState> PeerManagerRequestsBehavior for PeerManager<S> {
     }
 
     fn on_add_reserved_peer(&mut self, peer_id: PeerDestination) {
-        self.state.try_add_peer(peer_id, true, false);
+        if self.state.try_add_peer(peer_id.clone(), true, false).is_some() {
+            self.routing_table_insert(peer_id);
+        }
     }
 
     fn on_set_reserved_peers(&mut self, peers: HashSet<PeerId>) {
@@ -412,8 +675,47 @@ impl<S: PeersState> PeerManagerRequestsBehavior for PeerManager<S> {
         }
     }
 
+    fn on_remove_peer(&mut self, peer_id: PeerId) {
+        if let Some(mut peer) = self.state.peer(&peer_id) {
+            peer.set_reserved(false);
+        }
+        match self.state.peer(&peer_id) {
+            Some(PeerInState::Connected(_)) => self.disconnect(peer_id, true),
+            Some(PeerInState::NotConnected(ncp)) => {
+                ncp.forget();
+                self.routing_table_remove(&peer_id);
+            }
+            None => {}
+        }
+        info!("Peer {:?} removed", peer_id);
+    }
+
+    fn on_ban_peer(&mut self, peer_id: PeerId, duration: Option<Duration>) {
+        self.banned.ban(peer_id, duration, Instant::now());
+        // Connections are torn down by the NetworkController, so `Drop` isn't emitted here.
+        let ncp = match self.state.peer(&peer_id) {
+            Some(PeerInState::Connected(cp)) => Some(cp.disconnect()),
+            Some(PeerInState::NotConnected(ncp)) => Some(ncp),
+            None => None,
+        };
+        // Permanently banned peers are of no use to keep.
+        if let (Some(ncp), None) = (ncp, duration) {
+            ncp.forget();
+        }
+        // Banned peers shouldn't be suggested to others.
+        self.routing_table_remove(&peer_id);
+        info!("Peer {:?} banned for {:?}", peer_id, duration);
+    }
+
+    fn on_get_address_book(&mut self, response: Sender<Vec<KnownPeer>>) {
+        let _ = response.send(self.state.get_address_book());
+    }
+
     fn on_report_peer(&mut self, peer_id: PeerId, adjustment: ReputationChange) {
         if let Some(peer) = self.state.peer(&peer_id) {
+            if let Some(metrics) = &self.metrics {
+                metrics::record_reputation_change(metrics.as_ref(), adjustment);
+            }
             if adjustment.is_downgrade() {
                 self.out_queue.push_back(PeerManagerOut::NotifyPeerPunished {
                     peer_id,
@@ -421,6 +723,8 @@ impl<S: PeersState> PeerManagerRequestsBehavior for PeerManager<S> {
                 });
             }
 
+            self.reputation_decay.on_change(peer_id, adjustment);
+
             // A peer with reputation below self.conf.min_acceptable_reputation is classed as
             // unacceptable, and its connection will be dropped.
             let is_acceptable = peer
@@ -445,11 +749,30 @@ impl<S: PeersState> PeerManagerRequestsBehavior for PeerManager<S> {
             peer.set_protocols(protocols);
         }
     }
+
+    fn on_find_closest_peers(
+        &mut self,
+        target: PeerId,
+        limit: usize,
+        response: Sender<Vec<PeerDestination>>,
+    ) {
+        let peers = self
+            .routing_table
+            .as_ref()
+            .map(|rt| rt.closest(&target, limit))
+            .unwrap_or_default();
+        let _ = response.send(peers);
+    }
 }
 
 impl<S: PeersState> PeerManagerNotificationsBehavior for PeerManager<S> {
     fn on_incoming_connection(&mut self, peer_id: PeerId, conn_id: ConnectionId) {
         trace!("on_incoming_connection(peer_id={})", peer_id);
+        if self.banned.is_banned(&peer_id, Instant::now()) {
+            trace!("Peer is banned. Rejecting connection from {}", peer_id);
+            self.out_queue.push_back(PeerManagerOut::Reject(peer_id, conn_id));
+            return;
+        }
         match self.state.peer(&peer_id) {
             Some(PeerInState::NotConnected(ncp)) => {
                 if ncp.get_reputation() >= self.conf.min_reputation && ncp.try_accept_connection().is_ok() {
@@ -490,6 +813,9 @@ impl<S: PeersState> PeerManagerNotificationsBehavior for PeerManager<S> {
         if let Some(PeerInState::Connected(mut cp)) = self.state.peer(&peer_id) {
             trace!("Peer {} has been acknowledged as connected", peer_id);
             cp.confirm_connection();
+            let destination = cp.destination();
+            // The peer is alive, refresh it in the routing table.
+            self.routing_table_insert(destination);
         } else {
             error!("Peer {} hasn't been acknowledged as connected", peer_id)
         }
@@ -498,21 +824,21 @@ impl<S: PeersState> PeerManagerNotificationsBehavior for PeerManager<S> {
     fn on_connection_lost(&mut self, peer_id: PeerId, reason: ConnectionLossReason) {
         match self.state.peer(&peer_id) {
             Some(PeerInState::Connected(cp)) => {
-                let mut ncp = cp.disconnect();
+                let ncp = cp.disconnect();
+                let is_reserved = ncp.is_reserved();
                 match reason {
-                    ConnectionLossReason::ResetByPeer => {
-                        if !ncp.is_reserved() {
-                            let backoff_until = Instant::now().add(self.conf.conn_reset_outbound_backoff);
-                            ncp.set_backoff_until(backoff_until);
-                        }
-                    }
+                    ConnectionLossReason::ResetByPeer => {}
                     ConnectionLossReason::Reset(err) => match err {
                         ConnHandlerError::SyncChannelExhausted => {
                             self.on_report_peer(peer_id, ReputationChange::TooSlow);
                         }
                         ConnHandlerError::UnacceptablePeer => (),
                     },
-                    ConnectionLossReason::Unknown => {}
+                    // Connections closed in a regular way end up here, no need to back off.
+                    ConnectionLossReason::Unknown => return,
+                }
+                if !is_reserved {
+                    self.schedule_retry(peer_id, reason.into());
                 }
             }
             Some(PeerInState::NotConnected(_)) => {} // warn
@@ -522,13 +848,23 @@ impl<S: PeersState> PeerManagerNotificationsBehavior for PeerManager<S> {
 
     fn on_dial_failure(&mut self, peer_id: PeerId) {
         match self.state.peer(&peer_id) {
+            Some(PeerInState::Connected(cp)) if !cp.is_confirmed() => {
+                trace!("ON DIAL FAILURE: {:?} connection attempt failed", peer_id);
+                cp.disconnect();
+                // Unreachable peers shouldn't be suggested to others.
+                self.routing_table_remove(&peer_id);
+                self.schedule_retry(peer_id, DialRetryReason::DialFailure);
+                self.on_report_peer(peer_id, ReputationChange::NoResponse);
+            }
             Some(PeerInState::Connected(_)) => {
                 trace!("ON DIAL FAILURE: {:?} already connected", peer_id);
                 self.on_report_peer(peer_id, ReputationChange::NoResponse);
             }
             Some(PeerInState::NotConnected(_)) => {
                 trace!("ON DIAL FAILURE: {:?} NOT connected", peer_id);
-            } // warn
+                // Unreachable peers shouldn't be suggested to others.
+                self.routing_table_remove(&peer_id);
+            }
             None => {
                 trace!("ON DIAL FAILURE: {:?} unknown peer", peer_id);
             } // warn
@@ -548,6 +884,9 @@ impl<S: Unpin + PeersState> Stream for PeerManager<S> {
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
         loop {
             if let Some(out) = self.out_queue.pop_front() {
+                if let Some(metrics) = &self.metrics {
+                    metrics::record_peer_manager_event(metrics.as_ref(), &out);
+                }
                 return Poll::Ready(Some(out));
             }
 
@@ -577,9 +916,17 @@ impl<S: Unpin + PeersState> Stream for PeerManager<S> {
                             self.on_get_peer_reputation(pid, resp)
                         }
                         PeerManagerRequest::SetReservedPeers(peers) => self.on_set_reserved_peers(peers),
+                        PeerManagerRequest::RemovePeer(pid) => self.on_remove_peer(pid),
+                        PeerManagerRequest::BanPeer { peer_id, duration } => {
+                            self.on_ban_peer(peer_id, duration)
+                        }
+                        PeerManagerRequest::GetAddressBook(resp) => self.on_get_address_book(resp),
                         PeerManagerRequest::SetProtocols(pid, protocols) => {
                             self.on_set_peer_protocols(pid, protocols)
                         }
+                        PeerManagerRequest::FindClosestPeers { target, limit, snd } => {
+                            self.on_find_closest_peers(target, limit, snd)
+                        }
                     },
                 }
                 continue;
@@ -587,6 +934,7 @@ impl<S: Unpin + PeersState> Stream for PeerManager<S> {
 
             if Future::poll(Pin::new(&mut self.next_conn_alloc), cx).is_ready() {
                 trace!("Going to allocate more connections");
+                self.banned.prune(Instant::now());
                 self.connect_reserved(); // always try to allocate connections to reserved peers.
                 match self.prepare_allocate_connections() {
                     ConnAllocationMode::Active => {
@@ -608,7 +956,69 @@ impl<S: Unpin + PeersState> Stream for PeerManager<S> {
                 self.next_prot_alloc = Delay::new(self.conf.prot_alloc_interval);
             }
 
+            if Future::poll(Pin::new(&mut self.next_reputation_decay), cx).is_ready() {
+                self.decay_reputations();
+                self.next_reputation_decay = Delay::new(self.conf.reputation_decay.interval);
+                continue;
+            }
+
             return Poll::Pending;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::peer_manager::data::ProtocolAllocationPolicy;
+    use crate::peer_manager::peers_state::PeerRepo;
+    use crate::peer_manager::{NetworkingConfig, PeerManager, PeerManagerConfig};
+    use crate::protocol::{
+        ProtocolConfig, ProtocolPriority, StatefulProtocolConfig, DIFFUSION_PROTOCOL_ID,
+        DISCOVERY_PROTOCOL_ID, SIGMA_AGGR_PROTOCOL_ID,
+    };
+
+    fn conf_with_priority(priority: ProtocolPriority) -> ProtocolConfig {
+        ProtocolConfig::Stateful(StatefulProtocolConfig {
+            supported_versions: vec![],
+            preferred_versions: vec![],
+            priority,
+        })
+    }
+
+    #[test]
+    fn protocols_allocated_in_order_of_configured_priority() {
+        let conf = PeerManagerConfig {
+            protocols_allocation: vec![
+                (DISCOVERY_PROTOCOL_ID, ProtocolAllocationPolicy::Max),
+                (DIFFUSION_PROTOCOL_ID, ProtocolAllocationPolicy::Max),
+                (SIGMA_AGGR_PROTOCOL_ID, ProtocolAllocationPolicy::Max),
+            ],
+            ..PeerManagerConfig::default()
+        };
+        let protocols = HashMap::from([
+            (
+                DISCOVERY_PROTOCOL_ID,
+                conf_with_priority(ProtocolPriority::NORMAL),
+            ),
+            (SIGMA_AGGR_PROTOCOL_ID, conf_with_priority(ProtocolPriority::HIGH)),
+        ]);
+        let (pm, _) = PeerManager::new(PeerRepo::new(NetworkingConfig::default(), vec![]), conf);
+        let pm = pm.with_protocol_configs(&protocols);
+        let order = pm
+            .conf
+            .protocols_allocation
+            .iter()
+            .map(|(prot, _)| *prot)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            order,
+            vec![
+                SIGMA_AGGR_PROTOCOL_ID,
+                DISCOVERY_PROTOCOL_ID,
+                DIFFUSION_PROTOCOL_ID
+            ]
+        );
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use libp2p::PeerId;
+
+/// Peers that are banned either permanently or until some point in time.
+#[derive(Debug, Default)]
+pub struct BanList {
+    /// `None` stands for a permanent ban.
+    bans: HashMap<PeerId, Option<Instant>>,
+}
+
+impl BanList {
+    /// Ban the given peer for `duration` or permanently if it's `None`.
+    /// A new ban replaces the existing one.
+    pub fn ban(&mut self, peer_id: PeerId, duration: Option<Duration>, now: Instant) {
+        self.bans.insert(peer_id, duration.map(|d| now + d));
+    }
+
+    pub fn is_banned(&self, peer_id: &PeerId, now: Instant) -> bool {
+        match self.bans.get(peer_id) {
+            Some(Some(until)) => *until > now,
+            Some(None) => true,
+            None => false,
+        }
+    }
+
+    /// Lift expired bans.
+    pub fn prune(&mut self, now: Instant) {
+        self.bans
+            .retain(|_, until| !until.is_some_and(|until| until <= now));
+    }
+
+    pub fn len(&self) -> usize {
+        self.bans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bans.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use libp2p::PeerId;
+
+    use crate::peer_manager::ban_list::BanList;
+
+    #[test]
+    fn temporary_ban_expires() {
+        let mut bans = BanList::default();
+        let peer = PeerId::random();
+        let now = Instant::now();
+        bans.ban(peer, Some(Duration::from_secs(10)), now);
+        assert!(bans.is_banned(&peer, now + Duration::from_secs(9)));
+        assert!(!bans.is_banned(&peer, now + Duration::from_secs(10)));
+        bans.prune(now + Duration::from_secs(10));
+        assert!(bans.is_empty());
+    }
+
+    #[test]
+    fn permanent_ban_never_expires() {
+        let mut bans = BanList::default();
+        let peer = PeerId::random();
+        let now = Instant::now();
+        bans.ban(peer, None, now);
+        let far_future = now + Duration::from_secs(365 * 24 * 3600);
+        assert!(bans.is_banned(&peer, far_future));
+        bans.prune(far_future);
+        assert_eq!(bans.len(), 1);
+    }
+}
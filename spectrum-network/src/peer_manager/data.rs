@@ -2,6 +2,7 @@ use crate::peer_conn_handler::ConnHandlerError;
 use crate::types::{ProtocolId, Reputation};
 use libp2p::swarm::dial_opts::{DialOpts, PeerCondition};
 use libp2p::{Multiaddr, PeerId};
+use rand::Rng;
 
 use serde::de::{EnumAccess, Error, SeqAccess, Unexpected, VariantAccess, Visitor};
 use serde::ser::SerializeTupleVariant;
@@ -9,7 +10,7 @@ use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use std::fmt::Formatter;
 use std::str::from_utf8;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum PeerDestination {
@@ -186,10 +187,18 @@ impl From<PeerDestination> for DialOpts {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ReputationChange {
     NoResponse,
     TooSlow,
+    /// Peer served a modifier which failed validation.
+    InvalidModifier,
+    /// Peer served a modifier which was successfully applied.
+    UsefulModifier,
+    /// Peer exceeded the rate limit of inbound events.
+    Spam,
+    /// Peer sent a message exceeding the size limit of the protocol.
+    OversizedMessage,
 }
 
 impl ReputationChange {
@@ -198,6 +207,10 @@ impl ReputationChange {
         match self {
             ReputationChange::NoResponse => true,
             ReputationChange::TooSlow => true,
+            ReputationChange::InvalidModifier => true,
+            ReputationChange::UsefulModifier => false,
+            ReputationChange::Spam => true,
+            ReputationChange::OversizedMessage => true,
         }
     }
 }
@@ -207,6 +220,10 @@ impl From<ReputationChange> for i32 {
         match c {
             ReputationChange::NoResponse => -10,
             ReputationChange::TooSlow => -10,
+            ReputationChange::InvalidModifier => -50,
+            ReputationChange::UsefulModifier => 1,
+            ReputationChange::Spam => -20,
+            ReputationChange::OversizedMessage => -50,
         }
     }
 }
@@ -221,6 +238,143 @@ pub enum ConnectionLossReason {
     Unknown,
 }
 
+/// Reason an outbound connection attempt is deemed failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum DialRetryReason {
+    /// Peer could not be dialed.
+    DialFailure,
+    /// Connection has been explicitly reset by peer.
+    ResetByPeer,
+    /// Connection has been closed by us because of an error.
+    Reset,
+    /// Connection has been closed for an unknown reason.
+    Unknown,
+}
+
+impl From<ConnectionLossReason> for DialRetryReason {
+    fn from(reason: ConnectionLossReason) -> Self {
+        match reason {
+            ConnectionLossReason::ResetByPeer => DialRetryReason::ResetByPeer,
+            ConnectionLossReason::Reset(_) => DialRetryReason::Reset,
+            ConnectionLossReason::Unknown => DialRetryReason::Unknown,
+        }
+    }
+}
+
+/// Exponential backoff schedule.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Backoff {
+    /// Delay after the first failed attempt.
+    pub initial_backoff: Duration,
+    /// Upper bound of the delay between two consecutive attempts.
+    pub max_backoff: Duration,
+    /// Maximum number of consecutive failed attempts before the peer is given up on.
+    pub max_attempts: u32,
+}
+
+/// Policy of outbound connection retries.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RetryPolicy {
+    /// Schedule used unless overridden for a particular reason.
+    pub default: Backoff,
+    /// Upper bound of random jitter added to each backoff, percents of the backoff.
+    pub jitter_pct: u32,
+    /// Schedules for particular failure reasons.
+    pub overrides: Vec<(DialRetryReason, Backoff)>,
+}
+
+impl RetryPolicy {
+    pub fn schedule(&self, reason: DialRetryReason) -> Backoff {
+        self.overrides
+            .iter()
+            .find(|(r, _)| *r == reason)
+            .map(|(_, b)| *b)
+            .unwrap_or(self.default)
+    }
+
+    /// Backoff (without jitter) after the given failed attempt (starting from 1).
+    /// `None` if the number of attempts is exhausted.
+    pub fn backoff(&self, attempt: u32, reason: DialRetryReason) -> Option<Duration> {
+        let schedule = self.schedule(reason);
+        if attempt == 0 || attempt > schedule.max_attempts {
+            return None;
+        }
+        let factor = 1u32.checked_shl(attempt - 1).unwrap_or(u32::MAX);
+        Some(
+            schedule
+                .initial_backoff
+                .checked_mul(factor)
+                .map_or(schedule.max_backoff, |d| d.min(schedule.max_backoff)),
+        )
+    }
+
+    /// Add random jitter to the given backoff.
+    pub fn jittered(&self, backoff: Duration) -> Duration {
+        let max_jitter_ms = backoff.as_millis() as u64 * self.jitter_pct as u64 / 100;
+        if max_jitter_ms == 0 {
+            return backoff;
+        }
+        backoff + Duration::from_millis(rand::thread_rng().gen_range(0..=max_jitter_ms))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            default: Backoff {
+                initial_backoff: Duration::from_secs(5),
+                max_backoff: Duration::from_secs(600),
+                max_attempts: 10,
+            },
+            jitter_pct: 20,
+            overrides: vec![(
+                DialRetryReason::ResetByPeer,
+                Backoff {
+                    initial_backoff: Duration::from_secs(120),
+                    max_backoff: Duration::from_secs(1800),
+                    max_attempts: 10,
+                },
+            )],
+        }
+    }
+}
+
+/// Policy of reputation recovery. Effect of every reputation change fades away exponentially,
+/// so that reputations of peers drift back toward neutral unless they keep misbehaving.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ReputationDecayPolicy {
+    /// How often reputations are moved toward neutral.
+    pub interval: Duration,
+    /// Half-life used unless overridden for a particular reason.
+    pub default_half_life: Duration,
+    /// Half-lives of changes for particular reasons.
+    pub overrides: Vec<(ReputationChange, Duration)>,
+}
+
+impl ReputationDecayPolicy {
+    pub fn half_life(&self, reason: ReputationChange) -> Duration {
+        self.overrides
+            .iter()
+            .find(|(r, _)| *r == reason)
+            .map(|(_, hl)| *hl)
+            .unwrap_or(self.default_half_life)
+    }
+}
+
+impl Default for ReputationDecayPolicy {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(10),
+            default_half_life: Duration::from_secs(600),
+            overrides: vec![
+                (ReputationChange::Spam, Duration::from_secs(1800)),
+                (ReputationChange::InvalidModifier, Duration::from_secs(3600)),
+                (ReputationChange::OversizedMessage, Duration::from_secs(3600)),
+            ],
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ConnectionState {
     Connected(ConnectionDirection),
@@ -267,6 +421,10 @@ pub struct PeerInfo {
     pub last_handshake: Option<Instant>,
     /// Backoff of the next outbound connection attempt.
     pub outbound_backoff_until: Option<Instant>,
+    /// Number of consecutive failed outbound connection attempts.
+    pub failed_attempts: u32,
+    /// Reason of the last failed outbound connection attempt.
+    pub last_failure: Option<DialRetryReason>,
     /// Protocols supported by the peer. `None` if unknown.
     pub supported_protocols: Option<Vec<ProtocolId>>,
 }
@@ -282,6 +440,8 @@ impl PeerInfo {
             num_connections: 0,
             last_handshake: None,
             outbound_backoff_until: None,
+            failed_attempts: 0,
+            last_failure: None,
             supported_protocols: None,
         }
     }
@@ -295,6 +455,48 @@ impl PeerInfo {
     }
 }
 
+/// Address book in the format used to import/export known peers:
+/// ```json
+/// {
+///   "peers": [
+///     { "peer_id": "12D3KooWQYhTNQdmr3ArTeUHRYzFg94BKyTkoWBDWez9kSCVe2Xo", "addr": "/ip4/10.0.0.7/tcp/8000", "reserved": true },
+///     { "peer_id": "16Uiu2HAmHjpBZbKxb5WgyKMT2oP4uBm6L8yN7j1hzvZ8wTfYmwEB", "addr": null, "reserved": false }
+///   ]
+/// }
+/// ```
+/// `addr` is the multiaddress the peer can be dialed at (without the `/p2p` suffix), `null` if unknown.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct AddressBook {
+    pub peers: Vec<AddressBookEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressBookEntry {
+    pub peer_id: PeerId,
+    #[serde(default)]
+    pub addr: Option<Multiaddr>,
+    #[serde(default)]
+    pub reserved: bool,
+}
+
+impl AddressBookEntry {
+    pub fn destination(&self) -> PeerDestination {
+        match &self.addr {
+            Some(addr) => PeerDestination::PeerIdWithAddr(self.peer_id, addr.clone()),
+            None => PeerDestination::PeerId(self.peer_id),
+        }
+    }
+}
+
+/// A peer known to PM along with its current standing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KnownPeer {
+    #[serde(flatten)]
+    pub entry: AddressBookEntry,
+    pub reputation: Reputation,
+    pub connected: bool,
+}
+
 /// Policy of protocols allocation defines the way we should
 /// actively allocate connections for a particular protocol.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -306,3 +508,46 @@ pub enum ProtocolAllocationPolicy {
     /// Do not allocate any connections.
     Zero,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::peer_manager::data::{Backoff, DialRetryReason, RetryPolicy};
+
+    #[test]
+    fn backoff_grows_exponentially_and_respects_overrides() {
+        let policy = RetryPolicy {
+            default: Backoff {
+                initial_backoff: Duration::from_secs(1),
+                max_backoff: Duration::from_secs(5),
+                max_attempts: 4,
+            },
+            jitter_pct: 50,
+            overrides: vec![(
+                DialRetryReason::ResetByPeer,
+                Backoff {
+                    initial_backoff: Duration::from_secs(60),
+                    max_backoff: Duration::from_secs(60),
+                    max_attempts: 1,
+                },
+            )],
+        };
+        let reason = DialRetryReason::DialFailure;
+        assert_eq!(policy.backoff(0, reason), None);
+        assert_eq!(policy.backoff(1, reason), Some(Duration::from_secs(1)));
+        assert_eq!(policy.backoff(2, reason), Some(Duration::from_secs(2)));
+        assert_eq!(policy.backoff(3, reason), Some(Duration::from_secs(4)));
+        assert_eq!(policy.backoff(4, reason), Some(Duration::from_secs(5)));
+        assert_eq!(policy.backoff(5, reason), None);
+        assert_eq!(
+            policy.backoff(1, DialRetryReason::ResetByPeer),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(policy.backoff(2, DialRetryReason::ResetByPeer), None);
+        for _ in 0..100 {
+            let jittered = policy.jittered(Duration::from_secs(2));
+            assert!(jittered >= Duration::from_secs(2) && jittered <= Duration::from_secs(3));
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use crate::peer_manager::data::{
-    ConnectionDirection, ConnectionState, PeerDestination, PeerInfo, ReputationChange,
+    AddressBookEntry, ConnectionDirection, ConnectionState, DialRetryReason, KnownPeer, PeerDestination,
+    PeerInfo, ReputationChange,
 };
 use crate::peer_manager::peer_index::PeerIndex;
 use crate::peer_manager::NetworkingConfig;
@@ -17,7 +18,7 @@ pub struct ConnectedPeer<'a> {
     peer_info: OccupiedEntry<'a, PeerId, PeerInfo>,
     index: &'a mut PeerIndex,
     best_peers: &'a mut BTreeSet<(PeerId, Reputation)>,
-    netw_conf: NetworkingConfig,
+    netw_conf: &'a NetworkingConfig,
 }
 
 impl<'a> ConnectedPeer<'a> {
@@ -26,7 +27,7 @@ impl<'a> ConnectedPeer<'a> {
         peer_info: OccupiedEntry<'a, PeerId, PeerInfo>,
         index: &'a mut PeerIndex,
         best_peers: &'a mut BTreeSet<(PeerId, Reputation)>,
-        netw_conf: NetworkingConfig,
+        netw_conf: &'a NetworkingConfig,
     ) -> Self {
         Self {
             peer_id,
@@ -73,6 +74,8 @@ impl<'a> ConnectedPeer<'a> {
         match peer_info.state {
             ConnectionState::Connected(ConnectionDirection::Outbound(false)) => {
                 peer_info.state = ConnectionState::Connected(ConnectionDirection::Outbound(true));
+                peer_info.failed_attempts = 0;
+                peer_info.last_failure = None;
                 true
             }
             _ => false,
@@ -134,7 +137,7 @@ pub struct NotConnectedPeer<'a> {
     peer_info: OccupiedEntry<'a, PeerId, PeerInfo>,
     index: &'a mut PeerIndex,
     sorted_peers: &'a mut BTreeSet<(PeerId, Reputation)>,
-    netw_conf: NetworkingConfig,
+    netw_conf: &'a NetworkingConfig,
 }
 
 impl<'a> NotConnectedPeer<'a> {
@@ -143,7 +146,7 @@ impl<'a> NotConnectedPeer<'a> {
         peer_info: OccupiedEntry<'a, PeerId, PeerInfo>,
         peer_sets: &'a mut PeerIndex,
         sorted_peers: &'a mut BTreeSet<(PeerId, Reputation)>,
-        netw_conf: NetworkingConfig,
+        netw_conf: &'a NetworkingConfig,
     ) -> Self {
         Self {
             peer_id,
@@ -159,8 +162,12 @@ impl<'a> NotConnectedPeer<'a> {
         self.force_connect(ConnectionDirection::Outbound(false))
     }
 
-    pub fn try_accept_connection(self) -> Result<ConnectedPeer<'a>, Self> {
+    pub fn try_accept_connection(mut self) -> Result<ConnectedPeer<'a>, Self> {
         if self.index.num_inbound < self.netw_conf.max_inbound {
+            // The peer is alive, previous failures are no longer relevant.
+            let peer_info = self.peer_info.get_mut();
+            peer_info.failed_attempts = 0;
+            peer_info.last_failure = None;
             Ok(self.force_connect(ConnectionDirection::Inbound))
         } else {
             Err(self)
@@ -193,6 +200,22 @@ impl<'a> NotConnectedPeer<'a> {
         self.peer_info.get().outbound_backoff_until
     }
 
+    /// Register a failed outbound connection attempt. Returns the number of consecutive failures.
+    pub fn register_failure(&mut self, reason: DialRetryReason) -> u32 {
+        let peer_info = self.peer_info.get_mut();
+        peer_info.failed_attempts = peer_info.failed_attempts.saturating_add(1);
+        peer_info.last_failure = Some(reason);
+        peer_info.failed_attempts
+    }
+
+    pub fn failed_attempts(&self) -> u32 {
+        self.peer_info.get().failed_attempts
+    }
+
+    pub fn last_failure(&self) -> Option<DialRetryReason> {
+        self.peer_info.get().last_failure
+    }
+
     fn force_connect(mut self, direction: ConnectionDirection) -> ConnectedPeer<'a> {
         let peer_info = self.peer_info.get_mut();
         let _ = peer_info.num_connections.saturating_add(1);
@@ -223,10 +246,14 @@ impl<'a> PeerInState<'a> {
     }
 
     pub fn adjust_reputation(self, adjustment: ReputationChange) -> Self {
+        let reputation = self.get_reputation().apply(adjustment);
+        self.set_reputation(reputation)
+    }
+
+    pub fn set_reputation(self, new_rep: Reputation) -> Self {
         match self {
             PeerInState::Connected(mut cp) => {
                 let old_rep = cp.peer_info.get().reputation;
-                let new_rep = old_rep.apply(adjustment);
                 cp.peer_info.get_mut().reputation = new_rep;
                 cp.best_peers.remove(&(*cp.peer_id, old_rep));
                 cp.best_peers.insert((*cp.peer_id, new_rep));
@@ -234,7 +261,6 @@ impl<'a> PeerInState<'a> {
             }
             PeerInState::NotConnected(mut ncp) => {
                 let old_rep = ncp.peer_info.get().reputation;
-                let new_rep = old_rep.apply(adjustment);
                 ncp.peer_info.get_mut().reputation = new_rep;
                 ncp.sorted_peers.remove(&(*ncp.peer_id, old_rep));
                 ncp.sorted_peers.insert((*ncp.peer_id, new_rep));
@@ -309,6 +335,9 @@ pub trait PeersState {
     /// Get reputation of a peer with the given peer_id if such peer is known.
     fn get_peer_reputation(&self, peer_id: &PeerId) -> Option<Reputation>;
 
+    /// Get all known peers.
+    fn get_address_book(&self) -> Vec<KnownPeer>;
+
     /// Add a peer to PeersState.
     /// Returns a NotConnectedPeer if succeeded.
     fn try_add_peer(
@@ -364,6 +393,22 @@ impl PeerRepo {
             boot_peers,
         }
     }
+
+    pub(crate) fn iter_peers(&self) -> impl Iterator<Item = (&PeerId, &PeerInfo)> {
+        self.peers.iter()
+    }
+
+    /// Insert a peer known from a previous run of the node.
+    pub(crate) fn restore_peer(&mut self, peer_id: PeerId, peer_info: PeerInfo) {
+        if peer_info.is_reserved {
+            self.index.reserve_peer(peer_id);
+        }
+        if let Some(prev) = self.peers.get(&peer_id) {
+            self.sorted_peers.remove(&(peer_id, prev.reputation));
+        }
+        self.sorted_peers.insert((peer_id, peer_info.reputation));
+        self.peers.insert(peer_id, peer_info);
+    }
 }
 
 impl PeersState for PeerRepo {
@@ -375,14 +420,14 @@ impl PeersState for PeerRepo {
                     peer_info,
                     &mut self.index,
                     &mut self.sorted_peers,
-                    self.netw_conf,
+                    &self.netw_conf,
                 ))),
                 ConnectionState::NotConnected => Some(PeerInState::NotConnected(NotConnectedPeer::new(
                     Cow::Borrowed(peer_id),
                     peer_info,
                     &mut self.index,
                     &mut self.sorted_peers,
-                    self.netw_conf,
+                    &self.netw_conf,
                 ))),
             },
             Entry::Vacant(_) => None,
@@ -407,6 +452,21 @@ impl PeersState for PeerRepo {
         self.peers.get(peer_id).map(|p| p.reputation)
     }
 
+    fn get_address_book(&self) -> Vec<KnownPeer> {
+        self.peers
+            .iter()
+            .map(|(pid, pif)| KnownPeer {
+                entry: AddressBookEntry {
+                    peer_id: *pid,
+                    addr: pif.addr.clone(),
+                    reserved: pif.is_reserved,
+                },
+                reputation: pif.reputation,
+                connected: pif.state.is_connected(),
+            })
+            .collect()
+    }
+
     fn try_add_peer(
         &mut self,
         peer_dest: PeerDestination,
@@ -428,7 +488,7 @@ impl PeersState for PeerRepo {
                     peer_info,
                     &mut self.index,
                     &mut self.sorted_peers,
-                    self.netw_conf,
+                    &self.netw_conf,
                 )),
                 Entry::Vacant(_) => None,
             }
@@ -0,0 +1,433 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use libp2p::{Multiaddr, PeerId};
+use log::{error, info, warn};
+use rocksdb::{IteratorMode, WriteBatch, DB};
+use serde::{Deserialize, Serialize};
+
+use crate::peer_manager::data::{KnownPeer, PeerDestination, PeerInfo};
+use crate::peer_manager::peers_state::{
+    NetworkingState, NotConnectedPeer, PeerInState, PeerRepo, PeerStateFilter, PeersState,
+};
+use crate::peer_manager::{NetworkingConfig, PeerStorage};
+use crate::store_recovery::{self, RecoveryConf, RecoveryError, RecoveryReport};
+use crate::types::{ProtocolId, Reputation};
+
+/// What is remembered about a peer across restarts of the node.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct PeerRecord {
+    addr: Option<Multiaddr>,
+    reputation: Reputation,
+    is_reserved: bool,
+    is_boot: bool,
+    /// Outbound backoff remaining at the time of the snapshot, millis.
+    backoff_ms: Option<u64>,
+}
+
+impl PeerRecord {
+    fn new(peer_info: &PeerInfo, now: Instant) -> Self {
+        Self {
+            addr: peer_info.addr.clone(),
+            reputation: peer_info.reputation,
+            is_reserved: peer_info.is_reserved,
+            is_boot: peer_info.is_boot,
+            backoff_ms: peer_info
+                .outbound_backoff_until
+                .and_then(|ts| ts.checked_duration_since(now))
+                .map(|d| d.as_millis() as u64),
+        }
+    }
+
+    fn into_peer_info(self, now: Instant) -> PeerInfo {
+        let mut peer_info = PeerInfo::new(self.addr, self.is_reserved, self.is_boot);
+        peer_info.reputation = self.reputation;
+        peer_info.outbound_backoff_until = self.backoff_ms.map(|ms| now + Duration::from_millis(ms));
+        peer_info
+    }
+}
+
+/// [`PeerRepo`] backed by RocksDB. Peers are snapshotted every
+/// [`NetworkingConfig::peers_snapshot_interval`] and once again when the repo is dropped,
+/// so that reputation and backoffs accumulated by the node survive restarts.
+pub struct PersistentPeerRepo {
+    inner: PeerRepo,
+    db: DB,
+    snapshot_interval: Duration,
+    last_snapshot: Instant,
+    recovery_conf: RecoveryConf,
+    recovery_report: RecoveryReport,
+}
+
+impl PersistentPeerRepo {
+    /// Open the store at `db_path` and recover peers known from the previous run.
+    /// A store which can't be recovered is moved aside, so that the node can start with
+    /// no known peers instead of failing.
+    pub fn open<P: AsRef<Path>>(
+        db_path: P,
+        recovery_conf: RecoveryConf,
+        netw_conf: NetworkingConfig,
+        boot_peers: Vec<PeerDestination>,
+    ) -> Result<Self, RecoveryError> {
+        let (db, recovery_report) =
+            store_recovery::open_or_recover(db_path.as_ref(), &recovery_conf, true, DB::open_default)?;
+        let snapshot_interval = netw_conf.peers_snapshot_interval;
+        let mut inner = PeerRepo::new(netw_conf, boot_peers);
+        let now = Instant::now();
+        let mut restored = 0;
+        for item in db.iterator(IteratorMode::Start) {
+            let (key, value) = match item {
+                Ok(kv) => kv,
+                Err(err) => {
+                    // Peers restored so far are still usable, the rest is overwritten by snapshots.
+                    warn!(
+                        "Failed to read peer records, the rest of them is discarded: {}",
+                        err
+                    );
+                    break;
+                }
+            };
+            match (
+                PeerId::from_bytes(&key),
+                ciborium::de::from_reader::<PeerRecord, _>(&*value),
+            ) {
+                (Ok(peer_id), Ok(record)) => {
+                    inner.restore_peer(peer_id, record.into_peer_info(now));
+                    restored += 1;
+                }
+                _ => warn!("Skipping malformed peer record {:?}", key),
+            }
+        }
+        info!("Restored {} known peers", restored);
+        Ok(Self {
+            inner,
+            db,
+            snapshot_interval,
+            last_snapshot: now,
+            recovery_conf,
+            recovery_report,
+        })
+    }
+
+    /// Persist the current state of all known peers.
+    pub fn snapshot(&mut self) -> Result<(), rocksdb::Error> {
+        let now = Instant::now();
+        let mut batch = WriteBatch::default();
+        let mut known = HashSet::new();
+        for (peer_id, peer_info) in self.inner.iter_peers() {
+            let key = peer_id.to_bytes();
+            let mut value = Vec::new();
+            ciborium::ser::into_writer(&PeerRecord::new(peer_info, now), &mut value)
+                .expect("Serialization of PeerRecord never fails");
+            batch.put(&key, value);
+            known.insert(key);
+        }
+        // Drop peers forgotten since the last snapshot.
+        for item in self.db.iterator(IteratorMode::Start) {
+            let (key, _) = item?;
+            if !known.contains(&*key) {
+                batch.delete(key);
+            }
+        }
+        self.db.write(batch)?;
+        self.last_snapshot = now;
+        Ok(())
+    }
+
+    /// Actions taken to recover the store when it was opened.
+    pub fn recovery_report(&self) -> &RecoveryReport {
+        &self.recovery_report
+    }
+
+    fn maybe_snapshot(&mut self) {
+        if self.last_snapshot.elapsed() >= self.snapshot_interval {
+            if let Err(err) = self.snapshot() {
+                error!("Failed to persist peers: {}", err);
+            }
+        }
+    }
+}
+
+impl Drop for PersistentPeerRepo {
+    fn drop(&mut self) {
+        if let Err(err) = self.snapshot() {
+            error!("Failed to persist peers on shutdown: {}", err);
+        } else if let Err(err) = store_recovery::backup(&self.db, &self.recovery_conf) {
+            error!("Failed to back up peers on shutdown: {}", err);
+        }
+    }
+}
+
+impl PeersState for PersistentPeerRepo {
+    fn peer<'a>(&'a mut self, peer_id: &'a PeerId) -> Option<PeerInState<'a>> {
+        self.maybe_snapshot();
+        self.inner.peer(peer_id)
+    }
+
+    fn get_peers(&self, limit: usize) -> Vec<PeerDestination> {
+        self.inner.get_peers(limit)
+    }
+
+    fn get_peer_reputation(&self, peer_id: &PeerId) -> Option<Reputation> {
+        self.inner.get_peer_reputation(peer_id)
+    }
+
+    fn get_address_book(&self) -> Vec<KnownPeer> {
+        self.inner.get_address_book()
+    }
+
+    fn try_add_peer(
+        &mut self,
+        peer_id: PeerDestination,
+        is_reserved: bool,
+        is_boot: bool,
+    ) -> Option<NotConnectedPeer> {
+        self.maybe_snapshot();
+        self.inner.try_add_peer(peer_id, is_reserved, is_boot)
+    }
+
+    fn set_reserved_peers(&mut self, peers: HashSet<PeerId>) -> HashSet<PeerId> {
+        self.maybe_snapshot();
+        self.inner.set_reserved_peers(peers)
+    }
+
+    fn get_reserved_peers(&self, filter: Option<PeerStateFilter>) -> HashSet<PeerId> {
+        self.inner.get_reserved_peers(filter)
+    }
+
+    fn get_enabled_peers(&self, protocol_id: &ProtocolId) -> Option<&HashSet<PeerId>> {
+        self.inner.get_enabled_peers(protocol_id)
+    }
+
+    fn num_connected_peers(&self) -> usize {
+        self.inner.num_connected_peers()
+    }
+
+    fn networking_state(&self) -> NetworkingState {
+        self.inner.networking_state()
+    }
+
+    fn filter_peers<F>(&mut self, predicate: F) -> Vec<PeerId>
+    where
+        F: Fn(&PeerId, &PeerInfo) -> bool,
+    {
+        self.maybe_snapshot();
+        self.inner.filter_peers(predicate)
+    }
+
+    fn pick_best<F>(&self, filter: Option<F>) -> Option<PeerId>
+    where
+        F: Fn(&PeerId, &PeerInfo) -> bool,
+    {
+        self.inner.pick_best(filter)
+    }
+}
+
+/// Peer store selected by [`NetworkingConfig::peers_storage`].
+pub enum AnyPeerRepo {
+    Memory(PeerRepo),
+    Persistent(PersistentPeerRepo),
+}
+
+impl AnyPeerRepo {
+    /// `db_path` and `recovery_conf` are only used by [`PeerStorage::Persistent`] store.
+    pub fn open<P: AsRef<Path>>(
+        db_path: P,
+        recovery_conf: RecoveryConf,
+        netw_conf: NetworkingConfig,
+        boot_peers: Vec<PeerDestination>,
+    ) -> Result<Self, RecoveryError> {
+        Ok(match netw_conf.peers_storage {
+            PeerStorage::Memory => AnyPeerRepo::Memory(PeerRepo::new(netw_conf, boot_peers)),
+            PeerStorage::Persistent => AnyPeerRepo::Persistent(PersistentPeerRepo::open(
+                db_path,
+                recovery_conf,
+                netw_conf,
+                boot_peers,
+            )?),
+        })
+    }
+
+    /// Actions taken to recover the store, `None` if peers aren't persisted.
+    pub fn recovery_report(&self) -> Option<&RecoveryReport> {
+        match self {
+            AnyPeerRepo::Memory(_) => None,
+            AnyPeerRepo::Persistent(repo) => Some(repo.recovery_report()),
+        }
+    }
+}
+
+macro_rules! delegate {
+    ($self:ident, $repo:ident => $call:expr) => {
+        match $self {
+            AnyPeerRepo::Memory($repo) => $call,
+            AnyPeerRepo::Persistent($repo) => $call,
+        }
+    };
+}
+
+impl PeersState for AnyPeerRepo {
+    fn peer<'a>(&'a mut self, peer_id: &'a PeerId) -> Option<PeerInState<'a>> {
+        delegate!(self, repo => repo.peer(peer_id))
+    }
+
+    fn get_peers(&self, limit: usize) -> Vec<PeerDestination> {
+        delegate!(self, repo => repo.get_peers(limit))
+    }
+
+    fn get_peer_reputation(&self, peer_id: &PeerId) -> Option<Reputation> {
+        delegate!(self, repo => repo.get_peer_reputation(peer_id))
+    }
+
+    fn get_address_book(&self) -> Vec<KnownPeer> {
+        delegate!(self, repo => repo.get_address_book())
+    }
+
+    fn try_add_peer(
+        &mut self,
+        peer_id: PeerDestination,
+        is_reserved: bool,
+        is_boot: bool,
+    ) -> Option<NotConnectedPeer> {
+        delegate!(self, repo => repo.try_add_peer(peer_id, is_reserved, is_boot))
+    }
+
+    fn set_reserved_peers(&mut self, peers: HashSet<PeerId>) -> HashSet<PeerId> {
+        delegate!(self, repo => repo.set_reserved_peers(peers))
+    }
+
+    fn get_reserved_peers(&self, filter: Option<PeerStateFilter>) -> HashSet<PeerId> {
+        delegate!(self, repo => repo.get_reserved_peers(filter))
+    }
+
+    fn get_enabled_peers(&self, protocol_id: &ProtocolId) -> Option<&HashSet<PeerId>> {
+        delegate!(self, repo => repo.get_enabled_peers(protocol_id))
+    }
+
+    fn num_connected_peers(&self) -> usize {
+        delegate!(self, repo => repo.num_connected_peers())
+    }
+
+    fn networking_state(&self) -> NetworkingState {
+        delegate!(self, repo => repo.networking_state())
+    }
+
+    fn filter_peers<F>(&mut self, predicate: F) -> Vec<PeerId>
+    where
+        F: Fn(&PeerId, &PeerInfo) -> bool,
+    {
+        delegate!(self, repo => repo.filter_peers(predicate))
+    }
+
+    fn pick_best<F>(&self, filter: Option<F>) -> Option<PeerId>
+    where
+        F: Fn(&PeerId, &PeerInfo) -> bool,
+    {
+        delegate!(self, repo => repo.pick_best(filter))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::time::Duration;
+
+    use libp2p::PeerId;
+    use rand::RngCore;
+
+    use crate::peer_manager::data::{PeerDestination, ReputationChange};
+    use crate::peer_manager::peers_state::{PeerInState, PeerStateFilter, PeersState};
+    use crate::peer_manager::persistent_peers_state::{AnyPeerRepo, PersistentPeerRepo};
+    use crate::peer_manager::{NetworkingConfig, PeerStorage};
+    use crate::store_recovery::{RecoveryAction, RecoveryConf};
+
+    fn netw_conf() -> NetworkingConfig {
+        NetworkingConfig {
+            min_known_peers: 0,
+            min_outbound: 0,
+            max_inbound: 10,
+            max_outbound: 10,
+            peers_snapshot_interval: Duration::from_secs(60),
+            peers_storage: PeerStorage::Persistent,
+            ..NetworkingConfig::default()
+        }
+    }
+
+    #[test]
+    fn peers_survive_restart() {
+        let db_path = format!("./tmp/peers_{}", rand::thread_rng().next_u32());
+        let reserved_peer = PeerId::random();
+        let punished_peer = PeerId::random();
+        {
+            let mut repo =
+                PersistentPeerRepo::open(&db_path, RecoveryConf::default(), netw_conf(), vec![]).unwrap();
+            repo.try_add_peer(
+                PeerDestination::PeerIdWithAddr(reserved_peer, "/ip4/127.0.0.1/tcp/8000".parse().unwrap()),
+                true,
+                false,
+            );
+            repo.try_add_peer(PeerDestination::PeerId(punished_peer), false, false);
+            if let Some(PeerInState::NotConnected(mut ncp)) = repo.peer(&punished_peer) {
+                ncp.set_backoff_until(std::time::Instant::now() + Duration::from_secs(600));
+            }
+            repo.peer(&punished_peer)
+                .unwrap()
+                .adjust_reputation(ReputationChange::TooSlow);
+        }
+        let mut repo =
+            PersistentPeerRepo::open(&db_path, RecoveryConf::default(), netw_conf(), vec![]).unwrap();
+        assert_eq!(
+            repo.get_reserved_peers(Some(PeerStateFilter::NotConnected)),
+            HashSet::from([reserved_peer])
+        );
+        assert!(
+            repo.get_peer_reputation(&punished_peer).unwrap()
+                < repo.get_peer_reputation(&reserved_peer).unwrap()
+        );
+        assert_eq!(repo.get_address_book().len(), 2);
+        assert!(matches!(
+            repo.peer(&punished_peer),
+            Some(PeerInState::NotConnected(ncp)) if ncp.backoff_until().is_some()
+        ));
+    }
+
+    #[test]
+    fn corrupted_store_does_not_prevent_start() {
+        let db_path = format!("./tmp/peers_{}", rand::thread_rng().next_u32());
+        let peer = PeerId::random();
+        {
+            let mut repo =
+                PersistentPeerRepo::open(&db_path, RecoveryConf::default(), netw_conf(), vec![]).unwrap();
+            repo.try_add_peer(PeerDestination::PeerId(peer), false, false);
+        }
+        std::fs::write(format!("{}/CURRENT", db_path), b"garbage").unwrap();
+        let mut repo =
+            PersistentPeerRepo::open(&db_path, RecoveryConf::default(), netw_conf(), vec![]).unwrap();
+        assert!(matches!(
+            repo.recovery_report().actions.as_slice(),
+            [RecoveryAction::Repaired] | [RecoveryAction::MovedAside(_)]
+        ));
+        assert!(repo
+            .try_add_peer(PeerDestination::PeerId(PeerId::random()), false, false)
+            .is_some());
+    }
+
+    #[test]
+    fn storage_is_selected_by_config() {
+        let db_path = format!("./tmp/peers_{}", rand::thread_rng().next_u32());
+        let memory_conf = NetworkingConfig {
+            peers_storage: PeerStorage::Memory,
+            ..netw_conf()
+        };
+        assert!(matches!(
+            AnyPeerRepo::open(&db_path, RecoveryConf::default(), memory_conf, vec![]).unwrap(),
+            AnyPeerRepo::Memory(_)
+        ));
+        assert!(!std::path::Path::new(&db_path).exists());
+        assert!(matches!(
+            AnyPeerRepo::open(&db_path, RecoveryConf::default(), netw_conf(), vec![]).unwrap(),
+            AnyPeerRepo::Persistent(_)
+        ));
+    }
+}
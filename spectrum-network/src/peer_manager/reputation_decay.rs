@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use libp2p::PeerId;
+
+use crate::peer_manager::data::{ReputationChange, ReputationDecayPolicy};
+
+/// Part of the reputation of a peer caused by changes for one reason.
+#[derive(Debug, Copy, Clone)]
+struct Contribution {
+    /// Exact value after decay.
+    remaining: f64,
+    /// Value currently accounted in the reputation of the peer.
+    applied: i32,
+}
+
+/// Tracks reputation changes which didn't fade away yet.
+#[derive(Debug, Default)]
+pub struct ReputationDecay {
+    contributions: HashMap<PeerId, HashMap<ReputationChange, Contribution>>,
+}
+
+impl ReputationDecay {
+    pub fn on_change(&mut self, peer_id: PeerId, change: ReputationChange) {
+        let delta = i32::from(change);
+        let contribution = self
+            .contributions
+            .entry(peer_id)
+            .or_default()
+            .entry(change)
+            .or_insert(Contribution {
+                remaining: 0.0,
+                applied: 0,
+            });
+        contribution.remaining += delta as f64;
+        contribution.applied += delta;
+    }
+
+    /// Decay all contributions by the given amount of time.
+    /// Returns adjustments of reputations of affected peers.
+    pub fn decay(&mut self, policy: &ReputationDecayPolicy, elapsed: Duration) -> Vec<(PeerId, i32)> {
+        let mut adjustments = Vec::new();
+        for (peer_id, contributions) in self.contributions.iter_mut() {
+            let mut adjustment = 0;
+            for (reason, contribution) in contributions.iter_mut() {
+                let half_lives = elapsed.as_secs_f64() / policy.half_life(*reason).as_secs_f64();
+                contribution.remaining *= 0.5f64.powf(half_lives);
+                let applied = contribution.remaining.round() as i32;
+                adjustment += applied - contribution.applied;
+                contribution.applied = applied;
+            }
+            contributions.retain(|_, c| c.applied != 0);
+            if adjustment != 0 {
+                adjustments.push((*peer_id, adjustment));
+            }
+        }
+        self.contributions.retain(|_, cs| !cs.is_empty());
+        adjustments
+    }
+
+    pub fn forget(&mut self, peer_id: &PeerId) {
+        self.contributions.remove(peer_id);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.contributions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use libp2p::PeerId;
+
+    use crate::peer_manager::data::{ReputationChange, ReputationDecayPolicy};
+    use crate::peer_manager::reputation_decay::ReputationDecay;
+    use crate::types::Reputation;
+
+    fn policy() -> ReputationDecayPolicy {
+        ReputationDecayPolicy {
+            interval: Duration::from_secs(1),
+            default_half_life: Duration::from_secs(10),
+            overrides: vec![(ReputationChange::NoResponse, Duration::from_secs(60))],
+        }
+    }
+
+    /// Run decay in steps of `interval` for `total` time, applying adjustments to `reputation`.
+    fn run(decay: &mut ReputationDecay, reputation: &mut Reputation, total: Duration) {
+        let policy = policy();
+        let steps = total.as_secs() / policy.interval.as_secs();
+        for _ in 0..steps {
+            for (_, adjustment) in decay.decay(&policy, policy.interval) {
+                *reputation = reputation.shifted(adjustment);
+            }
+        }
+    }
+
+    #[test]
+    fn reputation_recovers_after_too_slow() {
+        let peer = PeerId::random();
+        let mut decay = ReputationDecay::default();
+        let mut reputation = Reputation::initial();
+        for _ in 0..2 {
+            reputation = reputation.apply(ReputationChange::TooSlow);
+            decay.on_change(peer, ReputationChange::TooSlow);
+        }
+        assert_eq!(reputation, Reputation::from(-20));
+        run(&mut decay, &mut reputation, Duration::from_secs(10));
+        assert_eq!(reputation, Reputation::from(-10));
+        run(&mut decay, &mut reputation, Duration::from_secs(100));
+        assert_eq!(reputation, Reputation::initial());
+        assert!(decay.is_empty());
+    }
+
+    #[test]
+    fn reputation_recovers_after_no_response_at_its_own_pace() {
+        let peer = PeerId::random();
+        let mut decay = ReputationDecay::default();
+        let mut reputation = Reputation::initial();
+        for change in [ReputationChange::NoResponse, ReputationChange::TooSlow] {
+            reputation = reputation.apply(change);
+            decay.on_change(peer, change);
+        }
+        assert_eq!(reputation, Reputation::from(-20));
+        // Penalty for slowness is gone, the one for missing response is halved only after a minute.
+        run(&mut decay, &mut reputation, Duration::from_secs(60));
+        assert_eq!(reputation, Reputation::from(-5));
+        run(&mut decay, &mut reputation, Duration::from_secs(600));
+        assert_eq!(reputation, Reputation::initial());
+        assert!(decay.is_empty());
+    }
+
+    #[test]
+    fn rewards_fade_away_too() {
+        let peer = PeerId::random();
+        let mut decay = ReputationDecay::default();
+        let mut reputation = Reputation::initial();
+        for _ in 0..4 {
+            reputation = reputation.apply(ReputationChange::UsefulModifier);
+            decay.on_change(peer, ReputationChange::UsefulModifier);
+        }
+        run(&mut decay, &mut reputation, Duration::from_secs(10));
+        assert_eq!(reputation, Reputation::from(2));
+        decay.forget(&peer);
+        assert!(decay.is_empty());
+    }
+}
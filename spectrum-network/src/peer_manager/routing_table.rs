@@ -0,0 +1,200 @@
+use std::collections::VecDeque;
+
+use libp2p::PeerId;
+
+use spectrum_crypto::digest::blake2b256_hash;
+
+use crate::peer_manager::data::PeerDestination;
+
+/// Max number of peers in a k-bucket.
+pub const K_BUCKET_SIZE: usize = 20;
+
+const KEY_BITS: usize = 256;
+
+/// Position of a peer in the key space.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+struct Key([u8; 32]);
+
+impl From<&PeerId> for Key {
+    fn from(peer_id: &PeerId) -> Self {
+        Key(*blake2b256_hash(&peer_id.to_bytes()).raw())
+    }
+}
+
+/// XOR distance between two peers in the key space.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Distance([u8; 32]);
+
+impl Distance {
+    pub fn between(a: &PeerId, b: &PeerId) -> Self {
+        Self::between_keys(&Key::from(a), &Key::from(b))
+    }
+
+    fn between_keys(a: &Key, b: &Key) -> Self {
+        let mut d = [0u8; 32];
+        for (i, byte) in d.iter_mut().enumerate() {
+            *byte = a.0[i] ^ b.0[i];
+        }
+        Distance(d)
+    }
+
+    /// Index of the k-bucket peers at this distance belong to.
+    /// `None` if the distance is zero.
+    fn bucket_ix(&self) -> Option<usize> {
+        let mut leading_zeros = 0;
+        for byte in self.0 {
+            leading_zeros += byte.leading_zeros() as usize;
+            if byte != 0 {
+                break;
+            }
+        }
+        (leading_zeros < KEY_BITS).then(|| KEY_BITS - 1 - leading_zeros)
+    }
+}
+
+/// Kademlia routing table. Known peers are arranged into k-buckets by their distance
+/// to the local node, so that the node knows many peers close to itself and a few far away ones.
+#[derive(Debug, Clone)]
+pub struct RoutingTable {
+    local_key: Key,
+    /// Each bucket is ordered from the least to the most recently seen peer.
+    buckets: Vec<VecDeque<PeerDestination>>,
+    bucket_size: usize,
+}
+
+impl RoutingTable {
+    pub fn new(local_peer_id: &PeerId, bucket_size: usize) -> Self {
+        Self {
+            local_key: Key::from(local_peer_id),
+            buckets: vec![VecDeque::new(); KEY_BITS],
+            bucket_size,
+        }
+    }
+
+    /// Insert a peer or mark it as the most recently seen one if it is already in the table.
+    /// As in Kademlia, long-lived peers are preferred: if the bucket is full, the new peer is
+    /// discarded. Returns `true` if the peer is in the table afterwards.
+    pub fn insert(&mut self, peer: PeerDestination) -> bool {
+        let Some(bucket) = self.bucket_mut(&peer.peer_id()) else {
+            return false;
+        };
+        let peer = match bucket.iter().position(|p| p.peer_id() == peer.peer_id()) {
+            Some(pos) => {
+                let prev = bucket.remove(pos).unwrap();
+                // Don't lose a known address when the peer is reported without one.
+                match peer {
+                    PeerDestination::PeerId(_) => prev,
+                    with_addr => with_addr,
+                }
+            }
+            None if bucket.len() >= self.bucket_size => return false,
+            None => peer,
+        };
+        bucket.push_back(peer);
+        true
+    }
+
+    /// Remove a peer from the table. Returns `true` if the peer was there.
+    pub fn remove(&mut self, peer_id: &PeerId) -> bool {
+        if let Some(bucket) = self.bucket_mut(peer_id) {
+            if let Some(pos) = bucket.iter().position(|p| p.peer_id() == *peer_id) {
+                bucket.remove(pos);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Up to `limit` peers closest to the `target`, nearest first.
+    pub fn closest(&self, target: &PeerId, limit: usize) -> Vec<PeerDestination> {
+        let target_key = Key::from(target);
+        let mut peers = self
+            .buckets
+            .iter()
+            .flatten()
+            .map(|p| (Distance::between_keys(&Key::from(&p.peer_id()), &target_key), p))
+            .collect::<Vec<_>>();
+        peers.sort_by_key(|(d, _)| *d);
+        peers.into_iter().take(limit).map(|(_, p)| p.clone()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.buckets.iter().map(|b| b.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn bucket_mut(&mut self, peer_id: &PeerId) -> Option<&mut VecDeque<PeerDestination>> {
+        Distance::between_keys(&self.local_key, &Key::from(peer_id))
+            .bucket_ix()
+            .map(|ix| &mut self.buckets[ix])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libp2p::PeerId;
+
+    use crate::peer_manager::data::PeerDestination;
+    use crate::peer_manager::routing_table::{Distance, RoutingTable};
+
+    #[test]
+    fn closest_peers_are_sorted_by_distance() {
+        let local = PeerId::random();
+        let mut table = RoutingTable::new(&local, 20);
+        let peers = (0..50).map(|_| PeerId::random()).collect::<Vec<_>>();
+        for p in &peers {
+            table.insert(PeerDestination::PeerId(*p));
+        }
+        let target = PeerId::random();
+        let closest = table.closest(&target, 10);
+        assert_eq!(closest.len(), 10.min(table.len()));
+        let distances = closest
+            .iter()
+            .map(|p| Distance::between(&p.peer_id(), &target))
+            .collect::<Vec<_>>();
+        let mut sorted = distances.clone();
+        sorted.sort();
+        assert_eq!(distances, sorted);
+    }
+
+    #[test]
+    fn local_peer_is_never_inserted() {
+        let local = PeerId::random();
+        let mut table = RoutingTable::new(&local, 20);
+        assert!(!table.insert(PeerDestination::PeerId(local)));
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn full_bucket_keeps_old_peers() {
+        let local = PeerId::random();
+        let mut table = RoutingTable::new(&local, 1);
+        // Half of all random peers fall into the farthest bucket.
+        let mut far_peers = (0..64)
+            .map(|_| PeerId::random())
+            .filter(|p| Distance::between(&local, p).bucket_ix() == Some(255));
+        let first = far_peers.next().unwrap();
+        let second = far_peers.next().unwrap();
+        assert!(table.insert(PeerDestination::PeerId(first)));
+        assert!(!table.insert(PeerDestination::PeerId(second)));
+        assert!(table.remove(&first));
+        assert!(table.insert(PeerDestination::PeerId(second)));
+    }
+
+    #[test]
+    fn known_address_is_preserved() {
+        let local = PeerId::random();
+        let mut table = RoutingTable::new(&local, 20);
+        let peer = PeerId::random();
+        let addr = "/ip4/127.0.0.1/tcp/8000".parse().unwrap();
+        table.insert(PeerDestination::PeerIdWithAddr(peer, addr));
+        table.insert(PeerDestination::PeerId(peer));
+        assert!(matches!(
+            table.closest(&peer, 1)[..],
+            [PeerDestination::PeerIdWithAddr(p, _)] if p == peer
+        ));
+    }
+}
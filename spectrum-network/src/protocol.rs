@@ -1,3 +1,6 @@
+use std::cmp::Reverse;
+use std::time::Duration;
+
 use either::Either;
 
 use crate::types::{ProtocolId, ProtocolVer};
@@ -8,12 +11,92 @@ pub const DIFFUSION_PROTOCOL_ID: ProtocolId = ProtocolId::from_u8(1);
 
 pub const SIGMA_AGGR_PROTOCOL_ID: ProtocolId = ProtocolId::from_u8(2);
 
+pub const GOSSIP_PROTOCOL_ID: ProtocolId = ProtocolId::from_u8(3);
+
+pub const DKG_PROTOCOL_ID: ProtocolId = ProtocolId::from_u8(4);
+
+/// Initial version of sigma aggregation protocol, contribution sets are encoded densely.
+pub const SIGMA_AGGR_V1: ProtocolVer = ProtocolVer(1);
+
+/// Sigma aggregation protocol with sparse encoding of contribution sets.
+pub const SIGMA_AGGR_V2: ProtocolVer = ProtocolVer(2);
+
+/// Sigma aggregation protocol with concurrent rounds, messages are tagged with the round they belong to.
+pub const SIGMA_AGGR_V3: ProtocolVer = ProtocolVer(3);
+
+/// Relative importance of a protocol. When a peer connects, protocols with higher priority
+/// are enabled first.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct ProtocolPriority(pub u8);
+
+impl ProtocolPriority {
+    /// Latency-critical protocols, e.g. aggregation among committee members.
+    pub const HIGH: ProtocolPriority = ProtocolPriority(u8::MAX);
+    pub const NORMAL: ProtocolPriority = ProtocolPriority(0);
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct StatefulProtocolSpec {
     /// Maximum allowed size for a single message.
     pub max_message_size: usize,
     /// Is explicit protocol approve is required.
     pub approve_required: bool,
+    /// Coalesce outbound messages into batches. Both sides batch once a version with batching
+    /// enabled is negotiated. Latency-sensitive protocols leave it `None`.
+    pub batching: Option<BatchingSpec>,
+    /// Compress large frames. Both sides compress once a version with compression enabled
+    /// is negotiated.
+    pub compression: Option<CompressionSpec>,
+}
+
+impl StatefulProtocolSpec {
+    /// Maximum allowed size of a single frame on the wire.
+    pub fn max_frame_size(&self) -> usize {
+        let frame_size = self.max_uncompressed_frame_size();
+        if self.compression.is_some() {
+            frame_size + COMPRESSION_TAG_SIZE
+        } else {
+            frame_size
+        }
+    }
+
+    /// Maximum size of a frame before compression, frames inflating beyond it are rejected.
+    pub fn max_uncompressed_frame_size(&self) -> usize {
+        if self.batching.is_some() {
+            // A message is always put into a batch envelope, even if it fills the batch alone.
+            self.max_message_size + MAX_BATCH_ENTRY_OVERHEAD
+        } else {
+            self.max_message_size
+        }
+    }
+}
+
+/// Length prefix of a message in a batch envelope is an unsigned varint.
+pub const MAX_BATCH_ENTRY_OVERHEAD: usize = 10;
+
+/// Messages are held back until the batch reaches `max_batch_size` bytes (but at most
+/// `max_message_size` of the protocol) or the oldest of them waits for `max_delay`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct BatchingSpec {
+    pub max_batch_size: usize,
+    pub max_delay: Duration,
+}
+
+/// Each frame is tagged as either compressed or not.
+pub const COMPRESSION_TAG_SIZE: usize = 1;
+
+/// Frames of at least `min_size` bytes are compressed with the given algorithm,
+/// smaller ones aren't worth it and are sent as is.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct CompressionSpec {
+    pub algorithm: CompressionAlgorithm,
+    pub min_size: usize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CompressionAlgorithm {
+    Zstd { level: i32 },
+    Lz4,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -25,12 +108,34 @@ pub struct OneShotProtocolSpec {
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct StatefulProtocolConfig {
     pub supported_versions: Vec<(ProtocolVer, StatefulProtocolSpec)>,
+    /// Versions in the order we prefer to negotiate them. Supported versions not listed here
+    /// follow, newest first. Empty list means the newest supported version is preferred.
+    pub preferred_versions: Vec<ProtocolVer>,
+    pub priority: ProtocolPriority,
+}
+
+impl StatefulProtocolConfig {
+    /// Supported versions ordered from the most to the least preferred one.
+    pub fn versions_by_preference(&self) -> Vec<(ProtocolVer, StatefulProtocolSpec)> {
+        let mut versions = self.supported_versions.clone();
+        versions.sort_by_key(|(ver, _)| {
+            let rank = self
+                .preferred_versions
+                .iter()
+                .position(|pref| pref == ver)
+                .unwrap_or(usize::MAX);
+            (rank, Reverse(u8::from(*ver)))
+        });
+        versions.dedup_by_key(|(ver, _)| *ver);
+        versions
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct OneShotProtocolConfig {
     pub version: ProtocolVer,
     pub spec: OneShotProtocolSpec,
+    pub priority: ProtocolPriority,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -38,3 +143,64 @@ pub enum ProtocolConfig {
     Stateful(StatefulProtocolConfig),
     OneShot(OneShotProtocolConfig),
 }
+
+impl ProtocolConfig {
+    pub fn priority(&self) -> ProtocolPriority {
+        match self {
+            ProtocolConfig::Stateful(conf) => conf.priority,
+            ProtocolConfig::OneShot(conf) => conf.priority,
+        }
+    }
+
+    /// All supported versions of the protocol.
+    pub fn versions(&self) -> Vec<ProtocolVer> {
+        match self {
+            ProtocolConfig::Stateful(conf) => conf.supported_versions.iter().map(|(ver, _)| *ver).collect(),
+            ProtocolConfig::OneShot(conf) => vec![conf.version],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::{ProtocolPriority, StatefulProtocolConfig, StatefulProtocolSpec};
+    use crate::types::ProtocolVer;
+
+    const SPEC: StatefulProtocolSpec = StatefulProtocolSpec {
+        max_message_size: 100,
+        approve_required: true,
+        batching: None,
+        compression: None,
+    };
+
+    fn config(supported: Vec<u8>, preferred: Vec<u8>) -> StatefulProtocolConfig {
+        StatefulProtocolConfig {
+            supported_versions: supported.into_iter().map(|v| (ProtocolVer(v), SPEC)).collect(),
+            preferred_versions: preferred.into_iter().map(ProtocolVer).collect(),
+            priority: ProtocolPriority::NORMAL,
+        }
+    }
+
+    fn versions(conf: &StatefulProtocolConfig) -> Vec<u8> {
+        conf.versions_by_preference()
+            .into_iter()
+            .map(|(ver, _)| u8::from(ver))
+            .collect()
+    }
+
+    #[test]
+    fn newest_version_is_preferred_by_default() {
+        assert_eq!(versions(&config(vec![1, 3, 2], vec![])), vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn explicit_preference_goes_first() {
+        assert_eq!(versions(&config(vec![1, 2, 3], vec![1])), vec![1, 3, 2]);
+        assert_eq!(versions(&config(vec![1, 2, 3], vec![2, 1])), vec![2, 1, 3]);
+    }
+
+    #[test]
+    fn unsupported_preferred_versions_are_ignored() {
+        assert_eq!(versions(&config(vec![1, 2], vec![5, 1])), vec![1, 2]);
+    }
+}
@@ -26,6 +26,7 @@ pub enum ProtocolEvent {
         handshake: Option<RawMessage>,
     },
     Disabled(PeerId),
+    EnableFailed(PeerId),
 }
 
 /// API to protocol handler without information about particular message/codec types.
@@ -53,6 +54,9 @@ pub trait ProtocolEvents {
 
     /// Notify protocol handler that the given protocol was enabled with the given peer.
     fn protocol_disabled(&self, peer_id: PeerId);
+
+    /// Notify protocol handler that all attempts to enable the protocol with the given peer failed.
+    fn protocol_enable_failed(&self, peer_id: PeerId);
 }
 
 #[derive(Clone)]
@@ -113,4 +117,9 @@ impl ProtocolEvents for ProtocolMailbox {
     fn protocol_disabled(&self, peer_id: PeerId) {
         let _ = futures::executor::block_on(self.events_snd.clone().send(ProtocolEvent::Disabled(peer_id)));
     }
+
+    fn protocol_enable_failed(&self, peer_id: PeerId) {
+        let _ =
+            futures::executor::block_on(self.events_snd.clone().send(ProtocolEvent::EnableFailed(peer_id)));
+    }
 }
@@ -1,5 +1,6 @@
 use std::collections::{BTreeMap, HashMap};
 use std::fmt::Debug;
+use std::future::Future;
 use std::marker::PhantomData;
 use std::pin::Pin;
 use std::task::{Context, Poll};
@@ -13,9 +14,11 @@ pub use libp2p::swarm::NetworkBehaviour;
 use libp2p::{Multiaddr, PeerId};
 use log::{error, trace};
 
+use crate::cancellation::{CancellationToken, WaitForCancellation};
 use crate::network_controller::NetworkAPI;
-use crate::peer_conn_handler::message_sink::MessageSink;
+use crate::peer_conn_handler::message_sink::{MessagePriority, MessageSink};
 use crate::peer_conn_handler::stream::FusedStream;
+use crate::peer_manager::data::ReputationChange;
 use crate::protocol_api::{ProtocolEvent, ProtocolMailbox};
 use crate::protocol_handler::versioning::Versioned;
 use crate::protocol_upgrade::handshake::PolyVerHandshakeSpec;
@@ -23,11 +26,17 @@ use crate::types::{ProtocolId, ProtocolTag, ProtocolVer, RawMessage};
 
 pub mod aggregation;
 pub mod codec;
+#[cfg(any(test, feature = "testkit"))]
+pub mod conformance;
 pub mod cosi;
 pub mod discovery;
+pub mod dkg;
+pub mod gossip;
 pub mod handel;
+pub mod inspect;
 pub mod multicasting;
 pub mod pool;
+pub mod request_response;
 pub mod sigma_aggregation;
 pub mod versioning;
 pub mod void;
@@ -57,6 +66,8 @@ pub enum NetworkAction<THandshake, TMessage> {
     },
     /// Ban peer.
     BanPeer(PeerId),
+    /// Adjust reputation of the peer, e.g. punish it for misbehaviour short of a ban.
+    ReportPeer(PeerId, ReputationChange),
 }
 
 #[derive(Debug)]
@@ -102,6 +113,7 @@ impl<'a, THandshake, TMessage> Bifunctor<'a, THandshake, TMessage>
                     message: right(message),
                 },
                 NetworkAction::BanPeer(peer) => NetworkAction::BanPeer(peer),
+                NetworkAction::ReportPeer(peer, change) => NetworkAction::ReportPeer(peer, change),
             }),
         }
     }
@@ -116,6 +128,11 @@ pub enum ProtocolHandlerError {
 pub trait ProtocolSpec {
     type THandshake: serde::Serialize + for<'de> serde::Deserialize<'de> + Versioned + Send;
     type TMessage: serde::Serialize + for<'de> serde::Deserialize<'de> + Versioned + Debug + Send + Clone;
+
+    /// Lane the message is sent to peers in.
+    fn message_priority(_message: &Self::TMessage) -> MessagePriority {
+        MessagePriority::Bulk
+    }
 }
 
 impl<L, R> ProtocolSpec for Either<L, R>
@@ -125,6 +142,13 @@ where
 {
     type THandshake = Either<L::THandshake, R::THandshake>;
     type TMessage = Either<L::TMessage, R::TMessage>;
+
+    fn message_priority(message: &Self::TMessage) -> MessagePriority {
+        match message {
+            Either::Left(msg) => L::message_priority(msg),
+            Either::Right(msg) => R::message_priority(msg),
+        }
+    }
 }
 
 /// Defines behaviour of particular stages of a protocol that terminates with `TOut`,
@@ -167,9 +191,11 @@ pub trait ProtocolBehaviour {
     fn inject_message(&mut self, peer_id: PeerId, content: <Self::TProto as ProtocolSpec>::TMessage) {}
 
     /// Inject protocol request coming from a peer.
+    /// `protocol_ver` is the version negotiated with the peer.
     fn inject_protocol_requested(
         &mut self,
         peer_id: PeerId,
+        protocol_ver: ProtocolVer,
         handshake: Option<<Self::TProto as ProtocolSpec>::THandshake>,
     ) {
     }
@@ -178,9 +204,11 @@ pub trait ProtocolBehaviour {
     fn inject_protocol_requested_locally(&mut self, peer_id: PeerId) {}
 
     /// Inject an event of protocol being enabled with a peer.
+    /// `protocol_ver` is the version negotiated with the peer.
     fn inject_protocol_enabled(
         &mut self,
         peer_id: PeerId,
+        protocol_ver: ProtocolVer,
         handshake: Option<<Self::TProto as ProtocolSpec>::THandshake>,
     ) {
     }
@@ -188,6 +216,13 @@ pub trait ProtocolBehaviour {
     /// Inject an event of protocol being disabled with a peer.
     fn inject_protocol_disabled(&mut self, peer_id: PeerId) {}
 
+    /// Inject an event of all attempts to enable protocol with a peer being failed.
+    fn inject_protocol_enable_failed(&mut self, peer_id: PeerId) {}
+
+    /// Inject an event of the handler being cancelled, see [`ProtocolHandler::with_cancellation`].
+    /// In-flight work is expected to be wound up, the behaviour isn't polled afterwards.
+    fn inject_cancelled(&mut self) {}
+
     /// Poll for output actions.
     fn poll(
         &mut self,
@@ -247,6 +282,8 @@ pub struct ProtocolHandler<TBehaviour, TNetwork> {
     pub protocol: ProtocolId,
     behaviour: TBehaviour,
     network: TNetwork,
+    cancelled: Option<WaitForCancellation>,
+    terminated: bool,
 }
 
 impl<TBehaviour, TNetwork> ProtocolHandler<TBehaviour, TNetwork> {
@@ -264,9 +301,17 @@ impl<TBehaviour, TNetwork> ProtocolHandler<TBehaviour, TNetwork> {
             protocol,
             behaviour,
             network,
+            cancelled: None,
+            terminated: false,
         };
         (prot_handler, prot_mailbox)
     }
+
+    /// Terminate the handler once the given token is cancelled.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancelled = Some(token.cancelled());
+        self
+    }
 }
 
 impl<TBehaviour, TNetwork> Stream for ProtocolHandler<TBehaviour, TNetwork>
@@ -282,6 +327,17 @@ where
     /// Polls the behaviour and the network, forwarding events from the former to the latter and
     /// vice versa.
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        if self.terminated {
+            return Poll::Ready(None);
+        }
+        if let Some(cancelled) = &mut self.cancelled {
+            if Future::poll(Pin::new(cancelled), cx).is_ready() {
+                trace!("Handler of protocol {:?} is cancelled", self.protocol);
+                self.terminated = true;
+                self.behaviour.inject_cancelled();
+                return Poll::Ready(None);
+            }
+        }
         loop {
             // 1. Poll behaviour for commands
             // (1) is polled before (2) to prioritize local work over incoming requests/events.
@@ -292,7 +348,11 @@ where
                             trace!("Sending message {:?} to peer {}", message, peer_id);
                             if let Some(sink) = self.peers.get(&peer_id) {
                                 trace!("Sink is available");
-                                if let Err(_) = sink.send_message(codec::encode(message.clone())) {
+                                let priority =
+                                    <TBehaviour::TProto as ProtocolSpec>::message_priority(&message);
+                                if let Err(_) =
+                                    sink.send_message_with_priority(codec::encode(message.clone()), priority)
+                                {
                                     trace!("Failed to submit a message to {:?}. Channel is closed.", peer_id)
                                 }
                                 trace!("Sent");
@@ -330,11 +390,16 @@ where
                                     .send_one_shot_message(peer, addr_hint, protocol, message_bytes);
                             }
                             NetworkAction::BanPeer(pid) => self.network.ban_peer(pid),
+                            NetworkAction::ReportPeer(pid, change) => self.network.report_peer(pid, change),
                         },
                     }
                     continue;
                 }
-                Poll::Ready(None) => return Poll::Ready(None), // terminate, behaviour is exhausted
+                Poll::Ready(None) => {
+                    // terminate, behaviour is exhausted
+                    self.terminated = true;
+                    return Poll::Ready(None);
+                }
                 Poll::Pending => {}
             }
 
@@ -350,18 +415,30 @@ where
                         protocol_ver: negotiated_ver,
                         content,
                     } => {
-                        if let Ok(msg) = codec::decode::<
+                        match codec::decode::<
                             <<TBehaviour as ProtocolBehaviour>::TProto as ProtocolSpec>::TMessage,
                         >(content)
                         {
-                            let actual_ver = msg.version();
-                            if actual_ver == negotiated_ver {
-                                self.behaviour.inject_message(peer_id, msg);
-                            } else {
+                            Ok(msg) => {
+                                let actual_ver = msg.version();
+                                if actual_ver == negotiated_ver {
+                                    self.behaviour.inject_message(peer_id, msg);
+                                } else {
+                                    #[cfg(feature = "audit")]
+                                    log::warn!(
+                                        "Message of version {:?} from {:?} negotiated {:?}",
+                                        actual_ver,
+                                        peer_id,
+                                        negotiated_ver
+                                    );
+                                    self.network.ban_peer(peer_id);
+                                }
+                            }
+                            Err(_err) => {
+                                #[cfg(feature = "audit")]
+                                log::warn!("Undecodable message from {:?}: {}", peer_id, _err);
                                 self.network.ban_peer(peer_id);
                             }
-                        } else {
-                            self.network.ban_peer(peer_id);
                         }
                     }
                     ProtocolEvent::Requested {
@@ -377,13 +454,19 @@ where
                             Some(Ok(hs)) => {
                                 let actual_ver = hs.version();
                                 if actual_ver == negotiated_ver {
-                                    self.behaviour.inject_protocol_requested(peer_id, Some(hs));
+                                    self.behaviour.inject_protocol_requested(
+                                        peer_id,
+                                        negotiated_ver,
+                                        Some(hs),
+                                    );
                                 } else {
                                     self.network.ban_peer(peer_id);
                                 }
                             }
                             Some(Err(_)) => self.network.ban_peer(peer_id),
-                            None => self.behaviour.inject_protocol_requested(peer_id, None),
+                            None => self
+                                .behaviour
+                                .inject_protocol_requested(peer_id, negotiated_ver, None),
                         }
                     }
                     ProtocolEvent::RequestedLocal(peer_id) => {
@@ -404,18 +487,25 @@ where
                             Some(Ok(hs)) => {
                                 let actual_ver = hs.version();
                                 if actual_ver == negotiated_ver {
-                                    self.behaviour.inject_protocol_enabled(peer_id, Some(hs));
+                                    self.behaviour
+                                        .inject_protocol_enabled(peer_id, negotiated_ver, Some(hs));
                                 } else {
                                     self.network.ban_peer(peer_id);
                                 }
                             }
                             Some(Err(_)) => self.network.ban_peer(peer_id),
-                            None => self.behaviour.inject_protocol_enabled(peer_id, None),
+                            None => self
+                                .behaviour
+                                .inject_protocol_enabled(peer_id, negotiated_ver, None),
                         }
                     }
                     ProtocolEvent::Disabled(peer_id) => {
+                        self.peers.remove(&peer_id);
                         self.behaviour.inject_protocol_disabled(peer_id);
                     }
+                    ProtocolEvent::EnableFailed(peer_id) => {
+                        self.behaviour.inject_protocol_enable_failed(peer_id);
+                    }
                 }
                 continue;
             }
@@ -425,12 +515,79 @@ where
     }
 }
 
-/// The stream of protocol events never terminates, so we can implement fused for it.
+/// The stream of protocol events terminates only once cancelled, so we can implement fused for it.
 impl<TBehaviour, TNetwork> FusedStream for ProtocolHandler<TBehaviour, TNetwork>
 where
     Self: Stream,
 {
     fn is_terminated(&self) -> bool {
-        false
+        self.terminated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use futures::channel::mpsc;
+    use futures::task::noop_waker_ref;
+    use futures::Stream;
+    use libp2p::PeerId;
+
+    use crate::network_controller::NetworkMailbox;
+    use crate::peer_conn_handler::message_sink::{channel, LaneBufferSizes};
+    use crate::protocol_api::ProtocolEvents;
+    use crate::protocol_handler::versioning::Versioned;
+    use crate::protocol_handler::{ProtocolBehaviour, ProtocolBehaviourOut, ProtocolHandler, ProtocolSpec};
+    use crate::types::{ProtocolId, ProtocolVer};
+
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+    struct Ping;
+
+    impl Versioned for Ping {
+        fn version(&self) -> ProtocolVer {
+            ProtocolVer::from(1)
+        }
+    }
+
+    struct PingSpec;
+
+    impl ProtocolSpec for PingSpec {
+        type THandshake = Ping;
+        type TMessage = Ping;
+    }
+
+    struct IdleBehaviour;
+
+    impl ProtocolBehaviour for IdleBehaviour {
+        type TProto = PingSpec;
+
+        fn poll(&mut self, _cx: &mut Context<'_>) -> Poll<Option<ProtocolBehaviourOut<Ping, Ping>>> {
+            Poll::Pending
+        }
+    }
+
+    #[test]
+    fn sink_is_dropped_once_protocol_is_disabled() {
+        let (network_snd, _network_recv) = mpsc::channel(10);
+        let network = NetworkMailbox {
+            mailbox_snd: network_snd,
+        };
+        let (mut handler, mailbox) = ProtocolHandler::new(IdleBehaviour, network, ProtocolId::from(1), 10);
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let peer_id = PeerId::random();
+        let buffer_sizes = LaneBufferSizes {
+            control: 1,
+            bulk_async: 1,
+            bulk_sync: 1,
+        };
+        let (sink, _lanes) = channel(peer_id, buffer_sizes);
+        mailbox.protocol_enabled(peer_id, ProtocolVer::from(1), sink, None);
+        let _ = Stream::poll_next(Pin::new(&mut handler), &mut cx);
+        assert!(handler.peers.contains_key(&peer_id));
+        mailbox.protocol_disabled(peer_id);
+        let _ = Stream::poll_next(Pin::new(&mut handler), &mut cx);
+        assert!(!handler.peers.contains_key(&peer_id));
     }
 }
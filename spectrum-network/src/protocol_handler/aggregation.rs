@@ -14,6 +14,11 @@ pub enum AggregationAction<H: HashMarker + FixedOutput> {
     Reset {
         new_committee: HashMap<PublicKey, Option<Multiaddr>>,
         new_message: Digest<H>,
+        /// Members known to be lost. They are treated as byzantine from the start of the round.
+        excluded_members: HashSet<PublicKey>,
+        /// Weights (e.g. stake) of members, thresholds are evaluated over the total weight.
+        /// Members not listed weigh 1, so an empty map stands for a committee of equals.
+        member_weights: HashMap<PublicKey, usize>,
         channel: Sender<Result<Aggregated<H>, ()>>,
     },
 }
@@ -0,0 +1,268 @@
+//! Differential conformance testing of wire formats across releases.
+//!
+//! A transcript holds frames exchanged by protocols as they were encoded by some release of the
+//! node, together with the representation of the messages they decoded to at the time. Checking
+//! the transcript against the current build reveals frames that no longer decode, decode to a
+//! different message or are encoded differently now. Transcripts of released versions are kept in
+//! `tests/conformance_corpus` of the crates defining the protocols.
+
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::protocol_handler::codec;
+use crate::types::{ProtocolId, ProtocolVer, RawMessage};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub protocol: ProtocolId,
+    pub version: u8,
+    #[serde(with = "serde_bytes")]
+    pub bytes: Vec<u8>,
+    /// `Debug` representation of the message at the time of recording.
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Transcript {
+    /// Release of the node the transcript was recorded with.
+    pub release: String,
+    pub frames: Vec<Frame>,
+}
+
+/// Difference in the handling of a recorded frame by the current build.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Divergence {
+    /// The frame can't be decoded anymore.
+    Undecodable { frame: usize, error: String },
+    /// The frame decodes to a different message.
+    Semantic {
+        frame: usize,
+        recorded: String,
+        actual: String,
+    },
+    /// The message is encoded differently now.
+    WireFormat { frame: usize },
+}
+
+impl Transcript {
+    pub fn new(release: impl Into<String>) -> Self {
+        Self {
+            release: release.into(),
+            frames: vec![],
+        }
+    }
+
+    /// Encode the message as it is sent over the wire and append it to the transcript.
+    pub fn record<T: Serialize + Debug>(&mut self, protocol: ProtocolId, version: ProtocolVer, msg: &T) {
+        self.frames.push(Frame {
+            protocol,
+            version: u8::from(version),
+            bytes: codec::encode(msg).into(),
+            message: format!("{:?}", msg),
+        });
+    }
+
+    /// Check frames of the given protocol against the message type `T` of the current build.
+    pub fn check<T>(&self, protocol: ProtocolId) -> Vec<Divergence>
+    where
+        T: Serialize + DeserializeOwned + Debug,
+    {
+        let mut divergences = vec![];
+        for (ix, frame) in self.frames.iter().enumerate() {
+            if frame.protocol != protocol {
+                continue;
+            }
+            match codec::decode::<T>(RawMessage::from(frame.bytes.clone())) {
+                Ok(msg) => {
+                    let actual = format!("{:?}", msg);
+                    if actual != frame.message {
+                        divergences.push(Divergence::Semantic {
+                            frame: ix,
+                            recorded: frame.message.clone(),
+                            actual,
+                        });
+                    } else if Vec::<u8>::from(codec::encode(&msg)) != frame.bytes {
+                        divergences.push(Divergence::WireFormat { frame: ix });
+                    }
+                }
+                Err(err) => divergences.push(Divergence::Undecodable {
+                    frame: ix,
+                    error: err.to_string(),
+                }),
+            }
+        }
+        divergences
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let file = std::fs::File::create(path)?;
+        ciborium::ser::into_writer(self, file)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        ciborium::de::from_reader(file)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))
+    }
+}
+
+/// Load all transcripts (`*.cbor` files) from the given directory.
+/// A missing directory is treated as an empty corpus.
+pub fn load_corpus(dir: impl AsRef<Path>) -> std::io::Result<Vec<(PathBuf, Transcript)>> {
+    let mut corpus = vec![];
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(corpus),
+        Err(err) => return Err(err),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().map_or(false, |ext| ext == "cbor") {
+            let transcript = Transcript::load(&path)?;
+            corpus.push((path, transcript));
+        }
+    }
+    corpus.sort_by(|(p1, _), (p2, _)| p1.cmp(p2));
+    Ok(corpus)
+}
+
+#[cfg(test)]
+mod tests {
+    use libp2p::PeerId;
+
+    use crate::peer_manager::data::PeerDestination;
+    use crate::protocol::{DISCOVERY_PROTOCOL_ID, SIGMA_AGGR_PROTOCOL_ID};
+    use crate::protocol_handler::conformance::{load_corpus, Divergence, Transcript};
+    use crate::protocol_handler::discovery::message::{
+        DiscoveryMessage, DiscoveryMessageV1, DiscoveryMessageV2, DiscoverySpec,
+    };
+    use crate::protocol_handler::handel::Threshold;
+    use crate::protocol_handler::sigma_aggregation::message::SigmaAggrMessage;
+    use crate::protocol_handler::sigma_aggregation::sim::{record_round, RoundSetup};
+    use crate::types::ProtocolVer;
+    use spectrum_crypto::digest::blake2b256_hash;
+
+    const CORPUS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/conformance_corpus");
+
+    /// Traffic of all protocols defined in this crate as encoded by the current build.
+    async fn record_transcript() -> Transcript {
+        let mut transcript = Transcript::new(env!("CARGO_PKG_VERSION"));
+        for msg in [
+            DiscoveryMessageV1::GetPeers,
+            DiscoveryMessageV1::Peers(vec![
+                PeerDestination::PeerId(PeerId::random()),
+                PeerDestination::PeerIdWithAddr(PeerId::random(), "/ip4/127.0.0.1/tcp/8000".parse().unwrap()),
+            ]),
+        ] {
+            transcript.record(
+                DISCOVERY_PROTOCOL_ID,
+                DiscoverySpec::v1(),
+                &DiscoveryMessage::DiscoveryMessageV1(msg),
+            );
+        }
+        let target = PeerId::random();
+        for msg in [
+            DiscoveryMessageV2::GetPeers,
+            DiscoveryMessageV2::Peers(vec![PeerDestination::PeerId(PeerId::random())]),
+            DiscoveryMessageV2::FindNode { target },
+            DiscoveryMessageV2::Nodes {
+                target,
+                peers: vec![PeerDestination::PeerIdWithAddr(
+                    PeerId::random(),
+                    "/ip4/127.0.0.1/tcp/8001".parse().unwrap(),
+                )],
+            },
+        ] {
+            transcript.record(
+                DISCOVERY_PROTOCOL_ID,
+                DiscoverySpec::v2(),
+                &DiscoveryMessage::DiscoveryMessageV2(msg),
+            );
+        }
+        let round = record_round(RoundSetup {
+            description: String::from("conformance"),
+            member_seeds: (0..4).map(|i| [i; 32]).collect(),
+            message: blake2b256_hash(b"conformance"),
+            threshold: Threshold { num: 2, denom: 3 },
+            partitioning_seed: [0; 32],
+            faults: vec![],
+            excluded: vec![],
+            max_passes: 500,
+        })
+        .await;
+        for rec in round.messages {
+            let ver = match rec.message {
+                SigmaAggrMessage::SigmaAggrMessageV1(_) => ProtocolVer::from(1),
+                SigmaAggrMessage::SigmaAggrMessageV2(_) => ProtocolVer::from(2),
+                SigmaAggrMessage::SigmaAggrMessageV3(_) => ProtocolVer::from(3),
+            };
+            transcript.record(SIGMA_AGGR_PROTOCOL_ID, ver, &rec.message);
+        }
+        transcript
+    }
+
+    fn check_all(transcript: &Transcript) -> Vec<Divergence> {
+        let mut divergences = transcript.check::<DiscoveryMessage>(DISCOVERY_PROTOCOL_ID);
+        divergences.extend(transcript.check::<SigmaAggrMessage>(SIGMA_AGGR_PROTOCOL_ID));
+        divergences
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn current_build_conforms_to_itself() {
+        let transcript = record_transcript().await;
+        let path = std::env::temp_dir().join("conformance_transcript.cbor");
+        transcript.save(&path).unwrap();
+        assert_eq!(check_all(&Transcript::load(&path).unwrap()), vec![]);
+    }
+
+    #[test]
+    fn divergence_is_detected() {
+        let mut transcript = Transcript::new("test");
+        transcript.record(
+            DISCOVERY_PROTOCOL_ID,
+            DiscoverySpec::v1(),
+            &DiscoveryMessage::DiscoveryMessageV1(DiscoveryMessageV1::GetPeers),
+        );
+        let mut tampered = transcript.clone();
+        tampered.frames[0].message = String::from("DiscoveryMessageV1(Peers([]))");
+        assert!(matches!(
+            tampered.check::<DiscoveryMessage>(DISCOVERY_PROTOCOL_ID)[..],
+            [Divergence::Semantic { frame: 0, .. }]
+        ));
+        assert!(matches!(
+            transcript.check::<SigmaAggrMessage>(DISCOVERY_PROTOCOL_ID)[..],
+            [Divergence::Undecodable { frame: 0, .. }]
+        ));
+    }
+
+    /// Traffic recorded with previous releases must be handled by the current build identically.
+    #[test]
+    fn replay_corpus() {
+        let corpus = load_corpus(CORPUS_DIR).unwrap();
+        assert!(!corpus.is_empty(), "Empty corpus");
+        for (path, transcript) in corpus {
+            assert_eq!(
+                check_all(&transcript),
+                vec![],
+                "Protocol change against release {} ({:?})",
+                transcript.release,
+                path
+            );
+        }
+    }
+
+    /// Pin the wire format of the current release. Run before cutting a release.
+    #[tokio::test(start_paused = true)]
+    #[ignore]
+    async fn record_release_transcript() {
+        let transcript = record_transcript().await;
+        std::fs::create_dir_all(CORPUS_DIR).unwrap();
+        transcript
+            .save(format!("{}/{}.cbor", CORPUS_DIR, transcript.release))
+            .unwrap();
+    }
+}
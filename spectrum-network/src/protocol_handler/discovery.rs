@@ -2,24 +2,47 @@ use std::collections::{HashMap, VecDeque};
 use std::future::Future;
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
 use derive_more::Display;
+use futures::channel::mpsc::{self, Receiver};
+use futures::channel::oneshot::{self, Sender};
 use futures::stream::FuturesOrdered;
-use futures::Stream;
-use libp2p::PeerId;
+use futures::{SinkExt, Stream};
+use libp2p::{Multiaddr, PeerId};
 use log::{error, info, trace};
+use wasm_timer::Delay;
 
+use crate::features::FeatureFlags;
+use crate::peer_manager::data::PeerDestination;
 use crate::peer_manager::Peers;
+use crate::protocol_handler::discovery::external_addr::{ExternalAddrConf, ExternalAddrs};
+use crate::protocol_handler::discovery::lookup::{Lookup, LOOKUP_K};
 use crate::protocol_handler::discovery::message::{
-    DiscoveryHandshake, DiscoveryMessage, DiscoveryMessageV1, DiscoverySpec, HandshakeV1,
+    DiscoveryHandshake, DiscoveryMessage, DiscoveryMessageV1, DiscoveryMessageV2, DiscoverySpec, HandshakeV1,
 };
+use crate::protocol_handler::versioning::Versioned;
 use crate::protocol_handler::{NetworkAction, ProtocolBehaviour, ProtocolBehaviourOut, ProtocolSpec};
-use crate::types::{ProtocolId, ProtocolVer};
+use crate::types::{NetworkId, ProtocolId, ProtocolVer};
 
+pub mod external_addr;
+mod lookup;
 pub mod message;
 
 const MAX_SHARED_PEERS: usize = 128;
 
+/// Lookups which haven't found the target in this time are given up.
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Feature guarding peer lookups, see [`DiscoveryBehaviour::with_feature_flags`].
+pub const LOOKUPS_FEATURE: &str = "discovery_lookups";
+
+/// Peer lookups were introduced in V2 of the protocol.
+/// Note, `ProtocolVer` is ordered newest first, so raw versions are compared here.
+fn supports_lookups(ver: ProtocolVer) -> bool {
+    u8::from(ver) >= u8::from(DiscoverySpec::v2())
+}
+
 #[derive(Clone)]
 pub struct NodeStatus {
     pub supported_protocols: Vec<ProtocolId>,
@@ -34,16 +57,78 @@ pub enum DiscoveryBehaviorError {
     OperationCancelled,
 }
 
-type DiscoveryTask =
-    Pin<Box<dyn Future<Output = Result<DiscoveryBehaviourOut, DiscoveryBehaviorError>> + Send>>;
+pub enum DiscoveryRequest {
+    /// Locate the peer with the given id without flooding the network.
+    /// Resolves to the destination of the peer if it was found.
+    FindPeer {
+        target: PeerId,
+        channel: Sender<Option<PeerDestination>>,
+    },
+    /// The given peer sees the local node at the given address.
+    AddressObserved { observer: PeerId, addr: Multiaddr },
+}
+
+/// API to a running [`DiscoveryBehaviour`], see [`DiscoveryBehaviour::with_inbox`].
+#[derive(Clone)]
+pub struct DiscoveryMailbox {
+    mailbox_snd: mpsc::Sender<DiscoveryRequest>,
+}
+
+impl DiscoveryMailbox {
+    /// Create the mailbox along with the inbox to be handed over to the behaviour.
+    pub fn new(buffer_size: usize) -> (Self, Receiver<DiscoveryRequest>) {
+        let (mailbox_snd, inbox) = mpsc::channel(buffer_size);
+        (Self { mailbox_snd }, inbox)
+    }
+
+    /// Locate the peer with the given id, e.g. a committee member, by iteratively querying
+    /// peers closest to it. Resolves to `None` if the peer wasn't found in time.
+    pub async fn find_peer(&mut self, target: PeerId) -> Option<PeerDestination> {
+        let (channel, result) = oneshot::channel();
+        self.mailbox_snd
+            .send(DiscoveryRequest::FindPeer { target, channel })
+            .await
+            .ok()?;
+        result.await.ok().flatten()
+    }
+
+    /// Report the address the given peer sees the local node at.
+    pub async fn address_observed(&mut self, observer: PeerId, addr: Multiaddr) {
+        let _ = self
+            .mailbox_snd
+            .send(DiscoveryRequest::AddressObserved { observer, addr })
+            .await;
+    }
+}
+
+enum DiscoveryTaskOut {
+    Out(DiscoveryBehaviourOut),
+    /// Peers closest to the target of a lookup known locally.
+    LookupSeeds {
+        target: PeerId,
+        peers: Vec<PeerDestination>,
+    },
+}
+
+type DiscoveryTask = Pin<Box<dyn Future<Output = Result<DiscoveryTaskOut, DiscoveryBehaviorError>> + Send>>;
 
 pub struct DiscoveryBehaviour<TPeers> {
     local_status: NodeStatus,
     outbox: VecDeque<DiscoveryBehaviourOut>,
     tracked_peers: HashMap<PeerId, NodeStatus>,
+    /// Negotiated version of the protocol with each peer.
+    peer_versions: HashMap<PeerId, ProtocolVer>,
     // ideally tasks should be ordered in the scope of one peer.
     tasks: FuturesOrdered<DiscoveryTask>,
     peers: TPeers,
+    inbox: Option<Receiver<DiscoveryRequest>>,
+    /// Lookups in progress by target.
+    lookups: HashMap<PeerId, Lookup>,
+    next_lookups_expiration: Option<Delay>,
+    /// Id of the local node and its external addresses shared along with known peers.
+    external_addrs: Option<(PeerId, ExternalAddrs)>,
+    features: Option<FeatureFlags>,
+    network_id: Option<NetworkId>,
 }
 
 impl<TPeers> DiscoveryBehaviour<TPeers>
@@ -55,50 +140,283 @@ where
             local_status,
             outbox: VecDeque::new(),
             tracked_peers: HashMap::new(),
+            peer_versions: HashMap::new(),
             tasks: FuturesOrdered::new(),
             peers,
+            inbox: None,
+            lookups: HashMap::new(),
+            next_lookups_expiration: None,
+            external_addrs: None,
+            features: None,
+            network_id: None,
         }
     }
 
+    /// Advertise external addresses of the local node chosen according to the given config
+    /// along with known peers.
+    pub fn with_external_addrs(mut self, local_peer_id: PeerId, conf: ExternalAddrConf) -> Self {
+        self.external_addrs = Some((local_peer_id, ExternalAddrs::new(conf)));
+        self
+    }
+
+    /// Records of the local node to share with other peers.
+    fn local_records(&self) -> Vec<PeerDestination> {
+        self.external_addrs
+            .as_ref()
+            .map_or(Vec::new(), |(local_peer_id, addrs)| {
+                addrs
+                    .advertised()
+                    .into_iter()
+                    .map(|addr| PeerDestination::PeerIdWithAddr(*local_peer_id, addr))
+                    .collect()
+            })
+    }
+
+    /// Issue and serve peer lookups only once [`LOOKUPS_FEATURE`] is active.
+    /// Lookups are always enabled otherwise.
+    pub fn with_feature_flags(mut self, features: FeatureFlags) -> Self {
+        self.features = Some(features);
+        self
+    }
+
+    fn lookups_enabled(&self) -> bool {
+        self.features
+            .as_ref()
+            .map_or(true, |features| features.is_enabled(LOOKUPS_FEATURE))
+    }
+
+    /// Announce the network the local node belongs to in handshakes and ban peers
+    /// announcing a different one. Peers announcing no network are tolerated.
+    pub fn with_network_id(mut self, network_id: NetworkId) -> Self {
+        self.network_id = Some(network_id);
+        self
+    }
+
+    /// Accept [`DiscoveryRequest`]s from the given inbox.
+    pub fn with_inbox(mut self, inbox: Receiver<DiscoveryRequest>) -> Self {
+        self.inbox = Some(inbox);
+        self
+    }
+
     fn make_poly_handshake(&self) -> Vec<(ProtocolVer, Option<DiscoveryHandshake>)> {
         let status = &self.local_status;
-        vec![(
-            DiscoverySpec::v1(),
-            Some(DiscoveryHandshake::HandshakeV1(HandshakeV1 {
-                supported_protocols: status.supported_protocols.clone(),
-                height: status.height,
-            })),
-        )]
+        let hs = HandshakeV1 {
+            supported_protocols: status.supported_protocols.clone(),
+            height: status.height,
+            network_id: self.network_id,
+        };
+        vec![
+            (
+                DiscoverySpec::v2(),
+                Some(DiscoveryHandshake::HandshakeV2(hs.clone())),
+            ),
+            (DiscoverySpec::v1(), Some(DiscoveryHandshake::HandshakeV1(hs))),
+        ]
+    }
+
+    /// Track the peer unless it belongs to a different network, in which case the peer is banned.
+    /// Returns `false` if the peer was banned.
+    fn track_peer(&mut self, peer_id: PeerId, handshake: DiscoveryHandshake) -> bool {
+        let (DiscoveryHandshake::HandshakeV1(hs) | DiscoveryHandshake::HandshakeV2(hs)) = handshake;
+        if let (Some(local), Some(remote)) = (self.network_id, hs.network_id) {
+            if local != remote {
+                info!("Peer {} belongs to network {}, banning", peer_id, remote);
+                self.outbox
+                    .push_back(ProtocolBehaviourOut::NetworkAction(NetworkAction::BanPeer(
+                        peer_id,
+                    )));
+                return false;
+            }
+        }
+        self.tracked_peers.insert(
+            peer_id,
+            NodeStatus {
+                supported_protocols: hs.supported_protocols,
+                height: hs.height,
+            },
+        );
+        true
+    }
+
+    fn supports_lookups(&self, peer_id: &PeerId) -> bool {
+        self.peer_versions
+            .get(peer_id)
+            .map_or(false, |ver| supports_lookups(*ver))
     }
 
     fn send_get_peers(&mut self, peer_id: PeerId) {
         trace!("Requesting peers from {}", peer_id);
-        self.outbox.push_back(DiscoveryBehaviourOut::Send {
-            peer_id,
-            message: DiscoveryMessage::DiscoveryMessageV1(DiscoveryMessageV1::GetPeers),
-        });
+        let message = if self.supports_lookups(&peer_id) {
+            DiscoveryMessage::DiscoveryMessageV2(DiscoveryMessageV2::GetPeers)
+        } else {
+            DiscoveryMessage::DiscoveryMessageV1(DiscoveryMessageV1::GetPeers)
+        };
+        self.outbox
+            .push_back(DiscoveryBehaviourOut::Send { peer_id, message });
     }
 
     fn send_peers(&mut self, peer_id: PeerId) {
         trace!("Sharing known peers with {}", peer_id);
-        let get_peers_fut = self.peers.get_peers(MAX_SHARED_PEERS);
+        let local_records = self.local_records();
+        let get_peers_fut = self
+            .peers
+            .get_peers(MAX_SHARED_PEERS.saturating_sub(local_records.len()));
+        let use_v2 = self.supports_lookups(&peer_id);
         self.tasks.push_back(Box::pin({
             async move {
                 trace!("Waiting for peers");
                 if let Ok(peers) = get_peers_fut.await {
                     trace!("My peers num {}", peers.len());
-                    Ok(ProtocolBehaviourOut::Send {
+                    let peers = local_records
+                        .into_iter()
+                        .chain(peers.into_iter().filter(|p| p.peer_id() != peer_id))
+                        .collect();
+                    let message = if use_v2 {
+                        DiscoveryMessage::DiscoveryMessageV2(DiscoveryMessageV2::Peers(peers))
+                    } else {
+                        DiscoveryMessage::DiscoveryMessageV1(DiscoveryMessageV1::Peers(peers))
+                    };
+                    Ok(DiscoveryTaskOut::Out(ProtocolBehaviourOut::Send {
                         peer_id,
-                        message: DiscoveryMessage::DiscoveryMessageV1(DiscoveryMessageV1::Peers(
-                            peers.into_iter().filter(|p| p.peer_id() != peer_id).collect(),
-                        )),
-                    })
+                        message,
+                    }))
+                } else {
+                    Err(DiscoveryBehaviorError::OperationCancelled)
+                }
+            }
+        }));
+    }
+
+    fn send_nodes(&mut self, peer_id: PeerId, target: PeerId) {
+        trace!("Sharing peers closest to {} with {}", target, peer_id);
+        let find_closest_fut = self.peers.find_closest_peers(target, LOOKUP_K);
+        self.tasks.push_back(Box::pin({
+            async move {
+                if let Ok(peers) = find_closest_fut.await {
+                    Ok(DiscoveryTaskOut::Out(ProtocolBehaviourOut::Send {
+                        peer_id,
+                        message: DiscoveryMessage::DiscoveryMessageV2(DiscoveryMessageV2::Nodes {
+                            target,
+                            peers: peers.into_iter().filter(|p| p.peer_id() != peer_id).collect(),
+                        }),
+                    }))
+                } else {
+                    Err(DiscoveryBehaviorError::OperationCancelled)
+                }
+            }
+        }));
+    }
+
+    fn find_peer(&mut self, target: PeerId, channel: Sender<Option<PeerDestination>>) {
+        if !self.lookups_enabled() {
+            trace!("Lookups aren't activated yet, {} can't be found", target);
+            let _ = channel.send(None);
+            return;
+        }
+        if let Some(lookup) = self.lookups.get_mut(&target) {
+            lookup.subscribe(channel);
+            return;
+        }
+        trace!("Starting lookup of {}", target);
+        let mut lookup = Lookup::new(target, Instant::now() + LOOKUP_TIMEOUT);
+        lookup.subscribe(channel);
+        self.lookups.insert(target, lookup);
+        if self.next_lookups_expiration.is_none() {
+            self.next_lookups_expiration = Some(Delay::new(LOOKUP_TIMEOUT));
+        }
+        let find_closest_fut = self.peers.find_closest_peers(target, LOOKUP_K);
+        // Peers we can talk to right away are always worth asking.
+        let enabled_peers = self
+            .peer_versions
+            .keys()
+            .filter(|pid| self.supports_lookups(pid))
+            .map(|pid| PeerDestination::PeerId(*pid))
+            .collect::<Vec<_>>();
+        self.tasks.push_back(Box::pin({
+            async move {
+                if let Ok(mut peers) = find_closest_fut.await {
+                    peers.extend(enabled_peers);
+                    Ok(DiscoveryTaskOut::LookupSeeds { target, peers })
                 } else {
                     Err(DiscoveryBehaviorError::OperationCancelled)
                 }
             }
         }));
     }
+
+    fn on_lookup_candidates(&mut self, target: PeerId, peers: Vec<PeerDestination>) {
+        if let Some(lookup) = self.lookups.get_mut(&target) {
+            if let Some(found) = lookup.add_candidates(peers) {
+                self.finish_lookup(target, Some(found));
+            } else {
+                self.advance_lookup(target);
+            }
+        }
+    }
+
+    /// Send further `FindNode` requests or finish the lookup if there is nobody left to ask.
+    fn advance_lookup(&mut self, target: PeerId) {
+        let versions = &self.peer_versions;
+        if let Some(lookup) = self.lookups.get_mut(&target) {
+            let queries =
+                lookup.next_queries(|pid| versions.get(pid).map_or(false, |ver| supports_lookups(*ver)));
+            for peer_id in queries {
+                trace!("Asking {} for peers closest to {}", peer_id, target);
+                self.outbox.push_back(DiscoveryBehaviourOut::Send {
+                    peer_id,
+                    message: DiscoveryMessage::DiscoveryMessageV2(DiscoveryMessageV2::FindNode { target }),
+                });
+            }
+            if lookup.is_exhausted() {
+                self.finish_lookup(target, None);
+            }
+        }
+    }
+
+    fn finish_lookup(&mut self, target: PeerId, result: Option<PeerDestination>) {
+        if let Some(lookup) = self.lookups.remove(&target) {
+            info!("Lookup of {} finished, found: {}", target, result.is_some());
+            if let Some(dest) = &result {
+                self.peers.add_peers(vec![dest.clone()]);
+            }
+            lookup.finish(result);
+        }
+    }
+
+    fn expire_lookups(&mut self) {
+        let now = Instant::now();
+        let expired = self
+            .lookups
+            .iter()
+            .filter(|(_, lookup)| lookup.deadline <= now)
+            .map(|(target, _)| *target)
+            .collect::<Vec<_>>();
+        for target in expired {
+            self.finish_lookup(target, None);
+        }
+        self.next_lookups_expiration = self
+            .lookups
+            .values()
+            .map(|lookup| lookup.deadline)
+            .min()
+            .map(|deadline| Delay::new(deadline.saturating_duration_since(now)));
+    }
+
+    fn on_nodes(&mut self, peer_id: PeerId, target: PeerId, peers: Vec<PeerDestination>) {
+        info!(
+            "Peer {} sent {} peers closest to {}",
+            peer_id,
+            peers.len(),
+            target
+        );
+        if let Some(lookup) = self.lookups.get_mut(&target) {
+            if lookup.on_response(&peer_id) {
+                // Let the peer manager know about new peers so that they can be connected.
+                self.peers.add_peers(peers.clone());
+                self.on_lookup_candidates(target, peers);
+            }
+        }
+    }
 }
 
 impl<TPeers> ProtocolBehaviour for DiscoveryBehaviour<TPeers>
@@ -107,6 +425,16 @@ where
 {
     type TProto = DiscoverySpec;
 
+    fn inject_cancelled(&mut self) {
+        self.tasks = FuturesOrdered::new();
+        self.outbox.clear();
+        let targets = self.lookups.keys().copied().collect::<Vec<_>>();
+        for target in targets {
+            self.finish_lookup(target, None);
+        }
+        self.next_lookups_expiration = None;
+    }
+
     fn inject_peer_connected(&mut self, peer_id: PeerId) {
         // Immediately enable sync with the peer.
         self.outbox
@@ -118,26 +446,44 @@ where
 
     fn inject_message(&mut self, peer_id: PeerId, msg: DiscoveryMessage) {
         match msg {
-            DiscoveryMessage::DiscoveryMessageV1(DiscoveryMessageV1::GetPeers) => {
+            DiscoveryMessage::DiscoveryMessageV1(DiscoveryMessageV1::GetPeers)
+            | DiscoveryMessage::DiscoveryMessageV2(DiscoveryMessageV2::GetPeers) => {
                 self.send_peers(peer_id);
             }
-            DiscoveryMessage::DiscoveryMessageV1(DiscoveryMessageV1::Peers(peers)) => {
+            DiscoveryMessage::DiscoveryMessageV1(DiscoveryMessageV1::Peers(peers))
+            | DiscoveryMessage::DiscoveryMessageV2(DiscoveryMessageV2::Peers(peers)) => {
                 info!("Peer {} sent {} peers", peer_id, peers.len());
                 self.peers.add_peers(peers);
             }
+            DiscoveryMessage::DiscoveryMessageV2(DiscoveryMessageV2::FindNode { target }) => {
+                if self.lookups_enabled() {
+                    self.send_nodes(peer_id, target);
+                } else {
+                    trace!(
+                        "Ignoring lookup of {} by {}, lookups aren't activated yet",
+                        target,
+                        peer_id
+                    );
+                }
+            }
+            DiscoveryMessage::DiscoveryMessageV2(DiscoveryMessageV2::Nodes { target, peers }) => {
+                self.on_nodes(peer_id, target, peers);
+            }
         }
     }
 
-    fn inject_protocol_requested(&mut self, peer_id: PeerId, handshake: Option<DiscoveryHandshake>) {
-        if let Some(DiscoveryHandshake::HandshakeV1(hs)) = handshake {
-            self.tracked_peers.insert(
-                peer_id,
-                NodeStatus {
-                    supported_protocols: hs.supported_protocols,
-                    height: hs.height,
-                },
-            );
+    fn inject_protocol_requested(
+        &mut self,
+        peer_id: PeerId,
+        protocol_ver: ProtocolVer,
+        handshake: Option<DiscoveryHandshake>,
+    ) {
+        if let Some(hs) = handshake {
+            if !self.track_peer(peer_id, hs) {
+                return;
+            }
         }
+        self.peer_versions.insert(peer_id, protocol_ver);
         // todo: DEV-384: Maybe no need for PolyVerHandshake here (bc version should already be defined)?
         self.outbox
             .push_back(ProtocolBehaviourOut::NetworkAction(NetworkAction::EnablePeer {
@@ -157,26 +503,72 @@ where
     fn inject_protocol_enabled(
         &mut self,
         peer_id: PeerId,
-        _handshake: Option<<Self::TProto as ProtocolSpec>::THandshake>,
+        protocol_ver: ProtocolVer,
+        handshake: Option<<Self::TProto as ProtocolSpec>::THandshake>,
     ) {
-        info!("Sync protocol enabled with peer {}", peer_id);
+        info!("Sync protocol {:?} enabled with peer {}", protocol_ver, peer_id);
+        if let Some(hs) = handshake {
+            if !self.track_peer(peer_id, hs) {
+                return;
+            }
+        }
+        self.peer_versions.insert(peer_id, protocol_ver);
         self.send_get_peers(peer_id);
+        // The peer may be a candidate of pending lookups.
+        for target in self.lookups.keys().copied().collect::<Vec<_>>() {
+            self.advance_lookup(target);
+        }
     }
 
     fn inject_protocol_disabled(&mut self, peer_id: PeerId) {
+        if let Some((_, addrs)) = &mut self.external_addrs {
+            addrs.forget_observer(&peer_id);
+        }
         self.tracked_peers.remove(&peer_id);
+        self.peer_versions.remove(&peer_id);
+        for lookup in self.lookups.values_mut() {
+            lookup.on_peer_gone(&peer_id);
+        }
+        for target in self.lookups.keys().copied().collect::<Vec<_>>() {
+            self.advance_lookup(target);
+        }
     }
 
     fn poll(
         &mut self,
         cx: &mut Context,
     ) -> Poll<Option<ProtocolBehaviourOut<DiscoveryHandshake, DiscoveryMessage>>> {
+        while let Some(Poll::Ready(Some(req))) = self
+            .inbox
+            .as_mut()
+            .map(|inbox| Stream::poll_next(Pin::new(inbox), cx))
+        {
+            match req {
+                DiscoveryRequest::FindPeer { target, channel } => self.find_peer(target, channel),
+                DiscoveryRequest::AddressObserved { observer, addr } => {
+                    if let Some((_, addrs)) = &mut self.external_addrs {
+                        addrs.observe(observer, addr);
+                    }
+                }
+            }
+        }
+        while let Some(timer) = &mut self.next_lookups_expiration {
+            if Future::poll(Pin::new(timer), cx).is_ready() {
+                self.expire_lookups();
+            } else {
+                break;
+            }
+        }
         loop {
             match Stream::poll_next(Pin::new(&mut self.tasks), cx) {
-                Poll::Ready(Some(Ok(out))) => {
+                Poll::Ready(Some(Ok(DiscoveryTaskOut::Out(out)))) => {
                     self.outbox.push_back(out);
                     continue;
                 }
+                Poll::Ready(Some(Ok(DiscoveryTaskOut::LookupSeeds { target, peers }))) => {
+                    self.on_lookup_candidates(target, peers);
+                    continue;
+                }
                 Poll::Ready(Some(Err(err))) => {
                     error!("An error occured: {}", err);
                     continue;
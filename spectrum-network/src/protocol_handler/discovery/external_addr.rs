@@ -0,0 +1,171 @@
+use std::collections::{HashMap, HashSet};
+
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId};
+
+/// Max number of distinct observed addresses tracked at once.
+const MAX_OBSERVED_ADDRS: usize = 32;
+
+/// Which addresses of the local node observed by remote peers are trusted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ObservedAddrPolicy {
+    /// Never advertise observed addresses.
+    Ignore,
+    /// Advertise addresses reported by at least `min_observers` distinct peers.
+    Confirmed { min_observers: usize },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExternalAddrConf {
+    /// Addresses specified by the operator, e.g. the ones of a proxy the node is behind.
+    /// Always advertised.
+    pub static_addrs: Vec<Multiaddr>,
+    pub observed_addr_policy: ObservedAddrPolicy,
+    /// Advertise observed addresses only if no static addresses are configured.
+    pub prefer_static: bool,
+    /// Whether observed addresses which are not globally routable (loopback, private ranges, etc.)
+    /// can be advertised.
+    pub advertise_private: bool,
+    /// Max number of addresses advertised.
+    pub max_advertised: usize,
+}
+
+impl Default for ExternalAddrConf {
+    fn default() -> Self {
+        Self {
+            static_addrs: Vec::new(),
+            observed_addr_policy: ObservedAddrPolicy::Confirmed { min_observers: 2 },
+            prefer_static: true,
+            advertise_private: false,
+            max_advertised: 8,
+        }
+    }
+}
+
+/// Chooses external addresses of the local node to advertise to other peers.
+#[derive(Clone, Debug)]
+pub struct ExternalAddrs {
+    conf: ExternalAddrConf,
+    /// Observed addresses and peers which reported them.
+    observed: HashMap<Multiaddr, HashSet<PeerId>>,
+}
+
+impl ExternalAddrs {
+    pub fn new(conf: ExternalAddrConf) -> Self {
+        Self {
+            conf,
+            observed: HashMap::new(),
+        }
+    }
+
+    /// Register the address of the local node as seen by the given peer.
+    pub fn observe(&mut self, observer: PeerId, addr: Multiaddr) {
+        if self.conf.observed_addr_policy == ObservedAddrPolicy::Ignore
+            || (!self.conf.advertise_private && !is_global(&addr))
+        {
+            return;
+        }
+        if self.observed.len() >= MAX_OBSERVED_ADDRS && !self.observed.contains_key(&addr) {
+            return;
+        }
+        // Each peer vouches for a single address at a time.
+        for observers in self.observed.values_mut() {
+            observers.remove(&observer);
+        }
+        self.observed.entry(addr).or_default().insert(observer);
+        self.observed.retain(|_, observers| !observers.is_empty());
+    }
+
+    /// Forget addresses reported by the given peer.
+    pub fn forget_observer(&mut self, observer: &PeerId) {
+        for observers in self.observed.values_mut() {
+            observers.remove(observer);
+        }
+        self.observed.retain(|_, observers| !observers.is_empty());
+    }
+
+    /// Addresses to advertise, static ones first.
+    pub fn advertised(&self) -> Vec<Multiaddr> {
+        let mut addrs = self.conf.static_addrs.clone();
+        let use_observed = !self.conf.prefer_static || self.conf.static_addrs.is_empty();
+        if let (ObservedAddrPolicy::Confirmed { min_observers }, true) =
+            (&self.conf.observed_addr_policy, use_observed)
+        {
+            let mut confirmed = self
+                .observed
+                .iter()
+                .filter(|(addr, observers)| observers.len() >= *min_observers && !addrs.contains(addr))
+                .collect::<Vec<_>>();
+            // Most confirmed first.
+            confirmed.sort_by(|(a1, o1), (a2, o2)| o2.len().cmp(&o1.len()).then_with(|| a1.cmp(a2)));
+            addrs.extend(confirmed.into_iter().map(|(addr, _)| addr.clone()));
+        }
+        addrs.truncate(self.conf.max_advertised);
+        addrs
+    }
+}
+
+/// Whether the given address is reachable from the public internet.
+/// Addresses with DNS names are assumed to be.
+fn is_global(addr: &Multiaddr) -> bool {
+    match addr.iter().next() {
+        Some(Protocol::Ip4(ip)) => {
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation())
+        }
+        Some(Protocol::Ip6(ip)) => {
+            let segments = ip.segments();
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                // Unique local fc00::/7
+                || (segments[0] & 0xfe00) == 0xfc00
+                // Link-local fe80::/10
+                || (segments[0] & 0xffc0) == 0xfe80)
+        }
+        Some(Protocol::Dns(_) | Protocol::Dns4(_) | Protocol::Dns6(_) | Protocol::Dnsaddr(_)) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libp2p::{Multiaddr, PeerId};
+
+    use crate::protocol_handler::discovery::external_addr::{
+        ExternalAddrConf, ExternalAddrs, ObservedAddrPolicy,
+    };
+
+    fn addr(s: &str) -> Multiaddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn observed_addrs_are_advertised_once_confirmed() {
+        let mut addrs = ExternalAddrs::new(ExternalAddrConf::default());
+        let observed = addr("/ip4/8.8.8.8/tcp/8000");
+        addrs.observe(PeerId::random(), observed.clone());
+        addrs.observe(PeerId::random(), addr("/ip4/192.168.0.10/tcp/8000"));
+        assert!(addrs.advertised().is_empty());
+        let observer = PeerId::random();
+        addrs.observe(observer, observed.clone());
+        assert_eq!(addrs.advertised(), vec![observed]);
+        addrs.forget_observer(&observer);
+        assert!(addrs.advertised().is_empty());
+    }
+
+    #[test]
+    fn static_addrs_take_precedence() {
+        let static_addr = addr("/dns4/node.example.com/tcp/8000");
+        let mut addrs = ExternalAddrs::new(ExternalAddrConf {
+            static_addrs: vec![static_addr.clone()],
+            observed_addr_policy: ObservedAddrPolicy::Confirmed { min_observers: 1 },
+            ..ExternalAddrConf::default()
+        });
+        addrs.observe(PeerId::random(), addr("/ip4/8.8.8.8/tcp/8000"));
+        assert_eq!(addrs.advertised(), vec![static_addr]);
+    }
+}
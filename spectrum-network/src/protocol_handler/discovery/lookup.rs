@@ -0,0 +1,181 @@
+use std::collections::{BTreeMap, HashSet};
+use std::time::Instant;
+
+use futures::channel::oneshot::Sender;
+use libp2p::PeerId;
+
+use crate::peer_manager::data::PeerDestination;
+use crate::peer_manager::routing_table::{Distance, K_BUCKET_SIZE};
+
+/// Number of closest peers tracked by a lookup.
+pub const LOOKUP_K: usize = K_BUCKET_SIZE;
+/// Max number of concurrent `FindNode` requests of a single lookup.
+pub const LOOKUP_ALPHA: usize = 3;
+
+/// Iterative lookup of a peer. Peers closest to the target are asked for even closer ones
+/// until the target itself is found, all candidates are queried, or the deadline is reached.
+pub struct Lookup {
+    target: PeerId,
+    /// Peers closest to the target found so far.
+    closest: BTreeMap<Distance, PeerDestination>,
+    queried: HashSet<PeerId>,
+    in_flight: HashSet<PeerId>,
+    pub deadline: Instant,
+    subscribers: Vec<Sender<Option<PeerDestination>>>,
+}
+
+impl Lookup {
+    pub fn new(target: PeerId, deadline: Instant) -> Self {
+        Self {
+            target,
+            closest: BTreeMap::new(),
+            queried: HashSet::new(),
+            in_flight: HashSet::new(),
+            deadline,
+            subscribers: vec![],
+        }
+    }
+
+    pub fn subscribe(&mut self, channel: Sender<Option<PeerDestination>>) {
+        self.subscribers.push(channel);
+    }
+
+    /// Add candidates learned from the local routing table or from remote peers.
+    /// Returns the destination of the target if it is among them.
+    pub fn add_candidates(&mut self, peers: Vec<PeerDestination>) -> Option<PeerDestination> {
+        let mut found: Option<PeerDestination> = None;
+        for peer in peers {
+            let peer_id = peer.peer_id();
+            if peer_id == self.target {
+                if !matches!(found, Some(PeerDestination::PeerIdWithAddr(..))) {
+                    found = Some(peer);
+                }
+                continue;
+            }
+            let distance = Distance::between(&peer_id, &self.target);
+            match self.closest.get(&distance) {
+                // Don't lose a known address when the peer is reported without one.
+                Some(PeerDestination::PeerIdWithAddr(..)) if matches!(peer, PeerDestination::PeerId(_)) => {}
+                _ => {
+                    self.closest.insert(distance, peer);
+                }
+            }
+            while self.closest.len() > LOOKUP_K {
+                self.closest.pop_last();
+            }
+        }
+        found
+    }
+
+    /// Pick the closest peers not queried yet, keeping at most [`LOOKUP_ALPHA`] requests in flight.
+    /// `reachable` tells whether a `FindNode` request can be sent to a peer at the moment.
+    pub fn next_queries<F>(&mut self, reachable: F) -> Vec<PeerId>
+    where
+        F: Fn(&PeerId) -> bool,
+    {
+        let num_queries = LOOKUP_ALPHA.saturating_sub(self.in_flight.len());
+        let queries = self
+            .closest
+            .values()
+            .map(|p| p.peer_id())
+            .filter(|pid| !self.queried.contains(pid) && reachable(pid))
+            .take(num_queries)
+            .collect::<Vec<_>>();
+        for pid in &queries {
+            self.queried.insert(*pid);
+            self.in_flight.insert(*pid);
+        }
+        queries
+    }
+
+    /// Returns `false` if no request was sent to the peer.
+    pub fn on_response(&mut self, peer_id: &PeerId) -> bool {
+        self.in_flight.remove(peer_id)
+    }
+
+    /// The peer won't respond anymore.
+    pub fn on_peer_gone(&mut self, peer_id: &PeerId) {
+        self.in_flight.remove(peer_id);
+    }
+
+    /// Nothing is in flight and all known candidates were queried.
+    pub fn is_exhausted(&self) -> bool {
+        self.in_flight.is_empty() && self.closest.values().all(|p| self.queried.contains(&p.peer_id()))
+    }
+
+    pub fn finish(self, result: Option<PeerDestination>) {
+        for channel in self.subscribers {
+            let _ = channel.send(result.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use libp2p::PeerId;
+
+    use crate::peer_manager::data::PeerDestination;
+    use crate::peer_manager::routing_table::Distance;
+    use crate::protocol_handler::discovery::lookup::{Lookup, LOOKUP_ALPHA, LOOKUP_K};
+
+    fn random_peers(n: usize) -> Vec<PeerDestination> {
+        (0..n)
+            .map(|_| PeerDestination::PeerId(PeerId::random()))
+            .collect()
+    }
+
+    #[test]
+    fn closest_candidates_are_queried_first() {
+        let target = PeerId::random();
+        let mut lookup = Lookup::new(target, Instant::now());
+        let candidates = random_peers(2 * LOOKUP_K);
+        assert_eq!(lookup.add_candidates(candidates.clone()), None);
+        let mut expected = candidates.iter().map(|p| p.peer_id()).collect::<Vec<_>>();
+        expected.sort_by_key(|pid| Distance::between(pid, &target));
+        let queries = lookup.next_queries(|_| true);
+        assert_eq!(queries, expected[..LOOKUP_ALPHA].to_vec());
+        // No more requests until responses arrive.
+        assert!(lookup.next_queries(|_| true).is_empty());
+        assert!(lookup.on_response(&queries[0]));
+        assert_eq!(lookup.next_queries(|_| true), vec![expected[LOOKUP_ALPHA]]);
+    }
+
+    #[test]
+    fn unreachable_candidates_are_skipped() {
+        let target = PeerId::random();
+        let mut lookup = Lookup::new(target, Instant::now());
+        let candidates = random_peers(2);
+        let reachable = candidates[1].peer_id();
+        lookup.add_candidates(candidates);
+        assert_eq!(lookup.next_queries(|pid| *pid == reachable), vec![reachable]);
+        lookup.on_response(&reachable);
+        assert!(!lookup.is_exhausted());
+    }
+
+    #[test]
+    fn lookup_is_exhausted_when_all_candidates_queried() {
+        let mut lookup = Lookup::new(PeerId::random(), Instant::now());
+        lookup.add_candidates(random_peers(2));
+        let queries = lookup.next_queries(|_| true);
+        assert!(!lookup.is_exhausted());
+        lookup.on_response(&queries[0]);
+        lookup.on_peer_gone(&queries[1]);
+        assert!(lookup.is_exhausted());
+    }
+
+    #[test]
+    fn target_is_found() {
+        let target = PeerId::random();
+        let mut lookup = Lookup::new(target, Instant::now());
+        let addr = "/ip4/127.0.0.1/tcp/8000".parse().unwrap();
+        let mut candidates = random_peers(3);
+        candidates.push(PeerDestination::PeerIdWithAddr(target, addr));
+        candidates.push(PeerDestination::PeerId(target));
+        assert!(matches!(
+            lookup.add_candidates(candidates),
+            Some(PeerDestination::PeerIdWithAddr(pid, _)) if pid == target
+        ));
+    }
+}
@@ -1,26 +1,33 @@
+use libp2p::PeerId;
 use serde::{Deserialize, Serialize};
 
 use crate::peer_manager::data::PeerDestination;
 use crate::protocol_handler::versioning::Versioned;
 use crate::protocol_handler::ProtocolSpec;
-use crate::types::{ProtocolId, ProtocolVer};
+use crate::types::{NetworkId, ProtocolId, ProtocolVer};
 
 /// Sync handshake provides initial node status.
 #[derive(Serialize, Deserialize, Debug)]
 pub enum DiscoveryHandshake {
     HandshakeV1(HandshakeV1),
+    /// Node status is the same in V2.
+    HandshakeV2(HandshakeV1),
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct HandshakeV1 {
     pub supported_protocols: Vec<ProtocolId>,
     pub height: usize,
+    /// Absent in handshakes of nodes not aware of the genesis of the network.
+    #[serde(default)]
+    pub network_id: Option<NetworkId>,
 }
 
 impl Versioned for DiscoveryHandshake {
     fn version(&self) -> ProtocolVer {
         match self {
             DiscoveryHandshake::HandshakeV1(_) => DiscoverySpec::v1(),
+            DiscoveryHandshake::HandshakeV2(_) => DiscoverySpec::v2(),
         }
     }
 }
@@ -28,6 +35,7 @@ impl Versioned for DiscoveryHandshake {
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub enum DiscoveryMessage {
     DiscoveryMessageV1(DiscoveryMessageV1),
+    DiscoveryMessageV2(DiscoveryMessageV2),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -36,10 +44,27 @@ pub enum DiscoveryMessageV1 {
     Peers(Vec<PeerDestination>),
 }
 
+/// Same as [`DiscoveryMessageV1`], plus iterative lookup of peers by id.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum DiscoveryMessageV2 {
+    GetPeers,
+    Peers(Vec<PeerDestination>),
+    /// Request peers closest to the `target` known to the remote node.
+    FindNode {
+        target: PeerId,
+    },
+    /// Response to [`DiscoveryMessageV2::FindNode`], nearest peers first.
+    Nodes {
+        target: PeerId,
+        peers: Vec<PeerDestination>,
+    },
+}
+
 impl Versioned for DiscoveryMessage {
     fn version(&self) -> ProtocolVer {
         match self {
             DiscoveryMessage::DiscoveryMessageV1(_) => DiscoverySpec::v1(),
+            DiscoveryMessage::DiscoveryMessageV2(_) => DiscoverySpec::v2(),
         }
     }
 }
@@ -50,6 +75,10 @@ impl DiscoverySpec {
     pub fn v1() -> ProtocolVer {
         ProtocolVer::from(1)
     }
+
+    pub fn v2() -> ProtocolVer {
+        ProtocolVer::from(2)
+    }
 }
 
 impl ProtocolSpec for DiscoverySpec {
@@ -0,0 +1,746 @@
+//! Distributed generation of the committee key.
+//!
+//! Follows the key generation of FROST: every member deals a random polynomial of degree
+//! `threshold - 1` (Feldman VSS). Dealers broadcast commitments to the coefficients of their
+//! polynomials along with a proof of knowledge of the secret term, and send each member its share
+//! directly. Group secret is the sum of secret terms of all dealers and is never assembled, each
+//! member ends up with the sum of shares dealt to it instead.
+//!
+//! When the committee changes, members of the outgoing committee reshare the key: each dealer
+//! deals its own share of the key, which the new members combine with Lagrange coefficients of
+//! the dealers. The group key stays the same, while shares of the outgoing committee become useless
+//! for the new sharing.
+//!
+//! Any invalid contribution aborts the run with the misbehaving dealer identified, rather than
+//! disqualifying it locally, so honest members never end up with different group keys.
+//! The run is then expected to be retried without the dealer.
+
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::channel::mpsc::Receiver;
+use futures::channel::oneshot::Sender;
+use futures::Stream;
+use k256::{ProjectivePoint, Scalar};
+use libp2p::PeerId;
+use log::{trace, warn};
+use wasm_timer::Delay;
+
+use spectrum_crypto::pubkey::PublicKey;
+
+use crate::protocol_handler::dkg::crypto::{
+    eval_commitments, lagrange_coefficient, participant_id, prove_knowledge, to_point, to_public_key,
+    verify_knowledge, verify_share, Polynomial,
+};
+use crate::protocol_handler::dkg::message::{
+    reshare_session_id, session_id, DkgMessage, DkgMessageV1, DkgSessionId, DkgSpec,
+};
+use crate::protocol_handler::void::VoidMessage;
+use crate::protocol_handler::{ProtocolBehaviour, ProtocolBehaviourOut};
+
+pub mod crypto;
+pub mod message;
+
+pub enum DkgAction {
+    /// Generate a key shared among the committee, so that any `threshold` members can use it.
+    /// All members must request the run with the same committee (in the same order), threshold and epoch.
+    /// Replaces the run in progress.
+    Run {
+        committee: Vec<PublicKey>,
+        threshold: usize,
+        epoch: u64,
+        channel: Sender<Result<DkgOutput, DkgError>>,
+    },
+    /// Hand the existing key over to the new committee, so that any `new_threshold` of its members
+    /// can use it. Requested by both the dealers and members of the new committee.
+    /// Dealers leaving the committee are done once their shares are sent, so they get `None`.
+    /// Replaces the run in progress.
+    Reshare {
+        resharing: Box<Resharing>,
+        channel: Sender<Result<Option<DkgOutput>, DkgError>>,
+    },
+}
+
+pub struct Resharing {
+    pub current_key: SharedKey,
+    /// Share of the host in the current key, required if the host is one of the dealers.
+    pub current_share: Option<Scalar>,
+    /// Members of the current committee dealing their shares, at least its threshold.
+    pub dealers: Vec<PublicKey>,
+    pub new_committee: Vec<PublicKey>,
+    pub new_threshold: usize,
+    pub epoch: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum DkgError {
+    #[error("Host is not a member of the committee")]
+    NotAMember,
+    #[error("Threshold {threshold} is out of range for a committee of {committee_size}")]
+    InvalidThreshold { threshold: usize, committee_size: usize },
+    #[error("Dealers aren't a qualified subset of the current committee")]
+    InvalidDealers,
+    #[error("Share of the host in the current key is required to deal it")]
+    MissingShare,
+    #[error("Dealer {0:?} sent invalid commitments")]
+    InvalidCommitments(PublicKey),
+    #[error("Dealer {0:?} sent a share inconsistent with its commitments")]
+    InvalidShare(PublicKey),
+    #[error("Contributions of {0:?} weren't received in time")]
+    Timeout(Vec<PublicKey>),
+    #[error("Run was replaced by another one")]
+    Superseded,
+}
+
+/// Public part of a key shared among a committee.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedKey {
+    pub committee: Vec<PublicKey>,
+    pub threshold: usize,
+    /// Key of the committee, e.g. the one vault contracts are guarded by.
+    pub group_public_key: PublicKey,
+    /// Public counterparts of secret shares of all members, in committee order.
+    pub verification_shares: Vec<PublicKey>,
+}
+
+/// Key material of the host resulting from a successful run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DkgOutput {
+    pub session: DkgSessionId,
+    /// Index of the host in the committee.
+    pub host_ix: usize,
+    /// Share of the group secret, i.e. evaluation of the group polynomial at `host_ix + 1`.
+    pub secret_share: Scalar,
+    pub key: SharedKey,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct DkgConfig {
+    /// Run fails if contributions of some dealers aren't received by then.
+    pub round_timeout: Duration,
+    /// Maximum number of messages kept for runs not started locally yet.
+    pub max_pending_messages: usize,
+}
+
+type DkgBehaviourOut = ProtocolBehaviourOut<VoidMessage, DkgMessage>;
+
+struct Dealer {
+    pk: PublicKey,
+    peer_id: PeerId,
+    /// Expected secret term of the dealer's polynomial, known when the key is reshared.
+    expected_secret: Option<PublicKey>,
+    /// Weight of the dealer's contribution to the group secret.
+    weight: Scalar,
+}
+
+enum RoundChannel {
+    Run(Sender<Result<DkgOutput, DkgError>>),
+    Reshare(Sender<Result<Option<DkgOutput>, DkgError>>),
+}
+
+impl RoundChannel {
+    fn send(self, result: Result<Option<DkgOutput>, DkgError>) {
+        match self {
+            RoundChannel::Run(channel) => {
+                let _ = channel.send(result.and_then(|out| out.ok_or(DkgError::NotAMember)));
+            }
+            RoundChannel::Reshare(channel) => {
+                let _ = channel.send(result);
+            }
+        }
+    }
+}
+
+struct DkgRound {
+    session: DkgSessionId,
+    dealers: Vec<Dealer>,
+    committee: Vec<PublicKey>,
+    /// Index of the host in the committee. Dealers leaving the committee don't receive shares.
+    host_ix: Option<usize>,
+    threshold: usize,
+    /// Contributions of dealers by their index in `dealers`.
+    commitments: HashMap<usize, Vec<PublicKey>>,
+    shares: HashMap<usize, Scalar>,
+    channel: RoundChannel,
+    deadline: Delay,
+}
+
+impl DkgRound {
+    fn is_complete(&self) -> bool {
+        self.commitments.len() == self.dealers.len() && self.shares.len() == self.dealers.len()
+    }
+
+    fn missing_dealers(&self) -> Vec<PublicKey> {
+        (0..self.dealers.len())
+            .filter(|ix| !self.commitments.contains_key(ix) || !self.shares.contains_key(ix))
+            .map(|ix| self.dealers[ix].pk)
+            .collect()
+    }
+
+    fn output(&self, host_ix: usize) -> DkgOutput {
+        let weighted = |dealer_ix: &usize, point: ProjectivePoint| point * self.dealers[*dealer_ix].weight;
+        let secret_share = self
+            .shares
+            .iter()
+            .map(|(dealer_ix, share)| *share * self.dealers[*dealer_ix].weight)
+            .sum();
+        let group_public_key = to_public_key(
+            self.commitments
+                .iter()
+                .map(|(dealer_ix, coefficients)| weighted(dealer_ix, to_point(coefficients[0])))
+                .sum::<ProjectivePoint>(),
+        );
+        let verification_shares = (0..self.committee.len())
+            .map(|ix| {
+                to_public_key(
+                    self.commitments
+                        .iter()
+                        .map(|(dealer_ix, coefficients)| {
+                            weighted(dealer_ix, eval_commitments(coefficients, participant_id(ix)))
+                        })
+                        .sum::<ProjectivePoint>(),
+                )
+            })
+            .collect();
+        DkgOutput {
+            session: self.session,
+            host_ix,
+            secret_share,
+            key: SharedKey {
+                committee: self.committee.clone(),
+                threshold: self.threshold,
+                group_public_key,
+                verification_shares,
+            },
+        }
+    }
+}
+
+pub struct DkgBehaviour {
+    host_pk: PublicKey,
+    conf: DkgConfig,
+    inbox: Receiver<DkgAction>,
+    outbox: VecDeque<DkgBehaviourOut>,
+    round: Option<DkgRound>,
+    /// Messages of runs not started locally yet, peers may be ahead of us.
+    pending: VecDeque<(PeerId, DkgMessageV1)>,
+}
+
+impl DkgBehaviour {
+    pub fn new(host_pk: PublicKey, conf: DkgConfig, inbox: Receiver<DkgAction>) -> Self {
+        Self {
+            host_pk,
+            conf,
+            inbox,
+            outbox: VecDeque::new(),
+            round: None,
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn send(&mut self, peer_id: PeerId, message: DkgMessageV1) {
+        self.outbox.push_back(ProtocolBehaviourOut::Send {
+            peer_id,
+            message: DkgMessage::DkgMessageV1(message),
+        });
+    }
+
+    fn finish(&mut self, result: Result<Option<DkgOutput>, DkgError>) {
+        if let Some(round) = self.round.take() {
+            round.channel.send(result);
+        }
+    }
+
+    fn run(&mut self, committee: Vec<PublicKey>, threshold: usize, epoch: u64, channel: RoundChannel) {
+        if !committee.contains(&self.host_pk) {
+            channel.send(Err(DkgError::NotAMember));
+            return;
+        }
+        let session = session_id(&committee, threshold, epoch);
+        let dealers = committee
+            .iter()
+            .map(|pk| Dealer {
+                pk: *pk,
+                peer_id: PeerId::from(pk),
+                expected_secret: None,
+                weight: Scalar::ONE,
+            })
+            .collect();
+        self.start(session, dealers, committee, threshold, None, channel);
+    }
+
+    fn reshare(&mut self, resharing: Resharing, channel: RoundChannel) {
+        let Resharing {
+            current_key,
+            current_share,
+            dealers,
+            new_committee,
+            new_threshold,
+            epoch,
+        } = resharing;
+        if !dealers.contains(&self.host_pk) && !new_committee.contains(&self.host_pk) {
+            channel.send(Err(DkgError::NotAMember));
+            return;
+        }
+        let dealer_ixs = dealers
+            .iter()
+            .map(|pk| current_key.committee.iter().position(|member| member == pk))
+            .collect::<Option<Vec<_>>>();
+        let Some(dealer_ixs) = dealer_ixs.filter(|ixs| {
+            let mut distinct = ixs.clone();
+            distinct.sort();
+            distinct.dedup();
+            distinct.len() == ixs.len()
+                && ixs.len() >= current_key.threshold
+                && current_key.verification_shares.len() == current_key.committee.len()
+        }) else {
+            channel.send(Err(DkgError::InvalidDealers));
+            return;
+        };
+        let session = reshare_session_id(
+            current_key.group_public_key,
+            &dealers,
+            &new_committee,
+            new_threshold,
+            epoch,
+        );
+        let dealers = dealer_ixs
+            .iter()
+            .map(|ix| Dealer {
+                pk: current_key.committee[*ix],
+                peer_id: PeerId::from(current_key.committee[*ix]),
+                expected_secret: Some(current_key.verification_shares[*ix]),
+                weight: lagrange_coefficient(*ix, &dealer_ixs),
+            })
+            .collect();
+        self.start(
+            session,
+            dealers,
+            new_committee,
+            new_threshold,
+            current_share,
+            channel,
+        );
+    }
+
+    fn start(
+        &mut self,
+        session: DkgSessionId,
+        dealers: Vec<Dealer>,
+        committee: Vec<PublicKey>,
+        threshold: usize,
+        current_share: Option<Scalar>,
+        channel: RoundChannel,
+    ) {
+        if threshold == 0 || threshold > committee.len() {
+            channel.send(Err(DkgError::InvalidThreshold {
+                threshold,
+                committee_size: committee.len(),
+            }));
+            return;
+        }
+        let host_ix = committee.iter().position(|pk| *pk == self.host_pk);
+        let mut round = DkgRound {
+            session,
+            dealers,
+            committee,
+            host_ix,
+            threshold,
+            commitments: HashMap::new(),
+            shares: HashMap::new(),
+            channel,
+            deadline: Delay::new(self.conf.round_timeout),
+        };
+        if let Some(dealer_ix) = round.dealers.iter().position(|d| d.pk == self.host_pk) {
+            let poly = match (round.dealers[dealer_ix].expected_secret, current_share) {
+                (None, _) => Polynomial::random(threshold),
+                (Some(_), Some(share)) => Polynomial::with_secret(share, threshold),
+                (Some(_), None) => {
+                    round.channel.send(Err(DkgError::MissingShare));
+                    return;
+                }
+            };
+            let coefficients = poly.commit();
+            let proof = prove_knowledge(session, dealer_ix, poly.secret());
+            for (ix, pk) in round.committee.iter().enumerate() {
+                if Some(ix) != host_ix {
+                    let peer_id = PeerId::from(pk);
+                    self.send(
+                        peer_id,
+                        DkgMessageV1::Commitments {
+                            session,
+                            coefficients: coefficients.clone(),
+                            proof: proof.clone(),
+                        },
+                    );
+                    self.send(
+                        peer_id,
+                        DkgMessageV1::Share {
+                            session,
+                            share: poly.eval(participant_id(ix)),
+                        },
+                    );
+                }
+            }
+            match host_ix {
+                Some(ix) => {
+                    round.commitments.insert(dealer_ix, coefficients);
+                    round.shares.insert(dealer_ix, poly.eval(participant_id(ix)));
+                }
+                None => {
+                    round.channel.send(Ok(None));
+                    return;
+                }
+            }
+        }
+        self.round = Some(round);
+        let (pending, rest) = self
+            .pending
+            .drain(..)
+            .partition::<Vec<_>, _>(|(_, msg)| msg.session() == session);
+        self.pending = rest.into();
+        for (peer_id, msg) in pending {
+            self.on_message(peer_id, msg);
+        }
+    }
+
+    fn on_message(&mut self, peer_id: PeerId, msg: DkgMessageV1) {
+        let Some(round) = self.round.as_mut().filter(|round| round.session == msg.session()) else {
+            self.pending.push_back((peer_id, msg));
+            if self.pending.len() > self.conf.max_pending_messages {
+                self.pending.pop_front();
+            }
+            return;
+        };
+        let Some(dealer_ix) = round.dealers.iter().position(|d| d.peer_id == peer_id) else {
+            trace!("Dropping DKG message from {} which is not a dealer", peer_id);
+            return;
+        };
+        let dealer = &round.dealers[dealer_ix];
+        match msg {
+            DkgMessageV1::Commitments {
+                session,
+                coefficients,
+                proof,
+            } => {
+                if round.commitments.contains_key(&dealer_ix) {
+                    return;
+                }
+                if coefficients.len() != round.threshold
+                    || dealer
+                        .expected_secret
+                        .is_some_and(|secret| secret != coefficients[0])
+                    || !verify_knowledge(session, dealer_ix, coefficients[0], &proof)
+                {
+                    warn!("Dealer {} sent invalid commitments", peer_id);
+                    let dealer = dealer.pk;
+                    self.finish(Err(DkgError::InvalidCommitments(dealer)));
+                    return;
+                }
+                round.commitments.insert(dealer_ix, coefficients);
+            }
+            DkgMessageV1::Share { share, .. } => {
+                round.shares.entry(dealer_ix).or_insert(share);
+            }
+        }
+        // Rounds are only kept by members of the committee.
+        let host_ix = round.host_ix.unwrap();
+        if let (Some(coefficients), Some(share)) =
+            (round.commitments.get(&dealer_ix), round.shares.get(&dealer_ix))
+        {
+            if !verify_share(*share, host_ix, coefficients) {
+                warn!("Dealer {} sent an invalid share", peer_id);
+                let dealer = round.dealers[dealer_ix].pk;
+                self.finish(Err(DkgError::InvalidShare(dealer)));
+                return;
+            }
+        }
+        if round.is_complete() {
+            let output = round.output(host_ix);
+            self.finish(Ok(Some(output)));
+        }
+    }
+}
+
+impl ProtocolBehaviour for DkgBehaviour {
+    type TProto = DkgSpec;
+
+    fn inject_message(&mut self, peer_id: PeerId, content: DkgMessage) {
+        let DkgMessage::DkgMessageV1(msg) = content;
+        self.on_message(peer_id, msg);
+    }
+
+    fn inject_cancelled(&mut self) {
+        self.pending.clear();
+        self.finish(Err(DkgError::Superseded));
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<Option<DkgBehaviourOut>> {
+        while let Poll::Ready(Some(action)) = Stream::poll_next(Pin::new(&mut self.inbox), cx) {
+            self.finish(Err(DkgError::Superseded));
+            match action {
+                DkgAction::Run {
+                    committee,
+                    threshold,
+                    epoch,
+                    channel,
+                } => self.run(committee, threshold, epoch, RoundChannel::Run(channel)),
+                DkgAction::Reshare { resharing, channel } => {
+                    self.reshare(*resharing, RoundChannel::Reshare(channel))
+                }
+            }
+        }
+        if let Some(round) = self.round.as_mut() {
+            if Future::poll(Pin::new(&mut round.deadline), cx).is_ready() {
+                let missing = round.missing_dealers();
+                self.finish(Err(DkgError::Timeout(missing)));
+            }
+        }
+        if let Some(out) = self.outbox.pop_front() {
+            return Poll::Ready(Some(out));
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::task::{Context, Poll};
+    use std::time::Duration;
+
+    use elliptic_curve::rand_core::OsRng;
+    use futures::channel::{mpsc, oneshot};
+    use futures::task::noop_waker_ref;
+    use k256::{ProjectivePoint, Scalar, SecretKey};
+    use libp2p::PeerId;
+
+    use spectrum_crypto::pubkey::PublicKey;
+
+    use crate::protocol_handler::dkg::crypto::{lagrange_coefficient, to_public_key};
+    use crate::protocol_handler::dkg::message::{DkgMessage, DkgMessageV1};
+    use crate::protocol_handler::dkg::{DkgAction, DkgBehaviour, DkgConfig, DkgError, DkgOutput, Resharing};
+    use crate::protocol_handler::{ProtocolBehaviour, ProtocolBehaviourOut};
+
+    const CONF: DkgConfig = DkgConfig {
+        round_timeout: Duration::from_secs(60),
+        max_pending_messages: 100,
+    };
+
+    fn random_committee(size: usize) -> Vec<PublicKey> {
+        (0..size)
+            .map(|_| PublicKey::from(SecretKey::random(&mut OsRng)))
+            .collect()
+    }
+
+    /// Let behaviours of the given hosts handle the actions, delivering messages between them
+    /// through `tamper`.
+    fn exchange<F>(hosts: Vec<(PublicKey, DkgAction)>, mut tamper: F)
+    where
+        F: FnMut(usize, DkgMessageV1) -> DkgMessageV1,
+    {
+        let peers: Vec<_> = hosts.iter().map(|(pk, _)| PeerId::from(pk)).collect();
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let mut nodes = vec![];
+        for (pk, action) in hosts {
+            let (mut snd, inbox) = mpsc::channel(1);
+            snd.try_send(action).unwrap();
+            nodes.push((DkgBehaviour::new(pk, CONF, inbox), snd));
+        }
+        let mut in_flight = VecDeque::new();
+        loop {
+            for (ix, (node, _)) in nodes.iter_mut().enumerate() {
+                while let Poll::Ready(Some(ProtocolBehaviourOut::Send { peer_id, message })) =
+                    node.poll(&mut cx)
+                {
+                    in_flight.push_back((ix, peer_id, message));
+                }
+            }
+            match in_flight.pop_front() {
+                Some((from, to, DkgMessage::DkgMessageV1(msg))) => {
+                    let to_ix = peers.iter().position(|pid| *pid == to).unwrap();
+                    let msg = tamper(from, msg);
+                    nodes[to_ix]
+                        .0
+                        .inject_message(peers[from], DkgMessage::DkgMessageV1(msg));
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn run_dkg<F>(committee: &[PublicKey], threshold: usize, tamper: F) -> Vec<Result<DkgOutput, DkgError>>
+    where
+        F: FnMut(usize, DkgMessageV1) -> DkgMessageV1,
+    {
+        let mut results = vec![];
+        let mut hosts = vec![];
+        for pk in committee {
+            let (channel, result) = oneshot::channel();
+            hosts.push((
+                *pk,
+                DkgAction::Run {
+                    committee: committee.to_vec(),
+                    threshold,
+                    epoch: 0,
+                    channel,
+                },
+            ));
+            results.push(result);
+        }
+        exchange(hosts, tamper);
+        results
+            .into_iter()
+            .map(|mut res| res.try_recv().unwrap().unwrap())
+            .collect()
+    }
+
+    fn group_secret(outputs: &[&DkgOutput]) -> Scalar {
+        let members: Vec<_> = outputs.iter().map(|out| out.host_ix).collect();
+        outputs
+            .iter()
+            .map(|out| out.secret_share * lagrange_coefficient(out.host_ix, &members))
+            .sum()
+    }
+
+    #[test]
+    fn members_agree_on_group_key() {
+        let committee = random_committee(5);
+        let outputs: Vec<_> = run_dkg(&committee, 3, |_, msg| msg)
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        let group_pk = outputs[0].key.group_public_key;
+        for out in &outputs {
+            assert_eq!(out.key, outputs[0].key);
+            assert_eq!(
+                to_public_key(ProjectivePoint::GENERATOR * out.secret_share),
+                out.key.verification_shares[out.host_ix]
+            );
+        }
+        let group_sk = group_secret(&[&outputs[0], &outputs[2], &outputs[4]]);
+        assert_eq!(to_public_key(ProjectivePoint::GENERATOR * group_sk), group_pk);
+    }
+
+    #[test]
+    fn invalid_share_identifies_dealer() {
+        let committee = random_committee(4);
+        let results = run_dkg(&committee, 2, |from, msg| match msg {
+            DkgMessageV1::Share { session, share } if from == 1 => DkgMessageV1::Share {
+                session,
+                share: share + Scalar::ONE,
+            },
+            msg => msg,
+        });
+        for (ix, res) in results.into_iter().enumerate() {
+            if ix == 1 {
+                // The dealer doesn't verify shares it dealt itself.
+                assert!(res.is_ok());
+            } else {
+                assert_eq!(res, Err(DkgError::InvalidShare(committee[1])));
+            }
+        }
+    }
+
+    #[test]
+    fn reshared_key_stays_the_same() {
+        let committee = random_committee(5);
+        let outputs: Vec<_> = run_dkg(&committee, 3, |_, msg| msg)
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        let current_key = outputs[0].key.clone();
+        // Members 0 and 1 stay, 2 deals and leaves, 3 and 4 are gone.
+        let dealers = vec![committee[0], committee[1], committee[2]];
+        let mut new_committee = random_committee(2);
+        new_committee.insert(1, committee[0]);
+        new_committee.push(committee[1]);
+        let mut hosts = vec![];
+        let mut results = vec![];
+        for (ix, pk) in committee[..3].iter().chain(&new_committee).enumerate() {
+            if ix >= 3 && dealers.contains(pk) {
+                continue;
+            }
+            let (channel, result) = oneshot::channel();
+            hosts.push((
+                *pk,
+                DkgAction::Reshare {
+                    resharing: Box::new(Resharing {
+                        current_key: current_key.clone(),
+                        current_share: outputs.get(ix).filter(|_| ix < 3).map(|out| out.secret_share),
+                        dealers: dealers.clone(),
+                        new_committee: new_committee.clone(),
+                        new_threshold: 2,
+                        epoch: 1,
+                    }),
+                    channel,
+                },
+            ));
+            results.push((*pk, result));
+        }
+        exchange(hosts, |_, msg| msg);
+        let mut new_outputs = vec![];
+        for (pk, mut result) in results {
+            match result.try_recv().unwrap().unwrap().unwrap() {
+                Some(out) => new_outputs.push(out),
+                None => assert_eq!(pk, committee[2]),
+            }
+        }
+        assert_eq!(new_outputs.len(), new_committee.len());
+        for out in &new_outputs {
+            assert_eq!(out.key, new_outputs[0].key);
+            assert_eq!(out.key.group_public_key, current_key.group_public_key);
+            assert_eq!(out.key.committee, new_committee);
+        }
+        let group_sk = group_secret(&[&new_outputs[1], &new_outputs[3]]);
+        assert_eq!(
+            to_public_key(ProjectivePoint::GENERATOR * group_sk),
+            current_key.group_public_key
+        );
+    }
+
+    #[test]
+    fn dealer_must_deal_its_current_share() {
+        let committee = random_committee(3);
+        let outputs: Vec<_> = run_dkg(&committee, 2, |_, msg| msg)
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        let new_committee = random_committee(2);
+        let mut hosts = vec![];
+        let mut results = vec![];
+        for (ix, pk) in committee[..2].iter().chain(&new_committee).enumerate() {
+            let (channel, result) = oneshot::channel();
+            hosts.push((
+                *pk,
+                DkgAction::Reshare {
+                    resharing: Box::new(Resharing {
+                        current_key: outputs[0].key.clone(),
+                        // The first dealer tries to smuggle in a secret of its own.
+                        current_share: match ix {
+                            0 => Some(Scalar::ONE),
+                            1 => Some(outputs[1].secret_share),
+                            _ => None,
+                        },
+                        dealers: committee[..2].to_vec(),
+                        new_committee: new_committee.clone(),
+                        new_threshold: 2,
+                        epoch: 1,
+                    }),
+                    channel,
+                },
+            ));
+            results.push(result);
+        }
+        exchange(hosts, |_, msg| msg);
+        for mut result in results.into_iter().skip(2) {
+            assert_eq!(
+                result.try_recv().unwrap().unwrap(),
+                Err(DkgError::InvalidCommitments(committee[0]))
+            );
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use elliptic_curve::ops::Reduce;
+use elliptic_curve::rand_core::OsRng;
+use elliptic_curve::Field;
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use k256::{FieldBytes, ProjectivePoint, Scalar, U256};
+
+use spectrum_crypto::digest::blake2b256_hash;
+use spectrum_crypto::pubkey::PublicKey;
+
+use crate::protocol_handler::dkg::message::{DkgSessionId, ProofOfKnowledge};
+
+/// Secret polynomial `f(x) = a_0 + a_1*x + ... + a_{t-1}*x^{t-1}` of a dealer.
+/// The dealer contributes `a_0` to the group secret.
+pub struct Polynomial(Vec<Scalar>);
+
+impl Polynomial {
+    /// Random polynomial of degree `threshold - 1`, so that any `threshold` shares recover it.
+    pub fn random(threshold: usize) -> Self {
+        Self(
+            (0..threshold.max(1))
+                .map(|_| Scalar::random(&mut OsRng))
+                .collect(),
+        )
+    }
+
+    /// Random polynomial of degree `threshold - 1` with the given secret term, e.g. to reshare
+    /// a share of the existing key.
+    pub fn with_secret(secret: Scalar, threshold: usize) -> Self {
+        let mut poly = Self::random(threshold);
+        poly.0[0] = secret;
+        poly
+    }
+
+    pub fn secret(&self) -> Scalar {
+        self.0[0]
+    }
+
+    /// `f(x)`
+    pub fn eval(&self, x: Scalar) -> Scalar {
+        self.0.iter().rev().fold(Scalar::ZERO, |acc, a| acc * x + a)
+    }
+
+    /// `C_k = g^{a_k}`
+    pub fn commit(&self) -> Vec<PublicKey> {
+        self.0
+            .iter()
+            .map(|a| to_public_key(ProjectivePoint::GENERATOR * a))
+            .collect()
+    }
+}
+
+/// Members are identified by their index in the committee. Polynomials are evaluated at `index + 1`,
+/// as `f(0)` is the secret itself.
+pub fn participant_id(index: usize) -> Scalar {
+    Scalar::from(index as u64 + 1)
+}
+
+/// `g^{f(x)} = Π_k C_k^{x^k}`
+pub fn eval_commitments(commitments: &[PublicKey], x: Scalar) -> ProjectivePoint {
+    commitments
+        .iter()
+        .rev()
+        .fold(ProjectivePoint::IDENTITY, |acc, ck| acc * x + to_point(*ck))
+}
+
+/// Check that the share received from a dealer lies on the polynomial the dealer committed to.
+pub fn verify_share(share: Scalar, index: usize, commitments: &[PublicKey]) -> bool {
+    ProjectivePoint::GENERATOR * share == eval_commitments(commitments, participant_id(index))
+}
+
+/// Schnorr proof of knowledge of `a_0` bound to the session and the dealer,
+/// so that it can't be replayed by another dealer or in another session.
+pub fn prove_knowledge(session: DkgSessionId, dealer_ix: usize, secret: Scalar) -> ProofOfKnowledge {
+    let k = Scalar::random(&mut OsRng);
+    let commitment = to_public_key(ProjectivePoint::GENERATOR * k);
+    let public = to_public_key(ProjectivePoint::GENERATOR * secret);
+    let c = pok_challenge(session, dealer_ix, public, commitment);
+    ProofOfKnowledge {
+        commitment,
+        response: k + secret * c,
+    }
+}
+
+pub fn verify_knowledge(
+    session: DkgSessionId,
+    dealer_ix: usize,
+    public: PublicKey,
+    proof: &ProofOfKnowledge,
+) -> bool {
+    let c = pok_challenge(session, dealer_ix, public, proof.commitment);
+    ProjectivePoint::GENERATOR * proof.response == to_point(proof.commitment) + to_point(public) * c
+}
+
+/// `c = H(session, i, g^{a_0}, R)`
+fn pok_challenge(
+    session: DkgSessionId,
+    dealer_ix: usize,
+    public: PublicKey,
+    commitment: PublicKey,
+) -> Scalar {
+    let mut bf = Vec::new();
+    bf.extend_from_slice(session.as_ref());
+    bf.extend_from_slice(&(dealer_ix as u64).to_be_bytes());
+    bf.extend_from_slice(&encode_point(public));
+    bf.extend_from_slice(&encode_point(commitment));
+    let digest = blake2b256_hash(&bf);
+    <Scalar as Reduce<U256>>::reduce_bytes(&FieldBytes::from(*digest.raw()))
+}
+
+/// Lagrange coefficient of the member at `index` for interpolation at zero over the given members,
+/// e.g. to combine partial signatures produced with secret shares.
+pub fn lagrange_coefficient(index: usize, members: &[usize]) -> Scalar {
+    let xi = participant_id(index);
+    let (num, den) =
+        members
+            .iter()
+            .filter(|j| **j != index)
+            .fold((Scalar::ONE, Scalar::ONE), |(num, den), j| {
+                let xj = participant_id(*j);
+                (num * xj, den * (xj - xi))
+            });
+    num * den.invert().unwrap()
+}
+
+pub fn to_point(pk: PublicKey) -> ProjectivePoint {
+    k256::PublicKey::from(pk).to_projective()
+}
+
+/// Panics on the identity point, which occurs with negligible probability for random secrets.
+pub fn to_public_key(point: ProjectivePoint) -> PublicKey {
+    PublicKey::from(k256::PublicKey::try_from(point).unwrap())
+}
+
+fn encode_point(pk: PublicKey) -> Box<[u8]> {
+    k256::PublicKey::from(pk).to_encoded_point(true).to_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::{ProjectivePoint, Scalar};
+
+    use spectrum_crypto::digest::blake2b256_hash;
+
+    use crate::protocol_handler::dkg::crypto::{
+        lagrange_coefficient, participant_id, prove_knowledge, to_public_key, verify_knowledge, verify_share,
+        Polynomial,
+    };
+
+    #[test]
+    fn shares_verified_against_commitments() {
+        let poly = Polynomial::random(3);
+        let commitments = poly.commit();
+        for ix in 0..5 {
+            let share = poly.eval(participant_id(ix));
+            assert!(verify_share(share, ix, &commitments));
+            assert!(!verify_share(share + Scalar::ONE, ix, &commitments));
+            assert!(!verify_share(share, ix + 1, &commitments));
+        }
+    }
+
+    #[test]
+    fn proof_of_knowledge_bound_to_dealer() {
+        let session = blake2b256_hash(b"session");
+        let poly = Polynomial::random(2);
+        let public = poly.commit()[0];
+        let proof = prove_knowledge(session, 1, poly.secret());
+        assert!(verify_knowledge(session, 1, public, &proof));
+        assert!(!verify_knowledge(session, 2, public, &proof));
+        assert!(!verify_knowledge(blake2b256_hash(b"other"), 1, public, &proof));
+    }
+
+    #[test]
+    fn any_threshold_of_shares_recovers_group_secret() {
+        let threshold = 3;
+        let dealers: Vec<_> = (0..5).map(|_| Polynomial::random(threshold)).collect();
+        let group_secret: Scalar = dealers.iter().map(|p| p.secret()).sum();
+        let share_of = |ix| -> Scalar { dealers.iter().map(|p| p.eval(participant_id(ix))).sum() };
+        for members in [vec![0, 1, 2], vec![1, 3, 4], vec![0, 2, 4]] {
+            let recovered: Scalar = members
+                .iter()
+                .map(|ix| share_of(*ix) * lagrange_coefficient(*ix, &members))
+                .sum();
+            assert_eq!(recovered, group_secret);
+        }
+        let recovered: Scalar = [0, 1]
+            .iter()
+            .map(|ix| share_of(*ix) * lagrange_coefficient(*ix, &[0, 1]))
+            .sum();
+        assert_ne!(recovered, group_secret);
+        assert_eq!(
+            to_public_key(ProjectivePoint::GENERATOR * group_secret),
+            to_public_key(
+                dealers
+                    .iter()
+                    .map(|p| ProjectivePoint::GENERATOR * p.secret())
+                    .sum::<ProjectivePoint>()
+            )
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use k256::Scalar;
+use serde::{Deserialize, Serialize};
+
+use spectrum_crypto::digest::{blake2b256_hash, Blake2bDigest256};
+use spectrum_crypto::pubkey::PublicKey;
+
+use crate::protocol_handler::versioning::Versioned;
+use crate::protocol_handler::void::VoidMessage;
+use crate::protocol_handler::ProtocolSpec;
+use crate::types::ProtocolVer;
+
+/// Identifies a run of the DKG among a particular committee.
+pub type DkgSessionId = Blake2bDigest256;
+
+/// Session of the committee (in the given order) generating a key shared with the given threshold.
+/// `epoch` tells apart repeated runs among the same committee.
+pub fn session_id(committee: &[PublicKey], threshold: usize, epoch: u64) -> DkgSessionId {
+    let mut bf = vec![];
+    ciborium::ser::into_writer(&(committee, threshold as u64, epoch), &mut bf).unwrap();
+    blake2b256_hash(&bf)
+}
+
+/// Session of the dealers handing the key over to the new committee (in the given order),
+/// which then shares it with the given threshold.
+pub fn reshare_session_id(
+    group_public_key: PublicKey,
+    dealers: &[PublicKey],
+    committee: &[PublicKey],
+    threshold: usize,
+    epoch: u64,
+) -> DkgSessionId {
+    let mut bf = vec![];
+    ciborium::ser::into_writer(
+        &(group_public_key, dealers, committee, threshold as u64, epoch),
+        &mut bf,
+    )
+    .unwrap();
+    blake2b256_hash(&bf)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProofOfKnowledge {
+    pub commitment: PublicKey,
+    pub response: Scalar,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum DkgMessage {
+    DkgMessageV1(DkgMessageV1),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum DkgMessageV1 {
+    /// Commitments to coefficients of the dealer's polynomial, broadcast to the whole committee.
+    /// When the key is reshared, the secret term is the share of the dealer in the current key.
+    Commitments {
+        session: DkgSessionId,
+        coefficients: Vec<PublicKey>,
+        proof: ProofOfKnowledge,
+    },
+    /// Evaluation of the dealer's polynomial at the recipient's identifier.
+    /// Sent to the recipient only, confidentiality relies on encryption of the connection.
+    Share { session: DkgSessionId, share: Scalar },
+}
+
+impl DkgMessageV1 {
+    pub fn session(&self) -> DkgSessionId {
+        match self {
+            DkgMessageV1::Commitments { session, .. } | DkgMessageV1::Share { session, .. } => *session,
+        }
+    }
+}
+
+impl Versioned for DkgMessage {
+    fn version(&self) -> ProtocolVer {
+        match self {
+            DkgMessage::DkgMessageV1(_) => DkgSpec::v1(),
+        }
+    }
+}
+
+pub struct DkgSpec;
+
+impl DkgSpec {
+    pub fn v1() -> ProtocolVer {
+        ProtocolVer::from(1)
+    }
+}
+
+impl ProtocolSpec for DkgSpec {
+    type THandshake = VoidMessage;
+    type TMessage = DkgMessage;
+}
@@ -0,0 +1,404 @@
+//! Topic based publish/subscribe.
+//!
+//! Peers announce topics they are subscribed to in the handshake and keep each other updated as
+//! subscriptions change. Publications are forwarded only to the fanout of their topic, i.e.
+//! subscribers with the highest reputation. Each publication is identified by its Blake2b digest,
+//! so that it's delivered and forwarded by the node only once.
+
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::channel::mpsc::{self, Receiver};
+use futures::channel::oneshot::Sender;
+use futures::stream::FuturesUnordered;
+use futures::Stream;
+use libp2p::PeerId;
+use log::{trace, warn};
+use wasm_timer::Delay;
+
+use crate::peer_manager::Peers;
+use crate::protocol_handler::gossip::message::{
+    GossipHandshake, GossipMessage, GossipMessageV1, GossipSpec, HandshakeV1, Publication, Topic,
+};
+use crate::protocol_handler::gossip::seen::SeenPublications;
+use crate::protocol_handler::{NetworkAction, ProtocolBehaviour, ProtocolBehaviourOut};
+use crate::types::{ProtocolVer, Reputation};
+
+pub mod message;
+mod seen;
+
+pub enum GossipAction {
+    /// Deliver publications to the topic through the given channel.
+    /// Replaces the previous subscription to the same topic.
+    Subscribe {
+        topic: Topic,
+        channel: mpsc::Sender<Publication>,
+    },
+    /// Stop delivering publications to the topic.
+    Unsubscribe { topic: Topic },
+    /// Publish the payload to the topic. Resolves to the number of peers it was sent to.
+    Publish {
+        topic: Topic,
+        payload: Vec<u8>,
+        channel: Sender<Result<usize, PublishError>>,
+    },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum PublishError {
+    #[error("Same publication was already seen")]
+    Duplicate,
+    #[error("No peers to forward the publication to")]
+    NoPeers,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct GossipConfig {
+    /// Maximum number of peers each publication is forwarded to.
+    pub fanout_size: usize,
+    /// Peers with lower reputation are never included into fanouts.
+    pub min_reputation: Reputation,
+    /// How often reputations of peers are refreshed.
+    pub heartbeat_interval: Duration,
+    /// Number of recent publications remembered to drop duplicates.
+    pub seen_cache_size: usize,
+}
+
+type GossipBehaviourOut = ProtocolBehaviourOut<GossipHandshake, GossipMessage>;
+
+type ReputationQuery = Pin<Box<dyn Future<Output = Option<(PeerId, Reputation)>> + Send>>;
+
+pub struct GossipBehaviour<TPeers> {
+    conf: GossipConfig,
+    peers: TPeers,
+    inbox: Receiver<GossipAction>,
+    outbox: VecDeque<GossipBehaviourOut>,
+    /// Local subscriptions.
+    subscriptions: HashMap<Topic, mpsc::Sender<Publication>>,
+    /// Topics announced by peers in handshakes, until the protocol is enabled with them.
+    requested_topics: HashMap<PeerId, HashSet<Topic>>,
+    /// Topics each peer the protocol is enabled with is subscribed to.
+    peer_topics: HashMap<PeerId, HashSet<Topic>>,
+    /// Last known reputation of peers.
+    reputations: HashMap<PeerId, Reputation>,
+    /// Peers publications to each topic are forwarded to.
+    fanout: HashMap<Topic, Vec<PeerId>>,
+    seen: SeenPublications,
+    reputation_queries: FuturesUnordered<ReputationQuery>,
+    next_heartbeat: Delay,
+}
+
+impl<TPeers> GossipBehaviour<TPeers>
+where
+    TPeers: Peers,
+{
+    pub fn new(peers: TPeers, conf: GossipConfig, inbox: Receiver<GossipAction>) -> Self {
+        Self {
+            conf,
+            peers,
+            inbox,
+            outbox: VecDeque::new(),
+            subscriptions: HashMap::new(),
+            requested_topics: HashMap::new(),
+            peer_topics: HashMap::new(),
+            reputations: HashMap::new(),
+            fanout: HashMap::new(),
+            seen: SeenPublications::new(conf.seen_cache_size),
+            reputation_queries: FuturesUnordered::new(),
+            next_heartbeat: Delay::new(conf.heartbeat_interval),
+        }
+    }
+
+    fn enable_peer(&mut self, peer_id: PeerId) {
+        let hs = HandshakeV1 {
+            topics: self.subscriptions.keys().cloned().collect(),
+        };
+        self.outbox
+            .push_back(ProtocolBehaviourOut::NetworkAction(NetworkAction::EnablePeer {
+                peer_id,
+                handshakes: vec![(GossipSpec::v1(), Some(GossipHandshake::HandshakeV1(hs)))],
+            }))
+    }
+
+    fn query_reputation(&mut self, peer_id: PeerId) {
+        let reputation_fut = self.peers.get_peer_reputation(peer_id);
+        self.reputation_queries.push(Box::pin(async move {
+            reputation_fut.await.ok().map(|rep| (peer_id, rep))
+        }));
+    }
+
+    fn rebuild_fanout(&mut self) {
+        self.fanout = select_fanout(&self.peer_topics, &self.reputations, &self.conf);
+    }
+
+    /// Send the message to all peers the protocol is enabled with.
+    fn broadcast(&mut self, message: GossipMessageV1) {
+        for peer_id in self.peer_topics.keys() {
+            self.outbox.push_back(ProtocolBehaviourOut::Send {
+                peer_id: *peer_id,
+                message: GossipMessage::GossipMessageV1(message.clone()),
+            });
+        }
+    }
+
+    /// Send the publication to the fanout of its topic except for the given peer.
+    /// Returns the number of peers it was sent to.
+    fn forward(&mut self, publication: Publication, except: Option<PeerId>) -> usize {
+        let mut forwarded = 0;
+        for peer_id in self.fanout.get(&publication.topic).into_iter().flatten() {
+            if Some(*peer_id) != except {
+                self.outbox.push_back(ProtocolBehaviourOut::Send {
+                    peer_id: *peer_id,
+                    message: GossipMessage::GossipMessageV1(GossipMessageV1::Publish(publication.clone())),
+                });
+                forwarded += 1;
+            }
+        }
+        forwarded
+    }
+
+    fn deliver(&mut self, publication: Publication) {
+        let topic = publication.topic.clone();
+        if let Some(channel) = self.subscriptions.get_mut(&topic) {
+            if let Err(err) = channel.try_send(publication) {
+                if err.is_disconnected() {
+                    trace!("Subscriber of topic {:?} is gone", topic);
+                    self.unsubscribe(topic);
+                } else {
+                    warn!("Subscriber of topic {:?} is lagging, publication dropped", topic);
+                }
+            }
+        }
+    }
+
+    fn subscribe(&mut self, topic: Topic, channel: mpsc::Sender<Publication>) {
+        if self.subscriptions.insert(topic.clone(), channel).is_none() {
+            self.broadcast(GossipMessageV1::Subscribe(vec![topic]));
+        }
+    }
+
+    fn unsubscribe(&mut self, topic: Topic) {
+        if self.subscriptions.remove(&topic).is_some() {
+            self.broadcast(GossipMessageV1::Unsubscribe(vec![topic]));
+        }
+    }
+
+    fn publish(&mut self, topic: Topic, payload: Vec<u8>) -> Result<usize, PublishError> {
+        let publication = Publication { topic, payload };
+        if !self.seen.insert(publication.digest()) {
+            return Err(PublishError::Duplicate);
+        }
+        match self.forward(publication, None) {
+            0 => Err(PublishError::NoPeers),
+            forwarded => Ok(forwarded),
+        }
+    }
+
+    fn on_publication(&mut self, peer_id: PeerId, publication: Publication) {
+        if !self.seen.insert(publication.digest()) {
+            trace!(
+                "Dropping duplicate publication to {:?} from {}",
+                publication.topic,
+                peer_id
+            );
+            return;
+        }
+        self.deliver(publication.clone());
+        self.forward(publication, Some(peer_id));
+    }
+
+    fn on_action(&mut self, action: GossipAction) {
+        match action {
+            GossipAction::Subscribe { topic, channel } => self.subscribe(topic, channel),
+            GossipAction::Unsubscribe { topic } => self.unsubscribe(topic),
+            GossipAction::Publish {
+                topic,
+                payload,
+                channel,
+            } => {
+                let _ = channel.send(self.publish(topic, payload));
+            }
+        }
+    }
+}
+
+/// Pick up to [`GossipConfig::fanout_size`] subscribers of each topic with the highest reputation.
+/// Peers whose reputation isn't known yet are treated as having the initial one.
+fn select_fanout(
+    peer_topics: &HashMap<PeerId, HashSet<Topic>>,
+    reputations: &HashMap<PeerId, Reputation>,
+    conf: &GossipConfig,
+) -> HashMap<Topic, Vec<PeerId>> {
+    let reputation_of = |peer_id: &PeerId| {
+        reputations
+            .get(peer_id)
+            .copied()
+            .unwrap_or_else(Reputation::initial)
+    };
+    let mut fanout: HashMap<Topic, Vec<PeerId>> = HashMap::new();
+    for (peer_id, topics) in peer_topics {
+        if reputation_of(peer_id) >= conf.min_reputation {
+            for topic in topics {
+                fanout.entry(topic.clone()).or_default().push(*peer_id);
+            }
+        }
+    }
+    for peers in fanout.values_mut() {
+        peers.sort_by_key(|peer_id| (Reverse(reputation_of(peer_id)), *peer_id));
+        peers.truncate(conf.fanout_size);
+    }
+    fanout
+}
+
+impl<TPeers> ProtocolBehaviour for GossipBehaviour<TPeers>
+where
+    TPeers: Peers,
+{
+    type TProto = GossipSpec;
+
+    fn inject_peer_connected(&mut self, peer_id: PeerId) {
+        self.enable_peer(peer_id);
+    }
+
+    fn inject_message(&mut self, peer_id: PeerId, content: GossipMessage) {
+        let GossipMessage::GossipMessageV1(msg) = content;
+        match msg {
+            GossipMessageV1::Subscribe(topics) => {
+                if let Some(peer_topics) = self.peer_topics.get_mut(&peer_id) {
+                    peer_topics.extend(topics);
+                    self.rebuild_fanout();
+                }
+            }
+            GossipMessageV1::Unsubscribe(topics) => {
+                if let Some(peer_topics) = self.peer_topics.get_mut(&peer_id) {
+                    for topic in &topics {
+                        peer_topics.remove(topic);
+                    }
+                    self.rebuild_fanout();
+                }
+            }
+            GossipMessageV1::Publish(publication) => self.on_publication(peer_id, publication),
+        }
+    }
+
+    fn inject_protocol_requested(
+        &mut self,
+        peer_id: PeerId,
+        _protocol_ver: ProtocolVer,
+        handshake: Option<GossipHandshake>,
+    ) {
+        if let Some(GossipHandshake::HandshakeV1(hs)) = handshake {
+            self.requested_topics
+                .insert(peer_id, hs.topics.into_iter().collect());
+        }
+        self.enable_peer(peer_id);
+    }
+
+    fn inject_protocol_requested_locally(&mut self, peer_id: PeerId) {
+        self.enable_peer(peer_id);
+    }
+
+    fn inject_protocol_enabled(
+        &mut self,
+        peer_id: PeerId,
+        _protocol_ver: ProtocolVer,
+        handshake: Option<GossipHandshake>,
+    ) {
+        let requested_topics = self.requested_topics.remove(&peer_id);
+        let topics = match handshake {
+            Some(GossipHandshake::HandshakeV1(hs)) => hs.topics.into_iter().collect(),
+            None => requested_topics.unwrap_or_default(),
+        };
+        trace!("Gossip enabled with {}, topics: {:?}", peer_id, topics);
+        self.peer_topics.insert(peer_id, topics);
+        self.query_reputation(peer_id);
+        self.rebuild_fanout();
+    }
+
+    fn inject_protocol_disabled(&mut self, peer_id: PeerId) {
+        self.requested_topics.remove(&peer_id);
+        self.reputations.remove(&peer_id);
+        if self.peer_topics.remove(&peer_id).is_some() {
+            self.rebuild_fanout();
+        }
+    }
+
+    fn inject_protocol_enable_failed(&mut self, peer_id: PeerId) {
+        self.requested_topics.remove(&peer_id);
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<Option<GossipBehaviourOut>> {
+        while let Poll::Ready(Some(action)) = Stream::poll_next(Pin::new(&mut self.inbox), cx) {
+            self.on_action(action);
+        }
+        while Future::poll(Pin::new(&mut self.next_heartbeat), cx).is_ready() {
+            for peer_id in self.peer_topics.keys().copied().collect::<Vec<_>>() {
+                self.query_reputation(peer_id);
+            }
+            self.next_heartbeat.reset(self.conf.heartbeat_interval);
+        }
+        let mut reputations_changed = false;
+        while let Poll::Ready(Some(res)) = Stream::poll_next(Pin::new(&mut self.reputation_queries), cx) {
+            if let Some((peer_id, rep)) = res {
+                if self.peer_topics.contains_key(&peer_id) {
+                    reputations_changed |= self.reputations.insert(peer_id, rep) != Some(rep);
+                }
+            }
+        }
+        if reputations_changed {
+            self.rebuild_fanout();
+        }
+        if let Some(out) = self.outbox.pop_front() {
+            return Poll::Ready(Some(out));
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+    use std::time::Duration;
+
+    use libp2p::PeerId;
+
+    use crate::protocol_handler::gossip::message::Topic;
+    use crate::protocol_handler::gossip::{select_fanout, GossipConfig};
+    use crate::types::Reputation;
+
+    #[test]
+    fn fanout_prefers_reputable_subscribers() {
+        let conf = GossipConfig {
+            fanout_size: 2,
+            min_reputation: Reputation::from(-10),
+            heartbeat_interval: Duration::from_secs(10),
+            seen_cache_size: 100,
+        };
+        let topic = Topic::from("blocks");
+        let (best, good, unknown, bad) = (
+            PeerId::random(),
+            PeerId::random(),
+            PeerId::random(),
+            PeerId::random(),
+        );
+        let peer_topics = HashMap::from([
+            (best, HashSet::from([topic.clone()])),
+            (good, HashSet::from([topic.clone()])),
+            (unknown, HashSet::from([topic.clone()])),
+            (bad, HashSet::from([topic.clone(), Topic::from("txs")])),
+        ]);
+        let reputations = HashMap::from([
+            (best, Reputation::from(50)),
+            (good, Reputation::from(10)),
+            (bad, Reputation::from(-20)),
+        ]);
+        let fanout = select_fanout(&peer_topics, &reputations, &conf);
+        assert_eq!(fanout.get(&topic), Some(&vec![best, good]));
+        // Peers below the minimal reputation are never picked.
+        assert_eq!(fanout.get(&Topic::from("txs")), None);
+    }
+}
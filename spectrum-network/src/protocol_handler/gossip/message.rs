@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+
+use spectrum_crypto::digest::{blake2b256_hash, Blake2bDigest256};
+
+use crate::protocol_handler::versioning::Versioned;
+use crate::protocol_handler::ProtocolSpec;
+use crate::types::ProtocolVer;
+
+/// Name of a topic messages are published to.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Topic(pub String);
+
+impl From<&str> for Topic {
+    fn from(name: &str) -> Self {
+        Self(name.to_string())
+    }
+}
+
+/// Handshake announces topics the node is subscribed to.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum GossipHandshake {
+    HandshakeV1(HandshakeV1),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HandshakeV1 {
+    pub topics: Vec<Topic>,
+}
+
+impl Versioned for GossipHandshake {
+    fn version(&self) -> ProtocolVer {
+        match self {
+            GossipHandshake::HandshakeV1(_) => GossipSpec::v1(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum GossipMessage {
+    GossipMessageV1(GossipMessageV1),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum GossipMessageV1 {
+    /// Sender is now interested in the given topics.
+    Subscribe(Vec<Topic>),
+    /// Sender is no longer interested in the given topics.
+    Unsubscribe(Vec<Topic>),
+    Publish(Publication),
+}
+
+/// Message published to a topic.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Publication {
+    pub topic: Topic,
+    #[serde(with = "serde_bytes")]
+    pub payload: Vec<u8>,
+}
+
+impl Publication {
+    /// Identifies the publication while it travels across the network.
+    pub fn digest(&self) -> Blake2bDigest256 {
+        let mut bf = vec![];
+        ciborium::ser::into_writer(self, &mut bf).unwrap();
+        blake2b256_hash(&bf)
+    }
+}
+
+impl Versioned for GossipMessage {
+    fn version(&self) -> ProtocolVer {
+        match self {
+            GossipMessage::GossipMessageV1(_) => GossipSpec::v1(),
+        }
+    }
+}
+
+pub struct GossipSpec;
+
+impl GossipSpec {
+    pub fn v1() -> ProtocolVer {
+        ProtocolVer::from(1)
+    }
+}
+
+impl ProtocolSpec for GossipSpec {
+    type THandshake = GossipHandshake;
+    type TMessage = GossipMessage;
+}
@@ -0,0 +1,59 @@
+use std::collections::{HashSet, VecDeque};
+
+use spectrum_crypto::digest::Blake2bDigest256;
+
+/// Digests of the most recent publications, the oldest ones are forgotten once
+/// the capacity is reached.
+pub struct SeenPublications {
+    digests: HashSet<Blake2bDigest256>,
+    order: VecDeque<Blake2bDigest256>,
+    capacity: usize,
+}
+
+impl SeenPublications {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            digests: HashSet::new(),
+            order: VecDeque::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Remember the digest. Returns `false` if it was already seen.
+    pub fn insert(&mut self, digest: Blake2bDigest256) -> bool {
+        if !self.digests.insert(digest) {
+            return false;
+        }
+        self.order.push_back(digest);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.digests.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use spectrum_crypto::digest::blake2b256_hash;
+
+    use crate::protocol_handler::gossip::seen::SeenPublications;
+
+    #[test]
+    fn duplicates_are_detected_until_forgotten() {
+        let mut seen = SeenPublications::new(2);
+        let (d1, d2, d3) = (
+            blake2b256_hash(b"1"),
+            blake2b256_hash(b"2"),
+            blake2b256_hash(b"3"),
+        );
+        assert!(seen.insert(d1));
+        assert!(!seen.insert(d1));
+        assert!(seen.insert(d2));
+        assert!(seen.insert(d3));
+        // The oldest digest was evicted.
+        assert!(seen.insert(d1));
+        assert!(!seen.insert(d3));
+    }
+}
@@ -6,7 +6,7 @@ use std::future::Future;
 use std::ops::{Add, Mul};
 use std::pin::Pin;
 use std::task::{Context, Poll};
-use std::time::{Duration, Instant};
+use std::time::Duration;
 use tracing::trace;
 
 use either::{Either, Left, Right};
@@ -17,17 +17,25 @@ use libp2p::PeerId;
 use algebra_core::CommutativePartialSemigroup;
 use spectrum_crypto::VerifiableAgainst;
 
+use crate::protocol_handler::handel::activation::{AdaptiveActivationConfig, LatencyTracker};
 use crate::protocol_handler::handel::message::HandelMessage;
 use crate::protocol_handler::handel::partitioning::{PeerIx, PeerOrd, PeerPartitions};
 use crate::protocol_handler::void::VoidMessage;
 use crate::protocol_handler::{NetworkAction, ProtocolBehaviourOut, TemporalProtocolStage};
 use crate::types::ProtocolVer;
 
+pub mod activation;
 pub mod message;
 pub mod partitioning;
 
 pub trait Weighted {
     fn weight(&self) -> usize;
+
+    /// Weight of the contribution in a committee with the given weights of members.
+    /// Contributions which don't track their contributors fall back to [`Weighted::weight`].
+    fn stake_weight(&self, _weights: &PeerWeights) -> usize {
+        self.weight()
+    }
 }
 
 #[derive(Copy, Clone, Serialize, Deserialize, Debug)]
@@ -42,6 +50,31 @@ impl Threshold {
     }
 }
 
+/// Weights (e.g. stake) of committee members. Thresholds are evaluated over the total weight.
+/// Members without explicit weight weigh 1, so by default all members are equal.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PeerWeights(HashMap<PeerIx, usize>);
+
+impl PeerWeights {
+    pub fn is_uniform(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn of(&self, peer_ix: &PeerIx) -> usize {
+        self.0.get(peer_ix).copied().unwrap_or(1)
+    }
+
+    pub fn total<'a, I: IntoIterator<Item = &'a PeerIx>>(&self, peers: I) -> usize {
+        peers.into_iter().map(|pix| self.of(pix)).sum()
+    }
+}
+
+impl From<HashMap<PeerIx, usize>> for PeerWeights {
+    fn from(weights: HashMap<PeerIx, usize>) -> Self {
+        Self(weights)
+    }
+}
+
 #[derive(Clone, Debug)]
 struct ActiveLevel<C> {
     prioritized_contributions: Vec<PendingContribution<C>>,
@@ -91,6 +124,22 @@ pub struct HandelConfig {
     pub dissemination_delay: Duration,
     pub level_activation_delay: Duration,
     pub throttle_factor: u32,
+    /// Activate levels depending on observed latencies of peers rather than after
+    /// `level_activation_delay`, which is used until latencies are known.
+    pub adaptive_activation: Option<AdaptiveActivationConfig>,
+}
+
+/// Progress of aggregation. Levels 0 and 1 are active from the start.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HandelProgress {
+    LevelActivated {
+        level: usize,
+    },
+    /// Best contribution at the level reached the threshold.
+    LevelCompleted {
+        level: usize,
+        score: usize,
+    },
 }
 
 /// A round of Handel protocol that drives aggregation of contribution `C`.
@@ -103,6 +152,9 @@ pub struct Handel<C, P, PP> {
     levels: Vec<Option<ActiveLevel<C>>>,
     /// Keeps track of byzantine peers.
     byzantine_nodes: HashSet<PeerIx>,
+    /// Peers known to be lost for the whole round. They are never contacted.
+    excluded_peers: HashSet<PeerIx>,
+    peer_weights: PeerWeights,
     /// Keeps track of the peers to whom we've sent our own contribution already.
     own_contribution_recvs: HashSet<PeerIx>,
     outbox: VecDeque<ProtocolBehaviourOut<VoidMessage, HandelMessage<C>>>,
@@ -113,6 +165,9 @@ pub struct Handel<C, P, PP> {
     next_processing: Option<Pin<Box<tokio::time::Sleep>>>,
     next_dissemination: Pin<Box<tokio::time::Sleep>>,
     next_activation: Pin<Box<tokio::time::Sleep>>,
+    /// Set if levels are activated adaptively.
+    latencies: Option<LatencyTracker>,
+    progress: VecDeque<HandelProgress>,
 }
 
 impl<C, P, PP> Handel<C, P, PP>
@@ -143,6 +198,8 @@ where
             peer_partitions,
             levels,
             byzantine_nodes: HashSet::new(),
+            excluded_peers: HashSet::new(),
+            peer_weights: PeerWeights::default(),
             own_contribution_recvs: HashSet::new(),
             outbox: VecDeque::new(),
             own_peer_ix,
@@ -150,12 +207,77 @@ where
             next_processing: None,
             next_dissemination: Box::pin(tokio::time::sleep(conf.dissemination_delay)),
             next_activation: Box::pin(tokio::time::sleep(conf.level_activation_delay)),
+            latencies: conf.adaptive_activation.map(LatencyTracker::new),
+            progress: VecDeque::new(),
+        }
+    }
+
+    /// Next event of aggregation progress, see [`HandelProgress`].
+    pub fn next_progress(&mut self) -> Option<HandelProgress> {
+        self.progress.pop_front()
+    }
+
+    /// Delay before activating the level following the given one.
+    fn activation_delay(&self, level: usize) -> Duration {
+        self.latencies
+            .as_ref()
+            .and_then(|latencies| {
+                let peers_at_level =
+                    live_peers_at_level(&self.peer_partitions, &self.excluded_peers, level, PeerOrd::VP);
+                latencies.activation_delay(&peers_at_level)
+            })
+            .unwrap_or(self.conf.level_activation_delay)
+    }
+
+    /// Treat the given peers as byzantine from the start, e.g. when they are known to be lost.
+    /// Levels are then completed without waiting for their contributions.
+    pub fn with_excluded_peers(mut self, peers: HashSet<PeerIx>) -> Self {
+        self.byzantine_nodes.extend(peers.iter().copied());
+        self.excluded_peers = peers;
+        // The first level is activated eagerly, re-activate it in case its peer is excluded.
+        self.levels[1] = None;
+        self.try_activate_level(1);
+        self
+    }
+
+    /// Weigh contributions by the given weights of committee members rather than
+    /// by the number of contributors.
+    pub fn with_peer_weights(mut self, weights: PeerWeights) -> Self {
+        for Verified(best_contrib) in self
+            .levels
+            .iter_mut()
+            .flatten()
+            .map(|lvl| &mut lvl.best_contribution)
+        {
+            best_contrib.score = best_contrib.contribution.stake_weight(&weights);
         }
+        self.peer_weights = weights;
+        self
+    }
+
+    /// Max weight of a contribution at levels up to the given one (inclusive).
+    fn max_weight_up_to(&self, level: usize) -> usize {
+        let peers_up_to_level = (1..=level)
+            .flat_map(|l| self.peer_partitions.peers_at_level(l, PeerOrd::VP))
+            .collect::<Vec<_>>();
+        // Excluded peers never contribute, so they don't count towards the max weight.
+        let excluded_weight = self.peer_weights.total(
+            peers_up_to_level
+                .iter()
+                .filter(|pix| self.excluded_peers.contains(pix)),
+        );
+        let total_weight = if self.peer_weights.is_uniform() {
+            (2 as usize).pow(level as u32)
+        } else {
+            self.peer_weights.of(&self.own_peer_ix) + self.peer_weights.total(&peers_up_to_level)
+        };
+        total_weight.saturating_sub(excluded_weight)
     }
 
     /// Run aggregation on the specified level.
     #[tracing::instrument(skip(self), level = "trace")]
     fn run_aggregation(&mut self, level: usize) {
+        let max_weight = self.max_weight_up_to(level);
         if let Some(lvl) = &mut self.levels[level] {
             // Prioritize contributions
             if !self.unverified_contributions[level].is_empty() {
@@ -203,7 +325,7 @@ where
                     .try_combine(&c.aggregate_contribution)
                 {
                     Some(aggr) => {
-                        let score = aggr.weight();
+                        let score = aggr.stake_weight(&self.peer_weights);
                         trace!(
                             "{:?} successful contribution (weight: {} ",
                             self.own_peer_ix,
@@ -223,7 +345,7 @@ where
                                 acc_aggr = aggr;
                             }
                         }
-                        let score = acc_aggr.weight();
+                        let score = acc_aggr.stake_weight(&self.peer_weights);
                         scored_contributions.insert(ScoredContributionTraced {
                             score,
                             sender_id: c.sender_id,
@@ -258,7 +380,16 @@ where
                 }
             }
             let Verified(best_contrib) = &lvl.best_contribution;
-            if is_complete(&best_contrib.contribution, level, self.conf.threshold) {
+            if is_complete(
+                &best_contrib.contribution,
+                &self.peer_weights,
+                max_weight,
+                self.conf.threshold,
+            ) {
+                self.progress.push_back(HandelProgress::LevelCompleted {
+                    level,
+                    score: best_contrib.score,
+                });
                 lvl.completed();
                 trace!("{:?}: RFP @ level {}", self.own_peer_ix, level);
                 self.run_fast_path(level);
@@ -273,9 +404,16 @@ where
         if self.levels.get(level).is_some() {
             if !self.is_active(level) {
                 if let Some(prev_level) = self.levels[level - 1].as_ref() {
-                    let peers_at_level = self.peer_partitions.peers_at_level(level, PeerOrd::VP);
+                    let peers_at_level =
+                        live_peers_at_level(&self.peer_partitions, &self.excluded_peers, level, PeerOrd::VP);
+                    self.progress.push_back(HandelProgress::LevelActivated { level });
                     if peers_at_level.is_empty() {
                         // This level is empty, skip it
+                        let Verified(best_contrib) = &prev_level.best_contribution;
+                        self.progress.push_back(HandelProgress::LevelCompleted {
+                            level,
+                            score: best_contrib.score,
+                        });
                         self.levels[level] = Some(ActiveLevel::unit(prev_level.best_contribution.clone()));
                         self.try_activate_level(level + 1);
                     } else {
@@ -301,7 +439,14 @@ where
         aggregate_contribution: C,
         individual_contribution: Option<C>,
     ) -> Result<(), ()> {
+        if level as usize >= self.levels.len() {
+            trace!("Got contribution @ unknown level {} from {:?}", level, peer_id);
+            return Err(());
+        }
         if let Some(peer_ix) = self.peer_partitions.try_index_peer(peer_id) {
+            if let Some(latencies) = &mut self.latencies {
+                latencies.on_response(peer_ix, tokio::time::Instant::now());
+            }
             let is_byzantine = self.byzantine_nodes.contains(&peer_ix);
             if !contact_sender {
                 self.peers_completed_levels
@@ -338,8 +483,12 @@ where
         if let Some(lvl) = &mut self.levels[level] {
             assert!(lvl.is_completed);
             let offset = lvl.last_contacted_peer_ix.map(|x| x + 1).unwrap_or(0);
-            let nodes_at_level = self.peer_partitions.peers_at_level(level, PeerOrd::CVP);
+            let nodes_at_level =
+                live_peers_at_level(&self.peer_partitions, &self.excluded_peers, level, PeerOrd::CVP);
             trace!("CVP_nodes_at_level: {:?}", nodes_at_level);
+            if nodes_at_level.is_empty() {
+                return;
+            }
             let indexes = (0..self.conf.fast_path_window)
                 .map(|ix| (ix + offset) % nodes_at_level.len())
                 .collect::<Vec<_>>();
@@ -363,6 +512,9 @@ where
             trace!("nodes_to_message: {:?}", nodes);
             for pix in nodes {
                 trace!("Sending contribution to {:?}", pix);
+                if let Some(latencies) = &mut self.latencies {
+                    latencies.on_contacted(pix, tokio::time::Instant::now());
+                }
                 let pid = self.peer_partitions.identify_peer(pix);
                 let maybe_own_contrib = if !self.own_contribution_recvs.contains(&pix) {
                     own_contrib.clone()
@@ -393,7 +545,8 @@ where
         let own_contrib = self.get_own_contribution();
         for (lix, lvl) in &mut self.levels.iter_mut().enumerate().skip(1) {
             if let Some(active_lvl) = lvl {
-                let peers_at_level = self.peer_partitions.peers_at_level(lix, PeerOrd::CVP);
+                let peers_at_level =
+                    live_peers_at_level(&self.peer_partitions, &self.excluded_peers, lix, PeerOrd::CVP);
                 if peers_at_level.iter().all(|peer_ix| {
                     assert_ne!(*peer_ix, self.own_peer_ix);
                     if let Some(completed_levels) = self.peers_completed_levels.get(peer_ix) {
@@ -493,6 +646,9 @@ where
                     active_lvl.sent_contribution_scores[next_peer_level_ix] = best_contrib.score;
                 }
                 trace!("Disseminating @ level {} to {:?}", lix, next_peer_ix);
+                if let Some(latencies) = &mut self.latencies {
+                    latencies.on_contacted(next_peer_ix, tokio::time::Instant::now());
+                }
                 self.outbox.push_back(ProtocolBehaviourOut::NetworkAction(
                     NetworkAction::SendOneShotMessage {
                         peer: next_peer,
@@ -561,10 +717,26 @@ where
     }
 }
 
-fn is_complete<C: Weighted>(contribution: &C, level: usize, threshold: Threshold) -> bool {
-    let weight = contribution.weight();
-    let max_score_at_level = (2 as usize).pow(level as u32);
-    let threshold = threshold.min(max_score_at_level);
+/// Peers at the given level except for the excluded ones.
+fn live_peers_at_level<PP: PeerPartitions>(
+    peer_partitions: &PP,
+    excluded_peers: &HashSet<PeerIx>,
+    level: usize,
+    ord: PeerOrd,
+) -> Vec<PeerIx> {
+    let mut peers = peer_partitions.peers_at_level(level, ord);
+    peers.retain(|pix| !excluded_peers.contains(pix));
+    peers
+}
+
+fn is_complete<C: Weighted>(
+    contribution: &C,
+    weights: &PeerWeights,
+    max_weight_at_level: usize,
+    threshold: Threshold,
+) -> bool {
+    let weight = contribution.stake_weight(weights);
+    let threshold = threshold.min(max_weight_at_level);
     weight >= threshold
 }
 
@@ -657,7 +829,8 @@ where
             Poll::Ready(_) => {
                 if let Some(lvl) = self.next_non_active_level() {
                     self.try_activate_level(lvl);
-                    self.next_activation = Box::pin(tokio::time::sleep(self.conf.level_activation_delay));
+                    let delay = self.activation_delay(lvl);
+                    self.next_activation = Box::pin(tokio::time::sleep(delay));
                 }
             }
             Poll::Pending => {}
@@ -699,8 +872,22 @@ impl<C, P, PP> NarrowTo<PP> for Handel<C, P, PP> {
     }
 }
 
+pub trait ObserveProgress {
+    fn next_progress(&mut self) -> Option<HandelProgress>;
+}
+
+impl<C, P, PP> ObserveProgress for Handel<C, P, PP>
+where
+    C: CommutativePartialSemigroup + Weighted + VerifiableAgainst<P> + Clone + Eq + Debug,
+    PP: PeerPartitions,
+{
+    fn next_progress(&mut self) -> Option<HandelProgress> {
+        Handel::next_progress(self)
+    }
+}
+
 pub trait HandelRound<'a, C, PP>:
-    TemporalProtocolStage<VoidMessage, HandelMessage<C>, C> + NarrowTo<PP> + 'a
+    TemporalProtocolStage<VoidMessage, HandelMessage<C>, C> + NarrowTo<PP> + ObserveProgress + 'a
 {
 }
 
@@ -714,7 +901,7 @@ where
 
 #[cfg(test)]
 mod tests {
-    use std::collections::HashSet;
+    use std::collections::{HashMap, HashSet};
     use std::time::Duration;
 
     use libp2p::{Multiaddr, PeerId};
@@ -726,7 +913,9 @@ mod tests {
     use crate::protocol_handler::handel::partitioning::{
         BinomialPeerPartitions, PeerIx, PeerOrd, PeerPartitions, PseudoRandomGenPerm,
     };
-    use crate::protocol_handler::handel::{Handel, HandelConfig, Threshold, Weighted};
+    use crate::protocol_handler::handel::{
+        Handel, HandelConfig, HandelProgress, PeerWeights, Threshold, Weighted,
+    };
     use crate::protocol_handler::{NetworkAction, ProtocolBehaviourOut, TemporalProtocolStage};
 
     #[derive(Clone, Eq, PartialEq, Debug)]
@@ -736,6 +925,13 @@ mod tests {
         fn weight(&self) -> usize {
             self.0.len()
         }
+
+        fn stake_weight(&self, weights: &PeerWeights) -> usize {
+            self.0
+                .iter()
+                .map(|ix| weights.of(&PeerIx::from(*ix as usize)))
+                .sum()
+        }
     }
 
     impl CommutativePartialSemigroup for Contrib {
@@ -758,6 +954,7 @@ mod tests {
         dissemination_delay: Duration::from_millis(2000),
         level_activation_delay: Duration::from_millis(400),
         throttle_factor: 5,
+        adaptive_activation: None,
     };
 
     fn make_handel(
@@ -876,6 +1073,113 @@ mod tests {
         assert!(handel.levels[2].is_some());
         assert!(handel.levels[2].as_ref().unwrap().is_completed);
         assert!(handel.levels[3].is_some());
+        let progress = std::iter::from_fn(|| handel.next_progress()).collect::<Vec<_>>();
+        assert_eq!(
+            progress,
+            vec![
+                HandelProgress::LevelCompleted { level: 1, score: 4 },
+                HandelProgress::LevelActivated { level: 2 },
+                HandelProgress::LevelCompleted { level: 2, score: 4 },
+                HandelProgress::LevelActivated { level: 3 },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn excluded_peers_are_not_awaited() {
+        let my_contrib = Contrib(HashSet::from([0]));
+        let peers = vec![
+            vec![],
+            vec![PeerId::random()],
+            vec![PeerId::random(), PeerId::random()],
+        ];
+        let pp = FakePartitions::new(peers.clone());
+        let lost_peer = pp.try_index_peer(peers[2][1]).unwrap();
+        let mut handel = Handel::new(CONF, my_contrib, (), pp, PeerIx::from(0_usize))
+            .with_excluded_peers(HashSet::from([lost_peer]));
+        let res = handel.handle_contribution(
+            peers[1][0],
+            1,
+            false,
+            Contrib(HashSet::from([1])),
+            Some(Contrib(HashSet::from([1]))),
+        );
+        assert!(res.is_ok());
+        handel.run_aggregation(1);
+        assert!(handel.levels[2].is_some());
+        let res = handel.handle_contribution(
+            peers[2][0],
+            2,
+            false,
+            Contrib(HashSet::from([2])),
+            Some(Contrib(HashSet::from([2]))),
+        );
+        assert!(res.is_ok());
+        handel.run_aggregation(2);
+        assert_eq!(
+            handel.get_complete_aggregate(),
+            Some(Contrib(HashSet::from([0, 1, 2])))
+        );
+        let res = handel.handle_contribution(
+            peers[2][1],
+            2,
+            false,
+            Contrib(HashSet::from([3])),
+            Some(Contrib(HashSet::from([3]))),
+        );
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    async fn thresholds_evaluated_over_weights() {
+        let peers = vec![
+            vec![],
+            vec![PeerId::random()],
+            vec![PeerId::random(), PeerId::random()],
+        ];
+        let pp = FakePartitions::new(peers.clone());
+        let ix = |pid: PeerId| pp.try_index_peer(pid).unwrap();
+        let (level_1_peer, light_peer, heavy_peer) = (ix(peers[1][0]), ix(peers[2][0]), ix(peers[2][1]));
+        let weights = PeerWeights::from(HashMap::from([(heavy_peer, 10)]));
+        let contrib = |pix: PeerIx| Contrib(HashSet::from([pix.unwrap() as u32]));
+        let own_peer_ix = PeerIx::from(3_usize);
+        let conf = HandelConfig {
+            threshold: Threshold { num: 2, denom: 3 },
+            ..CONF
+        };
+        let mut handel =
+            Handel::new(conf, contrib(own_peer_ix), (), pp, own_peer_ix).with_peer_weights(weights);
+        let res = handel.handle_contribution(
+            peers[1][0],
+            1,
+            false,
+            contrib(level_1_peer),
+            Some(contrib(level_1_peer)),
+        );
+        assert!(res.is_ok());
+        handel.run_aggregation(1);
+        assert!(handel.levels[2].is_some());
+        // 3 of 4 members, but only 3 of 13 units of weight.
+        let res = handel.handle_contribution(
+            peers[2][0],
+            2,
+            false,
+            contrib(light_peer),
+            Some(contrib(light_peer)),
+        );
+        assert!(res.is_ok());
+        handel.run_aggregation(2);
+        assert!(!handel.levels[2].as_ref().unwrap().is_completed);
+        let res = handel.handle_contribution(
+            peers[2][1],
+            2,
+            false,
+            contrib(heavy_peer),
+            Some(contrib(heavy_peer)),
+        );
+        assert!(res.is_ok());
+        handel.run_aggregation(2);
+        assert!(handel.levels[2].as_ref().unwrap().is_completed);
     }
 
     #[tokio::test]
@@ -962,6 +1266,7 @@ mod tests {
             dissemination_delay: Duration::from_millis(2000),
             level_activation_delay: Duration::from_millis(400),
             throttle_factor: 5,
+            adaptive_activation: None,
         };
 
         let byzantine_nodes = vec![0, 1, 2, 3, 9, 10];
@@ -0,0 +1,130 @@
+//! Adaptive activation of Handel levels.
+//!
+//! Levels are activated one after another. Instead of waiting for a fixed delay, the next level is
+//! activated once the fastest peers at the current one had time to respond, as estimated from
+//! latencies observed earlier in the round.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::protocol_handler::handel::partitioning::PeerIx;
+
+#[derive(Copy, Clone, Debug)]
+pub struct AdaptiveActivationConfig {
+    /// The next level is activated after `latency_factor` times the latency of the fastest peer
+    /// at the current level.
+    pub latency_factor: u32,
+    pub min_activation_delay: Duration,
+    pub max_activation_delay: Duration,
+    /// Weight of a new sample in the moving average of latency of a peer, in percents.
+    pub smoothing_pct: u32,
+}
+
+/// Latencies of peers, measured from the moment a peer is contacted until it responds.
+pub struct LatencyTracker {
+    conf: AdaptiveActivationConfig,
+    /// Peers contacted and not responded since, along with the time of the first such contact.
+    contacted: HashMap<PeerIx, Instant>,
+    latencies: HashMap<PeerIx, Duration>,
+}
+
+impl LatencyTracker {
+    pub fn new(conf: AdaptiveActivationConfig) -> Self {
+        Self {
+            conf,
+            contacted: HashMap::new(),
+            latencies: HashMap::new(),
+        }
+    }
+
+    pub fn on_contacted(&mut self, peer_ix: PeerIx, now: Instant) {
+        self.contacted.entry(peer_ix).or_insert(now);
+    }
+
+    /// Contributions of peers which weren't contacted don't tell anything about their latency.
+    pub fn on_response(&mut self, peer_ix: PeerIx, now: Instant) {
+        if let Some(contacted_at) = self.contacted.remove(&peer_ix) {
+            let sample = now.saturating_duration_since(contacted_at);
+            let pct = self.conf.smoothing_pct.min(100);
+            let latency = match self.latencies.get(&peer_ix) {
+                Some(avg) => (*avg * (100 - pct) + sample * pct) / 100,
+                None => sample,
+            };
+            self.latencies.insert(peer_ix, latency);
+        }
+    }
+
+    pub fn latency(&self, peer_ix: &PeerIx) -> Option<Duration> {
+        self.latencies.get(peer_ix).copied()
+    }
+
+    /// Delay before activating the level following the one with the given peers.
+    /// `None` if latency of none of them is known yet.
+    pub fn activation_delay(&self, peers_at_level: &[PeerIx]) -> Option<Duration> {
+        peers_at_level
+            .iter()
+            .filter_map(|pix| self.latencies.get(pix))
+            .min()
+            .map(|fastest| {
+                (*fastest * self.conf.latency_factor)
+                    .clamp(self.conf.min_activation_delay, self.conf.max_activation_delay)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use crate::protocol_handler::handel::activation::{AdaptiveActivationConfig, LatencyTracker};
+    use crate::protocol_handler::handel::partitioning::PeerIx;
+
+    const CONF: AdaptiveActivationConfig = AdaptiveActivationConfig {
+        latency_factor: 2,
+        min_activation_delay: Duration::from_millis(20),
+        max_activation_delay: Duration::from_millis(400),
+        smoothing_pct: 50,
+    };
+
+    #[test]
+    fn latency_measured_from_first_contact() {
+        let mut tracker = LatencyTracker::new(CONF);
+        let peer = PeerIx::from(1_usize);
+        let now = Instant::now();
+        // Unsolicited contribution.
+        tracker.on_response(peer, now);
+        assert_eq!(tracker.latency(&peer), None);
+        tracker.on_contacted(peer, now);
+        tracker.on_contacted(peer, now + Duration::from_millis(50));
+        tracker.on_response(peer, now + Duration::from_millis(100));
+        assert_eq!(tracker.latency(&peer), Some(Duration::from_millis(100)));
+        tracker.on_contacted(peer, now + Duration::from_millis(200));
+        tracker.on_response(peer, now + Duration::from_millis(250));
+        assert_eq!(tracker.latency(&peer), Some(Duration::from_millis(75)));
+    }
+
+    #[test]
+    fn fastest_peer_drives_activation() {
+        let mut tracker = LatencyTracker::new(CONF);
+        let (fast, slow, unknown) = (
+            PeerIx::from(1_usize),
+            PeerIx::from(2_usize),
+            PeerIx::from(3_usize),
+        );
+        let now = Instant::now();
+        assert_eq!(tracker.activation_delay(&[fast, slow, unknown]), None);
+        tracker.on_contacted(fast, now);
+        tracker.on_contacted(slow, now);
+        tracker.on_response(fast, now + Duration::from_millis(30));
+        tracker.on_response(slow, now + Duration::from_secs(1));
+        assert_eq!(
+            tracker.activation_delay(&[fast, slow, unknown]),
+            Some(Duration::from_millis(60))
+        );
+        assert_eq!(tracker.activation_delay(&[slow]), Some(CONF.max_activation_delay));
+    }
+}
@@ -0,0 +1,167 @@
+//! Human-readable decoding of raw protocol messages, e.g. those recorded in the event journal.
+//!
+//! Messages are decoded the same way protocol handlers do, including the check that the version
+//! of the decoded message matches the negotiated one. Mismatches are reported rather than treated
+//! as errors, as they are what usually goes wrong between nodes of different versions.
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use crate::protocol::{DISCOVERY_PROTOCOL_ID, DKG_PROTOCOL_ID, GOSSIP_PROTOCOL_ID, SIGMA_AGGR_PROTOCOL_ID};
+use crate::protocol_handler::codec;
+use crate::protocol_handler::discovery::message::DiscoverySpec;
+use crate::protocol_handler::dkg::message::DkgSpec;
+use crate::protocol_handler::gossip::message::GossipSpec;
+use crate::protocol_handler::sigma_aggregation::SigmaAggrSpec;
+use crate::protocol_handler::versioning::Versioned;
+use crate::protocol_handler::ProtocolSpec;
+use crate::types::{ProtocolId, ProtocolVer, RawMessage};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Inspection {
+    Decoded {
+        protocol: &'static str,
+        message: String,
+    },
+    /// Message decoded, but it belongs to a version other than the negotiated one.
+    /// Such messages are rejected by protocol handlers.
+    VersionMismatch {
+        protocol: &'static str,
+        actual_ver: ProtocolVer,
+        message: String,
+    },
+    Undecodable {
+        protocol: &'static str,
+        error: String,
+    },
+    UnknownProtocol(ProtocolId),
+}
+
+impl Display for Inspection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Inspection::Decoded { protocol, message } => write!(f, "{}: {}", protocol, message),
+            Inspection::VersionMismatch {
+                protocol,
+                actual_ver,
+                message,
+            } => write!(
+                f,
+                "{}: {} [version mismatch: message of v{}]",
+                protocol,
+                message,
+                u8::from(*actual_ver)
+            ),
+            Inspection::Undecodable { protocol, error } => write!(f, "{}: undecodable ({})", protocol, error),
+            Inspection::UnknownProtocol(protocol_id) => {
+                write!(f, "unknown protocol {}", u8::from(*protocol_id))
+            }
+        }
+    }
+}
+
+type Decoder = Box<dyn Fn(ProtocolVer, &[u8]) -> Inspection>;
+
+/// Decoders of messages of known protocols.
+pub struct MessageInspector {
+    decoders: BTreeMap<ProtocolId, (&'static str, Decoder)>,
+}
+
+impl MessageInspector {
+    pub fn new() -> Self {
+        Self {
+            decoders: BTreeMap::new(),
+        }
+    }
+
+    /// Protocols defined in this crate.
+    pub fn with_builtin_protocols(self) -> Self {
+        self.with_protocol::<DiscoverySpec>(DISCOVERY_PROTOCOL_ID, "discovery")
+            .with_protocol::<SigmaAggrSpec>(SIGMA_AGGR_PROTOCOL_ID, "sigma-aggregation")
+            .with_protocol::<GossipSpec>(GOSSIP_PROTOCOL_ID, "gossip")
+            .with_protocol::<DkgSpec>(DKG_PROTOCOL_ID, "dkg")
+    }
+
+    /// Decode messages of the given protocol as `TProto::TMessage`.
+    pub fn with_protocol<TProto>(mut self, protocol_id: ProtocolId, name: &'static str) -> Self
+    where
+        TProto: ProtocolSpec + 'static,
+    {
+        let decoder: Decoder = Box::new(move |negotiated_ver, bytes| {
+            match codec::decode::<TProto::TMessage>(RawMessage::from(bytes.to_vec())) {
+                Ok(msg) if msg.version() == negotiated_ver => Inspection::Decoded {
+                    protocol: name,
+                    message: format!("{:?}", msg),
+                },
+                Ok(msg) => Inspection::VersionMismatch {
+                    protocol: name,
+                    actual_ver: msg.version(),
+                    message: format!("{:?}", msg),
+                },
+                Err(err) => Inspection::Undecodable {
+                    protocol: name,
+                    error: err.to_string(),
+                },
+            }
+        });
+        self.decoders.insert(protocol_id, (name, decoder));
+        self
+    }
+
+    /// Name of the protocol if it's known.
+    pub fn protocol_name(&self, protocol_id: ProtocolId) -> Option<&'static str> {
+        self.decoders.get(&protocol_id).map(|(name, _)| *name)
+    }
+
+    /// Decode a message received under the given protocol and negotiated version.
+    pub fn inspect(&self, protocol_id: ProtocolId, negotiated_ver: ProtocolVer, bytes: &[u8]) -> Inspection {
+        match self.decoders.get(&protocol_id) {
+            Some((_, decode)) => decode(negotiated_ver, bytes),
+            None => Inspection::UnknownProtocol(protocol_id),
+        }
+    }
+}
+
+impl Default for MessageInspector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::{DISCOVERY_PROTOCOL_ID, GOSSIP_PROTOCOL_ID};
+    use crate::protocol_handler::codec;
+    use crate::protocol_handler::discovery::message::{DiscoveryMessage, DiscoveryMessageV1, DiscoverySpec};
+    use crate::protocol_handler::inspect::{Inspection, MessageInspector};
+    use crate::types::{ProtocolId, ProtocolVer};
+
+    #[test]
+    fn messages_decoded_against_negotiated_version() {
+        let inspector = MessageInspector::new().with_builtin_protocols();
+        let msg = DiscoveryMessage::DiscoveryMessageV1(DiscoveryMessageV1::GetPeers);
+        let bytes = Vec::<u8>::from(codec::encode(&msg));
+        assert_eq!(
+            inspector.inspect(DISCOVERY_PROTOCOL_ID, DiscoverySpec::v1(), &bytes),
+            Inspection::Decoded {
+                protocol: "discovery",
+                message: "DiscoveryMessageV1(GetPeers)".to_string()
+            }
+        );
+        assert!(matches!(
+            inspector.inspect(DISCOVERY_PROTOCOL_ID, DiscoverySpec::v2(), &bytes),
+            Inspection::VersionMismatch { actual_ver, .. } if actual_ver == DiscoverySpec::v1()
+        ));
+        assert!(matches!(
+            inspector.inspect(GOSSIP_PROTOCOL_ID, ProtocolVer(1), &bytes),
+            Inspection::Undecodable {
+                protocol: "gossip",
+                ..
+            }
+        ));
+        assert_eq!(
+            inspector.inspect(ProtocolId::from_u8(200), ProtocolVer(1), &bytes),
+            Inspection::UnknownProtocol(ProtocolId::from_u8(200))
+        );
+    }
+}
@@ -32,7 +32,7 @@ pub struct DagMulticasting<S, P, PP> {
     pub contacted_peers: HashSet<PeerId>,
     pub outbox: VecDeque<ProtocolBehaviourOut<VoidMessage, S>>,
     partitions: PP,
-    creation_time: std::time::Instant,
+    creation_time: tokio::time::Instant,
     processing_delay: Duration,
     next_processing: Option<Pin<Box<tokio::time::Sleep>>>,
     multicasting_duration: Duration,
@@ -71,7 +71,7 @@ where
             contacted_peers: HashSet::new(),
             outbox: VecDeque::new(),
             partitions,
-            creation_time: std::time::Instant::now(),
+            creation_time: tokio::time::Instant::now(),
             processing_delay: config.processing_delay,
             multicasting_duration: config.multicasting_duration,
             next_processing: Some(Box::pin(tokio::time::sleep(config.processing_delay))),
@@ -134,7 +134,7 @@ where
             }
         }
 
-        let finished_at = std::time::Instant::now();
+        let finished_at = tokio::time::Instant::now();
         let elapsed = finished_at.sub(self.creation_time);
         if elapsed > self.multicasting_duration {
             if let Some(stmt) = self.statement.take() {
@@ -222,8 +222,12 @@ where
     fn inject_message(&mut self, peer_id: PeerId, content: S) {
         if self.overlay.parent_nodes.contains(&peer_id) {
             let pd = Arc::clone(&self.public_data);
-            self.tasks.spawn(|to_behaviour| async move {
-                if let Ok(ver) = content.verify(&pd).await {
+            self.tasks.spawn(|to_behaviour, cancellation| async move {
+                let verified = content.verify(&pd).await;
+                if cancellation.is_cancelled() {
+                    return;
+                }
+                if let Ok(ver) = verified {
                     to_behaviour
                         .send(FromTask::ToBehaviour(ApplyStatement(ver)))
                         .await
@@ -9,6 +9,8 @@ use futures::stream::FuturesUnordered;
 use futures::{FutureExt, Stream};
 use log::warn;
 
+use crate::cancellation::CancellationToken;
+
 /// Tasks talk to protocol behaviour.
 pub enum FromTask<TIn, TOut> {
     /// Inject the input into protocol behaviour.
@@ -24,6 +26,8 @@ pub struct TaskPool<'a, TIn, TOut, R> {
     timeout: Duration,
     /// Communication channel with parental behaviour.
     channel: Sender<FromTask<TIn, TOut>>,
+    /// Parent of the tokens handed over to tasks.
+    cancellation: CancellationToken,
     tasks: FuturesUnordered<Pin<Box<dyn Future<Output = Result<R, TimeoutError>> + Send + 'a>>>,
 }
 
@@ -33,18 +37,27 @@ impl<'a, TIn, TOut, R> TaskPool<'a, TIn, TOut, R> {
             name,
             timeout,
             channel,
+            cancellation: CancellationToken::new(),
             tasks: FuturesUnordered::new(),
         }
     }
 
+    /// Spawn a task. The task is given its own cancellation token which it is expected
+    /// to check before reporting back to the behaviour.
     pub fn spawn<F, T>(&mut self, task: F)
     where
-        F: FnOnce(Sender<FromTask<TIn, TOut>>) -> T,
+        F: FnOnce(Sender<FromTask<TIn, TOut>>, CancellationToken) -> T,
         T: Future<Output = R> + Send + 'a,
         R: 'a,
     {
+        let token = self.cancellation.child_token();
         self.tasks
-            .push(timeout(self.timeout, task(self.channel.clone())).boxed())
+            .push(timeout(self.timeout, task(self.channel.clone(), token)).boxed())
+    }
+
+    /// Cancel all tasks spawned so far. Tasks spawned afterwards are not affected.
+    pub fn cancel(&mut self) {
+        std::mem::take(&mut self.cancellation).cancel();
     }
 }
 
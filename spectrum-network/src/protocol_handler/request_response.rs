@@ -0,0 +1,240 @@
+//! Request/response on top of one-way messaging.
+//!
+//! Behaviours tag outbound requests with a [`RequestId`] obtained from [`PendingRequests`] and
+//! peers echo it back in their responses. Requests left without response are reported by the
+//! tracker once they time out, along with the penalty for the unresponsive peer.
+
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::stream::FuturesUnordered;
+use futures::{FutureExt, Stream};
+use libp2p::PeerId;
+
+use crate::peer_manager::data::ReputationChange;
+
+/// Correlates a response with the request it answers.
+#[derive(
+    Copy,
+    Clone,
+    Eq,
+    PartialEq,
+    Hash,
+    Debug,
+    serde::Serialize,
+    serde::Deserialize,
+    derive_more::From,
+    derive_more::Into,
+    derive_more::Display,
+)]
+pub struct RequestId(u64);
+
+#[derive(Copy, Clone, Debug)]
+pub struct RequestResponseConfig {
+    /// How long to wait for the response before giving up on the request.
+    pub request_timeout: Duration,
+    /// Max number of requests awaiting response from a single peer.
+    pub max_in_flight_per_peer: usize,
+    /// Penalty for a peer which didn't respond in time.
+    pub timeout_penalty: ReputationChange,
+}
+
+#[derive(Debug, Eq, PartialEq, thiserror::Error)]
+pub enum RequestError {
+    #[error("Too many requests in flight to peer {0}")]
+    TooManyInFlight(PeerId),
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum RequestResponseOut<TCtx> {
+    /// Peer didn't respond in time, the request is given up on.
+    TimedOut {
+        peer_id: PeerId,
+        request_id: RequestId,
+        context: TCtx,
+    },
+    /// Reputation of the peer is to be adjusted, see [`crate::protocol_handler::NetworkAction::ReportPeer`].
+    ReportPeer(PeerId, ReputationChange),
+}
+
+struct Pending<TCtx> {
+    peer_id: PeerId,
+    context: TCtx,
+}
+
+/// Requests awaiting response. Each of them carries an arbitrary context,
+/// which is handed back along with the response or once the request times out.
+pub struct PendingRequests<TCtx> {
+    conf: RequestResponseConfig,
+    next_id: u64,
+    pending: HashMap<RequestId, Pending<TCtx>>,
+    in_flight: HashMap<PeerId, usize>,
+    timers: FuturesUnordered<BoxFuture<'static, RequestId>>,
+    /// Penalty to report once the timeout itself is reported.
+    pending_penalty: Option<(PeerId, ReputationChange)>,
+}
+
+impl<TCtx> PendingRequests<TCtx> {
+    pub fn new(conf: RequestResponseConfig) -> Self {
+        Self {
+            conf,
+            next_id: 0,
+            pending: HashMap::new(),
+            in_flight: HashMap::new(),
+            timers: FuturesUnordered::new(),
+            pending_penalty: None,
+        }
+    }
+
+    /// Register a request to the peer. The returned id must be sent along with the request.
+    pub fn register(&mut self, peer_id: PeerId, context: TCtx) -> Result<RequestId, RequestError> {
+        let in_flight = self.in_flight.entry(peer_id).or_insert(0);
+        if *in_flight >= self.conf.max_in_flight_per_peer {
+            return Err(RequestError::TooManyInFlight(peer_id));
+        }
+        *in_flight += 1;
+        let request_id = RequestId(self.next_id);
+        self.next_id += 1;
+        self.pending.insert(request_id, Pending { peer_id, context });
+        let timeout = self.conf.request_timeout;
+        self.timers.push(
+            async move {
+                async_std::task::sleep(timeout).await;
+                request_id
+            }
+            .boxed(),
+        );
+        Ok(request_id)
+    }
+
+    /// Number of requests awaiting response from the peer.
+    pub fn in_flight(&self, peer_id: &PeerId) -> usize {
+        self.in_flight.get(peer_id).copied().unwrap_or(0)
+    }
+
+    /// Match the response with its request. Returns the context of the request unless the request
+    /// is unknown, e.g. has already timed out, or was sent to another peer.
+    pub fn on_response(&mut self, peer_id: PeerId, request_id: RequestId) -> Option<TCtx> {
+        match self.pending.get(&request_id) {
+            Some(req) if req.peer_id == peer_id => self.complete(request_id),
+            _ => None,
+        }
+    }
+
+    /// Give up on all requests to the peer, e.g. once it's disconnected.
+    /// Returns contexts of the requests.
+    pub fn cancel_peer(&mut self, peer_id: PeerId) -> Vec<(RequestId, TCtx)> {
+        let cancelled = self
+            .pending
+            .iter()
+            .filter(|(_, req)| req.peer_id == peer_id)
+            .map(|(rid, _)| *rid)
+            .collect::<Vec<_>>();
+        cancelled
+            .into_iter()
+            .filter_map(|rid| self.complete(rid).map(|ctx| (rid, ctx)))
+            .collect()
+    }
+
+    fn complete(&mut self, request_id: RequestId) -> Option<TCtx> {
+        let req = self.pending.remove(&request_id)?;
+        if let Some(n) = self.in_flight.get_mut(&req.peer_id) {
+            *n -= 1;
+            if *n == 0 {
+                self.in_flight.remove(&req.peer_id);
+            }
+        }
+        Some(req.context)
+    }
+}
+
+impl<TCtx: Unpin> Stream for PendingRequests<TCtx> {
+    type Item = RequestResponseOut<TCtx>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some((peer_id, change)) = self.pending_penalty.take() {
+            return Poll::Ready(Some(RequestResponseOut::ReportPeer(peer_id, change)));
+        }
+        while let Poll::Ready(Some(request_id)) = Stream::poll_next(Pin::new(&mut self.timers), cx) {
+            let peer_id = match self.pending.get(&request_id) {
+                Some(req) => req.peer_id,
+                // Responded in time.
+                None => continue,
+            };
+            let context = self.complete(request_id).unwrap();
+            self.pending_penalty = Some((peer_id, self.conf.timeout_penalty));
+            return Poll::Ready(Some(RequestResponseOut::TimedOut {
+                peer_id,
+                request_id,
+                context,
+            }));
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::StreamExt;
+    use libp2p::PeerId;
+
+    use crate::peer_manager::data::ReputationChange;
+    use crate::protocol_handler::request_response::{
+        PendingRequests, RequestError, RequestResponseConfig, RequestResponseOut,
+    };
+
+    fn requests() -> PendingRequests<&'static str> {
+        PendingRequests::new(RequestResponseConfig {
+            request_timeout: Duration::from_millis(50),
+            max_in_flight_per_peer: 2,
+            timeout_penalty: ReputationChange::NoResponse,
+        })
+    }
+
+    #[test]
+    fn responses_correlated_with_requests() {
+        let mut requests = requests();
+        let peer = PeerId::random();
+        let other_peer = PeerId::random();
+        let a = requests.register(peer, "a").unwrap();
+        let b = requests.register(peer, "b").unwrap();
+        assert_eq!(
+            requests.register(peer, "c"),
+            Err(RequestError::TooManyInFlight(peer))
+        );
+        // Only the peer the request was sent to may respond.
+        assert_eq!(requests.on_response(other_peer, b), None);
+        assert_eq!(requests.on_response(peer, b), Some("b"));
+        assert_eq!(requests.on_response(peer, b), None);
+        assert_eq!(requests.in_flight(&peer), 1);
+        assert_eq!(requests.cancel_peer(peer), vec![(a, "a")]);
+        assert_eq!(requests.in_flight(&peer), 0);
+    }
+
+    #[async_std::test]
+    async fn unresponsive_peer_penalized() {
+        let mut requests = requests();
+        let peer = PeerId::random();
+        let answered = requests.register(peer, "answered").unwrap();
+        let lost = requests.register(peer, "lost").unwrap();
+        requests.on_response(peer, answered);
+        assert_eq!(
+            requests.next().await,
+            Some(RequestResponseOut::TimedOut {
+                peer_id: peer,
+                request_id: lost,
+                context: "lost"
+            })
+        );
+        assert_eq!(
+            requests.next().await,
+            Some(RequestResponseOut::ReportPeer(peer, ReputationChange::NoResponse))
+        );
+        assert_eq!(requests.in_flight(&peer), 0);
+    }
+}
@@ -1,5 +1,6 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt::Debug;
+use std::future::Future;
 use std::mem;
 use std::pin::Pin;
 use std::task::{Context, Poll};
@@ -12,52 +13,60 @@ use futures::channel::mpsc::Receiver;
 use futures::channel::oneshot::Sender;
 use futures::Stream;
 use higher::Bifunctor;
+use k256::schnorr::VerifyingKey;
 use k256::{Scalar, Secp256k1, SecretKey};
 use libp2p::{Multiaddr, PeerId};
-use tracing::{info, trace, trace_span};
+use tracing::{info, trace, trace_span, warn};
 
 use spectrum_crypto::digest::Digest;
 use spectrum_crypto::pubkey::PublicKey;
+use spectrum_crypto::signer::{AsyncSigner, InMemorySigner, SignerFuture};
 
+use crate::protocol::{SIGMA_AGGR_V1, SIGMA_AGGR_V2};
 use crate::protocol_handler::aggregation::AggregationAction;
 use crate::protocol_handler::handel::partitioning::{MakePeerPartitions, PeerIx, PeerPartitions};
-use crate::protocol_handler::handel::{Handel, HandelConfig, HandelRound};
+use crate::protocol_handler::handel::{Handel, HandelConfig, HandelRound, PeerWeights};
 use crate::protocol_handler::multicasting::overlay::{DagOverlay, MakeDagOverlay};
 use crate::protocol_handler::multicasting::{DagMulticasting, Multicasting};
 use crate::protocol_handler::sigma_aggregation::crypto::{
-    aggregate_commitment, aggregate_pk, aggregate_response, challenge, exclusion_proof, individual_input,
-    pre_commitment, response, schnorr_commitment_pair,
-};
-use crate::protocol_handler::sigma_aggregation::message::{
-    SigmaAggrMessage, SigmaAggrMessageV1, SigmaAggrSpec,
+    aggregate_commitment, aggregate_pk, aggregate_response, challenge, individual_input, pre_commitment,
 };
+use crate::protocol_handler::sigma_aggregation::message::{SigmaAggrMessage, SigmaAggrMessageV1};
 use crate::protocol_handler::sigma_aggregation::types::{
-    AggregateCommitment, Commitment, CommitmentSecret, CommitmentsVerifInput, CommitmentsWithProofs,
-    Contributions, PreCommitments, Responses, ResponsesVerifInput, Signature,
+    AggregateCommitment, Commitment, CommitmentsVerifInput, CommitmentsWithProofs, Contributions,
+    PreCommitments, Responses, ResponsesVerifInput, Signature,
 };
+use crate::protocol_handler::sigma_aggregation::validation::{validate_message, Rejection, RoundBounds};
+use crate::protocol_handler::versioning::Versioned;
 use crate::protocol_handler::void::VoidMessage;
-use crate::protocol_handler::ProtocolBehaviourOut;
+use crate::protocol_handler::{NetworkAction, ProtocolBehaviourOut};
 use crate::protocol_handler::{ProtocolBehaviour, TemporalProtocolStage};
+use crate::types::ProtocolVer;
 
 use super::multicasting::DagMulticastingConfig;
 
-mod crypto;
+pub mod crypto;
 mod message;
+pub use message::SigmaAggrSpec;
+pub mod sessions;
+#[cfg(any(test, feature = "testkit"))]
+pub mod sim;
 pub mod types;
+mod validation;
 
 struct AggregatePreCommitments<'a, H: HashMarker + FixedOutput, PP> {
-    /// `x_i`
-    host_sk: SecretKey,
     /// Host's index in the Handel overlay.
     host_ix: PeerIx,
     /// `{X_1, X_2, ..., X_n}`. Set of public keys of committee members.
     committee: HashMap<PeerIx, PublicKey>,
     /// `a_i = H(X_1, X_2, ..., X_n; X_i)`, `{a_1, a_2, ..., a_n}`
     individual_inputs: HashMap<PeerIx, Scalar>,
+    /// Members known to be lost, see [`AggregationAction::Reset`].
+    excluded_peers: HashSet<PeerIx>,
+    /// Weights of members, see [`AggregationAction::Reset`].
+    peer_weights: PeerWeights,
     /// Message that we aggregate signatures for.
     message_digest: Digest<H>,
-    /// `y_i`
-    host_secret: CommitmentSecret,
     /// `Y_i = g^{y_i}`
     host_commitment: Commitment,
     /// `σ_i`. Dlog proof of knowledge for `Y_i`.
@@ -74,22 +83,51 @@ where
     PP: PeerPartitions + Clone + Send + 'static,
 {
     fn init<MPP: MakePeerPartitions<PP = PP>, OB: MakeDagOverlay>(
-        host_sk: SecretKey,
-        committee: HashMap<PublicKey, Option<Multiaddr>>,
-        message_digest: Digest<H>,
+        host_pk: PublicKey,
+        AwaitCommitment {
+            committee,
+            message_digest,
+            excluded_members,
+            member_weights,
+            ..
+        }: AwaitCommitment<H>,
+        (host_commitment, host_explusion_proof): (VerifyingKey, k256::schnorr::Signature),
         partitioner: MPP,
         mcast_overlay_builder: OB,
         handel_conf: HandelConfig,
         multicasting_conf: DagMulticastingConfig,
     ) -> AggregatePreCommitments<'a, H, PP> {
-        let host_pk = PublicKey::from(host_sk.clone());
         let host_pid = PeerId::from(host_pk);
         let peers = committee
             .iter()
             .map(|(pk, maddr)| (PeerId::from(pk), maddr.clone()))
             .collect::<Vec<_>>();
-        let mcast_overlay = mcast_overlay_builder.make(None, host_pid, peers.clone());
+        let excluded_pids = excluded_members
+            .iter()
+            .map(|pk| PeerId::from(pk))
+            .collect::<HashSet<_>>();
+        // Lost members can't relay messages, so they are left out of the multicasting overlay.
+        let live_peers = peers
+            .iter()
+            .filter(|(pid, _)| !excluded_pids.contains(pid))
+            .cloned()
+            .collect::<Vec<_>>();
+        let mcast_overlay = mcast_overlay_builder.make(None, host_pid, live_peers);
         let partitions = partitioner.make(host_pid, peers);
+        let excluded_peers = excluded_pids
+            .into_iter()
+            .filter_map(|pid| partitions.try_index_peer(pid))
+            .collect::<HashSet<_>>();
+        let peer_weights = PeerWeights::from(
+            member_weights
+                .into_iter()
+                .filter_map(|(pk, weight)| {
+                    partitions
+                        .try_index_peer(PeerId::from(&pk))
+                        .map(|pix| (pix, weight))
+                })
+                .collect::<HashMap<_, _>>(),
+        );
         let committee_indexed = committee
             .into_iter()
             .map(|(pk, _)| {
@@ -107,29 +145,33 @@ where
             .iter()
             .map(|(pix, pk)| (*pix, individual_input::<H>(committee_keys.clone(), pk.clone())))
             .collect();
-        let (host_secret, host_commitment) = schnorr_commitment_pair();
+        let host_commitment = Commitment::from(host_commitment);
         let host_pre_commitment = pre_commitment(host_commitment.clone());
         let host_ix = partitions.try_index_peer(host_pid).unwrap();
         trace!("[SA] {:?} <-> {:?}", host_pid, host_ix);
         AggregatePreCommitments {
-            host_sk,
             host_ix,
             committee: committee_indexed,
             individual_inputs: ais,
+            excluded_peers: excluded_peers.clone(),
+            peer_weights: peer_weights.clone(),
             message_digest: message_digest,
-            host_secret: host_secret.clone(),
             host_commitment,
-            host_explusion_proof: exclusion_proof(host_secret, message_digest),
+            host_explusion_proof: Signature::from(host_explusion_proof),
             mcast_overlay,
             multicasting_conf,
             partitions: partitions.clone(),
-            handel: Box::new(Handel::new(
-                handel_conf,
-                Contributions::unit(host_ix, host_pre_commitment),
-                (),
-                partitions,
-                host_ix,
-            )),
+            handel: Box::new(
+                Handel::new(
+                    handel_conf,
+                    Contributions::unit(host_ix, host_pre_commitment),
+                    (),
+                    partitions,
+                    host_ix,
+                )
+                .with_excluded_peers(excluded_peers)
+                .with_peer_weights(peer_weights),
+            ),
         }
     }
 
@@ -140,12 +182,12 @@ where
     ) -> BroadcastPreCommitments<H, PP> {
         let handel_partitions = self.handel.narrow();
         BroadcastPreCommitments {
-            host_sk: self.host_sk,
             host_ix: self.host_ix,
             committee: self.committee,
             individual_inputs: self.individual_inputs,
+            excluded_peers: self.excluded_peers,
+            peer_weights: self.peer_weights,
             message_digest: self.message_digest,
-            host_secret: self.host_secret,
             host_commitment: self.host_commitment.clone(),
             host_explusion_proof: self.host_explusion_proof.clone(),
             handel_partitions: handel_partitions.clone(),
@@ -163,18 +205,18 @@ where
 }
 
 struct BroadcastPreCommitments<H: HashMarker + FixedOutput, PP> {
-    /// `x_i`
-    host_sk: SecretKey,
     /// Host's index in the Handel overlay.
     host_ix: PeerIx,
     /// `{X_1, X_2, ..., X_n}`. Set of public keys of committee members.
     committee: HashMap<PeerIx, PublicKey>,
     /// `a_i = H(X_1, X_2, ..., X_n; X_i)`, `{a_1, a_2, ..., a_n}`
     individual_inputs: HashMap<PeerIx, Scalar>,
+    /// Members known to be lost, see [`AggregationAction::Reset`].
+    excluded_peers: HashSet<PeerIx>,
+    /// Weights of members, see [`AggregationAction::Reset`].
+    peer_weights: PeerWeights,
     /// Message that we aggregate signatures for.
     message_digest: Digest<H>,
-    /// `y_i`
-    host_secret: CommitmentSecret,
     /// `Y_i = g^{y_i}`
     host_commitment: Commitment,
     /// `σ_i`. Dlog proof of knowledge for `Y_i`.
@@ -199,41 +241,45 @@ where
             message_digest_bytes: self.message_digest.as_ref().to_vec(),
         };
         AggregateCommitments {
-            host_sk: self.host_sk,
             host_ix: self.host_ix,
             committee: self.committee,
             individual_inputs: self.individual_inputs,
+            excluded_peers: self.excluded_peers.clone(),
+            peer_weights: self.peer_weights.clone(),
             message_digest: self.message_digest,
-            host_secret: self.host_secret,
             host_commitment: self.host_commitment.clone(),
             host_explusion_proof: self.host_explusion_proof.clone(),
             mcast_overlay: self.mcast_overlay,
             multicasting_conf: self.multicasting_conf,
             partitions: self.handel_partitions.clone(),
-            handel: Box::new(Handel::new(
-                handel_conf,
-                Contributions::unit(self.host_ix, (self.host_commitment, self.host_explusion_proof)),
-                verif_input,
-                self.handel_partitions,
-                self.host_ix,
-            )),
+            handel: Box::new(
+                Handel::new(
+                    handel_conf,
+                    Contributions::unit(self.host_ix, (self.host_commitment, self.host_explusion_proof)),
+                    verif_input,
+                    self.handel_partitions,
+                    self.host_ix,
+                )
+                .with_excluded_peers(self.excluded_peers)
+                .with_peer_weights(self.peer_weights),
+            ),
         }
     }
 }
 
 struct AggregateCommitments<'a, H: HashMarker + FixedOutput, PP> {
-    /// `x_i`
-    host_sk: SecretKey,
     /// Host's index in the Handel overlay.
     host_ix: PeerIx,
     /// `{X_1, X_2, ..., X_n}`. Set of public keys of committee members.
     committee: HashMap<PeerIx, PublicKey>,
     /// `a_i = H(X_1, X_2, ..., X_n; X_i)`, `{a_1, a_2, ..., a_n}`
     individual_inputs: HashMap<PeerIx, Scalar>,
+    /// Members known to be lost, see [`AggregationAction::Reset`].
+    excluded_peers: HashSet<PeerIx>,
+    /// Weights of members, see [`AggregationAction::Reset`].
+    peer_weights: PeerWeights,
     /// Message that we aggregate signatures for.
     message_digest: Digest<H>,
-    /// `y_i`
-    host_secret: CommitmentSecret,
     /// `Y_i = g^{y_i}`
     host_commitment: Commitment,
     /// `σ_i`. Dlog proof of knowledge for `Y_i`.
@@ -251,12 +297,12 @@ where
     fn complete(self, commitments_with_proofs: CommitmentsWithProofs) -> BroadcastCommitments<H, PP> {
         let handel_partitions = self.handel.narrow();
         BroadcastCommitments {
-            host_sk: self.host_sk,
             host_ix: self.host_ix,
             committee: self.committee,
             individual_inputs: self.individual_inputs,
+            excluded_peers: self.excluded_peers,
+            peer_weights: self.peer_weights,
             message_digest: self.message_digest,
-            host_secret: self.host_secret,
             host_commitment: self.host_commitment.clone(),
             host_explusion_proof: self.host_explusion_proof.clone(),
             handel_partitions: handel_partitions.clone(),
@@ -272,18 +318,18 @@ where
 }
 
 struct BroadcastCommitments<H: HashMarker + FixedOutput, PP> {
-    /// `x_i`
-    host_sk: SecretKey,
     /// Host's index in the Handel overlay.
     host_ix: PeerIx,
     /// `{X_1, X_2, ..., X_n}`. Set of public keys of committee members.
     committee: HashMap<PeerIx, PublicKey>,
     /// `a_i = H(X_1, X_2, ..., X_n; X_i)`, `{a_1, a_2, ..., a_n}`
     individual_inputs: HashMap<PeerIx, Scalar>,
+    /// Members known to be lost, see [`AggregationAction::Reset`].
+    excluded_peers: HashSet<PeerIx>,
+    /// Weights of members, see [`AggregationAction::Reset`].
+    peer_weights: PeerWeights,
     /// Message that we aggregate signatures for.
     message_digest: Digest<H>,
-    /// `y_i`
-    host_secret: CommitmentSecret,
     /// `Y_i = g^{y_i}`
     host_commitment: Commitment,
     /// `σ_i`. Dlog proof of knowledge for `Y_i`.
@@ -292,20 +338,20 @@ struct BroadcastCommitments<H: HashMarker + FixedOutput, PP> {
     mcast: Box<dyn Multicasting<CommitmentsWithProofs> + Send>,
 }
 
-impl<'a, H, PP> BroadcastCommitments<H, PP>
+impl<H, PP> BroadcastCommitments<H, PP>
 where
     H: HashMarker + FixedOutput<OutputSize = <Secp256k1 as Curve>::FieldBytesSize> + Default,
-    PP: PeerPartitions + Send + Clone + 'a,
+    PP: PeerPartitions + Send + Clone,
 {
     fn complete(
         self,
         commitments_with_proofs_intersect: CommitmentsWithProofs,
-        handel_conf: HandelConfig,
-    ) -> AggregateResponses<'a, H, PP> {
+        signer: &AsyncSigner,
+    ) -> AwaitResponse<H, PP> {
         // Need to ensure stable ordering for committee and individual inputs. Just sort by PeerIx.
         let mut committee = self.committee.clone().into_iter().collect::<Vec<_>>();
         committee.sort_by_key(|k| k.0);
-        let committee = committee.into_iter().map(|(_, key)| key).collect();
+        let committee = committee.into_iter().map(|(_, key)| key).collect::<Vec<_>>();
 
         let mut individual_inputs = self.individual_inputs.clone().into_iter().collect::<Vec<_>>();
         individual_inputs.sort_by_key(|k| k.0);
@@ -321,31 +367,80 @@ where
         );
         let challenge = challenge(aggr_pk, aggr_commitment.clone(), self.message_digest);
         let individual_input = *self.individual_inputs.get(&self.host_ix).unwrap();
-        let host_response = response(
-            self.host_secret.clone(),
-            self.host_sk.clone(),
-            challenge,
-            individual_input,
-        );
+        // `z_i = y_i + c * a_i * x_i`
+        let response =
+            signer.schnorr_response(self.host_commitment.clone().into(), challenge * individual_input);
         let verif_inputs = ResponsesVerifInput::new(
             commitments_with_proofs_intersect.clone(),
             self.committee.clone(),
             self.individual_inputs.clone(),
             challenge,
         );
-        AggregateResponses {
+        AwaitResponse {
             message_digest: self.message_digest,
             aggr_commitment,
             commitments_with_proofs: commitments_with_proofs_intersect,
             host_ix: self.host_ix,
-            partitions: self.handel_partitions.clone(),
-            handel: Box::new(Handel::new(
-                handel_conf,
-                Contributions::unit(self.host_ix, host_response),
-                verif_inputs,
-                self.handel_partitions,
-                self.host_ix,
-            )),
+            committee_size: self.committee.len(),
+            excluded_peers: self.excluded_peers,
+            peer_weights: self.peer_weights,
+            partitions: self.handel_partitions,
+            verif_inputs,
+            response,
+        }
+    }
+}
+
+/// Waiting for the signer to commit to the message, see [`AggregationAction::Reset`].
+struct AwaitCommitment<H: HashMarker + FixedOutput> {
+    committee: HashMap<PublicKey, Option<Multiaddr>>,
+    message_digest: Digest<H>,
+    excluded_members: HashSet<PublicKey>,
+    member_weights: HashMap<PublicKey, usize>,
+    /// `(Y_i, σ_i)` being produced by the signer.
+    commitment: SignerFuture<(VerifyingKey, k256::schnorr::Signature)>,
+}
+
+/// Waiting for the signer to respond to the challenge.
+struct AwaitResponse<H: HashMarker + FixedOutput, PP> {
+    message_digest: Digest<H>,
+    aggr_commitment: AggregateCommitment,
+    commitments_with_proofs: CommitmentsWithProofs,
+    host_ix: PeerIx,
+    committee_size: usize,
+    /// Members known to be lost, see [`AggregationAction::Reset`].
+    excluded_peers: HashSet<PeerIx>,
+    /// Weights of members, see [`AggregationAction::Reset`].
+    peer_weights: PeerWeights,
+    partitions: PP,
+    verif_inputs: ResponsesVerifInput,
+    /// `z_i` being produced by the signer.
+    response: SignerFuture<Scalar>,
+}
+
+impl<'a, H: HashMarker + FixedOutput, PP> AwaitResponse<H, PP>
+where
+    PP: PeerPartitions + Send + Clone + 'a,
+{
+    fn complete(self, host_response: Scalar, handel_conf: HandelConfig) -> AggregateResponses<'a, H, PP> {
+        AggregateResponses {
+            message_digest: self.message_digest,
+            aggr_commitment: self.aggr_commitment,
+            commitments_with_proofs: self.commitments_with_proofs,
+            host_ix: self.host_ix,
+            committee_size: self.committee_size,
+            partitions: self.partitions.clone(),
+            handel: Box::new(
+                Handel::new(
+                    handel_conf,
+                    Contributions::unit(self.host_ix, host_response),
+                    self.verif_inputs,
+                    self.partitions,
+                    self.host_ix,
+                )
+                .with_excluded_peers(self.excluded_peers)
+                .with_peer_weights(self.peer_weights),
+            ),
         }
     }
 }
@@ -355,6 +450,7 @@ struct AggregateResponses<'a, H: HashMarker + FixedOutput, PP> {
     aggr_commitment: AggregateCommitment,
     commitments_with_proofs: CommitmentsWithProofs,
     host_ix: PeerIx,
+    committee_size: usize,
     partitions: PP,
     handel: Box<dyn HandelRound<'a, Responses, PP> + Send>,
 }
@@ -387,13 +483,41 @@ pub struct Aggregated<H: HashMarker + FixedOutput> {
 }
 
 enum AggregationState<'a, H: HashMarker + FixedOutput, PP> {
+    AwaitCommitment(AwaitCommitment<H>),
     AggregatePreCommitments(AggregatePreCommitments<'a, H, PP>),
     BroadcastPreCommitments(BroadcastPreCommitments<H, PP>),
     AggregateCommitments(AggregateCommitments<'a, H, PP>),
     BroadcastCommitments(BroadcastCommitments<H, PP>),
+    AwaitResponse(AwaitResponse<H, PP>),
     AggregateResponses(AggregateResponses<'a, H, PP>),
 }
 
+impl<'a, H: HashMarker + FixedOutput, PP: PeerPartitions> AggregationState<'a, H, PP> {
+    /// Index of the given peer in the committee of the round along with the bounds
+    /// messages of the round are checked against.
+    fn bounds(&self, peer_id: PeerId) -> Option<(PeerIx, RoundBounds)> {
+        let (partitions, committee_size) = match self {
+            // Nothing to check against until the signer commits.
+            AggregationState::AwaitCommitment(_) => return None,
+            AggregationState::AggregatePreCommitments(st) => (&st.partitions, st.committee.len()),
+            AggregationState::BroadcastPreCommitments(st) => (&st.handel_partitions, st.committee.len()),
+            AggregationState::AggregateCommitments(st) => (&st.partitions, st.committee.len()),
+            AggregationState::BroadcastCommitments(st) => (&st.handel_partitions, st.committee.len()),
+            AggregationState::AwaitResponse(st) => (&st.partitions, st.committee_size),
+            AggregationState::AggregateResponses(st) => (&st.partitions, st.committee_size),
+        };
+        partitions.try_index_peer(peer_id).map(|ix| {
+            (
+                ix,
+                RoundBounds {
+                    committee_size,
+                    num_levels: partitions.num_levels(),
+                },
+            )
+        })
+    }
+}
+
 struct AggregationTask<'a, H: HashMarker + FixedOutput, PP> {
     state: AggregationState<'a, H, PP>,
     channel: Sender<Result<Aggregated<H>, ()>>,
@@ -460,7 +584,7 @@ where
     H: HashMarker + FixedOutput,
     MPP: MakePeerPartitions,
 {
-    host_sk: SecretKey,
+    signer: AsyncSigner,
     handel_conf: HandelConfig,
     multicasting_conf: DagMulticastingConfig,
     task: Option<AggregationTask<'a, H, MPP::PP>>,
@@ -469,6 +593,10 @@ where
     mcast_overlay_builder: OB,
     inbox: Receiver<AggregationAction<H>>,
     outbox: VecDeque<ProtocolBehaviourOut<VoidMessage, SigmaAggrMessage>>,
+    /// Highest version of the protocol messages are sent with.
+    wire_version: ProtocolVer,
+    /// Versions of the protocol committee members were last heard using.
+    peer_versions: HashMap<PeerId, ProtocolVer>,
 }
 
 trait AssertKinds: Unpin {}
@@ -495,9 +623,29 @@ where
         partitioner: MPP,
         mcast_overlay_builder: OB,
         inbox: Receiver<AggregationAction<H>>,
+    ) -> Self {
+        Self::with_signer(
+            AsyncSigner::spawn(InMemorySigner::new(host_sk)),
+            handel_conf,
+            multicasting_conf,
+            partitioner,
+            mcast_overlay_builder,
+            inbox,
+        )
+    }
+
+    /// Aggregation with partial signatures of the host produced by the given signer,
+    /// e.g. a spawned [`RemoteSigner`](spectrum_crypto::signer::RemoteSigner).
+    pub fn with_signer(
+        signer: AsyncSigner,
+        handel_conf: HandelConfig,
+        multicasting_conf: DagMulticastingConfig,
+        partitioner: MPP,
+        mcast_overlay_builder: OB,
+        inbox: Receiver<AggregationAction<H>>,
     ) -> Self {
         Self {
-            host_sk,
+            signer,
             handel_conf,
             multicasting_conf,
             task: None,
@@ -506,9 +654,19 @@ where
             mcast_overlay_builder,
             inbox,
             outbox: VecDeque::new(),
+            wire_version: SIGMA_AGGR_V2,
+            peer_versions: HashMap::new(),
         }
     }
 
+    /// Send messages with at most the given protocol version. A peer is sent messages with the
+    /// version it was last heard using (capped by this one), or [`SIGMA_AGGR_V1`] until then,
+    /// so that peers which don't support sparse encoding of contributions yet can decode them.
+    pub fn with_wire_version(mut self, wire_version: ProtocolVer) -> Self {
+        self.wire_version = wire_version;
+        self
+    }
+
     fn unstash_stage(&mut self, stage: StageTag)
     where
         H: Debug + HashMarker + FixedOutput<OutputSize = <Secp256k1 as Curve>::FieldBytesSize> + Default,
@@ -517,27 +675,48 @@ where
         OB: MakeDagOverlay + Clone,
     {
         for (p, m) in self.stash.unstash(stage) {
-            self.inject_message(p, SigmaAggrMessage::SigmaAggrMessageV1(m))
+            self.inject_decoded(p, m)
         }
     }
-}
-
-impl<'a, H, MPP, OB> ProtocolBehaviour for SigmaAggregation<'a, H, MPP, OB>
-where
-    H: Debug + HashMarker + FixedOutput<OutputSize = <Secp256k1 as Curve>::FieldBytesSize> + Default,
-    MPP: MakePeerPartitions + Clone + Send,
-    MPP::PP: Send + Clone + 'static,
-    OB: MakeDagOverlay + Clone,
-{
-    type TProto = SigmaAggrSpec;
 
-    #[tracing::instrument(skip(self, msg, peer_id), level = "trace")]
-    fn inject_message(
-        &mut self,
-        peer_id: PeerId,
-        SigmaAggrMessage::SigmaAggrMessageV1(msg): SigmaAggrMessage,
-    ) {
+    fn inject_decoded(&mut self, peer_id: PeerId, msg: SigmaAggrMessageV1)
+    where
+        H: Debug + HashMarker + FixedOutput<OutputSize = <Secp256k1 as Curve>::FieldBytesSize> + Default,
+        MPP: MakePeerPartitions + Clone + Send,
+        MPP::PP: Send + 'a,
+        OB: MakeDagOverlay + Clone,
+    {
+        if let Some(AggregationTask {
+            state: AggregationState::AwaitCommitment(st),
+            ..
+        }) = &self.task
+        {
+            // Validated once the signer commits and the message is unstashed.
+            if st.committee.keys().any(|pk| PeerId::from(pk) == peer_id) {
+                self.stash.stash(peer_id, msg);
+            }
+            return;
+        }
+        match self.task.as_ref().and_then(|task| task.state.bounds(peer_id)) {
+            Some((sender_ix, bounds)) => {
+                if let Err(rejection) = validate_message(&msg, sender_ix, bounds) {
+                    self.reject(peer_id, rejection);
+                    return;
+                }
+            }
+            None => {
+                trace!("SigmaAggrMessage from {:?} outside of the committee", peer_id);
+                return;
+            }
+        }
         match &mut self.task {
+            Some(AggregationTask {
+                state: AggregationState::AwaitCommitment(_) | AggregationState::AwaitResponse(_),
+                ..
+            }) => {
+                // Picked up once the signer is done.
+                self.stash.stash(peer_id, msg);
+            }
             Some(AggregationTask {
                 state: AggregationState::AggregatePreCommitments(ref mut pre_commitment),
                 ..
@@ -628,6 +807,54 @@ where
         }
     }
 
+    fn reject(&mut self, peer_id: PeerId, rejection: Rejection) {
+        #[cfg(feature = "audit")]
+        warn!("Rejected SigmaAggrMessage from {:?}: {}", peer_id, rejection);
+        #[cfg(not(feature = "audit"))]
+        trace!("Rejected SigmaAggrMessage from {:?}: {}", peer_id, rejection);
+        self.outbox
+            .push_back(ProtocolBehaviourOut::NetworkAction(NetworkAction::BanPeer(
+                peer_id,
+            )));
+    }
+
+    /// Let the requester of the round in progress know that it won't complete.
+    fn abandon_task(&mut self) {
+        if let Some(AggregationTask { channel, .. }) = self.task.take() {
+            let _ = channel.send(Err(()));
+        }
+    }
+}
+
+impl<'a, H, MPP, OB> ProtocolBehaviour for SigmaAggregation<'a, H, MPP, OB>
+where
+    H: Debug + HashMarker + FixedOutput<OutputSize = <Secp256k1 as Curve>::FieldBytesSize> + Default,
+    MPP: MakePeerPartitions + Clone + Send,
+    MPP::PP: Send + Clone + 'static,
+    OB: MakeDagOverlay + Clone,
+{
+    type TProto = SigmaAggrSpec;
+
+    fn inject_cancelled(&mut self) {
+        self.stash.flush();
+        self.abandon_task();
+    }
+
+    #[tracing::instrument(skip(self, msg, peer_id), level = "trace")]
+    fn inject_message(&mut self, peer_id: PeerId, msg: SigmaAggrMessage) {
+        let version = msg.version();
+        let msg = match msg.decode() {
+            Ok(msg) => msg,
+            Err(err) => {
+                // Could be a version this node doesn't fully understand, not necessarily misbehaviour.
+                warn!("Undecodable SigmaAggrMessage from {:?}: {}", peer_id, err);
+                return;
+            }
+        };
+        self.peer_versions.insert(peer_id, version);
+        self.inject_decoded(peer_id, msg);
+    }
+
     fn poll(
         &mut self,
         cx: &mut Context<'_>,
@@ -642,19 +869,23 @@ where
                     AggregationAction::Reset {
                         new_committee,
                         new_message,
+                        excluded_members,
+                        member_weights,
                         channel,
                     } => {
                         self.stash.flush();
+                        self.abandon_task();
+                        let members = new_committee.keys().map(PeerId::from).collect::<HashSet<_>>();
+                        self.peer_versions.retain(|peer, _| members.contains(peer));
+                        let commitment = self.signer.schnorr_commitment(new_message.as_ref().to_vec());
                         self.task = Some(AggregationTask {
-                            state: AggregationState::AggregatePreCommitments(AggregatePreCommitments::init(
-                                self.host_sk.clone(),
-                                new_committee,
-                                new_message,
-                                self.partitioner.clone(),
-                                self.mcast_overlay_builder.clone(),
-                                self.handel_conf.clone(),
-                                self.multicasting_conf,
-                            )),
+                            state: AggregationState::AwaitCommitment(AwaitCommitment {
+                                committee: new_committee,
+                                message_digest: new_message,
+                                excluded_members,
+                                member_weights,
+                                commitment,
+                            }),
                             channel,
                         });
                     }
@@ -663,6 +894,41 @@ where
 
             if let Some(task) = self.task.take() {
                 match task {
+                    AggregationTask {
+                        state: AggregationState::AwaitCommitment(mut st),
+                        channel,
+                    } => match st.commitment.as_mut().poll(cx) {
+                        Poll::Ready(Ok(commitment)) => {
+                            let st = AggregatePreCommitments::init(
+                                self.signer.public_key(),
+                                st,
+                                commitment,
+                                self.partitioner.clone(),
+                                self.mcast_overlay_builder.clone(),
+                                self.handel_conf.clone(),
+                                self.multicasting_conf,
+                            );
+                            self.task = Some(AggregationTask {
+                                state: AggregationState::AggregatePreCommitments(st),
+                                channel,
+                            });
+                            self.unstash_stage(StageTag::PreCommit);
+                            continue;
+                        }
+                        Poll::Ready(Err(err)) => {
+                            // Other committee members proceed without us.
+                            warn!("Signer failed to commit, leaving the round: {}", err);
+                            self.stash.flush();
+                            let _ = channel.send(Err(()));
+                            continue;
+                        }
+                        Poll::Pending => {
+                            self.task = Some(AggregationTask {
+                                state: AggregationState::AwaitCommitment(st),
+                                channel,
+                            });
+                        }
+                    },
                     AggregationTask {
                         state: AggregationState::AggregatePreCommitments(mut st),
                         channel,
@@ -672,11 +938,11 @@ where
                         match st.handel.poll(cx) {
                             Poll::Ready(out) => match out {
                                 Either::Left(cmd) => {
-                                    self.outbox.push_back(cmd.rmap(|m| {
-                                        SigmaAggrMessage::SigmaAggrMessageV1(
-                                            SigmaAggrMessageV1::PreCommitments(m),
-                                        )
-                                    }));
+                                    self.outbox.push_back(to_wire(
+                                        self.wire_version,
+                                        &self.peer_versions,
+                                        cmd.rmap(SigmaAggrMessageV1::PreCommitments),
+                                    ));
                                     self.task = Some(AggregationTask {
                                         state: AggregationState::AggregatePreCommitments(st),
                                         channel,
@@ -724,11 +990,11 @@ where
                         match st.mcast.poll(cx) {
                             Poll::Ready(out) => match out {
                                 Either::Left(cmd) => {
-                                    self.outbox.push_back(cmd.rmap(|m| {
-                                        SigmaAggrMessage::SigmaAggrMessageV1(
-                                            SigmaAggrMessageV1::BroadcastPreCommitments(m),
-                                        )
-                                    }));
+                                    self.outbox.push_back(to_wire(
+                                        self.wire_version,
+                                        &self.peer_versions,
+                                        cmd.rmap(SigmaAggrMessageV1::BroadcastPreCommitments),
+                                    ));
                                     self.task = Some(AggregationTask {
                                         state: AggregationState::BroadcastPreCommitments(st),
                                         channel,
@@ -779,11 +1045,11 @@ where
                         match st.handel.poll(cx) {
                             Poll::Ready(out) => match out {
                                 Either::Left(cmd) => {
-                                    self.outbox.push_back(cmd.rmap(|m| {
-                                        SigmaAggrMessage::SigmaAggrMessageV1(SigmaAggrMessageV1::Commitments(
-                                            m,
-                                        ))
-                                    }));
+                                    self.outbox.push_back(to_wire(
+                                        self.wire_version,
+                                        &self.peer_versions,
+                                        cmd.rmap(SigmaAggrMessageV1::Commitments),
+                                    ));
                                     self.task = Some(AggregationTask {
                                         state: AggregationState::AggregateCommitments(st),
                                         channel,
@@ -832,11 +1098,11 @@ where
                         match st.mcast.poll(cx) {
                             Poll::Ready(out) => match out {
                                 Either::Left(cmd) => {
-                                    self.outbox.push_back(cmd.rmap(|m| {
-                                        SigmaAggrMessage::SigmaAggrMessageV1(
-                                            SigmaAggrMessageV1::BroadcastCommitments(m),
-                                        )
-                                    }));
+                                    self.outbox.push_back(to_wire(
+                                        self.wire_version,
+                                        &self.peer_versions,
+                                        cmd.rmap(SigmaAggrMessageV1::BroadcastCommitments),
+                                    ));
                                     self.task = Some(AggregationTask {
                                         state: AggregationState::BroadcastCommitments(st),
                                         channel,
@@ -860,10 +1126,9 @@ where
                                         "Finished broadcasting commitments, missing from: {:?}",
                                         missing_peers
                                     );
-                                    self.unstash_stage(StageTag::Response);
                                     self.task = Some(AggregationTask {
-                                        state: AggregationState::AggregateResponses(
-                                            st.complete(commitments, self.handel_conf),
+                                        state: AggregationState::AwaitResponse(
+                                            st.complete(commitments, &self.signer),
                                         ),
                                         channel,
                                     });
@@ -878,6 +1143,34 @@ where
                             }
                         }
                     }
+                    AggregationTask {
+                        state: AggregationState::AwaitResponse(mut st),
+                        channel,
+                    } => match st.response.as_mut().poll(cx) {
+                        Poll::Ready(Ok(host_response)) => {
+                            self.task = Some(AggregationTask {
+                                state: AggregationState::AggregateResponses(
+                                    st.complete(host_response, self.handel_conf),
+                                ),
+                                channel,
+                            });
+                            self.unstash_stage(StageTag::Response);
+                            continue;
+                        }
+                        Poll::Ready(Err(err)) => {
+                            // Our commitment ends up in the exclusion set of others.
+                            warn!("Signer failed to respond, leaving the round: {}", err);
+                            self.stash.flush();
+                            let _ = channel.send(Err(()));
+                            continue;
+                        }
+                        Poll::Pending => {
+                            self.task = Some(AggregationTask {
+                                state: AggregationState::AwaitResponse(st),
+                                channel,
+                            });
+                        }
+                    },
                     AggregationTask {
                         state: AggregationState::AggregateResponses(mut st),
                         channel,
@@ -887,9 +1180,11 @@ where
                         match st.handel.poll(cx) {
                             Poll::Ready(out) => match out {
                                 Either::Left(cmd) => {
-                                    self.outbox.push_back(cmd.rmap(|m| {
-                                        SigmaAggrMessage::SigmaAggrMessageV1(SigmaAggrMessageV1::Responses(m))
-                                    }));
+                                    self.outbox.push_back(to_wire(
+                                        self.wire_version,
+                                        &self.peer_versions,
+                                        cmd.rmap(SigmaAggrMessageV1::Responses),
+                                    ));
                                     self.task = Some(AggregationTask {
                                         state: AggregationState::AggregateResponses(st),
                                         channel,
@@ -924,6 +1219,42 @@ where
     }
 }
 
+/// Encode outgoing message with the highest protocol version both sides support.
+fn to_wire(
+    wire_version: ProtocolVer,
+    peer_versions: &HashMap<PeerId, ProtocolVer>,
+    cmd: ProtocolBehaviourOut<VoidMessage, SigmaAggrMessageV1>,
+) -> ProtocolBehaviourOut<VoidMessage, SigmaAggrMessage> {
+    let version_for = |peer: &PeerId| {
+        peer_versions
+            .get(peer)
+            .copied()
+            .unwrap_or(SIGMA_AGGR_V1)
+            .min(wire_version)
+    };
+    match cmd {
+        ProtocolBehaviourOut::NetworkAction(NetworkAction::SendOneShotMessage {
+            peer,
+            addr_hint,
+            message,
+            ..
+        }) => {
+            let use_version = version_for(&peer);
+            ProtocolBehaviourOut::NetworkAction(NetworkAction::SendOneShotMessage {
+                peer,
+                addr_hint,
+                use_version,
+                message: SigmaAggrMessage::encode(message, use_version),
+            })
+        }
+        ProtocolBehaviourOut::Send { peer_id, message } => {
+            let message = SigmaAggrMessage::encode(message, version_for(&peer_id));
+            ProtocolBehaviourOut::Send { peer_id, message }
+        }
+        out => out.rmap(|m| SigmaAggrMessage::encode(m, SIGMA_AGGR_V1)),
+    }
+}
+
 fn msg_variant_as_str(msg: &SigmaAggrMessageV1) -> &str {
     match msg {
         SigmaAggrMessageV1::PreCommitments(_) => "SigmaAggrMessageV1::PreCommitments",
@@ -933,3 +1264,68 @@ fn msg_variant_as_str(msg: &SigmaAggrMessageV1) -> &str {
         SigmaAggrMessageV1::Responses(_) => "SigmaAggrMessageV1::Responses",
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use libp2p::PeerId;
+
+    use spectrum_crypto::digest::blake2b256_hash;
+
+    use crate::protocol::{SIGMA_AGGR_V1, SIGMA_AGGR_V2, SIGMA_AGGR_V3};
+    use crate::protocol_handler::handel::partitioning::PeerIx;
+    use crate::protocol_handler::sigma_aggregation::message::{SigmaAggrMessage, SigmaAggrMessageV1};
+    use crate::protocol_handler::sigma_aggregation::to_wire;
+    use crate::protocol_handler::versioning::Versioned;
+    use crate::protocol_handler::{NetworkAction, ProtocolBehaviourOut};
+    use crate::types::ProtocolVer;
+
+    fn sent_with(
+        wire_version: ProtocolVer,
+        peer_versions: &HashMap<PeerId, ProtocolVer>,
+        peer: PeerId,
+    ) -> (ProtocolVer, ProtocolVer) {
+        let msg = SigmaAggrMessageV1::BroadcastPreCommitments(
+            [(PeerIx::from(0), blake2b256_hash(&[]))].into_iter().collect(),
+        );
+        let cmd = ProtocolBehaviourOut::NetworkAction(NetworkAction::SendOneShotMessage {
+            peer,
+            addr_hint: None,
+            use_version: ProtocolVer::default(),
+            message: msg,
+        });
+        match to_wire(wire_version, peer_versions, cmd) {
+            ProtocolBehaviourOut::NetworkAction(NetworkAction::SendOneShotMessage {
+                use_version,
+                message,
+                ..
+            }) => (use_version, message.version()),
+            _ => panic!("Expected SendOneShotMessage"),
+        }
+    }
+
+    #[test]
+    fn highest_common_version_is_used() {
+        let unknown = PeerId::random();
+        let upgraded = PeerId::random();
+        let ahead = PeerId::random();
+        let peer_versions = HashMap::from([(upgraded, SIGMA_AGGR_V2), (ahead, SIGMA_AGGR_V3)]);
+        assert_eq!(
+            sent_with(SIGMA_AGGR_V2, &peer_versions, unknown),
+            (SIGMA_AGGR_V1, SIGMA_AGGR_V1)
+        );
+        assert_eq!(
+            sent_with(SIGMA_AGGR_V2, &peer_versions, upgraded),
+            (SIGMA_AGGR_V2, SIGMA_AGGR_V2)
+        );
+        assert_eq!(
+            sent_with(SIGMA_AGGR_V2, &peer_versions, ahead),
+            (SIGMA_AGGR_V2, SIGMA_AGGR_V2)
+        );
+        assert_eq!(
+            sent_with(SIGMA_AGGR_V1, &peer_versions, upgraded),
+            (SIGMA_AGGR_V1, SIGMA_AGGR_V1)
+        );
+    }
+}
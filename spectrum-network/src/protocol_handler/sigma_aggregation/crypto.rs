@@ -9,7 +9,8 @@ use k256::{ProjectivePoint, Scalar, Secp256k1, SecretKey};
 use spectrum_crypto::digest::{blake2b256_hash, Blake2bDigest256, Digest};
 use spectrum_crypto::pubkey::PublicKey;
 
-use crate::protocol_handler::handel::Threshold;
+use crate::protocol_handler::handel::partitioning::PeerIx;
+use crate::protocol_handler::handel::{PeerWeights, Threshold};
 use crate::protocol_handler::sigma_aggregation::types::{
     AggregateCommitment, Commitment, CommitmentSecret, Signature,
 };
@@ -141,6 +142,7 @@ pub fn verify<H>(
     committee: Vec<PublicKey>,
     md: Digest<H>,
     threshold: Threshold,
+    weights: &PeerWeights,
 ) -> bool
 where
     H: HashMarker + FixedOutput<OutputSize = <Secp256k1 as Curve>::FieldBytesSize> + Default,
@@ -184,12 +186,21 @@ where
             return false;
         }
     }
-    let num_succeeded_committees = committee.len() - exclusion_set.len();
-    num_succeeded_committees >= threshold.min(committee.len())
+    // Members are indexed by their position in the committee.
+    let total_weight = weights.total(&(0..committee.len()).map(PeerIx::from).collect::<Vec<_>>());
+    let excluded_weight = weights.total(
+        &exclusion_set
+            .iter()
+            .map(|(i, _)| PeerIx::from(*i))
+            .collect::<Vec<_>>(),
+    );
+    total_weight.saturating_sub(excluded_weight) >= threshold.min(total_weight)
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
     use blake2::Blake2b;
     use digest::consts::U32;
     use elliptic_curve::rand_core::OsRng;
@@ -199,7 +210,8 @@ mod tests {
     use spectrum_crypto::digest::blake2b256_hash;
     use spectrum_crypto::pubkey::PublicKey;
 
-    use crate::protocol_handler::handel::Threshold;
+    use crate::protocol_handler::handel::partitioning::PeerIx;
+    use crate::protocol_handler::handel::{PeerWeights, Threshold};
     use crate::protocol_handler::sigma_aggregation::crypto::{
         aggregate_commitment, aggregate_pk, aggregate_response, challenge, exclusion_proof, individual_input,
         response, schnorr_commitment_pair, verify, verify_response,
@@ -335,7 +347,8 @@ mod tests {
             exclusion_set,
             committee,
             md,
-            Threshold { num: 2, denom: 3 }
+            Threshold { num: 2, denom: 3 },
+            &PeerWeights::default()
         ))
     }
 
@@ -421,12 +434,29 @@ mod tests {
             })
             .collect::<Vec<_>>();
         assert!(verify(
+            aggregate_commitment.clone(),
+            aggregate_response,
+            exclusion_set.clone(),
+            committee.clone(),
+            md,
+            Threshold { num: 2, denom: 3 },
+            &PeerWeights::default()
+        ));
+        // The same signature falls short of the threshold once excluded members hold most of the weight.
+        let weights = PeerWeights::from(
+            byz_indexes
+                .iter()
+                .map(|i| (PeerIx::from(*i), num_participants))
+                .collect::<HashMap<_, _>>(),
+        );
+        assert!(!verify(
             aggregate_commitment,
             aggregate_response,
             exclusion_set,
             committee,
             md,
-            Threshold { num: 2, denom: 3 }
+            Threshold { num: 2, denom: 3 },
+            &weights
         ))
     }
 
@@ -489,7 +519,8 @@ mod tests {
             Vec::new(),
             committee,
             md,
-            Threshold { num: 1, denom: 1 }
+            Threshold { num: 1, denom: 1 },
+            &PeerWeights::default()
         ))
     }
 }
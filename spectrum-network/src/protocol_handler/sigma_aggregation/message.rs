@@ -1,7 +1,15 @@
+use k256::Scalar;
 use serde::{Deserialize, Serialize};
 
+use spectrum_crypto::digest::Blake2bDigest256;
+
+use crate::peer_conn_handler::message_sink::MessagePriority;
+use crate::protocol::{SIGMA_AGGR_V1, SIGMA_AGGR_V2, SIGMA_AGGR_V3};
 use crate::protocol_handler::handel::message::HandelMessage;
-use crate::protocol_handler::sigma_aggregation::types::{CommitmentsWithProofs, PreCommitments, Responses};
+use crate::protocol_handler::handel::partitioning::PeerIx;
+use crate::protocol_handler::sigma_aggregation::types::{
+    Commitment, CommitmentsWithProofs, Contributions, PreCommitments, Responses, Signature,
+};
 use crate::protocol_handler::versioning::Versioned;
 use crate::protocol_handler::void::VoidMessage;
 use crate::protocol_handler::ProtocolSpec;
@@ -10,6 +18,48 @@ use crate::types::ProtocolVer;
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub enum SigmaAggrMessage {
     SigmaAggrMessageV1(SigmaAggrMessageV1),
+    SigmaAggrMessageV2(SigmaAggrMessageV2),
+    SigmaAggrMessageV3(SessionMessage),
+}
+
+impl SigmaAggrMessage {
+    /// Wrap the message into the wire format of the given protocol version.
+    pub fn encode(msg: SigmaAggrMessageV1, ver: ProtocolVer) -> Self {
+        if ver == SIGMA_AGGR_V1 {
+            SigmaAggrMessage::SigmaAggrMessageV1(msg)
+        } else {
+            SigmaAggrMessage::SigmaAggrMessageV2(SigmaAggrMessageV2::from(msg))
+        }
+    }
+
+    /// Unpack the message regardless of the protocol version it was sent with.
+    /// Session the message is tagged with, if any, is dropped.
+    pub fn decode(self) -> Result<SigmaAggrMessageV1, SparseContributionsError> {
+        match self {
+            SigmaAggrMessage::SigmaAggrMessageV1(msg) => Ok(msg),
+            SigmaAggrMessage::SigmaAggrMessageV2(msg) => SigmaAggrMessageV1::try_from(msg),
+            SigmaAggrMessage::SigmaAggrMessageV3(msg) => SigmaAggrMessageV1::try_from(msg.message),
+        }
+    }
+
+    /// Tag the message with the session it belongs to, see [`SIGMA_AGGR_V3`].
+    pub fn into_session(self, session: Vec<u8>) -> Self {
+        let message = match self {
+            SigmaAggrMessage::SigmaAggrMessageV1(msg) => SigmaAggrMessageV2::from(msg),
+            SigmaAggrMessage::SigmaAggrMessageV2(msg) => msg,
+            SigmaAggrMessage::SigmaAggrMessageV3(msg) => msg.message,
+        };
+        SigmaAggrMessage::SigmaAggrMessageV3(SessionMessage { session, message })
+    }
+}
+
+/// Message of one of concurrent aggregation rounds.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SessionMessage {
+    /// Digest of the message aggregated in the round.
+    #[serde(with = "serde_bytes")]
+    pub session: Vec<u8>,
+    pub message: SigmaAggrMessageV2,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -21,10 +71,189 @@ pub enum SigmaAggrMessageV1 {
     Responses(HandelMessage<Responses>),
 }
 
+/// Same as [`SigmaAggrMessageV1`], but contribution sets are encoded sparsely.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum SigmaAggrMessageV2 {
+    PreCommitments(HandelMessage<SparseContributions<Blake2bDigest256>>),
+    Commitments(HandelMessage<SparseContributions<(Commitment, Signature)>>),
+    BroadcastPreCommitments(SparseContributions<Blake2bDigest256>),
+    BroadcastCommitments(SparseContributions<(Commitment, Signature)>),
+    Responses(HandelMessage<SparseContributions<Scalar>>),
+}
+
+impl From<SigmaAggrMessageV1> for SigmaAggrMessageV2 {
+    fn from(msg: SigmaAggrMessageV1) -> Self {
+        match msg {
+            SigmaAggrMessageV1::PreCommitments(m) => SigmaAggrMessageV2::PreCommitments(sparse_handel(m)),
+            SigmaAggrMessageV1::Commitments(m) => SigmaAggrMessageV2::Commitments(sparse_handel(m)),
+            SigmaAggrMessageV1::BroadcastPreCommitments(m) => {
+                SigmaAggrMessageV2::BroadcastPreCommitments(SparseContributions::from(m))
+            }
+            SigmaAggrMessageV1::BroadcastCommitments(m) => {
+                SigmaAggrMessageV2::BroadcastCommitments(SparseContributions::from(m))
+            }
+            SigmaAggrMessageV1::Responses(m) => SigmaAggrMessageV2::Responses(sparse_handel(m)),
+        }
+    }
+}
+
+impl TryFrom<SigmaAggrMessageV2> for SigmaAggrMessageV1 {
+    type Error = SparseContributionsError;
+    fn try_from(msg: SigmaAggrMessageV2) -> Result<Self, Self::Error> {
+        Ok(match msg {
+            SigmaAggrMessageV2::PreCommitments(m) => SigmaAggrMessageV1::PreCommitments(dense_handel(m)?),
+            SigmaAggrMessageV2::Commitments(m) => SigmaAggrMessageV1::Commitments(dense_handel(m)?),
+            SigmaAggrMessageV2::BroadcastPreCommitments(m) => {
+                SigmaAggrMessageV1::BroadcastPreCommitments(Contributions::try_from(m)?)
+            }
+            SigmaAggrMessageV2::BroadcastCommitments(m) => {
+                SigmaAggrMessageV1::BroadcastCommitments(Contributions::try_from(m)?)
+            }
+            SigmaAggrMessageV2::Responses(m) => SigmaAggrMessageV1::Responses(dense_handel(m)?),
+        })
+    }
+}
+
+fn sparse_handel<C>(m: HandelMessage<Contributions<C>>) -> HandelMessage<SparseContributions<C>> {
+    HandelMessage {
+        level: m.level,
+        individual_contribution: m.individual_contribution.map(SparseContributions::from),
+        aggregate_contribution: SparseContributions::from(m.aggregate_contribution),
+        contact_sender: m.contact_sender,
+    }
+}
+
+fn dense_handel<C>(
+    m: HandelMessage<SparseContributions<C>>,
+) -> Result<HandelMessage<Contributions<C>>, SparseContributionsError> {
+    Ok(HandelMessage {
+        level: m.level,
+        individual_contribution: m
+            .individual_contribution
+            .map(Contributions::try_from)
+            .transpose()?,
+        aggregate_contribution: Contributions::try_from(m.aggregate_contribution)?,
+        contact_sender: m.contact_sender,
+    })
+}
+
+#[derive(Debug, Eq, PartialEq, thiserror::Error)]
+pub enum SparseContributionsError {
+    #[error("Unknown encoding of contributors: {0}")]
+    UnknownEncoding(u8),
+    #[error("Malformed list of contributors")]
+    MalformedList,
+    #[error("{0} contributors given for {1} contributions")]
+    LengthMismatch(usize, usize),
+}
+
+/// Contributors are encoded as deltas between subsequent indexes packed into varints.
+const CONTRIBUTORS_LIST: u8 = 0;
+/// Contributors are encoded as a bitmap over the committee, LSB first.
+const CONTRIBUTORS_BITMAP: u8 = 1;
+
+/// Contribution set encoded compactly: contributions ordered by `PeerIx`
+/// and the set of contributors in whichever of the two encodings is shorter.
+/// A few contributions out of a large committee take a few bytes of list,
+/// a nearly complete set takes `n/8` bytes of bitmap.
+/// Unlike a map keyed by `PeerIx` this costs no more than a couple of bytes per contributor.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SparseContributions<C>(#[serde(with = "serde_bytes")] Vec<u8>, Vec<C>);
+
+impl<C> From<Contributions<C>> for SparseContributions<C> {
+    fn from(contributions: Contributions<C>) -> Self {
+        let mut entries = contributions.into_iter().collect::<Vec<_>>();
+        entries.sort_by_key(|(ix, _)| *ix);
+        let (ixs, contributions): (Vec<_>, Vec<_>) =
+            entries.into_iter().map(|(ix, c)| (ix.unwrap(), c)).unzip();
+        let list = encode_list(&ixs);
+        let bitmap = encode_bitmap(&ixs);
+        Self(
+            if bitmap.len() < list.len() { bitmap } else { list },
+            contributions,
+        )
+    }
+}
+
+impl<C> TryFrom<SparseContributions<C>> for Contributions<C> {
+    type Error = SparseContributionsError;
+    fn try_from(
+        SparseContributions(contributors, contributions): SparseContributions<C>,
+    ) -> Result<Self, Self::Error> {
+        let ixs = match contributors.split_first() {
+            None => vec![],
+            Some((&CONTRIBUTORS_LIST, list)) => decode_list(list)?,
+            Some((&CONTRIBUTORS_BITMAP, bitmap)) => decode_bitmap(bitmap),
+            Some((&tag, _)) => return Err(SparseContributionsError::UnknownEncoding(tag)),
+        };
+        if ixs.len() != contributions.len() {
+            return Err(SparseContributionsError::LengthMismatch(
+                ixs.len(),
+                contributions.len(),
+            ));
+        }
+        Ok(ixs.into_iter().map(PeerIx::from).zip(contributions).collect())
+    }
+}
+
+/// `ixs` must be sorted.
+fn encode_list(ixs: &[usize]) -> Vec<u8> {
+    let mut bf = vec![CONTRIBUTORS_LIST];
+    let mut varint_bf = unsigned_varint::encode::usize_buffer();
+    let mut next = 0;
+    for ix in ixs {
+        bf.extend_from_slice(unsigned_varint::encode::usize(ix - next, &mut varint_bf));
+        next = ix + 1;
+    }
+    bf
+}
+
+fn decode_list(mut bytes: &[u8]) -> Result<Vec<usize>, SparseContributionsError> {
+    let mut ixs = vec![];
+    let mut next = 0usize;
+    while !bytes.is_empty() {
+        let (delta, rem) =
+            unsigned_varint::decode::usize(bytes).map_err(|_| SparseContributionsError::MalformedList)?;
+        let ix = next
+            .checked_add(delta)
+            .ok_or(SparseContributionsError::MalformedList)?;
+        ixs.push(ix);
+        next = ix + 1;
+        bytes = rem;
+    }
+    Ok(ixs)
+}
+
+/// `ixs` must be sorted.
+fn encode_bitmap(ixs: &[usize]) -> Vec<u8> {
+    let mut bf = vec![CONTRIBUTORS_BITMAP];
+    if let Some(max_ix) = ixs.last() {
+        bf.resize(1 + max_ix / 8 + 1, 0);
+        for ix in ixs {
+            bf[1 + ix / 8] |= 1 << (ix % 8);
+        }
+    }
+    bf
+}
+
+fn decode_bitmap(bytes: &[u8]) -> Vec<usize> {
+    bytes
+        .iter()
+        .enumerate()
+        .flat_map(|(i, byte)| {
+            (0..8)
+                .filter(move |bit| byte & (1 << bit) != 0)
+                .map(move |bit| i * 8 + bit)
+        })
+        .collect()
+}
+
 impl Versioned for SigmaAggrMessage {
     fn version(&self) -> ProtocolVer {
         match self {
-            SigmaAggrMessage::SigmaAggrMessageV1(_) => ProtocolVer::default(),
+            SigmaAggrMessage::SigmaAggrMessageV1(_) => SIGMA_AGGR_V1,
+            SigmaAggrMessage::SigmaAggrMessageV2(_) => SIGMA_AGGR_V2,
+            SigmaAggrMessage::SigmaAggrMessageV3(_) => SIGMA_AGGR_V3,
         }
     }
 }
@@ -34,4 +263,112 @@ pub struct SigmaAggrSpec;
 impl ProtocolSpec for SigmaAggrSpec {
     type THandshake = VoidMessage;
     type TMessage = SigmaAggrMessage;
+
+    /// Aggregation rounds are latency-critical, while messages are small.
+    fn message_priority(_message: &SigmaAggrMessage) -> MessagePriority {
+        MessagePriority::Control
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::Scalar;
+
+    use spectrum_crypto::digest::blake2b256_hash;
+
+    use crate::protocol::{SIGMA_AGGR_V1, SIGMA_AGGR_V2};
+    use crate::protocol_handler::codec;
+    use crate::protocol_handler::handel::message::HandelMessage;
+    use crate::protocol_handler::handel::partitioning::PeerIx;
+    use crate::protocol_handler::sigma_aggregation::message::{
+        SigmaAggrMessage, SigmaAggrMessageV1, SparseContributions, SparseContributionsError,
+    };
+    use crate::protocol_handler::sigma_aggregation::types::{Contributions, Responses};
+    use crate::protocol_handler::sigma_aggregation::validation::{validate_message, Rejection, RoundBounds};
+
+    fn responses(ixs: &[usize]) -> Responses {
+        ixs.iter()
+            .map(|ix| (PeerIx::from(*ix), Scalar::from(*ix as u64 + 1)))
+            .collect()
+    }
+
+    #[test]
+    fn sparse_contributions_roundtrip() {
+        let few = vec![3, 700, 701, 1023];
+        let all = (0..1024).collect::<Vec<_>>();
+        let half = (0..1024).step_by(2).collect::<Vec<_>>();
+        for ixs in [vec![], vec![0], few, all, half] {
+            let dense = responses(&ixs);
+            let sparse = SparseContributions::from(dense.clone());
+            assert_eq!(Contributions::try_from(sparse), Ok(dense));
+        }
+    }
+
+    #[test]
+    fn shorter_encoding_is_chosen() {
+        let few = SparseContributions::from(responses(&[3, 700, 1023]));
+        assert_eq!(few.0.len(), 1 + 1 + 2 + 2);
+        let all = SparseContributions::from(responses(&(0..1024).collect::<Vec<_>>()));
+        assert_eq!(all.0.len(), 1 + 1024 / 8);
+    }
+
+    #[test]
+    fn malformed_contributors_rejected() {
+        let mut sparse = SparseContributions::from(responses(&[1, 2]));
+        sparse.1.pop();
+        assert_eq!(
+            Contributions::try_from(sparse),
+            Err(SparseContributionsError::LengthMismatch(2, 1))
+        );
+        let sparse = SparseContributions::<Scalar>(vec![7], vec![]);
+        assert_eq!(
+            Contributions::try_from(sparse),
+            Err(SparseContributionsError::UnknownEncoding(7))
+        );
+        let sparse = SparseContributions::<Scalar>(vec![0, 0x80], vec![Scalar::ONE]);
+        assert_eq!(
+            Contributions::try_from(sparse),
+            Err(SparseContributionsError::MalformedList)
+        );
+    }
+
+    #[test]
+    fn v2_is_smaller() {
+        let msg = SigmaAggrMessageV1::Responses(HandelMessage {
+            level: 6,
+            individual_contribution: Some(responses(&[517])),
+            aggregate_contribution: responses(&(512..576).collect::<Vec<_>>()),
+            contact_sender: false,
+        });
+        let v1 = SigmaAggrMessage::encode(msg.clone(), SIGMA_AGGR_V1);
+        let v2 = SigmaAggrMessage::encode(msg.clone(), SIGMA_AGGR_V2);
+        let v1_size = Vec::from(codec::encode(v1.clone())).len();
+        let v2_size = Vec::from(codec::encode(v2.clone())).len();
+        assert!(v2_size < v1_size);
+        let decoded = codec::decode::<SigmaAggrMessage>(codec::encode(v2)).unwrap();
+        assert_eq!(decoded.decode(), Ok(msg.clone()));
+        assert_eq!(v1.decode(), Ok(msg));
+    }
+
+    #[test]
+    fn out_of_range_sparse_contributor_rejected() {
+        let msg = SigmaAggrMessageV1::BroadcastPreCommitments(
+            [(PeerIx::from(100_000), blake2b256_hash(&[]))]
+                .into_iter()
+                .collect(),
+        );
+        let v2 = codec::encode(SigmaAggrMessage::encode(msg, SIGMA_AGGR_V2));
+        let decoded = codec::decode::<SigmaAggrMessage>(v2).unwrap().decode().unwrap();
+        let bounds = RoundBounds {
+            committee_size: 8,
+            num_levels: 4,
+        };
+        assert_eq!(
+            validate_message(&decoded, PeerIx::from(1), bounds),
+            Err(Rejection::IndexOutOfRange {
+                ix: PeerIx::from(100_000),
+                committee_size: 8
+            })
+        );
+    }
 }
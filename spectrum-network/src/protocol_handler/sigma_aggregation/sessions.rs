@@ -0,0 +1,464 @@
+//! Concurrent aggregation rounds.
+//!
+//! [`SigmaAggregation`] runs a single round at a time, a new [`AggregationAction::Reset`] abandons
+//! the round in progress. [`SigmaAggregationSessions`] instead runs a round per message, each of
+//! them with its own Handel overlay and deadline, so that several reports can be notarized
+//! simultaneously. Messages of rounds are tagged with the digest of the message being aggregated,
+//! see [`SIGMA_AGGR_V3`].
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use digest::{FixedOutput, HashMarker};
+use elliptic_curve::Curve;
+use futures::channel::{mpsc, oneshot};
+use futures::{FutureExt, Stream};
+use higher::Bifunctor;
+use k256::{Secp256k1, SecretKey};
+use libp2p::PeerId;
+use tracing::{trace, warn};
+
+use spectrum_crypto::digest::Digest;
+use spectrum_crypto::signer::{AsyncSigner, InMemorySigner};
+
+use crate::protocol::SIGMA_AGGR_V3;
+use crate::protocol_handler::aggregation::AggregationAction;
+use crate::protocol_handler::handel::partitioning::MakePeerPartitions;
+use crate::protocol_handler::handel::HandelConfig;
+use crate::protocol_handler::multicasting::overlay::MakeDagOverlay;
+use crate::protocol_handler::multicasting::DagMulticastingConfig;
+use crate::protocol_handler::sigma_aggregation::message::{SigmaAggrMessage, SigmaAggrSpec};
+use crate::protocol_handler::sigma_aggregation::{Aggregated, SigmaAggregation};
+use crate::protocol_handler::void::VoidMessage;
+use crate::protocol_handler::{NetworkAction, ProtocolBehaviour, ProtocolBehaviourOut};
+
+#[derive(Copy, Clone, Debug)]
+pub struct SessionsConfig {
+    /// Max number of rounds run concurrently. Rounds requested beyond it fail immediately.
+    pub max_sessions: usize,
+    /// Rounds not complete in this time are abandoned.
+    pub session_timeout: Duration,
+}
+
+struct Session<'a, H, MPP, OB>
+where
+    H: HashMarker + FixedOutput,
+    MPP: MakePeerPartitions,
+{
+    behaviour: SigmaAggregation<'a, H, MPP, OB>,
+    /// Outcome of the round as reported by `behaviour`.
+    result: oneshot::Receiver<Result<Aggregated<H>, ()>>,
+    /// Requester of the round.
+    channel: oneshot::Sender<Result<Aggregated<H>, ()>>,
+    deadline: Pin<Box<tokio::time::Sleep>>,
+}
+
+pub struct SigmaAggregationSessions<'a, H, MPP, OB>
+where
+    H: HashMarker + FixedOutput,
+    MPP: MakePeerPartitions,
+{
+    conf: SessionsConfig,
+    /// Shared by all rounds, the signer keeps a pending commitment per round.
+    signer: AsyncSigner,
+    handel_conf: HandelConfig,
+    multicasting_conf: DagMulticastingConfig,
+    partitioner: MPP,
+    mcast_overlay_builder: OB,
+    sessions: HashMap<Digest<H>, Session<'a, H, MPP, OB>>,
+    /// Each `Reset` starts a round for its message, leaving rounds of other messages intact.
+    inbox: mpsc::Receiver<AggregationAction<H>>,
+    outbox: VecDeque<ProtocolBehaviourOut<VoidMessage, SigmaAggrMessage>>,
+}
+
+impl<'a, H, MPP, OB> SigmaAggregationSessions<'a, H, MPP, OB>
+where
+    H: HashMarker + FixedOutput,
+    MPP: MakePeerPartitions + Clone,
+    MPP::PP: Clone + 'static,
+    OB: Clone,
+{
+    pub fn new(
+        host_sk: SecretKey,
+        conf: SessionsConfig,
+        handel_conf: HandelConfig,
+        multicasting_conf: DagMulticastingConfig,
+        partitioner: MPP,
+        mcast_overlay_builder: OB,
+        inbox: mpsc::Receiver<AggregationAction<H>>,
+    ) -> Self {
+        Self::with_signer(
+            AsyncSigner::spawn(InMemorySigner::new(host_sk)),
+            conf,
+            handel_conf,
+            multicasting_conf,
+            partitioner,
+            mcast_overlay_builder,
+            inbox,
+        )
+    }
+
+    pub fn with_signer(
+        signer: AsyncSigner,
+        conf: SessionsConfig,
+        handel_conf: HandelConfig,
+        multicasting_conf: DagMulticastingConfig,
+        partitioner: MPP,
+        mcast_overlay_builder: OB,
+        inbox: mpsc::Receiver<AggregationAction<H>>,
+    ) -> Self {
+        Self {
+            conf,
+            signer,
+            handel_conf,
+            multicasting_conf,
+            partitioner,
+            mcast_overlay_builder,
+            sessions: HashMap::new(),
+            inbox,
+            outbox: VecDeque::new(),
+        }
+    }
+
+    /// Number of rounds in progress.
+    pub fn num_sessions(&self) -> usize {
+        self.sessions.len()
+    }
+
+    fn start_session(&mut self, action: AggregationAction<H>) {
+        let AggregationAction::Reset {
+            new_committee,
+            new_message,
+            excluded_members,
+            member_weights,
+            channel,
+        } = action;
+        if let Some(prev) = self.sessions.remove(&new_message) {
+            let _ = prev.channel.send(Err(()));
+        }
+        if self.sessions.len() >= self.conf.max_sessions {
+            warn!(
+                "Too many aggregation rounds in progress, rejecting round for {:?}",
+                new_message
+            );
+            let _ = channel.send(Err(()));
+            return;
+        }
+        let (mut mailbox, inbox) = mpsc::channel(1);
+        let (snd, result) = oneshot::channel();
+        mailbox
+            .try_send(AggregationAction::Reset {
+                new_committee,
+                new_message: new_message.clone(),
+                excluded_members,
+                member_weights,
+                channel: snd,
+            })
+            .unwrap();
+        let behaviour = SigmaAggregation::with_signer(
+            self.signer.clone(),
+            self.handel_conf,
+            self.multicasting_conf,
+            self.partitioner.clone(),
+            self.mcast_overlay_builder.clone(),
+            inbox,
+        );
+        self.sessions.insert(
+            new_message,
+            Session {
+                behaviour,
+                result,
+                channel,
+                deadline: Box::pin(tokio::time::sleep(self.conf.session_timeout)),
+            },
+        );
+    }
+}
+
+impl<'a, H, MPP, OB> ProtocolBehaviour for SigmaAggregationSessions<'a, H, MPP, OB>
+where
+    H: Debug + HashMarker + FixedOutput<OutputSize = <Secp256k1 as Curve>::FieldBytesSize> + Default,
+    MPP: MakePeerPartitions + Clone + Send,
+    MPP::PP: Send + Clone + 'static,
+    OB: MakeDagOverlay + Clone,
+{
+    type TProto = SigmaAggrSpec;
+
+    fn inject_cancelled(&mut self) {
+        for (_, session) in self.sessions.drain() {
+            let _ = session.channel.send(Err(()));
+        }
+    }
+
+    fn inject_message(&mut self, peer_id: PeerId, msg: SigmaAggrMessage) {
+        let SigmaAggrMessage::SigmaAggrMessageV3(msg) = msg else {
+            trace!("SigmaAggrMessage from {:?} isn't tagged with a session", peer_id);
+            return;
+        };
+        let Ok(session) = Digest::<H>::try_from(msg.session) else {
+            warn!("Malformed session of SigmaAggrMessage from {:?}", peer_id);
+            self.outbox
+                .push_back(ProtocolBehaviourOut::NetworkAction(NetworkAction::BanPeer(
+                    peer_id,
+                )));
+            return;
+        };
+        match self.sessions.get_mut(&session) {
+            Some(session) => session
+                .behaviour
+                .inject_message(peer_id, SigmaAggrMessage::SigmaAggrMessageV2(msg.message)),
+            None => trace!(
+                "SigmaAggrMessage from {:?} of unknown session {:?}",
+                peer_id,
+                session
+            ),
+        }
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<ProtocolBehaviourOut<VoidMessage, SigmaAggrMessage>>> {
+        loop {
+            if let Some(out) = self.outbox.pop_front() {
+                return Poll::Ready(Some(out));
+            }
+
+            while let Poll::Ready(Some(action)) = Stream::poll_next(Pin::new(&mut self.inbox), cx) {
+                self.start_session(action);
+            }
+
+            let mut finished = vec![];
+            for (md, session) in self.sessions.iter_mut() {
+                while let Poll::Ready(Some(out)) = session.behaviour.poll(cx) {
+                    self.outbox.push_back(tag_session(md.as_ref().to_vec(), out));
+                }
+                match session.result.poll_unpin(cx) {
+                    Poll::Ready(res) => finished.push((md.clone(), res.unwrap_or(Err(())))),
+                    Poll::Pending => {
+                        if session.deadline.as_mut().poll(cx).is_ready() {
+                            warn!("Aggregation round for {:?} timed out", md);
+                            finished.push((md.clone(), Err(())));
+                        }
+                    }
+                }
+            }
+            for (md, res) in finished {
+                let session = self.sessions.remove(&md).unwrap();
+                let _ = session.channel.send(res);
+            }
+
+            if self.outbox.is_empty() {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+/// Tag outgoing message with the session it belongs to.
+fn tag_session(
+    session: Vec<u8>,
+    cmd: ProtocolBehaviourOut<VoidMessage, SigmaAggrMessage>,
+) -> ProtocolBehaviourOut<VoidMessage, SigmaAggrMessage> {
+    match cmd.rmap(move |m| m.into_session(session.clone())) {
+        ProtocolBehaviourOut::NetworkAction(NetworkAction::SendOneShotMessage {
+            peer,
+            addr_hint,
+            message,
+            ..
+        }) => ProtocolBehaviourOut::NetworkAction(NetworkAction::SendOneShotMessage {
+            peer,
+            addr_hint,
+            use_version: SIGMA_AGGR_V3,
+            message,
+        }),
+        out => out,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::task::{Context, Poll};
+    use std::time::Duration;
+
+    use elliptic_curve::rand_core::OsRng;
+    use futures::channel::{mpsc, oneshot};
+    use futures::task::noop_waker_ref;
+    use k256::SecretKey;
+    use libp2p::{Multiaddr, PeerId};
+
+    use spectrum_crypto::digest::{blake2b256_hash, Blake2b256, Blake2bDigest256};
+    use spectrum_crypto::pubkey::PublicKey;
+
+    use crate::protocol_handler::aggregation::AggregationAction;
+    use crate::protocol_handler::handel::partitioning::{MakeBinomialPeerPartitions, PseudoRandomGenPerm};
+    use crate::protocol_handler::handel::{HandelConfig, Threshold};
+    use crate::protocol_handler::multicasting::overlay::RedundancyDagOverlayBuilder;
+    use crate::protocol_handler::multicasting::DagMulticastingConfig;
+    use crate::protocol_handler::sigma_aggregation::sessions::{SessionsConfig, SigmaAggregationSessions};
+    use crate::protocol_handler::sigma_aggregation::Aggregated;
+    use crate::protocol_handler::{NetworkAction, ProtocolBehaviour, ProtocolBehaviourOut};
+
+    type Sessions = SigmaAggregationSessions<
+        'static,
+        Blake2b256,
+        MakeBinomialPeerPartitions<PseudoRandomGenPerm>,
+        RedundancyDagOverlayBuilder,
+    >;
+
+    type AggrResult = oneshot::Receiver<Result<Aggregated<Blake2b256>, ()>>;
+
+    const MULTICASTING_CONF: DagMulticastingConfig = DagMulticastingConfig {
+        processing_delay: Duration::from_millis(10),
+        multicasting_duration: Duration::from_millis(200),
+        redundancy_factor: 5,
+        seed: 42,
+    };
+
+    struct Member {
+        peer_id: PeerId,
+        behaviour: Sessions,
+        mailbox: mpsc::Sender<AggregationAction<Blake2b256>>,
+    }
+
+    fn members(n: usize, session_timeout: Duration) -> (Vec<Member>, HashMap<PublicKey, Option<Multiaddr>>) {
+        let keys = (0..n).map(|_| SecretKey::random(&mut OsRng)).collect::<Vec<_>>();
+        let committee = keys
+            .iter()
+            .map(|sk| (PublicKey::from(sk.clone()), None))
+            .collect::<HashMap<_, _>>();
+        let members = keys
+            .into_iter()
+            .map(|sk| {
+                let (mailbox, inbox) = mpsc::channel(4);
+                Member {
+                    peer_id: PeerId::from(PublicKey::from(sk.clone())),
+                    behaviour: SigmaAggregationSessions::new(
+                        sk,
+                        SessionsConfig {
+                            max_sessions: 2,
+                            session_timeout,
+                        },
+                        HandelConfig {
+                            threshold: Threshold { num: 2, denom: 3 },
+                            window_shrinking_factor: 4,
+                            initial_scoring_window: 3,
+                            fast_path_window: 16,
+                            dissemination_delay: Duration::from_millis(40),
+                            level_activation_delay: Duration::from_millis(50),
+                            throttle_factor: 5,
+                            adaptive_activation: None,
+                        },
+                        MULTICASTING_CONF,
+                        MakeBinomialPeerPartitions {
+                            rng: PseudoRandomGenPerm::new([0; 32]),
+                        },
+                        RedundancyDagOverlayBuilder {
+                            redundancy_factor: MULTICASTING_CONF.redundancy_factor,
+                            seed: MULTICASTING_CONF.seed,
+                        },
+                        inbox,
+                    ),
+                    mailbox,
+                }
+            })
+            .collect();
+        (members, committee)
+    }
+
+    fn start(
+        member: &mut Member,
+        committee: &HashMap<PublicKey, Option<Multiaddr>>,
+        message: Blake2bDigest256,
+    ) -> AggrResult {
+        let (snd, recv) = oneshot::channel();
+        member
+            .mailbox
+            .try_send(AggregationAction::Reset {
+                new_committee: committee.clone(),
+                new_message: message,
+                excluded_members: Default::default(),
+                member_weights: Default::default(),
+                channel: snd,
+            })
+            .unwrap();
+        recv
+    }
+
+    /// Route messages between members until all results are in.
+    async fn run(members: &mut [Member], mut results: Vec<AggrResult>) -> Vec<Result<Blake2bDigest256, ()>> {
+        let index = members
+            .iter()
+            .enumerate()
+            .map(|(ix, m)| (m.peer_id, ix))
+            .collect::<HashMap<_, _>>();
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let mut outcomes = vec![None; results.len()];
+        for _ in 0..500 {
+            let mut in_flight = vec![];
+            for member in members.iter_mut() {
+                while let Poll::Ready(Some(out)) = member.behaviour.poll(&mut cx) {
+                    if let ProtocolBehaviourOut::Send { peer_id, message }
+                    | ProtocolBehaviourOut::NetworkAction(NetworkAction::SendOneShotMessage {
+                        peer: peer_id,
+                        message,
+                        ..
+                    }) = out
+                    {
+                        if let Some(to) = index.get(&peer_id) {
+                            in_flight.push((member.peer_id, *to, message));
+                        }
+                    }
+                }
+            }
+            for (from, to, message) in in_flight {
+                members[to].behaviour.inject_message(from, message);
+            }
+            for (outcome, result) in outcomes.iter_mut().zip(results.iter_mut()) {
+                if let Ok(Some(res)) = result.try_recv() {
+                    *outcome = Some(res.map(|aggr| aggr.message_digest));
+                }
+            }
+            if outcomes.iter().all(Option::is_some) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        outcomes.into_iter().map(Option::unwrap).collect()
+    }
+
+    #[tokio::test]
+    async fn concurrent_rounds_complete() {
+        let (mut members, committee) = members(4, Duration::from_secs(30));
+        let messages = [blake2b256_hash(b"foo"), blake2b256_hash(b"bar")];
+        let mut results = vec![];
+        for member in members.iter_mut() {
+            for message in messages {
+                results.push(start(member, &committee, message));
+            }
+        }
+        let outcomes = run(&mut members, results).await;
+        for (ix, outcome) in outcomes.into_iter().enumerate() {
+            assert_eq!(outcome, Ok(messages[ix % 2]));
+        }
+        assert!(members.iter().all(|m| m.behaviour.num_sessions() == 0));
+    }
+
+    #[tokio::test]
+    async fn rounds_beyond_limit_rejected_and_stale_ones_time_out() {
+        let (mut members, committee) = members(4, Duration::from_millis(100));
+        // Other members never join, so the rounds can't complete.
+        let member = &mut members[0];
+        let results = (0..3)
+            .map(|i| start(member, &committee, blake2b256_hash(&[i as u8])))
+            .collect::<Vec<_>>();
+        let outcomes = run(&mut members[..1], results).await;
+        assert_eq!(outcomes, vec![Err(()), Err(()), Err(())]);
+        assert_eq!(members[0].behaviour.num_sessions(), 0);
+    }
+}
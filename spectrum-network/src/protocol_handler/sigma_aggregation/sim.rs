@@ -0,0 +1,493 @@
+//! Recording and deterministic replay of complete aggregation rounds.
+//!
+//! A round is simulated in-process in lockstep passes: in each pass messages due at this pass are
+//! delivered, then every member is polled until it has nothing more to send. Keys and commitment
+//! secrets of members are derived from seeds, and timers of members run on the paused tokio clock
+//! (see [`tokio::time::pause`]), which is advanced by a fixed step between passes, so that
+//! replaying the recorded message schedule reproduces the round. Recordings of interesting
+//! (e.g. byzantine) scenarios are kept in `tests/aggregation_corpus` and replayed as regression
+//! tests.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::channel::{mpsc, oneshot};
+use futures::task::noop_waker_ref;
+use k256::SecretKey;
+use libp2p::PeerId;
+use rand_chacha::rand_core::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use spectrum_crypto::digest::{blake2b256_hash, Blake2b256, Blake2bDigest256};
+use spectrum_crypto::pubkey::PublicKey;
+use spectrum_crypto::signer::{AsyncSigner, InMemorySigner};
+
+use crate::protocol_handler::aggregation::AggregationAction;
+use crate::protocol_handler::handel::partitioning::{MakeBinomialPeerPartitions, PseudoRandomGenPerm};
+use crate::protocol_handler::handel::{HandelConfig, Threshold};
+use crate::protocol_handler::multicasting::overlay::RedundancyDagOverlayBuilder;
+use crate::protocol_handler::multicasting::DagMulticastingConfig;
+use crate::protocol_handler::sigma_aggregation::message::SigmaAggrMessage;
+use crate::protocol_handler::sigma_aggregation::{Aggregated, SigmaAggregation};
+use crate::protocol_handler::{NetworkAction, ProtocolBehaviour, ProtocolBehaviourOut};
+
+/// Time the clock is advanced by between passes, lets timers of the members fire.
+const PASS_DURATION: Duration = Duration::from_millis(10);
+
+/// Scripted misbehaviour of a committee member.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// Member doesn't participate at all.
+    Silent,
+    /// Messages of the member are delivered the given number of passes late.
+    Delayed(u32),
+    /// Messages of the member to the given members are lost.
+    Partitioned(Vec<usize>),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RoundSetup {
+    pub description: String,
+    /// Seeds of keys and commitment secrets of committee members.
+    pub member_seeds: Vec<[u8; 32]>,
+    pub message: Blake2bDigest256,
+    pub threshold: Threshold,
+    pub partitioning_seed: [u8; 32],
+    pub faults: Vec<(usize, Fault)>,
+    /// Members excluded from the round upfront, see [`AggregationAction::Reset`].
+    #[serde(default)]
+    pub excluded: Vec<usize>,
+    /// The round is cut off after this number of passes.
+    pub max_passes: u32,
+}
+
+impl RoundSetup {
+    fn faults_of(&self, member: usize) -> impl Iterator<Item = &Fault> {
+        self.faults
+            .iter()
+            .filter(move |(ix, _)| *ix == member)
+            .map(|(_, fault)| fault)
+    }
+
+    fn is_silent(&self, member: usize) -> bool {
+        self.faults_of(member).any(|f| *f == Fault::Silent)
+    }
+
+    /// Number of passes a message from one member to another spends in flight,
+    /// `None` if the message is lost.
+    fn delivery_delay(&self, from: usize, to: usize) -> Option<u32> {
+        let mut delay = 0;
+        for fault in self.faults_of(from) {
+            match fault {
+                Fault::Silent => return None,
+                Fault::Partitioned(unreachable) if unreachable.contains(&to) => return None,
+                Fault::Partitioned(_) => {}
+                Fault::Delayed(passes) => delay += passes,
+            }
+        }
+        Some(delay)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RecordedMessage {
+    /// Pass the message was delivered at.
+    pub pass: u32,
+    /// Time elapsed on the clock of the simulation since the start of the round at delivery.
+    pub elapsed_millis: u64,
+    pub from: usize,
+    pub to: usize,
+    pub message: SigmaAggrMessage,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum MemberOutcome {
+    Aggregated {
+        num_excluded: usize,
+    },
+    Failed,
+    /// Round didn't complete within `max_passes`.
+    Pending,
+    /// Member didn't participate.
+    Silent,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RoundRecording {
+    pub setup: RoundSetup,
+    pub messages: Vec<RecordedMessage>,
+    pub outcomes: Vec<MemberOutcome>,
+}
+
+impl RoundRecording {
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let file = std::fs::File::create(path)?;
+        ciborium::ser::into_writer(self, file)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        ciborium::de::from_reader(file)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))
+    }
+}
+
+/// Load all recordings (`*.cbor` files) from the given directory.
+pub fn load_corpus(dir: impl AsRef<Path>) -> std::io::Result<Vec<(PathBuf, RoundRecording)>> {
+    let mut corpus = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().map_or(false, |ext| ext == "cbor") {
+            let rec = RoundRecording::load(&path)?;
+            corpus.push((path, rec));
+        }
+    }
+    corpus.sort_by(|(p1, _), (p2, _)| p1.cmp(p2));
+    Ok(corpus)
+}
+
+type SimAggregation = SigmaAggregation<
+    'static,
+    Blake2b256,
+    MakeBinomialPeerPartitions<PseudoRandomGenPerm>,
+    RedundancyDagOverlayBuilder,
+>;
+
+struct Member {
+    peer_id: PeerId,
+    behaviour: SimAggregation,
+    result: Option<oneshot::Receiver<Result<Aggregated<Blake2b256>, ()>>>,
+    outcome: MemberOutcome,
+}
+
+fn handel_conf(threshold: Threshold) -> HandelConfig {
+    HandelConfig {
+        threshold,
+        window_shrinking_factor: 4,
+        initial_scoring_window: 3,
+        fast_path_window: 16,
+        dissemination_delay: Duration::from_millis(40),
+        level_activation_delay: Duration::from_millis(50),
+        throttle_factor: 5,
+        adaptive_activation: None,
+    }
+}
+
+const MULTICASTING_CONF: DagMulticastingConfig = DagMulticastingConfig {
+    processing_delay: Duration::from_millis(10),
+    multicasting_duration: Duration::from_millis(200),
+    redundancy_factor: 5,
+    seed: 42,
+};
+
+fn setup_members(setup: &RoundSetup) -> Vec<Member> {
+    let keys = setup
+        .member_seeds
+        .iter()
+        .map(|seed| SecretKey::random(&mut ChaCha20Rng::from_seed(*seed)))
+        .collect::<Vec<_>>();
+    let committee = keys
+        .iter()
+        .map(|sk| (PublicKey::from(sk.clone()), None))
+        .collect::<HashMap<_, _>>();
+    let excluded_members = setup
+        .excluded
+        .iter()
+        .map(|ix| PublicKey::from(keys[*ix].clone()))
+        .collect::<HashSet<_>>();
+    keys.into_iter()
+        .zip(&setup.member_seeds)
+        .enumerate()
+        .map(|(ix, (sk, seed))| {
+            let peer_id = PeerId::from(PublicKey::from(sk.clone()));
+            let nonce_seed: [u8; 32] = blake2b256_hash(seed).as_ref().try_into().unwrap();
+            let (mut mailbox, inbox) = mpsc::channel(1);
+            let behaviour = SigmaAggregation::with_signer(
+                AsyncSigner::inline(InMemorySigner::seeded(sk, nonce_seed)),
+                handel_conf(setup.threshold),
+                MULTICASTING_CONF,
+                MakeBinomialPeerPartitions {
+                    rng: PseudoRandomGenPerm::new(setup.partitioning_seed),
+                },
+                RedundancyDagOverlayBuilder {
+                    redundancy_factor: MULTICASTING_CONF.redundancy_factor,
+                    seed: MULTICASTING_CONF.seed,
+                },
+                inbox,
+            );
+            let result = if setup.is_silent(ix) {
+                None
+            } else {
+                let (snd, recv) = oneshot::channel();
+                mailbox
+                    .try_send(AggregationAction::Reset {
+                        new_committee: committee.clone(),
+                        new_message: setup.message,
+                        excluded_members: excluded_members.clone(),
+                        member_weights: HashMap::new(),
+                        channel: snd,
+                    })
+                    .unwrap();
+                Some(recv)
+            };
+            Member {
+                peer_id,
+                behaviour,
+                result,
+                outcome: MemberOutcome::Pending,
+            }
+        })
+        .collect()
+}
+
+/// Run the round. Messages are routed between members according to the setup unless a recorded
+/// schedule is given, in which case exactly the recorded messages are delivered at the recorded time.
+///
+/// Panics unless the tokio clock is paused.
+async fn run_round(
+    setup: &RoundSetup,
+    schedule: Option<&[RecordedMessage]>,
+) -> (Vec<RecordedMessage>, Vec<MemberOutcome>) {
+    let mut members = setup_members(setup);
+    let index = members
+        .iter()
+        .enumerate()
+        .map(|(ix, m)| (m.peer_id, ix))
+        .collect::<HashMap<_, _>>();
+    let mut cx = Context::from_waker(noop_waker_ref());
+    let mut in_flight: Vec<(u32, usize, usize, SigmaAggrMessage)> = vec![];
+    let mut delivered = vec![];
+    let started_at = Instant::now();
+    for pass in 0..setup.max_passes {
+        let due = match schedule {
+            Some(schedule) => schedule
+                .iter()
+                .filter(|m| m.pass == pass)
+                .map(|m| {
+                    (
+                        Duration::from_millis(m.elapsed_millis),
+                        m.from,
+                        m.to,
+                        m.message.clone(),
+                    )
+                })
+                .collect::<Vec<_>>(),
+            None => {
+                let (due, later) = in_flight.drain(..).partition::<Vec<_>, _>(|(at, ..)| *at <= pass);
+                in_flight = later;
+                let now = started_at.elapsed();
+                due.into_iter()
+                    .map(|(_, from, to, message)| (now, from, to, message))
+                    .collect()
+            }
+        };
+        for (at, from, to, message) in due {
+            let elapsed = started_at.elapsed();
+            if at > elapsed {
+                tokio::time::advance(at - elapsed).await;
+            }
+            let from_peer = members[from].peer_id;
+            members[to].behaviour.inject_message(from_peer, message.clone());
+            delivered.push(RecordedMessage {
+                pass,
+                elapsed_millis: started_at.elapsed().as_millis() as u64,
+                from,
+                to,
+                message,
+            });
+        }
+        for from in 0..members.len() {
+            let member = &mut members[from];
+            if member.result.is_none() {
+                continue;
+            }
+            while let Poll::Ready(Some(out)) = member.behaviour.poll(&mut cx) {
+                let routed = match out {
+                    ProtocolBehaviourOut::Send { peer_id, message } => Some((peer_id, message)),
+                    ProtocolBehaviourOut::NetworkAction(NetworkAction::SendOneShotMessage {
+                        peer,
+                        message,
+                        ..
+                    }) => Some((peer, message)),
+                    ProtocolBehaviourOut::NetworkAction(_) => None,
+                };
+                if let (Some((peer_id, message)), None) = (routed, schedule) {
+                    let to = index[&peer_id];
+                    if let Some(delay) = setup.delivery_delay(from, to) {
+                        in_flight.push((pass + 1 + delay, from, to, message));
+                    }
+                }
+            }
+            if let Some(Ok(Some(res))) = member.result.as_mut().map(|r| r.try_recv()) {
+                member.outcome = match res {
+                    Ok(aggr) => MemberOutcome::Aggregated {
+                        num_excluded: aggr.exclusion_set.len(),
+                    },
+                    Err(()) => MemberOutcome::Failed,
+                };
+            }
+        }
+        let round_complete = members
+            .iter()
+            .all(|m| m.result.is_none() || m.outcome != MemberOutcome::Pending);
+        if round_complete {
+            break;
+        }
+        tokio::time::advance(PASS_DURATION).await;
+    }
+    let outcomes = members
+        .into_iter()
+        .map(|m| match m.result {
+            None => MemberOutcome::Silent,
+            Some(_) => m.outcome,
+        })
+        .collect();
+    (delivered, outcomes)
+}
+
+/// Simulate the round described by the setup and record it.
+pub async fn record_round(setup: RoundSetup) -> RoundRecording {
+    let (messages, outcomes) = run_round(&setup, None).await;
+    RoundRecording {
+        setup,
+        messages,
+        outcomes,
+    }
+}
+
+/// Replay the recorded round, returning outcomes observed by committee members.
+pub async fn replay_round(rec: &RoundRecording) -> Vec<MemberOutcome> {
+    run_round(&rec.setup, Some(&rec.messages)).await.1
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use spectrum_crypto::digest::blake2b256_hash;
+
+    use crate::protocol_handler::handel::Threshold;
+    use crate::protocol_handler::sigma_aggregation::sim::{
+        load_corpus, record_round, replay_round, Fault, MemberOutcome, RoundRecording, RoundSetup,
+    };
+
+    fn setup(n: u8, faults: Vec<(usize, Fault)>) -> RoundSetup {
+        RoundSetup {
+            description: String::from("test"),
+            member_seeds: (0..n).map(|i| [i; 32]).collect(),
+            message: blake2b256_hash(b"foo"),
+            threshold: Threshold { num: 2, denom: 3 },
+            partitioning_seed: [0; 32],
+            faults,
+            excluded: vec![],
+            max_passes: 500,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn record_and_replay_byzantine_round() {
+        let rec = record_round(setup(
+            8,
+            vec![
+                (0, Fault::Silent),
+                (3, Fault::Delayed(20)),
+                (5, Fault::Partitioned(vec![1, 2])),
+            ],
+        ))
+        .await;
+        assert_eq!(rec.outcomes[0], MemberOutcome::Silent);
+        assert!(rec.messages.iter().all(|m| m.from != 0));
+        assert!(rec
+            .messages
+            .iter()
+            .all(|m| m.from != 5 || ![1, 2].contains(&m.to)));
+
+        let path = std::env::temp_dir().join("sigma_aggr_round.cbor");
+        rec.save(&path).unwrap();
+        let rec = RoundRecording::load(&path).unwrap();
+        assert_eq!(replay_round(&rec).await, rec.outcomes);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn excluded_member_is_not_awaited() {
+        let mut setup = setup(8, vec![(0, Fault::Silent)]);
+        // Nothing but the whole committee would do without the exclusion.
+        setup.threshold = Threshold { num: 1, denom: 1 };
+        setup.excluded = vec![0];
+        let rec = record_round(setup).await;
+        assert_eq!(rec.outcomes[0], MemberOutcome::Silent);
+        assert!(rec.outcomes[1..]
+            .iter()
+            .all(|o| matches!(o, MemberOutcome::Aggregated { .. })));
+    }
+
+    const CORPUS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/aggregation_corpus");
+
+    /// Round pinned together with its expected outcomes (`*.yaml` files of the corpus).
+    #[derive(serde::Deserialize)]
+    struct PinnedRound {
+        setup: RoundSetup,
+        outcomes: Vec<MemberOutcome>,
+    }
+
+    fn load_pinned_rounds() -> Vec<(PathBuf, PinnedRound)> {
+        let mut rounds = vec![];
+        for entry in std::fs::read_dir(CORPUS_DIR).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().map_or(false, |ext| ext == "yaml") {
+                let file = std::fs::File::open(&path).unwrap();
+                rounds.push((path, serde_yaml::from_reader(file).unwrap()));
+            }
+        }
+        rounds.sort_by(|(p1, _), (p2, _)| p1.cmp(p2));
+        rounds
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn replay_corpus() {
+        let recordings = load_corpus(CORPUS_DIR).unwrap();
+        let pinned = load_pinned_rounds();
+        assert!(!recordings.is_empty() || !pinned.is_empty(), "Empty corpus");
+        for (path, rec) in recordings {
+            assert_eq!(
+                replay_round(&rec).await,
+                rec.outcomes,
+                "Regression in {:?}: {}",
+                path,
+                rec.setup.description
+            );
+        }
+        for (path, round) in pinned {
+            let description = round.setup.description.clone();
+            let rec = record_round(round.setup).await;
+            assert_eq!(
+                rec.outcomes, round.outcomes,
+                "Regression in {:?}: {}",
+                path, description
+            );
+            assert_eq!(
+                replay_round(&rec).await,
+                rec.outcomes,
+                "Replay diverged in {:?}: {}",
+                path,
+                description
+            );
+        }
+    }
+
+    /// Saves recordings of the pinned rounds next to them, run with `--ignored` to regenerate.
+    #[tokio::test(start_paused = true)]
+    #[ignore]
+    async fn record_corpus() {
+        for (path, round) in load_pinned_rounds() {
+            let rec = record_round(round.setup).await;
+            assert_eq!(rec.outcomes, round.outcomes, "Outcomes of {:?} changed", path);
+            rec.save(path.with_extension("cbor")).unwrap();
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use std::collections::HashMap;
+use std::fmt::Formatter;
 use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
 
 use derive_more::Into;
 use elliptic_curve::rand_core::OsRng;
@@ -7,7 +9,8 @@ use k256::elliptic_curve::sec1::ToEncodedPoint;
 use k256::schnorr::signature::*;
 use k256::schnorr::VerifyingKey;
 use k256::{ProjectivePoint, Scalar, SecretKey};
-use serde::{Deserialize, Serialize};
+use serde::de::{MapAccess, Visitor};
+use serde::{de, Deserialize, Deserializer, Serialize};
 
 use algebra_core::CommutativePartialSemigroup;
 use spectrum_crypto::digest::{blake2b256_hash, Blake2bDigest256};
@@ -15,7 +18,7 @@ use spectrum_crypto::pubkey::PublicKey;
 use spectrum_crypto::VerifiableAgainst;
 
 use crate::protocol_handler::handel::partitioning::PeerIx;
-use crate::protocol_handler::handel::Weighted;
+use crate::protocol_handler::handel::{PeerWeights, Weighted};
 use crate::protocol_handler::sigma_aggregation::crypto::verify_response;
 
 #[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, derive_more::From, derive_more::Into)]
@@ -40,9 +43,17 @@ impl From<AggregateCommitment> for ProjectivePoint {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, derive_more::From, derive_more::Into)]
+#[derive(Serialize, Clone, Debug, Eq, PartialEq, derive_more::From, derive_more::Into)]
 pub struct Commitment(VerifyingKey);
 
+impl<'de> Deserialize<'de> for Commitment {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        VerifyingKey::deserialize(deserializer)
+            .map(Self)
+            .map_err(|e| de::Error::custom(format!("Commitment is not a point on the curve: {}", e)))
+    }
+}
+
 impl Commitment {
     pub fn as_bytes(&self) -> Vec<u8> {
         let point = k256::PublicKey::from(self.0).to_encoded_point(true);
@@ -81,9 +92,47 @@ impl CommitmentSecret {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
 pub struct Contributions<C>(HashMap<PeerIx, C>);
 
+/// Contributions come from untrusted peers, so unlike a plain map a contribution set
+/// with a contributor occurring more than once is rejected instead of being silently deduplicated.
+impl<'de, C: Deserialize<'de>> Deserialize<'de> for Contributions<C> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct ContributionsVisitor<C>(PhantomData<C>);
+
+        impl<'de, C: Deserialize<'de>> Visitor<'de> for ContributionsVisitor<C> {
+            type Value = Contributions<C>;
+
+            fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+                f.write_str("a map of contributions keyed by PeerIx")
+            }
+
+            fn visit_newtype_struct<D: Deserializer<'de>>(
+                self,
+                deserializer: D,
+            ) -> std::result::Result<Self::Value, D::Error> {
+                deserializer.deserialize_map(self)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> std::result::Result<Self::Value, A::Error> {
+                let mut contributions = HashMap::new();
+                while let Some(ix) = map.next_key::<PeerIx>()? {
+                    let contribution = map
+                        .next_value::<C>()
+                        .map_err(|e| de::Error::custom(format!("Invalid contribution of {:?}: {}", ix, e)))?;
+                    if contributions.insert(ix, contribution).is_some() {
+                        return Err(de::Error::custom(format!("Duplicate contribution of {:?}", ix)));
+                    }
+                }
+                Ok(Contributions(contributions))
+            }
+        }
+
+        deserializer.deserialize_newtype_struct("Contributions", ContributionsVisitor(PhantomData))
+    }
+}
+
 impl<C> Contributions<C> {
     pub fn unit(peer: PeerIx, c: C) -> Self {
         Self(HashMap::from([(peer, c)]))
@@ -106,6 +155,24 @@ impl<C> Contributions<C> {
     pub fn get(&self, peer: &PeerIx) -> Option<&C> {
         self.0.get(peer)
     }
+
+    pub fn contributors(&self) -> impl Iterator<Item = PeerIx> + '_ {
+        self.0.keys().copied()
+    }
+}
+
+impl<C> IntoIterator for Contributions<C> {
+    type Item = (PeerIx, C);
+    type IntoIter = std::collections::hash_map::IntoIter<PeerIx, C>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<C> FromIterator<(PeerIx, C)> for Contributions<C> {
+    fn from_iter<T: IntoIterator<Item = (PeerIx, C)>>(iter: T) -> Self {
+        Self(iter.into_iter().collect())
+    }
 }
 
 impl<C> CommutativePartialSemigroup for Contributions<C>
@@ -131,6 +198,10 @@ impl<C> Weighted for Contributions<C> {
     fn weight(&self) -> usize {
         self.0.len()
     }
+
+    fn stake_weight(&self, weights: &PeerWeights) -> usize {
+        weights.total(self.0.keys())
+    }
 }
 
 pub type PreCommitments = Contributions<Blake2bDigest256>;
@@ -0,0 +1,193 @@
+//! Checks of inbound sigma aggregation messages against the committee of the current round.
+//!
+//! Messages come from peers which may be byzantine, so nothing they carry is trusted to be
+//! within bounds before it reaches Handel or multicasting. Structural defects (duplicate
+//! contributions, commitments off the curve, responses not reduced modulo the group order)
+//! are rejected when the message is decoded, see [`Contributions`]. The rest is checked here.
+//!
+//! With the `audit` feature enabled every rejected input is logged along with the reason.
+//!
+//! [`Contributions`]: crate::protocol_handler::sigma_aggregation::types::Contributions
+
+use crate::protocol_handler::handel::message::HandelMessage;
+use crate::protocol_handler::handel::partitioning::PeerIx;
+use crate::protocol_handler::sigma_aggregation::message::SigmaAggrMessageV1;
+use crate::protocol_handler::sigma_aggregation::types::Contributions;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum Rejection {
+    #[error("Level {level} is out of range, there are {num_levels} levels")]
+    LevelOutOfRange { level: u32, num_levels: usize },
+    #[error("Contribution of {ix:?} is out of range, the committee has {committee_size} members")]
+    IndexOutOfRange { ix: PeerIx, committee_size: usize },
+    #[error("Individual contribution of {sender:?} carries contribution of {ix:?}")]
+    ForeignIndividualContribution { sender: PeerIx, ix: PeerIx },
+}
+
+/// Bounds inbound messages are checked against.
+#[derive(Debug, Copy, Clone)]
+pub struct RoundBounds {
+    pub committee_size: usize,
+    pub num_levels: usize,
+}
+
+pub fn validate_message(
+    msg: &SigmaAggrMessageV1,
+    sender: PeerIx,
+    bounds: RoundBounds,
+) -> Result<(), Rejection> {
+    match msg {
+        SigmaAggrMessageV1::PreCommitments(m) => validate_handel_message(m, sender, bounds),
+        SigmaAggrMessageV1::Commitments(m) => validate_handel_message(m, sender, bounds),
+        SigmaAggrMessageV1::Responses(m) => validate_handel_message(m, sender, bounds),
+        SigmaAggrMessageV1::BroadcastPreCommitments(c) => validate_contributions(c, bounds),
+        SigmaAggrMessageV1::BroadcastCommitments(c) => validate_contributions(c, bounds),
+    }
+}
+
+fn validate_handel_message<C>(
+    msg: &HandelMessage<Contributions<C>>,
+    sender: PeerIx,
+    bounds: RoundBounds,
+) -> Result<(), Rejection> {
+    if msg.level as usize >= bounds.num_levels {
+        return Err(Rejection::LevelOutOfRange {
+            level: msg.level,
+            num_levels: bounds.num_levels,
+        });
+    }
+    validate_contributions(&msg.aggregate_contribution, bounds)?;
+    if let Some(individual) = &msg.individual_contribution {
+        if let Some(ix) = individual.contributors().find(|ix| *ix != sender) {
+            return Err(Rejection::ForeignIndividualContribution { sender, ix });
+        }
+    }
+    Ok(())
+}
+
+fn validate_contributions<C>(contributions: &Contributions<C>, bounds: RoundBounds) -> Result<(), Rejection> {
+    match contributions
+        .contributors()
+        .find(|ix| ix.unwrap() >= bounds.committee_size)
+    {
+        Some(ix) => Err(Rejection::IndexOutOfRange {
+            ix,
+            committee_size: bounds.committee_size,
+        }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::Scalar;
+
+    use spectrum_crypto::digest::blake2b256_hash;
+
+    use crate::protocol_handler::codec;
+    use crate::protocol_handler::handel::message::HandelMessage;
+    use crate::protocol_handler::handel::partitioning::PeerIx;
+    use crate::protocol_handler::sigma_aggregation::crypto::schnorr_commitment_pair;
+    use crate::protocol_handler::sigma_aggregation::message::SigmaAggrMessageV1;
+    use crate::protocol_handler::sigma_aggregation::types::{Commitment, Responses};
+    use crate::protocol_handler::sigma_aggregation::validation::{validate_message, Rejection, RoundBounds};
+
+    const BOUNDS: RoundBounds = RoundBounds {
+        committee_size: 8,
+        num_levels: 4,
+    };
+
+    fn responses(ixs: &[usize]) -> Responses {
+        ixs.iter()
+            .map(|ix| (PeerIx::from(*ix), Scalar::from(*ix as u64 + 1)))
+            .collect()
+    }
+
+    fn handel_responses(level: u32, individual: &[usize], aggregate: &[usize]) -> SigmaAggrMessageV1 {
+        SigmaAggrMessageV1::Responses(HandelMessage {
+            level,
+            individual_contribution: Some(responses(individual)),
+            aggregate_contribution: responses(aggregate),
+            contact_sender: false,
+        })
+    }
+
+    #[test]
+    fn well_formed_message_accepted() {
+        let msg = handel_responses(3, &[1], &[0, 1, 2, 3]);
+        assert_eq!(validate_message(&msg, PeerIx::from(1), BOUNDS), Ok(()));
+    }
+
+    #[test]
+    fn out_of_range_level_rejected() {
+        let msg = handel_responses(4, &[1], &[1]);
+        assert_eq!(
+            validate_message(&msg, PeerIx::from(1), BOUNDS),
+            Err(Rejection::LevelOutOfRange {
+                level: 4,
+                num_levels: 4
+            })
+        );
+    }
+
+    #[test]
+    fn out_of_range_index_rejected() {
+        let msg = handel_responses(1, &[1], &[1, usize::MAX]);
+        assert_eq!(
+            validate_message(&msg, PeerIx::from(1), BOUNDS),
+            Err(Rejection::IndexOutOfRange {
+                ix: PeerIx::from(usize::MAX),
+                committee_size: 8
+            })
+        );
+        let msg = SigmaAggrMessageV1::BroadcastPreCommitments(
+            [(PeerIx::from(8), blake2b256_hash(&[]))].into_iter().collect(),
+        );
+        assert!(matches!(
+            validate_message(&msg, PeerIx::from(1), BOUNDS),
+            Err(Rejection::IndexOutOfRange { .. })
+        ));
+    }
+
+    #[test]
+    fn foreign_individual_contribution_rejected() {
+        let msg = handel_responses(1, &[1, 2], &[1, 2]);
+        assert_eq!(
+            validate_message(&msg, PeerIx::from(1), BOUNDS),
+            Err(Rejection::ForeignIndividualContribution {
+                sender: PeerIx::from(1),
+                ix: PeerIx::from(2)
+            })
+        );
+    }
+
+    #[test]
+    fn duplicate_contributions_rejected() {
+        let scalar = ciborium::value::Value::serialized(&Scalar::ONE).unwrap();
+        let dup = ciborium::value::Value::Map(vec![
+            (ciborium::value::Value::from(1), scalar.clone()),
+            (ciborium::value::Value::from(1), scalar),
+        ]);
+        let err = codec::decode::<Responses>(codec::encode(dup)).unwrap_err();
+        assert!(err.to_string().contains("Duplicate contribution of PeerIx(1)"));
+    }
+
+    #[test]
+    fn commitment_off_curve_rejected() {
+        let (_, commitment) = schnorr_commitment_pair();
+        let mut der = codec::decode::<Vec<u8>>(codec::encode(commitment)).unwrap();
+        // Move the point off the curve by altering its Y coordinate.
+        *der.last_mut().unwrap() ^= 1;
+        let err = codec::decode::<Commitment>(codec::encode(der)).unwrap_err();
+        assert!(err.to_string().contains("not a point on the curve"));
+    }
+
+    #[test]
+    fn unreduced_response_rejected() {
+        let unreduced = ciborium::value::Value::Bytes(vec![0xff; 32]);
+        let resps = ciborium::value::Value::Map(vec![(ciborium::value::Value::from(1), unreduced)]);
+        let err = codec::decode::<Responses>(codec::encode(resps)).unwrap_err();
+        assert!(err.to_string().contains("Invalid contribution of PeerIx(1)"));
+        assert!(err.to_string().contains("scalar out of range"));
+    }
+}
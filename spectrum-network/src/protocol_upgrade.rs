@@ -1,9 +1,11 @@
 pub mod combinators;
+pub mod compression;
 pub mod handshake;
 mod message;
 pub(crate) mod substream;
 
 use crate::protocol::StatefulProtocolSpec;
+use crate::protocol_upgrade::compression::FrameCompression;
 use crate::protocol_upgrade::message::{Approve, APPROVE_SIZE};
 use crate::protocol_upgrade::substream::{ProtocolApproveState, ProtocolSubstreamIn, ProtocolSubstreamOut};
 use crate::types::{ProtocolId, ProtocolTag, ProtocolVer, RawMessage};
@@ -12,7 +14,6 @@ use futures::{AsyncRead, AsyncReadExt, AsyncWrite};
 use libp2p::core::{upgrade, UpgradeInfo};
 use libp2p::{InboundUpgrade, OutboundUpgrade};
 use log::trace;
-use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::future::Future;
 use std::pin::Pin;
@@ -41,13 +42,16 @@ pub struct InboundProtocolSpec {
     max_message_size: usize,
     /// Does the protocol negotiation require a special handshake or not.
     handshake_required: bool,
+    /// Compression of incoming frames, if enabled.
+    compression: Option<FrameCompression>,
 }
 
 impl From<StatefulProtocolSpec> for InboundProtocolSpec {
     fn from(spec: StatefulProtocolSpec) -> Self {
         Self {
-            max_message_size: spec.max_message_size,
+            max_message_size: spec.max_frame_size(),
             handshake_required: spec.approve_required,
+            compression: FrameCompression::of(&spec),
         }
     }
 }
@@ -58,9 +62,9 @@ impl From<StatefulProtocolSpec> for InboundProtocolSpec {
 pub struct ProtocolUpgradeIn {
     /// Protocol to negotiate.
     protocol_id: ProtocolId,
-    /// Protocol versions to negotiate.
+    /// Protocol versions to negotiate in order of preference.
     /// The first one is the main name, while the other ones are fall backs.
-    supported_versions: BTreeMap<ProtocolVer, InboundProtocolSpec>,
+    supported_versions: Vec<(ProtocolVer, InboundProtocolSpec)>,
 }
 
 impl ProtocolUpgradeIn {
@@ -68,11 +72,10 @@ impl ProtocolUpgradeIn {
         protocol_id: ProtocolId,
         supported_versions: Vec<(ProtocolVer, StatefulProtocolSpec)>,
     ) -> Self {
-        let supported_versions = BTreeMap::from_iter(
-            supported_versions
-                .into_iter()
-                .map(|(ver, spec)| (ver, InboundProtocolSpec::from(spec))),
-        );
+        let supported_versions = supported_versions
+            .into_iter()
+            .map(|(ver, spec)| (ver, InboundProtocolSpec::from(spec)))
+            .collect();
         Self {
             protocol_id,
             supported_versions,
@@ -86,9 +89,8 @@ impl UpgradeInfo for ProtocolUpgradeIn {
 
     fn protocol_info(&self) -> Self::InfoIter {
         self.supported_versions
-            .keys()
-            .cloned()
-            .map(|v| ProtocolTag::new(self.protocol_id, v))
+            .iter()
+            .map(|(v, _)| ProtocolTag::new(self.protocol_id, *v))
             .collect::<Vec<_>>()
             .into_iter()
     }
@@ -106,10 +108,7 @@ where
         Box::pin(async move {
             let target = format!("Inbound({})", negotiated_tag);
             trace!(target: &target, "upgrade_inbound()");
-            let pspec = self
-                .supported_versions
-                .get(&negotiated_tag.protocol_ver())
-                .unwrap();
+            let pspec = spec_for(&self.supported_versions, negotiated_tag.protocol_ver());
             let mut codec = UviBytes::default();
             codec.set_max_len(pspec.max_message_size);
             let handshake = if pspec.handshake_required {
@@ -129,6 +128,7 @@ where
             let substream = ProtocolSubstreamIn {
                 socket: Framed::new(socket, codec),
                 approve_state,
+                compression: pspec.compression,
             };
             Ok(InboundProtocolUpgraded {
                 negotiated_tag,
@@ -145,13 +145,16 @@ pub struct OutboundProtocolSpec {
     max_message_size: usize,
     /// Initial message to send when we start communicating.
     handshake: Option<RawMessage>,
+    /// Compression of outgoing frames, if enabled.
+    compression: Option<FrameCompression>,
 }
 
 impl OutboundProtocolSpec {
-    pub fn new(max_message_size: usize, handshake: Option<RawMessage>) -> Self {
+    pub fn new(spec: StatefulProtocolSpec, handshake: Option<RawMessage>) -> Self {
         Self {
-            max_message_size,
+            max_message_size: spec.max_frame_size(),
             handshake,
+            compression: FrameCompression::of(&spec),
         }
     }
 }
@@ -162,9 +165,9 @@ impl OutboundProtocolSpec {
 pub struct ProtocolUpgradeOut {
     /// Protocol to negotiate.
     protocol_id: ProtocolId,
-    /// Protocol versions to negotiate.
+    /// Protocol versions to negotiate in order of preference.
     /// The first one is the main name, while the other ones are fall backs.
-    supported_versions: BTreeMap<ProtocolVer, OutboundProtocolSpec>,
+    supported_versions: Vec<(ProtocolVer, OutboundProtocolSpec)>,
 }
 
 impl ProtocolUpgradeOut {
@@ -172,10 +175,10 @@ impl ProtocolUpgradeOut {
         protocol_id: ProtocolId,
         supported_versions: Vec<(ProtocolVer, StatefulProtocolSpec, Option<RawMessage>)>,
     ) -> Self {
-        let supported_versions =
-            BTreeMap::from_iter(supported_versions.into_iter().map(|(ver, spec, handshake)| {
-                (ver, OutboundProtocolSpec::new(spec.max_message_size, handshake))
-            }));
+        let supported_versions = supported_versions
+            .into_iter()
+            .map(|(ver, spec, handshake)| (ver, OutboundProtocolSpec::new(spec, handshake)))
+            .collect();
         Self {
             protocol_id,
             supported_versions,
@@ -189,9 +192,8 @@ impl UpgradeInfo for ProtocolUpgradeOut {
 
     fn protocol_info(&self) -> Self::InfoIter {
         self.supported_versions
-            .keys()
-            .cloned()
-            .map(|v| ProtocolTag::new(self.protocol_id, v))
+            .iter()
+            .map(|(v, _)| ProtocolTag::new(self.protocol_id, *v))
             .collect::<Vec<_>>()
             .into_iter()
     }
@@ -209,10 +211,7 @@ where
         Box::pin(async move {
             let target = format!("Outbound({})", negotiated_tag);
             trace!(target: &target, "upgrade_outbound()");
-            let pspec = self
-                .supported_versions
-                .get(&negotiated_tag.protocol_ver())
-                .unwrap();
+            let pspec = spec_for(&self.supported_versions, negotiated_tag.protocol_ver());
             let mut codec = UviBytes::default();
             codec.set_max_len(pspec.max_message_size);
             if let Some(handshake) = &pspec.handshake {
@@ -228,6 +227,7 @@ where
             };
             let substream = ProtocolSubstreamOut {
                 socket: Framed::new(socket, codec),
+                compression: pspec.compression,
             };
             Ok(OutboundProtocolUpgraded {
                 negotiated_tag,
@@ -251,6 +251,14 @@ pub struct OutboundProtocolUpgraded<Substream> {
     pub substream: Substream,
 }
 
+/// Spec of the version the remote agreed on. Multistream-select only yields tags we offered.
+fn spec_for<S>(supported_versions: &[(ProtocolVer, S)], ver: ProtocolVer) -> &S {
+    supported_versions
+        .iter()
+        .find_map(|(v, spec)| (*v == ver).then_some(spec))
+        .unwrap()
+}
+
 async fn read_handshake<Substream: AsyncRead + Unpin>(
     socket: &mut Substream,
     max_size: usize,
@@ -278,3 +286,68 @@ async fn write_handshake<Substream: AsyncWrite + Unpin>(
     upgrade::write_length_prefixed(socket, msg).await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::StatefulProtocolSpec;
+    use crate::protocol_upgrade::{ProtocolUpgradeIn, ProtocolUpgradeOut};
+    use crate::types::{ProtocolId, ProtocolTag, ProtocolVer};
+    use libp2p::core::UpgradeInfo;
+
+    const SPEC: StatefulProtocolSpec = StatefulProtocolSpec {
+        max_message_size: 100,
+        approve_required: true,
+        batching: None,
+        compression: None,
+    };
+
+    /// Emulates multistream-select: the dialer proposes its tags in order, the listener accepts
+    /// the first one it supports.
+    fn negotiate(dialer: &ProtocolUpgradeOut, listener: &ProtocolUpgradeIn) -> Option<ProtocolTag> {
+        let supported = listener.protocol_info().collect::<Vec<_>>();
+        dialer.protocol_info().find(|tag| supported.contains(tag))
+    }
+
+    fn upgrade_in(versions: Vec<u8>) -> ProtocolUpgradeIn {
+        ProtocolUpgradeIn::new(
+            ProtocolId::from_u8(1),
+            versions.into_iter().map(|v| (ProtocolVer(v), SPEC)).collect(),
+        )
+    }
+
+    fn upgrade_out(versions: Vec<u8>) -> ProtocolUpgradeOut {
+        ProtocolUpgradeOut::new(
+            ProtocolId::from_u8(1),
+            versions
+                .into_iter()
+                .map(|v| (ProtocolVer(v), SPEC, None))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn protocol_info_follows_preference() {
+        let tags = upgrade_out(vec![1, 3, 2])
+            .protocol_info()
+            .map(|tag| u8::from(tag.protocol_ver()))
+            .collect::<Vec<_>>();
+        assert_eq!(tags, vec![1, 3, 2]);
+    }
+
+    #[test]
+    fn mixed_version_peers_agree_on_common_version() {
+        let tag = negotiate(&upgrade_out(vec![3, 2, 1]), &upgrade_in(vec![2, 1]));
+        assert_eq!(tag.map(|t| t.protocol_ver()), Some(ProtocolVer(2)));
+    }
+
+    #[test]
+    fn dialer_preference_wins_over_newer_common_version() {
+        let tag = negotiate(&upgrade_out(vec![1, 2]), &upgrade_in(vec![2, 1]));
+        assert_eq!(tag.map(|t| t.protocol_ver()), Some(ProtocolVer(1)));
+    }
+
+    #[test]
+    fn disjoint_versions_do_not_negotiate() {
+        assert_eq!(negotiate(&upgrade_out(vec![3]), &upgrade_in(vec![1, 2])), None);
+    }
+}
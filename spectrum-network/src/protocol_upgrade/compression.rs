@@ -0,0 +1,133 @@
+use std::io;
+
+use crate::protocol::{CompressionAlgorithm, CompressionSpec, StatefulProtocolSpec};
+
+/// Frame is sent as is.
+const TAG_RAW: u8 = 0;
+/// Frame is compressed.
+const TAG_COMPRESSED: u8 = 1;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DecompressionError {
+    #[error("Empty frame")]
+    EmptyFrame,
+    #[error("Unknown frame tag {0}")]
+    UnknownTag(u8),
+    #[error("Frame inflates to {size} bytes, while at most {max_size} are allowed")]
+    TooLarge { size: usize, max_size: usize },
+    #[error("Malformed compressed frame")]
+    Malformed,
+}
+
+impl From<DecompressionError> for io::Error {
+    fn from(err: DecompressionError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+/// Frame compression applied by protocol substreams.
+#[derive(Debug, Copy, Clone)]
+pub struct FrameCompression {
+    pub spec: CompressionSpec,
+    /// Frames inflating beyond this size are rejected, so that peers can't exhaust our memory
+    /// with decompression bombs.
+    pub max_decompressed_size: usize,
+}
+
+impl FrameCompression {
+    /// Compression of frames of the given protocol version, if enabled.
+    pub fn of(spec: &StatefulProtocolSpec) -> Option<Self> {
+        spec.compression.map(|compression| Self {
+            spec: compression,
+            max_decompressed_size: spec.max_uncompressed_frame_size(),
+        })
+    }
+
+    /// Compressed frame is sent only if it is actually smaller than the original one.
+    pub fn compress(&self, frame: Vec<u8>) -> Vec<u8> {
+        if frame.len() >= self.spec.min_size {
+            let compressed = match self.spec.algorithm {
+                CompressionAlgorithm::Zstd { level } => zstd::bulk::compress(&frame, level).ok(),
+                CompressionAlgorithm::Lz4 => Some(lz4_flex::block::compress_prepend_size(&frame)),
+            };
+            if let Some(compressed) = compressed.filter(|c| c.len() < frame.len()) {
+                return tagged(TAG_COMPRESSED, compressed);
+            }
+        }
+        tagged(TAG_RAW, frame)
+    }
+
+    pub fn decompress(&self, frame: &[u8]) -> Result<Vec<u8>, DecompressionError> {
+        let (tag, body) = frame.split_first().ok_or(DecompressionError::EmptyFrame)?;
+        match *tag {
+            TAG_RAW => Ok(body.to_vec()),
+            TAG_COMPRESSED => match self.spec.algorithm {
+                // Decompression fails once the output exceeds the capacity.
+                CompressionAlgorithm::Zstd { .. } => zstd::bulk::decompress(body, self.max_decompressed_size)
+                    .map_err(|_| DecompressionError::Malformed),
+                CompressionAlgorithm::Lz4 => {
+                    let (size, compressed) = lz4_flex::block::uncompressed_size(body)
+                        .map_err(|_| DecompressionError::Malformed)?;
+                    if size > self.max_decompressed_size {
+                        return Err(DecompressionError::TooLarge {
+                            size,
+                            max_size: self.max_decompressed_size,
+                        });
+                    }
+                    lz4_flex::block::decompress(compressed, size).map_err(|_| DecompressionError::Malformed)
+                }
+            },
+            tag => Err(DecompressionError::UnknownTag(tag)),
+        }
+    }
+}
+
+fn tagged(tag: u8, body: Vec<u8>) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(body.len() + 1);
+    frame.push(tag);
+    frame.extend(body);
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::{CompressionAlgorithm, CompressionSpec};
+    use crate::protocol_upgrade::compression::{DecompressionError, FrameCompression};
+
+    fn compression(algorithm: CompressionAlgorithm, max_decompressed_size: usize) -> FrameCompression {
+        FrameCompression {
+            spec: CompressionSpec {
+                algorithm,
+                min_size: 64,
+            },
+            max_decompressed_size,
+        }
+    }
+
+    #[test]
+    fn large_frames_compressed_small_ones_sent_as_is() {
+        for algorithm in [CompressionAlgorithm::Zstd { level: 3 }, CompressionAlgorithm::Lz4] {
+            let comp = compression(algorithm, 1 << 20);
+            let large = vec![7u8; 4096];
+            let compressed = comp.compress(large.clone());
+            assert!(compressed.len() < large.len());
+            assert_eq!(comp.decompress(&compressed).unwrap(), large);
+            let small = vec![7u8; 16];
+            let sent = comp.compress(small.clone());
+            assert_eq!(sent.len(), small.len() + 1);
+            assert_eq!(comp.decompress(&sent).unwrap(), small);
+        }
+    }
+
+    #[test]
+    fn decompression_bombs_rejected() {
+        for algorithm in [CompressionAlgorithm::Zstd { level: 3 }, CompressionAlgorithm::Lz4] {
+            let bomb = compression(algorithm, 1 << 20).compress(vec![0u8; 1 << 20]);
+            let res = compression(algorithm, 1 << 10).decompress(&bomb);
+            assert!(matches!(
+                res,
+                Err(DecompressionError::TooLarge { .. } | DecompressionError::Malformed)
+            ));
+        }
+    }
+}
@@ -1,6 +1,8 @@
 use std::collections::BTreeMap;
 
-use crate::types::{ProtocolVer, RawMessage};
+use serde::{Deserialize, Serialize};
+
+use crate::types::{ProtocolId, ProtocolVer, RawMessage};
 
 /// A handshake encoded in formats of all supported versions.
 /// Note, versions must be listed in descending order.
@@ -15,6 +17,17 @@ impl PolyVerHandshakeSpec {
     pub fn handshake_for(&self, ver: ProtocolVer) -> Option<RawMessage> {
         self.0.get(&ver).cloned().flatten()
     }
+
+    /// Attach capabilities of the node to handshakes of all versions.
+    /// Versions without handshake stay as is, as there is nothing to attach them to.
+    pub fn advertise(self, capabilities: &Capabilities) -> PolyVerHandshakeSpec {
+        PolyVerHandshakeSpec(
+            self.0
+                .into_iter()
+                .map(|(ver, hs)| (ver, hs.map(|hs| AdvertisedHandshake::encode(capabilities, hs))))
+                .collect(),
+        )
+    }
 }
 
 impl From<PolyVerHandshakeSpec> for Vec<(ProtocolVer, Option<RawMessage>)> {
@@ -34,3 +47,107 @@ impl From<BTreeMap<ProtocolVer, Option<RawMessage>>> for PolyVerHandshakeSpec {
         Self(xs)
     }
 }
+
+/// Protocols supported by a node along with all their versions.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct Capabilities(BTreeMap<ProtocolId, Vec<ProtocolVer>>);
+
+impl Capabilities {
+    pub fn supports(&self, protocol: ProtocolId, ver: ProtocolVer) -> bool {
+        self.0.get(&protocol).is_some_and(|vers| vers.contains(&ver))
+    }
+
+    /// Protocols both nodes can speak, i.e. having at least one version in common.
+    pub fn common_protocols(&self, other: &Capabilities) -> Vec<ProtocolId> {
+        self.0
+            .iter()
+            .filter(|(prot, vers)| vers.iter().any(|ver| other.supports(**prot, *ver)))
+            .map(|(prot, _)| *prot)
+            .collect()
+    }
+}
+
+impl FromIterator<(ProtocolId, Vec<ProtocolVer>)> for Capabilities {
+    fn from_iter<T: IntoIterator<Item = (ProtocolId, Vec<ProtocolVer>)>>(iter: T) -> Self {
+        Self(BTreeMap::from_iter(iter))
+    }
+}
+
+/// Handshake of a particular protocol prefixed with capabilities of the sender.
+#[derive(Serialize, Deserialize)]
+struct AdvertisedHandshake {
+    capabilities: Capabilities,
+    handshake: Vec<u8>,
+}
+
+impl AdvertisedHandshake {
+    fn encode(capabilities: &Capabilities, handshake: RawMessage) -> RawMessage {
+        let mut bf = vec![];
+        ciborium::ser::into_writer(
+            &AdvertisedHandshake {
+                capabilities: capabilities.clone(),
+                handshake: handshake.into(),
+            },
+            &mut bf,
+        )
+        .unwrap();
+        RawMessage::from(bf)
+    }
+}
+
+/// Split the handshake received from a peer into the peer's capabilities and the handshake
+/// of the protocol itself. Handshakes of peers not advertising capabilities are passed as is.
+pub fn split_advertised(handshake: RawMessage) -> (Option<Capabilities>, RawMessage) {
+    match ciborium::de::from_reader::<AdvertisedHandshake, _>(handshake.as_ref()) {
+        Ok(AdvertisedHandshake {
+            capabilities,
+            handshake,
+        }) => (Some(capabilities), RawMessage::from(handshake)),
+        Err(_) => (None, handshake),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol_upgrade::handshake::{split_advertised, Capabilities, PolyVerHandshakeSpec};
+    use crate::types::{ProtocolId, ProtocolVer, RawMessage};
+
+    fn capabilities(protocols: Vec<(u8, Vec<u8>)>) -> Capabilities {
+        protocols
+            .into_iter()
+            .map(|(prot, vers)| {
+                (
+                    ProtocolId::from(prot),
+                    vers.into_iter().map(ProtocolVer).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn capabilities_travel_along_with_handshakes() {
+        let caps = capabilities(vec![(0, vec![1]), (2, vec![1, 2])]);
+        let hs = RawMessage::from(vec![1, 2, 3]);
+        let spec =
+            PolyVerHandshakeSpec::from(vec![(ProtocolVer(1), Some(hs.clone())), (ProtocolVer(2), None)])
+                .advertise(&caps);
+        assert_eq!(spec.handshake_for(ProtocolVer(2)), None);
+        assert_eq!(
+            split_advertised(spec.handshake_for(ProtocolVer(1)).unwrap()),
+            (Some(caps), hs)
+        );
+    }
+
+    #[test]
+    fn plain_handshakes_passed_as_is() {
+        let hs = RawMessage::from(vec![0xff, 0x00]);
+        assert_eq!(split_advertised(hs.clone()), (None, hs));
+    }
+
+    #[test]
+    fn protocols_without_common_versions_are_not_shared() {
+        let local = capabilities(vec![(0, vec![1]), (1, vec![1]), (2, vec![2, 3])]);
+        let remote = capabilities(vec![(0, vec![1]), (2, vec![1]), (3, vec![1])]);
+        assert_eq!(local.common_protocols(&remote), vec![ProtocolId::from(0)]);
+    }
+}
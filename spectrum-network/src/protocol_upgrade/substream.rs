@@ -1,3 +1,4 @@
+use crate::protocol_upgrade::compression::FrameCompression;
 use crate::protocol_upgrade::message::Approve;
 use crate::types::RawMessage;
 use asynchronous_codec::Framed;
@@ -43,6 +44,8 @@ pub struct ProtocolSubstreamIn<Substream> {
     pub socket: Framed<Substream, UviBytes<io::Cursor<Vec<u8>>>>,
     /// None in the case protocol approve is not required.
     pub approve_state: Option<ProtocolApproveState>,
+    /// Incoming frames are decompressed if compression is enabled.
+    pub compression: Option<FrameCompression>,
 }
 
 impl<Substream> ProtocolSubstreamIn<Substream>
@@ -118,7 +121,14 @@ where
                         }
                         Poll::Ready(Some(msg)) => {
                             *this.approve_state = Some(ProtocolApproveState::Sent);
-                            return Poll::Ready(Some(msg.map(RawMessage::from)));
+                            let msg = msg.and_then(|frame| match this.compression {
+                                Some(compression) => compression
+                                    .decompress(&frame)
+                                    .map(RawMessage::from)
+                                    .map_err(io::Error::from),
+                                None => Ok(RawMessage::from(frame)),
+                            });
+                            return Poll::Ready(Some(msg));
                         }
                         Poll::Pending => {
                             *this.approve_state = Some(ProtocolApproveState::Sent);
@@ -173,6 +183,8 @@ pub struct ProtocolSubstreamOut<Substream> {
     /// Substream where to send messages.
     #[pin]
     pub socket: Framed<Substream, UviBytes<io::Cursor<Vec<u8>>>>,
+    /// Outgoing frames are compressed if compression is enabled.
+    pub compression: Option<FrameCompression>,
 }
 
 impl<Substream> Sink<RawMessage> for ProtocolSubstreamOut<Substream>
@@ -188,8 +200,11 @@ where
 
     fn start_send(self: Pin<&mut Self>, item: RawMessage) -> Result<(), Self::Error> {
         let mut this = self.project();
-        Sink::start_send(this.socket.as_mut(), io::Cursor::new(item.into()))
-            .map_err(ProtocolSubstreamOutError::Io)
+        let frame = match this.compression {
+            Some(compression) => compression.compress(item.into()),
+            None => item.into(),
+        };
+        Sink::start_send(this.socket.as_mut(), io::Cursor::new(frame)).map_err(ProtocolSubstreamOutError::Io)
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
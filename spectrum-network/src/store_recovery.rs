@@ -0,0 +1,319 @@
+//! Recovery of RocksDB stores left in a bad state by a crash.
+//!
+//! Before a store is handed over to its owner it's checked for:
+//! - Stale locks. A lock is only cleared if the process which took it is known to be dead,
+//!   a store locked by a live process is never touched.
+//! - Corruption. Checksums of all blocks are verified if requested, the manifest is verified
+//!   by RocksDB itself when the store is opened. Corrupted stores are repaired, and if that fails
+//!   replaced by the latest backup. Since restoring loses all changes made after the backup,
+//!   it's only done once confirmed by the operator.
+//!
+//! All actions taken are collected into a [`RecoveryReport`] shown in startup diagnostics.
+
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+
+use log::{error, warn};
+use rocksdb::backup::{BackupEngine, BackupEngineOptions, RestoreOptions};
+use rocksdb::{Env, ErrorKind, IteratorMode, Options, ReadOptions, DB};
+
+#[derive(Debug, Clone, Default)]
+pub struct RecoveryConf {
+    /// Directory backups of the store are kept in. No backups are made if `None`.
+    pub backup_dir: Option<PathBuf>,
+    /// Number of the most recent backups to keep.
+    pub backups_to_keep: usize,
+    /// Operator confirmed that the store can be replaced by the latest backup.
+    pub restore_confirmed: bool,
+    /// Verify checksums of all blocks before the store is opened.
+    pub verify_checksums: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecoveryAction {
+    /// Lock left by the dead process was cleared.
+    StaleLockCleared { owner_pid: u32 },
+    /// Store was corrupted and repaired.
+    Repaired,
+    /// Store was corrupted beyond repair and replaced by the backup.
+    RestoredFromBackup { backup_id: u32 },
+    /// Store was corrupted beyond repair and moved to the given path, the owner starts over.
+    MovedAside(PathBuf),
+}
+
+impl Display for RecoveryAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecoveryAction::StaleLockCleared { owner_pid } => {
+                write!(f, "cleared stale lock of process {}", owner_pid)
+            }
+            RecoveryAction::Repaired => write!(f, "repaired corrupted store"),
+            RecoveryAction::RestoredFromBackup { backup_id } => {
+                write!(f, "restored corrupted store from backup #{}", backup_id)
+            }
+            RecoveryAction::MovedAside(path) => {
+                write!(f, "moved corrupted store to {:?} and started over", path)
+            }
+        }
+    }
+}
+
+/// Actions taken to open the store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecoveryReport {
+    pub db_path: PathBuf,
+    pub actions: Vec<RecoveryAction>,
+}
+
+impl RecoveryReport {
+    pub fn is_clean(&self) -> bool {
+        self.actions.is_empty()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RecoveryError {
+    #[error("Store error: {0}")]
+    Store(#[from] rocksdb::Error),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Store at {db_path:?} is locked by another process ({owner_pid:?})")]
+    Locked {
+        db_path: PathBuf,
+        owner_pid: Option<u32>,
+    },
+    #[error(
+        "Store at {db_path:?} can't be repaired, confirm restoring it from the latest backup in {backup_dir:?}"
+    )]
+    RestoreNotConfirmed { db_path: PathBuf, backup_dir: PathBuf },
+}
+
+/// Open the store at `db_path` with `open`, recovering it first if needed.
+/// Stores which are `discardable` are moved aside and started over if they can't be recovered.
+pub fn open_or_recover<D, F>(
+    db_path: &Path,
+    conf: &RecoveryConf,
+    discardable: bool,
+    open: F,
+) -> Result<(D, RecoveryReport), RecoveryError>
+where
+    F: Fn(&Path) -> Result<D, rocksdb::Error>,
+{
+    let mut report = RecoveryReport {
+        db_path: db_path.to_path_buf(),
+        actions: Vec::new(),
+    };
+    let verify_and_open = |db_path: &Path| {
+        if conf.verify_checksums && db_path.exists() {
+            verify_checksums(db_path)?;
+        }
+        open(db_path)
+    };
+    let res = match verify_and_open(db_path) {
+        Err(err) if is_lock_error(&err) => {
+            let owner_pid = lock_owner(db_path);
+            match owner_pid.filter(|pid| is_alive(*pid) == Some(false)) {
+                Some(owner_pid) => {
+                    warn!(
+                        "Clearing stale lock of store {:?} left by process {}",
+                        db_path, owner_pid
+                    );
+                    std::fs::remove_file(db_path.join(LOCK_FILE))?;
+                    report
+                        .actions
+                        .push(RecoveryAction::StaleLockCleared { owner_pid });
+                    verify_and_open(db_path)
+                }
+                None => {
+                    return Err(RecoveryError::Locked {
+                        db_path: db_path.to_path_buf(),
+                        owner_pid,
+                    })
+                }
+            }
+        }
+        res => res,
+    };
+    let db = match res {
+        Err(err) if err.kind() == ErrorKind::Corruption => {
+            warn!("Store at {:?} is corrupted: {}, repairing", db_path, err);
+            match DB::repair(&Options::default(), db_path).and_then(|_| verify_and_open(db_path)) {
+                Ok(db) => {
+                    report.actions.push(RecoveryAction::Repaired);
+                    db
+                }
+                Err(err) => {
+                    error!("Failed to repair store {:?}: {}", db_path, err);
+                    let backup_dir = conf
+                        .backup_dir
+                        .as_ref()
+                        .filter(|dir| latest_backup(dir).is_some());
+                    match backup_dir {
+                        Some(backup_dir) if conf.restore_confirmed => {
+                            move_aside(db_path)?;
+                            let backup_id = restore_latest_backup(db_path, backup_dir)?;
+                            report
+                                .actions
+                                .push(RecoveryAction::RestoredFromBackup { backup_id });
+                            open(db_path)?
+                        }
+                        Some(backup_dir) => {
+                            return Err(RecoveryError::RestoreNotConfirmed {
+                                db_path: db_path.to_path_buf(),
+                                backup_dir: backup_dir.clone(),
+                            })
+                        }
+                        None if discardable => {
+                            let aside_path = move_aside(db_path)?;
+                            report.actions.push(RecoveryAction::MovedAside(aside_path));
+                            open(db_path)?
+                        }
+                        None => return Err(err.into()),
+                    }
+                }
+            }
+        }
+        res => res?,
+    };
+    if let Err(err) = std::fs::write(db_path.join(LOCK_OWNER_FILE), std::process::id().to_string()) {
+        warn!("Failed to record owner of store {:?}: {}", db_path, err);
+    }
+    Ok((db, report))
+}
+
+/// Make a new backup of the store, dropping the oldest ones beyond [`RecoveryConf::backups_to_keep`].
+pub fn backup(db: &DB, conf: &RecoveryConf) -> Result<(), rocksdb::Error> {
+    if let Some(backup_dir) = &conf.backup_dir {
+        let mut engine = backup_engine(backup_dir)?;
+        engine.create_new_backup(db)?;
+        engine.purge_old_backups(conf.backups_to_keep.max(1))?;
+    }
+    Ok(())
+}
+
+fn backup_engine(backup_dir: &Path) -> Result<BackupEngine, rocksdb::Error> {
+    BackupEngine::open(&BackupEngineOptions::new(backup_dir)?, &Env::new()?)
+}
+
+fn latest_backup(backup_dir: &Path) -> Option<u32> {
+    backup_engine(backup_dir)
+        .ok()?
+        .get_backup_info()
+        .into_iter()
+        .map(|info| info.backup_id)
+        .max()
+}
+
+fn restore_latest_backup(db_path: &Path, backup_dir: &Path) -> Result<u32, RecoveryError> {
+    let mut engine = backup_engine(backup_dir)?;
+    engine.restore_from_latest_backup(db_path, db_path, &RestoreOptions::default())?;
+    Ok(latest_backup(backup_dir).unwrap_or_default())
+}
+
+/// Read all blocks of the store verifying their checksums.
+fn verify_checksums(db_path: &Path) -> Result<(), rocksdb::Error> {
+    let db = DB::open_for_read_only(&Options::default(), db_path, false)?;
+    let mut read_opts = ReadOptions::default();
+    read_opts.set_verify_checksums(true);
+    read_opts.fill_cache(false);
+    for item in db.iterator_opt(IteratorMode::Start, read_opts) {
+        item?;
+    }
+    Ok(())
+}
+
+/// Move the store out of the way, keeping it for investigation.
+fn move_aside(db_path: &Path) -> Result<PathBuf, std::io::Error> {
+    let mut file_name = db_path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".corrupted");
+    let aside_path = db_path.with_file_name(file_name);
+    if aside_path.exists() {
+        std::fs::remove_dir_all(&aside_path)?;
+    }
+    std::fs::rename(db_path, &aside_path)?;
+    Ok(aside_path)
+}
+
+fn is_lock_error(err: &rocksdb::Error) -> bool {
+    err.kind() == ErrorKind::IOError && err.as_ref().contains(LOCK_FILE)
+}
+
+fn lock_owner(db_path: &Path) -> Option<u32> {
+    std::fs::read_to_string(db_path.join(LOCK_OWNER_FILE))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Whether the process is running, `None` if it can't be determined on this platform.
+fn is_alive(pid: u32) -> Option<bool> {
+    if pid == std::process::id() {
+        Some(true)
+    } else if cfg!(target_os = "linux") {
+        Some(Path::new("/proc").join(pid.to_string()).exists())
+    } else {
+        None
+    }
+}
+
+const LOCK_FILE: &str = "LOCK";
+/// Id of the process which opened the store last.
+const LOCK_OWNER_FILE: &str = "LOCK.owner";
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use rand::RngCore;
+    use rocksdb::DB;
+
+    use crate::store_recovery::{
+        backup, open_or_recover, restore_latest_backup, RecoveryConf, RecoveryError,
+    };
+
+    fn tmp_path(prefix: &str) -> PathBuf {
+        PathBuf::from(format!("./tmp/{}_{}", prefix, rand::thread_rng().next_u32()))
+    }
+
+    fn open(db_path: &Path) -> Result<DB, rocksdb::Error> {
+        DB::open_default(db_path)
+    }
+
+    #[test]
+    fn store_locked_by_live_process_is_not_touched() {
+        let db_path = tmp_path("recovery");
+        let conf = RecoveryConf::default();
+        let (_db, report) = open_or_recover(&db_path, &conf, true, open).unwrap();
+        assert!(report.is_clean());
+        assert!(matches!(
+            open_or_recover(&db_path, &conf, true, open),
+            Err(RecoveryError::Locked {
+                owner_pid: Some(pid),
+                ..
+            }) if pid == std::process::id()
+        ));
+    }
+
+    #[test]
+    fn store_is_restored_from_backup() {
+        let db_path = tmp_path("recovery");
+        let conf = RecoveryConf {
+            backup_dir: Some(tmp_path("recovery_backup")),
+            backups_to_keep: 2,
+            restore_confirmed: true,
+            verify_checksums: true,
+        };
+        {
+            let (db, _) = open_or_recover(&db_path, &conf, false, open).unwrap();
+            db.put(b"key", b"value").unwrap();
+            backup(&db, &conf).unwrap();
+            db.put(b"key", b"lost").unwrap();
+        }
+        DB::destroy(&rocksdb::Options::default(), &db_path).unwrap();
+        restore_latest_backup(&db_path, conf.backup_dir.as_ref().unwrap()).unwrap();
+        let (db, report) = open_or_recover(&db_path, &conf, false, open).unwrap();
+        assert!(report.is_clean());
+        assert_eq!(db.get(b"key").unwrap(), Some(b"value".to_vec()));
+    }
+}
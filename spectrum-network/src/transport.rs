@@ -0,0 +1,147 @@
+//! Selection of local and remote addresses used for outbound connections.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, TcpListener};
+
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId};
+
+pub mod auth;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct TransportConfig {
+    /// Local address outbound connections are bound to, e.g. the address of a VPN interface.
+    /// The node must listen on this address, as outbound sockets reuse the listening one.
+    #[serde(default)]
+    pub outbound_local_addr: Option<IpAddr>,
+    /// Addresses to reach particular peers at. Take precedence over addresses known otherwise.
+    #[serde(default)]
+    pub routing_hints: HashMap<PeerId, Multiaddr>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TransportConfigError {
+    #[error("Local address {0} is not assigned to any interface: {1}")]
+    LocalAddrUnavailable(IpAddr, std::io::Error),
+    #[error("Outbound connections are bound to {expected}, but the node listens on {listen_addr}")]
+    ListenAddrMismatch {
+        expected: IpAddr,
+        listen_addr: Multiaddr,
+    },
+    #[error("Routing hint {1} for peer {0} is not a TCP address")]
+    UnsupportedHint(PeerId, Multiaddr),
+    #[error("Routing hint {1} for peer {0} is unreachable from local address {2}")]
+    UnreachableHint(PeerId, Multiaddr, IpAddr),
+}
+
+fn ip_of(addr: &Multiaddr) -> Option<IpAddr> {
+    addr.iter().find_map(|p| match p {
+        Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+        Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+        _ => None,
+    })
+}
+
+fn is_tcp(addr: &Multiaddr) -> bool {
+    ip_of(addr).is_some() && addr.iter().any(|p| matches!(p, Protocol::Tcp(_)))
+}
+
+impl TransportConfig {
+    /// Check the config against local interfaces and the address the node listens on.
+    pub fn validate(&self, listen_addr: &Multiaddr) -> Result<(), TransportConfigError> {
+        if let Some(local_ip) = self.outbound_local_addr {
+            TcpListener::bind((local_ip, 0))
+                .map_err(|err| TransportConfigError::LocalAddrUnavailable(local_ip, err))?;
+            if ip_of(listen_addr) != Some(local_ip) {
+                return Err(TransportConfigError::ListenAddrMismatch {
+                    expected: local_ip,
+                    listen_addr: listen_addr.clone(),
+                });
+            }
+        }
+        for (peer_id, addr) in &self.routing_hints {
+            if !is_tcp(addr) {
+                return Err(TransportConfigError::UnsupportedHint(*peer_id, addr.clone()));
+            }
+            if let (Some(local_ip), Some(remote_ip)) = (self.outbound_local_addr, ip_of(addr)) {
+                if local_ip.is_ipv4() != remote_ip.is_ipv4() {
+                    return Err(TransportConfigError::UnreachableHint(
+                        *peer_id,
+                        addr.clone(),
+                        local_ip,
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Config of the TCP transport honouring outbound address binding.
+    pub fn tcp_config(&self) -> libp2p::tcp::Config {
+        libp2p::tcp::Config::new().port_reuse(self.outbound_local_addr.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use libp2p::{Multiaddr, PeerId};
+
+    use crate::transport::{TransportConfig, TransportConfigError};
+
+    fn localhost() -> IpAddr {
+        IpAddr::V4(Ipv4Addr::LOCALHOST)
+    }
+
+    #[test]
+    fn validate_local_addr() {
+        let listen_addr: Multiaddr = "/ip4/127.0.0.1/tcp/8000".parse().unwrap();
+        let conf = TransportConfig {
+            outbound_local_addr: Some(localhost()),
+            routing_hints: HashMap::new(),
+        };
+        assert!(conf.validate(&listen_addr).is_ok());
+        assert!(matches!(
+            conf.validate(&"/ip4/0.0.0.0/tcp/8000".parse().unwrap()),
+            Err(TransportConfigError::ListenAddrMismatch { .. })
+        ));
+        // TEST-NET-3 address is never assigned to a local interface.
+        let conf = TransportConfig {
+            outbound_local_addr: Some("203.0.113.7".parse().unwrap()),
+            routing_hints: HashMap::new(),
+        };
+        assert!(matches!(
+            conf.validate(&"/ip4/203.0.113.7/tcp/8000".parse().unwrap()),
+            Err(TransportConfigError::LocalAddrUnavailable(_, _))
+        ));
+    }
+
+    #[test]
+    fn validate_routing_hints() {
+        let listen_addr: Multiaddr = "/ip4/127.0.0.1/tcp/8000".parse().unwrap();
+        let peer_id = PeerId::random();
+        let conf = TransportConfig {
+            outbound_local_addr: Some(localhost()),
+            routing_hints: HashMap::from([(peer_id, "/ip4/10.8.0.2/tcp/8000".parse().unwrap())]),
+        };
+        assert!(conf.validate(&listen_addr).is_ok());
+        let conf = TransportConfig {
+            outbound_local_addr: Some(localhost()),
+            routing_hints: HashMap::from([(peer_id, "/ip6/::1/tcp/8000".parse().unwrap())]),
+        };
+        assert!(matches!(
+            conf.validate(&listen_addr),
+            Err(TransportConfigError::UnreachableHint(_, _, _))
+        ));
+        let conf = TransportConfig {
+            outbound_local_addr: None,
+            routing_hints: HashMap::from([(peer_id, "/dns4/example.com/tcp/8000".parse().unwrap())]),
+        };
+        assert!(matches!(
+            conf.validate(&listen_addr),
+            Err(TransportConfigError::UnsupportedHint(_, _))
+        ));
+    }
+}
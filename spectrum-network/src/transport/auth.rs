@@ -0,0 +1,212 @@
+//! Authentication of peers by their committee keys, an alternative to Noise when links between
+//! committee members are private anyway, e.g. within a VPN.
+//!
+//! Peers exchange their secp256k1 keys along with random challenges and prove possession of the
+//! keys by signing the challenge of the other side. Connections with peers outside of the committee
+//! are refused. Unlike Noise, traffic isn't encrypted after the handshake.
+
+use std::collections::HashSet;
+use std::iter;
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, FutureExt};
+use k256::ecdsa::signature::{Signer, Verifier};
+use k256::ecdsa::{Signature, SigningKey, VerifyingKey};
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use k256::SecretKey;
+use libp2p::core::upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo};
+use libp2p::PeerId;
+
+use spectrum_crypto::pubkey::PublicKey;
+
+pub const COMMITTEE_AUTH_PROTOCOL: &str = "/spectrum/committee-auth/1.0.0";
+
+/// Domain separation tag of signed transcripts.
+const TRANSCRIPT_TAG: &[u8] = b"spectrum/committee-auth";
+const CHALLENGE_LEN: usize = 32;
+const COMPRESSED_KEY_LEN: usize = 33;
+const SIGNATURE_LEN: usize = 64;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CommitteeAuthError {
+    #[error("Handshake failed: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Remote key is not a valid secp256k1 point")]
+    MalformedKey,
+    #[error("Peer {0} is not a member of the committee")]
+    NotACommitteeMember(PeerId),
+    #[error("Peer {0} failed to prove possession of its key")]
+    InvalidSignature(PeerId),
+}
+
+/// Upgrade of raw connections authenticating both sides as members of the committee.
+/// Identities of peers are derived from their committee keys.
+#[derive(Clone)]
+pub struct CommitteeAuth {
+    local_sk: SecretKey,
+    committee: Arc<HashSet<PublicKey>>,
+}
+
+impl CommitteeAuth {
+    pub fn new<I>(local_sk: SecretKey, committee: I) -> Self
+    where
+        I: IntoIterator<Item = PublicKey>,
+    {
+        Self {
+            local_sk,
+            committee: Arc::new(committee.into_iter().collect()),
+        }
+    }
+
+    async fn handshake<C>(self, mut socket: C) -> Result<(PeerId, C), CommitteeAuthError>
+    where
+        C: AsyncRead + AsyncWrite + Unpin,
+    {
+        let local_pk = PublicKey::from(self.local_sk.public_key());
+        let local_challenge: [u8; CHALLENGE_LEN] = rand::random();
+        let mut hello = encode_key(local_pk);
+        hello.extend_from_slice(&local_challenge);
+        socket.write_all(&hello).await?;
+        socket.flush().await?;
+
+        let mut remote_hello = [0u8; COMPRESSED_KEY_LEN + CHALLENGE_LEN];
+        socket.read_exact(&mut remote_hello).await?;
+        let (remote_key, remote_challenge) = remote_hello.split_at(COMPRESSED_KEY_LEN);
+        let remote_pk = k256::PublicKey::from_sec1_bytes(remote_key)
+            .map(PublicKey::from)
+            .map_err(|_| CommitteeAuthError::MalformedKey)?;
+        let remote_peer_id = PeerId::from(remote_pk);
+        if !self.committee.contains(&remote_pk) {
+            return Err(CommitteeAuthError::NotACommitteeMember(remote_peer_id));
+        }
+
+        let signature: Signature =
+            SigningKey::from(&self.local_sk).sign(&transcript(remote_challenge, local_pk, remote_pk));
+        socket.write_all(&signature.to_bytes()).await?;
+        socket.flush().await?;
+
+        let mut remote_signature = [0u8; SIGNATURE_LEN];
+        socket.read_exact(&mut remote_signature).await?;
+        Signature::from_slice(&remote_signature)
+            .ok()
+            .filter(|sig| {
+                VerifyingKey::from(k256::PublicKey::from(remote_pk))
+                    .verify(&transcript(&local_challenge, remote_pk, local_pk), sig)
+                    .is_ok()
+            })
+            .ok_or(CommitteeAuthError::InvalidSignature(remote_peer_id))?;
+        Ok((remote_peer_id, socket))
+    }
+}
+
+/// Signature binds the challenge to both keys, so that it can't be relayed to another peer.
+fn transcript(challenge: &[u8], signer: PublicKey, verifier: PublicKey) -> Vec<u8> {
+    let mut bf = TRANSCRIPT_TAG.to_vec();
+    bf.extend_from_slice(challenge);
+    bf.extend(encode_key(signer));
+    bf.extend(encode_key(verifier));
+    bf
+}
+
+fn encode_key(pk: PublicKey) -> Vec<u8> {
+    k256::PublicKey::from(pk)
+        .to_encoded_point(true)
+        .as_bytes()
+        .to_vec()
+}
+
+impl UpgradeInfo for CommitteeAuth {
+    type Info = &'static str;
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(COMMITTEE_AUTH_PROTOCOL)
+    }
+}
+
+impl<C> InboundUpgrade<C> for CommitteeAuth
+where
+    C: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Output = (PeerId, C);
+    type Error = CommitteeAuthError;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(self, socket: C, _: Self::Info) -> Self::Future {
+        self.handshake(socket).boxed()
+    }
+}
+
+impl<C> OutboundUpgrade<C> for CommitteeAuth
+where
+    C: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Output = (PeerId, C);
+    type Error = CommitteeAuthError;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(self, socket: C, _: Self::Info) -> Self::Future {
+        self.handshake(socket).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_std::net::{TcpListener, TcpStream};
+    use k256::SecretKey;
+    use libp2p::PeerId;
+    use rand::rngs::OsRng;
+
+    use spectrum_crypto::pubkey::PublicKey;
+
+    use crate::transport::auth::{CommitteeAuth, CommitteeAuthError};
+
+    async fn connect(
+        left: CommitteeAuth,
+        right: CommitteeAuth,
+    ) -> (
+        Result<PeerId, CommitteeAuthError>,
+        Result<PeerId, CommitteeAuthError>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (inbound, outbound) = futures::join!(
+            async { right.handshake(listener.accept().await.unwrap().0).await },
+            async { left.handshake(TcpStream::connect(addr).await.unwrap()).await }
+        );
+        (outbound.map(|(pid, _)| pid), inbound.map(|(pid, _)| pid))
+    }
+
+    #[async_std::test]
+    async fn members_authenticated_by_committee_keys() {
+        let (alice, bob) = (SecretKey::random(&mut OsRng), SecretKey::random(&mut OsRng));
+        let committee = [
+            PublicKey::from(alice.public_key()),
+            PublicKey::from(bob.public_key()),
+        ];
+        let (alice_sees, bob_sees) = connect(
+            CommitteeAuth::new(alice.clone(), committee),
+            CommitteeAuth::new(bob.clone(), committee),
+        )
+        .await;
+        assert_eq!(alice_sees.unwrap(), PeerId::from(committee[1]));
+        assert_eq!(bob_sees.unwrap(), PeerId::from(committee[0]));
+    }
+
+    #[async_std::test]
+    async fn outsiders_refused() {
+        let (alice, mallory) = (SecretKey::random(&mut OsRng), SecretKey::random(&mut OsRng));
+        let committee = [PublicKey::from(alice.public_key())];
+        let (alice_sees, _) = connect(
+            CommitteeAuth::new(alice, committee),
+            CommitteeAuth::new(mallory.clone(), [PublicKey::from(mallory.public_key())]),
+        )
+        .await;
+        assert!(matches!(
+            alice_sees,
+            Err(CommitteeAuthError::NotACommitteeMember(pid))
+                if pid == PeerId::from(PublicKey::from(mallory.public_key()))
+        ));
+    }
+}
@@ -5,6 +5,8 @@ use libp2p::bytes::BytesMut;
 use libp2p::core::upgrade;
 use serde::{Deserialize, Serialize};
 
+use spectrum_crypto::digest::Blake2bDigest256;
+
 use crate::peer_manager::data::ReputationChange;
 
 /// Opaque identifier for an incoming connection. Allocated by the network.
@@ -19,7 +21,7 @@ impl From<u64> for IncomingIndex {
 
 /// Reputation value of the node, between `i32::MIN` (we hate that node) and
 /// `i32::MAX` (we love that node).
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Reputation(i32);
 
 impl Reputation {
@@ -29,6 +31,9 @@ impl Reputation {
     pub fn apply(&self, change: ReputationChange) -> Self {
         Reputation(self.0 + i32::from(change))
     }
+    pub fn shifted(&self, delta: i32) -> Self {
+        Reputation(self.0 + delta)
+    }
 }
 
 impl From<i32> for Reputation {
@@ -37,6 +42,13 @@ impl From<i32> for Reputation {
     }
 }
 
+/// Identifier of the network the node belongs to, derived from the genesis of the network.
+/// Nodes of different networks refuse to communicate.
+#[derive(
+    Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash, derive_more::From, derive_more::Display,
+)]
+pub struct NetworkId(Blake2bDigest256);
+
 /// Identifier of a protocol.
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ProtocolId(u8);
@@ -60,7 +72,7 @@ impl From<u8> for ProtocolId {
 }
 
 /// Version of a protocol.
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct ProtocolVer(pub u8);
 
 impl Default for ProtocolVer {
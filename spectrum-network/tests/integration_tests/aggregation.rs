@@ -16,12 +16,18 @@ use std::io::Write;
 use serde::{Deserialize, Serialize};
 use spectrum_crypto::digest::Blake2b256;
 use spectrum_crypto::pubkey::PublicKey;
-use spectrum_network::network_controller::{NetworkController, NetworkControllerIn, NetworkMailbox};
-use spectrum_network::peer_conn_handler::PeerConnHandlerConf;
+use spectrum_network::network_controller::{
+    EnableRetryPolicy, NetworkController, NetworkControllerIn, NetworkMailbox,
+};
+use spectrum_network::peer_conn_handler::{IdleSubstreamPolicy, PeerConnHandlerConf};
+use spectrum_network::peer_manager::data::{ReputationDecayPolicy, RetryPolicy};
 use spectrum_network::peer_manager::peers_state::PeerRepo;
-use spectrum_network::peer_manager::{NetworkingConfig, PeerManager, PeerManagerConfig, PeersMailbox};
+use spectrum_network::peer_manager::{
+    NetworkingConfig, PeerManager, PeerManagerConfig, PeerStorage, PeersMailbox,
+};
 use spectrum_network::protocol::{
-    OneShotProtocolConfig, OneShotProtocolSpec, ProtocolConfig, SIGMA_AGGR_PROTOCOL_ID,
+    OneShotProtocolConfig, OneShotProtocolSpec, ProtocolConfig, ProtocolPriority, SIGMA_AGGR_PROTOCOL_ID,
+    SIGMA_AGGR_V2,
 };
 use spectrum_network::protocol_api::ProtocolMailbox;
 use spectrum_network::protocol_handler::aggregation::AggregationAction;
@@ -33,7 +39,7 @@ use spectrum_network::protocol_handler::multicasting::overlay::RedundancyDagOver
 use spectrum_network::protocol_handler::multicasting::DagMulticastingConfig;
 use spectrum_network::protocol_handler::sigma_aggregation::SigmaAggregation;
 use spectrum_network::protocol_handler::ProtocolHandler;
-use spectrum_network::types::{ProtocolVer, Reputation};
+use spectrum_network::types::Reputation;
 
 pub fn k256_to_libsecp256k1(secret_key: SecretKey) -> identity::secp256k1::SecretKey {
     identity::secp256k1::SecretKey::try_from_bytes(secret_key.to_bytes().as_mut_slice()).unwrap()
@@ -67,27 +73,34 @@ pub fn setup_nodes<'de>(
         assert_eq!(key, peer_sk);
 
         let one_shot_proto_conf = OneShotProtocolConfig {
-            version: ProtocolVer::default(),
+            version: SIGMA_AGGR_V2,
             spec: OneShotProtocolSpec {
                 max_message_size: 5000,
             },
+            priority: ProtocolPriority::HIGH,
         };
         let peer_conn_handler_conf = PeerConnHandlerConf {
+            control_msg_buffer_size: 100,
             async_msg_buffer_size: 100,
             sync_msg_buffer_size: 100,
             open_timeout: Duration::from_secs(60),
             initial_keep_alive: Duration::from_secs(120),
+            idle_substream_policy: IdleSubstreamPolicy::KeepOpen,
         };
         let netw_config = NetworkingConfig {
             min_known_peers: 1,
             min_outbound: 1,
             max_inbound: 10,
             max_outbound: 20,
+            peers_snapshot_interval: Duration::from_secs(60),
+            peers_storage: PeerStorage::Memory,
+            ..NetworkingConfig::default()
         };
         let peer_manager_conf = PeerManagerConfig {
             min_acceptable_reputation: Reputation::from(-50),
             min_reputation: Reputation::from(-20),
-            conn_reset_outbound_backoff: Duration::from_secs(120),
+            dial_retry_policy: RetryPolicy::default(),
+            reputation_decay: ReputationDecayPolicy::default(),
             conn_alloc_interval: Duration::from_secs(30),
             prot_alloc_interval: Duration::from_secs(30),
             protocols_allocation: Vec::new(),
@@ -101,6 +114,7 @@ pub fn setup_nodes<'de>(
             dissemination_delay: Duration::from_millis(40),
             level_activation_delay: Duration::from_millis(50),
             throttle_factor: 5,
+            adaptive_activation: None,
         };
         let multicasting_conf = DagMulticastingConfig {
             processing_delay: Duration::from_millis(10),
@@ -150,6 +164,7 @@ pub fn setup_nodes<'de>(
                 peers,
                 peer_manager,
                 requests_recv,
+                EnableRetryPolicy::default(),
             );
         Peer {
             peer_id,
@@ -96,6 +96,7 @@ where
             Some(DiscoveryHandshake::HandshakeV1(HandshakeV1 {
                 supported_protocols: status.supported_protocols.clone(),
                 height: status.height,
+                network_id: None,
             })),
         )]
     }
@@ -146,7 +147,12 @@ where
         self.send_fake_msg(peer_id);
     }
 
-    fn inject_protocol_requested(&mut self, peer_id: PeerId, handshake: Option<DiscoveryHandshake>) {
+    fn inject_protocol_requested(
+        &mut self,
+        peer_id: PeerId,
+        _protocol_ver: ProtocolVer,
+        handshake: Option<DiscoveryHandshake>,
+    ) {
         if let Some(DiscoveryHandshake::HandshakeV1(hs)) = handshake {
             self.tracked_peers.insert(
                 peer_id,
@@ -175,6 +181,7 @@ where
     fn inject_protocol_enabled(
         &mut self,
         peer_id: PeerId,
+        _protocol_ver: ProtocolVer,
         _handshake: Option<<Self::TProto as spectrum_network::protocol_handler::ProtocolSpec>::THandshake>,
     ) {
         self.send_fake_msg(peer_id);
@@ -1,7 +1,10 @@
 use std::ops::Sub;
 use std::sync::Arc;
 use std::time::Instant;
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
 
 use async_std::sync::Mutex;
 use futures::channel::mpsc::Sender;
@@ -21,7 +24,10 @@ use log4rs_test_utils::test_logging::init_logging_once_for;
 
 use spectrum_crypto::digest::blake2b256_hash;
 use spectrum_crypto::pubkey::PublicKey;
-use spectrum_network::protocol::{OneShotProtocolConfig, OneShotProtocolSpec, ProtocolConfig};
+use spectrum_network::metrics::{Metric, PrometheusMetrics};
+use spectrum_network::protocol::{
+    OneShotProtocolConfig, OneShotProtocolSpec, ProtocolConfig, ProtocolPriority,
+};
 use spectrum_network::protocol_api::ProtocolEvent;
 use spectrum_network::protocol_handler::aggregation::AggregationAction;
 use spectrum_network::protocol_handler::handel::{
@@ -31,14 +37,17 @@ use spectrum_network::protocol_handler::handel::{
 use spectrum_network::protocol_handler::multicasting::overlay::{
     MakeDagOverlay, RedundancyDagOverlayBuilder,
 };
+use spectrum_network::protocol_upgrade::handshake::PolyVerHandshakeSpec;
 use spectrum_network::types::{ProtocolTag, RawMessage};
 use spectrum_network::{
-    network_controller::{NetworkController, NetworkControllerIn, NetworkControllerOut, NetworkMailbox},
-    peer_conn_handler::{ConnHandlerError, PeerConnHandlerConf},
+    network_controller::{
+        EnableRetryPolicy, NetworkController, NetworkControllerIn, NetworkControllerOut, NetworkMailbox,
+    },
+    peer_conn_handler::{ConnHandlerError, IdleSubstreamPolicy, PeerConnHandlerConf},
     peer_manager::{
-        data::{ConnectionLossReason, PeerDestination, ReputationChange},
+        data::{ConnectionLossReason, PeerDestination, ReputationChange, ReputationDecayPolicy, RetryPolicy},
         peers_state::PeerRepo,
-        NetworkingConfig, PeerManager, PeerManagerConfig, PeersMailbox,
+        NetworkingConfig, PeerManager, PeerManagerConfig, PeerStorage, PeersMailbox,
     },
     protocol::{StatefulProtocolConfig, StatefulProtocolSpec, DISCOVERY_PROTOCOL_ID},
     protocol_api::ProtocolMailbox,
@@ -110,6 +119,7 @@ async fn one_shot_messaging() {
         spec: OneShotProtocolSpec {
             max_message_size: 100,
         },
+        priority: ProtocolPriority::NORMAL,
     };
     let protocols_0 = HashMap::from([(
         pid,
@@ -945,6 +955,217 @@ async fn integration_test_2() {
     );
 }
 
+/// Integration test which covers resolution of simultaneous dials:
+///  - both peers dial each other at once
+///  - exactly one connection survives on both sides, without peers being disconnected
+#[cfg_attr(feature = "test_peer_punish_too_slow", ignore)]
+#[async_std::test]
+async fn simultaneous_dials() {
+    //  --------             --------
+    // | peer_0 | <~~~~~~~> | peer_1 |
+    //  --------             --------
+    let local_key_0 = identity::Keypair::generate_ed25519();
+    let local_peer_id_0 = PeerId::from(local_key_0.public());
+    let local_key_1 = identity::Keypair::generate_ed25519();
+    let local_peer_id_1 = PeerId::from(local_key_1.public());
+
+    let addr_0: Multiaddr = "/ip4/127.0.0.1/tcp/1243".parse().unwrap();
+    let addr_1: Multiaddr = "/ip4/127.0.0.1/tcp/1244".parse().unwrap();
+    let (nc_0, _nc_mailbox_0) = make_nc_without_protocol_handler(
+        vec![PeerDestination::PeerIdWithAddr(local_peer_id_1, addr_1.clone())],
+        HashMap::new(),
+    );
+    let (nc_1, _nc_mailbox_1) = make_nc_without_protocol_handler(
+        vec![PeerDestination::PeerIdWithAddr(local_peer_id_0, addr_0.clone())],
+        HashMap::new(),
+    );
+
+    let ((events_0, num_conns_0), (events_1, num_conns_1)) = futures::join!(
+        run_swarm_for(local_key_0, nc_0, addr_0, Duration::from_secs(10)),
+        run_swarm_for(local_key_1, nc_1, addr_1, Duration::from_secs(10)),
+    );
+
+    for (events, num_conns) in [(events_0, num_conns_0), (events_1, num_conns_1)] {
+        assert_eq!(num_conns, 1, "events: {:?}", events);
+        let num_connected = events
+            .iter()
+            .filter(|e| {
+                matches!(
+                    e,
+                    NetworkControllerOut::ConnectedWithInboundPeer(_)
+                        | NetworkControllerOut::ConnectedWithOutboundPeer(_)
+                )
+            })
+            .count();
+        assert_eq!(num_connected, 1, "events: {:?}", events);
+        assert!(
+            !events
+                .iter()
+                .any(|e| matches!(e, NetworkControllerOut::Disconnected { .. })),
+            "events: {:?}",
+            events
+        );
+    }
+}
+
+/// Integration test which covers:
+///  - eviction of an outbound substream nothing was sent over for the idle timeout
+///  - re-opening of the evicted substream on the next send
+///  - the connection and the protocol staying enabled meanwhile
+#[cfg_attr(feature = "test_peer_punish_too_slow", ignore)]
+#[async_std::test]
+async fn idle_substreams_evicted_and_reopened() {
+    //  --------             --------
+    // | peer_0 | <~~~~~~~~ | peer_1 |
+    //  --------             --------
+    //
+    // In this scenario `peer_1` enables a protocol with `peer_0`, sends a message, stays silent
+    // for longer than the idle timeout and then sends another message.
+    let local_key_0 = identity::Keypair::generate_ed25519();
+    let local_peer_id_0 = PeerId::from(local_key_0.public());
+    let local_key_1 = identity::Keypair::generate_ed25519();
+
+    let addr_0: Multiaddr = "/ip4/127.0.0.1/tcp/1245".parse().unwrap();
+    let addr_1: Multiaddr = "/ip4/127.0.0.1/tcp/1246".parse().unwrap();
+    let peers_1 = vec![PeerDestination::PeerIdWithAddr(local_peer_id_0, addr_0.clone())];
+
+    let pid = ProtocolId::from_u8(2);
+    let ver = ProtocolVer::from(1u8);
+    let conf = ProtocolConfig::Stateful(StatefulProtocolConfig {
+        supported_versions: vec![(
+            ver,
+            StatefulProtocolSpec {
+                max_message_size: 100,
+                approve_required: false,
+                batching: None,
+                compression: None,
+            },
+        )],
+        preferred_versions: vec![],
+        priority: ProtocolPriority::NORMAL,
+    });
+    let idle_timeout = Duration::from_secs(1);
+    let (protocol_snd_0, mut protocol_recv_0) = mpsc::channel::<ProtocolEvent>(100);
+    let (protocol_snd_1, mut protocol_recv_1) = mpsc::channel::<ProtocolEvent>(100);
+    let (nc_0, mut nc_mailbox_0) = make_nc_with_idle_policy(
+        vec![],
+        HashMap::from([(pid, (conf.clone(), ProtocolMailbox::new(protocol_snd_0)))]),
+        IdleSubstreamPolicy::CloseAfter(idle_timeout),
+    );
+    let (nc_1, mut nc_mailbox_1) = make_nc_with_idle_policy(
+        peers_1,
+        HashMap::from([(pid, (conf, ProtocolMailbox::new(protocol_snd_1)))]),
+        IdleSubstreamPolicy::CloseAfter(idle_timeout),
+    );
+    let metrics_1 = PrometheusMetrics::new();
+    let nc_1 = nc_1.with_metrics(Arc::new(metrics_1.clone()));
+    let protocol_label = vec![("protocol", "2".to_string())];
+
+    let enable = move |peer| NetworkControllerIn::EnableProtocol {
+        protocol: pid,
+        peer,
+        handshake: PolyVerHandshakeSpec::from(vec![(ver, None)]),
+    };
+    let drive = async {
+        let sink = loop {
+            futures::select! {
+                event = protocol_recv_0.select_next_some() => {
+                    if let ProtocolEvent::Requested { peer_id, .. } = event {
+                        nc_mailbox_0.send(enable(peer_id)).await.unwrap();
+                    }
+                },
+                event = protocol_recv_1.select_next_some() => match event {
+                    ProtocolEvent::Connected(peer_id) => nc_mailbox_1.send(enable(peer_id)).await.unwrap(),
+                    ProtocolEvent::Enabled { sink, .. } => break sink,
+                    _ => {}
+                },
+            }
+        };
+        let mut received = vec![];
+        for message in [RawMessage::from(vec![0, 0, 0]), RawMessage::from(vec![1, 1, 1])] {
+            sink.send_message(message).unwrap();
+            let content = async_std::future::timeout(Duration::from_secs(5), async {
+                loop {
+                    match protocol_recv_0.select_next_some().await {
+                        ProtocolEvent::Message { content, .. } => break content,
+                        ProtocolEvent::Disabled(_) => panic!("Protocol disabled by peer_0"),
+                        _ => {}
+                    }
+                }
+            })
+            .await
+            .unwrap();
+            received.push(content);
+            wasm_timer::Delay::new(idle_timeout * 3).await.unwrap();
+            assert!(
+                metrics_1
+                    .get(Metric::SubstreamsEvicted, protocol_label.clone())
+                    .unwrap_or(0)
+                    >= received.len() as i64
+            );
+        }
+        assert_eq!(
+            metrics_1.get(Metric::SubstreamsReopened, protocol_label.clone()),
+            Some(1)
+        );
+        received
+    };
+
+    let ((events_0, num_conns_0), (events_1, num_conns_1), received) = futures::join!(
+        run_swarm_for(local_key_0, nc_0, addr_0, Duration::from_secs(15)),
+        run_swarm_for(local_key_1, nc_1, addr_1, Duration::from_secs(15)),
+        drive,
+    );
+
+    assert_eq!(
+        received,
+        vec![RawMessage::from(vec![0, 0, 0]), RawMessage::from(vec![1, 1, 1])]
+    );
+    for (events, num_conns) in [(events_0, num_conns_0), (events_1, num_conns_1)] {
+        assert_eq!(num_conns, 1, "events: {:?}", events);
+        assert!(
+            !events.iter().any(|e| matches!(
+                e,
+                NetworkControllerOut::Disconnected { .. } | NetworkControllerOut::ProtocolDisabled { .. }
+            )),
+            "events: {:?}",
+            events
+        );
+    }
+}
+
+/// Run the swarm for the given period of time.
+/// Returns events emitted by the network controller and the number of connections left.
+async fn run_swarm_for(
+    local_key: identity::Keypair,
+    nc: NetworkController<PeersMailbox, PeerManager<PeerRepo>, ProtocolMailbox>,
+    addr: Multiaddr,
+    duration: Duration,
+) -> (Vec<NetworkControllerOut>, u32) {
+    let transport = tcp::async_io::Transport::default()
+        .upgrade(Version::V1Lazy)
+        .authenticate(noise::Config::new(&local_key).unwrap())
+        .multiplex(yamux::Config::default())
+        .boxed();
+    let local_peer_id = PeerId::from(local_key.public());
+    let nc = nc.with_local_peer_id(local_peer_id);
+    let mut swarm = SwarmBuilder::with_async_std_executor(transport, nc, local_peer_id).build();
+
+    swarm.listen_on(addr).unwrap();
+
+    let mut events = Vec::new();
+    let _ = async_std::future::timeout(duration, async {
+        loop {
+            if let SwarmEvent::Behaviour(event) = swarm.select_next_some().await {
+                events.push(event);
+            }
+        }
+    })
+    .await;
+    let num_conns = swarm.network_info().connection_counters().num_established();
+    (events, num_conns)
+}
+
 async fn create_swarm<P>(
     local_key: identity::Keypair,
     nc: NetworkController<PeersMailbox, PeerManager<PeerRepo>, ProtocolMailbox>,
@@ -1191,6 +1412,8 @@ async fn run_sigma_aggregation_test(
         async_std::task::block_on(aggr_handler_mailbox.clone().send(AggregationAction::Reset {
             new_committee: committee.clone(),
             new_message: md,
+            excluded_members: HashSet::new(),
+            member_weights: HashMap::new(),
             channel: snd,
         }))
         .unwrap();
@@ -1243,21 +1466,27 @@ where
     F: FnOnce(PeersMailbox) -> P,
 {
     let peer_conn_handler_conf = PeerConnHandlerConf {
+        control_msg_buffer_size: msg_buffer_size,
         async_msg_buffer_size: msg_buffer_size,
         sync_msg_buffer_size: msg_buffer_size,
         open_timeout: Duration::from_secs(60),
         initial_keep_alive: Duration::from_secs(60),
+        idle_substream_policy: IdleSubstreamPolicy::KeepOpen,
     };
     let netw_config = NetworkingConfig {
         min_known_peers: 1,
         min_outbound: 1,
         max_inbound: 10,
         max_outbound: 20,
+        peers_snapshot_interval: Duration::from_secs(60),
+        peers_storage: PeerStorage::Memory,
+        ..NetworkingConfig::default()
     };
     let peer_manager_conf = PeerManagerConfig {
         min_acceptable_reputation: Reputation::from(0),
         min_reputation: Reputation::from(0),
-        conn_reset_outbound_backoff: Duration::from_secs(120),
+        dial_retry_policy: RetryPolicy::default(),
+        reputation_decay: ReputationDecayPolicy::default(),
         conn_alloc_interval: Duration::from_secs(30),
         prot_alloc_interval: Duration::from_secs(30),
         protocols_allocation: Vec::new(),
@@ -1271,8 +1500,12 @@ where
             StatefulProtocolSpec {
                 max_message_size: 100,
                 approve_required: true,
+                batching: None,
+                compression: None,
             },
         )],
+        preferred_versions: vec![],
+        priority: ProtocolPriority::NORMAL,
     };
 
     let (requests_snd, requests_recv) = mpsc::channel::<NetworkControllerIn>(10);
@@ -1294,6 +1527,7 @@ where
         peers,
         peer_manager,
         requests_recv,
+        EnableRetryPolicy::default(),
     );
 
     (sync_handler, nc)
@@ -1305,23 +1539,40 @@ pub fn make_nc_without_protocol_handler(
 ) -> (
     NetworkController<PeersMailbox, PeerManager<PeerRepo>, ProtocolMailbox>,
     Sender<NetworkControllerIn>,
+) {
+    make_nc_with_idle_policy(peers, protocols, IdleSubstreamPolicy::KeepOpen)
+}
+
+pub fn make_nc_with_idle_policy(
+    peers: Vec<PeerDestination>,
+    protocols: HashMap<ProtocolId, (ProtocolConfig, ProtocolMailbox)>,
+    idle_substream_policy: IdleSubstreamPolicy,
+) -> (
+    NetworkController<PeersMailbox, PeerManager<PeerRepo>, ProtocolMailbox>,
+    Sender<NetworkControllerIn>,
 ) {
     let peer_conn_handler_conf = PeerConnHandlerConf {
+        control_msg_buffer_size: 100,
         async_msg_buffer_size: 100,
         sync_msg_buffer_size: 100,
         open_timeout: Duration::from_secs(60),
         initial_keep_alive: Duration::from_secs(120),
+        idle_substream_policy,
     };
     let netw_config = NetworkingConfig {
         min_known_peers: 1,
         min_outbound: 1,
         max_inbound: 10,
         max_outbound: 20,
+        peers_snapshot_interval: Duration::from_secs(60),
+        peers_storage: PeerStorage::Memory,
+        ..NetworkingConfig::default()
     };
     let peer_manager_conf = PeerManagerConfig {
         min_acceptable_reputation: Reputation::from(-50),
         min_reputation: Reputation::from(-20),
-        conn_reset_outbound_backoff: Duration::from_secs(120),
+        dial_retry_policy: RetryPolicy::default(),
+        reputation_decay: ReputationDecayPolicy::default(),
         conn_alloc_interval: Duration::from_secs(30),
         prot_alloc_interval: Duration::from_secs(30),
         protocols_allocation: Vec::new(),
@@ -1336,6 +1587,7 @@ pub fn make_nc_without_protocol_handler(
         peers,
         peer_manager,
         requests_recv,
+        EnableRetryPolicy::default(),
     );
     (nc, requests_snd)
 }
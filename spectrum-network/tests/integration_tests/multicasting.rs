@@ -18,12 +18,15 @@ use rand::rngs::OsRng;
 
 use algebra_core::CommutativePartialSemigroup;
 use spectrum_crypto::VerifiableAgainst;
-use spectrum_network::network_controller::{NetworkController, NetworkControllerIn, NetworkMailbox};
-use spectrum_network::peer_conn_handler::PeerConnHandlerConf;
+use spectrum_network::network_controller::{
+    EnableRetryPolicy, NetworkController, NetworkControllerIn, NetworkMailbox,
+};
+use spectrum_network::peer_conn_handler::{IdleSubstreamPolicy, PeerConnHandlerConf};
+use spectrum_network::peer_manager::data::{ReputationDecayPolicy, RetryPolicy};
 use spectrum_network::peer_manager::peers_state::PeerRepo;
-use spectrum_network::peer_manager::{NetworkingConfig, PeerManager, PeerManagerConfig};
+use spectrum_network::peer_manager::{NetworkingConfig, PeerManager, PeerManagerConfig, PeerStorage};
 use spectrum_network::protocol::{
-    OneShotProtocolConfig, OneShotProtocolSpec, ProtocolConfig, SIGMA_AGGR_PROTOCOL_ID,
+    OneShotProtocolConfig, OneShotProtocolSpec, ProtocolConfig, ProtocolPriority, SIGMA_AGGR_PROTOCOL_ID,
 };
 use spectrum_network::protocol_handler::handel::partitioning::{
     BinomialPeerPartitions, MakeBinomialPeerPartitions, MakePeerPartitions, PseudoRandomGenPerm,
@@ -320,23 +323,30 @@ where
                 spec: OneShotProtocolSpec {
                     max_message_size: 5000,
                 },
+                priority: ProtocolPriority::NORMAL,
             };
             let peer_conn_handler_conf = PeerConnHandlerConf {
+                control_msg_buffer_size: 100,
                 async_msg_buffer_size: 100,
                 sync_msg_buffer_size: 100,
                 open_timeout: Duration::from_secs(60),
                 initial_keep_alive: Duration::from_secs(120),
+                idle_substream_policy: IdleSubstreamPolicy::KeepOpen,
             };
             let netw_config = NetworkingConfig {
                 min_known_peers: 1,
                 min_outbound: 1,
                 max_inbound: 10,
                 max_outbound: 20,
+                peers_snapshot_interval: Duration::from_secs(60),
+                peers_storage: PeerStorage::Memory,
+                ..NetworkingConfig::default()
             };
             let peer_manager_conf = PeerManagerConfig {
                 min_acceptable_reputation: Reputation::from(-50),
                 min_reputation: Reputation::from(-20),
-                conn_reset_outbound_backoff: Duration::from_secs(120),
+                dial_retry_policy: RetryPolicy::default(),
+                reputation_decay: ReputationDecayPolicy::default(),
                 conn_alloc_interval: Duration::from_secs(30),
                 prot_alloc_interval: Duration::from_secs(30),
                 protocols_allocation: Vec::new(),
@@ -372,6 +382,7 @@ where
                 peers,
                 peer_manager,
                 requests_recv,
+                EnableRetryPolicy::default(),
             );
             let (abortable_peer, handle) =
                 futures::future::abortable(create_swarm(peer_key.clone(), nc, peer_addr.clone(), node_ix));
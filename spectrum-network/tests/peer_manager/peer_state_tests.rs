@@ -1,9 +1,12 @@
-use libp2p::PeerId;
+use std::time::Duration;
+
+use libp2p::{Multiaddr, PeerId};
 use spectrum_network::peer_manager::{
-    data::PeerDestination,
+    data::{AddressBookEntry, KnownPeer, PeerDestination},
     peers_state::{PeerRepo, PeersState},
-    NetworkingConfig,
+    NetworkingConfig, PeerStorage,
 };
+use spectrum_network::types::Reputation;
 
 #[test]
 fn should_add_peer() {
@@ -45,12 +48,58 @@ fn err_connect_to_peer_when_vacant_connections_not_available() {
     //assert!(peer.connect().is_err());
 }
 
+#[test]
+fn should_list_known_peers_in_address_book() {
+    let mut peer_state = mk_peers_state(4, 2, 10);
+    let reserved = PeerId::random();
+    let addr: Multiaddr = "/ip4/127.0.0.1/tcp/8000".parse().unwrap();
+    peer_state.try_add_peer(
+        PeerDestination::PeerIdWithAddr(reserved, addr.clone()),
+        true,
+        false,
+    );
+    let other = PeerId::random();
+    let _ = peer_state
+        .try_add_peer(PeerDestination::PeerId(other), false, false)
+        .unwrap()
+        .connect();
+
+    let mut book = peer_state.get_address_book();
+    book.sort_by_key(|p| !p.entry.reserved);
+    assert_eq!(
+        book,
+        vec![
+            KnownPeer {
+                entry: AddressBookEntry {
+                    peer_id: reserved,
+                    addr: Some(addr),
+                    reserved: true,
+                },
+                reputation: Reputation::initial(),
+                connected: false,
+            },
+            KnownPeer {
+                entry: AddressBookEntry {
+                    peer_id: other,
+                    addr: None,
+                    reserved: false,
+                },
+                reputation: Reputation::initial(),
+                connected: true,
+            },
+        ]
+    );
+}
+
 fn mk_peers_state(max_inbound: usize, max_outbound: usize, capacity: usize) -> impl PeersState {
     let netw_conf = NetworkingConfig {
         min_known_peers: 2,
         min_outbound: 1,
         max_inbound,
         max_outbound,
+        peers_snapshot_interval: Duration::from_secs(60),
+        peers_storage: PeerStorage::Memory,
+        ..NetworkingConfig::default()
     };
     let boot_peers = vec![
         PeerDestination::PeerId(PeerId::random()),
@@ -22,13 +22,17 @@ use libp2p::{
     yamux, Multiaddr, PeerId, Transport,
 };
 
-use spectrum_network::network_controller::{NetworkController, NetworkControllerIn, NetworkMailbox};
-use spectrum_network::peer_conn_handler::{ConnHandlerIn, PeerConnHandlerConf};
-use spectrum_network::peer_manager::data::PeerDestination;
+use spectrum_network::network_controller::{
+    EnableRetryPolicy, NetworkController, NetworkControllerIn, NetworkMailbox,
+};
+use spectrum_network::peer_conn_handler::{ConnHandlerIn, IdleSubstreamPolicy, PeerConnHandlerConf};
+use spectrum_network::peer_manager::data::{PeerDestination, ReputationDecayPolicy, RetryPolicy};
 use spectrum_network::peer_manager::peers_state::PeerRepo;
-use spectrum_network::peer_manager::{NetworkingConfig, PeerManager, PeerManagerConfig, PeersMailbox};
+use spectrum_network::peer_manager::{
+    NetworkingConfig, PeerManager, PeerManagerConfig, PeerStorage, PeersMailbox,
+};
 use spectrum_network::protocol::{
-    ProtocolConfig, StatefulProtocolConfig, StatefulProtocolSpec, DISCOVERY_PROTOCOL_ID,
+    ProtocolConfig, ProtocolPriority, StatefulProtocolConfig, StatefulProtocolSpec, DISCOVERY_PROTOCOL_ID,
 };
 use spectrum_network::protocol_api::ProtocolMailbox;
 use spectrum_network::protocol_handler::discovery::message::DiscoverySpec;
@@ -126,15 +130,18 @@ pub fn build_node<'de>(
         .boxed();
 
     let peer_conn_handler_conf = PeerConnHandlerConf {
+        control_msg_buffer_size: 10,
         async_msg_buffer_size: 10,
         sync_msg_buffer_size: 40,
         open_timeout: Duration::from_secs(60),
         initial_keep_alive: Duration::from_secs(60),
+        idle_substream_policy: IdleSubstreamPolicy::KeepOpen,
     };
     let peer_manager_conf = PeerManagerConfig {
         min_acceptable_reputation: Reputation::from(0),
         min_reputation: Reputation::from(10),
-        conn_reset_outbound_backoff: Duration::from_secs(120),
+        dial_retry_policy: RetryPolicy::default(),
+        reputation_decay: ReputationDecayPolicy::default(),
         conn_alloc_interval: Duration::from_secs(30),
         protocols_allocation: Vec::new(),
         prot_alloc_interval: Duration::from_secs(30),
@@ -145,6 +152,9 @@ pub fn build_node<'de>(
         min_outbound: 1,
         max_inbound: 25,
         max_outbound: 50,
+        peers_snapshot_interval: Duration::from_secs(60),
+        peers_storage: PeerStorage::Memory,
+        ..NetworkingConfig::default()
     };
     let boot_peers = vec![
         PeerDestination::PeerId(PeerId::random()),
@@ -159,8 +169,12 @@ pub fn build_node<'de>(
             StatefulProtocolSpec {
                 max_message_size: 100,
                 approve_required: true,
+                batching: None,
+                compression: None,
             },
         )],
+        preferred_versions: vec![],
+        priority: ProtocolPriority::NORMAL,
     };
     let sync_behaviour = DiscoveryBehaviour::new(peers.clone(), local_status);
     let (requests_snd, requests_recv) = mpsc::channel::<NetworkControllerIn>(10);
@@ -178,6 +192,7 @@ pub fn build_node<'de>(
         peers,
         peer_manager,
         requests_recv,
+        EnableRetryPolicy::default(),
     );
     let behaviour = CustomProtoWithAddr {
         inner: nc,
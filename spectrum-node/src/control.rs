@@ -0,0 +1,355 @@
+//! Control API of the node. Lets the operator manage peers manually at runtime,
+//! inspect the state of feature flags, the schedule of validator duties and the genesis
+//! the node runs with, and scrape metrics.
+
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{delete, get, put};
+use axum::{Json, Router};
+use futures::channel::oneshot;
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId};
+use log::{error, info};
+
+use spectrum_network::features::{FeatureFlags, FeatureStatus};
+use spectrum_network::metrics::PrometheusMetrics;
+use spectrum_network::peer_manager::data::{AddressBook, AddressBookEntry, KnownPeer, PeerDestination};
+use spectrum_network::peer_manager::{PeerManagerRequest, PeersMailbox};
+
+use crate::duties::{DutyScheduler, ScheduleStatus};
+use crate::genesis::Genesis;
+use crate::supervisor::{Ready, Shutdown};
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct AddPeerRequest {
+    /// Address of the peer, must end with `/p2p/<peer_id>`.
+    pub addr: Multiaddr,
+    #[serde(default)]
+    pub reserved: bool,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct SetReservedRequest {
+    pub peers: Vec<PeerId>,
+}
+
+/// Genesis the node runs with along with the id of the network derived from it.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct GenesisInfo {
+    pub network_id: String,
+    pub genesis: Genesis,
+}
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum ControlError {
+    #[error("Address {0} doesn't end with /p2p/<peer_id>")]
+    NoPeerId(Multiaddr),
+    #[error("Peer manager is not available")]
+    PeerManagerUnavailable,
+}
+
+impl From<ControlError> for (StatusCode, String) {
+    fn from(err: ControlError) -> Self {
+        let status = match err {
+            ControlError::NoPeerId(_) => StatusCode::BAD_REQUEST,
+            ControlError::PeerManagerUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        (status, err.to_string())
+    }
+}
+
+/// Split `/ip4/../tcp/../p2p/<peer_id>` into the peer id and the address to dial it at.
+fn parse_peer_addr(mut addr: Multiaddr) -> Result<PeerDestination, ControlError> {
+    match addr.pop() {
+        Some(Protocol::P2p(peer_id)) if addr.is_empty() => Ok(PeerDestination::PeerId(peer_id)),
+        Some(Protocol::P2p(peer_id)) => Ok(PeerDestination::PeerIdWithAddr(peer_id, addr)),
+        Some(other) => {
+            addr.push(other);
+            Err(ControlError::NoPeerId(addr))
+        }
+        None => Err(ControlError::NoPeerId(addr)),
+    }
+}
+
+/// Requests are awaited rather than submitted via [`Peers`](spectrum_network::peer_manager::Peers), which blocks the runtime worker
+/// while the mailbox of the peer manager is full.
+async fn request(peers: &PeersMailbox, request: PeerManagerRequest) -> Result<(), ControlError> {
+    peers
+        .request(request)
+        .await
+        .map_err(|_| ControlError::PeerManagerUnavailable)
+}
+
+async fn add_peer(
+    State(peers): State<PeersMailbox>,
+    Json(req): Json<AddPeerRequest>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let destination = parse_peer_addr(req.addr)?;
+    if req.reserved {
+        request(&peers, PeerManagerRequest::AddReservedPeer(destination)).await?;
+    } else {
+        request(&peers, PeerManagerRequest::AddPeers(vec![destination])).await?;
+    }
+    Ok(StatusCode::OK)
+}
+
+async fn remove_peer(
+    State(peers): State<PeersMailbox>,
+    Path(peer_id): Path<PeerId>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    request(&peers, PeerManagerRequest::RemovePeer(peer_id)).await?;
+    Ok(StatusCode::OK)
+}
+
+async fn set_reserved(
+    State(peers): State<PeersMailbox>,
+    Json(req): Json<SetReservedRequest>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    request(
+        &peers,
+        PeerManagerRequest::SetReservedPeers(HashSet::from_iter(req.peers)),
+    )
+    .await?;
+    Ok(StatusCode::OK)
+}
+
+async fn list_address_book(
+    State(peers): State<PeersMailbox>,
+) -> Result<Json<Vec<KnownPeer>>, (StatusCode, String)> {
+    let (snd, recv) = oneshot::channel();
+    request(&peers, PeerManagerRequest::GetAddressBook(snd)).await?;
+    let known_peers = recv.await.map_err(|_| ControlError::PeerManagerUnavailable)?;
+    Ok(Json(known_peers))
+}
+
+async fn export_address_book(
+    State(peers): State<PeersMailbox>,
+) -> Result<Json<AddressBook>, (StatusCode, String)> {
+    let Json(known_peers) = list_address_book(State(peers)).await?;
+    Ok(Json(AddressBook {
+        peers: known_peers.into_iter().map(|p| p.entry).collect(),
+    }))
+}
+
+async fn import_address_book(
+    State(peers): State<PeersMailbox>,
+    Json(book): Json<AddressBook>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let (reserved, other): (Vec<AddressBookEntry>, Vec<AddressBookEntry>) =
+        book.peers.into_iter().partition(|e| e.reserved);
+    for entry in reserved {
+        request(&peers, PeerManagerRequest::AddReservedPeer(entry.destination())).await?;
+    }
+    let other = other.iter().map(AddressBookEntry::destination).collect();
+    request(&peers, PeerManagerRequest::AddPeers(other)).await?;
+    Ok(StatusCode::OK)
+}
+
+async fn list_features(State(features): State<FeatureFlags>) -> Json<Vec<FeatureStatus>> {
+    Json(features.status())
+}
+
+async fn render_metrics(State(metrics): State<PrometheusMetrics>) -> String {
+    metrics.render()
+}
+
+async fn show_duties(State(duties): State<DutyScheduler>) -> Json<ScheduleStatus> {
+    Json(duties.status())
+}
+
+async fn show_genesis(State(genesis): State<Arc<GenesisInfo>>) -> Json<GenesisInfo> {
+    Json(GenesisInfo::clone(&genesis))
+}
+
+fn router(
+    peers: PeersMailbox,
+    features: FeatureFlags,
+    metrics: PrometheusMetrics,
+    duties: DutyScheduler,
+    genesis: Arc<GenesisInfo>,
+) -> Router {
+    let features_router = Router::new()
+        .route("/features", get(list_features))
+        .with_state(features);
+    let duties_router = Router::new()
+        .route("/duties", get(show_duties))
+        .with_state(duties);
+    let genesis_router = Router::new()
+        .route("/genesis", get(show_genesis))
+        .with_state(genesis);
+    let metrics_router = Router::new()
+        .route("/metrics", get(render_metrics))
+        .with_state(metrics);
+    Router::new()
+        .route("/peers", get(list_address_book).post(add_peer))
+        .route("/peers/reserved", put(set_reserved))
+        .route("/peers/:peer_id", delete(remove_peer))
+        .route(
+            "/address_book",
+            get(export_address_book).post(import_address_book),
+        )
+        .with_state(peers)
+        .merge(features_router)
+        .merge(metrics_router)
+        .merge(duties_router)
+        .merge(genesis_router)
+}
+
+/// Serve the control API until the node is shut down.
+pub async fn serve(
+    addr: SocketAddr,
+    peers: PeersMailbox,
+    features: FeatureFlags,
+    metrics: PrometheusMetrics,
+    duties: DutyScheduler,
+    genesis: GenesisInfo,
+    ready: Ready,
+    shutdown: Shutdown,
+) {
+    match axum::Server::try_bind(&addr) {
+        Ok(server) => {
+            info!("[Control] API is listening on {}", addr);
+            ready.notify();
+            let res = server
+                .serve(router(peers, features, metrics, duties, Arc::new(genesis)).into_make_service())
+                .with_graceful_shutdown(shutdown)
+                .await;
+            if let Err(err) = res {
+                error!("[Control] API terminated: {}", err);
+            }
+        }
+        Err(err) => error!("[Control] Cannot bind {}: {}", addr, err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::extract::{Path, State};
+    use axum::http::StatusCode;
+    use axum::Json;
+    use futures::StreamExt;
+    use libp2p::{Multiaddr, PeerId};
+
+    use spectrum_network::peer_manager::data::{
+        AddressBook, AddressBookEntry, PeerDestination, ReputationDecayPolicy, RetryPolicy,
+    };
+    use spectrum_network::peer_manager::peers_state::PeerRepo;
+    use spectrum_network::peer_manager::{
+        NetworkingConfig, PeerManager, PeerManagerConfig, PeerStorage, PeersMailbox,
+    };
+    use spectrum_network::types::Reputation;
+
+    use crate::control::{
+        add_peer, export_address_book, import_address_book, parse_peer_addr, remove_peer, AddPeerRequest,
+        ControlError,
+    };
+
+    fn spawn_peer_manager() -> PeersMailbox {
+        let netw_conf = NetworkingConfig {
+            min_known_peers: 0,
+            min_outbound: 0,
+            max_inbound: 10,
+            max_outbound: 0,
+            peers_snapshot_interval: Duration::from_secs(60),
+            peers_storage: PeerStorage::Memory,
+            ..NetworkingConfig::default()
+        };
+        let conf = PeerManagerConfig {
+            min_acceptable_reputation: Reputation::from(0),
+            min_reputation: Reputation::from(0),
+            dial_retry_policy: RetryPolicy::default(),
+            reputation_decay: ReputationDecayPolicy::default(),
+            conn_alloc_interval: Duration::from_secs(30),
+            prot_alloc_interval: Duration::from_secs(30),
+            protocols_allocation: Vec::new(),
+            peer_manager_msg_buffer_size: 10,
+        };
+        let (pm, peers) = PeerManager::new(PeerRepo::new(netw_conf, vec![]), conf);
+        async_std::task::spawn(pm.for_each(|_| futures::future::ready(())));
+        peers
+    }
+
+    #[test]
+    fn peer_id_is_extracted_from_multiaddr() {
+        let peer_id = PeerId::random();
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/8000".parse().unwrap();
+        assert_eq!(
+            parse_peer_addr(addr.clone().with(libp2p::multiaddr::Protocol::P2p(peer_id))),
+            Ok(PeerDestination::PeerIdWithAddr(peer_id, addr.clone()))
+        );
+        assert_eq!(parse_peer_addr(addr.clone()), Err(ControlError::NoPeerId(addr)));
+    }
+
+    #[async_std::test]
+    async fn address_book_round_trip() {
+        let peers = spawn_peer_manager();
+        let book = AddressBook {
+            peers: vec![
+                AddressBookEntry {
+                    peer_id: PeerId::random(),
+                    addr: Some("/ip4/127.0.0.1/tcp/8000".parse().unwrap()),
+                    reserved: true,
+                },
+                AddressBookEntry {
+                    peer_id: PeerId::random(),
+                    addr: None,
+                    reserved: false,
+                },
+            ],
+        };
+        import_address_book(State(peers.clone()), Json(book.clone()))
+            .await
+            .unwrap();
+        let Json(mut exported) = export_address_book(State(peers)).await.unwrap();
+        exported.peers.sort_by_key(|e| !e.reserved);
+        assert_eq!(exported, book);
+    }
+
+    #[async_std::test]
+    async fn add_and_remove_peer() {
+        let peers = spawn_peer_manager();
+        let peer_id = PeerId::random();
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/8000".parse().unwrap();
+        add_peer(
+            State(peers.clone()),
+            Json(AddPeerRequest {
+                addr: addr.clone().with(libp2p::multiaddr::Protocol::P2p(peer_id)),
+                reserved: false,
+            }),
+        )
+        .await
+        .unwrap();
+        let Json(exported) = export_address_book(State(peers.clone())).await.unwrap();
+        assert_eq!(
+            exported.peers,
+            vec![AddressBookEntry {
+                peer_id,
+                addr: Some(addr),
+                reserved: false,
+            }]
+        );
+        remove_peer(State(peers.clone()), Path(peer_id)).await.unwrap();
+        let Json(exported) = export_address_book(State(peers)).await.unwrap();
+        assert!(exported.peers.is_empty());
+    }
+
+    #[async_std::test]
+    async fn unavailable_peer_manager_reported() {
+        let (pm, peers) = PeerManager::new(
+            PeerRepo::new(NetworkingConfig::default(), vec![]),
+            PeerManagerConfig::default(),
+        );
+        drop(pm);
+        let res = remove_peer(State(peers), Path(PeerId::random())).await;
+        assert_eq!(
+            res.map_err(|(status, _)| status),
+            Err(StatusCode::SERVICE_UNAVAILABLE)
+        );
+    }
+}
@@ -0,0 +1,87 @@
+//! Deterministic execution mode, meant for reproducing consensus divergence.
+//!
+//! In this mode spawned tasks run on a single worker thread of each executor, so state machines
+//! are driven in the same order given the same inputs, and everything the node would otherwise
+//! draw from the OS, such as its identity, is derived from the seed given by the operator.
+//! Two nodes started with the same seed and replaying the same inputs end up in the same state.
+//!
+//! Never run a production node in this mode: the identity of the node and secrets of components
+//! seeded from it are trivially recoverable by anyone knowing the seed.
+
+use std::io;
+
+use libp2p::identity;
+
+use spectrum_crypto::digest::blake2b256_hash;
+
+/// Enables the mode, e.g. `--deterministic=42`.
+const DETERMINISTIC_FLAG: &str = "--deterministic=";
+/// Number of worker threads of the async-std executor, read once the executor is started.
+const ASYNC_STD_THREAD_COUNT: &str = "ASYNC_STD_THREAD_COUNT";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Determinism {
+    seed: u64,
+}
+
+impl Determinism {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    /// Parse the mode from command line arguments, if requested.
+    pub fn from_args() -> Result<Option<Self>, std::num::ParseIntError> {
+        std::env::args()
+            .find_map(|arg| arg.strip_prefix(DETERMINISTIC_FLAG).map(str::to_owned))
+            .map(|seed| seed.parse().map(Self::new))
+            .transpose()
+    }
+
+    /// Pin the async-std executor to a single worker thread.
+    /// Has no effect once the executor is started, so must be called before anything is spawned.
+    pub fn pin_executor(&self) {
+        std::env::set_var(ASYNC_STD_THREAD_COUNT, "1");
+    }
+
+    /// Seed of the given component. Every component draws from its own stream,
+    /// so that extra draws by one of them don't affect the others.
+    pub fn seed_of(&self, component: &str) -> [u8; 32] {
+        let mut bf = self.seed.to_be_bytes().to_vec();
+        bf.extend_from_slice(component.as_bytes());
+        *blake2b256_hash(&bf).raw()
+    }
+
+    pub fn identity(&self) -> identity::Keypair {
+        identity::Keypair::ed25519_from_bytes(self.seed_of("identity")).unwrap()
+    }
+}
+
+/// Tokio runtime with a single worker thread in deterministic mode.
+pub fn tokio_runtime(determinism: Option<Determinism>) -> io::Result<tokio::runtime::Runtime> {
+    match determinism {
+        Some(_) => tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build(),
+        None => tokio::runtime::Runtime::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libp2p::PeerId;
+
+    use crate::determinism::Determinism;
+
+    #[test]
+    fn same_seed_same_node() {
+        let a = Determinism::new(42);
+        let b = Determinism::new(42);
+        assert_eq!(
+            PeerId::from(a.identity().public()),
+            PeerId::from(b.identity().public())
+        );
+        assert_ne!(a.seed_of("faucet"), a.seed_of("identity"));
+        assert_ne!(a.seed_of("identity"), Determinism::new(43).seed_of("identity"));
+    }
+}
@@ -0,0 +1,971 @@
+//! Development mode: a single validator produces blocks on a dev leader schedule and submits them
+//! to the regular NodeView, which validates them against the ledger stores. Test value is minted
+//! by the faucet and external chains are emulated by mock connectors.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures::channel::{mpsc, oneshot};
+use futures::future::{self, Future};
+use futures::{FutureExt, StreamExt};
+use k256::{Secp256k1, SecretKey};
+use log::{error, info, warn};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use spectrum_consensus::leader::LeaderEligibility;
+use spectrum_consensus::protocol_params::ProtocolParams;
+use spectrum_crypto::digest::{blake2b256_hash, Blake2b256, Blake2bDigest256};
+use spectrum_crypto::pubkey::PublicKey;
+use spectrum_kes::{kes_gen, kes_sign, KESSecret};
+use spectrum_ledger::block::{BlockBody, BlockHeader, BlockId, HeaderBody, ProtocolVer};
+use spectrum_ledger::cell::{
+    ActiveCell, AnyCell, CellMeta, CellPtr, DatumRef, NativeCoin, Owner, SValue, ScriptRef, Serial,
+};
+use spectrum_ledger::interop::{Effect, Point};
+use spectrum_ledger::transaction::{EvaluatedTransaction, TxId, ValidTx};
+use spectrum_ledger::{
+    BlockNo, ChainId, DomainVKey, EpochNo, KESSignature, KESVKey, Modifier, ModifierId, SlotNo, StakePoolId,
+    SystemDigest, VRFProof, VRFVKey,
+};
+use spectrum_move::{SerializedModule, SerializedValue};
+use spectrum_network::features::{ChainProgress, FeatureFlags, FeatureFlagsConf, FeatureStatus};
+use spectrum_validation::rules::{
+    ConsensusRuleSet, NonTermRuleId, NonTermRuleSpec, TermRuleId, TermRuleSpec,
+};
+use spectrum_validation::validation::InvalidModifier;
+use spectrum_view::history::bodies::{FsColdStore, RetentionConfig, TieredBodyStore};
+use spectrum_view::history::{
+    LedgerHistoryReadAsync, LedgerHistoryReadSync, LedgerHistoryRocksDB, LedgerHistoryWrite,
+};
+use spectrum_view::mempool::{Mempool, PackageError};
+use spectrum_view::node_view::{ModifierSource, NodeViewWriteAsync};
+use spectrum_view::snapshot::StateSnapshot;
+use spectrum_view::state::store::LedgerStateRocksDB;
+use spectrum_view::state::{
+    Cells, ConsensusIndexes, LedgerStateError, LedgerStateWrite, StakeDistribution, ValidatorCredentials,
+};
+use spectrum_vrf::{vrf_prove, vrf_verify, ECVRFProof};
+
+use crate::node_view::{NodeView, NodeViewMailbox, ValidationResultsHandler};
+use crate::supervisor::{Ready, Shutdown, Stage, Supervisor};
+use crate::SUBSYSTEM_READINESS_TIMEOUT;
+
+/// Message the randomness proof of every epoch of the dev chain is derived from.
+const DEV_EPOCH_SEED: &[u8] = b"spectrum-dev-epoch";
+/// Stake delegated to the dev validator, which is all the stake there is.
+const DEV_STAKE: u64 = 1;
+const DEV_KEEP_VERSIONS: u64 = 16;
+const NODE_VIEW_BUFFER_SIZE: usize = 16;
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct DevConfig {
+    /// Interval between blocks produced by the node.
+    pub block_interval_millis: u64,
+    /// Address the faucet API is served at.
+    pub faucet_addr: SocketAddr,
+    /// Max amount of native coin a single faucet request can mint.
+    pub faucet_limit: u64,
+    /// Directory of the ledger stores. Wiped on start, the dev chain always begins at the origin.
+    #[serde(default = "default_chain_dir")]
+    pub chain_dir: PathBuf,
+    /// External chains emulated by mock connectors.
+    #[serde(default)]
+    pub mock_chains: Vec<MockChainConfig>,
+    /// Activations of features, they are driven by blocks of the dev chain.
+    #[serde(default)]
+    pub features: FeatureFlagsConf,
+}
+
+fn default_chain_dir() -> PathBuf {
+    PathBuf::from("./data/dev-chain")
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct MockChainConfig {
+    pub chain_id: ChainId,
+    /// Number of points the chain advances by each Spectrum block.
+    pub points_per_block: u64,
+}
+
+/// Pseudo-chain native coin minted by the faucet is imported from.
+fn faucet_chain_id() -> ChainId {
+    ChainId::from(u16::MAX)
+}
+
+/// Message the VRF proof of a header is computed over.
+fn leadership_seed(epoch_rand_proof: &VRFProof, slot: SlotNo) -> Blake2bDigest256 {
+    let mut encoded = Vec::new();
+    ciborium::ser::into_writer(&(epoch_rand_proof, slot), &mut encoded).unwrap();
+    blake2b256_hash(&encoded)
+}
+
+/// Keys of the only validator of the dev chain.
+pub struct DevValidator {
+    vrf_sk: SecretKey,
+    /// Single-period KES key, KES signatures aren't checked by header validation yet.
+    kes_sk: KESSecret<Blake2b256, Secp256k1>,
+    kes_vk: KESVKey,
+    epoch_rand_proof: VRFProof,
+}
+
+impl DevValidator {
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        let vrf_sk = SecretKey::random(&mut StdRng::from_seed(seed));
+        let (kes_sk, kes_pk) = kes_gen::<Blake2b256, Secp256k1>(&0, &blake2b256_hash(&seed)).unwrap();
+        let epoch_rand_proof = VRFProof::from(
+            vrf_prove::<Blake2b256, Secp256k1>(vrf_sk.clone(), blake2b256_hash(DEV_EPOCH_SEED)).unwrap(),
+        );
+        Self {
+            vrf_sk,
+            kes_sk,
+            kes_vk: KESVKey::from(PublicKey::from(kes_pk)),
+            epoch_rand_proof,
+        }
+    }
+
+    pub fn vrf_vk(&self) -> VRFVKey {
+        VRFVKey::from(PublicKey::from(self.vrf_sk.clone()))
+    }
+
+    /// Header of a block produced by the validator in the given slot.
+    pub fn forge(
+        &self,
+        prev_id: BlockId,
+        block_num: BlockNo,
+        slot_num: SlotNo,
+        block_body_root: Blake2bDigest256,
+    ) -> BlockHeader {
+        let vrf_proof = vrf_prove::<Blake2b256, Secp256k1>(
+            self.vrf_sk.clone(),
+            leadership_seed(&self.epoch_rand_proof, slot_num),
+        )
+        .unwrap();
+        let body = HeaderBody {
+            prev_id,
+            block_num,
+            slot_num,
+            vrf_vk: self.vrf_vk(),
+            vrf_proof: VRFProof::from(vrf_proof),
+            block_body_root,
+            protocol_version: ProtocolVer::INITIAL,
+        };
+        let body_signature = KESSignature::from(kes_sign(&body.digest(), &self.kes_sk, &0).unwrap());
+        BlockHeader { body, body_signature }
+    }
+}
+
+/// Every slot of the dev chain is led by the dev validator.
+#[derive(Clone)]
+pub struct DevLeaderSchedule {
+    leader: VRFVKey,
+}
+
+impl DevLeaderSchedule {
+    pub fn new(leader: VRFVKey) -> Self {
+        Self { leader }
+    }
+}
+
+impl LeaderEligibility for DevLeaderSchedule {
+    fn verify_vrf(&self, hdr: &HeaderBody, epoch_rand_proof: &VRFProof) -> bool {
+        vrf_verify::<Blake2b256, Secp256k1>(
+            k256::PublicKey::from(PublicKey::from(hdr.vrf_vk)),
+            leadership_seed(epoch_rand_proof, hdr.slot_num),
+            ECVRFProof::from(hdr.vrf_proof.clone()),
+        )
+        .unwrap_or(false)
+    }
+
+    fn is_slot_leader(&self, hdr: &HeaderBody, _stake: NativeCoin, _total_stake: NativeCoin) -> bool {
+        hdr.vrf_vk == self.leader
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct DevProtocolParams;
+
+impl ProtocolParams for DevProtocolParams {
+    /// The dev validator produces a block every slot.
+    fn fk(&self) -> u64 {
+        1
+    }
+
+    fn base_vrf_range(&self) -> u32 {
+        64
+    }
+
+    /// The only validator holds all the stake, so it is always a member of the committee.
+    fn consensus_selection_frac(&self) -> (u32, u32) {
+        (1, 1)
+    }
+}
+
+/// Every rule is enforced, violations are fatal.
+#[derive(Copy, Clone)]
+pub struct DevRules;
+
+impl ConsensusRuleSet for DevRules {
+    fn get_rule(&self, _rule_id: NonTermRuleId) -> NonTermRuleSpec {
+        NonTermRuleSpec {
+            active: true,
+            description: "Enforced in dev mode",
+        }
+    }
+
+    fn get_term_rule(&self, _rule_id: TermRuleId) -> TermRuleSpec {
+        TermRuleSpec {
+            fatal: true,
+            description: "Enforced in dev mode",
+        }
+    }
+}
+
+/// Ledger state of the dev chain. All stake is delegated to the dev validator.
+pub struct DevState {
+    store: LedgerStateRocksDB,
+    pool_id: StakePoolId,
+    kes_vk: KESVKey,
+    epoch_rand_proof: VRFProof,
+}
+
+impl DevState {
+    pub fn new(store: LedgerStateRocksDB, validator: &DevValidator) -> Self {
+        Self {
+            store,
+            pool_id: StakePoolId::from(validator.vrf_vk()),
+            kes_vk: validator.kes_vk,
+            epoch_rand_proof: validator.epoch_rand_proof.clone(),
+        }
+    }
+}
+
+impl Cells for DevState {
+    fn get_cell(&self, ptr: CellPtr) -> Option<CellMeta<AnyCell>> {
+        self.store.get_cell(ptr)
+    }
+
+    fn progress_of(&self, chain_id: ChainId) -> Point {
+        self.store.progress_of(chain_id)
+    }
+
+    fn get_ref_script(&self, script_ref: ScriptRef) -> Option<SerializedModule> {
+        self.store.get_ref_script(script_ref)
+    }
+
+    fn get_ref_datum(&self, datum_ref: DatumRef) -> Option<SerializedValue> {
+        self.store.get_ref_datum(datum_ref)
+    }
+}
+
+impl LedgerStateWrite for DevState {
+    fn apply_tx(&self, tx: ValidTx<EvaluatedTransaction>) -> Result<(), LedgerStateError> {
+        self.store.apply_tx(tx)
+    }
+
+    fn apply_eff(&self, chain_id: ChainId, eff: ValidTx<Effect>) -> Result<(), LedgerStateError> {
+        self.store.apply_eff(chain_id, eff)
+    }
+
+    fn commit(&self, tag: Blake2bDigest256) {
+        self.store.commit(tag)
+    }
+
+    fn rollback(&self, tag: Blake2bDigest256) -> Result<(), LedgerStateError> {
+        self.store.rollback(tag)
+    }
+
+    fn install_snapshot(&self, tag: Blake2bDigest256, snapshot: StateSnapshot) {
+        self.store.install_snapshot(tag, snapshot)
+    }
+}
+
+impl ConsensusIndexes for DevState {
+    fn get_epoch_rand_proof(&self, _epoch: EpochNo) -> Option<VRFProof> {
+        Some(self.epoch_rand_proof.clone())
+    }
+}
+
+impl StakeDistribution for DevState {
+    fn get_stake(&self, pool_id: StakePoolId) -> NativeCoin {
+        NativeCoin::from(if pool_id == self.pool_id { DEV_STAKE } else { 0 })
+    }
+
+    fn get_total_stake(&self) -> NativeCoin {
+        NativeCoin::from(DEV_STAKE)
+    }
+}
+
+impl ValidatorCredentials for DevState {
+    fn get_pool_creds(&self, pool_id: StakePoolId) -> Option<(KESVKey, Vec<(ChainId, DomainVKey)>)> {
+        (pool_id == self.pool_id).then(|| (self.kes_vk, Vec::new()))
+    }
+}
+
+/// External chain emulated locally. Advances steadily and reports queued deposits
+/// the same way a real connector reports observed inbound value.
+#[derive(Clone, Debug)]
+pub struct MockChain {
+    conf: MockChainConfig,
+    point: u64,
+    deposits: Vec<AnyCell>,
+}
+
+impl MockChain {
+    pub fn new(conf: MockChainConfig) -> Self {
+        Self {
+            conf,
+            point: 0,
+            deposits: Vec::new(),
+        }
+    }
+
+    /// Queue value to be imported into Spectrum with the next block.
+    pub fn deposit(&mut self, cell: AnyCell) {
+        self.deposits.push(cell);
+    }
+
+    fn advance(&mut self) -> Vec<Effect> {
+        self.point += self.conf.points_per_block;
+        self.deposits
+            .drain(..)
+            .map(Effect::Imported)
+            .chain([Effect::Progressed(Point::from(self.point))])
+            .collect()
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, thiserror::Error)]
+pub enum FaucetError {
+    #[error("Amount must be positive")]
+    ZeroAmount,
+    #[error("Requested {0}, at most {1} can be minted at once")]
+    AmountTooLarge(u64, u64),
+    #[error("Invalid owner key")]
+    InvalidOwner,
+    #[error("Chain {0:?} is not mocked")]
+    UnknownChain(ChainId),
+}
+
+/// Mints test value, either directly or as deposits on mock chains.
+pub struct Faucet {
+    pending_mints: Vec<AnyCell>,
+    mock_chains: HashMap<ChainId, MockChain>,
+    limit: u64,
+    nonce: u64,
+}
+
+impl Faucet {
+    pub fn new(conf: &DevConfig) -> Self {
+        Self {
+            pending_mints: Vec::new(),
+            mock_chains: conf
+                .mock_chains
+                .iter()
+                .map(|c| (c.chain_id, MockChain::new(c.clone())))
+                .collect(),
+            limit: conf.faucet_limit,
+            nonce: 0,
+        }
+    }
+
+    /// Mint a cell holding the given amount of native coin to the owner.
+    /// When `from_chain` is given the value arrives as a deposit on the mock chain instead.
+    /// The cell becomes available once the next block is applied.
+    pub fn mint(
+        &mut self,
+        owner: Owner,
+        amount: u64,
+        from_chain: Option<ChainId>,
+    ) -> Result<AnyCell, FaucetError> {
+        if amount == 0 {
+            return Err(FaucetError::ZeroAmount);
+        }
+        if amount > self.limit {
+            return Err(FaucetError::AmountTooLarge(amount, self.limit));
+        }
+        let mut encoded = Vec::new();
+        ciborium::ser::into_writer(&(self.nonce, owner, amount), &mut encoded).unwrap();
+        let cell = AnyCell::Mut(ActiveCell {
+            value: SValue {
+                native: NativeCoin::from(amount),
+                assets: HashMap::new(),
+            },
+            owner,
+            datum: None,
+            reference_script: None,
+            reference_datum: None,
+            tx_id: TxId::from(blake2b256_hash(&encoded)),
+            index: 0,
+            ver: Serial::INITIAL,
+        });
+        match from_chain {
+            Some(chain_id) => self
+                .mock_chains
+                .get_mut(&chain_id)
+                .ok_or(FaucetError::UnknownChain(chain_id))?
+                .deposit(cell.clone()),
+            None => self.pending_mints.push(cell.clone()),
+        }
+        self.nonce += 1;
+        Ok(cell)
+    }
+
+    /// Effects observed since the previous block, in the order of chain ids.
+    pub fn advance(&mut self) -> Vec<(ChainId, Effect)> {
+        let mut chain_ids = self.mock_chains.keys().copied().collect::<Vec<_>>();
+        chain_ids.sort();
+        let minted = self
+            .pending_mints
+            .drain(..)
+            .map(|cell| (faucet_chain_id(), Effect::Imported(cell)))
+            .collect::<Vec<_>>();
+        chain_ids
+            .into_iter()
+            .flat_map(|chain_id| {
+                self.mock_chains
+                    .get_mut(&chain_id)
+                    .unwrap()
+                    .advance()
+                    .into_iter()
+                    .map(move |eff| (chain_id, eff))
+            })
+            .chain(minted)
+            .collect()
+    }
+}
+
+/// Block submitted to the NodeView, but not applied yet.
+struct PendingBlock {
+    block_id: BlockId,
+    block_num: BlockNo,
+    slot_num: SlotNo,
+    effects: Vec<(ChainId, Effect)>,
+}
+
+type PendingBlocks = Arc<Mutex<HashMap<ModifierId, PendingBlock>>>;
+
+/// Bodies can't carry effects of mock chains, as reports need certificates of a committee.
+/// Instead, effects of a block are applied to the ledger state once the NodeView accepts its header.
+#[derive(Clone)]
+pub struct DevResults {
+    pending: PendingBlocks,
+    state: Arc<LedgerStateRocksDB>,
+    features: FeatureFlags,
+}
+
+impl ValidationResultsHandler for DevResults {
+    fn on_applied_modifier(&self, modifier_id: ModifierId, _source: ModifierSource) {
+        let Some(blk) = self.pending.lock().unwrap().remove(&modifier_id) else {
+            return;
+        };
+        for (chain_id, eff) in &blk.effects {
+            if let Err(err) = self.state.apply_effect(*chain_id, eff) {
+                warn!(
+                    "[Dev] Effect {:?} of chain {:?} not applied: {:?}",
+                    eff, chain_id, err
+                );
+            }
+        }
+        self.state.commit(Blake2bDigest256::from(blk.block_id));
+        info!(
+            "[Dev] Applied block #{:?} at slot {}, {} effects",
+            blk.block_num,
+            blk.slot_num,
+            blk.effects.len()
+        );
+        self.features.on_chain_progress(ChainProgress {
+            height: u64::from(blk.block_num),
+            epoch: u64::from(blk.slot_num.epoch_num()),
+        });
+    }
+
+    fn on_accepted_package(&self, pkg_id: ModifierId, _source: ModifierSource) {
+        info!("[Dev] Accepted package {:?}", pkg_id);
+    }
+
+    fn on_invalid_modifier(&self, err: InvalidModifier, _source: ModifierSource) {
+        self.pending.lock().unwrap().remove(&err.modifier_id);
+        error!("[Dev] Produced an invalid modifier: {:?}", err);
+    }
+
+    fn on_rejected_package(&self, err: PackageError, _source: ModifierSource) {
+        warn!("[Dev] Rejected package: {:?}", err);
+    }
+}
+
+pub type DevNodeView = NodeView<
+    DevState,
+    LedgerHistoryRocksDB,
+    Mempool,
+    DevResults,
+    DevRules,
+    DevProtocolParams,
+    DevLeaderSchedule,
+>;
+
+/// Ledger stores of the dev chain, state and history share a single database.
+#[derive(Clone)]
+pub struct DevStores {
+    db: Arc<rocksdb::OptimisticTransactionDB>,
+    cold_dir: PathBuf,
+}
+
+impl DevStores {
+    /// Open stores of a new dev chain, wiping the one left by the previous run.
+    pub fn open_fresh(chain_dir: &Path) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if chain_dir.exists() {
+            std::fs::remove_dir_all(chain_dir)?;
+        }
+        Ok(Self::open(chain_dir)?)
+    }
+
+    pub fn open(chain_dir: &Path) -> Result<Self, rocksdb::Error> {
+        Ok(Self {
+            db: Arc::new(rocksdb::OptimisticTransactionDB::open_default(
+                chain_dir.join("ledger"),
+            )?),
+            cold_dir: chain_dir.join("cold"),
+        })
+    }
+
+    pub fn state(&self) -> LedgerStateRocksDB {
+        LedgerStateRocksDB {
+            db: self.db.clone(),
+            keep_versions: DEV_KEEP_VERSIONS,
+        }
+    }
+
+    pub fn history(&self) -> LedgerHistoryRocksDB {
+        LedgerHistoryRocksDB {
+            db: self.db.clone(),
+            bodies: TieredBodyStore {
+                db: self.db.clone(),
+                cold: Arc::new(FsColdStore {
+                    root: self.cold_dir.clone(),
+                }),
+                retention: RetentionConfig {
+                    hot_slots: u64::MAX,
+                    migration_batch_size: 0,
+                    migration_interval: Duration::MAX,
+                },
+            },
+        }
+    }
+}
+
+/// Produces blocks on top of the best chain of the NodeView.
+pub struct DevProducer {
+    validator: DevValidator,
+    history: LedgerHistoryRocksDB,
+    faucet: Arc<Mutex<Faucet>>,
+    pending: PendingBlocks,
+    mailbox: NodeViewMailbox,
+}
+
+impl DevProducer {
+    pub async fn produce_block(&mut self) -> BlockHeader {
+        let parent = self.history.get_tip().await.modifier.body;
+        let body = BlockBody {
+            reports: Vec::new(),
+            certificates: Vec::new(),
+            txs: Vec::new(),
+            witnesses: Vec::new(),
+        };
+        let hdr = self.validator.forge(
+            BlockId::from(parent.digest()),
+            parent.block_num + BlockNo::from(1),
+            parent.slot_num + SlotNo::UNIT,
+            body.digest(),
+        );
+        let block_id = BlockId::from(hdr.body.digest());
+        let effects = self.faucet.lock().unwrap().advance();
+        self.pending.lock().unwrap().insert(
+            ModifierId::from(block_id),
+            PendingBlock {
+                block_id,
+                block_num: hdr.body.block_num,
+                slot_num: hdr.body.slot_num,
+                effects,
+            },
+        );
+        self.mailbox
+            .apply_modifier(Modifier::BlockHeader(hdr.clone()), ModifierSource::Local)
+            .await;
+        self.mailbox
+            .apply_modifier(Modifier::BlockBody(body), ModifierSource::Local)
+            .await;
+        hdr
+    }
+}
+
+/// Assemble the NodeView of the dev chain on top of the given stores, along with the producer
+/// feeding it. History is started from a dev origin block unless it already has one.
+pub fn launch(
+    stores: &DevStores,
+    validator: DevValidator,
+    faucet: Arc<Mutex<Faucet>>,
+    features: FeatureFlags,
+) -> (DevNodeView, DevProducer) {
+    let history = stores.history();
+    if history.get_header_at(SlotNo::ORIGIN).is_none() {
+        let origin = validator.forge(
+            BlockId::ORIGIN,
+            BlockNo::ORIGIN,
+            SlotNo::ORIGIN,
+            Blake2bDigest256::zero(),
+        );
+        history.install_checkpoint(origin);
+    }
+    let (snd, recv) = mpsc::channel(NODE_VIEW_BUFFER_SIZE);
+    let pending = PendingBlocks::default();
+    let view = NodeView::new(
+        DevState::new(stores.state(), &validator),
+        stores.history(),
+        Mempool::new(),
+        DevResults {
+            pending: pending.clone(),
+            state: Arc::new(stores.state()),
+            features,
+        },
+        DevRules,
+        DevProtocolParams,
+        DevLeaderSchedule::new(validator.vrf_vk()),
+        recv,
+    );
+    let producer = DevProducer {
+        validator,
+        history,
+        faucet,
+        pending,
+        mailbox: NodeViewMailbox::new(snd),
+    };
+    (view, producer)
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct FaucetRequest {
+    /// Hex-encoded SEC1 public key of the owner.
+    pub owner: String,
+    pub amount: u64,
+    /// Deliver value as a deposit from the given mock chain.
+    #[serde(default)]
+    pub from_chain: Option<ChainId>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct FaucetResponse {
+    pub cell_id: String,
+    pub tx_id: String,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct TipResponse {
+    pub block_id: String,
+    pub block_num: u64,
+    pub slot_num: u64,
+}
+
+#[derive(Clone)]
+struct DevApi {
+    faucet: Arc<Mutex<Faucet>>,
+    history: Arc<LedgerHistoryRocksDB>,
+}
+
+fn parse_owner(hex: &str) -> Result<Owner, FaucetError> {
+    base16::decode(hex)
+        .ok()
+        .and_then(|bytes| k256::PublicKey::from_sec1_bytes(&bytes).ok())
+        .map(Owner::ProveDlog)
+        .ok_or(FaucetError::InvalidOwner)
+}
+
+async fn faucet(
+    State(api): State<DevApi>,
+    Json(request): Json<FaucetRequest>,
+) -> Result<Json<FaucetResponse>, (StatusCode, String)> {
+    let owner = parse_owner(&request.owner).map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+    let cell = api
+        .faucet
+        .lock()
+        .unwrap()
+        .mint(owner, request.amount, request.from_chain)
+        .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+    let tx_id = match &cell {
+        AnyCell::Mut(c) => c.tx_id,
+        AnyCell::Term(c) => c.tx_id,
+    };
+    Ok(Json(FaucetResponse {
+        cell_id: format!("{:?}", cell.id()),
+        tx_id: format!("{:?}", tx_id),
+    }))
+}
+
+async fn tip(State(api): State<DevApi>) -> Json<TipResponse> {
+    let tip = api.history.get_tip().await.modifier.body;
+    Json(TipResponse {
+        block_id: BlockId::from(tip.digest()).to_string(),
+        block_num: u64::from(tip.block_num),
+        slot_num: u64::from(tip.slot_num),
+    })
+}
+
+async fn features(State(features): State<FeatureFlags>) -> Json<Vec<FeatureStatus>> {
+    Json(features.status())
+}
+
+async fn serve_api(addr: SocketAddr, api: DevApi, features: FeatureFlags, ready: Ready, shutdown: Shutdown) {
+    let app: Router<(), _> = Router::new()
+        .route("/faucet", post(faucet))
+        .route("/tip", get(tip))
+        .with_state(api)
+        .merge(
+            Router::new()
+                .route("/features", get(features))
+                .with_state(features),
+        );
+    match axum::Server::try_bind(&addr) {
+        Ok(server) => {
+            info!("[Dev] Faucet is listening on {}", addr);
+            ready.notify();
+            let res = server
+                .serve(app.into_make_service())
+                .with_graceful_shutdown(shutdown)
+                .await;
+            if let Err(err) = res {
+                error!("[Dev] Faucet API terminated: {}", err);
+            }
+        }
+        Err(err) => error!("[Dev] Cannot bind {}: {}", addr, err),
+    }
+}
+
+/// Run single-node chain serving the faucet API until `stop` resolves.
+/// Keys of the validator are derived from the given seed, the API is served on the given tokio runtime.
+pub async fn run<S: Future<Output = ()> + Unpin>(
+    conf: DevConfig,
+    seed: [u8; 32],
+    api_rt: tokio::runtime::Handle,
+    stop: S,
+) -> Result<(), Box<dyn Error>> {
+    let faucet = Arc::new(Mutex::new(Faucet::new(&conf)));
+    let features = FeatureFlags::new(conf.features.clone());
+    let (stores_snd, stores_recv) = oneshot::channel::<DevStores>();
+    let (producer_snd, producer_recv) = oneshot::channel::<DevProducer>();
+    let (history_snd, history_recv) = oneshot::channel::<LedgerHistoryRocksDB>();
+    let mut supervisor = Supervisor::new(SUBSYSTEM_READINESS_TIMEOUT);
+
+    let chain_dir = conf.chain_dir.clone();
+    supervisor.add(Stage::Storage, "ledger", move |ready, shutdown| async move {
+        match DevStores::open_fresh(&chain_dir) {
+            Ok(stores) => {
+                let _ = stores_snd.send(stores);
+                ready.notify();
+                shutdown.await;
+            }
+            Err(err) => error!("[Dev] Cannot open ledger stores at {:?}: {}", chain_dir, err),
+        }
+    });
+
+    let validator = DevValidator::from_seed(seed);
+    let node_view_faucet = faucet.clone();
+    let node_view_features = features.clone();
+    supervisor.add(Stage::NodeView, "node_view", move |ready, shutdown| async move {
+        if let Ok(stores) = stores_recv.await {
+            let (view, producer) = launch(&stores, validator, node_view_faucet, node_view_features);
+            let _ = history_snd.send(stores.history());
+            let _ = producer_snd.send(producer);
+            ready.notify();
+            future::select(view.for_each(|_| future::ready(())).boxed(), shutdown).await;
+        }
+    });
+
+    let block_interval = Duration::from_millis(conf.block_interval_millis);
+    supervisor.add(
+        Stage::Connectors,
+        "block_producer",
+        move |ready, shutdown| async move {
+            if let Ok(mut producer) = producer_recv.await {
+                ready.notify();
+                let mut shutdown = shutdown.fuse();
+                loop {
+                    futures::select! {
+                        _ = async_std::task::sleep(block_interval).fuse() => {
+                            let hdr = producer.produce_block().await;
+                            info!(
+                                "[Dev] Produced block #{:?} at slot {}",
+                                hdr.body.block_num, hdr.body.slot_num
+                            );
+                        },
+                        _ = shutdown => break,
+                    }
+                }
+            }
+        },
+    );
+
+    let faucet_addr = conf.faucet_addr;
+    supervisor.add(Stage::Api, "faucet", move |ready, shutdown| {
+        api_rt
+            .spawn(async move {
+                if let Ok(history) = history_recv.await {
+                    let api = DevApi {
+                        faucet,
+                        history: Arc::new(history),
+                    };
+                    serve_api(faucet_addr, api, features, ready, shutdown).await
+                }
+            })
+            .map(|_| ())
+    });
+
+    supervisor.run_until(stop).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use futures::StreamExt;
+    use k256::elliptic_curve::rand_core::OsRng;
+    use k256::SecretKey;
+    use rand::RngCore;
+
+    use spectrum_consensus::block_header::validate_block_header;
+    use spectrum_crypto::digest::Blake2bDigest256;
+    use spectrum_ledger::block::BlockId;
+    use spectrum_ledger::cell::{CellPtr, Owner};
+    use spectrum_ledger::interop::{Effect, Point};
+    use spectrum_ledger::{BlockNo, ChainId, SlotNo, SystemDigest};
+    use spectrum_network::features::FeatureFlags;
+    use spectrum_view::history::LedgerHistoryReadAsync;
+    use spectrum_view::state::Cells;
+
+    use crate::dev::{
+        launch, DevConfig, DevLeaderSchedule, DevProtocolParams, DevRules, DevState, DevStores, DevValidator,
+        Faucet, FaucetError, MockChainConfig,
+    };
+
+    fn conf() -> DevConfig {
+        DevConfig {
+            block_interval_millis: 1000,
+            faucet_addr: "127.0.0.1:9090".parse().unwrap(),
+            faucet_limit: 1000,
+            chain_dir: PathBuf::from(format!("./tmp/dev_chain_{}", rand::thread_rng().next_u32())),
+            mock_chains: vec![MockChainConfig {
+                chain_id: ChainId::from(0),
+                points_per_block: 10,
+            }],
+            features: Default::default(),
+        }
+    }
+
+    fn owner() -> Owner {
+        Owner::ProveDlog(SecretKey::random(&mut OsRng).public_key())
+    }
+
+    #[async_std::test]
+    async fn produced_blocks_go_through_node_view() {
+        let conf = conf();
+        let stores = DevStores::open(&conf.chain_dir).unwrap();
+        let faucet = Arc::new(Mutex::new(Faucet::new(&conf)));
+        let (view, mut producer) = launch(
+            &stores,
+            DevValidator::from_seed([0; 32]),
+            faucet.clone(),
+            FeatureFlags::new(conf.features.clone()),
+        );
+        async_std::task::spawn(view.for_each(|_| futures::future::ready(())));
+        let chain_id = ChainId::from(0);
+        let minted = faucet.lock().unwrap().mint(owner(), 100, None).unwrap();
+        let deposited = faucet.lock().unwrap().mint(owner(), 100, Some(chain_id)).unwrap();
+        let hdr = producer.produce_block().await;
+        assert_eq!(hdr.body.block_num, BlockNo::from(1));
+        let history = stores.history();
+        let state = stores.state();
+        for _ in 0..100 {
+            if state.progress_of(chain_id) == Point::from(10) {
+                break;
+            }
+            async_std::task::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(history.get_tip().await.modifier, hdr);
+        assert!(state.get_cell(CellPtr::Id(minted.id())).is_some());
+        assert!(state.get_cell(CellPtr::Id(deposited.id())).is_some());
+        assert_eq!(state.progress_of(chain_id), Point::from(10));
+    }
+
+    #[async_std::test]
+    async fn headers_of_other_validators_rejected() {
+        let conf = conf();
+        let stores = DevStores::open(&conf.chain_dir).unwrap();
+        let validator = DevValidator::from_seed([0; 32]);
+        let leader = DevLeaderSchedule::new(validator.vrf_vk());
+        let state = DevState::new(stores.state(), &validator);
+        let faucet = Arc::new(Mutex::new(Faucet::new(&conf)));
+        let (_view, _producer) = launch(
+            &stores,
+            DevValidator::from_seed([0; 32]),
+            faucet,
+            FeatureFlags::new(conf.features.clone()),
+        );
+        let history = stores.history();
+        let origin = history.get_tip().await.modifier.body;
+        let forge = |validator: &DevValidator| {
+            validator.forge(
+                BlockId::from(origin.digest()),
+                BlockNo::from(1),
+                SlotNo::UNIT,
+                Blake2bDigest256::zero(),
+            )
+        };
+        let validate = |hdr| {
+            validate_block_header(hdr, &history, &state, &DevRules, &DevProtocolParams, &leader).result()
+        };
+        assert!(validate(forge(&validator)).is_ok());
+        assert!(validate(forge(&DevValidator::from_seed([1; 32]))).is_err());
+    }
+
+    #[test]
+    fn deposits_are_reported_by_mock_chain() {
+        let mut faucet = Faucet::new(&conf());
+        let chain_id = ChainId::from(0);
+        let cell = faucet.mint(owner(), 100, Some(chain_id)).unwrap();
+        assert_eq!(
+            faucet.advance(),
+            vec![
+                (chain_id, Effect::Imported(cell.clone())),
+                (chain_id, Effect::Progressed(Point::from(10)))
+            ]
+        );
+        assert_eq!(
+            faucet.advance(),
+            vec![(chain_id, Effect::Progressed(Point::from(20)))]
+        );
+    }
+
+    #[test]
+    fn faucet_limits() {
+        let mut faucet = Faucet::new(&conf());
+        assert_eq!(faucet.mint(owner(), 0, None), Err(FaucetError::ZeroAmount));
+        assert_eq!(
+            faucet.mint(owner(), 1001, None),
+            Err(FaucetError::AmountTooLarge(1001, 1000))
+        );
+        assert_eq!(
+            faucet.mint(owner(), 1, Some(ChainId::from(7))),
+            Err(FaucetError::UnknownChain(ChainId::from(7)))
+        );
+    }
+}
@@ -0,0 +1,336 @@
+//! Scheduling of validator duties.
+//!
+//! Given the slot clock, membership of the node in committees and vault work pending on
+//! connected chains, [`DutyScheduler`] decides when the node should produce a block, initiate
+//! an aggregation round or ask a connector to process deposits. Duties touching the vault of
+//! the same chain conflict with each other, so at most one of them is in flight per chain.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use log::info;
+use serde::Serialize;
+
+use spectrum_ledger::{ChainId, EpochNo, SlotNo};
+
+/// Maps wall clock time to slots.
+#[derive(Copy, Clone, Debug)]
+pub struct SlotClock {
+    genesis: SystemTime,
+    slot_duration: Duration,
+}
+
+impl SlotClock {
+    pub fn new(genesis: SystemTime, slot_duration: Duration) -> Self {
+        Self {
+            genesis,
+            slot_duration,
+        }
+    }
+
+    pub fn slot_at(&self, time: SystemTime) -> SlotNo {
+        let elapsed = time.duration_since(self.genesis).unwrap_or_default();
+        SlotNo::from((elapsed.as_nanos() / self.slot_duration.as_nanos()) as u64)
+    }
+
+    pub fn current_slot(&self) -> SlotNo {
+        self.slot_at(SystemTime::now())
+    }
+
+    /// Time left until the given slot begins.
+    pub fn until(&self, slot: SlotNo) -> Duration {
+        let slots = u32::try_from(<u64>::from(slot)).unwrap_or(u32::MAX);
+        let start = self.genesis + self.slot_duration.saturating_mul(slots);
+        start.duration_since(SystemTime::now()).unwrap_or_default()
+    }
+}
+
+/// Role of the node in the committee of an epoch.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommitteeRole {
+    /// Not a member of the committee.
+    Idle,
+    Member,
+    /// Member which initiates rounds on behalf of the committee.
+    Leader,
+}
+
+/// Vault work pending on a connected chain.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct VaultWork {
+    pub deposits: usize,
+    pub withdrawals: usize,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(tag = "duty", rename_all = "snake_case")]
+pub enum Duty {
+    ProduceBlock {
+        slot: SlotNo,
+    },
+    /// Initiate an aggregation round notarizing pending withdrawals.
+    Aggregate {
+        chain_id: ChainId,
+    },
+    /// Ask the connector to process pending deposits.
+    ProcessDeposits {
+        chain_id: ChainId,
+    },
+}
+
+impl Duty {
+    /// Chain whose vault the duty acts upon.
+    fn vault(&self) -> Option<ChainId> {
+        match self {
+            Duty::ProduceBlock { .. } => None,
+            Duty::Aggregate { chain_id } | Duty::ProcessDeposits { chain_id } => Some(*chain_id),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct DutySchedulerConfig {
+    /// Vault duties aren't initiated within this many slots before the end of an epoch,
+    /// so that they don't outlive the committee which initiated them.
+    pub epoch_cutoff_slots: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PendingVaultWork {
+    pub chain_id: ChainId,
+    pub work: VaultWork,
+}
+
+/// Current schedule, as exposed by the API.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ScheduleStatus {
+    pub slot: SlotNo,
+    pub role: CommitteeRole,
+    pub in_flight: Vec<Duty>,
+    pub upcoming_blocks: Vec<SlotNo>,
+    pub pending: Vec<PendingVaultWork>,
+}
+
+#[derive(Debug)]
+struct SchedulerState {
+    conf: DutySchedulerConfig,
+    slot: SlotNo,
+    roles: BTreeMap<EpochNo, CommitteeRole>,
+    leader_slots: BTreeSet<SlotNo>,
+    pending: BTreeMap<ChainId, VaultWork>,
+    in_flight: BTreeSet<Duty>,
+}
+
+impl SchedulerState {
+    fn role(&self) -> CommitteeRole {
+        self.roles
+            .get(&self.slot.epoch_num())
+            .copied()
+            .unwrap_or(CommitteeRole::Idle)
+    }
+
+    fn within_cutoff(&self) -> bool {
+        let slot_in_epoch = <u64>::from(self.slot) % SlotNo::SLOTS_PER_EPOCH;
+        SlotNo::SLOTS_PER_EPOCH - slot_in_epoch <= self.conf.epoch_cutoff_slots
+    }
+
+    fn due(&mut self, slot: SlotNo) -> Vec<Duty> {
+        self.slot = slot;
+        let epoch = slot.epoch_num();
+        self.roles.retain(|e, _| *e >= epoch);
+        // Slots which are already gone are missed.
+        self.leader_slots = self.leader_slots.split_off(&slot);
+        let mut duties = vec![];
+        // Block production never waits for other duties.
+        if self.leader_slots.remove(&slot) {
+            duties.push(Duty::ProduceBlock { slot });
+        }
+        if self.role() == CommitteeRole::Leader && !self.within_cutoff() {
+            let busy = self
+                .in_flight
+                .iter()
+                .filter_map(Duty::vault)
+                .collect::<BTreeSet<_>>();
+            for (chain_id, work) in &self.pending {
+                if busy.contains(chain_id) {
+                    continue;
+                }
+                // Withdrawals go first, users are waiting for them.
+                let chain_id = *chain_id;
+                if work.withdrawals > 0 {
+                    duties.push(Duty::Aggregate { chain_id });
+                } else if work.deposits > 0 {
+                    duties.push(Duty::ProcessDeposits { chain_id });
+                }
+            }
+        }
+        for duty in &duties {
+            if duty.vault().is_some() {
+                self.in_flight.insert(*duty);
+            }
+        }
+        duties
+    }
+
+    fn status(&self) -> ScheduleStatus {
+        ScheduleStatus {
+            slot: self.slot,
+            role: self.role(),
+            in_flight: self.in_flight.iter().copied().collect(),
+            upcoming_blocks: self.leader_slots.iter().copied().collect(),
+            pending: self
+                .pending
+                .iter()
+                .map(|(chain_id, work)| PendingVaultWork {
+                    chain_id: *chain_id,
+                    work: *work,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Scheduler of validator duties shared by the components feeding it and the API.
+#[derive(Clone, Debug)]
+pub struct DutyScheduler {
+    state: Arc<RwLock<SchedulerState>>,
+}
+
+impl DutyScheduler {
+    pub fn new(conf: DutySchedulerConfig) -> Self {
+        Self {
+            state: Arc::new(RwLock::new(SchedulerState {
+                conf,
+                slot: SlotNo::ORIGIN,
+                roles: BTreeMap::new(),
+                leader_slots: BTreeSet::new(),
+                pending: BTreeMap::new(),
+                in_flight: BTreeSet::new(),
+            })),
+        }
+    }
+
+    /// Role of the node in the committee of the given epoch.
+    pub fn set_role(&self, epoch: EpochNo, role: CommitteeRole) {
+        info!("[Duties] Role in epoch {}: {:?}", epoch, role);
+        self.state.write().unwrap().roles.insert(epoch, role);
+    }
+
+    /// Slots the node won the right to produce a block in.
+    pub fn add_leader_slots(&self, slots: impl IntoIterator<Item = SlotNo>) {
+        self.state.write().unwrap().leader_slots.extend(slots);
+    }
+
+    /// Vault work currently pending on the given chain, as reported by its connector.
+    pub fn set_vault_work(&self, chain_id: ChainId, work: VaultWork) {
+        let mut state = self.state.write().unwrap();
+        if work == VaultWork::default() {
+            state.pending.remove(&chain_id);
+        } else {
+            state.pending.insert(chain_id, work);
+        }
+    }
+
+    /// Duties to be initiated in the given slot. Vault duties stay in flight until
+    /// reported [`complete`](Self::complete).
+    pub fn due(&self, slot: SlotNo) -> Vec<Duty> {
+        self.state.write().unwrap().due(slot)
+    }
+
+    /// Report that the duty is done (or abandoned), conflicting duties may be initiated again.
+    pub fn complete(&self, duty: Duty) {
+        self.state.write().unwrap().in_flight.remove(&duty);
+    }
+
+    pub fn status(&self) -> ScheduleStatus {
+        self.state.read().unwrap().status()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use spectrum_ledger::{ChainId, EpochNo, SlotNo};
+
+    use crate::duties::{CommitteeRole, Duty, DutyScheduler, DutySchedulerConfig, SlotClock, VaultWork};
+
+    fn scheduler() -> DutyScheduler {
+        DutyScheduler::new(DutySchedulerConfig {
+            epoch_cutoff_slots: 10,
+        })
+    }
+
+    #[test]
+    fn slots_follow_the_clock() {
+        let genesis = SystemTime::UNIX_EPOCH;
+        let clock = SlotClock::new(genesis, Duration::from_secs(2));
+        assert_eq!(clock.slot_at(genesis), SlotNo::ORIGIN);
+        assert_eq!(clock.slot_at(genesis + Duration::from_secs(5)), SlotNo::from(2));
+        assert_eq!(clock.until(SlotNo::ORIGIN), Duration::ZERO);
+    }
+
+    #[test]
+    fn vault_duties_on_the_same_chain_are_serialized() {
+        let scheduler = scheduler();
+        let chain_id = ChainId::from(0);
+        scheduler.set_role(EpochNo::from(0), CommitteeRole::Leader);
+        scheduler.add_leader_slots([SlotNo::from(2)]);
+        scheduler.set_vault_work(
+            chain_id,
+            VaultWork {
+                deposits: 1,
+                withdrawals: 1,
+            },
+        );
+        assert_eq!(scheduler.due(SlotNo::from(1)), vec![Duty::Aggregate { chain_id }]);
+        // Block production doesn't conflict with vault duties.
+        assert_eq!(
+            scheduler.due(SlotNo::from(2)),
+            vec![Duty::ProduceBlock {
+                slot: SlotNo::from(2)
+            }]
+        );
+        scheduler.complete(Duty::Aggregate { chain_id });
+        scheduler.set_vault_work(
+            chain_id,
+            VaultWork {
+                deposits: 1,
+                withdrawals: 0,
+            },
+        );
+        assert_eq!(
+            scheduler.due(SlotNo::from(3)),
+            vec![Duty::ProcessDeposits { chain_id }]
+        );
+        assert_eq!(
+            scheduler.status().in_flight,
+            vec![Duty::ProcessDeposits { chain_id }]
+        );
+    }
+
+    #[test]
+    fn only_leader_initiates_vault_duties_before_cutoff() {
+        let scheduler = scheduler();
+        let chain_id = ChainId::from(0);
+        scheduler.set_vault_work(
+            chain_id,
+            VaultWork {
+                deposits: 1,
+                withdrawals: 0,
+            },
+        );
+        scheduler.set_role(EpochNo::from(0), CommitteeRole::Member);
+        scheduler.set_role(EpochNo::from(1), CommitteeRole::Leader);
+        assert!(scheduler.due(SlotNo::from(1)).is_empty());
+        assert_eq!(
+            scheduler.due(SlotNo::from(SlotNo::SLOTS_PER_EPOCH)),
+            vec![Duty::ProcessDeposits { chain_id }]
+        );
+        scheduler.complete(Duty::ProcessDeposits { chain_id });
+        let cutoff = SlotNo::from(SlotNo::SLOTS_PER_EPOCH * 2 - 10);
+        assert!(scheduler.due(cutoff).is_empty());
+    }
+}
@@ -0,0 +1,147 @@
+//! Genesis of the network: the initial committee, length of epochs and chains connected to
+//! the network along with initial parameters of their vaults.
+//!
+//! Genesis is specified in a JSON file loaded at startup. Nodes agree on the network they
+//! belong to by the hash of the genesis, see [`Genesis::network_id`], which is announced in
+//! handshakes and exposed by the control API for verification by the operator.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use spectrum_crypto::digest::blake2b256_hash;
+use spectrum_crypto::pubkey::PublicKey;
+use spectrum_ledger::{ChainId, SlotNo};
+use spectrum_network::types::NetworkId;
+
+/// Initial parameters of the vault on a connected chain.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VaultGenesis {
+    /// Address of the vault in the encoding native to the connected chain.
+    pub address: String,
+    /// Height of the connected chain the vault was deployed at.
+    /// Earlier blocks aren't scanned by the connector.
+    pub starting_height: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainGenesis {
+    pub chain_id: ChainId,
+    pub vault: VaultGenesis,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Genesis {
+    /// Public keys of members of the initial committee.
+    pub committee: Vec<PublicKey>,
+    /// Length of an epoch in slots.
+    pub epoch_length: u64,
+    pub chains: Vec<ChainGenesis>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum GenesisError {
+    #[error("Cannot read genesis: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Malformed genesis: {0}")]
+    Format(#[from] serde_json::Error),
+    #[error("Initial committee is empty")]
+    EmptyCommittee,
+    #[error("Committee member {0:?} is listed more than once")]
+    DuplicateMember(PublicKey),
+    #[error("Chain {0:?} is listed more than once")]
+    DuplicateChain(ChainId),
+    #[error("Epochs of {0} slots aren't supported, epoch length must be {1}")]
+    UnsupportedEpochLength(u64, u64),
+}
+
+impl Genesis {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, GenesisError> {
+        let genesis: Genesis = serde_json::from_reader(std::fs::File::open(path)?)?;
+        genesis.validate()?;
+        Ok(genesis)
+    }
+
+    fn validate(&self) -> Result<(), GenesisError> {
+        if self.committee.is_empty() {
+            return Err(GenesisError::EmptyCommittee);
+        }
+        if self.epoch_length != SlotNo::SLOTS_PER_EPOCH {
+            return Err(GenesisError::UnsupportedEpochLength(
+                self.epoch_length,
+                SlotNo::SLOTS_PER_EPOCH,
+            ));
+        }
+        let mut members = HashSet::new();
+        if let Some(pk) = self.committee.iter().find(|pk| !members.insert(**pk)) {
+            return Err(GenesisError::DuplicateMember(*pk));
+        }
+        let mut chains = HashSet::new();
+        if let Some(chain) = self.chains.iter().find(|c| !chains.insert(c.chain_id)) {
+            return Err(GenesisError::DuplicateChain(chain.chain_id));
+        }
+        Ok(())
+    }
+
+    /// Hash of the canonical (CBOR) encoding of the genesis,
+    /// so that the id doesn't depend on formatting of the genesis file.
+    pub fn network_id(&self) -> NetworkId {
+        let mut encoded = Vec::new();
+        ciborium::ser::into_writer(self, &mut encoded).unwrap();
+        NetworkId::from(blake2b256_hash(&encoded))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::SecretKey;
+
+    use spectrum_crypto::pubkey::PublicKey;
+    use spectrum_ledger::{ChainId, SlotNo};
+
+    use crate::genesis::{ChainGenesis, Genesis, GenesisError, VaultGenesis};
+
+    fn genesis() -> Genesis {
+        Genesis {
+            committee: (0..3)
+                .map(|_| PublicKey::from(SecretKey::random(&mut rand::thread_rng())))
+                .collect(),
+            epoch_length: SlotNo::SLOTS_PER_EPOCH,
+            chains: vec![ChainGenesis {
+                chain_id: ChainId::from(0),
+                vault: VaultGenesis {
+                    address: "vault".to_string(),
+                    starting_height: 100,
+                },
+            }],
+        }
+    }
+
+    #[test]
+    fn network_id_doesnt_depend_on_formatting() {
+        let genesis = genesis();
+        let compact = serde_json::to_string(&genesis).unwrap();
+        let pretty = serde_json::to_string_pretty(&genesis).unwrap();
+        let from_compact: Genesis = serde_json::from_str(&compact).unwrap();
+        let from_pretty: Genesis = serde_json::from_str(&pretty).unwrap();
+        assert_eq!(from_compact.network_id(), from_pretty.network_id());
+        assert_eq!(from_compact.network_id(), genesis.network_id());
+        let mut other = genesis.clone();
+        other.chains[0].vault.starting_height += 1;
+        assert_ne!(other.network_id(), genesis.network_id());
+    }
+
+    #[test]
+    fn inconsistent_genesis_rejected() {
+        let mut genesis = genesis();
+        assert!(genesis.validate().is_ok());
+        genesis.committee.push(genesis.committee[0]);
+        assert!(matches!(
+            genesis.validate(),
+            Err(GenesisError::DuplicateMember(_))
+        ));
+        genesis.committee.clear();
+        assert!(matches!(genesis.validate(), Err(GenesisError::EmptyCommittee)));
+    }
+}
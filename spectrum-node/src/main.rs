@@ -1,36 +1,102 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::error::Error;
+use std::net::SocketAddr;
 use std::str::FromStr;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
-use futures::channel::mpsc;
 use futures::prelude::*;
 use libp2p::identity;
 use libp2p::swarm::{SwarmBuilder, SwarmEvent};
 use libp2p::Multiaddr;
 use libp2p::PeerId;
+use log::{info, warn};
 
-use spectrum_network::network_controller::{NetworkController, NetworkControllerIn, NetworkMailbox};
-use spectrum_network::peer_conn_handler::PeerConnHandlerConf;
+use spectrum_crypto::keystore::{libp2p_keypair, KeyPurpose, Keystore, KeystoreError};
+use spectrum_ledger::SlotNo;
+use spectrum_network::cancellation::CancellationToken;
+use spectrum_network::features::{Activation, FeatureFlags, FeatureFlagsConf};
+use spectrum_network::memory_budget::MemoryBudget;
+use spectrum_network::metrics::PrometheusMetrics;
+use spectrum_network::network_builder::{Network, NetworkBuilder};
 use spectrum_network::peer_manager::data::PeerDestination;
-use spectrum_network::peer_manager::peers_state::PeerRepo;
-use spectrum_network::peer_manager::{NetworkingConfig, PeerManager, PeerManagerConfig};
+use spectrum_network::peer_manager::persistent_peers_state::AnyPeerRepo;
+use spectrum_network::peer_manager::{NetworkingConfig, PeerStorage};
 use spectrum_network::protocol::{
-    ProtocolConfig, StatefulProtocolConfig, StatefulProtocolSpec, DIFFUSION_PROTOCOL_ID,
+    ProtocolConfig, ProtocolPriority, StatefulProtocolConfig, StatefulProtocolSpec, DIFFUSION_PROTOCOL_ID,
 };
 use spectrum_network::protocol_handler::discovery::message::DiscoverySpec;
-use spectrum_network::protocol_handler::discovery::{DiscoveryBehaviour, NodeStatus};
-use spectrum_network::protocol_handler::ProtocolHandler;
-use spectrum_network::types::Reputation;
+use spectrum_network::protocol_handler::discovery::{DiscoveryBehaviour, NodeStatus, LOOKUPS_FEATURE};
+use spectrum_network::store_recovery::RecoveryConf;
+
+use crate::control::GenesisInfo;
+use crate::determinism::Determinism;
+use crate::duties::{DutyScheduler, DutySchedulerConfig, SlotClock};
+use crate::genesis::Genesis;
+use crate::supervisor::{Stage, Supervisor};
 
 mod consensus;
+mod control;
+mod determinism;
+mod dev;
+mod duties;
+mod genesis;
 mod node_view;
+mod supervisor;
+
+const SUBSYSTEM_READINESS_TIMEOUT: Duration = Duration::from_secs(30);
+/// Address the control API is served at, [`DEFAULT_CONTROL_API_ADDR`] if not set.
+const CONTROL_API_ADDR_ENV: &str = "SPECTRUM_CONTROL_API_ADDR";
+const DEFAULT_CONTROL_API_ADDR: &str = "127.0.0.1:9091";
+const GENESIS_PATH: &str = "conf/genesis.json";
+const PEERS_DB_PATH: &str = "./data/peers";
+const PEERS_BACKUP_PATH: &str = "./data/backups/peers";
+const KEYSTORE_PATH: &str = "./data/keystore";
+/// Passphrase of the keystore. Identity of the node is ephemeral if not set.
+const KEYSTORE_PASSPHRASE_ENV: &str = "SPECTRUM_KEYSTORE_PASSPHRASE";
+const NODE_IDENTITY_KEY: &str = "node-identity";
+/// Operator confirms that stores corrupted beyond repair may be restored from backups.
+const RESTORE_FROM_BACKUP_FLAG: &str = "--restore-from-backup";
+const MEMORY_BUDGET_BYTES: usize = 512 * 1024 * 1024;
+const NETWORK_MEMORY_QUOTA_BYTES: usize = 128 * 1024 * 1024;
+const SLOT_DURATION: Duration = Duration::from_secs(1);
+const DUTIES_EPOCH_CUTOFF_SLOTS: u64 = 10;
 
-#[async_std::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+fn main() -> Result<(), Box<dyn Error>> {
+    let determinism = Determinism::from_args()?;
+    if let Some(determinism) = determinism {
+        // Executors are configured once started, i.e. before the first task is spawned.
+        determinism.pin_executor();
+    }
     log4rs::init_file("conf/log4rs.yaml", Default::default()).unwrap();
+    if let Some(determinism) = determinism {
+        warn!(
+            "[Startup] Running in deterministic mode ({:?}), never use it in production",
+            determinism
+        );
+    }
+
+    if std::env::args().nth(1).as_deref() == Some("--dev") {
+        let conf_path = std::env::args().nth(2).unwrap_or("conf/dev.yaml".to_string());
+        let conf: dev::DevConfig = serde_yaml::from_reader(std::fs::File::open(conf_path)?)?;
+        let seed = determinism.map_or_else(rand::random, |determinism| determinism.seed_of("dev-validator"));
+        // The faucet API is served by axum, which requires tokio.
+        let rt = determinism::tokio_runtime(determinism)?;
+        let stop = supervisor::termination_signal()?;
+        return async_std::task::block_on(dev::run(conf, seed, rt.handle().clone(), stop));
+    }
+    async_std::task::block_on(run(determinism))
+}
+
+async fn run(determinism: Option<Determinism>) -> Result<(), Box<dyn Error>> {
+    let genesis = Genesis::load(GENESIS_PATH)?;
+    let network_id = genesis.network_id();
+    info!("[Startup] Network id: {}", network_id);
 
-    let local_key = identity::Keypair::generate_ed25519();
+    let local_key = match determinism {
+        Some(determinism) => determinism.identity(),
+        None => node_identity()?,
+    };
     let local_peer_id = PeerId::from(local_key.public());
     println!("Local peer id: {:?}", local_peer_id);
 
@@ -57,83 +123,183 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
     }
 
-    let peer_conn_handler_conf = PeerConnHandlerConf {
-        async_msg_buffer_size: 10,
-        sync_msg_buffer_size: 40,
-        open_timeout: Duration::from_secs(60),
-        initial_keep_alive: Duration::from_secs(60),
-    };
-    let netw_config = NetworkingConfig {
-        min_known_peers: 1,
-        min_outbound: 1,
-        max_inbound: 10,
-        max_outbound: 20,
+    let netw_conf = NetworkingConfig {
+        peers_storage: PeerStorage::Persistent,
+        ..NetworkingConfig::default()
     };
-    let peer_manager_conf = PeerManagerConfig {
-        min_acceptable_reputation: Reputation::from(0),
-        min_reputation: Reputation::from(0),
-        conn_reset_outbound_backoff: Duration::from_secs(120),
-        conn_alloc_interval: Duration::from_secs(30),
-        prot_alloc_interval: Duration::from_secs(30),
-        protocols_allocation: Vec::new(),
-        peer_manager_msg_buffer_size: 10,
+    let recovery_conf = RecoveryConf {
+        backup_dir: Some(PEERS_BACKUP_PATH.into()),
+        backups_to_keep: 3,
+        restore_confirmed: std::env::args().any(|arg| arg == RESTORE_FROM_BACKUP_FLAG),
+        verify_checksums: true,
     };
-    let peer_state = PeerRepo::new(netw_config, boot_peers);
-    let (peer_manager, peers) = PeerManager::new(peer_state, peer_manager_conf);
+    let transport_conf = netw_conf.transport.clone();
+    let peer_state = AnyPeerRepo::open(PEERS_DB_PATH, recovery_conf, netw_conf, boot_peers)?;
+    if let Some(report) = peer_state.recovery_report() {
+        if report.is_clean() {
+            info!("[Startup] Store {:?} is intact", report.db_path);
+        }
+        for action in &report.actions {
+            warn!("[Startup] Store {:?}: {}", report.db_path, action);
+        }
+    }
     let sync_conf = StatefulProtocolConfig {
-        supported_versions: vec![(
-            DiscoverySpec::v1(),
-            StatefulProtocolSpec {
-                max_message_size: 100,
-                approve_required: true,
-            },
-        )],
+        supported_versions: vec![
+            (
+                DiscoverySpec::v2(),
+                StatefulProtocolSpec {
+                    max_message_size: 100,
+                    approve_required: true,
+                    batching: None,
+                    compression: None,
+                },
+            ),
+            (
+                DiscoverySpec::v1(),
+                StatefulProtocolSpec {
+                    max_message_size: 100,
+                    approve_required: true,
+                    batching: None,
+                    compression: None,
+                },
+            ),
+        ],
+        preferred_versions: vec![DiscoverySpec::v2(), DiscoverySpec::v1()],
+        priority: ProtocolPriority::NORMAL,
     };
 
     let local_status = NodeStatus {
         supported_protocols: Vec::from([DIFFUSION_PROTOCOL_ID]),
         height: 0,
     };
-    let sync_behaviour = DiscoveryBehaviour::new(peers.clone(), local_status);
-    const NC_MSG_BUFFER_SIZE: usize = 10;
-    let (requests_snd, requests_recv) = mpsc::channel::<NetworkControllerIn>(NC_MSG_BUFFER_SIZE);
-    let network_api = NetworkMailbox {
-        mailbox_snd: requests_snd,
-    };
-    const PH_MSG_BUFFER_SIZE: usize = 10;
-    let (mut sync_handler, sync_mailbox) = ProtocolHandler::new(
-        sync_behaviour,
-        network_api,
-        DIFFUSION_PROTOCOL_ID,
-        PH_MSG_BUFFER_SIZE,
-    );
-    let nc = NetworkController::new(
-        peer_conn_handler_conf,
-        HashMap::from([(
-            sync_handler.protocol,
-            (ProtocolConfig::Stateful(sync_conf), sync_mailbox),
-        )]),
-        peers,
-        peer_manager,
-        requests_recv,
-    );
+    let metrics = PrometheusMetrics::new();
+    let memory_budget = MemoryBudget::new(MEMORY_BUDGET_BYTES).with_metrics(Arc::new(metrics.clone()));
+    let features = FeatureFlags::new(FeatureFlagsConf(BTreeMap::from([(
+        LOOKUPS_FEATURE.to_string(),
+        Activation::Enabled,
+    )])));
+    let discovery_features = features.clone();
+    let protocols_cancellation = CancellationToken::new();
+    let Network {
+        controller: nc,
+        peers: control_peers,
+        mut protocol_handlers,
+        ..
+    } = NetworkBuilder::new(local_peer_id, peer_state)
+        .with_cancellation(protocols_cancellation.clone())
+        .with_routing_table()
+        .with_routing_hints(transport_conf.routing_hints.clone())
+        .with_metrics(Arc::new(metrics.clone()))
+        .with_memory_quota(memory_budget.register("network_controller", NETWORK_MEMORY_QUOTA_BYTES))
+        .with_protocol(
+            DIFFUSION_PROTOCOL_ID,
+            ProtocolConfig::Stateful(sync_conf),
+            move |peers| {
+                DiscoveryBehaviour::new(peers, local_status)
+                    .with_feature_flags(discovery_features)
+                    .with_network_id(network_id)
+            },
+        )
+        .build();
 
     let mut swarm = SwarmBuilder::with_async_std_executor(transport, nc, local_peer_id).build();
+    let listen_addr: Multiaddr = std::env::args().nth(1).unwrap().parse()?;
+    transport_conf.validate(&listen_addr)?;
+    swarm.listen_on(listen_addr)?;
 
-    swarm.listen_on(std::env::args().nth(1).unwrap().parse()?)?;
+    let mut supervisor = Supervisor::new(SUBSYSTEM_READINESS_TIMEOUT);
+    supervisor.add(Stage::Network, "network", move |ready, shutdown| async move {
+        ready.notify();
+        let mut shutdown = shutdown.fuse();
+        loop {
+            futures::select! {
+                event = swarm.select_next_some() => match event {
+                    SwarmEvent::NewListenAddr { address, .. } => println!("Listening on {:?}", address),
+                    SwarmEvent::Behaviour(event) => println!("{:?}", event),
+                    SwarmEvent::ConnectionEstablished { peer_id, .. } => println!("New conn {:?}", peer_id),
+                    _ => {}
+                },
+                _ = shutdown => break,
+            }
+        }
+    });
+    supervisor.add(
+        Stage::ProtocolHandlers,
+        "sync",
+        move |ready, shutdown| async move {
+            ready.notify();
+            let mut shutdown = shutdown.fuse();
+            loop {
+                futures::select! {
+                    _ = protocol_handlers.select_next_some() => {},
+                    _ = shutdown => break,
+                }
+            }
+            // Let the behaviours wind down in-flight work before the network goes away.
+            protocols_cancellation.cancel();
+            while protocol_handlers.next().await.is_some() {}
+        },
+    );
 
-    async_std::task::spawn(async move {
+    let duties = DutyScheduler::new(DutySchedulerConfig {
+        epoch_cutoff_slots: DUTIES_EPOCH_CUTOFF_SLOTS,
+    });
+    let clock = SlotClock::new(SystemTime::UNIX_EPOCH, SLOT_DURATION);
+    let scheduled_duties = duties.clone();
+    supervisor.add(Stage::Connectors, "duties", move |ready, shutdown| async move {
+        ready.notify();
+        let mut shutdown = shutdown.fuse();
+        let mut slot = clock.current_slot();
         loop {
-            sync_handler.select_next_some().await;
+            futures::select! {
+                _ = async_std::task::sleep(clock.until(slot)).fuse() => {
+                    for duty in scheduled_duties.due(slot) {
+                        info!("[Duties] {:?} is due", duty);
+                    }
+                    slot = slot + SlotNo::UNIT;
+                },
+                _ = shutdown => break,
+            }
         }
     });
 
-    loop {
-        match swarm.select_next_some().await {
-            SwarmEvent::NewListenAddr { address, .. } => println!("Listening on {:?}", address),
-            SwarmEvent::Behaviour(event) => println!("{:?}", event),
-            SwarmEvent::ConnectionEstablished { peer_id, .. } => println!("New conn {:?}", peer_id),
-            _ => {}
+    // Control API is served by axum, which requires tokio.
+    let rt = determinism::tokio_runtime(determinism)?;
+    let rt_handle = rt.handle().clone();
+    let control_addr: SocketAddr = std::env::var(CONTROL_API_ADDR_ENV)
+        .as_deref()
+        .unwrap_or(DEFAULT_CONTROL_API_ADDR)
+        .parse()?;
+    supervisor.add(Stage::Api, "control", move |ready, shutdown| {
+        rt_handle
+            .spawn(control::serve(
+                control_addr,
+                control_peers,
+                features,
+                metrics,
+                duties,
+                GenesisInfo {
+                    network_id: network_id.to_string(),
+                    genesis,
+                },
+                ready,
+                shutdown,
+            ))
+            .map(|_| ())
+    });
+
+    supervisor.run_until(supervisor::termination_signal()?).await?;
+    Ok(())
+}
+
+/// Identity of the node kept in the keystore, or an ephemeral one if the keystore isn't enabled.
+fn node_identity() -> Result<identity::Keypair, KeystoreError> {
+    match std::env::var(KEYSTORE_PASSPHRASE_ENV) {
+        Ok(passphrase) => {
+            let sk = Keystore::open(KEYSTORE_PATH, &passphrase)?
+                .get_or_generate(NODE_IDENTITY_KEY, KeyPurpose::NodeIdentity)?;
+            Ok(libp2p_keypair(&sk))
         }
+        Err(_) => Ok(identity::Keypair::generate_ed25519()),
     }
 }
@@ -4,80 +4,247 @@ use std::task::{Context, Poll};
 use futures::channel::mpsc::{Receiver, Sender};
 use futures::{SinkExt, Stream, StreamExt};
 
+use spectrum_consensus::block_body::validate_block_body;
 use spectrum_consensus::block_header::validate_block_header;
+use spectrum_consensus::leader::LeaderEligibility;
 use spectrum_consensus::protocol_params::ProtocolParams;
-use spectrum_ledger::Modifier;
+use spectrum_crypto::digest::Blake2bDigest256;
+use spectrum_diffusion::behaviour::DiffusionMailbox;
+use spectrum_ledger::transaction::TxPackage;
+use spectrum_ledger::{Modifier, ModifierId, ModifierType};
+use spectrum_network::peer_manager::data::ReputationChange;
+use spectrum_network::peer_manager::Peers;
 use spectrum_validation::rules::ConsensusRuleSet;
 use spectrum_validation::validation::InvalidModifier;
 use spectrum_view::history::{LedgerHistoryReadSync, LedgerHistoryWrite};
-use spectrum_view::node_view::NodeViewWriteAsync;
+use spectrum_view::mempool::{MempoolWrite, PackageError};
+use spectrum_view::node_view::{ModifierSource, NodeViewWriteAsync};
+use spectrum_view::snapshot::{SignedSnapshot, StateSnapshot};
 use spectrum_view::state::{
     Cells, ConsensusIndexes, LedgerStateWrite, StakeDistribution, ValidatorCredentials,
 };
 
 #[derive(Clone, Debug)]
 pub enum NodeViewIn {
-    ApplyModifier(Modifier),
+    ApplyModifier(Modifier, ModifierSource),
+    /// Snapshot verified against the certificate of the committee.
+    InstallSnapshot(Box<(SignedSnapshot, StateSnapshot)>, ModifierSource),
 }
 
-pub trait ErrorHandler {
-    fn on_invalid_modifier(&self, err: InvalidModifier);
+/// Outcomes of validation of modifiers along with their sources.
+pub trait ValidationResultsHandler {
+    fn on_applied_modifier(&self, modifier_id: ModifierId, source: ModifierSource);
+    fn on_accepted_package(&self, pkg_id: ModifierId, source: ModifierSource);
+    fn on_invalid_modifier(&self, err: InvalidModifier, source: ModifierSource);
+    fn on_rejected_package(&self, err: PackageError, source: ModifierSource);
 }
 
-pub struct NodeView<TState, THistory, TMempool, TErrHandler, TRuleSet, TProtocol> {
+/// Adjusts reputation of peers according to the usefulness of data they serve.
+#[derive(Clone)]
+pub struct PeerScoring<TPeers> {
+    peers: TPeers,
+}
+
+impl<TPeers> PeerScoring<TPeers> {
+    pub fn new(peers: TPeers) -> Self {
+        Self { peers }
+    }
+}
+
+impl<TPeers> PeerScoring<TPeers>
+where
+    TPeers: Peers + Clone,
+{
+    fn report(&self, source: ModifierSource, change: ReputationChange) {
+        if let ModifierSource::Remote(peer_id) = source {
+            self.peers.clone().report_peer(peer_id, change);
+        }
+    }
+}
+
+impl<TPeers> ValidationResultsHandler for PeerScoring<TPeers>
+where
+    TPeers: Peers + Clone,
+{
+    fn on_applied_modifier(&self, _modifier_id: ModifierId, source: ModifierSource) {
+        self.report(source, ReputationChange::UsefulModifier);
+    }
+
+    fn on_accepted_package(&self, _pkg_id: ModifierId, source: ModifierSource) {
+        self.report(source, ReputationChange::UsefulModifier);
+    }
+
+    fn on_invalid_modifier(&self, err: InvalidModifier, source: ModifierSource) {
+        // Non-fatal violations may be caused by the local view lagging behind, e.g. a missing parent.
+        if err.fatal {
+            self.report(source, ReputationChange::InvalidModifier);
+        }
+    }
+
+    fn on_rejected_package(&self, err: PackageError, source: ModifierSource) {
+        match err {
+            PackageError::EmptyPackage | PackageError::TooLarge(_) | PackageError::InvalidMember { .. } => {
+                self.report(source, ReputationChange::InvalidModifier)
+            }
+            // Honest peers can race with each other.
+            PackageError::AlreadyKnown(_) | PackageError::DoubleSpend { .. } => {}
+        }
+    }
+}
+
+/// Relays packages accepted to the mempool to peers.
+#[derive(Clone)]
+pub struct PackageRelay<TResults> {
+    inner: TResults,
+    diffusion: DiffusionMailbox,
+}
+
+impl<TResults> PackageRelay<TResults> {
+    pub fn new(inner: TResults, diffusion: DiffusionMailbox) -> Self {
+        Self { inner, diffusion }
+    }
+}
+
+impl<TResults> ValidationResultsHandler for PackageRelay<TResults>
+where
+    TResults: ValidationResultsHandler,
+{
+    fn on_applied_modifier(&self, modifier_id: ModifierId, source: ModifierSource) {
+        self.inner.on_applied_modifier(modifier_id, source)
+    }
+
+    fn on_accepted_package(&self, pkg_id: ModifierId, source: ModifierSource) {
+        self.diffusion
+            .announce(ModifierType::TxPackage, vec![pkg_id], source);
+        self.inner.on_accepted_package(pkg_id, source)
+    }
+
+    fn on_invalid_modifier(&self, err: InvalidModifier, source: ModifierSource) {
+        self.inner.on_invalid_modifier(err, source)
+    }
+
+    fn on_rejected_package(&self, err: PackageError, source: ModifierSource) {
+        self.inner.on_rejected_package(err, source)
+    }
+}
+
+pub struct NodeView<TState, THistory, TMempool, TResults, TRuleSet, TProtocol, TLeader> {
     state: TState,
     history: THistory,
     mempool: TMempool,
-    err_handler: TErrHandler,
+    results_handler: TResults,
     rules: TRuleSet,
     protocol: TProtocol,
+    leader: TLeader,
     inbox: Receiver<NodeViewIn>,
 }
 
-impl<TState, THistory, TMempool, TErrHandler, TRuleSet, TProtocol>
-    NodeView<TState, THistory, TMempool, TErrHandler, TRuleSet, TProtocol>
+impl<TState, THistory, TMempool, TResults, TRuleSet, TProtocol, TLeader>
+    NodeView<TState, THistory, TMempool, TResults, TRuleSet, TProtocol, TLeader>
+{
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        state: TState,
+        history: THistory,
+        mempool: TMempool,
+        results_handler: TResults,
+        rules: TRuleSet,
+        protocol: TProtocol,
+        leader: TLeader,
+        inbox: Receiver<NodeViewIn>,
+    ) -> Self {
+        Self {
+            state,
+            history,
+            mempool,
+            results_handler,
+            rules,
+            protocol,
+            leader,
+            inbox,
+        }
+    }
+}
+
+impl<TState, THistory, TMempool, TResults, TRuleSet, TProtocol, TLeader>
+    NodeView<TState, THistory, TMempool, TResults, TRuleSet, TProtocol, TLeader>
 where
     TState: Cells + LedgerStateWrite + ConsensusIndexes + StakeDistribution + ValidatorCredentials,
     THistory: LedgerHistoryWrite + LedgerHistoryReadSync,
-    TErrHandler: ErrorHandler,
+    TMempool: MempoolWrite,
+    TResults: ValidationResultsHandler,
     TRuleSet: ConsensusRuleSet,
     TProtocol: ProtocolParams,
+    TLeader: LeaderEligibility,
 {
-    fn on_event(&self, event: NodeViewIn) {
+    fn on_event(&mut self, event: NodeViewIn) {
         match event {
-            NodeViewIn::ApplyModifier(md) => {
-                self.apply_modifier(md)
-                    .unwrap_or_else(|e| self.err_handler.on_invalid_modifier(e));
+            NodeViewIn::ApplyModifier(Modifier::Transaction(tx), source) => {
+                self.accept_package(TxPackage::from(tx), source)
+            }
+            NodeViewIn::ApplyModifier(Modifier::TxPackage(pkg), source) => self.accept_package(pkg, source),
+            NodeViewIn::ApplyModifier(md, source) => {
+                let modifier_id = md.id();
+                match self.apply_modifier(md) {
+                    Ok(()) => self.results_handler.on_applied_modifier(modifier_id, source),
+                    Err(err) => self.results_handler.on_invalid_modifier(err, source),
+                }
+            }
+            NodeViewIn::InstallSnapshot(snapshot, source) => {
+                let (snapshot, state) = *snapshot;
+                let checkpoint = snapshot.manifest.checkpoint;
+                self.install_snapshot(snapshot, state);
+                self.results_handler
+                    .on_applied_modifier(ModifierId::from(checkpoint.id), source);
             }
         }
     }
 
+    /// Replace the local state with the snapshot and continue the chain from the snapshot block.
+    fn install_snapshot(&self, snapshot: SignedSnapshot, state: StateSnapshot) {
+        let checkpoint = snapshot.manifest.checkpoint;
+        self.state
+            .install_snapshot(Blake2bDigest256::from(checkpoint.id), state);
+        self.history.install_checkpoint(snapshot.header);
+    }
+
     fn apply_modifier(&self, modifier: Modifier) -> Result<(), InvalidModifier> {
         match modifier {
-            Modifier::BlockHeader(hd) => {
-                validate_block_header(hd, &self.history, &self.state, &self.rules, &self.protocol)
-                    .result()
-                    .map(|valid_hd| self.history.apply_header(valid_hd))
-            }
-            Modifier::BlockBody(blk) => {
-                todo!()
-            }
-            Modifier::Transaction(_) => {
-                todo!()
-            }
+            Modifier::BlockHeader(hd) => validate_block_header(
+                hd,
+                &self.history,
+                &self.state,
+                &self.rules,
+                &self.protocol,
+                &self.leader,
+            )
+            .result()
+            .map(|valid_hd| self.history.apply_header(valid_hd)),
+            Modifier::BlockBody(blk) => validate_block_body(blk, &self.history, &self.state, &self.rules)
+                .result()
+                .map(|valid_blk| self.history.apply_body(valid_blk)),
+            Modifier::Transaction(_) | Modifier::TxPackage(_) => unreachable!("Transactions go to mempool"),
+        }
+    }
+
+    fn accept_package(&mut self, pkg: TxPackage, source: ModifierSource) {
+        match self.mempool.accept_package(&self.state, pkg) {
+            Ok(pkg_id) => self.results_handler.on_accepted_package(pkg_id, source),
+            Err(err) => self.results_handler.on_rejected_package(err, source),
         }
     }
 }
 
-impl<TState, THistory, TMempool, TErrHandler, TRuleSet, TProtocol> Stream
-    for NodeView<TState, THistory, TMempool, TErrHandler, TRuleSet, TProtocol>
+impl<TState, THistory, TMempool, TResults, TRuleSet, TProtocol, TLeader> Stream
+    for NodeView<TState, THistory, TMempool, TResults, TRuleSet, TProtocol, TLeader>
 where
     TState: Cells + LedgerStateWrite + ConsensusIndexes + StakeDistribution + ValidatorCredentials + Unpin,
     THistory: LedgerHistoryWrite + LedgerHistoryReadSync + Unpin,
-    TMempool: Unpin,
-    TErrHandler: ErrorHandler + Unpin,
+    TMempool: MempoolWrite + Unpin,
+    TResults: ValidationResultsHandler + Unpin,
     TRuleSet: ConsensusRuleSet + Unpin,
     TProtocol: ProtocolParams + Unpin,
+    TLeader: LeaderEligibility + Unpin,
 {
     type Item = ();
 
@@ -89,7 +256,7 @@ where
                     continue;
                 }
                 Poll::Pending => {}
-                Poll::Ready(None) => unreachable!(),
+                Poll::Ready(None) => return Poll::Ready(None),
             }
             return Poll::Pending;
         }
@@ -109,10 +276,102 @@ impl NodeViewMailbox {
 
 #[async_trait::async_trait]
 impl NodeViewWriteAsync for NodeViewMailbox {
-    async fn apply_modifier(&mut self, modifier: Modifier) {
+    async fn apply_modifier(&mut self, modifier: Modifier, source: ModifierSource) {
+        self.inner
+            .send(NodeViewIn::ApplyModifier(modifier, source))
+            .await
+            .unwrap();
+    }
+
+    async fn install_snapshot(
+        &mut self,
+        snapshot: SignedSnapshot,
+        state: StateSnapshot,
+        source: ModifierSource,
+    ) {
         self.inner
-            .send(NodeViewIn::ApplyModifier(modifier))
+            .send(NodeViewIn::InstallSnapshot(Box::new((snapshot, state)), source))
             .await
             .unwrap();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::StreamExt;
+    use libp2p::PeerId;
+
+    use spectrum_ledger::{ModifierId, ModifierType};
+    use spectrum_network::peer_manager::data::{PeerDestination, ReputationDecayPolicy, RetryPolicy};
+    use spectrum_network::peer_manager::peers_state::PeerRepo;
+    use spectrum_network::peer_manager::{
+        NetworkingConfig, PeerManager, PeerManagerConfig, PeerStorage, Peers, PeersMailbox,
+    };
+    use spectrum_network::types::Reputation;
+    use spectrum_validation::validation::InvalidModifier;
+    use spectrum_view::mempool::PackageError;
+    use spectrum_view::node_view::ModifierSource;
+
+    use crate::node_view::{PeerScoring, ValidationResultsHandler};
+
+    fn spawn_peer_manager() -> PeersMailbox {
+        let netw_conf = NetworkingConfig {
+            min_known_peers: 0,
+            min_outbound: 0,
+            max_inbound: 10,
+            max_outbound: 0,
+            peers_snapshot_interval: Duration::from_secs(60),
+            peers_storage: PeerStorage::Memory,
+            ..NetworkingConfig::default()
+        };
+        let conf = PeerManagerConfig {
+            min_acceptable_reputation: Reputation::from(-100),
+            min_reputation: Reputation::from(-100),
+            dial_retry_policy: RetryPolicy::default(),
+            reputation_decay: ReputationDecayPolicy::default(),
+            conn_alloc_interval: Duration::from_secs(30),
+            prot_alloc_interval: Duration::from_secs(30),
+            protocols_allocation: Vec::new(),
+            peer_manager_msg_buffer_size: 10,
+        };
+        let (pm, peers) = PeerManager::new(PeerRepo::new(netw_conf, vec![]), conf);
+        async_std::task::spawn(pm.for_each(|_| futures::future::ready(())));
+        peers
+    }
+
+    fn invalid_header(fatal: bool) -> InvalidModifier {
+        InvalidModifier {
+            modifier_id: ModifierId::random(),
+            modifier_type: ModifierType::BlockHeader,
+            fatal,
+            violations: vec![],
+        }
+    }
+
+    #[async_std::test]
+    async fn reputation_follows_validation_results() {
+        let mut peers = spawn_peer_manager();
+        let honest = PeerId::random();
+        let byzantine = PeerId::random();
+        peers.add_peers(vec![
+            PeerDestination::PeerId(honest),
+            PeerDestination::PeerId(byzantine),
+        ]);
+        let scoring = PeerScoring::new(peers.clone());
+        scoring.on_applied_modifier(ModifierId::random(), ModifierSource::Remote(honest));
+        // Not the peer's fault, most likely.
+        scoring.on_invalid_modifier(invalid_header(false), ModifierSource::Remote(honest));
+        scoring.on_rejected_package(PackageError::EmptyPackage, ModifierSource::Remote(byzantine));
+        scoring.on_invalid_modifier(invalid_header(true), ModifierSource::Local);
+        assert_eq!(
+            peers.get_peer_reputation(honest).await.unwrap(),
+            Reputation::from(1)
+        );
+        assert_eq!(
+            peers.get_peer_reputation(byzantine).await.unwrap(),
+            Reputation::from(-50)
+        );
+    }
+}
@@ -0,0 +1,276 @@
+//! Startup and shutdown of node subsystems in dependency order.
+//!
+//! Subsystems are started stage by stage: a stage is started only once every subsystem of
+//! the previous stages signalled readiness. Shutdown goes in the reverse order, so that no
+//! subsystem outlives the ones it depends on.
+
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_std::task::JoinHandle;
+use futures::channel::oneshot;
+use futures::future::{self, BoxFuture, Either};
+use futures::{Future, FutureExt};
+use log::{info, warn};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+use spectrum_network::cancellation::{CancellationToken, DropGuard, WaitForCancellation};
+
+/// Subsystems of the node in the order they have to be started.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+    Storage,
+    NodeView,
+    Network,
+    ProtocolHandlers,
+    Connectors,
+    Api,
+}
+
+/// Handle used by a subsystem to signal that it's ready to serve dependent subsystems.
+pub struct Ready(oneshot::Sender<()>);
+
+impl Ready {
+    pub fn notify(self) {
+        let _ = self.0.send(());
+    }
+}
+
+/// Resolves once the subsystem is requested to stop.
+pub struct Shutdown {
+    token: CancellationToken,
+    cancelled: WaitForCancellation,
+}
+
+impl Shutdown {
+    fn new(token: CancellationToken) -> Self {
+        Self {
+            cancelled: token.cancelled(),
+            token,
+        }
+    }
+
+    /// Token cancelled along with the subsystem, to be handed over to the work it runs,
+    /// e.g. [`NetworkBuilder::with_cancellation`](spectrum_network::network_builder::NetworkBuilder::with_cancellation).
+    pub fn token(&self) -> CancellationToken {
+        self.token.child_token()
+    }
+}
+
+impl Future for Shutdown {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.cancelled.poll_unpin(cx)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum SupervisorError {
+    #[error("Subsystem {0} terminated before signalling readiness")]
+    TerminatedEarly(String),
+    #[error("Subsystem {0} didn't become ready within {1:?}")]
+    ReadinessTimeout(String, Duration),
+}
+
+type RunSubsystem = Box<dyn FnOnce(Ready, Shutdown) -> BoxFuture<'static, ()> + Send>;
+
+struct PendingSubsystem {
+    name: String,
+    run: RunSubsystem,
+}
+
+struct RunningSubsystem {
+    name: String,
+    stage: Stage,
+    /// Supervisor gone is as good as an explicit request to stop.
+    stop: DropGuard,
+    handle: JoinHandle<()>,
+}
+
+pub struct Supervisor {
+    pending: BTreeMap<Stage, Vec<PendingSubsystem>>,
+    /// Subsystems in the order they were started.
+    running: Vec<RunningSubsystem>,
+    readiness_timeout: Duration,
+}
+
+impl Supervisor {
+    pub fn new(readiness_timeout: Duration) -> Self {
+        Self {
+            pending: BTreeMap::new(),
+            running: Vec::new(),
+            readiness_timeout,
+        }
+    }
+
+    /// Register a subsystem. `run` is expected to call [`Ready::notify`] once the subsystem
+    /// is operational and to return after [`Shutdown`] resolves.
+    pub fn add<F, Fut>(&mut self, stage: Stage, name: &str, run: F)
+    where
+        F: FnOnce(Ready, Shutdown) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.pending.entry(stage).or_default().push(PendingSubsystem {
+            name: name.to_string(),
+            run: Box::new(move |ready, shutdown| run(ready, shutdown).boxed()),
+        });
+    }
+
+    /// Start all registered subsystems in dependency order.
+    /// Subsystems started so far are shut down if any of them fails to become ready.
+    pub async fn start(&mut self) -> Result<(), SupervisorError> {
+        for (stage, subsystems) in std::mem::take(&mut self.pending) {
+            info!("[Supervisor] Starting {:?}", stage);
+            let mut readiness = Vec::new();
+            for PendingSubsystem { name, run } in subsystems {
+                let (ready_snd, ready_recv) = oneshot::channel();
+                let stop = CancellationToken::new();
+                let handle = async_std::task::spawn(run(Ready(ready_snd), Shutdown::new(stop.clone())));
+                readiness.push((name.clone(), ready_recv));
+                self.running.push(RunningSubsystem {
+                    name,
+                    stage,
+                    stop: stop.drop_guard(),
+                    handle,
+                });
+            }
+            for (name, ready) in readiness {
+                let res = match async_std::future::timeout(self.readiness_timeout, ready).await {
+                    Ok(Ok(())) => Ok(()),
+                    Ok(Err(_)) => Err(SupervisorError::TerminatedEarly(name)),
+                    Err(_) => Err(SupervisorError::ReadinessTimeout(name, self.readiness_timeout)),
+                };
+                if let Err(err) = res {
+                    warn!("[Supervisor] {}", err);
+                    self.shutdown().await;
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Stop running subsystems in the reverse order.
+    pub async fn shutdown(&mut self) {
+        while let Some(stage) = self.running.last().map(|s| s.stage) {
+            info!("[Supervisor] Stopping {:?}", stage);
+            let split_at = self.running.partition_point(|s| s.stage < stage);
+            let stopping = self.running.split_off(split_at);
+            let mut handles = Vec::new();
+            for RunningSubsystem {
+                name, stop, handle, ..
+            } in stopping
+            {
+                drop(stop);
+                handles.push(handle.map(move |_| name));
+            }
+            for name in future::join_all(handles).await {
+                info!("[Supervisor] {} stopped", name);
+            }
+        }
+    }
+
+    /// Start all subsystems and keep them running until `stop` resolves.
+    pub async fn run_until<S: Future<Output = ()> + Unpin>(mut self, stop: S) -> Result<(), SupervisorError> {
+        let pending_stop = {
+            let started = self.start();
+            futures::pin_mut!(started);
+            match future::select(started, stop).await {
+                Either::Left((res, stop)) => res.map(|_| Some(stop))?,
+                // Stop requested while still starting.
+                Either::Right(_) => None,
+            }
+        };
+        if let Some(stop) = pending_stop {
+            stop.await;
+        }
+        self.shutdown().await;
+        Ok(())
+    }
+}
+
+/// Resolves once the process is asked to terminate with SIGINT or SIGTERM.
+pub fn termination_signal() -> std::io::Result<BoxFuture<'static, ()>> {
+    let mut signals = Signals::new([SIGINT, SIGTERM])?;
+    Ok(async_std::task::spawn_blocking(move || {
+        if let Some(signal) = signals.forever().next() {
+            info!("[Supervisor] Received signal {}", signal);
+        }
+    })
+    .boxed())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use crate::supervisor::{Stage, Supervisor, SupervisorError};
+
+    fn add_recording(sv: &mut Supervisor, stage: Stage, name: &'static str, log: Arc<Mutex<Vec<String>>>) {
+        sv.add(stage, name, move |ready, shutdown| async move {
+            log.lock().unwrap().push(format!("start {}", name));
+            ready.notify();
+            shutdown.await;
+            log.lock().unwrap().push(format!("stop {}", name));
+        });
+    }
+
+    #[async_std::test]
+    async fn subsystems_start_in_order_and_stop_in_reverse() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut sv = Supervisor::new(Duration::from_secs(1));
+        add_recording(&mut sv, Stage::Connectors, "connector", log.clone());
+        add_recording(&mut sv, Stage::Network, "network", log.clone());
+        add_recording(&mut sv, Stage::Storage, "storage", log.clone());
+        add_recording(&mut sv, Stage::NodeView, "node_view", log.clone());
+        sv.start().await.unwrap();
+        sv.shutdown().await;
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![
+                "start storage",
+                "start node_view",
+                "start network",
+                "start connector",
+                "stop connector",
+                "stop network",
+                "stop node_view",
+                "stop storage",
+            ]
+        );
+    }
+
+    #[async_std::test]
+    async fn dependents_are_not_started_if_dependency_fails() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut sv = Supervisor::new(Duration::from_secs(1));
+        add_recording(&mut sv, Stage::Storage, "storage", log.clone());
+        sv.add(Stage::NodeView, "node_view", |_ready, _shutdown| async {});
+        add_recording(&mut sv, Stage::Connectors, "connector", log.clone());
+        assert_eq!(
+            sv.start().await,
+            Err(SupervisorError::TerminatedEarly("node_view".to_string()))
+        );
+        assert_eq!(*log.lock().unwrap(), vec!["start storage", "stop storage"]);
+    }
+
+    #[async_std::test]
+    async fn readiness_timeout() {
+        let mut sv = Supervisor::new(Duration::from_millis(50));
+        sv.add(Stage::Network, "network", |ready, shutdown| async move {
+            let _never_ready = ready;
+            shutdown.await;
+        });
+        assert_eq!(
+            sv.start().await,
+            Err(SupervisorError::ReadinessTimeout(
+                "network".to_string(),
+                Duration::from_millis(50)
+            ))
+        );
+    }
+}
@@ -1,7 +1,8 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::Write;
 use std::net::{IpAddr, SocketAddr};
 use std::ops::Sub;
+use std::path::PathBuf;
 use std::time::{Duration, Instant};
 
 use axum::extract::State;
@@ -14,19 +15,26 @@ use futures::{SinkExt, StreamExt};
 use k256::SecretKey;
 use libp2p::core::upgrade::Version;
 use libp2p::swarm::{SwarmBuilder, SwarmEvent};
-use libp2p::{identity, Multiaddr, PeerId, Transport};
+use libp2p::{Multiaddr, PeerId, Transport};
 use rand::rngs::OsRng;
 use rand::Rng;
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
 use spectrum_crypto::digest::{blake2b256_hash, Blake2b256, Blake2bDigest256};
+use spectrum_crypto::keystore::{libp2p_keypair, Keystore, KeystoreError};
 use spectrum_crypto::pubkey::PublicKey;
-use spectrum_network::network_controller::{NetworkController, NetworkControllerIn, NetworkMailbox};
-use spectrum_network::peer_conn_handler::PeerConnHandlerConf;
+use spectrum_network::network_controller::{
+    EnableRetryPolicy, NetworkController, NetworkControllerIn, NetworkMailbox,
+};
+use spectrum_network::peer_conn_handler::{IdleSubstreamPolicy, PeerConnHandlerConf};
+use spectrum_network::peer_manager::data::{ReputationDecayPolicy, RetryPolicy};
 use spectrum_network::peer_manager::peers_state::PeerRepo;
-use spectrum_network::peer_manager::{NetworkingConfig, PeerManager, PeerManagerConfig, PeersMailbox};
+use spectrum_network::peer_manager::{
+    NetworkingConfig, PeerManager, PeerManagerConfig, PeerStorage, PeersMailbox,
+};
 use spectrum_network::protocol::{
-    OneShotProtocolConfig, OneShotProtocolSpec, ProtocolConfig, SIGMA_AGGR_PROTOCOL_ID,
+    OneShotProtocolConfig, OneShotProtocolSpec, ProtocolConfig, ProtocolPriority, SIGMA_AGGR_PROTOCOL_ID,
+    SIGMA_AGGR_V2,
 };
 use spectrum_network::protocol_api::ProtocolMailbox;
 use spectrum_network::protocol_handler::aggregation::AggregationAction;
@@ -38,12 +46,14 @@ use spectrum_network::protocol_handler::multicasting::overlay::RedundancyDagOver
 use spectrum_network::protocol_handler::multicasting::DagMulticastingConfig;
 use spectrum_network::protocol_handler::sigma_aggregation::SigmaAggregation;
 use spectrum_network::protocol_handler::ProtocolHandler;
-use spectrum_network::types::{ProtocolVer, Reputation};
+use spectrum_network::transport::auth::CommitteeAuth;
+use spectrum_network::transport::TransportConfig;
+use spectrum_network::types::Reputation;
 use tokio::time::sleep;
 use tracing::{debug, trace};
 
 #[tokio::main]
-async fn main() {
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = AppArgs::parse();
     let command = Command::from(args.command);
     match command {
@@ -53,19 +63,21 @@ async fn main() {
                 .with_ansi(false)
                 .finish();
             tracing::subscriber::set_global_default(subscriber).unwrap();
+            if let Err(err) = config.transport.validate(&config.peer_addr()) {
+                panic!("Invalid transport config: {}", err);
+            }
             let addr = SocketAddr::from((
                 config.public_info.network_info.ip_address,
                 config.public_info.network_info.rest_api_port,
             ));
+            // Decrypted once, the KDF is way too slow to run on every request.
+            let peer_sk = config.load_peer_sk()?;
             let app: Router<(), _> = Router::new()
                 .route("/aggregate", post(aggregate))
-                .with_state(config);
+                .with_state(NodeState { config, peer_sk });
 
             tracing::debug!("listening on {}", addr);
-            axum::Server::bind(&addr)
-                .serve(app.into_make_service())
-                .await
-                .unwrap();
+            axum::Server::bind(&addr).serve(app.into_make_service()).await?;
         }
         Command::GenerateNewCommittee(form_new_committee) => {
             let mut members = vec![];
@@ -89,34 +101,49 @@ async fn main() {
             file.flush().unwrap();
         }
     }
+    Ok(())
+}
+
+/// State shared by handlers of the REST API.
+#[derive(Clone)]
+struct NodeState {
+    config: NodeConfig,
+    peer_sk: SecretKey,
 }
 
 async fn aggregate(
-    State(config): State<NodeConfig>,
+    State(NodeState { config, peer_sk }): State<NodeState>,
     Json(request): Json<SigmaAggregationRequest>,
 ) -> StatusCode {
     let one_shot_proto_conf = OneShotProtocolConfig {
-        version: ProtocolVer::default(),
+        version: SIGMA_AGGR_V2,
         spec: OneShotProtocolSpec {
             max_message_size: 5000,
         },
+        priority: ProtocolPriority::HIGH,
     };
     let peer_conn_handler_conf = PeerConnHandlerConf {
+        control_msg_buffer_size: 100,
         async_msg_buffer_size: 100,
         sync_msg_buffer_size: 100,
         open_timeout: Duration::from_secs(60),
         initial_keep_alive: Duration::from_secs(120),
+        idle_substream_policy: IdleSubstreamPolicy::KeepOpen,
     };
     let netw_config = NetworkingConfig {
         min_known_peers: 1,
         min_outbound: 1,
         max_inbound: 10,
         max_outbound: 20,
+        peers_snapshot_interval: Duration::from_secs(60),
+        peers_storage: PeerStorage::Memory,
+        transport: config.transport.clone(),
     };
     let peer_manager_conf = PeerManagerConfig {
         min_acceptable_reputation: Reputation::from(-50),
         min_reputation: Reputation::from(-20),
-        conn_reset_outbound_backoff: Duration::from_secs(120),
+        dial_retry_policy: RetryPolicy::default(),
+        reputation_decay: ReputationDecayPolicy::default(),
         conn_alloc_interval: Duration::from_secs(30),
         prot_alloc_interval: Duration::from_secs(30),
         protocols_allocation: Vec::new(),
@@ -130,6 +157,7 @@ async fn aggregate(
         dissemination_delay: Duration::from_millis(40),
         level_activation_delay: Duration::from_millis(50),
         throttle_factor: 5,
+        adaptive_activation: None,
     };
     let multicasting_conf = DagMulticastingConfig {
         processing_delay: Duration::from_millis(10),
@@ -143,8 +171,6 @@ async fn aggregate(
         seed: multicasting_conf.seed,
     };
     let gen_perm = PseudoRandomGenPerm::new(request.public_seed);
-    let peer_sk_bytes = base16::decode(&config.peer_sk_base_16).unwrap();
-    let peer_sk = k256::SecretKey::from_slice(&peer_sk_bytes).unwrap();
     let sig_aggr = SigmaAggregation::new(
         peer_sk.clone(),
         handel_conf,
@@ -155,6 +181,7 @@ async fn aggregate(
         overlay_builder,
         aggr_handler_inbox,
     );
+    let transport_conf = netw_config.transport.clone();
     let peer_state = PeerRepo::new(netw_config, vec![]);
     let (peer_manager, peers) = PeerManager::new(peer_state, peer_manager_conf);
     let (requests_snd, requests_recv) = mpsc::channel::<NetworkControllerIn>(100);
@@ -182,20 +209,19 @@ async fn aggregate(
         peers,
         peer_manager,
         requests_recv,
+        EnableRetryPolicy::default(),
     );
 
-    let peer_key = libp2p::identity::Keypair::from(libp2p::identity::secp256k1::Keypair::from(
-        k256_to_libsecp256k1(peer_sk.clone()),
-    ));
+    let peer_key = libp2p_keypair(&peer_sk);
 
-    let mut peer_addr = Multiaddr::from(config.public_info.network_info.ip_address);
-    peer_addr.push(libp2p::multiaddr::Protocol::Tcp(
-        config.public_info.network_info.peer_port,
+    let (abortable_peer, abort_handle) = futures::future::abortable(create_swarm(
+        peer_key.clone(),
+        CommitteeAuth::new(peer_sk.clone(), request.committee.keys().copied()),
+        nc.with_routing_hints(transport_conf.routing_hints.clone()),
+        transport_conf.tcp_config(),
+        config.peer_addr(),
     ));
 
-    let (abortable_peer, abort_handle) =
-        futures::future::abortable(create_swarm(peer_key.clone(), nc, peer_addr));
-
     tokio::task::spawn(async move {
         trace!("Spawning protocol handler..");
         loop {
@@ -213,6 +239,8 @@ async fn aggregate(
     async_std::task::block_on(aggr_handler_snd.send(AggregationAction::Reset {
         new_committee: request.committee,
         new_message: request.message,
+        excluded_members: request.excluded_members,
+        member_weights: request.member_weights,
         channel: snd,
     }))
     .unwrap();
@@ -252,6 +280,8 @@ async fn orchestrate_aggregation(orchestrate_aggr: OrchestrateAggregation, commi
         committee: committee_for_request,
         public_seed: orchestrate_aggr.public_seed,
         threshold: orchestrate_aggr.threshold,
+        excluded_members: HashSet::new(),
+        member_weights: HashMap::new(),
     };
 
     let mut join_handles = vec![];
@@ -297,20 +327,20 @@ async fn orchestrate_aggregation(orchestrate_aggr: OrchestrateAggregation, commi
     let _ = futures::future::join_all(join_handles).await;
 }
 
-fn k256_to_libsecp256k1(secret_key: k256::SecretKey) -> libp2p::identity::secp256k1::SecretKey {
-    libp2p::identity::secp256k1::SecretKey::try_from_bytes(secret_key.to_bytes().as_mut_slice()).unwrap()
-}
-
 async fn create_swarm(
     local_key: libp2p::identity::Keypair,
+    #[cfg_attr(not(feature = "noiseless"), allow(unused_variables))] committee_auth: CommitteeAuth,
     nc: NetworkController<PeersMailbox, PeerManager<PeerRepo>, ProtocolMailbox>,
+    tcp_conf: libp2p::tcp::Config,
     addr: Multiaddr,
 ) {
-    let transport = libp2p::tcp::async_io::Transport::default()
-        .upgrade(Version::V1Lazy)
-        .authenticate(libp2p::noise::Config::new(&local_key).unwrap()) // todo: avoid auth
-        .multiplex(libp2p::yamux::Config::default())
-        .boxed();
+    let transport = libp2p::tcp::async_io::Transport::new(tcp_conf).upgrade(Version::V1Lazy);
+    // Members of the committee authenticate each other by their keys, traffic isn't encrypted.
+    #[cfg(feature = "noiseless")]
+    let transport = transport.authenticate(committee_auth);
+    #[cfg(not(feature = "noiseless"))]
+    let transport = transport.authenticate(libp2p::noise::Config::new(&local_key).unwrap());
+    let transport = transport.multiplex(libp2p::yamux::Config::default()).boxed();
     let local_peer_id = PeerId::from(local_key.public());
     let mut swarm = SwarmBuilder::with_async_std_executor(transport, nc, local_peer_id).build();
 
@@ -329,7 +359,57 @@ async fn create_swarm(
 #[derive(Clone, Debug, Serialize, Deserialize)]
 struct NodeConfig {
     public_info: PublicNodeInfo,
+    /// Committee key of the node, unless it's kept in the keystore.
+    #[serde(default)]
     peer_sk_base_16: String,
+    #[serde(default)]
+    keystore: Option<KeystoreConfig>,
+    #[serde(default)]
+    transport: TransportConfig,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct KeystoreConfig {
+    dir: PathBuf,
+    /// Name of the committee key in the keystore.
+    key_name: String,
+    /// Env var holding the passphrase of the keystore.
+    passphrase_env: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+enum PeerKeyError {
+    #[error("Passphrase env var {0} isn't set")]
+    MissingPassphrase(String),
+    #[error("Failed to load committee key: {0}")]
+    Keystore(#[from] KeystoreError),
+    #[error("Invalid committee key in the config")]
+    InvalidKey,
+}
+
+impl NodeConfig {
+    fn load_peer_sk(&self) -> Result<SecretKey, PeerKeyError> {
+        match &self.keystore {
+            Some(conf) => {
+                let passphrase = std::env::var(&conf.passphrase_env)
+                    .map_err(|_| PeerKeyError::MissingPassphrase(conf.passphrase_env.clone()))?;
+                let keystore = Keystore::open(&conf.dir, &passphrase)?;
+                Ok(keystore.export(&conf.key_name)?)
+            }
+            None => base16::decode(&self.peer_sk_base_16)
+                .ok()
+                .and_then(|bytes| SecretKey::from_slice(&bytes).ok())
+                .ok_or(PeerKeyError::InvalidKey),
+        }
+    }
+
+    fn peer_addr(&self) -> Multiaddr {
+        let mut peer_addr = Multiaddr::from(self.public_info.network_info.ip_address);
+        peer_addr.push(libp2p::multiaddr::Protocol::Tcp(
+            self.public_info.network_info.peer_port,
+        ));
+        peer_addr
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -353,6 +433,12 @@ struct SigmaAggregationRequest {
     committee: HashMap<PublicKey, Option<Multiaddr>>,
     public_seed: [u8; 32],
     threshold: Threshold,
+    /// Members known to be lost, they aren't awaited during aggregation.
+    #[serde(default)]
+    excluded_members: HashSet<PublicKey>,
+    /// Weights (e.g. stake) of members, all members weigh the same if empty.
+    #[serde(default)]
+    member_weights: HashMap<PublicKey, usize>,
 }
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -527,9 +613,7 @@ fn generate_node_config_files(NodeIx(node_ix): NodeIx, network_info: &NodeNetwor
     let mut rng = OsRng;
     let peer_sk = SecretKey::random(&mut rng);
     let pub_key = peer_sk.public_key();
-    let peer_key = libp2p::identity::Keypair::from(identity::secp256k1::Keypair::from(k256_to_libsecp256k1(
-        peer_sk.clone(),
-    )));
+    let peer_key = libp2p_keypair(&peer_sk);
     let peer_id = PeerId::from(peer_key.public());
     let mut peer_addr = Multiaddr::from(network_info.ip_address);
     peer_addr.push(libp2p::multiaddr::Protocol::Tcp(network_info.peer_port));
@@ -540,6 +624,8 @@ fn generate_node_config_files(NodeIx(node_ix): NodeIx, network_info: &NodeNetwor
             network_info: network_info.clone(),
         },
         peer_sk_base_16: base16::encode_lower(&peer_sk.to_bytes().to_vec()),
+        keystore: None,
+        transport: TransportConfig::default(),
     };
 
     let yaml_string = serde_yaml::to_string(&node_config).unwrap();
@@ -47,6 +47,16 @@ pub fn aggregate_pk(committee: Vec<PublicKey>, individual_inputs: Vec<Scalar>) -
     )
 }
 
+/// Digest of the committee: hash of the concatenated compressed public keys of members.
+/// Stored in R9 of the first committee box on Ergo.
+pub fn committee_hash(committee: &[PublicKey]) -> Blake2bDigest256 {
+    let c_bytes = committee.iter().fold(Vec::<u8>::new(), |mut b, p| {
+        b.extend_from_slice(&k256::PublicKey::from(*p).to_encoded_point(true).to_bytes());
+        b
+    });
+    blake2b256_hash(&c_bytes)
+}
+
 /// `Y = Π_iY_i`
 pub fn aggregate_commitment(individual_commitments: Vec<Commitment>) -> AggregateCommitment {
     AggregateCommitment::try_from(
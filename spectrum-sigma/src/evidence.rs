@@ -0,0 +1,294 @@
+//! Evidence of misbehaviour of committee members during aggregation.
+//!
+//! Aggregation tolerates byzantine members by leaving them out of the aggregate signature.
+//! Evidence records what a member did in a form anyone knowing the committee can recheck, so that
+//! the member can be slashed on-chain later. Messages of the protocol aren't signed by their
+//! senders, hence attribution rests on the member who observed the misbehaviour and signed the
+//! evidence. Members which ended up with different sets of commitments respond to different
+//! challenges, so evidence of invalid responses must be corroborated by enough reporters.
+
+use std::fmt::Debug;
+use std::io;
+use std::marker::PhantomData;
+use std::path::PathBuf;
+
+use derivative::Derivative;
+use digest::{FixedOutput, HashMarker};
+use elliptic_curve::Curve;
+use k256::schnorr::signature::Verifier;
+use k256::schnorr::VerifyingKey;
+use k256::{Scalar, Secp256k1};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use spectrum_crypto::digest::{blake2b256_hash, Digest};
+use spectrum_crypto::pubkey::PublicKey;
+use spectrum_crypto::signer::{verifying_key, Signer, SignerError};
+use spectrum_crypto::VerifiableAgainst;
+
+use crate::crypto::{aggregate_pk, challenge, individual_input, verify_response};
+use crate::{AggregateCommitment, Commitment, Signature};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum Misbehaviour {
+    /// Response which doesn't match the commitment of the member: `g^{z_i} != Y_i * X_i^{a_i * c}`,
+    /// where `c` is derived from the aggregate commitment `Y`.
+    InvalidResponse {
+        commitment: Commitment,
+        aggregate_commitment: AggregateCommitment,
+        response: Scalar,
+    },
+    /// Two distinct commitments to the same message, each with a valid dlog proof.
+    EquivocatingCommitments {
+        first: (Commitment, Signature),
+        second: (Commitment, Signature),
+    },
+}
+
+/// Misbehaviour of a member while aggregating a signature of the given message.
+#[derive(Debug, Derivative, Clone, Serialize, Deserialize)]
+#[derivative(Eq(bound = "H: FixedOutput"), PartialEq(bound = "H: FixedOutput"))]
+#[serde(bound = "H: Debug")]
+pub struct Evidence<H: FixedOutput> {
+    pub offender: PublicKey,
+    pub message_digest: Digest<H>,
+    pub misbehaviour: Misbehaviour,
+}
+
+/// Verified against the committee `{X_1, X_2, ..., X_n}` ordered by `PeerIx`.
+impl<H> VerifiableAgainst<Vec<PublicKey>> for Evidence<H>
+where
+    H: HashMarker + FixedOutput<OutputSize = <Secp256k1 as Curve>::FieldBytesSize> + Default,
+{
+    fn verify(&self, committee: &Vec<PublicKey>) -> bool {
+        if !committee.contains(&self.offender) {
+            return false;
+        }
+        match &self.misbehaviour {
+            Misbehaviour::InvalidResponse {
+                commitment,
+                aggregate_commitment,
+                response,
+            } => {
+                let individual_inputs = committee
+                    .iter()
+                    .map(|pk| individual_input::<H>(committee.clone(), *pk))
+                    .collect();
+                let aggr_pk = aggregate_pk(committee.clone(), individual_inputs);
+                let c = challenge(aggr_pk, aggregate_commitment.clone(), self.message_digest);
+                let ai = individual_input::<H>(committee.clone(), self.offender);
+                !verify_response(response, &ai, &c, commitment.clone(), self.offender)
+            }
+            Misbehaviour::EquivocatingCommitments { first, second } => {
+                let proven = |(commitment, proof): &(Commitment, Signature)| {
+                    commitment
+                        .0
+                        .verify(self.message_digest.as_ref(), &proof.0)
+                        .is_ok()
+                };
+                first.0 != second.0 && proven(first) && proven(second)
+            }
+        }
+    }
+}
+
+/// Evidence attested by the member who observed the misbehaviour.
+#[derive(Debug, Derivative, Clone, Serialize, Deserialize)]
+#[derivative(Eq(bound = "H: FixedOutput"), PartialEq(bound = "H: FixedOutput"))]
+#[serde(bound = "H: Debug")]
+pub struct SignedEvidence<H: FixedOutput> {
+    pub evidence: Evidence<H>,
+    pub reporter: PublicKey,
+    /// Signature of the encoded evidence under the key of the reporter.
+    pub signature: Signature,
+}
+
+impl<H: FixedOutput + Debug> SignedEvidence<H> {
+    pub fn sign(evidence: Evidence<H>, signer: &dyn Signer) -> Result<Self, SignerError> {
+        let signature = signer.sign(&bincode::serialize(&evidence).unwrap())?;
+        Ok(Self {
+            evidence,
+            reporter: signer.public_key(),
+            signature: Signature::from(signature),
+        })
+    }
+}
+
+/// Verified against the committee both the reporter and the offender are members of.
+impl<H> VerifiableAgainst<Vec<PublicKey>> for SignedEvidence<H>
+where
+    H: HashMarker + FixedOutput<OutputSize = <Secp256k1 as Curve>::FieldBytesSize> + Default + Debug,
+{
+    fn verify(&self, committee: &Vec<PublicKey>) -> bool {
+        let attested = VerifyingKey::verify(
+            &verifying_key(self.reporter),
+            &bincode::serialize(&self.evidence).unwrap(),
+            &self.signature.0,
+        )
+        .is_ok();
+        attested
+            && self.reporter != self.evidence.offender
+            && committee.contains(&self.reporter)
+            && self.evidence.verify(committee)
+    }
+}
+
+/// Storage of collected evidence.
+pub trait EvidenceStore<H: FixedOutput>: Send {
+    /// Store the evidence. Storing the same evidence again must succeed.
+    fn put(&mut self, evidence: &SignedEvidence<H>) -> io::Result<()>;
+    fn get_all(&self) -> io::Result<Vec<SignedEvidence<H>>>;
+}
+
+/// Store keeping a file per evidence in the given directory.
+pub struct FsEvidenceStore<H> {
+    pub root: PathBuf,
+    pd: PhantomData<H>,
+}
+
+impl<H> FsEvidenceStore<H> {
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            pd: PhantomData,
+        }
+    }
+}
+
+impl<H> EvidenceStore<H> for FsEvidenceStore<H>
+where
+    H: FixedOutput + Debug + Send,
+    SignedEvidence<H>: DeserializeOwned,
+{
+    fn put(&mut self, evidence: &SignedEvidence<H>) -> io::Result<()> {
+        std::fs::create_dir_all(&self.root)?;
+        let bytes = bincode::serialize(evidence).unwrap();
+        // Evidence is named after its content, so that it's recorded at most once.
+        let path = self.root.join(format!("{}.evidence", blake2b256_hash(&bytes)));
+        // Written aside first, so that evidence is never read partially written.
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, bytes)?;
+        std::fs::rename(&tmp, &path)
+    }
+
+    fn get_all(&self) -> io::Result<Vec<SignedEvidence<H>>> {
+        let entries = match std::fs::read_dir(&self.root) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err),
+        };
+        let mut evidence = vec![];
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "evidence") {
+                let bytes = std::fs::read(&path)?;
+                evidence.push(
+                    bincode::deserialize(&bytes)
+                        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+                );
+            }
+        }
+        Ok(evidence)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::schnorr::signature::Signer as _;
+    use k256::schnorr::SigningKey;
+    use k256::{Scalar, SecretKey};
+    use rand::rngs::OsRng;
+
+    use spectrum_crypto::digest::{blake2b256_hash, Blake2b256};
+    use spectrum_crypto::pubkey::PublicKey;
+    use spectrum_crypto::signer::InMemorySigner;
+    use spectrum_crypto::VerifiableAgainst;
+
+    use crate::crypto::{
+        aggregate_commitment, aggregate_pk, challenge, individual_input, response, schnorr_commitment_pair,
+    };
+    use crate::evidence::{Evidence, EvidenceStore, FsEvidenceStore, Misbehaviour, SignedEvidence};
+    use crate::{Commitment, Signature};
+
+    #[test]
+    fn invalid_response_evidence() {
+        let sks = (0..3).map(|_| SecretKey::random(&mut OsRng)).collect::<Vec<_>>();
+        let committee = sks
+            .iter()
+            .map(|sk| PublicKey::from(sk.clone()))
+            .collect::<Vec<_>>();
+        let md = blake2b256_hash(b"report");
+        let (secret, commitment) = schnorr_commitment_pair();
+        let aggr_commitment = aggregate_commitment(vec![commitment.clone()]);
+        let inputs = committee
+            .iter()
+            .map(|pk| individual_input::<Blake2b256>(committee.clone(), *pk))
+            .collect::<Vec<_>>();
+        let c = challenge(
+            aggregate_pk(committee.clone(), inputs.clone()),
+            aggr_commitment.clone(),
+            md,
+        );
+        let valid_response = response(secret, sks[1].clone(), c, inputs[1]);
+        let evidence = |response: Scalar| Evidence {
+            offender: committee[1],
+            message_digest: md,
+            misbehaviour: Misbehaviour::InvalidResponse {
+                commitment: commitment.clone(),
+                aggregate_commitment: aggr_commitment.clone(),
+                response,
+            },
+        };
+        assert!(!evidence(valid_response).verify(&committee));
+        assert!(evidence(valid_response + Scalar::ONE).verify(&committee));
+
+        let reporter = InMemorySigner::new(sks[0].clone());
+        let signed = SignedEvidence::sign(evidence(valid_response + Scalar::ONE), &reporter).unwrap();
+        assert!(signed.verify(&committee));
+        // Reporter outside of the committee.
+        assert!(!signed.verify(&committee[1..].to_vec()));
+        let mut forged = signed.clone();
+        forged.evidence.offender = committee[2];
+        assert!(!forged.verify(&committee));
+    }
+
+    #[test]
+    fn equivocating_commitments_evidence() {
+        let sks = (0..3).map(|_| SecretKey::random(&mut OsRng)).collect::<Vec<_>>();
+        let committee = sks
+            .iter()
+            .map(|sk| PublicKey::from(sk.clone()))
+            .collect::<Vec<_>>();
+        let md = blake2b256_hash(b"report");
+        let commit = || {
+            let secret = SigningKey::random(&mut OsRng);
+            (
+                Commitment(*secret.verifying_key()),
+                Signature::from(secret.sign(md.as_ref())),
+            )
+        };
+        let first = commit();
+        let evidence = |second| Evidence {
+            offender: committee[2],
+            message_digest: md,
+            misbehaviour: Misbehaviour::EquivocatingCommitments {
+                first: first.clone(),
+                second,
+            },
+        };
+        assert!(evidence(commit()).verify(&committee));
+        assert!(!evidence(first.clone()).verify(&committee));
+        let mut unproven = commit();
+        unproven.1 = first.1.clone();
+        assert!(!evidence(unproven).verify(&committee));
+
+        let root = std::env::temp_dir().join(format!("spectrum-evidence-{}", rand::random::<u64>()));
+        let mut store = FsEvidenceStore::new(root.clone());
+        assert!(store.get_all().unwrap().is_empty());
+        let signed = SignedEvidence::sign(evidence(commit()), &InMemorySigner::new(sks[0].clone())).unwrap();
+        store.put(&signed).unwrap();
+        store.put(&signed).unwrap();
+        assert_eq!(store.get_all().unwrap(), vec![signed]);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
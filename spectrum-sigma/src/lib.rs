@@ -19,6 +19,7 @@ use spectrum_handel::Weighted;
 use crate::crypto::verify_response;
 
 pub mod crypto;
+pub mod evidence;
 pub mod message;
 pub mod sigma_aggregation;
 
@@ -1,6 +1,7 @@
 use derivative::Derivative;
 use std::collections::{HashMap, VecDeque};
 use std::fmt::Debug;
+use std::future::Future;
 use std::mem;
 use std::pin::Pin;
 use std::task::{Context, Poll};
@@ -13,12 +14,17 @@ use futures::channel::mpsc::Receiver;
 use futures::channel::oneshot::Sender;
 use futures::Stream;
 use higher::Bifunctor;
-use k256::{Scalar, Secp256k1, SecretKey};
+use k256::schnorr::signature::Verifier;
+use k256::schnorr::VerifyingKey;
+use k256::{Scalar, Secp256k1};
 use libp2p::{Multiaddr, PeerId};
-use tracing::{info, trace, trace_span};
+use tracing::{info, trace, trace_span, warn};
 
 use spectrum_crypto::digest::Digest;
 use spectrum_crypto::pubkey::PublicKey;
+use spectrum_crypto::signer::{AsyncSigner, SignerFuture};
+use spectrum_crypto::VerifiableAgainst;
+use spectrum_handel::message::HandelMessage;
 use spectrum_handel::partitioning::{MakePeerPartitions, PeerIx, PeerPartitions};
 use spectrum_handel::{Handel, HandelConfig, HandelRound};
 use spectrum_mcast::behaviour::DagMulticastingConfig;
@@ -29,13 +35,13 @@ use spectrum_network::protocol_handler::ProtocolBehaviourOut;
 use spectrum_network::protocol_handler::{ProtocolBehaviour, TemporalProtocolStage};
 
 use crate::crypto::{
-    aggregate_commitment, aggregate_pk, aggregate_response, challenge, exclusion_proof, individual_input,
-    pre_commitment, response, schnorr_commitment_pair,
+    aggregate_commitment, aggregate_pk, aggregate_response, challenge, individual_input, pre_commitment,
 };
+use crate::evidence::{Evidence, EvidenceStore, Misbehaviour, SignedEvidence};
 use crate::message::{SigmaAggrMessage, SigmaAggrMessageV1, SigmaAggrSpec};
 use crate::{
-    AggregateCommitment, Commitment, CommitmentSecret, CommitmentsVerifInput, CommitmentsWithProofs,
-    Contributions, PreCommitments, Responses, ResponsesVerifInput, Signature,
+    AggregateCommitment, Commitment, CommitmentsVerifInput, CommitmentsWithProofs, Contributions,
+    PreCommitments, Responses, ResponsesVerifInput, Signature,
 };
 
 pub enum AggregationAction<H: HashMarker + FixedOutput> {
@@ -48,8 +54,6 @@ pub enum AggregationAction<H: HashMarker + FixedOutput> {
 }
 
 struct AggregatePreCommitments<'a, H: FixedOutput, PP> {
-    /// `x_i`
-    host_sk: SecretKey,
     /// Host's index in the Handel overlay.
     host_ix: PeerIx,
     /// `{X_1, X_2, ..., X_n}`. Set of public keys of committee members.
@@ -58,9 +62,7 @@ struct AggregatePreCommitments<'a, H: FixedOutput, PP> {
     individual_inputs: HashMap<PeerIx, Scalar>,
     /// Message that we aggregate signatures for.
     message_digest: Digest<H>,
-    /// `y_i`
-    host_secret: CommitmentSecret,
-    /// `Y_i = g^{y_i}`
+    /// `Y_i = g^{y_i}`, `y_i` is held by the signer.
     host_commitment: Commitment,
     /// `σ_i`. Dlog proof of knowledge for `Y_i`.
     host_explusion_proof: Signature,
@@ -76,15 +78,15 @@ where
     PP: PeerPartitions + Clone + Send + 'static,
 {
     fn init<MPP: MakePeerPartitions<PP = PP>, OB: MakeDagOverlay>(
-        host_sk: SecretKey,
+        host_pk: PublicKey,
         committee: HashMap<PublicKey, Option<Multiaddr>>,
         message_digest: Digest<H>,
+        (host_commitment, host_explusion_proof): (VerifyingKey, k256::schnorr::Signature),
         partitioner: MPP,
         mcast_overlay_builder: OB,
         handel_conf: HandelConfig,
         multicasting_conf: DagMulticastingConfig,
     ) -> AggregatePreCommitments<'a, H, PP> {
-        let host_pk = PublicKey::from(host_sk.clone());
         let host_pid = PeerId::from(host_pk);
         let peers = committee
             .iter()
@@ -109,19 +111,17 @@ where
             .iter()
             .map(|(pix, pk)| (*pix, individual_input::<H>(committee_keys.clone(), pk.clone())))
             .collect();
-        let (host_secret, host_commitment) = schnorr_commitment_pair();
+        let host_commitment = Commitment(host_commitment);
         let host_pre_commitment = pre_commitment(host_commitment.clone());
         let host_ix = partitions.try_index_peer(host_pid).unwrap();
         trace!("[SA] {:?} <-> {:?}", host_pid, host_ix);
         AggregatePreCommitments {
-            host_sk,
             host_ix,
             committee: committee_indexed,
             individual_inputs: ais,
             message_digest: message_digest,
-            host_secret: host_secret.clone(),
             host_commitment,
-            host_explusion_proof: exclusion_proof(host_secret, message_digest),
+            host_explusion_proof: Signature::from(host_explusion_proof),
             mcast_overlay,
             multicasting_conf,
             partitions: partitions.clone(),
@@ -142,12 +142,10 @@ where
     ) -> BroadcastPreCommitments<H, PP> {
         let handel_partitions = self.handel.narrow();
         BroadcastPreCommitments {
-            host_sk: self.host_sk,
             host_ix: self.host_ix,
             committee: self.committee,
             individual_inputs: self.individual_inputs,
             message_digest: self.message_digest,
-            host_secret: self.host_secret,
             host_commitment: self.host_commitment.clone(),
             host_explusion_proof: self.host_explusion_proof.clone(),
             handel_partitions: handel_partitions.clone(),
@@ -165,8 +163,6 @@ where
 }
 
 struct BroadcastPreCommitments<H: FixedOutput, PP> {
-    /// `x_i`
-    host_sk: SecretKey,
     /// Host's index in the Handel overlay.
     host_ix: PeerIx,
     /// `{X_1, X_2, ..., X_n}`. Set of public keys of committee members.
@@ -175,9 +171,7 @@ struct BroadcastPreCommitments<H: FixedOutput, PP> {
     individual_inputs: HashMap<PeerIx, Scalar>,
     /// Message that we aggregate signatures for.
     message_digest: Digest<H>,
-    /// `y_i`
-    host_secret: CommitmentSecret,
-    /// `Y_i = g^{y_i}`
+    /// `Y_i = g^{y_i}`, `y_i` is held by the signer.
     host_commitment: Commitment,
     /// `σ_i`. Dlog proof of knowledge for `Y_i`.
     host_explusion_proof: Signature,
@@ -201,17 +195,16 @@ where
             message_digest_bytes: self.message_digest.as_ref().to_vec(),
         };
         AggregateCommitments {
-            host_sk: self.host_sk,
             host_ix: self.host_ix,
             committee: self.committee,
             individual_inputs: self.individual_inputs,
             message_digest: self.message_digest,
-            host_secret: self.host_secret,
             host_commitment: self.host_commitment.clone(),
             host_explusion_proof: self.host_explusion_proof.clone(),
             mcast_overlay: self.mcast_overlay,
             multicasting_conf: self.multicasting_conf,
             partitions: self.handel_partitions.clone(),
+            seen_commitments: HashMap::new(),
             handel: Box::new(Handel::new(
                 handel_conf,
                 Contributions::unit(self.host_ix, (self.host_commitment, self.host_explusion_proof)),
@@ -224,8 +217,6 @@ where
 }
 
 struct AggregateCommitments<'a, H: FixedOutput, PP> {
-    /// `x_i`
-    host_sk: SecretKey,
     /// Host's index in the Handel overlay.
     host_ix: PeerIx,
     /// `{X_1, X_2, ..., X_n}`. Set of public keys of committee members.
@@ -234,15 +225,15 @@ struct AggregateCommitments<'a, H: FixedOutput, PP> {
     individual_inputs: HashMap<PeerIx, Scalar>,
     /// Message that we aggregate signatures for.
     message_digest: Digest<H>,
-    /// `y_i`
-    host_secret: CommitmentSecret,
-    /// `Y_i = g^{y_i}`
+    /// `Y_i = g^{y_i}`, `y_i` is held by the signer.
     host_commitment: Commitment,
     /// `σ_i`. Dlog proof of knowledge for `Y_i`.
     host_explusion_proof: Signature,
     mcast_overlay: DagOverlay,
     multicasting_conf: DagMulticastingConfig,
     partitions: PP,
+    /// Commitments members sent on their own behalf, kept to detect equivocation.
+    seen_commitments: HashMap<PeerIx, (Commitment, Signature)>,
     handel: Box<dyn HandelRound<'a, CommitmentsWithProofs, PP> + Send>,
 }
 
@@ -253,12 +244,10 @@ where
     fn complete(self, commitments_with_proofs: CommitmentsWithProofs) -> BroadcastCommitments<H, PP> {
         let handel_partitions = self.handel.narrow();
         BroadcastCommitments {
-            host_sk: self.host_sk,
             host_ix: self.host_ix,
             committee: self.committee,
             individual_inputs: self.individual_inputs,
             message_digest: self.message_digest,
-            host_secret: self.host_secret,
             host_commitment: self.host_commitment.clone(),
             host_explusion_proof: self.host_explusion_proof.clone(),
             handel_partitions: handel_partitions.clone(),
@@ -271,11 +260,38 @@ where
             )),
         }
     }
+
+    /// Compare the commitment the sender made on its own behalf with the one it made before, if any.
+    fn detect_equivocation(
+        &mut self,
+        sender: PeerIx,
+        msg: &HandelMessage<CommitmentsWithProofs>,
+    ) -> Option<Evidence<H>> {
+        let offender = *self.committee.get(&sender)?;
+        let commitment = msg.individual_contribution.as_ref()?.get(&sender)?.clone();
+        let (Commitment(yi), Signature(proof)) = &commitment;
+        if yi.verify(self.message_digest.as_ref(), proof).is_err() {
+            return None;
+        }
+        match self.seen_commitments.get(&sender) {
+            Some(first) if first.0 != commitment.0 => Some(Evidence {
+                offender,
+                message_digest: self.message_digest.clone(),
+                misbehaviour: Misbehaviour::EquivocatingCommitments {
+                    first: first.clone(),
+                    second: commitment,
+                },
+            }),
+            Some(_) => None,
+            None => {
+                self.seen_commitments.insert(sender, commitment);
+                None
+            }
+        }
+    }
 }
 
 struct BroadcastCommitments<H: FixedOutput, PP> {
-    /// `x_i`
-    host_sk: SecretKey,
     /// Host's index in the Handel overlay.
     host_ix: PeerIx,
     /// `{X_1, X_2, ..., X_n}`. Set of public keys of committee members.
@@ -284,9 +300,7 @@ struct BroadcastCommitments<H: FixedOutput, PP> {
     individual_inputs: HashMap<PeerIx, Scalar>,
     /// Message that we aggregate signatures for.
     message_digest: Digest<H>,
-    /// `y_i`
-    host_secret: CommitmentSecret,
-    /// `Y_i = g^{y_i}`
+    /// `Y_i = g^{y_i}`, `y_i` is held by the signer.
     host_commitment: Commitment,
     /// `σ_i`. Dlog proof of knowledge for `Y_i`.
     host_explusion_proof: Signature,
@@ -294,20 +308,18 @@ struct BroadcastCommitments<H: FixedOutput, PP> {
     mcast: Box<dyn Multicasting<CommitmentsWithProofs> + Send>,
 }
 
-impl<'a, H, PP> BroadcastCommitments<H, PP>
+impl<H, PP> BroadcastCommitments<H, PP>
 where
     H: HashMarker + FixedOutput<OutputSize = <Secp256k1 as Curve>::FieldBytesSize> + Default,
-    PP: PeerPartitions + Send + Clone + 'a,
+    PP: PeerPartitions + Send + Clone,
 {
     fn complete(
         self,
         commitments_with_proofs_intersect: CommitmentsWithProofs,
-        handel_conf: HandelConfig,
-    ) -> AggregateResponses<'a, H, PP> {
+        signer: &AsyncSigner,
+    ) -> AwaitResponse<H, PP> {
         // Need to ensure stable ordering for committee and individual inputs. Just sort by PeerIx.
-        let mut committee = self.committee.clone().into_iter().collect::<Vec<_>>();
-        committee.sort_by_key(|k| k.0);
-        let committee = committee.into_iter().map(|(_, key)| key).collect();
+        let committee = ordered_committee(&self.committee);
 
         let mut individual_inputs = self.individual_inputs.clone().into_iter().collect::<Vec<_>>();
         individual_inputs.sort_by_key(|k| k.0);
@@ -323,29 +335,66 @@ where
         );
         let challenge = challenge(aggr_pk, aggr_commitment.clone(), self.message_digest);
         let individual_input = *self.individual_inputs.get(&self.host_ix).unwrap();
-        let host_response = response(
-            self.host_secret.clone(),
-            self.host_sk.clone(),
-            challenge,
-            individual_input,
-        );
+        // `z_i = y_i + c * a_i * x_i`
+        let response = signer.schnorr_response(self.host_commitment.0, challenge * individual_input);
         let verif_inputs = ResponsesVerifInput::new(
             commitments_with_proofs_intersect.clone(),
             self.committee.clone(),
             self.individual_inputs.clone(),
             challenge,
         );
-        AggregateResponses {
+        AwaitResponse {
             message_digest: self.message_digest,
             aggr_commitment,
             commitments_with_proofs: commitments_with_proofs_intersect,
+            committee: self.committee,
             host_ix: self.host_ix,
-            partitions: self.handel_partitions.clone(),
+            partitions: self.handel_partitions,
+            verif_inputs,
+            response,
+        }
+    }
+}
+
+/// Waiting for the signer to commit to the message.
+struct AwaitCommitment<H: FixedOutput> {
+    committee: HashMap<PublicKey, Option<Multiaddr>>,
+    message_digest: Digest<H>,
+    /// `(Y_i, σ_i)` being produced by the signer.
+    commitment: SignerFuture<(VerifyingKey, k256::schnorr::Signature)>,
+}
+
+/// Waiting for the signer to respond to the challenge.
+struct AwaitResponse<H: FixedOutput, PP> {
+    message_digest: Digest<H>,
+    aggr_commitment: AggregateCommitment,
+    commitments_with_proofs: CommitmentsWithProofs,
+    /// `{X_1, X_2, ..., X_n}`. Set of public keys of committee members.
+    committee: HashMap<PeerIx, PublicKey>,
+    host_ix: PeerIx,
+    partitions: PP,
+    verif_inputs: ResponsesVerifInput,
+    /// `z_i` being produced by the signer.
+    response: SignerFuture<Scalar>,
+}
+
+impl<'a, H: FixedOutput, PP> AwaitResponse<H, PP>
+where
+    PP: PeerPartitions + Send + Clone + 'a,
+{
+    fn complete(self, host_response: Scalar, handel_conf: HandelConfig) -> AggregateResponses<'a, H, PP> {
+        AggregateResponses {
+            message_digest: self.message_digest,
+            aggr_commitment: self.aggr_commitment,
+            commitments_with_proofs: self.commitments_with_proofs,
+            committee: self.committee,
+            host_ix: self.host_ix,
+            partitions: self.partitions.clone(),
             handel: Box::new(Handel::new(
                 handel_conf,
                 Contributions::unit(self.host_ix, host_response),
-                verif_inputs,
-                self.handel_partitions,
+                self.verif_inputs,
+                self.partitions,
                 self.host_ix,
             )),
         }
@@ -356,6 +405,8 @@ struct AggregateResponses<'a, H: FixedOutput, PP> {
     message_digest: Digest<H>,
     aggr_commitment: AggregateCommitment,
     commitments_with_proofs: CommitmentsWithProofs,
+    /// `{X_1, X_2, ..., X_n}`. Set of public keys of committee members.
+    committee: HashMap<PeerIx, PublicKey>,
     host_ix: PeerIx,
     partitions: PP,
     handel: Box<dyn HandelRound<'a, Responses, PP> + Send>,
@@ -377,6 +428,30 @@ impl<'a, H: HashMarker + FixedOutput, PP> AggregateResponses<'a, H, PP> {
             exclusion_set,
         }
     }
+
+    /// Suspected invalid response the sender made on its own behalf. It's only evidence if it doesn't
+    /// verify, see [`Evidence::verify`].
+    fn suspect_response(&self, sender: PeerIx, msg: &HandelMessage<Responses>) -> Option<Evidence<H>> {
+        let offender = *self.committee.get(&sender)?;
+        let response = *msg.individual_contribution.as_ref()?.get(&sender)?;
+        let (commitment, _) = self.commitments_with_proofs.get(&sender)?;
+        Some(Evidence {
+            offender,
+            message_digest: self.message_digest.clone(),
+            misbehaviour: Misbehaviour::InvalidResponse {
+                commitment: commitment.clone(),
+                aggregate_commitment: self.aggr_commitment.clone(),
+                response,
+            },
+        })
+    }
+}
+
+/// Committee keys ordered by their `PeerIx`.
+fn ordered_committee(committee: &HashMap<PeerIx, PublicKey>) -> Vec<PublicKey> {
+    let mut committee = committee.iter().collect::<Vec<_>>();
+    committee.sort_by_key(|(pix, _)| **pix);
+    committee.into_iter().map(|(_, pk)| *pk).collect()
 }
 
 /// Result of an aggregation.
@@ -391,10 +466,12 @@ pub struct AggregateCertificate<H: FixedOutput> {
 }
 
 enum AggregationState<'a, H: FixedOutput, PP> {
+    AwaitCommitment(AwaitCommitment<H>),
     AggregatePreCommitments(AggregatePreCommitments<'a, H, PP>),
     BroadcastPreCommitments(BroadcastPreCommitments<H, PP>),
     AggregateCommitments(AggregateCommitments<'a, H, PP>),
     BroadcastCommitments(BroadcastCommitments<H, PP>),
+    AwaitResponse(AwaitResponse<H, PP>),
     AggregateResponses(AggregateResponses<'a, H, PP>),
 }
 
@@ -464,9 +541,13 @@ where
     H: HashMarker + FixedOutput,
     MPP: MakePeerPartitions,
 {
-    host_sk: SecretKey,
+    signer: AsyncSigner,
     handel_conf: HandelConfig,
     multicasting_conf: DagMulticastingConfig,
+    /// Evidence of misbehaviour of other members is only collected when the store is set.
+    evidence_store: Option<Box<dyn EvidenceStore<H>>>,
+    /// Evidence being signed by the host.
+    pending_evidence: Vec<SignerFuture<SignedEvidence<H>>>,
     task: Option<AggregationTask<'a, H, MPP::PP>>,
     stash: MessageStash,
     partitioner: MPP,
@@ -492,8 +573,9 @@ where
     MPP: MakePeerPartitions + Clone,
     MPP::PP: Clone + 'static,
 {
+    /// Host key is held by the `signer`, e.g. a spawned [`spectrum_crypto::signer::InMemorySigner`].
     pub fn new(
-        host_sk: SecretKey,
+        signer: AsyncSigner,
         handel_conf: HandelConfig,
         multicasting_conf: DagMulticastingConfig,
         partitioner: MPP,
@@ -501,9 +583,11 @@ where
         inbox: Receiver<AggregationAction<H>>,
     ) -> Self {
         Self {
-            host_sk,
+            signer,
             handel_conf,
             multicasting_conf,
+            evidence_store: None,
+            pending_evidence: Vec::new(),
             task: None,
             stash: MessageStash::new(),
             partitioner,
@@ -513,9 +597,58 @@ where
         }
     }
 
+    /// Collect evidence of misbehaviour of other members into the given store.
+    pub fn with_evidence_store(mut self, store: Box<dyn EvidenceStore<H>>) -> Self {
+        self.evidence_store = Some(store);
+        self
+    }
+
+    fn record_evidence(&mut self, evidence: Evidence<H>)
+    where
+        H: Debug + Send + 'static,
+    {
+        if self.evidence_store.is_some() {
+            info!(
+                "[SA] Recording evidence of misbehaviour of {:?}",
+                evidence.offender
+            );
+            self.pending_evidence.push(
+                self.signer
+                    .call(move |signer| SignedEvidence::sign(evidence, signer)),
+            );
+        }
+    }
+
+    /// Store evidence once it's signed.
+    fn poll_evidence(&mut self, cx: &mut Context<'_>) {
+        let store = match self.evidence_store.as_mut() {
+            Some(store) => store,
+            None => return,
+        };
+        self.pending_evidence
+            .retain_mut(|signing| match signing.as_mut().poll(cx) {
+                Poll::Ready(Ok(signed)) => {
+                    if let Err(err) = store.put(&signed) {
+                        warn!("Failed to store evidence: {}", err);
+                    }
+                    false
+                }
+                Poll::Ready(Err(err)) => {
+                    warn!("Failed to sign evidence: {}", err);
+                    false
+                }
+                Poll::Pending => true,
+            });
+    }
+
     fn unstash_stage(&mut self, stage: StageTag)
     where
-        H: Debug + HashMarker + FixedOutput<OutputSize = <Secp256k1 as Curve>::FieldBytesSize> + Default,
+        H: Debug
+            + Send
+            + 'static
+            + HashMarker
+            + FixedOutput<OutputSize = <Secp256k1 as Curve>::FieldBytesSize>
+            + Default,
         MPP: MakePeerPartitions + Clone + Send,
         MPP::PP: Send + 'a,
         OB: MakeDagOverlay + Clone,
@@ -528,7 +661,12 @@ where
 
 impl<'a, H, MPP, OB> ProtocolBehaviour for SigmaAggregation<'a, H, MPP, OB>
 where
-    H: Debug + HashMarker + FixedOutput<OutputSize = <Secp256k1 as Curve>::FieldBytesSize> + Default,
+    H: Debug
+        + Send
+        + 'static
+        + HashMarker
+        + FixedOutput<OutputSize = <Secp256k1 as Curve>::FieldBytesSize>
+        + Default,
     MPP: MakePeerPartitions + Clone + Send,
     MPP::PP: Send + Clone + 'static,
     OB: MakeDagOverlay + Clone,
@@ -541,7 +679,16 @@ where
         peer_id: PeerId,
         SigmaAggrMessage::SigmaAggrMessageV1(msg): SigmaAggrMessage,
     ) {
+        let collect_evidence = self.evidence_store.is_some();
+        let mut evidence = None;
         match &mut self.task {
+            Some(AggregationTask {
+                state: AggregationState::AwaitCommitment(_) | AggregationState::AwaitResponse(_),
+                ..
+            }) => {
+                // Picked up once the signer is done.
+                self.stash.stash(peer_id, msg);
+            }
             Some(AggregationTask {
                 state: AggregationState::AggregatePreCommitments(ref mut pre_commitment),
                 ..
@@ -584,6 +731,13 @@ where
                 let span = trace_span!("", host_ix = ?commitment.host_ix, stage = ?StageTag::Commit);
                 let _enter = span.enter();
                 if let SigmaAggrMessageV1::Commitments(commits) = msg {
+                    if collect_evidence {
+                        if let Some(sender) = commitment.partitions.try_index_peer(peer_id) {
+                            evidence = commitment
+                                .detect_equivocation(sender, &commits)
+                                .filter(|ev| ev.verify(&ordered_committee(&commitment.committee)));
+                        }
+                    }
                     commitment.handel.inject_message(peer_id, commits);
                 } else {
                     trace!(
@@ -618,6 +772,13 @@ where
                 let span = trace_span!("", host_ix = ?response.host_ix, stage = ?StageTag::Response);
                 let _enter = span.enter();
                 if let SigmaAggrMessageV1::Responses(resps) = msg {
+                    if collect_evidence {
+                        if let Some(sender) = response.partitions.try_index_peer(peer_id) {
+                            evidence = response
+                                .suspect_response(sender, &resps)
+                                .filter(|ev| ev.verify(&ordered_committee(&response.committee)));
+                        }
+                    }
                     response.handel.inject_message(peer_id, resps);
                 } else {
                     trace!(
@@ -630,6 +791,9 @@ where
             }
             None => {}
         }
+        if let Some(evidence) = evidence {
+            self.record_evidence(evidence);
+        }
     }
 
     fn poll(
@@ -641,6 +805,8 @@ where
                 return Poll::Ready(Some(out));
             }
 
+            self.poll_evidence(cx);
+
             if let Poll::Ready(Some(notif)) = Stream::poll_next(Pin::new(&mut self.inbox), cx) {
                 match notif {
                     AggregationAction::Reset {
@@ -649,16 +815,13 @@ where
                         channel,
                     } => {
                         self.stash.flush();
+                        let commitment = self.signer.schnorr_commitment(new_message.as_ref().to_vec());
                         self.task = Some(AggregationTask {
-                            state: AggregationState::AggregatePreCommitments(AggregatePreCommitments::init(
-                                self.host_sk.clone(),
-                                new_committee,
-                                new_message,
-                                self.partitioner.clone(),
-                                self.mcast_overlay_builder.clone(),
-                                self.handel_conf.clone(),
-                                self.multicasting_conf,
-                            )),
+                            state: AggregationState::AwaitCommitment(AwaitCommitment {
+                                committee: new_committee,
+                                message_digest: new_message,
+                                commitment,
+                            }),
                             channel,
                         });
                     }
@@ -667,6 +830,41 @@ where
 
             if let Some(task) = self.task.take() {
                 match task {
+                    AggregationTask {
+                        state: AggregationState::AwaitCommitment(mut st),
+                        channel,
+                    } => match st.commitment.as_mut().poll(cx) {
+                        Poll::Ready(Ok(commitment)) => {
+                            let st = AggregatePreCommitments::init(
+                                self.signer.public_key(),
+                                st.committee,
+                                st.message_digest,
+                                commitment,
+                                self.partitioner.clone(),
+                                self.mcast_overlay_builder.clone(),
+                                self.handel_conf.clone(),
+                                self.multicasting_conf,
+                            );
+                            self.task = Some(AggregationTask {
+                                state: AggregationState::AggregatePreCommitments(st),
+                                channel,
+                            });
+                            self.unstash_stage(StageTag::PreCommit);
+                            continue;
+                        }
+                        Poll::Ready(Err(err)) => {
+                            warn!("Failed to commit to the message: {}", err);
+                            self.stash.flush();
+                            let _ = channel.send(Err(()));
+                            continue;
+                        }
+                        Poll::Pending => {
+                            self.task = Some(AggregationTask {
+                                state: AggregationState::AwaitCommitment(st),
+                                channel,
+                            });
+                        }
+                    },
                     AggregationTask {
                         state: AggregationState::AggregatePreCommitments(mut st),
                         channel,
@@ -864,10 +1062,9 @@ where
                                         "Finished broadcasting commitments, missing from: {:?}",
                                         missing_peers
                                     );
-                                    self.unstash_stage(StageTag::Response);
                                     self.task = Some(AggregationTask {
-                                        state: AggregationState::AggregateResponses(
-                                            st.complete(commitments, self.handel_conf),
+                                        state: AggregationState::AwaitResponse(
+                                            st.complete(commitments, &self.signer),
                                         ),
                                         channel,
                                     });
@@ -882,6 +1079,33 @@ where
                             }
                         }
                     }
+                    AggregationTask {
+                        state: AggregationState::AwaitResponse(mut st),
+                        channel,
+                    } => match st.response.as_mut().poll(cx) {
+                        Poll::Ready(Ok(host_response)) => {
+                            self.task = Some(AggregationTask {
+                                state: AggregationState::AggregateResponses(
+                                    st.complete(host_response, self.handel_conf),
+                                ),
+                                channel,
+                            });
+                            self.unstash_stage(StageTag::Response);
+                            continue;
+                        }
+                        Poll::Ready(Err(err)) => {
+                            warn!("Failed to respond to the challenge: {}", err);
+                            self.stash.flush();
+                            let _ = channel.send(Err(()));
+                            continue;
+                        }
+                        Poll::Pending => {
+                            self.task = Some(AggregationTask {
+                                state: AggregationState::AwaitResponse(st),
+                                channel,
+                            });
+                        }
+                    },
                     AggregationTask {
                         state: AggregationState::AggregateResponses(mut st),
                         channel,
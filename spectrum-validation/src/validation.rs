@@ -9,6 +9,12 @@ use crate::rules::{ConsensusRuleSet, NonTermRuleId, TermRuleId};
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct ValidModifier<T>(T);
 
+impl<T> ValidModifier<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct InvalidModifier {
     pub modifier_id: ModifierId,
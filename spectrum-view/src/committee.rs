@@ -0,0 +1,204 @@
+//! History of committees elected for each epoch.
+//!
+//! Certificates must be verified against the committee which issued them, which is not
+//! necessarily the current one, e.g. when a report from a past epoch is re-observed after
+//! a reorg of the external chain. Committees are therefore kept per epoch along with their
+//! aggregate key and hash, so that they can be looked up by what the external chain records.
+
+use std::sync::Arc;
+
+use rocksdb::{Direction, IteratorMode, OptimisticTransactionDB};
+
+use spectrum_crypto::digest::{Blake2b256, Blake2bDigest256};
+use spectrum_crypto::pubkey::PublicKey;
+use spectrum_ledger::{EpochNo, SlotNo};
+use spectrum_sigma::crypto::{aggregate_pk, committee_hash, individual_input};
+
+use crate::finality::Committees;
+
+/// Committee in charge of an epoch.
+#[derive(Clone, Eq, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+pub struct CommitteeRecord {
+    pub epoch: EpochNo,
+    /// Members in the order they sign.
+    pub members: Vec<PublicKey>,
+    pub aggregate_key: PublicKey,
+    /// Hash of the committee as stored in R9 of the first committee box on Ergo.
+    pub hash: Blake2bDigest256,
+}
+
+impl CommitteeRecord {
+    pub fn new(epoch: EpochNo, members: Vec<PublicKey>) -> Self {
+        let individual_inputs = members
+            .iter()
+            .map(|pk| individual_input::<Blake2b256>(members.clone(), *pk))
+            .collect();
+        Self {
+            epoch,
+            aggregate_key: aggregate_pk(members.clone(), individual_inputs),
+            hash: committee_hash(&members),
+            members,
+        }
+    }
+}
+
+/// Committees elected for each epoch.
+pub trait CommitteeRegistry: Send + Sync {
+    /// Record the committee elected for the given epoch, replacing the one recorded before, if any.
+    /// Committee must not be empty.
+    fn put(&self, epoch: EpochNo, members: Vec<PublicKey>) -> CommitteeRecord;
+    /// Get the committee in charge of the given epoch.
+    fn get_by_epoch(&self, epoch: EpochNo) -> Option<CommitteeRecord>;
+    /// Get the latest epoch in which the committee with the given hash was in charge.
+    fn get_by_hash(&self, hash: &Blake2bDigest256) -> Option<CommitteeRecord>;
+    /// Forget committees of epochs after the given one, e.g. when their election is rolled back.
+    fn rollback_to(&self, epoch: EpochNo);
+}
+
+pub struct CommitteeRegistryRocksDB {
+    pub db: Arc<OptimisticTransactionDB>,
+}
+
+const EPOCH_PREFIX: &[u8] = b"c:e:";
+const HASH_PREFIX: &[u8] = b"c:h:";
+
+fn epoch_key(epoch: EpochNo) -> Vec<u8> {
+    let mut key = EPOCH_PREFIX.to_vec();
+    key.extend_from_slice(&u64::from(epoch).to_be_bytes());
+    key
+}
+
+fn hash_prefix(hash: &Blake2bDigest256) -> Vec<u8> {
+    let mut key = HASH_PREFIX.to_vec();
+    key.extend_from_slice(hash.raw());
+    key
+}
+
+fn hash_key(hash: &Blake2bDigest256, epoch: EpochNo) -> Vec<u8> {
+    let mut key = hash_prefix(hash);
+    key.extend_from_slice(&u64::from(epoch).to_be_bytes());
+    key
+}
+
+impl CommitteeRegistry for CommitteeRegistryRocksDB {
+    fn put(&self, epoch: EpochNo, members: Vec<PublicKey>) -> CommitteeRecord {
+        assert!(!members.is_empty(), "Committee of epoch {} is empty", epoch);
+        let record = CommitteeRecord::new(epoch, members);
+        let tx = self.db.transaction();
+        if let Some(prev) = tx.get(epoch_key(epoch)).unwrap() {
+            let prev: CommitteeRecord = bincode::deserialize(&prev).unwrap();
+            tx.delete(hash_key(&prev.hash, epoch)).unwrap();
+        }
+        tx.put(epoch_key(epoch), bincode::serialize(&record).unwrap())
+            .unwrap();
+        tx.put(hash_key(&record.hash, epoch), b"").unwrap();
+        tx.commit().unwrap();
+        record
+    }
+
+    fn get_by_epoch(&self, epoch: EpochNo) -> Option<CommitteeRecord> {
+        self.db
+            .get(epoch_key(epoch))
+            .unwrap()
+            .map(|bytes| bincode::deserialize(&bytes).unwrap())
+    }
+
+    fn get_by_hash(&self, hash: &Blake2bDigest256) -> Option<CommitteeRecord> {
+        let prefix = hash_prefix(hash);
+        // Index keys of the same committee are ordered by epoch.
+        let latest = self
+            .db
+            .iterator(IteratorMode::From(&prefix, Direction::Forward))
+            .map(Result::unwrap)
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .last()?;
+        let epoch = u64::from_be_bytes(latest.0[prefix.len()..].try_into().unwrap());
+        self.get_by_epoch(EpochNo::from(epoch))
+    }
+
+    fn rollback_to(&self, epoch: EpochNo) {
+        let from = epoch_key(EpochNo::from(u64::from(epoch) + 1));
+        let tx = self.db.transaction();
+        for (key, value) in self
+            .db
+            .iterator(IteratorMode::From(&from, Direction::Forward))
+            .map(Result::unwrap)
+            .take_while(|(key, _)| key.starts_with(EPOCH_PREFIX))
+        {
+            let record: CommitteeRecord = bincode::deserialize(&value).unwrap();
+            tx.delete(key).unwrap();
+            tx.delete(hash_key(&record.hash, record.epoch)).unwrap();
+        }
+        tx.commit().unwrap();
+    }
+}
+
+impl Committees for CommitteeRegistryRocksDB {
+    fn get_committee(&self, slot: SlotNo) -> Option<Vec<PublicKey>> {
+        self.get_by_epoch(slot.epoch_num()).map(|record| record.members)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use k256::SecretKey;
+    use rand::rngs::OsRng;
+    use rand::RngCore;
+
+    use spectrum_crypto::pubkey::PublicKey;
+    use spectrum_ledger::{EpochNo, SlotNo};
+
+    use crate::committee::{CommitteeRegistry, CommitteeRegistryRocksDB};
+    use crate::finality::Committees;
+
+    fn registry() -> CommitteeRegistryRocksDB {
+        let rnd = rand::thread_rng().next_u32();
+        CommitteeRegistryRocksDB {
+            db: Arc::new(
+                rocksdb::OptimisticTransactionDB::open_default(format!("./tmp/committees_{}", rnd)).unwrap(),
+            ),
+        }
+    }
+
+    fn committee(n: usize) -> Vec<PublicKey> {
+        (0..n)
+            .map(|_| PublicKey::from(SecretKey::random(&mut OsRng)))
+            .collect()
+    }
+
+    #[test]
+    fn committees_queried_by_epoch_and_hash() {
+        let registry = registry();
+        let (first, second) = (committee(3), committee(4));
+        let r0 = registry.put(EpochNo::from(0), first.clone());
+        let r1 = registry.put(EpochNo::from(1), second.clone());
+        // The first committee is re-elected.
+        let r2 = registry.put(EpochNo::from(2), first.clone());
+        assert_eq!(r0.hash, r2.hash);
+        assert_eq!(r0.aggregate_key, r2.aggregate_key);
+        assert_ne!(r0.aggregate_key, r1.aggregate_key);
+        assert_eq!(registry.get_by_epoch(EpochNo::from(1)), Some(r1.clone()));
+        assert_eq!(registry.get_by_hash(&r1.hash), Some(r1.clone()));
+        assert_eq!(registry.get_by_hash(&r0.hash), Some(r2));
+        assert_eq!(
+            registry.get_committee(SlotNo::from(SlotNo::SLOTS_PER_EPOCH + 1)),
+            Some(second)
+        );
+
+        registry.rollback_to(EpochNo::from(1));
+        assert_eq!(registry.get_by_epoch(EpochNo::from(2)), None);
+        assert_eq!(registry.get_by_hash(&r0.hash), Some(r0));
+        assert_eq!(registry.get_by_hash(&r1.hash), Some(r1));
+    }
+
+    #[test]
+    fn committee_of_epoch_replaced() {
+        let registry = registry();
+        let replaced = registry.put(EpochNo::from(5), committee(2));
+        let record = registry.put(EpochNo::from(5), committee(2));
+        assert_eq!(registry.get_by_epoch(EpochNo::from(5)), Some(record));
+        assert_eq!(registry.get_by_hash(&replaced.hash), None);
+    }
+}
@@ -0,0 +1,382 @@
+use std::sync::Arc;
+
+use spectrum_crypto::digest::{Blake2b256, Blake2bDigest256};
+use spectrum_crypto::pubkey::PublicKey;
+use spectrum_handel::Threshold;
+use spectrum_ledger::block::BlockId;
+use spectrum_ledger::SlotNo;
+use spectrum_sigma::crypto::verify;
+use spectrum_sigma::sigma_aggregation::AggregateCertificate;
+
+use crate::history::LedgerHistoryReadSync;
+
+/// Block which can't be reverted anymore.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Checkpoint {
+    pub id: BlockId,
+    pub slot: SlotNo,
+}
+
+/// Committee signature over a block id.
+#[derive(Clone, Eq, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+pub struct FinalityCertificate {
+    pub block_id: BlockId,
+    pub certificate: AggregateCertificate<Blake2b256>,
+}
+
+#[derive(Eq, PartialEq, Debug, thiserror::Error)]
+pub enum FinalityError {
+    #[error("Certificate doesn't commit to block {0}")]
+    DigestMismatch(BlockId),
+    #[error("Unknown block {0}")]
+    UnknownBlock(BlockId),
+    #[error("No committee known for slot {0:?}")]
+    UnknownCommittee(SlotNo),
+    #[error("Invalid certificate")]
+    InvalidCertificate,
+    #[error("Block {0} conflicts with finalized checkpoint {1:?}")]
+    Conflict(BlockId, Checkpoint),
+}
+
+/// Tracks blocks finalized by the committee.
+pub trait FinalityOracle: Send + Sync {
+    /// Verify the certificate and finalize the certified block along with all its ancestors.
+    fn apply_certificate(&self, cert: FinalityCertificate) -> Result<Checkpoint, FinalityError>;
+    /// Get the latest finalized block.
+    fn get_last_finalized(&self) -> Option<Checkpoint>;
+    /// Check whether the chain can be rolled back to the given block
+    /// without reverting finalized blocks.
+    fn can_rollback_to(&self, id: &BlockId) -> bool;
+}
+
+/// Minimal view of the local block tree needed to reason about finality.
+pub trait BlockTree: Send + Sync {
+    /// Get slot of the given block along with the id of its parent.
+    fn get_parent(&self, id: &BlockId) -> Option<(SlotNo, BlockId)>;
+}
+
+impl<T: LedgerHistoryReadSync + Send + Sync> BlockTree for T {
+    fn get_parent(&self, id: &BlockId) -> Option<(SlotNo, BlockId)> {
+        self.get_header(id)
+            .map(|hdr| (hdr.body.slot_num, hdr.body.prev_id))
+    }
+}
+
+/// Committees in charge of finalization.
+pub trait Committees: Send + Sync {
+    /// Get members of the committee in charge of the given slot in the order they sign.
+    fn get_committee(&self, slot: SlotNo) -> Option<Vec<PublicKey>>;
+}
+
+pub struct FinalityOracleRocksDB<TTree, TCommittees> {
+    pub db: Arc<rocksdb::OptimisticTransactionDB>,
+    pub tree: Arc<TTree>,
+    pub committees: Arc<TCommittees>,
+    /// Share of the committee which must have signed a block to finalize it.
+    pub threshold: Threshold,
+}
+
+const LAST_FINALIZED_KEY: &[u8] = b"f:last";
+const CHECKPOINT_PREFIX: &[u8] = b"f:cp:";
+
+fn checkpoint_key(slot: SlotNo) -> Vec<u8> {
+    let mut key = CHECKPOINT_PREFIX.to_vec();
+    key.extend_from_slice(&u64::from(slot).to_be_bytes());
+    key
+}
+
+/// Read the latest checkpoint from the database the checkpoints are stored in.
+pub(crate) fn read_last_finalized(db: &rocksdb::OptimisticTransactionDB) -> Option<Checkpoint> {
+    db.get(LAST_FINALIZED_KEY)
+        .unwrap()
+        .map(|bytes| bincode::deserialize(&bytes).unwrap())
+}
+
+/// Check whether `id` is `ancestor` or one of its descendants.
+fn descends_from<TTree: BlockTree>(tree: &TTree, id: BlockId, ancestor: Checkpoint) -> bool {
+    let mut id = id;
+    loop {
+        if id == ancestor.id {
+            return true;
+        }
+        match tree.get_parent(&id) {
+            Some((slot, parent)) if slot > ancestor.slot => id = parent,
+            _ => return false,
+        }
+    }
+}
+
+impl<TTree, TCommittees> FinalityOracleRocksDB<TTree, TCommittees> {
+    /// Get checkpoint finalized at the given slot, if any.
+    pub fn get_checkpoint(&self, slot: SlotNo) -> Option<Checkpoint> {
+        self.db
+            .get(checkpoint_key(slot))
+            .unwrap()
+            .map(|bytes| bincode::deserialize(&bytes).unwrap())
+    }
+}
+
+impl<TTree, TCommittees> FinalityOracle for FinalityOracleRocksDB<TTree, TCommittees>
+where
+    TTree: BlockTree,
+    TCommittees: Committees,
+{
+    fn apply_certificate(&self, cert: FinalityCertificate) -> Result<Checkpoint, FinalityError> {
+        let FinalityCertificate {
+            block_id,
+            certificate,
+        } = cert;
+        if certificate.message_digest != Blake2bDigest256::from(block_id) {
+            return Err(FinalityError::DigestMismatch(block_id));
+        }
+        let (slot, _) = self
+            .tree
+            .get_parent(&block_id)
+            .ok_or(FinalityError::UnknownBlock(block_id))?;
+        let committee = self
+            .committees
+            .get_committee(slot)
+            .ok_or(FinalityError::UnknownCommittee(slot))?;
+        if !verify(
+            certificate.aggregate_commitment,
+            certificate.aggregate_response,
+            certificate.exclusion_set,
+            committee,
+            certificate.message_digest,
+            self.threshold,
+        ) {
+            return Err(FinalityError::InvalidCertificate);
+        }
+        let checkpoint = Checkpoint { id: block_id, slot };
+        let tx = self.db.transaction();
+        let last_finalized = tx
+            .get_for_update(LAST_FINALIZED_KEY, true)
+            .unwrap()
+            .map(|bytes| bincode::deserialize::<Checkpoint>(&bytes).unwrap());
+        if let Some(last) = last_finalized {
+            if slot <= last.slot {
+                // Ancestors of the last finalized block are final already.
+                return if descends_from(&*self.tree, last.id, checkpoint) {
+                    Ok(checkpoint)
+                } else {
+                    Err(FinalityError::Conflict(block_id, last))
+                };
+            }
+            if !descends_from(&*self.tree, block_id, last) {
+                return Err(FinalityError::Conflict(block_id, last));
+            }
+        }
+        let bytes = bincode::serialize(&checkpoint).unwrap();
+        tx.put(checkpoint_key(slot), &bytes).unwrap();
+        tx.put(LAST_FINALIZED_KEY, &bytes).unwrap();
+        tx.commit().unwrap();
+        Ok(checkpoint)
+    }
+
+    fn get_last_finalized(&self) -> Option<Checkpoint> {
+        read_last_finalized(&self.db)
+    }
+
+    fn can_rollback_to(&self, id: &BlockId) -> bool {
+        match self.get_last_finalized() {
+            Some(last) => descends_from(&*self.tree, *id, last),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use k256::elliptic_curve::rand_core::OsRng;
+    use k256::SecretKey;
+    use rand::RngCore;
+
+    use spectrum_crypto::digest::{Blake2b256, Blake2bDigest256};
+    use spectrum_crypto::pubkey::PublicKey;
+    use spectrum_handel::Threshold;
+    use spectrum_ledger::block::BlockId;
+    use spectrum_ledger::SlotNo;
+    use spectrum_sigma::crypto::{
+        aggregate_commitment, aggregate_pk, aggregate_response, challenge, individual_input, response,
+        schnorr_commitment_pair,
+    };
+    use spectrum_sigma::sigma_aggregation::AggregateCertificate;
+
+    use crate::finality::{
+        BlockTree, Checkpoint, Committees, FinalityCertificate, FinalityError, FinalityOracle,
+        FinalityOracleRocksDB,
+    };
+    use crate::history::bodies::{FsColdStore, RetentionConfig, TieredBodyStore};
+    use crate::history::{LedgerHistoryReadAsync, LedgerHistoryRocksDB};
+
+    struct Tree(HashMap<BlockId, (SlotNo, BlockId)>);
+
+    impl BlockTree for Tree {
+        fn get_parent(&self, id: &BlockId) -> Option<(SlotNo, BlockId)> {
+            self.0.get(id).cloned()
+        }
+    }
+
+    struct StaticCommittee(Vec<PublicKey>);
+
+    impl Committees for StaticCommittee {
+        fn get_committee(&self, _: SlotNo) -> Option<Vec<PublicKey>> {
+            Some(self.0.clone())
+        }
+    }
+
+    /// Chain of `n` blocks on top of origin followed by a fork of `m` blocks branching off at `fork_at`.
+    fn make_tree(n: u64, fork_at: usize, m: u64) -> (Tree, Vec<BlockId>, Vec<BlockId>) {
+        let mut links = HashMap::new();
+        let mut main = vec![BlockId::ORIGIN];
+        links.insert(BlockId::ORIGIN, (SlotNo::from(0), BlockId::ORIGIN));
+        for slot in 1..=n {
+            let id = BlockId::random();
+            links.insert(id, (SlotNo::from(slot), *main.last().unwrap()));
+            main.push(id);
+        }
+        let mut fork = vec![main[fork_at]];
+        for slot in 1..=m {
+            let id = BlockId::random();
+            links.insert(id, (SlotNo::from(fork_at as u64 + slot), *fork.last().unwrap()));
+            fork.push(id);
+        }
+        (Tree(links), main, fork)
+    }
+
+    fn certify(sks: &Vec<SecretKey>, block_id: BlockId) -> FinalityCertificate {
+        let committee = sks
+            .iter()
+            .map(|sk| PublicKey::from(sk.clone()))
+            .collect::<Vec<_>>();
+        let md = Blake2bDigest256::from(block_id);
+        let ais = committee
+            .iter()
+            .map(|pk| individual_input::<Blake2b256>(committee.clone(), pk.clone()))
+            .collect::<Vec<_>>();
+        let aggr_pk = aggregate_pk(committee.clone(), ais.clone());
+        let pairs = sks.iter().map(|_| schnorr_commitment_pair()).collect::<Vec<_>>();
+        let aggr_commitment = aggregate_commitment(pairs.iter().map(|(_, yi)| yi.clone()).collect());
+        let c = challenge(aggr_pk, aggr_commitment.clone(), md);
+        let responses = sks
+            .iter()
+            .zip(pairs)
+            .zip(ais)
+            .map(|((sk, (yi, _)), ai)| response(yi, sk.clone(), c, ai))
+            .collect();
+        FinalityCertificate {
+            block_id,
+            certificate: AggregateCertificate {
+                message_digest: md,
+                aggregate_commitment: aggr_commitment,
+                aggregate_response: aggregate_response(responses),
+                exclusion_set: vec![],
+            },
+        }
+    }
+
+    fn make_oracle(tree: Tree, committee: Vec<PublicKey>) -> FinalityOracleRocksDB<Tree, StaticCommittee> {
+        let rnd = rand::thread_rng().next_u32();
+        FinalityOracleRocksDB {
+            db: Arc::new(
+                rocksdb::OptimisticTransactionDB::open_default(format!("./tmp/finality_{}", rnd)).unwrap(),
+            ),
+            tree: Arc::new(tree),
+            committees: Arc::new(StaticCommittee(committee)),
+            threshold: Threshold { num: 2, denom: 3 },
+        }
+    }
+
+    #[test]
+    fn finalize_blocks() {
+        let (tree, main, fork) = make_tree(10, 4, 3);
+        let sks = (0..4).map(|_| SecretKey::random(&mut OsRng)).collect::<Vec<_>>();
+        let oracle = make_oracle(tree, sks.iter().map(|sk| PublicKey::from(sk.clone())).collect());
+        assert_eq!(oracle.get_last_finalized(), None);
+        assert!(oracle.can_rollback_to(&fork[1]));
+
+        let cp = oracle.apply_certificate(certify(&sks, main[6])).unwrap();
+        assert_eq!(
+            cp,
+            Checkpoint {
+                id: main[6],
+                slot: SlotNo::from(6)
+            }
+        );
+        assert_eq!(oracle.get_last_finalized(), Some(cp));
+        assert_eq!(oracle.get_checkpoint(SlotNo::from(6)), Some(cp));
+        // Ancestors are final already.
+        assert!(oracle.apply_certificate(certify(&sks, main[2])).is_ok());
+        assert_eq!(oracle.get_last_finalized(), Some(cp));
+
+        assert!(oracle.can_rollback_to(&main[6]));
+        assert!(oracle.can_rollback_to(&main[8]));
+        assert!(!oracle.can_rollback_to(&main[5]));
+        assert!(!oracle.can_rollback_to(&fork[3]));
+        assert!(matches!(
+            oracle.apply_certificate(certify(&sks, fork[3])),
+            Err(FinalityError::Conflict(_, _))
+        ));
+
+        oracle.apply_certificate(certify(&sks, main[9])).unwrap();
+        assert_eq!(oracle.get_last_finalized().map(|cp| cp.id), Some(main[9]));
+    }
+
+    #[async_std::test]
+    async fn history_reads_last_finalized() {
+        let (tree, main, _) = make_tree(4, 0, 0);
+        let sks = (0..4).map(|_| SecretKey::random(&mut OsRng)).collect::<Vec<_>>();
+        let oracle = make_oracle(tree, sks.iter().map(|sk| PublicKey::from(sk.clone())).collect());
+        let rnd = rand::thread_rng().next_u32();
+        let history = LedgerHistoryRocksDB {
+            db: oracle.db.clone(),
+            bodies: TieredBodyStore {
+                db: oracle.db.clone(),
+                cold: Arc::new(FsColdStore {
+                    root: format!("./tmp/cold_{}", rnd).into(),
+                }),
+                retention: RetentionConfig {
+                    hot_slots: 10,
+                    migration_batch_size: 2,
+                    migration_interval: Duration::from_secs(1),
+                },
+            },
+        };
+        assert_eq!(history.get_last_finalized().await, None);
+        let cp = oracle.apply_certificate(certify(&sks, main[2])).unwrap();
+        assert_eq!(history.get_last_finalized().await, Some(cp));
+        oracle.apply_certificate(certify(&sks, main[3])).unwrap();
+        assert_eq!(history.get_last_finalized().await.map(|cp| cp.id), Some(main[3]));
+    }
+
+    #[test]
+    fn reject_invalid_certificates() {
+        let (tree, main, _) = make_tree(4, 0, 0);
+        let sks = (0..4).map(|_| SecretKey::random(&mut OsRng)).collect::<Vec<_>>();
+        let oracle = make_oracle(tree, sks.iter().map(|sk| PublicKey::from(sk.clone())).collect());
+
+        let mut cert = certify(&sks, main[2]);
+        cert.block_id = main[3];
+        assert_eq!(
+            oracle.apply_certificate(cert),
+            Err(FinalityError::DigestMismatch(main[3]))
+        );
+
+        let outsiders = (0..4).map(|_| SecretKey::random(&mut OsRng)).collect::<Vec<_>>();
+        assert_eq!(
+            oracle.apply_certificate(certify(&outsiders, main[2])),
+            Err(FinalityError::InvalidCertificate)
+        );
+
+        let unknown = BlockId::random();
+        assert_eq!(
+            oracle.apply_certificate(certify(&sks, unknown)),
+            Err(FinalityError::UnknownBlock(unknown))
+        );
+        assert_eq!(oracle.get_last_finalized(), None);
+    }
+}
@@ -2,12 +2,18 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 use nonempty::NonEmpty;
+use rocksdb::{Direction, IteratorMode};
 
+use spectrum_crypto::digest::Blake2bDigest256;
 use spectrum_ledger::block::{BlockBody, BlockHeader, BlockId, BlockSectionType};
-use spectrum_ledger::{ModifierId, ModifierRecord, SerializedModifier, SlotNo};
+use spectrum_ledger::{ModifierId, ModifierRecord, SerializedModifier, SlotNo, SystemDigest};
 use spectrum_validation::validation::ValidModifier;
 
 use crate::chain::HeaderLike;
+use crate::finality::{read_last_finalized, Checkpoint};
+use crate::history::bodies::{FsColdStore, TieredBodyStore};
+
+pub mod bodies;
 
 /// Sync API to ledger history.
 pub trait LedgerHistoryWrite {
@@ -15,11 +21,16 @@ pub trait LedgerHistoryWrite {
     fn apply_header(&self, hdr: ValidModifier<BlockHeader>);
     /// Apply block body.
     fn apply_body(&self, body: ValidModifier<BlockBody>);
+    /// Make the given header the new tip regardless of its ancestors.
+    /// The header must be certified by the committee.
+    fn install_checkpoint(&self, hdr: BlockHeader);
 }
 
 pub trait LedgerHistoryReadSync {
     fn get_header(&self, id: &BlockId) -> Option<BlockHeader>;
     fn get_header_at(&self, slot: SlotNo) -> Option<BlockHeader>;
+    /// Get the header committing to the block body with the given root hash.
+    fn get_header_by_body_root(&self, body_root: &Blake2bDigest256) -> Option<BlockHeader>;
 }
 
 /// Read-only async API to ledger history.
@@ -31,6 +42,9 @@ pub trait LedgerHistoryReadAsync<H: HeaderLike>: Send + Sync {
     async fn contains(&self, id: &ModifierId) -> bool;
     /// Get chain tip header (best block header).
     async fn get_tip(&self) -> ModifierRecord<H>;
+    /// Get the latest block finalized by the committee.
+    /// Chain is never rolled back beyond this block.
+    async fn get_last_finalized(&self) -> Option<Checkpoint>;
     /// Get tail of the chain. Chain always has at least origin block.
     async fn get_tail(&self, n: usize) -> NonEmpty<ModifierRecord<H>>;
     /// Follow best chain starting from `pre_start` until either the local tip
@@ -46,29 +60,190 @@ pub trait LedgerHistoryReadAsync<H: HeaderLike>: Send + Sync {
 }
 
 pub struct LedgerHistoryRocksDB {
+    /// Shared with [`crate::finality::FinalityOracleRocksDB`], which stores checkpoints in it.
     pub db: Arc<rocksdb::OptimisticTransactionDB>,
+    pub bodies: TieredBodyStore<FsColdStore>,
+}
+
+/// Headers in the wire format, i.e. CBOR.
+const HEADER_PREFIX: &[u8] = b"h:hdr:";
+/// Ids of headers indexed by roots of their bodies.
+const BODY_ROOT_PREFIX: &[u8] = b"h:root:";
+/// Ids of blocks in the best chain indexed by slot.
+const BEST_CHAIN_PREFIX: &[u8] = b"h:best:";
+const TIP_KEY: &[u8] = b"h:tip";
+
+fn header_key(id: BlockId) -> Vec<u8> {
+    let mut key = HEADER_PREFIX.to_vec();
+    key.extend_from_slice(Blake2bDigest256::from(id).raw());
+    key
+}
+
+fn body_root_key(root: &Blake2bDigest256) -> Vec<u8> {
+    let mut key = BODY_ROOT_PREFIX.to_vec();
+    key.extend_from_slice(root.raw());
+    key
+}
+
+fn best_chain_key(slot: SlotNo) -> Vec<u8> {
+    let mut key = BEST_CHAIN_PREFIX.to_vec();
+    key.extend_from_slice(&u64::from(slot).to_be_bytes());
+    key
+}
+
+fn header_id(hdr: &BlockHeader) -> BlockId {
+    BlockId::from(hdr.body.digest())
+}
+
+impl LedgerHistoryRocksDB {
+    fn get_raw_header(&self, id: BlockId) -> Option<Vec<u8>> {
+        self.db.get(header_key(id)).unwrap()
+    }
+
+    fn get_block_id(&self, key: Vec<u8>) -> Option<BlockId> {
+        self.db
+            .get(key)
+            .unwrap()
+            .map(|bytes| bincode::deserialize(&bytes).unwrap())
+    }
+
+    fn get_tip_id(&self) -> Option<BlockId> {
+        self.get_block_id(TIP_KEY.to_vec())
+    }
+
+    /// Store the header, making it the new tip if `new_tip` is set.
+    fn put_header(&self, hdr: &BlockHeader, new_tip: bool) {
+        let id = header_id(hdr);
+        let mut encoded = Vec::new();
+        ciborium::ser::into_writer(hdr, &mut encoded).unwrap();
+        let raw_id = bincode::serialize(&id).unwrap();
+        let tx = self.db.transaction();
+        tx.put(header_key(id), encoded).unwrap();
+        tx.put(body_root_key(&hdr.body.block_body_root), &raw_id).unwrap();
+        if new_tip {
+            let from = best_chain_key(hdr.body.slot_num);
+            // Blocks of the former best chain beyond the new tip, if any.
+            let stale = self
+                .db
+                .iterator(IteratorMode::From(&from, Direction::Forward))
+                .map(Result::unwrap)
+                .take_while(|(key, _)| key.starts_with(BEST_CHAIN_PREFIX));
+            for (key, _) in stale {
+                tx.delete(key).unwrap();
+            }
+            tx.put(from, &raw_id).unwrap();
+            tx.put(TIP_KEY, &raw_id).unwrap();
+        }
+        tx.commit().unwrap();
+    }
+}
+
+impl LedgerHistoryWrite for LedgerHistoryRocksDB {
+    /// Headers extending the tip become part of the best chain.
+    /// Headers on forks are stored, but never adopted.
+    fn apply_header(&self, hdr: ValidModifier<BlockHeader>) {
+        let hdr = hdr.into_inner();
+        let extends_tip = self.get_tip_id().map_or(true, |tip| tip == hdr.body.prev_id);
+        self.put_header(&hdr, extends_tip);
+    }
+
+    fn apply_body(&self, body: ValidModifier<BlockBody>) {
+        let body = body.into_inner();
+        let root = body.digest();
+        if let Some(hdr) = self.get_header_by_body_root(&root) {
+            let mut encoded = Vec::new();
+            ciborium::ser::into_writer(&body, &mut encoded).unwrap();
+            self.bodies.put_blocking(
+                BlockId::from(root),
+                hdr.body.slot_num,
+                SerializedModifier(encoded),
+            );
+        }
+    }
+
+    fn install_checkpoint(&self, hdr: BlockHeader) {
+        self.put_header(&hdr, true);
+    }
+}
+
+impl LedgerHistoryReadSync for LedgerHistoryRocksDB {
+    fn get_header(&self, id: &BlockId) -> Option<BlockHeader> {
+        self.get_raw_header(*id)
+            .map(|bytes| ciborium::de::from_reader(&bytes[..]).unwrap())
+    }
+
+    fn get_header_at(&self, slot: SlotNo) -> Option<BlockHeader> {
+        self.get_block_id(best_chain_key(slot))
+            .and_then(|id| self.get_header(&id))
+    }
+
+    fn get_header_by_body_root(&self, body_root: &Blake2bDigest256) -> Option<BlockHeader> {
+        self.get_block_id(body_root_key(body_root))
+            .and_then(|id| self.get_header(&id))
+    }
 }
 
 #[async_trait]
 impl LedgerHistoryReadAsync<BlockHeader> for LedgerHistoryRocksDB {
     async fn member(&self, id: &BlockId) -> bool {
-        todo!()
+        self.get_header(id).map_or(false, |hdr| {
+            self.get_block_id(best_chain_key(hdr.body.slot_num)) == Some(*id)
+        })
     }
 
     async fn contains(&self, id: &ModifierId) -> bool {
-        todo!()
+        let id = <ModifierId as Into<BlockId>>::into(*id);
+        self.get_raw_header(id).is_some() || matches!(self.bodies.get(id).await, Ok(Some(_)))
     }
 
     async fn get_tip(&self) -> ModifierRecord<BlockHeader> {
-        todo!()
+        let id = self
+            .get_tip_id()
+            .expect("History is initialized with the origin block");
+        ModifierRecord {
+            id: ModifierId::from(id),
+            modifier: self.get_header(&id).unwrap(),
+        }
+    }
+
+    async fn get_last_finalized(&self) -> Option<Checkpoint> {
+        read_last_finalized(&self.db)
     }
 
     async fn get_tail(&self, n: usize) -> NonEmpty<ModifierRecord<BlockHeader>> {
-        todo!()
+        let tip = self.get_tip().await;
+        let mut prev_id = tip.modifier.body.prev_id;
+        let mut tail = vec![tip];
+        while tail.len() < n {
+            let Some(hdr) = self.get_header(&prev_id) else {
+                break;
+            };
+            let id = std::mem::replace(&mut prev_id, hdr.body.prev_id);
+            tail.push(ModifierRecord {
+                id: ModifierId::from(id),
+                modifier: hdr,
+            });
+        }
+        tail.reverse();
+        NonEmpty::from_vec(tail).unwrap()
     }
 
     async fn follow(&self, pre_start: BlockId, n: usize) -> Vec<BlockId> {
-        todo!()
+        let Some(start) = self.get_header(&pre_start) else {
+            return vec![];
+        };
+        let from = best_chain_key(start.body.slot_num);
+        if self.get_block_id(from.clone()) != Some(pre_start) {
+            return vec![];
+        }
+        self.db
+            .iterator(IteratorMode::From(&from, Direction::Forward))
+            .map(Result::unwrap)
+            .take_while(|(key, _)| key.starts_with(BEST_CHAIN_PREFIX))
+            .skip(1)
+            .take(n)
+            .map(|(_, id)| bincode::deserialize(&id).unwrap())
+            .collect()
     }
 
     async fn multi_get_raw(
@@ -76,6 +251,16 @@ impl LedgerHistoryReadAsync<BlockHeader> for LedgerHistoryRocksDB {
         sec_type: BlockSectionType,
         ids: Vec<ModifierId>,
     ) -> Vec<SerializedModifier> {
-        todo!()
+        match sec_type {
+            BlockSectionType::Header => ids
+                .into_iter()
+                .filter_map(|id| self.get_raw_header(id.into()).map(SerializedModifier))
+                .collect(),
+            BlockSectionType::Body => {
+                self.bodies
+                    .multi_get(ids.into_iter().map(Into::into).collect())
+                    .await
+            }
+        }
     }
 }
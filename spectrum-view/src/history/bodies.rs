@@ -0,0 +1,267 @@
+//! Tiered storage of block bodies.
+//!
+//! Bodies of recent blocks are kept in the primary (hot) store, while bodies of blocks which fell
+//! out of the retention window are migrated to a cold backend, e.g. a slower disk or an object
+//! store. Reads are served from whichever tier holds the body, transparently to the caller.
+
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::task::spawn_blocking;
+use async_trait::async_trait;
+use log::warn;
+use rocksdb::{Direction, IteratorMode, OptimisticTransactionDB};
+
+use spectrum_crypto::digest::Blake2bDigest256;
+use spectrum_ledger::block::BlockId;
+use spectrum_ledger::{SerializedModifier, SlotNo};
+
+/// Backend holding bodies of old blocks.
+#[async_trait]
+pub trait ColdStore: Send + Sync {
+    /// Store the body. Storing the same body again must succeed.
+    async fn put(&self, id: BlockId, body: SerializedModifier) -> io::Result<()>;
+    async fn get(&self, id: BlockId) -> io::Result<Option<SerializedModifier>>;
+}
+
+/// Cold store keeping a file per body in the given directory.
+pub struct FsColdStore {
+    pub root: PathBuf,
+}
+
+#[async_trait]
+impl ColdStore for FsColdStore {
+    async fn put(&self, id: BlockId, body: SerializedModifier) -> io::Result<()> {
+        let root = self.root.clone();
+        spawn_blocking(move || {
+            std::fs::create_dir_all(&root)?;
+            let path = root.join(id.to_string());
+            // Written aside first, so that a body is never read partially written.
+            let tmp = path.with_extension("tmp");
+            std::fs::write(&tmp, &body.0)?;
+            std::fs::rename(&tmp, &path)
+        })
+        .await
+    }
+
+    async fn get(&self, id: BlockId) -> io::Result<Option<SerializedModifier>> {
+        let path = self.root.join(id.to_string());
+        spawn_blocking(move || match std::fs::read(path) {
+            Ok(bytes) => Ok(Some(SerializedModifier(bytes))),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        })
+        .await
+    }
+}
+
+#[derive(Copy, Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct RetentionConfig {
+    /// Bodies of blocks this many slots behind the tip and older are moved to the cold store.
+    pub hot_slots: u64,
+    /// Max number of bodies moved by a single migration run.
+    pub migration_batch_size: usize,
+    /// Delay between migration runs.
+    pub migration_interval: Duration,
+}
+
+pub struct TieredBodyStore<TCold> {
+    /// Primary store.
+    pub db: Arc<OptimisticTransactionDB>,
+    pub cold: Arc<TCold>,
+    pub retention: RetentionConfig,
+}
+
+const BODY_PREFIX: &[u8] = b"b:body:";
+/// Bodies in the primary store indexed by slot of their blocks.
+const SLOT_INDEX_PREFIX: &[u8] = b"b:slot:";
+
+fn body_key(id: BlockId) -> Vec<u8> {
+    let mut key = BODY_PREFIX.to_vec();
+    key.extend_from_slice(Blake2bDigest256::from(id).raw());
+    key
+}
+
+fn slot_index_key(slot: SlotNo, id: BlockId) -> Vec<u8> {
+    let mut key = SLOT_INDEX_PREFIX.to_vec();
+    key.extend_from_slice(&u64::from(slot).to_be_bytes());
+    key.extend_from_slice(Blake2bDigest256::from(id).raw());
+    key
+}
+
+fn put_hot(db: &OptimisticTransactionDB, id: BlockId, slot: SlotNo, body: SerializedModifier) {
+    let tx = db.transaction();
+    tx.put(body_key(id), body.0).unwrap();
+    tx.put(slot_index_key(slot, id), bincode::serialize(&id).unwrap())
+        .unwrap();
+    tx.commit().unwrap();
+}
+
+impl<TCold: ColdStore> TieredBodyStore<TCold> {
+    /// Store the body of a new block in the primary store.
+    pub async fn put(&self, id: BlockId, slot: SlotNo, body: SerializedModifier) {
+        let db = Arc::clone(&self.db);
+        spawn_blocking(move || put_hot(&db, id, slot, body)).await
+    }
+
+    /// Blocking version of [TieredBodyStore::put].
+    pub fn put_blocking(&self, id: BlockId, slot: SlotNo, body: SerializedModifier) {
+        put_hot(&self.db, id, slot, body)
+    }
+
+    pub async fn get(&self, id: BlockId) -> io::Result<Option<SerializedModifier>> {
+        let db = Arc::clone(&self.db);
+        let hot = spawn_blocking(move || db.get(body_key(id)).unwrap()).await;
+        match hot {
+            Some(bytes) => Ok(Some(SerializedModifier(bytes))),
+            None => self.cold.get(id).await,
+        }
+    }
+
+    /// Bodies which are found, bodies the cold store failed to read are skipped.
+    pub async fn multi_get(&self, ids: Vec<BlockId>) -> Vec<SerializedModifier> {
+        let mut bodies = Vec::with_capacity(ids.len());
+        for id in ids {
+            match self.get(id).await {
+                Ok(Some(body)) => bodies.push(body),
+                Ok(None) => {}
+                Err(err) => {
+                    warn!(target: "history", "Cannot read body of block {} from cold store: {}", id, err)
+                }
+            }
+        }
+        bodies
+    }
+
+    /// Move bodies which fell out of the retention window to the cold store, oldest first.
+    /// Returns the number of bodies moved.
+    pub async fn migrate(&self, tip: SlotNo) -> io::Result<usize> {
+        let Some(cutoff) = u64::from(tip).checked_sub(self.retention.hot_slots) else {
+            return Ok(0);
+        };
+        let db = Arc::clone(&self.db);
+        let batch_size = self.retention.migration_batch_size;
+        let stale = spawn_blocking(move || {
+            db.iterator(IteratorMode::From(SLOT_INDEX_PREFIX, Direction::Forward))
+                .map(Result::unwrap)
+                .take_while(|(key, _)| key.starts_with(SLOT_INDEX_PREFIX))
+                .map(|(key, value)| {
+                    let slot_bytes = &key[SLOT_INDEX_PREFIX.len()..SLOT_INDEX_PREFIX.len() + 8];
+                    let slot = u64::from_be_bytes(slot_bytes.try_into().unwrap());
+                    (slot, bincode::deserialize::<BlockId>(&value).unwrap())
+                })
+                .take_while(|(slot, _)| *slot <= cutoff)
+                .take(batch_size)
+                .filter_map(|(slot, id)| {
+                    db.get(body_key(id))
+                        .unwrap()
+                        .map(|body| (SlotNo::from(slot), id, SerializedModifier(body)))
+                })
+                .collect::<Vec<_>>()
+        })
+        .await;
+        let mut moved = Vec::with_capacity(stale.len());
+        for (slot, id, body) in stale {
+            // Body stays in the primary store until it's safely stored in the cold one,
+            // so that it's readable from either tier in the meantime.
+            self.cold.put(id, body).await?;
+            moved.push((slot, id));
+        }
+        let num_moved = moved.len();
+        let db = Arc::clone(&self.db);
+        spawn_blocking(move || {
+            let tx = db.transaction();
+            for (slot, id) in moved {
+                tx.delete(body_key(id)).unwrap();
+                tx.delete(slot_index_key(slot, id)).unwrap();
+            }
+            tx.commit().unwrap();
+        })
+        .await;
+        Ok(num_moved)
+    }
+
+    /// Run migrations periodically against the tip given by `tip`.
+    pub async fn run_migrations<F, Fut>(&self, tip: F)
+    where
+        F: Fn() -> Fut,
+        Fut: std::future::Future<Output = SlotNo>,
+    {
+        loop {
+            async_std::task::sleep(self.retention.migration_interval).await;
+            let tip = tip().await;
+            loop {
+                match self.migrate(tip).await {
+                    Ok(moved) if moved == self.retention.migration_batch_size => continue,
+                    Ok(_) => break,
+                    Err(err) => {
+                        warn!(target: "history", "Migration of bodies to cold store failed: {}", err);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use rand::RngCore;
+
+    use spectrum_crypto::digest::Blake2bDigest256;
+    use spectrum_ledger::block::BlockId;
+    use spectrum_ledger::{SerializedModifier, SlotNo};
+
+    use crate::history::bodies::{FsColdStore, RetentionConfig, TieredBodyStore};
+
+    fn store() -> TieredBodyStore<FsColdStore> {
+        let rnd = rand::thread_rng().next_u32();
+        TieredBodyStore {
+            db: Arc::new(
+                rocksdb::OptimisticTransactionDB::open_default(format!("./tmp/hot_{}", rnd)).unwrap(),
+            ),
+            cold: Arc::new(FsColdStore {
+                root: format!("./tmp/cold_{}", rnd).into(),
+            }),
+            retention: RetentionConfig {
+                hot_slots: 10,
+                migration_batch_size: 2,
+                migration_interval: Duration::from_secs(1),
+            },
+        }
+    }
+
+    #[async_std::test]
+    async fn old_bodies_moved_to_cold_store() {
+        let store = store();
+        let blocks = (0..4u64)
+            .map(|i| {
+                (
+                    BlockId::from(Blake2bDigest256::random()),
+                    SlotNo::from(i * 5),
+                    SerializedModifier(vec![i as u8; 16]),
+                )
+            })
+            .collect::<Vec<_>>();
+        for (id, slot, body) in &blocks {
+            store.put(*id, *slot, body.clone()).await;
+        }
+        // Bodies at slots 0, 5 and 10 fell out of the retention window, two of them per run.
+        assert_eq!(store.migrate(SlotNo::from(20)).await.unwrap(), 2);
+        assert_eq!(store.migrate(SlotNo::from(20)).await.unwrap(), 1);
+        assert_eq!(store.migrate(SlotNo::from(20)).await.unwrap(), 0);
+        let (old_id, _, old_body) = &blocks[0];
+        assert!(store.db.get(super::body_key(*old_id)).unwrap().is_none());
+        assert_eq!(store.cold.get(*old_id).await.unwrap().as_ref(), Some(old_body));
+        let ids = blocks.iter().map(|(id, _, _)| *id).collect();
+        assert_eq!(
+            store.multi_get(ids).await,
+            blocks.into_iter().map(|(_, _, body)| body).collect::<Vec<_>>()
+        );
+    }
+}
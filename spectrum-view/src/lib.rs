@@ -1,5 +1,9 @@
 pub mod chain;
+pub mod committee;
+pub mod finality;
 pub mod history;
+pub mod mempool;
 pub mod node_view;
+pub mod snapshot;
 pub mod state;
 pub mod versioned_avl_storage;
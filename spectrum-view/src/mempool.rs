@@ -0,0 +1,718 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+
+use spectrum_crypto::digest::Blake2bDigest256;
+use spectrum_ledger::cell::{AnyCell, CellId, CellMeta, CellPtr, DatumRef, Owner, ScriptRef};
+use spectrum_ledger::interop::Point;
+use spectrum_ledger::transaction::{EvaluatedTransaction, Transaction, TxId, TxPackage};
+use spectrum_ledger::{ChainId, ModifierId, ModifierType, SerializedModifier, SystemDigest};
+use spectrum_move::{SerializedModule, SerializedValue};
+
+use crate::state::eval::{EvaluationError, ProgrammableTxEvaluator, TxEvaluator};
+use crate::state::linking::{LedgerTxLinker, LinkingError, TxLinker};
+use crate::state::Cells;
+
+/// Max number of transactions in a single package.
+pub const MAX_PACKAGE_SIZE: usize = 25;
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug, thiserror::Error)]
+pub enum TxRejection {
+    #[error("Linking failed: {0:?}")]
+    Linking(LinkingError),
+    #[error("Evaluation failed: {0:?}")]
+    Evaluation(EvaluationError),
+}
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug, thiserror::Error)]
+pub enum PackageError {
+    #[error("Empty package")]
+    EmptyPackage,
+    #[error("Package of {0} transactions exceeds size limit")]
+    TooLarge(usize),
+    #[error("Transaction {0:?} is already known")]
+    AlreadyKnown(TxId),
+    #[error("Transaction #{index} spends cell {cell:?} which is already spent by {spent_by:?}")]
+    DoubleSpend {
+        index: usize,
+        cell: CellId,
+        spent_by: TxId,
+    },
+    #[error("Transaction #{index} is invalid: {err}")]
+    InvalidMember { index: usize, err: TxRejection },
+    #[error("Transaction #{index} pays fee {fee} while at least {required} is required")]
+    InsufficientFee { index: usize, fee: u64, required: u64 },
+    #[error("Transaction #{index} exceeds the limit of unconfirmed transactions of its sender")]
+    SenderLimitExceeded { index: usize },
+    #[error("Package replaces {0} packages which exceeds the replacement limit")]
+    TooManyReplacements(usize),
+    #[error("Package pays fee {fee} while at least {required} is required to replace conflicting packages")]
+    ReplacementUnderpriced { fee: u64, required: u64 },
+}
+
+/// Admission policy of the [`Mempool`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MempoolConfig {
+    /// Min fee in native coins paid by each transaction.
+    pub min_fee: u64,
+    /// Packages which stay unconfirmed for longer are evicted, see [`Mempool::evict_expired`].
+    pub max_age: Duration,
+    /// Max number of unconfirmed transactions of a single sender,
+    /// i.e. the owner of the first input of a transaction.
+    pub max_txs_per_sender: usize,
+    /// A package conflicting with unconfirmed packages replaces them only if it pays
+    /// at least this many percent more in fees than all of the replaced packages.
+    pub replacement_fee_bump_pct: u64,
+    /// Max number of packages evicted by a single replacement, descendants included.
+    pub max_replaced_packages: usize,
+}
+
+impl Default for MempoolConfig {
+    fn default() -> Self {
+        Self {
+            min_fee: 0,
+            max_age: Duration::from_secs(60 * 60),
+            max_txs_per_sender: 100,
+            replacement_fee_bump_pct: 10,
+            max_replaced_packages: 100,
+        }
+    }
+}
+
+pub trait MempoolWrite {
+    /// Validate the given package against the ledger state extended with unconfirmed transactions.
+    /// All members of the package are admitted together, or none of them.
+    fn accept_package<P: Cells>(&mut self, state: &P, pkg: TxPackage) -> Result<ModifierId, PackageError>;
+}
+
+/// Read-only async API to unconfirmed transactions.
+#[async_trait]
+pub trait MempoolReadAsync: Send + Sync {
+    /// Check if the given transaction or package is in the pool.
+    async fn contains(&self, id: &ModifierId) -> bool;
+    /// Bulk select transactions or packages. Modifiers of other types are never found.
+    /// The modifiers are returned in serialized form.
+    async fn multi_get_raw(&self, mod_type: ModifierType, ids: Vec<ModifierId>) -> Vec<SerializedModifier>;
+}
+
+struct PooledTx {
+    tx: Transaction,
+    pkg_id: ModifierId,
+    sender: Owner,
+    fee: u64,
+    /// Cells consumed by the transaction.
+    inputs: Vec<CellId>,
+    /// Cells created by the transaction.
+    outputs: Vec<CellId>,
+}
+
+struct PooledPackage {
+    tx_ids: Vec<TxId>,
+    fee: u64,
+    admitted_at: Instant,
+}
+
+/// Pool of unconfirmed transactions.
+/// Transactions are admitted in packages, so that a child can be accepted along with
+/// its yet unconfirmed parents.
+#[derive(Default)]
+pub struct Mempool {
+    conf: MempoolConfig,
+    txs: HashMap<TxId, PooledTx>,
+    packages: HashMap<ModifierId, PooledPackage>,
+    /// Cells created by unconfirmed transactions along with the creating transaction.
+    created: HashMap<CellId, (TxId, CellMeta<AnyCell>)>,
+    /// Cells consumed by unconfirmed transactions.
+    spent: HashMap<CellId, TxId>,
+    /// Number of unconfirmed transactions of each sender.
+    txs_per_sender: BTreeMap<Owner, usize>,
+}
+
+impl Mempool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_config(mut self, conf: MempoolConfig) -> Self {
+        self.conf = conf;
+        self
+    }
+
+    pub fn contains(&self, tx_id: &TxId) -> bool {
+        self.txs.contains_key(tx_id)
+    }
+
+    pub fn get_tx(&self, tx_id: &TxId) -> Option<&Transaction> {
+        self.txs.get(tx_id).map(|ptx| &ptx.tx)
+    }
+
+    /// Get package by its ID in the form it is relayed to peers.
+    pub fn get_package(&self, pkg_id: &ModifierId) -> Option<TxPackage> {
+        self.packages.get(pkg_id).map(|pkg| {
+            TxPackage(
+                pkg.tx_ids
+                    .iter()
+                    .filter_map(|tx_id| self.get_tx(tx_id).cloned())
+                    .collect(),
+            )
+        })
+    }
+
+    /// Evict packages which stayed unconfirmed for longer than [`MempoolConfig::max_age`]
+    /// along with their descendants. IDs of the evicted packages are returned.
+    pub fn evict_expired(&mut self, now: Instant) -> Vec<ModifierId> {
+        let expired = self
+            .packages
+            .iter()
+            .filter(|(_, pkg)| pkg.admitted_at + self.conf.max_age <= now)
+            .map(|(pkg_id, _)| *pkg_id)
+            .collect();
+        let evicted = self.with_descendants(expired);
+        for pkg_id in &evicted {
+            self.remove_package(pkg_id);
+        }
+        evicted.into_iter().collect()
+    }
+
+    /// Complement the given packages with all packages spending their outputs, transitively.
+    fn with_descendants(&self, mut pkgs: HashSet<ModifierId>) -> HashSet<ModifierId> {
+        let mut queue = pkgs.iter().copied().collect::<Vec<_>>();
+        while let Some(pkg_id) = queue.pop() {
+            for tx_id in &self.packages[&pkg_id].tx_ids {
+                for cell in &self.txs[tx_id].outputs {
+                    if let Some(spender) = self.spent.get(cell) {
+                        let child = self.txs[spender].pkg_id;
+                        if pkgs.insert(child) {
+                            queue.push(child);
+                        }
+                    }
+                }
+            }
+        }
+        pkgs
+    }
+
+    fn remove_package(&mut self, pkg_id: &ModifierId) {
+        if let Some(pkg) = self.packages.remove(pkg_id) {
+            for tx_id in pkg.tx_ids {
+                if let Some(ptx) = self.txs.remove(&tx_id) {
+                    for cell in ptx.inputs {
+                        self.spent.remove(&cell);
+                    }
+                    for cell in ptx.outputs {
+                        self.created.remove(&cell);
+                    }
+                    if let Some(n) = self.txs_per_sender.get_mut(&ptx.sender) {
+                        *n -= 1;
+                        if *n == 0 {
+                            self.txs_per_sender.remove(&ptx.sender);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl MempoolWrite for Mempool {
+    fn accept_package<P: Cells>(&mut self, state: &P, pkg: TxPackage) -> Result<ModifierId, PackageError> {
+        let size = pkg.0.len();
+        if size == 0 {
+            return Err(PackageError::EmptyPackage);
+        }
+        if size > MAX_PACKAGE_SIZE {
+            return Err(PackageError::TooLarge(size));
+        }
+        // Unconfirmed packages spending the same cells are candidates for replacement.
+        let conflicting = pkg
+            .0
+            .iter()
+            .flat_map(|tx| tx.body.inputs.clone())
+            .filter_map(|(ptr, _)| self.spent.get(&ptr.cell_id()))
+            .map(|tx_id| self.txs[tx_id].pkg_id)
+            .collect();
+        let replaced = self.with_descendants(conflicting);
+        let evicted = replaced
+            .iter()
+            .flat_map(|pkg_id| self.packages[pkg_id].tx_ids.iter().copied())
+            .collect::<HashSet<_>>();
+        let mut view = PackageView {
+            state,
+            mempool: self,
+            evicted: &evicted,
+            created: HashMap::new(),
+            spent: HashMap::new(),
+        };
+        let mut txs_per_sender = self.txs_per_sender.clone();
+        for tx_id in &evicted {
+            if let Some(n) = txs_per_sender.get_mut(&self.txs[tx_id].sender) {
+                *n -= 1;
+            }
+        }
+        let pkg_id = ModifierId::from(pkg.digest());
+        let mut members = Vec::with_capacity(size);
+        for (index, tx) in pkg.0.iter().enumerate() {
+            let tx_id = tx.id();
+            if (self.txs.contains_key(&tx_id) && !evicted.contains(&tx_id))
+                || members.iter().any(|m: &PooledTx| m.tx.id() == tx_id)
+            {
+                return Err(PackageError::AlreadyKnown(tx_id));
+            }
+            let mut consumed = vec![];
+            for (ptr, _) in tx.body.inputs.clone() {
+                let cell = ptr.cell_id();
+                if let Some(spent_by) = view.spent_by(&cell) {
+                    return Err(PackageError::DoubleSpend {
+                        index,
+                        cell,
+                        spent_by,
+                    });
+                }
+                if consumed.contains(&cell) {
+                    return Err(PackageError::DoubleSpend {
+                        index,
+                        cell,
+                        spent_by: tx_id,
+                    });
+                }
+                consumed.push(cell);
+            }
+            let linked = LedgerTxLinker { pool: &view }
+                .link_transaction(tx.clone())
+                .map_err(|err| PackageError::InvalidMember {
+                    index,
+                    err: TxRejection::Linking(err),
+                })?;
+            let evaluated = ProgrammableTxEvaluator { pool: &view }
+                .evaluate_transaction(linked)
+                .map_err(|err| PackageError::InvalidMember {
+                    index,
+                    err: TxRejection::Evaluation(err),
+                })?;
+            let fee = fee(&evaluated);
+            if fee < self.conf.min_fee {
+                return Err(PackageError::InsufficientFee {
+                    index,
+                    fee,
+                    required: self.conf.min_fee,
+                });
+            }
+            let sender = evaluated.inputs[0].owner;
+            let sender_txs = txs_per_sender.entry(sender).or_default();
+            if *sender_txs >= self.conf.max_txs_per_sender {
+                return Err(PackageError::SenderLimitExceeded { index });
+            }
+            *sender_txs += 1;
+            for cell in &consumed {
+                view.spent.insert(*cell, tx_id);
+            }
+            let mut outputs = vec![];
+            for output in evaluated.outputs {
+                let cell = output.cell.id();
+                outputs.push(cell);
+                view.created.insert(cell, (tx_id, output));
+            }
+            members.push(PooledTx {
+                tx: tx.clone(),
+                pkg_id,
+                sender,
+                fee,
+                inputs: consumed,
+                outputs,
+            });
+        }
+        let PackageView { created, spent, .. } = view;
+        let pkg_fee = members.iter().map(|m| m.fee).sum::<u64>();
+        if !replaced.is_empty() {
+            if replaced.len() > self.conf.max_replaced_packages {
+                return Err(PackageError::TooManyReplacements(replaced.len()));
+            }
+            let replaced_fee = replaced
+                .iter()
+                .map(|pkg_id| self.packages[pkg_id].fee)
+                .sum::<u64>();
+            let bump = (replaced_fee.saturating_mul(self.conf.replacement_fee_bump_pct) / 100).max(1);
+            let required = replaced_fee.saturating_add(bump);
+            if pkg_fee < required {
+                return Err(PackageError::ReplacementUnderpriced {
+                    fee: pkg_fee,
+                    required,
+                });
+            }
+            for pkg_id in &replaced {
+                self.remove_package(pkg_id);
+            }
+        }
+        self.created.extend(created);
+        self.spent.extend(spent);
+        let mut tx_ids = Vec::with_capacity(size);
+        for member in members {
+            let tx_id = member.tx.id();
+            *self.txs_per_sender.entry(member.sender).or_default() += 1;
+            self.txs.insert(tx_id, member);
+            tx_ids.push(tx_id);
+        }
+        self.packages.insert(
+            pkg_id,
+            PooledPackage {
+                tx_ids,
+                fee: pkg_fee,
+                admitted_at: Instant::now(),
+            },
+        );
+        Ok(pkg_id)
+    }
+}
+
+#[async_trait]
+impl MempoolReadAsync for async_std::sync::RwLock<Mempool> {
+    async fn contains(&self, id: &ModifierId) -> bool {
+        let mempool = self.read().await;
+        mempool.packages.contains_key(id) || mempool.contains(&TxId::from(<Blake2bDigest256>::from(*id)))
+    }
+
+    async fn multi_get_raw(&self, mod_type: ModifierType, ids: Vec<ModifierId>) -> Vec<SerializedModifier> {
+        let mempool = self.read().await;
+        let mut raw = vec![];
+        for id in ids {
+            let mut encoded = Vec::new();
+            let found = match mod_type {
+                ModifierType::Transaction => mempool
+                    .get_tx(&TxId::from(<Blake2bDigest256>::from(id)))
+                    .map(|tx| ciborium::ser::into_writer(tx, &mut encoded)),
+                ModifierType::TxPackage => mempool
+                    .get_package(&id)
+                    .map(|pkg| ciborium::ser::into_writer(&pkg, &mut encoded)),
+                ModifierType::BlockHeader | ModifierType::BlockBody => None,
+            };
+            if let Some(Ok(())) = found {
+                raw.push(SerializedModifier(encoded));
+            }
+        }
+        raw
+    }
+}
+
+/// Native coins consumed by the transaction and not locked in its outputs.
+fn fee(tx: &EvaluatedTransaction) -> u64 {
+    let inputs = tx
+        .inputs
+        .iter()
+        .map(|cell| u64::from(cell.value.native))
+        .sum::<u64>();
+    let outputs = tx
+        .outputs
+        .iter()
+        .map(|output| match &output.cell {
+            AnyCell::Mut(cell) => u64::from(cell.value.native),
+            AnyCell::Term(cell) => u64::from(cell.value.native),
+        })
+        .sum::<u64>();
+    inputs.saturating_sub(outputs)
+}
+
+/// Ledger state as seen by a member of the package under validation:
+/// confirmed cells, plus cells created by unconfirmed transactions and preceding members of the package,
+/// minus cells consumed by them.
+struct PackageView<'a, P> {
+    state: &'a P,
+    mempool: &'a Mempool,
+    /// Unconfirmed transactions replaced by the package.
+    evicted: &'a HashSet<TxId>,
+    created: HashMap<CellId, (TxId, CellMeta<AnyCell>)>,
+    spent: HashMap<CellId, TxId>,
+}
+
+impl<'a, P> PackageView<'a, P> {
+    fn spent_by(&self, cell: &CellId) -> Option<TxId> {
+        self.spent
+            .get(cell)
+            .or_else(|| self.mempool.spent.get(cell))
+            .filter(|tx_id| !self.evicted.contains(tx_id))
+            .copied()
+    }
+}
+
+impl<'a, P: Cells> Cells for PackageView<'a, P> {
+    fn get_cell(&self, ptr: CellPtr) -> Option<CellMeta<AnyCell>> {
+        let id = ptr.cell_id();
+        if self.spent_by(&id).is_some() {
+            return None;
+        }
+        let created = self
+            .created
+            .get(&id)
+            .or_else(|| self.mempool.created.get(&id))
+            .filter(|(tx_id, _)| !self.evicted.contains(tx_id));
+        match created {
+            Some((_, cell)) => match ptr {
+                CellPtr::Ref(cref) if cell.cell.cref() != cref => None,
+                _ => Some(cell.clone()),
+            },
+            None => self.state.get_cell(ptr),
+        }
+    }
+
+    fn progress_of(&self, chain_id: ChainId) -> Point {
+        self.state.progress_of(chain_id)
+    }
+
+    fn get_ref_script(&self, script_ref: ScriptRef) -> Option<SerializedModule> {
+        self.state.get_ref_script(script_ref)
+    }
+
+    fn get_ref_datum(&self, datum_ref: DatumRef) -> Option<SerializedValue> {
+        self.state.get_ref_datum(datum_ref)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Instant;
+
+    use k256::elliptic_curve::rand_core::OsRng;
+    use k256::schnorr::signature::Signer;
+    use k256::schnorr::{SigningKey, VerifyingKey};
+    use k256::SecretKey;
+
+    use spectrum_crypto::digest::Blake2bDigest256;
+    use spectrum_ledger::cell::{
+        ActiveCell, AnyCell, CellId, CellMeta, CellPtr, DatumRef, NativeCoin, Owner, SValue, ScriptRef,
+        Serial,
+    };
+    use spectrum_ledger::interop::Point;
+    use spectrum_ledger::transaction::{Transaction, TransactionBody, TxId, TxInputs, TxPackage, Witness};
+    use spectrum_ledger::{ChainId, SystemDigest};
+    use spectrum_move::{SerializedModule, SerializedValue};
+
+    use crate::mempool::{Mempool, MempoolConfig, MempoolWrite, PackageError, TxRejection};
+    use crate::state::linking::LinkingError;
+    use crate::state::Cells;
+
+    struct State(HashMap<CellId, CellMeta<AnyCell>>);
+
+    impl Cells for State {
+        fn get_cell(&self, ptr: CellPtr) -> Option<CellMeta<AnyCell>> {
+            match ptr {
+                CellPtr::Id(id) => self.0.get(&id).cloned(),
+                CellPtr::Ref(cref) => {
+                    let (id, _): (CellId, Serial) = cref.into();
+                    self.0.get(&id).filter(|c| c.cell.cref() == cref).cloned()
+                }
+            }
+        }
+        fn progress_of(&self, _: ChainId) -> Point {
+            Point::from(0)
+        }
+        fn get_ref_script(&self, _: ScriptRef) -> Option<SerializedModule> {
+            None
+        }
+        fn get_ref_datum(&self, _: DatumRef) -> Option<SerializedValue> {
+            None
+        }
+    }
+
+    fn keypair() -> (SecretKey, Owner) {
+        loop {
+            let sk = SecretKey::random(&mut OsRng);
+            let pk = sk.public_key();
+            if VerifyingKey::try_from(pk).is_ok() {
+                return (sk, Owner::ProveDlog(pk));
+            }
+        }
+    }
+
+    fn cell(owner: Owner, amount: u64) -> ActiveCell {
+        ActiveCell {
+            value: SValue {
+                native: NativeCoin::from(amount),
+                assets: HashMap::new(),
+            },
+            owner,
+            datum: None,
+            reference_script: None,
+            reference_datum: None,
+            tx_id: TxId::from(Blake2bDigest256::random()),
+            index: 0,
+            ver: Serial::INITIAL,
+        }
+    }
+
+    /// Transaction spending `input` into a single output owned by the same key.
+    fn spend(sk: &SecretKey, owner: Owner, input: &ActiveCell) -> (Transaction, ActiveCell) {
+        spend_paying(sk, owner, input, 0)
+    }
+
+    fn spend_paying(sk: &SecretKey, owner: Owner, input: &ActiveCell, fee: u64) -> (Transaction, ActiveCell) {
+        let output = cell(owner, u64::from(input.value.native) - fee);
+        let mut tx = Transaction {
+            body: TransactionBody {
+                inputs: TxInputs {
+                    head: (input.cref(), Some(0)),
+                    tail: vec![],
+                },
+                reference_inputs: vec![],
+                invocations: vec![],
+                evaluated_outputs: vec![AnyCell::Mut(output.clone())],
+            },
+            witness: Witness {
+                scripts: vec![],
+                data: vec![],
+                signatures: vec![],
+            },
+        };
+        let sig: k256::schnorr::Signature = SigningKey::from(sk).sign(tx.digest().as_ref());
+        tx.witness.signatures.push(sig.into());
+        (tx, output)
+    }
+
+    fn state_with(cell: &ActiveCell) -> State {
+        State(HashMap::from([(
+            cell.id(),
+            CellMeta {
+                cell: AnyCell::Mut(cell.clone()),
+                ancors: vec![],
+            },
+        )]))
+    }
+
+    #[test]
+    fn accept_chained_package() {
+        let (sk, owner) = keypair();
+        let confirmed = cell(owner, 100);
+        let state = state_with(&confirmed);
+        let (parent, parent_out) = spend(&sk, owner, &confirmed);
+        let (child, _) = spend(&sk, owner, &parent_out);
+        let pkg = TxPackage(vec![parent.clone(), child.clone()]);
+        let mut mempool = Mempool::new();
+        let pkg_id = mempool.accept_package(&state, pkg.clone()).unwrap();
+        assert!(mempool.contains(&parent.id()));
+        assert!(mempool.contains(&child.id()));
+        assert_eq!(mempool.get_package(&pkg_id), Some(pkg));
+    }
+
+    #[test]
+    fn reject_orphan_child() {
+        let (sk, owner) = keypair();
+        let confirmed = cell(owner, 100);
+        let state = state_with(&confirmed);
+        let (_, parent_out) = spend(&sk, owner, &confirmed);
+        let (child, _) = spend(&sk, owner, &parent_out);
+        let mut mempool = Mempool::new();
+        assert_eq!(
+            mempool.accept_package(&state, TxPackage::from(child)),
+            Err(PackageError::InvalidMember {
+                index: 0,
+                err: TxRejection::Linking(LinkingError::MissingInput(CellPtr::Ref(parent_out.cref())))
+            })
+        );
+    }
+
+    #[test]
+    fn package_is_rejected_atomically() {
+        let (sk, owner) = keypair();
+        let confirmed = cell(owner, 100);
+        let state = state_with(&confirmed);
+        let (parent, parent_out) = spend(&sk, owner, &confirmed);
+        let (mut child, _) = spend(&sk, owner, &parent_out);
+        child.witness.signatures = parent.witness.signatures.clone();
+        let mut mempool = Mempool::new();
+        let res = mempool.accept_package(&state, TxPackage(vec![parent.clone(), child]));
+        assert!(matches!(
+            res,
+            Err(PackageError::InvalidMember {
+                index: 1,
+                err: TxRejection::Evaluation(_)
+            })
+        ));
+        assert!(!mempool.contains(&parent.id()));
+    }
+
+    #[test]
+    fn reject_conflicting_package() {
+        let (sk, owner) = keypair();
+        let confirmed = cell(owner, 100);
+        let state = state_with(&confirmed);
+        let (tx, _) = spend(&sk, owner, &confirmed);
+        let (conflicting_tx, _) = spend(&sk, owner, &confirmed);
+        let mut mempool = Mempool::new();
+        mempool
+            .accept_package(&state, TxPackage::from(tx.clone()))
+            .unwrap();
+        assert_eq!(
+            mempool.accept_package(&state, TxPackage::from(conflicting_tx)),
+            Err(PackageError::ReplacementUnderpriced { fee: 0, required: 1 })
+        );
+        assert!(mempool.contains(&tx.id()));
+    }
+
+    #[test]
+    fn replacement_evicts_conflicting_package_with_descendants() {
+        let (sk, owner) = keypair();
+        let confirmed = cell(owner, 100);
+        let state = state_with(&confirmed);
+        let (tx, tx_out) = spend(&sk, owner, &confirmed);
+        let (child, _) = spend(&sk, owner, &tx_out);
+        let (replacement, _) = spend_paying(&sk, owner, &confirmed, 10);
+        let mut mempool = Mempool::new();
+        mempool
+            .accept_package(&state, TxPackage::from(tx.clone()))
+            .unwrap();
+        mempool
+            .accept_package(&state, TxPackage::from(child.clone()))
+            .unwrap();
+        mempool
+            .accept_package(&state, TxPackage::from(replacement.clone()))
+            .unwrap();
+        assert!(!mempool.contains(&tx.id()));
+        assert!(!mempool.contains(&child.id()));
+        assert!(mempool.contains(&replacement.id()));
+    }
+
+    #[test]
+    fn admission_policies() {
+        let (sk, owner) = keypair();
+        let confirmed = cell(owner, 100);
+        let state = state_with(&confirmed);
+        let (parent, parent_out) = spend_paying(&sk, owner, &confirmed, 5);
+        let (child, _) = spend_paying(&sk, owner, &parent_out, 5);
+        let conf = MempoolConfig {
+            min_fee: 5,
+            max_txs_per_sender: 1,
+            ..MempoolConfig::default()
+        };
+        let mut mempool = Mempool::new().with_config(conf);
+        assert_eq!(
+            mempool.accept_package(&state, TxPackage::from(spend(&sk, owner, &confirmed).0)),
+            Err(PackageError::InsufficientFee {
+                index: 0,
+                fee: 0,
+                required: 5
+            })
+        );
+        assert_eq!(
+            mempool.accept_package(&state, TxPackage(vec![parent, child])),
+            Err(PackageError::SenderLimitExceeded { index: 1 })
+        );
+    }
+
+    #[test]
+    fn expired_packages_are_evicted() {
+        let (sk, owner) = keypair();
+        let confirmed = cell(owner, 100);
+        let state = state_with(&confirmed);
+        let (tx, _) = spend(&sk, owner, &confirmed);
+        let mut mempool = Mempool::new();
+        let pkg_id = mempool
+            .accept_package(&state, TxPackage::from(tx.clone()))
+            .unwrap();
+        assert!(mempool.evict_expired(Instant::now()).is_empty());
+        let max_age = MempoolConfig::default().max_age;
+        assert_eq!(mempool.evict_expired(Instant::now() + max_age), vec![pkg_id]);
+        assert!(!mempool.contains(&tx.id()));
+        // Cells spent by the evicted transaction are available again.
+        mempool.accept_package(&state, TxPackage::from(tx)).unwrap();
+    }
+}
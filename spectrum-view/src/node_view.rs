@@ -1,6 +1,26 @@
+use libp2p_identity::PeerId;
+
 use spectrum_ledger::Modifier;
 
+use crate::snapshot::{SignedSnapshot, StateSnapshot};
+
+/// Where a modifier came from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ModifierSource {
+    /// Produced by the node itself.
+    Local,
+    /// Received from the given peer.
+    Remote(PeerId),
+}
+
 #[async_trait::async_trait]
 pub trait NodeViewWriteAsync: Send + Sync + Clone {
-    async fn apply_modifier(&mut self, modifier: Modifier);
+    async fn apply_modifier(&mut self, modifier: Modifier, source: ModifierSource);
+    /// Install the verified snapshot of the ledger state as the new tip.
+    async fn install_snapshot(
+        &mut self,
+        snapshot: SignedSnapshot,
+        state: StateSnapshot,
+        source: ModifierSource,
+    );
 }
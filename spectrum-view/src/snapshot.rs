@@ -0,0 +1,389 @@
+//! Snapshots of the ledger state at finalized blocks.
+//!
+//! A node joining the network can install a snapshot as its initial state instead of replaying
+//! the chain from genesis. The snapshot is split into chunks listed in a manifest, and the manifest
+//! is signed by the committee in charge of the slot of the snapshot, so that chunks can be fetched
+//! from untrusted peers and verified one by one.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use spectrum_crypto::digest::{blake2b256_hash, Blake2b256, Blake2bDigest256};
+use spectrum_handel::Threshold;
+use spectrum_ledger::block::{BlockHeader, BlockId};
+use spectrum_ledger::cell::{AnyCell, CellMeta};
+use spectrum_ledger::interop::Point;
+use spectrum_ledger::{ChainId, Modifier, ModifierId, SlotNo};
+use spectrum_sigma::crypto::verify;
+use spectrum_sigma::sigma_aggregation::AggregateCertificate;
+
+use crate::finality::{Checkpoint, Committees};
+
+/// Ledger state at some block.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct StateSnapshot {
+    pub cells: Vec<CellMeta<AnyCell>>,
+    pub progress: Vec<(ChainId, Point)>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+enum SnapshotEntry {
+    Cell(CellMeta<AnyCell>),
+    Progress(ChainId, Point),
+}
+
+/// Part of a snapshot, a sequence of CBOR-encoded entries.
+#[derive(Clone, Eq, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+pub struct SnapshotChunk(pub Vec<u8>);
+
+impl SnapshotChunk {
+    pub fn digest(&self) -> Blake2bDigest256 {
+        blake2b256_hash(&self.0)
+    }
+
+    fn decode(&self) -> Result<Vec<SnapshotEntry>, ciborium::de::Error<std::io::Error>> {
+        let mut rd = &self.0[..];
+        let mut entries = Vec::new();
+        while !rd.is_empty() {
+            entries.push(ciborium::de::from_reader(&mut rd)?);
+        }
+        Ok(entries)
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+pub struct SnapshotManifest {
+    pub checkpoint: Checkpoint,
+    /// Digests of chunks in the order they are assembled in.
+    pub chunks: Vec<Blake2bDigest256>,
+}
+
+impl SnapshotManifest {
+    /// Digest the committee signs.
+    pub fn digest(&self) -> Blake2bDigest256 {
+        let mut encoded = Vec::new();
+        ciborium::ser::into_writer(self, &mut encoded).unwrap();
+        blake2b256_hash(&encoded)
+    }
+}
+
+/// Manifest of a snapshot signed by the committee along with the header of the snapshot block.
+#[derive(Clone, Eq, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+pub struct SignedSnapshot {
+    pub header: BlockHeader,
+    pub manifest: SnapshotManifest,
+    pub certificate: AggregateCertificate<Blake2b256>,
+}
+
+impl StateSnapshot {
+    /// Split the snapshot of the state at the given checkpoint into chunks of
+    /// roughly `max_chunk_bytes` each.
+    pub fn split(
+        self,
+        checkpoint: Checkpoint,
+        max_chunk_bytes: usize,
+    ) -> (SnapshotManifest, Vec<SnapshotChunk>) {
+        let entries = self.cells.into_iter().map(SnapshotEntry::Cell).chain(
+            self.progress
+                .into_iter()
+                .map(|(chain_id, point)| SnapshotEntry::Progress(chain_id, point)),
+        );
+        let mut chunks = Vec::new();
+        let mut chunk = Vec::new();
+        for entry in entries {
+            if !chunk.is_empty() && chunk.len() >= max_chunk_bytes {
+                chunks.push(SnapshotChunk(std::mem::take(&mut chunk)));
+            }
+            ciborium::ser::into_writer(&entry, &mut chunk).unwrap();
+        }
+        if !chunk.is_empty() {
+            chunks.push(SnapshotChunk(chunk));
+        }
+        let manifest = SnapshotManifest {
+            checkpoint,
+            chunks: chunks.iter().map(SnapshotChunk::digest).collect(),
+        };
+        (manifest, chunks)
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, thiserror::Error)]
+pub enum SnapshotError {
+    #[error("Header doesn't match checkpoint {0:?}")]
+    HeaderMismatch(Checkpoint),
+    #[error("Certificate doesn't commit to the manifest")]
+    DigestMismatch,
+    #[error("No committee known for slot {0:?}")]
+    UnknownCommittee(SlotNo),
+    #[error("Invalid certificate")]
+    InvalidCertificate,
+    #[error("Chunk #{0} is not listed in the manifest")]
+    UnexpectedChunk(u32),
+    #[error("Chunk #{0} doesn't match the manifest")]
+    ChunkMismatch(u32),
+    #[error("Chunk #{0} is malformed")]
+    MalformedChunk(u32),
+}
+
+/// Checks snapshots against certificates of the committee.
+#[derive(Clone)]
+pub struct SnapshotVerifier {
+    pub committees: Arc<dyn Committees>,
+    /// Share of the committee which must have signed a manifest.
+    pub threshold: Threshold,
+}
+
+impl SnapshotVerifier {
+    pub fn verify(&self, snapshot: &SignedSnapshot) -> Result<(), SnapshotError> {
+        let checkpoint = snapshot.manifest.checkpoint;
+        if snapshot.header.id() != ModifierId::from(checkpoint.id)
+            || snapshot.header.body.slot_num != checkpoint.slot
+        {
+            return Err(SnapshotError::HeaderMismatch(checkpoint));
+        }
+        self.verify_manifest(&snapshot.manifest, &snapshot.certificate)
+    }
+
+    pub fn verify_manifest(
+        &self,
+        manifest: &SnapshotManifest,
+        certificate: &AggregateCertificate<Blake2b256>,
+    ) -> Result<(), SnapshotError> {
+        if certificate.message_digest != manifest.digest() {
+            return Err(SnapshotError::DigestMismatch);
+        }
+        let slot = manifest.checkpoint.slot;
+        let committee = self
+            .committees
+            .get_committee(slot)
+            .ok_or(SnapshotError::UnknownCommittee(slot))?;
+        if verify(
+            certificate.aggregate_commitment.clone(),
+            certificate.aggregate_response,
+            certificate.exclusion_set.clone(),
+            committee,
+            certificate.message_digest,
+            self.threshold,
+        ) {
+            Ok(())
+        } else {
+            Err(SnapshotError::InvalidCertificate)
+        }
+    }
+}
+
+/// Collects chunks of a snapshot delivered in arbitrary order.
+pub struct SnapshotAssembler {
+    manifest: SnapshotManifest,
+    chunks: Vec<Option<Vec<SnapshotEntry>>>,
+}
+
+impl SnapshotAssembler {
+    /// The manifest must be verified beforehand.
+    pub fn new(manifest: SnapshotManifest) -> Self {
+        let chunks = manifest.chunks.iter().map(|_| None).collect();
+        Self { manifest, chunks }
+    }
+
+    pub fn manifest(&self) -> &SnapshotManifest {
+        &self.manifest
+    }
+
+    /// Index of the first chunk not delivered yet.
+    pub fn next_missing(&self) -> Option<u32> {
+        self.chunks.iter().position(Option::is_none).map(|ix| ix as u32)
+    }
+
+    pub fn add_chunk(&mut self, index: u32, chunk: SnapshotChunk) -> Result<(), SnapshotError> {
+        let expected = self
+            .manifest
+            .chunks
+            .get(index as usize)
+            .ok_or(SnapshotError::UnexpectedChunk(index))?;
+        if chunk.digest() != *expected {
+            return Err(SnapshotError::ChunkMismatch(index));
+        }
+        let entries = chunk.decode().map_err(|_| SnapshotError::MalformedChunk(index))?;
+        self.chunks[index as usize] = Some(entries);
+        Ok(())
+    }
+
+    /// Assembled snapshot, `None` until all chunks are delivered.
+    pub fn finish(self) -> Option<StateSnapshot> {
+        let mut snapshot = StateSnapshot::default();
+        for entry in self
+            .chunks
+            .into_iter()
+            .collect::<Option<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+        {
+            match entry {
+                SnapshotEntry::Cell(cell) => snapshot.cells.push(cell),
+                SnapshotEntry::Progress(chain_id, point) => snapshot.progress.push((chain_id, point)),
+            }
+        }
+        Some(snapshot)
+    }
+}
+
+/// Snapshots served to peers bootstrapping from a checkpoint.
+#[async_trait]
+pub trait SnapshotSource: Send + Sync {
+    /// Get snapshot of the state at the given block, if available.
+    async fn get_snapshot(&self, block_id: BlockId) -> Option<SignedSnapshot>;
+    /// Get chunk of the snapshot at the given block.
+    async fn get_chunk(&self, block_id: BlockId, index: u32) -> Option<SnapshotChunk>;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use k256::elliptic_curve::rand_core::OsRng;
+    use k256::SecretKey;
+
+    use spectrum_crypto::digest::{Blake2b256, Blake2bDigest256};
+    use spectrum_crypto::pubkey::PublicKey;
+    use spectrum_handel::Threshold;
+    use spectrum_ledger::block::BlockId;
+    use spectrum_ledger::cell::{ActiveCell, AnyCell, CellMeta, NativeCoin, Owner, SValue, Serial};
+    use spectrum_ledger::interop::Point;
+    use spectrum_ledger::transaction::TxId;
+    use spectrum_ledger::{ChainId, SlotNo};
+    use spectrum_sigma::crypto::{
+        aggregate_commitment, aggregate_pk, aggregate_response, challenge, individual_input, response,
+        schnorr_commitment_pair,
+    };
+    use spectrum_sigma::sigma_aggregation::AggregateCertificate;
+
+    use crate::finality::{Checkpoint, Committees};
+    use crate::snapshot::{
+        SnapshotAssembler, SnapshotChunk, SnapshotError, SnapshotManifest, SnapshotVerifier, StateSnapshot,
+    };
+
+    struct StaticCommittee(Vec<PublicKey>);
+
+    impl Committees for StaticCommittee {
+        fn get_committee(&self, _: SlotNo) -> Option<Vec<PublicKey>> {
+            Some(self.0.clone())
+        }
+    }
+
+    fn state(num_cells: u64) -> StateSnapshot {
+        StateSnapshot {
+            cells: (0..num_cells)
+                .map(|i| CellMeta {
+                    cell: AnyCell::Mut(ActiveCell {
+                        value: SValue {
+                            native: NativeCoin::from(i),
+                            assets: HashMap::new(),
+                        },
+                        owner: Owner::ProveDlog(SecretKey::random(&mut OsRng).public_key()),
+                        datum: None,
+                        reference_script: None,
+                        reference_datum: None,
+                        tx_id: TxId::from(Blake2bDigest256::random()),
+                        index: 0,
+                        ver: Serial::INITIAL,
+                    }),
+                    ancors: vec![],
+                })
+                .collect(),
+            progress: vec![(ChainId::from(0), Point::from(100))],
+        }
+    }
+
+    fn checkpoint() -> Checkpoint {
+        Checkpoint {
+            id: BlockId::random(),
+            slot: SlotNo::from(10),
+        }
+    }
+
+    fn certify(sks: &[SecretKey], manifest: &SnapshotManifest) -> AggregateCertificate<Blake2b256> {
+        let committee = sks
+            .iter()
+            .map(|sk| PublicKey::from(sk.clone()))
+            .collect::<Vec<_>>();
+        let md = manifest.digest();
+        let ais = committee
+            .iter()
+            .map(|pk| individual_input::<Blake2b256>(committee.clone(), pk.clone()))
+            .collect::<Vec<_>>();
+        let aggr_pk = aggregate_pk(committee.clone(), ais.clone());
+        let pairs = sks.iter().map(|_| schnorr_commitment_pair()).collect::<Vec<_>>();
+        let aggr_commitment = aggregate_commitment(pairs.iter().map(|(_, yi)| yi.clone()).collect());
+        let c = challenge(aggr_pk, aggr_commitment.clone(), md);
+        let responses = sks
+            .iter()
+            .zip(pairs)
+            .zip(ais)
+            .map(|((sk, (yi, _)), ai)| response(yi, sk.clone(), c, ai))
+            .collect();
+        AggregateCertificate {
+            message_digest: md,
+            aggregate_commitment: aggr_commitment,
+            aggregate_response: aggregate_response(responses),
+            exclusion_set: vec![],
+        }
+    }
+
+    #[test]
+    fn assemble_chunks_in_any_order() {
+        let state = state(16);
+        let (manifest, chunks) = state.clone().split(checkpoint(), 256);
+        assert!(chunks.len() > 1);
+        let mut assembler = SnapshotAssembler::new(manifest);
+        for (ix, chunk) in chunks.into_iter().enumerate().rev() {
+            assert_eq!(assembler.next_missing(), Some(0));
+            assembler.add_chunk(ix as u32, chunk).unwrap();
+        }
+        assert_eq!(assembler.next_missing(), None);
+        assert_eq!(assembler.finish(), Some(state));
+    }
+
+    #[test]
+    fn reject_foreign_chunks() {
+        let (manifest, chunks) = state(4).split(checkpoint(), 1 << 20);
+        let mut assembler = SnapshotAssembler::new(manifest);
+        assert_eq!(
+            assembler.add_chunk(0, SnapshotChunk(vec![0; 16])),
+            Err(SnapshotError::ChunkMismatch(0))
+        );
+        assert_eq!(
+            assembler.add_chunk(1, chunks[0].clone()),
+            Err(SnapshotError::UnexpectedChunk(1))
+        );
+        assert!(assembler.finish().is_none());
+    }
+
+    #[test]
+    fn verify_manifest_certificate() {
+        let sks = (0..4).map(|_| SecretKey::random(&mut OsRng)).collect::<Vec<_>>();
+        let verifier = SnapshotVerifier {
+            committees: Arc::new(StaticCommittee(
+                sks.iter().map(|sk| PublicKey::from(sk.clone())).collect(),
+            )),
+            threshold: Threshold { num: 2, denom: 3 },
+        };
+        let (manifest, _) = state(4).split(checkpoint(), 1 << 20);
+        let cert = certify(&sks, &manifest);
+        assert_eq!(verifier.verify_manifest(&manifest, &cert), Ok(()));
+
+        let mut tampered = manifest.clone();
+        tampered.chunks.push(Blake2bDigest256::random());
+        assert_eq!(
+            verifier.verify_manifest(&tampered, &cert),
+            Err(SnapshotError::DigestMismatch)
+        );
+
+        let outsiders = (0..4).map(|_| SecretKey::random(&mut OsRng)).collect::<Vec<_>>();
+        assert_eq!(
+            verifier.verify_manifest(&manifest, &certify(&outsiders, &manifest)),
+            Err(SnapshotError::InvalidCertificate)
+        );
+    }
+}
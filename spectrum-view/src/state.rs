@@ -1,28 +1,38 @@
 use spectrum_crypto::digest::Blake2bDigest256;
-use spectrum_ledger::cell::{AnyCell, CellMeta, CellPtr, DatumRef, NativeCoin, ScriptRef};
+use spectrum_ledger::cell::{AnyCell, CellMeta, CellPtr, DatumRef, NativeCoin, Owner, ScriptRef};
 use spectrum_ledger::consensus::AnyRuleId;
 use spectrum_ledger::interop::{Effect, Point};
-use spectrum_ledger::transaction::{EvaluatedTransaction, ValidTx};
+use spectrum_ledger::transaction::{EvaluatedTransaction, TxId, ValidTx};
 use spectrum_ledger::{ChainId, EpochNo, VRFProof};
 use spectrum_ledger::{DomainVKey, KESVKey, StakePoolId};
 use spectrum_move::{SerializedModule, SerializedValue};
 
+use crate::snapshot::StateSnapshot;
+
 pub mod eval;
 pub mod linking;
+pub mod store;
 
 #[derive(Eq, PartialEq, Debug, thiserror::Error)]
 pub enum LedgerStateError {
     #[error("Invalid transaction")]
     InvalidTransaction,
+    #[error("Unknown version {0}")]
+    UnknownVersion(Blake2bDigest256),
 }
 
 pub trait LedgerStateWrite {
     /// Apply valid transaction.
     fn apply_tx(&self, tx: ValidTx<EvaluatedTransaction>) -> Result<(), LedgerStateError>;
-    /// Apply valid effect.
-    fn apply_eff(&self, tx: ValidTx<Effect>) -> Result<(), LedgerStateError>;
-    /// Rollback state to previous version.
-    fn rollback(&self, tag: Blake2bDigest256);
+    /// Apply valid effect observed on the given chain.
+    fn apply_eff(&self, chain_id: ChainId, eff: ValidTx<Effect>) -> Result<(), LedgerStateError>;
+    /// Seal changes applied since the previous version as a new version marked with the given tag.
+    fn commit(&self, tag: Blake2bDigest256);
+    /// Rollback state to the version marked with the given tag.
+    fn rollback(&self, tag: Blake2bDigest256) -> Result<(), LedgerStateError>;
+    /// Replace the whole state with the given snapshot as a version marked with the given tag.
+    /// Earlier versions can't be rolled back to afterwards.
+    fn install_snapshot(&self, tag: Blake2bDigest256, snapshot: StateSnapshot);
 }
 
 /// Pool of cells.
@@ -37,6 +47,30 @@ pub trait Cells {
     fn get_ref_datum(&self, datum_ref: DatumRef) -> Option<SerializedValue>;
 }
 
+impl<T: Cells> Cells for &T {
+    fn get_cell(&self, ptr: CellPtr) -> Option<CellMeta<AnyCell>> {
+        (*self).get_cell(ptr)
+    }
+    fn progress_of(&self, chain_id: ChainId) -> Point {
+        (*self).progress_of(chain_id)
+    }
+    fn get_ref_script(&self, script_ref: ScriptRef) -> Option<SerializedModule> {
+        (*self).get_ref_script(script_ref)
+    }
+    fn get_ref_datum(&self, datum_ref: DatumRef) -> Option<SerializedValue> {
+        (*self).get_ref_datum(datum_ref)
+    }
+}
+
+/// Indexes of the state serving wallets and explorers.
+pub trait StateIndexes {
+    /// Get all unspent cells of the given owner.
+    fn get_cells_of(&self, owner: Owner) -> Vec<CellMeta<AnyCell>>;
+    /// Check if the given transaction is applied to the state.
+    /// Transactions applied before the latest installed snapshot aren't known.
+    fn is_applied(&self, tx_id: TxId) -> bool;
+}
+
 /// Registered validator credentials.
 pub trait ValidatorCredentials {
     /// Query validator credentials by his public VRF key.
@@ -3,18 +3,31 @@ use std::collections::HashMap;
 use k256::schnorr::signature::Verifier;
 use k256::schnorr::VerifyingKey;
 
-use spectrum_ledger::cell::{ActiveCell, CellMeta, Owner, ProgressPoint, ScriptHash};
+use spectrum_ledger::cell::{ActiveCell, AnyCell, CellMeta, Owner, ProgressPoint, ScriptHash};
 use spectrum_ledger::interop::Point;
-use spectrum_ledger::transaction::{EvaluatedTransaction, LinkedTransaction};
+use spectrum_ledger::transaction::{EvaluatedTransaction, LinkedScriptInv, LinkedTransaction};
 use spectrum_ledger::ChainId;
+use spectrum_move::execution::{ExecutionError, ScriptExecutor};
 use spectrum_move::{GasUnits, SerializedModule};
 
 use crate::state::Cells;
 
+/// Max gas all invocations of a single transaction may consume together.
+pub const TX_GAS_LIMIT: u64 = 1_000_000;
+
 #[derive(Eq, PartialEq, Copy, Clone, Debug)]
-pub struct EvaluationError {
-    pub at_input: usize,
-    pub gas_consumed: GasUnits,
+pub enum EvaluationError {
+    /// Signature of the input is invalid.
+    InvalidSignature { at_input: usize },
+    /// Input is owned by a script which is not invoked by the transaction.
+    Unauthorized { at_input: usize },
+    InvocationFailed {
+        at_invocation: usize,
+        err: ExecutionError,
+        gas_consumed: GasUnits,
+    },
+    /// Invocation returned a value which is not a serialized cell.
+    MalformedOutput { at_invocation: usize },
 }
 
 pub trait TxEvaluator {
@@ -24,7 +37,8 @@ pub trait TxEvaluator {
 
 pub struct InvokationScope {
     pub script: SerializedModule,
-    pub owned_inputs: Vec<ActiveCell>,
+    /// Inputs owned by the script along with their indexes.
+    pub owned_inputs: Vec<(usize, ActiveCell)>,
 }
 
 impl InvokationScope {
@@ -34,9 +48,29 @@ impl InvokationScope {
             owned_inputs: Vec::new(),
         }
     }
-    pub fn add_owned_input(&mut self, cell: ActiveCell) {
-        self.owned_inputs.push(cell);
+    pub fn add_owned_input(&mut self, ix: usize, cell: ActiveCell) {
+        self.owned_inputs.push((ix, cell));
+    }
+}
+
+/// Arguments of an invocation in BCS: serialized inputs owned by the script as `vector<vector<u8>>`,
+/// then the datum as `vector<u8>` if any, then the arguments supplied by the transaction.
+fn invocation_args(scope: &InvokationScope, inv: &LinkedScriptInv) -> Vec<Vec<u8>> {
+    let owned_inputs = scope
+        .owned_inputs
+        .iter()
+        .map(|(_, cell)| {
+            let mut encoded = Vec::new();
+            ciborium::ser::into_writer(cell, &mut encoded).unwrap();
+            encoded
+        })
+        .collect::<Vec<_>>();
+    let mut args = vec![bcs::to_bytes(&owned_inputs).unwrap()];
+    if let Some(datum) = &inv.datum {
+        args.push(bcs::to_bytes(&<Vec<u8>>::from(datum.clone())).unwrap());
     }
+    args.extend(inv.args.iter().cloned().map(<Vec<u8>>::from));
+    args
 }
 
 pub struct ProgrammableTxEvaluator<P> {
@@ -77,27 +111,54 @@ where
                 }
                 converged_ancors.insert(chain_id, point);
             }
-            if let Some(sig) = maybe_sig {
-                match i.owner {
-                    Owner::ProveDlog(pk) => {
-                        let vk = VerifyingKey::try_from(pk).unwrap();
-                        if vk.verify(hash.as_ref(), &sig.into()).is_ok() {
-                            verified_inputs.push(i);
-                        } else {
-                            return Err(EvaluationError {
-                                at_input: ix,
-                                gas_consumed: GasUnits::ZERO,
-                            });
-                        }
+            match (i.owner, maybe_sig) {
+                (Owner::ProveDlog(pk), Some(sig)) => {
+                    let vk = VerifyingKey::try_from(pk).unwrap();
+                    if vk.verify(hash.as_ref(), &sig.into()).is_ok() {
+                        verified_inputs.push((ix, i));
+                    } else {
+                        return Err(EvaluationError::InvalidSignature { at_input: ix });
                     }
-                    Owner::ScriptHash(sh) => {
-                        if let Some(iscope) = invokation_scopes.get_mut(&sh) {
-                            iscope.add_owned_input(i);
-                        }
+                }
+                (Owner::ScriptHash(sh), _) => {
+                    if let Some(iscope) = invokation_scopes.get_mut(&sh) {
+                        iscope.add_owned_input(ix, i);
+                    } else {
+                        return Err(EvaluationError::Unauthorized { at_input: ix });
                     }
                 }
+                (Owner::ProveDlog(_), None) => return Err(EvaluationError::Unauthorized { at_input: ix }),
             }
         }
+        let mut executor = ScriptExecutor::new(GasUnits::from(TX_GAS_LIMIT));
+        for (ix, inv) in invokations.iter().enumerate() {
+            let scope = &invokation_scopes[&ScriptHash::from(inv.script.clone())];
+            let returned = executor
+                .invoke(
+                    &scope.script,
+                    &inv.function,
+                    inv.targs.clone(),
+                    invocation_args(scope, inv),
+                )
+                .map_err(|err| EvaluationError::InvocationFailed {
+                    at_invocation: ix,
+                    err,
+                    gas_consumed: executor.gas_consumed(),
+                })?;
+            for bytes in returned {
+                let cell = ciborium::de::from_reader::<AnyCell, _>(&bytes[..])
+                    .map_err(|_| EvaluationError::MalformedOutput { at_invocation: ix })?;
+                evaluated_outputs.push(cell);
+            }
+        }
+        // Inputs owned by scripts are approved once all invocations succeed.
+        verified_inputs.extend(
+            invokation_scopes
+                .into_values()
+                .flat_map(|scope| scope.owned_inputs),
+        );
+        verified_inputs.sort_by_key(|(ix, _)| *ix);
+        let verified_inputs = verified_inputs.into_iter().map(|(_, cell)| cell).collect();
         let converged_ancors = converged_ancors
             .into_iter()
             .filter(|(chain_id, point)| self.pool.progress_of(*chain_id) < *point) // remove reached ancors.
@@ -110,10 +171,195 @@ where
                 ancors: converged_ancors.clone(),
             })
             .collect();
-        // todo: perform invokations, add computed outputs to `evaluated_outputs`;
         Ok(EvaluatedTransaction {
             inputs: verified_inputs,
             outputs,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use move_core_types::identifier::Identifier;
+    use move_ir_compiler::Compiler;
+
+    use spectrum_crypto::digest::Blake2bDigest256;
+    use spectrum_ledger::cell::{
+        ActiveCell, AnyCell, CellMeta, CellPtr, DatumRef, NativeCoin, Owner, SValue, ScriptHash, ScriptRef,
+        Serial,
+    };
+    use spectrum_ledger::interop::Point;
+    use spectrum_ledger::transaction::{LinkedScriptInv, LinkedTransaction, TxId};
+    use spectrum_ledger::ChainId;
+    use spectrum_move::execution::ExecutionError;
+    use spectrum_move::{GasUnits, SerializedModule, SerializedValue};
+
+    use crate::state::eval::{EvaluationError, ProgrammableTxEvaluator, TxEvaluator, TX_GAS_LIMIT};
+    use crate::state::Cells;
+
+    struct NoCells;
+
+    impl Cells for NoCells {
+        fn get_cell(&self, _: CellPtr) -> Option<CellMeta<AnyCell>> {
+            None
+        }
+        fn progress_of(&self, _: ChainId) -> Point {
+            Point::from(0)
+        }
+        fn get_ref_script(&self, _: ScriptRef) -> Option<SerializedModule> {
+            None
+        }
+        fn get_ref_datum(&self, _: DatumRef) -> Option<SerializedValue> {
+            None
+        }
+    }
+
+    fn cell(owner: Owner, amount: u64) -> ActiveCell {
+        ActiveCell {
+            value: SValue {
+                native: NativeCoin::from(amount),
+                assets: HashMap::new(),
+            },
+            owner,
+            datum: None,
+            reference_script: None,
+            reference_datum: None,
+            tx_id: TxId::from(Blake2bDigest256::random()),
+            index: 0,
+            ver: Serial::INITIAL,
+        }
+    }
+
+    /// Script whose `produce` function returns the given bytes as an output.
+    fn script(output: &[u8]) -> SerializedModule {
+        let code = format!(
+            r#"
+            module 0x1.Script {{
+                public entry produce(inputs: vector<vector<u8>>): vector<u8> {{
+                label l0:
+                    return h"{}";
+                }}
+                public entry fail(inputs: vector<vector<u8>>) {{
+                label l0:
+                    abort(42);
+                }}
+                public entry spin(inputs: vector<vector<u8>>) {{
+                label l0:
+                    jump l0;
+                }}
+            }}
+            "#,
+            base16::encode_lower(output)
+        );
+        SerializedModule::from(Compiler::new(vec![]).into_module_blob(&code).unwrap())
+    }
+
+    fn invocation(script: &SerializedModule, function: &str) -> LinkedScriptInv {
+        LinkedScriptInv {
+            script: script.clone(),
+            datum: None,
+            function: Identifier::new(function).unwrap(),
+            args: vec![],
+            targs: vec![],
+        }
+    }
+
+    /// Transaction spending a cell owned by the script with the given invocations of it.
+    fn tx(script: &SerializedModule, functions: &[&str]) -> (LinkedTransaction, ActiveCell) {
+        let input = cell(Owner::ScriptHash(ScriptHash::from(script.clone())), 10);
+        let tx = LinkedTransaction {
+            inputs: vec![(
+                CellMeta {
+                    cell: input.clone(),
+                    ancors: vec![],
+                },
+                None,
+            )],
+            reference_inputs: vec![],
+            invokations: functions.iter().map(|f| invocation(script, f)).collect(),
+            evaluated_outputs: vec![],
+            hash: Blake2bDigest256::random(),
+        };
+        (tx, input)
+    }
+
+    fn encode(cell: &ActiveCell) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        ciborium::ser::into_writer(&AnyCell::Mut(cell.clone()), &mut encoded).unwrap();
+        encoded
+    }
+
+    #[test]
+    fn outputs_of_invocations_are_evaluated() {
+        let output = cell(
+            Owner::ScriptHash(ScriptHash::from(SerializedModule::from(vec![]))),
+            10,
+        );
+        let script = script(&encode(&output));
+        let (tx, input) = tx(&script, &["produce"]);
+        let evaluated = ProgrammableTxEvaluator { pool: NoCells }
+            .evaluate_transaction(tx)
+            .unwrap();
+        assert_eq!(evaluated.inputs, vec![input]);
+        assert_eq!(
+            evaluated.outputs,
+            vec![CellMeta {
+                cell: AnyCell::Mut(output),
+                ancors: vec![],
+            }]
+        );
+    }
+
+    #[test]
+    fn failed_invocation_is_reported_with_its_index() {
+        let output = cell(
+            Owner::ScriptHash(ScriptHash::from(SerializedModule::from(vec![]))),
+            10,
+        );
+        let script = script(&encode(&output));
+        let (tx, _) = tx(&script, &["produce", "fail"]);
+        let evaluator = ProgrammableTxEvaluator { pool: NoCells };
+        match evaluator.evaluate_transaction(tx) {
+            Err(EvaluationError::InvocationFailed {
+                at_invocation,
+                err,
+                gas_consumed,
+            }) => {
+                assert_eq!(at_invocation, 1);
+                assert_eq!(err, ExecutionError::Aborted(42));
+                assert_ne!(gas_consumed, GasUnits::ZERO);
+            }
+            res => panic!("Unexpected evaluation result {:?}", res.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn invocations_are_bounded_by_gas_limit() {
+        let script = script(&[0]);
+        let (tx, _) = tx(&script, &["spin"]);
+        assert_eq!(
+            ProgrammableTxEvaluator { pool: NoCells }
+                .evaluate_transaction(tx)
+                .map(|_| ()),
+            Err(EvaluationError::InvocationFailed {
+                at_invocation: 0,
+                err: ExecutionError::OutOfGas,
+                gas_consumed: GasUnits::from(TX_GAS_LIMIT),
+            })
+        );
+    }
+
+    #[test]
+    fn malformed_output_is_rejected() {
+        let script = script(&[1, 2, 3]);
+        let (tx, _) = tx(&script, &["produce"]);
+        assert_eq!(
+            ProgrammableTxEvaluator { pool: NoCells }
+                .evaluate_transaction(tx)
+                .map(|_| ()),
+            Err(EvaluationError::MalformedOutput { at_invocation: 0 })
+        );
+    }
+}
@@ -0,0 +1,654 @@
+//! Ledger state persisted in RocksDB.
+//!
+//! Changes are applied on top of the latest version and sealed into a new version by
+//! [`LedgerStateWrite::commit`]. Every change is recorded in the undo-log of the version it belongs
+//! to along with what it overwrites, so the state can be rolled back to any of the last
+//! `keep_versions` versions by replaying the log backwards instead of replaying the chain.
+//!
+//! Cells are additionally indexed by their owners and by the transactions which created them,
+//! see [`StateIndexes`]. Indexes are derived from cells, so they are updated along with them
+//! and aren't recorded in the undo-log.
+
+use std::sync::Arc;
+
+use rocksdb::{Direction, IteratorMode, OptimisticTransactionDB, Transaction};
+
+use spectrum_crypto::digest::Blake2bDigest256;
+use spectrum_ledger::cell::{AnyCell, CellId, CellMeta, CellPtr, CellRef, DatumRef, Owner, ScriptRef};
+use spectrum_ledger::interop::{Effect, Point};
+use spectrum_ledger::transaction::{EvaluatedTransaction, TxId, ValidTx};
+use spectrum_ledger::ChainId;
+use spectrum_move::{SerializedModule, SerializedValue};
+
+use crate::snapshot::StateSnapshot;
+use crate::state::{Cells, LedgerStateError, LedgerStateWrite, StateIndexes};
+
+pub struct LedgerStateRocksDB {
+    pub db: Arc<OptimisticTransactionDB>,
+    /// Number of latest versions the state can be rolled back by.
+    pub keep_versions: u64,
+}
+
+const CELL_PREFIX: &[u8] = b"s:c:";
+const PROGRESS_PREFIX: &[u8] = b"s:p:";
+const UNDO_PREFIX: &[u8] = b"s:u:";
+const TAG_PREFIX: &[u8] = b"s:t:";
+const VERSION_TAG_PREFIX: &[u8] = b"s:v:";
+const OWNER_PREFIX: &[u8] = b"s:o:";
+const TX_PREFIX: &[u8] = b"s:x:";
+/// Prefix of all keys of the state.
+const STATE_PREFIX: &[u8] = b"s:";
+/// Latest committed version.
+const VERSION_KEY: &[u8] = b"s:ver";
+/// Number of undo records of the pending version.
+const SEQ_KEY: &[u8] = b"s:seq";
+
+fn cell_key(id: CellId) -> Vec<u8> {
+    let mut key = CELL_PREFIX.to_vec();
+    key.extend_from_slice(&bincode::serialize(&id).unwrap());
+    key
+}
+
+fn progress_key(chain_id: ChainId) -> Vec<u8> {
+    let mut key = PROGRESS_PREFIX.to_vec();
+    key.extend_from_slice(&u16::from(chain_id).to_be_bytes());
+    key
+}
+
+fn owner_prefix(owner: Owner) -> Vec<u8> {
+    let mut key = OWNER_PREFIX.to_vec();
+    key.extend_from_slice(&bincode::serialize(&owner).unwrap());
+    key
+}
+
+fn owner_key(owner: Owner, id: CellId) -> Vec<u8> {
+    let mut key = owner_prefix(owner);
+    key.extend_from_slice(&bincode::serialize(&id).unwrap());
+    key
+}
+
+fn tx_key(tx_id: TxId) -> Vec<u8> {
+    let mut key = TX_PREFIX.to_vec();
+    key.extend_from_slice(&bincode::serialize(&tx_id).unwrap());
+    key
+}
+
+fn undo_key(version: u64, seq: u32) -> Vec<u8> {
+    let mut key = undo_version_prefix(version);
+    key.extend_from_slice(&seq.to_be_bytes());
+    key
+}
+
+fn undo_version_prefix(version: u64) -> Vec<u8> {
+    let mut key = UNDO_PREFIX.to_vec();
+    key.extend_from_slice(&version.to_be_bytes());
+    key
+}
+
+fn tag_key(tag: Blake2bDigest256) -> Vec<u8> {
+    let mut key = TAG_PREFIX.to_vec();
+    key.extend_from_slice(tag.raw());
+    key
+}
+
+fn version_tag_key(version: u64) -> Vec<u8> {
+    let mut key = VERSION_TAG_PREFIX.to_vec();
+    key.extend_from_slice(&version.to_be_bytes());
+    key
+}
+
+fn decode_u64(bytes: &[u8]) -> u64 {
+    u64::from_be_bytes(bytes.try_into().unwrap())
+}
+
+fn creating_tx(cell: &CellMeta<AnyCell>) -> TxId {
+    match &cell.cell {
+        AnyCell::Mut(active) => active.tx_id,
+        AnyCell::Term(term) => term.tx_id,
+    }
+}
+
+/// Add the cell to the indexes. Term cells aren't owned by anyone on this side.
+fn index_cell(tx: &Transaction<OptimisticTransactionDB>, cell: &CellMeta<AnyCell>) {
+    if let AnyCell::Mut(active) = &cell.cell {
+        tx.put(owner_key(active.owner, active.id()), b"").unwrap();
+    }
+    tx.put(tx_key(creating_tx(cell)), b"").unwrap();
+}
+
+/// Remove the spent cell from the owner index. The transaction which created it stays applied.
+fn unindex_spent_cell(tx: &Transaction<OptimisticTransactionDB>, cell: &CellMeta<AnyCell>) {
+    if let AnyCell::Mut(active) = &cell.cell {
+        tx.delete(owner_key(active.owner, active.id())).unwrap();
+    }
+}
+
+/// Record of a change sufficient to revert it.
+#[derive(serde::Serialize, serde::Deserialize)]
+enum UndoOp {
+    /// Cell was created, reverted by removing it.
+    Created(CellId),
+    /// Cell was spent or overwritten, reverted by restoring it.
+    Spent(CellMeta<AnyCell>),
+    /// Chain progressed from the given point.
+    Progressed(ChainId, Option<Point>),
+}
+
+/// Changes of the pending version applied atomically.
+struct Changes<'a> {
+    tx: Transaction<'a, OptimisticTransactionDB>,
+    version: u64,
+    seq: u32,
+}
+
+impl<'a> Changes<'a> {
+    fn begin(db: &'a OptimisticTransactionDB) -> Self {
+        let tx = db.transaction();
+        let version = tx
+            .get_for_update(VERSION_KEY, true)
+            .unwrap()
+            .map_or(0, |bytes| decode_u64(&bytes))
+            + 1;
+        let seq = tx.get_for_update(SEQ_KEY, true).unwrap().map_or(0, |bytes| {
+            u32::from_be_bytes(bytes.as_slice().try_into().unwrap())
+        });
+        Self { tx, version, seq }
+    }
+
+    fn get_cell(&self, id: CellId) -> Option<CellMeta<AnyCell>> {
+        self.tx
+            .get_for_update(cell_key(id), true)
+            .unwrap()
+            .map(|bytes| bincode::deserialize(&bytes).unwrap())
+    }
+
+    fn record(&mut self, op: UndoOp) {
+        self.tx
+            .put(undo_key(self.version, self.seq), bincode::serialize(&op).unwrap())
+            .unwrap();
+        self.seq += 1;
+    }
+
+    fn put_cell(&mut self, cell: CellMeta<AnyCell>) {
+        let id = cell.cell.id();
+        if let Some(prev) = self.get_cell(id) {
+            unindex_spent_cell(&self.tx, &prev);
+            self.record(UndoOp::Spent(prev));
+        }
+        self.record(UndoOp::Created(id));
+        index_cell(&self.tx, &cell);
+        self.tx
+            .put(cell_key(id), bincode::serialize(&cell).unwrap())
+            .unwrap();
+    }
+
+    fn remove_cell(&mut self, id: CellId) -> Result<(), LedgerStateError> {
+        let prev = self.get_cell(id).ok_or(LedgerStateError::InvalidTransaction)?;
+        unindex_spent_cell(&self.tx, &prev);
+        self.record(UndoOp::Spent(prev));
+        self.tx.delete(cell_key(id)).unwrap();
+        Ok(())
+    }
+
+    fn set_progress(&mut self, chain_id: ChainId, point: Point) {
+        let key = progress_key(chain_id);
+        let prev = self
+            .tx
+            .get_for_update(&key, true)
+            .unwrap()
+            .map(|bytes| bincode::deserialize(&bytes).unwrap());
+        self.record(UndoOp::Progressed(chain_id, prev));
+        self.tx.put(key, bincode::serialize(&point).unwrap()).unwrap();
+    }
+
+    fn commit(self) {
+        self.tx.put(SEQ_KEY, self.seq.to_be_bytes()).unwrap();
+        self.tx.commit().unwrap();
+    }
+}
+
+impl LedgerStateRocksDB {
+    /// Consume inputs of the transaction and create its outputs.
+    pub fn apply_evaluated(&self, tx: &EvaluatedTransaction) -> Result<(), LedgerStateError> {
+        let mut changes = Changes::begin(&self.db);
+        for input in &tx.inputs {
+            match changes.get_cell(input.id()) {
+                Some(meta) if meta.cell.cref() == input.cref() => changes.remove_cell(input.id())?,
+                _ => return Err(LedgerStateError::InvalidTransaction),
+            }
+        }
+        for output in &tx.outputs {
+            changes.put_cell(output.clone());
+        }
+        changes.commit();
+        Ok(())
+    }
+
+    /// Apply effect observed on the given chain.
+    pub fn apply_effect(&self, chain_id: ChainId, eff: &Effect) -> Result<(), LedgerStateError> {
+        let mut changes = Changes::begin(&self.db);
+        match eff {
+            Effect::Imported(cell) => changes.put_cell(CellMeta {
+                cell: cell.clone(),
+                ancors: Vec::new(),
+            }),
+            Effect::Exported(cell_id) | Effect::Revoked(cell_id) => changes.remove_cell(*cell_id)?,
+            Effect::Progressed(point) => changes.set_progress(chain_id, *point),
+        }
+        changes.commit();
+        Ok(())
+    }
+
+    /// Snapshot of the current state, meant to be taken right after a version is committed.
+    pub fn snapshot(&self) -> StateSnapshot {
+        let entries = |prefix: &'static [u8]| {
+            self.db
+                .iterator(IteratorMode::From(prefix, Direction::Forward))
+                .map(Result::unwrap)
+                .take_while(move |(key, _)| key.starts_with(prefix))
+        };
+        StateSnapshot {
+            cells: entries(CELL_PREFIX)
+                .map(|(_, value)| bincode::deserialize(&value).unwrap())
+                .collect(),
+            progress: entries(PROGRESS_PREFIX)
+                .map(|(key, value)| {
+                    let chain_id = u16::from_be_bytes(key[PROGRESS_PREFIX.len()..].try_into().unwrap());
+                    (ChainId::from(chain_id), bincode::deserialize(&value).unwrap())
+                })
+                .collect(),
+        }
+    }
+
+    /// Latest committed version, `0` stands for the initial state.
+    pub fn get_version(&self) -> u64 {
+        self.db
+            .get(VERSION_KEY)
+            .unwrap()
+            .map_or(0, |bytes| decode_u64(&bytes))
+    }
+}
+
+impl LedgerStateWrite for LedgerStateRocksDB {
+    fn apply_tx(&self, tx: ValidTx<EvaluatedTransaction>) -> Result<(), LedgerStateError> {
+        self.apply_evaluated(tx.as_ref())
+    }
+
+    fn apply_eff(&self, chain_id: ChainId, eff: ValidTx<Effect>) -> Result<(), LedgerStateError> {
+        self.apply_effect(chain_id, eff.as_ref())
+    }
+
+    fn commit(&self, tag: Blake2bDigest256) {
+        let tx = self.db.transaction();
+        let version = tx
+            .get_for_update(VERSION_KEY, true)
+            .unwrap()
+            .map_or(0, |bytes| decode_u64(&bytes))
+            + 1;
+        tx.put(VERSION_KEY, version.to_be_bytes()).unwrap();
+        tx.delete(SEQ_KEY).unwrap();
+        tx.put(tag_key(tag), version.to_be_bytes()).unwrap();
+        tx.put(version_tag_key(version), tag.raw()).unwrap();
+        // Rolling back to versions older than `keep_versions` isn't possible anymore,
+        // so the undo-log needed to get there and their tags are discarded.
+        if let Some(pruned) = version.checked_sub(self.keep_versions) {
+            let prefix = undo_version_prefix(pruned);
+            let stale = tx
+                .iterator(IteratorMode::From(&prefix, Direction::Forward))
+                .map(|res| res.unwrap().0)
+                .take_while(|key| key.starts_with(&prefix))
+                .collect::<Vec<_>>();
+            for key in stale {
+                tx.delete(key).unwrap();
+            }
+            if let Some(unreachable) = pruned.checked_sub(1) {
+                if let Some(tag) = tx.get(version_tag_key(unreachable)).unwrap() {
+                    let mut key = TAG_PREFIX.to_vec();
+                    key.extend_from_slice(&tag);
+                    tx.delete(key).unwrap();
+                    tx.delete(version_tag_key(unreachable)).unwrap();
+                }
+            }
+        }
+        tx.commit().unwrap();
+    }
+
+    fn rollback(&self, tag: Blake2bDigest256) -> Result<(), LedgerStateError> {
+        let tx = self.db.transaction();
+        let target = tx
+            .get_for_update(tag_key(tag), true)
+            .unwrap()
+            .map(|bytes| decode_u64(&bytes))
+            .ok_or(LedgerStateError::UnknownVersion(tag))?;
+        let last = tx
+            .get_for_update(VERSION_KEY, true)
+            .unwrap()
+            .map_or(0, |bytes| decode_u64(&bytes));
+        // Changes made after the target version, including uncommitted ones.
+        let start = undo_version_prefix(target + 1);
+        let records = tx
+            .iterator(IteratorMode::From(&start, Direction::Forward))
+            .map(Result::unwrap)
+            .take_while(|(key, _)| key.starts_with(UNDO_PREFIX))
+            .collect::<Vec<_>>();
+        for (key, op) in records.into_iter().rev() {
+            match bincode::deserialize(&op).unwrap() {
+                UndoOp::Created(id) => {
+                    if let Some(bytes) = tx.get(cell_key(id)).unwrap() {
+                        let cell = bincode::deserialize(&bytes).unwrap();
+                        unindex_spent_cell(&tx, &cell);
+                        tx.delete(tx_key(creating_tx(&cell))).unwrap();
+                    }
+                    tx.delete(cell_key(id)).unwrap()
+                }
+                UndoOp::Spent(cell) => {
+                    index_cell(&tx, &cell);
+                    tx.put(cell_key(cell.cell.id()), bincode::serialize(&cell).unwrap())
+                        .unwrap()
+                }
+                UndoOp::Progressed(chain_id, Some(point)) => tx
+                    .put(progress_key(chain_id), bincode::serialize(&point).unwrap())
+                    .unwrap(),
+                UndoOp::Progressed(chain_id, None) => tx.delete(progress_key(chain_id)).unwrap(),
+            }
+            tx.delete(key).unwrap();
+        }
+        for version in target + 1..=last {
+            if let Some(tag) = tx.get(version_tag_key(version)).unwrap() {
+                let mut key = TAG_PREFIX.to_vec();
+                key.extend_from_slice(&tag);
+                tx.delete(key).unwrap();
+                tx.delete(version_tag_key(version)).unwrap();
+            }
+        }
+        tx.put(VERSION_KEY, target.to_be_bytes()).unwrap();
+        tx.delete(SEQ_KEY).unwrap();
+        tx.commit().unwrap();
+        Ok(())
+    }
+
+    fn install_snapshot(&self, tag: Blake2bDigest256, snapshot: StateSnapshot) {
+        let tx = self.db.transaction();
+        let stale = tx
+            .iterator(IteratorMode::From(STATE_PREFIX, Direction::Forward))
+            .map(|res| res.unwrap().0)
+            .take_while(|key| key.starts_with(STATE_PREFIX))
+            .collect::<Vec<_>>();
+        for key in stale {
+            tx.delete(key).unwrap();
+        }
+        for cell in snapshot.cells {
+            index_cell(&tx, &cell);
+            tx.put(cell_key(cell.cell.id()), bincode::serialize(&cell).unwrap())
+                .unwrap();
+        }
+        for (chain_id, point) in snapshot.progress {
+            tx.put(progress_key(chain_id), bincode::serialize(&point).unwrap())
+                .unwrap();
+        }
+        let version = 1u64;
+        tx.put(VERSION_KEY, version.to_be_bytes()).unwrap();
+        tx.put(tag_key(tag), version.to_be_bytes()).unwrap();
+        tx.put(version_tag_key(version), tag.raw()).unwrap();
+        tx.commit().unwrap();
+    }
+}
+
+impl Cells for LedgerStateRocksDB {
+    fn get_cell(&self, ptr: CellPtr) -> Option<CellMeta<AnyCell>> {
+        let cell = self
+            .db
+            .get(cell_key(ptr.cell_id()))
+            .unwrap()
+            .map(|bytes| bincode::deserialize::<CellMeta<AnyCell>>(&bytes).unwrap());
+        match ptr {
+            CellPtr::Id(_) => cell,
+            CellPtr::Ref(cref) => cell.filter(|c| c.cell.cref() == cref),
+        }
+    }
+
+    fn progress_of(&self, chain_id: ChainId) -> Point {
+        self.db
+            .get(progress_key(chain_id))
+            .unwrap()
+            .map_or(Point::from(0), |bytes| bincode::deserialize(&bytes).unwrap())
+    }
+
+    fn get_ref_script(&self, script_ref: ScriptRef) -> Option<SerializedModule> {
+        match self.get_cell(CellPtr::Ref(CellRef::from(script_ref)))?.cell {
+            AnyCell::Mut(cell) => cell.reference_script,
+            AnyCell::Term(_) => None,
+        }
+    }
+
+    fn get_ref_datum(&self, datum_ref: DatumRef) -> Option<SerializedValue> {
+        match self.get_cell(CellPtr::Ref(CellRef::from(datum_ref)))?.cell {
+            AnyCell::Mut(cell) => cell.reference_datum,
+            AnyCell::Term(_) => None,
+        }
+    }
+}
+
+impl StateIndexes for LedgerStateRocksDB {
+    fn get_cells_of(&self, owner: Owner) -> Vec<CellMeta<AnyCell>> {
+        let prefix = owner_prefix(owner);
+        self.db
+            .iterator(IteratorMode::From(&prefix, Direction::Forward))
+            .map(|res| res.unwrap().0)
+            .take_while(|key| key.starts_with(&prefix))
+            .filter_map(|key| {
+                let id = bincode::deserialize(&key[prefix.len()..]).unwrap();
+                self.get_cell(CellPtr::Id(id))
+            })
+            .collect()
+    }
+
+    fn is_applied(&self, tx_id: TxId) -> bool {
+        self.db.get(tx_key(tx_id)).unwrap().is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use rand::RngCore;
+
+    use spectrum_crypto::digest::Blake2bDigest256;
+    use spectrum_ledger::cell::{
+        ActiveCell, AnyCell, CellMeta, CellPtr, NativeCoin, Owner, SValue, ScriptRef, Serial,
+    };
+    use spectrum_ledger::interop::{Effect, Point};
+    use spectrum_ledger::transaction::{EvaluatedTransaction, TxId};
+    use spectrum_ledger::ChainId;
+    use spectrum_move::SerializedModule;
+
+    use crate::snapshot::StateSnapshot;
+    use crate::state::store::LedgerStateRocksDB;
+    use crate::state::{Cells, LedgerStateError, LedgerStateWrite, StateIndexes};
+
+    fn make_state(keep_versions: u64) -> LedgerStateRocksDB {
+        let rnd = rand::thread_rng().next_u32();
+        LedgerStateRocksDB {
+            db: Arc::new(
+                rocksdb::OptimisticTransactionDB::open_default(format!("./tmp/state_{}", rnd)).unwrap(),
+            ),
+            keep_versions,
+        }
+    }
+
+    fn cell(amount: u64) -> ActiveCell {
+        ActiveCell {
+            value: SValue {
+                native: NativeCoin::from(amount),
+                assets: HashMap::new(),
+            },
+            owner: Owner::ProveDlog(k256::SecretKey::random(&mut rand::thread_rng()).public_key()),
+            datum: None,
+            reference_script: None,
+            reference_datum: None,
+            tx_id: TxId::from(Blake2bDigest256::random()),
+            index: 0,
+            ver: Serial::INITIAL,
+        }
+    }
+
+    fn meta(cell: &ActiveCell) -> CellMeta<AnyCell> {
+        CellMeta {
+            cell: AnyCell::Mut(cell.clone()),
+            ancors: vec![],
+        }
+    }
+
+    fn spend(input: &ActiveCell, output: &ActiveCell) -> EvaluatedTransaction {
+        EvaluatedTransaction {
+            inputs: vec![input.clone()],
+            outputs: vec![meta(output)],
+        }
+    }
+
+    #[test]
+    fn rollback_reverts_later_versions() {
+        let state = make_state(10);
+        let chain = ChainId::from(0);
+        let (a, b, c) = (cell(100), cell(100), cell(50));
+        state
+            .apply_effect(chain, &Effect::Imported(AnyCell::Mut(a.clone())))
+            .unwrap();
+        state
+            .apply_effect(chain, &Effect::Progressed(Point::from(10)))
+            .unwrap();
+        let v1 = Blake2bDigest256::random();
+        state.commit(v1);
+
+        state.apply_evaluated(&spend(&a, &b)).unwrap();
+        state
+            .apply_effect(chain, &Effect::Progressed(Point::from(11)))
+            .unwrap();
+        let v2 = Blake2bDigest256::random();
+        state.commit(v2);
+        state.apply_evaluated(&spend(&b, &c)).unwrap();
+        state.commit(Blake2bDigest256::random());
+        // Uncommitted changes are reverted too.
+        state
+            .apply_effect(chain, &Effect::Progressed(Point::from(12)))
+            .unwrap();
+        assert_eq!(state.get_version(), 3);
+
+        state.rollback(v2).unwrap();
+        assert_eq!(state.get_version(), 2);
+        assert_eq!(state.get_cell(CellPtr::Id(b.id())), Some(meta(&b)));
+        assert_eq!(state.get_cell(CellPtr::Id(c.id())), None);
+        assert_eq!(state.progress_of(chain), Point::from(11));
+
+        state.rollback(v1).unwrap();
+        assert_eq!(state.get_cell(CellPtr::Ref(a.cref())), Some(meta(&a)));
+        assert_eq!(state.get_cell(CellPtr::Id(b.id())), None);
+        assert_eq!(state.progress_of(chain), Point::from(10));
+        // Versions built on top of the target are discarded.
+        assert_eq!(state.rollback(v2), Err(LedgerStateError::UnknownVersion(v2)));
+    }
+
+    #[test]
+    fn reject_missing_inputs() {
+        let state = make_state(10);
+        let (a, b) = (cell(100), cell(100));
+        assert_eq!(
+            state.apply_evaluated(&spend(&a, &b)),
+            Err(LedgerStateError::InvalidTransaction)
+        );
+        assert_eq!(state.get_cell(CellPtr::Id(b.id())), None);
+    }
+
+    #[test]
+    fn old_versions_are_pruned() {
+        let state = make_state(2);
+        let tags = (0..4)
+            .map(|i| {
+                state
+                    .apply_effect(ChainId::from(0), &Effect::Progressed(Point::from(i)))
+                    .unwrap();
+                let tag = Blake2bDigest256::random();
+                state.commit(tag);
+                tag
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            state.rollback(tags[0]),
+            Err(LedgerStateError::UnknownVersion(tags[0]))
+        );
+        state.rollback(tags[1]).unwrap();
+        assert_eq!(state.progress_of(ChainId::from(0)), Point::from(1));
+    }
+
+    #[test]
+    fn install_snapshot_replaces_state() {
+        let source = make_state(10);
+        let chain = ChainId::from(0);
+        let (a, b) = (cell(100), cell(100));
+        source
+            .apply_effect(chain, &Effect::Imported(AnyCell::Mut(a.clone())))
+            .unwrap();
+        source
+            .apply_effect(chain, &Effect::Progressed(Point::from(10)))
+            .unwrap();
+        source.commit(Blake2bDigest256::random());
+
+        let state = make_state(10);
+        state
+            .apply_effect(chain, &Effect::Imported(AnyCell::Mut(b.clone())))
+            .unwrap();
+        let old = Blake2bDigest256::random();
+        state.commit(old);
+        let tag = Blake2bDigest256::random();
+        state.install_snapshot(tag, source.snapshot());
+        assert_eq!(state.snapshot(), source.snapshot());
+        assert_eq!(state.get_cell(CellPtr::Id(a.id())), Some(meta(&a)));
+        assert_eq!(state.get_cell(CellPtr::Id(b.id())), None);
+        assert_eq!(state.progress_of(chain), Point::from(10));
+        assert_eq!(state.rollback(old), Err(LedgerStateError::UnknownVersion(old)));
+        assert_eq!(state.rollback(tag), Ok(()));
+    }
+
+    #[test]
+    fn cells_indexed_by_owner_and_creating_tx() {
+        let state = make_state(10);
+        let (a, mut b) = (cell(100), cell(100));
+        b.owner = a.owner;
+        let c = cell(50);
+        state
+            .apply_effect(ChainId::from(0), &Effect::Imported(AnyCell::Mut(a.clone())))
+            .unwrap();
+        let v1 = Blake2bDigest256::random();
+        state.commit(v1);
+        state.apply_evaluated(&spend(&a, &b)).unwrap();
+        assert_eq!(state.get_cells_of(a.owner), vec![meta(&b)]);
+        assert!(state.is_applied(b.tx_id));
+        state.apply_evaluated(&spend(&b, &c)).unwrap();
+        assert!(state.get_cells_of(a.owner).is_empty());
+        assert_eq!(state.get_cells_of(c.owner), vec![meta(&c)]);
+        // Spending outputs of a transaction doesn't revert it.
+        assert!(state.is_applied(b.tx_id));
+
+        state.rollback(v1).unwrap();
+        assert_eq!(state.get_cells_of(a.owner), vec![meta(&a)]);
+        assert!(state.get_cells_of(c.owner).is_empty());
+        assert!(!state.is_applied(b.tx_id));
+        assert!(!state.is_applied(c.tx_id));
+    }
+
+    #[test]
+    fn resolve_reference_scripts() {
+        let state = make_state(10);
+        let mut a = cell(100);
+        a.reference_script = Some(SerializedModule::from(vec![1, 2, 3]));
+        state
+            .apply_effect(ChainId::from(0), &Effect::Imported(AnyCell::Mut(a.clone())))
+            .unwrap();
+        assert_eq!(
+            state.get_ref_script(ScriptRef::from(a.cref())),
+            a.reference_script
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use spectrum_chain_connector::Kilobytes;
+
+use crate::sim::{simulate, Projection, SimParams};
+
+mod sim;
+mod trace;
+
+fn main() {
+    let args = AppArgs::parse();
+    let trace = trace::load(&args.trace_path).expect("Cannot load trace");
+    println!(
+        "{:>9} {:>9} {:>7} {:>8} {:>8} {:>10} {:>10} {:>10} {:>8} {:>14} {:>12} {:>16}",
+        "committee",
+        "epoch(s)",
+        "rounds",
+        "w-txs",
+        "d-txs",
+        "w/hour",
+        "lat(s)",
+        "max-lat(s)",
+        "kb/tx",
+        "fees(nERG)",
+        "fee/w(nERG)",
+        "withdrawn(nERG)"
+    );
+    for committee_size in args.committee_sizes.iter().copied() {
+        for epoch_len_secs in args.epoch_lengths_secs.iter().copied() {
+            let params = SimParams {
+                committee_size,
+                byzantine_fraction: args.byzantine_fraction,
+                epoch_len_secs,
+                max_tx_size: Kilobytes(args.max_tx_size_kb),
+                round_overhead_secs: args.round_overhead_secs,
+                level_secs: args.level_secs,
+                rotation_secs: args.rotation_secs,
+                fee_per_kb: args.fee_per_kb,
+            };
+            print_projection(simulate(&trace, &params));
+        }
+    }
+}
+
+fn print_projection(p: Projection) {
+    println!(
+        "{:>9} {:>9} {:>7} {:>8} {:>8} {:>10.1} {:>10.1} {:>10.1} {:>8.3} {:>14} {:>12.0} {:>16}",
+        p.committee_size,
+        p.epoch_len_secs,
+        p.num_rounds,
+        p.num_withdrawal_txs,
+        p.num_deposit_txs,
+        p.throughput_per_hour,
+        p.mean_latency_secs,
+        p.max_latency_secs,
+        p.mean_withdrawal_tx_size.0,
+        p.total_fees,
+        p.fee_per_withdrawal,
+        p.value_withdrawn
+    );
+    if p.withdrawals_rejected > 0 {
+        println!(
+            "  {} withdrawals don't fit into a TX even alone",
+            p.withdrawals_rejected
+        );
+    }
+}
+
+/// Projects throughput and costs of the vault manager from historical withdrawal/deposit traces.
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct AppArgs {
+    /// Path to the CSV trace with header `timestamp,kind,value,num_tokens`.
+    #[arg(long, short)]
+    trace_path: PathBuf,
+    /// Committee sizes to simulate.
+    #[arg(long, value_delimiter = ',', default_value = "16,32,64,128")]
+    committee_sizes: Vec<usize>,
+    /// Epoch lengths to simulate, `0` disables committee rotation.
+    #[arg(long, value_delimiter = ',', default_value = "3600,86400")]
+    epoch_lengths_secs: Vec<u64>,
+    /// Share of committee members assumed to be byzantine.
+    #[arg(long, default_value_t = 0.33)]
+    byzantine_fraction: f32,
+    /// Maximum size of a withdrawal TX.
+    #[arg(long, default_value_t = 32.0)]
+    max_tx_size_kb: f32,
+    /// Fixed part of the duration of an aggregation round.
+    #[arg(long, default_value_t = 10.0)]
+    round_overhead_secs: f64,
+    /// Duration of a single level of Handel aggregation.
+    #[arg(long, default_value_t = 1.0)]
+    level_secs: f64,
+    /// Duration of the committee rotation.
+    #[arg(long, default_value_t = 120.0)]
+    rotation_secs: f64,
+    /// TX fee per kilobyte in nanoERG.
+    #[arg(long, default_value_t = 1_000_000)]
+    fee_per_kb: u64,
+}
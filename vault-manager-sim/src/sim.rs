@@ -0,0 +1,256 @@
+//! Discrete-time model of a vault manager processing a historical trace.
+//!
+//! The committee runs notarization rounds back to back. At the start of a round pending
+//! withdrawals are packed into a single report by [`pack`], the rest wait for the next round.
+//! Pending deposits are swept into a deposit TX once per round, they don't require
+//! aggregation. Once per epoch the vault is handed over to the next committee, no rounds run
+//! meanwhile.
+
+use std::collections::VecDeque;
+
+use spectrum_chain_connector::report_builder::{pack, Packed};
+use spectrum_chain_connector::Kilobytes;
+use spectrum_ergo_connector::script::estimate_tx_size_in_kb;
+
+use crate::trace::{EventKind, TraceEvent};
+
+#[derive(Debug, Clone)]
+pub struct SimParams {
+    pub committee_size: usize,
+    /// Share of committee members assumed to be byzantine, affects the size of withdrawal TXs.
+    pub byzantine_fraction: f32,
+    /// Committee is never rotated if `0`.
+    pub epoch_len_secs: u64,
+    pub max_tx_size: Kilobytes,
+    /// Fixed part of an aggregation round, e.g. commitment and TX submission.
+    pub round_overhead_secs: f64,
+    /// Duration of a single Handel level, a round takes `log2(committee_size)` levels.
+    pub level_secs: f64,
+    /// Duration of the committee rotation at the end of an epoch.
+    pub rotation_secs: f64,
+    /// Fee per kilobyte of a TX, in nanoERG.
+    pub fee_per_kb: u64,
+}
+
+impl SimParams {
+    fn num_byzantine_nodes(&self) -> usize {
+        (self.committee_size as f32 * self.byzantine_fraction).floor() as usize
+    }
+
+    fn round_secs(&self) -> f64 {
+        let num_levels = (self.committee_size.max(1) as f64).log2().ceil();
+        self.round_overhead_secs + num_levels * self.level_secs
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Projection {
+    pub committee_size: usize,
+    pub epoch_len_secs: u64,
+    pub num_rounds: usize,
+    pub num_withdrawal_txs: usize,
+    pub num_deposit_txs: usize,
+    pub withdrawals_processed: usize,
+    pub deposits_processed: usize,
+    /// Withdrawals which don't fit into a TX even alone.
+    pub withdrawals_rejected: usize,
+    /// Total value of processed withdrawals, in nanoERG.
+    pub value_withdrawn: u64,
+    /// Time it took to process the whole trace.
+    pub makespan_secs: f64,
+    /// Processed withdrawals per hour.
+    pub throughput_per_hour: f64,
+    pub mean_latency_secs: f64,
+    pub max_latency_secs: f64,
+    pub mean_withdrawal_tx_size: Kilobytes,
+    /// Total fees of all TXs, in nanoERG.
+    pub total_fees: u64,
+    /// Fees paid per processed withdrawal, in nanoERG.
+    pub fee_per_withdrawal: f64,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Pending {
+    /// Time the transfer was requested at, relative to the start of the trace.
+    arrived_at: f64,
+    value: u64,
+    num_tokens: usize,
+}
+
+pub fn simulate(trace: &[TraceEvent], params: &SimParams) -> Projection {
+    let start = trace.first().map(|e| e.timestamp).unwrap_or(0);
+    let num_byzantine_nodes = params.num_byzantine_nodes();
+    let estimate = |num_byzantine_nodes: usize| {
+        move |transfers: &[Pending]| {
+            let num_tokens = transfers.iter().map(|t| t.num_tokens).sum();
+            Kilobytes(estimate_tx_size_in_kb(
+                transfers.len(),
+                num_byzantine_nodes,
+                num_tokens,
+            ))
+        }
+    };
+    let fee = |size: Kilobytes| (size.0 as f64 * params.fee_per_kb as f64).ceil() as u64;
+
+    let mut events = trace.iter().peekable();
+    let mut withdrawals = VecDeque::new();
+    let mut deposits = VecDeque::new();
+    let mut now = 0.0;
+    let mut next_rotation = params.epoch_len_secs as f64;
+
+    let mut num_rounds = 0;
+    let mut num_withdrawal_txs = 0;
+    let mut num_deposit_txs = 0;
+    let mut withdrawals_rejected = 0;
+    let mut deposits_processed = 0;
+    let mut latencies = vec![];
+    let mut value_withdrawn = 0;
+    let mut withdrawal_tx_sizes = 0.0;
+    let mut total_fees = 0;
+
+    loop {
+        if params.epoch_len_secs > 0 && now >= next_rotation {
+            now += params.rotation_secs;
+            next_rotation = now + params.epoch_len_secs as f64;
+        }
+        while let Some(event) = events.next_if(|e| (e.timestamp - start) as f64 <= now) {
+            let pending = Pending {
+                arrived_at: (event.timestamp - start) as f64,
+                value: event.value,
+                num_tokens: event.num_tokens,
+            };
+            match event.kind {
+                EventKind::Withdrawal => withdrawals.push_back(pending),
+                EventKind::Deposit => deposits.push_back(pending),
+            }
+        }
+        if withdrawals.is_empty() && deposits.is_empty() {
+            match events.peek() {
+                // Idle until the next request.
+                Some(event) => {
+                    now = f64::max(now, (event.timestamp - start) as f64);
+                    continue;
+                }
+                None => break,
+            }
+        }
+
+        num_rounds += 1;
+        let round_end = now + params.round_secs();
+        if !withdrawals.is_empty() {
+            let Packed {
+                selected,
+                deferred,
+                estimated_size,
+            } = pack(
+                withdrawals.drain(..).collect(),
+                params.max_tx_size,
+                estimate(num_byzantine_nodes),
+            );
+            if selected.is_empty() {
+                // Nothing fits, so the oldest withdrawal can never be processed.
+                withdrawals_rejected += 1;
+                withdrawals.extend(deferred.into_iter().skip(1));
+            } else {
+                num_withdrawal_txs += 1;
+                withdrawal_tx_sizes += estimated_size.0 as f64;
+                total_fees += fee(estimated_size);
+                latencies.extend(selected.iter().map(|w| round_end - w.arrived_at));
+                value_withdrawn += selected.iter().map(|w| w.value).sum::<u64>();
+                withdrawals.extend(deferred);
+            }
+        }
+        if !deposits.is_empty() {
+            let Packed {
+                selected,
+                deferred,
+                estimated_size,
+            } = pack(deposits.drain(..).collect(), params.max_tx_size, estimate(0));
+            if selected.is_empty() {
+                // Deposit which exceeds the limit is swept on its own.
+                num_deposit_txs += 1;
+                deposits_processed += 1;
+                total_fees += fee(estimate(0)(&deferred[..1]));
+                deposits.extend(deferred.into_iter().skip(1));
+            } else {
+                num_deposit_txs += 1;
+                deposits_processed += selected.len();
+                total_fees += fee(estimated_size);
+                deposits.extend(deferred);
+            }
+        }
+        now = round_end;
+    }
+
+    let withdrawals_processed = latencies.len();
+    let mean = |total: f64, n: usize| if n > 0 { total / n as f64 } else { 0.0 };
+    Projection {
+        committee_size: params.committee_size,
+        epoch_len_secs: params.epoch_len_secs,
+        num_rounds,
+        num_withdrawal_txs,
+        num_deposit_txs,
+        withdrawals_processed,
+        deposits_processed,
+        withdrawals_rejected,
+        value_withdrawn,
+        makespan_secs: now,
+        throughput_per_hour: withdrawals_processed as f64 * 3600.0 / now.max(1.0),
+        mean_latency_secs: mean(latencies.iter().sum(), latencies.len()),
+        max_latency_secs: latencies.iter().copied().fold(0.0, f64::max),
+        mean_withdrawal_tx_size: Kilobytes(mean(withdrawal_tx_sizes, num_withdrawal_txs) as f32),
+        total_fees,
+        fee_per_withdrawal: mean(total_fees as f64, withdrawals_processed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use spectrum_chain_connector::Kilobytes;
+
+    use crate::sim::{simulate, SimParams};
+    use crate::trace::{EventKind, TraceEvent};
+
+    fn params(committee_size: usize, epoch_len_secs: u64, max_tx_size: f32) -> SimParams {
+        SimParams {
+            committee_size,
+            byzantine_fraction: 0.0,
+            epoch_len_secs,
+            max_tx_size: Kilobytes(max_tx_size),
+            round_overhead_secs: 10.0,
+            level_secs: 1.0,
+            rotation_secs: 100.0,
+            fee_per_kb: 1000,
+        }
+    }
+
+    fn withdrawals(num: usize) -> Vec<TraceEvent> {
+        (0..num)
+            .map(|_| TraceEvent {
+                timestamp: 1_700_000_000,
+                kind: EventKind::Withdrawal,
+                value: 1_000_000_000,
+                num_tokens: 0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn withdrawals_exceeding_tx_size_are_split_across_rounds() {
+        // Overhead of a TX is 0.67kb, every withdrawal takes 0.086kb, so 3 fit into 1kb.
+        let projection = simulate(&withdrawals(7), &params(16, 0, 1.0));
+        assert_eq!(projection.num_withdrawal_txs, 3);
+        assert_eq!(projection.withdrawals_processed, 7);
+        // A round with 16 members takes 10s + 4 levels of 1s.
+        assert_eq!(projection.makespan_secs, 42.0);
+    }
+
+    #[test]
+    fn committee_rotation_delays_processing() {
+        let long_epoch = simulate(&withdrawals(7), &params(16, 3600, 1.0));
+        let short_epoch = simulate(&withdrawals(7), &params(16, 20, 1.0));
+        assert_eq!(long_epoch.makespan_secs, 42.0);
+        assert!(short_epoch.makespan_secs > long_epoch.makespan_secs);
+        assert!(short_epoch.throughput_per_hour < long_epoch.throughput_per_hour);
+    }
+}
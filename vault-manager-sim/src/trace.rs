@@ -0,0 +1,84 @@
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Withdrawal,
+    Deposit,
+}
+
+/// Single entry of a historical trace.
+///
+/// CSV header: `timestamp,kind,value,num_tokens`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct TraceEvent {
+    /// Unix time of the event in seconds.
+    pub timestamp: u64,
+    pub kind: EventKind,
+    /// Native value transferred, in nanoERG.
+    pub value: u64,
+    /// Number of distinct tokens transferred along with the native value.
+    #[serde(default)]
+    pub num_tokens: usize,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TraceError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("Malformed trace: {0}")]
+    Malformed(#[from] csv::Error),
+    #[error("Trace is empty")]
+    Empty,
+}
+
+/// Read the trace, events are ordered by time.
+pub fn load(path: &Path) -> Result<Vec<TraceEvent>, TraceError> {
+    let file = std::fs::File::open(path)?;
+    parse(file)
+}
+
+pub fn parse<R: io::Read>(reader: R) -> Result<Vec<TraceEvent>, TraceError> {
+    let mut events = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader)
+        .deserialize()
+        .collect::<Result<Vec<TraceEvent>, _>>()?;
+    if events.is_empty() {
+        return Err(TraceError::Empty);
+    }
+    events.sort_by_key(|e| e.timestamp);
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::trace::{parse, EventKind, TraceEvent};
+
+    #[test]
+    fn events_are_ordered_by_time() {
+        let csv = "timestamp,kind,value,num_tokens\n\
+                   20, deposit, 5, 1\n\
+                   10, withdrawal, 7, 0\n";
+        assert_eq!(
+            parse(csv.as_bytes()).unwrap(),
+            vec![
+                TraceEvent {
+                    timestamp: 10,
+                    kind: EventKind::Withdrawal,
+                    value: 7,
+                    num_tokens: 0,
+                },
+                TraceEvent {
+                    timestamp: 20,
+                    kind: EventKind::Deposit,
+                    value: 5,
+                    num_tokens: 1,
+                },
+            ]
+        );
+    }
+}